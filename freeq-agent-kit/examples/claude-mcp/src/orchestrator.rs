@@ -193,8 +193,11 @@ impl Orchestrator {
                 || cfg.server.starts_with("https://")
                 || cfg.server.starts_with("wss://"),
             tls_insecure: false,
+            tls_options: Default::default(),
             web_token: None,
             websocket_url,
+            nick_fallback: Default::default(),
+            capabilities: Default::default(),
         };
         let signer = Arc::new(KeySigner::new(ident.did.clone(), ident.private_key));
         let (handle, mut events) = client::connect(conn_config, Some(signer));
@@ -2,7 +2,7 @@ import { useState, useCallback, useEffect, useRef } from 'react';
 import { useStore } from './store';
 import { useKeyboard } from './hooks/useKeyboard';
 import { setUnreadCount } from './lib/notifications';
-import { ConnectScreen } from './components/ConnectScreen';
+import { ConnectScreen, LS_PENDING_INVITE } from './components/ConnectScreen';
 import { Sidebar } from './components/Sidebar';
 import { TopBar } from './components/TopBar';
 import { MessageList } from './components/MessageList';
@@ -138,17 +138,31 @@ export default function App() {
     // Notification permission is deferred to first mention (see notifications.ts)
   }, [registered]);
 
-  // Handle invite link auto-join when already connected
+  // Handle invite link auto-join when already connected, or an invite
+  // link followed before signing in (stashed by the connect screen)
   useEffect(() => {
     if (!registered) return;
     const hash = window.location.hash;
+    let join: { channel: string; token?: string } | null = null;
     if (hash.startsWith('#auto-join=')) {
-      const ch = decodeURIComponent(hash.slice('#auto-join='.length));
+      const [target, token] = hash.slice('#auto-join='.length).split('&invite=');
       window.history.replaceState(null, '', window.location.pathname);
+      join = { channel: decodeURIComponent(target), token };
+    } else {
+      const pending = localStorage.getItem(LS_PENDING_INVITE);
+      localStorage.removeItem(LS_PENDING_INVITE);
+      try {
+        join = pending ? JSON.parse(pending) : null;
+      } catch {
+        join = null;
+      }
+    }
+    if (join) {
+      const { channel, token } = join;
       // Join and switch to the channel
       import('./irc/client').then(({ joinChannel }) => {
-        joinChannel(ch);
-        setActive(ch);
+        joinChannel(channel, token);
+        setActive(channel);
       });
     }
   }, [registered, setActive]);
@@ -93,6 +93,7 @@ function nickFromHandle(handle: string): string {
 // localStorage keys
 const LS_HANDLE = 'freeq-handle';
 const LS_CHANNELS = 'freeq-channels';
+export const LS_PENDING_INVITE = 'freeq-pending-invite';
 const LS_BROKER_TOKEN = 'freeq-broker-token';
 const LS_BROKER_BASE = 'freeq-broker-base';
 
@@ -109,8 +110,15 @@ export function ConnectScreen() {
     // Check for auto-join from invite link (e.g. #auto-join=#channel)
     const hash = window.location.hash;
     if (hash.startsWith('#auto-join=')) {
-      const ch = decodeURIComponent(hash.slice('#auto-join='.length));
+      const [target, invite] = hash.slice('#auto-join='.length).split('&invite=');
+      const ch = decodeURIComponent(target);
       window.location.hash = '';
+      if (invite) {
+        // Invite links join with their token once registered (see App.tsx);
+        // a plain auto-join would just bounce off +i/+k or the policy.
+        localStorage.setItem(LS_PENDING_INVITE, JSON.stringify({ channel: ch, token: invite }));
+        return localStorage.getItem(LS_CHANNELS) || '#freeq';
+      }
       const existing = localStorage.getItem(LS_CHANNELS) || '';
       const merged = new Set(existing.split(',').map(s => s.trim()).filter(Boolean));
       merged.add(ch);
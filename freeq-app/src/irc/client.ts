@@ -187,8 +187,8 @@ export function sendUnreact(target: string, emoji: string, msgId: string) {
   client?.sendUnreact(target, emoji, msgId);
 }
 
-export function joinChannel(channel: string) {
-  client?.join(channel);
+export function joinChannel(channel: string, key?: string) {
+  client?.join(channel, key);
   useStore.getState().addChannel(channel);
   useStore.getState().setActiveChannel(channel);
 }
@@ -0,0 +1,364 @@
+//! Apple App Attest attestation objects.
+//!
+//! Verifies an attestation the way Apple's "Validating apps that connect
+//! to your server" describes: the certificate chain up to the App
+//! Attestation Root CA, the nonce binding the challenge, the app ID, and
+//! the key ID. Each mint carries a fresh attestation; the broker keeps no
+//! per-device keys, so assertions aren't accepted.
+
+use sha2::{Digest, Sha256, Sha384};
+use x509_cert::Certificate;
+use x509_cert::der::asn1::ObjectIdentifier;
+use x509_cert::der::{Decode, DecodePem, Encode};
+
+use crate::attest::decode_base64;
+
+/// Apple's nonce extension on the credential certificate.
+const NONCE_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113635.100.8.2");
+const ECDSA_WITH_SHA256: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.2");
+const ECDSA_WITH_SHA384: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.3");
+const SECP256R1: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.3.1.7");
+const SECP384R1: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.132.0.34");
+
+/// DER prefix of the nonce extension: SEQUENCE { [1] { OCTET STRING (32) } }.
+const NONCE_PREFIX: [u8; 6] = [0x30, 0x24, 0xa1, 0x22, 0x04, 0x20];
+
+const AAGUID_PRODUCTION: &[u8; 16] = b"appattest\0\0\0\0\0\0\0";
+const AAGUID_DEVELOPMENT: &[u8; 16] = b"appattestdevelop";
+
+#[derive(Debug, Clone)]
+pub struct AppAttestConfig {
+    /// Apps allowed to mint, as `TEAMID.bundle.id`.
+    pub app_ids: Vec<String>,
+    /// Apple App Attestation Root CA
+    /// (<https://www.apple.com/certificateauthority/Apple_App_Attestation_Root_CA.pem>).
+    pub root_ca: Certificate,
+    /// Also accept attestations from the development environment.
+    pub allow_development: bool,
+}
+
+impl AppAttestConfig {
+    /// Read `BROKER_APP_ATTEST_APP_IDS` (comma-separated), the root CA PEM
+    /// at `BROKER_APP_ATTEST_ROOT_CA`, and `BROKER_APP_ATTEST_DEVELOPMENT`.
+    /// `None` when no app IDs are set.
+    pub fn from_env() -> Result<Option<Self>, anyhow::Error> {
+        let Some(app_ids) = crate::config::env_list("BROKER_APP_ATTEST_APP_IDS") else {
+            return Ok(None);
+        };
+        let Ok(path) = std::env::var("BROKER_APP_ATTEST_ROOT_CA") else {
+            anyhow::bail!("BROKER_APP_ATTEST_APP_IDS is set but BROKER_APP_ATTEST_ROOT_CA is not");
+        };
+        let pem = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow::anyhow!("reading App Attest root CA {path}: {e}"))?;
+        let root_ca = Certificate::from_pem(pem.as_bytes())
+            .map_err(|e| anyhow::anyhow!("parsing App Attest root CA {path}: {e}"))?;
+        let allow_development = matches!(
+            std::env::var("BROKER_APP_ATTEST_DEVELOPMENT").as_deref(),
+            Ok("1" | "true" | "yes")
+        );
+        Ok(Some(Self {
+            app_ids,
+            root_ca,
+            allow_development,
+        }))
+    }
+}
+
+/// Verify a base64 attestation object bound to `challenge`; returns the
+/// attested app ID.
+pub(crate) fn verify(
+    config: &AppAttestConfig,
+    token: &str,
+    challenge: &str,
+) -> Result<String, anyhow::Error> {
+    let object = decode_base64(token)?;
+    let AttestationObject {
+        credential,
+        intermediate,
+        auth_data,
+    } = parse_object(&object)?;
+    let credential = Certificate::from_der(&credential)
+        .map_err(|e| anyhow::anyhow!("bad credential certificate: {e}"))?;
+    let intermediate = Certificate::from_der(&intermediate)
+        .map_err(|e| anyhow::anyhow!("bad intermediate certificate: {e}"))?;
+
+    let now = std::time::SystemTime::now();
+    check_issued_by(&credential, &intermediate, now)?;
+    check_issued_by(&intermediate, &config.root_ca, now)?;
+
+    // The challenge is bound through the nonce extension.
+    let client_data_hash = Sha256::digest(challenge.as_bytes());
+    let mut nonce_input = auth_data.clone();
+    nonce_input.extend_from_slice(&client_data_hash);
+    let mut expected = NONCE_PREFIX.to_vec();
+    expected.extend_from_slice(&Sha256::digest(&nonce_input));
+    let nonce = credential
+        .tbs_certificate
+        .extensions
+        .iter()
+        .flatten()
+        .find(|ext| ext.extn_id == NONCE_OID)
+        .map(|ext| ext.extn_value.as_bytes())
+        .ok_or_else(|| anyhow::anyhow!("credential certificate has no nonce"))?;
+    if nonce != expected.as_slice() {
+        anyhow::bail!("nonce does not match the challenge");
+    }
+
+    let public_key = credential
+        .tbs_certificate
+        .subject_public_key_info
+        .subject_public_key
+        .as_bytes()
+        .ok_or_else(|| anyhow::anyhow!("credential key is not byte-aligned"))?;
+    let key_id = Sha256::digest(public_key);
+
+    // authenticatorData: rpIdHash(32) flags(1) signCount(4) aaguid(16)
+    // credentialIdLength(2) credentialId(..) ...
+    if auth_data.len() < 55 {
+        anyhow::bail!("authenticator data too short");
+    }
+    let rp_id_hash = &auth_data[..32];
+    let app_id = config
+        .app_ids
+        .iter()
+        .find(|id| Sha256::digest(id.as_bytes()).as_slice() == rp_id_hash)
+        .ok_or_else(|| anyhow::anyhow!("app ID is not allowed"))?;
+    if auth_data[33..37] != [0, 0, 0, 0] {
+        anyhow::bail!("counter is not zero");
+    }
+    match &auth_data[37..53] {
+        aaguid if aaguid == AAGUID_PRODUCTION => {}
+        aaguid if aaguid == AAGUID_DEVELOPMENT && config.allow_development => {}
+        aaguid if aaguid == AAGUID_DEVELOPMENT => {
+            anyhow::bail!("development attestations are not accepted")
+        }
+        _ => anyhow::bail!("unknown AAGUID"),
+    }
+    let id_len = u16::from_be_bytes([auth_data[53], auth_data[54]]) as usize;
+    if auth_data.get(55..55 + id_len) != Some(key_id.as_slice()) {
+        anyhow::bail!("credential ID does not match the attested key");
+    }
+    Ok(app_id.clone())
+}
+
+/// The parts of a CBOR attestation object that get verified.
+struct AttestationObject {
+    /// DER credential certificate (`x5c[0]`).
+    credential: Vec<u8>,
+    /// DER intermediate certificate (`x5c[1]`).
+    intermediate: Vec<u8>,
+    auth_data: Vec<u8>,
+}
+
+fn parse_object(object: &[u8]) -> Result<AttestationObject, anyhow::Error> {
+    use ciborium::Value;
+
+    let value: Value = ciborium::from_reader(object)
+        .map_err(|e| anyhow::anyhow!("attestation is not CBOR: {e}"))?;
+    let field = |map: &Value, key: &str| -> Option<Value> {
+        map.as_map()?
+            .iter()
+            .find(|(k, _)| k.as_text() == Some(key))
+            .map(|(_, v)| v.clone())
+    };
+    if field(&value, "fmt").as_ref().and_then(Value::as_text) != Some("apple-appattest") {
+        anyhow::bail!("not an apple-appattest attestation");
+    }
+    let statement =
+        field(&value, "attStmt").ok_or_else(|| anyhow::anyhow!("attestation has no attStmt"))?;
+    let x5c = field(&statement, "x5c")
+        .and_then(|v| v.into_array().ok())
+        .ok_or_else(|| anyhow::anyhow!("attestation has no certificate chain"))?;
+    let mut certs = x5c.into_iter().map(|c| c.into_bytes().ok());
+    let (Some(Some(credential)), Some(Some(intermediate))) = (certs.next(), certs.next()) else {
+        anyhow::bail!("attestation needs two certificates");
+    };
+    let auth_data = field(&value, "authData")
+        .and_then(|v| v.into_bytes().ok())
+        .ok_or_else(|| anyhow::anyhow!("attestation has no authData"))?;
+    Ok(AttestationObject {
+        credential,
+        intermediate,
+        auth_data,
+    })
+}
+
+/// Check that `issuer` signed `cert` and that `cert` is valid at `now`.
+fn check_issued_by(
+    cert: &Certificate,
+    issuer: &Certificate,
+    now: std::time::SystemTime,
+) -> Result<(), anyhow::Error> {
+    let tbs = &cert.tbs_certificate;
+    if tbs.issuer != issuer.tbs_certificate.subject {
+        anyhow::bail!("certificate chain is broken");
+    }
+    if now < tbs.validity.not_before.to_system_time()
+        || now > tbs.validity.not_after.to_system_time()
+    {
+        anyhow::bail!("certificate is not valid now");
+    }
+
+    let message = tbs.to_der()?;
+    let digest = match cert.signature_algorithm.oid {
+        ECDSA_WITH_SHA256 => Sha256::digest(&message).to_vec(),
+        ECDSA_WITH_SHA384 => Sha384::digest(&message).to_vec(),
+        other => anyhow::bail!("unsupported signature algorithm {other}"),
+    };
+    let signature = cert
+        .signature
+        .as_bytes()
+        .ok_or_else(|| anyhow::anyhow!("signature is not byte-aligned"))?;
+    let spki = &issuer.tbs_certificate.subject_public_key_info;
+    let key = spki
+        .subject_public_key
+        .as_bytes()
+        .ok_or_else(|| anyhow::anyhow!("issuer key is not byte-aligned"))?;
+    let curve = spki
+        .algorithm
+        .parameters
+        .as_ref()
+        .and_then(|p| p.decode_as::<ObjectIdentifier>().ok());
+
+    use p256::ecdsa::signature::hazmat::PrehashVerifier;
+    let verified = match curve {
+        Some(SECP256R1) => {
+            let key = p256::ecdsa::VerifyingKey::from_sec1_bytes(key)?;
+            let signature = p256::ecdsa::Signature::from_der(signature)?;
+            key.verify_prehash(&digest, &signature).is_ok()
+        }
+        Some(SECP384R1) => {
+            let key = p384::ecdsa::VerifyingKey::from_sec1_bytes(key)?;
+            let signature = p384::ecdsa::Signature::from_der(signature)?;
+            key.verify_prehash(&digest, &signature).is_ok()
+        }
+        _ => anyhow::bail!("unsupported issuer key"),
+    };
+    if !verified {
+        anyhow::bail!("certificate signature is invalid");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{
+        BasicConstraints, CertificateParams, CustomExtension, IsCa, KeyPair,
+        PKCS_ECDSA_P256_SHA256, PKCS_ECDSA_P384_SHA384,
+    };
+
+    const APP_ID: &str = "TEAM123456.at.freeq.ios";
+
+    struct Chain {
+        root: Certificate,
+        object: Vec<u8>,
+    }
+
+    /// A root → intermediate → credential chain shaped like Apple's,
+    /// attesting `app_id` with `challenge`.
+    fn attest(app_id: &str, challenge: &str, aaguid: &[u8; 16]) -> Chain {
+        let ca = || {
+            let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+            params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            params
+        };
+        let root_key = KeyPair::generate_for(&PKCS_ECDSA_P384_SHA384).unwrap();
+        let root = ca().self_signed(&root_key).unwrap();
+        let int_key = KeyPair::generate_for(&PKCS_ECDSA_P384_SHA384).unwrap();
+        let mut int_params = ca();
+        int_params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, "App Attestation CA");
+        let intermediate = int_params.signed_by(&int_key, &root, &root_key).unwrap();
+
+        let cred_key = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256).unwrap();
+        let key_id = Sha256::digest(cred_key.public_key_raw());
+        let mut auth_data = Sha256::digest(app_id.as_bytes()).to_vec();
+        auth_data.push(0x40);
+        auth_data.extend_from_slice(&[0, 0, 0, 0]);
+        auth_data.extend_from_slice(aaguid);
+        auth_data.extend_from_slice(&(key_id.len() as u16).to_be_bytes());
+        auth_data.extend_from_slice(&key_id);
+
+        let mut nonce_input = auth_data.clone();
+        nonce_input.extend_from_slice(&Sha256::digest(challenge.as_bytes()));
+        let mut nonce = NONCE_PREFIX.to_vec();
+        nonce.extend_from_slice(&Sha256::digest(&nonce_input));
+        let mut cred_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        cred_params
+            .custom_extensions
+            .push(CustomExtension::from_oid_content(
+                &[1, 2, 840, 113635, 100, 8, 2],
+                nonce,
+            ));
+        let credential = cred_params
+            .signed_by(&cred_key, &intermediate, &int_key)
+            .unwrap();
+
+        let object = ciborium::Value::Map(vec![
+            ("fmt".into(), "apple-appattest".into()),
+            (
+                "attStmt".into(),
+                ciborium::Value::Map(vec![(
+                    "x5c".into(),
+                    ciborium::Value::Array(vec![
+                        ciborium::Value::Bytes(credential.der().to_vec()),
+                        ciborium::Value::Bytes(intermediate.der().to_vec()),
+                    ]),
+                )]),
+            ),
+            ("authData".into(), ciborium::Value::Bytes(auth_data)),
+        ]);
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&object, &mut bytes).unwrap();
+        Chain {
+            root: Certificate::from_der(root.der()).unwrap(),
+            object: bytes,
+        }
+    }
+
+    fn config(root: Certificate) -> AppAttestConfig {
+        AppAttestConfig {
+            app_ids: vec![APP_ID.to_string()],
+            root_ca: root,
+            allow_development: false,
+        }
+    }
+
+    fn token(object: &[u8]) -> String {
+        use base64::Engine;
+        base64::engine::general_purpose::STANDARD.encode(object)
+    }
+
+    #[test]
+    fn valid_attestation_verifies() {
+        let chain = attest(APP_ID, "challenge-1", AAGUID_PRODUCTION);
+        let app = verify(&config(chain.root), &token(&chain.object), "challenge-1").unwrap();
+        assert_eq!(app, APP_ID);
+    }
+
+    #[test]
+    fn wrong_challenge_app_or_root_is_refused() {
+        let chain = attest(APP_ID, "challenge-1", AAGUID_PRODUCTION);
+        let err = verify(&config(chain.root.clone()), &token(&chain.object), "other");
+        assert!(err.unwrap_err().to_string().contains("nonce"));
+
+        let other_app = attest("TEAM123456.evil", "c", AAGUID_PRODUCTION);
+        let err = verify(&config(other_app.root), &token(&other_app.object), "c");
+        assert!(err.unwrap_err().to_string().contains("not allowed"));
+
+        let other_root = attest(APP_ID, "c", AAGUID_PRODUCTION).root;
+        let err = verify(&config(other_root), &token(&chain.object), "challenge-1");
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn development_attestations_need_opt_in() {
+        let chain = attest(APP_ID, "c", AAGUID_DEVELOPMENT);
+        let mut config = config(chain.root);
+        assert!(verify(&config, &token(&chain.object), "c").is_err());
+        config.allow_development = true;
+        assert_eq!(verify(&config, &token(&chain.object), "c").unwrap(), APP_ID);
+    }
+}
@@ -0,0 +1,278 @@
+//! Mobile client attestation on the token-mint path.
+//!
+//! Without it, anyone who knows the broker URL can complete OAuth and mint
+//! freeq tokens from any app. With it, a mobile app proves it is a genuine
+//! build of an allowed app before the broker mints:
+//!
+//! 1. `POST /attest/challenge` returns a single-use challenge.
+//! 2. The app binds the challenge into an App Attest attestation (its
+//!    SHA-256 is the `clientDataHash`) or a Play Integrity token (it is the
+//!    `nonce`).
+//! 3. The app sends `{platform, token, challenge}` to `/session` (as the
+//!    `attestation` field) or to `/auth/login?mobile=1` (as the
+//!    `attestation_platform`, `attestation` and `challenge` query params).
+//!
+//! Browsers can't attest, so the broker exempts them on its own record,
+//! not on anything the request claims: a non-mobile login whose tokens
+//! return to one of `allowed_origins` is never refused for a *missing*
+//! attestation, and neither is a `/session` refresh of that login's
+//! session from that same origin. Mobile-flow sessions stay bound to
+//! [`AttestationMode::Required`] whatever `Origin` they send. An invalid
+//! attestation is always refused.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::app_attest::{self, AppAttestConfig};
+use crate::crypto::generate_random_string;
+use crate::play_integrity::{self, PlayIntegrityConfig};
+
+/// How long an issued challenge stays valid.
+pub(crate) const CHALLENGE_TTL: Duration = Duration::from_secs(300);
+
+/// Most challenges outstanding at once; beyond this, issuing fails until
+/// some expire.
+const MAX_CHALLENGES: usize = 10_000;
+
+/// What the broker does with client attestations.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AttestationMode {
+    /// Ignore attestations entirely.
+    #[default]
+    Off,
+    /// Verify attestations that are sent; mint without one.
+    Optional,
+    /// Refuse to mint for mobile clients without a valid attestation.
+    Required,
+}
+
+impl std::str::FromStr for AttestationMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "" | "off" => Ok(Self::Off),
+            "optional" => Ok(Self::Optional),
+            "required" => Ok(Self::Required),
+            other => anyhow::bail!("unknown attestation mode {other:?} (off, optional, required)"),
+        }
+    }
+}
+
+/// Attestation policy and the per-platform verifiers. A platform without
+/// a config can't attest, so under [`AttestationMode::Required`] its apps
+/// can't mint.
+#[derive(Debug, Clone, Default)]
+pub struct AttestationConfig {
+    pub mode: AttestationMode,
+    pub app_attest: Option<AppAttestConfig>,
+    pub play_integrity: Option<PlayIntegrityConfig>,
+}
+
+/// The attestation mechanism a client used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Platform {
+    /// Apple App Attest (iOS, iPadOS, macOS).
+    AppAttest,
+    /// Google Play Integrity (Android).
+    PlayIntegrity,
+}
+
+/// An attestation as sent by a client.
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct Attestation {
+    pub platform: Platform,
+    /// Base64 attestation object (App Attest) or the integrity token
+    /// (Play Integrity).
+    pub token: String,
+    /// The challenge from `/attest/challenge` bound into `token`.
+    pub challenge: String,
+}
+
+/// The app an attestation proved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct VerifiedApp {
+    pub platform: Platform,
+    /// `TEAMID.bundle.id` for App Attest, the package name for Play.
+    pub app_id: String,
+}
+
+/// Single-use challenges handed out by `/attest/challenge`.
+#[derive(Default)]
+pub(crate) struct Challenges {
+    issued: Mutex<HashMap<String, Instant>>,
+}
+
+impl Challenges {
+    /// A fresh challenge, or `None` when too many are outstanding.
+    pub(crate) fn issue(&self) -> Option<String> {
+        let mut issued = self.issued.lock().unwrap();
+        if issued.len() >= MAX_CHALLENGES {
+            let now = Instant::now();
+            issued.retain(|_, expires| *expires > now);
+            if issued.len() >= MAX_CHALLENGES {
+                return None;
+            }
+        }
+        let challenge = generate_random_string(32);
+        issued.insert(challenge.clone(), Instant::now() + CHALLENGE_TTL);
+        Some(challenge)
+    }
+
+    /// Spend `challenge`; true if it was issued and hasn't expired.
+    fn redeem(&self, challenge: &str) -> bool {
+        self.issued
+            .lock()
+            .unwrap()
+            .remove(challenge)
+            .is_some_and(|expires| expires > Instant::now())
+    }
+}
+
+impl AttestationConfig {
+    /// Read `BROKER_ATTESTATION` (`off`, `optional` or `required`) and the
+    /// platform settings; see [`AppAttestConfig::from_env`] and
+    /// [`PlayIntegrityConfig::from_env`].
+    pub fn from_env() -> Result<Self, anyhow::Error> {
+        let mode = std::env::var("BROKER_ATTESTATION")
+            .unwrap_or_default()
+            .parse()?;
+        let config = Self {
+            mode,
+            app_attest: AppAttestConfig::from_env()?,
+            play_integrity: PlayIntegrityConfig::from_env()?,
+        };
+        if mode != AttestationMode::Off
+            && config.app_attest.is_none()
+            && config.play_integrity.is_none()
+        {
+            anyhow::bail!(
+                "BROKER_ATTESTATION={mode:?} but neither App Attest nor Play Integrity is configured"
+            );
+        }
+        Ok(config)
+    }
+
+    /// Apply the policy to a mint request. `exempt` requests (web logins
+    /// and their sessions; see the module docs) are only refused for an
+    /// invalid attestation, never a missing one. Returns the verified
+    /// app, if any.
+    pub(crate) fn check(
+        &self,
+        challenges: &Challenges,
+        attestation: Option<&Attestation>,
+        exempt: bool,
+    ) -> Result<Option<VerifiedApp>, anyhow::Error> {
+        if self.mode == AttestationMode::Off {
+            return Ok(None);
+        }
+        match attestation {
+            Some(attestation) => self.verify(challenges, attestation).map(Some),
+            None if self.mode == AttestationMode::Required && !exempt => {
+                anyhow::bail!("client attestation required")
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn verify(
+        &self,
+        challenges: &Challenges,
+        attestation: &Attestation,
+    ) -> Result<VerifiedApp, anyhow::Error> {
+        if !challenges.redeem(&attestation.challenge) {
+            anyhow::bail!("unknown or expired challenge");
+        }
+        let app_id = match attestation.platform {
+            Platform::AppAttest => {
+                let Some(config) = &self.app_attest else {
+                    anyhow::bail!("App Attest is not configured");
+                };
+                app_attest::verify(config, &attestation.token, &attestation.challenge)?
+            }
+            Platform::PlayIntegrity => {
+                let Some(config) = &self.play_integrity else {
+                    anyhow::bail!("Play Integrity is not configured");
+                };
+                play_integrity::verify(config, &attestation.token, &attestation.challenge)?
+            }
+        };
+        Ok(VerifiedApp {
+            platform: attestation.platform,
+            app_id,
+        })
+    }
+}
+
+/// Decode standard or URL-safe base64, padded or not.
+pub(crate) fn decode_base64(s: &str) -> Result<Vec<u8>, anyhow::Error> {
+    let s = s.trim().trim_end_matches('=');
+    let engine = if s.contains(['+', '/']) {
+        &base64::engine::general_purpose::STANDARD_NO_PAD
+    } else {
+        &base64::engine::general_purpose::URL_SAFE_NO_PAD
+    };
+    engine
+        .decode(s)
+        .map_err(|e| anyhow::anyhow!("invalid base64: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn challenges_are_single_use() {
+        let challenges = Challenges::default();
+        let c = challenges.issue().unwrap();
+        assert!(challenges.redeem(&c));
+        assert!(!challenges.redeem(&c));
+        assert!(!challenges.redeem("never-issued"));
+    }
+
+    #[test]
+    fn policy_only_refuses_missing_attestations_when_required() {
+        let challenges = Challenges::default();
+        let mut config = AttestationConfig::default();
+        assert!(config.check(&challenges, None, false).unwrap().is_none());
+
+        config.mode = AttestationMode::Optional;
+        assert!(config.check(&challenges, None, false).unwrap().is_none());
+
+        config.mode = AttestationMode::Required;
+        assert!(config.check(&challenges, None, false).is_err());
+        assert!(config.check(&challenges, None, true).unwrap().is_none());
+
+        // Present but unverifiable is refused even for exempt requests.
+        let attestation = Attestation {
+            platform: Platform::PlayIntegrity,
+            token: "x".to_string(),
+            challenge: challenges.issue().unwrap(),
+        };
+        let err = config
+            .check(&challenges, Some(&attestation), true)
+            .unwrap_err();
+        assert!(err.to_string().contains("not configured"), "{err}");
+    }
+
+    #[test]
+    fn mode_parses() {
+        assert_eq!("".parse::<AttestationMode>().unwrap(), AttestationMode::Off);
+        assert_eq!(
+            "Required".parse::<AttestationMode>().unwrap(),
+            AttestationMode::Required
+        );
+        assert!("always".parse::<AttestationMode>().is_err());
+    }
+
+    #[test]
+    fn base64_variants_decode() {
+        assert_eq!(decode_base64("+/8=").unwrap(), vec![0xfb, 0xff]);
+        assert_eq!(decode_base64("-_8").unwrap(), vec![0xfb, 0xff]);
+    }
+}
@@ -0,0 +1,366 @@
+//! Signing keys for `private_key_jwt` client authentication.
+//!
+//! A confidential broker proves itself at authorization servers' PAR and
+//! token endpoints with a short-lived ES256 client assertion (RFC 7523)
+//! signed by a key published at `/jwks.json`. Keys live in the
+//! [`BrokerStore`], encrypted like session credentials, and rotate on
+//! their own:
+//!
+//! - A successor is published [`PUBLISH_LEAD`] before it takes over, so
+//!   authorization servers holding a cached JWKS have already seen it.
+//! - New sessions sign with the newest key published at least that long;
+//!   older keys are retired when it takes over.
+//! - A session signs its refreshes with the key it was created with
+//!   (authorization servers bind sessions to it), so retired keys stay
+//!   published for [`RETIRED_KEEP`], the longest a refresh token lives,
+//!   before they're deleted.
+
+use std::sync::{Arc, Mutex};
+
+use base64::Engine;
+use p256::ecdsa::{Signature, SigningKey, signature::Signer};
+use sha2::{Digest, Sha256};
+
+use crate::crypto::{decrypt_field, encrypt_field, generate_random_string};
+use crate::store::{BrokerStore, StoredClientKey};
+
+/// How long a successor key is published before it signs anything.
+const PUBLISH_LEAD: i64 = 24 * 3600;
+
+/// How long a retired key stays published.
+const RETIRED_KEEP: i64 = 180 * 24 * 3600;
+
+/// How often the key set is reloaded and rotation re-checked.
+const CHECK_INTERVAL: i64 = 3600;
+
+/// Lifetime of a client assertion.
+const ASSERTION_TTL: i64 = 60;
+
+/// `client_assertion_type` for a JWT client assertion.
+pub(crate) const ASSERTION_TYPE: &str = "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+
+#[derive(Clone)]
+pub(crate) struct ClientKey {
+    /// RFC 7638 thumbprint of the public key.
+    pub kid: String,
+    signing_key: SigningKey,
+    created_at: i64,
+    retired_at: Option<i64>,
+}
+
+impl ClientKey {
+    fn generate(now: i64) -> Self {
+        let signing_key = SigningKey::random(&mut rand::thread_rng());
+        Self {
+            kid: thumbprint(&signing_key),
+            signing_key,
+            created_at: now,
+            retired_at: None,
+        }
+    }
+
+    /// The public key as published in the JWKS.
+    fn jwk(&self) -> serde_json::Value {
+        let (x, y) = coordinates(&self.signing_key);
+        serde_json::json!({
+            "kty": "EC",
+            "crv": "P-256",
+            "x": x,
+            "y": y,
+            "kid": self.kid,
+            "use": "sig",
+            "alg": "ES256",
+        })
+    }
+
+    /// A client assertion for `client_id` at the authorization server
+    /// `issuer`. Each request needs a fresh one (the `jti` is single-use).
+    pub(crate) fn assertion(&self, client_id: &str, issuer: &str) -> Result<String, anyhow::Error> {
+        let b64 = &base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        let header = serde_json::json!({
+            "typ": "JWT",
+            "alg": "ES256",
+            "kid": self.kid,
+        });
+        let now = chrono::Utc::now().timestamp();
+        let claims = serde_json::json!({
+            "iss": client_id,
+            "sub": client_id,
+            "aud": issuer,
+            "jti": generate_random_string(16),
+            "iat": now,
+            "exp": now + ASSERTION_TTL,
+        });
+        let signing_input = format!(
+            "{}.{}",
+            b64.encode(serde_json::to_vec(&header)?),
+            b64.encode(serde_json::to_vec(&claims)?)
+        );
+        let signature: Signature = self.signing_key.sign(signing_input.as_bytes());
+        Ok(format!(
+            "{signing_input}.{}",
+            b64.encode(signature.to_bytes())
+        ))
+    }
+}
+
+fn coordinates(signing_key: &SigningKey) -> (String, String) {
+    let b64 = &base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    let point = signing_key.verifying_key().to_encoded_point(false);
+    (
+        b64.encode(point.x().unwrap()),
+        b64.encode(point.y().unwrap()),
+    )
+}
+
+/// RFC 7638 JWK thumbprint: SHA-256 of the required members in
+/// lexicographic order.
+fn thumbprint(signing_key: &SigningKey) -> String {
+    let (x, y) = coordinates(signing_key);
+    let canonical = format!(r#"{{"crv":"P-256","kty":"EC","x":"{x}","y":"{y}"}}"#);
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(Sha256::digest(canonical))
+}
+
+/// The key new sessions sign with: the newest live key that has been
+/// published for [`PUBLISH_LEAD`], or the newest at all on a fresh store.
+fn signing_key(keys: &[ClientKey], now: i64) -> Option<&ClientKey> {
+    let mut live = keys.iter().filter(|k| k.retired_at.is_none()).rev();
+    let newest = live.clone().next();
+    live.find(|k| k.created_at <= now - PUBLISH_LEAD).or(newest)
+}
+
+/// The broker's client signing keys, kept rotated.
+pub(crate) struct ClientKeys {
+    store: Arc<dyn BrokerStore>,
+    encryption_key: [u8; 32],
+    /// Seconds a key signs new sessions before a successor takes over.
+    rotation: i64,
+    /// `(checked_at, keys)`, oldest key first.
+    loaded: Mutex<Option<(i64, Vec<ClientKey>)>>,
+}
+
+impl ClientKeys {
+    pub(crate) fn new(
+        store: Arc<dyn BrokerStore>,
+        encryption_key: [u8; 32],
+        rotation: std::time::Duration,
+    ) -> Self {
+        Self {
+            store,
+            encryption_key,
+            rotation: rotation.as_secs() as i64,
+            loaded: Mutex::new(None),
+        }
+    }
+
+    /// The key to sign a new session with.
+    pub(crate) fn current(&self) -> Result<ClientKey, anyhow::Error> {
+        let now = chrono::Utc::now().timestamp();
+        let keys = self.keys(now)?;
+        signing_key(&keys, now)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("no client signing key"))
+    }
+
+    /// A published key by id.
+    pub(crate) fn get(&self, kid: &str) -> Result<Option<ClientKey>, anyhow::Error> {
+        let keys = self.keys(chrono::Utc::now().timestamp())?;
+        Ok(keys.into_iter().find(|k| k.kid == kid))
+    }
+
+    /// The JWKS document served at `/jwks.json`.
+    pub(crate) fn jwks(&self) -> Result<serde_json::Value, anyhow::Error> {
+        let keys = self.keys(chrono::Utc::now().timestamp())?;
+        let keys: Vec<_> = keys.iter().rev().map(ClientKey::jwk).collect();
+        Ok(serde_json::json!({ "keys": keys }))
+    }
+
+    /// The published keys, rotating first when the last check is stale.
+    fn keys(&self, now: i64) -> Result<Vec<ClientKey>, anyhow::Error> {
+        let mut loaded = self.loaded.lock().unwrap();
+        if let Some((checked_at, keys)) = loaded.as_ref()
+            && now - checked_at < CHECK_INTERVAL
+        {
+            return Ok(keys.clone());
+        }
+        let keys = self.rotate(now)?;
+        *loaded = Some((now, keys.clone()));
+        Ok(keys)
+    }
+
+    /// Bring the stored keys up to date as of `now`: delete expired ones,
+    /// retire those the signing key has replaced, and publish a successor
+    /// when the signing key is due to hand over.
+    fn rotate(&self, now: i64) -> Result<Vec<ClientKey>, anyhow::Error> {
+        let mut keys = Vec::new();
+        for stored in self.store.list_client_keys()? {
+            if stored.retired_at.is_some_and(|at| at < now - RETIRED_KEEP) {
+                tracing::info!(kid = %stored.kid, "Deleting expired client signing key");
+                self.store.delete_client_key(&stored.kid)?;
+                continue;
+            }
+            match self.decrypt(&stored) {
+                Ok(key) => keys.push(key),
+                Err(e) => {
+                    // Encrypted under another shared secret; nothing can
+                    // sign with it any more.
+                    tracing::warn!(kid = %stored.kid, error = %e, "Deleting unreadable client signing key");
+                    self.store.delete_client_key(&stored.kid)?;
+                }
+            }
+        }
+
+        if keys.iter().all(|k| k.retired_at.is_some()) {
+            keys.push(self.insert(now)?);
+        }
+        let signing = signing_key(&keys, now).map(|k| (k.kid.clone(), k.created_at));
+        if let Some((kid, created_at)) = signing {
+            for key in keys.iter_mut() {
+                if key.retired_at.is_none() && key.created_at < created_at {
+                    tracing::info!(kid = %key.kid, successor = %kid, "Retiring client signing key");
+                    self.store.retire_client_key(&key.kid, now)?;
+                    key.retired_at = Some(now);
+                }
+            }
+            let has_successor = keys.iter().any(|k| k.created_at > created_at);
+            if !has_successor && now - created_at >= self.rotation - PUBLISH_LEAD {
+                let successor = self.insert(now)?;
+                tracing::info!(kid = %successor.kid, "Published successor client signing key");
+                keys.push(successor);
+            }
+        }
+        Ok(keys)
+    }
+
+    fn insert(&self, now: i64) -> Result<ClientKey, anyhow::Error> {
+        let key = ClientKey::generate(now);
+        let private_key =
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(key.signing_key.to_bytes());
+        self.store.insert_client_key(&StoredClientKey {
+            kid: key.kid.clone(),
+            private_key: encrypt_field(&self.encryption_key, &private_key),
+            created_at: now,
+            retired_at: None,
+        })?;
+        Ok(key)
+    }
+
+    fn decrypt(&self, stored: &StoredClientKey) -> Result<ClientKey, anyhow::Error> {
+        let private_key = decrypt_field(&self.encryption_key, &stored.private_key)?;
+        let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(private_key)?;
+        let signing_key = SigningKey::from_slice(&bytes)
+            .map_err(|e| anyhow::anyhow!("invalid client key: {e}"))?;
+        Ok(ClientKey {
+            kid: stored.kid.clone(),
+            signing_key,
+            created_at: stored.created_at,
+            retired_at: stored.retired_at,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::SqliteStore;
+    use p256::ecdsa::{VerifyingKey, signature::Verifier};
+
+    const DAY: i64 = 24 * 3600;
+
+    fn keys() -> ClientKeys {
+        ClientKeys::new(
+            Arc::new(SqliteStore::open_in_memory().unwrap()),
+            [7; 32],
+            std::time::Duration::from_secs(30 * DAY as u64),
+        )
+    }
+
+    fn kids(keys: &[ClientKey]) -> Vec<&str> {
+        keys.iter().map(|k| k.kid.as_str()).collect()
+    }
+
+    #[test]
+    fn keys_rotate_with_a_published_lead_and_a_retired_tail() {
+        let keys = keys();
+        let t0 = 1_000_000_000;
+
+        let first = keys.rotate(t0).unwrap();
+        assert_eq!(first.len(), 1);
+        let a = first[0].kid.clone();
+        assert_eq!(signing_key(&first, t0).unwrap().kid, a);
+
+        // Nothing changes mid-period.
+        assert_eq!(kids(&keys.rotate(t0 + 10 * DAY).unwrap()), [a.as_str()]);
+
+        // A day before the handover the successor is published, unused.
+        let published = keys.rotate(t0 + 29 * DAY).unwrap();
+        assert_eq!(published.len(), 2);
+        let b = published[1].kid.clone();
+        assert_eq!(signing_key(&published, t0 + 29 * DAY).unwrap().kid, a);
+
+        // A day later it signs and the old key is retired, still published.
+        let t1 = t0 + 30 * DAY;
+        let handed_over = keys.rotate(t1).unwrap();
+        assert_eq!(kids(&handed_over), [a.as_str(), b.as_str()]);
+        assert_eq!(signing_key(&handed_over, t1).unwrap().kid, b);
+        assert_eq!(handed_over[0].retired_at, Some(t1));
+
+        // The retired key is deleted once its sessions' refresh tokens
+        // can no longer be alive.
+        let later = keys.rotate(t1 + RETIRED_KEEP + 1).unwrap();
+        assert!(!kids(&later).contains(&a.as_str()));
+        assert!(kids(&later).contains(&b.as_str()));
+    }
+
+    #[test]
+    fn keys_survive_a_reload_and_other_secrets_cannot_read_them() {
+        let store: Arc<dyn BrokerStore> = Arc::new(SqliteStore::open_in_memory().unwrap());
+        let rotation = std::time::Duration::from_secs(30 * DAY as u64);
+        let now = chrono::Utc::now().timestamp();
+        let a = ClientKeys::new(store.clone(), [7; 32], rotation)
+            .current()
+            .unwrap();
+        let reloaded = ClientKeys::new(store.clone(), [7; 32], rotation);
+        assert_eq!(reloaded.current().unwrap().kid, a.kid);
+        assert!(reloaded.get(&a.kid).unwrap().is_some());
+
+        let other = ClientKeys::new(store, [8; 32], rotation);
+        let replaced = other.rotate(now).unwrap();
+        assert_eq!(replaced.len(), 1);
+        assert_ne!(replaced[0].kid, a.kid);
+    }
+
+    #[test]
+    fn assertion_verifies_against_the_published_jwk() {
+        let keys = keys();
+        let key = keys.current().unwrap();
+        let jwks = keys.jwks().unwrap();
+        let jwk = &jwks["keys"][0];
+        assert_eq!(jwk["kid"], key.kid.as_str());
+        assert!(jwk.get("d").is_none());
+
+        let b64 = &base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        let jwt = key
+            .assertion(
+                "https://auth.example/client-metadata.json",
+                "https://pds.example",
+            )
+            .unwrap();
+        let (signed, signature) = jwt.rsplit_once('.').unwrap();
+        let (header, claims) = signed.split_once('.').unwrap();
+        let header: serde_json::Value =
+            serde_json::from_slice(&b64.decode(header).unwrap()).unwrap();
+        let claims: serde_json::Value =
+            serde_json::from_slice(&b64.decode(claims).unwrap()).unwrap();
+        assert_eq!(header["kid"], key.kid.as_str());
+        assert_eq!(claims["iss"], claims["sub"]);
+        assert_eq!(claims["aud"], "https://pds.example");
+
+        let mut point = vec![4];
+        point.extend(b64.decode(jwk["x"].as_str().unwrap()).unwrap());
+        point.extend(b64.decode(jwk["y"].as_str().unwrap()).unwrap());
+        let verifying_key = VerifyingKey::from_sec1_bytes(&point).unwrap();
+        let signature = Signature::from_slice(&b64.decode(signature).unwrap()).unwrap();
+        verifying_key.verify(signed.as_bytes(), &signature).unwrap();
+    }
+}
@@ -0,0 +1,293 @@
+//! Typed broker configuration.
+
+use crate::attest::AttestationConfig;
+
+/// Origins allowed to call `/session` and to receive CORS responses when
+/// no explicit list is configured.
+pub const DEFAULT_ALLOWED_ORIGINS: &[&str] = &[
+    "https://irc.freeq.at",
+    "https://revenant-watch.boxd.sh",
+    "http://localhost:5173",
+    "http://localhost:8000",
+    "http://127.0.0.1:5173",
+];
+
+/// URL prefixes accepted as a post-login `return_to` when no explicit
+/// list is configured. Relative URLs are always accepted.
+pub const DEFAULT_RETURN_TO_PREFIXES: &[&str] = &[
+    "https://irc.freeq.at",
+    "https://staging.freeq.at",
+    "https://revenant-watch.boxd.sh",
+    "http://localhost:",
+    "http://localhost/",
+    "http://127.0.0.1:",
+    "http://127.0.0.1/",
+];
+
+/// The deployment a broker runs as. Picks the defaults for the public
+/// URL and `return_to`, and the client name authorization servers show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Environment {
+    /// Local development: a loopback public URL and a public client.
+    Dev,
+    Staging,
+    Prod,
+}
+
+impl std::str::FromStr for Environment {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "dev" | "development" => Ok(Self::Dev),
+            "staging" => Ok(Self::Staging),
+            "prod" | "production" => Ok(Self::Prod),
+            other => anyhow::bail!("unknown environment {other:?} (dev, staging, prod)"),
+        }
+    }
+}
+
+impl Environment {
+    /// The broker's URL when `BROKER_PUBLIC_URL` is unset. Staging has no
+    /// default: its URL must be configured.
+    pub fn default_public_url(self) -> Option<&'static str> {
+        match self {
+            Self::Dev => Some("http://127.0.0.1:8081"),
+            Self::Staging => None,
+            Self::Prod => Some("https://auth.freeq.at"),
+        }
+    }
+
+    /// Where non-mobile logins land when `BROKER_DEFAULT_RETURN_TO` is unset.
+    pub fn default_return_to(self) -> &'static str {
+        match self {
+            Self::Dev => "http://127.0.0.1:5173",
+            Self::Staging => "https://staging.freeq.at",
+            Self::Prod => "https://irc.freeq.at",
+        }
+    }
+
+    /// `client_name` in the client metadata.
+    pub fn client_name(self) -> &'static str {
+        match self {
+            Self::Dev => "freeq-auth-broker (dev)",
+            Self::Staging => "freeq-auth-broker (staging)",
+            Self::Prod => "freeq-auth-broker",
+        }
+    }
+
+    /// Dev for a loopback or private-network URL, prod otherwise.
+    fn of_url(public_url: &str) -> Self {
+        if is_loopback_origin(public_url) {
+            Self::Dev
+        } else {
+            Self::Prod
+        }
+    }
+}
+
+/// How the broker authenticates to authorization servers' token and PAR
+/// endpoints.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ClientAuth {
+    /// A public client (`token_endpoint_auth_method: none`).
+    #[default]
+    None,
+    /// A confidential client signing ES256 client assertions with keys
+    /// published at `/jwks.json`.
+    PrivateKeyJwt,
+}
+
+impl std::str::FromStr for ClientAuth {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "" | "none" => Ok(Self::None),
+            "private_key_jwt" => Ok(Self::PrivateKeyJwt),
+            other => anyhow::bail!("unknown client auth {other:?} (none, private_key_jwt)"),
+        }
+    }
+}
+
+/// Default for [`BrokerConfig::client_key_rotation`].
+pub const DEFAULT_CLIENT_KEY_ROTATION: std::time::Duration =
+    std::time::Duration::from_secs(30 * 24 * 3600);
+
+#[derive(Debug, Clone)]
+pub struct BrokerConfig {
+    /// Public base URL of the broker (OAuth client_id and redirect_uri are
+    /// derived from it).
+    pub public_url: String,
+    /// Which deployment this is; see [`Environment`].
+    pub environment: Environment,
+    /// Token-endpoint client authentication.
+    pub client_auth: ClientAuth,
+    /// How long a `private_key_jwt` signing key is used before the next
+    /// one takes over.
+    pub client_key_rotation: std::time::Duration,
+    /// Base URL of the freeq server that web tokens and sessions are
+    /// pushed to.
+    pub freeq_server_url: String,
+    /// HMAC secret shared with the freeq server. Also the root of the
+    /// at-rest encryption key for stored refresh tokens.
+    pub shared_secret: String,
+    /// Origins allowed for CORS and for `POST /session` (CSRF check).
+    /// Web logins whose tokens land on one of them are the only ones
+    /// exempt from client attestation.
+    pub allowed_origins: Vec<String>,
+    /// Prefixes a `return_to` URL must start with (open-redirect guard).
+    pub return_to_prefixes: Vec<String>,
+    /// Where the web app lives; used as the `return_to` fallback for
+    /// non-mobile logins.
+    pub default_return_to: String,
+    /// Mobile app attestation required (or checked) before minting.
+    pub attestation: AttestationConfig,
+}
+
+impl BrokerConfig {
+    pub fn new(
+        public_url: impl Into<String>,
+        freeq_server_url: impl Into<String>,
+        shared_secret: impl Into<String>,
+    ) -> Self {
+        let public_url = public_url.into();
+        let environment = Environment::of_url(&public_url);
+        Self {
+            public_url,
+            environment,
+            client_auth: ClientAuth::None,
+            client_key_rotation: DEFAULT_CLIENT_KEY_ROTATION,
+            freeq_server_url: freeq_server_url.into(),
+            shared_secret: shared_secret.into(),
+            allowed_origins: DEFAULT_ALLOWED_ORIGINS
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            return_to_prefixes: DEFAULT_RETURN_TO_PREFIXES
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            default_return_to: environment.default_return_to().to_string(),
+            attestation: AttestationConfig::default(),
+        }
+    }
+
+    /// Read configuration from `BROKER_*` / `FREEQ_SERVER_URL` env vars.
+    ///
+    /// `BROKER_ALLOWED_ORIGINS` and `BROKER_RETURN_TO_PREFIXES` are
+    /// comma-separated and replace the defaults when set. `BROKER_ENV`
+    /// (`dev`, `staging` or `prod`) picks the defaults for the rest; unset,
+    /// it follows `BROKER_PUBLIC_URL` (dev for loopback, prod otherwise, dev
+    /// when that is unset too). `BROKER_CLIENT_AUTH=private_key_jwt` makes
+    /// the broker a confidential client whose signing key rotates every
+    /// `BROKER_CLIENT_KEY_ROTATION_DAYS` (30). Attestation is read by
+    /// [`AttestationConfig::from_env`].
+    pub fn from_env() -> Result<Self, anyhow::Error> {
+        let public_url = std::env::var("BROKER_PUBLIC_URL")
+            .ok()
+            .filter(|v| !v.is_empty());
+        let environment = match std::env::var("BROKER_ENV") {
+            Ok(v) if !v.is_empty() => v.parse()?,
+            _ => public_url
+                .as_deref()
+                .map_or(Environment::Dev, Environment::of_url),
+        };
+        let Some(public_url) =
+            public_url.or_else(|| environment.default_public_url().map(|url| url.to_string()))
+        else {
+            anyhow::bail!("BROKER_ENV={environment:?} needs BROKER_PUBLIC_URL set");
+        };
+        let freeq_server_url = std::env::var("FREEQ_SERVER_URL")
+            .unwrap_or_else(|_| "https://irc.freeq.at".to_string());
+        let shared_secret = std::env::var("BROKER_SHARED_SECRET").unwrap_or_default();
+        if shared_secret.is_empty() {
+            anyhow::bail!(
+                "BROKER_SHARED_SECRET not set — refusing to start. Set this env var to a strong random secret."
+            );
+        }
+
+        let mut config = Self::new(public_url, freeq_server_url, shared_secret);
+        config.environment = environment;
+        config.default_return_to = environment.default_return_to().to_string();
+        // The environment's own web app may always call in.
+        if !config
+            .allowed_origins
+            .iter()
+            .any(|o| o == &config.default_return_to)
+        {
+            config
+                .allowed_origins
+                .push(config.default_return_to.clone());
+        }
+        config.client_auth = std::env::var("BROKER_CLIENT_AUTH")
+            .unwrap_or_default()
+            .parse()?;
+        if config.client_auth == ClientAuth::PrivateKeyJwt && is_loopback_origin(&config.public_url)
+        {
+            anyhow::bail!(
+                "BROKER_CLIENT_AUTH=private_key_jwt needs a public BROKER_PUBLIC_URL (loopback clients are public)"
+            );
+        }
+        if let Ok(v) = std::env::var("BROKER_CLIENT_KEY_ROTATION_DAYS")
+            && !v.is_empty()
+        {
+            let days: u64 = v
+                .parse()
+                .map_err(|_| anyhow::anyhow!("BROKER_CLIENT_KEY_ROTATION_DAYS must be a number"))?;
+            // A successor key is published a day before it takes over.
+            if days < 2 {
+                anyhow::bail!("BROKER_CLIENT_KEY_ROTATION_DAYS must be at least 2");
+            }
+            config.client_key_rotation = std::time::Duration::from_secs(days * 24 * 3600);
+        }
+        if let Some(origins) = env_list("BROKER_ALLOWED_ORIGINS") {
+            config.allowed_origins = origins;
+        }
+        if let Some(prefixes) = env_list("BROKER_RETURN_TO_PREFIXES") {
+            config.return_to_prefixes = prefixes;
+        }
+        if let Ok(v) = std::env::var("BROKER_DEFAULT_RETURN_TO")
+            && !v.is_empty()
+        {
+            config.default_return_to = v;
+        }
+        config.attestation = AttestationConfig::from_env()?;
+        Ok(config)
+    }
+
+    /// The allowed origin that `url` is on, if any.
+    pub fn allowed_origin_of(&self, url: &str) -> Option<String> {
+        let origin = url::Url::parse(url).ok()?.origin().ascii_serialization();
+        self.allowed_origins.contains(&origin).then_some(origin)
+    }
+
+    /// Validate return_to against the allowlist to prevent open redirects.
+    pub fn is_valid_return_to(&self, url: &str) -> bool {
+        // Allow relative URLs
+        if url.starts_with('/') {
+            return true;
+        }
+        self.return_to_prefixes
+            .iter()
+            .any(|prefix| url.starts_with(prefix.as_str()))
+    }
+}
+
+/// Origins that get the loopback client_id (a public client with no
+/// metadata document) instead of `{public_url}/client-metadata.json`.
+pub(crate) fn is_loopback_origin(url: &str) -> bool {
+    url.starts_with("http://127.")
+        || url.starts_with("http://192.168.")
+        || url.starts_with("http://10.")
+}
+
+pub(crate) fn env_list(name: &str) -> Option<Vec<String>> {
+    let raw = std::env::var(name).ok()?;
+    let items: Vec<String> = raw
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect();
+    if items.is_empty() { None } else { Some(items) }
+}
@@ -0,0 +1,88 @@
+//! Session-field encryption, request signing, and random token helpers.
+
+use aes_gcm::aead::Aead;
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use base64::Engine;
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+/// Derive a 256-bit encryption key from the shared secret using HKDF-SHA256.
+pub(crate) fn derive_encryption_key(shared_secret: &str) -> [u8; 32] {
+    let hk = Hkdf::<Sha256>::new(None, shared_secret.as_bytes());
+    let mut key = [0u8; 32];
+    hk.expand(b"freeq-broker-session-encryption-v1", &mut key)
+        .expect("HKDF expand failed");
+    key
+}
+
+/// Encrypt a plaintext string with AES-256-GCM. Returns base64url(nonce || ciphertext).
+pub(crate) fn encrypt_field(key: &[u8; 32], plaintext: &str) -> String {
+    use rand::RngCore;
+    let cipher = Aes256Gcm::new(key.into());
+    let mut nonce_bytes = [0u8; 12];
+    rand::thread_rng().fill_bytes(&mut nonce_bytes);
+    let nonce = Nonce::from_slice(&nonce_bytes);
+    let ciphertext = cipher
+        .encrypt(nonce, plaintext.as_bytes())
+        .expect("AES-GCM encryption failed");
+    let mut combined = nonce_bytes.to_vec();
+    combined.extend_from_slice(&ciphertext);
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(&combined)
+}
+
+/// Decrypt a field previously encrypted with encrypt_field.
+pub(crate) fn decrypt_field(key: &[u8; 32], encoded: &str) -> Result<String, anyhow::Error> {
+    let combined = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(encoded)
+        .map_err(|e| anyhow::anyhow!("base64 decode failed: {e}"))?;
+    if combined.len() < 13 {
+        return Err(anyhow::anyhow!("encrypted field too short"));
+    }
+    let (nonce_bytes, ciphertext) = combined.split_at(12);
+    let cipher = Aes256Gcm::new(key.into());
+    let nonce = Nonce::from_slice(nonce_bytes);
+    let plaintext = cipher
+        .decrypt(nonce, ciphertext)
+        .map_err(|e| anyhow::anyhow!("AES-GCM decryption failed: {e}"))?;
+    String::from_utf8(plaintext).map_err(|e| anyhow::anyhow!("UTF-8 decode failed: {e}"))
+}
+
+/// Sign a request body with HMAC-SHA256. Returns (signature, timestamp) pair.
+/// The MAC covers `ts={timestamp}\n` || body_bytes to prevent replay attacks.
+pub(crate) fn sign_body(
+    secret: &str,
+    body: &serde_json::Value,
+) -> Result<(String, String), anyhow::Error> {
+    use base64::Engine;
+    use hmac::{Hmac, Mac};
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        .to_string();
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(secret.as_bytes())?;
+    let bytes = serde_json::to_vec(body)?;
+    mac.update(format!("ts={timestamp}\n").as_bytes());
+    mac.update(&bytes);
+    Ok((
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes()),
+        timestamp,
+    ))
+}
+
+pub(crate) fn generate_pkce() -> (String, String) {
+    use base64::Engine;
+    use sha2::{Digest, Sha256};
+    let verifier = generate_random_string(32);
+    let hash = Sha256::digest(verifier.as_bytes());
+    let challenge = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(hash);
+    (verifier, challenge)
+}
+
+pub(crate) fn generate_random_string(len: usize) -> String {
+    use base64::Engine;
+    use rand::RngCore;
+    let mut bytes = vec![0u8; len];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(&bytes)
+}
@@ -0,0 +1,89 @@
+//! DPoP (RFC 9449) proof-of-possession keys for the upstream PDS token calls.
+
+use base64::Engine;
+use p256::ecdsa::SigningKey;
+
+use crate::crypto::generate_random_string;
+
+#[derive(Debug, Clone)]
+pub(crate) struct DpopKey {
+    signing_key: SigningKey,
+}
+
+impl DpopKey {
+    pub(crate) fn generate() -> Self {
+        let signing_key = SigningKey::random(&mut rand::thread_rng());
+        Self { signing_key }
+    }
+
+    pub(crate) fn to_base64url(&self) -> String {
+        use base64::Engine;
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(self.signing_key.to_bytes())
+    }
+
+    pub(crate) fn from_base64url(s: &str) -> Result<Self, anyhow::Error> {
+        use base64::Engine;
+        let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(s)?;
+        let signing_key =
+            SigningKey::from_slice(&bytes).map_err(|e| anyhow::anyhow!("Invalid DPoP key: {e}"))?;
+        Ok(Self { signing_key })
+    }
+
+    pub(crate) fn jwk(&self) -> serde_json::Value {
+        let verifying_key = self.signing_key.verifying_key();
+        let point = verifying_key.to_encoded_point(false);
+        let x = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(point.x().unwrap());
+        let y = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(point.y().unwrap());
+        serde_json::json!({
+            "kty": "EC",
+            "crv": "P-256",
+            "x": x,
+            "y": y,
+        })
+    }
+
+    pub(crate) fn proof(
+        &self,
+        method: &str,
+        url: &str,
+        nonce: Option<&str>,
+        access_token: Option<&str>,
+    ) -> Result<String, anyhow::Error> {
+        use base64::Engine;
+        use p256::ecdsa::{Signature, signature::Signer};
+        use sha2::{Digest, Sha256};
+
+        let header = serde_json::json!({
+            "typ": "dpop+jwt",
+            "alg": "ES256",
+            "jwk": self.jwk(),
+        });
+
+        let mut payload = serde_json::json!({
+            "jti": generate_random_string(16),
+            "htm": method,
+            "htu": url,
+            "iat": chrono::Utc::now().timestamp(),
+        });
+        if let Some(nonce) = nonce {
+            payload["nonce"] = serde_json::Value::String(nonce.to_string());
+        }
+        if let Some(token) = access_token {
+            let hash = Sha256::digest(token.as_bytes());
+            payload["ath"] = serde_json::Value::String(
+                base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(hash),
+            );
+        }
+
+        let header_b64 =
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?);
+        let payload_b64 =
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(serde_json::to_vec(&payload)?);
+        let signing_input = format!("{header_b64}.{payload_b64}");
+
+        let sig: Signature = self.signing_key.sign(signing_input.as_bytes());
+        let sig_b64 = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(sig.to_bytes());
+
+        Ok(format!("{signing_input}.{sig_b64}"))
+    }
+}
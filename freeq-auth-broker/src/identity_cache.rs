@@ -0,0 +1,330 @@
+//! Handle→DID and DID→PDS resolution cache.
+//!
+//! Every `/auth/login` used to re-resolve the handle over HTTPS and fetch
+//! the DID document from plc.directory. Hot logins (the same user
+//! re-authenticating, a client retrying after a popup was closed) hammer
+//! those services for answers that almost never change.
+//!
+//! Lookups go through two tiers: an in-process map, backed by the
+//! [`BrokerStore`] identity table so restarts don't start cold.
+//! Entries have three phases:
+//!
+//! - **fresh** (`< FRESH_SECS` old): served directly.
+//! - **stale** (`< STALE_SECS` old): served directly, and a single
+//!   background refresh is spawned (stale-while-revalidate).
+//! - **expired**: resolved inline; the caller waits.
+//!
+//! Failures are cached too (`NEGATIVE_SECS`) so a typo'd handle being
+//! retried in a loop doesn't turn into one upstream request per attempt.
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::Arc;
+
+use crate::store::{BrokerStore, IdentityRecord};
+
+/// Positive entries younger than this are served without revalidation.
+const FRESH_SECS: i64 = 10 * 60;
+/// Positive entries younger than this are served while a refresh runs.
+const STALE_SECS: i64 = 24 * 60 * 60;
+/// How long a failed resolution is remembered.
+const NEGATIVE_SECS: i64 = 60;
+/// Upper bound on in-memory entries before expired ones are swept.
+const MAX_MEM_ENTRIES: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum LookupKind {
+    /// Handle → DID.
+    Handle,
+    /// DID → PDS endpoint.
+    Pds,
+}
+
+impl LookupKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            LookupKind::Handle => "handle",
+            LookupKind::Pds => "pds",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    /// `Ok(value)` for a successful resolution, `Err(message)` for a
+    /// negatively-cached failure.
+    value: Result<String, String>,
+    fetched_at: i64,
+}
+
+enum Freshness {
+    Fresh,
+    Stale,
+    Expired,
+}
+
+impl CacheEntry {
+    fn freshness(&self, now: i64) -> Freshness {
+        let age = now - self.fetched_at;
+        match &self.value {
+            Ok(_) if age < FRESH_SECS => Freshness::Fresh,
+            Ok(_) if age < STALE_SECS => Freshness::Stale,
+            Err(_) if age < NEGATIVE_SECS => Freshness::Fresh,
+            _ => Freshness::Expired,
+        }
+    }
+}
+
+pub(crate) struct IdentityCache {
+    store: Arc<dyn BrokerStore>,
+    mem: std::sync::Mutex<HashMap<(LookupKind, String), CacheEntry>>,
+    /// Keys with a background refresh in flight, so a burst of logins
+    /// against a stale entry only triggers one upstream request.
+    refreshing: std::sync::Mutex<HashSet<(LookupKind, String)>>,
+}
+
+impl IdentityCache {
+    pub(crate) fn new(store: Arc<dyn BrokerStore>) -> Self {
+        Self {
+            store,
+            mem: std::sync::Mutex::new(HashMap::new()),
+            refreshing: std::sync::Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Resolve `key` through the cache, calling `fetch` on a miss,
+    /// expiry, or (in the background) a stale hit.
+    pub(crate) async fn lookup<F, Fut>(
+        self: &Arc<Self>,
+        kind: LookupKind,
+        key: &str,
+        fetch: F,
+    ) -> Result<String, anyhow::Error>
+    where
+        F: FnOnce(String) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<String, anyhow::Error>> + Send + 'static,
+    {
+        let key = normalize_key(kind, key);
+        let now = chrono::Utc::now().timestamp();
+
+        let cached = match self.mem_get(kind, &key) {
+            Some(entry) => Some(entry),
+            None => self.store_get(kind, &key),
+        };
+
+        if let Some(entry) = cached {
+            match entry.freshness(now) {
+                Freshness::Fresh => {
+                    return entry.value.map_err(|e| anyhow::anyhow!("{e} (cached)"));
+                }
+                Freshness::Stale => {
+                    self.spawn_refresh(kind, key, fetch);
+                    return entry.value.map_err(|e| anyhow::anyhow!("{e} (cached)"));
+                }
+                Freshness::Expired => {}
+            }
+        }
+
+        let result = fetch(key.clone()).await;
+        self.store_put(kind, &key, &result);
+        result
+    }
+
+    fn spawn_refresh<F, Fut>(self: &Arc<Self>, kind: LookupKind, key: String, fetch: F)
+    where
+        F: FnOnce(String) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<String, anyhow::Error>> + Send + 'static,
+    {
+        if !self.refreshing.lock().unwrap().insert((kind, key.clone())) {
+            return;
+        }
+        let cache = Arc::clone(self);
+        tokio::spawn(async move {
+            let result = fetch(key.clone()).await;
+            // A failed revalidation keeps serving the stale value rather
+            // than replacing it with a negative entry — the upstream was
+            // reachable once and the answer rarely changes.
+            match &result {
+                Ok(_) => cache.store_put(kind, &key, &result),
+                Err(e) => {
+                    tracing::debug!(kind = kind.as_str(), key = %key, error = %e, "Identity cache revalidation failed");
+                }
+            }
+            cache.refreshing.lock().unwrap().remove(&(kind, key));
+        });
+    }
+
+    fn mem_get(&self, kind: LookupKind, key: &str) -> Option<CacheEntry> {
+        self.mem
+            .lock()
+            .unwrap()
+            .get(&(kind, key.to_string()))
+            .cloned()
+    }
+
+    fn store_get(&self, kind: LookupKind, key: &str) -> Option<CacheEntry> {
+        let record = match self.store.get_identity(kind.as_str(), key) {
+            Ok(record) => record?,
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to read identity cache entry");
+                return None;
+            }
+        };
+        let entry = CacheEntry {
+            value: record.value.ok_or_else(|| record.error.unwrap_or_default()),
+            fetched_at: record.fetched_at,
+        };
+        self.mem_put(kind, key, entry.clone());
+        Some(entry)
+    }
+
+    fn store_put(&self, kind: LookupKind, key: &str, result: &Result<String, anyhow::Error>) {
+        let entry = CacheEntry {
+            value: result.as_ref().cloned().map_err(|e| e.to_string()),
+            fetched_at: chrono::Utc::now().timestamp(),
+        };
+        self.mem_put(kind, key, entry.clone());
+
+        let record = IdentityRecord {
+            value: entry.value.as_ref().ok().cloned(),
+            error: entry.value.as_ref().err().cloned(),
+            fetched_at: entry.fetched_at,
+        };
+        if let Err(e) = self.store.put_identity(kind.as_str(), key, &record) {
+            tracing::warn!(error = %e, "Failed to persist identity cache entry");
+        }
+    }
+
+    fn mem_put(&self, kind: LookupKind, key: &str, entry: CacheEntry) {
+        let mut mem = self.mem.lock().unwrap();
+        if mem.len() >= MAX_MEM_ENTRIES {
+            let now = chrono::Utc::now().timestamp();
+            mem.retain(|_, e| !matches!(e.freshness(now), Freshness::Expired));
+            // Still full of live entries: drop everything rather than let
+            // the map grow without bound. SQLite keeps the warm copy.
+            if mem.len() >= MAX_MEM_ENTRIES {
+                mem.clear();
+            }
+        }
+        mem.insert((kind, key.to_string()), entry);
+    }
+
+    /// Delete rows that are past every TTL. Called on startup so the table
+    /// doesn't accumulate one row per handle ever typed into the login box.
+    pub(crate) fn prune(store: &dyn BrokerStore) -> Result<usize, anyhow::Error> {
+        let now = chrono::Utc::now().timestamp();
+        store.prune_identities(now - STALE_SECS, now - NEGATIVE_SECS)
+    }
+}
+
+/// Handles are case-insensitive and users paste them with a leading `@`.
+fn normalize_key(kind: LookupKind, key: &str) -> String {
+    match kind {
+        LookupKind::Handle => key.trim().trim_start_matches('@').to_lowercase(),
+        LookupKind::Pds => key.trim().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::store::SqliteStore;
+
+    fn cache() -> Arc<IdentityCache> {
+        Arc::new(IdentityCache::new(Arc::new(
+            SqliteStore::open_in_memory().unwrap(),
+        )))
+    }
+
+    #[tokio::test]
+    async fn hit_skips_fetch() {
+        let cache = cache();
+        let calls = Arc::new(AtomicUsize::new(0));
+        for _ in 0..3 {
+            let calls = calls.clone();
+            let did = cache
+                .lookup(
+                    LookupKind::Handle,
+                    "@Alice.Example.com",
+                    move |h| async move {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        assert_eq!(h, "alice.example.com");
+                        Ok("did:plc:alice".to_string())
+                    },
+                )
+                .await
+                .unwrap();
+            assert_eq!(did, "did:plc:alice");
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn failures_are_negatively_cached() {
+        let cache = cache();
+        let calls = Arc::new(AtomicUsize::new(0));
+        for _ in 0..2 {
+            let calls = calls.clone();
+            let res = cache
+                .lookup(LookupKind::Handle, "nobody.invalid", move |_| async move {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    Err(anyhow::anyhow!("No DID in response"))
+                })
+                .await;
+            assert!(res.is_err());
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn persisted_entries_survive_restart() {
+        let store: Arc<dyn BrokerStore> = Arc::new(SqliteStore::open_in_memory().unwrap());
+        let first = Arc::new(IdentityCache::new(store.clone()));
+        first
+            .lookup(LookupKind::Pds, "did:plc:alice", |_| async {
+                Ok("https://pds.example.com".to_string())
+            })
+            .await
+            .unwrap();
+
+        let second = Arc::new(IdentityCache::new(store));
+        let pds = second
+            .lookup(LookupKind::Pds, "did:plc:alice", |_| async {
+                panic!("should be served from SQLite")
+            })
+            .await
+            .unwrap();
+        assert_eq!(pds, "https://pds.example.com");
+    }
+
+    #[tokio::test]
+    async fn stale_entry_is_served_and_refreshed() {
+        let cache = cache();
+        cache.mem_put(
+            LookupKind::Pds,
+            "did:plc:alice",
+            CacheEntry {
+                value: Ok("https://old.example.com".to_string()),
+                fetched_at: chrono::Utc::now().timestamp() - FRESH_SECS - 1,
+            },
+        );
+        let pds = cache
+            .lookup(LookupKind::Pds, "did:plc:alice", |_| async {
+                Ok("https://new.example.com".to_string())
+            })
+            .await
+            .unwrap();
+        assert_eq!(pds, "https://old.example.com");
+
+        for _ in 0..50 {
+            if cache.refreshing.lock().unwrap().is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let entry = cache.mem_get(LookupKind::Pds, "did:plc:alice").unwrap();
+        assert_eq!(entry.value.unwrap(), "https://new.example.com");
+    }
+}
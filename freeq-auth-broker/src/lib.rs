@@ -0,0 +1,29 @@
+//! freeq auth broker: AT Protocol OAuth + DPoP login that mints freeq
+//! web sessions.
+//!
+//! The `freeq-auth-broker` binary is a thin wrapper around
+//! [`BrokerService`]; other axum apps can mount the same routes with
+//! [`BrokerService::router`] and supply their own [`BrokerStore`].
+
+mod app_attest;
+mod attest;
+mod client_keys;
+mod config;
+mod crypto;
+mod dpop;
+mod identity_cache;
+mod play_integrity;
+mod resolve;
+mod service;
+mod store;
+mod webhook;
+
+pub use app_attest::AppAttestConfig;
+pub use attest::{AttestationConfig, AttestationMode, Platform};
+pub use config::{
+    BrokerConfig, ClientAuth, DEFAULT_ALLOWED_ORIGINS, DEFAULT_CLIENT_KEY_ROTATION,
+    DEFAULT_RETURN_TO_PREFIXES, Environment,
+};
+pub use play_integrity::PlayIntegrityConfig;
+pub use service::BrokerService;
+pub use store::{BrokerStore, IdentityRecord, SqliteStore, StoredClientKey, StoredSession};
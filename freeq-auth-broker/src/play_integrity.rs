@@ -0,0 +1,280 @@
+//! Google Play Integrity tokens, decrypted and verified locally.
+//!
+//! Uses the "manage and download your response encryption keys" option in
+//! the Play Console: the token is a JWE (A256KW + A256GCM) around an ES256
+//! JWS, opened with the console's decryption and verification keys, so no
+//! call to Google is needed per mint.
+
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use base64::Engine;
+use p256::ecdsa::{Signature, VerifyingKey, signature::Verifier};
+use p256::pkcs8::DecodePublicKey;
+
+use crate::attest::decode_base64;
+
+/// How old a token's request timestamp may be.
+const MAX_AGE_MS: i64 = 5 * 60 * 1000;
+
+#[derive(Debug, Clone)]
+pub struct PlayIntegrityConfig {
+    /// Package names allowed to mint.
+    pub package_names: Vec<String>,
+    /// AES-256 response decryption key from the Play Console.
+    pub decryption_key: [u8; 32],
+    /// EC P-256 response verification key from the Play Console.
+    pub verification_key: VerifyingKey,
+}
+
+impl PlayIntegrityConfig {
+    /// Read `BROKER_PLAY_INTEGRITY_PACKAGES` (comma-separated) and the
+    /// base64 keys in `BROKER_PLAY_INTEGRITY_DECRYPTION_KEY` and
+    /// `BROKER_PLAY_INTEGRITY_VERIFICATION_KEY`, as the Play Console shows
+    /// them. `None` when no packages are set.
+    pub fn from_env() -> Result<Option<Self>, anyhow::Error> {
+        let Some(package_names) = crate::config::env_list("BROKER_PLAY_INTEGRITY_PACKAGES") else {
+            return Ok(None);
+        };
+        let key = |name: &str| -> Result<Vec<u8>, anyhow::Error> {
+            let value = std::env::var(name).map_err(|_| {
+                anyhow::anyhow!("BROKER_PLAY_INTEGRITY_PACKAGES is set but {name} is not")
+            })?;
+            decode_base64(&value).map_err(|e| anyhow::anyhow!("{name}: {e}"))
+        };
+        let decryption_key = key("BROKER_PLAY_INTEGRITY_DECRYPTION_KEY")?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("the Play Integrity decryption key must be 32 bytes"))?;
+        let verification_key =
+            VerifyingKey::from_public_key_der(&key("BROKER_PLAY_INTEGRITY_VERIFICATION_KEY")?)
+                .map_err(|e| anyhow::anyhow!("bad Play Integrity verification key: {e}"))?;
+        Ok(Some(Self {
+            package_names,
+            decryption_key,
+            verification_key,
+        }))
+    }
+}
+
+/// Verify an integrity token whose nonce is `challenge`; returns the
+/// attested package name.
+pub(crate) fn verify(
+    config: &PlayIntegrityConfig,
+    token: &str,
+    challenge: &str,
+) -> Result<String, anyhow::Error> {
+    let jws = decrypt(config, token)?;
+    let payload = verify_signature(config, &jws)?;
+    let claims: serde_json::Value = serde_json::from_slice(&payload)?;
+
+    let request = &claims["requestDetails"];
+    let package = request["requestPackageName"].as_str().unwrap_or_default();
+    if !config.package_names.iter().any(|p| p == package) {
+        anyhow::bail!("package {package:?} is not allowed");
+    }
+    if request["nonce"].as_str() != Some(challenge) {
+        anyhow::bail!("nonce does not match the challenge");
+    }
+    // Play sends the timestamp as a string; accept a number too.
+    let timestamp = match &request["timestampMillis"] {
+        serde_json::Value::String(s) => s.parse().ok(),
+        v => v.as_i64(),
+    }
+    .ok_or_else(|| anyhow::anyhow!("token has no timestamp"))?;
+    if (chrono::Utc::now().timestamp_millis() - timestamp).abs() > MAX_AGE_MS {
+        anyhow::bail!("token is stale");
+    }
+
+    let app = &claims["appIntegrity"];
+    if app["appRecognitionVerdict"].as_str() != Some("PLAY_RECOGNIZED") {
+        anyhow::bail!("app is not recognized by Play");
+    }
+    if app["packageName"].as_str() != Some(package) {
+        anyhow::bail!("app package does not match the request");
+    }
+    let meets_device_integrity = claims["deviceIntegrity"]["deviceRecognitionVerdict"]
+        .as_array()
+        .is_some_and(|v| v.iter().any(|v| v == "MEETS_DEVICE_INTEGRITY"));
+    if !meets_device_integrity {
+        anyhow::bail!("device does not meet integrity");
+    }
+    Ok(package.to_string())
+}
+
+/// Open the JWE: unwrap the content key, then AES-GCM decrypt.
+fn decrypt(config: &PlayIntegrityConfig, token: &str) -> Result<Vec<u8>, anyhow::Error> {
+    let b64 = &base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    let parts: Vec<&str> = token.trim().split('.').collect();
+    let [header_b64, wrapped_key, iv, ciphertext, tag] = parts[..] else {
+        anyhow::bail!("token is not a compact JWE");
+    };
+    let header: serde_json::Value = serde_json::from_slice(&b64.decode(header_b64)?)?;
+    if header["alg"] != "A256KW" || header["enc"] != "A256GCM" {
+        anyhow::bail!("unexpected JWE algorithms");
+    }
+
+    let wrapped_key = b64.decode(wrapped_key)?;
+    let mut key = [0u8; 32];
+    if wrapped_key.len() != key.len() + 8 {
+        anyhow::bail!("wrapped key has the wrong length");
+    }
+    aes_kw::KekAes256::from(config.decryption_key)
+        .unwrap(&wrapped_key, &mut key)
+        .map_err(|_| anyhow::anyhow!("cannot unwrap the content key"))?;
+
+    let iv = b64.decode(iv)?;
+    if iv.len() != 12 {
+        anyhow::bail!("IV has the wrong length");
+    }
+    let mut sealed = b64.decode(ciphertext)?;
+    sealed.extend_from_slice(&b64.decode(tag)?);
+    Aes256Gcm::new(&key.into())
+        .decrypt(
+            Nonce::from_slice(&iv),
+            Payload {
+                msg: &sealed,
+                aad: header_b64.as_bytes(),
+            },
+        )
+        .map_err(|_| anyhow::anyhow!("cannot decrypt the token"))
+}
+
+/// Check the ES256 JWS signature; returns the payload.
+fn verify_signature(config: &PlayIntegrityConfig, jws: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+    let b64 = &base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    let jws = std::str::from_utf8(jws)?;
+    let mut parts = jws.rsplitn(2, '.');
+    let (Some(signature), Some(signed)) = (parts.next(), parts.next()) else {
+        anyhow::bail!("token is not a compact JWS");
+    };
+    let Some((header_b64, payload_b64)) = signed.split_once('.') else {
+        anyhow::bail!("token is not a compact JWS");
+    };
+    let header: serde_json::Value = serde_json::from_slice(&b64.decode(header_b64)?)?;
+    if header["alg"] != "ES256" {
+        anyhow::bail!("unexpected JWS algorithm");
+    }
+    let signature = Signature::from_slice(&b64.decode(signature)?)?;
+    config
+        .verification_key
+        .verify(signed.as_bytes(), &signature)
+        .map_err(|_| anyhow::anyhow!("token signature is invalid"))?;
+    Ok(b64.decode(payload_b64)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p256::ecdsa::SigningKey;
+    use p256::ecdsa::signature::Signer;
+
+    const PACKAGE: &str = "at.freeq.android";
+
+    struct Keys {
+        config: PlayIntegrityConfig,
+        signing_key: SigningKey,
+    }
+
+    fn keys() -> Keys {
+        let signing_key = SigningKey::random(&mut rand::thread_rng());
+        Keys {
+            config: PlayIntegrityConfig {
+                package_names: vec![PACKAGE.to_string()],
+                decryption_key: rand::random(),
+                verification_key: *signing_key.verifying_key(),
+            },
+            signing_key,
+        }
+    }
+
+    /// Sign and encrypt `claims` the way Play does.
+    fn token(keys: &Keys, claims: &serde_json::Value) -> String {
+        let b64 = &base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        let header = b64.encode(br#"{"alg":"ES256"}"#);
+        let signed = format!("{header}.{}", b64.encode(claims.to_string()));
+        let signature: Signature = keys.signing_key.sign(signed.as_bytes());
+        let jws = format!("{signed}.{}", b64.encode(signature.to_bytes()));
+
+        let cek: [u8; 32] = rand::random();
+        let iv: [u8; 12] = rand::random();
+        let wrapped = aes_kw::KekAes256::from(keys.config.decryption_key)
+            .wrap_vec(&cek)
+            .unwrap();
+        let header = b64.encode(br#"{"alg":"A256KW","enc":"A256GCM"}"#);
+        let sealed = Aes256Gcm::new(&cek.into())
+            .encrypt(
+                Nonce::from_slice(&iv),
+                Payload {
+                    msg: jws.as_bytes(),
+                    aad: header.as_bytes(),
+                },
+            )
+            .unwrap();
+        let (ciphertext, tag) = sealed.split_at(sealed.len() - 16);
+        format!(
+            "{header}.{}.{}.{}.{}",
+            b64.encode(wrapped),
+            b64.encode(iv),
+            b64.encode(ciphertext),
+            b64.encode(tag)
+        )
+    }
+
+    fn claims(nonce: &str) -> serde_json::Value {
+        serde_json::json!({
+            "requestDetails": {
+                "requestPackageName": PACKAGE,
+                "nonce": nonce,
+                "timestampMillis": chrono::Utc::now().timestamp_millis().to_string(),
+            },
+            "appIntegrity": {
+                "appRecognitionVerdict": "PLAY_RECOGNIZED",
+                "packageName": PACKAGE,
+            },
+            "deviceIntegrity": {
+                "deviceRecognitionVerdict": ["MEETS_DEVICE_INTEGRITY"],
+            },
+        })
+    }
+
+    #[test]
+    fn valid_token_verifies() {
+        let keys = keys();
+        let token = token(&keys, &claims("abc"));
+        assert_eq!(verify(&keys.config, &token, "abc").unwrap(), PACKAGE);
+    }
+
+    #[test]
+    fn bad_verdicts_and_bindings_are_refused() {
+        let keys = keys();
+        let err = |claims: serde_json::Value, challenge: &str| {
+            verify(&keys.config, &token(&keys, &claims), challenge)
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(err(claims("abc"), "xyz").contains("nonce"));
+
+        let mut c = claims("abc");
+        c["appIntegrity"]["appRecognitionVerdict"] = "UNRECOGNIZED_VERSION".into();
+        assert!(err(c, "abc").contains("not recognized"));
+
+        let mut c = claims("abc");
+        c["deviceIntegrity"]["deviceRecognitionVerdict"] = serde_json::json!([]);
+        assert!(err(c, "abc").contains("device"));
+
+        let mut c = claims("abc");
+        c["requestDetails"]["timestampMillis"] = "1".into();
+        assert!(err(c, "abc").contains("stale"));
+
+        let mut c = claims("abc");
+        c["requestDetails"]["requestPackageName"] = "com.example.clone".into();
+        assert!(err(c, "abc").contains("not allowed"));
+    }
+
+    #[test]
+    fn tokens_for_other_keys_are_refused() {
+        let keys = keys();
+        let other = self::keys();
+        let token = token(&other, &claims("abc"));
+        assert!(verify(&keys.config, &token, "abc").is_err());
+    }
+}
@@ -0,0 +1,167 @@
+//! Handle → DID → PDS resolution against the public AT Protocol network.
+
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+struct DidDocument {
+    #[serde(default)]
+    service: Vec<DidService>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DidService {
+    #[serde(rename = "type")]
+    service_type: String,
+    #[serde(rename = "serviceEndpoint")]
+    service_endpoint: String,
+}
+
+/// Hard-bounded client for every upstream call the broker makes during
+/// `/auth/login`. Default reqwest waits forever; if bsky.social or a
+/// user's `.well-known` server is slow we'd accumulate stuck requests
+/// until the platform's gateway times out — and meanwhile the user
+/// stares at a spinner with no actionable error.
+///
+/// `pool_max_idle_per_host(0)` disables connection reuse — the
+/// observed failure mode was the *second* POST to bsky.social/oauth/par
+/// (DPoP nonce retry) consistently dying with "error sending request"
+/// while the first POST on the same client succeeded. Each call now
+/// uses a fresh TCP/TLS connection, which dodges that.
+///
+/// `http1_only()` similarly avoids HTTP/2 stream-state weirdness; the
+/// request volume from this endpoint is tiny so the perf cost is
+/// irrelevant.
+pub(crate) fn upstream_client() -> Result<reqwest::Client, anyhow::Error> {
+    Ok(reqwest::Client::builder()
+        // 30s overall. Individual calls to bsky.social are normally
+        // fast (~600ms from a healthy network) but Miren's egress can
+        // be slow, so we keep headroom.
+        .timeout(std::time::Duration::from_secs(30))
+        .connect_timeout(std::time::Duration::from_secs(8))
+        // Miren's egress can't reliably open a SECOND TCP connection
+        // to bsky.social inside the same login flow — the connect
+        // phase consistently `TimedOut`. Reusing the first connection
+        // via HTTP/2 multiplexing avoids opening a second TCP socket
+        // at all. We explicitly enable keep-alive idle pooling so the
+        // second POST piggybacks on the open connection from the first.
+        .pool_idle_timeout(std::time::Duration::from_secs(90))
+        .pool_max_idle_per_host(32)
+        .tcp_keepalive(std::time::Duration::from_secs(30))
+        .build()?)
+}
+
+pub(crate) async fn resolve_handle(handle: &str) -> Result<String, anyhow::Error> {
+    let client = upstream_client()?;
+    // Try HTTPS well-known first
+    let url = format!("https://{handle}/.well-known/atproto-did");
+    if let Ok(resp) = client.get(&url).send().await
+        && resp.status().is_success()
+    {
+        let did = resp.text().await?.trim().to_string();
+        if did.starts_with("did:") {
+            return Ok(did);
+        }
+    }
+
+    // Fallback to public API (DNS TXT)
+    let api_url = format!(
+        "https://public.api.bsky.app/xrpc/com.atproto.identity.resolveHandle?handle={}",
+        handle
+    );
+    let json: serde_json::Value = client.get(&api_url).send().await?.json().await?;
+    let did = json["did"]
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("No DID in response"))?;
+    Ok(did.to_string())
+}
+
+async fn resolve_did(did: &str) -> Result<DidDocument, anyhow::Error> {
+    let client = upstream_client()?;
+    if did.starts_with("did:plc:") {
+        let url = format!("https://plc.directory/{did}");
+        let doc: DidDocument = client.get(&url).send().await?.json().await?;
+        return Ok(doc);
+    }
+    if did.starts_with("did:web:") {
+        let domain = did.trim_start_matches("did:web:").replace(':', "/");
+        let url = format!("https://{domain}/.well-known/did.json");
+
+        // SSRF protection: resolve hostname and reject private IPs
+        let host = domain.split('/').next().unwrap_or(&domain);
+        reject_private_host(host).await?;
+
+        let doc: DidDocument = client.get(&url).send().await?.json().await?;
+        return Ok(doc);
+    }
+    Err(anyhow::anyhow!("Unsupported DID method"))
+}
+
+/// SSRF protection: resolve a hostname and reject private/loopback IPs.
+async fn reject_private_host(host: &str) -> Result<(), anyhow::Error> {
+    let host_lower = host.to_lowercase();
+    if host_lower == "localhost"
+        || host_lower.ends_with(".local")
+        || host_lower.ends_with(".internal")
+        || host_lower.ends_with(".localhost")
+    {
+        anyhow::bail!("SSRF blocked: private hostname {host}");
+    }
+
+    // If the host is an IP literal, check directly
+    if let Ok(ip) = host.parse::<std::net::IpAddr>() {
+        if is_private_ip(&ip) {
+            anyhow::bail!("SSRF blocked: private IP {ip}");
+        }
+        return Ok(());
+    }
+
+    let addrs: Vec<std::net::SocketAddr> = tokio::net::lookup_host(format!("{host}:443"))
+        .await?
+        .collect();
+    for addr in &addrs {
+        if is_private_ip(&addr.ip()) {
+            anyhow::bail!(
+                "SSRF blocked: {} resolves to private IP {}",
+                host,
+                addr.ip()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn is_private_ip(ip: &std::net::IpAddr) -> bool {
+    match ip {
+        std::net::IpAddr::V4(v4) => {
+            v4.is_loopback()
+                || v4.is_private()
+                || v4.is_link_local()
+                || v4.is_broadcast()
+                || v4.is_unspecified()
+                || (v4.octets()[0] == 100 && (v4.octets()[1] & 0xC0) == 64) // CGNAT
+        }
+        std::net::IpAddr::V6(v6) => {
+            v6.is_loopback()
+                || v6.is_unspecified()
+                || (v6.segments()[0] & 0xfe00) == 0xfc00 // ULA
+                || (v6.segments()[0] & 0xffc0) == 0xfe80 // link-local
+        }
+    }
+}
+
+fn pds_endpoint(doc: &DidDocument) -> Option<String> {
+    doc.service.iter().find_map(|svc| {
+        if svc.service_type == "AtprotoPersonalDataServer" {
+            Some(svc.service_endpoint.clone())
+        } else {
+            None
+        }
+    })
+}
+
+pub(crate) async fn resolve_pds(did: &str) -> Result<String, anyhow::Error> {
+    let doc = resolve_did(did)
+        .await
+        .map_err(|e| anyhow::anyhow!("Cannot resolve DID: {e}"))?;
+    pds_endpoint(&doc).ok_or_else(|| anyhow::anyhow!("No PDS in DID document"))
+}
@@ -0,0 +1,1393 @@
+//! The broker's HTTP surface: [`BrokerService`] and its axum handlers.
+
+use std::sync::Arc;
+
+use axum::http::Method;
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
+};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tower_http::cors::{AllowHeaders, AllowOrigin, CorsLayer};
+
+use crate::attest::{Attestation, CHALLENGE_TTL, Challenges, Platform};
+use crate::client_keys::{ASSERTION_TYPE, ClientKey, ClientKeys};
+use crate::config::{BrokerConfig, ClientAuth, is_loopback_origin};
+use crate::crypto::{
+    decrypt_field, derive_encryption_key, encrypt_field, generate_pkce, generate_random_string,
+    sign_body,
+};
+use crate::dpop::DpopKey;
+use crate::identity_cache::{IdentityCache, LookupKind};
+use crate::resolve::{resolve_handle, resolve_pds, upstream_client};
+use crate::store::{BrokerStore, StoredSession};
+use crate::webhook::{self, SessionEvent, SessionWebhook};
+
+/// AT Protocol OAuth + DPoP login broker, mountable in any axum app.
+///
+/// ```no_run
+/// # async fn run() -> anyhow::Result<()> {
+/// use std::sync::Arc;
+/// use freeq_auth_broker::{BrokerConfig, BrokerService, SqliteStore};
+///
+/// let config = BrokerConfig::from_env()?;
+/// let store = Arc::new(SqliteStore::open("broker.db")?);
+/// let app = axum::Router::new().merge(BrokerService::new(config, store).router());
+/// # let _ = app;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct BrokerService {
+    state: Arc<BrokerState>,
+}
+
+struct BrokerState {
+    config: BrokerConfig,
+    /// Derived from `config.shared_secret`; encrypts stored credentials.
+    encryption_key: [u8; 32],
+    pending: Mutex<std::collections::HashMap<String, PendingAuth>>,
+    store: Arc<dyn BrokerStore>,
+    identity_cache: Arc<IdentityCache>,
+    challenges: Challenges,
+    client_keys: ClientKeys,
+}
+
+impl BrokerService {
+    pub fn new(config: BrokerConfig, store: Arc<dyn BrokerStore>) -> Self {
+        let encryption_key = derive_encryption_key(&config.shared_secret);
+        tracing::info!("Session encryption key derived from BROKER_SHARED_SECRET");
+        match IdentityCache::prune(store.as_ref()) {
+            Ok(n) if n > 0 => tracing::info!(pruned = n, "Pruned expired identity cache entries"),
+            Ok(_) => {}
+            Err(e) => tracing::warn!(error = %e, "Failed to prune identity cache"),
+        }
+        let client_keys =
+            ClientKeys::new(store.clone(), encryption_key, config.client_key_rotation);
+        if config.client_auth == ClientAuth::PrivateKeyJwt {
+            match client_keys.current() {
+                Ok(key) => tracing::info!(kid = %key.kid, "Client signing key loaded"),
+                Err(e) => tracing::warn!(error = %e, "Failed to load client signing keys"),
+            }
+        }
+        Self {
+            state: Arc::new(BrokerState {
+                config,
+                encryption_key,
+                pending: Mutex::new(std::collections::HashMap::new()),
+                identity_cache: Arc::new(IdentityCache::new(store.clone())),
+                store,
+                challenges: Challenges::default(),
+                client_keys,
+            }),
+        }
+    }
+
+    /// All broker routes (`/health`, `/client-metadata.json`, `/jwks.json`,
+    /// `/auth/login`, `/auth/callback`, `/session`, `/session/revoke`,
+    /// `/attest/challenge`)
+    /// with the CORS layer for `config.allowed_origins` applied.
+    pub fn router(&self) -> Router {
+        let origins: Vec<axum::http::HeaderValue> = self
+            .state
+            .config
+            .allowed_origins
+            .iter()
+            .filter_map(|o| match o.parse() {
+                Ok(v) => Some(v),
+                Err(_) => {
+                    tracing::warn!(origin = %o, "Ignoring unparseable allowed origin");
+                    None
+                }
+            })
+            .collect();
+        Router::new()
+            .route("/health", get(health))
+            .route("/health-v3", get(health_v3))
+            .route("/client-metadata.json", get(client_metadata))
+            .route("/jwks.json", get(jwks))
+            .route("/auth/login", get(auth_login))
+            .route("/auth/callback", get(auth_callback))
+            .route("/session", post(session))
+            .route("/session/revoke", post(revoke_session))
+            .route("/attest/challenge", post(attest_challenge))
+            .layer(
+                CorsLayer::new()
+                    .allow_origin(AllowOrigin::list(origins))
+                    .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
+                    .allow_headers(AllowHeaders::any()),
+            )
+            .with_state(self.state.clone())
+    }
+}
+
+#[derive(Clone)]
+struct PendingAuth {
+    handle: String,
+    did: String,
+    pds_url: String,
+    code_verifier: String,
+    redirect_uri: String,
+    client_id: String,
+    token_endpoint: String,
+    /// Authorization server issuer; the audience of client assertions.
+    issuer: String,
+    /// Signs client assertions when the broker uses `private_key_jwt`.
+    client_key: Option<ClientKey>,
+    dpop_key_b64: String,
+    dpop_nonce: Option<String>,
+    mobile: bool,
+    return_to: Option<String>,
+    /// The allowed origin a web login returns to; see
+    /// [`StoredSession::web_origin`].
+    web_origin: Option<String>,
+    popup: bool,
+}
+
+#[derive(Deserialize)]
+struct AuthLoginQuery {
+    handle: String,
+    mobile: Option<String>,
+    return_to: Option<String>,
+    popup: Option<String>,
+    attestation: Option<String>,
+    attestation_platform: Option<Platform>,
+    challenge: Option<String>,
+}
+
+impl AuthLoginQuery {
+    /// The attestation, when all three of its params are present.
+    fn attestation(&self) -> Option<Attestation> {
+        Some(Attestation {
+            platform: self.attestation_platform?,
+            token: self.attestation.clone()?,
+            challenge: self.challenge.clone()?,
+        })
+    }
+}
+
+fn is_truthy(value: Option<&str>) -> bool {
+    matches!(value, Some("1") | Some("true") | Some("yes"))
+}
+
+#[derive(Deserialize)]
+struct AuthCallbackQuery {
+    state: Option<String>,
+    code: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+    _iss: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BrokerSessionRequest {
+    broker_token: String,
+    #[serde(default)]
+    attestation: Option<Attestation>,
+}
+
+#[derive(Deserialize)]
+struct RevokeSessionRequest {
+    broker_token: String,
+}
+
+#[derive(Serialize)]
+struct BrokerSessionResponse {
+    token: String,
+    nick: String,
+    did: String,
+    handle: String,
+}
+
+#[derive(Serialize)]
+struct BrokerSessionRecord {
+    broker_token: String,
+    did: String,
+    handle: String,
+    pds_url: String,
+    token_endpoint: String,
+    refresh_token: String,
+    dpop_key_b64: String,
+    dpop_nonce: Option<String>,
+    issuer: Option<String>,
+    client_kid: Option<String>,
+    web_origin: Option<String>,
+    created_at: i64,
+    updated_at: i64,
+}
+
+const GIT_COMMIT_FILE: &str = include_str!("../git_commit.txt");
+
+fn git_commit() -> String {
+    if let Ok(v) = std::env::var("GIT_HASH")
+        && !v.is_empty()
+    {
+        return v;
+    }
+    let trimmed = GIT_COMMIT_FILE.trim();
+    if !trimmed.is_empty() {
+        return trimmed.to_string();
+    }
+    let built_in = env!("GIT_HASH");
+    if !built_in.is_empty() {
+        return built_in.to_string();
+    }
+    "unknown".to_string()
+}
+
+async fn health() -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "status": "ok",
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_commit": git_commit(),
+    }))
+}
+
+async fn health_v3() -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "status": "ok",
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_commit": git_commit(),
+    }))
+}
+
+async fn attest_challenge(
+    State(state): State<Arc<BrokerState>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let challenge = state.challenges.issue().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "Too many outstanding challenges".to_string(),
+    ))?;
+    Ok(Json(serde_json::json!({
+        "challenge": challenge,
+        "expires_in": CHALLENGE_TTL.as_secs(),
+    })))
+}
+
+/// Apply the attestation policy to a mint request; 403 when refused.
+fn check_attestation(
+    state: &BrokerState,
+    attestation: Option<&Attestation>,
+    exempt: bool,
+) -> Result<(), (StatusCode, String)> {
+    match state
+        .config
+        .attestation
+        .check(&state.challenges, attestation, exempt)
+    {
+        Ok(Some(app)) => {
+            tracing::info!(platform = ?app.platform, app = %app.app_id, "Client attestation verified");
+            Ok(())
+        }
+        Ok(None) => Ok(()),
+        Err(e) => {
+            tracing::warn!(error = %e, "Refused to mint: client attestation");
+            Err((StatusCode::FORBIDDEN, format!("Attestation failed: {e}")))
+        }
+    }
+}
+
+async fn client_metadata(State(state): State<Arc<BrokerState>>) -> Json<serde_json::Value> {
+    let redirect_uri = format!(
+        "{}/auth/callback",
+        state.config.public_url.trim_end_matches('/')
+    );
+    let client_id = build_client_id(&state.config.public_url, &redirect_uri);
+    let mut metadata = serde_json::json!({
+        "client_id": client_id,
+        "client_name": state.config.environment.client_name(),
+        "client_uri": state.config.public_url,
+        "logo_uri": format!("{}/freeq.png", state.config.public_url),
+        "tos_uri": state.config.public_url,
+        "policy_uri": state.config.public_url,
+        "redirect_uris": [redirect_uri],
+        // Union of scopes the broker may ever request, plus
+        // `transition:generic` for backward compat with refresh tokens
+        // issued before this change. We never request it at /authorize
+        // — the broker only asks for `atproto`. Remove transition:generic
+        // once the PDS grace period closes.
+        "scope": "atproto blob:image/* repo:blue.irc.media?action=create repo:app.bsky.feed.post transition:generic",
+        "grant_types": ["authorization_code", "refresh_token"],
+        "response_types": ["code"],
+        "token_endpoint_auth_method": "none",
+        "application_type": "web",
+        "dpop_bound_access_tokens": true
+    });
+    if state.config.client_auth == ClientAuth::PrivateKeyJwt {
+        metadata["token_endpoint_auth_method"] = "private_key_jwt".into();
+        metadata["token_endpoint_auth_signing_alg"] = "ES256".into();
+        metadata["jwks_uri"] = format!(
+            "{}/jwks.json",
+            state.config.public_url.trim_end_matches('/')
+        )
+        .into();
+    }
+    Json(metadata)
+}
+
+/// The public halves of the `private_key_jwt` signing keys; 404 for a
+/// public client.
+async fn jwks(
+    State(state): State<Arc<BrokerState>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if state.config.client_auth != ClientAuth::PrivateKeyJwt {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "Not a confidential client".to_string(),
+        ));
+    }
+    state.client_keys.jwks().map(Json).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Client keys unavailable: {e}"),
+        )
+    })
+}
+
+async fn auth_login(
+    Query(q): Query<AuthLoginQuery>,
+    State(state): State<Arc<BrokerState>>,
+    headers: HeaderMap,
+) -> Result<Redirect, (StatusCode, String)> {
+    let is_mobile = is_truthy(q.mobile.as_deref());
+    let mut return_to = q.return_to.clone();
+
+    // C-6: Validate return_to against allowlist to prevent open redirects
+    if let Some(ref rt) = return_to
+        && !state.config.is_valid_return_to(rt)
+    {
+        tracing::warn!(return_to = %rt, "Rejected invalid return_to URL");
+        return Err((StatusCode::BAD_REQUEST, "Invalid return_to URL".to_string()));
+    }
+
+    if return_to.is_none()
+        && let Some(referer) = headers.get("referer").and_then(|v| v.to_str().ok())
+        && let Ok(url) = url::Url::parse(referer)
+    {
+        let origin = url.origin().ascii_serialization();
+        if state.config.is_valid_return_to(&origin) {
+            return_to = Some(origin);
+        }
+    }
+    if return_to.is_none() && !is_mobile {
+        return_to = Some(state.config.default_return_to.clone());
+    }
+
+    // Mobile apps prove themselves before any upstream work. Browsers
+    // can't attest, so a web login is exempt when its tokens go back to
+    // an allowed origin — and only that origin may refresh the session.
+    let web_origin = return_to
+        .as_deref()
+        .filter(|_| !is_mobile)
+        .and_then(|rt| state.config.allowed_origin_of(rt));
+    check_attestation(&state, q.attestation().as_ref(), web_origin.is_some())?;
+
+    let handle = q.handle.trim().to_string();
+    let did = state
+        .identity_cache
+        .lookup(LookupKind::Handle, &handle, |h| async move {
+            resolve_handle(&h).await
+        })
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Cannot resolve handle: {e}"),
+            )
+        })?;
+    let pds_url = state
+        .identity_cache
+        .lookup(
+            LookupKind::Pds,
+            &did,
+            |d| async move { resolve_pds(&d).await },
+        )
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    let client = upstream_client().map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("upstream client init: {e}"),
+        )
+    })?;
+    let pr_url = format!(
+        "{}/.well-known/oauth-protected-resource",
+        pds_url.trim_end_matches('/')
+    );
+    let pr_meta: serde_json::Value = client
+        .get(&pr_url)
+        .send()
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::BAD_GATEWAY,
+                format!("PDS metadata fetch failed: {e}"),
+            )
+        })?
+        .json()
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::BAD_GATEWAY,
+                format!("PDS metadata parse failed: {e}"),
+            )
+        })?;
+    let auth_server = pr_meta["authorization_servers"][0]
+        .as_str()
+        .ok_or_else(|| {
+            (
+                StatusCode::BAD_GATEWAY,
+                "No authorization server".to_string(),
+            )
+        })?;
+
+    let as_url = format!(
+        "{}/.well-known/oauth-authorization-server",
+        auth_server.trim_end_matches('/')
+    );
+    let as_resp = client.get(&as_url).send().await.map_err(|e| {
+        (
+            StatusCode::BAD_GATEWAY,
+            format!("Auth server metadata failed: {e:#?}"),
+        )
+    })?;
+    let as_status = as_resp.status();
+    let as_body = as_resp.text().await.unwrap_or_default();
+    let auth_meta: serde_json::Value = serde_json::from_str(&as_body).map_err(|e| {
+        (
+            StatusCode::BAD_GATEWAY,
+            format!(
+                "Auth server metadata parse failed: {e} (status={as_status}, body_len={}, body_preview={:?})",
+                as_body.len(),
+                as_body.chars().take(200).collect::<String>(),
+            ),
+        )
+    })?;
+
+    let authorization_endpoint = auth_meta["authorization_endpoint"]
+        .as_str()
+        .ok_or_else(|| {
+            (
+                StatusCode::BAD_GATEWAY,
+                "No authorization_endpoint".to_string(),
+            )
+        })?;
+    let token_endpoint = auth_meta["token_endpoint"]
+        .as_str()
+        .ok_or_else(|| (StatusCode::BAD_GATEWAY, "No token_endpoint".to_string()))?;
+    let par_endpoint = auth_meta["pushed_authorization_request_endpoint"]
+        .as_str()
+        .ok_or_else(|| (StatusCode::BAD_GATEWAY, "No PAR endpoint".to_string()))?;
+    let issuer = auth_meta["issuer"].as_str().unwrap_or(auth_server);
+    let client_key = match state.config.client_auth {
+        ClientAuth::None => None,
+        ClientAuth::PrivateKeyJwt => Some(state.client_keys.current().map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Client key unavailable: {e}"),
+            )
+        })?),
+    };
+
+    let redirect_uri = format!(
+        "{}/auth/callback",
+        state.config.public_url.trim_end_matches('/')
+    );
+    // Identity-only scope. The broker's job is to mint a session token
+    // for SASL — that needs nothing more than `atproto`. PDS-touching
+    // features (image upload, Bluesky cross-post) are step-ups served
+    // by the freeq-server's `/auth/step-up`, never the broker.
+    let scope = "atproto";
+    let client_id = build_client_id(&state.config.public_url, &redirect_uri);
+
+    let dpop_key = DpopKey::generate();
+    let (code_verifier, code_challenge) = generate_pkce();
+    let oauth_state = generate_random_string(16);
+
+    let params = [
+        ("response_type", "code"),
+        ("client_id", &client_id),
+        ("redirect_uri", &redirect_uri),
+        ("code_challenge", &code_challenge),
+        ("code_challenge_method", "S256"),
+        ("scope", scope),
+        ("state", &oauth_state),
+        ("login_hint", &handle),
+    ];
+
+    let dpop_proof = dpop_key
+        .proof("POST", par_endpoint, None, None)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("DPoP proof failed: {e}"),
+            )
+        })?;
+    let resp = client
+        .post(par_endpoint)
+        .header("DPoP", &dpop_proof)
+        .form(&client_form(&params, client_key.as_ref(), issuer).map_err(assertion_failed)?)
+        .send()
+        .await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, format!("PAR failed: {e}")))?;
+
+    let status = resp.status();
+    let dpop_nonce = resp
+        .headers()
+        .get("dpop-nonce")
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string());
+
+    // Surface the first-PAR response body in logs and require the
+    // canonical `use_dpop_nonce` error code before retrying — a 400
+    // with an incidental DPoP-Nonce header but a different error
+    // (e.g. invalid_client_metadata) should NOT be retried into a
+    // timeout; the underlying problem isn't going to resolve.
+    let first_body = resp.text().await.unwrap_or_default();
+    let body_preview = first_body.chars().take(300).collect::<String>();
+    tracing::info!(
+        status = %status,
+        has_nonce = dpop_nonce.is_some(),
+        body = %body_preview,
+        "first PAR response"
+    );
+    let par_resp: serde_json::Value = if status.as_u16() == 400
+        && dpop_nonce.is_some()
+        && first_body.contains("use_dpop_nonce")
+    {
+        let nonce = dpop_nonce.as_deref().unwrap();
+        let dpop_proof2 = dpop_key
+            .proof("POST", par_endpoint, Some(nonce), None)
+            .map_err(|e| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("DPoP retry failed: {e}"),
+                )
+            })?;
+        // Reuse the SAME client for the retry. Miren's egress couldn't
+        // open a second TCP connection to bsky.social within 30s, so
+        // we lean on HTTP/2 multiplexing over the first call's still-
+        // open connection. Connection pooling is enabled in
+        // `upstream_client`.
+        let resp2 = client
+            .post(par_endpoint)
+            .header("DPoP", &dpop_proof2)
+            .form(&client_form(&params, client_key.as_ref(), issuer).map_err(assertion_failed)?)
+            .send()
+            .await
+            .map_err(|e| (StatusCode::BAD_GATEWAY, format!("PAR retry failed: {e:#?}")))?;
+        if !resp2.status().is_success() {
+            let text = resp2.text().await.unwrap_or_default();
+            return Err((StatusCode::BAD_GATEWAY, format!("PAR failed: {text}")));
+        }
+        resp2
+            .json()
+            .await
+            .map_err(|e| (StatusCode::BAD_GATEWAY, format!("PAR parse failed: {e}")))?
+    } else if status.is_success() {
+        serde_json::from_str(&first_body)
+            .map_err(|e| (StatusCode::BAD_GATEWAY, format!("PAR parse failed: {e}")))?
+    } else {
+        return Err((
+            StatusCode::BAD_GATEWAY,
+            format!("PAR failed ({status}): {first_body}"),
+        ));
+    };
+
+    let request_uri = par_resp["request_uri"].as_str().ok_or_else(|| {
+        (
+            StatusCode::BAD_GATEWAY,
+            "No request_uri in PAR response".to_string(),
+        )
+    })?;
+
+    let is_popup = is_truthy(q.popup.as_deref());
+
+    tracing::info!(handle = %handle, did = %did, popup = %is_popup, return_to = ?return_to, "BROKER_LOGIN_PARAMS_V3");
+
+    state.pending.lock().await.insert(
+        oauth_state.clone(),
+        PendingAuth {
+            handle: handle.clone(),
+            did: did.clone(),
+            pds_url: pds_url.clone(),
+            code_verifier,
+            redirect_uri: redirect_uri.clone(),
+            client_id: client_id.clone(),
+            token_endpoint: token_endpoint.to_string(),
+            issuer: issuer.to_string(),
+            client_key,
+            dpop_key_b64: dpop_key.to_base64url(),
+            dpop_nonce: dpop_nonce.clone(),
+            mobile: is_mobile,
+            return_to,
+            web_origin,
+            popup: is_popup,
+        },
+    );
+
+    let auth_url = format!(
+        "{}?client_id={}&request_uri={}",
+        authorization_endpoint,
+        urlencod(&client_id),
+        urlencod(request_uri)
+    );
+
+    Ok(Redirect::temporary(&auth_url))
+}
+
+async fn auth_callback(
+    Query(q): Query<AuthCallbackQuery>,
+    State(state): State<Arc<BrokerState>>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    if let Some(err) = q.error.as_deref() {
+        let detail = q.error_description.as_deref().unwrap_or(err);
+        return Ok(
+            Html(oauth_result_page(&format!("OAuth error: {detail}"), None)).into_response(),
+        );
+    }
+
+    let state_value = match q.state.as_deref() {
+        Some(s) => s,
+        None => {
+            return Ok(
+                Html(oauth_result_page("OAuth callback missing state", None)).into_response(),
+            );
+        }
+    };
+    let code = match q.code.as_deref() {
+        Some(c) => c,
+        None => {
+            return Ok(Html(oauth_result_page("OAuth callback missing code", None)).into_response());
+        }
+    };
+
+    let pending = {
+        let mut pending_map = state.pending.lock().await;
+        pending_map.remove(state_value)
+    };
+    let pending = match pending {
+        Some(p) => p,
+        None => return Ok(Html(oauth_result_page("Invalid OAuth state", None)).into_response()),
+    };
+    tracing::info!(popup = %pending.popup, return_to = ?pending.return_to, "BROKER_CALLBACK_PARAMS_V3");
+    let return_to = pending
+        .return_to
+        .clone()
+        .unwrap_or_else(|| state.config.default_return_to.clone());
+
+    let dpop_key = DpopKey::from_base64url(&pending.dpop_key_b64).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Invalid DPoP key: {e}"),
+        )
+    })?;
+
+    let params = [
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", pending.redirect_uri.as_str()),
+        ("client_id", pending.client_id.as_str()),
+        ("code_verifier", pending.code_verifier.as_str()),
+    ];
+
+    let client = reqwest::Client::new();
+    // CRITICAL: include any nonce we already have from the PAR step on the FIRST
+    // attempt. The PDS consumes the auth code on a failed token request even when
+    // the failure is "use_dpop_nonce", so a retry with a fresh nonce gets
+    // `invalid_grant: Invalid code`. Sending the known nonce up front avoids this.
+    let dpop_proof = dpop_key
+        .proof(
+            "POST",
+            &pending.token_endpoint,
+            pending.dpop_nonce.as_deref(),
+            None,
+        )
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("DPoP proof failed: {e}"),
+            )
+        })?;
+    let resp = client
+        .post(&pending.token_endpoint)
+        .header("DPoP", &dpop_proof)
+        .form(
+            &client_form(&params, pending.client_key.as_ref(), &pending.issuer)
+                .map_err(assertion_failed)?,
+        )
+        .send()
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::BAD_GATEWAY,
+                format!("Token exchange failed: {e}"),
+            )
+        })?;
+
+    let status = resp.status();
+    let dpop_nonce = resp
+        .headers()
+        .get("dpop-nonce")
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string())
+        .or(pending.dpop_nonce.clone());
+
+    let token_resp: serde_json::Value = if (status.as_u16() == 400 || status.as_u16() == 401)
+        && dpop_nonce.is_some()
+    {
+        let nonce = dpop_nonce.as_deref().unwrap();
+        tracing::info!(nonce = %nonce, "DPoP nonce retry for token exchange");
+        let dpop_proof2 = dpop_key
+            .proof("POST", &pending.token_endpoint, Some(nonce), None)
+            .map_err(|e| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("DPoP retry failed: {e}"),
+                )
+            })?;
+        let resp2 = client
+            .post(&pending.token_endpoint)
+            .header("DPoP", &dpop_proof2)
+            .form(
+                &client_form(&params, pending.client_key.as_ref(), &pending.issuer)
+                    .map_err(assertion_failed)?,
+            )
+            .send()
+            .await
+            .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Token retry failed: {e}")))?;
+        let resp2_status = resp2.status();
+        if !resp2_status.is_success() {
+            let text = resp2.text().await.unwrap_or_default();
+            tracing::error!(status = %resp2_status, body = %text, "Token exchange retry failed");
+            let err_msg = format!("Token exchange failed: {text}");
+            if pending.mobile {
+                let redirect = format!("freeq://auth?error={}", urlencod(&err_msg));
+                return Ok(axum::response::Redirect::to(&redirect).into_response());
+            }
+            return Ok(Html(oauth_result_page(&err_msg, None)).into_response());
+        }
+        resp2
+            .json()
+            .await
+            .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Token parse failed: {e}")))?
+    } else if status.is_success() {
+        resp.json()
+            .await
+            .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Token parse failed: {e}")))?
+    } else {
+        let text = resp.text().await.unwrap_or_default();
+        tracing::error!(status = %status, body = %text, "Token exchange failed");
+        let err_msg = format!("Token exchange failed ({status}): {text}");
+        if pending.mobile {
+            let redirect = format!("freeq://auth?error={}", urlencod(&err_msg));
+            return Ok(axum::response::Redirect::to(&redirect).into_response());
+        }
+        return Ok(Html(oauth_result_page(&err_msg, None)).into_response());
+    };
+
+    let refresh_token = token_resp["refresh_token"]
+        .as_str()
+        .ok_or((StatusCode::BAD_GATEWAY, "No refresh_token".to_string()))?;
+
+    let broker_token = generate_random_string(32);
+    let now = chrono::Utc::now().timestamp();
+    // C-5: Encrypt sensitive fields before storing in DB
+    let enc_key = &state.encryption_key;
+    let encrypted_refresh = encrypt_field(enc_key, refresh_token);
+    let encrypted_dpop = encrypt_field(enc_key, &pending.dpop_key_b64);
+    let encrypted_nonce = dpop_nonce.as_deref().map(|n| encrypt_field(enc_key, n));
+    state
+        .store
+        .upsert_session(&StoredSession {
+            broker_token: broker_token.clone(),
+            did: pending.did.clone(),
+            handle: pending.handle.clone(),
+            pds_url: pending.pds_url.clone(),
+            token_endpoint: pending.token_endpoint.clone(),
+            refresh_token: encrypted_refresh,
+            dpop_key_b64: encrypted_dpop,
+            dpop_nonce: encrypted_nonce,
+            issuer: pending.client_key.is_some().then(|| pending.issuer.clone()),
+            client_kid: pending.client_key.as_ref().map(|k| k.kid.clone()),
+            web_origin: pending.web_origin.clone(),
+            created_at: now,
+            updated_at: now,
+        })
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("DB error: {e}")))?;
+
+    // Mint a one-time web-token + web session on the freeq server. Optional:
+    // a standalone broker (not trusted by irc.freeq.at's shared secret) just
+    // can't mint one — the verified DID + handle + broker_token are enough for
+    // identity-only consumers, so degrade gracefully instead of failing login.
+    let (web_token, nick) = mint_web_token(&state.config, &pending.did, &pending.handle)
+        .await
+        .unwrap_or_else(|e| {
+            tracing::warn!(error = %e, "web-token mint failed — continuing identity-only");
+            (String::new(), pending.handle.clone())
+        });
+
+    let session_id = webhook::session_id(&broker_token);
+    if let Err(e) = push_web_session(
+        &state.config,
+        &pending,
+        &session_id,
+        &token_resp,
+        dpop_nonce.clone(),
+    )
+    .await
+    {
+        tracing::warn!(error = %e, "Failed to push web session to server");
+    }
+    webhook::notify(
+        &state.config,
+        SessionWebhook {
+            event: SessionEvent::Created,
+            did: pending.did.clone(),
+            session: session_id,
+            device: webhook::device_label(&headers),
+            at: now,
+        },
+    );
+
+    if pending.mobile {
+        let redirect = format!(
+            "freeq://auth?token={}&broker_token={}&nick={}&did={}&handle={}",
+            urlencod(&web_token),
+            urlencod(&broker_token),
+            urlencod(&nick),
+            urlencod(&pending.did),
+            urlencod(&pending.handle),
+        );
+        // Must be a 302 redirect — ASWebAuthenticationSession only intercepts
+        // HTTP redirects with the custom scheme, not JS/meta-refresh in HTML.
+        return Ok(axum::response::Redirect::to(&redirect).into_response());
+    }
+
+    let result = serde_json::json!({
+        "token": web_token,
+        "broker_token": broker_token,
+        "nick": nick,
+        "did": pending.did,
+        "handle": pending.handle,
+        "pds_url": pending.pds_url,
+    });
+
+    let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .encode(serde_json::to_vec(&result).unwrap_or_default());
+    let redirect = format!("{return_to}#oauth={payload}");
+    tracing::info!(redirect_base = %return_to, "OAuth callback redirecting to app");
+    Ok(Redirect::temporary(&redirect).into_response())
+}
+
+async fn session(
+    State(state): State<Arc<BrokerState>>,
+    headers: HeaderMap,
+    Json(req): Json<BrokerSessionRequest>,
+) -> Result<Json<BrokerSessionResponse>, (StatusCode, String)> {
+    // M-13: CSRF protection — reject requests from disallowed origins
+    if let Some(origin) = headers.get("origin").and_then(|v| v.to_str().ok())
+        && !state.config.allowed_origins.iter().any(|o| o == origin)
+    {
+        tracing::warn!(origin = %origin, "Rejected /session request from disallowed origin");
+        return Err((StatusCode::FORBIDDEN, "Origin not allowed".to_string()));
+    }
+    let record = get_session(&state, &req.broker_token)
+        .await
+        .ok_or((StatusCode::UNAUTHORIZED, "Invalid broker token".to_string()))?;
+    // Browsers can't attest. A session from a web login may refresh
+    // without attestation, but only from the origin it was handed to;
+    // an `Origin` header alone exempts nothing.
+    let origin = headers.get("origin").and_then(|v| v.to_str().ok());
+    let from_its_browser = record.web_origin.is_some() && record.web_origin.as_deref() == origin;
+    check_attestation(&state, req.attestation.as_ref(), from_its_browser)?;
+
+    let (access_token, refresh_token, dpop_nonce, granted_scope) =
+        refresh_access_token(&state, &record)
+            .await
+            .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Refresh failed: {e}")))?;
+
+    // Update stored refresh token + nonce (C-5: encrypt before storing)
+    let now = chrono::Utc::now().timestamp();
+    let enc_key = &state.encryption_key;
+    let encrypted_refresh = encrypt_field(enc_key, &refresh_token);
+    let encrypted_nonce = dpop_nonce.as_deref().map(|n| encrypt_field(enc_key, n));
+    state
+        .store
+        .update_session_tokens(
+            &record.broker_token,
+            &encrypted_refresh,
+            encrypted_nonce.as_deref(),
+            now,
+        )
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("DB error: {e}")))?;
+
+    let (web_token, nick) = mint_web_token(&state.config, &record.did, &record.handle)
+        .await
+        .unwrap_or_else(|e| {
+            tracing::warn!(error = %e, "web-token mint failed — continuing identity-only");
+            (String::new(), record.handle.clone())
+        });
+
+    let pending = PendingAuth {
+        handle: record.handle.clone(),
+        did: record.did.clone(),
+        pds_url: record.pds_url.clone(),
+        code_verifier: String::new(),
+        redirect_uri: String::new(),
+        client_id: String::new(),
+        token_endpoint: record.token_endpoint.clone(),
+        issuer: String::new(),
+        client_key: None,
+        dpop_key_b64: record.dpop_key_b64.clone(),
+        dpop_nonce: dpop_nonce.clone(),
+        mobile: true,
+        return_to: None,
+        web_origin: record.web_origin.clone(),
+        popup: false,
+    };
+    let session_id = webhook::session_id(&record.broker_token);
+    if let Err(e) = push_web_session_with_token(
+        &state.config,
+        &pending,
+        &session_id,
+        &access_token,
+        dpop_nonce.clone(),
+        // Forward the actually-granted scope from the refresh response so
+        // the freeq-server's per-purpose checks see the truth, not a
+        // hard-coded narrow assumption.
+        &granted_scope,
+    )
+    .await
+    {
+        tracing::warn!(error = %e, "Failed to refresh web session on server");
+    }
+    webhook::notify(
+        &state.config,
+        SessionWebhook {
+            event: SessionEvent::Refreshed,
+            did: record.did.clone(),
+            session: session_id,
+            device: webhook::device_label(&headers),
+            at: now,
+        },
+    );
+
+    Ok(Json(BrokerSessionResponse {
+        token: web_token,
+        nick,
+        did: record.did,
+        handle: record.handle,
+    }))
+}
+
+/// Log out: forget the broker session so its refresh token can't be used
+/// again, and tell the server so it drops the web session it pushed.
+async fn revoke_session(
+    State(state): State<Arc<BrokerState>>,
+    headers: HeaderMap,
+    Json(req): Json<RevokeSessionRequest>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    if let Some(origin) = headers.get("origin").and_then(|v| v.to_str().ok())
+        && !state.config.allowed_origins.iter().any(|o| o == origin)
+    {
+        tracing::warn!(origin = %origin, "Rejected /session/revoke request from disallowed origin");
+        return Err((StatusCode::FORBIDDEN, "Origin not allowed".to_string()));
+    }
+    let stored = state
+        .store
+        .delete_session(&req.broker_token)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("DB error: {e}")))?
+        .ok_or((StatusCode::UNAUTHORIZED, "Invalid broker token".to_string()))?;
+    tracing::info!(did = %stored.did, "Broker session revoked");
+    webhook::notify(
+        &state.config,
+        SessionWebhook {
+            event: SessionEvent::Revoked,
+            did: stored.did,
+            session: webhook::session_id(&req.broker_token),
+            device: webhook::device_label(&headers),
+            at: chrono::Utc::now().timestamp(),
+        },
+    );
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_session(state: &Arc<BrokerState>, broker_token: &str) -> Option<BrokerSessionRecord> {
+    let stored = state
+        .store
+        .get_session(broker_token)
+        .map_err(|e| tracing::error!("Failed to load broker session: {e}"))
+        .ok()??;
+    let enc_key = &state.encryption_key;
+    // C-5: Decrypt sensitive fields after reading from DB
+    let refresh_token = decrypt_field(enc_key, &stored.refresh_token)
+        .map_err(|e| tracing::error!("Failed to decrypt refresh_token: {e}"))
+        .ok()?;
+    let dpop_key_b64 = decrypt_field(enc_key, &stored.dpop_key_b64)
+        .map_err(|e| tracing::error!("Failed to decrypt dpop_key_b64: {e}"))
+        .ok()?;
+    let dpop_nonce = stored
+        .dpop_nonce
+        .map(|n| decrypt_field(enc_key, &n))
+        .transpose()
+        .map_err(|e| tracing::error!("Failed to decrypt dpop_nonce: {e}"))
+        .ok()?;
+    Some(BrokerSessionRecord {
+        broker_token: stored.broker_token,
+        did: stored.did,
+        handle: stored.handle,
+        pds_url: stored.pds_url,
+        token_endpoint: stored.token_endpoint,
+        refresh_token,
+        dpop_key_b64,
+        dpop_nonce,
+        issuer: stored.issuer,
+        client_kid: stored.client_kid,
+        web_origin: stored.web_origin,
+        created_at: stored.created_at,
+        updated_at: stored.updated_at,
+    })
+}
+
+/// Returns `(access_token, refresh_token, dpop_nonce, granted_scope)`.
+///
+/// `granted_scope` is read from the refresh response's `scope` field
+/// when present. When the PDS omits it (some implementations do for
+/// refreshes), we default to `transition:generic`. That is intentionally
+/// conservative: the only refresh tokens the broker holds today were
+/// originally granted under `atproto transition:generic` (the broker
+/// only started narrowing to `atproto` in this same release), so the
+/// pre-existing grant is wide. After this release, every NEW broker
+/// session's first refresh response will carry the narrow scope
+/// explicitly and we'll record it correctly.
+async fn refresh_access_token(
+    state: &BrokerState,
+    record: &BrokerSessionRecord,
+) -> Result<(String, String, Option<String>, String), anyhow::Error> {
+    let config = &state.config;
+    let dpop_key = DpopKey::from_base64url(&record.dpop_key_b64)?;
+    let redirect_uri = format!("{}/auth/callback", config.public_url.trim_end_matches('/'));
+    let client_id = build_client_id(&config.public_url, &redirect_uri);
+    let params = [
+        ("grant_type", "refresh_token"),
+        ("refresh_token", record.refresh_token.as_str()),
+        ("client_id", client_id.as_str()),
+    ];
+    // Sessions created as a confidential client stay one, signing with
+    // the key they were created with while it is still published.
+    let client_key = match &record.client_kid {
+        None => None,
+        Some(kid) => Some(match state.client_keys.get(kid)? {
+            Some(key) => key,
+            None => {
+                tracing::warn!(%kid, "Session's client key is gone; signing with the current one");
+                state.client_keys.current()?
+            }
+        }),
+    };
+    let issuer = record.issuer.as_deref().unwrap_or_default();
+
+    let client = reqwest::Client::new();
+    let dpop_proof = dpop_key.proof("POST", &record.token_endpoint, None, None)?;
+    let resp = client
+        .post(&record.token_endpoint)
+        .header("DPoP", &dpop_proof)
+        .form(&client_form(&params, client_key.as_ref(), issuer)?)
+        .send()
+        .await?;
+    let status = resp.status();
+    let mut dpop_nonce = resp
+        .headers()
+        .get("dpop-nonce")
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string())
+        .or(record.dpop_nonce.clone());
+
+    let token_resp: serde_json::Value =
+        if (status.as_u16() == 400 || status.as_u16() == 401) && dpop_nonce.is_some() {
+            let nonce = dpop_nonce.as_deref().unwrap();
+            let dpop_proof2 = dpop_key.proof("POST", &record.token_endpoint, Some(nonce), None)?;
+            let resp2 = client
+                .post(&record.token_endpoint)
+                .header("DPoP", &dpop_proof2)
+                .form(&client_form(&params, client_key.as_ref(), issuer)?)
+                .send()
+                .await?;
+            if !resp2.status().is_success() {
+                return Err(anyhow::anyhow!(
+                    "Refresh failed: {}",
+                    resp2.text().await.unwrap_or_default()
+                ));
+            }
+            resp2.json().await?
+        } else if status.is_success() {
+            resp.json().await?
+        } else {
+            return Err(anyhow::anyhow!("Refresh failed ({status})"));
+        };
+
+    let access_token = token_resp["access_token"]
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("No access_token"))?
+        .to_string();
+    let refresh_token = token_resp["refresh_token"]
+        .as_str()
+        .unwrap_or(&record.refresh_token)
+        .to_string();
+    dpop_nonce = token_resp
+        .get("dpop_nonce")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+        .or(dpop_nonce);
+    // Conservative default explained on the function signature: refresh
+    // tokens stored in the broker today were originally granted under
+    // `transition:generic`, so a missing `scope` in the response means
+    // "preserve the existing wide grant".
+    let granted_scope = token_resp["scope"]
+        .as_str()
+        .unwrap_or("atproto transition:generic")
+        .to_string();
+
+    Ok((access_token, refresh_token, dpop_nonce, granted_scope))
+}
+
+async fn mint_web_token(
+    config: &BrokerConfig,
+    did: &str,
+    handle: &str,
+) -> Result<(String, String), anyhow::Error> {
+    let body = serde_json::json!({"did": did, "handle": handle});
+    let (sig, ts) = sign_body(&config.shared_secret, &body)?;
+    let url = format!(
+        "{}/auth/broker/web-token",
+        config.freeq_server_url.trim_end_matches('/')
+    );
+    let client = reqwest::Client::new();
+    let resp = client
+        .post(&url)
+        .header("X-Broker-Signature", sig)
+        .header("X-Broker-Timestamp", ts)
+        .json(&body)
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        return Err(anyhow::anyhow!(
+            "web-token failed: {}",
+            resp.text().await.unwrap_or_default()
+        ));
+    }
+    let json: serde_json::Value = resp.json().await?;
+    let token = json["token"].as_str().unwrap_or_default().to_string();
+    let nick = json["nick"].as_str().unwrap_or_default().to_string();
+    Ok((token, nick))
+}
+
+async fn push_web_session(
+    config: &BrokerConfig,
+    pending: &PendingAuth,
+    broker_session: &str,
+    token_resp: &serde_json::Value,
+    dpop_nonce: Option<String>,
+) -> Result<(), anyhow::Error> {
+    let access_token = token_resp["access_token"]
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("No access_token"))?;
+    // Read the actually-granted scope from the token response. Older
+    // PDSes may downgrade us to `transition:generic`. Defaults to
+    // `atproto` only when missing — that's the scope this broker now
+    // requests at /authorize, so it's the right narrow assumption for
+    // a code-exchange where the response forgot to echo the scope.
+    let granted_scope = token_resp["scope"].as_str().unwrap_or("atproto");
+    push_web_session_with_token(
+        config,
+        pending,
+        broker_session,
+        access_token,
+        dpop_nonce,
+        granted_scope,
+    )
+    .await
+}
+
+async fn push_web_session_with_token(
+    config: &BrokerConfig,
+    pending: &PendingAuth,
+    broker_session: &str,
+    access_token: &str,
+    dpop_nonce: Option<String>,
+    granted_scope: &str,
+) -> Result<(), anyhow::Error> {
+    let body = serde_json::json!({
+        "did": pending.did,
+        "handle": pending.handle,
+        "pds_url": pending.pds_url,
+        "access_token": access_token,
+        "dpop_key_b64": pending.dpop_key_b64,
+        "dpop_nonce": dpop_nonce,
+        "granted_scope": granted_scope,
+        "broker_session": broker_session,
+    });
+    let (sig, ts) = sign_body(&config.shared_secret, &body)?;
+    let url = format!(
+        "{}/auth/broker/session",
+        config.freeq_server_url.trim_end_matches('/')
+    );
+    let client = reqwest::Client::new();
+    let resp = client
+        .post(&url)
+        .header("X-Broker-Signature", sig)
+        .header("X-Broker-Timestamp", ts)
+        .json(&body)
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        return Err(anyhow::anyhow!(
+            "session push failed: {}",
+            resp.text().await.unwrap_or_default()
+        ));
+    }
+    Ok(())
+}
+
+fn oauth_result_page(message: &str, _result: Option<&serde_json::Value>) -> String {
+    format!(
+        r#"<!DOCTYPE html><html><head><meta charset="utf-8"><title>freeq auth</title>
+        <style>
+        body {{ font-family: system-ui; background: #1e1e2e; color: #cdd6f4; display: flex; align-items: center; justify-content: center; height: 100vh; margin: 0; }}
+        .box {{ text-align: center; }}
+        h1 {{ color: #89b4fa; font-size: 20px; }}
+        p {{ color: #a6adc8; }}
+        </style></head>
+        <body><div class="box"><h1>freeq</h1><p>{message}</p></div></body></html>"#
+    )
+}
+
+fn urlencod(s: &str) -> String {
+    use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+    utf8_percent_encode(s, NON_ALPHANUMERIC).to_string()
+}
+
+fn assertion_failed(e: anyhow::Error) -> (StatusCode, String) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Client assertion failed: {e}"),
+    )
+}
+
+/// Token-endpoint form `params` plus, for a confidential client, a fresh
+/// client assertion for `issuer` (each request needs its own `jti`).
+fn client_form(
+    params: &[(&str, &str)],
+    client_key: Option<&ClientKey>,
+    issuer: &str,
+) -> Result<Vec<(String, String)>, anyhow::Error> {
+    let mut form: Vec<(String, String)> = params
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    if let Some(key) = client_key {
+        let client_id = params
+            .iter()
+            .find(|(k, _)| *k == "client_id")
+            .map_or("", |(_, v)| *v);
+        form.push((
+            "client_assertion_type".to_string(),
+            ASSERTION_TYPE.to_string(),
+        ));
+        form.push((
+            "client_assertion".to_string(),
+            key.assertion(client_id, issuer)?,
+        ));
+    }
+    Ok(form)
+}
+
+fn build_client_id(web_origin: &str, redirect_uri: &str) -> String {
+    if is_loopback_origin(web_origin) {
+        // Loopback client_id advertises the union of scopes the broker
+        // could ever use, including the legacy transition:generic for
+        // refresh-token grace period. Actual login asks for "atproto".
+        let scope = "atproto blob:image/* repo:blue.irc.media?action=create repo:app.bsky.feed.post transition:generic";
+        format!(
+            "http://localhost?redirect_uri={}&scope={}",
+            urlencod(redirect_uri),
+            urlencod(scope),
+        )
+    } else {
+        format!("{web_origin}/client-metadata.json")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attest::AttestationMode;
+    use crate::store::SqliteStore;
+
+    /// A broker requiring attestation, holding a web-login session handed
+    /// to `https://irc.freeq.at` (`web`) and a mobile-flow one (`mobile`).
+    async fn required_broker() -> String {
+        let mut config = BrokerConfig::new("http://127.0.0.1:8081", "http://127.0.0.1:1", "secret");
+        config.attestation.mode = AttestationMode::Required;
+        let key = derive_encryption_key(&config.shared_secret);
+        let store = Arc::new(SqliteStore::open_in_memory().unwrap());
+        for (token, web_origin) in [("web", Some("https://irc.freeq.at")), ("mobile", None)] {
+            store
+                .upsert_session(&StoredSession {
+                    broker_token: token.to_string(),
+                    did: "did:plc:alice".to_string(),
+                    handle: "alice.test".to_string(),
+                    pds_url: "http://127.0.0.1:1".to_string(),
+                    token_endpoint: "http://127.0.0.1:1/token".to_string(),
+                    refresh_token: encrypt_field(&key, "refresh"),
+                    dpop_key_b64: encrypt_field(&key, "not-a-key"),
+                    dpop_nonce: None,
+                    issuer: None,
+                    client_kid: None,
+                    web_origin: web_origin.map(str::to_string),
+                    created_at: 0,
+                    updated_at: 0,
+                })
+                .unwrap();
+        }
+        let router = BrokerService::new(config, store).router();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        url
+    }
+
+    async fn post_session(url: &str, token: &str, origin: &str) -> (StatusCode, String) {
+        let resp = reqwest::Client::new()
+            .post(format!("{url}/session"))
+            .header("origin", origin)
+            .json(&serde_json::json!({ "broker_token": token }))
+            .send()
+            .await
+            .unwrap();
+        (resp.status(), resp.text().await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn an_origin_header_alone_does_not_skip_required_attestation() {
+        let url = required_broker().await;
+        let (status, body) = post_session(&url, "mobile", "https://irc.freeq.at").await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(body.contains("attestation required"), "{body}");
+
+        // A web session is only exempt from the origin it was handed to;
+        // past the check, the refresh fails upstream.
+        let (status, _) = post_session(&url, "web", "http://localhost:5173").await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, body) = post_session(&url, "web", "https://irc.freeq.at").await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY, "{body}");
+    }
+}
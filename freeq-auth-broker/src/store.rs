@@ -0,0 +1,343 @@
+//! Persistence for broker sessions, client signing keys and the identity
+//! resolution cache.
+//!
+//! The broker only ever hands a store ciphertext for the sensitive fields
+//! (refresh token, DPoP key, DPoP nonce, client private keys) — encryption
+//! happens in the
+//! service layer with a key derived from the shared secret, so a custom
+//! store never sees upstream credentials in the clear.
+
+use std::sync::Mutex;
+
+/// A persisted broker session, keyed by the opaque `broker_token` handed
+/// to the client.
+#[derive(Debug, Clone)]
+pub struct StoredSession {
+    pub broker_token: String,
+    pub did: String,
+    pub handle: String,
+    pub pds_url: String,
+    pub token_endpoint: String,
+    /// Encrypted.
+    pub refresh_token: String,
+    /// Encrypted.
+    pub dpop_key_b64: String,
+    /// Encrypted.
+    pub dpop_nonce: Option<String>,
+    /// Issuer of the authorization server, for sessions that authenticate
+    /// to it with `private_key_jwt`.
+    pub issuer: Option<String>,
+    /// The client signing key the session was created with; its refreshes
+    /// are signed with the same key.
+    pub client_kid: Option<String>,
+    /// The allowed origin a web login handed the session to. Only that
+    /// origin may refresh it without a client attestation.
+    pub web_origin: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// An ES256 key for `private_key_jwt` client assertions.
+#[derive(Debug, Clone)]
+pub struct StoredClientKey {
+    pub kid: String,
+    /// Encrypted.
+    pub private_key: String,
+    pub created_at: i64,
+    /// When a newer key took over signing for new sessions.
+    pub retired_at: Option<i64>,
+}
+
+/// A cached handle→DID or DID→PDS resolution. Exactly one of `value` /
+/// `error` is set.
+#[derive(Debug, Clone)]
+pub struct IdentityRecord {
+    pub value: Option<String>,
+    pub error: Option<String>,
+    pub fetched_at: i64,
+}
+
+/// Storage backend for [`crate::BrokerService`].
+///
+/// Methods are synchronous: they're called from request handlers for a
+/// handful of small row operations, same as the SQLite calls they replaced.
+pub trait BrokerStore: Send + Sync {
+    /// Insert a session, or refresh the token and `updated_at` of an
+    /// existing one with the same `broker_token`.
+    fn upsert_session(&self, session: &StoredSession) -> Result<(), anyhow::Error>;
+
+    fn get_session(&self, broker_token: &str) -> Result<Option<StoredSession>, anyhow::Error>;
+
+    /// Record a rotated refresh token / DPoP nonce after a refresh grant.
+    fn update_session_tokens(
+        &self,
+        broker_token: &str,
+        refresh_token: &str,
+        dpop_nonce: Option<&str>,
+        updated_at: i64,
+    ) -> Result<(), anyhow::Error>;
+
+    /// Forget a session (the user logged out). Returns the deleted
+    /// session, or `None` if there was none.
+    fn delete_session(&self, broker_token: &str) -> Result<Option<StoredSession>, anyhow::Error>;
+
+    fn get_identity(&self, kind: &str, key: &str) -> Result<Option<IdentityRecord>, anyhow::Error>;
+
+    fn put_identity(
+        &self,
+        kind: &str,
+        key: &str,
+        record: &IdentityRecord,
+    ) -> Result<(), anyhow::Error>;
+
+    /// Delete successful entries fetched before `positive_before` and
+    /// failures fetched before `negative_before`. Returns rows removed.
+    fn prune_identities(
+        &self,
+        positive_before: i64,
+        negative_before: i64,
+    ) -> Result<usize, anyhow::Error>;
+
+    /// All client signing keys, oldest first.
+    fn list_client_keys(&self) -> Result<Vec<StoredClientKey>, anyhow::Error>;
+
+    fn insert_client_key(&self, key: &StoredClientKey) -> Result<(), anyhow::Error>;
+
+    fn retire_client_key(&self, kid: &str, retired_at: i64) -> Result<(), anyhow::Error>;
+
+    fn delete_client_key(&self, kid: &str) -> Result<(), anyhow::Error>;
+}
+
+/// The default [`BrokerStore`], backed by a single SQLite connection.
+pub struct SqliteStore {
+    db: Mutex<rusqlite::Connection>,
+}
+
+impl SqliteStore {
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<Self, anyhow::Error> {
+        Self::from_connection(rusqlite::Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, anyhow::Error> {
+        Self::from_connection(rusqlite::Connection::open_in_memory()?)
+    }
+
+    /// Wrap an already-open connection, creating tables if needed.
+    pub fn from_connection(db: rusqlite::Connection) -> Result<Self, anyhow::Error> {
+        init_db(&db)?;
+        Ok(Self { db: Mutex::new(db) })
+    }
+}
+
+impl BrokerStore for SqliteStore {
+    fn upsert_session(&self, s: &StoredSession) -> Result<(), anyhow::Error> {
+        let db = self.db.lock().unwrap();
+        db.execute(
+            "INSERT INTO sessions (broker_token, did, handle, pds_url, token_endpoint, refresh_token, dpop_key_b64, dpop_nonce, issuer, client_kid, web_origin, created_at, updated_at)\
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)\
+             ON CONFLICT(broker_token) DO UPDATE SET refresh_token=excluded.refresh_token, updated_at=excluded.updated_at",
+            rusqlite::params![
+                s.broker_token,
+                s.did,
+                s.handle,
+                s.pds_url,
+                s.token_endpoint,
+                s.refresh_token,
+                s.dpop_key_b64,
+                s.dpop_nonce,
+                s.issuer,
+                s.client_kid,
+                s.web_origin,
+                s.created_at,
+                s.updated_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn get_session(&self, broker_token: &str) -> Result<Option<StoredSession>, anyhow::Error> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare(
+            "SELECT broker_token, did, handle, pds_url, token_endpoint, refresh_token, dpop_key_b64, dpop_nonce, issuer, client_kid, web_origin, created_at, updated_at FROM sessions WHERE broker_token = ?1"
+        )?;
+        let mut rows = stmt.query(rusqlite::params![broker_token])?;
+        let Some(row) = rows.next()? else {
+            return Ok(None);
+        };
+        Ok(Some(StoredSession {
+            broker_token: row.get(0)?,
+            did: row.get(1)?,
+            handle: row.get(2)?,
+            pds_url: row.get(3)?,
+            token_endpoint: row.get(4)?,
+            refresh_token: row.get(5)?,
+            dpop_key_b64: row.get(6)?,
+            dpop_nonce: row.get(7)?,
+            issuer: row.get(8)?,
+            client_kid: row.get(9)?,
+            web_origin: row.get(10)?,
+            created_at: row.get(11)?,
+            updated_at: row.get(12)?,
+        }))
+    }
+
+    fn update_session_tokens(
+        &self,
+        broker_token: &str,
+        refresh_token: &str,
+        dpop_nonce: Option<&str>,
+        updated_at: i64,
+    ) -> Result<(), anyhow::Error> {
+        let db = self.db.lock().unwrap();
+        db.execute(
+            "UPDATE sessions SET refresh_token = ?1, dpop_nonce = ?2, updated_at = ?3 WHERE broker_token = ?4",
+            rusqlite::params![refresh_token, dpop_nonce, updated_at, broker_token],
+        )?;
+        Ok(())
+    }
+
+    fn delete_session(&self, broker_token: &str) -> Result<Option<StoredSession>, anyhow::Error> {
+        let session = self.get_session(broker_token)?;
+        if session.is_some() {
+            self.db.lock().unwrap().execute(
+                "DELETE FROM sessions WHERE broker_token = ?1",
+                rusqlite::params![broker_token],
+            )?;
+        }
+        Ok(session)
+    }
+
+    fn get_identity(&self, kind: &str, key: &str) -> Result<Option<IdentityRecord>, anyhow::Error> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare(
+            "SELECT value, error, fetched_at FROM identity_cache WHERE kind = ?1 AND key = ?2",
+        )?;
+        let mut rows = stmt.query(rusqlite::params![kind, key])?;
+        let Some(row) = rows.next()? else {
+            return Ok(None);
+        };
+        Ok(Some(IdentityRecord {
+            value: row.get(0)?,
+            error: row.get(1)?,
+            fetched_at: row.get(2)?,
+        }))
+    }
+
+    fn put_identity(
+        &self,
+        kind: &str,
+        key: &str,
+        record: &IdentityRecord,
+    ) -> Result<(), anyhow::Error> {
+        let db = self.db.lock().unwrap();
+        db.execute(
+            "INSERT INTO identity_cache (kind, key, value, error, fetched_at) VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(kind, key) DO UPDATE SET value=excluded.value, error=excluded.error, fetched_at=excluded.fetched_at",
+            rusqlite::params![kind, key, record.value, record.error, record.fetched_at],
+        )?;
+        Ok(())
+    }
+
+    fn prune_identities(
+        &self,
+        positive_before: i64,
+        negative_before: i64,
+    ) -> Result<usize, anyhow::Error> {
+        let db = self.db.lock().unwrap();
+        Ok(db.execute(
+            "DELETE FROM identity_cache WHERE (value IS NOT NULL AND fetched_at < ?1) OR (value IS NULL AND fetched_at < ?2)",
+            rusqlite::params![positive_before, negative_before],
+        )?)
+    }
+
+    fn list_client_keys(&self) -> Result<Vec<StoredClientKey>, anyhow::Error> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare(
+            "SELECT kid, private_key, created_at, retired_at FROM client_keys ORDER BY created_at, kid",
+        )?;
+        let keys = stmt
+            .query_map([], |row| {
+                Ok(StoredClientKey {
+                    kid: row.get(0)?,
+                    private_key: row.get(1)?,
+                    created_at: row.get(2)?,
+                    retired_at: row.get(3)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(keys)
+    }
+
+    fn insert_client_key(&self, key: &StoredClientKey) -> Result<(), anyhow::Error> {
+        let db = self.db.lock().unwrap();
+        db.execute(
+            "INSERT INTO client_keys (kid, private_key, created_at, retired_at) VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params![key.kid, key.private_key, key.created_at, key.retired_at],
+        )?;
+        Ok(())
+    }
+
+    fn retire_client_key(&self, kid: &str, retired_at: i64) -> Result<(), anyhow::Error> {
+        let db = self.db.lock().unwrap();
+        db.execute(
+            "UPDATE client_keys SET retired_at = ?1 WHERE kid = ?2 AND retired_at IS NULL",
+            rusqlite::params![retired_at, kid],
+        )?;
+        Ok(())
+    }
+
+    fn delete_client_key(&self, kid: &str) -> Result<(), anyhow::Error> {
+        let db = self.db.lock().unwrap();
+        db.execute(
+            "DELETE FROM client_keys WHERE kid = ?1",
+            rusqlite::params![kid],
+        )?;
+        Ok(())
+    }
+}
+
+fn init_db(db: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+    db.execute_batch(
+        "CREATE TABLE IF NOT EXISTS sessions (
+            broker_token TEXT PRIMARY KEY,
+            did TEXT NOT NULL,
+            handle TEXT NOT NULL,
+            pds_url TEXT NOT NULL,
+            token_endpoint TEXT NOT NULL,
+            refresh_token TEXT NOT NULL,
+            dpop_key_b64 TEXT NOT NULL,
+            dpop_nonce TEXT,
+            issuer TEXT,
+            client_kid TEXT,
+            web_origin TEXT,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS client_keys (
+            kid TEXT PRIMARY KEY,
+            private_key TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            retired_at INTEGER
+        );
+        CREATE TABLE IF NOT EXISTS identity_cache (
+            kind TEXT NOT NULL,
+            key TEXT NOT NULL,
+            value TEXT,
+            error TEXT,
+            fetched_at INTEGER NOT NULL,
+            PRIMARY KEY (kind, key)
+        );",
+    )?;
+    // Sessions tables from before private_key_jwt support and web-origin
+    // binding.
+    let has_column = |name: &str| -> Result<bool, rusqlite::Error> {
+        db.prepare("SELECT 1 FROM pragma_table_info('sessions') WHERE name = ?1")?
+            .exists([name])
+    };
+    for column in ["issuer", "client_kid", "web_origin"] {
+        if !has_column(column)? {
+            db.execute_batch(&format!("ALTER TABLE sessions ADD COLUMN {column} TEXT"))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,134 @@
+//! Session lifecycle webhooks to the freeq server.
+//!
+//! Token pushes only tell the server about a session while it's in use.
+//! These tell it when a broker session is created (OAuth callback),
+//! refreshed (`/session`) and revoked (`/session/revoke`), so it can list
+//! the user's devices in `SESSIONS` and drop a revoked device's web
+//! session right away instead of waiting for it to idle out.
+//!
+//! Each event is a signed `POST {freeq_server_url}/auth/broker/events`,
+//! same HMAC scheme as the token pushes. Delivery is best-effort: a
+//! server that's down or doesn't know the endpoint never fails a login.
+
+use base64::Engine;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::config::BrokerConfig;
+use crate::crypto::sign_body;
+
+/// Longest device label forwarded; User-Agents can be very long.
+const MAX_DEVICE_LEN: usize = 80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SessionEvent {
+    Created,
+    Refreshed,
+    Revoked,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct SessionWebhook {
+    pub event: SessionEvent,
+    pub did: String,
+    /// [`session_id`] of the broker session.
+    pub session: String,
+    pub device: Option<String>,
+    /// Unix seconds.
+    pub at: i64,
+}
+
+/// Stable public ID for a broker session. The broker token is a bearer
+/// credential, so the server only ever sees a hash of it.
+pub(crate) fn session_id(broker_token: &str) -> String {
+    let hash = Sha256::digest(broker_token.as_bytes());
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(&hash[..9])
+}
+
+/// A short device label from the request's User-Agent.
+pub(crate) fn device_label(headers: &axum::http::HeaderMap) -> Option<String> {
+    let ua = headers
+        .get(axum::http::header::USER_AGENT)?
+        .to_str()
+        .ok()?
+        .trim();
+    (!ua.is_empty()).then(|| ua.chars().take(MAX_DEVICE_LEN).collect())
+}
+
+/// Send `hook` in the background, logging (not returning) failures.
+pub(crate) fn notify(config: &BrokerConfig, hook: SessionWebhook) {
+    let secret = config.shared_secret.clone();
+    let url = format!(
+        "{}/auth/broker/events",
+        config.freeq_server_url.trim_end_matches('/')
+    );
+    tokio::spawn(async move {
+        if let Err(e) = send(&secret, &url, &hook).await {
+            tracing::warn!(error = %e, event = ?hook.event, "Session webhook failed");
+        }
+    });
+}
+
+async fn send(secret: &str, url: &str, hook: &SessionWebhook) -> Result<(), anyhow::Error> {
+    let body = serde_json::to_value(hook)?;
+    let (sig, ts) = sign_body(secret, &body)?;
+    let resp = reqwest::Client::new()
+        .post(url)
+        .header("X-Broker-Signature", sig)
+        .header("X-Broker-Timestamp", ts)
+        .json(&body)
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        return Err(anyhow::anyhow!(
+            "{}: {}",
+            resp.status(),
+            resp.text().await.unwrap_or_default()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_id_is_stable_and_hides_the_token() {
+        let id = session_id("secret-broker-token");
+        assert_eq!(id, session_id("secret-broker-token"));
+        assert_ne!(id, session_id("another-broker-token"));
+        assert_eq!(id.len(), 12);
+        assert!(!"secret-broker-token".contains(&id));
+    }
+
+    #[test]
+    fn webhook_body_shape() {
+        let hook = SessionWebhook {
+            event: SessionEvent::Revoked,
+            did: "did:plc:abc".into(),
+            session: "xyz".into(),
+            device: None,
+            at: 1_700_000_000,
+        };
+        assert_eq!(
+            serde_json::to_value(&hook).unwrap(),
+            serde_json::json!({
+                "event": "revoked",
+                "did": "did:plc:abc",
+                "session": "xyz",
+                "device": null,
+                "at": 1_700_000_000,
+            })
+        );
+    }
+
+    #[test]
+    fn device_label_is_truncated() {
+        let mut headers = axum::http::HeaderMap::new();
+        assert_eq!(device_label(&headers), None);
+        headers.insert("user-agent", "x".repeat(200).parse().unwrap());
+        assert_eq!(device_label(&headers).unwrap().len(), MAX_DEVICE_LEN);
+    }
+}
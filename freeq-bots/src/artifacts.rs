@@ -0,0 +1,278 @@
+//! Artifacts browser — read-only HTTP access to generated project files.
+//!
+//! Each project workspace is served under a capability URL,
+//! `/p/{project}/{token}/`, where the token is an HMAC of the project name.
+//! The bot posts that URL in-channel next to the deploy URL, so anyone in
+//! the channel can read the code without a shell on the host, and nobody
+//! can enumerate other projects.
+//!
+//! The root of a project URL lists its files; `/p/{project}/{token}/{path}`
+//! returns one file as plain text. Dotfiles (`.env`, `.git/`, `.miren/`)
+//! are never served, and nothing outside the workspace is reachable even
+//! through symlinks the agents may have created.
+
+use std::net::SocketAddr;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::Router;
+use axum::extract::{Path as UrlPath, State};
+use axum::http::{StatusCode, header};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::get;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::tools;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Largest file served; bigger ones get 413.
+const MAX_FILE_BYTES: u64 = 2 * 1024 * 1024;
+
+/// Serves project workspaces under `workspace_base`.
+#[derive(Clone)]
+pub struct Artifacts {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    workspace_base: PathBuf,
+    public_url: String,
+    key: Vec<u8>,
+}
+
+impl Artifacts {
+    /// `public_url` is where the server is reachable from IRC clients
+    /// (e.g. `https://bots.example.com`); `key` signs the project tokens.
+    pub fn new(workspace_base: PathBuf, public_url: &str, key: &[u8]) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                workspace_base,
+                public_url: public_url.trim_end_matches('/').to_string(),
+                key: key.to_vec(),
+            }),
+        }
+    }
+
+    /// The base64url HMAC token for `project`.
+    pub fn token(&self, project: &str) -> String {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.inner.key).expect("HMAC accepts any key length");
+        mac.update(project.as_bytes());
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+    }
+
+    /// Check a token for `project` in constant time.
+    pub fn verify(&self, project: &str, token: &str) -> bool {
+        let Ok(provided) = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(token) else {
+            return false;
+        };
+        let mut mac =
+            HmacSha256::new_from_slice(&self.inner.key).expect("HMAC accepts any key length");
+        mac.update(project.as_bytes());
+        mac.verify_slice(&provided).is_ok()
+    }
+
+    /// The URL to post in-channel for browsing `project`.
+    pub fn url(&self, project: &str) -> String {
+        format!(
+            "{}/p/{}/{}/",
+            self.inner.public_url,
+            project,
+            self.token(project)
+        )
+    }
+
+    /// The HTTP routes, for embedding in another server.
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/p/{project}/{token}/", get(list_project))
+            .route("/p/{project}/{token}/{*path}", get(get_file))
+            .with_state(self.clone())
+    }
+
+    /// Serve on `addr` until the process exits.
+    pub async fn serve(self, addr: SocketAddr) -> Result<()> {
+        self.serve_with(addr, Router::new()).await
+    }
+
+    /// Serve on `addr` with `extra` routes (e.g. webhooks) alongside.
+    pub async fn serve_with(self, addr: SocketAddr, extra: Router) -> Result<()> {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        tracing::info!(%addr, "Artifacts browser listening");
+        axum::serve(listener, self.router().merge(extra)).await?;
+        Ok(())
+    }
+
+    /// The workspace root for `project`, if the token is valid and the
+    /// project exists.
+    fn project_root(&self, project: &str, token: &str) -> Option<PathBuf> {
+        if !is_project_name(project) || !self.verify(project, token) {
+            return None;
+        }
+        let root = self.inner.workspace_base.join(project);
+        root.is_dir().then_some(root)
+    }
+}
+
+/// Workspace directory names, as [`tools::Workspace::create`] makes them.
+fn is_project_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('-')
+        && name.chars().all(|c| c.is_alphanumeric() || c == '-')
+}
+
+/// Resolve a request path inside `root`: plain, non-hidden components only,
+/// and the resolved file must still be inside `root` after symlinks.
+fn resolve(root: &Path, path: &str) -> Option<PathBuf> {
+    let rel = Path::new(path);
+    for component in rel.components() {
+        match component {
+            Component::Normal(name) if !name.to_string_lossy().starts_with('.') => {}
+            _ => return None,
+        }
+    }
+    let root = root.canonicalize().ok()?;
+    let full = root.join(rel).canonicalize().ok()?;
+    full.starts_with(&root).then_some(full)
+}
+
+async fn list_project(
+    State(artifacts): State<Artifacts>,
+    UrlPath((project, token)): UrlPath<(String, String)>,
+) -> Response {
+    let Some(root) = artifacts.project_root(&project, &token) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let mut files = tokio::task::spawn_blocking(move || tools::list_files_sync_pub(&root))
+        .await
+        .unwrap_or_default();
+    files.retain(|f| !f.split('/').any(|c| c.starts_with('.')));
+    files.sort();
+    Html(render_listing(&project, &files)).into_response()
+}
+
+async fn get_file(
+    State(artifacts): State<Artifacts>,
+    UrlPath((project, token, path)): UrlPath<(String, String, String)>,
+) -> Response {
+    let Some(root) = artifacts.project_root(&project, &token) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let Some(full) = resolve(&root, &path) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    match tokio::fs::metadata(&full).await {
+        Ok(meta) if meta.is_file() && meta.len() > MAX_FILE_BYTES => {
+            return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+        }
+        Ok(meta) if meta.is_file() => {}
+        _ => return StatusCode::NOT_FOUND.into_response(),
+    }
+    let Ok(bytes) = tokio::fs::read(&full).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let content_type = if std::str::from_utf8(&bytes).is_ok() {
+        "text/plain; charset=utf-8"
+    } else {
+        "application/octet-stream"
+    };
+    (
+        [
+            (header::CONTENT_TYPE, content_type),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff"),
+        ],
+        bytes,
+    )
+        .into_response()
+}
+
+fn render_listing(project: &str, files: &[String]) -> String {
+    let project = escape(project);
+    let mut html = format!(
+        "<!doctype html>\n<meta charset=\"utf-8\">\n<title>{project}</title>\n<h1>{project}</h1>\n"
+    );
+    if files.is_empty() {
+        html.push_str("<p>No files yet.</p>\n");
+        return html;
+    }
+    html.push_str("<ul>\n");
+    for file in files {
+        let file = escape(file);
+        html.push_str(&format!("<li><a href=\"{file}\">{file}</a></li>\n"));
+    }
+    html.push_str("</ul>\n");
+    html
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_per_project() {
+        let a = Artifacts::new(PathBuf::from("/tmp"), "https://bots.example/", b"key");
+        let token = a.token("todo-app");
+        assert!(a.verify("todo-app", &token));
+        assert!(!a.verify("other-app", &token));
+        assert!(!a.verify("todo-app", "not base64!"));
+        let other_key = Artifacts::new(PathBuf::from("/tmp"), "https://bots.example", b"other");
+        assert!(!other_key.verify("todo-app", &token));
+        assert_eq!(
+            a.url("todo-app"),
+            format!("https://bots.example/p/todo-app/{token}/")
+        );
+    }
+
+    #[test]
+    fn project_names_match_workspace_names() {
+        assert!(is_project_name("todo-app2"));
+        assert!(!is_project_name(""));
+        assert!(!is_project_name(".."));
+        assert!(!is_project_name("a/b"));
+        assert!(!is_project_name("-rf"));
+    }
+
+    #[test]
+    fn paths_stay_inside_the_workspace() {
+        let root = std::env::temp_dir().join(format!("freeq-artifacts-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(root.join("src/app.py"), "print('hi')").unwrap();
+        std::fs::write(root.join(".env"), "SECRET=1").unwrap();
+
+        let canonical = root.canonicalize().unwrap();
+        assert_eq!(
+            resolve(&root, "src/app.py"),
+            Some(canonical.join("src/app.py"))
+        );
+        assert_eq!(resolve(&root, ".env"), None);
+        assert_eq!(resolve(&root, "src/../.env"), None);
+        assert_eq!(resolve(&root, "../etc/passwd"), None);
+        assert_eq!(resolve(&root, "/etc/passwd"), None);
+        assert_eq!(resolve(&root, "missing.py"), None);
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("/etc", root.join("etc")).unwrap();
+            assert_eq!(resolve(&root, "etc/passwd"), None);
+        }
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn listings_escape_file_names() {
+        let html = render_listing("app", &["a<b>.py".to_string()]);
+        assert!(html.contains("<a href=\"a&lt;b&gt;.py\">a&lt;b&gt;.py</a>"));
+        assert!(render_listing("app", &[]).contains("No files yet"));
+    }
+}
@@ -2,19 +2,26 @@
 //!
 //! Triggered by `/audit <github-url>` — clones the repo, analyzes structure,
 //! and posts findings: system diagram, bottlenecks, coupling, suggestions.
+//! The diagram is Mermaid, attached as a rendered image when a renderer is
+//! set (see [`crate::diagram`]).
+//! `/audit security <github-url>` runs the dependency and secret scanners
+//! in [`scan`] first, has the LLM rank what they found, and attaches the
+//! findings as JSON.
+
+pub mod scan;
 
 use anyhow::Result;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use crate::llm::LlmClient;
+use crate::llm::{LlmClient, Task};
 use crate::output::{self, AgentId};
+use crate::sink::OutputSink;
 use crate::tools::{self, Workspace};
-use freeq_sdk::client::ClientHandle;
 
 fn auditor() -> AgentId {
     AgentId {
         role: "auditor".to_string(),
-        color: None,
+        color: Some(output::color::BROWN),
     }
 }
 
@@ -23,7 +30,8 @@ const SYSTEM: &str = r#"You are a principal engineer performing an architecture
 Given a repository's file tree and key file contents, produce a structured audit:
 
 1. **System Overview**: What this project does, in one paragraph.
-2. **Architecture Diagram**: ASCII diagram of major components and data flow.
+2. **Architecture Diagram**: A Mermaid flowchart of major components and data flow, in a
+   ```mermaid code fence. Keep it under 20 nodes; quote labels with punctuation.
 3. **Stack**: Languages, frameworks, databases, infrastructure.
 4. **Strengths**: What's well-designed (2-3 bullets).
 5. **Risks & Bottlenecks**: Scaling risks, single points of failure, tight coupling (3-5 bullets).
@@ -31,16 +39,35 @@ Given a repository's file tree and key file contents, produce a structured audit
 
 Be specific. Reference actual file names and patterns you see. No generic advice."#;
 
+const SECURITY_SYSTEM: &str = r#"You are a security engineer performing a security audit.
+
+You are given a repository's file tree, key file contents, and the ranked output of
+dependency scanners (cargo audit, npm audit, pip-audit) and a secret scanner. Produce:
+
+1. **Summary**: Overall security posture in one paragraph, with the finding totals.
+2. **Critical & High**: Each serious finding — what it is, where, whether it is
+   reachable from this code, and the fix (upgrade target, rotation). Most severe first.
+3. **Medium & Low**: Grouped briefly; call out likely false positives (test fixtures,
+   placeholders, dev-only dependencies).
+4. **Beyond the Scanners**: 2-4 risks you see in the code itself (input handling, authn/z,
+   unsafe defaults, secrets handling) that no tool reported.
+5. **Remediation Plan**: Prioritized, concrete steps.
+
+Never repeat secret values. Reference actual file names and advisory ids."#;
+
+/// Findings quoted in the security prompt; the rest are in the attachment.
+const PROMPT_FINDINGS: usize = 60;
+
 /// Run an architecture audit on a GitHub repo or local path.
 pub async fn audit(
-    handle: &ClientHandle,
+    sink: &dyn OutputSink,
     channel: &str,
     target: &str,
     llm: &LlmClient,
     workspace_base: &Path,
 ) -> Result<()> {
     output::status(
-        handle,
+        sink,
         channel,
         &auditor(),
         "🔍",
@@ -49,44 +76,155 @@ pub async fn audit(
     .await?;
 
     let workspace = Workspace::create(workspace_base, "audit-workspace").await?;
+    let Some(repo_dir) = checkout(sink, channel, target, &workspace).await? else {
+        return Ok(());
+    };
+    let context = repo_context(sink, channel, &workspace, &repo_dir).await?;
+
+    // Build the audit prompt
+    let prompt = format!("Audit this repository.\n\n{context}");
+
+    output::status(sink, channel, &auditor(), "🧠", "Analyzing architecture...").await?;
+
+    // Stream the analysis in real-time
+    let deltas = llm
+        .for_task(Task::Audit)
+        .complete_stream(SYSTEM, &prompt)
+        .await?;
+    let (analysis, _) = output::stream_response(sink, channel, &auditor(), deltas).await?;
+    output::diagrams(sink, channel, &auditor(), &analysis).await?;
+
+    // Clean up
+    let _ = tokio::fs::remove_dir_all(&workspace.root).await;
+
+    output::status(sink, channel, &auditor(), "✅", "Audit complete").await?;
+    Ok(())
+}
+
+/// Run a security audit: dependency advisories and a secret scan, ranked
+/// by the LLM, with the raw findings attached as `security-audit.json`.
+pub async fn security_audit(
+    sink: &dyn OutputSink,
+    channel: &str,
+    target: &str,
+    llm: &LlmClient,
+    workspace_base: &Path,
+) -> Result<()> {
+    output::status(
+        sink,
+        channel,
+        &auditor(),
+        "🛡️",
+        &format!("Starting security audit: {target}"),
+    )
+    .await?;
+
+    let workspace = Workspace::create(workspace_base, "audit-workspace").await?;
+    let Some(repo_dir) = checkout(sink, channel, target, &workspace).await? else {
+        return Ok(());
+    };
+    let context = repo_context(sink, channel, &workspace, &repo_dir).await?;
+
+    output::status(
+        sink,
+        channel,
+        &auditor(),
+        "🔎",
+        "Running dependency audits and secret scan...",
+    )
+    .await?;
+    let report = scan::run_all(&repo_dir, target).await;
+    output::status(
+        sink,
+        channel,
+        &auditor(),
+        "📊",
+        &format!("Scanners: {}", report.headline()),
+    )
+    .await?;
+
+    let prompt = format!(
+        "Security-audit this repository.\n\n## Scanner Findings\n```\n{}\n```\n\n{context}",
+        report.to_prompt(PROMPT_FINDINGS)
+    );
+
+    output::status(sink, channel, &auditor(), "🧠", "Analyzing findings...").await?;
+    let deltas = llm
+        .for_task(Task::Audit)
+        .complete_stream(SECURITY_SYSTEM, &prompt)
+        .await?;
+    output::stream_response(sink, channel, &auditor(), deltas).await?;
+
+    let json = serde_json::to_string_pretty(&report)?;
+    output::attachment(
+        sink,
+        channel,
+        &auditor(),
+        "security-audit.json",
+        "application/json",
+        &json,
+    )
+    .await?;
+
+    let _ = tokio::fs::remove_dir_all(&workspace.root).await;
 
+    output::status(sink, channel, &auditor(), "✅", "Security audit complete").await?;
+    Ok(())
+}
+
+/// Clone `target` into the workspace if it's a URL; the repo root, or
+/// `None` (after reporting it) if the clone failed.
+async fn checkout(
+    sink: &dyn OutputSink,
+    channel: &str,
+    target: &str,
+    workspace: &Workspace,
+) -> Result<Option<PathBuf>> {
     // Clone if it's a URL, otherwise treat as local
     if target.starts_with("http") || target.contains("github.com") {
-        output::status(handle, channel, &auditor(), "📥", "Cloning repository...").await?;
+        output::status(sink, channel, &auditor(), "📥", "Cloning repository...").await?;
         let clone_result = tools::shell(
-            &workspace,
+            workspace,
             &format!("git clone --depth 1 {target} repo 2>&1"),
             60,
         )
         .await?;
         if clone_result.contains("fatal") {
             output::error(
-                handle,
+                sink,
                 channel,
                 &auditor(),
                 &format!("Clone failed: {clone_result}"),
             )
             .await?;
-            return Ok(());
+            return Ok(None);
         }
     }
 
     // Find the repo root
-    let repo_dir = if workspace.root.join("repo").exists() {
+    Ok(Some(if workspace.root.join("repo").exists() {
         workspace.root.join("repo")
     } else {
         workspace.root.clone()
-    };
+    }))
+}
 
+/// File tree, source layout and key file contents, as prompt sections.
+async fn repo_context(
+    sink: &dyn OutputSink,
+    channel: &str,
+    workspace: &Workspace,
+    repo_dir: &Path,
+) -> Result<String> {
     // Gather file tree
-    output::status(handle, channel, &auditor(), "📁", "Scanning file tree...").await?;
-    let tree = tools::shell(&workspace, &format!(
+    output::status(sink, channel, &auditor(), "📁", "Scanning file tree...").await?;
+    let tree = tools::shell(workspace, &format!(
         "find {} -type f -not -path '*/.git/*' -not -path '*/node_modules/*' -not -path '*/target/*' -not -path '*/__pycache__/*' -not -path '*/.next/*' | head -200 | sort",
         repo_dir.display()
     ), 10).await?;
 
     // Read key files
-    output::status(handle, channel, &auditor(), "📄", "Reading key files...").await?;
+    output::status(sink, channel, &auditor(), "📄", "Reading key files...").await?;
     let key_files = [
         "Cargo.toml",
         "package.json",
@@ -125,32 +263,12 @@ pub async fn audit(
     }
 
     // Also try to find the main source structure
-    let src_tree = tools::shell(&workspace, &format!(
+    let src_tree = tools::shell(workspace, &format!(
         "find {} -maxdepth 3 -name '*.rs' -o -name '*.py' -o -name '*.ts' -o -name '*.go' -o -name '*.js' 2>/dev/null | head -50 | sort",
         repo_dir.display()
     ), 10).await.unwrap_or_default();
 
-    // Build the audit prompt
-    let prompt = format!(
-        "Audit this repository.\n\n## File Tree\n```\n{tree}\n```\n\n## Source Files\n```\n{src_tree}\n```\n\n## Key File Contents\n{file_contents}"
-    );
-
-    output::status(
-        handle,
-        channel,
-        &auditor(),
-        "🧠",
-        "Analyzing architecture...",
-    )
-    .await?;
-
-    // Stream the analysis in real-time
-    let deltas = llm.complete_stream(SYSTEM, &prompt).await?;
-    output::stream_response(handle, channel, &auditor(), deltas).await?;
-
-    // Clean up
-    let _ = tokio::fs::remove_dir_all(&workspace.root).await;
-
-    output::status(handle, channel, &auditor(), "✅", "Audit complete").await?;
-    Ok(())
+    Ok(format!(
+        "## File Tree\n```\n{tree}\n```\n\n## Source Files\n```\n{src_tree}\n```\n\n## Key File Contents\n{file_contents}"
+    ))
 }
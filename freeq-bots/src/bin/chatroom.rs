@@ -335,8 +335,11 @@ async fn run_bot(
         realname: persona.realname.clone(),
         tls,
         tls_insecure: false,
+        tls_options: Default::default(),
         web_token: None,
         websocket_url: None,
+        nick_fallback: Default::default(),
+        capabilities: Default::default(),
     };
 
     let (handle, mut events) = client::connect(config, None);
@@ -22,7 +22,7 @@ use clap::Parser;
 use tokio::sync::mpsc;
 
 use freeq_bots::context::{AgentContext, AgentIdentity, ContextConfig, HistoryMessage};
-use freeq_bots::llm::LlmClient;
+use freeq_bots::llm::{LlmClient, Routes, Task};
 use freeq_bots::memory::Memory;
 use freeq_sdk::client::{self, ConnectConfig};
 use freeq_sdk::event::Event;
@@ -47,9 +47,13 @@ struct Args {
     db: PathBuf,
 
     /// Claude model to use
-    #[arg(long, default_value = "claude-sonnet-4-20250514")]
+    #[arg(long, default_value = freeq_bots::llm::DEFAULT_MODEL)]
     model: String,
 
+    /// Cheaper model for replies, summaries and fact extraction (default: --model)
+    #[arg(long, env = "FREEQ_CHEAP_MODEL")]
+    cheap_model: Option<String>,
+
     /// Messages to fetch from history on connect
     #[arg(long, default_value = "50")]
     history_count: usize,
@@ -84,7 +88,8 @@ async fn main() -> Result<()> {
     let memory = Arc::new(Memory::open(&args.db)?);
     tracing::info!(db = %args.db.display(), "Opened memory database");
 
-    let llm = LlmClient::new(api_key).with_model(&args.model);
+    let llm =
+        LlmClient::new(api_key).with_routes(Routes::new(&args.model, args.cheap_model.as_deref()));
 
     let identity = AgentIdentity {
         nick: args.nick.clone(),
@@ -193,6 +198,7 @@ async fn run_once(args: &Args, llm: &LlmClient, ctx: &Arc<AgentContext>) -> Resu
                 target,
                 text,
                 tags,
+                ..
             } if target == args.channel => {
                 // Record every message
                 let timestamp = tags
@@ -290,7 +296,11 @@ async fn run_once(args: &Args, llm: &LlmClient, ctx: &Arc<AgentContext>) -> Resu
                          summaries, stored facts) to give informed answers."
                     );
 
-                    match llm.complete(&system, &user_prompt).await {
+                    match llm
+                        .for_task(Task::Chat)
+                        .complete(&system, &user_prompt)
+                        .await
+                    {
                         Ok(response) => {
                             // Send response (split long messages)
                             for line in split_irc_message(&response, 400) {
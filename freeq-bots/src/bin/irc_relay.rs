@@ -0,0 +1,117 @@
+//! Channel relay between freeq and a classic IRC network.
+//!
+//! Mirrors linked channels in both directions with nick-prefixed lines;
+//! see `freeq_bots::relay` for the config format.
+//!
+//! Usage:
+//!   cargo run --release --bin irc-relay -- --config relay.toml
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Result;
+use clap::Parser;
+use freeq_sdk::client::{self, ClientHandle, ConnectConfig};
+use freeq_sdk::event::Event;
+use tokio::sync::mpsc;
+
+use freeq_bots::relay::{Endpoint, Relay, RelayConfig, Side};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Parser)]
+#[command(
+    name = "irc-relay",
+    about = "Relay channels between freeq and another IRC network"
+)]
+struct Args {
+    /// Path to the relay config (TOML)
+    #[arg(long, default_value = "relay.toml")]
+    config: PathBuf,
+}
+
+fn connect(endpoint: &Endpoint) -> (ClientHandle, mpsc::Receiver<Event>) {
+    let config = ConnectConfig {
+        server_addr: endpoint.server.clone(),
+        nick: endpoint.nick.clone(),
+        user: "relay".to_string(),
+        realname: "freeq channel relay".to_string(),
+        tls: endpoint.tls,
+        ..Default::default()
+    };
+    client::connect(config, None)
+}
+
+/// One side's connection, reopened whenever it drops.
+struct Link {
+    side: Side,
+    handle: ClientHandle,
+    events: mpsc::Receiver<Event>,
+}
+
+impl Link {
+    fn open(side: Side, relay: &Relay) -> Self {
+        let endpoint = match side {
+            Side::Freeq => &relay.config().freeq,
+            Side::Remote => &relay.config().remote,
+        };
+        let (handle, events) = connect(endpoint);
+        Self {
+            side,
+            handle,
+            events,
+        }
+    }
+
+    async fn reconnect(&mut self, relay: &mut Relay) {
+        tokio::time::sleep(RECONNECT_DELAY).await;
+        relay.reset(self.side);
+        *self = Link::open(self.side, relay);
+    }
+}
+
+/// Handle one event from `from`, sending whatever it routes to `to`.
+async fn handle(relay: &mut Relay, from: &mut Link, to: &Link, event: Option<Event>) {
+    match event {
+        Some(Event::Registered { nick }) => {
+            tracing::info!(side = ?from.side, %nick, "registered");
+            relay.route(from.side, &Event::Registered { nick });
+            if let Err(e) = from.handle.join_many(&relay.channels(from.side)).await {
+                tracing::warn!(side = ?from.side, "join failed: {e}");
+            }
+        }
+        Some(Event::Disconnected { reason }) => {
+            tracing::warn!(side = ?from.side, "disconnected: {reason}; reconnecting");
+            from.reconnect(relay).await;
+        }
+        None => from.reconnect(relay).await,
+        Some(event) => {
+            for out in relay.route(from.side, &event) {
+                debug_assert_eq!(out.side, to.side);
+                if let Err(e) = to.handle.privmsg(&out.target, &out.text).await {
+                    tracing::warn!(side = ?to.side, "send failed: {e}");
+                }
+            }
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(std::env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string()))
+        .init();
+
+    let args = Args::parse();
+    let mut relay = Relay::new(RelayConfig::load(&args.config)?);
+
+    let mut freeq = Link::open(Side::Freeq, &relay);
+    let mut remote = Link::open(Side::Remote, &relay);
+
+    loop {
+        tokio::select! {
+            event = freeq.events.recv() => handle(&mut relay, &mut freeq, &remote, event).await,
+            event = remote.events.recv() => handle(&mut relay, &mut remote, &freeq, event).await,
+        }
+    }
+}
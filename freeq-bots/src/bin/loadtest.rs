@@ -124,8 +124,11 @@ async fn main() -> Result<()> {
             realname: format!("Load test user {i}"),
             tls: args.tls,
             tls_insecure: args.tls,
+            tls_options: Default::default(),
             web_token: None,
             websocket_url: None,
+            nick_fallback: Default::default(),
+            capabilities: Default::default(),
         };
 
         let (handle, events) = freeq_sdk::client::connect(config, None);
@@ -103,8 +103,11 @@ async fn run_once(cfg: Config) -> anyhow::Result<()> {
         realname: "freeq pi bridge".to_string(),
         tls: cfg.server_addr.ends_with(":6697") || cfg.server_addr.ends_with(":443"),
         tls_insecure: false,
+        tls_options: Default::default(),
         web_token,
         websocket_url: None,
+        nick_fallback: Default::default(),
+        capabilities: Default::default(),
     };
 
     let (handle, mut events) = client::connect(config, None);
@@ -202,6 +205,7 @@ async fn run_once(cfg: Config) -> anyhow::Result<()> {
                 target,
                 text,
                 tags,
+                ..
             } => {
                 if tags.contains_key("batch") {
                     continue;
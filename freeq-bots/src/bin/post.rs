@@ -44,8 +44,11 @@ async fn main() -> Result<()> {
         realname: "freeq post tool".to_string(),
         tls: args.tls,
         tls_insecure: args.tls_insecure,
+        tls_options: Default::default(),
         web_token: None,
         websocket_url: None,
+        nick_fallback: Default::default(),
+        capabilities: Default::default(),
     };
 
     let conn = client::establish_connection(&config)
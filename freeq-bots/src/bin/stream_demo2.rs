@@ -21,8 +21,11 @@ async fn main() -> Result<()> {
         realname: "Streaming Message Demo".to_string(),
         tls: true,
         tls_insecure: false,
+        tls_options: Default::default(),
         web_token: None,
         websocket_url: None,
+        nick_fallback: Default::default(),
+        capabilities: Default::default(),
     };
 
     let conn = client::establish_connection(&config).await?;
@@ -39,7 +39,7 @@ use anyhow::Result;
 use chrono::Utc;
 use tokio::sync::Mutex;
 
-use crate::llm::LlmClient;
+use crate::llm::{LlmClient, Task};
 use crate::memory::Memory;
 use freeq_sdk::client::ClientHandle;
 use freeq_sdk::event::Event;
@@ -467,6 +467,7 @@ impl AgentContext {
         };
 
         let summary = llm
+            .for_task(Task::Summarize)
             .complete(
                 "You are a conversation summarizer. Be concise and factual. \
                  Preserve all actionable information. Do not editorialize.",
@@ -524,6 +525,7 @@ impl AgentContext {
         );
 
         let response = llm
+            .for_task(Task::Summarize)
             .complete(
                 "You extract structured facts from conversations. \
                  Output valid JSON only, no markdown fences.",
@@ -0,0 +1,145 @@
+//! Mermaid diagrams rendered to images.
+//!
+//! The auditor and the factory architect draw components and data flow as
+//! ```` ```mermaid ```` blocks rather than ASCII art, which wraps badly on
+//! IRC. A [`Renderer`] runs the headless mermaid-cli (`mmdc`, from
+//! `@mermaid-js/mermaid-cli`) on a block to get an SVG or PNG;
+//! [`crate::output::diagrams`] uploads it through the paste service and
+//! posts the link with the Mermaid source next to it, for editing.
+
+use anyhow::{Context, Result};
+use std::time::Duration;
+use tokio::process::Command;
+
+/// Image format diagrams are rendered to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Svg,
+    Png,
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Svg => "svg",
+            Format::Png => "png",
+        }
+    }
+
+    pub fn mime(self) -> &'static str {
+        match self {
+            Format::Svg => "image/svg+xml",
+            Format::Png => "image/png",
+        }
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "svg" => Ok(Format::Svg),
+            "png" => Ok(Format::Png),
+            other => anyhow::bail!("unknown diagram format '{other}' (svg, png)"),
+        }
+    }
+}
+
+/// How long one render may take; mmdc starts a headless browser.
+const RENDER_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Source larger than this isn't rendered.
+const MAX_SOURCE_BYTES: usize = 50_000;
+
+/// The headless Mermaid renderer.
+#[derive(Debug, Clone)]
+pub struct Renderer {
+    /// The mermaid-cli command and any extra arguments, split on
+    /// whitespace, e.g. `mmdc` or `mmdc -p /etc/puppeteer.json`.
+    pub command: String,
+    pub format: Format,
+}
+
+impl Renderer {
+    /// Render Mermaid `source` to an image in [`Self::format`].
+    pub async fn render(&self, source: &str) -> Result<Vec<u8>> {
+        if source.trim().is_empty() {
+            anyhow::bail!("empty diagram");
+        }
+        if source.len() > MAX_SOURCE_BYTES {
+            anyhow::bail!("diagram source over {MAX_SOURCE_BYTES} bytes");
+        }
+        let mut words = self.command.split_whitespace();
+        let program = words.next().context("no mermaid-cli command set")?;
+
+        let dir =
+            std::env::temp_dir().join(format!("freeq-mermaid-{:016x}", rand::random::<u64>()));
+        tokio::fs::create_dir_all(&dir).await?;
+        let input = dir.join("diagram.mmd");
+        let output = dir.join(format!("diagram.{}", self.format.extension()));
+        let result = async {
+            tokio::fs::write(&input, source).await?;
+            let run = Command::new(program)
+                .args(words)
+                .arg("--quiet")
+                .args(["--backgroundColor", "white"])
+                .arg("--input")
+                .arg(&input)
+                .arg("--output")
+                .arg(&output)
+                .kill_on_drop(true)
+                .output();
+            let out = tokio::time::timeout(RENDER_TIMEOUT, run)
+                .await
+                .context("mermaid-cli timed out")?
+                .with_context(|| format!("failed to run {program}"))?;
+            if !out.status.success() {
+                let stderr = String::from_utf8_lossy(&out.stderr);
+                let reason = stderr
+                    .lines()
+                    .find(|l| !l.trim().is_empty())
+                    .unwrap_or("no output");
+                anyhow::bail!("mermaid-cli failed ({}): {reason}", out.status);
+            }
+            tokio::fs::read(&output)
+                .await
+                .context("mermaid-cli wrote no image")
+        }
+        .await;
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_parse_and_name_their_files() {
+        assert_eq!("SVG".parse::<Format>().unwrap(), Format::Svg);
+        assert_eq!("png".parse::<Format>().unwrap().mime(), "image/png");
+        assert!("gif".parse::<Format>().is_err());
+    }
+
+    #[tokio::test]
+    async fn render_reports_a_missing_or_failing_renderer() {
+        let missing = Renderer {
+            command: "freeq-no-such-mmdc".into(),
+            format: Format::Svg,
+        };
+        let err = missing.render("graph TD; A-->B").await.unwrap_err();
+        assert!(err.to_string().contains("failed to run"), "{err}");
+
+        let failing = Renderer {
+            command: "false".into(),
+            format: Format::Png,
+        };
+        let err = failing.render("graph TD; A-->B").await.unwrap_err();
+        assert!(err.to_string().contains("mermaid-cli failed"), "{err}");
+
+        assert!(failing.render("  \n").await.is_err());
+    }
+}
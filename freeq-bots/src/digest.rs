@@ -0,0 +1,849 @@
+//! Channel digests — "voice of the channel" summaries, posted on a schedule.
+//!
+//! `/digest enable weekly` (or `daily`) has the bot post, once a period, a
+//! digest of what the channel talked about: the top topics, decisions
+//! that were made, the links people shared and who joined. Each digest is
+//! pinned in its channel (when the bot is a channel operator), replacing
+//! the one before. Only channel operators can change a channel's digest.
+//!
+//! Messages are read back from the server's chat history (CHATHISTORY,
+//! paged back to the start of the period). Topics and decisions are
+//! written by the LLM; links and new members are collected exactly. New
+//! members are nicks the bot saw join for the first time since the digest
+//! was enabled ([`Digester::joined`]).
+//!
+//! With `/digest publish on` the digest also goes to the bot's archive
+//! channel, a public (+A) channel whose messages the server shows at
+//! `/archive/<channel>`, and the pinned digest links to it.
+//!
+//! Settings are stored in [`Memory`] (project = channel, kind [`KIND`]) and
+//! survive restarts. `/digest` shows them; `/digest now` posts a digest of
+//! the period so far without waiting.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+
+use anyhow::{Result, bail};
+use freeq_sdk::client::ClientHandle;
+use freeq_sdk::event::Event;
+use freeq_sdk::pending::CallOptions;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::freeq_admin::{ChannelRole, ChannelRoles};
+use crate::llm::{LlmClient, Task};
+use crate::memory::Memory;
+
+/// Memory kind the per-channel settings are stored under.
+pub const KIND: &str = "digest";
+/// Memory kind of the members seen in digested channels.
+const MEMBER_KIND: &str = "digest-member";
+/// Messages per CHATHISTORY request.
+const HISTORY_PAGE: usize = 100;
+/// How long one CHATHISTORY request may take.
+const HISTORY_TIMEOUT: Duration = Duration::from_secs(30);
+/// Most messages read for one digest.
+const MAX_MESSAGES: usize = 3000;
+/// Most transcript characters given to the LLM; older messages are left
+/// out past this.
+const MAX_TRANSCRIPT: usize = 60_000;
+/// Most topics, decisions and links listed.
+const MAX_ITEMS: usize = 5;
+/// Most new members named.
+const MAX_MEMBERS: usize = 20;
+/// Longest digest line, in characters.
+const MAX_LINE: usize = 400;
+
+static URL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"https?://[^\s<>"'`]+"#).expect("valid regex"));
+
+/// How often a channel gets a digest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Cadence {
+    Daily,
+    Weekly,
+}
+
+impl Cadence {
+    /// The time a digest covers.
+    pub fn period(self) -> Duration {
+        match self {
+            Cadence::Daily => Duration::from_secs(24 * 60 * 60),
+            Cadence::Weekly => Duration::from_secs(7 * 24 * 60 * 60),
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            Cadence::Daily => "Daily",
+            Cadence::Weekly => "Weekly",
+        }
+    }
+}
+
+impl fmt::Display for Cadence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cadence::Daily => write!(f, "daily"),
+            Cadence::Weekly => write!(f, "weekly"),
+        }
+    }
+}
+
+impl FromStr for Cadence {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "daily" | "day" => Ok(Cadence::Daily),
+            "weekly" | "week" => Ok(Cadence::Weekly),
+            other => Err(format!(
+                "unknown digest schedule {other:?} (daily or weekly)"
+            )),
+        }
+    }
+}
+
+/// A channel's digest settings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Settings {
+    pub channel: String,
+    pub cadence: Cadence,
+    /// Also post to the archive channel.
+    #[serde(default)]
+    pub publish: bool,
+    /// Unix time the last digest covered up to (or the digest was enabled).
+    pub since: i64,
+    /// The pinned digest, unpinned when the next one is posted.
+    #[serde(default)]
+    pub pinned: Option<String>,
+}
+
+impl Settings {
+    /// Unix time the next digest is due.
+    pub fn due_at(&self) -> i64 {
+        self.since + self.cadence.period().as_secs() as i64
+    }
+}
+
+/// Store (or replace) `settings`.
+pub fn save(memory: &Memory, settings: &Settings) -> Result<()> {
+    let project = settings.channel.to_lowercase();
+    // `set` appends a row per call; keep one per channel
+    memory.delete(&project, KIND, "settings")?;
+    memory.set(
+        &project,
+        KIND,
+        "settings",
+        &serde_json::to_string(settings)?,
+    )
+}
+
+/// `channel`'s settings, if it has digests.
+pub fn get(memory: &Memory, channel: &str) -> Result<Option<Settings>> {
+    Ok(memory
+        .get(&channel.to_lowercase(), KIND, "settings")?
+        .and_then(|v| serde_json::from_str(&v).ok()))
+}
+
+/// Every channel with digests.
+pub fn all(memory: &Memory) -> Result<Vec<Settings>> {
+    Ok(memory
+        .list_kind(KIND)?
+        .into_iter()
+        .filter_map(|e| serde_json::from_str(&e.value).ok())
+        .collect())
+}
+
+/// Stop `channel`'s digests. Returns whether it had them.
+pub fn remove(memory: &Memory, channel: &str) -> Result<bool> {
+    let had = get(memory, channel)?.is_some();
+    memory.delete(&channel.to_lowercase(), KIND, "settings")?;
+    Ok(had)
+}
+
+/// A member of a digested channel, and when the bot first saw them join
+/// (0 for people already there when the digest was enabled).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Member {
+    nick: String,
+    first_seen: i64,
+}
+
+/// Remember `nick` in `channel` as first seen at `at`, unless already known.
+pub fn record_member(memory: &Memory, channel: &str, nick: &str, at: i64) -> Result<()> {
+    let project = channel.to_lowercase();
+    let key = nick.to_lowercase();
+    if memory.get(&project, MEMBER_KIND, &key)?.is_some() {
+        return Ok(());
+    }
+    let member = Member {
+        nick: nick.to_string(),
+        first_seen: at,
+    };
+    memory.set(
+        &project,
+        MEMBER_KIND,
+        &key,
+        &serde_json::to_string(&member)?,
+    )
+}
+
+/// Nicks first seen in `channel` at or after `since`, in order of arrival.
+pub fn new_members(memory: &Memory, channel: &str, since: i64) -> Result<Vec<String>> {
+    let mut members: Vec<Member> = memory
+        .list(&channel.to_lowercase(), MEMBER_KIND)?
+        .into_iter()
+        .filter_map(|e| serde_json::from_str(&e.value).ok())
+        .filter(|m: &Member| m.first_seen >= since && m.first_seen > 0)
+        .collect();
+    members.sort_by_key(|m| m.first_seen);
+    Ok(members.into_iter().map(|m| m.nick).collect())
+}
+
+/// Whether `nick` is an operator of `channel`, going by the bot's roster.
+pub fn is_channel_op(handle: &ClientHandle, channel: &str, nick: &str) -> bool {
+    handle.channel(channel).is_some_and(|state| {
+        state
+            .members
+            .iter()
+            .any(|m| m.op && m.nick.eq_ignore_ascii_case(nick))
+    })
+}
+
+// ─── What was said ───────────────────────────────────────────────────
+
+/// One message from the channel's history.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Said {
+    pub nick: String,
+    pub text: String,
+    /// Unix time, from the `time` tag.
+    pub time: Option<i64>,
+}
+
+/// The links in `said`, most shared first (first shared breaks ties),
+/// with how many messages shared each.
+pub fn links(said: &[Said]) -> Vec<(String, usize)> {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    for s in said {
+        let mut seen: Vec<&str> = Vec::new();
+        for m in URL.find_iter(&s.text) {
+            let url = m
+                .as_str()
+                .trim_end_matches(['.', ',', ';', ':', '!', '?', ')', ']']);
+            if seen.contains(&url) {
+                continue;
+            }
+            seen.push(url);
+            match counts.iter_mut().find(|(u, _)| u == url) {
+                Some((_, n)) => *n += 1,
+                None => counts.push((url.to_string(), 1)),
+            }
+        }
+    }
+    // Stable, so equal counts keep the order they were first shared in.
+    counts.sort_by_key(|(_, n)| std::cmp::Reverse(*n));
+    counts
+}
+
+/// `said` as `[Mon 14:02] nick: text` lines for the LLM, newest kept when
+/// it runs past [`MAX_TRANSCRIPT`].
+fn transcript(said: &[Said]) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut len = 0;
+    for s in said.iter().rev() {
+        let when = s
+            .time
+            .and_then(|t| chrono::DateTime::from_timestamp(t, 0))
+            .map(|t| t.format("%a %H:%M").to_string())
+            .unwrap_or_default();
+        let line = format!("[{when}] {}: {}", s.nick, s.text);
+        len += line.len() + 1;
+        if len > MAX_TRANSCRIPT {
+            lines.push("(earlier messages left out)".to_string());
+            break;
+        }
+        lines.push(line);
+    }
+    lines.reverse();
+    lines.join("\n")
+}
+
+/// What the LLM made of the transcript.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+pub struct Summary {
+    #[serde(default)]
+    pub topics: Vec<String>,
+    #[serde(default)]
+    pub decisions: Vec<String>,
+}
+
+/// Parse the LLM's reply: a JSON object with `topics` and `decisions`,
+/// possibly wrapped in prose or a code fence.
+pub fn parse_summary(reply: &str) -> Option<Summary> {
+    let start = reply.find('{')?;
+    let end = reply.rfind('}')?;
+    let mut summary: Summary = serde_json::from_str(reply.get(start..=end)?).ok()?;
+    for list in [&mut summary.topics, &mut summary.decisions] {
+        list.retain(|s| !s.trim().is_empty());
+        list.truncate(MAX_ITEMS);
+    }
+    Some(summary)
+}
+
+/// Topics and decisions from `said`; empty if the LLM fails.
+pub async fn summarize(llm: &LlmClient, channel: &str, said: &[Said]) -> Summary {
+    let result = llm
+        .for_task(Task::Summarize)
+        .complete(
+            "You write the periodic digest of a chat channel. Reply with only a JSON \
+             object: {\"topics\": [...], \"decisions\": [...]}. topics: up to 5 subjects \
+             the channel talked about most, most discussed first, each a short phrase \
+             under 80 characters. decisions: up to 5 things the channel agreed on or \
+             decided, each under 120 characters; an empty list if there were none. \
+             Don't name people unless the decision is about them.",
+            &format!("Messages in {channel}:\n{}", transcript(said)),
+        )
+        .await;
+    match result {
+        Ok(reply) => parse_summary(&reply).unwrap_or_else(|| {
+            tracing::warn!(channel, "Digest summary wasn't JSON");
+            Summary::default()
+        }),
+        Err(e) => {
+            tracing::warn!(error = %e, channel, "Digest summary failed");
+            Summary::default()
+        }
+    }
+}
+
+// ─── The digest ──────────────────────────────────────────────────────
+
+/// One period of a channel, digested.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Digest {
+    pub channel: String,
+    pub cadence: Cadence,
+    pub from: i64,
+    pub to: i64,
+    pub messages: usize,
+    pub speakers: usize,
+    pub summary: Summary,
+    pub links: Vec<(String, usize)>,
+    pub new_members: Vec<String>,
+}
+
+impl Digest {
+    fn new(
+        settings: &Settings,
+        to: i64,
+        said: &[Said],
+        summary: Summary,
+        new_members: Vec<String>,
+    ) -> Self {
+        let mut speakers: Vec<String> = said.iter().map(|s| s.nick.to_lowercase()).collect();
+        speakers.sort();
+        speakers.dedup();
+        let mut links = links(said);
+        links.truncate(MAX_ITEMS);
+        Self {
+            channel: settings.channel.clone(),
+            cadence: settings.cadence,
+            from: settings.since,
+            to,
+            messages: said.len(),
+            speakers: speakers.len(),
+            summary,
+            links,
+            new_members,
+        }
+    }
+
+    /// The digest as chat lines, headline first; empty sections are left
+    /// out.
+    pub fn lines(&self) -> Vec<String> {
+        let day = |t: i64| {
+            chrono::DateTime::from_timestamp(t, 0)
+                .map(|t| t.format("%b %-d").to_string())
+                .unwrap_or_default()
+        };
+        let people = match self.speakers {
+            1 => "1 person".to_string(),
+            n => format!("{n} people"),
+        };
+        let mut lines = vec![format!(
+            "📰 {} digest for {}, {} – {}: {} messages from {people}",
+            self.cadence.title(),
+            self.channel,
+            day(self.from),
+            day(self.to),
+            self.messages
+        )];
+        if !self.summary.topics.is_empty() {
+            lines.push(format!("🗣 Top topics: {}", self.summary.topics.join(" · ")));
+        }
+        if !self.summary.decisions.is_empty() {
+            lines.push(format!(
+                "✅ Decisions: {}",
+                self.summary.decisions.join(" · ")
+            ));
+        }
+        if !self.links.is_empty() {
+            let links: Vec<String> = self
+                .links
+                .iter()
+                .map(|(url, n)| match n {
+                    1 => url.clone(),
+                    n => format!("{url} ({n}×)"),
+                })
+                .collect();
+            lines.push(format!("🔗 Links: {}", links.join(" · ")));
+        }
+        if !self.new_members.is_empty() {
+            let mut names = self
+                .new_members
+                .iter()
+                .take(MAX_MEMBERS)
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", ");
+            if self.new_members.len() > MAX_MEMBERS {
+                names.push_str(&format!(
+                    " and {} more",
+                    self.new_members.len() - MAX_MEMBERS
+                ));
+            }
+            lines.push(format!("👋 New members: {names}"));
+        }
+        lines.into_iter().map(|l| clip(&l, MAX_LINE)).collect()
+    }
+}
+
+/// `line` cut to `max` characters, with an ellipsis when cut.
+fn clip(line: &str, max: usize) -> String {
+    match line.char_indices().nth(max) {
+        Some((at, _)) => format!("{}…", &line[..at]),
+        None => line.to_string(),
+    }
+}
+
+// ─── Digester ────────────────────────────────────────────────────────
+
+/// Where published digests go: a public (+A) channel, and the server's
+/// web URL for linking to them.
+#[derive(Debug, Clone)]
+pub struct Archive {
+    pub channel: String,
+    pub web_url: Option<String>,
+}
+
+impl Archive {
+    /// The web archive page of message `msgid`.
+    fn link(&self, msgid: &str) -> Option<String> {
+        let base = self.web_url.as_deref()?.trim_end_matches('/');
+        let name = self.channel.trim_start_matches('#');
+        Some(format!("{base}/archive/{name}#{msgid}"))
+    }
+}
+
+/// Keeps the digest settings, tracks who joins, and posts digests when
+/// they're due.
+#[derive(Clone)]
+pub struct Digester {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    handle: ClientHandle,
+    /// The bot's nick; its own messages aren't digested.
+    nick: String,
+    memory: Arc<Memory>,
+    llm: LlmClient,
+    roles: ChannelRoles,
+    archive: Option<Archive>,
+    /// Serializes posting, so a scheduled digest and `/digest now` can't
+    /// both go out.
+    busy: tokio::sync::Mutex<()>,
+}
+
+impl Digester {
+    pub fn new(
+        handle: ClientHandle,
+        nick: &str,
+        memory: Arc<Memory>,
+        llm: LlmClient,
+        roles: ChannelRoles,
+        archive: Option<Archive>,
+    ) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                handle,
+                nick: nick.to_string(),
+                memory,
+                llm,
+                roles,
+                archive,
+                busy: tokio::sync::Mutex::new(()),
+            }),
+        }
+    }
+
+    /// Whether digests can be published.
+    pub fn has_archive(&self) -> bool {
+        self.inner.archive.is_some()
+    }
+
+    /// Post `channel`'s digest every `cadence`, the first one period from
+    /// now. Keeps the publish setting of an earlier enable.
+    pub fn enable(&self, channel: &str, cadence: Cadence) -> Result<Settings> {
+        let memory = &self.inner.memory;
+        let now = chrono::Utc::now().timestamp();
+        let settings = match get(memory, channel)? {
+            Some(old) => Settings {
+                cadence,
+                since: now,
+                ..old
+            },
+            None => Settings {
+                channel: channel.to_string(),
+                cadence,
+                publish: false,
+                since: now,
+                pinned: None,
+            },
+        };
+        // Whoever is here already isn't new.
+        if let Some(state) = self.inner.handle.channel(channel) {
+            for member in &state.members {
+                record_member(memory, channel, &member.nick, 0)?;
+            }
+        }
+        save(memory, &settings)?;
+        Ok(settings)
+    }
+
+    /// Turn publishing to the archive channel on or off. `None` when
+    /// `channel` has no digests.
+    pub fn set_publish(&self, channel: &str, publish: bool) -> Result<Option<Settings>> {
+        let Some(mut settings) = get(&self.inner.memory, channel)? else {
+            return Ok(None);
+        };
+        if publish && self.inner.archive.is_none() {
+            bail!("this bot has no archive channel (--digest-archive-channel)");
+        }
+        settings.publish = publish;
+        save(&self.inner.memory, &settings)?;
+        Ok(Some(settings))
+    }
+
+    /// Someone joined `channel`: remember them as new, if it has digests.
+    pub fn joined(&self, channel: &str, nick: &str) -> Result<()> {
+        let memory = &self.inner.memory;
+        if nick.eq_ignore_ascii_case(&self.inner.nick) || get(memory, channel)?.is_none() {
+            return Ok(());
+        }
+        record_member(memory, channel, nick, chrono::Utc::now().timestamp())
+    }
+
+    /// Check for due digests every `interval` until the process exits.
+    pub async fn run(self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            let due = match all(&self.inner.memory) {
+                Ok(all) => {
+                    let now = chrono::Utc::now().timestamp();
+                    all.into_iter().filter(|s| s.due_at() <= now).collect()
+                }
+                Err(e) => {
+                    tracing::error!(error = %e, "Loading digest settings failed");
+                    Vec::new()
+                }
+            };
+            for settings in due {
+                if let Err(e) = self.post(&settings.channel).await {
+                    tracing::warn!(error = %e, channel = %settings.channel, "Digest failed");
+                }
+            }
+        }
+    }
+
+    /// Digest `channel` from the end of its last digest until now, and
+    /// post it. Returns whether there was anything to post.
+    pub async fn post(&self, channel: &str) -> Result<bool> {
+        let _busy = self.inner.busy.lock().await;
+        let memory = &self.inner.memory;
+        let Some(mut settings) = get(memory, channel)? else {
+            bail!("{channel} has no digest");
+        };
+        let now = chrono::Utc::now().timestamp();
+        let said = self.history(channel, settings.since).await?;
+        if said.is_empty() {
+            tracing::info!(channel, "Nothing said since the last digest");
+            settings.since = now;
+            save(memory, &settings)?;
+            return Ok(false);
+        }
+        let summary = summarize(&self.inner.llm, channel, &said).await;
+        let new_members = new_members(memory, channel, settings.since)?;
+        let digest = Digest::new(&settings, now, &said, summary, new_members);
+        let mut lines = digest.lines();
+
+        if settings.publish
+            && let Some(archive) = &self.inner.archive
+        {
+            let text = lines.join("\n");
+            match self.send(&archive.channel, &text).await {
+                Ok(msgid) => {
+                    if let Some(link) = msgid.as_deref().and_then(|id| archive.link(id)) {
+                        lines.push(format!("📚 Archived: {link}"));
+                    }
+                }
+                Err(e) => tracing::warn!(error = %e, channel, "Publishing digest failed"),
+            }
+        }
+
+        let msgid = self.send(channel, &lines.join("\n")).await?;
+        let handle = &self.inner.handle;
+        if self.inner.roles.role(channel) == Some(ChannelRole::Op) {
+            if let Some(old) = &settings.pinned {
+                handle.unpin(channel, old).await?;
+            }
+            if let Some(msgid) = &msgid {
+                handle.pin(channel, msgid).await?;
+            }
+            settings.pinned = msgid;
+        } else {
+            tracing::info!(channel, "Not a channel operator; digest left unpinned");
+        }
+        settings.since = now;
+        save(memory, &settings)?;
+        Ok(true)
+    }
+
+    /// Post `text` to `target` as one message and return its msgid, or
+    /// post it line by line (the msgid being the headline's) when the
+    /// server has no multiline messages. `None` when the server didn't
+    /// echo it back.
+    async fn send(&self, target: &str, text: &str) -> Result<Option<String>> {
+        let handle = &self.inner.handle;
+        let multiline = handle.has_cap("draft/multiline") && handle.has_cap("batch");
+        let (first, rest) = match text.split_once('\n') {
+            Some((first, rest)) if !multiline => (first, Some(rest)),
+            _ => (text, None),
+        };
+        let msgid = match handle
+            .send_and_await_echo(target, first, HashMap::new())
+            .await
+        {
+            Ok(msgid) => Some(msgid),
+            Err(e) => {
+                tracing::warn!(error = %e, target, "No msgid for the digest");
+                None
+            }
+        };
+        for line in rest.into_iter().flat_map(str::lines) {
+            handle.privmsg(target, line).await?;
+        }
+        Ok(msgid)
+    }
+
+    /// What was said in `channel` since `since`, oldest first, read back a
+    /// page at a time up to [`MAX_MESSAGES`].
+    async fn history(&self, channel: &str, since: i64) -> Result<Vec<Said>> {
+        let handle = &self.inner.handle;
+        let mut said: Vec<Said> = Vec::new();
+        let mut before: Option<String> = None;
+        loop {
+            let events = handle
+                .fetch_history(
+                    channel,
+                    before.as_deref(),
+                    HISTORY_PAGE,
+                    CallOptions::timeout(HISTORY_TIMEOUT),
+                )
+                .await?;
+            let mut page = Vec::new();
+            let mut oldest = None;
+            let mut done = events.len() < HISTORY_PAGE;
+            for event in &events {
+                let Event::Message {
+                    from, text, tags, ..
+                } = event
+                else {
+                    continue;
+                };
+                if oldest.is_none() {
+                    oldest = tags.get("msgid").cloned();
+                }
+                let time = tags
+                    .get("time")
+                    .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+                    .map(|t| t.timestamp());
+                if time.is_some_and(|t| t < since) {
+                    done = true;
+                    continue;
+                }
+                if from.eq_ignore_ascii_case(&self.inner.nick) {
+                    continue;
+                }
+                page.push(Said {
+                    nick: from.clone(),
+                    text: text.clone(),
+                    time,
+                });
+            }
+            page.append(&mut said);
+            said = page;
+            if said.len() >= MAX_MESSAGES {
+                let excess = said.len() - MAX_MESSAGES;
+                said.drain(..excess);
+                break;
+            }
+            match oldest {
+                Some(msgid) if !done => before = Some(msgid),
+                _ => break,
+            }
+        }
+        Ok(said)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn said(nick: &str, text: &str) -> Said {
+        Said {
+            nick: nick.to_string(),
+            text: text.to_string(),
+            time: Some(1_700_000_000),
+        }
+    }
+
+    fn settings(channel: &str) -> Settings {
+        Settings {
+            channel: channel.to_string(),
+            cadence: Cadence::Weekly,
+            publish: false,
+            since: 1_700_000_000,
+            pinned: None,
+        }
+    }
+
+    #[test]
+    fn cadences_parse_and_set_the_due_time() {
+        assert_eq!("weekly".parse(), Ok(Cadence::Weekly));
+        assert_eq!("Daily".parse(), Ok(Cadence::Daily));
+        assert!("monthly".parse::<Cadence>().is_err());
+        let s = settings("#rust");
+        assert_eq!(s.due_at(), 1_700_000_000 + 7 * 24 * 60 * 60);
+    }
+
+    #[test]
+    fn settings_and_members_persist_per_channel() {
+        let memory = Memory::in_memory().unwrap();
+        save(&memory, &settings("#Rust")).unwrap();
+        let mut changed = settings("#rust");
+        changed.publish = true;
+        save(&memory, &changed).unwrap();
+        assert_eq!(get(&memory, "#RUST").unwrap(), Some(changed));
+        assert_eq!(all(&memory).unwrap().len(), 1);
+        assert!(get(&memory, "#go").unwrap().is_none());
+
+        record_member(&memory, "#rust", "alice", 0).unwrap();
+        record_member(&memory, "#rust", "bob", 200).unwrap();
+        record_member(&memory, "#rust", "carol", 100).unwrap();
+        record_member(&memory, "#rust", "Alice", 300).unwrap();
+        record_member(&memory, "#go", "dave", 300).unwrap();
+        assert_eq!(new_members(&memory, "#rust", 50).unwrap(), ["carol", "bob"]);
+        assert_eq!(new_members(&memory, "#rust", 150).unwrap(), ["bob"]);
+
+        assert!(remove(&memory, "#rust").unwrap());
+        assert!(!remove(&memory, "#rust").unwrap());
+    }
+
+    #[test]
+    fn links_are_counted_once_per_message() {
+        let found = links(&[
+            said("a", "see https://example.com/a, and https://example.com/b."),
+            said("b", "(https://example.com/b) again https://example.com/b"),
+            said("c", "no links here"),
+        ]);
+        assert_eq!(
+            found,
+            [
+                ("https://example.com/b".to_string(), 2),
+                ("https://example.com/a".to_string(), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn summaries_parse_from_wrapped_json() {
+        let reply = "Here you go:\n```json\n{\"topics\": [\"release\", \" \", \"ci\"], \
+                     \"decisions\": [\"ship 1.2 on friday\"]}\n```";
+        let summary = parse_summary(reply).unwrap();
+        assert_eq!(summary.topics, ["release", "ci"]);
+        assert_eq!(summary.decisions, ["ship 1.2 on friday"]);
+        assert!(parse_summary("no json").is_none());
+    }
+
+    #[test]
+    fn digests_leave_out_empty_sections() {
+        let messages = [
+            said("alice", "release plan: https://example.com/plan"),
+            said("Bob", "sounds good"),
+            said("bob", "ship it"),
+        ];
+        let summary = Summary {
+            topics: vec!["release plan".to_string()],
+            decisions: Vec::new(),
+        };
+        let digest = Digest::new(
+            &settings("#rust"),
+            1_700_000_000 + 7 * 24 * 60 * 60,
+            &messages,
+            summary,
+            vec!["carol".to_string()],
+        );
+        let lines = digest.lines();
+        assert_eq!(
+            lines[0],
+            "📰 Weekly digest for #rust, Nov 14 – Nov 21: 3 messages from 2 people"
+        );
+        assert_eq!(lines[1], "🗣 Top topics: release plan");
+        assert_eq!(lines[2], "🔗 Links: https://example.com/plan");
+        assert_eq!(lines[3], "👋 New members: carol");
+        assert_eq!(lines.len(), 4);
+    }
+
+    #[test]
+    fn long_lines_are_clipped() {
+        assert_eq!(clip("héllo", 10), "héllo");
+        assert_eq!(clip("héllo wörld", 5), "héllo…");
+    }
+
+    #[test]
+    fn archive_links_point_at_the_message() {
+        let archive = Archive {
+            channel: "#digests".to_string(),
+            web_url: Some("https://irc.example.com/".to_string()),
+        };
+        assert_eq!(
+            archive.link("01HXYZ").as_deref(),
+            Some("https://irc.example.com/archive/digests#01HXYZ")
+        );
+        let unlinked = Archive {
+            web_url: None,
+            ..archive
+        };
+        assert_eq!(unlinked.link("01HXYZ"), None);
+    }
+}
@@ -0,0 +1,563 @@
+//! Evaluation harness for the agent pipelines.
+//!
+//! Prompt changes can silently regress quality, so `freeq-bots eval` runs a
+//! corpus of specs and repositories through the factory, prototype and
+//! auditor pipelines headless — into a [`TranscriptSink`] instead of an
+//! IRC channel — and scores each case on the expected properties it meets. Reports are stored in [`Memory`] under
+//! the run's label, so runs across prompt versions can be compared.
+//!
+//! ```toml
+//! [[case]]
+//! name = "todo"
+//! pipeline = "prototype"
+//! input = "A todo list web app with tags and due dates"
+//!
+//! [case.expect]
+//! files = ["app.py", "requirements.txt"]
+//! command = "pip install -q -r requirements.txt && python -m pytest -q"
+//! deploy = true
+//! reachable = true
+//!
+//! [[case]]
+//! name = "audit-flask"
+//! pipeline = "audit"
+//! input = "https://github.com/pallets/flask"
+//!
+//! [case.expect]
+//! mentions = ["Risks", "Refactor"]
+//! ```
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::factory::{Factory, FactoryConfig};
+use crate::llm::LlmClient;
+use crate::memory::Memory;
+use crate::sink::{OutputSink, TranscriptSink};
+
+/// Memory project that reports are stored under.
+pub const MEMORY_PROJECT: &str = "eval";
+
+/// Channel the headless pipelines post to.
+const CHANNEL: &str = "#eval";
+
+/// How long an `expect.command` may run.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// An eval corpus, loaded from TOML.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Corpus {
+    #[serde(rename = "case")]
+    pub cases: Vec<Case>,
+}
+
+/// One input to run through a pipeline.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Case {
+    pub name: String,
+    pub pipeline: Pipeline,
+    /// The spec (factory, prototype) or repository (audit).
+    pub input: String,
+    #[serde(default)]
+    pub expect: Expect,
+    /// Give up on the pipeline after this long.
+    #[serde(default = "default_timeout")]
+    pub timeout_secs: u64,
+}
+
+/// Which pipeline a case exercises.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Pipeline {
+    Factory,
+    Prototype,
+    Audit,
+}
+
+/// Properties the outcome should have. The pipeline finishing without an
+/// error is always checked on top of these.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Expect {
+    /// Files the generated project must contain.
+    #[serde(default)]
+    pub files: Vec<String>,
+    /// Shell command that must exit 0 in the project (it builds, tests pass).
+    pub command: Option<String>,
+    /// The pipeline must report a deploy URL.
+    #[serde(default)]
+    pub deploy: bool,
+    /// The deploy URL must answer without an HTTP error.
+    #[serde(default)]
+    pub reachable: bool,
+    /// Text the channel output must contain (case-insensitive).
+    #[serde(default)]
+    pub mentions: Vec<String>,
+}
+
+fn default_timeout() -> u64 {
+    900
+}
+
+impl Corpus {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        let corpus: Self =
+            toml::from_str(&text).with_context(|| format!("parsing {}", path.display()))?;
+        corpus.validate()?;
+        Ok(corpus)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.cases.is_empty() {
+            bail!("no [[case]] entries");
+        }
+        let mut names = HashSet::new();
+        for case in &self.cases {
+            // Names become workspace directories.
+            if case.name.is_empty()
+                || !case
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                bail!(
+                    "case {:?}: names may only use letters, digits, - and _",
+                    case.name
+                );
+            }
+            if !names.insert(case.name.as_str()) {
+                bail!("case {} is defined twice", case.name);
+            }
+            let e = &case.expect;
+            if case.pipeline == Pipeline::Audit
+                && (!e.files.is_empty() || e.command.is_some() || e.deploy || e.reachable)
+            {
+                bail!("case {}: audits only support `mentions`", case.name);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The outcome of one expected property.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Check {
+    pub name: String,
+    pub passed: bool,
+    /// Why it failed, when it did.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub detail: String,
+}
+
+impl Check {
+    fn pass(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            passed: true,
+            detail: String::new(),
+        }
+    }
+
+    fn fail(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            passed: false,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// How one case did.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaseReport {
+    pub name: String,
+    pub pipeline: Pipeline,
+    pub duration_secs: f64,
+    pub checks: Vec<Check>,
+}
+
+impl CaseReport {
+    /// Fraction of checks passed, 0.0–1.0.
+    pub fn score(&self) -> f64 {
+        if self.checks.is_empty() {
+            return 0.0;
+        }
+        let passed = self.checks.iter().filter(|c| c.passed).count();
+        passed as f64 / self.checks.len() as f64
+    }
+}
+
+/// A scored run of a corpus.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Report {
+    /// What was being evaluated, e.g. a prompt version.
+    pub label: String,
+    /// UTC start time, `YYYYMMDDTHHMMSSZ`.
+    pub started_at: String,
+    pub model: String,
+    pub cases: Vec<CaseReport>,
+}
+
+impl Report {
+    /// Mean case score, 0.0–1.0.
+    pub fn score(&self) -> f64 {
+        if self.cases.is_empty() {
+            return 0.0;
+        }
+        self.cases.iter().map(CaseReport::score).sum::<f64>() / self.cases.len() as f64
+    }
+
+    /// The Memory key the report is stored under.
+    pub fn key(&self) -> String {
+        format!("{}@{}", self.started_at, self.label)
+    }
+
+    pub fn save(&self, memory: &Memory) -> Result<()> {
+        memory.set(
+            MEMORY_PROJECT,
+            "report",
+            &self.key(),
+            &serde_json::to_string(self)?,
+        )
+    }
+
+    /// Stored reports, oldest first.
+    pub fn history(memory: &Memory) -> Result<Vec<Report>> {
+        let mut reports: Vec<Report> = memory
+            .list(MEMORY_PROJECT, "report")?
+            .into_iter()
+            .filter_map(|e| serde_json::from_str(&e.value).ok())
+            .collect();
+        reports.sort_by(|a, b| a.started_at.cmp(&b.started_at));
+        Ok(reports)
+    }
+
+    /// Per-case scores and failed checks, for the terminal.
+    pub fn summary(&self) -> String {
+        let mut out = format!(
+            "{} ({}, {}): {:.0}%\n",
+            self.label,
+            self.started_at,
+            self.model,
+            self.score() * 100.0
+        );
+        for case in &self.cases {
+            out.push_str(&format!(
+                "  {:<24} {:<9} {:>4.0}%  {:>6.0}s\n",
+                case.name,
+                format!("{:?}", case.pipeline).to_lowercase(),
+                case.score() * 100.0,
+                case.duration_secs
+            ));
+            for check in case.checks.iter().filter(|c| !c.passed) {
+                out.push_str(&format!("      ✗ {}: {}\n", check.name, check.detail));
+            }
+        }
+        out
+    }
+
+    /// Score changes from `previous`, for cases present in both runs.
+    pub fn compare(&self, previous: &Report) -> String {
+        let mut out = format!(
+            "vs {} ({}): {:+.0} points\n",
+            previous.label,
+            previous.started_at,
+            (self.score() - previous.score()) * 100.0
+        );
+        for case in &self.cases {
+            let Some(before) = previous.cases.iter().find(|c| c.name == case.name) else {
+                continue;
+            };
+            let delta = (case.score() - before.score()) * 100.0;
+            if delta.abs() >= 0.5 {
+                out.push_str(&format!("  {:<24} {delta:+.0}\n", case.name));
+            }
+        }
+        out
+    }
+}
+
+/// Run every case in `corpus`. Projects are generated under
+/// `workspace_base/eval/<started_at>/<case>`.
+pub async fn run(
+    corpus: &Corpus,
+    label: &str,
+    llm: &LlmClient,
+    model: &str,
+    workspace_base: &Path,
+) -> Report {
+    let started_at = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let base = workspace_base.join("eval").join(&started_at);
+    let mut cases = Vec::new();
+    for case in &corpus.cases {
+        tracing::info!(case = %case.name, pipeline = ?case.pipeline, "Running eval case");
+        let report = run_case(case, llm, &base.join(&case.name)).await;
+        tracing::info!(case = %case.name, score = report.score(), "Eval case done");
+        cases.push(report);
+    }
+    Report {
+        label: label.to_string(),
+        started_at,
+        model: model.to_string(),
+        cases,
+    }
+}
+
+async fn run_case(case: &Case, llm: &LlmClient, base: &Path) -> CaseReport {
+    let start = Instant::now();
+    let transcript = TranscriptSink::default();
+    // Pipeline memory is per case; only the report goes to the real store.
+    let memory = Memory::in_memory();
+
+    let outcome = match &memory {
+        Ok(memory) => tokio::time::timeout(
+            Duration::from_secs(case.timeout_secs),
+            run_pipeline(case, &transcript, llm, memory, base),
+        )
+        .await
+        .unwrap_or_else(|_| Err(anyhow!("timed out after {}s", case.timeout_secs))),
+        Err(e) => Err(anyhow!("memory: {e}")),
+    };
+    let mut checks = vec![match outcome {
+        Ok(()) => Check::pass("completes"),
+        Err(e) => Check::fail("completes", format!("{e:#}")),
+    }];
+
+    let project = project_dir(base);
+    let e = &case.expect;
+    for file in &e.files {
+        let name = format!("file {file}");
+        checks.push(match &project {
+            Some((_, dir)) if dir.join(file).is_file() => Check::pass(name),
+            _ => Check::fail(name, "missing"),
+        });
+    }
+    if let Some(cmd) = &e.command {
+        checks.push(match &project {
+            Some((_, dir)) => run_command(cmd, dir).await,
+            None => Check::fail("command", "no project generated"),
+        });
+    }
+
+    let url = project.as_ref().and_then(|(name, _)| {
+        memory
+            .as_ref()
+            .ok()
+            .and_then(|m| m.get(name, "deploy", "url").ok().flatten())
+    });
+    if e.deploy {
+        checks.push(match &url {
+            Some(_) => Check::pass("deploy"),
+            None => Check::fail("deploy", "no deploy URL"),
+        });
+    }
+    if e.reachable {
+        checks.push(match &url {
+            Some(url) => check_reachable(url).await,
+            None => Check::fail("reachable", "no deploy URL"),
+        });
+    }
+
+    let transcript = transcript.text().to_lowercase();
+    for text in &e.mentions {
+        let name = format!("mentions {text:?}");
+        checks.push(if transcript.contains(&text.to_lowercase()) {
+            Check::pass(name)
+        } else {
+            Check::fail(name, "not in channel output")
+        });
+    }
+
+    CaseReport {
+        name: case.name.clone(),
+        pipeline: case.pipeline,
+        duration_secs: start.elapsed().as_secs_f64(),
+        checks,
+    }
+}
+
+async fn run_pipeline(
+    case: &Case,
+    sink: &dyn OutputSink,
+    llm: &LlmClient,
+    memory: &Memory,
+    base: &Path,
+) -> Result<()> {
+    tokio::fs::create_dir_all(base).await?;
+    match case.pipeline {
+        Pipeline::Factory => {
+            let factory = Factory::new(FactoryConfig {
+                channel: CHANNEL.to_string(),
+                workspace_base: base.to_path_buf(),
+            });
+            factory
+                .handle_command(sink, CHANNEL, "eval", "build", &case.input, llm, memory)
+                .await
+        }
+        Pipeline::Prototype => {
+            crate::prototype::build(sink, CHANNEL, &case.input, llm, memory, base, None)
+                .await
+                .map(|_| ())
+        }
+        Pipeline::Audit => crate::auditor::audit(sink, CHANNEL, &case.input, llm, base).await,
+    }
+}
+
+/// The workspace a pipeline created under `base`: its name and path.
+fn project_dir(base: &Path) -> Option<(String, PathBuf)> {
+    std::fs::read_dir(base)
+        .ok()?
+        .flatten()
+        .find(|e| e.path().is_dir())
+        .map(|e| (e.file_name().to_string_lossy().to_string(), e.path()))
+}
+
+async fn run_command(cmd: &str, dir: &Path) -> Check {
+    let run = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .current_dir(dir)
+        .kill_on_drop(true)
+        .output();
+    match tokio::time::timeout(COMMAND_TIMEOUT, run).await {
+        Ok(Ok(out)) if out.status.success() => Check::pass("command"),
+        Ok(Ok(out)) => {
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            let last = stderr.lines().last().unwrap_or("").trim();
+            Check::fail("command", format!("{}: {last}", out.status))
+        }
+        Ok(Err(e)) => Check::fail("command", e.to_string()),
+        Err(_) => Check::fail("command", "timed out"),
+    }
+}
+
+async fn check_reachable(url: &str) -> Check {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build();
+    let resp = match client {
+        Ok(client) => client.get(url).send().await,
+        Err(e) => return Check::fail("reachable", e.to_string()),
+    };
+    match resp {
+        Ok(r) if r.status().is_success() || r.status().is_redirection() => Check::pass("reachable"),
+        Ok(r) => Check::fail("reachable", format!("HTTP {}", r.status())),
+        Err(e) => Check::fail("reachable", e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CORPUS: &str = r#"
+[[case]]
+name = "todo"
+pipeline = "prototype"
+input = "A todo app"
+timeout_secs = 60
+
+[case.expect]
+files = ["app.py"]
+deploy = true
+
+[[case]]
+name = "audit-x"
+pipeline = "audit"
+input = "https://github.com/x/y"
+
+[case.expect]
+mentions = ["Risks"]
+"#;
+
+    fn case(name: &str, passed: &[bool]) -> CaseReport {
+        CaseReport {
+            name: name.to_string(),
+            pipeline: Pipeline::Prototype,
+            duration_secs: 1.0,
+            checks: passed
+                .iter()
+                .map(|&p| {
+                    if p {
+                        Check::pass("c")
+                    } else {
+                        Check::fail("c", "no")
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    fn report(label: &str, started_at: &str, cases: Vec<CaseReport>) -> Report {
+        Report {
+            label: label.to_string(),
+            started_at: started_at.to_string(),
+            model: "m".to_string(),
+            cases,
+        }
+    }
+
+    #[test]
+    fn corpus_parses_and_validates() {
+        let corpus: Corpus = toml::from_str(CORPUS).unwrap();
+        corpus.validate().unwrap();
+        assert_eq!(corpus.cases.len(), 2);
+        assert_eq!(corpus.cases[0].pipeline, Pipeline::Prototype);
+        assert_eq!(corpus.cases[0].timeout_secs, 60);
+        assert!(corpus.cases[0].expect.deploy);
+        assert_eq!(corpus.cases[1].timeout_secs, default_timeout());
+        assert_eq!(corpus.cases[1].expect.mentions, ["Risks"]);
+
+        let bad = CORPUS.replace("audit-x", "todo");
+        let corpus: Corpus = toml::from_str(&bad).unwrap();
+        assert!(corpus.validate().is_err(), "duplicate names");
+        let bad = CORPUS.replace("mentions = [\"Risks\"]", "deploy = true");
+        let corpus: Corpus = toml::from_str(&bad).unwrap();
+        assert!(corpus.validate().is_err(), "audits don't deploy");
+        let bad = CORPUS.replace("\"todo\"", "\"../todo\"");
+        let corpus: Corpus = toml::from_str(&bad).unwrap();
+        assert!(corpus.validate().is_err(), "names are directories");
+    }
+
+    #[test]
+    fn scores_are_fractions_of_checks_passed() {
+        let r = report(
+            "v1",
+            "20260101T000000Z",
+            vec![
+                case("a", &[true, true]),
+                case("b", &[true, false, false, false]),
+            ],
+        );
+        assert_eq!(r.cases[1].score(), 0.25);
+        assert_eq!(r.score(), 0.625);
+        assert!(r.summary().contains("✗ c: no"));
+    }
+
+    #[test]
+    fn reports_round_trip_through_memory() {
+        let memory = Memory::in_memory().unwrap();
+        let old = report("v1", "20260101T000000Z", vec![case("a", &[false, true])]);
+        let new = report("v2", "20260102T000000Z", vec![case("a", &[true, true])]);
+        new.save(&memory).unwrap();
+        old.save(&memory).unwrap();
+
+        let history = Report::history(&memory).unwrap();
+        assert_eq!(
+            history.iter().map(|r| r.label.as_str()).collect::<Vec<_>>(),
+            ["v1", "v2"]
+        );
+        let diff = history[1].compare(&history[0]);
+        assert!(
+            diff.starts_with("vs v1 (20260101T000000Z): +50 points"),
+            "{diff}"
+        );
+        assert!(diff.contains("a"), "{diff}");
+    }
+}
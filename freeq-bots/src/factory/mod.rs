@@ -10,7 +10,14 @@
 //! - Reviewer: critiques code quality and spec alignment
 //! - QA: generates and runs tests
 //! - Deploy: deploys to staging and posts preview URL
+//!
+//! Each channel builds one long-running [`project`]: successive builds
+//! evolve the same workspace and git history, and each build leaves a
+//! [`report`] transcript of what the agents did.
 
 mod orchestrator;
+pub mod project;
+pub mod report;
 
 pub use orchestrator::{Factory, FactoryConfig};
+pub use project::Projects;
@@ -6,11 +6,15 @@ use std::sync::Arc;
 use anyhow::Result;
 use tokio::sync::Mutex;
 
-use crate::llm::{ContentBlock, LlmClient, Message, MessageContent, ToolResultBlock};
+use super::project::{self, Projects};
+use super::report::{Report, Step};
+use crate::artifacts::Artifacts;
+use crate::freeq_admin::{self, ChannelRoles};
+use crate::llm::{ContentBlock, LlmClient, Message, MessageContent, Task, ToolResultBlock};
 use crate::memory::Memory;
 use crate::output::{self, AgentId};
-use crate::tools::{self, Workspace};
-use freeq_sdk::client::ClientHandle;
+use crate::sink::OutputSink;
+use crate::tools;
 
 /// Factory configuration.
 #[derive(Debug, Clone)]
@@ -55,37 +59,37 @@ impl std::fmt::Display for Phase {
 fn product() -> AgentId {
     AgentId {
         role: "product".to_string(),
-        color: None,
+        color: Some(output::color::PINK),
     }
 }
 fn architect() -> AgentId {
     AgentId {
         role: "architect".to_string(),
-        color: None,
+        color: Some(output::color::BLUE),
     }
 }
 fn builder() -> AgentId {
     AgentId {
         role: "builder".to_string(),
-        color: None,
+        color: Some(output::color::GREEN),
     }
 }
 fn reviewer() -> AgentId {
     AgentId {
         role: "reviewer".to_string(),
-        color: None,
+        color: Some(output::color::ORANGE),
     }
 }
 fn qa() -> AgentId {
     AgentId {
         role: "qa".to_string(),
-        color: None,
+        color: Some(output::color::PURPLE),
     }
 }
 fn deployer() -> AgentId {
     AgentId {
         role: "deploy".to_string(),
-        color: None,
+        color: Some(output::color::TEAL),
     }
 }
 
@@ -93,24 +97,50 @@ fn deployer() -> AgentId {
 pub struct Factory {
     pub config: FactoryConfig,
     pub phase: Arc<Mutex<Phase>>,
-    workspace: Arc<Mutex<Option<Workspace>>>,
-    project_name: Arc<Mutex<Option<String>>>,
+    /// Each channel's long-running project.
+    projects: Projects,
+    /// When set, the builder also gets the `freeq_admin` channel tools.
+    admin: Option<ChannelRoles>,
+    /// When set, finished projects are linked in the artifacts browser.
+    artifacts: Option<Artifacts>,
 }
 
 impl Factory {
     pub fn new(config: FactoryConfig) -> Self {
+        let projects = Projects::new(config.workspace_base.clone());
         Self {
             config,
             phase: Arc::new(Mutex::new(Phase::Idle)),
-            workspace: Arc::new(Mutex::new(None)),
-            project_name: Arc::new(Mutex::new(None)),
+            projects,
+            admin: None,
+            artifacts: None,
         }
     }
 
-    /// Handle a user command directed at the factory.
+    /// Let the builder manage freeq channels (create a project channel,
+    /// pin the deploy URL, ...), gated on the bot's roles in `roles`.
+    pub fn with_admin(mut self, roles: ChannelRoles) -> Self {
+        self.admin = Some(roles);
+        self
+    }
+
+    /// Post a link to the generated code in the artifacts browser when a
+    /// build completes.
+    pub fn with_artifacts(mut self, artifacts: Artifacts) -> Self {
+        self.artifacts = Some(artifacts);
+        self
+    }
+
+    /// The artifacts browser set with [`Factory::with_artifacts`].
+    pub fn artifacts(&self) -> Option<&Artifacts> {
+        self.artifacts.as_ref()
+    }
+
+    /// Handle a user command directed at the factory. Output goes to
+    /// `sink`; the admin tools are only offered when it is an IRC handle.
     pub async fn handle_command(
         &self,
-        handle: &ClientHandle,
+        sink: &dyn OutputSink,
         channel: &str,
         _sender: &str,
         command: &str,
@@ -120,14 +150,13 @@ impl Factory {
     ) -> Result<()> {
         match command {
             "build" | "create" | "make" => {
-                self.start_build(handle, channel, args, llm, memory).await?;
+                self.start_build(sink, channel, args, llm, memory).await?;
             }
             "status" => {
-                let phase = self.phase.lock().await;
-                let project = self.project_name.lock().await;
-                let name = project.as_deref().unwrap_or("none");
+                let phase = self.phase.lock().await.clone();
+                let name = self.projects.name_for(memory, channel)?;
                 output::status(
-                    handle,
+                    sink,
                     channel,
                     &product(),
                     "📊",
@@ -137,36 +166,46 @@ impl Factory {
             }
             "pause" => {
                 *self.phase.lock().await = Phase::Paused;
-                output::status(handle, channel, &product(), "⏸️", "Factory paused").await?;
+                output::status(sink, channel, &product(), "⏸️", "Factory paused").await?;
             }
             "resume" => {
-                output::status(handle, channel, &product(), "▶️", "Factory resumed").await?;
+                output::status(sink, channel, &product(), "▶️", "Factory resumed").await?;
             }
             "spec" => {
-                if let Some(ref name) = *self.project_name.lock().await {
-                    if let Some(spec) = memory.get(name, "spec", "current")? {
-                        output::say(handle, channel, &product(), &spec).await?;
-                    } else {
-                        output::say(handle, channel, &product(), "No spec yet.").await?;
-                    }
+                let name = self.projects.name_for(memory, channel)?;
+                if let Some(spec) = memory.get(&name, "spec", "current")? {
+                    output::say(sink, channel, &product(), &spec).await?;
+                } else {
+                    output::say(sink, channel, &product(), "No spec yet.").await?;
                 }
             }
+            "report" => {
+                self.post_report(sink, channel, args, memory).await?;
+            }
             "files" => {
-                if let Some(ref ws) = *self.workspace.lock().await {
+                let name = self.projects.name_for(memory, channel)?;
+                if self.projects.exists(&name) {
+                    let ws = self.projects.open(&name).await?;
                     let root = ws.root.clone();
                     let files = tokio::task::spawn_blocking(move || {
                         crate::tools::list_files_sync_pub(&root)
                     })
                     .await?;
-                    output::file_tree(handle, channel, &builder(), &files).await?;
+                    output::file_tree(sink, channel, &builder(), &files).await?;
+                    if let Some(ref artifacts) = self.artifacts {
+                        let url = artifacts.url(&ws.project_name);
+                        output::status(sink, channel, &builder(), "📂", &url).await?;
+                    }
+                } else {
+                    output::say(sink, channel, &builder(), "No files yet.").await?;
                 }
             }
             _ => {
                 output::say(
-                    handle,
+                    sink,
                     channel,
                     &product(),
-                    "Unknown command. Try: build <spec>, status, pause, resume, spec, files",
+                    "Unknown command. Try: build <spec>, status, pause, resume, spec, files, report [<build>]",
                 )
                 .await?;
             }
@@ -174,10 +213,149 @@ impl Factory {
         Ok(())
     }
 
-    /// Run the full factory pipeline.
+    /// Post the report of `channel`'s last build, or of build `args`: the
+    /// Markdown and JSON as attachments when a paste service is set, else
+    /// the Markdown inline and where the files are.
+    async fn post_report(
+        &self,
+        sink: &dyn OutputSink,
+        channel: &str,
+        args: &str,
+        memory: &Memory,
+    ) -> Result<()> {
+        let build = match args.trim() {
+            "" => None,
+            n => match n.trim_start_matches('#').parse::<usize>() {
+                Ok(n) => Some(n),
+                Err(_) => {
+                    return output::say(
+                        sink,
+                        channel,
+                        &product(),
+                        "Usage: /factory report [<build>]",
+                    )
+                    .await;
+                }
+            },
+        };
+        let name = self.projects.name_for(memory, channel)?;
+        let Some(report) = Report::load(memory, &name, build)? else {
+            let text = match build {
+                Some(n) => format!("Project {name} has no report for build {n}"),
+                None => format!("Project {name} has no build reports yet"),
+            };
+            return output::say(sink, channel, &product(), &text).await;
+        };
+        let stem = report.name();
+        output::attachment(
+            sink,
+            channel,
+            &product(),
+            &format!("{stem}.md"),
+            "text/markdown; charset=utf-8",
+            &report.to_markdown(),
+        )
+        .await?;
+        if output::has_paste_service() {
+            output::attachment(
+                sink,
+                channel,
+                &product(),
+                &format!("{stem}.json"),
+                "application/json",
+                &report.to_json(),
+            )
+            .await
+        } else {
+            let dir = super::report::dir(&self.config.workspace_base, &name);
+            let text = format!(
+                "Full report: {}/build-{}.{{md,json}}",
+                dir.display(),
+                report.build
+            );
+            output::status(sink, channel, &product(), "📄", &text).await
+        }
+    }
+
+    /// Handle a `/project` command for `channel`'s project: `create
+    /// <name>`, `diff`, `log`, `reset [<commit>]`, or show which project
+    /// the channel builds.
+    pub async fn handle_project_command(
+        &self,
+        sink: &dyn OutputSink,
+        channel: &str,
+        command: &str,
+        args: &str,
+        memory: &Memory,
+    ) -> Result<()> {
+        if command == "create" {
+            if args.trim().is_empty() {
+                output::say(sink, channel, &product(), "Usage: /project create <name>").await?;
+                return Ok(());
+            }
+            let name = match self.projects.bind(memory, channel, args) {
+                Ok(name) => name,
+                Err(e) => return output::error(sink, channel, &product(), &e.to_string()).await,
+            };
+            let existed = self.projects.exists(&name);
+            self.projects.open(&name).await?;
+            let text = if existed {
+                format!("{channel} now builds the existing project {name}")
+            } else {
+                format!("Created project {name}; /factory build here evolves it")
+            };
+            return output::status(sink, channel, &product(), "📁", &text).await;
+        }
+
+        let name = self.projects.name_for(memory, channel)?;
+        if !self.projects.exists(&name) {
+            let text = format!("Project {name} has no builds yet. Start one with /factory build");
+            return output::say(sink, channel, &product(), &text).await;
+        }
+        let ws = self.projects.open(&name).await?;
+        let result = match command {
+            "diff" => project::diff(&ws)
+                .await
+                .map(|patch| ("last-build.diff", patch)),
+            "log" => project::log(&ws, 10).await.map(|log| ("build log", log)),
+            "reset" => {
+                let rev = Some(args.trim()).filter(|rev| !rev.is_empty());
+                match project::reset(&ws, rev).await {
+                    Ok(head) => {
+                        project::sync_memory(&ws, memory).await?;
+                        let text = format!("Project {name} reset to {head}");
+                        return output::status(sink, channel, &builder(), "⏪", &text).await;
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            "" | "status" => {
+                let builds = project::builds(&ws).await?;
+                let text = format!("{channel} builds project {name} ({builds} builds)");
+                return output::status(sink, channel, &product(), "📁", &text).await;
+            }
+            _ => {
+                return output::say(
+                    sink,
+                    channel,
+                    &product(),
+                    "Unknown command. Try: project create <name>, diff, log, reset [<commit>]",
+                )
+                .await;
+            }
+        };
+        match result {
+            Ok((title, text)) => output::code(sink, channel, &builder(), title, &text, 40).await,
+            Err(e) => output::error(sink, channel, &builder(), &e.to_string()).await,
+        }
+    }
+
+    /// Run the full factory pipeline on `channel`'s project: a new one is
+    /// built from scratch, an existing one is changed in place to match the
+    /// request. Every build ends with a commit.
     async fn start_build(
         &self,
-        handle: &ClientHandle,
+        sink: &dyn OutputSink,
         channel: &str,
         spec: &str,
         llm: &LlmClient,
@@ -185,7 +363,7 @@ impl Factory {
     ) -> Result<()> {
         if spec.trim().is_empty() {
             output::say(
-                handle,
+                sink,
                 channel,
                 &product(),
                 "I need a spec! Tell me what to build.",
@@ -194,40 +372,55 @@ impl Factory {
             return Ok(());
         }
 
+        let project_name = self.projects.name_for(memory, channel)?;
+        let workspace = self.projects.open(&project_name).await?;
+        let builds = project::builds(&workspace).await?;
+        // A project that has been built before is changed in place, starting
+        // from its current spec and design.
+        let current = if builds > 0 {
+            Some((
+                memory
+                    .get(&project_name, "spec", "current")?
+                    .unwrap_or_default(),
+                memory
+                    .get(&project_name, "decision", "architecture")?
+                    .unwrap_or_default(),
+            ))
+        } else {
+            None
+        };
+        let mut report = Report::new(&project_name, channel, builds + 1, spec);
+
         // Phase 1: Product — clarify and write spec
         *self.phase.lock().await = Phase::Specifying;
-        output::status(
-            handle,
-            channel,
-            &product(),
-            "📋",
-            "Analyzing requirements...",
-        )
-        .await?;
-
-        let spec_deltas = llm.complete_stream(
-            "You are a product lead. Take the user's rough idea and produce a clear, concise product spec. Include: purpose, core features (bulleted), tech constraints (if any), and success criteria. Be specific but brief. Output ONLY the spec, no preamble.",
-            spec,
-        ).await?;
-
-        let project_name = crate::prototype::generate_project_name_pub(llm, spec).await?;
-        *self.project_name.lock().await = Some(project_name.clone());
-
-        output::say(
-            handle,
-            channel,
-            &product(),
-            &format!("Project: {project_name}"),
-        )
-        .await?;
+        output::status(sink, channel, &product(), "📋", "Analyzing requirements...").await?;
+
+        let spec_deltas = match current {
+            None => llm.for_task(Task::Plan).complete_stream(
+                "You are a product lead. Take the user's rough idea and produce a clear, concise product spec. Include: purpose, core features (bulleted), tech constraints (if any), and success criteria. Be specific but brief. Output ONLY the spec, no preamble.",
+                spec,
+            ).await?,
+            Some((ref current_spec, _)) => llm.for_task(Task::Plan).complete_stream(
+                "You are a product lead. Update the current product spec to take in the change request, keeping everything that still applies. Be specific but brief. Output ONLY the full updated spec, no preamble.",
+                &format!("## Current spec\n{current_spec}\n\n## Change request\n{spec}"),
+            ).await?,
+        };
+
+        let heading = if builds > 0 {
+            format!("Project: {project_name} (build {})", builds + 1)
+        } else {
+            format!("Project: {project_name}")
+        };
+        output::say(sink, channel, &product(), &heading).await?;
         let (refined_spec, _) =
-            output::stream_response(handle, channel, &product(), spec_deltas).await?;
+            output::stream_response(sink, channel, &product(), spec_deltas).await?;
         memory.set(&project_name, "spec", "current", &refined_spec)?;
+        report.spec = refined_spec.clone();
 
         // Phase 2: Architect — propose design
         *self.phase.lock().await = Phase::Designing;
         output::status(
-            handle,
+            sink,
             channel,
             &architect(),
             "🏗️",
@@ -235,24 +428,49 @@ impl Factory {
         )
         .await?;
 
-        let design_deltas = llm.complete_stream(
-            "You are a software architect. Given a product spec, propose a minimal, deployable architecture. Include: stack choice (prefer Python/Flask for speed), file structure, key abstractions. Be terse. Output ONLY the design, no preamble.",
-            &refined_spec,
-        ).await?;
+        let design_deltas = match current {
+            None => llm.for_task(Task::Plan).complete_stream(
+                "You are a software architect. Given a product spec, propose a minimal, deployable architecture. Include: stack choice (prefer Python/Flask for speed), file structure, key abstractions, and a Mermaid flowchart of the components in a ```mermaid code fence. Be terse. Output ONLY the design, no preamble.",
+                &refined_spec,
+            ).await?,
+            Some((_, ref current_design)) => llm.for_task(Task::Plan).complete_stream(
+                "You are a software architect. Given an updated product spec and the project's current architecture, update the architecture. Keep the existing stack and structure unless the spec requires a change, and redraw its Mermaid flowchart in a ```mermaid code fence. Be terse. Output ONLY the full updated design, no preamble.",
+                &format!("## Spec\n{refined_spec}\n\n## Current architecture\n{current_design}"),
+            ).await?,
+        };
 
         let (design, _) =
-            output::stream_response(handle, channel, &architect(), design_deltas).await?;
+            output::stream_response(sink, channel, &architect(), design_deltas).await?;
+        output::diagrams(sink, channel, &architect(), &design).await?;
         memory.set(&project_name, "decision", "architecture", &design)?;
+        report.design = design.clone();
 
         // Phase 3: Builder — write code
         *self.phase.lock().await = Phase::Building;
-        let workspace = Workspace::create(&self.config.workspace_base, &project_name).await?;
 
-        let build_prompt = format!(
-            "Build this project. Write ALL the code files, then deploy.\n\n## Spec\n{refined_spec}\n\n## Architecture\n{design}"
-        );
-
-        let tools = tools::code_tools();
+        let mut build_prompt = if builds > 0 {
+            let root = workspace.root.clone();
+            let files =
+                tokio::task::spawn_blocking(move || tools::list_files_sync_pub(&root)).await?;
+            format!(
+                "Update this existing project to match the spec. Read the files you change first, keep what still works, then deploy.\n\n## Spec\n{refined_spec}\n\n## Architecture\n{design}\n\n## Current files\n{}",
+                files.join("\n")
+            )
+        } else {
+            format!(
+                "Build this project. Write ALL the code files, then deploy.\n\n## Spec\n{refined_spec}\n\n## Architecture\n{design}"
+            )
+        };
+
+        // Channel admin needs a live IRC connection behind the sink.
+        let admin = self.admin.as_ref().zip(sink.irc());
+        let mut tools = tools::code_tools();
+        if admin.is_some() {
+            tools.extend(freeq_admin::admin_tools());
+            build_prompt.push_str(&format!(
+                "\n\nOnce deployed, use create_channel to open #{project_name} for the project, then pin_message the live URL there."
+            ));
+        }
         let mut messages = vec![Message {
             role: "user".to_string(),
             content: MessageContent::Text(build_prompt),
@@ -265,7 +483,7 @@ impl Factory {
             let phase = { self.phase.lock().await.clone() };
             if phase == Phase::Paused {
                 output::status(
-                    handle,
+                    sink,
                     channel,
                     &builder(),
                     "⏸️",
@@ -276,7 +494,10 @@ impl Factory {
                 break;
             }
 
-            let resp = llm.chat(BUILDER_SYSTEM, &messages, &tools, 4096).await?;
+            let resp = llm
+                .for_task(Task::Build)
+                .chat(BUILDER_SYSTEM, &messages, &tools, 4096)
+                .await?;
 
             let mut text_parts = Vec::new();
             let mut tool_uses = Vec::new();
@@ -291,8 +512,11 @@ impl Factory {
 
             // Post commentary (non-streaming since it's between tool calls)
             let commentary = text_parts.join("").trim().to_string();
+            if !commentary.is_empty() {
+                report.note(&builder().role, &commentary);
+            }
             if !commentary.is_empty() && commentary.len() < 500 {
-                output::say(handle, channel, &builder(), &commentary).await?;
+                output::say(sink, channel, &builder(), &commentary).await?;
             }
 
             if tool_uses.is_empty() {
@@ -333,28 +557,41 @@ impl Factory {
                 match tu.name.as_str() {
                     "write_file" => {
                         let path = tu.input["path"].as_str().unwrap_or("?");
-                        output::status(handle, channel, &agent, "✏️", &format!("Writing {path}"))
+                        output::status(sink, channel, &agent, "✏️", &format!("Writing {path}"))
                             .await?;
                     }
                     "shell" => {
                         let cmd = tu.input["command"].as_str().unwrap_or("?");
                         let short = if cmd.len() > 60 { &cmd[..57] } else { cmd };
-                        output::status(handle, channel, &agent, "⚙️", &format!("$ {short}"))
-                            .await?;
+                        output::status(sink, channel, &agent, "⚙️", &format!("$ {short}")).await?;
                     }
                     "deploy" => {
-                        output::status(handle, channel, &agent, "🚀", "Deploying...").await?;
+                        output::status(sink, channel, &agent, "🚀", "Deploying...").await?;
+                    }
+                    name if freeq_admin::is_admin_tool(name) => {
+                        let target = tu.input["channel"].as_str().unwrap_or("?");
+                        output::status(sink, channel, &agent, "🛠️", &format!("{name} {target}"))
+                            .await?;
                     }
                     _ => {}
                 }
 
-                let result = match tools::execute_tool(&workspace, &tu.name, &tu.input).await {
+                let outcome = match admin {
+                    Some((roles, handle)) if freeq_admin::is_admin_tool(&tu.name) => {
+                        freeq_admin::execute(handle, roles, &tu.name, &tu.input).await
+                    }
+                    _ => tools::execute_tool(&workspace, &tu.name, &tu.input).await,
+                };
+                report
+                    .steps
+                    .push(Step::tool(&agent.role, &tu.name, &tu.input, &outcome));
+                let result = match outcome {
                     Ok(out) => {
                         if tu.name == "deploy"
                             && let Some(url) = extract_url(&out)
                         {
                             deployed_url = Some(url.clone());
-                            output::deploy_result(handle, channel, &deployer(), &url).await?;
+                            output::deploy_result(sink, channel, &deployer(), &url).await?;
                             memory.set(&project_name, "deploy", "url", &url)?;
                         }
                         if tu.name == "write_file"
@@ -366,8 +603,7 @@ impl Factory {
                         out
                     }
                     Err(e) => {
-                        output::error(handle, channel, &agent, &format!("{}: {e}", tu.name))
-                            .await?;
+                        output::error(sink, channel, &agent, &format!("{}: {e}", tu.name)).await?;
                         format!("Error: {e}")
                     }
                 };
@@ -389,18 +625,47 @@ impl Factory {
         *self.phase.lock().await = Phase::Reviewing;
         let ctx = memory.project_context(&project_name)?;
         if !ctx.is_empty() {
-            let review_deltas = llm.complete_stream(
+            let review_deltas = llm.for_task(Task::Review).complete_stream(
                 "You are a code reviewer. Given a project's files and spec, give a brief review: what's good, what could be improved. Be constructive and concise. 3-5 bullet points max.",
                 &ctx,
             ).await?;
-            output::stream_response(handle, channel, &reviewer(), review_deltas).await?;
+            let (review, _) =
+                output::stream_response(sink, channel, &reviewer(), review_deltas).await?;
+            report.review = Some(review);
+        }
+
+        // Every build is a commit, for /project diff, log and reset.
+        let summary: String = spec
+            .trim()
+            .lines()
+            .next()
+            .unwrap_or("")
+            .chars()
+            .take(72)
+            .collect();
+        match project::commit(&workspace, &summary).await {
+            Ok(Some(hash)) => {
+                let text = format!("Committed build {} as {hash}", builds + 1);
+                output::status(sink, channel, &builder(), "📝", &text).await?;
+                match project::diff(&workspace).await {
+                    Ok(diff) => report.set_diff(&diff),
+                    Err(e) => tracing::warn!(error = %e, "No diff for the build report"),
+                }
+                report.commit = Some(hash);
+            }
+            Ok(None) => {
+                output::status(sink, channel, &builder(), "📝", "No changes to commit").await?;
+            }
+            Err(e) => {
+                output::error(sink, channel, &builder(), &format!("Commit failed: {e}")).await?;
+            }
         }
 
         // Done
         *self.phase.lock().await = Phase::Complete;
         if let Some(ref url) = deployed_url {
             output::status(
-                handle,
+                sink,
                 channel,
                 &product(),
                 "✅",
@@ -408,11 +673,32 @@ impl Factory {
             )
             .await?;
         } else {
-            output::status(handle, channel, &product(), "✅", "Factory complete!").await?;
+            output::status(sink, channel, &product(), "✅", "Factory complete!").await?;
+        }
+        if let Some(ref artifacts) = self.artifacts {
+            let url = artifacts.url(&workspace.project_name);
+            output::status(
+                sink,
+                channel,
+                &builder(),
+                "📂",
+                &format!("Browse the code: {url}"),
+            )
+            .await?;
         }
 
-        // Store workspace
-        *self.workspace.lock().await = Some(workspace);
+        report.deploy_url = deployed_url;
+        report.finished_at = chrono::Utc::now().timestamp();
+        match report.save(&self.config.workspace_base, memory).await {
+            Ok(_) => {
+                let text = format!("Build {} report ready: /factory report", report.build);
+                output::status(sink, channel, &product(), "📄", &text).await?;
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to save the build report");
+                output::error(sink, channel, &product(), &format!("Report not saved: {e}")).await?;
+            }
+        }
 
         Ok(())
     }
@@ -0,0 +1,285 @@
+//! Long-running projects — one persistent workspace and git repo per channel.
+//!
+//! Each channel the factory works in is bound to a project, by default
+//! named after the channel (`#todo-app` → `todo-app`); `/project create
+//! <name>` binds it to another one. The project's workspace is never
+//! recreated, and it is a git repository: every `/factory build` commits
+//! what it changed, so successive builds evolve the same codebase and
+//! `/project diff`, `/project log` and `/project reset` can inspect and
+//! undo them. Bindings live in the bot's [`Memory`], so they survive a
+//! restart.
+
+use std::path::PathBuf;
+
+use anyhow::{Context, Result, bail};
+use tokio::process::Command;
+
+use crate::memory::Memory;
+use crate::tools::{self, Workspace};
+
+/// Memory namespace holding channel → project bindings.
+const BINDINGS: &str = "channels";
+
+/// Longest project name.
+const MAX_NAME_LEN: usize = 40;
+
+/// Written into new repos so builds don't commit dependencies or secrets.
+const GITIGNORE: &str = "node_modules/\n__pycache__/\n.venv/\ntarget/\n.env\n";
+
+/// Committer for build commits.
+const AUTHOR: [&str; 4] = [
+    "-c",
+    "user.name=freeq factory",
+    "-c",
+    "user.email=factory@freeq.invalid",
+];
+
+/// Projects under a workspace base directory.
+#[derive(Debug, Clone)]
+pub struct Projects {
+    base: PathBuf,
+}
+
+impl Projects {
+    pub fn new(base: PathBuf) -> Self {
+        Self { base }
+    }
+
+    /// The project `channel` is bound to: the one set with [`bind`](Self::bind),
+    /// else one named after the channel.
+    pub fn name_for(&self, memory: &Memory, channel: &str) -> Result<String> {
+        if let Some(name) = memory.get(BINDINGS, "project", &channel.to_lowercase())? {
+            return Ok(name);
+        }
+        project_name(channel).context("this channel's name can't name a project")
+    }
+
+    /// Bind `channel` to project `name` (cleaned up into a directory name)
+    /// and return the name used.
+    pub fn bind(&self, memory: &Memory, channel: &str, name: &str) -> Result<String> {
+        let name = project_name(name).context("project names need a letter or digit")?;
+        memory.set(BINDINGS, "project", &channel.to_lowercase(), &name)?;
+        Ok(name)
+    }
+
+    /// Open project `name`, creating its workspace and repo the first time.
+    pub async fn open(&self, name: &str) -> Result<Workspace> {
+        let workspace = Workspace::create(&self.base, name).await?;
+        if !workspace.root.join(".git").exists() {
+            git(&workspace, &["init", "--quiet"]).await?;
+            let gitignore = workspace.root.join(".gitignore");
+            if !gitignore.exists() {
+                tokio::fs::write(&gitignore, GITIGNORE).await?;
+            }
+        }
+        Ok(workspace)
+    }
+
+    /// Whether project `name` has a workspace yet.
+    pub fn exists(&self, name: &str) -> bool {
+        self.base.join(name).join(".git").exists()
+    }
+}
+
+/// `text` as a project name: lowercase letters, digits and single dashes.
+fn project_name(text: &str) -> Option<String> {
+    let mut name = String::new();
+    for c in text.trim().to_lowercase().chars() {
+        if c.is_alphanumeric() {
+            name.push(c);
+        } else if !name.is_empty() && !name.ends_with('-') {
+            name.push('-');
+        }
+    }
+    let name: String = name.chars().take(MAX_NAME_LEN).collect();
+    let name = name.trim_end_matches('-');
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Run git in the workspace and return its stdout.
+async fn git(workspace: &Workspace, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(&workspace.root)
+        .output()
+        .await
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        bail!(
+            "git {}: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Number of commits (builds) in the project.
+pub async fn builds(workspace: &Workspace) -> Result<usize> {
+    if git(workspace, &["rev-parse", "--verify", "--quiet", "HEAD"])
+        .await
+        .is_err()
+    {
+        return Ok(0);
+    }
+    let count = git(workspace, &["rev-list", "--count", "HEAD"]).await?;
+    Ok(count.trim().parse()?)
+}
+
+/// Commit everything in the workspace. Returns the short hash, or `None`
+/// when nothing changed.
+pub async fn commit(workspace: &Workspace, message: &str) -> Result<Option<String>> {
+    git(workspace, &["add", "--all"]).await?;
+    if git(workspace, &["status", "--porcelain"])
+        .await?
+        .trim()
+        .is_empty()
+    {
+        return Ok(None);
+    }
+    let mut args = AUTHOR.to_vec();
+    args.extend(["commit", "--quiet", "-m", message]);
+    git(workspace, &args).await?;
+    let hash = git(workspace, &["rev-parse", "--short", "HEAD"]).await?;
+    Ok(Some(hash.trim().to_string()))
+}
+
+/// The last build's changes, as a patch with a stat summary.
+pub async fn diff(workspace: &Workspace) -> Result<String> {
+    if builds(workspace).await? == 0 {
+        bail!("no builds yet");
+    }
+    git(
+        workspace,
+        &["show", "--stat", "--patch", "--format=%h %s", "HEAD"],
+    )
+    .await
+}
+
+/// The last `limit` builds, newest first.
+pub async fn log(workspace: &Workspace, limit: usize) -> Result<String> {
+    if builds(workspace).await? == 0 {
+        bail!("no builds yet");
+    }
+    let limit = format!("-n{limit}");
+    git(
+        workspace,
+        &["log", &limit, "--date=short", "--format=%h %ad %s"],
+    )
+    .await
+}
+
+/// Go back to build `rev` (a commit hash), or to the one before the last
+/// when `None`, discarding everything after it. Returns the commit now
+/// checked out.
+pub async fn reset(workspace: &Workspace, rev: Option<&str>) -> Result<String> {
+    let target = match rev {
+        Some(rev) => {
+            if !(4..=40).contains(&rev.len()) || !rev.chars().all(|c| c.is_ascii_hexdigit()) {
+                bail!("{rev:?} is not a commit hash; see /project log");
+            }
+            rev
+        }
+        None if builds(workspace).await? < 2 => bail!("there's no earlier build to go back to"),
+        None => "HEAD~1",
+    };
+    git(workspace, &["reset", "--quiet", "--hard", target]).await?;
+    git(workspace, &["clean", "--quiet", "-fd"]).await?;
+    let head = git(workspace, &["log", "-n1", "--format=%h %s"]).await?;
+    Ok(head.trim().to_string())
+}
+
+/// Replace the project's remembered files with what's in the workspace,
+/// after a reset.
+pub async fn sync_memory(workspace: &Workspace, memory: &Memory) -> Result<()> {
+    let project = &workspace.project_name;
+    for entry in memory.list(project, "file")? {
+        memory.delete(project, "file", &entry.key)?;
+    }
+    let root = workspace.root.clone();
+    let files = tokio::task::spawn_blocking(move || tools::list_files_sync_pub(&root)).await?;
+    for path in files {
+        if path.starts_with('.') {
+            continue;
+        }
+        if let Ok(content) = workspace.read_file(&path).await {
+            memory.set(project, "file", &path, &content)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channels_name_projects() {
+        assert_eq!(project_name("#Todo-App").as_deref(), Some("todo-app"));
+        assert_eq!(
+            project_name("&my  cool_app!").as_deref(),
+            Some("my-cool-app")
+        );
+        assert_eq!(project_name("#-rf").as_deref(), Some("rf"));
+        assert_eq!(project_name("../etc").as_deref(), Some("etc"));
+        assert_eq!(project_name("#!!"), None);
+        assert_eq!(project_name(&"a".repeat(60)).unwrap().len(), MAX_NAME_LEN);
+    }
+
+    #[test]
+    fn bindings_persist_in_memory() {
+        let memory = Memory::in_memory().unwrap();
+        let projects = Projects::new(PathBuf::from("/tmp"));
+        assert_eq!(projects.name_for(&memory, "#Shop").unwrap(), "shop");
+        assert_eq!(
+            projects.bind(&memory, "#shop", "Store Front").unwrap(),
+            "store-front"
+        );
+        assert_eq!(projects.name_for(&memory, "#SHOP").unwrap(), "store-front");
+        assert!(projects.bind(&memory, "#shop", "???").is_err());
+    }
+
+    #[tokio::test]
+    async fn builds_commit_and_reset() {
+        let base = std::env::temp_dir().join(format!("freeq-projects-{}", std::process::id()));
+        let projects = Projects::new(base.clone());
+        let ws = projects.open("app").await.unwrap();
+        assert!(projects.exists("app"));
+        assert_eq!(builds(&ws).await.unwrap(), 0);
+        assert!(log(&ws, 10).await.is_err());
+
+        ws.write_file("app.py", "v1\n").await.unwrap();
+        let first = commit(&ws, "First build").await.unwrap().unwrap();
+        assert_eq!(commit(&ws, "Nothing").await.unwrap(), None);
+        ws.write_file("app.py", "v2\n").await.unwrap();
+        ws.write_file("extra.py", "x\n").await.unwrap();
+        commit(&ws, "Second build").await.unwrap().unwrap();
+
+        // Reopening keeps the history.
+        let ws = projects.open("app").await.unwrap();
+        assert_eq!(builds(&ws).await.unwrap(), 2);
+        let history = log(&ws, 10).await.unwrap();
+        assert!(history.contains("Second build") && history.contains(&first));
+        let patch = diff(&ws).await.unwrap();
+        assert!(patch.contains("+v2") && patch.contains("extra.py"));
+
+        assert!(reset(&ws, Some("HEAD; rm -rf /")).await.is_err());
+        let head = reset(&ws, None).await.unwrap();
+        assert!(head.starts_with(&first), "{head}");
+        assert_eq!(ws.read_file("app.py").await.unwrap(), "v1\n");
+        assert!(!ws.root.join("extra.py").exists());
+        assert!(reset(&ws, None).await.is_err());
+
+        let memory = Memory::in_memory().unwrap();
+        memory.set("app", "file", "extra.py", "x\n").unwrap();
+        sync_memory(&ws, &memory).await.unwrap();
+        let files: Vec<_> = memory.list("app", "file").unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(
+            (files[0].key.as_str(), files[0].value.as_str()),
+            ("app.py", "v1\n")
+        );
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,393 @@
+//! Build reports — a shareable transcript of one `/factory build`.
+//!
+//! While a build runs the orchestrator records what happened: the request,
+//! the spec and design the agents settled on, every builder remark and tool
+//! call, the test runs, the review, the commit with its diff, and the
+//! deploy URL. The finished [`Report`] is rendered as Markdown and JSON,
+//! written next to the project workspaces (`<base>/.reports/<project>/`,
+//! outside the project's repo so it never lands in a build commit) and kept
+//! in [`Memory`]. `/factory report [<build>]` posts it as attachments.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::memory::Memory;
+
+/// Memory kind holding reports, keyed by build number.
+const KIND: &str = "report";
+
+/// Directory under the workspace base holding report files. Project names
+/// can't start with a dot, so it never collides with a project.
+const DIR: &str = ".reports";
+
+/// Longest tool output kept per call.
+const MAX_OUTPUT: usize = 4000;
+
+/// Longest diff kept.
+const MAX_DIFF: usize = 100_000;
+
+/// One build of a project, start to finish.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    pub project: String,
+    pub channel: String,
+    /// 1 for the first build.
+    pub build: usize,
+    /// Unix seconds.
+    pub started_at: i64,
+    pub finished_at: i64,
+    /// What was asked for.
+    pub request: String,
+    pub spec: String,
+    pub design: String,
+    pub steps: Vec<Step>,
+    pub review: Option<String>,
+    /// Short hash of the build commit, if anything changed.
+    pub commit: Option<String>,
+    /// The commit's patch.
+    pub diff: Option<String>,
+    pub deploy_url: Option<String>,
+}
+
+/// Something an agent did during the build.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Step {
+    /// An agent explaining what it is doing.
+    Note { agent: String, text: String },
+    /// A tool call and its (truncated) result.
+    Tool {
+        agent: String,
+        name: String,
+        /// What the call did, e.g. the file written or command run.
+        summary: String,
+        ok: bool,
+        output: String,
+    },
+}
+
+impl Step {
+    /// A tool call by `agent`. Test runs are shell calls made by the QA
+    /// agent; a non-zero exit counts as a failure.
+    pub fn tool(
+        agent: &str,
+        name: &str,
+        input: &serde_json::Value,
+        result: &Result<String>,
+    ) -> Self {
+        let summary = match name {
+            "write_file" => {
+                let path = input["path"].as_str().unwrap_or("?");
+                let lines = input["content"].as_str().map_or(0, |c| c.lines().count());
+                format!("{path} ({lines} lines)")
+            }
+            "read_file" => input["path"].as_str().unwrap_or("?").to_string(),
+            "shell" => format!("$ {}", input["command"].as_str().unwrap_or("?")),
+            _ => truncate(&input.to_string(), 200),
+        };
+        let (ok, output) = match result {
+            Ok(out) => (!out.contains("[exit code: "), out.as_str().into()),
+            Err(e) => (false, format!("Error: {e}")),
+        };
+        Step::Tool {
+            agent: agent.to_string(),
+            name: name.to_string(),
+            summary,
+            ok,
+            output: truncate(&output, MAX_OUTPUT),
+        }
+    }
+
+    fn is_test(&self) -> bool {
+        matches!(self, Step::Tool { agent, name, .. } if agent == "qa" && name == "shell")
+    }
+}
+
+impl Report {
+    pub fn new(project: &str, channel: &str, build: usize, request: &str) -> Self {
+        Self {
+            project: project.to_string(),
+            channel: channel.to_string(),
+            build,
+            started_at: chrono::Utc::now().timestamp(),
+            request: request.trim().to_string(),
+            ..Self::default()
+        }
+    }
+
+    pub fn note(&mut self, agent: &str, text: &str) {
+        self.steps.push(Step::Note {
+            agent: agent.to_string(),
+            text: text.to_string(),
+        });
+    }
+
+    pub fn set_diff(&mut self, diff: &str) {
+        self.diff = Some(truncate(diff, MAX_DIFF));
+    }
+
+    /// The test runs, in order.
+    pub fn tests(&self) -> impl Iterator<Item = &Step> {
+        self.steps.iter().filter(|s| s.is_test())
+    }
+
+    /// File name stem, e.g. `todo-app-build-3`.
+    pub fn name(&self) -> String {
+        format!("{}-build-{}", self.project, self.build)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let when = |t: i64| {
+            chrono::DateTime::from_timestamp(t, 0)
+                .map(|t| t.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+                .unwrap_or_else(|| t.to_string())
+        };
+        let mut md = format!("# {} — build {}\n\n", self.project, self.build);
+        md.push_str(&format!("- Channel: {}\n", self.channel));
+        md.push_str(&format!(
+            "- Started: {} ({}s)\n",
+            when(self.started_at),
+            (self.finished_at - self.started_at).max(0)
+        ));
+        if let Some(commit) = &self.commit {
+            md.push_str(&format!("- Commit: `{commit}`\n"));
+        }
+        let tests: Vec<&Step> = self.tests().collect();
+        if !tests.is_empty() {
+            let passed = tests
+                .iter()
+                .filter(|t| matches!(t, Step::Tool { ok: true, .. }))
+                .count();
+            md.push_str(&format!("- Tests: {passed}/{} runs passed\n", tests.len()));
+        }
+        if let Some(url) = &self.deploy_url {
+            md.push_str(&format!("- Live at: {url}\n"));
+        }
+
+        md.push_str("\n## Request\n\n");
+        for line in self.request.lines() {
+            md.push_str(&format!("> {line}\n"));
+        }
+        for (heading, text) in [("Spec", &self.spec), ("Architecture", &self.design)] {
+            if !text.is_empty() {
+                md.push_str(&format!("\n## {heading}\n\n{}\n", text.trim()));
+            }
+        }
+
+        if !self.steps.is_empty() {
+            md.push_str("\n## Build\n\n");
+            for (i, step) in self.steps.iter().enumerate() {
+                match step {
+                    Step::Note { agent, text } => {
+                        md.push_str(&format!("{}. **{agent}**: {}\n", i + 1, text.trim()));
+                    }
+                    Step::Tool {
+                        agent,
+                        name,
+                        summary,
+                        ok,
+                        output,
+                    } => {
+                        let mark = if *ok { "✅" } else { "❌" };
+                        md.push_str(&format!(
+                            "{}. **{agent}** `{name}` {} {mark}\n",
+                            i + 1,
+                            summary.replace('\n', " ")
+                        ));
+                        // File contents are in the diff; keep the log readable.
+                        let echoes_file = matches!(name.as_str(), "write_file" | "read_file");
+                        if !output.trim().is_empty() && (!ok || !echoes_file) {
+                            md.push_str(&fenced(output, "", "   "));
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(review) = &self.review {
+            md.push_str(&format!("\n## Review\n\n{}\n", review.trim()));
+        }
+        if let Some(diff) = &self.diff {
+            md.push_str("\n## Changes\n\n");
+            md.push_str(&fenced(diff, "diff", ""));
+        }
+        md
+    }
+
+    /// Write the Markdown and JSON under `base` and store the report in
+    /// `memory`. Returns the Markdown file's path.
+    pub async fn save(&self, base: &Path, memory: &Memory) -> Result<PathBuf> {
+        let dir = dir(base, &self.project);
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        let stem = dir.join(format!("build-{}", self.build));
+        let json = self.to_json();
+        tokio::fs::write(stem.with_extension("json"), &json).await?;
+        let md = stem.with_extension("md");
+        tokio::fs::write(&md, self.to_markdown()).await?;
+        memory.set(&self.project, KIND, &self.build.to_string(), &json)?;
+        Ok(md)
+    }
+
+    /// Build `build`'s report for `project`, or the latest one.
+    pub fn load(memory: &Memory, project: &str, build: Option<usize>) -> Result<Option<Self>> {
+        let entry = memory
+            .list(project, KIND)?
+            .into_iter()
+            .filter_map(|e| Some((e.key.parse::<usize>().ok()?, e.value)))
+            .filter(|(n, _)| build.is_none_or(|b| b == *n))
+            .max_by_key(|(n, _)| *n);
+        entry
+            .map(|(_, json)| serde_json::from_str(&json).context("Stored report is corrupt"))
+            .transpose()
+    }
+}
+
+/// Where `project`'s report files live.
+pub fn dir(base: &Path, project: &str) -> PathBuf {
+    base.join(DIR).join(project)
+}
+
+/// `text` cut to `max` bytes (on a char boundary), noting what was cut.
+fn truncate(text: &str, max: usize) -> String {
+    if text.len() <= max {
+        return text.to_string();
+    }
+    let mut end = max;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}\n... ({} more bytes)", &text[..end], text.len() - end)
+}
+
+/// `text` in a code fence long enough not to be closed by its contents,
+/// each line prefixed with `indent`.
+fn fenced(text: &str, lang: &str, indent: &str) -> String {
+    let mut fence = "```".to_string();
+    while text.contains(fence.as_str()) {
+        fence.push('`');
+    }
+    let mut out = format!("{indent}{fence}{lang}\n");
+    for line in text.trim_end().lines() {
+        out.push_str(&format!("{indent}{line}\n"));
+    }
+    out.push_str(&format!("{indent}{fence}\n"));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> Report {
+        let mut report = Report::new("todo", "#todo", 2, "  add tags\n");
+        report.spec = "A todo app with tags".into();
+        report.design = "Flask, one file".into();
+        report.note("builder", "Writing the app");
+        report.steps.push(Step::tool(
+            "builder",
+            "write_file",
+            &serde_json::json!({"path": "app.py", "content": "a\nb\n"}),
+            &Ok("Wrote app.py".into()),
+        ));
+        report.steps.push(Step::tool(
+            "qa",
+            "shell",
+            &serde_json::json!({"command": "pytest"}),
+            &Ok("1 failed\n[exit code: 1]".into()),
+        ));
+        report.steps.push(Step::tool(
+            "qa",
+            "shell",
+            &serde_json::json!({"command": "pytest"}),
+            &Ok("1 passed".into()),
+        ));
+        report.steps.push(Step::tool(
+            "deploy",
+            "deploy",
+            &serde_json::json!({}),
+            &Err(anyhow::anyhow!("miren is down")),
+        ));
+        report.review = Some("- Fine".into());
+        report.commit = Some("abc1234".into());
+        report.set_diff("+```\n+x");
+        report.deploy_url = Some("https://todo.example".into());
+        report.finished_at = report.started_at + 42;
+        report
+    }
+
+    #[test]
+    fn tool_steps_summarize_and_judge_calls() {
+        let report = report();
+        let Step::Tool { summary, ok, .. } = &report.steps[1] else {
+            panic!("not a tool call");
+        };
+        assert_eq!((summary.as_str(), *ok), ("app.py (2 lines)", true));
+        let oks: Vec<bool> = report
+            .tests()
+            .map(|t| matches!(t, Step::Tool { ok: true, .. }))
+            .collect();
+        assert_eq!(oks, [false, true]);
+        let Step::Tool { ok, output, .. } = &report.steps[4] else {
+            panic!("not a tool call");
+        };
+        assert!(!ok);
+        assert_eq!(output, "Error: miren is down");
+
+        let long = truncate(&"é".repeat(10), 5);
+        assert_eq!(long, "éé\n... (16 more bytes)");
+    }
+
+    #[test]
+    fn markdown_covers_the_whole_build() {
+        let md = report().to_markdown();
+        for expected in [
+            "# todo — build 2",
+            "- Commit: `abc1234`",
+            "- Tests: 1/2 runs passed",
+            "- Live at: https://todo.example",
+            "> add tags",
+            "## Spec\n\nA todo app with tags",
+            "1. **builder**: Writing the app",
+            "2. **builder** `write_file` app.py (2 lines) ✅",
+            "3. **qa** `shell` $ pytest ❌",
+            "5. **deploy** `deploy` {} ❌",
+            "## Review\n\n- Fine",
+            "````diff\n+```\n+x\n````",
+        ] {
+            assert!(md.contains(expected), "missing {expected:?} in\n{md}");
+        }
+        // File contents aren't echoed back.
+        assert!(!md.contains("Wrote app.py"));
+    }
+
+    #[tokio::test]
+    async fn reports_are_saved_to_disk_and_memory() {
+        let base = std::env::temp_dir().join(format!("freeq-reports-{}", std::process::id()));
+        let memory = Memory::in_memory().unwrap();
+        let mut first = report();
+        first.build = 1;
+        first.save(&base, &memory).await.unwrap();
+        let second = report();
+        let md = second.save(&base, &memory).await.unwrap();
+
+        assert_eq!(md, base.join(".reports/todo/build-2.md"));
+        assert_eq!(std::fs::read_to_string(&md).unwrap(), second.to_markdown());
+        let json = std::fs::read_to_string(base.join(".reports/todo/build-2.json")).unwrap();
+        assert_eq!(serde_json::from_str::<Report>(&json).unwrap(), second);
+
+        assert_eq!(Report::load(&memory, "todo", None).unwrap(), Some(second));
+        assert_eq!(Report::load(&memory, "todo", Some(1)).unwrap(), Some(first));
+        assert_eq!(Report::load(&memory, "todo", Some(3)).unwrap(), None);
+        assert_eq!(Report::load(&memory, "other", None).unwrap(), None);
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,162 @@
+//! What the connected server supports, and how the bot adapts to it.
+//!
+//! The SDK follows capability changes after registration (`CAP NEW` /
+//! `CAP DEL`); the bot also reads the server's version from `004` and its
+//! `005` (ISUPPORT) tokens. Whenever the negotiated capabilities or the
+//! bot's identity change, [`Features::adapt`] re-applies its settings:
+//!
+//! - status lines go out `+freeq.at/priority=low` when the server vets
+//!   priorities (`freeq.at/priority`) and the bot is signed in;
+//! - multi-line replies go out as one `draft/multiline` BATCH while
+//!   `draft/multiline` and `batch` are acked (the IRC sink checks this
+//!   per message), and line by line otherwise.
+//!
+//! `/botinfo` reports the result with [`Features::report`].
+
+use std::collections::BTreeMap;
+
+use freeq_sdk::client::ClientHandle;
+use freeq_sdk::event::Event;
+use freeq_sdk::irc::Message;
+use freeq_sdk::proto::tags::Priority;
+use freeq_sdk::proto::{caps, numeric};
+
+use crate::output;
+
+/// This bot's version.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// What the bot knows about the server it is connected to.
+#[derive(Debug, Default)]
+pub struct Features {
+    /// The server's software version, from `004`.
+    pub server_version: Option<String>,
+    /// ISUPPORT tokens from `005`; valueless ones map to "".
+    pub isupport: BTreeMap<String, String>,
+    /// Whether SASL succeeded on this connection.
+    pub signed_in: bool,
+    /// The capabilities and identity the settings were last applied for.
+    applied: Option<(Vec<String>, bool)>,
+}
+
+impl Features {
+    /// Note what `event` tells us about the server or our identity.
+    pub fn observe(&mut self, event: &Event) {
+        match event {
+            Event::Authenticated { .. } => self.signed_in = true,
+            Event::Disconnected { .. } => *self = Self::default(),
+            Event::RawLine(line) => {
+                let Some(msg) = Message::parse(line) else {
+                    return;
+                };
+                match msg.command.as_str() {
+                    numeric::RPL_MYINFO => self.server_version = msg.params.get(2).cloned(),
+                    // `<nick> <token>... :are supported by this server`
+                    numeric::RPL_ISUPPORT if msg.params.len() > 2 => {
+                        for token in &msg.params[1..msg.params.len() - 1] {
+                            if let Some(removed) = token.strip_prefix('-') {
+                                self.isupport.remove(removed);
+                                continue;
+                            }
+                            let (key, value) = token.split_once('=').unwrap_or((token, ""));
+                            self.isupport.insert(key.to_string(), value.to_string());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Re-apply the bot's settings if the negotiated capabilities or the
+    /// sign-in changed since last time. Cheap enough to call per event.
+    pub fn adapt(&mut self, handle: &ClientHandle) {
+        let current = (handle.caps(), self.signed_in);
+        if self.applied.as_ref() == Some(&current) {
+            return;
+        }
+        let priority = if self.signed_in && handle.has_cap(caps::PRIORITY) {
+            Priority::Low
+        } else {
+            Priority::Normal
+        };
+        if priority != output::status_priority() {
+            tracing::info!(%priority, "Status lines now go out at {priority} priority");
+            output::set_status_priority(priority);
+        }
+        tracing::debug!(caps = ?current.0, "Adapted to server capabilities");
+        self.applied = Some(current);
+    }
+
+    /// What `/botinfo` says: versions, negotiated capabilities, the
+    /// server's ISUPPORT tokens and what the bot does with them.
+    pub fn report(&self, handle: &ClientHandle) -> Vec<String> {
+        let on = |yes: bool| if yes { "on" } else { "off" };
+        let server = self
+            .server_version
+            .as_deref()
+            .unwrap_or("an unknown server");
+        let acked = handle.caps();
+        let isupport: Vec<String> = self
+            .isupport
+            .iter()
+            .map(|(k, v)| {
+                if v.is_empty() {
+                    k.clone()
+                } else {
+                    format!("{k}={v}")
+                }
+            })
+            .collect();
+        vec![
+            format!("freeq-bots {VERSION} on {server}"),
+            format!(
+                "Capabilities: {}",
+                if acked.is_empty() {
+                    "none".to_string()
+                } else {
+                    acked.join(", ")
+                }
+            ),
+            format!("Server supports: {}", isupport.join(" ")),
+            format!(
+                "Signed in: {} · multi-line replies: {} · status priority: {} · paste uploads: {}",
+                if self.signed_in { "yes" } else { "no" },
+                on(handle.has_cap(caps::MULTILINE) && handle.has_cap(caps::BATCH)),
+                output::status_priority(),
+                on(output::has_paste_service()),
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_version_and_isupport() {
+        let mut f = Features::default();
+        for line in [
+            ":irc.test 004 bot irc.test freeq-0.2 o o",
+            ":irc.test 005 bot CASEMAPPING=rfc1459 NICKLEN=64 WHOX :are supported by this server",
+            ":irc.test 005 bot -WHOX MODES=4 :are supported by this server",
+        ] {
+            f.observe(&Event::RawLine(line.to_string()));
+        }
+        f.observe(&Event::Authenticated {
+            did: "did:plc:bot".into(),
+        });
+
+        assert_eq!(f.server_version.as_deref(), Some("freeq-0.2"));
+        let tokens: Vec<_> = f.isupport.iter().map(|(k, v)| format!("{k}={v}")).collect();
+        assert_eq!(tokens, ["CASEMAPPING=rfc1459", "MODES=4", "NICKLEN=64"]);
+        assert!(f.signed_in);
+
+        f.observe(&Event::Disconnected {
+            reason: "EOF".into(),
+        });
+        assert!(f.server_version.is_none() && f.isupport.is_empty() && !f.signed_in);
+    }
+}
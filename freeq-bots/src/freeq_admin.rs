@@ -0,0 +1,453 @@
+//! `freeq_admin` toolset — lets agents manage freeq channels.
+//!
+//! Tools for setting topics, pinning messages, inviting users, creating
+//! channels and setting modes, backed by [`ClientHandle`]. Each tool is
+//! gated on the bot's own role in the target channel as tracked by
+//! [`ChannelRoles`], so an agent is told up front when it lacks ops
+//! rather than firing commands the server will refuse.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use anyhow::{Result, bail};
+use freeq_sdk::client::ClientHandle;
+use freeq_sdk::event::Event;
+use serde_json::{Value, json};
+
+use crate::llm::ToolDef;
+
+/// The bot's standing in a channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ChannelRole {
+    Member,
+    Voice,
+    Op,
+}
+
+impl std::fmt::Display for ChannelRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChannelRole::Member => write!(f, "member"),
+            ChannelRole::Voice => write!(f, "voice"),
+            ChannelRole::Op => write!(f, "op"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Roles {
+    nick: String,
+    /// Keyed by lowercased channel.
+    channels: HashMap<String, ChannelRole>,
+}
+
+/// The bot's role in each channel it is in, kept current from client
+/// events. Cheap to clone; clones share state.
+#[derive(Debug, Clone, Default)]
+pub struct ChannelRoles {
+    inner: Arc<RwLock<Roles>>,
+}
+
+impl ChannelRoles {
+    /// Track roles for the bot registered as `nick`.
+    pub fn new(nick: &str) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(Roles {
+                nick: nick.to_string(),
+                channels: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Update from a client event. Call for every event the bot receives.
+    pub fn handle_event(&self, event: &Event) {
+        let mut roles = self.inner.write().unwrap_or_else(|e| e.into_inner());
+        let is_us = |nick: &str, roles: &Roles| nick.eq_ignore_ascii_case(&roles.nick);
+        match event {
+            Event::Registered { nick } => roles.nick = nick.clone(),
+            Event::NickChanged { old_nick, new_nick } if is_us(old_nick, &roles) => {
+                roles.nick = new_nick.clone();
+            }
+            Event::Joined { channel, nick, .. } if is_us(nick, &roles) => {
+                roles
+                    .channels
+                    .insert(channel.to_lowercase(), ChannelRole::Member);
+            }
+            Event::Parted { channel, nick } | Event::Kicked { channel, nick, .. }
+                if is_us(nick, &roles) =>
+            {
+                roles.channels.remove(&channel.to_lowercase());
+            }
+            Event::Names { channel, nicks } => {
+                let ours = nicks.iter().find_map(|n| {
+                    let bare = n.trim_start_matches(['@', '%', '+', '~', '&']);
+                    is_us(bare, &roles).then(|| prefix_role(&n[..n.len() - bare.len()]))
+                });
+                if let Some(role) = ours {
+                    roles.channels.insert(channel.to_lowercase(), role);
+                }
+            }
+            Event::ModeChanged {
+                channel, mode, arg, ..
+            } if arg.as_deref().is_some_and(|a| is_us(a, &roles)) => {
+                let key = channel.to_lowercase();
+                let Some(role) = roles.channels.get(&key).copied() else {
+                    return;
+                };
+                let role = match mode.as_str() {
+                    "+o" => ChannelRole::Op,
+                    "+v" if role < ChannelRole::Voice => ChannelRole::Voice,
+                    "-o" | "-v" => ChannelRole::Member,
+                    _ => role,
+                };
+                roles.channels.insert(key, role);
+            }
+            _ => {}
+        }
+    }
+
+    /// The bot's role in `channel`, or `None` when it isn't in it.
+    pub fn role(&self, channel: &str) -> Option<ChannelRole> {
+        self.inner
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .channels
+            .get(&channel.to_lowercase())
+            .copied()
+    }
+
+    /// Wait up to `timeout` for the bot to reach at least `role` in
+    /// `channel` — a JOIN's NAMES reply lands a moment after the command.
+    async fn wait_for(&self, channel: &str, role: ChannelRole, timeout: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            if self.role(channel).is_some_and(|r| r >= role) {
+                return true;
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+}
+
+/// Role for a NAMES prefix (the highest one, with multi-prefix).
+fn prefix_role(prefix: &str) -> ChannelRole {
+    if prefix.contains(['@', '~', '&', '%']) {
+        ChannelRole::Op
+    } else if prefix.contains('+') {
+        ChannelRole::Voice
+    } else {
+        ChannelRole::Member
+    }
+}
+
+/// The role a tool needs in its channel. `create_channel` needs none.
+fn required_role(tool: &str) -> Option<ChannelRole> {
+    match tool {
+        "set_topic" | "pin_message" | "set_mode" => Some(ChannelRole::Op),
+        "invite_user" => Some(ChannelRole::Member),
+        _ => None,
+    }
+}
+
+/// Whether `name` is one of the [`admin_tools`].
+pub fn is_admin_tool(name: &str) -> bool {
+    matches!(
+        name,
+        "set_topic" | "pin_message" | "invite_user" | "create_channel" | "set_mode"
+    )
+}
+
+fn channel_arg(input: &Value) -> Result<&str> {
+    let channel = input["channel"].as_str().unwrap_or("");
+    if !channel.starts_with('#')
+        || channel.len() < 2
+        || channel.len() > 64
+        || channel
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || c == ',')
+    {
+        bail!("Invalid channel name: {channel:?}");
+    }
+    Ok(channel)
+}
+
+/// A single IRC parameter: non-empty, no spaces or control characters.
+fn word_arg<'a>(input: &'a Value, field: &str) -> Result<&'a str> {
+    let value = input[field].as_str().unwrap_or("");
+    if value.is_empty()
+        || value.starts_with(':')
+        || value.chars().any(|c| c.is_whitespace() || c.is_control())
+    {
+        bail!("Invalid {field}: {value:?}");
+    }
+    Ok(value)
+}
+
+/// Check the bot may run `tool` in `channel`.
+fn check_role(roles: &ChannelRoles, tool: &str, channel: &str) -> Result<()> {
+    let Some(needed) = required_role(tool) else {
+        return Ok(());
+    };
+    match roles.role(channel) {
+        None => bail!("{tool}: not in {channel}"),
+        Some(role) if role < needed => {
+            bail!("{tool}: needs {needed} in {channel}, the bot is {role}")
+        }
+        Some(_) => Ok(()),
+    }
+}
+
+/// Execute a `freeq_admin` tool call and describe what was done.
+pub async fn execute(
+    handle: &ClientHandle,
+    roles: &ChannelRoles,
+    tool_name: &str,
+    input: &Value,
+) -> Result<String> {
+    let channel = channel_arg(input)?;
+    check_role(roles, tool_name, channel)?;
+    match tool_name {
+        "set_topic" => {
+            let topic: String = input["topic"]
+                .as_str()
+                .unwrap_or("")
+                .chars()
+                .filter(|c| !c.is_control())
+                .collect();
+            handle.topic(channel, &topic).await?;
+            Ok(format!("Set topic of {channel}"))
+        }
+
+        "pin_message" => {
+            let msgid = match (input["msgid"].as_str(), input["text"].as_str()) {
+                (Some(msgid), _) => word_arg(input, "msgid").map(|_| msgid.to_string())?,
+                (None, Some(text)) => {
+                    handle
+                        .send_and_await_echo(channel, text, HashMap::new())
+                        .await?
+                }
+                (None, None) => bail!("pin_message needs msgid or text"),
+            };
+            handle.pin(channel, &msgid).await?;
+            Ok(format!("Pinned {msgid} in {channel}"))
+        }
+
+        "invite_user" => {
+            let nick = word_arg(input, "nick")?;
+            handle.raw(&format!("INVITE {nick} {channel}")).await?;
+            Ok(format!("Invited {nick} to {channel}"))
+        }
+
+        "create_channel" => {
+            if roles.role(channel).is_some() {
+                bail!("Already in {channel}");
+            }
+            handle.join(channel).await?;
+            if !roles
+                .wait_for(channel, ChannelRole::Member, Duration::from_secs(5))
+                .await
+            {
+                bail!("Joining {channel} timed out");
+            }
+            // NAMES arrives right after the JOIN; give it a moment to land.
+            roles
+                .wait_for(channel, ChannelRole::Op, Duration::from_secs(1))
+                .await;
+            match roles.role(channel) {
+                Some(ChannelRole::Op) => Ok(format!("Created {channel} (bot is op)")),
+                _ => Ok(format!(
+                    "Joined {channel}, which already existed (bot is not op)"
+                )),
+            }
+        }
+
+        "set_mode" => {
+            let mode = word_arg(input, "mode")?;
+            if !mode.starts_with(['+', '-'])
+                || mode.len() < 2
+                || !mode[1..].chars().all(|c| c.is_ascii_alphabetic())
+            {
+                bail!("Invalid mode: {mode:?}");
+            }
+            let arg = match input["arg"].as_str() {
+                Some(_) => Some(word_arg(input, "arg")?),
+                None => None,
+            };
+            handle.mode(channel, mode, arg).await?;
+            Ok(format!("Set {mode} on {channel}"))
+        }
+
+        _ => bail!("Unknown tool: {tool_name}"),
+    }
+}
+
+/// Tool definitions for managing freeq channels.
+pub fn admin_tools() -> Vec<ToolDef> {
+    vec![
+        ToolDef {
+            name: "create_channel".to_string(),
+            description: "Create (join) a freeq channel. A new channel makes the bot its operator."
+                .to_string(),
+            input_schema: json!({
+                "type": "object",
+                "required": ["channel"],
+                "properties": {
+                    "channel": {
+                        "type": "string",
+                        "description": "Channel name, starting with # (e.g. '#my-project')"
+                    }
+                }
+            }),
+        },
+        ToolDef {
+            name: "set_topic".to_string(),
+            description: "Set a channel's topic. Needs channel operator.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "required": ["channel", "topic"],
+                "properties": {
+                    "channel": { "type": "string", "description": "Channel name" },
+                    "topic": { "type": "string", "description": "New topic" }
+                }
+            }),
+        },
+        ToolDef {
+            name: "pin_message".to_string(),
+            description: "Pin a message in a channel: an existing one by msgid, or post `text` and pin it. Needs channel operator.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "required": ["channel"],
+                "properties": {
+                    "channel": { "type": "string", "description": "Channel name" },
+                    "msgid": { "type": "string", "description": "ID of the message to pin" },
+                    "text": { "type": "string", "description": "Message to post and pin (when no msgid)" }
+                }
+            }),
+        },
+        ToolDef {
+            name: "invite_user".to_string(),
+            description: "Invite a user to a channel the bot is in.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "required": ["channel", "nick"],
+                "properties": {
+                    "channel": { "type": "string", "description": "Channel name" },
+                    "nick": { "type": "string", "description": "Nick to invite" }
+                }
+            }),
+        },
+        ToolDef {
+            name: "set_mode".to_string(),
+            description: "Set a channel mode, e.g. +t, +i, or +o with a nick. Needs channel operator.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "required": ["channel", "mode"],
+                "properties": {
+                    "channel": { "type": "string", "description": "Channel name" },
+                    "mode": { "type": "string", "description": "Mode change, e.g. '+t' or '-i'" },
+                    "arg": { "type": "string", "description": "Mode argument, e.g. a nick for +o" }
+                }
+            }),
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn joined(roles: &ChannelRoles, channel: &str, names: &[&str]) {
+        roles.handle_event(&Event::Joined {
+            channel: channel.into(),
+            nick: "factory".into(),
+            account: None,
+        });
+        roles.handle_event(&Event::Names {
+            channel: channel.into(),
+            nicks: names.iter().map(|n| n.to_string()).collect(),
+        });
+    }
+
+    #[test]
+    fn roles_follow_join_names_and_mode() {
+        let roles = ChannelRoles::new("factory");
+        joined(&roles, "#Proj", &["@factory", "alice"]);
+        assert_eq!(roles.role("#proj"), Some(ChannelRole::Op));
+
+        joined(&roles, "#lobby", &["@alice", "+factory"]);
+        assert_eq!(roles.role("#lobby"), Some(ChannelRole::Voice));
+        roles.handle_event(&Event::ModeChanged {
+            channel: "#lobby".into(),
+            mode: "+o".into(),
+            arg: Some("Factory".into()),
+            set_by: "alice".into(),
+        });
+        assert_eq!(roles.role("#lobby"), Some(ChannelRole::Op));
+        roles.handle_event(&Event::ModeChanged {
+            channel: "#lobby".into(),
+            mode: "-o".into(),
+            arg: Some("factory".into()),
+            set_by: "alice".into(),
+        });
+        assert_eq!(roles.role("#lobby"), Some(ChannelRole::Member));
+
+        roles.handle_event(&Event::Kicked {
+            channel: "#lobby".into(),
+            nick: "factory".into(),
+            by: "alice".into(),
+            reason: String::new(),
+        });
+        assert_eq!(roles.role("#lobby"), None);
+    }
+
+    #[test]
+    fn roles_follow_our_nick_changes() {
+        let roles = ChannelRoles::new("factory");
+        roles.handle_event(&Event::NickChanged {
+            old_nick: "factory".into(),
+            new_nick: "factory2".into(),
+        });
+        joined(&roles, "#x", &["factory"]);
+        assert_eq!(roles.role("#x"), None, "JOIN by the old nick");
+        roles.handle_event(&Event::Joined {
+            channel: "#x".into(),
+            nick: "factory2".into(),
+            account: None,
+        });
+        assert_eq!(roles.role("#x"), Some(ChannelRole::Member));
+    }
+
+    #[test]
+    fn tools_are_gated_on_the_bots_role() {
+        let roles = ChannelRoles::new("factory");
+        joined(&roles, "#lobby", &["factory"]);
+        assert!(check_role(&roles, "invite_user", "#lobby").is_ok());
+        let err = check_role(&roles, "set_topic", "#lobby").unwrap_err();
+        assert!(err.to_string().contains("needs op"), "{err}");
+        assert!(check_role(&roles, "pin_message", "#elsewhere").is_err());
+        assert!(check_role(&roles, "create_channel", "#new").is_ok());
+    }
+
+    #[test]
+    fn arguments_cannot_smuggle_irc_commands() {
+        assert!(channel_arg(&json!({"channel": "#ok-chan"})).is_ok());
+        assert!(channel_arg(&json!({"channel": "nochan"})).is_err());
+        assert!(channel_arg(&json!({"channel": "#a,#b"})).is_err());
+        assert!(channel_arg(&json!({"channel": "#a\r\nQUIT"})).is_err());
+        assert!(word_arg(&json!({"nick": "bob"}), "nick").is_ok());
+        assert!(word_arg(&json!({"nick": "bob #other"}), "nick").is_err());
+        assert!(word_arg(&json!({"nick": ":x"}), "nick").is_err());
+    }
+
+    #[test]
+    fn every_admin_tool_is_recognized() {
+        for tool in admin_tools() {
+            assert!(is_admin_tool(&tool.name), "{}", tool.name);
+        }
+        assert!(!is_admin_tool("shell"));
+    }
+}
@@ -0,0 +1,414 @@
+//! Channel knowledge base — answered questions, kept and reused.
+//!
+//! The bot watches each channel for questions (messages ending in `?`) and
+//! the messages after them. An answer counts as accepted when the asker
+//! reacts to it with one of [`ACCEPT_EMOJI`], or [`ACCEPT_VOTES`] other
+//! people do. The question and accepted answer are then stored in
+//! [`Memory`] (project = channel, kind [`KIND`]) with an embedding of the
+//! question, and a later question close enough to it is answered straight
+//! away, citing the original message.
+//!
+//! Embeddings are computed locally — feature-hashed word counts, compared
+//! by cosine similarity — so repeats are found without an embedding API.
+//! They match rewordings that share the important words, not paraphrases.
+//!
+//! `/kb search <terms>` lists stored answers; `/kb forget <id>` drops one.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::memory::Memory;
+
+/// Memory kind the Q/A pairs are stored under.
+pub const KIND: &str = "kb";
+/// Reactions that accept an answer.
+pub const ACCEPT_EMOJI: &[&str] = &["✅", "✔️", "☑️", "👍", "💯", "🙏"];
+/// Accepting reactions from people other than the asker that also accept
+/// an answer.
+pub const ACCEPT_VOTES: usize = 2;
+/// How long after a question a message can still be its answer.
+pub const ANSWER_WINDOW: Duration = Duration::from_secs(30 * 60);
+/// Similarity at which a new question is taken as a repeat.
+pub const MATCH_THRESHOLD: f32 = 0.8;
+/// Messages remembered per channel while waiting for reactions.
+const RECENT_MESSAGES: usize = 200;
+/// Embedding dimensions.
+const DIMENSIONS: usize = 256;
+
+/// Words too common to tell questions apart.
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "any", "anyone", "are", "at", "be", "can", "could", "do", "does", "for",
+    "from", "get", "has", "have", "hi", "how", "i", "if", "in", "is", "it", "know", "me", "my",
+    "of", "on", "one", "or", "please", "should", "so", "some", "that", "the", "there", "this",
+    "to", "way", "we", "what", "when", "where", "which", "who", "why", "with", "would", "you",
+];
+
+/// A question with its accepted answer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Pair {
+    pub question: String,
+    pub question_msgid: String,
+    pub asker: String,
+    pub answer: String,
+    pub answer_msgid: String,
+    pub answerer: String,
+    /// Embedding of the question (see [`embed`]).
+    #[serde(default)]
+    pub embedding: Vec<f32>,
+}
+
+/// A pair as stored, with its memory id.
+#[derive(Debug, Clone)]
+pub struct Stored {
+    pub id: i64,
+    pub pair: Pair,
+}
+
+#[derive(Debug)]
+struct Recent {
+    msgid: String,
+    nick: String,
+    text: String,
+    reply_to: Option<String>,
+    at: Instant,
+    /// Lowercased nicks who reacted to it with an accepting emoji.
+    votes: HashSet<String>,
+}
+
+/// Recent messages per channel, for matching reactions to answers.
+#[derive(Debug, Default)]
+pub struct Tracker {
+    /// Lowercased channel → its recent messages, oldest first.
+    recent: HashMap<String, VecDeque<Recent>>,
+    /// Answer msgids already stored.
+    accepted: HashSet<String>,
+}
+
+impl Tracker {
+    /// Remember a channel message (`tags` carry its `msgid` and `+reply`).
+    pub fn message(
+        &mut self,
+        channel: &str,
+        nick: &str,
+        text: &str,
+        tags: &HashMap<String, String>,
+        now: Instant,
+    ) {
+        let Some(msgid) = tags.get("msgid") else {
+            return;
+        };
+        let recent = self.recent.entry(channel.to_lowercase()).or_default();
+        recent.push_back(Recent {
+            msgid: msgid.clone(),
+            nick: nick.to_string(),
+            text: text.to_string(),
+            reply_to: tags.get("+reply").cloned(),
+            at: now,
+            votes: HashSet::new(),
+        });
+        while recent.len() > RECENT_MESSAGES
+            || recent
+                .front()
+                .is_some_and(|m| now.duration_since(m.at) > ANSWER_WINDOW * 2)
+        {
+            recent.pop_front();
+        }
+    }
+
+    /// A TAGMSG from `nick` in `channel`: if it's an accepting reaction
+    /// that settles an answer, return the Q/A pair to store.
+    pub fn reaction(
+        &mut self,
+        channel: &str,
+        nick: &str,
+        tags: &HashMap<String, String>,
+    ) -> Option<Pair> {
+        let (Some(emoji), Some(target)) = (tags.get("+react"), tags.get("+reply")) else {
+            return None;
+        };
+        if !ACCEPT_EMOJI.contains(&emoji.as_str()) || self.accepted.contains(target) {
+            return None;
+        }
+        let recent = self.recent.get_mut(&channel.to_lowercase())?;
+        let pos = recent.iter().position(|m| &m.msgid == target)?;
+        let question = question_for(recent, pos)?;
+        let (asker, question_msgid, question_text) = {
+            let q = &recent[question];
+            (q.nick.clone(), q.msgid.clone(), q.text.clone())
+        };
+        let answer = &mut recent[pos];
+        if nick.eq_ignore_ascii_case(&answer.nick) {
+            return None;
+        }
+        let accepted = nick.eq_ignore_ascii_case(&asker) || {
+            answer.votes.insert(nick.to_lowercase());
+            answer.votes.len() >= ACCEPT_VOTES
+        };
+        if !accepted {
+            return None;
+        }
+        self.accepted.insert(answer.msgid.clone());
+        Some(Pair {
+            embedding: embed(&question_text),
+            question: question_text,
+            question_msgid,
+            asker,
+            answer: answer.text.clone(),
+            answer_msgid: answer.msgid.clone(),
+            answerer: answer.nick.clone(),
+        })
+    }
+}
+
+/// The question the message at `pos` answers: the one it replies to, or
+/// else the latest question before it from someone else, within
+/// [`ANSWER_WINDOW`].
+fn question_for(recent: &VecDeque<Recent>, pos: usize) -> Option<usize> {
+    let answer = &recent[pos];
+    if let Some(parent) = &answer.reply_to {
+        return recent
+            .iter()
+            .position(|m| &m.msgid == parent)
+            .filter(|&q| is_question(&recent[q].text));
+    }
+    (0..pos).rev().find(|&q| {
+        let m = &recent[q];
+        is_question(&m.text)
+            && !m.nick.eq_ignore_ascii_case(&answer.nick)
+            && answer.at.duration_since(m.at) <= ANSWER_WINDOW
+    })
+}
+
+/// Whether `text` asks something.
+pub fn is_question(text: &str) -> bool {
+    text.trim_end().ends_with('?') && !words(text).is_empty()
+}
+
+/// Lowercased content words of `text`, crudely stemmed.
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .filter(|w| !w.is_empty() && !STOPWORDS.contains(&w.as_str()))
+        .map(|w| match w.strip_suffix('s') {
+            Some(stem) if stem.len() > 2 && !stem.ends_with('s') => stem.to_string(),
+            _ => w,
+        })
+        .collect()
+}
+
+/// Embed `text`: word counts hashed into [`DIMENSIONS`] buckets,
+/// normalised to unit length.
+pub fn embed(text: &str) -> Vec<f32> {
+    let mut v = vec![0.0f32; DIMENSIONS];
+    for word in words(text) {
+        // FNV-1a, so stored embeddings stay valid across builds.
+        let hash = word.bytes().fold(0xcbf2_9ce4_8422_2325u64, |h, b| {
+            (h ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+        });
+        let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+        v[(hash % DIMENSIONS as u64) as usize] += sign;
+    }
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
+    v
+}
+
+/// Cosine similarity of two unit vectors (0 if their sizes differ).
+pub fn similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// Store `pair` for `channel`, keyed by its answer.
+pub fn store(memory: &Memory, channel: &str, pair: &Pair) -> Result<()> {
+    let project = channel.to_lowercase();
+    memory.set(
+        &project,
+        KIND,
+        &pair.answer_msgid,
+        &serde_json::to_string(pair)?,
+    )
+}
+
+/// Every pair stored for `channel`, oldest first.
+pub fn list(memory: &Memory, channel: &str) -> Result<Vec<Stored>> {
+    Ok(memory
+        .list(&channel.to_lowercase(), KIND)?
+        .into_iter()
+        .filter_map(|e| {
+            let mut pair: Pair = serde_json::from_str(&e.value).ok()?;
+            if pair.embedding.len() != DIMENSIONS {
+                pair.embedding = embed(&pair.question);
+            }
+            Some(Stored { id: e.id, pair })
+        })
+        .collect())
+}
+
+/// Stored pairs for `channel` ranked by how well their questions match
+/// `text`, best first, leaving out those that don't match at all.
+pub fn search(memory: &Memory, channel: &str, text: &str) -> Result<Vec<(Stored, f32)>> {
+    let query = embed(text);
+    let mut hits: Vec<(Stored, f32)> = list(memory, channel)?
+        .into_iter()
+        .map(|s| {
+            let score = similarity(&query, &s.pair.embedding);
+            (s, score)
+        })
+        .filter(|(_, score)| *score > 0.0)
+        .collect();
+    hits.sort_by(|a, b| b.1.total_cmp(&a.1));
+    Ok(hits)
+}
+
+/// The stored pair whose question `text` repeats, if any.
+pub fn find_repeat(memory: &Memory, channel: &str, text: &str) -> Result<Option<Stored>> {
+    if !is_question(text) {
+        return Ok(None);
+    }
+    Ok(search(memory, channel, text)?
+        .into_iter()
+        .next()
+        .filter(|(_, score)| *score >= MATCH_THRESHOLD)
+        .map(|(s, _)| s))
+}
+
+/// Drop pair `id` from `channel`. Returns whether it was there.
+pub fn forget(memory: &Memory, channel: &str, id: i64) -> Result<bool> {
+    let project = channel.to_lowercase();
+    let Some(entry) = memory
+        .list(&project, KIND)?
+        .into_iter()
+        .find(|e| e.id == id)
+    else {
+        return Ok(false);
+    };
+    memory.delete(&project, KIND, &entry.key)?;
+    Ok(true)
+}
+
+/// The auto-reply to `from`, citing where `stored` was answered.
+pub fn citation(from: &str, stored: &Stored) -> String {
+    let pair = &stored.pair;
+    format!(
+        "{from}: {} answered this before (kb #{}, msg {}): {}",
+        pair.answerer, stored.id, pair.answer_msgid, pair.answer
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn react(emoji: &str, msgid: &str) -> HashMap<String, String> {
+        tags(&[("+react", emoji), ("+reply", msgid)])
+    }
+
+    #[test]
+    fn asker_or_enough_votes_accept_an_answer() {
+        let mut t = Tracker::default();
+        let now = Instant::now();
+        t.message(
+            "#rust",
+            "alice",
+            "How do I pin a crate version?",
+            &tags(&[("msgid", "q1")]),
+            now,
+        );
+        t.message(
+            "#rust",
+            "bob",
+            "Use = in Cargo.toml, like serde = \"=1.0.1\"",
+            &tags(&[("msgid", "a1")]),
+            now,
+        );
+        t.message("#rust", "carol", "nice", &tags(&[("msgid", "x")]), now);
+
+        // The answerer can't accept their own answer, nor can other emoji.
+        assert_eq!(t.reaction("#rust", "bob", &react("✅", "a1")), None);
+        assert_eq!(t.reaction("#rust", "alice", &react("😂", "a1")), None);
+        // One vote from someone else isn't enough; the asker's is.
+        assert_eq!(t.reaction("#rust", "carol", &react("👍", "a1")), None);
+        let pair = t.reaction("#RUST", "alice", &react("✅", "a1")).unwrap();
+        assert_eq!(pair.question_msgid, "q1");
+        assert_eq!(pair.answerer, "bob");
+        assert_eq!(pair.embedding.len(), DIMENSIONS);
+        // Stored once.
+        assert_eq!(t.reaction("#rust", "dave", &react("👍", "a1")), None);
+
+        // Two other people accept a threaded reply.
+        t.message(
+            "#rust",
+            "erin",
+            "Is 1.80 out?",
+            &tags(&[("msgid", "q2")]),
+            now,
+        );
+        t.message("#rust", "frank", "what?", &tags(&[("msgid", "q3")]), now);
+        t.message(
+            "#rust",
+            "gina",
+            "yes, since July",
+            &tags(&[("msgid", "a2"), ("+reply", "q2")]),
+            now,
+        );
+        assert_eq!(t.reaction("#rust", "hal", &react("👍", "a2")), None);
+        let pair = t.reaction("#rust", "ivy", &react("💯", "a2")).unwrap();
+        assert_eq!(pair.question, "Is 1.80 out?");
+        // Not an answer to anything.
+        assert_eq!(t.reaction("#rust", "alice", &react("✅", "q1")), None);
+    }
+
+    #[test]
+    fn repeats_are_found_and_can_be_forgotten() {
+        let memory = Memory::in_memory().unwrap();
+        let question = "How do I pin a crate version?";
+        let pair = Pair {
+            question: question.to_string(),
+            question_msgid: "q1".into(),
+            asker: "alice".into(),
+            answer: "Use = in Cargo.toml".into(),
+            answer_msgid: "a1".into(),
+            answerer: "bob".into(),
+            embedding: embed(question),
+        };
+        store(&memory, "#Rust", &pair).unwrap();
+
+        let hit = find_repeat(&memory, "#rust", "how can I pin crate versions?")
+            .unwrap()
+            .unwrap();
+        assert_eq!(hit.pair, pair);
+        assert!(citation("dave", &hit).contains("bob answered this before"));
+        assert!(
+            find_repeat(&memory, "#rust", "How do I pin a crate version")
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            find_repeat(&memory, "#rust", "How do I unpin a tab in firefox?")
+                .unwrap()
+                .is_none()
+        );
+        assert!(find_repeat(&memory, "#go", question).unwrap().is_none());
+
+        let hits = search(&memory, "#rust", "crate").unwrap();
+        assert_eq!(hits.len(), 1);
+        assert!(!forget(&memory, "#rust", hit.id + 1).unwrap());
+        assert!(forget(&memory, "#rust", hit.id).unwrap());
+        assert!(list(&memory, "#rust").unwrap().is_empty());
+    }
+}
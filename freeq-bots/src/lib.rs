@@ -4,12 +4,38 @@
 //! - Software Factory: multi-agent development team
 //! - Architecture Auditor: repo analysis and recommendations
 //! - Spec-to-Prototype: idea → deployed app in minutes
+//! - Channel relay to classic IRC networks ([`relay`])
+//! - Channel management tools for agents ([`freeq_admin`])
+//! - Natural-language mentions of the bot ([`mention`])
+//! - Per-channel knowledge base of answered questions ([`kb`])
+//! - Who may run the expensive commands ([`operators`])
+//! - Read-only browsing of generated projects over HTTP ([`artifacts`])
+//! - Headless scoring of the pipelines against a corpus ([`eval`])
+//! - Pluggable output: IRC, stdout or a JSON log ([`sink`])
+//! - Mermaid diagrams rendered and attached as images ([`diagram`])
+//! - Adapting to what the server supports ([`features`])
+//! - Clarifying questions and an approved spec before a prototype ([`refine`])
+//! - Commit and CI summaries for watched repositories ([`watch`])
+//! - Weekly channel digests, pinned and optionally archived ([`digest`])
 
+pub mod artifacts;
 pub mod auditor;
 pub mod context;
+pub mod digest;
+pub mod diagram;
+pub mod eval;
 pub mod factory;
+pub mod features;
+pub mod freeq_admin;
+pub mod kb;
 pub mod llm;
 pub mod memory;
+pub mod mention;
+pub mod operators;
 pub mod output;
 pub mod prototype;
+pub mod refine;
+pub mod relay;
+pub mod sink;
 pub mod tools;
+pub mod watch;
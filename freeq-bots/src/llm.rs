@@ -2,12 +2,198 @@
 //!
 //! Provides structured LLM interaction for all agent roles.
 //! Each agent gets a system prompt and optional tool definitions.
+//!
+//! Requests are routed by [`Task`]: classification, summaries and small
+//! talk go to a cheap model when one is configured, code generation,
+//! reviews and audits to the strong one (see [`Routes`]). A request that
+//! hits a provider error (overload, rate limit, outage, unknown model) is
+//! retried once on the route's fallback model. Each route keeps
+//! [`RouteStats`].
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
 use futures::StreamExt;
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc;
 
+/// The default strong model.
+pub const DEFAULT_MODEL: &str = "claude-sonnet-4-20250514";
+
+/// What a request is for, which decides the model that serves it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Task {
+    /// Conversational replies.
+    Chat,
+    /// Intent classification.
+    Classify,
+    /// Conversation summaries and fact extraction.
+    Summarize,
+    /// Specs and designs.
+    Plan,
+    /// Code generation.
+    Build,
+    /// Code review.
+    Review,
+    /// Repository audits.
+    Audit,
+}
+
+impl Task {
+    pub const ALL: [Task; 7] = [
+        Task::Chat,
+        Task::Classify,
+        Task::Summarize,
+        Task::Plan,
+        Task::Build,
+        Task::Review,
+        Task::Audit,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Task::Chat => "chat",
+            Task::Classify => "classify",
+            Task::Summarize => "summarize",
+            Task::Plan => "plan",
+            Task::Build => "build",
+            Task::Review => "review",
+            Task::Audit => "audit",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Task::ALL
+            .into_iter()
+            .find(|t| t.as_str().eq_ignore_ascii_case(s))
+    }
+
+    /// Whether the cheap model is good enough by default.
+    pub fn is_cheap(self) -> bool {
+        matches!(self, Task::Chat | Task::Classify | Task::Summarize)
+    }
+}
+
+impl fmt::Display for Task {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The model a task goes to, and the one tried if that fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub model: String,
+    pub fallback: Option<String>,
+}
+
+impl fmt::Display for Route {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.fallback {
+            Some(fallback) => write!(f, "{} (fallback {fallback})", self.model),
+            None => f.write_str(&self.model),
+        }
+    }
+}
+
+/// Routing policy: a strong model, an optional cheap one for
+/// [`Task::is_cheap`] tasks, and per-task overrides. Without overrides the
+/// cheap and strong models are each other's fallback.
+#[derive(Debug, Clone)]
+pub struct Routes {
+    strong: String,
+    cheap: Option<String>,
+    overrides: BTreeMap<Task, Route>,
+}
+
+impl Routes {
+    pub fn new(strong: &str, cheap: Option<&str>) -> Self {
+        Self {
+            strong: strong.to_string(),
+            cheap: cheap.map(str::to_string),
+            overrides: BTreeMap::new(),
+        }
+    }
+
+    /// Apply an override written `<task>=<model>[/<fallback>]`.
+    pub fn set(&mut self, spec: &str) -> Result<()> {
+        let (task, models) = spec
+            .split_once('=')
+            .with_context(|| format!("route '{spec}' should be <task>=<model>[/<fallback>]"))?;
+        let task = Task::parse(task.trim()).with_context(|| {
+            let tasks: Vec<&str> = Task::ALL.iter().map(|t| t.as_str()).collect();
+            format!("unknown task '{task}' (one of {})", tasks.join(", "))
+        })?;
+        let (model, fallback) = match models.split_once('/') {
+            Some((model, fallback)) => (model.trim(), Some(fallback.trim().to_string())),
+            None => (models.trim(), None),
+        };
+        anyhow::ensure!(!model.is_empty(), "route '{spec}' names no model");
+        self.overrides.insert(
+            task,
+            Route {
+                model: model.to_string(),
+                fallback: fallback.filter(|f| !f.is_empty()),
+            },
+        );
+        Ok(())
+    }
+
+    /// Where `task` goes.
+    pub fn route(&self, task: Task) -> Route {
+        if let Some(route) = self.overrides.get(&task) {
+            return route.clone();
+        }
+        match &self.cheap {
+            Some(cheap) if task.is_cheap() => Route {
+                model: cheap.clone(),
+                fallback: Some(self.strong.clone()),
+            },
+            cheap => Route {
+                model: self.strong.clone(),
+                fallback: cheap.clone(),
+            },
+        }
+    }
+}
+
+/// Counters for one route.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RouteStats {
+    pub requests: u64,
+    /// Requests that failed on every model tried.
+    pub failures: u64,
+    /// Requests served by the fallback model.
+    pub fallbacks: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Total time to a response (headers, for streams).
+    pub latency: Duration,
+}
+
+impl fmt::Display for RouteStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} requests, {} failed, {} fell back, {} in / {} out tokens",
+            self.requests, self.failures, self.fallbacks, self.input_tokens, self.output_tokens
+        )?;
+        if self.requests > 0 {
+            let avg = self.latency / self.requests as u32;
+            write!(f, ", avg {:.1}s", avg.as_secs_f64())?;
+        }
+        Ok(())
+    }
+}
+
+/// An API failure worth retrying on another model.
+fn is_provider_error(status: reqwest::StatusCode) -> bool {
+    status.is_server_error() || matches!(status.as_u16(), 404 | 408 | 429 | 529)
+}
+
 /// A message in a conversation.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
@@ -103,9 +289,16 @@ pub struct Usage {
 }
 
 /// Claude API client.
+///
+/// Clones share routes and stats. A client serves one [`Task`], by
+/// default [`Task::Build`], so an unrouted client always gets the strong
+/// model; use [`LlmClient::for_task`] to pick another.
+#[derive(Clone)]
 pub struct LlmClient {
     api_key: String,
-    model: String,
+    routes: Arc<Routes>,
+    task: Task,
+    stats: Arc<Mutex<BTreeMap<Task, RouteStats>>>,
     http: reqwest::Client,
 }
 
@@ -113,16 +306,111 @@ impl LlmClient {
     pub fn new(api_key: String) -> Self {
         Self {
             api_key,
-            model: "claude-sonnet-4-20250514".to_string(),
+            routes: Arc::new(Routes::new(DEFAULT_MODEL, None)),
+            task: Task::Build,
+            stats: Arc::default(),
             http: reqwest::Client::new(),
         }
     }
 
+    /// Use `model` as the strong model (and the only one, unless routes
+    /// are set).
     pub fn with_model(mut self, model: &str) -> Self {
-        self.model = model.to_string();
+        Arc::make_mut(&mut self.routes).strong = model.to_string();
+        self
+    }
+
+    pub fn with_routes(mut self, routes: Routes) -> Self {
+        self.routes = Arc::new(routes);
         self
     }
 
+    /// This client, serving `task`.
+    pub fn for_task(&self, task: Task) -> Self {
+        Self {
+            task,
+            ..self.clone()
+        }
+    }
+
+    /// Every task's route and stats so far.
+    pub fn stats(&self) -> Vec<(Task, Route, RouteStats)> {
+        let stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        Task::ALL
+            .into_iter()
+            .map(|task| {
+                let counters = stats.get(&task).cloned().unwrap_or_default();
+                (task, self.routes.route(task), counters)
+            })
+            .collect()
+    }
+
+    fn record(&self, update: impl FnOnce(&mut RouteStats)) {
+        let mut stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        update(stats.entry(self.task).or_default());
+    }
+
+    /// POST `body` to the messages API on this task's route, falling back
+    /// once on a provider error. Counts the request, its latency and any
+    /// fallback or failure.
+    async fn send(&self, mut body: serde_json::Value) -> Result<reqwest::Response> {
+        let route = self.routes.route(self.task);
+        let started = Instant::now();
+        let mut result = self.post(&route.model, &mut body).await;
+        let mut fell_back = false;
+        if let (Err((retry, e)), Some(fallback)) = (&result, &route.fallback)
+            && *retry
+        {
+            tracing::warn!(
+                task = %self.task,
+                model = %route.model,
+                %fallback,
+                error = %e,
+                "Model failed, falling back"
+            );
+            result = self.post(fallback, &mut body).await;
+            fell_back = true;
+        }
+        self.record(|s| {
+            s.requests += 1;
+            s.latency += started.elapsed();
+            s.fallbacks += u64::from(fell_back && result.is_ok());
+            s.failures += u64::from(result.is_err());
+        });
+        result.map_err(|(_, e)| e)
+    }
+
+    /// One attempt on `model`. Errors say whether another model might do
+    /// better.
+    async fn post(
+        &self,
+        model: &str,
+        body: &mut serde_json::Value,
+    ) -> std::result::Result<reqwest::Response, (bool, anyhow::Error)> {
+        body["model"] = model.into();
+        let resp = self
+            .http
+            .post("https://api.anthropic.com/v1/messages")
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01")
+            .header("content-type", "application/json")
+            .json(&body)
+            .send()
+            .await
+            .context("Failed to call Claude API")
+            .map_err(|e| (true, e))?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            let body = resp.text().await.unwrap_or_default();
+            return Err((
+                is_provider_error(status),
+                anyhow::anyhow!("Claude API error {status} ({model}): {body}"),
+            ));
+        }
+        Ok(resp)
+    }
+
     /// Send a conversation to Claude and get a response.
     pub async fn chat(
         &self,
@@ -132,7 +420,6 @@ impl LlmClient {
         max_tokens: u32,
     ) -> Result<ApiResponse> {
         let mut body = serde_json::json!({
-            "model": &self.model,
             "max_tokens": max_tokens,
             "system": system,
             "messages": messages,
@@ -143,25 +430,18 @@ impl LlmClient {
         }
 
         let resp = self
-            .http
-            .post("https://api.anthropic.com/v1/messages")
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
-            .header("content-type", "application/json")
-            .json(&body)
-            .send()
+            .send(body)
+            .await?
+            .json::<ApiResponse>()
             .await
-            .context("Failed to call Claude API")?;
-
-        let status = resp.status();
-        if !status.is_success() {
-            let body = resp.text().await.unwrap_or_default();
-            anyhow::bail!("Claude API error {status}: {body}");
+            .context("Failed to parse Claude response")?;
+        if let Some(usage) = &resp.usage {
+            self.record(|s| {
+                s.input_tokens += usage.input_tokens;
+                s.output_tokens += usage.output_tokens;
+            });
         }
-
-        resp.json::<ApiResponse>()
-            .await
-            .context("Failed to parse Claude response")
+        Ok(resp)
     }
 
     /// Simple single-turn text completion (no tools).
@@ -198,7 +478,6 @@ impl LlmClient {
         max_tokens: u32,
     ) -> Result<mpsc::Receiver<StreamDelta>> {
         let mut body = serde_json::json!({
-            "model": &self.model,
             "max_tokens": max_tokens,
             "system": system,
             "messages": messages,
@@ -209,22 +488,7 @@ impl LlmClient {
             body["tools"] = serde_json::to_value(tools)?;
         }
 
-        let resp = self
-            .http
-            .post("https://api.anthropic.com/v1/messages")
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
-            .header("content-type", "application/json")
-            .json(&body)
-            .send()
-            .await
-            .context("Failed to call Claude API")?;
-
-        let status = resp.status();
-        if !status.is_success() {
-            let body = resp.text().await.unwrap_or_default();
-            anyhow::bail!("Claude API error {status}: {body}");
-        }
+        let resp = self.send(body).await?;
 
         let (tx, rx) = mpsc::channel(256);
 
@@ -321,3 +585,83 @@ struct StreamEventDelta {
     #[serde(default)]
     text: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(model: &str, fallback: Option<&str>) -> Route {
+        Route {
+            model: model.to_string(),
+            fallback: fallback.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn one_model_serves_everything_without_a_fallback() {
+        let routes = Routes::new("strong", None);
+        for task in Task::ALL {
+            assert_eq!(routes.route(task), route("strong", None));
+        }
+    }
+
+    #[test]
+    fn chatter_goes_cheap_and_builds_go_strong_falling_back_to_each_other() {
+        let routes = Routes::new("strong", Some("cheap"));
+        for task in [Task::Chat, Task::Classify, Task::Summarize] {
+            assert_eq!(routes.route(task), route("cheap", Some("strong")));
+        }
+        for task in [Task::Plan, Task::Build, Task::Review, Task::Audit] {
+            assert_eq!(routes.route(task), route("strong", Some("cheap")));
+        }
+    }
+
+    #[test]
+    fn overrides_win_per_task() {
+        let mut routes = Routes::new("strong", Some("cheap"));
+        routes.set("plan=cheap").unwrap();
+        routes.set(" Chat = mid / strong ").unwrap();
+        routes.set("audit=big/").unwrap();
+        assert_eq!(routes.route(Task::Plan), route("cheap", None));
+        assert_eq!(routes.route(Task::Chat), route("mid", Some("strong")));
+        assert_eq!(routes.route(Task::Audit), route("big", None));
+        assert_eq!(routes.route(Task::Build), route("strong", Some("cheap")));
+
+        assert!(routes.set("plan").is_err());
+        assert!(routes.set("deploy=cheap").is_err());
+        assert!(routes.set("build=/cheap").is_err());
+    }
+
+    #[test]
+    fn only_provider_errors_fall_back() {
+        for code in [404, 408, 429, 500, 502, 503, 529] {
+            assert!(is_provider_error(code.try_into().unwrap()), "{code}");
+        }
+        for code in [400, 401, 403, 413] {
+            assert!(!is_provider_error(code.try_into().unwrap()), "{code}");
+        }
+    }
+
+    #[test]
+    fn clients_for_different_tasks_share_stats() {
+        let llm = LlmClient::new("key".into()).with_routes(Routes::new("strong", Some("cheap")));
+        let chat = llm.for_task(Task::Chat);
+        chat.record(|s| {
+            s.requests += 1;
+            s.fallbacks += 1;
+        });
+        llm.record(|s| s.failures += 1);
+
+        let stats: BTreeMap<Task, (Route, RouteStats)> = llm
+            .stats()
+            .into_iter()
+            .map(|(task, route, stats)| (task, (route, stats)))
+            .collect();
+        assert_eq!(stats.len(), Task::ALL.len());
+        let (chat_route, chat_stats) = &stats[&Task::Chat];
+        assert_eq!(chat_route.model, "cheap");
+        assert_eq!((chat_stats.requests, chat_stats.fallbacks), (1, 1));
+        assert_eq!(stats[&Task::Build].1.failures, 1);
+        assert_eq!(stats[&Task::Audit].1, RouteStats::default());
+    }
+}
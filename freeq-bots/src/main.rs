@@ -6,22 +6,76 @@
 //!   /factory build <spec>     — Start the software factory
 //!   /factory status           — Check factory status
 //!   /factory pause / resume   — Control the pipeline
+//!   /project create <name>    — Bind the channel to a named project
+//!   /project diff / log       — The last build's changes / the build history
+//!   /project reset [<commit>] — Go back to an earlier build
 //!   /audit <repo-url>         — Architecture audit
+//!   /audit security <repo-url> — Dependency and secret scan + security audit
 //!   /prototype <spec>         — Quick spec-to-deployed-prototype
+//!   /prototype --refine <idea> — Clarifying questions and a spec first
+//!   /approve, /cancel         — Build the refined spec, or drop it
+//!   /verbosity [level]        — Per-channel output verbosity
+//!   /grant <nick|did>         — Make someone a factory operator
+//!   /kb search <terms>        — Search the channel's answered questions
+//!   /kb forget <id>           — Drop a stored answer
+//!   /watch add <repo> [branch] — Post commit and CI summaries for a repo
+//!   /watch list / remove      — The channel's watches / stop watching
+//!   /digest enable [weekly]   — Post a pinned digest of the channel (ops)
+//!   /digest publish on / now  — Also archive digests / post one now (ops)
+//!   /botinfo                  — Bot version and negotiated features
 //!   /help                     — List commands
 //!
+//! Mentioning the bot by nick works too ("factory, build me a todo app"):
+//! the LLM works out what's wanted, and builds are confirmed first.
+//!
+//! Questions that get an accepted answer (a ✅ or 👍 reaction from the
+//! asker) are kept per channel, and repeats of them are answered with a
+//! citation of the original (see `freeq_bots::kb`).
+//!
+//! The bot adapts as the server's capabilities change (see
+//! `freeq_bots::features`), e.g. marking status lines low priority once the
+//! server vets priorities.
+//!
+//! With `--operators-api`, builds, prototypes, audits and project changes
+//! are limited to factory operators (see `freeq_bots::operators`).
+//!
+//! Watched repositories are polled on GitHub; with `--watch-webhook-secret`
+//! the artifacts server also takes GitHub webhooks at `/hooks/github` (see
+//! `freeq_bots::watch`).
+//!
+//! `freeq-bots eval <corpus.toml>` runs the pipelines headless against an
+//! eval corpus instead and prints a scored report (see `freeq_bots::eval`);
+//! `run-prototype`, `run-factory` and `run-audit` run one pipeline and print
+//! the agents' output to stdout.
+//!
 //! Requires ANTHROPIC_API_KEY environment variable.
 
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{Result, bail};
+use clap::{Parser, Subcommand};
 use freeq_sdk::client::{self, ClientHandle, ConnectConfig};
 use freeq_sdk::event::Event;
+use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
 
+use freeq_bots::artifacts::Artifacts;
+use freeq_bots::context::{AgentContext, AgentIdentity, ContextConfig, HistoryMessage};
+use freeq_bots::diagram::{self, Renderer};
+use freeq_bots::digest::{self, Archive, Cadence, Digester};
+use freeq_bots::eval;
 use freeq_bots::factory::{Factory, FactoryConfig};
-use freeq_bots::llm::LlmClient;
+use freeq_bots::features::Features;
+use freeq_bots::freeq_admin::ChannelRoles;
+use freeq_bots::kb;
+use freeq_bots::llm::{LlmClient, Routes};
 use freeq_bots::memory::Memory;
-use freeq_bots::output::{self, AgentId};
+use freeq_bots::mention::{self, Conversations, Intent, Route};
+use freeq_bots::operators::{self, OperatorConfig, Operators};
+use freeq_bots::output::{self, AgentId, Verbosity};
+use freeq_bots::refine::{self, Refinements};
+use freeq_bots::sink::{JsonLogSink, OutputSink, StdoutSink};
+use freeq_bots::watch::{self, GitHub, Watcher};
 
 #[derive(Parser)]
 #[command(name = "freeq-bots", about = "AI agent bots for freeq IRC")]
@@ -50,10 +104,19 @@ struct Args {
     #[arg(long, default_value = "/tmp/freeq-bots/memory.db")]
     memory_db: PathBuf,
 
-    /// Claude model to use
-    #[arg(long, default_value = "claude-sonnet-4-20250514")]
+    /// Claude model to use (the strong model: builds, reviews, audits)
+    #[arg(long, default_value = freeq_bots::llm::DEFAULT_MODEL)]
     model: String,
 
+    /// Cheaper model for classification, summaries and small talk (default: --model)
+    #[arg(long, env = "FREEQ_CHEAP_MODEL")]
+    cheap_model: Option<String>,
+
+    /// Route a task type to a model, <task>=<model>[/<fallback>] (repeatable;
+    /// tasks: chat, classify, summarize, plan, build, review, audit)
+    #[arg(long = "route", env = "FREEQ_ROUTES", value_delimiter = ',')]
+    routes: Vec<String>,
+
     /// Anthropic API key (or set ANTHROPIC_API_KEY env var)
     #[arg(long, env = "ANTHROPIC_API_KEY")]
     api_key: String,
@@ -61,6 +124,155 @@ struct Args {
     /// Command prefix
     #[arg(long, default_value = "/")]
     prefix: String,
+
+    /// Most clarifying questions the product lead asks for `/prototype --refine`
+    #[arg(long, default_value = "3")]
+    refine_questions: usize,
+
+    /// Server web URL for pasting long code and output (e.g. https://irc.freeq.at)
+    #[arg(long, env = "FREEQ_PASTE_URL")]
+    paste_url: Option<String>,
+
+    /// DID to upload pastes as (needs a live session on the server, or --paste-token)
+    #[arg(long, env = "FREEQ_PASTE_DID")]
+    paste_did: Option<String>,
+
+    /// Upload token minted for --paste-did
+    #[arg(long, env = "FREEQ_PASTE_TOKEN")]
+    paste_token: Option<String>,
+
+    /// Headless Mermaid renderer for agent diagrams, e.g. `mmdc` (needs the paste service)
+    #[arg(long, env = "FREEQ_MERMAID_CLI")]
+    mermaid_cli: Option<String>,
+
+    /// Image format for rendered diagrams: svg or png
+    #[arg(long, env = "FREEQ_DIAGRAM_FORMAT", default_value = "svg")]
+    diagram_format: diagram::Format,
+
+    /// Serve generated project files read-only on this address (e.g. 0.0.0.0:8090)
+    #[arg(long, env = "FREEQ_ARTIFACTS_LISTEN")]
+    artifacts_listen: Option<SocketAddr>,
+
+    /// Public base URL of the artifacts browser (default: http://<artifacts-listen>)
+    #[arg(long, env = "FREEQ_ARTIFACTS_URL")]
+    artifacts_url: Option<String>,
+
+    /// Key for signing artifact URLs; random per run if unset, so links expire on restart
+    #[arg(long, env = "FREEQ_ARTIFACTS_SECRET")]
+    artifacts_secret: Option<String>,
+
+    /// Server web URL whose credential API decides who may run builds,
+    /// prototypes and audits (e.g. https://irc.freeq.at); anyone may if unset
+    #[arg(long, env = "FREEQ_OPERATORS_API")]
+    operators_api: Option<String>,
+
+    /// Issuer DID trusted for factory-operator credentials (repeatable;
+    /// default: did:web:<operators-api host>:verify)
+    #[arg(
+        long = "operator-issuer",
+        env = "FREEQ_OPERATOR_ISSUERS",
+        value_delimiter = ','
+    )]
+    operator_issuers: Vec<String>,
+
+    /// DID that is always a factory operator, to appoint the first ones (repeatable)
+    #[arg(long = "operator", env = "FREEQ_OPERATORS", value_delimiter = ',')]
+    operators: Vec<String>,
+
+    /// The server's role issuer secret, so operators can /grant the role
+    #[arg(long, env = "ROLE_ISSUER_SECRET")]
+    role_issuer_secret: Option<String>,
+
+    /// GitHub token for /watch (raises the API rate limit, reaches private repos)
+    #[arg(long, env = "GITHUB_TOKEN")]
+    github_token: Option<String>,
+
+    /// Seconds between polls of watched repositories (0: webhooks only)
+    #[arg(long, default_value = "60")]
+    watch_interval: u64,
+
+    /// Secret for GitHub webhooks at /hooks/github on the artifacts server
+    #[arg(long, env = "FREEQ_WATCH_WEBHOOK_SECRET")]
+    watch_webhook_secret: Option<String>,
+
+    /// Seconds between checks for due channel digests (0: only /digest now)
+    #[arg(long, default_value = "600")]
+    digest_interval: u64,
+
+    /// Public (+A) channel that /digest publish posts digests to, linked
+    /// from the web archive at --paste-url
+    #[arg(long, env = "FREEQ_DIGEST_ARCHIVE_CHANNEL")]
+    digest_archive_channel: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the pipelines headless against an eval corpus and score them
+    Eval(EvalArgs),
+    /// Build and deploy a prototype from a spec, without IRC
+    RunPrototype(SpecArgs),
+    /// Run the full factory pipeline on a spec, without IRC
+    RunFactory(SpecArgs),
+    /// Audit a repository (URL or path), without IRC
+    RunAudit {
+        /// GitHub URL or local path
+        target: String,
+
+        /// Run the security audit (dependency and secret scanners) instead
+        #[arg(long)]
+        security: bool,
+
+        /// Print newline-delimited JSON instead of plain text
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(clap::Args)]
+struct SpecArgs {
+    /// File holding the spec ("-" for stdin)
+    #[arg(long)]
+    spec: PathBuf,
+
+    /// Print newline-delimited JSON instead of plain text
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(clap::Args)]
+struct EvalArgs {
+    /// Corpus file (TOML)
+    corpus: Option<PathBuf>,
+
+    /// Label for this run, e.g. the prompt version under test
+    #[arg(long, default_value = "unlabeled")]
+    label: String,
+
+    /// Only run cases whose name contains this
+    #[arg(long)]
+    filter: Option<String>,
+
+    /// Compare with the latest run with this label (default: the latest run)
+    #[arg(long)]
+    against: Option<String>,
+
+    /// List stored reports instead of running
+    #[arg(long)]
+    history: bool,
+}
+
+impl Args {
+    /// An LLM client with the configured routes.
+    fn llm(&self) -> Result<LlmClient> {
+        let mut routes = Routes::new(&self.model, self.cheap_model.as_deref());
+        for spec in &self.routes {
+            routes.set(spec)?;
+        }
+        Ok(LlmClient::new(self.api_key.clone()).with_routes(routes))
+    }
 }
 
 #[tokio::main]
@@ -70,6 +282,8 @@ async fn main() -> Result<()> {
             tracing_subscriber::EnvFilter::try_from_default_env()
                 .unwrap_or_else(|_| "freeq_bots=info".into()),
         )
+        // Stdout is agent output for the one-shot subcommands.
+        .with_writer(std::io::stderr)
         .init();
 
     let args = Args::parse();
@@ -80,13 +294,62 @@ async fn main() -> Result<()> {
         tokio::fs::create_dir_all(parent).await?;
     }
 
+    match &args.command {
+        Some(Command::Eval(eval_args)) => return run_eval(&args, eval_args).await,
+        Some(command) => return run_one_shot(&args, command).await,
+        None => {}
+    }
+
+    if let (Some(base_url), Some(did)) = (&args.paste_url, &args.paste_did) {
+        output::set_paste_service(Some(output::PasteService {
+            base_url: base_url.clone(),
+            did: did.clone(),
+            upload_token: args.paste_token.clone(),
+        }));
+    }
+    if let Some(command) = &args.mermaid_cli {
+        output::set_diagram_renderer(Some(Renderer {
+            command: command.clone(),
+            format: args.diagram_format,
+        }));
+    }
+
     // Initialize components
-    let llm = LlmClient::new(args.api_key.clone()).with_model(&args.model);
-    let memory = Memory::open(&args.memory_db)?;
-    let factory = Factory::new(FactoryConfig {
+    let llm = args.llm()?;
+    let memory = Arc::new(Memory::open(&args.memory_db)?);
+    let roles = ChannelRoles::new(&args.nick);
+    let operators = args.operators_api.as_ref().map(|api_url| {
+        let mut issuers = args.operator_issuers.clone();
+        if issuers.is_empty() {
+            issuers.extend(OperatorConfig::default_issuer(api_url));
+        }
+        Operators::new(OperatorConfig {
+            api_url: api_url.clone(),
+            issuers,
+            bootstrap: args.operators.clone(),
+            issuer_secret: args.role_issuer_secret.clone(),
+        })
+    });
+    let artifacts = args.artifacts_listen.map(|addr| {
+        let key = match &args.artifacts_secret {
+            Some(secret) => secret.clone().into_bytes(),
+            None => rand::random::<[u8; 32]>().to_vec(),
+        };
+        let url = args
+            .artifacts_url
+            .clone()
+            .unwrap_or_else(|| format!("http://{addr}"));
+        (Artifacts::new(args.workspace.clone(), &url, &key), addr)
+    });
+    let (artifacts, artifacts_addr) = artifacts.unzip();
+    let mut factory = Factory::new(FactoryConfig {
         channel: args.channel.clone(),
         workspace_base: args.workspace.clone(),
-    });
+    })
+    .with_admin(roles.clone());
+    if let Some(ref artifacts) = artifacts {
+        factory = factory.with_artifacts(artifacts.clone());
+    }
 
     tracing::info!(
         server = %args.server,
@@ -103,8 +366,11 @@ async fn main() -> Result<()> {
         realname: "freeq AI factory bot".to_string(),
         tls: args.tls,
         tls_insecure: false,
+        tls_options: Default::default(),
         web_token: None,
         websocket_url: None,
+        nick_fallback: Default::default(),
+        capabilities: Default::default(),
     })
     .await?;
 
@@ -115,22 +381,89 @@ async fn main() -> Result<()> {
         realname: "freeq AI factory bot".to_string(),
         tls: args.tls,
         tls_insecure: false,
+        tls_options: Default::default(),
         web_token: None,
         websocket_url: None,
+        nick_fallback: Default::default(),
+        capabilities: Default::default(),
     };
 
     let (handle, mut events) = client::connect_with_stream(conn, config, None);
 
+    let watcher = Watcher::new(
+        Arc::clone(&memory),
+        GitHub::new(args.github_token.clone()),
+        llm.clone(),
+        Arc::new(handle.clone()),
+    );
+    if args.watch_interval > 0 {
+        let interval = std::time::Duration::from_secs(args.watch_interval);
+        tokio::spawn(watcher.clone().run(interval));
+    }
+    if let (Some(server), Some(addr)) = (artifacts.clone(), artifacts_addr) {
+        let hooks = match &args.watch_webhook_secret {
+            Some(secret) => watch::router(watcher.clone(), secret),
+            None => axum::Router::new(),
+        };
+        tokio::spawn(async move {
+            if let Err(e) = server.serve_with(addr, hooks).await {
+                tracing::error!(error = %e, "Artifacts browser failed");
+            }
+        });
+    } else if args.watch_webhook_secret.is_some() {
+        tracing::warn!("--watch-webhook-secret needs --artifacts-listen; polling only");
+    }
+
+    let digester = Digester::new(
+        handle.clone(),
+        &args.nick,
+        Arc::clone(&memory),
+        llm.clone(),
+        roles.clone(),
+        args.digest_archive_channel.clone().map(|channel| Archive {
+            channel,
+            web_url: args.paste_url.clone(),
+        }),
+    );
+    if args.digest_interval > 0 {
+        let interval = std::time::Duration::from_secs(args.digest_interval);
+        tokio::spawn(digester.clone().run(interval));
+    }
+
     // Join channel after registration
     let channel = args.channel.clone();
+    let archive_channel = args.digest_archive_channel.clone();
     let h2 = handle.clone();
     tokio::spawn(async move {
         tokio::time::sleep(std::time::Duration::from_secs(2)).await;
         let _ = h2.join(&channel).await;
         tracing::info!("Joined {channel}");
+        if let Some(archive) = archive_channel {
+            let _ = h2.join(&archive).await;
+        }
     });
 
     let bot_nick = args.nick.clone();
+    // A short rolling window per channel, so mentions and follow-ups are
+    // understood in context.
+    let context = AgentContext::new(
+        AgentIdentity {
+            nick: bot_nick.clone(),
+            did: None,
+            role: "AI software factory: builds, audits and deploys software on request".to_string(),
+            channels: vec![args.channel.clone()],
+            system_prompt: None,
+        },
+        Arc::clone(&memory),
+        ContextConfig {
+            recent_window_size: 20,
+            ..Default::default()
+        },
+    );
+    let mut conversations = Conversations::default();
+    let mut refinements = Refinements::default();
+    let mut answers = kb::Tracker::default();
+    let mut features = Features::default();
 
     tracing::info!("Bot running. Ctrl+C to stop.");
 
@@ -138,8 +471,30 @@ async fn main() -> Result<()> {
     loop {
         match events.recv().await {
             Some(event) => {
-                if let Err(e) =
-                    handle_event(&handle, &bot_nick, &args, &event, &llm, &memory, &factory).await
+                features.observe(&event);
+                features.adapt(&handle);
+                roles.handle_event(&event);
+                if let Some(ops) = &operators {
+                    ops.handle_event(&event);
+                }
+                if let Err(e) = handle_event(
+                    &handle,
+                    &bot_nick,
+                    &args,
+                    &event,
+                    &llm,
+                    &memory,
+                    &factory,
+                    operators.as_ref(),
+                    &watcher,
+                    &digester,
+                    &context,
+                    &mut conversations,
+                    &mut refinements,
+                    &mut answers,
+                    &features,
+                )
+                .await
                 {
                     tracing::error!(error = %e, "Event handler error");
                 }
@@ -157,7 +512,7 @@ async fn main() -> Result<()> {
 fn system_agent() -> AgentId {
     AgentId {
         role: "system".to_string(),
-        color: None,
+        color: Some(output::color::GREY),
     }
 }
 
@@ -169,10 +524,22 @@ async fn handle_event(
     llm: &LlmClient,
     memory: &Memory,
     factory: &Factory,
+    operators: Option<&Operators>,
+    watcher: &Watcher,
+    digester: &Digester,
+    context: &AgentContext,
+    conversations: &mut Conversations,
+    refinements: &mut Refinements,
+    answers: &mut kb::Tracker,
+    features: &Features,
 ) -> Result<()> {
     match event {
         Event::Connected => tracing::info!("Connected"),
-        Event::Registered { nick } => tracing::info!("Registered as {nick}"),
+        Event::Registered { nick } => {
+            tracing::info!("Registered as {nick}");
+            handle.register_agent("agent").await?;
+        }
+        Event::Authenticated { did } => tracing::info!("Authenticated as {did}"),
 
         Event::Joined { channel, nick, .. } if nick == bot_nick => {
             output::status(handle, channel, &system_agent(), "🤖",
@@ -180,11 +547,14 @@ async fn handle_event(
                 ).await?;
         }
 
+        Event::Joined { channel, nick, .. } => digester.joined(channel, nick)?,
+
         Event::Message {
             from,
             target,
             text,
             tags,
+            ..
         } => {
             if tags.contains_key("batch") {
                 return Ok(());
@@ -201,134 +571,791 @@ async fn handle_event(
 
             let channel = target;
 
+            context
+                .record_message(
+                    channel,
+                    HistoryMessage {
+                        nick: from.clone(),
+                        text: text.clone(),
+                        timestamp: chrono::Utc::now().timestamp() as u64,
+                        msgid: tags.get("msgid").cloned(),
+                        tags: tags.clone(),
+                    },
+                )
+                .await;
+            answers.message(channel, from, text, tags, Instant::now());
+
             // Parse commands
             if let Some(cmd_text) = text.strip_prefix(&args.prefix) {
                 let parts: Vec<&str> = cmd_text.splitn(2, ' ').collect();
                 let cmd = parts[0].to_lowercase();
                 let cmd_args = parts.get(1).unwrap_or(&"").trim();
+                if cmd == "botinfo" {
+                    for line in features.report(handle) {
+                        output::say(handle, channel, &system_agent(), &line).await?;
+                    }
+                    return Ok(());
+                }
+                run_command(
+                    handle,
+                    channel,
+                    from,
+                    &cmd,
+                    cmd_args,
+                    args,
+                    llm,
+                    memory,
+                    factory,
+                    operators,
+                    watcher,
+                    digester,
+                    refinements,
+                )
+                .await?;
+            } else if let Some(step) = refinements.answer(channel, from, text, Instant::now()) {
+                match step {
+                    refine::Step::Ask => {
+                        if let Some(refinement) = refinements.get(channel) {
+                            refine::ask(handle, channel, refinement).await?;
+                        }
+                    }
+                    refine::Step::WriteSpec => {
+                        refine::present(handle, channel, refinements, llm, memory).await?;
+                    }
+                }
+            } else if let Some(stored) = kb::find_repeat(memory, channel, text)? {
+                output::say(
+                    handle,
+                    channel,
+                    &system_agent(),
+                    &kb::citation(from, &stored),
+                )
+                .await?;
+            } else {
+                handle_mention(
+                    handle,
+                    bot_nick,
+                    args,
+                    channel,
+                    from,
+                    text,
+                    llm,
+                    memory,
+                    factory,
+                    operators,
+                    watcher,
+                    digester,
+                    context,
+                    conversations,
+                    refinements,
+                )
+                .await?;
+            }
+        }
+
+        // Reactions: an accepted answer goes into the knowledge base.
+        Event::TagMsg { from, target, tags } if target.starts_with('#') => {
+            if let Some(pair) = answers.reaction(target, from, tags) {
+                kb::store(memory, target, &pair)?;
+                tracing::info!(
+                    channel = %target,
+                    question = %pair.question_msgid,
+                    answer = %pair.answer_msgid,
+                    "Stored answered question"
+                );
+                handle.react(target, "📚", &pair.answer_msgid).await?;
+            }
+        }
+
+        Event::Disconnected { reason } => {
+            tracing::warn!("Disconnected: {reason}");
+        }
+
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Run a prefixed command (without the prefix).
+async fn run_command(
+    handle: &ClientHandle,
+    channel: &str,
+    from: &str,
+    cmd: &str,
+    cmd_args: &str,
+    args: &Args,
+    llm: &LlmClient,
+    memory: &Memory,
+    factory: &Factory,
+    operators: Option<&Operators>,
+    watcher: &Watcher,
+    digester: &Digester,
+    refinements: &mut Refinements,
+) -> Result<()> {
+    if operators::is_gated(cmd, cmd_args) && !may_run(handle, channel, from, cmd, operators).await?
+    {
+        return Ok(());
+    }
+    match cmd {
+        "factory" => {
+            let sub_parts: Vec<&str> = cmd_args.splitn(2, ' ').collect();
+            let sub_cmd = sub_parts.first().unwrap_or(&"status");
+            let sub_args = sub_parts.get(1).unwrap_or(&"");
+            factory
+                .handle_command(handle, channel, from, sub_cmd, sub_args, llm, memory)
+                .await?;
+        }
+
+        "project" => {
+            let (sub_cmd, sub_args) = cmd_args.split_once(' ').unwrap_or((cmd_args, ""));
+            factory
+                .handle_project_command(handle, channel, sub_cmd, sub_args, memory)
+                .await?;
+        }
 
-                match cmd.as_str() {
-                    "factory" => {
-                        let sub_parts: Vec<&str> = cmd_args.splitn(2, ' ').collect();
-                        let sub_cmd = sub_parts.first().unwrap_or(&"status");
-                        let sub_args = sub_parts.get(1).unwrap_or(&"");
-                        factory
-                            .handle_command(handle, channel, from, sub_cmd, sub_args, llm, memory)
-                            .await?;
+        "audit" => {
+            if cmd_args.is_empty() {
+                output::say(
+                    handle,
+                    channel,
+                    &system_agent(),
+                    "Usage: /audit [security] <github-url or repo-path>",
+                )
+                .await?;
+            } else {
+                let h = handle.clone();
+                let ch = channel.to_string();
+                let (security, target) = match cmd_args.split_once(' ') {
+                    Some(("security", rest)) => (true, rest.trim().to_string()),
+                    _ => (false, cmd_args.to_string()),
+                };
+                let llm = llm.clone();
+                let ws = args.workspace.clone();
+                tokio::spawn(async move {
+                    let result = if security {
+                        freeq_bots::auditor::security_audit(&h, &ch, &target, &llm, &ws).await
+                    } else {
+                        freeq_bots::auditor::audit(&h, &ch, &target, &llm, &ws).await
+                    };
+                    if let Err(e) = result {
+                        tracing::error!(error = %e, "Audit failed");
+                        let _ = output::error(
+                            &h,
+                            &ch,
+                            &AgentId {
+                                role: "auditor".to_string(),
+                                color: Some(output::color::BROWN),
+                            },
+                            &format!("Audit failed: {e}"),
+                        )
+                        .await;
                     }
+                });
+            }
+        }
 
-                    "audit" => {
-                        if cmd_args.is_empty() {
-                            output::say(
-                                handle,
-                                channel,
-                                &system_agent(),
-                                "Usage: /audit <github-url or repo-path>",
-                            )
-                            .await?;
-                        } else {
-                            let h = handle.clone();
-                            let ch = channel.to_string();
-                            let target = cmd_args.to_string();
-                            let llm_key = args.api_key.clone();
-                            let model = args.model.clone();
-                            let ws = args.workspace.clone();
-                            tokio::spawn(async move {
-                                let llm = LlmClient::new(llm_key).with_model(&model);
-                                if let Err(e) =
-                                    freeq_bots::auditor::audit(&h, &ch, &target, &llm, &ws).await
-                                {
-                                    tracing::error!(error = %e, "Audit failed");
-                                    let _ = output::error(
-                                        &h,
-                                        &ch,
-                                        &AgentId {
-                                            role: "auditor".to_string(),
-                                            color: None,
-                                        },
-                                        &format!("Audit failed: {e}"),
-                                    )
-                                    .await;
-                                }
-                            });
-                        }
+        "prototype" | "proto" => {
+            if let Some(idea) = cmd_args.strip_prefix("--refine") {
+                let idea = idea.trim();
+                if idea.is_empty() {
+                    output::say(
+                        handle,
+                        channel,
+                        &system_agent(),
+                        "Usage: /prototype --refine <describe what to build>",
+                    )
+                    .await?;
+                    return Ok(());
+                }
+                let questions = refine::questions(llm, idea, args.refine_questions).await?;
+                let refinement = refinements.start(channel, from, idea, questions, Instant::now());
+                if refinement.next_question().is_some() {
+                    refine::ask(handle, channel, refinement).await?;
+                } else {
+                    refine::present(handle, channel, refinements, llm, memory).await?;
+                }
+            } else if cmd_args.is_empty() {
+                output::say(
+                    handle,
+                    channel,
+                    &system_agent(),
+                    "Usage: /prototype [--refine] <describe what to build>",
+                )
+                .await?;
+            } else {
+                spawn_prototype(handle, channel, cmd_args, args, llm, factory);
+            }
+        }
+
+        "approve" => match refinements.approve(channel, from, Instant::now()) {
+            Ok(spec) => spawn_prototype(handle, channel, &spec, args, llm, factory),
+            Err(reply) => output::say(handle, channel, &system_agent(), &reply).await?,
+        },
+
+        "cancel" => {
+            let reply = if refinements.cancel(channel, from) {
+                format!("{from}: OK, dropped the spec.")
+            } else {
+                format!("{from}: you have no spec being refined here.")
+            };
+            output::say(handle, channel, &system_agent(), &reply).await?;
+        }
+
+        "verbosity" => {
+            let text = if cmd_args.is_empty() {
+                format!("Verbosity in {channel}: {}", output::verbosity(channel))
+            } else {
+                match cmd_args.parse::<output::Verbosity>() {
+                    Ok(v) => {
+                        output::set_verbosity(channel, v);
+                        format!("Verbosity in {channel} set to {v}")
+                    }
+                    Err(e) => format!("{e}"),
+                }
+            };
+            output::say(handle, channel, &system_agent(), &text).await?;
+        }
+
+        "grant" => {
+            let text = match (operators, cmd_args.split_whitespace().next()) {
+                (None, _) => {
+                    "Operator checks are off here: anyone can run any command.".to_string()
+                }
+                (Some(_), None) => "Usage: /grant <nick or did>".to_string(),
+                (Some(ops), Some(who)) => grant(ops, from, who).await,
+            };
+            output::say(handle, channel, &system_agent(), &text).await?;
+        }
+
+        "kb" => {
+            let (sub, terms) = cmd_args.split_once(' ').unwrap_or((cmd_args, ""));
+            let terms = terms.trim();
+            let replies = match sub {
+                "search" if !terms.is_empty() => {
+                    let hits = kb::search(memory, channel, terms)?;
+                    if hits.is_empty() {
+                        vec![format!("No stored answers in {channel} match \"{terms}\".")]
+                    } else {
+                        hits.iter()
+                            .take(5)
+                            .map(|(s, _)| {
+                                format!(
+                                    "#{} {} asked: {} — {} answered: {}",
+                                    s.id,
+                                    s.pair.asker,
+                                    s.pair.question,
+                                    s.pair.answerer,
+                                    s.pair.answer
+                                )
+                            })
+                            .collect()
                     }
+                }
+                "forget" => match terms.parse::<i64>() {
+                    Ok(id) if kb::forget(memory, channel, id)? => {
+                        vec![format!("Forgot kb #{id}.")]
+                    }
+                    Ok(id) => vec![format!("No kb #{id} in {channel}.")],
+                    Err(_) => vec!["Usage: /kb forget <id>".to_string()],
+                },
+                _ => vec!["Usage: /kb search <terms> | /kb forget <id>".to_string()],
+            };
+            for reply in &replies {
+                output::say(handle, channel, &system_agent(), reply).await?;
+            }
+        }
+
+        "watch" => {
+            let mut words = cmd_args.split_whitespace();
+            let (sub, repo, branch) = (words.next(), words.next(), words.next());
+            let repo = repo.map(|r| watch::parse_repo(r).ok_or(r));
+            let replies = match (sub, repo) {
+                (Some("add" | "remove"), Some(Err(repo))) => {
+                    vec![format!("{from}: {repo} isn't a GitHub repository (owner/name or URL)")]
+                }
+                (Some("add"), Some(Ok(repo))) => match watcher.add(channel, &repo, branch).await {
+                    Ok(w) => vec![format!(
+                        "👀 Watching {}@{} here: pushes and CI results will be summarized.",
+                        w.repo, w.branch
+                    )],
+                    Err(e) => vec![format!("{from}: couldn't watch {repo}: {e:#}")],
+                },
+                (Some("remove"), Some(Ok(repo))) => {
+                    let removed = watch::remove(memory, channel, &repo, branch)?;
+                    if removed.is_empty() {
+                        vec![format!("{from}: {repo} isn't watched here.")]
+                    } else {
+                        removed
+                            .iter()
+                            .map(|w| format!("Stopped watching {}@{}.", w.repo, w.branch))
+                            .collect()
+                    }
+                }
+                (None | Some("list"), _) => {
+                    let watches = watch::list(memory, channel)?;
+                    if watches.is_empty() {
+                        vec![format!("Nothing is watched in {channel}.")]
+                    } else {
+                        watches
+                            .iter()
+                            .map(|w| format!("👀 {}@{}", w.repo, w.branch))
+                            .collect()
+                    }
+                }
+                _ => vec![
+                    "Usage: /watch add <repo> [branch] | /watch list | /watch remove <repo> [branch]"
+                        .to_string(),
+                ],
+            };
+            for reply in &replies {
+                output::say(handle, channel, &system_agent(), reply).await?;
+            }
+        }
 
-                    "prototype" | "proto" => {
-                        if cmd_args.is_empty() {
-                            output::say(
-                                handle,
-                                channel,
-                                &system_agent(),
-                                "Usage: /prototype <describe what to build>",
+        "digest" => {
+            let mut words = cmd_args.split_whitespace();
+            let (sub, value) = (words.next(), words.next());
+            let reply = if !matches!(sub, None | Some("status"))
+                && !digest::is_channel_op(handle, channel, from)
+            {
+                format!("{from}: only channel operators can change the digest.")
+            } else {
+                match (sub, value) {
+                    (None | Some("status"), _) => match digest::get(memory, channel)? {
+                        Some(s) => {
+                            let due = chrono::DateTime::from_timestamp(s.due_at(), 0)
+                                .map(|t| t.format("%a %b %-d %H:%M UTC").to_string())
+                                .unwrap_or_default();
+                            let publish = if s.publish {
+                                ", published to the archive"
+                            } else {
+                                ""
+                            };
+                            format!(
+                                "📰 {channel} gets a {} digest{publish}; next one {due}.",
+                                s.cadence
                             )
-                            .await?;
+                        }
+                        None => format!("{channel} has no digest. Ops: /digest enable weekly"),
+                    },
+                    (Some("enable"), cadence) => {
+                        match cadence.unwrap_or("weekly").parse::<Cadence>() {
+                            Ok(cadence) => {
+                                digester.enable(channel, cadence)?;
+                                format!("📰 {channel} will get a {cadence} digest, pinned here.")
+                            }
+                            Err(e) => format!("{from}: {e}"),
+                        }
+                    }
+                    (Some("disable"), _) => {
+                        if digest::remove(memory, channel)? {
+                            format!("No more digests in {channel}.")
                         } else {
-                            let h = handle.clone();
-                            let ch = channel.to_string();
-                            let spec = cmd_args.to_string();
-                            let llm_key = args.api_key.clone();
-                            let model = args.model.clone();
-                            let ws = args.workspace.clone();
-                            let db = args.memory_db.clone();
-                            tokio::spawn(async move {
-                                let llm = LlmClient::new(llm_key).with_model(&model);
-                                let mem = match Memory::open(&db) {
-                                    Ok(m) => m,
-                                    Err(e) => {
-                                        tracing::error!("Failed to open memory: {e}");
-                                        return;
-                                    }
-                                };
-                                if let Err(e) =
-                                    freeq_bots::prototype::build(&h, &ch, &spec, &llm, &mem, &ws)
-                                        .await
-                                {
-                                    tracing::error!(error = %e, "Prototype build failed");
-                                    let _ = output::error(
-                                        &h,
-                                        &ch,
-                                        &AgentId {
-                                            role: "builder".to_string(),
-                                            color: None,
-                                        },
-                                        &format!("Build failed: {e}"),
-                                    )
-                                    .await;
-                                }
-                            });
+                            format!("{from}: {channel} has no digest.")
                         }
                     }
-
-                    "help" | "h" => {
-                        let lines = [
-                            "🤖 freeq AI Factory — Commands:",
-                            "/factory build <spec>  — Full software factory pipeline",
-                            "/factory status        — Current factory status",
-                            "/factory pause/resume  — Control the pipeline",
-                            "/factory spec          — Show current project spec",
-                            "/factory files         — List project files",
-                            "/audit <repo-url>      — Architecture audit of a GitHub repo",
-                            "/prototype <spec>      — Quick spec → deployed prototype",
-                            "/help                  — This help message",
-                        ];
-                        for line in &lines {
-                            handle.privmsg(channel, line).await?;
-                            tokio::time::sleep(std::time::Duration::from_millis(80)).await;
+                    (Some("publish"), Some(value @ ("on" | "off"))) => {
+                        match digester.set_publish(channel, value == "on") {
+                            Ok(Some(_)) if value == "on" => {
+                                "📚 Digests here will also be published to the archive.".to_string()
+                            }
+                            Ok(Some(_)) => "Digests here won't be published.".to_string(),
+                            Ok(None) => format!("{from}: {channel} has no digest."),
+                            Err(e) => format!("{from}: {e:#}"),
                         }
                     }
+                    (Some("now"), _) => match digester.post(channel).await {
+                        Ok(true) => return Ok(()),
+                        Ok(false) => {
+                            format!("Nothing was said in {channel} since the last digest.")
+                        }
+                        Err(e) => format!("{from}: couldn't post the digest: {e:#}"),
+                    },
+                    _ => "Usage: /digest | /digest enable [weekly|daily] | /digest disable \
+                          | /digest publish on|off | /digest now"
+                        .to_string(),
+                }
+            };
+            output::say(handle, channel, &system_agent(), &reply).await?;
+        }
+
+        "models" => {
+            for (task, route, stats) in llm.stats() {
+                let line = format!("{task}: {route} — {stats}");
+                output::say(handle, channel, &system_agent(), &line).await?;
+            }
+        }
+
+        "help" | "h" => {
+            let lines = [
+                "🤖 freeq AI Factory — Commands:",
+                "/factory build <spec>  — Full software factory pipeline",
+                "/factory status        — Current factory status",
+                "/factory pause/resume  — Control the pipeline",
+                "/factory spec          — Show current project spec",
+                "/factory files         — List project files",
+                "/factory report [n]    — Transcript of the last build (or build n)",
+                "/project               — Which project this channel builds",
+                "/project create <name> — Build a named project in this channel",
+                "/project diff          — Changes made by the last build",
+                "/project log           — Build history",
+                "/project reset [<sha>] — Undo the last build, or go back to <sha>",
+                "/audit <repo-url>      — Architecture audit of a GitHub repo",
+                "/audit security <url>  — Dependency + secret scan and security audit",
+                "/prototype <spec>      — Quick spec → deployed prototype",
+                "/prototype --refine <idea> — Answer a few questions, approve the spec, then build",
+                "/approve | /cancel     — Build the refined spec, or drop it",
+                "/verbosity [level]     — quiet, normal or verbose output here",
+                "/grant <nick|did>      — Make someone a factory operator (operators only)",
+                "/kb search <terms>     — Search questions answered here before",
+                "/kb forget <id>        — Drop a stored answer (operators only)",
+                "/watch add <repo> [branch] — Summarize a repo's pushes and CI results here",
+                "/watch list | remove <repo> — Watched repos / stop watching one",
+                "/digest enable [weekly|daily] — Pinned digest of the channel (channel ops)",
+                "/digest publish on|off | now — Archive digests / post one now (channel ops)",
+                "/botinfo               — Bot version and what the server supports",
+                "/models                — Model per task type, with usage so far",
+                "/help                  — This help message",
+            ];
+            for line in &lines {
+                handle.privmsg(channel, line).await?;
+                tokio::time::sleep(std::time::Duration::from_millis(80)).await;
+            }
+        }
+
+        _ => {} // Ignore unknown commands silently
+    }
+    Ok(())
+}
+
+/// Build and deploy a prototype of `spec` in the background.
+fn spawn_prototype(
+    handle: &ClientHandle,
+    channel: &str,
+    spec: &str,
+    args: &Args,
+    llm: &LlmClient,
+    factory: &Factory,
+) {
+    let h = handle.clone();
+    let ch = channel.to_string();
+    let spec = spec.to_string();
+    let llm = llm.clone();
+    let ws = args.workspace.clone();
+    let db = args.memory_db.clone();
+    let artifacts = factory.artifacts().cloned();
+    tokio::spawn(async move {
+        let mem = match Memory::open(&db) {
+            Ok(m) => m,
+            Err(e) => {
+                tracing::error!("Failed to open memory: {e}");
+                return;
+            }
+        };
+        if let Err(e) =
+            freeq_bots::prototype::build(&h, &ch, &spec, &llm, &mem, &ws, artifacts.as_ref()).await
+        {
+            tracing::error!(error = %e, "Prototype build failed");
+            let _ = output::error(
+                &h,
+                &ch,
+                &AgentId {
+                    role: "builder".to_string(),
+                    color: Some(output::color::GREEN),
+                },
+                &format!("Build failed: {e}"),
+            )
+            .await;
+        }
+    });
+}
+
+/// Whether `from` may run gated command `cmd`. When they can't, say why.
+async fn may_run(
+    handle: &ClientHandle,
+    channel: &str,
+    from: &str,
+    cmd: &str,
+    operators: Option<&Operators>,
+) -> Result<bool> {
+    let Some(operators) = operators else {
+        return Ok(true);
+    };
+    let reply = match operators.did_of(from) {
+        None => format!("{from}: only factory operators can use /{cmd}; sign in first"),
+        Some(did) => match operators.is_operator(&did).await {
+            Ok(true) => return Ok(true),
+            Ok(false) => {
+                format!("{from}: only factory operators can use /{cmd}; ask one to /grant you")
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "Operator check failed");
+                format!("{from}: couldn't check whether you're a factory operator: {e}")
+            }
+        },
+    };
+    output::say(handle, channel, &system_agent(), &reply).await?;
+    Ok(false)
+}
+
+/// `/grant <who>` from `from`: appoint `who` (a nick or DID) as an operator.
+async fn grant(operators: &Operators, from: &str, who: &str) -> String {
+    let Some(granter) = operators.did_of(from) else {
+        return format!("{from}: sign in first");
+    };
+    let subject = if who.starts_with("did:") {
+        who.to_string()
+    } else {
+        match operators.did_of(who) {
+            Some(did) => did,
+            None => {
+                return format!(
+                    "{from}: I don't know {who}'s DID; they need to be signed in and say \
+                     something here first, or use /grant <did>"
+                );
+            }
+        }
+    };
+    match operators.grant(&granter, &subject).await {
+        Ok(()) => format!("✅ {who} is now a factory operator (granted by {from})"),
+        Err(e) => format!("{from}: couldn't grant: {e}"),
+    }
+}
 
-                    _ => {} // Ignore unknown commands silently
+/// A channel message that isn't a command: act on it if it mentions the bot,
+/// follows up on an exchange, or answers a confirmation question.
+async fn handle_mention(
+    handle: &ClientHandle,
+    bot_nick: &str,
+    args: &Args,
+    channel: &str,
+    from: &str,
+    text: &str,
+    llm: &LlmClient,
+    memory: &Memory,
+    factory: &Factory,
+    operators: Option<&Operators>,
+    watcher: &Watcher,
+    digester: &Digester,
+    context: &AgentContext,
+    conversations: &mut Conversations,
+    refinements: &mut Refinements,
+) -> Result<()> {
+    let now = Instant::now();
+    let intent = match conversations.route(channel, from, text, bot_nick, now) {
+        Route::Ignore => return Ok(()),
+        Route::Declined => {
+            output::say(
+                handle,
+                channel,
+                &system_agent(),
+                &format!("{from}: OK, cancelled."),
+            )
+            .await?;
+            conversations.engage(channel, from, now);
+            return Ok(());
+        }
+        Route::Confirmed(intent) => intent,
+        Route::Classify => {
+            let ctx = context.assemble(channel, None).await;
+            let intent = mention::classify(llm, bot_nick, &ctx, from, text).await;
+            if intent.needs_confirmation() {
+                if let Intent::Build(spec) = &intent {
+                    output::say(
+                        handle,
+                        channel,
+                        &system_agent(),
+                        &format!("{from}: Start a factory build for \"{spec}\"? (yes/no)"),
+                    )
+                    .await?;
                 }
+                conversations.propose(channel, from, intent, now);
+                return Ok(());
             }
+            intent
         }
+    };
 
-        Event::Disconnected { reason } => {
-            tracing::warn!("Disconnected: {reason}");
+    match intent {
+        Intent::Build(spec) => {
+            let cmd_args = format!("build {spec}");
+            run_command(
+                handle,
+                channel,
+                from,
+                "factory",
+                &cmd_args,
+                args,
+                llm,
+                memory,
+                factory,
+                operators,
+                watcher,
+                digester,
+                refinements,
+            )
+            .await?;
+        }
+        Intent::Audit(target) => {
+            run_command(
+                handle,
+                channel,
+                from,
+                "audit",
+                &target,
+                args,
+                llm,
+                memory,
+                factory,
+                operators,
+                watcher,
+                digester,
+                refinements,
+            )
+            .await?;
+        }
+        Intent::Question => {
+            let ctx = context.assemble(channel, None).await;
+            let reply = mention::answer(llm, &ctx, from, text).await?;
+            output::say(handle, channel, &system_agent(), &reply).await?;
+            context
+                .record_message(
+                    channel,
+                    HistoryMessage {
+                        nick: bot_nick.to_string(),
+                        text: reply,
+                        timestamp: chrono::Utc::now().timestamp() as u64,
+                        msgid: None,
+                        tags: Default::default(),
+                    },
+                )
+                .await;
+        }
+        Intent::Ignore => return Ok(()),
+    }
+    conversations.engage(channel, from, now);
+    Ok(())
+}
+
+/// `freeq-bots eval`: score the pipelines on a corpus and store the report.
+async fn run_eval(args: &Args, eval_args: &EvalArgs) -> Result<()> {
+    let memory = Memory::open(&args.memory_db)?;
+    let history = eval::Report::history(&memory)?;
+    if eval_args.history {
+        for report in &history {
+            print!("{}", report.summary());
         }
+        return Ok(());
+    }
 
-        _ => {}
+    let Some(path) = &eval_args.corpus else {
+        bail!("eval needs a corpus file (or --history)");
+    };
+    let mut corpus = eval::Corpus::load(path)?;
+    if let Some(filter) = &eval_args.filter {
+        corpus.cases.retain(|c| c.name.contains(filter.as_str()));
     }
 
+    let llm = args.llm()?;
+    let report = eval::run(
+        &corpus,
+        &eval_args.label,
+        &llm,
+        &args.model,
+        &args.workspace,
+    )
+    .await;
+    report.save(&memory)?;
+    print!("{}", report.summary());
+
+    let previous = match &eval_args.against {
+        Some(label) => history.iter().rev().find(|r| &r.label == label),
+        None => history.last(),
+    };
+    if let Some(previous) = previous {
+        print!("{}", report.compare(previous));
+    }
+    Ok(())
+}
+
+/// Label the one-shot subcommands post to.
+const ONE_SHOT_TARGET: &str = "#local";
+
+/// `run-prototype`, `run-factory`, `run-audit`: one pipeline, output to
+/// stdout.
+async fn run_one_shot(args: &Args, command: &Command) -> Result<()> {
+    let json = match command {
+        Command::RunPrototype(spec) | Command::RunFactory(spec) => spec.json,
+        Command::RunAudit { json, .. } => *json,
+        Command::Eval(_) => unreachable!("eval is not a one-shot"),
+    };
+    let sink: Box<dyn OutputSink> = if json {
+        Box::new(JsonLogSink::stdout())
+    } else {
+        Box::new(StdoutSink)
+    };
+    output::set_verbosity(ONE_SHOT_TARGET, Verbosity::Verbose);
+    let llm = args.llm()?;
+    let memory = Memory::open(&args.memory_db)?;
+
+    match command {
+        Command::RunPrototype(spec) => {
+            let spec = read_spec(&spec.spec)?;
+            freeq_bots::prototype::build(
+                sink.as_ref(),
+                ONE_SHOT_TARGET,
+                &spec,
+                &llm,
+                &memory,
+                &args.workspace,
+                None,
+            )
+            .await?;
+        }
+        Command::RunFactory(spec) => {
+            let spec = read_spec(&spec.spec)?;
+            let factory = Factory::new(FactoryConfig {
+                channel: ONE_SHOT_TARGET.to_string(),
+                workspace_base: args.workspace.clone(),
+            });
+            factory
+                .handle_command(
+                    sink.as_ref(),
+                    ONE_SHOT_TARGET,
+                    "cli",
+                    "build",
+                    &spec,
+                    &llm,
+                    &memory,
+                )
+                .await?;
+        }
+        Command::RunAudit {
+            target, security, ..
+        } => {
+            let (sink, ws) = (sink.as_ref(), &args.workspace);
+            if *security {
+                freeq_bots::auditor::security_audit(sink, ONE_SHOT_TARGET, target, &llm, ws)
+                    .await?;
+            } else {
+                freeq_bots::auditor::audit(sink, ONE_SHOT_TARGET, target, &llm, ws).await?;
+            }
+        }
+        Command::Eval(_) => unreachable!("eval is not a one-shot"),
+    }
     Ok(())
 }
+
+/// Read a spec from `path`, or stdin for "-".
+fn read_spec(path: &std::path::Path) -> Result<String> {
+    let spec = if path.as_os_str() == "-" {
+        std::io::read_to_string(std::io::stdin())?
+    } else {
+        std::fs::read_to_string(path)?
+    };
+    if spec.trim().is_empty() {
+        bail!("the spec is empty");
+    }
+    Ok(spec)
+}
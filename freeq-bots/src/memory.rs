@@ -101,6 +101,29 @@ impl Memory {
         Ok(entries)
     }
 
+    /// Get all entries of a kind, across projects.
+    pub fn list_kind(&self, kind: &str) -> Result<Vec<Entry>> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare(
+            "SELECT id, project, kind, key, value, created_at
+             FROM memory WHERE kind = ?1
+             ORDER BY id ASC",
+        )?;
+        let entries = stmt
+            .query_map(rusqlite::params![kind], |row| {
+                Ok(Entry {
+                    id: row.get(0)?,
+                    project: row.get(1)?,
+                    kind: row.get(2)?,
+                    key: row.get(3)?,
+                    value: row.get(4)?,
+                    created_at: row.get(5)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(entries)
+    }
+
     /// Delete a specific entry.
     pub fn delete(&self, project: &str, kind: &str, key: &str) -> Result<()> {
         let db = self.db.lock().unwrap();
@@ -0,0 +1,334 @@
+//! Natural-language mentions — talking to the bot without the command prefix.
+//!
+//! A channel message that names the bot ("factory, build me a todo app",
+//! "@factory audit github.com/x/y") is classified by the LLM into an
+//! [`Intent`]. Questions are answered from the channel's conversation
+//! context; builds are confirmed with the asker first, since they write and
+//! deploy code. Once the bot has replied, the asker's next messages carry on
+//! the exchange for [`FOLLOW_UP_WINDOW`] without naming the bot again.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use crate::llm::{LlmClient, Task};
+
+/// How long after the bot replies that the asker can follow up without
+/// mentioning it.
+pub const FOLLOW_UP_WINDOW: Duration = Duration::from_secs(120);
+/// How long a confirmation question stays open.
+pub const CONFIRM_WINDOW: Duration = Duration::from_secs(120);
+
+/// What a message addressed to the bot asks for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Intent {
+    /// Run the software factory on a spec.
+    Build(String),
+    /// Audit a repository (URL or path).
+    Audit(String),
+    /// Anything to answer in conversation.
+    Question,
+    /// Not for the bot, or needs no reply.
+    Ignore,
+}
+
+impl Intent {
+    /// Whether to ask before acting: a build writes and deploys code.
+    pub fn needs_confirmation(&self) -> bool {
+        matches!(self, Intent::Build(_))
+    }
+}
+
+/// What to do with a channel message that isn't a prefixed command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Route {
+    /// Not for the bot.
+    Ignore,
+    /// Addressed to the bot (a mention or a follow-up): classify it.
+    Classify,
+    /// The asker confirmed a pending intent.
+    Confirmed(Intent),
+    /// The asker declined a pending intent.
+    Declined,
+}
+
+#[derive(Debug)]
+struct Pending {
+    nick: String,
+    intent: Intent,
+    at: Instant,
+}
+
+/// Per-channel conversation state: who the bot is talking with and what it
+/// is waiting on a yes/no for.
+#[derive(Debug, Default)]
+pub struct Conversations {
+    /// `(channel, nick)`, lowercased → when the bot last replied to them.
+    engaged: HashMap<(String, String), Instant>,
+    /// Lowercased channel → the intent awaiting confirmation there.
+    pending: HashMap<String, Pending>,
+}
+
+impl Conversations {
+    /// Decide what to do with `text` from `from` in `channel`.
+    pub fn route(
+        &mut self,
+        channel: &str,
+        from: &str,
+        text: &str,
+        bot_nick: &str,
+        now: Instant,
+    ) -> Route {
+        let chan = channel.to_lowercase();
+        self.pending
+            .retain(|_, p| now.duration_since(p.at) < CONFIRM_WINDOW);
+        self.engaged
+            .retain(|_, at| now.duration_since(*at) < FOLLOW_UP_WINDOW);
+
+        if self
+            .pending
+            .get(&chan)
+            .is_some_and(|p| p.nick.eq_ignore_ascii_case(from))
+            && let Some(yes) = confirmation(text)
+            && let Some(pending) = self.pending.remove(&chan)
+        {
+            return if yes {
+                Route::Confirmed(pending.intent)
+            } else {
+                Route::Declined
+            };
+        }
+        if is_mention(text, bot_nick) || self.engaged.contains_key(&(chan, from.to_lowercase())) {
+            Route::Classify
+        } else {
+            Route::Ignore
+        }
+    }
+
+    /// The bot replied to `nick`; their next messages are follow-ups.
+    pub fn engage(&mut self, channel: &str, nick: &str, now: Instant) {
+        self.engaged
+            .insert((channel.to_lowercase(), nick.to_lowercase()), now);
+    }
+
+    /// Ask `nick` to confirm `intent`, replacing any open question in
+    /// `channel`.
+    pub fn propose(&mut self, channel: &str, nick: &str, intent: Intent, now: Instant) {
+        self.pending.insert(
+            channel.to_lowercase(),
+            Pending {
+                nick: nick.to_string(),
+                intent,
+                at: now,
+            },
+        );
+        self.engage(channel, nick, now);
+    }
+}
+
+/// Whether `text` names `nick`: `@nick`, or `nick` as a whole word.
+pub fn is_mention(text: &str, nick: &str) -> bool {
+    let text = text.to_lowercase();
+    let nick = nick.to_lowercase();
+    if nick.is_empty() {
+        return false;
+    }
+    let is_nick_char = |c: char| c.is_alphanumeric() || "-_[]{}\\|^`".contains(c);
+    text.match_indices(&nick).any(|(i, _)| {
+        let before = text[..i].chars().next_back();
+        let after = text[i + nick.len()..].chars().next();
+        !before.is_some_and(is_nick_char) && !after.is_some_and(is_nick_char)
+    })
+}
+
+/// A yes/no answer to a confirmation question, if `text` is one.
+pub fn confirmation(text: &str) -> Option<bool> {
+    let word = text.trim().trim_end_matches(['.', '!']).to_lowercase();
+    match word.as_str() {
+        "yes" | "y" | "yep" | "yeah" | "sure" | "ok" | "okay" | "go" | "go ahead" | "do it" => {
+            Some(true)
+        }
+        "no" | "n" | "nope" | "cancel" | "stop" | "don't" | "dont" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parse the classifier's reply: a JSON object with `intent` and
+/// `argument`, possibly wrapped in prose or a code fence.
+pub fn parse_intent(reply: &str) -> Option<Intent> {
+    let start = reply.find('{')?;
+    let end = reply.rfind('}')?;
+    let value: serde_json::Value = serde_json::from_str(reply.get(start..=end)?).ok()?;
+    let argument = value["argument"].as_str().unwrap_or("").trim().to_string();
+    match value["intent"].as_str()? {
+        "build" if !argument.is_empty() => Some(Intent::Build(argument)),
+        "audit" if !argument.is_empty() => Some(Intent::Audit(argument)),
+        // A build or audit with nothing to act on needs a conversation.
+        "build" | "audit" | "question" => Some(Intent::Question),
+        "ignore" => Some(Intent::Ignore),
+        _ => None,
+    }
+}
+
+/// Classify `text` from `from`, given the channel's assembled `context`.
+/// Replies the classifier can't be understood are ignored.
+pub async fn classify(
+    llm: &LlmClient,
+    bot_nick: &str,
+    context: &str,
+    from: &str,
+    text: &str,
+) -> Intent {
+    let system = format!(
+        r#"You route IRC messages for a bot named {bot_nick}. It can build software from a spec (build), audit a GitHub repository (audit), or chat (question).
+
+Reply with ONLY a JSON object: {{"intent": "build" | "audit" | "question" | "ignore", "argument": "..."}}
+- build: argument is the full spec of what to build.
+- audit: argument is the repository URL or path.
+- question: a question or remark for {bot_nick}; argument is empty.
+- ignore: not addressed to {bot_nick}, or needs no reply.
+
+{context}"#
+    );
+    let prompt = format!("Latest message — {from}: {text}");
+    match llm
+        .for_task(Task::Classify)
+        .complete(&system, &prompt)
+        .await
+    {
+        Ok(reply) => parse_intent(&reply).unwrap_or_else(|| {
+            tracing::warn!(reply = %reply, "Unparseable intent, ignoring");
+            Intent::Ignore
+        }),
+        Err(e) => {
+            tracing::warn!(error = %e, "Intent classification failed");
+            Intent::Ignore
+        }
+    }
+}
+
+/// Answer `text` from `from` in conversation.
+pub async fn answer(llm: &LlmClient, context: &str, from: &str, text: &str) -> Result<String> {
+    let system = format!(
+        "{context}\n\nYou are chatting in IRC. Answer in a few short plain-text lines. \
+         If they want something built or a repo audited, tell them to ask you to build or audit it."
+    );
+    llm.for_task(Task::Chat)
+        .complete(&system, &format!("{from}: {text}"))
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mentions_need_the_nick_as_a_word() {
+        assert!(is_mention("@factory build a todo app", "factory"));
+        assert!(is_mention("hey Factory, what's up?", "factory"));
+        assert!(is_mention("factory: status", "factory"));
+        assert!(!is_mention("the factory_bot is down", "factory"));
+        assert!(!is_mention("software factories", "factory"));
+        assert!(!is_mention("anything", ""));
+    }
+
+    #[test]
+    fn confirmations_are_yes_or_no() {
+        assert_eq!(confirmation("Yes!"), Some(true));
+        assert_eq!(confirmation(" go ahead "), Some(true));
+        assert_eq!(confirmation("no."), Some(false));
+        assert_eq!(confirmation("yes but make it blue"), None);
+    }
+
+    #[test]
+    fn intents_parse_from_json_in_prose() {
+        assert_eq!(
+            parse_intent(
+                "Sure:\n```json\n{\"intent\": \"build\", \"argument\": \"a todo app\"}\n```"
+            ),
+            Some(Intent::Build("a todo app".into()))
+        );
+        assert_eq!(
+            parse_intent(r#"{"intent":"audit","argument":"https://github.com/x/y"}"#),
+            Some(Intent::Audit("https://github.com/x/y".into()))
+        );
+        assert_eq!(
+            parse_intent(r#"{"intent":"build","argument":""}"#),
+            Some(Intent::Question)
+        );
+        assert_eq!(parse_intent(r#"{"intent":"ignore"}"#), Some(Intent::Ignore));
+        assert_eq!(parse_intent("no json here"), None);
+        assert_eq!(parse_intent(r#"{"intent":"dance"}"#), None);
+    }
+
+    #[test]
+    fn follow_ups_continue_until_the_window_closes() {
+        let mut c = Conversations::default();
+        let t0 = Instant::now();
+        assert_eq!(
+            c.route("#f", "alice", "hello all", "factory", t0),
+            Route::Ignore
+        );
+        assert_eq!(
+            c.route("#f", "alice", "factory, what can you do?", "factory", t0),
+            Route::Classify
+        );
+        c.engage("#f", "alice", t0);
+        assert_eq!(
+            c.route(
+                "#F",
+                "Alice",
+                "and audits?",
+                "factory",
+                t0 + Duration::from_secs(30)
+            ),
+            Route::Classify
+        );
+        assert_eq!(
+            c.route(
+                "#f",
+                "bob",
+                "unrelated",
+                "factory",
+                t0 + Duration::from_secs(30)
+            ),
+            Route::Ignore
+        );
+        assert_eq!(
+            c.route("#f", "alice", "later", "factory", t0 + FOLLOW_UP_WINDOW),
+            Route::Ignore
+        );
+    }
+
+    #[test]
+    fn only_the_asker_can_confirm_and_only_in_time() {
+        let mut c = Conversations::default();
+        let t0 = Instant::now();
+        let build = Intent::Build("a todo app".into());
+        c.propose("#f", "alice", build.clone(), t0);
+        assert_eq!(c.route("#f", "bob", "yes", "factory", t0), Route::Ignore);
+        assert_eq!(
+            c.route("#f", "alice", "yes", "factory", t0),
+            Route::Confirmed(build.clone())
+        );
+        // Answered: a second "yes" is just a follow-up.
+        assert_eq!(
+            c.route("#f", "alice", "yes", "factory", t0),
+            Route::Classify
+        );
+
+        c.propose("#f", "alice", build.clone(), t0);
+        assert_eq!(
+            c.route("#f", "alice", "nope", "factory", t0),
+            Route::Declined
+        );
+
+        c.propose("#f", "alice", build, t0);
+        let late = t0 + CONFIRM_WINDOW;
+        assert_eq!(
+            c.route("#f", "alice", "yes", "factory", late),
+            Route::Ignore
+        );
+    }
+}
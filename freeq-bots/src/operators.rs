@@ -0,0 +1,291 @@
+//! Factory operators — who may drive the expensive commands.
+//!
+//! Builds, prototypes and audits spend real money on LLM calls, so with
+//! `--operators-api` set only factory operators may start them. An
+//! operator is a signed-in user whose DID holds a [`ROLE`] credential
+//! from one of the trusted issuers, as reported by the server's policy
+//! API (`GET /api/v1/credentials/{did}`), or one of the bootstrap DIDs
+//! given with `--operator`.
+//!
+//! Operators appoint new ones with `/grant <nick|did>`. Following the
+//! moderation verifier's pattern, the bot asks the server's role verifier
+//! (`POST /verify/role/appoint`, authenticated with the server's role
+//! issuer secret) to sign a credential, which the verifier presents to
+//! the policy API on the new operator's behalf.
+//!
+//! Senders are identified by the `account` tag on their messages, so
+//! guests can never be operators.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, bail};
+use freeq_sdk::event::Event;
+use serde::Deserialize;
+
+/// Credential type that makes a DID a factory operator.
+pub const ROLE: &str = "factory-operator";
+
+/// How long a credential lookup is trusted before asking the server again.
+const CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Where operator credentials come from.
+#[derive(Debug, Clone)]
+pub struct OperatorConfig {
+    /// Server web URL, e.g. `https://irc.freeq.at`.
+    pub api_url: String,
+    /// Issuer DIDs whose [`ROLE`] credentials count.
+    pub issuers: Vec<String>,
+    /// DIDs that are operators without a credential, to appoint the first
+    /// ones.
+    pub bootstrap: Vec<String>,
+    /// The server's role issuer secret; `/grant` needs it.
+    pub issuer_secret: Option<String>,
+}
+
+impl OperatorConfig {
+    /// The server's own verifier DID, `did:web:<host>:verify`, for
+    /// `api_url`.
+    pub fn default_issuer(api_url: &str) -> Option<String> {
+        let rest = api_url.split_once("://").map_or(api_url, |(_, r)| r);
+        let host = rest.split(['/', ':']).next().unwrap_or_default();
+        (!host.is_empty()).then(|| format!("did:web:{host}:verify"))
+    }
+}
+
+/// A credential as listed by the policy API.
+#[derive(Debug, Deserialize)]
+struct StoredCredential {
+    credential_type: String,
+    issuer: String,
+}
+
+/// Whether `creds` include a [`ROLE`] credential from one of `issuers`.
+fn holds_role(creds: &[StoredCredential], issuers: &[String]) -> bool {
+    creds
+        .iter()
+        .any(|c| c.credential_type == ROLE && issuers.contains(&c.issuer))
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Lowercased nick → DID, for signed-in users the bot has seen.
+    accounts: HashMap<String, String>,
+    /// DID → (is an operator, when checked).
+    checked: HashMap<String, (bool, Instant)>,
+}
+
+/// Operator checks and grants. Cheap to clone; clones share state.
+#[derive(Debug, Clone)]
+pub struct Operators {
+    config: Arc<OperatorConfig>,
+    http: reqwest::Client,
+    state: Arc<Mutex<State>>,
+}
+
+impl Operators {
+    pub fn new(config: OperatorConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            http: reqwest::Client::new(),
+            state: Arc::default(),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Update who is signed in as whom from a client event. Call for every
+    /// event the bot receives.
+    pub fn handle_event(&self, event: &Event) {
+        let mut state = self.state();
+        match event {
+            Event::Message { from, tags, .. } if !tags.contains_key("batch") => {
+                match tags.get("account") {
+                    Some(did) => state.accounts.insert(from.to_lowercase(), did.clone()),
+                    None => state.accounts.remove(&from.to_lowercase()),
+                };
+            }
+            Event::Joined { nick, account, .. } => {
+                match account {
+                    Some(did) => state.accounts.insert(nick.to_lowercase(), did.clone()),
+                    None => state.accounts.remove(&nick.to_lowercase()),
+                };
+            }
+            Event::NickChanged { old_nick, new_nick } => {
+                if let Some(did) = state.accounts.remove(&old_nick.to_lowercase()) {
+                    state.accounts.insert(new_nick.to_lowercase(), did);
+                }
+            }
+            Event::UserQuit { nick, .. } => {
+                state.accounts.remove(&nick.to_lowercase());
+            }
+            _ => {}
+        }
+    }
+
+    /// The DID `nick` is signed in as, if the bot has seen it.
+    pub fn did_of(&self, nick: &str) -> Option<String> {
+        self.state().accounts.get(&nick.to_lowercase()).cloned()
+    }
+
+    /// Whether `did` is a factory operator.
+    pub async fn is_operator(&self, did: &str) -> Result<bool> {
+        if self.config.bootstrap.iter().any(|d| d == did) {
+            return Ok(true);
+        }
+        if let Some((yes, at)) = self.state().checked.get(did)
+            && at.elapsed() < CACHE_TTL
+        {
+            return Ok(*yes);
+        }
+        let url = format!(
+            "{}/api/v1/credentials/{did}",
+            self.config.api_url.trim_end_matches('/')
+        );
+        let resp = self.http.get(url).send().await?;
+        if !resp.status().is_success() {
+            bail!("credential lookup failed ({})", resp.status());
+        }
+        let creds: Vec<StoredCredential> = resp.json().await?;
+        let yes = holds_role(&creds, &self.config.issuers);
+        self.state()
+            .checked
+            .insert(did.to_string(), (yes, Instant::now()));
+        Ok(yes)
+    }
+
+    /// Make `subject_did` an operator, appointed by `granter_did` (who
+    /// must be one already).
+    pub async fn grant(&self, granter_did: &str, subject_did: &str) -> Result<()> {
+        let Some(secret) = &self.config.issuer_secret else {
+            bail!("granting isn't set up on this bot (no role issuer secret)");
+        };
+        if !self.is_operator(granter_did).await? {
+            bail!("only factory operators can grant the role");
+        }
+        let api = self.config.api_url.trim_end_matches('/');
+        let resp = self
+            .http
+            .post(format!("{api}/verify/role/appoint"))
+            .bearer_auth(secret)
+            .json(&serde_json::json!({
+                "role": ROLE,
+                "subject_did": subject_did,
+                "appointed_by": granter_did,
+                "callback": format!("{api}/api/v1/credentials/present"),
+            }))
+            .timeout(Duration::from_secs(20))
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let text = resp.text().await.unwrap_or_default();
+            bail!("appointment failed ({status}): {text}");
+        }
+        self.state().checked.remove(subject_did);
+        Ok(())
+    }
+}
+
+/// Whether `cmd` with `args` needs an operator: anything that starts a
+/// pipeline, rewrites a project or drops what the bot has learned.
+pub fn is_gated(cmd: &str, args: &str) -> bool {
+    let sub = args.split_whitespace().next().unwrap_or_default();
+    match cmd {
+        "audit" | "prototype" | "proto" => !args.is_empty(),
+        "approve" => true,
+        "factory" => sub == "build",
+        "project" => matches!(sub, "create" | "reset"),
+        "kb" => sub == "forget",
+        "watch" => matches!(sub, "add" | "remove"),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cred(credential_type: &str, issuer: &str) -> StoredCredential {
+        StoredCredential {
+            credential_type: credential_type.to_string(),
+            issuer: issuer.to_string(),
+        }
+    }
+
+    #[test]
+    fn only_trusted_issuers_count() {
+        let issuers = vec!["did:web:irc.example:verify".to_string()];
+        assert!(holds_role(
+            &[
+                cred("github_membership", "did:web:irc.example:verify"),
+                cred(ROLE, "did:web:irc.example:verify"),
+            ],
+            &issuers
+        ));
+        assert!(!holds_role(&[cred(ROLE, "did:web:evil.example")], &issuers));
+        assert!(!holds_role(
+            &[cred("channel_moderator", "did:web:irc.example:verify")],
+            &issuers
+        ));
+        assert_eq!(
+            OperatorConfig::default_issuer("https://irc.example:8443/x").as_deref(),
+            Some("did:web:irc.example:verify")
+        );
+    }
+
+    #[test]
+    fn expensive_commands_are_gated() {
+        assert!(is_gated("factory", "build a todo app"));
+        assert!(is_gated("prototype", "a chess clock"));
+        assert!(is_gated("approve", ""));
+        assert!(is_gated("audit", "security https://github.com/x/y"));
+        assert!(is_gated("project", "reset abc123"));
+        assert!(is_gated("kb", "forget 12"));
+        assert!(!is_gated("kb", "search cargo"));
+        assert!(is_gated("watch", "add chad/freeq"));
+        assert!(!is_gated("watch", "list"));
+        assert!(!is_gated("factory", "status"));
+        assert!(!is_gated("project", "log"));
+        assert!(!is_gated("audit", ""));
+        assert!(!is_gated("help", ""));
+    }
+
+    #[tokio::test]
+    async fn accounts_follow_messages_and_nick_changes() {
+        let ops = Operators::new(OperatorConfig {
+            api_url: "http://127.0.0.1:9".to_string(),
+            issuers: vec![],
+            bootstrap: vec!["did:plc:root".to_string()],
+            issuer_secret: None,
+        });
+        let message = |from: &str, account: Option<&str>| Event::Message {
+            from: from.into(),
+            target: "#factory".into(),
+            text: "hi".into(),
+            tags: account
+                .map(|a| ("account".to_string(), a.to_string()))
+                .into_iter()
+                .collect(),
+            formatted_text: None,
+            encrypted: false,
+        };
+        ops.handle_event(&message("Alice", Some("did:plc:alice")));
+        assert_eq!(ops.did_of("alice").as_deref(), Some("did:plc:alice"));
+        ops.handle_event(&Event::NickChanged {
+            old_nick: "alice".into(),
+            new_nick: "alice_".into(),
+        });
+        assert_eq!(ops.did_of("alice"), None);
+        assert_eq!(ops.did_of("ALICE_").as_deref(), Some("did:plc:alice"));
+        ops.handle_event(&message("alice_", None));
+        assert_eq!(ops.did_of("alice_"), None);
+
+        assert!(ops.is_operator("did:plc:root").await.unwrap());
+        let err = ops.grant("did:plc:root", "did:plc:alice").await;
+        assert!(err.unwrap_err().to_string().contains("secret"));
+    }
+}
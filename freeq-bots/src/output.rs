@@ -1,10 +1,19 @@
 //! Structured output formatting for IRC channels.
 //!
 //! Agents produce structured artifacts (code diffs, diagrams, status updates).
-//! This module formats them for readable IRC output.
+//! This module formats them for readable IRC output: role prefixes in mIRC
+//! colors, lines wrapped at word boundaries to fit the 512-byte IRC line,
+//! long code blocks uploaded to the server's paste service and linked, and
+//! output trimmed to each channel's [`Verbosity`]. The formatted lines go
+//! to an [`OutputSink`] — the IRC connection, or stdout, a JSON log, ...
 
+use std::collections::HashMap;
+use std::sync::{LazyLock, RwLock};
+
+use crate::diagram::Renderer;
 use crate::llm::StreamDelta;
-use freeq_sdk::client::ClientHandle;
+use crate::sink::OutputSink;
+use freeq_sdk::proto::tags::Priority;
 use freeq_sdk::streaming::StreamingMessage;
 use tokio::sync::mpsc;
 
@@ -13,43 +22,515 @@ use tokio::sync::mpsc;
 pub struct AgentId {
     /// Display name shown in messages, e.g. "architect", "builder"
     pub role: String,
-    /// IRC color code (optional).
+    /// IRC color code (optional), one of the [`color`] constants.
     pub color: Option<u8>,
 }
 
-/// Post a message to a channel with agent role prefix. The SDK
-/// auto-routes `\n`-bearing text through a `draft/multiline` BATCH
-/// when the cap is acked, so a long agent response arrives as ONE
-/// logical message (msgid coherence for edits/reactions) instead of
-/// the N per-line PRIVMSGs the old wrap-and-sleep workaround produced.
+/// mIRC color codes for agent role prefixes.
+pub mod color {
+    pub const BROWN: u8 = 5;
+    pub const PURPLE: u8 = 6;
+    pub const ORANGE: u8 = 7;
+    pub const GREEN: u8 = 9;
+    pub const TEAL: u8 = 10;
+    pub const CYAN: u8 = 11;
+    pub const BLUE: u8 = 12;
+    pub const PINK: u8 = 13;
+    pub const GREY: u8 = 14;
+}
+
+/// The IRC line limit, including the trailing CRLF.
+const IRC_LINE_BYTES: usize = 512;
+/// Room left for the `:nick!user@host ` prefix the server adds on relay.
+const HOSTMASK_RESERVE: usize = 100;
+
+/// How much an agent says in a channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Verbosity {
+    /// A few lines; code is always linked rather than inlined.
+    Quiet,
+    #[default]
+    Normal,
+    /// Everything, inline.
+    Verbose,
+}
+
+impl Verbosity {
+    /// Lines a message may take before the rest is cut (and pasted, when a
+    /// paste service is configured).
+    fn max_lines(self) -> usize {
+        match self {
+            Verbosity::Quiet => 4,
+            Verbosity::Normal => 20,
+            Verbosity::Verbose => usize::MAX,
+        }
+    }
+
+    /// Lines a code block may take inline before it is pasted instead.
+    fn inline_code_lines(self) -> usize {
+        match self {
+            Verbosity::Quiet => 0,
+            Verbosity::Normal => 12,
+            Verbosity::Verbose => 60,
+        }
+    }
+}
+
+impl std::fmt::Display for Verbosity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Verbosity::Quiet => write!(f, "quiet"),
+            Verbosity::Normal => write!(f, "normal"),
+            Verbosity::Verbose => write!(f, "verbose"),
+        }
+    }
+}
+
+impl std::str::FromStr for Verbosity {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "quiet" => Ok(Verbosity::Quiet),
+            "normal" => Ok(Verbosity::Normal),
+            "verbose" => Ok(Verbosity::Verbose),
+            other => anyhow::bail!("unknown verbosity '{other}' (quiet, normal, verbose)"),
+        }
+    }
+}
+
+/// The server's upload endpoint (`POST /api/v1/upload`), used to paste
+/// code and long output instead of flooding the channel.
+#[derive(Debug, Clone)]
+pub struct PasteService {
+    /// Server web base URL, e.g. `https://irc.freeq.at`.
+    pub base_url: String,
+    /// DID the uploads are made as. The server accepts them while this
+    /// DID has a session, or with `upload_token`.
+    pub did: String,
+    /// `X-Upload-Token` minted for `did`, if any.
+    pub upload_token: Option<String>,
+}
+
+impl PasteService {
+    /// Upload `content` as a text file; returns its URL.
+    pub async fn upload(
+        &self,
+        channel: &str,
+        filename: &str,
+        content: &str,
+    ) -> anyhow::Result<String> {
+        self.upload_file(channel, filename, "text/plain; charset=utf-8", content)
+            .await
+    }
+
+    /// Upload `content` as a file of type `mime`; returns its URL.
+    pub async fn upload_file(
+        &self,
+        channel: &str,
+        filename: &str,
+        mime: &str,
+        content: &str,
+    ) -> anyhow::Result<String> {
+        self.upload_bytes(channel, filename, mime, content.as_bytes().to_vec())
+            .await
+    }
+
+    /// Upload binary `content` (e.g. a rendered image) as a file of type
+    /// `mime`; returns its URL.
+    pub async fn upload_bytes(
+        &self,
+        channel: &str,
+        filename: &str,
+        mime: &str,
+        content: Vec<u8>,
+    ) -> anyhow::Result<String> {
+        let part = reqwest::multipart::Part::bytes(content)
+            .file_name(filename.to_string())
+            .mime_str(mime)?;
+        let form = reqwest::multipart::Form::new()
+            .part("file", part)
+            .text("did", self.did.clone())
+            .text("channel", channel.to_string());
+        let url = format!("{}/api/v1/upload", self.base_url.trim_end_matches('/'));
+        let mut req = reqwest::Client::new().post(url).multipart(form);
+        if let Some(token) = &self.upload_token {
+            req = req.header("x-upload-token", token);
+        }
+        let resp = req.send().await?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let text = resp.text().await.unwrap_or_default();
+            anyhow::bail!("paste upload failed ({status}): {text}");
+        }
+        let body: serde_json::Value = resp.json().await?;
+        body["url"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow::anyhow!("paste upload returned no url"))
+    }
+}
+
+#[derive(Default)]
+struct Settings {
+    paste: Option<PasteService>,
+    diagrams: Option<Renderer>,
+    /// Keyed by lowercased channel.
+    verbosity: HashMap<String, Verbosity>,
+    status_priority: Priority,
+}
+
+static SETTINGS: LazyLock<RwLock<Settings>> = LazyLock::new(Default::default);
+
+/// Set (or clear) the paste service used for long code and output.
+pub fn set_paste_service(paste: Option<PasteService>) {
+    SETTINGS.write().unwrap_or_else(|e| e.into_inner()).paste = paste;
+}
+
+fn paste_service() -> Option<PasteService> {
+    SETTINGS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .paste
+        .clone()
+}
+
+/// Set (or clear) the renderer for Mermaid diagrams; see [`diagrams`].
+pub fn set_diagram_renderer(renderer: Option<Renderer>) {
+    SETTINGS.write().unwrap_or_else(|e| e.into_inner()).diagrams = renderer;
+}
+
+fn diagram_renderer() -> Option<Renderer> {
+    SETTINGS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .diagrams
+        .clone()
+}
+
+/// Whether long output is uploaded to a paste service.
+pub fn has_paste_service() -> bool {
+    SETTINGS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .paste
+        .is_some()
+}
+
+/// Set how much agents say in `channel`.
+pub fn set_verbosity(channel: &str, verbosity: Verbosity) {
+    SETTINGS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .verbosity
+        .insert(channel.to_lowercase(), verbosity);
+}
+
+/// How much agents say in `channel`.
+pub fn verbosity(channel: &str) -> Verbosity {
+    SETTINGS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .verbosity
+        .get(&channel.to_lowercase())
+        .copied()
+        .unwrap_or_default()
+}
+
+/// Set the priority [`status`] lines go out at. `Low` lets capable
+/// clients fold them away, but the server only takes it from a signed-in
+/// agent; the bot switches it with [`crate::features::Features::adapt`].
+pub fn set_status_priority(priority: Priority) {
+    SETTINGS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .status_priority = priority;
+}
+
+/// The priority [`status`] lines go out at.
+pub fn status_priority() -> Priority {
+    SETTINGS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .status_priority
+}
+
+/// `[role]`, colored when the agent has a color.
+fn role_prefix(agent: &AgentId) -> String {
+    match agent.color {
+        Some(c) => format!("\x03{c:02}[{}]\x03", agent.role),
+        None => format!("[{}]", agent.role),
+    }
+}
+
+/// Bytes of message text that fit in one PRIVMSG to `target`.
+fn line_budget(target: &str) -> usize {
+    IRC_LINE_BYTES - HOSTMASK_RESERVE - "PRIVMSG  :\r\n".len() - target.len()
+}
+
+/// Wrap `line` at word boundaries into pieces of at most `max` bytes.
+/// Words longer than `max` are split on char boundaries.
+fn wrap(line: &str, max: usize) -> Vec<String> {
+    let max = max.max(4);
+    if line.len() <= max {
+        return vec![line.to_string()];
+    }
+    let mut out = Vec::new();
+    let mut cur = String::new();
+    // Whether a word has gone into `cur` yet. Empty words (from leading
+    // spaces) count, so code indentation survives.
+    let mut started = false;
+    for word in line.split(' ') {
+        let sep = usize::from(started);
+        if cur.len() + sep + word.len() <= max {
+            if started {
+                cur.push(' ');
+            }
+            cur.push_str(word);
+            started = true;
+            continue;
+        }
+        if !cur.is_empty() {
+            out.push(std::mem::take(&mut cur));
+        }
+        started = true;
+        let mut rest = word;
+        while rest.len() > max {
+            let mut end = max;
+            while !rest.is_char_boundary(end) {
+                end -= 1;
+            }
+            out.push(rest[..end].to_string());
+            rest = &rest[end..];
+        }
+        cur.push_str(rest);
+    }
+    out.push(cur);
+    out
+}
+
+/// A stretch of agent output.
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Prose(String),
+    /// A fenced code block. `lang` is the fence's info string.
+    Code {
+        lang: String,
+        body: String,
+    },
+    /// A code block that went to the paste service.
+    Pasted {
+        lang: String,
+        lines: usize,
+        url: String,
+    },
+}
+
+/// Split markdown-ish text into prose and ```-fenced code blocks. An
+/// unterminated fence runs to the end.
+fn segments(text: &str) -> Vec<Segment> {
+    let mut out = Vec::new();
+    let mut prose: Vec<&str> = Vec::new();
+    let mut code: Option<(String, Vec<&str>)> = None;
+    for line in text.lines() {
+        let fence = line.trim_start().strip_prefix("```");
+        match (&mut code, fence) {
+            (None, Some(info)) => {
+                if !prose.is_empty() {
+                    out.push(Segment::Prose(prose.join("\n")));
+                    prose.clear();
+                }
+                code = Some((info.trim().to_string(), Vec::new()));
+            }
+            (Some(_), Some(_)) => {
+                let (lang, body) = code.take().unwrap_or_default();
+                out.push(Segment::Code {
+                    lang,
+                    body: body.join("\n"),
+                });
+            }
+            (Some((_, body)), None) => body.push(line),
+            (None, None) => prose.push(line),
+        }
+    }
+    if let Some((lang, body)) = code {
+        out.push(Segment::Code {
+            lang,
+            body: body.join("\n"),
+        });
+    }
+    if !prose.is_empty() {
+        out.push(Segment::Prose(prose.join("\n")));
+    }
+    out
+}
+
+/// Lay segments out as wire lines: prefix on the first, code indented
+/// (and cut at the verbosity's inline limit), every line wrapped to
+/// `budget` bytes.
+fn render(prefix: &str, segs: &[Segment], verbosity: Verbosity, budget: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for seg in segs {
+        match seg {
+            Segment::Prose(text) => lines.extend(text.lines().map(str::to_string)),
+            Segment::Code { body, .. } => {
+                let code: Vec<&str> = body.lines().collect();
+                let shown = code.len().min(verbosity.inline_code_lines());
+                lines.extend(code[..shown].iter().map(|l| format!("  {l}")));
+                if shown < code.len() {
+                    lines.push(format!("  ... ({} more lines)", code.len() - shown));
+                }
+            }
+            Segment::Pasted {
+                lang,
+                lines: n,
+                url,
+            } => {
+                let what = if lang.is_empty() { "code" } else { lang };
+                lines.push(format!("📎 {what} ({n} lines): {url}"));
+            }
+        }
+    }
+    while lines.last().is_some_and(|l| l.trim().is_empty()) {
+        lines.pop();
+    }
+    match lines.first_mut() {
+        Some(first) => *first = format!("{prefix} {first}"),
+        None => lines.push(prefix.to_string()),
+    }
+    lines.iter().flat_map(|l| wrap(l, budget)).collect()
+}
+
+/// Cut `lines` to `max`, noting what was left out and where to find it.
+fn cap_lines(lines: &mut Vec<String>, max: usize, full: Option<&str>) {
+    if lines.len() <= max {
+        return;
+    }
+    let more = lines.len() - max;
+    lines.truncate(max);
+    lines.push(match full {
+        Some(url) => format!("... ({more} more lines) full text: {url}"),
+        None => format!("... ({more} more lines)"),
+    });
+}
+
+/// File extension for a code fence's info string.
+fn extension(lang: &str) -> &str {
+    match lang {
+        "rust" => "rs",
+        "python" => "py",
+        "javascript" => "js",
+        "typescript" => "ts",
+        "shell" | "bash" | "sh" => "sh",
+        "" => "txt",
+        other => other,
+    }
+}
+
+/// Drop mIRC color and formatting codes.
+pub fn strip_formatting(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x03' => {
+                // Up to two foreground digits, then optionally ,bg.
+                for _ in 0..2 {
+                    chars.next_if(char::is_ascii_digit);
+                }
+                if chars.peek() == Some(&',') {
+                    let mut ahead = chars.clone();
+                    ahead.next();
+                    if ahead.peek().is_some_and(char::is_ascii_digit) {
+                        chars.next();
+                        for _ in 0..2 {
+                            chars.next_if(char::is_ascii_digit);
+                        }
+                    }
+                }
+            }
+            '\x02' | '\x0f' | '\x11' | '\x16' | '\x1d' | '\x1e' | '\x1f' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Post a message to a channel with agent role prefix. Fenced code longer
+/// than the channel's [`Verbosity`] allows goes to the paste service (when
+/// one is set) and is linked; the rest is wrapped to fit IRC lines and sent
+/// as ONE logical message — a `draft/multiline` BATCH when the server
+/// supports it, so edits and reactions apply to the whole response.
 pub async fn say(
-    handle: &ClientHandle,
+    sink: &dyn OutputSink,
     channel: &str,
     agent: &AgentId,
     text: &str,
 ) -> anyhow::Result<()> {
-    let msg = format!("[{}] {}", agent.role, text);
-    handle.privmsg(channel, &msg).await
+    let verbosity = verbosity(channel);
+    let paste = paste_service();
+    let mut segs = segments(text);
+    if let Some(paste) = &paste {
+        for seg in &mut segs {
+            let Segment::Code { lang, body } = seg else {
+                continue;
+            };
+            let lines = body.lines().count();
+            if lines <= verbosity.inline_code_lines() {
+                continue;
+            }
+            let filename = format!("snippet.{}", extension(lang));
+            match paste.upload(channel, &filename, body).await {
+                Ok(url) => {
+                    *seg = Segment::Pasted {
+                        lang: std::mem::take(lang),
+                        lines,
+                        url,
+                    }
+                }
+                Err(e) => tracing::warn!(error = %e, "Paste upload failed, inlining code"),
+            }
+        }
+    }
+
+    let mut lines = render(&role_prefix(agent), &segs, verbosity, line_budget(channel));
+    let max = verbosity.max_lines();
+    let mut full = None;
+    if lines.len() > max
+        && let Some(paste) = &paste
+    {
+        match paste
+            .upload(channel, &format!("{}.md", agent.role), text)
+            .await
+        {
+            Ok(url) => full = Some(url),
+            Err(e) => tracing::warn!(error = %e, "Paste upload failed, truncating"),
+        }
+    }
+    cap_lines(&mut lines, max, full.as_deref());
+    sink.send(channel, agent, &lines).await
 }
 
-/// Post a status update (brief, one-line).
+/// Post a status update (brief, one-line), at the
+/// [`status_priority`].
 pub async fn status(
-    handle: &ClientHandle,
+    sink: &dyn OutputSink,
     channel: &str,
     agent: &AgentId,
     emoji: &str,
     text: &str,
 ) -> anyhow::Result<()> {
-    let msg = format!("[{}] {} {}", agent.role, emoji, text);
-    handle.privmsg(channel, &msg).await
+    let msg = format!("{} {} {}", role_prefix(agent), emoji, text);
+    sink.send_status(channel, agent, &wrap(&msg, line_budget(channel)))
+        .await
 }
 
 /// Post a code block (multi-line, formatted for readability). Sends
-/// a status header PRIVMSG, then ONE multi-line PRIVMSG carrying the
-/// indented body — the SDK BATCH-routes the body so it stays one
-/// logical message rather than N flood-rate-limited per-line PRIVMSGs.
+/// a status header PRIVMSG, then the indented body — or, when it runs
+/// past `max_lines` or the channel's [`Verbosity`] and a paste service is
+/// set, a link to the whole file.
 pub async fn code(
-    handle: &ClientHandle,
+    sink: &dyn OutputSink,
     channel: &str,
     agent: &AgentId,
     filename: &str,
@@ -57,11 +538,22 @@ pub async fn code(
     max_lines: usize,
 ) -> anyhow::Result<()> {
     let lines: Vec<&str> = content.lines().collect();
+    let max_lines = max_lines.min(verbosity(channel).inline_code_lines());
     let truncated = lines.len() > max_lines;
     let show_lines = if truncated { max_lines } else { lines.len() };
 
+    if truncated && let Some(paste) = paste_service() {
+        match paste.upload(channel, filename, content).await {
+            Ok(url) => {
+                let header = format!("{filename} ({} lines): {url}", lines.len());
+                return status(sink, channel, agent, "📄", &header).await;
+            }
+            Err(e) => tracing::warn!(error = %e, "Paste upload failed, inlining code"),
+        }
+    }
+
     status(
-        handle,
+        sink,
         channel,
         agent,
         "📄",
@@ -69,26 +561,124 @@ pub async fn code(
     )
     .await?;
 
-    let mut body = lines[..show_lines]
+    if show_lines == 0 {
+        return Ok(());
+    }
+    let mut body: Vec<String> = lines[..show_lines]
         .iter()
-        .map(|l| format!("  {l}"))
-        .collect::<Vec<_>>()
-        .join("\n");
+        .flat_map(|l| wrap(&format!("  {l}"), line_budget(channel)))
+        .collect();
     if truncated {
-        body.push_str(&format!("\n  ... ({} more lines)", lines.len() - max_lines));
+        body.push(format!("  ... ({} more lines)", lines.len() - max_lines));
+    }
+    sink.send(channel, agent, &body).await
+}
+
+/// Post a file (e.g. a machine-readable report) as an attachment: uploaded
+/// to the paste service and linked, or posted as a [`code`] block when no
+/// service is set or the upload fails.
+pub async fn attachment(
+    sink: &dyn OutputSink,
+    channel: &str,
+    agent: &AgentId,
+    filename: &str,
+    mime: &str,
+    content: &str,
+) -> anyhow::Result<()> {
+    if let Some(paste) = paste_service() {
+        match paste.upload_file(channel, filename, mime, content).await {
+            Ok(url) => {
+                return status(sink, channel, agent, "📎", &format!("{filename}: {url}")).await;
+            }
+            Err(e) => tracing::warn!(error = %e, "Attachment upload failed, inlining it"),
+        }
     }
-    handle.privmsg(channel, &body).await
+    code(sink, channel, agent, filename, content, 40).await
+}
+
+/// The bodies of the ```` ```mermaid ```` blocks in `text`.
+pub fn mermaid_blocks(text: &str) -> Vec<String> {
+    segments(text)
+        .into_iter()
+        .filter_map(|seg| match seg {
+            Segment::Code { lang, body }
+                if lang.eq_ignore_ascii_case("mermaid") && !body.trim().is_empty() =>
+            {
+                Some(body)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Attach rendered images of the Mermaid blocks in `text`, a message
+/// already posted: each image is uploaded through the paste service and
+/// linked along with its `.mmd` source, for editing. Does nothing without
+/// a diagram renderer and a paste service — the source in the message
+/// stands on its own — and a diagram that fails to render is skipped.
+pub async fn diagrams(
+    sink: &dyn OutputSink,
+    channel: &str,
+    agent: &AgentId,
+    text: &str,
+) -> anyhow::Result<()> {
+    let (Some(renderer), Some(paste)) = (diagram_renderer(), paste_service()) else {
+        return Ok(());
+    };
+    let blocks = mermaid_blocks(text);
+    for (i, source) in blocks.iter().enumerate() {
+        let name = if blocks.len() == 1 {
+            format!("{}-diagram", agent.role)
+        } else {
+            format!("{}-diagram-{}", agent.role, i + 1)
+        };
+        let image = match renderer.render(source).await {
+            Ok(image) => image,
+            Err(e) => {
+                tracing::warn!(error = %e, %name, "Diagram render failed");
+                status(
+                    sink,
+                    channel,
+                    agent,
+                    "⚠️",
+                    &format!("Couldn't render {name}; its Mermaid source is above"),
+                )
+                .await?;
+                continue;
+            }
+        };
+        let filename = format!("{name}.{}", renderer.format.extension());
+        let image_url = match paste
+            .upload_bytes(channel, &filename, renderer.format.mime(), image)
+            .await
+        {
+            Ok(url) => url,
+            Err(e) => {
+                tracing::warn!(error = %e, %name, "Diagram upload failed");
+                continue;
+            }
+        };
+        let line = match paste.upload(channel, &format!("{name}.mmd"), source).await {
+            Ok(source_url) => format!("{filename}: {image_url} · Mermaid source: {source_url}"),
+            Err(e) => {
+                tracing::warn!(error = %e, %name, "Diagram source upload failed");
+                format!("{filename}: {image_url}")
+            }
+        };
+        status(sink, channel, agent, "🗺️", &line).await?;
+    }
+    Ok(())
 }
 
 /// Post a file listing — status header + one multi-line body PRIVMSG.
 pub async fn file_tree(
-    handle: &ClientHandle,
+    sink: &dyn OutputSink,
     channel: &str,
     agent: &AgentId,
     files: &[String],
 ) -> anyhow::Result<()> {
     status(
-        handle,
+        sink,
         channel,
         agent,
         "📁",
@@ -96,52 +686,63 @@ pub async fn file_tree(
     )
     .await?;
 
-    let mut body = files
-        .iter()
-        .take(20)
-        .map(|f| format!("  {f}"))
-        .collect::<Vec<_>>()
-        .join("\n");
+    let mut body: Vec<String> = files.iter().take(20).map(|f| format!("  {f}")).collect();
     if files.len() > 20 {
-        body.push_str(&format!("\n  ... and {} more", files.len() - 20));
+        body.push(format!("  ... and {} more", files.len() - 20));
     }
-    handle.privmsg(channel, &body).await
+    sink.send(channel, agent, &body).await
 }
 
 /// Post a deploy result with the URL highlighted.
 pub async fn deploy_result(
-    handle: &ClientHandle,
+    sink: &dyn OutputSink,
     channel: &str,
     agent: &AgentId,
     url: &str,
 ) -> anyhow::Result<()> {
-    status(handle, channel, agent, "🚀", &format!("Deployed → {url}")).await
+    status(sink, channel, agent, "🚀", &format!("Deployed → {url}")).await
 }
 
 /// Post an error.
 pub async fn error(
-    handle: &ClientHandle,
+    sink: &dyn OutputSink,
     channel: &str,
     agent: &AgentId,
     text: &str,
 ) -> anyhow::Result<()> {
-    status(handle, channel, agent, "❌", text).await
+    status(sink, channel, agent, "❌", text).await
 }
 
 /// Stream an LLM response to a channel, updating a single message in real-time.
 ///
 /// Uses the IRC edit-message hack: sends an initial message, then repeatedly
 /// edits it as tokens arrive from the LLM stream. Clients that support
-/// `+draft/edit` see the message update in place.
+/// `+draft/edit` see the message update in place. Sinks without an IRC
+/// connection get the finished response as one [`say`].
 ///
-/// Returns the final message text and msgid.
+/// Returns the final message text and msgid (empty off IRC).
 pub async fn stream_response(
-    handle: &ClientHandle,
+    sink: &dyn OutputSink,
     channel: &str,
     agent: &AgentId,
     mut deltas: mpsc::Receiver<StreamDelta>,
 ) -> anyhow::Result<(String, String)> {
-    let prefix = format!("[{}] ", agent.role);
+    let Some(handle) = sink.irc() else {
+        let mut full_text = String::new();
+        while let Some(delta) = deltas.recv().await {
+            match delta {
+                StreamDelta::Text(chunk) => full_text.push_str(&chunk),
+                StreamDelta::Done => break,
+                StreamDelta::Error(e) => {
+                    error(sink, channel, agent, &format!("Stream error: {e}")).await?;
+                    anyhow::bail!("LLM stream error: {e}");
+                }
+            }
+        }
+        say(sink, channel, agent, &full_text).await?;
+        return Ok((full_text, String::new()));
+    };
+    let prefix = format!("{} ", role_prefix(agent));
 
     // Start a streaming message with a thinking cursor
     let mut stream = StreamingMessage::start(handle, channel).await?;
@@ -168,3 +769,136 @@ pub async fn stream_response(
     let msgid = stream.finish_with(&final_text).await?;
     Ok((full_text, msgid))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_breaks_at_words_and_splits_long_ones() {
+        assert_eq!(wrap("one two three", 7), vec!["one two", "three"]);
+        assert_eq!(wrap("abcdefghij", 4), vec!["abcd", "efgh", "ij"]);
+        assert_eq!(wrap("", 10), vec![""]);
+        // Never splits inside a multi-byte char.
+        for piece in wrap(&"é".repeat(10), 5) {
+            assert!(piece.len() <= 5);
+        }
+    }
+
+    #[test]
+    fn every_rendered_line_fits_the_irc_limit() {
+        let channel = "#factory";
+        let budget = line_budget(channel);
+        let text = "word ".repeat(500);
+        let lines = render("[architect]", &segments(&text), Verbosity::Verbose, budget);
+        assert!(lines.len() > 1);
+        for line in &lines {
+            let wire = format!(
+                ":{} PRIVMSG {channel} :{line}\r\n",
+                "x".repeat(HOSTMASK_RESERVE - 2)
+            );
+            assert!(wire.len() <= IRC_LINE_BYTES, "{} bytes", wire.len());
+        }
+        assert!(lines[0].starts_with("[architect] word"));
+    }
+
+    #[test]
+    fn segments_split_out_fenced_code() {
+        let segs = segments("Here:\n```rust\nfn main() {}\n```\nDone.");
+        assert_eq!(
+            segs,
+            vec![
+                Segment::Prose("Here:".into()),
+                Segment::Code {
+                    lang: "rust".into(),
+                    body: "fn main() {}".into()
+                },
+                Segment::Prose("Done.".into()),
+            ]
+        );
+        let open = segments("```\nno end");
+        assert_eq!(
+            open,
+            vec![Segment::Code {
+                lang: String::new(),
+                body: "no end".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn mermaid_blocks_are_picked_out_of_the_text() {
+        let text = "Design:\n```mermaid\ngraph TD\n  A-->B\n```\n```rust\nfn x() {}\n```\n```Mermaid\n```\n```mermaid\nsequenceDiagram";
+        assert_eq!(
+            mermaid_blocks(text),
+            vec!["graph TD\n  A-->B".to_string(), "sequenceDiagram".into()]
+        );
+        assert!(mermaid_blocks("no diagrams here").is_empty());
+    }
+
+    #[test]
+    fn code_is_cut_at_the_verbosity_limit_or_linked() {
+        let body = (1..=30).map(|i| format!("line {i}")).collect::<Vec<_>>();
+        let segs = vec![Segment::Code {
+            lang: "rust".into(),
+            body: body.join("\n"),
+        }];
+        let lines = render("[b]", &segs, Verbosity::Normal, 400);
+        assert_eq!(lines.len(), 13);
+        assert_eq!(lines[0], "[b]   line 1");
+        assert_eq!(lines[12], "  ... (18 more lines)");
+
+        let pasted = vec![Segment::Pasted {
+            lang: "rust".into(),
+            lines: 30,
+            url: "https://x/a.rs".into(),
+        }];
+        assert_eq!(
+            render("[b]", &pasted, Verbosity::Quiet, 400),
+            vec!["[b] 📎 rust (30 lines): https://x/a.rs"]
+        );
+    }
+
+    #[test]
+    fn formatting_is_stripped() {
+        assert_eq!(
+            strip_formatting("\x0306[qa]\x03 all \x02good\x02"),
+            "[qa] all good"
+        );
+        assert_eq!(strip_formatting("\x0304,01red\x0f, 5"), "red, 5");
+        assert_eq!(strip_formatting("\x03,x"), ",x");
+    }
+
+    #[test]
+    fn cap_lines_notes_the_overflow() {
+        let mut lines: Vec<String> = (0..10).map(|i| i.to_string()).collect();
+        cap_lines(&mut lines, 4, Some("https://x/full.md"));
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[4], "... (6 more lines) full text: https://x/full.md");
+    }
+
+    #[test]
+    fn role_prefix_uses_mirc_colors() {
+        let agent = AgentId {
+            role: "qa".into(),
+            color: Some(color::PURPLE),
+        };
+        assert_eq!(role_prefix(&agent), "\x0306[qa]\x03");
+        assert_eq!(
+            role_prefix(&AgentId {
+                role: "qa".into(),
+                color: None
+            }),
+            "[qa]"
+        );
+    }
+
+    #[test]
+    fn verbosity_parses_and_is_per_channel() {
+        assert_eq!("Quiet".parse::<Verbosity>().unwrap(), Verbosity::Quiet);
+        assert!("loud".parse::<Verbosity>().is_err());
+        set_verbosity("#Output-Test", Verbosity::Verbose);
+        assert_eq!(verbosity("#output-test"), Verbosity::Verbose);
+        assert_eq!(verbosity("#elsewhere"), Verbosity::Normal);
+    }
+}
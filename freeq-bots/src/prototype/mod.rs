@@ -11,11 +11,12 @@
 use anyhow::Result;
 use std::path::Path;
 
-use crate::llm::{ContentBlock, LlmClient, Message, MessageContent, ToolResultBlock};
+use crate::artifacts::Artifacts;
+use crate::llm::{ContentBlock, LlmClient, Message, MessageContent, Task, ToolResultBlock};
 use crate::memory::Memory;
 use crate::output::{self, AgentId};
+use crate::sink::OutputSink;
 use crate::tools::{self, Workspace};
-use freeq_sdk::client::ClientHandle;
 
 const SYSTEM_PROMPT: &str = r#"You are a rapid prototype builder. Given a product spec, you build a working, deployable application.
 
@@ -48,36 +49,38 @@ Work step by step:
 fn architect() -> AgentId {
     AgentId {
         role: "architect".to_string(),
-        color: None,
+        color: Some(output::color::BLUE),
     }
 }
 fn builder() -> AgentId {
     AgentId {
         role: "builder".to_string(),
-        color: None,
+        color: Some(output::color::GREEN),
     }
 }
 fn deployer() -> AgentId {
     AgentId {
         role: "deploy".to_string(),
-        color: None,
+        color: Some(output::color::TEAL),
     }
 }
 
-/// Run the prototype pipeline for a spec.
+/// Run the prototype pipeline for a spec. With `artifacts`, the generated
+/// code is linked in-channel alongside the deploy URL.
 pub async fn build(
-    handle: &ClientHandle,
+    sink: &dyn OutputSink,
     channel: &str,
     spec: &str,
     llm: &LlmClient,
     memory: &Memory,
     workspace_base: &Path,
+    artifacts: Option<&Artifacts>,
 ) -> Result<Option<String>> {
     // Generate a project name from the spec
     let project_name = generate_project_name(llm, spec).await?;
 
     output::status(
-        handle,
+        sink,
         channel,
         &architect(),
         "🔍",
@@ -109,7 +112,7 @@ pub async fn build(
         iteration += 1;
         if iteration > MAX_ITERATIONS {
             output::error(
-                handle,
+                sink,
                 channel,
                 &builder(),
                 "Max iterations reached, stopping",
@@ -118,7 +121,10 @@ pub async fn build(
             break;
         }
 
-        let resp = llm.chat(SYSTEM_PROMPT, &messages, &tools, 4096).await?;
+        let resp = llm
+            .for_task(Task::Build)
+            .chat(SYSTEM_PROMPT, &messages, &tools, 4096)
+            .await?;
 
         // Collect text and tool uses from response
         let mut text_parts = Vec::new();
@@ -145,7 +151,7 @@ pub async fn build(
             } else {
                 commentary.clone()
             };
-            output::say(handle, channel, &builder(), &short).await?;
+            output::say(sink, channel, &builder(), &short).await?;
         }
 
         // If no tool uses, we're done
@@ -176,14 +182,8 @@ pub async fn build(
             match tu.name.as_str() {
                 "write_file" => {
                     let path = tu.input["path"].as_str().unwrap_or("?");
-                    output::status(
-                        handle,
-                        channel,
-                        &builder(),
-                        "✏️",
-                        &format!("Writing {path}"),
-                    )
-                    .await?;
+                    output::status(sink, channel, &builder(), "✏️", &format!("Writing {path}"))
+                        .await?;
                 }
                 "shell" => {
                     let cmd = tu.input["command"].as_str().unwrap_or("?");
@@ -193,7 +193,7 @@ pub async fn build(
                         cmd.to_string()
                     };
                     output::status(
-                        handle,
+                        sink,
                         channel,
                         &builder(),
                         "⚙️",
@@ -202,11 +202,11 @@ pub async fn build(
                     .await?;
                 }
                 "deploy" => {
-                    output::status(handle, channel, &deployer(), "🚀", "Deploying to miren...")
+                    output::status(sink, channel, &deployer(), "🚀", "Deploying to miren...")
                         .await?;
                 }
                 "list_files" => {
-                    output::status(handle, channel, &builder(), "📁", "Listing files").await?;
+                    output::status(sink, channel, &builder(), "📁", "Listing files").await?;
                 }
                 _ => {}
             }
@@ -218,7 +218,7 @@ pub async fn build(
                         && let Some(url) = extract_deploy_url(&output)
                     {
                         deployed_url = Some(url.clone());
-                        output::deploy_result(handle, channel, &deployer(), &url).await?;
+                        output::deploy_result(sink, channel, &deployer(), &url).await?;
                         memory.set(&project_name, "deploy", "url", &url)?;
                     }
 
@@ -235,7 +235,7 @@ pub async fn build(
                 Err(e) => {
                     let err = format!("Error: {e}");
                     output::error(
-                        handle,
+                        sink,
                         channel,
                         &builder(),
                         &format!("Tool {} failed: {e}", tu.name),
@@ -262,7 +262,7 @@ pub async fn build(
     // Final summary
     if let Some(ref url) = deployed_url {
         output::status(
-            handle,
+            sink,
             channel,
             &deployer(),
             "✅",
@@ -271,7 +271,7 @@ pub async fn build(
         .await?;
     } else {
         output::status(
-            handle,
+            sink,
             channel,
             &builder(),
             "⚠️",
@@ -279,6 +279,17 @@ pub async fn build(
         )
         .await?;
     }
+    if let Some(artifacts) = artifacts {
+        let url = artifacts.url(&workspace.project_name);
+        output::status(
+            sink,
+            channel,
+            &builder(),
+            "📂",
+            &format!("Browse the code: {url}"),
+        )
+        .await?;
+    }
 
     memory.log(&project_name, "event", "Build complete")?;
     Ok(deployed_url)
@@ -292,6 +303,7 @@ pub async fn generate_project_name_pub(llm: &LlmClient, spec: &str) -> Result<St
 /// Generate a short project name from a spec.
 async fn generate_project_name(llm: &LlmClient, spec: &str) -> Result<String> {
     let name = llm
+        .for_task(Task::Chat)
         .complete(
             "You generate short, lowercase, hyphenated project names. Respond with ONLY the name, nothing else. Max 20 chars.",
             &format!("Generate a project name for:\n{spec}"),
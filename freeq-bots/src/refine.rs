@@ -0,0 +1,375 @@
+//! Spec refinement before a prototype build.
+//!
+//! `/prototype <idea>` builds straight from a one-liner. With
+//! `/prototype --refine <idea>` the product lead first asks a few
+//! clarifying questions in the channel, one at a time; the requester's next
+//! messages answer them (`skip` stops the questions early). The answers are
+//! worked into a structured spec, stored in [`Memory`] under the channel,
+//! and shown. The build only starts when the requester says `/approve`;
+//! `/cancel` drops the spec.
+//!
+//! A refinement nobody touches for [`REFINE_WINDOW`] is forgotten.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use crate::llm::{LlmClient, Task};
+use crate::memory::Memory;
+use crate::output::{self, AgentId};
+use crate::sink::OutputSink;
+
+/// How long a refinement waits for its next answer or approval.
+pub const REFINE_WINDOW: Duration = Duration::from_secs(30 * 60);
+
+/// Memory kind the refined specs are stored under.
+const KIND: &str = "refinement";
+
+fn product() -> AgentId {
+    AgentId {
+        role: "product".to_string(),
+        color: Some(output::color::PINK),
+    }
+}
+
+/// One idea being refined into a spec.
+#[derive(Debug, Clone)]
+pub struct Refinement {
+    /// Who asked for it; only they answer and approve.
+    pub nick: String,
+    pub idea: String,
+    questions: Vec<String>,
+    answers: Vec<String>,
+    /// The written spec, once every question is answered.
+    spec: Option<String>,
+    at: Instant,
+}
+
+impl Refinement {
+    /// The questions answered so far, with their answers.
+    pub fn answered(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.questions
+            .iter()
+            .zip(&self.answers)
+            .map(|(q, a)| (q.as_str(), a.as_str()))
+    }
+
+    /// The question waiting for an answer, as `(number, total, text)`.
+    pub fn next_question(&self) -> Option<(usize, usize, &str)> {
+        if self.spec.is_some() {
+            return None;
+        }
+        let i = self.answers.len();
+        self.questions
+            .get(i)
+            .map(|q| (i + 1, self.questions.len(), q.as_str()))
+    }
+}
+
+/// What an answer leads to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// Ask the next question.
+    Ask,
+    /// Every question is answered (or skipped): write the spec.
+    WriteSpec,
+}
+
+/// Refinements in progress, one per channel.
+#[derive(Debug, Default)]
+pub struct Refinements {
+    /// Lowercased channel → its refinement.
+    by_channel: HashMap<String, Refinement>,
+}
+
+impl Refinements {
+    fn expire(&mut self, now: Instant) {
+        self.by_channel
+            .retain(|_, r| now.duration_since(r.at) < REFINE_WINDOW);
+    }
+
+    /// Start refining `idea` for `nick` in `channel` with `questions`,
+    /// replacing any refinement already there.
+    pub fn start(
+        &mut self,
+        channel: &str,
+        nick: &str,
+        idea: &str,
+        questions: Vec<String>,
+        now: Instant,
+    ) -> &Refinement {
+        self.expire(now);
+        let refinement = Refinement {
+            nick: nick.to_string(),
+            idea: idea.to_string(),
+            questions,
+            answers: Vec::new(),
+            spec: None,
+            at: now,
+        };
+        let key = channel.to_lowercase();
+        self.by_channel.insert(key.clone(), refinement);
+        &self.by_channel[&key]
+    }
+
+    /// The refinement in `channel`, if any.
+    pub fn get(&self, channel: &str) -> Option<&Refinement> {
+        self.by_channel.get(&channel.to_lowercase())
+    }
+
+    /// Take `text` from `from` as the answer to the open question in
+    /// `channel`, if it is one.
+    pub fn answer(&mut self, channel: &str, from: &str, text: &str, now: Instant) -> Option<Step> {
+        self.expire(now);
+        let r = self.by_channel.get_mut(&channel.to_lowercase())?;
+        if !r.nick.eq_ignore_ascii_case(from) || r.next_question().is_none() {
+            return None;
+        }
+        r.at = now;
+        if text.trim().eq_ignore_ascii_case("skip") {
+            r.questions.truncate(r.answers.len());
+            return Some(Step::WriteSpec);
+        }
+        r.answers.push(text.trim().to_string());
+        Some(if r.next_question().is_some() {
+            Step::Ask
+        } else {
+            Step::WriteSpec
+        })
+    }
+
+    /// Record the spec written for `channel`; it now waits for approval.
+    pub fn set_spec(&mut self, channel: &str, spec: &str, now: Instant) {
+        if let Some(r) = self.by_channel.get_mut(&channel.to_lowercase()) {
+            r.spec = Some(spec.to_string());
+            r.at = now;
+        }
+    }
+
+    /// `/approve` from `from`: the spec to build, or why not.
+    pub fn approve(&mut self, channel: &str, from: &str, now: Instant) -> Result<String, String> {
+        self.expire(now);
+        let key = channel.to_lowercase();
+        let Some(r) = self.by_channel.get(&key) else {
+            return Err(format!(
+                "{from}: nothing to approve; start with /prototype --refine <idea>"
+            ));
+        };
+        if !r.nick.eq_ignore_ascii_case(from) {
+            return Err(format!("{from}: only {} can approve this spec", r.nick));
+        }
+        if r.spec.is_none() {
+            return Err(format!(
+                "{from}: the spec isn't written yet; answer the questions first (or say skip)"
+            ));
+        }
+        Ok(self
+            .by_channel
+            .remove(&key)
+            .and_then(|r| r.spec)
+            .unwrap_or_default())
+    }
+
+    /// `/cancel` from `from`: drop their refinement in `channel`.
+    pub fn cancel(&mut self, channel: &str, from: &str) -> bool {
+        let key = channel.to_lowercase();
+        if self
+            .by_channel
+            .get(&key)
+            .is_some_and(|r| r.nick.eq_ignore_ascii_case(from))
+        {
+            self.by_channel.remove(&key);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Parse the product lead's questions: a JSON array of strings, possibly in
+/// prose or a code fence, else one question per line. At most `max`.
+pub fn parse_questions(reply: &str, max: usize) -> Vec<String> {
+    let json = reply
+        .find('[')
+        .zip(reply.rfind(']'))
+        .and_then(|(start, end)| reply.get(start..=end))
+        .and_then(|s| serde_json::from_str::<Vec<String>>(s).ok());
+    let questions = match json {
+        Some(questions) => questions,
+        None => reply
+            .lines()
+            .map(|l| {
+                l.trim()
+                    .trim_start_matches(|c: char| c.is_ascii_digit() || "-*.) ".contains(c))
+                    .to_string()
+            })
+            .filter(|l| l.ends_with('?'))
+            .collect(),
+    };
+    questions
+        .into_iter()
+        .map(|q| q.trim().to_string())
+        .filter(|q| !q.is_empty())
+        .take(max)
+        .collect()
+}
+
+/// Ask the LLM which (at most `max`) questions `idea` leaves open. None
+/// when it's clear enough to spec as it is.
+pub async fn questions(llm: &LlmClient, idea: &str, max: usize) -> Result<Vec<String>> {
+    if max == 0 {
+        return Ok(Vec::new());
+    }
+    let system = format!(
+        "You are a product lead about to spec a small prototype. Ask the clarifying questions whose answers would most change what gets built: users, core features, data, look and feel, integrations. At most {max}, each short and answerable in a sentence. Ask none if the idea is already clear. Reply with ONLY a JSON array of strings."
+    );
+    let reply = llm.for_task(Task::Plan).complete(&system, idea).await?;
+    Ok(parse_questions(&reply, max))
+}
+
+/// Write the structured spec for `refinement` from its idea and answers.
+pub async fn write_spec(llm: &LlmClient, refinement: &Refinement) -> Result<String> {
+    let mut prompt = format!("## Idea\n{}\n", refinement.idea);
+    let mut answered = refinement.answered().peekable();
+    if answered.peek().is_some() {
+        prompt.push_str("\n## Clarifications\n");
+        for (q, a) in answered {
+            prompt.push_str(&format!("Q: {q}\nA: {a}\n"));
+        }
+    }
+    llm.for_task(Task::Plan).complete(
+        "You are a product lead. Turn the idea and the requester's clarifications into a product spec with these sections: Purpose, Users, Core features (bulleted), Out of scope, Tech constraints, Success criteria. Use the answers; where something is still open, choose the simplest option and say so. Be specific but brief. Output ONLY the spec, no preamble.",
+        &prompt,
+    )
+    .await
+}
+
+/// Store `spec` and the questions behind it for `channel`.
+pub fn save(memory: &Memory, channel: &str, refinement: &Refinement, spec: &str) -> Result<()> {
+    let project = channel.to_lowercase();
+    let transcript: Vec<String> = refinement
+        .answered()
+        .map(|(q, a)| format!("Q: {q}\nA: {a}"))
+        .collect();
+    memory.set(&project, KIND, "idea", &refinement.idea)?;
+    memory.set(&project, KIND, "clarifications", &transcript.join("\n\n"))?;
+    memory.set(&project, KIND, "spec", spec)?;
+    memory.log(&project, "event", "Spec refined, waiting for approval")
+}
+
+/// Post the open question in `refinement` to its requester.
+pub async fn ask(sink: &dyn OutputSink, channel: &str, refinement: &Refinement) -> Result<()> {
+    if let Some((n, total, question)) = refinement.next_question() {
+        let text = format!("{}: ({n}/{total}) {question}", refinement.nick);
+        output::say(sink, channel, &product(), &text).await?;
+    }
+    Ok(())
+}
+
+/// Write, store and show the spec for the refinement in `channel`.
+pub async fn present(
+    sink: &dyn OutputSink,
+    channel: &str,
+    refinements: &mut Refinements,
+    llm: &LlmClient,
+    memory: &Memory,
+) -> Result<()> {
+    let Some(refinement) = refinements.get(channel).cloned() else {
+        return Ok(());
+    };
+    output::status(sink, channel, &product(), "📋", "Writing the spec...").await?;
+    let spec = write_spec(llm, &refinement).await?;
+    save(memory, channel, &refinement, &spec)?;
+    refinements.set_spec(channel, &spec, Instant::now());
+    output::say(sink, channel, &product(), &spec).await?;
+    output::say(
+        sink,
+        channel,
+        &product(),
+        &format!(
+            "{}: /approve to build this, /cancel to drop it",
+            refinement.nick
+        ),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn qs(n: usize) -> Vec<String> {
+        (1..=n).map(|i| format!("Question {i}?")).collect()
+    }
+
+    #[test]
+    fn questions_parse_from_json_or_lines() {
+        assert_eq!(
+            parse_questions("Here:\n```json\n[\"Who uses it?\", \"Dark mode?\"]\n```", 5),
+            vec!["Who uses it?", "Dark mode?"]
+        );
+        assert_eq!(
+            parse_questions("1. Who uses it?\n2) Do you need login?\nThanks", 5),
+            vec!["Who uses it?", "Do you need login?"]
+        );
+        assert_eq!(parse_questions("[\"a?\", \"b?\", \"c?\"]", 2).len(), 2);
+        assert!(parse_questions("[]", 3).is_empty());
+    }
+
+    #[test]
+    fn only_the_requester_answers_and_approves() {
+        let mut r = Refinements::default();
+        let t0 = Instant::now();
+        r.start("#f", "alice", "a chess clock", qs(2), t0);
+        assert_eq!(
+            r.get("#F").unwrap().next_question(),
+            Some((1, 2, "Question 1?"))
+        );
+
+        assert_eq!(r.answer("#f", "bob", "blue", t0), None);
+        assert!(
+            r.approve("#f", "alice", t0)
+                .unwrap_err()
+                .contains("isn't written")
+        );
+        assert_eq!(r.answer("#f", "Alice", "two players", t0), Some(Step::Ask));
+        assert_eq!(r.answer("#f", "alice", "  yes ", t0), Some(Step::WriteSpec));
+        assert_eq!(
+            r.answer("#f", "alice", "more", t0),
+            None,
+            "no open question"
+        );
+        assert_eq!(
+            r.get("#f").unwrap().answered().collect::<Vec<_>>(),
+            vec![("Question 1?", "two players"), ("Question 2?", "yes")]
+        );
+
+        r.set_spec("#f", "Purpose: time chess", t0);
+        assert!(
+            r.approve("#f", "bob", t0)
+                .unwrap_err()
+                .contains("only alice")
+        );
+        assert_eq!(r.approve("#f", "alice", t0).unwrap(), "Purpose: time chess");
+        assert!(r.get("#f").is_none());
+    }
+
+    #[test]
+    fn skip_ends_the_questions_and_idle_refinements_expire() {
+        let mut r = Refinements::default();
+        let t0 = Instant::now();
+        r.start("#f", "alice", "a todo app", qs(3), t0);
+        assert_eq!(r.answer("#f", "alice", "solo", t0), Some(Step::Ask));
+        assert_eq!(r.answer("#f", "alice", "Skip", t0), Some(Step::WriteSpec));
+        assert_eq!(r.get("#f").unwrap().answered().count(), 1);
+        assert_eq!(r.get("#f").unwrap().next_question(), None);
+
+        assert!(!r.cancel("#f", "bob"));
+        assert!(r.cancel("#f", "alice"));
+
+        r.start("#f", "alice", "a todo app", qs(1), t0);
+        assert_eq!(r.answer("#f", "alice", "late", t0 + REFINE_WINDOW), None);
+        assert!(r.get("#f").is_none());
+    }
+}
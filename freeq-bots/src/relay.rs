@@ -0,0 +1,603 @@
+//! Channel relay between freeq and a classic IRC network (Libera, OFTC, ...).
+//!
+//! The relay holds one [`ClientHandle`](freeq_sdk::client::ClientHandle)
+//! per network and mirrors messages between linked channels, prefixing
+//! each line with the speaker's nick. [`Relay`] is the routing logic only:
+//! it turns an [`Event`] from one side into lines to send on the other,
+//! so the `irc-relay` binary just shuttles events and lines around.
+//!
+//! XMPP MUCs are not supported; both sides must speak IRC.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use freeq_sdk::event::Event;
+use freeq_sdk::media::MediaAttachment;
+use freeq_sdk::proto::tags;
+use serde::Deserialize;
+
+/// Longest text (in bytes) put in one relayed line. Legacy networks cut
+/// lines at 512 bytes including the prefix and command.
+pub const MAX_LINE_BYTES: usize = 400;
+
+/// Relay configuration, loaded from TOML.
+///
+/// ```toml
+/// ignore_nicks = ["otherbridge"]
+///
+/// [freeq]
+/// server = "irc.freeq.at:6697"
+/// tls = true
+/// nick = "libera"
+///
+/// [remote]
+/// server = "irc.libera.chat:6697"
+/// tls = true
+/// nick = "freeq-relay"
+///
+/// [[channels]]
+/// freeq = "#freeq"
+/// remote = "#freeq"
+/// join_part = true
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct RelayConfig {
+    pub freeq: Endpoint,
+    pub remote: Endpoint,
+    /// Nicks never relayed, on either side (other bridges and bots).
+    #[serde(default)]
+    pub ignore_nicks: Vec<String>,
+    pub channels: Vec<ChannelLink>,
+}
+
+/// One network the relay connects to.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Endpoint {
+    /// Server address (host:port).
+    pub server: String,
+    #[serde(default)]
+    pub tls: bool,
+    pub nick: String,
+}
+
+/// A pair of channels mirrored into each other.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChannelLink {
+    pub freeq: String,
+    pub remote: String,
+    #[serde(default)]
+    pub direction: Direction,
+    /// Relay joins, parts, kicks, quits and nick changes. Off by default:
+    /// they are noisy and the other side can't see who is present anyway.
+    #[serde(default)]
+    pub join_part: bool,
+    /// Prefix for relayed lines; `{nick}` is replaced with the speaker.
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+}
+
+/// Which way messages flow through a link.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Direction {
+    #[default]
+    Both,
+    ToRemote,
+    ToFreeq,
+}
+
+fn default_prefix() -> String {
+    "<{nick}> ".to_string()
+}
+
+impl RelayConfig {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        let config: Self =
+            toml::from_str(&text).with_context(|| format!("parsing {}", path.display()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.channels.is_empty() {
+            bail!("no [[channels]] configured");
+        }
+        let mut freeq = HashSet::new();
+        let mut remote = HashSet::new();
+        for link in &self.channels {
+            if !link.freeq.starts_with('#') || !link.remote.starts_with('#') {
+                bail!(
+                    "{} ↔ {}: channels must start with #",
+                    link.freeq,
+                    link.remote
+                );
+            }
+            if !freeq.insert(link.freeq.to_lowercase()) {
+                bail!("{} is linked twice", link.freeq);
+            }
+            if !remote.insert(link.remote.to_lowercase()) {
+                bail!("{} is linked twice", link.remote);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// One of the two networks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Side {
+    Freeq,
+    Remote,
+}
+
+impl Side {
+    pub fn other(self) -> Side {
+        match self {
+            Side::Freeq => Side::Remote,
+            Side::Remote => Side::Freeq,
+        }
+    }
+}
+
+/// A line to send as PRIVMSG.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outgoing {
+    pub side: Side,
+    pub target: String,
+    pub text: String,
+}
+
+/// Routing state for a running relay.
+pub struct Relay {
+    config: RelayConfig,
+    /// Our confirmed nick on each side.
+    own_nicks: HashMap<Side, String>,
+    /// Lowercased nicks present in each lowercased channel, per side, so
+    /// quits and nick changes can be attributed to linked channels.
+    members: HashMap<Side, HashMap<String, HashSet<String>>>,
+    /// Open chathistory batches, whose replayed lines are not relayed.
+    history_batches: HashSet<String>,
+    ignore: HashSet<String>,
+}
+
+impl Relay {
+    pub fn new(config: RelayConfig) -> Self {
+        let own_nicks = HashMap::from([
+            (Side::Freeq, config.freeq.nick.clone()),
+            (Side::Remote, config.remote.nick.clone()),
+        ]);
+        let ignore = config
+            .ignore_nicks
+            .iter()
+            .map(|n| n.to_lowercase())
+            .collect();
+        Self {
+            config,
+            own_nicks,
+            members: HashMap::new(),
+            history_batches: HashSet::new(),
+            ignore,
+        }
+    }
+
+    pub fn config(&self) -> &RelayConfig {
+        &self.config
+    }
+
+    /// Channels to join on `side`.
+    pub fn channels(&self, side: Side) -> Vec<&str> {
+        self.config
+            .channels
+            .iter()
+            .map(|l| channel_on(l, side))
+            .collect()
+    }
+
+    /// Forget membership for `side`, e.g. after it reconnects.
+    pub fn reset(&mut self, side: Side) {
+        self.members.remove(&side);
+        if side == Side::Freeq {
+            self.history_batches.clear();
+        }
+    }
+
+    fn link(&self, side: Side, channel: &str) -> Option<&ChannelLink> {
+        self.config
+            .channels
+            .iter()
+            .find(|l| channel_on(l, side).eq_ignore_ascii_case(channel))
+    }
+
+    /// Whether lines from `nick` on `side` must not be relayed: ourselves
+    /// (the echo of what we relayed) and configured bots.
+    fn ignored(&self, side: Side, nick: &str) -> bool {
+        self.own_nicks
+            .get(&side)
+            .is_some_and(|own| own.eq_ignore_ascii_case(nick))
+            || self.ignore.contains(&nick.to_lowercase())
+    }
+
+    fn channel_members(&mut self, side: Side, channel: &str) -> &mut HashSet<String> {
+        self.members
+            .entry(side)
+            .or_default()
+            .entry(channel.to_lowercase())
+            .or_default()
+    }
+
+    /// Linked channels on `side` that `nick` is in, as (link, channel) pairs.
+    fn channels_with(&self, side: Side, nick: &str) -> Vec<(ChannelLink, String)> {
+        let nick = nick.to_lowercase();
+        let Some(channels) = self.members.get(&side) else {
+            return Vec::new();
+        };
+        channels
+            .iter()
+            .filter(|(_, nicks)| nicks.contains(&nick))
+            .filter_map(|(channel, _)| {
+                self.link(side, channel)
+                    .map(|l| (l.clone(), channel.clone()))
+            })
+            .collect()
+    }
+
+    /// Route one event from `side`. Returns the lines to send.
+    pub fn route(&mut self, side: Side, event: &Event) -> Vec<Outgoing> {
+        match event {
+            Event::Registered { nick } => {
+                self.own_nicks.insert(side, nick.clone());
+                Vec::new()
+            }
+            Event::BatchStart { id, batch_type, .. } => {
+                if batch_type.eq_ignore_ascii_case("chathistory") {
+                    self.history_batches.insert(id.clone());
+                }
+                Vec::new()
+            }
+            Event::BatchEnd { id } => {
+                self.history_batches.remove(id);
+                Vec::new()
+            }
+            Event::Names { channel, nicks } => {
+                let members = self.channel_members(side, channel);
+                for nick in nicks {
+                    // Strip membership prefixes (@, +, ...).
+                    let nick = nick.trim_start_matches(|c: char| "~&@%+".contains(c));
+                    members.insert(nick.to_lowercase());
+                }
+                Vec::new()
+            }
+            Event::Message {
+                from,
+                target,
+                text,
+                tags: msg_tags,
+                ..
+            } => {
+                let in_history = msg_tags
+                    .get(tags::BATCH)
+                    .is_some_and(|id| self.history_batches.contains(id));
+                if in_history || self.ignored(side, from) {
+                    return Vec::new();
+                }
+                let Some(link) = self.link(side, target) else {
+                    return Vec::new();
+                };
+                if !flows(link, side) {
+                    return Vec::new();
+                }
+                let text = relay_text(text, msg_tags);
+                format_lines(link, side.other(), from, &text)
+            }
+            Event::Joined { channel, nick, .. } => {
+                self.channel_members(side, channel)
+                    .insert(nick.to_lowercase());
+                self.membership(side, channel, nick, &format!("{nick} joined"))
+            }
+            Event::Parted { channel, nick } => {
+                self.channel_members(side, channel)
+                    .remove(&nick.to_lowercase());
+                self.membership(side, channel, nick, &format!("{nick} left"))
+            }
+            Event::Kicked {
+                channel,
+                nick,
+                by,
+                reason,
+            } => {
+                self.channel_members(side, channel)
+                    .remove(&nick.to_lowercase());
+                self.membership(
+                    side,
+                    channel,
+                    nick,
+                    &format!("{nick} was kicked by {by} ({reason})"),
+                )
+            }
+            Event::UserQuit { nick, reason } => {
+                let channels = self.channels_with(side, nick);
+                for (_, channel) in &channels {
+                    self.channel_members(side, channel)
+                        .remove(&nick.to_lowercase());
+                }
+                let text = format!("{nick} quit ({reason})");
+                channels
+                    .iter()
+                    .filter(|(link, _)| link.join_part && flows(link, side))
+                    .filter(|_| !self.ignored(side, nick))
+                    .map(|(link, _)| notice(link, side.other(), &text))
+                    .collect()
+            }
+            Event::NickChanged { old_nick, new_nick } => {
+                let channels = self.channels_with(side, old_nick);
+                for (_, channel) in &channels {
+                    let members = self.channel_members(side, channel);
+                    members.remove(&old_nick.to_lowercase());
+                    members.insert(new_nick.to_lowercase());
+                }
+                let text = format!("{old_nick} is now {new_nick}");
+                channels
+                    .iter()
+                    .filter(|(link, _)| link.join_part && flows(link, side))
+                    .filter(|_| !self.ignored(side, old_nick) && !self.ignored(side, new_nick))
+                    .map(|(link, _)| notice(link, side.other(), &text))
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn membership(&self, side: Side, channel: &str, nick: &str, text: &str) -> Vec<Outgoing> {
+        match self.link(side, channel) {
+            Some(link) if link.join_part && flows(link, side) && !self.ignored(side, nick) => {
+                vec![notice(link, side.other(), text)]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn channel_on(link: &ChannelLink, side: Side) -> &str {
+    match side {
+        Side::Freeq => &link.freeq,
+        Side::Remote => &link.remote,
+    }
+}
+
+/// Whether messages from `side` cross this link.
+fn flows(link: &ChannelLink, from: Side) -> bool {
+    matches!(
+        (link.direction, from),
+        (Direction::Both, _)
+            | (Direction::ToRemote, Side::Freeq)
+            | (Direction::ToFreeq, Side::Remote)
+    )
+}
+
+/// A membership change, sent without a nick prefix.
+fn notice(link: &ChannelLink, to: Side, text: &str) -> Outgoing {
+    Outgoing {
+        side: to,
+        target: channel_on(link, to).to_string(),
+        text: format!("* {text}"),
+    }
+}
+
+/// Plain text for a message: attachments become their link and edits are
+/// marked, since the other network has no tags to carry them.
+fn relay_text(text: &str, msg_tags: &HashMap<String, String>) -> String {
+    if let Some(media) = MediaAttachment::from_tags(msg_tags) {
+        return media.fallback_text();
+    }
+    if msg_tags.contains_key(tags::EDIT) {
+        return format!("(edit) {text}");
+    }
+    text.to_string()
+}
+
+/// Prefix each line of `text` with the speaker and split it to fit legacy
+/// line limits. CTCP ACTIONs are relayed as `* nick does something`.
+pub fn format_lines(link: &ChannelLink, to: Side, nick: &str, text: &str) -> Vec<Outgoing> {
+    let (text, action) = match text
+        .strip_prefix("\x01ACTION ")
+        .and_then(|t| t.strip_suffix('\x01'))
+    {
+        Some(action) => (action, true),
+        None => (text, false),
+    };
+    let prefix = if action {
+        format!("* {nick} ")
+    } else {
+        link.prefix.replace("{nick}", nick)
+    };
+    let target = channel_on(link, to).to_string();
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .flat_map(|line| split_bytes(line, MAX_LINE_BYTES.saturating_sub(prefix.len())))
+        .map(|chunk| Outgoing {
+            side: to,
+            target: target.clone(),
+            text: format!("{prefix}{chunk}"),
+        })
+        .collect()
+}
+
+/// Split `s` into pieces of at most `max` bytes on char boundaries.
+fn split_bytes(s: &str, max: usize) -> Vec<&str> {
+    let max = max.max(4);
+    let mut out = Vec::new();
+    let mut rest = s;
+    while rest.len() > max {
+        let mut end = max;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        out.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    out.push(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r##"
+ignore_nicks = ["OtherBridge"]
+
+[freeq]
+server = "irc.freeq.at:6697"
+tls = true
+nick = "libera"
+
+[remote]
+server = "irc.libera.chat:6697"
+tls = true
+nick = "freeq-relay"
+
+[[channels]]
+freeq = "#freeq"
+remote = "#freeq-dev"
+join_part = true
+
+[[channels]]
+freeq = "#announce"
+remote = "#announce"
+direction = "to-remote"
+prefix = "[{nick}] "
+"##;
+
+    fn relay() -> Relay {
+        let config: RelayConfig = toml::from_str(CONFIG).unwrap();
+        config.validate().unwrap();
+        Relay::new(config)
+    }
+
+    fn msg(from: &str, target: &str, text: &str) -> Event {
+        Event::Message {
+            from: from.into(),
+            target: target.into(),
+            text: text.into(),
+            tags: HashMap::new(),
+            formatted_text: None,
+            encrypted: false,
+        }
+    }
+
+    #[test]
+    fn relays_with_prefix_and_direction() {
+        let mut relay = relay();
+        let out = relay.route(Side::Freeq, &msg("alice", "#FreeQ", "hi"));
+        assert_eq!(
+            out,
+            vec![Outgoing {
+                side: Side::Remote,
+                target: "#freeq-dev".into(),
+                text: "<alice> hi".into()
+            }]
+        );
+
+        let out = relay.route(
+            Side::Freeq,
+            &msg("bob", "#announce", "\x01ACTION waves\x01"),
+        );
+        assert_eq!(out[0].text, "* bob waves");
+        let out = relay.route(Side::Freeq, &msg("bob", "#announce", "news"));
+        assert_eq!(out[0].text, "[bob] news");
+        assert!(
+            relay
+                .route(Side::Remote, &msg("carol", "#announce", "nope"))
+                .is_empty()
+        );
+        assert!(
+            relay
+                .route(Side::Freeq, &msg("x", "#other", "hi"))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn prevents_loops() {
+        let mut relay = relay();
+        relay.route(
+            Side::Remote,
+            &Event::Registered {
+                nick: "freeq-relay1".into(),
+            },
+        );
+        assert!(
+            relay
+                .route(Side::Remote, &msg("freeq-relay1", "#freeq-dev", "<a> hi"))
+                .is_empty()
+        );
+        assert!(
+            relay
+                .route(Side::Freeq, &msg("otherbridge", "#freeq", "hi"))
+                .is_empty()
+        );
+
+        let mut t = HashMap::new();
+        t.insert("batch".to_string(), "h1".to_string());
+        relay.route(
+            Side::Freeq,
+            &Event::BatchStart {
+                id: "h1".into(),
+                batch_type: "chathistory".into(),
+                target: "#freeq".into(),
+            },
+        );
+        let replay = Event::Message {
+            from: "alice".into(),
+            target: "#freeq".into(),
+            text: "old".into(),
+            tags: t,
+            formatted_text: None,
+            encrypted: false,
+        };
+        assert!(relay.route(Side::Freeq, &replay).is_empty());
+    }
+
+    #[test]
+    fn membership_follows_join_part_setting() {
+        let mut relay = relay();
+        let join = Event::Joined {
+            channel: "#freeq-dev".into(),
+            nick: "dave".into(),
+            account: None,
+        };
+        assert_eq!(relay.route(Side::Remote, &join)[0].text, "* dave joined");
+
+        let quit = Event::UserQuit {
+            nick: "dave".into(),
+            reason: "bye".into(),
+        };
+        let out = relay.route(Side::Remote, &quit);
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].target, "#freeq");
+        assert_eq!(out[0].text, "* dave quit (bye)");
+
+        // #announce has join_part off.
+        let join = Event::Joined {
+            channel: "#announce".into(),
+            nick: "erin".into(),
+            account: None,
+        };
+        assert!(relay.route(Side::Freeq, &join).is_empty());
+    }
+
+    #[test]
+    fn splits_long_and_multiline_text() {
+        let relay = relay();
+        let link = &relay.config().channels[0];
+        let long = "é".repeat(300);
+        let out = format_lines(link, Side::Remote, "alice", &format!("one\n\n{long}"));
+        assert_eq!(out[0].text, "<alice> one");
+        assert!(out.len() >= 3);
+        assert!(out.iter().all(|o| o.text.len() <= MAX_LINE_BYTES));
+    }
+}
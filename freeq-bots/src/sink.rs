@@ -0,0 +1,251 @@
+//! Where agent output goes.
+//!
+//! The pipelines talk through [`output`](crate::output), which formats each
+//! message and hands the wire lines to an [`OutputSink`]. A
+//! [`ClientHandle`] is the IRC sink; the others let the pipelines run as
+//! plain library calls — for the eval harness, CLI one-shots, or embedding
+//! in another service — with no IRC connection at all.
+
+use std::io::Write;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use freeq_sdk::client::ClientHandle;
+use freeq_sdk::proto::caps;
+use freeq_sdk::proto::tags::Priority;
+use futures::future::BoxFuture;
+
+use crate::output::{self, AgentId, strip_formatting};
+
+/// A destination for agent messages.
+pub trait OutputSink: Send + Sync {
+    /// Deliver one logical message from `agent` to `target`, already laid
+    /// out as lines that fit an IRC PRIVMSG.
+    fn send<'a>(
+        &'a self,
+        target: &'a str,
+        agent: &'a AgentId,
+        lines: &'a [String],
+    ) -> BoxFuture<'a, Result<()>>;
+
+    /// Deliver a status line, like [`send`](Self::send) but at the
+    /// [`output::status_priority`](crate::output::status_priority) where
+    /// the destination has priorities.
+    fn send_status<'a>(
+        &'a self,
+        target: &'a str,
+        agent: &'a AgentId,
+        lines: &'a [String],
+    ) -> BoxFuture<'a, Result<()>> {
+        self.send(target, agent, lines)
+    }
+
+    /// The IRC connection behind this sink, when there is one. Streaming
+    /// edits and channel admin tools need it; other sinks get whole
+    /// messages and no admin tools.
+    fn irc(&self) -> Option<&ClientHandle> {
+        None
+    }
+}
+
+/// IRC: one PRIVMSG per message — a `draft/multiline` BATCH when the
+/// server acked it, otherwise one PRIVMSG per line. Status lines carry
+/// the `+freeq.at/priority` tag unless it is normal.
+impl OutputSink for ClientHandle {
+    fn send<'a>(
+        &'a self,
+        target: &'a str,
+        _agent: &'a AgentId,
+        lines: &'a [String],
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(send_lines(self, target, lines, Priority::Normal))
+    }
+
+    fn send_status<'a>(
+        &'a self,
+        target: &'a str,
+        _agent: &'a AgentId,
+        lines: &'a [String],
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(send_lines(self, target, lines, output::status_priority()))
+    }
+
+    fn irc(&self) -> Option<&ClientHandle> {
+        Some(self)
+    }
+}
+
+async fn send_lines(
+    handle: &ClientHandle,
+    target: &str,
+    lines: &[String],
+    priority: Priority,
+) -> Result<()> {
+    if lines.len() > 1 && !(handle.has_cap(caps::MULTILINE) && handle.has_cap(caps::BATCH)) {
+        for line in lines {
+            handle.privmsg_with_priority(target, line, priority).await?;
+            tokio::time::sleep(std::time::Duration::from_millis(80)).await;
+        }
+        return Ok(());
+    }
+    handle
+        .privmsg_with_priority(target, &lines.join("\n"), priority)
+        .await
+}
+
+/// Plain text on stdout, IRC formatting stripped.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StdoutSink;
+
+impl OutputSink for StdoutSink {
+    fn send<'a>(
+        &'a self,
+        _target: &'a str,
+        _agent: &'a AgentId,
+        lines: &'a [String],
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let mut out = std::io::stdout().lock();
+            for line in lines {
+                writeln!(out, "{}", strip_formatting(line))?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// One JSON object per message, newline-delimited:
+/// `{"ts": "...", "target": "#chan", "agent": "builder", "text": "..."}`.
+pub struct JsonLogSink {
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl JsonLogSink {
+    pub fn new(out: impl Write + Send + 'static) -> Self {
+        Self {
+            out: Mutex::new(Box::new(out)),
+        }
+    }
+
+    pub fn stdout() -> Self {
+        Self::new(std::io::stdout())
+    }
+}
+
+impl OutputSink for JsonLogSink {
+    fn send<'a>(
+        &'a self,
+        target: &'a str,
+        agent: &'a AgentId,
+        lines: &'a [String],
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let text = strip_formatting(&lines.join("\n"));
+            let prefix = format!("[{}] ", agent.role);
+            let text = text.strip_prefix(&prefix).unwrap_or(&text);
+            let entry = serde_json::json!({
+                "ts": chrono::Utc::now().to_rfc3339(),
+                "target": target,
+                "agent": agent.role,
+                "text": text,
+            });
+            let mut out = self.out.lock().unwrap_or_else(|e| e.into_inner());
+            writeln!(out, "{entry}")?;
+            out.flush()?;
+            Ok(())
+        })
+    }
+}
+
+/// Keeps everything said in memory, formatting stripped.
+#[derive(Debug, Default)]
+pub struct TranscriptSink {
+    lines: Mutex<Vec<String>>,
+}
+
+impl TranscriptSink {
+    /// Everything said so far, one line per wire line.
+    pub fn text(&self) -> String {
+        self.lines
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .join("\n")
+    }
+}
+
+impl OutputSink for TranscriptSink {
+    fn send<'a>(
+        &'a self,
+        _target: &'a str,
+        _agent: &'a AgentId,
+        lines: &'a [String],
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            self.lines
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .extend(lines.iter().map(|l| strip_formatting(l)));
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// A `Write` whose bytes the test can read back.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn qa() -> AgentId {
+        AgentId {
+            role: "qa".to_string(),
+            color: Some(crate::output::color::PURPLE),
+        }
+    }
+
+    #[tokio::test]
+    async fn json_log_has_one_object_per_message() {
+        let buf = Shared::default();
+        let sink = JsonLogSink::new(buf.clone());
+        let lines = vec![
+            "\x0306[qa]\x03 all green".to_string(),
+            "  3 passed".to_string(),
+        ];
+        sink.send("#factory", &qa(), &lines).await.unwrap();
+        sink.send("#factory", &qa(), &lines[..1]).await.unwrap();
+
+        let out = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        let entries: Vec<serde_json::Value> = out
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["target"], "#factory");
+        assert_eq!(entries[0]["agent"], "qa");
+        assert_eq!(entries[0]["text"], "all green\n  3 passed");
+    }
+
+    #[tokio::test]
+    async fn transcript_collects_plain_lines() {
+        let sink = TranscriptSink::default();
+        crate::output::status(&sink, "#eval", &qa(), "✅", "done")
+            .await
+            .unwrap();
+        assert_eq!(sink.text(), "[qa] ✅ done");
+        assert!(sink.irc().is_none());
+    }
+}
@@ -0,0 +1,1004 @@
+//! Repository watch — commit and CI summaries posted in-channel.
+//!
+//! `/watch add <repo> [branch]` follows a GitHub repository's branch (the
+//! default branch if none is given). Each push gets one line in the
+//! channel: the commit count, the authors and a short LLM-written summary
+//! of what changed ("refactors auth module; adds 3 tests"), followed by one
+//! more line when its CI checks finish.
+//!
+//! New commits are found by polling the GitHub API every
+//! [`POLL_INTERVAL`], or as soon as they happen when the repository sends
+//! webhooks to `/hooks/github` on the artifacts server (see [`router`]).
+//! Both paths go through the same [`Watcher`], which remembers the last
+//! head it reported, so a push is never posted twice.
+//!
+//! Watches are stored in [`Memory`] (project = channel, kind [`KIND`]) and
+//! survive restarts. `/watch list` shows a channel's watches and
+//! `/watch remove <repo> [branch]` drops them.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use axum::Router;
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::post;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::llm::{LlmClient, Task};
+use crate::memory::Memory;
+use crate::output::{self, AgentId};
+use crate::sink::OutputSink;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Memory kind the watches are stored under.
+pub const KIND: &str = "watch";
+/// How often watched branches are polled.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(60);
+/// How long to wait for CI on a push before giving up on reporting it.
+pub const CI_TIMEOUT: Duration = Duration::from_secs(2 * 60 * 60);
+/// Most commits described to the LLM per push.
+const MAX_COMMITS: usize = 20;
+/// Most changed files described to the LLM per push.
+const MAX_FILES: usize = 40;
+
+/// A branch being watched for one channel.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Watch {
+    pub channel: String,
+    /// `owner/name`.
+    pub repo: String,
+    pub branch: String,
+    /// The last head reported (or seen when the watch was added).
+    #[serde(default)]
+    pub head: Option<String>,
+    /// A reported push whose CI result is still to be posted, with the
+    /// Unix time it was pushed.
+    #[serde(default)]
+    pub ci_pending: Option<(String, i64)>,
+}
+
+impl Watch {
+    fn key(&self) -> String {
+        format!("{}@{}", self.repo, self.branch)
+    }
+
+    fn matches(&self, repo: &str, branch: &str) -> bool {
+        self.repo.eq_ignore_ascii_case(repo) && self.branch == branch
+    }
+}
+
+/// `owner/name` from `owner/name`, `github.com/owner/name` or a GitHub URL.
+pub fn parse_repo(s: &str) -> Option<String> {
+    let s = s.trim().trim_end_matches('/');
+    let s = s
+        .strip_prefix("https://")
+        .or_else(|| s.strip_prefix("http://"))
+        .unwrap_or(s);
+    let s = s.strip_prefix("github.com/").unwrap_or(s);
+    let s = s.strip_suffix(".git").unwrap_or(s);
+    let (owner, name) = s.split_once('/')?;
+    let valid = |part: &str| {
+        !part.is_empty()
+            && !part.starts_with('.')
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    };
+    (valid(owner) && valid(name)).then(|| format!("{owner}/{name}"))
+}
+
+/// Store (or replace) `watch`.
+pub fn save(memory: &Memory, watch: &Watch) -> Result<()> {
+    let project = watch.channel.to_lowercase();
+    // `set` appends a row per call; keep one per watch
+    memory.delete(&project, KIND, &watch.key())?;
+    memory.set(&project, KIND, &watch.key(), &serde_json::to_string(watch)?)
+}
+
+/// The watches in `channel`, oldest first.
+pub fn list(memory: &Memory, channel: &str) -> Result<Vec<Watch>> {
+    Ok(parse_entries(memory.list(&channel.to_lowercase(), KIND)?))
+}
+
+/// Every watch, in every channel.
+pub fn all(memory: &Memory) -> Result<Vec<Watch>> {
+    Ok(parse_entries(memory.list_kind(KIND)?))
+}
+
+fn parse_entries(entries: Vec<crate::memory::Entry>) -> Vec<Watch> {
+    entries
+        .into_iter()
+        .filter_map(|e| serde_json::from_str(&e.value).ok())
+        .collect()
+}
+
+/// Drop `channel`'s watches of `repo` (on `branch`, or on every branch).
+/// Returns the ones removed.
+pub fn remove(
+    memory: &Memory,
+    channel: &str,
+    repo: &str,
+    branch: Option<&str>,
+) -> Result<Vec<Watch>> {
+    let removed: Vec<Watch> = list(memory, channel)?
+        .into_iter()
+        .filter(|w| w.repo.eq_ignore_ascii_case(repo) && branch.is_none_or(|b| w.branch == b))
+        .collect();
+    for watch in &removed {
+        memory.delete(&channel.to_lowercase(), KIND, &watch.key())?;
+    }
+    Ok(removed)
+}
+
+// ─── What happened ───────────────────────────────────────────────────
+
+/// One commit of a push.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    pub sha: String,
+    pub author: String,
+    pub message: String,
+}
+
+/// One changed file; line counts are unknown for webhook pushes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileChange {
+    pub path: String,
+    pub additions: Option<u64>,
+    pub deletions: Option<u64>,
+}
+
+/// New commits on a watched branch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Push {
+    pub repo: String,
+    pub branch: String,
+    pub after: String,
+    /// Oldest first.
+    pub commits: Vec<Commit>,
+    pub files: Vec<FileChange>,
+    /// Where to see the whole change.
+    pub url: String,
+}
+
+/// The state of a commit's CI checks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ci {
+    /// No checks yet, or some still running.
+    Pending,
+    /// Every check finished.
+    Finished { total: usize, failed: Vec<String> },
+}
+
+#[derive(Deserialize)]
+struct CheckRuns {
+    check_runs: Vec<CheckRun>,
+}
+
+#[derive(Deserialize)]
+struct CheckRun {
+    name: String,
+    status: String,
+    conclusion: Option<String>,
+}
+
+/// The CI state from a commit's check runs. Skipped and neutral checks
+/// don't count as failures.
+fn ci_state(runs: &[CheckRun]) -> Ci {
+    if runs.is_empty() || runs.iter().any(|r| r.status != "completed") {
+        return Ci::Pending;
+    }
+    let failed = runs
+        .iter()
+        .filter(|r| {
+            !matches!(
+                r.conclusion.as_deref(),
+                Some("success" | "skipped" | "neutral")
+            )
+        })
+        .map(|r| r.name.clone())
+        .collect();
+    Ci::Finished {
+        total: runs.len(),
+        failed,
+    }
+}
+
+// ─── GitHub API ──────────────────────────────────────────────────────
+
+/// A small GitHub REST client.
+#[derive(Clone)]
+pub struct GitHub {
+    http: reqwest::Client,
+    api_url: String,
+    token: Option<String>,
+}
+
+impl GitHub {
+    /// Unauthenticated requests are limited to 60 an hour; a token lifts
+    /// that and gives access to private repositories.
+    pub fn new(token: Option<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            api_url: "https://api.github.com".to_string(),
+            token,
+        }
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let mut req = self
+            .http
+            .get(format!("{}{path}", self.api_url))
+            .header("Accept", "application/vnd.github+json")
+            .header("User-Agent", "freeq-bots");
+        if let Some(token) = &self.token {
+            req = req.bearer_auth(token);
+        }
+        let resp = req.send().await?;
+        let status = resp.status();
+        if !status.is_success() {
+            bail!("GitHub {path}: {status}");
+        }
+        Ok(resp.json().await?)
+    }
+
+    /// The repository's default branch.
+    pub async fn default_branch(&self, repo: &str) -> Result<String> {
+        #[derive(Deserialize)]
+        struct Repo {
+            default_branch: String,
+        }
+        let r: Repo = self.get(&format!("/repos/{repo}")).await?;
+        Ok(r.default_branch)
+    }
+
+    /// The commit `branch` points at.
+    pub async fn head(&self, repo: &str, branch: &str) -> Result<String> {
+        #[derive(Deserialize)]
+        struct Branch {
+            commit: Sha,
+        }
+        #[derive(Deserialize)]
+        struct Sha {
+            sha: String,
+        }
+        let b: Branch = self
+            .get(&format!("/repos/{repo}/branches/{branch}"))
+            .await?;
+        Ok(b.commit.sha)
+    }
+
+    /// The commits and files between `before` and `after`.
+    pub async fn compare(
+        &self,
+        repo: &str,
+        branch: &str,
+        before: &str,
+        after: &str,
+    ) -> Result<Push> {
+        #[derive(Deserialize)]
+        struct Comparison {
+            html_url: String,
+            commits: Vec<ApiCommit>,
+            #[serde(default)]
+            files: Vec<ApiFile>,
+        }
+        #[derive(Deserialize)]
+        struct ApiCommit {
+            sha: String,
+            commit: ApiCommitDetail,
+            author: Option<ApiUser>,
+        }
+        #[derive(Deserialize)]
+        struct ApiCommitDetail {
+            message: String,
+            author: ApiCommitAuthor,
+        }
+        #[derive(Deserialize)]
+        struct ApiCommitAuthor {
+            name: String,
+        }
+        #[derive(Deserialize)]
+        struct ApiUser {
+            login: String,
+        }
+        #[derive(Deserialize)]
+        struct ApiFile {
+            filename: String,
+            additions: u64,
+            deletions: u64,
+        }
+        let c: Comparison = self
+            .get(&format!("/repos/{repo}/compare/{before}...{after}"))
+            .await?;
+        Ok(Push {
+            repo: repo.to_string(),
+            branch: branch.to_string(),
+            after: after.to_string(),
+            commits: c
+                .commits
+                .into_iter()
+                .map(|c| Commit {
+                    sha: c.sha,
+                    author: c.author.map_or(c.commit.author.name, |a| a.login),
+                    message: c.commit.message,
+                })
+                .collect(),
+            files: c
+                .files
+                .into_iter()
+                .map(|f| FileChange {
+                    path: f.filename,
+                    additions: Some(f.additions),
+                    deletions: Some(f.deletions),
+                })
+                .collect(),
+            url: c.html_url,
+        })
+    }
+
+    /// The state of `sha`'s CI checks.
+    pub async fn ci(&self, repo: &str, sha: &str) -> Result<Ci> {
+        let runs: CheckRuns = self
+            .get(&format!(
+                "/repos/{repo}/commits/{sha}/check-runs?per_page=100"
+            ))
+            .await?;
+        Ok(ci_state(&runs.check_runs))
+    }
+}
+
+// ─── Messages ────────────────────────────────────────────────────────
+
+fn watcher_agent() -> AgentId {
+    AgentId {
+        role: "watch".to_string(),
+        color: Some(output::color::TEAL),
+    }
+}
+
+fn short(sha: &str) -> &str {
+    &sha[..sha.len().min(7)]
+}
+
+/// First line of each commit and the changed files, for the LLM.
+fn describe(push: &Push) -> String {
+    let mut text = String::from("Commits (oldest first):\n");
+    for c in push.commits.iter().rev().take(MAX_COMMITS).rev() {
+        let subject = c.message.lines().next().unwrap_or_default();
+        text.push_str(&format!("- {subject}\n"));
+    }
+    if push.commits.len() > MAX_COMMITS {
+        text.push_str(&format!(
+            "(and {} earlier commits)\n",
+            push.commits.len() - MAX_COMMITS
+        ));
+    }
+    text.push_str("\nChanged files:\n");
+    for f in push.files.iter().take(MAX_FILES) {
+        match (f.additions, f.deletions) {
+            (Some(a), Some(d)) => text.push_str(&format!("- {} (+{a} -{d})\n", f.path)),
+            _ => text.push_str(&format!("- {}\n", f.path)),
+        }
+    }
+    if push.files.len() > MAX_FILES {
+        text.push_str(&format!("(and {} more)\n", push.files.len() - MAX_FILES));
+    }
+    text
+}
+
+/// A one-line summary of `push`; the newest commit's subject if the LLM
+/// fails.
+pub async fn summarize(llm: &LlmClient, push: &Push) -> String {
+    let fallback = push
+        .commits
+        .last()
+        .and_then(|c| c.message.lines().next())
+        .unwrap_or_default()
+        .to_string();
+    let result = llm
+        .for_task(Task::Summarize)
+        .complete(
+            "You summarize git pushes for a chat channel. Reply with one line \
+             under 100 characters: lowercase verb phrases separated by semicolons, \
+             e.g. \"refactors auth module; adds 3 tests\". No preamble, no quotes.",
+            &describe(push),
+        )
+        .await;
+    match result {
+        Ok(summary) if !summary.trim().is_empty() => summary
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .trim_matches('"')
+            .to_string(),
+        Ok(_) => fallback,
+        Err(e) => {
+            tracing::warn!(error = %e, repo = %push.repo, "Push summary failed");
+            fallback
+        }
+    }
+}
+
+/// `owner/repo@main: 3 commits by alice, bob — <summary> <url>`
+pub fn push_line(push: &Push, summary: &str) -> String {
+    let mut authors: Vec<&str> = Vec::new();
+    for c in &push.commits {
+        if !authors.contains(&c.author.as_str()) {
+            authors.push(&c.author);
+        }
+    }
+    let count = match push.commits.len() {
+        1 => "1 commit".to_string(),
+        n => format!("{n} commits"),
+    };
+    format!(
+        "{}@{}: {count} by {} — {summary} {}",
+        push.repo,
+        push.branch,
+        authors.join(", "),
+        push.url
+    )
+}
+
+/// The line for a finished CI run, or None while it's pending.
+pub fn ci_line(watch: &Watch, sha: &str, ci: &Ci) -> Option<String> {
+    let Ci::Finished { total, failed } = ci else {
+        return None;
+    };
+    let at = format!("{}@{} {}", watch.repo, watch.branch, short(sha));
+    Some(if failed.is_empty() {
+        format!("✅ CI passed for {at} ({total} checks)")
+    } else {
+        format!(
+            "❌ CI failed for {at}: {} ({} of {total} checks)",
+            failed.join(", "),
+            failed.len()
+        )
+    })
+}
+
+// ─── Watcher ─────────────────────────────────────────────────────────
+
+/// Polls the watched branches, takes webhook deliveries, and posts what
+/// changed.
+#[derive(Clone)]
+pub struct Watcher {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    memory: Arc<Memory>,
+    github: GitHub,
+    llm: LlmClient,
+    sink: Arc<dyn OutputSink>,
+    /// Serializes updates, so a webhook and a poll can't both report a push.
+    busy: tokio::sync::Mutex<()>,
+}
+
+impl Watcher {
+    pub fn new(
+        memory: Arc<Memory>,
+        github: GitHub,
+        llm: LlmClient,
+        sink: Arc<dyn OutputSink>,
+    ) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                memory,
+                github,
+                llm,
+                sink,
+                busy: tokio::sync::Mutex::new(()),
+            }),
+        }
+    }
+
+    /// Start watching `repo` (on its default branch if `branch` is None)
+    /// for `channel`, from its current head.
+    pub async fn add(&self, channel: &str, repo: &str, branch: Option<&str>) -> Result<Watch> {
+        let github = &self.inner.github;
+        let branch = match branch {
+            Some(b) => b.to_string(),
+            None => github.default_branch(repo).await?,
+        };
+        let head = github
+            .head(repo, &branch)
+            .await
+            .with_context(|| format!("no branch {branch} in {repo}"))?;
+        let watch = Watch {
+            channel: channel.to_string(),
+            repo: repo.to_string(),
+            branch,
+            head: Some(head),
+            ci_pending: None,
+        };
+        let _busy = self.inner.busy.lock().await;
+        save(&self.inner.memory, &watch)?;
+        Ok(watch)
+    }
+
+    /// Poll every watch every `interval` until the process exits.
+    pub async fn run(self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            let watches = match all(&self.inner.memory) {
+                Ok(w) => w,
+                Err(e) => {
+                    tracing::error!(error = %e, "Loading watches failed");
+                    continue;
+                }
+            };
+            for watch in watches {
+                if let Err(e) = self.poll(&watch).await {
+                    tracing::warn!(error = %e, repo = %watch.repo, "Watch poll failed");
+                }
+            }
+        }
+    }
+
+    /// Report new commits on `watch`'s branch, and its pending CI result.
+    async fn poll(&self, watch: &Watch) -> Result<()> {
+        let github = &self.inner.github;
+        let head = github.head(&watch.repo, &watch.branch).await?;
+        if watch.head.as_deref() != Some(head.as_str()) {
+            let push = match &watch.head {
+                Some(before) => Some(
+                    github
+                        .compare(&watch.repo, &watch.branch, before, &head)
+                        .await?,
+                ),
+                None => None,
+            };
+            self.pushed(&watch.repo, &watch.branch, &head, push).await?;
+        }
+        if let Some((sha, _)) = &watch.ci_pending {
+            let ci = github.ci(&watch.repo, sha).await?;
+            self.ci_changed(&watch.repo, &watch.branch, sha, &ci)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// `repo`'s `branch` moved to `after`: post `push` (when known) to every
+    /// channel watching it that hasn't seen `after` yet.
+    async fn pushed(
+        &self,
+        repo: &str,
+        branch: &str,
+        after: &str,
+        push: Option<Push>,
+    ) -> Result<()> {
+        let _busy = self.inner.busy.lock().await;
+        let watches: Vec<Watch> = all(&self.inner.memory)?
+            .into_iter()
+            .filter(|w| w.matches(repo, branch) && w.head.as_deref() != Some(after))
+            .collect();
+        if watches.is_empty() {
+            return Ok(());
+        }
+        let line = match &push {
+            Some(push) if !push.commits.is_empty() => {
+                let summary = summarize(&self.inner.llm, push).await;
+                Some(push_line(push, &summary))
+            }
+            _ => None,
+        };
+        for mut watch in watches {
+            if let Some(line) = &line {
+                let sink = self.inner.sink.as_ref();
+                output::status(sink, &watch.channel, &watcher_agent(), "📦", line).await?;
+                watch.ci_pending = Some((after.to_string(), chrono::Utc::now().timestamp()));
+            }
+            watch.head = Some(after.to_string());
+            save(&self.inner.memory, &watch)?;
+        }
+        Ok(())
+    }
+
+    /// Post `sha`'s CI result where it's awaited, once it's finished (or
+    /// stop waiting after [`CI_TIMEOUT`]).
+    async fn ci_changed(&self, repo: &str, branch: &str, sha: &str, ci: &Ci) -> Result<()> {
+        let _busy = self.inner.busy.lock().await;
+        let now = chrono::Utc::now().timestamp();
+        for mut watch in all(&self.inner.memory)? {
+            let Some((pending, since)) = &watch.ci_pending else {
+                continue;
+            };
+            if !watch.matches(repo, branch) || pending != sha {
+                continue;
+            }
+            match ci_line(&watch, sha, ci) {
+                Some(line) => {
+                    let sink = self.inner.sink.as_ref();
+                    output::say(sink, &watch.channel, &watcher_agent(), &line).await?;
+                }
+                None if now - since < CI_TIMEOUT.as_secs() as i64 => continue,
+                None => {}
+            }
+            watch.ci_pending = None;
+            save(&self.inner.memory, &watch)?;
+        }
+        Ok(())
+    }
+}
+
+// ─── Webhooks ────────────────────────────────────────────────────────
+
+/// `POST /hooks/github`, for mounting on the artifacts server. Deliveries
+/// must be signed with `secret` (the webhook's secret on GitHub); `push`
+/// and completed `check_suite` events are acted on, others acknowledged.
+pub fn router(watcher: Watcher, secret: &str) -> Router {
+    Router::new()
+        .route("/hooks/github", post(github_hook))
+        .with_state((watcher, Arc::<[u8]>::from(secret.as_bytes())))
+}
+
+/// Check a `X-Hub-Signature-256` header (`sha256=<hex>`) in constant time.
+pub fn verify_signature(secret: &[u8], body: &[u8], header: &str) -> bool {
+    let Some(provided) = header.strip_prefix("sha256=").and_then(decode_hex) else {
+        return false;
+    };
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(body);
+    mac.verify_slice(&provided).is_ok()
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// What a webhook delivery asks the watcher to do.
+#[derive(Debug, PartialEq, Eq)]
+enum Hook {
+    Push {
+        push: Push,
+        before: String,
+    },
+    CheckSuite {
+        repo: String,
+        branch: String,
+        sha: String,
+    },
+    Ignore,
+}
+
+#[derive(Deserialize)]
+struct HookRepo {
+    full_name: String,
+}
+
+fn parse_hook(event: &str, body: &[u8]) -> Result<Hook> {
+    #[derive(Deserialize)]
+    struct PushEvent {
+        #[serde(rename = "ref")]
+        git_ref: String,
+        before: String,
+        after: String,
+        #[serde(default)]
+        deleted: bool,
+        compare: String,
+        repository: HookRepo,
+        commits: Vec<HookCommit>,
+    }
+    #[derive(Deserialize)]
+    struct HookCommit {
+        id: String,
+        message: String,
+        author: HookAuthor,
+        #[serde(default)]
+        added: Vec<String>,
+        #[serde(default)]
+        removed: Vec<String>,
+        #[serde(default)]
+        modified: Vec<String>,
+    }
+    #[derive(Deserialize)]
+    struct HookAuthor {
+        name: String,
+        username: Option<String>,
+    }
+    #[derive(Deserialize)]
+    struct CheckSuiteEvent {
+        action: String,
+        check_suite: CheckSuite,
+        repository: HookRepo,
+    }
+    #[derive(Deserialize)]
+    struct CheckSuite {
+        head_branch: Option<String>,
+        head_sha: String,
+    }
+
+    Ok(match event {
+        "push" => {
+            let e: PushEvent = serde_json::from_slice(body)?;
+            let Some(branch) = e.git_ref.strip_prefix("refs/heads/") else {
+                return Ok(Hook::Ignore);
+            };
+            if e.deleted {
+                return Ok(Hook::Ignore);
+            }
+            let mut files: Vec<FileChange> = Vec::new();
+            for c in &e.commits {
+                for path in c.added.iter().chain(&c.removed).chain(&c.modified) {
+                    if !files.iter().any(|f| &f.path == path) {
+                        files.push(FileChange {
+                            path: path.clone(),
+                            additions: None,
+                            deletions: None,
+                        });
+                    }
+                }
+            }
+            Hook::Push {
+                push: Push {
+                    repo: e.repository.full_name,
+                    branch: branch.to_string(),
+                    after: e.after,
+                    commits: e
+                        .commits
+                        .into_iter()
+                        .map(|c| Commit {
+                            sha: c.id,
+                            author: c.author.username.unwrap_or(c.author.name),
+                            message: c.message,
+                        })
+                        .collect(),
+                    files,
+                    url: e.compare,
+                },
+                before: e.before,
+            }
+        }
+        "check_suite" => {
+            let e: CheckSuiteEvent = serde_json::from_slice(body)?;
+            match e.check_suite.head_branch {
+                Some(branch) if e.action == "completed" => Hook::CheckSuite {
+                    repo: e.repository.full_name,
+                    branch,
+                    sha: e.check_suite.head_sha,
+                },
+                _ => Hook::Ignore,
+            }
+        }
+        _ => Hook::Ignore,
+    })
+}
+
+async fn github_hook(
+    State((watcher, secret)): State<(Watcher, Arc<[u8]>)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> StatusCode {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+    };
+    if !verify_signature(&secret, &body, header("x-hub-signature-256")) {
+        return StatusCode::UNAUTHORIZED;
+    }
+    let hook = match parse_hook(header("x-github-event"), &body) {
+        Ok(hook) => hook,
+        Err(e) => {
+            tracing::warn!(error = %e, "Bad GitHub webhook");
+            return StatusCode::BAD_REQUEST;
+        }
+    };
+    // GitHub wants an answer within 10s; summaries can take longer.
+    tokio::spawn(async move {
+        let result = match hook {
+            // A new branch's "before" is all zeros; it can't be watched yet
+            Hook::Push { before, .. } if before.bytes().all(|b| b == b'0') => Ok(()),
+            Hook::Push { push, .. } => {
+                let (repo, branch, after) =
+                    (push.repo.clone(), push.branch.clone(), push.after.clone());
+                watcher.pushed(&repo, &branch, &after, Some(push)).await
+            }
+            Hook::CheckSuite { repo, branch, sha } => {
+                match watcher.inner.github.ci(&repo, &sha).await {
+                    Ok(ci) => watcher.ci_changed(&repo, &branch, &sha, &ci).await,
+                    Err(e) => Err(e),
+                }
+            }
+            Hook::Ignore => Ok(()),
+        };
+        if let Err(e) = result {
+            tracing::warn!(error = %e, "GitHub webhook handling failed");
+        }
+    });
+    StatusCode::NO_CONTENT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn watch(channel: &str, repo: &str, branch: &str) -> Watch {
+        Watch {
+            channel: channel.to_string(),
+            repo: repo.to_string(),
+            branch: branch.to_string(),
+            head: Some("abc".to_string()),
+            ci_pending: None,
+        }
+    }
+
+    #[test]
+    fn repos_parse_from_names_and_urls() {
+        for s in [
+            "chad/freeq",
+            "github.com/chad/freeq",
+            "https://github.com/chad/freeq.git",
+            "https://github.com/chad/freeq/",
+        ] {
+            assert_eq!(parse_repo(s).as_deref(), Some("chad/freeq"), "{s}");
+        }
+        assert_eq!(parse_repo("freeq"), None);
+        assert_eq!(parse_repo("chad/../etc"), None);
+        assert_eq!(parse_repo("chad/free q"), None);
+    }
+
+    #[test]
+    fn watches_persist_per_channel() {
+        let memory = Memory::in_memory().unwrap();
+        save(&memory, &watch("#Dev", "chad/freeq", "main")).unwrap();
+        save(&memory, &watch("#dev", "chad/freeq", "next")).unwrap();
+        save(&memory, &watch("#ops", "chad/freeq", "main")).unwrap();
+
+        // Saving again replaces
+        let mut updated = watch("#dev", "chad/freeq", "main");
+        updated.head = Some("def".to_string());
+        save(&memory, &updated).unwrap();
+
+        let dev = list(&memory, "#dev").unwrap();
+        assert_eq!(dev.len(), 2);
+        assert_eq!(all(&memory).unwrap().len(), 3);
+
+        let removed = remove(&memory, "#dev", "Chad/Freeq", Some("main")).unwrap();
+        assert_eq!(removed, [updated]);
+        let removed = remove(&memory, "#dev", "chad/freeq", None).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert!(list(&memory, "#dev").unwrap().is_empty());
+        assert_eq!(list(&memory, "#ops").unwrap().len(), 1);
+    }
+
+    fn run(name: &str, status: &str, conclusion: Option<&str>) -> CheckRun {
+        CheckRun {
+            name: name.to_string(),
+            status: status.to_string(),
+            conclusion: conclusion.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn ci_finishes_when_every_check_has() {
+        assert_eq!(ci_state(&[]), Ci::Pending);
+        assert_eq!(
+            ci_state(&[
+                run("build", "completed", Some("success")),
+                run("test", "in_progress", None)
+            ]),
+            Ci::Pending
+        );
+        let ci = ci_state(&[
+            run("build", "completed", Some("success")),
+            run("lint", "completed", Some("skipped")),
+            run("test", "completed", Some("failure")),
+        ]);
+        assert_eq!(
+            ci,
+            Ci::Finished {
+                total: 3,
+                failed: vec!["test".to_string()]
+            }
+        );
+        let w = watch("#dev", "chad/freeq", "main");
+        assert_eq!(
+            ci_line(&w, "0123456789", &ci).unwrap(),
+            "❌ CI failed for chad/freeq@main 0123456: test (1 of 3 checks)"
+        );
+        assert_eq!(ci_line(&w, "0123456789", &Ci::Pending), None);
+    }
+
+    fn commit(author: &str, message: &str) -> Commit {
+        Commit {
+            sha: "0".repeat(40),
+            author: author.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn push_lines_name_the_authors_once() {
+        let push = Push {
+            repo: "chad/freeq".to_string(),
+            branch: "main".to_string(),
+            after: "def".to_string(),
+            commits: vec![
+                commit("alice", "Refactor auth\n\nDetails"),
+                commit("bob", "Add tests"),
+                commit("alice", "Fix typo"),
+            ],
+            files: vec![FileChange {
+                path: "src/auth.rs".to_string(),
+                additions: Some(40),
+                deletions: Some(12),
+            }],
+            url: "https://github.com/chad/freeq/compare/abc...def".to_string(),
+        };
+        assert_eq!(
+            push_line(&push, "refactors auth module; adds 3 tests"),
+            "chad/freeq@main: 3 commits by alice, bob — refactors auth module; adds 3 tests \
+             https://github.com/chad/freeq/compare/abc...def"
+        );
+        let description = describe(&push);
+        assert!(description.contains("- Refactor auth\n"));
+        assert!(!description.contains("Details"));
+        assert!(description.contains("- src/auth.rs (+40 -12)\n"));
+    }
+
+    #[test]
+    fn webhooks_are_signed_and_parsed() {
+        let body = br#"{
+            "ref": "refs/heads/main",
+            "before": "abc",
+            "after": "def",
+            "compare": "https://github.com/chad/freeq/compare/abc...def",
+            "repository": {"full_name": "chad/freeq"},
+            "commits": [{
+                "id": "def",
+                "message": "Add watch mode",
+                "author": {"name": "Alice", "username": "alice"},
+                "added": ["src/watch.rs"],
+                "modified": ["src/main.rs", "src/watch.rs"]
+            }]
+        }"#;
+        let mut mac = HmacSha256::new_from_slice(b"s3cret").unwrap();
+        mac.update(body);
+        let hex: String = mac
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        assert!(verify_signature(b"s3cret", body, &format!("sha256={hex}")));
+        assert!(!verify_signature(b"other", body, &format!("sha256={hex}")));
+        assert!(!verify_signature(b"s3cret", body, &hex));
+        assert!(!verify_signature(b"s3cret", body, "sha256=zz"));
+
+        let Hook::Push { push, before } = parse_hook("push", body).unwrap() else {
+            panic!("not a push");
+        };
+        assert_eq!(before, "abc");
+        assert_eq!(
+            (push.repo.as_str(), push.branch.as_str()),
+            ("chad/freeq", "main")
+        );
+        assert_eq!(push.commits[0].author, "alice");
+        let paths: Vec<&str> = push.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, ["src/watch.rs", "src/main.rs"]);
+
+        let tag = br#"{"ref": "refs/tags/v1", "before": "0", "after": "1", "compare": "",
+            "repository": {"full_name": "chad/freeq"}, "commits": []}"#;
+        assert_eq!(parse_hook("push", tag).unwrap(), Hook::Ignore);
+        assert_eq!(parse_hook("ping", b"{}").unwrap(), Hook::Ignore);
+    }
+}
@@ -309,8 +309,11 @@ fn connect_config(server: &str, nick: &str) -> anyhow::Result<ConnectConfig> {
             || server.starts_with("https://")
             || server.starts_with("wss://"),
         tls_insecure: false,
+        tls_options: Default::default(),
         web_token: None,
         websocket_url,
+        nick_fallback: Default::default(),
+        capabilities: Default::default(),
     })
 }
 
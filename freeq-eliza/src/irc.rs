@@ -549,8 +549,11 @@ pub async fn run(cfg: RunConfig) -> Result<()> {
             || server.starts_with("https://")
             || server.starts_with("wss://"),
         tls_insecure: false,
+        tls_options: Default::default(),
         web_token: None,
         websocket_url,
+        nick_fallback: Default::default(),
+        capabilities: Default::default(),
     };
 
     let signer = Arc::new(KeySigner::new(did, private_key));
@@ -178,8 +178,11 @@ impl Witness {
             realname: "witness".to_string(),
             tls: false,
             tls_insecure: false,
+            tls_options: Default::default(),
             web_token: None,
             websocket_url: None,
+            nick_fallback: Default::default(),
+            capabilities: Default::default(),
         };
         let (handle, mut events) = client::connect(config, None);
 
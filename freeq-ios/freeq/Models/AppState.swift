@@ -139,6 +139,40 @@ enum BufferCacheStore {
     }
 }
 
+/// Where the SDK spools messages that arrive while the app is suspended.
+/// The spool holds decrypted DM text, so it lives in its own directory
+/// with complete-until-first-unlock protection, which the SDK's files
+/// (it rewrites the spool through a temp file and a rename) inherit. Not
+/// `.complete` like the buffer cache: the SDK writes it while suspended,
+/// when the device may be locked.
+enum EventSpoolStore {
+    private static let protection: [FileAttributeKey: Any] = [
+        .protectionKey: FileProtectionType.completeUntilFirstUserAuthentication,
+    ]
+
+    static func spoolURL() -> URL? {
+        let fm = FileManager.default
+        guard let appSupport = fm.urls(for: .applicationSupportDirectory, in: .userDomainMask).first else { return nil }
+        let dir = appSupport.appendingPathComponent("freeq/spool", isDirectory: true)
+        do {
+            if !fm.fileExists(atPath: dir.path) {
+                try fm.createDirectory(at: dir, withIntermediateDirectories: true, attributes: protection)
+            } else {
+                try fm.setAttributes(protection, ofItemAtPath: dir.path)
+            }
+            let file = dir.appendingPathComponent("events.jsonl")
+            if fm.fileExists(atPath: file.path) {
+                try fm.setAttributes(protection, ofItemAtPath: file.path)
+            } else {
+                fm.createFile(atPath: file.path, contents: nil, attributes: protection)
+            }
+            return file
+        } catch {
+            return nil
+        }
+    }
+}
+
 extension ISO8601DateFormatter {
     /// Shared formatter for the server's CHATHISTORY TARGETS `time` tag
     /// (`YYYY-MM-DDTHH:MM:SS.sssZ`). Cached because `ISO8601DateFormatter`
@@ -1228,6 +1262,13 @@ class AppState: ObservableObject {
             // and use the configured TCP server when falling back.
             let wsUrl = useWebSocket ? ServerConfig.wssServer : ""
             try client?.setWebsocketUrl(url: wsUrl)
+
+            // Spool messages that arrive while suspended; drained on
+            // return to the foreground (see `handleScenePhase`).
+            if let spool = EventSpoolStore.spoolURL() {
+                try? client?.enableEventSpool(path: spool.path)
+                drainSpooledEvents()
+            }
             print("[freeq.connect] transport=\(useWebSocket ? "ws" : "tcp") wsUrl=\(wsUrl) tcp=\(serverAddress) nick=\(nick) hasToken=\(pendingWebToken != nil)")
             authLog.info("connect transport=\(useWebSocket ? "ws" : "tcp", privacy: .public) ws_url=\(wsUrl, privacy: .public)")
 
@@ -1652,7 +1693,10 @@ class AppState: ObservableObject {
     func handleScenePhase(_ phase: ScenePhase) {
         switch phase {
         case .active:
-            // Returning to foreground — reconnect if needed.
+            // Returning to foreground — replay what arrived while we were
+            // suspended, then reconnect if needed.
+            client?.setSuspended(suspended: false)
+            drainSpooledEvents()
             NotificationManager.shared.clearBadge()
             // FEAT-004: skip the broker round-trip when the SDK still has a
             // live transport. Backgrounded apps with healthy WebSocket /
@@ -1681,6 +1725,9 @@ class AppState: ObservableObject {
             // the latest state. iOS also calls .inactive shortly before
             // .background; we save on both for belt-and-suspenders.
             flushBuffersToCache()
+            // Messages that still arrive go to the SDK's spool instead of
+            // a UI that won't render them.
+            client?.setSuspended(suspended: true)
         case .inactive:
             flushBuffersToCache()
         @unknown default:
@@ -1688,6 +1735,17 @@ class AppState: ObservableObject {
         }
     }
 
+    /// Feed events the SDK spooled while the app was suspended (or a
+    /// previous process was killed while suspended) through the normal
+    /// event handler, oldest first. Call after `setSuspended(false)`.
+    private func drainSpooledEvents() {
+        guard let c = client else { return }
+        let pending = c.drainPendingEvents()
+        guard !pending.isEmpty else { return }
+        let handler = SwiftEventHandler(appState: self)
+        pending.forEach(handler.handleEvent)
+    }
+
     func incrementUnread(_ channel: String) {
         guard activeChannel != channel else { return }
         guard !mutedChannels.contains(channel) else { return }
@@ -0,0 +1,151 @@
+//! What each simulated client does once it has joined its channels.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+/// One simulated client's behavior.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Behavior {
+    /// Joins and stays connected without speaking — the bulk of a real
+    /// network, and the receiving end of fan-out.
+    Idle,
+    /// Joins and sends a message to one of its channels every
+    /// `--msg-interval-ms`.
+    Chatty,
+    /// Parts and rejoins its channels every `--churn-interval-ms`, which
+    /// keeps the membership paths busy. Never counted as a receiver.
+    Churn,
+}
+
+impl Behavior {
+    pub const ALL: [Behavior; 3] = [Behavior::Idle, Behavior::Chatty, Behavior::Churn];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Behavior::Idle => "idle",
+            Behavior::Chatty => "chatty",
+            Behavior::Churn => "churn",
+        }
+    }
+}
+
+impl fmt::Display for Behavior {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Behavior {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Behavior::ALL
+            .into_iter()
+            .find(|b| b.as_str() == s)
+            .ok_or_else(|| format!("unknown behavior {s:?} (expected idle, chatty or churn)"))
+    }
+}
+
+/// Relative weights of each behavior, e.g. `idle=70,chatty=25,churn=5`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mix(Vec<(Behavior, u32)>);
+
+impl fmt::Display for Mix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (behavior, weight)) in self.0.iter().enumerate() {
+            let sep = if i == 0 { "" } else { "," };
+            write!(f, "{sep}{behavior}={weight}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Mix {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut weights: Vec<(Behavior, u32)> = Vec::new();
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (name, weight) = part
+                .split_once('=')
+                .ok_or_else(|| format!("expected behavior=weight, got {part:?}"))?;
+            let behavior: Behavior = name.trim().parse()?;
+            let weight: u32 = weight
+                .trim()
+                .parse()
+                .map_err(|_| format!("bad weight for {behavior}: {weight:?}"))?;
+            if weights.iter().any(|(b, _)| *b == behavior) {
+                return Err(format!("{behavior} given twice"));
+            }
+            if weight > 0 {
+                weights.push((behavior, weight));
+            }
+        }
+        if weights.is_empty() {
+            return Err("the mix needs at least one behavior with a non-zero weight".into());
+        }
+        weights.sort();
+        Ok(Mix(weights))
+    }
+}
+
+impl Mix {
+    /// Behaviors for `clients` clients, in proportion to the weights and
+    /// interleaved, so a ramped start brings every behavior up together
+    /// rather than all the idle clients first.
+    pub fn assign(&self, clients: usize) -> Vec<Behavior> {
+        let total: u64 = self.0.iter().map(|(_, w)| u64::from(*w)).sum();
+        let mut given = vec![0u64; self.0.len()];
+        (0..clients as u64)
+            .map(|i| {
+                // Pick the behavior furthest behind its share of the first i+1.
+                let (slot, _) = self
+                    .0
+                    .iter()
+                    .enumerate()
+                    .map(|(slot, (_, w))| {
+                        let due = u64::from(*w) * (i + 1);
+                        (slot, due as i128 - (given[slot] * total) as i128)
+                    })
+                    .max_by_key(|(slot, deficit)| (*deficit, std::cmp::Reverse(*slot)))
+                    .expect("mix is non-empty");
+                given[slot] += 1;
+                self.0[slot].0
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mix_parses_and_rejects_nonsense() {
+        let mix: Mix = "chatty=25, idle=70,churn=0".parse().unwrap();
+        assert_eq!(mix, Mix(vec![(Behavior::Idle, 70), (Behavior::Chatty, 25)]));
+        assert_eq!(mix.to_string(), "idle=70,chatty=25");
+        assert!("idle".parse::<Mix>().is_err());
+        assert!("idle=x".parse::<Mix>().is_err());
+        assert!("lurk=1".parse::<Mix>().is_err());
+        assert!("idle=1,idle=2".parse::<Mix>().is_err());
+        assert!("idle=0".parse::<Mix>().is_err());
+    }
+
+    #[test]
+    fn assignment_is_proportional_and_interleaved() {
+        let mix: Mix = "idle=70,chatty=25,churn=5".parse().unwrap();
+        let assigned = mix.assign(100);
+        let count = |b| assigned.iter().filter(|a| **a == b).count();
+        assert_eq!(count(Behavior::Idle), 70);
+        assert_eq!(count(Behavior::Chatty), 25);
+        assert_eq!(count(Behavior::Churn), 5);
+        assert!(assigned[..10].contains(&Behavior::Chatty));
+
+        let one: Mix = "chatty=1".parse().unwrap();
+        assert_eq!(one.assign(3), vec![Behavior::Chatty; 3]);
+    }
+}
@@ -0,0 +1,233 @@
+//! freeq-loadgen — how many clients can one node take?
+//!
+//! Spawns N simulated clients against a server, each with a behavior from
+//! the `--mix` (idle, chatty or churn), and writes a JSON report of
+//! registration and join latency, message fan-out latency and drop rate.
+//! Run it before and after a change to the server's locking or fan-out
+//! to compare like with like.
+//!
+//! A run has three phases: setup (connect over `--ramp-secs`, register,
+//! join), the measured run (`--duration-secs`), and a drain
+//! (`--drain-secs`) for messages still in flight.
+//!
+//! Usage:
+//!   freeq-loadgen --server 127.0.0.1:6667 --clients 500 --mix idle=80,chatty=15,churn=5 --out report.json
+
+mod behavior;
+mod report;
+mod sim;
+
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use rand::Rng;
+use serde_json::json;
+use tokio::sync::watch;
+
+use behavior::Mix;
+use report::{Clients, Delivery, Latency, Report};
+use sim::{ClientOutcome, Phase, Plan};
+
+#[derive(Parser, Debug)]
+#[command(name = "freeq-loadgen", about = "Load generator for freeq servers")]
+struct Args {
+    /// Server address (host:port)
+    #[arg(long, default_value = "127.0.0.1:6667")]
+    server: String,
+
+    /// Use TLS (certificates aren't verified)
+    #[arg(long)]
+    tls: bool,
+
+    /// Number of simulated clients
+    #[arg(long, default_value = "100")]
+    clients: usize,
+
+    /// Relative weights of the behaviors: idle, chatty, churn
+    #[arg(long, default_value = "idle=80,chatty=15,churn=5")]
+    mix: Mix,
+
+    /// Number of channels to spread the clients over
+    #[arg(long, default_value = "10")]
+    channels: usize,
+
+    /// Channels each client joins
+    #[arg(long, default_value = "1")]
+    channels_per_client: usize,
+
+    /// Average time between a chatty client's messages (ms)
+    #[arg(long, default_value = "1000")]
+    msg_interval_ms: u64,
+
+    /// Average time between a churning client's part/rejoin cycles (ms)
+    #[arg(long, default_value = "5000")]
+    churn_interval_ms: u64,
+
+    /// Spread the connections evenly over this many seconds
+    #[arg(long, default_value = "10")]
+    ramp_secs: u64,
+
+    /// How long clients have to register and join before the run starts
+    /// without them (seconds, counted from the end of the ramp)
+    #[arg(long, default_value = "30")]
+    setup_timeout_secs: u64,
+
+    /// Length of the measured phase (seconds)
+    #[arg(long, default_value = "60")]
+    duration_secs: u64,
+
+    /// Time to keep receiving after the run (seconds)
+    #[arg(long, default_value = "5")]
+    drain_secs: u64,
+
+    /// Write the report here instead of stdout
+    #[arg(long)]
+    out: Option<std::path::PathBuf>,
+
+    /// Exit non-zero if the drop rate exceeds this fraction (e.g. 0.001)
+    #[arg(long)]
+    max_drop_rate: Option<f64>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| "freeq_loadgen=info".into()),
+        )
+        .with_writer(std::io::stderr)
+        .init();
+
+    let args = Args::parse();
+    anyhow::ensure!(args.clients > 0, "--clients must be at least 1");
+    anyhow::ensure!(args.channels > 0, "--channels must be at least 1");
+    let per_client = args.channels_per_client.clamp(1, args.channels);
+
+    let run_id = format!("{:06x}", rand::thread_rng().gen_range(0..0x100_0000u32));
+    let started_at = chrono::Utc::now().to_rfc3339();
+    let plan = Arc::new(Plan {
+        server: args.server.clone(),
+        tls: args.tls,
+        run_id: run_id.clone(),
+        channels: (0..args.channels)
+            .map(|i| format!("#loadgen-{run_id}-{i}"))
+            .collect(),
+        msg_interval: Duration::from_millis(args.msg_interval_ms.max(1)),
+        churn_interval: Duration::from_millis(args.churn_interval_ms.max(1)),
+        setup_timeout: Duration::from_secs(args.ramp_secs + args.setup_timeout_secs),
+        epoch: Instant::now(),
+        ready: Default::default(),
+        settled: Default::default(),
+    });
+    let (phase_tx, phase_rx) = watch::channel(Phase::Setup);
+
+    tracing::info!(
+        "run {run_id}: {} clients against {} ({})",
+        args.clients,
+        args.server,
+        args.mix
+    );
+    let stagger = Duration::from_secs(args.ramp_secs) / args.clients as u32;
+    let mut tasks = Vec::with_capacity(args.clients);
+    for (index, behavior) in args.mix.assign(args.clients).into_iter().enumerate() {
+        let channels = (0..per_client)
+            .map(|k| (index + k) % args.channels)
+            .collect();
+        let outcome = ClientOutcome::new(index, behavior, channels);
+        tasks.push(tokio::spawn(sim::run_client(
+            plan.clone(),
+            outcome,
+            phase_rx.clone(),
+        )));
+        if !stagger.is_zero() {
+            tokio::time::sleep(stagger).await;
+        }
+    }
+
+    // Start once every client is in its channels or setup times out.
+    let setup_deadline = Instant::now() + Duration::from_secs(args.setup_timeout_secs);
+    while Instant::now() < setup_deadline && plan.settled.load(Ordering::Relaxed) < args.clients {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    tracing::info!(
+        "{} of {} clients ready",
+        plan.ready.load(Ordering::Relaxed),
+        args.clients
+    );
+
+    phase_tx.send_replace(Phase::Run);
+    let run_started = Instant::now();
+    tokio::time::sleep(Duration::from_secs(args.duration_secs)).await;
+    let run_secs = run_started.elapsed().as_secs_f64();
+    tracing::info!("run over, draining");
+    phase_tx.send_replace(Phase::Drain);
+    tokio::time::sleep(Duration::from_secs(args.drain_secs)).await;
+    phase_tx.send_replace(Phase::Done);
+
+    let mut outcomes = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        outcomes.push(task.await.context("client task panicked")?);
+    }
+
+    let delivery = Delivery::account(&outcomes);
+    let ms = |d: &Duration| d.as_secs_f64() * 1000.0;
+    let report = Report {
+        server: args.server.clone(),
+        run_id,
+        started_at,
+        config: json!({
+            "clients": args.clients,
+            "mix": args.mix.to_string(),
+            "channels": args.channels,
+            "channels_per_client": per_client,
+            "msg_interval_ms": args.msg_interval_ms,
+            "churn_interval_ms": args.churn_interval_ms,
+            "ramp_secs": args.ramp_secs,
+            "duration_secs": args.duration_secs,
+            "drain_secs": args.drain_secs,
+        }),
+        clients: Clients::count(args.clients, &outcomes),
+        registration_ms: Latency::summarize(
+            outcomes
+                .iter()
+                .filter_map(|o| o.registration.as_ref())
+                .map(ms)
+                .collect(),
+        ),
+        join_ms: Latency::summarize(outcomes.iter().flat_map(|o| &o.joins).map(ms).collect()),
+        fanout_ms: Latency::summarize(
+            outcomes
+                .iter()
+                .flat_map(|o| o.fanout_ms.iter().copied())
+                .collect(),
+        ),
+        sent_per_sec: delivery.sent as f64 / run_secs,
+        delivered_per_sec: delivery.received as f64 / run_secs,
+        delivery,
+        run_secs,
+    };
+
+    let json = serde_json::to_string_pretty(&report)?;
+    match &args.out {
+        Some(path) => {
+            std::fs::write(path, format!("{json}\n"))
+                .with_context(|| format!("writing {}", path.display()))?;
+            tracing::info!("report written to {}", path.display());
+        }
+        None => println!("{json}"),
+    }
+
+    if let Some(max) = args.max_drop_rate
+        && report.delivery.drop_rate > max
+    {
+        anyhow::bail!(
+            "drop rate {:.4} is over the limit of {max}",
+            report.delivery.drop_rate
+        );
+    }
+    Ok(())
+}
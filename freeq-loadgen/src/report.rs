@@ -0,0 +1,196 @@
+//! The JSON report: latency percentiles and delivery accounting over what
+//! the simulated clients saw.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::behavior::Behavior;
+use crate::sim::ClientOutcome;
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub server: String,
+    pub run_id: String,
+    pub started_at: String,
+    pub config: serde_json::Value,
+    pub clients: Clients,
+    /// From opening the connection to `001`.
+    pub registration_ms: Latency,
+    /// From `JOIN` to the end of `NAMES`, per channel.
+    pub join_ms: Latency,
+    /// From a chatty client sending a message to each other member
+    /// receiving it.
+    pub fanout_ms: Latency,
+    pub delivery: Delivery,
+    /// Length of the measured phase, in seconds.
+    pub run_secs: f64,
+    pub sent_per_sec: f64,
+    pub delivered_per_sec: f64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Clients {
+    pub requested: usize,
+    pub registered: usize,
+    /// Registered and joined every channel before the measured phase.
+    pub ready: usize,
+    /// Lost their connection before the end of the run.
+    pub disconnected: usize,
+    pub by_behavior: BTreeMap<Behavior, usize>,
+}
+
+impl Clients {
+    pub fn count(requested: usize, outcomes: &[ClientOutcome]) -> Self {
+        let mut clients = Clients {
+            requested,
+            ..Default::default()
+        };
+        for o in outcomes {
+            clients.registered += usize::from(o.registration.is_some());
+            clients.ready += usize::from(o.ready);
+            clients.disconnected += usize::from(o.disconnected);
+            *clients.by_behavior.entry(o.behavior).or_default() += 1;
+        }
+        clients
+    }
+}
+
+/// Summary of a set of latency samples, in milliseconds.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Latency {
+    pub samples: usize,
+    pub min: f64,
+    pub mean: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl Latency {
+    pub fn summarize(mut samples: Vec<f64>) -> Self {
+        if samples.is_empty() {
+            return Latency::default();
+        }
+        samples.sort_by(f64::total_cmp);
+        // Nearest rank: the smallest sample at or above the percentile.
+        let rank = |p: f64| {
+            let i = (p / 100.0 * samples.len() as f64).ceil() as usize;
+            samples[i.clamp(1, samples.len()) - 1]
+        };
+        Latency {
+            samples: samples.len(),
+            min: samples[0],
+            mean: samples.iter().sum::<f64>() / samples.len() as f64,
+            p50: rank(50.0),
+            p90: rank(90.0),
+            p99: rank(99.0),
+            max: samples[samples.len() - 1],
+        }
+    }
+}
+
+/// Messages the ready, non-churning members of a channel should have
+/// received against what they did.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Delivery {
+    pub sent: usize,
+    /// One per message per other ready member of its channel.
+    pub expected: usize,
+    pub received: usize,
+    pub dropped: usize,
+    /// The same message delivered to the same client more than once.
+    pub duplicates: usize,
+    /// `dropped / expected`, 0 when nothing was expected.
+    pub drop_rate: f64,
+}
+
+impl Delivery {
+    pub fn account(outcomes: &[ClientOutcome]) -> Self {
+        // The clients that count as receivers in each channel.
+        let mut members: HashMap<usize, HashSet<usize>> = HashMap::new();
+        for o in outcomes.iter().filter(|o| o.counts_as_receiver()) {
+            for &channel in &o.channels {
+                members.entry(channel).or_default().insert(o.index);
+            }
+        }
+        let mut channel_of = HashMap::new();
+        let mut delivery = Delivery::default();
+        for o in outcomes {
+            for &(channel, seq) in &o.sent {
+                channel_of.insert((o.index, seq), channel);
+                delivery.sent += 1;
+                delivery.expected += members
+                    .get(&channel)
+                    .map_or(0, |m| m.len() - usize::from(m.contains(&o.index)));
+            }
+        }
+        for o in outcomes.iter().filter(|o| o.counts_as_receiver()) {
+            for (&(sender, seq), &times) in &o.received {
+                let Some(channel) = channel_of.get(&(sender, seq)) else {
+                    continue;
+                };
+                if sender != o.index && o.channels.contains(channel) {
+                    delivery.received += 1;
+                    delivery.duplicates += times as usize - 1;
+                }
+            }
+        }
+        delivery.dropped = delivery.expected.saturating_sub(delivery.received);
+        if delivery.expected > 0 {
+            delivery.drop_rate = delivery.dropped as f64 / delivery.expected as f64;
+        }
+        delivery
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_use_the_nearest_rank() {
+        let latency = Latency::summarize((1..=100).rev().map(f64::from).collect());
+        assert_eq!(latency.samples, 100);
+        assert_eq!((latency.min, latency.max), (1.0, 100.0));
+        assert_eq!((latency.p50, latency.p90, latency.p99), (50.0, 90.0, 99.0));
+        assert_eq!(latency.mean, 50.5);
+        assert_eq!(Latency::summarize(vec![7.0]).p99, 7.0);
+        assert_eq!(Latency::summarize(vec![]), Latency::default());
+    }
+
+    fn client(index: usize, behavior: Behavior, channels: &[usize]) -> ClientOutcome {
+        let mut o = ClientOutcome::new(index, behavior, channels.to_vec());
+        o.ready = true;
+        o
+    }
+
+    #[test]
+    fn drops_count_only_ready_members_other_than_the_sender() {
+        let mut talker = client(0, Behavior::Chatty, &[0]);
+        talker.sent = vec![(0, 0), (0, 1)];
+        let mut heard_all = client(1, Behavior::Idle, &[0]);
+        heard_all.received.insert((0, 0), 2);
+        heard_all.received.insert((0, 1), 1);
+        let mut heard_one = client(2, Behavior::Idle, &[0]);
+        heard_one.received.insert((0, 1), 1);
+        let mut late = client(3, Behavior::Idle, &[0]);
+        late.ready = false;
+        let churner = client(4, Behavior::Churn, &[0]);
+        let elsewhere = client(5, Behavior::Idle, &[1]);
+
+        let delivery = Delivery::account(&[talker, heard_all, heard_one, late, churner, elsewhere]);
+        assert_eq!(
+            delivery,
+            Delivery {
+                sent: 2,
+                expected: 4,
+                received: 3,
+                dropped: 1,
+                duplicates: 1,
+                drop_rate: 0.25,
+            }
+        );
+    }
+}
@@ -0,0 +1,285 @@
+//! One simulated client: connect, join, act out its [`Behavior`] while the
+//! run lasts, and record what it saw.
+//!
+//! Every message a chatty client sends carries the run id, the sender's
+//! index, a sequence number and the send time relative to the shared
+//! epoch, so any receiver in this process can work out fan-out latency and
+//! the report can match receipts to sends.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use freeq_sdk::client::{self, ConnectConfig};
+use freeq_sdk::event::Event;
+use rand::Rng;
+use tokio::sync::{mpsc, watch};
+use tokio::time::{sleep_until, timeout};
+
+use crate::behavior::Behavior;
+
+/// Where the run is; the main task moves everyone along.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Clients connect and join. Those not ready by the end of it don't
+    /// count as receivers.
+    Setup,
+    /// The measured phase: chatty and churning clients act.
+    Run,
+    /// Nobody acts; messages still in flight are received.
+    Drain,
+    /// Quit.
+    Done,
+}
+
+/// Settings shared by every client.
+pub struct Plan {
+    pub server: String,
+    pub tls: bool,
+    pub run_id: String,
+    pub channels: Vec<String>,
+    pub msg_interval: Duration,
+    pub churn_interval: Duration,
+    pub setup_timeout: Duration,
+    /// What message send times are measured from.
+    pub epoch: Instant,
+    /// Clients that finished setup.
+    pub ready: AtomicUsize,
+    /// Clients that finished setup or gave up on it.
+    pub settled: AtomicUsize,
+}
+
+impl Plan {
+    fn tag(&self) -> String {
+        format!("lg-{}", self.run_id)
+    }
+}
+
+/// What one client did and saw.
+#[derive(Debug)]
+pub struct ClientOutcome {
+    pub index: usize,
+    pub behavior: Behavior,
+    /// Indexes into [`Plan::channels`].
+    pub channels: Vec<usize>,
+    pub registration: Option<Duration>,
+    pub joins: Vec<Duration>,
+    /// Registered and joined before the measured phase began.
+    pub ready: bool,
+    pub disconnected: bool,
+    /// `(channel, seq)` of each message sent.
+    pub sent: Vec<(usize, u64)>,
+    /// `(sender, seq)` of each run message received, and how many times.
+    pub received: HashMap<(usize, u64), u32>,
+    /// Fan-out latency of each receipt, in milliseconds.
+    pub fanout_ms: Vec<f64>,
+}
+
+impl ClientOutcome {
+    pub fn new(index: usize, behavior: Behavior, channels: Vec<usize>) -> Self {
+        ClientOutcome {
+            index,
+            behavior,
+            channels,
+            registration: None,
+            joins: Vec::new(),
+            ready: false,
+            disconnected: false,
+            sent: Vec::new(),
+            received: HashMap::new(),
+            fanout_ms: Vec::new(),
+        }
+    }
+
+    /// Churning clients miss messages by design, and clients that weren't
+    /// in their channels when the run began may have missed some.
+    pub fn counts_as_receiver(&self) -> bool {
+        self.ready && self.behavior != Behavior::Churn
+    }
+}
+
+/// `lg-<run> <sender> <seq> <micros since epoch>`
+fn encode(tag: &str, sender: usize, seq: u64, sent_at: Duration) -> String {
+    format!("{tag} {sender} {seq} {}", sent_at.as_micros())
+}
+
+fn decode(tag: &str, text: &str) -> Option<(usize, u64, Duration)> {
+    let mut words = text.split_whitespace();
+    if words.next()? != tag {
+        return None;
+    }
+    let sender = words.next()?.parse().ok()?;
+    let seq = words.next()?.parse().ok()?;
+    let micros = words.next()?.parse().ok()?;
+    Some((sender, seq, Duration::from_micros(micros)))
+}
+
+/// `interval`, give or take half, so clients drift apart instead of
+/// acting in lockstep.
+fn jittered(interval: Duration) -> Instant {
+    let factor = rand::thread_rng().gen_range(0.5..1.5);
+    Instant::now() + interval.mul_f64(factor)
+}
+
+/// Wait for `want`, giving up on disconnect or at `deadline`.
+async fn wait_for<T>(
+    events: &mut mpsc::Receiver<Event>,
+    deadline: Instant,
+    mut want: impl FnMut(&Event) -> Option<T>,
+) -> Result<T, String> {
+    loop {
+        let event = match timeout(
+            deadline.saturating_duration_since(Instant::now()),
+            events.recv(),
+        )
+        .await
+        {
+            Ok(Some(event)) => event,
+            Ok(None) => return Err("event stream closed".into()),
+            Err(_) => return Err("timed out".into()),
+        };
+        if let Event::Disconnected { reason } = &event {
+            return Err(format!("disconnected: {reason}"));
+        }
+        if let Some(found) = want(&event) {
+            return Ok(found);
+        }
+    }
+}
+
+pub async fn run_client(
+    plan: Arc<Plan>,
+    mut outcome: ClientOutcome,
+    mut phase: watch::Receiver<Phase>,
+) -> ClientOutcome {
+    let index = outcome.index;
+    let config = ConnectConfig {
+        server_addr: plan.server.clone(),
+        nick: format!("lg{}x{index}", plan.run_id),
+        user: "loadgen".to_string(),
+        realname: format!("freeq-loadgen {}", outcome.behavior),
+        tls: plan.tls,
+        tls_insecure: plan.tls,
+        tls_options: Default::default(),
+        web_token: None,
+        websocket_url: None,
+        nick_fallback: Default::default(),
+        capabilities: Default::default(),
+    };
+    let deadline = Instant::now() + plan.setup_timeout;
+    let started = Instant::now();
+    let (handle, mut events) = client::connect(config, None);
+
+    let setup = async {
+        wait_for(&mut events, deadline, |e| {
+            matches!(e, Event::Registered { .. }).then_some(())
+        })
+        .await?;
+        outcome.registration = Some(started.elapsed());
+        for &channel in &outcome.channels {
+            let name = &plan.channels[channel];
+            let asked = Instant::now();
+            handle.join(name).await.map_err(|e| e.to_string())?;
+            wait_for(&mut events, deadline, |e| match e {
+                Event::NamesEnd { channel } if channel.eq_ignore_ascii_case(name) => Some(()),
+                _ => None,
+            })
+            .await?;
+            outcome.joins.push(asked.elapsed());
+        }
+        Ok::<_, String>(())
+    };
+    let setup = setup.await;
+    plan.settled.fetch_add(1, Ordering::Relaxed);
+    if let Err(e) = setup {
+        tracing::warn!(client = index, "setup failed: {e}");
+        outcome.disconnected = e.starts_with("disconnected");
+        let _ = handle.quit(Some("loadgen setup failed")).await;
+        return outcome;
+    }
+    outcome.ready = *phase.borrow() == Phase::Setup;
+    plan.ready
+        .fetch_add(usize::from(outcome.ready), Ordering::Relaxed);
+
+    let tag = plan.tag();
+    let interval = match outcome.behavior {
+        Behavior::Idle => None,
+        Behavior::Chatty => Some(plan.msg_interval),
+        Behavior::Churn => Some(plan.churn_interval),
+    };
+    let mut next = interval.map_or_else(Instant::now, jittered);
+    let mut seq = 0u64;
+    loop {
+        let acting = interval.is_some() && *phase.borrow() == Phase::Run;
+        tokio::select! {
+            event = events.recv() => match event {
+                Some(Event::Message { text, .. }) => {
+                    if let Some((sender, seq, sent_at)) = decode(&tag, &text) {
+                        let latency = plan.epoch.elapsed().saturating_sub(sent_at);
+                        outcome.fanout_ms.push(latency.as_secs_f64() * 1000.0);
+                        *outcome.received.entry((sender, seq)).or_default() += 1;
+                    }
+                }
+                Some(Event::Disconnected { reason }) => {
+                    tracing::warn!(client = index, "disconnected: {reason}");
+                    outcome.disconnected = true;
+                    return outcome;
+                }
+                None => {
+                    outcome.disconnected = true;
+                    return outcome;
+                }
+                Some(_) => {}
+            },
+            changed = phase.changed() => {
+                if changed.is_err() || *phase.borrow() == Phase::Done {
+                    break;
+                }
+                if let Some(interval) = interval {
+                    next = jittered(interval);
+                }
+            }
+            _ = sleep_until(next.into()), if acting => {
+                match outcome.behavior {
+                    Behavior::Chatty => {
+                        let channel = outcome.channels[seq as usize % outcome.channels.len()];
+                        let text = encode(&tag, index, seq, plan.epoch.elapsed());
+                        if handle.privmsg(&plan.channels[channel], &text).await.is_ok() {
+                            outcome.sent.push((channel, seq));
+                        }
+                        seq += 1;
+                    }
+                    Behavior::Churn => {
+                        for &channel in &outcome.channels {
+                            let name = &plan.channels[channel];
+                            let _ = handle.raw(&format!("PART {name}")).await;
+                            let _ = handle.join(name).await;
+                        }
+                    }
+                    Behavior::Idle => {}
+                }
+                next = jittered(interval.unwrap_or_default());
+            }
+        }
+    }
+    let _ = handle.quit(Some("loadgen done")).await;
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_messages_roundtrip_and_ignore_other_runs() {
+        let text = encode("lg-abc", 7, 42, Duration::from_micros(1_234_567));
+        assert_eq!(
+            decode("lg-abc", &text),
+            Some((7, 42, Duration::from_micros(1_234_567)))
+        );
+        assert_eq!(decode("lg-xyz", &text), None);
+        assert_eq!(decode("lg-abc", "lg-abc 7 nope 1"), None);
+        assert_eq!(decode("lg-abc", "hello"), None);
+    }
+}
@@ -7,6 +7,8 @@ cd "$REPO_ROOT"
 
 git ls-files -z -- \
   Cargo.lock \
+  freeq-proto/Cargo.toml \
+  freeq-proto/src \
   freeq-sdk/Cargo.toml \
   freeq-sdk/src \
   freeq-sdk-ffi/Cargo.toml \
@@ -0,0 +1,137 @@
+//! Appservice HTTP listener: the homeserver pushes room events here.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::{get, put};
+use parking_lot::Mutex;
+use serde_json::{Value, json};
+use tokio::sync::mpsc;
+
+/// Transaction IDs remembered for retry dedup.
+const SEEN_TXNS: usize = 256;
+
+struct AppState {
+    hs_token: String,
+    events: mpsc::Sender<Value>,
+    seen: Mutex<VecDeque<String>>,
+}
+
+/// Routes for the appservice API. Events from each new transaction are
+/// forwarded to `events` in order.
+pub fn router(hs_token: String, events: mpsc::Sender<Value>) -> axum::Router {
+    let state = Arc::new(AppState {
+        hs_token,
+        events,
+        seen: Mutex::new(VecDeque::new()),
+    });
+    axum::Router::new()
+        .route("/_matrix/app/v1/transactions/{txn_id}", put(transaction))
+        .route("/_matrix/app/v1/users/{user_id}", get(not_found))
+        .route("/_matrix/app/v1/rooms/{alias}", get(not_found))
+        .with_state(state)
+}
+
+fn authorized(state: &AppState, headers: &HeaderMap) -> bool {
+    headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|token| token == state.hs_token)
+}
+
+async fn transaction(
+    State(state): State<Arc<AppState>>,
+    Path(txn_id): Path<String>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> (StatusCode, Json<Value>) {
+    if !authorized(&state, &headers) {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(json!({ "errcode": "M_FORBIDDEN" })),
+        );
+    }
+    {
+        let mut seen = state.seen.lock();
+        if seen.contains(&txn_id) {
+            return (StatusCode::OK, Json(json!({})));
+        }
+        seen.push_back(txn_id);
+        if seen.len() > SEEN_TXNS {
+            seen.pop_front();
+        }
+    }
+    if let Some(events) = body["events"].as_array() {
+        for event in events {
+            if state.events.send(event.clone()).await.is_err() {
+                return (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    Json(json!({ "errcode": "M_UNKNOWN" })),
+                );
+            }
+        }
+    }
+    (StatusCode::OK, Json(json!({})))
+}
+
+/// The bridge creates ghosts and has no aliases to provision on demand.
+async fn not_found(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> (StatusCode, Json<Value>) {
+    if !authorized(&state, &headers) {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(json!({ "errcode": "M_FORBIDDEN" })),
+        );
+    }
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({ "errcode": "M_NOT_FOUND" })),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn serve() -> (std::net::SocketAddr, mpsc::Receiver<Value>) {
+        let (tx, rx) = mpsc::channel(16);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = router("hs".into(), tx);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (addr, rx)
+    }
+
+    #[tokio::test]
+    async fn transactions_are_authenticated_and_deduplicated() {
+        let (addr, mut rx) = serve().await;
+        let http = reqwest::Client::new();
+        let url = format!("http://{addr}/_matrix/app/v1/transactions/t1");
+        let body = json!({ "events": [{ "type": "m.room.message", "event_id": "$1" }] });
+
+        let resp = http.put(&url).json(&body).send().await.unwrap();
+        assert_eq!(resp.status(), 403);
+
+        for _ in 0..2 {
+            let resp = http
+                .put(&url)
+                .bearer_auth("hs")
+                .json(&body)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), 200);
+        }
+        assert_eq!(rx.recv().await.unwrap()["event_id"], "$1");
+        assert!(
+            rx.try_recv().is_err(),
+            "retried transaction delivered twice"
+        );
+    }
+}
@@ -0,0 +1,662 @@
+//! The bridge loop: one relay connection to freeq, the appservice
+//! listener, and the state that ties message IDs and users together.
+//!
+//! freeq → Matrix: the relay bot sits in every bridged channel and posts
+//! each message into the room as a ghost user standing in for the nick.
+//!
+//! Matrix → freeq: each active Matrix user gets a puppet connection with
+//! their own nick. When a puppet can't be opened the relay bot speaks for
+//! them with a `<name>` prefix instead.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, bail};
+use freeq_sdk::auth::{ChallengeSigner, KeySigner};
+use freeq_sdk::client::{self, ClientHandle, ConnectConfig};
+use freeq_sdk::crypto::PrivateKey;
+use freeq_sdk::event::Event;
+use freeq_sdk::media::MediaAttachment;
+use freeq_sdk::proto::tags;
+use serde_json::Value;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::appservice;
+use crate::config::Config;
+use crate::matrix::MatrixClient;
+use crate::translate::{self, FromFreeq, FromMatrix};
+
+/// Matches the server's upload limit.
+const MAX_MEDIA_BYTES: usize = 10 * 1024 * 1024;
+/// msgid ↔ event ID pairs remembered for replies, edits and reactions.
+const EVENT_MAP_CAP: usize = 10_000;
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const PUPPET_REGISTER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Bounded two-way map between freeq msgids and Matrix event IDs. The
+/// oldest pair is forgotten once `cap` is reached.
+pub struct EventMap {
+    cap: usize,
+    order: VecDeque<(String, String)>,
+    by_msgid: HashMap<String, String>,
+    by_event: HashMap<String, String>,
+}
+
+impl EventMap {
+    pub fn new(cap: usize) -> Self {
+        Self {
+            cap,
+            order: VecDeque::new(),
+            by_msgid: HashMap::new(),
+            by_event: HashMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, msgid: &str, event_id: &str) {
+        if self.order.len() >= self.cap
+            && let Some((old_msgid, old_event)) = self.order.pop_front()
+        {
+            self.by_msgid.remove(&old_msgid);
+            self.by_event.remove(&old_event);
+        }
+        self.order
+            .push_back((msgid.to_string(), event_id.to_string()));
+        self.by_msgid
+            .insert(msgid.to_string(), event_id.to_string());
+        self.by_event
+            .insert(event_id.to_string(), msgid.to_string());
+    }
+
+    pub fn event_for(&self, msgid: &str) -> Option<&str> {
+        self.by_msgid.get(msgid).map(String::as_str)
+    }
+
+    pub fn msgid_for(&self, event_id: &str) -> Option<&str> {
+        self.by_event.get(event_id).map(String::as_str)
+    }
+}
+
+/// A freeq connection speaking for one Matrix user.
+struct Puppet {
+    handle: ClientHandle,
+    nick: String,
+    joined: HashSet<String>,
+    last_active: Instant,
+}
+
+struct Bridge {
+    config: Config,
+    matrix: MatrixClient,
+    relay: ClientHandle,
+    relay_nick: String,
+    /// DID the relay authenticates as; attachments are uploaded under it.
+    relay_did: Option<String>,
+    /// Keyed by Matrix user ID.
+    puppets: HashMap<String, Puppet>,
+    /// (ghost user ID, room ID) pairs already joined.
+    ghosts: HashSet<(String, String)>,
+    events: EventMap,
+    /// Open chathistory batches; their replayed messages are not bridged.
+    history_batches: HashSet<String>,
+}
+
+/// Run the bridge until the appservice listener fails.
+pub async fn run(config: Config) -> Result<()> {
+    let matrix = MatrixClient::new(&config.matrix.homeserver, &config.matrix.as_token);
+
+    let (matrix_tx, mut matrix_rx) = mpsc::channel(256);
+    let listener = tokio::net::TcpListener::bind(&config.matrix.bind)
+        .await
+        .with_context(|| format!("binding {}", config.matrix.bind))?;
+    let app = appservice::router(config.matrix.hs_token.clone(), matrix_tx);
+    let mut server = tokio::spawn(async move { axum::serve(listener, app).await });
+    tracing::info!(bind = %config.matrix.bind, "appservice listening");
+
+    for mapping in &config.rooms {
+        if let Err(e) = matrix.join(&mapping.room, None).await {
+            tracing::warn!(room = %mapping.room, "bridge bot could not join: {e}");
+        }
+    }
+
+    let signer = load_signer(&config)?;
+    let relay_did = signer.as_ref().map(|s| s.did().to_string());
+    let (relay, mut freeq_rx) = connect_relay(&config, signer.clone());
+
+    let mut bridge = Bridge {
+        relay_nick: config.freeq.nick.clone(),
+        config,
+        matrix,
+        relay,
+        relay_did,
+        puppets: HashMap::new(),
+        ghosts: HashSet::new(),
+        events: EventMap::new(EVENT_MAP_CAP),
+        history_batches: HashSet::new(),
+    };
+
+    let mut sweep = tokio::time::interval(Duration::from_secs(60));
+    loop {
+        tokio::select! {
+            event = freeq_rx.recv() => match event {
+                Some(Event::Disconnected { reason }) => {
+                    tracing::warn!("relay disconnected: {reason}");
+                    bridge.drop_puppets().await;
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                    let (relay, rx) = connect_relay(&bridge.config, signer.clone());
+                    bridge.relay = relay;
+                    freeq_rx = rx;
+                }
+                None => {
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                    let (relay, rx) = connect_relay(&bridge.config, signer.clone());
+                    bridge.relay = relay;
+                    freeq_rx = rx;
+                }
+                Some(event) => {
+                    if let Err(e) = bridge.on_freeq(event).await {
+                        tracing::warn!("freeq → matrix: {e:#}");
+                    }
+                }
+            },
+            Some(event) = matrix_rx.recv() => {
+                if let Err(e) = bridge.on_matrix(event).await {
+                    tracing::warn!("matrix → freeq: {e:#}");
+                }
+            }
+            _ = sweep.tick() => bridge.sweep_puppets().await,
+            result = &mut server => {
+                result??;
+                bail!("appservice listener exited");
+            }
+        }
+    }
+}
+
+/// The relay's signer, from the `freeq-bot-id` seed in `key_file`.
+fn load_signer(config: &Config) -> Result<Option<Arc<KeySigner>>> {
+    let Some(path) = &config.freeq.key_file else {
+        return Ok(None);
+    };
+    let seed = std::fs::read(path).with_context(|| format!("reading {path}"))?;
+    let key = PrivateKey::ed25519_from_bytes(&seed).context("loading ed25519 seed")?;
+    let did = format!("did:key:{}", key.public_key_multibase());
+    tracing::info!(%did, "relay identity");
+    Ok(Some(Arc::new(KeySigner::new(did, key))))
+}
+
+fn connect_relay(
+    config: &Config,
+    signer: Option<Arc<KeySigner>>,
+) -> (ClientHandle, mpsc::Receiver<Event>) {
+    let connect = ConnectConfig {
+        server_addr: config.freeq.server.clone(),
+        nick: config.freeq.nick.clone(),
+        user: "matrix".to_string(),
+        realname: "freeq ↔ Matrix bridge".to_string(),
+        tls: config.freeq.tls,
+        ..Default::default()
+    };
+    client::connect(connect, signer.map(|s| s as Arc<dyn ChallengeSigner>))
+}
+
+impl Bridge {
+    /// Whether `nick` is the relay or one of our puppets.
+    fn is_bridge_nick(&self, nick: &str) -> bool {
+        nick.eq_ignore_ascii_case(&self.relay_nick)
+            || self
+                .puppets
+                .values()
+                .any(|p| p.nick.eq_ignore_ascii_case(nick))
+    }
+
+    fn is_history(&self, msg_tags: &HashMap<String, String>) -> bool {
+        msg_tags
+            .get(tags::BATCH)
+            .is_some_and(|id| self.history_batches.contains(id))
+    }
+
+    async fn on_freeq(&mut self, event: Event) -> Result<()> {
+        match event {
+            Event::Registered { nick } => {
+                self.relay_nick = nick;
+                let channels: Vec<&str> = self
+                    .config
+                    .rooms
+                    .iter()
+                    .map(|m| m.channel.as_str())
+                    .collect();
+                self.relay.join_many(&channels).await?;
+            }
+            Event::BatchStart { id, batch_type, .. } => {
+                if batch_type.eq_ignore_ascii_case("chathistory") {
+                    self.history_batches.insert(id);
+                }
+            }
+            Event::BatchEnd { id } => {
+                self.history_batches.remove(&id);
+            }
+            Event::Message {
+                from,
+                target,
+                text,
+                tags: msg_tags,
+                ..
+            } => {
+                if self.is_history(&msg_tags) || self.is_bridge_nick(&from) {
+                    return Ok(());
+                }
+                let Some(room) = self.config.room_for_channel(&target).map(str::to_string) else {
+                    return Ok(());
+                };
+                let ghost = self.ensure_ghost(&from, &room).await?;
+                let content = match translate::from_freeq_message(&text, &msg_tags) {
+                    FromFreeq::Text {
+                        text,
+                        emote,
+                        reply_to,
+                    } => {
+                        let reply = reply_to.and_then(|m| self.events.event_for(&m));
+                        translate::text_content(&text, emote, reply)
+                    }
+                    FromFreeq::Edit { original, text } => match self.events.event_for(&original) {
+                        Some(event_id) => translate::edit_content(event_id, &text),
+                        None => translate::text_content(&format!("* {text}"), false, None),
+                    },
+                    FromFreeq::Media(media) => match self.rehost(&media).await {
+                        Ok(mxc) => translate::media_content(&mxc, &media),
+                        Err(e) => {
+                            tracing::debug!(url = %media.url, "not re-hosting attachment: {e:#}");
+                            translate::text_content(&media.fallback_text(), false, None)
+                        }
+                    },
+                    // Only produced for TAGMSG.
+                    FromFreeq::Reaction { .. } | FromFreeq::Delete { .. } => return Ok(()),
+                };
+                let event_id = self
+                    .matrix
+                    .send(&room, &ghost, "m.room.message", &content)
+                    .await?;
+                if let Some(msgid) = msg_tags.get(tags::MSGID) {
+                    self.events.insert(msgid, &event_id);
+                }
+            }
+            Event::TagMsg {
+                from,
+                target,
+                tags: msg_tags,
+            } => {
+                if self.is_history(&msg_tags) || self.is_bridge_nick(&from) {
+                    return Ok(());
+                }
+                let Some(room) = self.config.room_for_channel(&target).map(str::to_string) else {
+                    return Ok(());
+                };
+                match translate::from_freeq_tagmsg(&msg_tags) {
+                    Some(FromFreeq::Reaction { target, emoji }) => {
+                        let Some(event_id) = self.events.event_for(&target).map(str::to_string)
+                        else {
+                            return Ok(());
+                        };
+                        let ghost = self.ensure_ghost(&from, &room).await?;
+                        let content = translate::reaction_content(&event_id, &emoji);
+                        self.matrix
+                            .send(&room, &ghost, "m.reaction", &content)
+                            .await?;
+                    }
+                    Some(FromFreeq::Delete { target }) => {
+                        let Some(event_id) = self.events.event_for(&target).map(str::to_string)
+                        else {
+                            return Ok(());
+                        };
+                        let ghost = self.ensure_ghost(&from, &room).await?;
+                        self.matrix.redact(&room, &ghost, &event_id).await?;
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// The ghost user for `nick`, registered and joined to `room`.
+    async fn ensure_ghost(&mut self, nick: &str, room: &str) -> Result<String> {
+        let m = &self.config.matrix;
+        let user_id = format!(
+            "@{}:{}",
+            translate::ghost_localpart(&m.ghost_prefix, nick),
+            m.domain
+        );
+        let key = (user_id.clone(), room.to_string());
+        if self.ghosts.contains(&key) {
+            return Ok(user_id);
+        }
+        if !self.ghosts.iter().any(|(u, _)| *u == user_id) {
+            let localpart = translate::ghost_localpart(&m.ghost_prefix, nick);
+            self.matrix.register(&localpart).await?;
+            if let Err(e) = self.matrix.set_displayname(&user_id, nick).await {
+                tracing::debug!(%user_id, "setting displayname: {e:#}");
+            }
+        }
+        // Fails harmlessly when the ghost is already a member.
+        let _ = self.matrix.invite(room, &user_id).await;
+        self.matrix.join(room, Some(&user_id)).await?;
+        self.ghosts.insert(key);
+        Ok(user_id)
+    }
+
+    /// Copy a freeq attachment into the Matrix media repository.
+    async fn rehost(&self, media: &MediaAttachment) -> Result<String> {
+        let url = url::Url::parse(&media.url).context("invalid media URL")?;
+        if !matches!(url.scheme(), "http" | "https") {
+            bail!("unsupported scheme {}", url.scheme());
+        }
+        let host = url.host_str().context("media URL has no host")?.to_string();
+        let port = url.port_or_known_default().unwrap_or(443);
+        let addrs = freeq_sdk::ssrf::resolve_and_check(&host, port).await?;
+        let http = freeq_sdk::ssrf::pinned_client(&host, &addrs, Duration::from_secs(30))?;
+        let resp = http.get(url).send().await?.error_for_status()?;
+        if resp
+            .content_length()
+            .is_some_and(|n| n as usize > MAX_MEDIA_BYTES)
+        {
+            bail!("attachment too large");
+        }
+        let bytes = resp.bytes().await?;
+        if bytes.len() > MAX_MEDIA_BYTES {
+            bail!("attachment too large");
+        }
+        self.matrix
+            .upload(
+                bytes.to_vec(),
+                &media.content_type,
+                media.filename.as_deref(),
+            )
+            .await
+    }
+
+    async fn on_matrix(&mut self, event: Value) -> Result<()> {
+        let Some((envelope, action)) = translate::parse_matrix_event(&event) else {
+            return Ok(());
+        };
+        let m = &self.config.matrix;
+        if translate::is_bridge_user(
+            &envelope.sender,
+            &m.ghost_prefix,
+            &self.config.bot_user_id(),
+            &m.domain,
+        ) {
+            return Ok(());
+        }
+        let Some(channel) = self
+            .config
+            .channel_for_room(&envelope.room_id)
+            .map(str::to_string)
+        else {
+            return Ok(());
+        };
+
+        let puppet = self.puppet_for(&envelope.sender, &channel).await;
+        let via_relay = puppet.is_none();
+        let handle = puppet.unwrap_or_else(|| self.relay.clone());
+        let name = translate::puppet_nick(&envelope.sender, "");
+
+        match action {
+            FromMatrix::Text {
+                body,
+                emote,
+                reply_to,
+            } => {
+                let text = match (via_relay, emote) {
+                    (false, false) => body,
+                    (false, true) => format!("\x01ACTION {body}\x01"),
+                    (true, false) => format!("<{name}> {body}"),
+                    (true, true) => format!("* {name} {body}"),
+                };
+                let mut msg_tags = HashMap::new();
+                if let Some(msgid) = reply_to.and_then(|e| self.events.msgid_for(&e)) {
+                    msg_tags.insert(tags::REPLY.to_string(), msgid.to_string());
+                }
+                let msgid = self
+                    .sent(
+                        &envelope.sender,
+                        handle.send_and_await_echo(&channel, &text, msg_tags),
+                    )
+                    .await?;
+                self.events.insert(&msgid, &envelope.event_id);
+            }
+            FromMatrix::Edit { original, body } => {
+                let Some(msgid) = self.events.msgid_for(&original).map(str::to_string) else {
+                    return Ok(());
+                };
+                let body = if via_relay {
+                    format!("<{name}> {body}")
+                } else {
+                    body
+                };
+                self.sent(
+                    &envelope.sender,
+                    handle.edit_message(&channel, &msgid, &body),
+                )
+                .await?;
+            }
+            FromMatrix::Reaction { target, key } => {
+                // A reaction from the relay would be attributed to it.
+                if via_relay {
+                    return Ok(());
+                }
+                let Some(msgid) = self.events.msgid_for(&target).map(str::to_string) else {
+                    return Ok(());
+                };
+                self.sent(&envelope.sender, handle.react(&channel, &key, &msgid))
+                    .await?;
+            }
+            FromMatrix::Redaction { target } => {
+                let Some(msgid) = self.events.msgid_for(&target).map(str::to_string) else {
+                    return Ok(());
+                };
+                self.sent(&envelope.sender, handle.delete_message(&channel, &msgid))
+                    .await?;
+            }
+            FromMatrix::Media {
+                mxc,
+                body,
+                mimetype,
+                size,
+            } => {
+                if size.is_some_and(|n| n as usize > MAX_MEDIA_BYTES) {
+                    bail!("attachment too large");
+                }
+                let (bytes, served_type) = self.matrix.download(&mxc, MAX_MEDIA_BYTES).await?;
+                let content_type = mimetype.unwrap_or(served_type);
+                let size = bytes.len() as u64;
+                let url = self.upload(&channel, bytes, &content_type, &body).await?;
+                let media = MediaAttachment {
+                    content_type,
+                    url,
+                    alt: via_relay.then(|| format!("<{name}>")),
+                    width: None,
+                    height: None,
+                    blurhash: None,
+                    size: Some(size),
+                    filename: Some(body),
+                };
+                let msgid = self
+                    .sent(
+                        &envelope.sender,
+                        handle.send_and_await_echo(
+                            &channel,
+                            &media.fallback_text(),
+                            media.to_tags(),
+                        ),
+                    )
+                    .await?;
+                self.events.insert(&msgid, &envelope.event_id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Await a send made for `sender`. A failure usually means the
+    /// puppet's connection died, so drop it; the next message respawns it.
+    async fn sent<T>(
+        &mut self,
+        sender: &str,
+        send: impl std::future::Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let result = send.await;
+        if result.is_err() {
+            self.puppets.remove(sender);
+        }
+        result
+    }
+
+    /// Upload an attachment through the server's upload endpoint.
+    async fn upload(
+        &self,
+        channel: &str,
+        bytes: Vec<u8>,
+        content_type: &str,
+        filename: &str,
+    ) -> Result<String> {
+        let (Some(web_url), Some(did)) = (&self.config.freeq.web_url, &self.relay_did) else {
+            bail!("attachments need freeq.web_url and freeq.key_file");
+        };
+        let part = reqwest::multipart::Part::bytes(bytes)
+            .file_name(filename.to_string())
+            .mime_str(content_type)?;
+        let form = reqwest::multipart::Form::new()
+            .part("file", part)
+            .text("did", did.clone())
+            .text("channel", channel.to_string());
+        let resp = reqwest::Client::new()
+            .post(format!("{}/api/v1/upload", web_url.trim_end_matches('/')))
+            .multipart(form)
+            .send()
+            .await?
+            .error_for_status()?;
+        let body: Value = resp.json().await?;
+        body["url"]
+            .as_str()
+            .map(str::to_string)
+            .context("upload response without url")
+    }
+
+    /// The puppet for `user_id`, joined to `channel`. Opens a connection
+    /// if needed, closing the least recently used one at the cap. `None`
+    /// means the relay has to speak for this user.
+    async fn puppet_for(&mut self, user_id: &str, channel: &str) -> Option<ClientHandle> {
+        if !self.puppets.contains_key(user_id) {
+            if self.puppets.len() >= self.config.freeq.max_puppets
+                && let Some(oldest) = self
+                    .puppets
+                    .iter()
+                    .min_by_key(|(_, p)| p.last_active)
+                    .map(|(id, _)| id.clone())
+                && let Some(puppet) = self.puppets.remove(&oldest)
+            {
+                let _ = puppet.handle.quit(Some("idle")).await;
+            }
+            match self.spawn_puppet(user_id).await {
+                Ok(puppet) => {
+                    self.puppets.insert(user_id.to_string(), puppet);
+                }
+                Err(e) => {
+                    tracing::warn!(%user_id, "puppet connection failed: {e:#}");
+                    return None;
+                }
+            }
+        }
+        let puppet = self.puppets.get_mut(user_id)?;
+        puppet.last_active = Instant::now();
+        if puppet.joined.insert(channel.to_lowercase())
+            && puppet.handle.join(channel).await.is_err()
+        {
+            self.puppets.remove(user_id);
+            return None;
+        }
+        Some(puppet.handle.clone())
+    }
+
+    async fn spawn_puppet(&self, user_id: &str) -> Result<Puppet> {
+        let connect = ConnectConfig {
+            server_addr: self.config.freeq.server.clone(),
+            nick: translate::puppet_nick(user_id, &self.config.freeq.puppet_suffix),
+            user: "matrix".to_string(),
+            realname: user_id.to_string(),
+            tls: self.config.freeq.tls,
+            ..Default::default()
+        };
+        let (handle, mut events) = client::connect(connect, None);
+        let (registered_tx, registered_rx) = oneshot::channel();
+        // Puppets only send; their events are drained so the client loop
+        // never blocks. The relay sees the same channel traffic.
+        tokio::spawn(async move {
+            let mut registered_tx = Some(registered_tx);
+            while let Some(event) = events.recv().await {
+                match event {
+                    Event::Registered { nick } => {
+                        if let Some(tx) = registered_tx.take() {
+                            let _ = tx.send(nick);
+                        }
+                    }
+                    Event::Disconnected { .. } => break,
+                    _ => {}
+                }
+            }
+        });
+        let nick = tokio::time::timeout(PUPPET_REGISTER_TIMEOUT, registered_rx)
+            .await
+            .context("timed out registering")?
+            .context("disconnected before registering")?;
+        tracing::info!(%user_id, %nick, "puppet connected");
+        Ok(Puppet {
+            handle,
+            nick,
+            joined: HashSet::new(),
+            last_active: Instant::now(),
+        })
+    }
+
+    /// Disconnect puppets that have been quiet for `puppet_idle_secs`.
+    async fn sweep_puppets(&mut self) {
+        let idle = Duration::from_secs(self.config.freeq.puppet_idle_secs);
+        let stale: Vec<String> = self
+            .puppets
+            .iter()
+            .filter(|(_, p)| p.last_active.elapsed() >= idle)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for user_id in stale {
+            if let Some(puppet) = self.puppets.remove(&user_id) {
+                let _ = puppet.handle.quit(Some("idle")).await;
+            }
+        }
+    }
+
+    async fn drop_puppets(&mut self) {
+        for (_, puppet) in self.puppets.drain() {
+            let _ = puppet.handle.quit(None).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_map_is_bidirectional_and_bounded() {
+        let mut map = EventMap::new(2);
+        map.insert("m1", "$e1");
+        map.insert("m2", "$e2");
+        assert_eq!(map.event_for("m1"), Some("$e1"));
+        assert_eq!(map.msgid_for("$e2"), Some("m2"));
+
+        map.insert("m3", "$e3");
+        assert_eq!(map.event_for("m1"), None);
+        assert_eq!(map.msgid_for("$e1"), None);
+        assert_eq!(map.event_for("m3"), Some("$e3"));
+    }
+}
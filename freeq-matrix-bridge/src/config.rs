@@ -0,0 +1,274 @@
+//! Bridge configuration, loaded from a TOML file.
+//!
+//! ```toml
+//! [freeq]
+//! server = "irc.example.com:6697"
+//! tls = true
+//! web_url = "https://irc.example.com"
+//! nick = "matrix"
+//! key_file = "/var/lib/freeq-matrix/key.ed25519"
+//!
+//! [matrix]
+//! homeserver = "https://matrix.example.org"
+//! domain = "example.org"
+//! bind = "127.0.0.1:9010"
+//! url = "http://127.0.0.1:9010"
+//! as_token = "..."
+//! hs_token = "..."
+//!
+//! [[rooms]]
+//! channel = "#freeq"
+//! room = "!abcdef:example.org"
+//! ```
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    pub freeq: FreeqConfig,
+    pub matrix: MatrixConfig,
+    #[serde(default)]
+    pub rooms: Vec<RoomMapping>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FreeqConfig {
+    /// IRC server address (host:port).
+    pub server: String,
+    #[serde(default)]
+    pub tls: bool,
+    /// Base URL of the server's web listener, for `/api/v1/upload`.
+    /// Without it, attachments are bridged as links only.
+    pub web_url: Option<String>,
+    /// Nick of the relay bot that listens on every bridged channel.
+    #[serde(default = "default_nick")]
+    pub nick: String,
+    /// ed25519 seed written by `freeq-bot-id`. The relay bot
+    /// authenticates as the matching `did:key`, which is also the DID
+    /// attachments are uploaded under.
+    pub key_file: Option<String>,
+    /// Appended to a Matrix user's name to form their freeq nick.
+    #[serde(default = "default_puppet_suffix")]
+    pub puppet_suffix: String,
+    /// Most puppet connections open at once. The server caps
+    /// connections per IP, so keep this below that limit.
+    #[serde(default = "default_max_puppets")]
+    pub max_puppets: usize,
+    /// Puppets that stay silent this long are disconnected.
+    #[serde(default = "default_puppet_idle_secs")]
+    pub puppet_idle_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MatrixConfig {
+    /// Client-server API base URL of the homeserver.
+    pub homeserver: String,
+    /// The homeserver's server name, as it appears in user IDs.
+    pub domain: String,
+    /// Where the appservice listener binds.
+    #[serde(default = "default_bind")]
+    pub bind: String,
+    /// How the homeserver reaches the appservice listener.
+    pub url: String,
+    /// Token the bridge presents to the homeserver.
+    pub as_token: String,
+    /// Token the homeserver presents to the bridge.
+    pub hs_token: String,
+    #[serde(default = "default_bot_localpart")]
+    pub bot_localpart: String,
+    /// Localpart prefix of the ghost users that stand in for freeq nicks.
+    #[serde(default = "default_ghost_prefix")]
+    pub ghost_prefix: String,
+}
+
+/// One bridged channel.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RoomMapping {
+    pub channel: String,
+    /// Matrix room ID (`!id:server`).
+    pub room: String,
+}
+
+fn default_nick() -> String {
+    "matrix".to_string()
+}
+
+fn default_puppet_suffix() -> String {
+    "[m]".to_string()
+}
+
+fn default_max_puppets() -> usize {
+    15
+}
+
+fn default_puppet_idle_secs() -> u64 {
+    3600
+}
+
+fn default_bind() -> String {
+    "127.0.0.1:9010".to_string()
+}
+
+fn default_bot_localpart() -> String {
+    "freeqbridge".to_string()
+}
+
+fn default_ghost_prefix() -> String {
+    "_freeq_".to_string()
+}
+
+impl Config {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        let config: Self =
+            toml::from_str(&text).with_context(|| format!("parsing {}", path.display()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.rooms.is_empty() {
+            bail!("no [[rooms]] configured");
+        }
+        let mut channels = HashSet::new();
+        let mut rooms = HashSet::new();
+        for mapping in &self.rooms {
+            if !mapping.channel.starts_with('#') {
+                bail!("channel {:?} must start with #", mapping.channel);
+            }
+            if !mapping.room.starts_with('!') {
+                bail!("room {:?} must be a room ID (!id:server)", mapping.room);
+            }
+            if !channels.insert(mapping.channel.to_lowercase()) {
+                bail!("channel {} is mapped twice", mapping.channel);
+            }
+            if !rooms.insert(mapping.room.as_str()) {
+                bail!("room {} is mapped twice", mapping.room);
+            }
+        }
+        if self.matrix.ghost_prefix.is_empty() {
+            bail!("matrix.ghost_prefix must not be empty");
+        }
+        if self.freeq.max_puppets == 0 {
+            bail!("freeq.max_puppets must be at least 1");
+        }
+        Ok(())
+    }
+
+    /// Matrix ID of the bridge bot.
+    pub fn bot_user_id(&self) -> String {
+        format!("@{}:{}", self.matrix.bot_localpart, self.matrix.domain)
+    }
+
+    /// The channel bridged to `room`.
+    pub fn channel_for_room(&self, room: &str) -> Option<&str> {
+        self.rooms
+            .iter()
+            .find(|m| m.room == room)
+            .map(|m| m.channel.as_str())
+    }
+
+    /// The room bridged to `channel` (case-insensitive).
+    pub fn room_for_channel(&self, channel: &str) -> Option<&str> {
+        self.rooms
+            .iter()
+            .find(|m| m.channel.eq_ignore_ascii_case(channel))
+            .map(|m| m.room.as_str())
+    }
+
+    /// Appservice registration file to hand to the homeserver.
+    pub fn registration_yaml(&self) -> String {
+        let m = &self.matrix;
+        let domain = escape_regex(&m.domain);
+        let prefix = escape_regex(&m.ghost_prefix);
+        format!(
+            "id: freeq\n\
+             url: {url}\n\
+             as_token: {as_token}\n\
+             hs_token: {hs_token}\n\
+             sender_localpart: {bot}\n\
+             rate_limited: false\n\
+             namespaces:\n  \
+               users:\n    \
+                 - exclusive: true\n      \
+                   regex: '@{prefix}.*:{domain}'\n  \
+               aliases: []\n  \
+               rooms: []\n",
+            url = m.url,
+            as_token = m.as_token,
+            hs_token = m.hs_token,
+            bot = m.bot_localpart,
+        )
+    }
+}
+
+fn escape_regex(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if ".+*?()|[]{}^$\\".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r##"
+[freeq]
+server = "irc.example.com:6697"
+tls = true
+
+[matrix]
+homeserver = "https://matrix.example.org"
+domain = "example.org"
+url = "http://127.0.0.1:9010"
+as_token = "as-secret"
+hs_token = "hs-secret"
+
+[[rooms]]
+channel = "#Freeq"
+room = "!abc:example.org"
+"##;
+
+    #[test]
+    fn parses_with_defaults() {
+        let config: Config = toml::from_str(SAMPLE).unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.freeq.nick, "matrix");
+        assert_eq!(config.freeq.puppet_suffix, "[m]");
+        assert_eq!(config.matrix.ghost_prefix, "_freeq_");
+        assert_eq!(config.bot_user_id(), "@freeqbridge:example.org");
+        assert_eq!(config.room_for_channel("#freeq"), Some("!abc:example.org"));
+        assert_eq!(config.channel_for_room("!abc:example.org"), Some("#Freeq"));
+        assert_eq!(config.channel_for_room("!other:example.org"), None);
+    }
+
+    #[test]
+    fn rejects_duplicate_mappings() {
+        let mut config: Config = toml::from_str(SAMPLE).unwrap();
+        config.rooms.push(RoomMapping {
+            channel: "#freeq".into(),
+            room: "!def:example.org".into(),
+        });
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn registration_claims_ghost_namespace() {
+        let config: Config = toml::from_str(SAMPLE).unwrap();
+        let yaml = config.registration_yaml();
+        assert!(yaml.contains("sender_localpart: freeqbridge\n"));
+        assert!(yaml.contains("    - exclusive: true\n      regex: '@_freeq_.*:example\\.org'\n"));
+        assert!(yaml.contains("hs_token: hs-secret\n"));
+    }
+}
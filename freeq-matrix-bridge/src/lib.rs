@@ -0,0 +1,18 @@
+//! Matrix appservice bridge for freeq.
+//!
+//! Bridges freeq channels to Matrix rooms in both directions: messages,
+//! replies, edits, reactions, deletions and attachments. freeq nicks
+//! appear in Matrix as appservice ghost users; Matrix users appear in
+//! freeq as puppet connections with their own nick.
+//!
+//! - [`config`] — TOML config and the appservice registration file
+//! - [`appservice`] — HTTP listener the homeserver pushes events to
+//! - [`matrix`] — client-server API calls made as the appservice
+//! - [`translate`] — pure mapping between Matrix events and freeq tags
+//! - [`bridge`] — the event loop tying both sides together
+
+pub mod appservice;
+pub mod bridge;
+pub mod config;
+pub mod matrix;
+pub mod translate;
@@ -0,0 +1,46 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::Parser;
+use freeq_matrix_bridge::config::Config;
+
+#[derive(Parser)]
+#[command(
+    name = "freeq-matrix-bridge",
+    about = "Bridge freeq channels to Matrix rooms"
+)]
+struct Args {
+    /// Path to the bridge config (TOML)
+    #[arg(
+        long,
+        short,
+        env = "FREEQ_MATRIX_CONFIG",
+        default_value = "freeq-matrix.toml"
+    )]
+    config: PathBuf,
+
+    /// Print the appservice registration YAML and exit
+    #[arg(long)]
+    registration: bool,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    let config = Config::load(&args.config)?;
+
+    if args.registration {
+        print!("{}", config.registration_yaml());
+        return Ok(());
+    }
+
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+                tracing_subscriber::EnvFilter::new("freeq_matrix_bridge=info,freeq_sdk=warn")
+            }),
+        )
+        .init();
+
+    freeq_matrix_bridge::bridge::run(config).await
+}
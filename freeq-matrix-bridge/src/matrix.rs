@@ -0,0 +1,240 @@
+//! Minimal Matrix client-server API client, authenticated as the
+//! appservice. Calls made on behalf of a ghost pass `user_id`, which the
+//! appservice token allows for any user in its namespace.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{Context, Result, bail};
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+use serde_json::{Value, json};
+
+pub struct MatrixClient {
+    http: reqwest::Client,
+    homeserver: String,
+    as_token: String,
+    txn: AtomicU64,
+    txn_prefix: String,
+}
+
+fn enc(s: &str) -> String {
+    utf8_percent_encode(s, NON_ALPHANUMERIC).to_string()
+}
+
+impl MatrixClient {
+    pub fn new(homeserver: &str, as_token: &str) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            homeserver: homeserver.trim_end_matches('/').to_string(),
+            as_token: as_token.to_string(),
+            txn: AtomicU64::new(0),
+            // Transaction IDs must not repeat across restarts.
+            txn_prefix: format!(
+                "{:x}",
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis()
+            ),
+        }
+    }
+
+    fn next_txn(&self) -> String {
+        format!(
+            "{}.{}",
+            self.txn_prefix,
+            self.txn.fetch_add(1, Ordering::Relaxed)
+        )
+    }
+
+    fn url(&self, path: &str, as_user: Option<&str>) -> String {
+        match as_user {
+            Some(user) => format!("{}{path}?user_id={}", self.homeserver, enc(user)),
+            None => format!("{}{path}", self.homeserver),
+        }
+    }
+
+    async fn call(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        as_user: Option<&str>,
+        body: &Value,
+    ) -> Result<Value> {
+        let resp = self
+            .http
+            .request(method, self.url(path, as_user))
+            .bearer_auth(&self.as_token)
+            .json(body)
+            .send()
+            .await
+            .with_context(|| format!("request to {path}"))?;
+        let status = resp.status();
+        let body: Value = resp.json().await.unwrap_or(Value::Null);
+        if !status.is_success() {
+            bail!(
+                "{path}: {status} {} {}",
+                body["errcode"].as_str().unwrap_or(""),
+                body["error"].as_str().unwrap_or("")
+            );
+        }
+        Ok(body)
+    }
+
+    /// Register a ghost user. Already-registered users are fine.
+    pub async fn register(&self, localpart: &str) -> Result<()> {
+        let body = json!({ "type": "m.login.application_service", "username": localpart });
+        match self
+            .call(
+                reqwest::Method::POST,
+                "/_matrix/client/v3/register",
+                None,
+                &body,
+            )
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) if e.to_string().contains("M_USER_IN_USE") => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn set_displayname(&self, user_id: &str, name: &str) -> Result<()> {
+        let path = format!("/_matrix/client/v3/profile/{}/displayname", enc(user_id));
+        self.call(
+            reqwest::Method::PUT,
+            &path,
+            Some(user_id),
+            &json!({ "displayname": name }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Invite `user_id` as the bridge bot. Errors are expected when the
+    /// user is already in the room, so callers usually ignore them.
+    pub async fn invite(&self, room_id: &str, user_id: &str) -> Result<()> {
+        let path = format!("/_matrix/client/v3/rooms/{}/invite", enc(room_id));
+        self.call(
+            reqwest::Method::POST,
+            &path,
+            None,
+            &json!({ "user_id": user_id }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn join(&self, room_id: &str, as_user: Option<&str>) -> Result<()> {
+        let path = format!("/_matrix/client/v3/join/{}", enc(room_id));
+        self.call(reqwest::Method::POST, &path, as_user, &json!({}))
+            .await?;
+        Ok(())
+    }
+
+    /// Send a room event; returns its event ID.
+    pub async fn send(
+        &self,
+        room_id: &str,
+        as_user: &str,
+        event_type: &str,
+        content: &Value,
+    ) -> Result<String> {
+        let path = format!(
+            "/_matrix/client/v3/rooms/{}/send/{}/{}",
+            enc(room_id),
+            enc(event_type),
+            self.next_txn()
+        );
+        let resp = self
+            .call(reqwest::Method::PUT, &path, Some(as_user), content)
+            .await?;
+        resp["event_id"]
+            .as_str()
+            .map(str::to_string)
+            .context("send response without event_id")
+    }
+
+    pub async fn redact(&self, room_id: &str, as_user: &str, event_id: &str) -> Result<()> {
+        let path = format!(
+            "/_matrix/client/v3/rooms/{}/redact/{}/{}",
+            enc(room_id),
+            enc(event_id),
+            self.next_txn()
+        );
+        self.call(reqwest::Method::PUT, &path, Some(as_user), &json!({}))
+            .await?;
+        Ok(())
+    }
+
+    /// Upload to the media repository; returns the `mxc://` URI.
+    pub async fn upload(
+        &self,
+        bytes: Vec<u8>,
+        content_type: &str,
+        filename: Option<&str>,
+    ) -> Result<String> {
+        let mut url = format!("{}/_matrix/media/v3/upload", self.homeserver);
+        if let Some(name) = filename {
+            url.push_str(&format!("?filename={}", enc(name)));
+        }
+        let resp = self
+            .http
+            .post(url)
+            .bearer_auth(&self.as_token)
+            .header(reqwest::header::CONTENT_TYPE, content_type)
+            .body(bytes)
+            .send()
+            .await
+            .context("media upload")?;
+        if !resp.status().is_success() {
+            bail!("media upload: {}", resp.status());
+        }
+        let body: Value = resp.json().await?;
+        body["content_uri"]
+            .as_str()
+            .map(str::to_string)
+            .context("upload response without content_uri")
+    }
+
+    /// Download an `mxc://server/id` URI; returns the bytes and their
+    /// content type.
+    pub async fn download(&self, mxc: &str, max_bytes: usize) -> Result<(Vec<u8>, String)> {
+        let Some((server, media_id)) = mxc.strip_prefix("mxc://").and_then(|r| r.split_once('/'))
+        else {
+            bail!("not an mxc URI: {mxc}");
+        };
+        let url = format!(
+            "{}/_matrix/client/v1/media/download/{}/{}",
+            self.homeserver,
+            enc(server),
+            enc(media_id)
+        );
+        let resp = self
+            .http
+            .get(url)
+            .bearer_auth(&self.as_token)
+            .send()
+            .await
+            .context("media download")?;
+        if !resp.status().is_success() {
+            bail!("media download: {}", resp.status());
+        }
+        if resp
+            .content_length()
+            .is_some_and(|n| n as usize > max_bytes)
+        {
+            bail!("media too large");
+        }
+        let content_type = resp
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("application/octet-stream")
+            .to_string();
+        let bytes = resp.bytes().await?;
+        if bytes.len() > max_bytes {
+            bail!("media too large");
+        }
+        Ok((bytes.to_vec(), content_type))
+    }
+}
@@ -0,0 +1,486 @@
+//! Pure translation between Matrix events and freeq messages.
+//!
+//! Nothing here does I/O; [`crate::bridge`] resolves event IDs to msgids
+//! (and back) and performs the sends.
+
+use std::collections::HashMap;
+
+use freeq_sdk::media::MediaAttachment;
+use freeq_sdk::proto::tags;
+use serde_json::{Value, json};
+
+/// Where a Matrix event came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    pub room_id: String,
+    pub sender: String,
+    pub event_id: String,
+}
+
+/// A Matrix event the bridge knows how to carry to freeq.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FromMatrix {
+    Text {
+        body: String,
+        emote: bool,
+        /// Event ID of the message this replies to.
+        reply_to: Option<String>,
+    },
+    Edit {
+        original: String,
+        body: String,
+    },
+    Reaction {
+        target: String,
+        key: String,
+    },
+    Redaction {
+        target: String,
+    },
+    Media {
+        mxc: String,
+        /// Filename or caption.
+        body: String,
+        mimetype: Option<String>,
+        size: Option<u64>,
+    },
+}
+
+/// Parse a client event from an appservice transaction. Returns `None`
+/// for event types the bridge ignores (state, receipts, ...).
+pub fn parse_matrix_event(event: &Value) -> Option<(Envelope, FromMatrix)> {
+    let envelope = Envelope {
+        room_id: event["room_id"].as_str()?.to_string(),
+        sender: event["sender"].as_str()?.to_string(),
+        event_id: event["event_id"].as_str()?.to_string(),
+    };
+    let content = &event["content"];
+    let relates = &content["m.relates_to"];
+    let action = match event["type"].as_str()? {
+        "m.room.message" => {
+            if relates["rel_type"] == "m.replace" {
+                FromMatrix::Edit {
+                    original: relates["event_id"].as_str()?.to_string(),
+                    body: content["m.new_content"]["body"].as_str()?.to_string(),
+                }
+            } else {
+                let body = content["body"].as_str()?;
+                match content["msgtype"].as_str()? {
+                    "m.text" | "m.notice" | "m.emote" => {
+                        let reply_to = relates["m.in_reply_to"]["event_id"]
+                            .as_str()
+                            .map(str::to_string);
+                        let body = if reply_to.is_some() {
+                            strip_reply_fallback(body)
+                        } else {
+                            body
+                        };
+                        FromMatrix::Text {
+                            body: body.to_string(),
+                            emote: content["msgtype"] == "m.emote",
+                            reply_to,
+                        }
+                    }
+                    "m.image" | "m.file" | "m.video" | "m.audio" => FromMatrix::Media {
+                        mxc: content["url"].as_str()?.to_string(),
+                        body: body.to_string(),
+                        mimetype: content["info"]["mimetype"].as_str().map(str::to_string),
+                        size: content["info"]["size"].as_u64(),
+                    },
+                    _ => return None,
+                }
+            }
+        }
+        "m.reaction" if relates["rel_type"] == "m.annotation" => FromMatrix::Reaction {
+            target: relates["event_id"].as_str()?.to_string(),
+            key: relates["key"].as_str()?.to_string(),
+        },
+        // Room v11 moved `redacts` into the content.
+        "m.room.redaction" => FromMatrix::Redaction {
+            target: event["redacts"]
+                .as_str()
+                .or_else(|| content["redacts"].as_str())?
+                .to_string(),
+        },
+        _ => return None,
+    };
+    Some((envelope, action))
+}
+
+/// Drop the `> <@user> quoted text` block clients put in front of reply
+/// bodies; freeq carries the reply as a tag.
+fn strip_reply_fallback(body: &str) -> &str {
+    if !body.starts_with("> ") {
+        return body;
+    }
+    match body.find("\n\n") {
+        Some(end) => &body[end + 2..],
+        None => body,
+    }
+}
+
+/// A freeq message or TAGMSG the bridge carries to Matrix.
+#[derive(Debug, Clone)]
+pub enum FromFreeq {
+    Text {
+        text: String,
+        emote: bool,
+        reply_to: Option<String>,
+    },
+    Edit {
+        original: String,
+        text: String,
+    },
+    Media(MediaAttachment),
+    Reaction {
+        target: String,
+        emoji: String,
+    },
+    Delete {
+        target: String,
+    },
+}
+
+/// Classify a PRIVMSG/NOTICE body and its tags.
+pub fn from_freeq_message(text: &str, msg_tags: &HashMap<String, String>) -> FromFreeq {
+    if let Some(original) = msg_tags.get(tags::EDIT) {
+        return FromFreeq::Edit {
+            original: original.clone(),
+            text: text.to_string(),
+        };
+    }
+    if let Some(media) = MediaAttachment::from_tags(msg_tags) {
+        return FromFreeq::Media(media);
+    }
+    let (text, emote) = match text
+        .strip_prefix("\x01ACTION ")
+        .and_then(|t| t.strip_suffix('\x01'))
+    {
+        Some(action) => (action, true),
+        None => (text, false),
+    };
+    FromFreeq::Text {
+        text: text.to_string(),
+        emote,
+        reply_to: reply_target(msg_tags).map(str::to_string),
+    }
+}
+
+/// Classify a TAGMSG. Typing indicators and other tags are ignored.
+pub fn from_freeq_tagmsg(msg_tags: &HashMap<String, String>) -> Option<FromFreeq> {
+    if let Some(target) = msg_tags.get(tags::DELETE) {
+        return Some(FromFreeq::Delete {
+            target: target.clone(),
+        });
+    }
+    let emoji = msg_tags
+        .get(tags::REACT)
+        .or_else(|| msg_tags.get(tags::DRAFT_REACT))?;
+    Some(FromFreeq::Reaction {
+        target: reply_target(msg_tags)?.to_string(),
+        emoji: emoji.clone(),
+    })
+}
+
+fn reply_target(msg_tags: &HashMap<String, String>) -> Option<&str> {
+    msg_tags
+        .get(tags::REPLY)
+        .or_else(|| msg_tags.get(tags::DRAFT_REPLY))
+        .map(String::as_str)
+}
+
+/// `m.room.message` content for a text message.
+pub fn text_content(text: &str, emote: bool, reply_to: Option<&str>) -> Value {
+    let mut content = json!({
+        "msgtype": if emote { "m.emote" } else { "m.text" },
+        "body": text,
+    });
+    if let Some(event_id) = reply_to {
+        content["m.relates_to"] = json!({ "m.in_reply_to": { "event_id": event_id } });
+    }
+    content
+}
+
+/// `m.room.message` content replacing `original`.
+pub fn edit_content(original: &str, text: &str) -> Value {
+    json!({
+        "msgtype": "m.text",
+        "body": format!("* {text}"),
+        "m.new_content": { "msgtype": "m.text", "body": text },
+        "m.relates_to": { "rel_type": "m.replace", "event_id": original },
+    })
+}
+
+/// `m.reaction` content.
+pub fn reaction_content(target: &str, key: &str) -> Value {
+    json!({
+        "m.relates_to": { "rel_type": "m.annotation", "event_id": target, "key": key },
+    })
+}
+
+/// `m.room.message` content for an attachment re-hosted at `mxc`.
+pub fn media_content(mxc: &str, media: &MediaAttachment) -> Value {
+    let msgtype = if media.is_image() {
+        "m.image"
+    } else if media.is_video() {
+        "m.video"
+    } else if media.is_audio() {
+        "m.audio"
+    } else {
+        "m.file"
+    };
+    let body = media
+        .filename
+        .clone()
+        .or_else(|| media.alt.clone())
+        .unwrap_or_else(|| "attachment".to_string());
+    let mut info = json!({ "mimetype": media.content_type });
+    if let Some(size) = media.size {
+        info["size"] = json!(size);
+    }
+    if let (Some(w), Some(h)) = (media.width, media.height) {
+        info["w"] = json!(w);
+        info["h"] = json!(h);
+    }
+    json!({ "msgtype": msgtype, "body": body, "url": mxc, "info": info })
+}
+
+/// Localpart of the ghost user for a freeq nick, using the Matrix spec's
+/// mapping for foreign identifiers: `_` doubles, capitals become `_` plus
+/// the lowercase letter, and anything else outside `a-z0-9-./` becomes
+/// `=xx` per UTF-8 byte.
+pub fn ghost_localpart(prefix: &str, nick: &str) -> String {
+    let mut out = String::from(prefix);
+    for b in nick.bytes() {
+        match b {
+            b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'/' => out.push(b as char),
+            b'_' => out.push_str("__"),
+            b'A'..=b'Z' => {
+                out.push('_');
+                out.push(b.to_ascii_lowercase() as char);
+            }
+            _ => out.push_str(&format!("={b:02x}")),
+        }
+    }
+    out
+}
+
+/// Whether `user_id` belongs to the bridge (its bot or a ghost), so
+/// events from it must not be bridged back.
+pub fn is_bridge_user(user_id: &str, prefix: &str, bot_user_id: &str, domain: &str) -> bool {
+    user_id == bot_user_id
+        || (user_id.starts_with(&format!("@{prefix}")) && user_id.ends_with(&format!(":{domain}")))
+}
+
+/// freeq nick for a Matrix user: the localpart, cut to the characters
+/// IRC clients handle well, plus `suffix`.
+pub fn puppet_nick(user_id: &str, suffix: &str) -> String {
+    let localpart = user_id
+        .strip_prefix('@')
+        .and_then(|u| u.split(':').next())
+        .unwrap_or(user_id);
+    let mut nick: String = localpart
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+        .take(30)
+        .collect();
+    if nick.is_empty() || nick.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+        nick.insert_str(0, "m");
+    }
+    nick.push_str(suffix);
+    nick
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(kind: &str, content: Value) -> Value {
+        json!({
+            "type": kind,
+            "room_id": "!room:hs",
+            "sender": "@alice:hs",
+            "event_id": "$ev1",
+            "content": content,
+        })
+    }
+
+    #[test]
+    fn parses_matrix_messages() {
+        let (env, action) = parse_matrix_event(&event(
+            "m.room.message",
+            json!({"msgtype": "m.text", "body": "hi"}),
+        ))
+        .unwrap();
+        assert_eq!(env.sender, "@alice:hs");
+        assert_eq!(
+            action,
+            FromMatrix::Text {
+                body: "hi".into(),
+                emote: false,
+                reply_to: None
+            }
+        );
+
+        let reply = event(
+            "m.room.message",
+            json!({
+                "msgtype": "m.text",
+                "body": "> <@bob:hs> original\n\nanswer",
+                "m.relates_to": {"m.in_reply_to": {"event_id": "$parent"}},
+            }),
+        );
+        assert_eq!(
+            parse_matrix_event(&reply).unwrap().1,
+            FromMatrix::Text {
+                body: "answer".into(),
+                emote: false,
+                reply_to: Some("$parent".into())
+            }
+        );
+
+        let edit = event(
+            "m.room.message",
+            json!({
+                "msgtype": "m.text",
+                "body": "* fixed",
+                "m.new_content": {"msgtype": "m.text", "body": "fixed"},
+                "m.relates_to": {"rel_type": "m.replace", "event_id": "$orig"},
+            }),
+        );
+        assert_eq!(
+            parse_matrix_event(&edit).unwrap().1,
+            FromMatrix::Edit {
+                original: "$orig".into(),
+                body: "fixed".into()
+            }
+        );
+
+        let image = event(
+            "m.room.message",
+            json!({
+                "msgtype": "m.image",
+                "body": "cat.png",
+                "url": "mxc://hs/abc",
+                "info": {"mimetype": "image/png", "size": 1234},
+            }),
+        );
+        assert_eq!(
+            parse_matrix_event(&image).unwrap().1,
+            FromMatrix::Media {
+                mxc: "mxc://hs/abc".into(),
+                body: "cat.png".into(),
+                mimetype: Some("image/png".into()),
+                size: Some(1234)
+            }
+        );
+    }
+
+    #[test]
+    fn parses_reactions_and_redactions() {
+        let reaction = event(
+            "m.reaction",
+            json!({"m.relates_to": {"rel_type": "m.annotation", "event_id": "$t", "key": "👍"}}),
+        );
+        assert_eq!(
+            parse_matrix_event(&reaction).unwrap().1,
+            FromMatrix::Reaction {
+                target: "$t".into(),
+                key: "👍".into()
+            }
+        );
+
+        let mut redaction = event("m.room.redaction", json!({}));
+        redaction["redacts"] = json!("$gone");
+        assert_eq!(
+            parse_matrix_event(&redaction).unwrap().1,
+            FromMatrix::Redaction {
+                target: "$gone".into()
+            }
+        );
+        let v11 = event("m.room.redaction", json!({"redacts": "$gone"}));
+        assert!(parse_matrix_event(&v11).is_some());
+
+        assert!(parse_matrix_event(&event("m.room.member", json!({}))).is_none());
+    }
+
+    #[test]
+    fn classifies_freeq_messages() {
+        let mut t = HashMap::new();
+        t.insert("+draft/reply".to_string(), "m1".to_string());
+        match from_freeq_message("\x01ACTION waves\x01", &t) {
+            FromFreeq::Text {
+                text,
+                emote,
+                reply_to,
+            } => {
+                assert_eq!(text, "waves");
+                assert!(emote);
+                assert_eq!(reply_to.as_deref(), Some("m1"));
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+
+        let mut t = HashMap::new();
+        t.insert("+draft/edit".to_string(), "m1".to_string());
+        assert!(matches!(
+            from_freeq_message("new", &t),
+            FromFreeq::Edit { .. }
+        ));
+
+        let mut t = HashMap::new();
+        t.insert("+react".to_string(), "🎉".to_string());
+        t.insert("+reply".to_string(), "m2".to_string());
+        match from_freeq_tagmsg(&t) {
+            Some(FromFreeq::Reaction { target, emoji }) => {
+                assert_eq!((target.as_str(), emoji.as_str()), ("m2", "🎉"));
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+        assert!(from_freeq_tagmsg(&HashMap::new()).is_none());
+    }
+
+    #[test]
+    fn content_builders() {
+        let c = text_content("hi", false, Some("$p"));
+        assert_eq!(c["m.relates_to"]["m.in_reply_to"]["event_id"], "$p");
+        let c = edit_content("$o", "new");
+        assert_eq!(c["m.new_content"]["body"], "new");
+        assert_eq!(c["body"], "* new");
+        let media = MediaAttachment {
+            content_type: "video/mp4".into(),
+            url: "https://x/v.mp4".into(),
+            alt: None,
+            width: Some(640),
+            height: Some(480),
+            blurhash: None,
+            size: Some(99),
+            filename: Some("v.mp4".into()),
+        };
+        let c = media_content("mxc://hs/v", &media);
+        assert_eq!(c["msgtype"], "m.video");
+        assert_eq!(c["info"]["w"], 640);
+        assert_eq!(c["url"], "mxc://hs/v");
+    }
+
+    #[test]
+    fn identity_mapping() {
+        assert_eq!(
+            ghost_localpart("_freeq_", "Alice_[x]"),
+            "_freeq__alice__=5bx=5d"
+        );
+        assert!(is_bridge_user("@_freeq_bob:hs", "_freeq_", "@bot:hs", "hs"));
+        assert!(is_bridge_user("@bot:hs", "_freeq_", "@bot:hs", "hs"));
+        assert!(!is_bridge_user(
+            "@_freeq_bob:evil",
+            "_freeq_",
+            "@bot:hs",
+            "hs"
+        ));
+        assert!(!is_bridge_user("@alice:hs", "_freeq_", "@bot:hs", "hs"));
+
+        assert_eq!(puppet_nick("@alice.smith:hs", "[m]"), "alicesmith[m]");
+        assert_eq!(puppet_nick("@1234:hs", "[m]"), "m1234[m]");
+        assert_eq!(puppet_nick("@...:hs", "[m]"), "m[m]");
+    }
+}
@@ -0,0 +1,38 @@
+//! Fuzz the IRC line parser with raw client bytes.
+//!
+//! Run with `cargo +nightly fuzz run parse_line` from `freeq-proto/`.
+
+#![no_main]
+
+use freeq_proto::{Message, MessageRef, ParseError};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // The server quarantines non-UTF-8 lines before they reach the parser.
+    let Ok(line) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Ok(msg) = MessageRef::parse(line) else {
+        return;
+    };
+
+    // Normalizing an accepted line must be stable.
+    let normalized = msg.to_string();
+    let again = MessageRef::parse(&normalized).expect("normalized line parses");
+    assert_eq!(again.to_string(), normalized);
+
+    // The owned form must survive serialization. Re-escaping can grow a
+    // tag section that was right at the limit, which is the one allowed
+    // way for it to fail.
+    let owned = msg.to_message();
+    let wire = owned.to_string();
+    match Message::parse(&wire) {
+        Some(back) => {
+            assert_eq!(back.tags, owned.tags);
+            assert_eq!(back.prefix, owned.prefix);
+            assert_eq!(back.command, owned.command);
+            assert_eq!(back.params, owned.params);
+        }
+        None => assert_eq!(MessageRef::parse(&wire), Err(ParseError::TagsTooLong)),
+    }
+});
@@ -0,0 +1,53 @@
+//! IRCv3 capability names.
+//!
+//! The server advertises these in `CAP LS` and the SDK requests them by
+//! the same names. Capabilities with values (`draft/multiline`,
+//! `draft/metadata-2`, `freeq.at/language`, `iroh`) are advertised as
+//! `name=value`; the value is the server's business.
+
+pub const SASL: &str = "sasl";
+pub const MESSAGE_TAGS: &str = "message-tags";
+pub const MULTI_PREFIX: &str = "multi-prefix";
+pub const ECHO_MESSAGE: &str = "echo-message";
+pub const SERVER_TIME: &str = "server-time";
+pub const BATCH: &str = "batch";
+pub const CHATHISTORY: &str = "draft/chathistory";
+pub const ACCOUNT_NOTIFY: &str = "account-notify";
+pub const ACCOUNT_TAG: &str = "account-tag";
+pub const EXTENDED_JOIN: &str = "extended-join";
+pub const AWAY_NOTIFY: &str = "away-notify";
+pub const MULTILINE: &str = "draft/multiline";
+pub const METADATA: &str = "draft/metadata-2";
+/// freeq extension: extra WHOIS numerics (credentials, roles, E2EE).
+pub const WHOIS_EXTENDED: &str = "freeq.at/whois-extended";
+/// freeq extension: the server brokers direct DM transport negotiation.
+pub const P2P_DM: &str = "freeq.at/p2p-dm";
+/// freeq extension: the server vets and relays `+freeq.at/priority` (see
+/// [`crate::tags::Priority`]).
+pub const PRIORITY: &str = "freeq.at/priority";
+/// The server announces capabilities it gains or loses after
+/// registration with `CAP NEW` and `CAP DEL`. Implied by `CAP LS 302`.
+pub const CAP_NOTIFY: &str = "cap-notify";
+/// freeq extension: the server's iroh endpoint ID, for QUIC transport.
+pub const IROH: &str = "iroh";
+/// freeq extension: the languages (comma-separated codes) the server can
+/// translate numeric and notice text into; pick one with `LANGUAGE`.
+pub const LANGUAGE: &str = "freeq.at/language";
+
+/// Valueless capabilities every freeq server advertises, in `CAP LS` order.
+pub const ADVERTISED: &[&str] = &[
+    SASL,
+    MESSAGE_TAGS,
+    MULTI_PREFIX,
+    ECHO_MESSAGE,
+    SERVER_TIME,
+    BATCH,
+    CHATHISTORY,
+    ACCOUNT_NOTIFY,
+    ACCOUNT_TAG,
+    EXTENDED_JOIN,
+    AWAY_NOTIFY,
+    WHOIS_EXTENDED,
+    P2P_DM,
+    PRIORITY,
+];
@@ -0,0 +1,21 @@
+//! # freeq-proto
+//!
+//! Wire protocol definitions shared by `freeq-server`, `freeq-sdk` and the
+//! FFI bindings, so the two ends of a connection can't drift apart on a
+//! tag name or a numeric.
+//!
+//! ## Modules
+//!
+//! - [`message`] — IRC line parser and serializer ([`Message`], [`MessageRef`])
+//! - [`numeric`] — Numeric reply codes
+//! - [`caps`] — IRCv3 capability names
+//! - [`tags`] — Message tag keys
+//! - [`s2s`] — Server-to-server JSON message schema
+
+pub mod caps;
+pub mod message;
+pub mod numeric;
+pub mod s2s;
+pub mod tags;
+
+pub use message::{Message, MessageRef, ParseError};
@@ -0,0 +1,701 @@
+//! IRC message parsing and formatting.
+//!
+//! [`Message`] is the owned form, for building and sending lines; its
+//! `Display` impl is the serializer and strips CR, LF and NUL so a
+//! parameter can never smuggle in a second line.
+//!
+//! [`MessageRef`] borrows every part of a line — tags, prefix, command and
+//! params — straight from the input, so bots and bridges can inspect
+//! traffic without allocating. Tag values stay escaped until asked for
+//! with [`TagValue::unescape`], which only allocates when the value
+//! contains an escape sequence. `Message::parse` is implemented on top of
+//! [`MessageRef`], so the two never disagree about a line.
+//!
+//! ```
+//! use freeq_proto::MessageRef;
+//!
+//! let line = "@msgid=abc;+draft/reply=xyz :alice!a@host PRIVMSG #chan :hi there";
+//! let msg = MessageRef::parse(line).unwrap();
+//! assert!(msg.is("privmsg"));
+//! assert_eq!(msg.prefix().unwrap().nick, "alice");
+//! assert_eq!(msg.param(1), Some("hi there"));
+//! assert_eq!(msg.tag("+draft/reply").unwrap().unescape(), "xyz");
+//! ```
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+
+/// A parsed IRC message with optional IRCv3 tags.
+#[derive(Debug, Clone)]
+pub struct Message {
+    /// IRCv3 message tags (key=value pairs).
+    pub tags: HashMap<String, String>,
+    /// Optional message prefix (server or user origin).
+    pub prefix: Option<String>,
+    /// The IRC command (e.g. "NICK", "PRIVMSG", "001").
+    pub command: String,
+    /// Command parameters.
+    pub params: Vec<String>,
+}
+
+impl Message {
+    /// Parse a raw IRC line, including optional message tags.
+    /// See [`MessageRef`] for a borrowing parser.
+    pub fn parse(line: &str) -> Option<Self> {
+        MessageRef::parse(line).ok().map(|m| m.to_message())
+    }
+
+    /// Create a new message with no prefix.
+    pub fn new(command: &str, params: Vec<&str>) -> Self {
+        Self {
+            tags: HashMap::new(),
+            prefix: None,
+            command: command.to_string(),
+            params: params.into_iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    /// Create a new message with a server prefix.
+    pub fn from_server(server: &str, command: &str, params: Vec<&str>) -> Self {
+        Self {
+            tags: HashMap::new(),
+            prefix: Some(server.to_string()),
+            command: command.to_string(),
+            params: params.into_iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    /// Create a message with tags.
+    pub fn with_tags(tags: HashMap<String, String>, command: &str, params: Vec<&str>) -> Self {
+        Self {
+            tags,
+            prefix: None,
+            command: command.to_string(),
+            params: params.into_iter().map(|s| s.to_string()).collect(),
+        }
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Write tags
+        if !self.tags.is_empty() {
+            write!(f, "@")?;
+            let mut first = true;
+            for (key, value) in &self.tags {
+                if !first {
+                    write!(f, ";")?;
+                }
+                first = false;
+                if value.is_empty() {
+                    write!(f, "{key}")?;
+                } else {
+                    write!(f, "{key}={}", escape_tag_value(value))?;
+                }
+            }
+            write!(f, " ")?;
+        }
+
+        if let Some(ref prefix) = self.prefix {
+            // Strip control characters from prefix to prevent protocol injection
+            let safe: String = prefix
+                .chars()
+                .filter(|c| *c != '\r' && *c != '\n' && *c != '\0')
+                .collect();
+            write!(f, ":{safe} ")?;
+        }
+        write!(f, "{}", self.command)?;
+        for (i, param) in self.params.iter().enumerate() {
+            // Strip CRLF/NUL from all params to prevent protocol injection
+            let safe: String = param
+                .chars()
+                .filter(|c| *c != '\r' && *c != '\n' && *c != '\0')
+                .collect();
+            let is_last = i == self.params.len() - 1;
+            if is_last && (safe.contains(' ') || safe.starts_with(':') || safe.is_empty()) {
+                write!(f, " :{safe}")?;
+            } else if !is_last && safe.contains(' ') {
+                // Space in non-last param: force to trailing position by
+                // writing remaining params as a single trailing
+                let remaining: Vec<String> = self.params[i..]
+                    .iter()
+                    .map(|p| {
+                        p.chars()
+                            .filter(|c| *c != '\r' && *c != '\n' && *c != '\0')
+                            .collect()
+                    })
+                    .collect();
+                write!(f, " :{}", remaining.join(" "))?;
+                return Ok(());
+            } else {
+                write!(f, " {safe}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Unescape IRCv3 tag values.
+/// `\:` → `;`, `\s` → space, `\\` → `\`, `\r` → CR, `\n` → LF
+pub fn unescape_tag_value(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some(':') => result.push(';'),
+                Some('s') => result.push(' '),
+                Some('\\') => result.push('\\'),
+                Some('r') => result.push('\r'),
+                Some('n') => result.push('\n'),
+                Some(other) => {
+                    result.push('\\');
+                    result.push(other);
+                }
+                None => result.push('\\'),
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// Escape a value for IRCv3 tag encoding.
+/// `;` → `\:`, space → `\s`, `\` → `\\`, CR → `\r`, LF → `\n`; NUL,
+/// which has no escape, is dropped.
+pub fn escape_tag_value(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\0' => {}
+            ';' => result.push_str("\\:"),
+            ' ' => result.push_str("\\s"),
+            '\\' => result.push_str("\\\\"),
+            '\r' => result.push_str("\\r"),
+            '\n' => result.push_str("\\n"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Longest tag section accepted, counting the leading `@` and the
+/// trailing space (the IRCv3 message-tags limit).
+pub const MAX_TAGS_LEN: usize = 8191;
+
+/// Most params one line may carry (RFC 2812).
+pub const MAX_PARAMS: usize = 15;
+
+/// Why a line couldn't be parsed.
+///
+/// The parser rejects rather than guesses: a line that fails here would
+/// otherwise have been read as something its sender didn't mean, so
+/// callers should drop it (the server logs it as quarantined).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum ParseError {
+    #[error("empty line")]
+    Empty,
+    #[error("no command")]
+    MissingCommand,
+    #[error("NUL, CR or LF inside the line")]
+    ControlChar,
+    #[error("tag section over {MAX_TAGS_LEN} bytes")]
+    TagsTooLong,
+    #[error("malformed tag key")]
+    InvalidTag,
+    #[error("empty prefix")]
+    EmptyPrefix,
+    #[error("command is neither a word nor a three-digit numeric")]
+    InvalidCommand,
+    #[error("more than {MAX_PARAMS} params")]
+    TooManyParams,
+}
+
+/// A parsed IRC line borrowing from its source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageRef<'a> {
+    /// Raw tag section without the leading `@`; empty when untagged.
+    tags: &'a str,
+    prefix: Option<&'a str>,
+    command: &'a str,
+    /// Everything after the command.
+    params: &'a str,
+}
+
+impl<'a> MessageRef<'a> {
+    /// Parse one line. A trailing CR/LF is ignored, as are repeated spaces
+    /// between parts. Never allocates and never indexes past the input;
+    /// see [`ParseError`] for what is rejected.
+    pub fn parse(line: &'a str) -> Result<Self, ParseError> {
+        let mut rest = line.trim_end_matches(['\r', '\n']);
+        if rest.trim_start_matches(' ').is_empty() {
+            return Err(ParseError::Empty);
+        }
+        if rest.contains(['\0', '\r', '\n']) {
+            return Err(ParseError::ControlChar);
+        }
+
+        let mut tags = "";
+        if let Some(tagged) = rest.strip_prefix('@') {
+            let (t, r) = tagged.split_once(' ').ok_or(ParseError::MissingCommand)?;
+            if t.len() + 2 > MAX_TAGS_LEN {
+                return Err(ParseError::TagsTooLong);
+            }
+            let mut keys = t.split(';').filter(|pair| !pair.is_empty()).peekable();
+            if keys.peek().is_none()
+                || !keys.all(|pair| valid_tag_key(pair.split_once('=').map_or(pair, |(k, _)| k)))
+            {
+                return Err(ParseError::InvalidTag);
+            }
+            tags = t;
+            rest = r;
+        }
+        rest = rest.trim_start_matches(' ');
+
+        let mut prefix = None;
+        if let Some(prefixed) = rest.strip_prefix(':') {
+            let (p, r) = prefixed.split_once(' ').ok_or(ParseError::MissingCommand)?;
+            if p.is_empty() {
+                return Err(ParseError::EmptyPrefix);
+            }
+            prefix = Some(p);
+            rest = r.trim_start_matches(' ');
+        }
+
+        let (command, params) = rest.split_once(' ').unwrap_or((rest, ""));
+        if command.is_empty() {
+            return Err(ParseError::MissingCommand);
+        }
+        let numeric = command.len() == 3 && command.bytes().all(|b| b.is_ascii_digit());
+        if !numeric && !command.bytes().all(|b| b.is_ascii_alphabetic()) {
+            return Err(ParseError::InvalidCommand);
+        }
+        if (Params { rest: params }).count() > MAX_PARAMS {
+            return Err(ParseError::TooManyParams);
+        }
+        Ok(Self {
+            tags,
+            prefix,
+            command,
+            params,
+        })
+    }
+
+    /// All tags, in line order.
+    pub fn tags(&self) -> Tags<'a> {
+        Tags { rest: self.tags }
+    }
+
+    /// The value of tag `key`. Valueless tags give an empty value.
+    pub fn tag(&self, key: &str) -> Option<TagValue<'a>> {
+        self.tags().find(|(k, _)| *k == key).map(|(_, v)| v)
+    }
+
+    /// The prefix (source), if the line has one.
+    pub fn prefix(&self) -> Option<Prefix<'a>> {
+        self.prefix.map(Prefix::parse)
+    }
+
+    /// The command as written. Use [`is`](Self::is) to compare, since
+    /// commands are case-insensitive.
+    pub fn command(&self) -> &'a str {
+        self.command
+    }
+
+    /// Case-insensitive command comparison.
+    pub fn is(&self, command: &str) -> bool {
+        self.command.eq_ignore_ascii_case(command)
+    }
+
+    /// The params, including the trailing one.
+    pub fn params(&self) -> Params<'a> {
+        Params { rest: self.params }
+    }
+
+    /// The param at `index`.
+    pub fn param(&self, index: usize) -> Option<&'a str> {
+        self.params().nth(index)
+    }
+
+    /// Copy into an owned [`Message`], unescaping tag values and
+    /// upper-casing the command.
+    pub fn to_message(&self) -> Message {
+        Message {
+            tags: self
+                .tags()
+                .map(|(k, v)| (k.to_string(), v.unescape().into_owned()))
+                .collect::<HashMap<_, _>>(),
+            prefix: self.prefix.map(str::to_string),
+            command: self.command.to_ascii_uppercase(),
+            params: self.params().map(str::to_string).collect(),
+        }
+    }
+}
+
+impl fmt::Display for MessageRef<'_> {
+    /// Re-serialize in normalized form: single spaces, and a `:` only
+    /// before a last param that needs one.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.tags.is_empty() {
+            write!(f, "@{} ", self.tags)?;
+        }
+        if let Some(prefix) = self.prefix {
+            write!(f, ":{prefix} ")?;
+        }
+        f.write_str(self.command)?;
+        let mut params = self.params().peekable();
+        while let Some(param) = params.next() {
+            let last = params.peek().is_none();
+            if last && (param.is_empty() || param.contains(' ') || param.starts_with(':')) {
+                write!(f, " :{param}")?;
+            } else {
+                write!(f, " {param}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `[+][vendor/]name`, where the vendor is a hostname and the name is
+/// letters, digits and hyphens.
+fn valid_tag_key(key: &str) -> bool {
+    let key = key.strip_prefix('+').unwrap_or(key);
+    let name_ok = |name: &str| {
+        !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+    };
+    match key.rsplit_once('/') {
+        Some((vendor, name)) => {
+            !vendor.is_empty()
+                && vendor
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'.')
+                && name_ok(name)
+        }
+        None => name_ok(key),
+    }
+}
+
+/// Iterator over `(key, value)` tag pairs.
+#[derive(Debug, Clone)]
+pub struct Tags<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Iterator for Tags<'a> {
+    type Item = (&'a str, TagValue<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.rest.is_empty() {
+                return None;
+            }
+            let (pair, rest) = self.rest.split_once(';').unwrap_or((self.rest, ""));
+            self.rest = rest;
+            if pair.is_empty() {
+                continue;
+            }
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            return Some((key, TagValue(value)));
+        }
+    }
+}
+
+/// A tag value as it appears on the wire (still escaped).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TagValue<'a>(&'a str);
+
+impl<'a> TagValue<'a> {
+    pub fn raw(&self) -> &'a str {
+        self.0
+    }
+
+    /// The value with IRCv3 escapes (`\:`, `\s`, `\\`, `\r`, `\n`)
+    /// decoded. Borrows when there is nothing to decode.
+    pub fn unescape(&self) -> Cow<'a, str> {
+        if self.0.contains('\\') {
+            Cow::Owned(unescape_tag_value(self.0))
+        } else {
+            Cow::Borrowed(self.0)
+        }
+    }
+}
+
+/// A message source: `nick!user@host`, or a server name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Prefix<'a> {
+    pub raw: &'a str,
+    /// The nick, or the whole prefix for server sources.
+    pub nick: &'a str,
+    pub user: Option<&'a str>,
+    pub host: Option<&'a str>,
+}
+
+impl<'a> Prefix<'a> {
+    pub fn parse(raw: &'a str) -> Self {
+        let (rest, host) = match raw.split_once('@') {
+            Some((rest, host)) => (rest, Some(host)),
+            None => (raw, None),
+        };
+        let (nick, user) = match rest.split_once('!') {
+            Some((nick, user)) => (nick, Some(user)),
+            None => (rest, None),
+        };
+        Self {
+            raw,
+            nick,
+            user,
+            host,
+        }
+    }
+}
+
+/// Iterator over a message's params.
+#[derive(Debug, Clone)]
+pub struct Params<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Iterator for Params<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let rest = self.rest.trim_start_matches(' ');
+        if rest.is_empty() {
+            self.rest = rest;
+            return None;
+        }
+        if let Some(trailing) = rest.strip_prefix(':') {
+            self.rest = "";
+            return Some(trailing);
+        }
+        let (param, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+        self.rest = rest;
+        Some(param)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_simple() {
+        let msg = Message::parse("NICK alice").unwrap();
+        assert!(msg.tags.is_empty());
+        assert_eq!(msg.command, "NICK");
+        assert_eq!(msg.params, vec!["alice"]);
+    }
+
+    #[test]
+    fn parse_with_tags() {
+        let msg = Message::parse("@content-type=image/jpeg;media-url=https://example.com/img.jpg :alice!a@host PRIVMSG #chan :check this out").unwrap();
+        assert_eq!(msg.tags.get("content-type").unwrap(), "image/jpeg");
+        assert_eq!(
+            msg.tags.get("media-url").unwrap(),
+            "https://example.com/img.jpg"
+        );
+        assert_eq!(msg.prefix.as_deref(), Some("alice!a@host"));
+        assert_eq!(msg.command, "PRIVMSG");
+        assert_eq!(msg.params, vec!["#chan", "check this out"]);
+    }
+
+    #[test]
+    fn tag_escaping_roundtrip() {
+        let original = "hello world; backslash\\ and\nnewline";
+        let escaped = escape_tag_value(original);
+        let unescaped = unescape_tag_value(&escaped);
+        assert_eq!(unescaped, original);
+    }
+
+    #[test]
+    fn parse_tags_with_escapes() {
+        let msg = Message::parse(
+            "@media-alt=A\\ssunset\\sover\\smountains :bob PRIVMSG #pics :sunset.jpg",
+        )
+        .unwrap();
+        assert_eq!(
+            msg.tags.get("media-alt").unwrap(),
+            "A sunset over mountains"
+        );
+    }
+
+    #[test]
+    fn format_with_tags() {
+        let mut tags = HashMap::new();
+        tags.insert("content-type".to_string(), "image/jpeg".to_string());
+        let msg = Message::with_tags(tags, "PRIVMSG", vec!["#chan", "check this out"]);
+        let s = msg.to_string();
+        assert!(s.starts_with("@content-type=image/jpeg"));
+        assert!(s.contains("PRIVMSG #chan :check this out"));
+    }
+
+    #[test]
+    fn parse_with_prefix_no_tags() {
+        let msg = Message::parse(":server 001 alice :Welcome").unwrap();
+        assert!(msg.tags.is_empty());
+        assert_eq!(msg.prefix.as_deref(), Some("server"));
+        assert_eq!(msg.command, "001");
+    }
+
+    #[test]
+    fn parse_valueless_tag() {
+        let msg = Message::parse("@draft/reply PRIVMSG #chan :text").unwrap();
+        assert_eq!(msg.tags.get("draft/reply").unwrap(), "");
+    }
+
+    #[test]
+    fn parse_pin_notice() {
+        // Exact format server sends for PIN broadcast
+        let msg = Message::parse(
+            "@+freeq.at/pin=01KM9EDCZD9QVT7G4PYPR2C9TG :zapnap!~u@host NOTICE #naptest :\x01ACTION pinned a message\x01"
+        ).unwrap();
+        assert_eq!(
+            msg.tags.get("+freeq.at/pin").unwrap(),
+            "01KM9EDCZD9QVT7G4PYPR2C9TG"
+        );
+        assert_eq!(msg.prefix.as_deref(), Some("zapnap!~u@host"));
+        assert_eq!(msg.command, "NOTICE");
+        assert_eq!(msg.params[0], "#naptest");
+        assert!(msg.params[1].contains("ACTION pinned a message"));
+    }
+
+    #[test]
+    fn parse_privmsg() {
+        let msg = Message::parse(":alice!~a@host PRIVMSG #chan :hello world").unwrap();
+        assert_eq!(msg.command, "PRIVMSG");
+        assert_eq!(msg.params, vec!["#chan", "hello world"]);
+    }
+
+    #[test]
+    fn from_server_roundtrip() {
+        let msg = Message::from_server("irc.example", "001", vec!["alice", "Welcome to IRC"]);
+        let s = msg.to_string();
+        assert_eq!(s, ":irc.example 001 alice :Welcome to IRC");
+    }
+
+    #[test]
+    fn display_strips_line_breaks() {
+        let msg = Message::new("PRIVMSG", vec!["#chan", "hi\r\nQUIT :bye"]);
+        assert_eq!(msg.to_string(), "PRIVMSG #chan :hiQUIT :bye");
+    }
+
+    #[test]
+    fn parse_full_line() {
+        let msg = MessageRef::parse(
+            "@msgid=abc;+draft/reply;+freeq.at/alt=a\\sb :alice!al@host PRIVMSG #chan :hi there\r\n",
+        )
+        .unwrap();
+        let tags: Vec<_> = msg.tags().map(|(k, v)| (k, v.raw())).collect();
+        assert_eq!(
+            tags,
+            [
+                ("msgid", "abc"),
+                ("+draft/reply", ""),
+                ("+freeq.at/alt", "a\\sb")
+            ]
+        );
+        assert!(matches!(
+            msg.tag("msgid").unwrap().unescape(),
+            Cow::Borrowed("abc")
+        ));
+        assert_eq!(msg.tag("+freeq.at/alt").unwrap().unescape(), "a b");
+        assert!(msg.tag("missing").is_none());
+
+        let prefix = msg.prefix().unwrap();
+        assert_eq!(
+            (prefix.nick, prefix.user, prefix.host),
+            ("alice", Some("al"), Some("host"))
+        );
+        assert!(msg.is("privmsg"));
+        assert_eq!(msg.params().collect::<Vec<_>>(), ["#chan", "hi there"]);
+    }
+
+    #[test]
+    fn params_edge_cases() {
+        let msg = MessageRef::parse("CMD  a   b :").unwrap();
+        assert_eq!(msg.params().collect::<Vec<_>>(), ["a", "b", ""]);
+        let msg = MessageRef::parse("CMD a ::colon").unwrap();
+        assert_eq!(msg.param(1), Some(":colon"));
+        let msg = MessageRef::parse("PING").unwrap();
+        assert_eq!(msg.params().count(), 0);
+        let msg = MessageRef::parse(":irc.example.org 001 alice :Welcome").unwrap();
+        let prefix = msg.prefix().unwrap();
+        assert_eq!((prefix.nick, prefix.host), ("irc.example.org", None));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(MessageRef::parse(""), Err(ParseError::Empty));
+        assert_eq!(MessageRef::parse("  \r\n"), Err(ParseError::Empty));
+        assert_eq!(MessageRef::parse("@a=b"), Err(ParseError::MissingCommand));
+        assert_eq!(MessageRef::parse(":nick"), Err(ParseError::MissingCommand));
+        assert_eq!(MessageRef::parse("@a=b  "), Err(ParseError::MissingCommand));
+    }
+
+    #[test]
+    fn strict_rejections() {
+        let cases = [
+            ("PRIVMSG #c :a\0b", ParseError::ControlChar),
+            ("PRIVMSG #c :a\rQUIT", ParseError::ControlChar),
+            ("@ PRIVMSG #c :hi", ParseError::InvalidTag),
+            ("@;; PRIVMSG #c :hi", ParseError::InvalidTag),
+            ("@=v PRIVMSG #c :hi", ParseError::InvalidTag),
+            ("@a b=c PRIVMSG #c :hi", ParseError::InvalidCommand),
+            ("@+/x=1 PRIVMSG #c :hi", ParseError::InvalidTag),
+            ("@vendor/=1 PRIVMSG #c :hi", ParseError::InvalidTag),
+            ("@a_b=1 PRIVMSG #c :hi", ParseError::InvalidTag),
+            (": PRIVMSG #c :hi", ParseError::EmptyPrefix),
+            ("PRIV-MSG #c", ParseError::InvalidCommand),
+            ("0001 alice :hi", ParseError::InvalidCommand),
+            ("P1 x", ParseError::InvalidCommand),
+            (
+                "CMD 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16",
+                ParseError::TooManyParams,
+            ),
+        ];
+        for (line, err) in cases {
+            assert_eq!(MessageRef::parse(line), Err(err), "{line:?}");
+            assert!(Message::parse(line).is_none());
+        }
+
+        let long = format!("@a={} PING", "x".repeat(MAX_TAGS_LEN));
+        assert_eq!(MessageRef::parse(&long), Err(ParseError::TagsTooLong));
+        let fits = format!("@a={} PING", "x".repeat(MAX_TAGS_LEN - 4));
+        assert!(MessageRef::parse(&fits).is_ok());
+
+        let fifteen = "CMD 1 2 3 4 5 6 7 8 9 10 11 12 13 14 :fifteen params";
+        assert_eq!(MessageRef::parse(fifteen).unwrap().params().count(), 15);
+        assert!(MessageRef::parse("@a;+b.c/d-e=;x= 005 n :ok").is_ok());
+    }
+
+    #[test]
+    fn display_normalizes() {
+        let line = "@a=b\\sc :n!u@h privmsg  #chan   :hello world";
+        let msg = MessageRef::parse(line).unwrap();
+        assert_eq!(
+            msg.to_string(),
+            "@a=b\\sc :n!u@h privmsg #chan :hello world"
+        );
+        assert_eq!(
+            MessageRef::parse("MODE #c +o :alice").unwrap().to_string(),
+            "MODE #c +o alice"
+        );
+    }
+
+    #[test]
+    fn owned_roundtrip() {
+        let line = "@+x=a\\:b :n!u@h privmsg #chan :hello world";
+        let owned = MessageRef::parse(line).unwrap().to_message();
+        assert_eq!(owned.command, "PRIVMSG");
+        assert_eq!(owned.tags["+x"], "a;b");
+        let wire = owned.to_string();
+        let reparsed = MessageRef::parse(&wire).unwrap();
+        assert_eq!(reparsed.tag("+x").unwrap().unescape(), "a;b");
+        assert_eq!(
+            reparsed.params().collect::<Vec<_>>(),
+            ["#chan", "hello world"]
+        );
+    }
+}
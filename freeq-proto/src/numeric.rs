@@ -0,0 +1,140 @@
+//! IRC numeric reply codes.
+//!
+//! The server sends these and the SDK matches on them, so both name them
+//! from here. Numerics in the 6xx range marked as freeq extensions are
+//! not part of any IRC spec.
+
+// Standard IRC numerics
+pub const RPL_WELCOME: &str = "001";
+pub const RPL_YOURHOST: &str = "002";
+pub const RPL_CREATED: &str = "003";
+pub const RPL_MYINFO: &str = "004";
+pub const RPL_ISUPPORT: &str = "005";
+
+// SASL numerics
+pub const RPL_LOGGEDIN: &str = "900";
+pub const RPL_SASLSUCCESS: &str = "903";
+pub const ERR_SASLFAIL: &str = "904";
+pub const ERR_SASLALREADY: &str = "907";
+
+// CAP / channel numerics
+pub const RPL_NAMREPLY: &str = "353";
+pub const RPL_ENDOFNAMES: &str = "366";
+pub const RPL_TOPIC: &str = "332";
+pub const RPL_TOPICWHOTIME: &str = "333";
+pub const RPL_NOTOPIC: &str = "331";
+
+// Channel mode numerics
+pub const RPL_CHANNELMODEIS: &str = "324";
+pub const RPL_CREATIONTIME: &str = "329";
+pub const RPL_BANLIST: &str = "367";
+pub const RPL_ENDOFBANLIST: &str = "368";
+pub const RPL_INVITELIST: &str = "346";
+pub const RPL_ENDOFINVITELIST: &str = "347";
+pub const RPL_QUIETLIST: &str = "728";
+pub const RPL_ENDOFQUIETLIST: &str = "729";
+
+pub const ERR_NOSUCHCHANNEL: &str = "403";
+pub const ERR_TOOMANYCHANNELS: &str = "405";
+pub const ERR_CHANNELISFULL: &str = "471";
+pub const ERR_NEEDREGGEDNICK: &str = "477";
+pub const ERR_BANNEDFROMCHAN: &str = "474";
+pub const ERR_INVITEONLYCHAN: &str = "473";
+pub const ERR_BADCHANNELKEY: &str = "475";
+pub const ERR_BADCHANNAME: &str = "479";
+
+// Error numerics for channels
+pub const ERR_NOTONCHANNEL: &str = "442";
+pub const ERR_CHANOPRIVSNEEDED: &str = "482";
+pub const ERR_USERNOTINCHANNEL: &str = "441";
+pub const ERR_NEEDMOREPARAMS: &str = "461";
+pub const ERR_UNKNOWNMODE: &str = "472";
+pub const ERR_INVALIDMODEPARAM: &str = "696";
+
+// User mode numerics
+pub const RPL_UMODEIS: &str = "221";
+pub const ERR_UMODEUNKNOWNFLAG: &str = "501";
+pub const ERR_USERSDONTMATCH: &str = "502";
+
+// WHOIS numerics
+pub const RPL_WHOISUSER: &str = "311";
+pub const RPL_WHOISSERVER: &str = "312";
+pub const RPL_WHOISSPECIAL: &str = "320";
+pub const RPL_WHOISACCOUNT: &str = "330";
+pub const RPL_WHOISCHANNELS: &str = "319";
+pub const RPL_WHOISIDLE: &str = "317";
+pub const RPL_WHOISBOT: &str = "335";
+pub const RPL_WHOISACTUALLY: &str = "338";
+pub const RPL_ENDOFWHOIS: &str = "318";
+// freeq extensions, sent only with the `freeq.at/whois-extended` cap
+pub const RPL_WHOISCREDENTIALS: &str = "674";
+pub const RPL_WHOISROLES: &str = "675";
+pub const RPL_WHOISE2EE: &str = "676";
+
+// draft/metadata-2 numerics
+pub const RPL_KEYVALUE: &str = "761";
+pub const RPL_KEYNOTSET: &str = "766";
+pub const RPL_METADATASUBOK: &str = "770";
+pub const RPL_METADATAUNSUBOK: &str = "771";
+pub const RPL_METADATASUBS: &str = "772";
+
+// MONITOR numerics
+pub const RPL_MONONLINE: &str = "730";
+pub const RPL_MONOFFLINE: &str = "731";
+
+// MOTD numerics
+pub const RPL_MOTDSTART: &str = "375";
+pub const RPL_MOTD: &str = "372";
+pub const RPL_ENDOFMOTD: &str = "376";
+pub const ERR_NOMOTD: &str = "422";
+
+// LIST numerics
+pub const RPL_LIST: &str = "322";
+pub const RPL_LISTEND: &str = "323";
+
+// WHO numerics
+pub const RPL_WHOREPLY: &str = "352";
+pub const RPL_ENDOFWHO: &str = "315";
+pub const RPL_WHOSPCRPL: &str = "354";
+
+// AWAY numerics
+pub const RPL_AWAY: &str = "301";
+pub const RPL_UNAWAY: &str = "305";
+pub const RPL_NOWAWAY: &str = "306";
+
+// LUSERS numerics
+pub const RPL_LUSERCLIENT: &str = "251";
+pub const RPL_LUSEROP: &str = "252";
+pub const RPL_LUSERCHANNELS: &str = "254";
+pub const RPL_LUSERME: &str = "255";
+
+// VERSION / TIME / ADMIN / INFO
+pub const RPL_VERSION: &str = "351";
+pub const RPL_TIME: &str = "391";
+pub const RPL_ADMINME: &str = "256";
+pub const RPL_ADMINLOC1: &str = "257";
+pub const RPL_ADMINLOC2: &str = "258";
+pub const RPL_ADMINEMAIL: &str = "259";
+pub const RPL_INFO: &str = "371";
+pub const RPL_ENDOFINFO: &str = "374";
+
+// HELP / HELPOP
+pub const RPL_HELPSTART: &str = "704";
+pub const RPL_HELPTXT: &str = "705";
+pub const RPL_ENDOFHELP: &str = "706";
+pub const ERR_HELPNOTFOUND: &str = "524";
+
+// USERHOST / ISON
+pub const RPL_USERHOST: &str = "302";
+pub const RPL_ISON: &str = "303";
+
+// Errors
+pub const ERR_UNKNOWNCOMMAND: &str = "421";
+pub const ERR_NONICKNAMEGIVEN: &str = "431";
+pub const ERR_ERRONEUSNICKNAME: &str = "432";
+pub const ERR_NICKNAMEINUSE: &str = "433";
+pub const ERR_NOSUCHNICK: &str = "401";
+pub const ERR_NOTREGISTERED: &str = "451";
+pub const ERR_CANNOTSENDTOCHAN: &str = "404";
+/// freeq extension: the sender matches the channel's +q (quiet) list.
+pub const ERR_QUIETED: &str = "677";
@@ -0,0 +1,684 @@
+//! Server-to-server message schema.
+//!
+//! Each S2S link carries newline-delimited JSON, one [`S2sMessage`] per
+//! line, tagged by `type`. New fields must be `#[serde(default)]` so
+//! servers on older versions keep interoperating. The transport and
+//! trust handling live in `freeq-server`'s `s2s` module.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// ALPN for server-to-server links.
+pub const S2S_ALPN: &[u8] = b"freeq/s2s/1";
+
+/// One line of a draft/multiline batch, serialized for S2S relay.
+/// Kept minimal so the wire size doesn't balloon for typical agent
+/// turns: just the body and the concat flag.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultilineLine {
+    pub body: String,
+    /// True if this line carried `draft/multiline-concat` (join to
+    /// previous with no separator). Serialized only when non-default
+    /// to keep typical-case wire small.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub concat: bool,
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+/// Messages exchanged between servers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum S2sMessage {
+    /// Identity handshake — sent immediately on link establishment.
+    /// Binds the transport identity (iroh endpoint ID) to the logical
+    /// server_name. The peer_id is verified against the QUIC connection's
+    /// remote_id() — spoofing is impossible.
+    #[serde(rename = "hello")]
+    Hello {
+        /// Iroh endpoint ID (must match connection's remote_id).
+        peer_id: String,
+        /// Human-readable server name (untrusted display metadata).
+        server_name: String,
+        /// Protocol version for capability negotiation.
+        #[serde(default)]
+        protocol_version: u32,
+        /// Trust level this server offers to the peer (informational).
+        #[serde(default)]
+        trust_level: Option<String>,
+    },
+
+    /// Phase 1: Mutual auth acknowledgment — sent after receiving Hello.
+    /// Confirms the peer is in our allowlist and we accept them.
+    #[serde(rename = "hello_ack")]
+    HelloAck {
+        /// Our endpoint ID (for verification).
+        peer_id: String,
+        /// Whether we accept this peer (in our allowlist).
+        accepted: bool,
+        /// Our trust level for this peer.
+        #[serde(default)]
+        trust_level: Option<String>,
+    },
+
+    /// Phase 2: Signed message envelope. All messages after Hello/HelloAck
+    /// are wrapped in this envelope for non-repudiation.
+    #[serde(rename = "signed")]
+    Signed {
+        /// Base64url-encoded serialized inner S2sMessage.
+        payload: String,
+        /// Base64url-encoded ed25519 signature over the payload bytes.
+        signature: String,
+        /// Signing server's endpoint ID (for key lookup).
+        signer: String,
+    },
+
+    /// Phase 4: Key rotation announcement. Signed by the OLD key to prove
+    /// continuity. Peers update their allowlists to accept the new ID.
+    #[serde(rename = "key_rotation")]
+    KeyRotation {
+        /// The old endpoint ID (must match current transport identity).
+        old_id: String,
+        /// The new endpoint ID that will replace it.
+        new_id: String,
+        /// Unix timestamp of the rotation.
+        timestamp: u64,
+        /// Signature by the old key over "rotate:{old_id}:{new_id}:{timestamp}".
+        signature: String,
+    },
+
+    /// A PRIVMSG or NOTICE relayed between servers.
+    #[serde(rename = "privmsg")]
+    Privmsg {
+        /// Stable event ID for dedup: "{origin_peer_id}:{counter}".
+        #[serde(default)]
+        event_id: String,
+        from: String,
+        target: String,
+        text: String,
+        /// Origin iroh endpoint ID (to prevent relay loops).
+        origin: String,
+        /// ULID message ID (IRCv3 `msgid` tag).
+        #[serde(default)]
+        msgid: Option<String>,
+        /// Server-attested message signature (`+freeq.at/sig`).
+        #[serde(default)]
+        sig: Option<String>,
+        /// Sender's DID — the value of the IRCv3 `account` tag. Carried
+        /// so the receiving server can stamp `account` for a remote
+        /// sender it has no local session for; without it the receiver
+        /// has no DID and federated clients show no identity. Always
+        /// origin-stamped from the authenticated session, never
+        /// client-set (preserves the anti-spoof rule across S2S).
+        /// `serde(default)` → older peers omit it (wire back-compat).
+        #[serde(default)]
+        account: Option<String>,
+        /// Application coordination tags (`+freeq.at/event` etc.) that ride
+        /// with the message so federated clients render the same card the
+        /// origin shows. `serde(default)` → older peers omit it, deserialize
+        /// to empty (wire back-compat). See `relay_coordination_tags`.
+        #[serde(default)]
+        tags: HashMap<String, String>,
+        /// When the relayed message originated as a `draft/multiline`
+        /// batch, the per-line breakdown so the receiving peer can
+        /// re-emit BATCH-wrapped frames to its own multiline-capable
+        /// clients (and N PRIVMSGs to fallback clients) instead of a
+        /// single PRIVMSG with `\n` in the body. Absent for normal
+        /// single-PRIVMSG sends; `text` always carries the assembled
+        /// body so peers without multiline-aware fan-out still get
+        /// the content (even if wire-broken on their own clients).
+        ///
+        /// # Why one event with a per-line breakdown, not N events
+        ///
+        /// An alternative shape would have been to ship a multiline
+        /// message as a sequence of N `S2sMessage::Privmsg` events,
+        /// mirroring the local wire shape (N PRIVMSGs grouped by
+        /// BATCH). That was rejected because:
+        ///
+        /// - **Atomicity**: a logical message is a single delivery
+        ///   unit; receiving peers shouldn't have to wait for N events
+        ///   to arrive (in order, with no drops) before they can fan
+        ///   out to their local channel members.
+        /// - **Dedup**: each event carries an `event_id`. Splitting
+        ///   into N events means N dedup entries and a separate
+        ///   grouping mechanism so a partial re-sync doesn't
+        ///   half-deliver.
+        /// - **Signatures**: `+freeq.at/sig` is signed over the
+        ///   assembled body. One sig per logical message keeps the
+        ///   verification cheap and unambiguous; N separately-signed
+        ///   chunks would either expensive (N sigs) or leak unverified
+        ///   payload (sig on first chunk only).
+        /// - **msgid placement**: a multiline message has one msgid
+        ///   per the IRCv3 spec. Bundling keeps "one event = one
+        ///   msgid" intact; splitting forces a non-obvious choice
+        ///   about which event owns the msgid.
+        ///
+        /// This bundling pattern is consistent with how the rest of
+        /// freeq's S2S layer transmits atomic-application units:
+        /// `SyncResponse` carries `Vec<ChannelInfo>` (the cluster's
+        /// channel view as of now, not N per-channel events);
+        /// `PolicySync` ships the full PolicyDocument JSON in one
+        /// event; `CrdtSync` bundles many CRDT ops into one payload.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        multiline_lines: Option<Vec<MultilineLine>>,
+    },
+
+    /// A PIN/UNPIN relayed between servers.
+    #[serde(rename = "pin")]
+    Pin {
+        #[serde(default)]
+        event_id: String,
+        channel: String,
+        /// The ULID msgid of the pinned/unpinned message.
+        msgid: String,
+        /// Who pinned/unpinned it.
+        pinned_by: String,
+        /// true = pin added, false = pin removed.
+        #[serde(default)]
+        adding: bool,
+        origin: String,
+    },
+
+    /// A TAGMSG relayed between servers (reactions, typing, etc.).
+    #[serde(rename = "tagmsg")]
+    Tagmsg {
+        #[serde(default)]
+        event_id: String,
+        from: String,
+        target: String,
+        /// IRCv3 tags (e.g. +react, +reply, +typing).
+        tags: HashMap<String, String>,
+        origin: String,
+    },
+
+    /// A user joined a channel.
+    #[serde(rename = "join")]
+    Join {
+        #[serde(default)]
+        event_id: String,
+        nick: String,
+        channel: String,
+        /// Authenticated DID (if any) — used for DID-based ops.
+        did: Option<String>,
+        /// Resolved AT Protocol handle (e.g. "chadfowler.com").
+        handle: Option<String>,
+        /// Whether this user is an operator on their home server.
+        #[serde(default)]
+        is_op: bool,
+        /// Actor class: "human", "agent", or "external_agent".
+        #[serde(default)]
+        actor_class: Option<String>,
+        origin: String,
+    },
+
+    /// A channel was created (carries founder info for authority resolution).
+    #[serde(rename = "channel_created")]
+    ChannelCreated {
+        #[serde(default)]
+        event_id: String,
+        channel: String,
+        /// DID of the channel founder.
+        founder_did: Option<String>,
+        /// DIDs with operator status.
+        did_ops: Vec<String>,
+        /// Unix timestamp of channel creation (informational only).
+        created_at: u64,
+        origin: String,
+    },
+
+    /// A user left a channel.
+    #[serde(rename = "part")]
+    Part {
+        #[serde(default)]
+        event_id: String,
+        nick: String,
+        channel: String,
+        origin: String,
+    },
+
+    /// A user quit.
+    #[serde(rename = "quit")]
+    Quit {
+        #[serde(default)]
+        event_id: String,
+        nick: String,
+        reason: String,
+        origin: String,
+    },
+
+    /// A user changed nick.
+    #[serde(rename = "nick_change")]
+    NickChange {
+        #[serde(default)]
+        event_id: String,
+        old: String,
+        new: String,
+        origin: String,
+    },
+
+    /// Channel topic changed.
+    #[serde(rename = "topic")]
+    Topic {
+        #[serde(default)]
+        event_id: String,
+        channel: String,
+        topic: String,
+        set_by: String,
+        origin: String,
+    },
+
+    /// Channel mode changed.
+    #[serde(rename = "mode")]
+    Mode {
+        #[serde(default)]
+        event_id: String,
+        channel: String,
+        mode: String,
+        arg: Option<String>,
+        set_by: String,
+        origin: String,
+    },
+
+    /// Request full state sync (sent on initial link).
+    #[serde(rename = "sync_request")]
+    SyncRequest,
+
+    /// Response with current server state.
+    #[serde(rename = "sync_response")]
+    SyncResponse {
+        /// Server's iroh endpoint ID.
+        server_id: String,
+        /// Active channels and their topics.
+        channels: Vec<ChannelInfo>,
+    },
+
+    /// Automerge CRDT sync message for convergent state.
+    #[serde(rename = "crdt_sync")]
+    CrdtSync {
+        /// Base64-encoded Automerge sync message.
+        data: String,
+        /// Origin iroh endpoint ID (used to key sync state).
+        origin: String,
+    },
+
+    /// A user was kicked from a channel.
+    #[serde(rename = "kick")]
+    Kick {
+        #[serde(default)]
+        event_id: String,
+        /// Nick of the user being kicked.
+        nick: String,
+        channel: String,
+        /// Nick of the op who kicked them.
+        by: String,
+        reason: String,
+        origin: String,
+    },
+
+    /// A ban was set or removed on a channel.
+    #[serde(rename = "ban")]
+    Ban {
+        #[serde(default)]
+        event_id: String,
+        channel: String,
+        /// The ban mask (nick!user@host or DID).
+        mask: String,
+        /// Who set/removed the ban.
+        set_by: String,
+        /// true = ban added, false = ban removed.
+        adding: bool,
+        origin: String,
+    },
+
+    /// An invite-exception (+I) entry was set or removed on a channel.
+    #[serde(rename = "invite_exception")]
+    InviteException {
+        #[serde(default)]
+        event_id: String,
+        channel: String,
+        /// The mask (nick!user@host or DID).
+        mask: String,
+        /// Who set/removed the entry.
+        set_by: String,
+        /// true = entry added, false = entry removed.
+        adding: bool,
+        origin: String,
+    },
+
+    /// A quiet (+q) entry was set or removed on a channel.
+    #[serde(rename = "quiet")]
+    Quiet {
+        #[serde(default)]
+        event_id: String,
+        channel: String,
+        /// The mask (nick!user@host or DID).
+        mask: String,
+        /// Who set/removed the entry.
+        set_by: String,
+        /// true = entry added, false = entry removed.
+        adding: bool,
+        origin: String,
+    },
+
+    /// Policy sync — share a channel's policy document with peers.
+    /// Sent when a policy is created/updated/cleared.
+    #[serde(rename = "policy_sync")]
+    PolicySync {
+        #[serde(default)]
+        event_id: String,
+        channel: String,
+        /// JSON-serialized PolicyDocument (None = policy cleared).
+        policy_json: Option<String>,
+        /// JSON-serialized AuthoritySet.
+        authority_set_json: Option<String>,
+        origin: String,
+    },
+
+    /// Ask a peer for a channel's whole policy chain, after its sync
+    /// announced a [`PolicyHead`] we don't have. Answered with
+    /// [`S2sMessage::PolicyChain`].
+    #[serde(rename = "policy_chain_request")]
+    PolicyChainRequest {
+        #[serde(default)]
+        event_id: String,
+        channel: String,
+        origin: String,
+    },
+
+    /// A channel's policy chain, for arbitration: the receiver adopts it
+    /// if it is validly signed and extends or beats its own (longer chain
+    /// first, then the lower head hash), along with the founder the chain
+    /// records.
+    #[serde(rename = "policy_chain")]
+    PolicyChain {
+        #[serde(default)]
+        event_id: String,
+        channel: String,
+        /// JSON-serialized PolicyDocuments, oldest first.
+        policies: Vec<String>,
+        /// JSON-serialized AuthoritySets the policies refer to.
+        #[serde(default)]
+        authority_sets: Vec<String>,
+        origin: String,
+    },
+
+    /// A user erased their data: blank the listed messages they authored.
+    /// Receivers only touch messages stored with `sender_did == did`, and
+    /// tell their own clients with `+draft/delete`. Large erasures are
+    /// split across several events.
+    #[serde(rename = "erase")]
+    Erase {
+        #[serde(default)]
+        event_id: String,
+        did: String,
+        /// ULID msgids of the erased messages.
+        msgids: Vec<String>,
+        origin: String,
+    },
+
+    /// An invite was issued for a user on a channel.
+    #[serde(rename = "invite")]
+    Invite {
+        #[serde(default)]
+        event_id: String,
+        channel: String,
+        /// The invitee identifier (DID, nick:XXX, or session ID).
+        invitee: String,
+        /// Nick of the user who issued the invite.
+        invited_by: String,
+        origin: String,
+    },
+
+    // ── AV session federation ───────────────────────────────────────
+    /// An AV session was created (voice/video call started).
+    #[serde(rename = "av_session_created")]
+    AvSessionCreated {
+        #[serde(default)]
+        event_id: String,
+        session_id: String,
+        channel: String,
+        created_by_did: String,
+        created_by_nick: String,
+        title: Option<String>,
+        iroh_ticket: Option<String>,
+        origin: String,
+    },
+
+    /// A user joined an AV session.
+    #[serde(rename = "av_session_joined")]
+    AvSessionJoined {
+        #[serde(default)]
+        event_id: String,
+        session_id: String,
+        did: String,
+        nick: String,
+        origin: String,
+    },
+
+    /// A user left an AV session.
+    #[serde(rename = "av_session_left")]
+    AvSessionLeft {
+        #[serde(default)]
+        event_id: String,
+        session_id: String,
+        did: String,
+        origin: String,
+    },
+
+    /// An AV session ended.
+    #[serde(rename = "av_session_ended")]
+    AvSessionEnded {
+        #[serde(default)]
+        event_id: String,
+        session_id: String,
+        ended_by: Option<String>,
+        origin: String,
+    },
+
+    /// The public channels the sender has local members in, for a
+    /// federation-wide LIST. Sent on link and periodically; each one
+    /// replaces the previous one from that peer. Secret (+s) and
+    /// local-only (`&`) channels are never included.
+    #[serde(rename = "channel_directory")]
+    ChannelDirectory {
+        channels: Vec<DirectoryEntry>,
+        origin: String,
+    },
+
+    /// Internal event: a peer's S2S link has disconnected.
+    /// Not sent over the wire — synthesized locally so the event processor
+    /// can clean up remote_members for that peer's origin.
+    #[serde(rename = "peer_disconnected")]
+    PeerDisconnected {
+        /// The iroh endpoint ID of the peer that disconnected.
+        peer_id: String,
+    },
+}
+
+/// Per-user info in a channel sync.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncNick {
+    pub nick: String,
+    #[serde(default)]
+    pub is_op: bool,
+    pub did: Option<String>,
+    /// Actor class: "human", "agent", or "external_agent".
+    #[serde(default)]
+    pub actor_class: Option<String>,
+}
+
+/// One channel in a [`S2sMessage::ChannelDirectory`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirectoryEntry {
+    pub name: String,
+    /// Members the sender sees, local and remote.
+    pub members: u32,
+    /// Members connected to the sender itself.
+    pub local_members: u32,
+    pub topic: Option<String>,
+}
+
+/// Channel info for sync.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelInfo {
+    pub name: String,
+    pub topic: Option<String>,
+    /// Legacy field: plain nick list (for backward compat with old servers).
+    #[serde(default)]
+    pub nicks: Vec<String>,
+    /// Rich nick list with per-user metadata (preferred over `nicks`).
+    #[serde(default)]
+    pub nick_info: Vec<SyncNick>,
+    /// Channel founder DID.
+    pub founder_did: Option<String>,
+    /// DIDs with persistent operator status.
+    pub did_ops: Vec<String>,
+    /// Channel creation timestamp.
+    pub created_at: u64,
+    /// Channel modes: topic_locked, invite_only, no_ext_msg, moderated
+    #[serde(default)]
+    pub topic_locked: bool,
+    #[serde(default)]
+    pub invite_only: bool,
+    #[serde(default)]
+    pub no_ext_msg: bool,
+    #[serde(default)]
+    pub moderated: bool,
+    /// Public archive (+A).
+    #[serde(default)]
+    pub archived: bool,
+    /// Auditorium (+u).
+    #[serde(default)]
+    pub auditorium: bool,
+    /// No statistics (+S).
+    #[serde(default)]
+    pub no_stats: bool,
+    /// Secret (+s).
+    #[serde(default)]
+    pub secret: bool,
+    /// Temporary (+T) mode argument, e.g. `2h!`.
+    #[serde(default)]
+    pub temporary: Option<String>,
+    /// History visibility (+H), e.g. `members-since-join`. `None` from
+    /// peers that predate it.
+    #[serde(default)]
+    pub history_visibility: Option<String>,
+    #[serde(default)]
+    pub key: Option<String>,
+    /// Active bans (mask strings).
+    #[serde(default)]
+    pub bans: Vec<String>,
+    /// Active invites (DIDs, nick:XXX tokens).
+    #[serde(default)]
+    pub invites: Vec<String>,
+    /// Active +I invite-exception entries (mask strings, hostmask or DID).
+    #[serde(default)]
+    pub invite_exceptions: Vec<String>,
+    /// Active +q quiet entries (mask strings, hostmask or DID).
+    #[serde(default)]
+    pub quiets: Vec<String>,
+    /// Previous topics, oldest first (TOPICHIST).
+    #[serde(default)]
+    pub topic_history: Vec<SyncTopic>,
+    /// Latest version of the channel's policy chain, if it has a policy.
+    #[serde(default)]
+    pub policy_head: Option<PolicyHead>,
+}
+
+/// The latest policy version a server holds for a channel.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyHead {
+    /// The document's `policy_id` (canonical hash).
+    pub hash: String,
+    /// Its version, i.e. the chain's length.
+    pub version: i64,
+}
+
+/// A previous channel topic, for sync.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncTopic {
+    pub text: String,
+    pub set_by: String,
+    pub set_at: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hello_from_older_peer_uses_defaults() {
+        let msg: S2sMessage =
+            serde_json::from_str(r#"{"type":"hello","peer_id":"abc","server_name":"old"}"#)
+                .unwrap();
+        let S2sMessage::Hello {
+            protocol_version,
+            trust_level,
+            ..
+        } = msg
+        else {
+            panic!("expected hello");
+        };
+        assert_eq!(protocol_version, 0);
+        assert_eq!(trust_level, None);
+    }
+
+    #[test]
+    fn multiline_concat_is_omitted_when_false() {
+        let line = MultilineLine {
+            body: "hi".into(),
+            concat: false,
+        };
+        assert_eq!(serde_json::to_string(&line).unwrap(), r#"{"body":"hi"}"#);
+    }
+
+    #[test]
+    fn quiet_is_tagged_separately_from_ban() {
+        let msg = S2sMessage::Quiet {
+            event_id: "peer:1".into(),
+            channel: "#chan".into(),
+            mask: "did:plc:abc".into(),
+            set_by: "alice".into(),
+            adding: true,
+            origin: "peer".into(),
+        };
+        let json = serde_json::to_string(&msg).unwrap();
+        assert!(json.contains(r#""type":"quiet""#), "{json}");
+        let back: S2sMessage = serde_json::from_str(&json).unwrap();
+        assert!(matches!(back, S2sMessage::Quiet { adding: true, .. }));
+    }
+
+    #[test]
+    fn channel_directory_round_trips() {
+        let msg = S2sMessage::ChannelDirectory {
+            channels: vec![DirectoryEntry {
+                name: "#rust".into(),
+                members: 12,
+                local_members: 5,
+                topic: Some("crabs".into()),
+            }],
+            origin: "peer".into(),
+        };
+        let json = serde_json::to_string(&msg).unwrap();
+        assert!(json.contains(r#""type":"channel_directory""#), "{json}");
+        let S2sMessage::ChannelDirectory { channels, origin } =
+            serde_json::from_str(&json).unwrap()
+        else {
+            panic!("expected channel_directory");
+        };
+        assert_eq!(origin, "peer");
+        assert_eq!(channels[0].local_members, 5);
+    }
+
+    #[test]
+    fn channel_info_from_older_peer_has_no_policy_head() {
+        let info: ChannelInfo = serde_json::from_str(
+            r##"{"name":"#a","topic":null,"founder_did":null,"did_ops":[],"created_at":0}"##,
+        )
+        .unwrap();
+        assert_eq!(info.policy_head, None);
+    }
+}
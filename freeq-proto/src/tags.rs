@@ -0,0 +1,123 @@
+//! IRCv3 message tag keys.
+//!
+//! Client-only tags start with `+`. freeq's own tags live under
+//! `+freeq.at/`.
+
+// Server tags
+pub const MSGID: &str = "msgid";
+pub const TIME: &str = "time";
+pub const ACCOUNT: &str = "account";
+pub const BATCH: &str = "batch";
+
+// Replies, reactions, edits
+pub const REPLY: &str = "+reply";
+pub const DRAFT_REPLY: &str = "+draft/reply";
+pub const REACT: &str = "+react";
+pub const DRAFT_REACT: &str = "+draft/react";
+pub const EDIT: &str = "+draft/edit";
+pub const DELETE: &str = "+draft/delete";
+
+// Ephemeral presence (typing, status)
+pub const TYPING: &str = "+typing";
+pub const DRAFT_TYPING: &str = "+draft/typing";
+pub const STATUS: &str = "+freeq.at/status";
+/// Short form of [`STATUS`] some clients send.
+pub const SHORT_STATUS: &str = "+freeq/status";
+/// Server tag: how many people are typing in a busy channel.
+pub const TYPING_COUNT: &str = "freeq.at/typing-count";
+
+// Message priority
+/// How much a PRIVMSG or NOTICE matters, as a [`Priority`]. Untagged
+/// messages are normal; clients may fold runs of low-priority ones.
+pub const PRIORITY: &str = "+freeq.at/priority";
+/// Short form of [`PRIORITY`].
+pub const SHORT_PRIORITY: &str = "+freeq/priority";
+
+/// Draft and short tags and the canonical names the server normalizes
+/// them to.
+pub const DRAFT_ALIASES: [(&str, &str); 5] = [
+    (DRAFT_REACT, REACT),
+    (DRAFT_REPLY, REPLY),
+    (DRAFT_TYPING, TYPING),
+    (SHORT_STATUS, STATUS),
+    (SHORT_PRIORITY, PRIORITY),
+];
+
+/// Values of the [`PRIORITY`] tag. The server only accepts `low` from
+/// signed-in agents, so bots' status chatter can be folded without
+/// letting anyone bury a human's message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+        }
+    }
+
+    /// Parse a tag value, ignoring case.
+    pub fn parse(value: &str) -> Option<Self> {
+        [Priority::Low, Priority::Normal, Priority::High]
+            .into_iter()
+            .find(|p| value.eq_ignore_ascii_case(p.as_str()))
+    }
+
+    /// The priority `tags` carry, in either form; `Normal` when untagged
+    /// or unrecognised.
+    pub fn of(tags: &std::collections::HashMap<String, String>) -> Self {
+        tags.get(PRIORITY)
+            .or_else(|| tags.get(SHORT_PRIORITY))
+            .and_then(|v| Self::parse(v))
+            .unwrap_or_default()
+    }
+}
+
+impl std::fmt::Display for Priority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// draft/multiline
+pub const MULTILINE_CONCAT: &str = "draft/multiline-concat";
+
+/// Prefix shared by all freeq tags.
+pub const FREEQ_PREFIX: &str = "+freeq.at/";
+
+pub const SIG: &str = "+freeq.at/sig";
+pub const ORIGIN: &str = "+freeq.at/origin";
+pub const MULTILINE: &str = "+freeq.at/multiline";
+pub const STREAMING: &str = "+freeq.at/streaming";
+pub const PIN: &str = "+freeq.at/pin";
+pub const UNPIN: &str = "+freeq.at/unpin";
+pub const UNREACT: &str = "+freeq.at/unreact";
+pub const REACTIONS: &str = "+freeq.at/reactions";
+pub const THREAD: &str = "+freeq.at/thread";
+pub const ALT: &str = "+freeq.at/alt";
+/// Servers a federated message passed through, origin first, each as
+/// `<server>@<unix-ms>`; see `freeq_server::s2s::add_hop`.
+pub const HOPS: &str = "+freeq.at/hops";
+
+// Agent coordination
+pub const EVENT: &str = "+freeq.at/event";
+pub const PAYLOAD: &str = "+freeq.at/payload";
+pub const TASK_ID: &str = "+freeq.at/task-id";
+pub const EVIDENCE_TYPE: &str = "+freeq.at/evidence-type";
+
+// Direct DM transport negotiation
+pub const P2P_OFFER: &str = "+freeq.at/p2p-offer";
+pub const P2P_ANSWER: &str = "+freeq.at/p2p-answer";
+pub const P2P_CLOSE: &str = "+freeq.at/p2p-close";
+/// Server tag on a relayed p2p offer or answer: the sender's DID.
+pub const P2P_DID: &str = "freeq.at/p2p-did";
+
+// Double Ratchet session reset (see `freeq_sdk::session_reset`)
+pub const E2EE_RESET: &str = "+freeq.at/e2ee-reset";
@@ -0,0 +1,149 @@
+//! Property tests for the IRC line parser.
+//!
+//! Arbitrary input must never panic and every borrowed part must come
+//! from the input; well-formed messages must survive a serialize/parse
+//! round trip; and normalizing an accepted line must be idempotent.
+//! `fuzz/` runs the same checks under libFuzzer.
+
+use std::collections::HashMap;
+
+use freeq_proto::message::{MAX_PARAMS, MAX_TAGS_LEN};
+use freeq_proto::{Message, MessageRef, ParseError};
+use proptest::prelude::*;
+
+/// Whether `part` points inside `line`.
+fn within(line: &str, part: &str) -> bool {
+    let start = line.as_ptr() as usize;
+    let p = part.as_ptr() as usize;
+    p >= start && p + part.len() <= start + line.len()
+}
+
+fn tag_key() -> impl Strategy<Value = String> {
+    "\\+?([a-z0-9.-]{1,12}/)?[A-Za-z0-9-]{1,16}"
+}
+
+fn tag_value() -> impl Strategy<Value = String> {
+    // Everything that needs escaping, plus NUL (which is dropped).
+    proptest::collection::vec(
+        prop_oneof![
+            Just(';'),
+            Just(' '),
+            Just('\\'),
+            Just('\r'),
+            Just('\n'),
+            Just('\0'),
+            any::<char>()
+        ],
+        0..24,
+    )
+    .prop_map(|chars| chars.into_iter().collect())
+}
+
+fn command() -> impl Strategy<Value = String> {
+    prop_oneof!["[A-Z]{1,12}", "[0-9]{3}"]
+}
+
+fn middle_param() -> impl Strategy<Value = String> {
+    "[^ :\0\r\n][^ \0\r\n]{0,20}"
+}
+
+fn trailing_param() -> impl Strategy<Value = String> {
+    prop_oneof!["[^\0\r\n]{0,40}", "[^\0\r\n]{500,10000}"]
+}
+
+prop_compose! {
+    fn message()(
+        tags in proptest::collection::hash_map(tag_key(), tag_value(), 0..6),
+        prefix in proptest::option::of("[^ :\0\r\n][^ \0\r\n]{0,30}"),
+        command in command(),
+        middles in proptest::collection::vec(middle_param(), 0..MAX_PARAMS),
+        trailing in proptest::option::of(trailing_param()),
+    ) -> Message {
+        let mut params = middles;
+        params.extend(trailing);
+        Message { tags, prefix, command, params }
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn arbitrary_text_never_panics(line in any::<String>()) {
+        if let Ok(msg) = MessageRef::parse(&line) {
+            prop_assert!(within(&line, msg.command()));
+            if let Some(prefix) = msg.prefix() {
+                prop_assert!(within(&line, prefix.raw));
+            }
+            for (key, value) in msg.tags() {
+                prop_assert!(within(&line, key) && within(&line, value.raw()));
+                let _ = value.unescape();
+            }
+            prop_assert!(msg.params().count() <= MAX_PARAMS);
+            for param in msg.params() {
+                prop_assert!(within(&line, param));
+            }
+        }
+    }
+
+    #[test]
+    fn ircish_text_never_panics(line in "(@[a-z+/=;\\\\ ]{0,40} )?(:[!@a-z]{0,10} )?[A-Za-z0-9]{0,5}( [: a-z]{0,10}){0,20}") {
+        let _ = MessageRef::parse(&line).map(|m| m.to_message().to_string());
+    }
+
+    #[test]
+    fn invalid_utf8_is_rejected_before_parsing(
+        mut bytes in proptest::collection::vec(any::<u8>(), 0..200),
+        at in any::<prop::sample::Index>(),
+    ) {
+        // 0xFF never appears in UTF-8. What reaches the parser is the lossy
+        // decoding, which must still be safe.
+        let i = at.index(bytes.len() + 1);
+        bytes.insert(i, 0xFF);
+        prop_assert!(std::str::from_utf8(&bytes).is_err());
+        let lossy = String::from_utf8_lossy(&bytes);
+        let _ = MessageRef::parse(&lossy);
+    }
+
+    #[test]
+    fn well_formed_messages_round_trip(msg in message()) {
+        let wire = msg.to_string();
+        let parsed = Message::parse(&wire);
+        prop_assert!(parsed.is_some(), "rejected {wire:?}: {:?}", MessageRef::parse(&wire));
+        let parsed = parsed.unwrap();
+        let expected_tags: HashMap<String, String> = msg
+            .tags
+            .iter()
+            .map(|(k, v)| (k.clone(), v.replace('\0', "")))
+            .collect();
+        prop_assert_eq!(parsed.tags, expected_tags);
+        prop_assert_eq!(parsed.prefix, msg.prefix);
+        prop_assert_eq!(parsed.command, msg.command);
+        prop_assert_eq!(parsed.params, msg.params);
+    }
+
+    #[test]
+    fn normalizing_is_idempotent(msg in message()) {
+        let wire = msg.to_string();
+        let first = MessageRef::parse(&wire).unwrap().to_string();
+        let second = MessageRef::parse(&first).unwrap().to_string();
+        prop_assert_eq!(first, second);
+    }
+
+    #[test]
+    fn oversized_tag_sections_are_rejected(extra in 0usize..64) {
+        let line = format!("@k={} PING", "v".repeat(MAX_TAGS_LEN - 4 + 1 + extra));
+        prop_assert_eq!(MessageRef::parse(&line), Err(ParseError::TagsTooLong));
+    }
+
+    #[test]
+    fn param_count_is_capped(n in 0usize..40) {
+        let line = format!("CMD{}", " p".repeat(n));
+        let parsed = MessageRef::parse(&line);
+        if n <= MAX_PARAMS {
+            prop_assert_eq!(parsed.unwrap().params().count(), n);
+        } else {
+            prop_assert_eq!(parsed, Err(ParseError::TooManyParams));
+        }
+    }
+}
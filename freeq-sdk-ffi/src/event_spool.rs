@@ -0,0 +1,336 @@
+//! Persistent spool for events that arrive while the app is suspended.
+//!
+//! When iOS suspends the app, `on_event` callbacks queued onto the main
+//! thread never run, and if the process is then killed they are gone. So
+//! while Swift has marked the client suspended, the event pump appends
+//! the events that must not be lost — `Message` and `Disconnected` — to
+//! a JSON-lines file instead of handing them to the callback. On resume
+//! Swift calls `drain_pending_events()` and replays them; a spool left
+//! over from a killed process is picked up the same way on next launch.
+//!
+//! The spool is a ring: beyond [`CAPACITY`] events the oldest are dropped,
+//! so a long suspension in a busy channel can't fill the disk. Appends are
+//! a single `write` of one line followed by `sync_data`; the file is
+//! compacted back to the newest `CAPACITY` lines once it holds twice that.
+
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+
+use crate::{FreeqEvent, FreeqHighlight, IrcMessage, ReactionTally};
+
+/// Most events kept; older ones are dropped first.
+pub const CAPACITY: usize = 1_000;
+
+pub struct EventSpool {
+    path: PathBuf,
+    file: File,
+    /// Encoded lines, oldest first, mirroring the file after compaction.
+    lines: VecDeque<String>,
+    /// Lines in the file, including ones already evicted from `lines`.
+    on_disk: usize,
+}
+
+impl EventSpool {
+    /// Open (or create) the spool at `path`, keeping anything a previous
+    /// process left behind.
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut lines: VecDeque<String> = match fs::read_to_string(&path) {
+            Ok(text) => text
+                .lines()
+                .filter(|l| decode(l).is_some())
+                .map(str::to_string)
+                .collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => VecDeque::new(),
+            Err(e) => return Err(e),
+        };
+        while lines.len() > CAPACITY {
+            lines.pop_front();
+        }
+        let on_disk = lines.len();
+        // Rewrite so a torn final line from a crash is not appended to.
+        write_all_lines(&path, &lines)?;
+        let file = OpenOptions::new().append(true).open(&path)?;
+        Ok(Self {
+            path,
+            file,
+            lines,
+            on_disk,
+        })
+    }
+
+    /// Whether `event` is one the spool keeps while suspended.
+    pub fn is_durable(event: &FreeqEvent) -> bool {
+        matches!(
+            event,
+            FreeqEvent::Message { .. } | FreeqEvent::Disconnected { .. }
+        )
+    }
+
+    /// Append `event`. Events that aren't durable are ignored.
+    pub fn push(&mut self, event: &FreeqEvent) -> io::Result<()> {
+        let Some(line) = encode(event) else {
+            return Ok(());
+        };
+        self.file.write_all(format!("{line}\n").as_bytes())?;
+        self.file.sync_data()?;
+        self.lines.push_back(line);
+        self.on_disk += 1;
+        if self.lines.len() > CAPACITY {
+            self.lines.pop_front();
+        }
+        if self.on_disk >= CAPACITY * 2 {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    /// Take every spooled event, oldest first, and empty the spool.
+    pub fn drain(&mut self) -> io::Result<Vec<FreeqEvent>> {
+        self.file.set_len(0)?;
+        self.file.sync_data()?;
+        self.on_disk = 0;
+        Ok(self.lines.drain(..).filter_map(|l| decode(&l)).collect())
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    fn compact(&mut self) -> io::Result<()> {
+        write_all_lines(&self.path, &self.lines)?;
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        self.on_disk = self.lines.len();
+        Ok(())
+    }
+}
+
+/// Replace the file at `path` with `lines` via a rename, so a crash
+/// mid-write leaves either the old spool or the new one.
+fn write_all_lines(path: &Path, lines: &VecDeque<String>) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    let mut out = File::create(&tmp)?;
+    for line in lines {
+        out.write_all(line.as_bytes())?;
+        out.write_all(b"\n")?;
+    }
+    out.sync_all()?;
+    fs::rename(&tmp, path)
+}
+
+fn encode(event: &FreeqEvent) -> Option<String> {
+    let value = match event {
+        FreeqEvent::Message { msg } => json!({
+            "type": "message",
+            "from_nick": msg.from_nick,
+            "target": msg.target,
+            "text": msg.text,
+            "msgid": msg.msgid,
+            "reply_to": msg.reply_to,
+            "replaces_msgid": msg.replaces_msgid,
+            "edit_of": msg.edit_of,
+            "batch_id": msg.batch_id,
+            "pin_msgid": msg.pin_msgid,
+            "unpin_msgid": msg.unpin_msgid,
+            "is_action": msg.is_action,
+            "is_signed": msg.is_signed,
+            "timestamp_ms": msg.timestamp_ms,
+            "account": msg.account,
+            "origin": msg.origin,
+            "reactions": msg
+                .reactions
+                .iter()
+                .map(|r| json!({ "emoji": r.emoji, "nicks": r.nicks }))
+                .collect::<Vec<_>>(),
+            "formatted_text": msg.formatted_text,
+            "encrypted": msg.encrypted,
+            "highlight": msg.highlight.map(FreeqHighlight::as_str),
+        }),
+        FreeqEvent::Disconnected { reason } => json!({
+            "type": "disconnected",
+            "reason": reason,
+        }),
+        _ => return None,
+    };
+    Some(value.to_string())
+}
+
+fn decode(line: &str) -> Option<FreeqEvent> {
+    let v: Value = serde_json::from_str(line).ok()?;
+    let string = |key: &str| v[key].as_str().map(str::to_string);
+    match v["type"].as_str()? {
+        "message" => Some(FreeqEvent::Message {
+            msg: IrcMessage {
+                from_nick: string("from_nick")?,
+                target: string("target")?,
+                text: string("text")?,
+                msgid: string("msgid"),
+                reply_to: string("reply_to"),
+                replaces_msgid: string("replaces_msgid"),
+                edit_of: string("edit_of"),
+                batch_id: string("batch_id"),
+                pin_msgid: string("pin_msgid"),
+                unpin_msgid: string("unpin_msgid"),
+                is_action: v["is_action"].as_bool().unwrap_or(false),
+                is_signed: v["is_signed"].as_bool().unwrap_or(false),
+                timestamp_ms: v["timestamp_ms"].as_i64()?,
+                account: string("account"),
+                origin: string("origin"),
+                reactions: v["reactions"]
+                    .as_array()
+                    .map(|rs| {
+                        rs.iter()
+                            .filter_map(|r| {
+                                Some(ReactionTally {
+                                    emoji: r["emoji"].as_str()?.to_string(),
+                                    nicks: r["nicks"]
+                                        .as_array()?
+                                        .iter()
+                                        .filter_map(|n| n.as_str().map(str::to_string))
+                                        .collect(),
+                                })
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                formatted_text: string("formatted_text"),
+                encrypted: v["encrypted"].as_bool().unwrap_or(false),
+                highlight: v["highlight"].as_str().and_then(FreeqHighlight::parse),
+            },
+        }),
+        "disconnected" => Some(FreeqEvent::Disconnected {
+            reason: string("reason")?,
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spool_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("freeq-spool-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("events.jsonl")
+    }
+
+    fn message(text: &str) -> FreeqEvent {
+        FreeqEvent::Message {
+            msg: IrcMessage {
+                from_nick: "alice".into(),
+                target: "#freeq".into(),
+                text: text.into(),
+                msgid: Some("01J".into()),
+                reply_to: None,
+                replaces_msgid: None,
+                edit_of: None,
+                batch_id: None,
+                pin_msgid: None,
+                unpin_msgid: None,
+                is_action: false,
+                is_signed: true,
+                timestamp_ms: 1_700_000_000_000,
+                account: Some("did:plc:alice".into()),
+                origin: None,
+                reactions: vec![ReactionTally {
+                    emoji: "👍".into(),
+                    nicks: vec!["bob".into()],
+                }],
+                formatted_text: Some("**hi**".into()),
+                encrypted: true,
+                highlight: Some(FreeqHighlight::Mention),
+            },
+        }
+    }
+
+    fn text_of(event: &FreeqEvent) -> &str {
+        match event {
+            FreeqEvent::Message { msg } => &msg.text,
+            FreeqEvent::Disconnected { reason } => reason,
+            _ => panic!("not a spooled event"),
+        }
+    }
+
+    #[test]
+    fn events_survive_reopen_and_drain_empties() {
+        let path = spool_path("reopen");
+        let mut spool = EventSpool::open(&path).unwrap();
+        spool.push(&message("hi\nthere")).unwrap();
+        spool
+            .push(&FreeqEvent::Connected)
+            .expect("non-durable events are ignored");
+        spool
+            .push(&FreeqEvent::Disconnected {
+                reason: "suspended".into(),
+            })
+            .unwrap();
+        drop(spool);
+
+        let mut spool = EventSpool::open(&path).unwrap();
+        assert_eq!(spool.len(), 2);
+        let events = spool.drain().unwrap();
+        assert_eq!(text_of(&events[0]), "hi\nthere");
+        assert_eq!(text_of(&events[1]), "suspended");
+        let FreeqEvent::Message { msg } = &events[0] else {
+            unreachable!()
+        };
+        assert_eq!(msg.account.as_deref(), Some("did:plc:alice"));
+        assert_eq!(msg.reactions[0].nicks, ["bob"]);
+        assert_eq!(msg.formatted_text.as_deref(), Some("**hi**"));
+        assert!(msg.is_signed);
+        assert!(msg.encrypted);
+        assert_eq!(msg.highlight, Some(FreeqHighlight::Mention));
+
+        assert!(spool.is_empty());
+        drop(spool);
+        assert!(EventSpool::open(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn oldest_events_are_dropped_past_capacity() {
+        let path = spool_path("ring");
+        let mut spool = EventSpool::open(&path).unwrap();
+        for i in 0..CAPACITY * 2 + 5 {
+            spool.push(&message(&i.to_string())).unwrap();
+        }
+        drop(spool);
+
+        let mut spool = EventSpool::open(&path).unwrap();
+        let events = spool.drain().unwrap();
+        assert_eq!(events.len(), CAPACITY);
+        assert_eq!(text_of(&events[0]), (CAPACITY + 5).to_string());
+        assert_eq!(
+            text_of(&events[CAPACITY - 1]),
+            (CAPACITY * 2 + 4).to_string()
+        );
+    }
+
+    #[test]
+    fn torn_trailing_line_is_discarded() {
+        let path = spool_path("torn");
+        let mut spool = EventSpool::open(&path).unwrap();
+        spool.push(&message("kept")).unwrap();
+        drop(spool);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"type":"message","from_n"#).unwrap();
+        drop(file);
+
+        let mut spool = EventSpool::open(&path).unwrap();
+        spool.push(&message("after")).unwrap();
+        let events = spool.drain().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(text_of(&events[1]), "after");
+    }
+}
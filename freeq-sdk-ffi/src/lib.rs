@@ -1,8 +1,14 @@
 //! FFI wrapper around freeq-sdk for Swift/Kotlin consumption via UniFFI.
 
+use freeq_proto::tags as tag;
+use freeq_sdk::highlight::Highlighter;
+use freeq_sdk::pending::{CallOptions, CancellationToken};
 use once_cell::sync::Lazy;
 use std::sync::{Arc, Mutex};
 
+mod event_spool;
+use event_spool::EventSpool;
+
 /// Install a tracing subscriber that writes to stderr the first time anyone
 /// touches the SDK. iOS captures this in the Xcode console pane while
 /// debugging — invaluable for triaging connect-path hangs. Idempotent: a
@@ -61,6 +67,16 @@ pub struct IrcMessage {
     /// server's `+freeq.at/reactions` tag (CHATHISTORY / JOIN replay).
     /// Live reactions still arrive as separate `TagMsg` events.
     pub reactions: Vec<ReactionTally>,
+    /// `text` as markdown when it carries IRC formatting (bold, italic,
+    /// monospace); `None` for plain text. Render this when present.
+    pub formatted_text: Option<String>,
+    /// The message was end-to-end encrypted and `text` is the plaintext;
+    /// see `FreeqClient::enable_auto_decrypt`.
+    pub encrypted: bool,
+    /// Why this message should raise a notification, if it should: a DM,
+    /// a mention of our nick, or a keyword from `set_highlight_keywords`.
+    /// Only set on live messages, never on history replays.
+    pub highlight: Option<FreeqHighlight>,
 }
 
 pub struct ReactionTally {
@@ -115,22 +131,190 @@ pub struct IrcMember {
     pub away_msg: Option<String>,
 }
 
+/// A channel member in a roster delta (see `freeq_sdk::channels`).
+pub struct RosterMember {
+    pub nick: String,
+    pub account: Option<String>,
+    /// `@`, `%`, `+` or empty.
+    pub prefix: String,
+    pub is_away: bool,
+}
+
+impl From<&freeq_sdk::channels::Member> for RosterMember {
+    fn from(member: &freeq_sdk::channels::Member) -> Self {
+        Self {
+            nick: member.nick.clone(),
+            account: member.account.clone(),
+            prefix: member.prefix().to_string(),
+            is_away: member.away,
+        }
+    }
+}
+
+/// TLS settings for `set_tls_options` (see `freeq_sdk::tls::TlsOptions`).
+pub struct TlsConfig {
+    /// `sha256/<base64>` SPKI hashes; the chain must contain one.
+    pub spki_pins: Vec<String>,
+    /// PEM CA certificates trusted in addition to the system roots.
+    pub root_certs_pem: Vec<String>,
+    /// ALPN protocols to offer.
+    pub alpn: Vec<String>,
+}
+
+/// How generated fallback nicks are formed (see `freeq_sdk::client::NickSuffix`).
+pub enum NickSuffixStyle {
+    Numbered,
+    Underscore,
+    Off,
+}
+
+/// Nick fallback for `set_nick_fallback` (see `freeq_sdk::client::NickFallback`).
+pub struct NickFallbackConfig {
+    /// Nicks to try, in order, when the desired one is taken.
+    pub alternatives: Vec<String>,
+    /// How to generate further nicks once `alternatives` run out.
+    pub suffix: NickSuffixStyle,
+    /// Take the desired nick back once it's free.
+    pub regain: bool,
+}
+
 pub struct ChannelTopic {
     pub text: String,
     pub set_by: Option<String>,
 }
 
+/// Which channels `list_channels` returns (see `freeq_sdk::directory::ListFilter`).
+pub struct ChannelListFilter {
+    pub min_users: Option<u32>,
+    pub max_users: Option<u32>,
+    /// Glob over the channel name, e.g. `*rust*`.
+    pub mask: Option<String>,
+}
+
+impl From<ChannelListFilter> for freeq_sdk::directory::ListFilter {
+    fn from(filter: ChannelListFilter) -> Self {
+        Self {
+            min_users: filter.min_users,
+            max_users: filter.max_users,
+            mask: filter.mask.filter(|m| !m.is_empty()),
+        }
+    }
+}
+
+/// One channel in the server's directory.
+pub struct ChannelListEntry {
+    pub name: String,
+    pub users: u32,
+    pub topic: String,
+}
+
+impl From<freeq_sdk::directory::ChannelListing> for ChannelListEntry {
+    fn from(listing: freeq_sdk::directory::ChannelListing) -> Self {
+        Self {
+            name: listing.name,
+            users: listing.users,
+            topic: listing.topic,
+        }
+    }
+}
+
+/// A contact's aggregated presence (see `freeq_sdk::presence`).
+pub enum FreeqPresence {
+    Online,
+    Away,
+    Offline,
+}
+
+/// How much of the freeq protocol the server speaks (see
+/// `freeq_sdk::profile`), so the app can hide what won't work.
+pub enum FreeqServerProfile {
+    FreeqFull,
+    Ircv3Basic,
+    Legacy,
+}
+
+/// Why a message highlights (see `freeq_sdk::highlight`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FreeqHighlight {
+    DirectMessage,
+    Mention,
+    Keyword,
+}
+
+impl FreeqHighlight {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            FreeqHighlight::DirectMessage => "dm",
+            FreeqHighlight::Mention => "mention",
+            FreeqHighlight::Keyword => "keyword",
+        }
+    }
+
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        match s {
+            "dm" => Some(FreeqHighlight::DirectMessage),
+            "mention" => Some(FreeqHighlight::Mention),
+            "keyword" => Some(FreeqHighlight::Keyword),
+            _ => None,
+        }
+    }
+}
+
+impl From<freeq_sdk::highlight::Highlight> for FreeqHighlight {
+    fn from(highlight: freeq_sdk::highlight::Highlight) -> Self {
+        use freeq_sdk::highlight::Highlight;
+        match highlight {
+            Highlight::DirectMessage => FreeqHighlight::DirectMessage,
+            Highlight::Mention => FreeqHighlight::Mention,
+            Highlight::Keyword(_) => FreeqHighlight::Keyword,
+        }
+    }
+}
+
+impl From<freeq_sdk::presence::PresenceState> for FreeqPresence {
+    fn from(state: freeq_sdk::presence::PresenceState) -> Self {
+        use freeq_sdk::presence::PresenceState;
+        match state {
+            PresenceState::Online => FreeqPresence::Online,
+            PresenceState::Away => FreeqPresence::Away,
+            PresenceState::Offline => FreeqPresence::Offline,
+        }
+    }
+}
+
+impl From<freeq_sdk::profile::ServerProfile> for FreeqServerProfile {
+    fn from(profile: freeq_sdk::profile::ServerProfile) -> Self {
+        use freeq_sdk::profile::ServerProfile;
+        match profile {
+            ServerProfile::FreeqFull => FreeqServerProfile::FreeqFull,
+            ServerProfile::Ircv3Basic => FreeqServerProfile::Ircv3Basic,
+            ServerProfile::Legacy => FreeqServerProfile::Legacy,
+        }
+    }
+}
+
 pub enum FreeqEvent {
     Connected,
     Registered {
         nick: String,
     },
+    /// Our effective nick, on registration and whenever it changes.
+    NickAssigned {
+        nick: String,
+    },
     Authenticated {
         did: String,
     },
     AuthFailed {
         reason: String,
     },
+    /// Capabilities in effect after negotiation, refused by the server,
+    /// or asked for but not offered.
+    CapabilitiesAcked {
+        acked: Vec<String>,
+        rejected: Vec<String>,
+        unavailable: Vec<String>,
+    },
     Joined {
         channel: String,
         nick: String,
@@ -153,6 +337,15 @@ pub enum FreeqEvent {
     TagMsg {
         msg: TagMessage,
     },
+    /// Follows the reply's `Message` (see `freeq_sdk::event::Event::ThreadReply`).
+    ThreadReply {
+        from_nick: String,
+        target: String,
+        text: String,
+        msgid: String,
+        parent_msgid: String,
+        root_msgid: String,
+    },
     Names {
         channel: String,
         members: Vec<IrcMember>,
@@ -193,12 +386,37 @@ pub enum FreeqEvent {
         nick: String,
         info: String,
     },
+    PresenceChanged {
+        did_or_nick: String,
+        state: FreeqPresence,
+    },
+    /// Roster deltas, so a member list can be patched instead of rebuilt
+    /// from `Names`.
+    MemberAdded {
+        channel: String,
+        member: RosterMember,
+    },
+    MemberRemoved {
+        channel: String,
+        nick: String,
+    },
+    MemberChanged {
+        channel: String,
+        member: RosterMember,
+    },
     Notice {
         text: String,
     },
     Disconnected {
         reason: String,
     },
+    /// The encrypted DM session with `did` was replaced with a fresh one
+    /// (see `FreeqE2ee::enable_session_reset`); older messages from it may
+    /// no longer decrypt.
+    SessionReset {
+        did: String,
+        reason: String,
+    },
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -211,8 +429,34 @@ pub enum FreeqError {
     SendFailed,
     #[error("Invalid argument")]
     InvalidArgument,
+    #[error("Timed out")]
+    Timeout,
+    #[error("Cancelled")]
+    Cancelled,
+    #[error("Refused by server")]
+    Refused,
+    #[error("Not supported by the server")]
+    Unsupported,
 }
 
+impl From<freeq_sdk::pending::CallError> for FreeqError {
+    fn from(err: freeq_sdk::pending::CallError) -> Self {
+        use freeq_sdk::pending::CallError;
+        match err {
+            CallError::Timeout(_) => FreeqError::Timeout,
+            CallError::Cancelled => FreeqError::Cancelled,
+            CallError::Rejected { .. } => FreeqError::Refused,
+            CallError::Disconnected => FreeqError::NotConnected,
+            CallError::Unsupported(_) => FreeqError::Unsupported,
+        }
+    }
+}
+
+/// An SDK call that waits for the server, as run by `FreeqClient::wait_for`.
+type PendingCall<T = ()> = std::pin::Pin<
+    Box<dyn std::future::Future<Output = Result<T, freeq_sdk::pending::CallError>> + Send>,
+>;
+
 pub trait EventHandler: Send + Sync + 'static {
     fn on_event(&self, event: FreeqEvent);
 }
@@ -231,6 +475,22 @@ pub struct FreeqClient {
     /// transport over raw TCP — used by iOS so it can reach the server on
     /// networks that block port 6667.
     websocket_url: Arc<Mutex<Option<String>>>,
+    tls_options: Arc<Mutex<freeq_sdk::tls::TlsOptions>>,
+    nick_fallback: Arc<Mutex<freeq_sdk::client::NickFallback>>,
+    capabilities: Arc<Mutex<freeq_sdk::capabilities::Capabilities>>,
+    /// Cancellation tokens of `*_and_wait` calls in flight, by the
+    /// caller-chosen operation id.
+    operations: Arc<Mutex<HashMap<u64, CancellationToken>>>,
+    /// Where durable events go while `suspended`; see `event_spool`.
+    spool: Arc<Mutex<Option<EventSpool>>>,
+    suspended: Arc<Mutex<bool>>,
+    /// Low-power mode, applied to every connection; see `set_low_power`.
+    low_power: Arc<Mutex<bool>>,
+    /// Whose sessions the decrypt interceptor uses and whose session
+    /// resets the event pump drives; see `enable_auto_decrypt`.
+    auto_decrypt: Arc<Mutex<Option<Arc<FreeqE2ee>>>>,
+    /// Keywords that highlight messages; see `set_highlight_keywords`.
+    highlighter: Arc<Mutex<Highlighter>>,
 }
 
 impl FreeqClient {
@@ -248,6 +508,15 @@ impl FreeqClient {
             web_token: Arc::new(Mutex::new(None)),
             platform: Arc::new(Mutex::new("freeq ios".to_string())),
             websocket_url: Arc::new(Mutex::new(None)),
+            tls_options: Arc::new(Mutex::new(Default::default())),
+            nick_fallback: Arc::new(Mutex::new(Default::default())),
+            capabilities: Arc::new(Mutex::new(Default::default())),
+            operations: Arc::new(Mutex::new(HashMap::new())),
+            spool: Arc::new(Mutex::new(None)),
+            suspended: Arc::new(Mutex::new(false)),
+            low_power: Arc::new(Mutex::new(false)),
+            auto_decrypt: Arc::new(Mutex::new(None)),
+            highlighter: Arc::new(Mutex::new(Highlighter::default())),
         })
     }
 
@@ -276,6 +545,69 @@ impl FreeqClient {
         Ok(())
     }
 
+    /// Custom roots, SPKI pins and ALPN for the next `connect()`. Fails
+    /// with `InvalidArgument` if a pin or certificate doesn't parse. A pin
+    /// mismatch or expired certificate at connect time arrives as a
+    /// `Disconnected` event whose reason names the failure.
+    pub fn set_tls_options(&self, options: TlsConfig) -> Result<(), FreeqError> {
+        let options = freeq_sdk::tls::TlsOptions {
+            spki_pins: options.spki_pins,
+            root_certs_pem: options.root_certs_pem,
+            alpn: options.alpn,
+        };
+        options.validate().map_err(|e| {
+            tracing::warn!("[FFI] set_tls_options: {e}");
+            FreeqError::InvalidArgument
+        })?;
+        *self.tls_options.lock().unwrap() = options;
+        Ok(())
+    }
+
+    /// Alternatives and regain behaviour for when the nick is taken, used
+    /// from the next `connect()`. Fails with `InvalidArgument` if an
+    /// alternative isn't a valid nick.
+    pub fn set_nick_fallback(&self, config: NickFallbackConfig) -> Result<(), FreeqError> {
+        use freeq_sdk::client::NickSuffix;
+        let fallback = freeq_sdk::client::NickFallback {
+            alternatives: config.alternatives,
+            suffix: match config.suffix {
+                NickSuffixStyle::Numbered => NickSuffix::Numbered,
+                NickSuffixStyle::Underscore => NickSuffix::Underscore,
+                NickSuffixStyle::Off => NickSuffix::None,
+            },
+            regain: config.regain,
+        };
+        fallback.validate().map_err(|e| {
+            tracing::warn!("[FFI] set_nick_fallback: {e}");
+            FreeqError::InvalidArgument
+        })?;
+        *self.nick_fallback.lock().unwrap() = fallback;
+        Ok(())
+    }
+
+    /// Capabilities to request from the next `connect()`: the SDK's
+    /// defaults plus `add`, minus `remove`. Fails with `InvalidArgument`
+    /// if a name isn't a valid capability or is one the SDK negotiates
+    /// itself (`sasl`).
+    pub fn set_capabilities(&self, add: Vec<String>, remove: Vec<String>) -> Result<(), FreeqError> {
+        use freeq_sdk::capabilities::{Capabilities, Capability};
+        let parse = |name: &String| {
+            name.parse::<Capability>().map_err(|e| {
+                tracing::warn!("[FFI] set_capabilities: {e}");
+                FreeqError::InvalidArgument
+            })
+        };
+        let mut capabilities = Capabilities::default();
+        for name in &remove {
+            capabilities.remove(&parse(name)?);
+        }
+        for name in &add {
+            capabilities.add(parse(name)?);
+        }
+        *self.capabilities.lock().unwrap() = capabilities;
+        Ok(())
+    }
+
     pub fn connect(&self) -> Result<(), FreeqError> {
         let nick = self.nick.lock().unwrap().clone();
         let web_token = self.web_token.lock().unwrap().take();
@@ -293,8 +625,11 @@ impl FreeqClient {
             realname: self.platform.lock().unwrap().clone(),
             tls: self.server.contains(":6697") || self.server.contains(":443"),
             tls_insecure: false,
+            tls_options: self.tls_options.lock().unwrap().clone(),
             web_token,
             websocket_url,
+            nick_fallback: self.nick_fallback.lock().unwrap().clone(),
+            capabilities: self.capabilities.lock().unwrap().clone(),
         };
 
         // MUST call connect() inside the runtime — it uses tokio::spawn internally.
@@ -302,25 +637,65 @@ impl FreeqClient {
         let connected_store = self.connected.clone();
         let handler = self.handler.clone();
         let nick_state = self.nick.clone();
+        let spool = self.spool.clone();
+        let suspended = self.suspended.clone();
+        let low_power = self.low_power.clone();
+        let auto_decrypt = self.auto_decrypt.lock().unwrap().clone();
+        let highlighter = self.highlighter.clone();
 
         // Use a std::thread to avoid blocking the main thread (UniFFI calls from Swift main thread).
         // The thread enters the tokio runtime, calls connect, then pumps events.
         std::thread::spawn(move || {
             RUNTIME.block_on(async move {
                 let (client_handle, mut event_rx) = freeq_sdk::client::connect(config, None);
+                let resets = auto_decrypt.map(|e2ee| {
+                    client_handle.add_interceptor(Box::new(Decrypt::new(e2ee.sessions.clone())));
+                    e2ee.resets.clone()
+                });
+                client_handle.set_low_power(*low_power.lock().unwrap());
 
-                *handle_store.lock().unwrap() = Some(client_handle);
+                *handle_store.lock().unwrap() = Some(client_handle.clone());
                 *connected_store.lock().unwrap() = true;
 
                 // Pump events
                 while let Some(event) = event_rx.recv().await {
-                    let ffi_event = convert_event(&event);
+                    if let Some(resets) = &resets {
+                        if let Some(resets) = resets.lock().await.as_mut() {
+                            match resets.handle_event(&event, &client_handle).await {
+                                Ok(Some(reset)) => handler.on_event(FreeqEvent::SessionReset {
+                                    did: reset.did,
+                                    reason: reset.reason,
+                                }),
+                                Ok(None) => {}
+                                Err(e) => tracing::warn!("[FFI] E2EE session reset failed: {e}"),
+                            }
+                        }
+                    }
+                    let mut ffi_event = convert_event(&event);
+                    if let FreeqEvent::Message { ref mut msg } = ffi_event {
+                        if msg.batch_id.is_none() {
+                            let own_nick = nick_state.lock().unwrap().clone();
+                            msg.highlight = highlighter
+                                .lock()
+                                .unwrap()
+                                .check(&own_nick, &msg.from_nick, &msg.target, &msg.text)
+                                .map(FreeqHighlight::from);
+                        }
+                    }
                     if let FreeqEvent::Disconnected { .. } = &ffi_event {
                         *connected_store.lock().unwrap() = false;
                     }
-                    if let FreeqEvent::Registered { ref nick } = &ffi_event {
+                    if let FreeqEvent::Registered { ref nick } | FreeqEvent::NickAssigned { ref nick } = &ffi_event {
                         *nick_state.lock().unwrap() = nick.clone();
                     }
+                    if *suspended.lock().unwrap() && EventSpool::is_durable(&ffi_event) {
+                        if let Some(spool) = spool.lock().unwrap().as_mut() {
+                            match spool.push(&ffi_event) {
+                                Ok(()) => continue,
+                                Err(e) => tracing::warn!("[FFI] event spool write failed: {e}"),
+                            }
+                        }
+                    }
                     handler.on_event(ffi_event);
                 }
             });
@@ -359,6 +734,105 @@ impl FreeqClient {
         rx.recv().map_err(|_| FreeqError::SendFailed)?
     }
 
+    /// Join and block until the server confirms or refuses, at most
+    /// `timeout_ms`. Call off the main thread; `cancel_operation` with the
+    /// same `operation_id` abandons the wait with `Cancelled`.
+    pub fn join_and_wait(
+        &self,
+        channel: String,
+        timeout_ms: u32,
+        operation_id: u64,
+    ) -> Result<(), FreeqError> {
+        self.wait_for(timeout_ms, operation_id, move |handle, options| {
+            Box::pin(async move { handle.join_with(&channel, options).await })
+        })
+    }
+
+    /// Request the latest `count` messages of `target` and block until the
+    /// history batch has been delivered as events, at most `timeout_ms`.
+    pub fn history_latest_and_wait(
+        &self,
+        target: String,
+        count: u32,
+        timeout_ms: u32,
+        operation_id: u64,
+    ) -> Result<(), FreeqError> {
+        self.wait_for(timeout_ms, operation_id, move |handle, options| {
+            Box::pin(async move {
+                handle
+                    .history_latest_with(&target, count as usize, options)
+                    .await
+            })
+        })
+    }
+
+    /// The server's channels matching `filter`, busiest first, for a
+    /// directory screen. Served from a short-lived cache unless `refresh`;
+    /// otherwise blocks for the LIST reply, at most `timeout_ms`.
+    /// `cancel_operation` with `operation_id` abandons it.
+    pub fn list_channels(
+        &self,
+        filter: ChannelListFilter,
+        refresh: bool,
+        timeout_ms: u32,
+        operation_id: u64,
+    ) -> Result<Vec<ChannelListEntry>, FreeqError> {
+        let filter = freeq_sdk::directory::ListFilter::from(filter);
+        self.wait_for(timeout_ms, operation_id, move |handle, options| {
+            Box::pin(async move {
+                let listings = if refresh {
+                    handle.refresh_channel_list(&filter, options).await
+                } else {
+                    handle.list_channels(&filter, options).await
+                };
+                listings.map(|l| {
+                    l.into_iter()
+                        .map(ChannelListEntry::from)
+                        .collect::<Vec<_>>()
+                })
+            })
+        })
+    }
+
+    /// Abandon the `*_and_wait` call started with `operation_id`, e.g. when
+    /// the user navigates away. Unknown or finished ids are ignored.
+    pub fn cancel_operation(&self, operation_id: u64) {
+        if let Some(token) = self.operations.lock().unwrap().remove(&operation_id) {
+            token.cancel();
+        }
+    }
+
+    /// Run a waiting SDK call on the runtime and block for its result.
+    fn wait_for<T, F>(&self, timeout_ms: u32, operation_id: u64, call: F) -> Result<T, FreeqError>
+    where
+        T: Send + 'static,
+        F: FnOnce(freeq_sdk::client::ClientHandle, CallOptions) -> PendingCall<T> + Send + 'static,
+    {
+        let handle = self
+            .handle
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or(FreeqError::NotConnected)?;
+        let token = CancellationToken::new();
+        self.operations
+            .lock()
+            .unwrap()
+            .insert(operation_id, token.clone());
+        let timeout = std::time::Duration::from_millis(timeout_ms.into());
+        let options = CallOptions::timeout(timeout).cancel_with(token);
+        let (tx, rx) = std::sync::mpsc::channel();
+        RUNTIME.spawn(async move {
+            let _ = tx.send(call(handle, options).await);
+        });
+        let result = rx.recv().map_err(|_| FreeqError::SendFailed)?;
+        self.operations.lock().unwrap().remove(&operation_id);
+        result.map_err(|e| {
+            tracing::debug!("[FFI] operation {operation_id} failed: {e}");
+            FreeqError::from(e)
+        })
+    }
+
     pub fn part(&self, channel: String) -> Result<(), FreeqError> {
         let handle = self
             .handle
@@ -395,6 +869,30 @@ impl FreeqClient {
         rx.recv().map_err(|_| FreeqError::SendFailed)?
     }
 
+    /// Send `markdown` to `target` with bold, italic, inline code and
+    /// links converted to IRC formatting.
+    pub fn send_formatted_message(
+        &self,
+        target: String,
+        markdown: String,
+    ) -> Result<(), FreeqError> {
+        let handle = self
+            .handle
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or(FreeqError::NotConnected)?;
+        let (tx, rx) = std::sync::mpsc::channel();
+        RUNTIME.spawn(async move {
+            let result = handle
+                .privmsg_formatted(&target, &markdown)
+                .await
+                .map_err(|_| FreeqError::SendFailed);
+            let _ = tx.send(result);
+        });
+        rx.recv().map_err(|_| FreeqError::SendFailed)?
+    }
+
     pub fn send_raw(&self, line: String) -> Result<(), FreeqError> {
         tracing::debug!("[FFI] send_raw called: {}", &line);
         let handle = self
@@ -436,13 +934,116 @@ impl FreeqClient {
         self.send_raw(format!("NICK {new_nick}"))
     }
 
+    /// Request the whole thread `msgid` belongs to (CHATHISTORY THREAD).
+    pub fn fetch_thread(&self, target: String, msgid: String) -> Result<(), FreeqError> {
+        self.send_raw(format!("CHATHISTORY THREAD {target} msgid={msgid}"))
+    }
+
     pub fn is_connected(&self) -> bool {
         *self.connected.lock().unwrap()
     }
 
+    /// Decrypt ENC3 messages with `e2ee`'s sessions before they reach
+    /// `on_event`, so `Message` events carry plaintext and `encrypted`,
+    /// and reset sessions that stop decrypting once
+    /// `FreeqE2ee::enable_session_reset` is called. Takes effect at the
+    /// next `connect`.
+    pub fn enable_auto_decrypt(&self, e2ee: Arc<FreeqE2ee>) {
+        *self.auto_decrypt.lock().unwrap() = Some(e2ee);
+    }
+
+    /// Open the suspension spool at `path` (a file in the app container).
+    /// Events spooled by a previous process stay pending until drained.
+    /// The spool holds decrypted message text; on iOS, put it in a
+    /// directory with complete-until-first-unlock data protection, which
+    /// the files written here inherit.
+    pub fn enable_event_spool(&self, path: String) -> Result<(), FreeqError> {
+        let spool = EventSpool::open(&path).map_err(|e| {
+            tracing::warn!("[FFI] cannot open event spool {path}: {e}");
+            FreeqError::InvalidArgument
+        })?;
+        *self.spool.lock().unwrap() = Some(spool);
+        Ok(())
+    }
+
+    /// Mark the app suspended (or resumed). While suspended, `Message` and
+    /// `Disconnected` events are written to the spool instead of being
+    /// passed to `on_event`. Has no effect until `enable_event_spool`.
+    pub fn set_suspended(&self, suspended: bool) {
+        *self.suspended.lock().unwrap() = suspended;
+    }
+
+    /// Switch low-power mode: sparse keepalives, no typing or away
+    /// relays, events delivered in batches and lookups deferred (see
+    /// `freeq_sdk::power`). Tie it to the OS battery or data-saver
+    /// state; it carries over to later connections.
+    pub fn set_low_power(&self, enabled: bool) {
+        *self.low_power.lock().unwrap() = enabled;
+        if let Some(handle) = self.handle.lock().unwrap().as_ref() {
+            handle.set_low_power(enabled);
+        }
+    }
+
+    /// Words besides our nick that flag a message's `highlight`, matched
+    /// case-insensitively as whole words. Replaces the previous list and
+    /// applies to messages from now on.
+    pub fn set_highlight_keywords(&self, keywords: Vec<String>) {
+        *self.highlighter.lock().unwrap() = Highlighter::new(keywords);
+    }
+
+    /// Events spooled while suspended, oldest first, emptying the spool;
+    /// call after `set_suspended(false)` so nothing lands behind the drain.
+    pub fn drain_pending_events(&self) -> Vec<FreeqEvent> {
+        let mut spool = self.spool.lock().unwrap();
+        let Some(spool) = spool.as_mut() else {
+            return Vec::new();
+        };
+        spool.drain().unwrap_or_else(|e| {
+            tracing::warn!("[FFI] event spool drain failed: {e}");
+            Vec::new()
+        })
+    }
+
+    /// The SDK's connection timeline (DNS, TCP, TLS, registration and
+    /// disconnects, with timings) as JSON, for attaching to a support
+    /// report. It covers every connection this process has made and holds
+    /// no message content.
+    pub fn export_diagnostics(&self) -> String {
+        freeq_sdk::diagnostics::export_diagnostics()
+    }
+
     pub fn current_nick(&self) -> Option<String> {
         Some(self.nick.lock().unwrap().clone())
     }
+
+    /// Aggregated presence of a contact, by DID or nick. `Offline` when
+    /// not connected.
+    pub fn presence(&self, did_or_nick: String) -> FreeqPresence {
+        match self.handle.lock().unwrap().as_ref() {
+            Some(handle) => handle.presence(&did_or_nick).into(),
+            None => FreeqPresence::Offline,
+        }
+    }
+
+    /// What the server supports; `None` until registered.
+    pub fn server_profile(&self) -> Option<FreeqServerProfile> {
+        self.handle
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|handle| handle.server_profile())
+            .map(Into::into)
+    }
+
+    /// Whether history can be fetched; `false` once registration has
+    /// shown the server has no chat history.
+    pub fn has_history(&self) -> bool {
+        self.handle
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|handle| handle.has_history())
+    }
 }
 
 // ── Event conversion ──
@@ -452,10 +1053,20 @@ fn convert_event(event: &freeq_sdk::event::Event) -> FreeqEvent {
     match event {
         Event::Connected => FreeqEvent::Connected,
         Event::Registered { nick } => FreeqEvent::Registered { nick: nick.clone() },
+        Event::NickAssigned { nick } => FreeqEvent::NickAssigned { nick: nick.clone() },
         Event::Authenticated { did } => FreeqEvent::Authenticated { did: did.clone() },
         Event::AuthFailed { reason } => FreeqEvent::AuthFailed {
             reason: reason.clone(),
         },
+        Event::CapabilitiesAcked {
+            acked,
+            rejected,
+            unavailable,
+        } => FreeqEvent::CapabilitiesAcked {
+            acked: acked.clone(),
+            rejected: rejected.clone(),
+            unavailable: unavailable.clone(),
+        },
         Event::Joined { channel, nick, .. } => FreeqEvent::Joined {
             channel: channel.clone(),
             nick: nick.clone(),
@@ -469,14 +1080,16 @@ fn convert_event(event: &freeq_sdk::event::Event) -> FreeqEvent {
             target,
             text,
             tags,
+            formatted_text,
+            encrypted,
         } => {
-            let msgid = tags.get("msgid").cloned();
-            let reply_to = tags.get("+reply").cloned();
-            let replaces_msgid = tags.get("+draft/edit").cloned();
-            let edit_of = tags.get("+draft/edit").cloned();
-            let batch_id = tags.get("batch").cloned();
-            let pin_msgid = tags.get("+freeq.at/pin").cloned();
-            let unpin_msgid = tags.get("+freeq.at/unpin").cloned();
+            let msgid = tags.get(tag::MSGID).cloned();
+            let reply_to = tags.get(tag::REPLY).cloned();
+            let replaces_msgid = tags.get(tag::EDIT).cloned();
+            let edit_of = tags.get(tag::EDIT).cloned();
+            let batch_id = tags.get(tag::BATCH).cloned();
+            let pin_msgid = tags.get(tag::PIN).cloned();
+            let unpin_msgid = tags.get(tag::UNPIN).cloned();
             let is_action = text.starts_with("\x01ACTION ") && text.ends_with('\x01');
             let clean_text = if is_action {
                 text.trim_start_matches("\x01ACTION ")
@@ -485,13 +1098,20 @@ fn convert_event(event: &freeq_sdk::event::Event) -> FreeqEvent {
             } else {
                 text.clone()
             };
+            // The SDK renders the whole line; an action's markdown is
+            // rendered from the body alone, like `text`.
+            let formatted_text = if is_action {
+                freeq_sdk::format::formatted_text(&clean_text)
+            } else {
+                formatted_text.clone()
+            };
             let ts = tags
-                .get("time")
+                .get(tag::TIME)
                 .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
                 .map(|dt: chrono::DateTime<chrono::FixedOffset>| dt.timestamp_millis())
                 .unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
             let reactions = tags
-                .get("+freeq.at/reactions")
+                .get(tag::REACTIONS)
                 .map(|raw| parse_reactions_tag(raw))
                 .unwrap_or_default();
             FreeqEvent::Message {
@@ -507,11 +1127,14 @@ fn convert_event(event: &freeq_sdk::event::Event) -> FreeqEvent {
                     pin_msgid,
                     unpin_msgid,
                     is_action,
-                    is_signed: tags.contains_key("+freeq.at/sig"),
+                    is_signed: tags.contains_key(tag::SIG),
                     timestamp_ms: ts,
-                    account: tags.get("account").cloned(),
-                    origin: tags.get("+freeq.at/origin").cloned(),
+                    account: tags.get(tag::ACCOUNT).cloned(),
+                    origin: tags.get(tag::ORIGIN).cloned(),
                     reactions,
+                    formatted_text,
+                    encrypted: *encrypted,
+                    highlight: None,
                 },
             }
         }
@@ -635,6 +1258,37 @@ fn convert_event(event: &freeq_sdk::event::Event) -> FreeqEvent {
             nick: nick.clone(),
             info: info.clone(),
         },
+        Event::ThreadReply {
+            from,
+            target,
+            text,
+            msgid,
+            parent_msgid,
+            root_msgid,
+        } => FreeqEvent::ThreadReply {
+            from_nick: from.clone(),
+            target: target.clone(),
+            text: text.clone(),
+            msgid: msgid.clone(),
+            parent_msgid: parent_msgid.clone(),
+            root_msgid: root_msgid.clone(),
+        },
+        Event::PresenceChanged { did_or_nick, state } => FreeqEvent::PresenceChanged {
+            did_or_nick: did_or_nick.clone(),
+            state: (*state).into(),
+        },
+        Event::MemberAdded { channel, member } => FreeqEvent::MemberAdded {
+            channel: channel.clone(),
+            member: member.into(),
+        },
+        Event::MemberRemoved { channel, nick } => FreeqEvent::MemberRemoved {
+            channel: channel.clone(),
+            nick: nick.clone(),
+        },
+        Event::MemberChanged { channel, member } => FreeqEvent::MemberChanged {
+            channel: channel.clone(),
+            member: member.into(),
+        },
         Event::RawLine(_) => FreeqEvent::Notice {
             text: String::new(),
         },
@@ -643,16 +1297,22 @@ fn convert_event(event: &freeq_sdk::event::Event) -> FreeqEvent {
 
 // ── E2EE Manager ───────────────────────────────────────────────────
 
+use freeq_sdk::decrypt::{Decrypt, SessionStore};
 use freeq_sdk::ratchet::{self, Session as RatchetSession};
+use freeq_sdk::session_reset::{LocalKeys, PeerKeys, SessionResets};
 use std::collections::HashMap;
 
 /// E2EE manager for iOS — wraps Rust Double Ratchet sessions.
 pub struct FreeqE2ee {
-    sessions: Mutex<HashMap<String, RatchetSession>>,
+    /// Shared with the client's decrypt interceptor once
+    /// `FreeqClient::enable_auto_decrypt` is called.
+    sessions: SessionStore,
     identity_secret: Mutex<Option<[u8; 32]>>,
     identity_public: Mutex<Option<[u8; 32]>>,
     spk_secret: Mutex<Option<[u8; 32]>>,
     spk_public: Mutex<Option<[u8; 32]>>,
+    /// Set by `enable_session_reset`; driven by the client's event pump.
+    resets: Arc<tokio::sync::Mutex<Option<SessionResets>>>,
 }
 
 /// Pre-key bundle for uploading to the server.
@@ -671,11 +1331,12 @@ pub struct SafetyNumber {
 impl FreeqE2ee {
     fn new() -> Self {
         Self {
-            sessions: Mutex::new(HashMap::new()),
+            sessions: SessionStore::new(),
             identity_secret: Mutex::new(None),
             identity_public: Mutex::new(None),
             spk_secret: Mutex::new(None),
             spk_public: Mutex::new(None),
+            resets: Arc::new(tokio::sync::Mutex::new(None)),
         }
     }
 
@@ -828,35 +1489,29 @@ impl FreeqE2ee {
             RatchetSession::init_bob(shared_secret, my_spk)
         };
 
-        self.sessions.lock().unwrap().insert(remote_did, session);
+        self.sessions.insert_session(&remote_did, session);
         Ok(())
     }
 
     /// Encrypt a message for a remote user. Returns ENC3:... wire format.
     fn encrypt_message(&self, remote_did: String, plaintext: String) -> Result<String, FreeqError> {
-        let mut sessions = self.sessions.lock().unwrap();
-        let session = sessions
-            .get_mut(&remote_did)
-            .ok_or(FreeqError::NotConnected)?;
-        session
-            .encrypt(&plaintext)
+        self.sessions
+            .with_session(&remote_did, |session| session.encrypt(&plaintext))
+            .ok_or(FreeqError::NotConnected)?
             .map_err(|_| FreeqError::SendFailed)
     }
 
     /// Decrypt a message from a remote user.
     fn decrypt_message(&self, remote_did: String, wire: String) -> Result<String, FreeqError> {
-        let mut sessions = self.sessions.lock().unwrap();
-        let session = sessions
-            .get_mut(&remote_did)
-            .ok_or(FreeqError::NotConnected)?;
-        session
-            .decrypt(&wire)
+        self.sessions
+            .with_session(&remote_did, |session| session.decrypt(&wire))
+            .ok_or(FreeqError::NotConnected)?
             .map_err(|_| FreeqError::InvalidArgument)
     }
 
     /// Check if we have an active session with a user.
     fn has_session(&self, remote_did: String) -> bool {
-        self.sessions.lock().unwrap().contains_key(&remote_did)
+        self.sessions.has_session(&remote_did)
     }
 
     /// Check if a message is encrypted.
@@ -896,18 +1551,110 @@ impl FreeqE2ee {
         })
     }
 
+    /// Reset sessions that keep failing to decrypt, and accept resets
+    /// from peers, as `our_did`. `signing_key_b64` is a 32-byte ed25519
+    /// secret that signs our reset requests; peers verify them with its
+    /// public half (see `trust_peer`). Returns our bundle with the
+    /// signed pre-key signed by that key, to upload in place of the one
+    /// from `generate_keys`/`restore_keys`.
+    fn enable_session_reset(
+        &self,
+        our_did: String,
+        signing_key_b64: String,
+    ) -> Result<PreKeyBundle, FreeqError> {
+        use base64::engine::general_purpose::URL_SAFE_NO_PAD as B64;
+        use base64::Engine;
+        use ed25519_dalek::Signer;
+        use freeq_sdk::x3dh::{IdentityKeyPair, SignedPreKey};
+
+        let signing_bytes: [u8; 32] = B64
+            .decode(&signing_key_b64)
+            .map_err(|_| FreeqError::InvalidArgument)?
+            .try_into()
+            .map_err(|_| FreeqError::InvalidArgument)?;
+        let signing_key = ed25519_dalek::SigningKey::from_bytes(&signing_bytes);
+        let ik = self
+            .identity_secret
+            .lock()
+            .unwrap()
+            .ok_or(FreeqError::NotConnected)?;
+        let spk = self
+            .spk_secret
+            .lock()
+            .unwrap()
+            .ok_or(FreeqError::NotConnected)?;
+        let identity = IdentityKeyPair::from_secret(ik);
+        let spk_public = x25519_dalek::PublicKey::from(&x25519_dalek::StaticSecret::from(spk));
+        let signature = signing_key.sign(spk_public.as_bytes()).to_bytes().to_vec();
+        let spk = SignedPreKey::from_parts(1, spk, signature);
+
+        let bundle = PreKeyBundle {
+            identity_key: B64.encode(identity.public.as_bytes()),
+            signed_pre_key: B64.encode(spk.public.as_bytes()),
+            spk_signature: B64.encode(&spk.signature),
+            spk_id: spk.id,
+        };
+        let local = LocalKeys {
+            did: our_did,
+            identity,
+            spk,
+            signing_key,
+        };
+        *self.resets.blocking_lock() = Some(SessionResets::new(local, self.sessions.clone()));
+        Ok(bundle)
+    }
+
+    /// Trust `remote_did`'s published bundle and reset-signing key
+    /// (base64url ed25519 public key) for session resets. Call again
+    /// whenever a fresh bundle is fetched. Needs `enable_session_reset`.
+    fn trust_peer(
+        &self,
+        remote_did: String,
+        bundle: PreKeyBundle,
+        verify_key_b64: String,
+    ) -> Result<(), FreeqError> {
+        use base64::engine::general_purpose::URL_SAFE_NO_PAD as B64;
+        use base64::Engine;
+
+        let verify_bytes: [u8; 32] = B64
+            .decode(&verify_key_b64)
+            .map_err(|_| FreeqError::InvalidArgument)?
+            .try_into()
+            .map_err(|_| FreeqError::InvalidArgument)?;
+        let verify_key = ed25519_dalek::VerifyingKey::from_bytes(&verify_bytes)
+            .map_err(|_| FreeqError::InvalidArgument)?;
+        let keys = PeerKeys {
+            bundle: freeq_sdk::x3dh::PreKeyBundle {
+                did: remote_did.clone(),
+                identity_key: bundle.identity_key,
+                signed_pre_key: bundle.signed_pre_key,
+                spk_signature: bundle.spk_signature,
+                spk_id: bundle.spk_id,
+            },
+            verify_key,
+        };
+        self.resets
+            .blocking_lock()
+            .as_mut()
+            .ok_or(FreeqError::NotConnected)?
+            .trust(&remote_did, keys);
+        Ok(())
+    }
+
     /// Serialize a session state for persistence.
     fn export_session(&self, remote_did: String) -> Result<String, FreeqError> {
-        let sessions = self.sessions.lock().unwrap();
-        let session = sessions.get(&remote_did).ok_or(FreeqError::NotConnected)?;
-        serde_json::to_string(session).map_err(|_| FreeqError::SendFailed)
+        let session = self
+            .sessions
+            .session(&remote_did)
+            .ok_or(FreeqError::NotConnected)?;
+        serde_json::to_string(&session).map_err(|_| FreeqError::SendFailed)
     }
 
     /// Restore a session from serialized state.
     fn import_session(&self, remote_did: String, json: String) -> Result<(), FreeqError> {
         let session: RatchetSession =
             serde_json::from_str(&json).map_err(|_| FreeqError::InvalidArgument)?;
-        self.sessions.lock().unwrap().insert(remote_did, session);
+        self.sessions.insert_session(&remote_did, session);
         Ok(())
     }
 }
@@ -1837,6 +2584,8 @@ mod tests {
             target: "#naptest".to_string(),
             text: "hi".to_string(),
             tags,
+            formatted_text: None,
+            encrypted: false,
         };
         let out = convert_event(&ev);
         let FreeqEvent::Message { msg } = out else {
@@ -1862,6 +2611,8 @@ mod tests {
             target: "#x".to_string(),
             text: "no reactions here".to_string(),
             tags,
+            formatted_text: None,
+            encrypted: false,
         };
         let out = convert_event(&ev);
         let FreeqEvent::Message { msg } = out else {
@@ -1869,4 +2620,22 @@ mod tests {
         };
         assert!(msg.reactions.is_empty());
     }
+
+    #[test]
+    fn convert_event_action_formatted_text_drops_ctcp_wrapper() {
+        let text = "\x01ACTION waves \x02hard\x02\x01".to_string();
+        let ev = freeq_sdk::event::Event::Message {
+            from: "alice".to_string(),
+            target: "#x".to_string(),
+            formatted_text: freeq_sdk::format::formatted_text(&text),
+            text,
+            tags: std::collections::HashMap::new(),
+            encrypted: false,
+        };
+        let FreeqEvent::Message { msg } = convert_event(&ev) else {
+            panic!("expected Message variant");
+        };
+        assert!(msg.is_action);
+        assert_eq!(msg.formatted_text.as_deref(), Some("waves **hard**"));
+    }
 }
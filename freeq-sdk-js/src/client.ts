@@ -506,9 +506,9 @@ export class FreeqClient extends EventEmitter {
 
   // ── Channel management ──
 
-  /** Join a channel. */
-  join(channel: string): void {
-    this.raw(`JOIN ${channel}`);
+  /** Join a channel, with its key or an invite token if given. */
+  join(channel: string, key?: string): void {
+    this.raw(key ? `JOIN ${channel} ${key}` : `JOIN ${channel}`);
   }
 
   /** Leave a channel. */
@@ -1661,12 +1661,13 @@ export class FreeqClient extends EventEmitter {
         const nicks = (msg.params[3] || '').split(' ').filter(Boolean);
         const members: Array<Partial<Member> & { nick: string }> = [];
         for (const n of nicks) {
-          const prefixMatch = n.match(/^([@%+]+)/);
+          const prefixMatch = n.match(/^([~&@%+]+)/);
           const prefixes = prefixMatch ? prefixMatch[1] : '';
           const bare = n.slice(prefixes.length);
           members.push({
             nick: bare,
-            isOp: prefixes.includes('@'),
+            // Founders (~) and admins (&) are always ops.
+            isOp: /[~&@]/.test(prefixes),
             isHalfop: prefixes.includes('%'),
             isVoiced: prefixes.includes('+'),
           });
@@ -39,8 +39,11 @@ async fn main() -> Result<()> {
         realname: "Pi Coding Agent (test)".to_string(),
         tls: args.tls,
         tls_insecure: false,
+        tls_options: Default::default(),
         web_token: None,
         websocket_url: None,
+        nick_fallback: Default::default(),
+        capabilities: Default::default(),
     };
 
     let conn = client::establish_connection(&config).await?;
@@ -57,6 +57,7 @@ async fn wait_owner(
                 target,
                 text,
                 tags,
+                ..
             })) => {
                 if tags.contains_key("batch") {
                     continue;
@@ -162,8 +163,11 @@ async fn main() -> Result<()> {
         realname: "Phase 4 Factory Agent".to_string(),
         tls: args.tls,
         tls_insecure: false,
+        tls_options: Default::default(),
         web_token: None,
         websocket_url: None,
+        nick_fallback: Default::default(),
+        capabilities: Default::default(),
     };
     let conn = client::establish_connection(&config).await?;
     let (handle, mut events) =
@@ -621,6 +625,7 @@ async fn main() -> Result<()> {
                 target,
                 text,
                 tags,
+                ..
             })) => {
                 if tags.contains_key("batch") {
                     continue;
@@ -52,8 +52,11 @@ async fn main() -> Result<()> {
         realname: "Freeq Echo Bot".to_string(),
         tls: args.tls,
         tls_insecure: false,
+        tls_options: Default::default(),
         web_token: None,
         websocket_url: None,
+        nick_fallback: Default::default(),
+        capabilities: Default::default(),
     })
     .await?;
 
@@ -64,8 +67,11 @@ async fn main() -> Result<()> {
         realname: "Freeq Echo Bot".to_string(),
         tls: args.tls,
         tls_insecure: false,
+        tls_options: Default::default(),
         web_token: None,
         websocket_url: None,
+        nick_fallback: Default::default(),
+        capabilities: Default::default(),
     };
 
     // No signer = guest mode (no AT Protocol authentication)
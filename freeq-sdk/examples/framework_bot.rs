@@ -76,8 +76,11 @@ async fn main() -> Result<()> {
         realname: "Freeq Framework Bot".to_string(),
         tls: args.tls,
         tls_insecure: false,
+        tls_options: Default::default(),
         web_token: None,
         websocket_url: None,
+        nick_fallback: Default::default(),
+        capabilities: Default::default(),
     };
 
     let conn = client::establish_connection(&config).await?;
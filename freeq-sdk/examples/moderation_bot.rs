@@ -185,8 +185,11 @@ async fn main() -> Result<()> {
         realname: "freeq moderation bot".to_string(),
         tls: args.tls || args.server.ends_with(":6697"),
         tls_insecure: false,
+        tls_options: Default::default(),
         web_token: None,
         websocket_url: None,
+        nick_fallback: Default::default(),
+        capabilities: Default::default(),
     };
 
     let reconnect = ReconnectConfig {
@@ -232,8 +232,11 @@ async fn main() -> Result<()> {
         realname: "pi remote agent".to_string(),
         tls: args.tls,
         tls_insecure: false,
+        tls_options: Default::default(),
         web_token: None,
         websocket_url: None,
+        nick_fallback: Default::default(),
+        capabilities: Default::default(),
     };
     let conn = client::establish_connection(&config).await?;
     let (handle, mut events) =
@@ -302,6 +305,7 @@ async fn main() -> Result<()> {
                 target,
                 text,
                 tags,
+                ..
             })) => {
                 if tags.contains_key("batch") {
                     continue;
@@ -19,6 +19,11 @@ pub struct Challenge {
     pub session_id: String,
     pub nonce: String,
     pub timestamp: i64,
+    /// Channel binding the server sees on the connection
+    /// (`tls-exporter:…` or `iroh:…`). Its presence tells the client the
+    /// server checks the binding reported in [`ChallengeResponse::binding`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub binding: Option<String>,
 }
 
 /// The response we send back to the server.
@@ -47,6 +52,12 @@ pub struct ChallengeResponse {
     /// the challenge it issued (the PDS itself has no knowledge of our nonce).
     #[serde(skip_serializing_if = "Option::is_none")]
     pub challenge_nonce: Option<String>,
+    /// Channel binding the client observed on its own end of the
+    /// transport. The `crypto` method signs it along with the challenge
+    /// (see [`signed_payload`]), so a response relayed onto another
+    /// connection fails the server's comparison with that connection.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub binding: Option<String>,
 }
 
 /// Decode a base64url-encoded challenge from the server.
@@ -61,6 +72,17 @@ pub fn decode_challenge_bytes(encoded: &str) -> anyhow::Result<Vec<u8>> {
     Ok(URL_SAFE_NO_PAD.decode(encoded)?)
 }
 
+/// The bytes a `crypto` response signs: the raw challenge, followed by a
+/// newline and the client's channel binding when it reports one.
+pub fn signed_payload(challenge_bytes: &[u8], binding: Option<&str>) -> Vec<u8> {
+    let mut payload = challenge_bytes.to_vec();
+    if let Some(binding) = binding {
+        payload.push(b'\n');
+        payload.extend_from_slice(binding.as_bytes());
+    }
+    payload
+}
+
 /// Encode a challenge response as base64url for sending via AUTHENTICATE.
 pub fn encode_response(response: &ChallengeResponse) -> String {
     let json = serde_json::to_vec(response).expect("response serialization");
@@ -75,6 +97,18 @@ pub trait ChallengeSigner: Send + Sync {
     /// Produce the SASL response for the given challenge bytes.
     fn respond(&self, challenge_bytes: &[u8]) -> anyhow::Result<ChallengeResponse>;
 
+    /// Produce the SASL response on a transport whose channel binding, as
+    /// seen from this end, is `binding`. Signers that sign the challenge
+    /// commit to it; the default (token-based methods) ignores it.
+    fn respond_bound(
+        &self,
+        challenge_bytes: &[u8],
+        binding: Option<&str>,
+    ) -> anyhow::Result<ChallengeResponse> {
+        let _ = binding;
+        self.respond(challenge_bytes)
+    }
+
     /// Update the DPoP nonce for PDS OAuth signers. Default is a no-op.
     fn set_dpop_nonce(&self, _nonce: &str) {}
 }
@@ -97,7 +131,16 @@ impl ChallengeSigner for KeySigner {
     }
 
     fn respond(&self, challenge_bytes: &[u8]) -> anyhow::Result<ChallengeResponse> {
-        let signature = self.private_key.sign_base64url(challenge_bytes);
+        self.respond_bound(challenge_bytes, None)
+    }
+
+    fn respond_bound(
+        &self,
+        challenge_bytes: &[u8],
+        binding: Option<&str>,
+    ) -> anyhow::Result<ChallengeResponse> {
+        let payload = signed_payload(challenge_bytes, binding);
+        let signature = self.private_key.sign_base64url(&payload);
         Ok(ChallengeResponse {
             did: self.did.clone(),
             signature,
@@ -105,6 +148,7 @@ impl ChallengeSigner for KeySigner {
             pds_url: None,
             dpop_proof: None,
             challenge_nonce: None, // Not needed: crypto method signs the full challenge
+            binding: binding.map(str::to_string),
         })
     }
 }
@@ -250,6 +294,7 @@ impl ChallengeSigner for PdsSessionSigner {
                 pds_url: Some(self.pds_url.clone()),
                 dpop_proof: Some(dpop_proof),
                 challenge_nonce,
+                binding: None,
             })
         } else {
             // App-password mode: plain Bearer token
@@ -260,6 +305,7 @@ impl ChallengeSigner for PdsSessionSigner {
                 pds_url: Some(self.pds_url.clone()),
                 dpop_proof: None,
                 challenge_nonce,
+                binding: None,
             })
         }
     }
@@ -307,6 +353,30 @@ mod tests {
         public_key.verify(challenge_bytes, &sig_bytes).unwrap();
     }
 
+    #[test]
+    fn key_signer_commits_to_its_binding() {
+        let private_key = PrivateKey::generate_ed25519();
+        let public_key = private_key.public_key();
+        let signer = KeySigner::new("did:plc:test".to_string(), private_key);
+
+        let challenge_bytes = b"test challenge data";
+        let response = signer
+            .respond_bound(challenge_bytes, Some("tls-exporter:AAAA"))
+            .unwrap();
+        assert_eq!(response.binding.as_deref(), Some("tls-exporter:AAAA"));
+        let sig_bytes = URL_SAFE_NO_PAD.decode(&response.signature).unwrap();
+        public_key
+            .verify(
+                &signed_payload(challenge_bytes, Some("tls-exporter:AAAA")),
+                &sig_bytes,
+            )
+            .unwrap();
+        // Dropping or swapping the binding breaks the signature
+        assert!(public_key.verify(challenge_bytes, &sig_bytes).is_err());
+        let other = signed_payload(challenge_bytes, Some("tls-exporter:BBBB"));
+        assert!(public_key.verify(&other, &sig_bytes).is_err());
+    }
+
     #[test]
     fn pds_session_signer_bearer() {
         let signer = PdsSessionSigner::new(
@@ -319,6 +389,7 @@ mod tests {
             session_id: "sess-1".to_string(),
             nonce: "test-nonce-abc".to_string(),
             timestamp: 1000,
+            binding: None,
         };
         let challenge_bytes = serde_json::to_vec(&challenge).unwrap();
         let response = signer.respond(&challenge_bytes).unwrap();
@@ -343,6 +414,7 @@ mod tests {
             session_id: "sess-2".to_string(),
             nonce: "test-nonce-def".to_string(),
             timestamp: 2000,
+            binding: None,
         };
         let challenge_bytes = serde_json::to_vec(&challenge).unwrap();
         let response = signer.respond(&challenge_bytes).unwrap();
@@ -361,6 +433,7 @@ mod tests {
             pds_url: None,
             dpop_proof: None,
             challenge_nonce: None,
+            binding: None,
         };
         let encoded = encode_response(&resp);
         let bytes = URL_SAFE_NO_PAD.decode(&encoded).unwrap();
@@ -379,6 +452,7 @@ mod tests {
             pds_url: Some("https://pds.example.com".to_string()),
             dpop_proof: Some("dpop.proof.jwt".to_string()),
             challenge_nonce: Some("server-nonce-xyz".to_string()),
+            binding: None,
         };
         let encoded = encode_response(&resp);
         let bytes = URL_SAFE_NO_PAD.decode(&encoded).unwrap();
@@ -0,0 +1,307 @@
+//! Which IRCv3 capabilities the client asks for.
+//!
+//! [`ConnectConfig::capabilities`](crate::client::ConnectConfig) starts
+//! from [`Capabilities::default`], the set the SDK's features are built
+//! on, and can drop some (a low-power client turning off `away-notify`)
+//! or add others (a `draft/` capability the app handles itself from
+//! [`Event::RawLine`](crate::event::Event)):
+//!
+//! ```
+//! use freeq_sdk::capabilities::{Capabilities, Capability};
+//!
+//! let caps = Capabilities::default()
+//!     .without(Capability::AwayNotify)
+//!     .with("draft/read-marker".parse().unwrap());
+//! assert!(!caps.contains("away-notify"));
+//! ```
+//!
+//! Only capabilities the server offers in `CAP LS` (or later `CAP NEW`)
+//! are requested; the rest are reported as unavailable in
+//! [`Event::CapabilitiesAcked`](crate::event::Event). Capabilities the
+//! SDK has no variant for go out in a `CAP REQ` of their own, so a server
+//! refusing one of them doesn't cost the client the rest. `sasl` and
+//! `freeq.at/p2p-dm` aren't listed here: they follow from signing in.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::proto::caps;
+
+/// Longest capability name accepted in [`Capability::Other`].
+const MAX_NAME_LEN: usize = 64;
+
+/// One capability the client can request.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Capability {
+    MessageTags,
+    ServerTime,
+    Batch,
+    EchoMessage,
+    AwayNotify,
+    AccountNotify,
+    AccountTag,
+    ExtendedJoin,
+    MultiPrefix,
+    Chathistory,
+    Multiline,
+    Metadata,
+    CapNotify,
+    /// `freeq.at/priority`.
+    Priority,
+    /// `freeq.at/whois-extended`.
+    WhoisExtended,
+    /// `freeq.at/language`.
+    Language,
+    /// Any other capability, by name. Build it with [`str::parse`] so the
+    /// name is checked.
+    Other(String),
+}
+
+/// Why a capability name can't be requested.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum CapabilityError {
+    #[error("capability name {0:?} is not a valid IRCv3 capability name")]
+    InvalidName(String),
+    #[error("capability {0:?} is negotiated by the SDK when signing in")]
+    Managed(String),
+}
+
+impl Capability {
+    /// Every capability with a variant, in request order.
+    const KNOWN: [Capability; 16] = [
+        Self::MessageTags,
+        Self::ServerTime,
+        Self::Batch,
+        Self::EchoMessage,
+        Self::AwayNotify,
+        Self::AccountNotify,
+        Self::AccountTag,
+        Self::ExtendedJoin,
+        Self::MultiPrefix,
+        Self::Chathistory,
+        Self::Multiline,
+        Self::Metadata,
+        Self::CapNotify,
+        Self::Priority,
+        Self::WhoisExtended,
+        Self::Language,
+    ];
+
+    /// The name sent in `CAP REQ`.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::MessageTags => caps::MESSAGE_TAGS,
+            Self::ServerTime => caps::SERVER_TIME,
+            Self::Batch => caps::BATCH,
+            Self::EchoMessage => caps::ECHO_MESSAGE,
+            Self::AwayNotify => caps::AWAY_NOTIFY,
+            Self::AccountNotify => caps::ACCOUNT_NOTIFY,
+            Self::AccountTag => caps::ACCOUNT_TAG,
+            Self::ExtendedJoin => caps::EXTENDED_JOIN,
+            Self::MultiPrefix => caps::MULTI_PREFIX,
+            Self::Chathistory => caps::CHATHISTORY,
+            Self::Multiline => caps::MULTILINE,
+            Self::Metadata => caps::METADATA,
+            Self::CapNotify => caps::CAP_NOTIFY,
+            Self::Priority => caps::PRIORITY,
+            Self::WhoisExtended => caps::WHOIS_EXTENDED,
+            Self::Language => caps::LANGUAGE,
+            Self::Other(name) => name,
+        }
+    }
+
+    /// Whether this is an [`Other`](Self::Other), requested on its own.
+    pub fn is_other(&self) -> bool {
+        matches!(self, Self::Other(_))
+    }
+
+    /// Check a name: lowercase letters, digits and `-./_`, starting with a
+    /// letter or digit, and not one the SDK negotiates itself.
+    fn check(name: &str) -> Result<(), CapabilityError> {
+        let valid = !name.is_empty()
+            && name.len() <= MAX_NAME_LEN
+            && name
+                .bytes()
+                .next()
+                .is_some_and(|b| b.is_ascii_lowercase() || b.is_ascii_digit())
+            && name.bytes().all(|b| {
+                b.is_ascii_lowercase()
+                    || b.is_ascii_digit()
+                    || matches!(b, b'-' | b'.' | b'/' | b'_')
+            });
+        if !valid {
+            return Err(CapabilityError::InvalidName(name.to_string()));
+        }
+        if name == caps::SASL || name == caps::P2P_DM {
+            return Err(CapabilityError::Managed(name.to_string()));
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Capability {
+    type Err = CapabilityError;
+
+    /// A known name gives its variant; any other valid name
+    /// [`Other`](Self::Other).
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let name = name.trim().to_ascii_lowercase();
+        if let Some(known) = Self::KNOWN.iter().find(|c| c.name() == name) {
+            return Ok(known.clone());
+        }
+        Self::check(&name)?;
+        Ok(Self::Other(name))
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The capabilities to request, in order, without duplicates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    requested: Vec<Capability>,
+}
+
+impl Default for Capabilities {
+    /// What the SDK's own features use.
+    fn default() -> Self {
+        Self {
+            requested: vec![
+                Capability::MessageTags,
+                Capability::ServerTime,
+                Capability::Batch,
+                Capability::EchoMessage,
+                Capability::AwayNotify,
+                Capability::AccountNotify,
+                Capability::AccountTag,
+                Capability::ExtendedJoin,
+                Capability::Chathistory,
+                Capability::Multiline,
+                Capability::Priority,
+                Capability::CapNotify,
+            ],
+        }
+    }
+}
+
+impl Capabilities {
+    /// Request nothing beyond what signing in needs.
+    pub fn none() -> Self {
+        Self {
+            requested: Vec::new(),
+        }
+    }
+
+    /// These capabilities plus `cap`.
+    pub fn with(mut self, cap: Capability) -> Self {
+        self.add(cap);
+        self
+    }
+
+    /// These capabilities without `cap`.
+    pub fn without(mut self, cap: Capability) -> Self {
+        self.remove(&cap);
+        self
+    }
+
+    pub fn add(&mut self, cap: Capability) {
+        if !self.requested.contains(&cap) {
+            self.requested.push(cap);
+        }
+    }
+
+    pub fn remove(&mut self, cap: &Capability) {
+        self.requested.retain(|c| c != cap);
+    }
+
+    /// Whether the capability called `name` is requested.
+    pub fn contains(&self, name: &str) -> bool {
+        self.requested.iter().any(|c| c.name() == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Capability> {
+        self.requested.iter()
+    }
+
+    /// Check the names of any [`Capability::Other`] built directly rather
+    /// than parsed.
+    pub fn validate(&self) -> Result<(), CapabilityError> {
+        self.requested
+            .iter()
+            .filter(|c| c.is_other())
+            .try_for_each(|c| Capability::check(c.name()))
+    }
+}
+
+/// The names in a `CAP LS` or `CAP NEW` list, without their values.
+pub(crate) fn offered(list: &str) -> impl Iterator<Item = &str> {
+    list.split_whitespace()
+        .map(|cap| cap.split_once('=').map_or(cap, |(name, _)| name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_parse_to_variants_or_checked_others() {
+        assert_eq!("away-notify".parse(), Ok(Capability::AwayNotify));
+        assert_eq!(" Draft/Chathistory ".parse(), Ok(Capability::Chathistory));
+        assert_eq!(
+            "draft/read-marker".parse(),
+            Ok(Capability::Other("draft/read-marker".to_string()))
+        );
+        for bad in ["", "has space", "-ack", "a=b", &"x".repeat(65)] {
+            assert!(
+                matches!(
+                    bad.parse::<Capability>(),
+                    Err(CapabilityError::InvalidName(_))
+                ),
+                "{bad:?}"
+            );
+        }
+        assert_eq!(
+            "sasl".parse::<Capability>(),
+            Err(CapabilityError::Managed("sasl".to_string()))
+        );
+        for cap in Capability::KNOWN {
+            assert_eq!(cap.name().parse(), Ok(cap.clone()));
+        }
+    }
+
+    #[test]
+    fn the_set_is_ordered_and_deduplicated() {
+        let caps = Capabilities::default()
+            .without(Capability::AwayNotify)
+            .with(Capability::MultiPrefix)
+            .with(Capability::MultiPrefix)
+            .with(Capability::MessageTags);
+        assert!(!caps.contains(caps::AWAY_NOTIFY));
+        let names: Vec<&str> = caps.iter().map(Capability::name).collect();
+        assert_eq!(names.first(), Some(&caps::MESSAGE_TAGS));
+        assert_eq!(names.last(), Some(&caps::MULTI_PREFIX));
+        assert_eq!(
+            names.iter().filter(|n| **n == caps::MULTI_PREFIX).count(),
+            1
+        );
+
+        assert!(Capabilities::none().iter().next().is_none());
+        assert!(
+            Capabilities::none()
+                .with(Capability::Other("Bad Name".to_string()))
+                .validate()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn offered_names_drop_values() {
+        let names: Vec<&str> =
+            offered("sasl draft/multiline=max-bytes=4096 iroh=abc batch").collect();
+        assert_eq!(names, ["sasl", "draft/multiline", "iroh", "batch"]);
+    }
+}
@@ -0,0 +1,531 @@
+//! Channel state — topic and member list of every channel we're in.
+//!
+//! [`ChannelTracker`] follows JOIN/PART/KICK/QUIT/NICK, NAMES, TOPIC,
+//! away-notify and prefix MODE changes, so an app can render a channel's
+//! roster in one go instead of replaying the event stream. Read it with
+//! [`ClientHandle::channel`](crate::client::ClientHandle::channel).
+//!
+//! It also reports what changed in each roster since it was last reported
+//! ([`RosterChange`], relayed as [`Event::MemberAdded`],
+//! [`Event::MemberRemoved`] and [`Event::MemberChanged`]), so a UI can
+//! patch its member list instead of redrawing it. A NAMES reply is
+//! compared as a whole once it ends: refreshing an unchanged channel
+//! reports nothing. A nick change is a removal and an addition. Leaving
+//! a channel or disconnecting reports nothing; the `Parted`, `Kicked` or
+//! `Disconnected` event already says the roster is gone.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::event::Event;
+
+/// One member of a channel.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Member {
+    pub nick: String,
+    /// The member's DID, from extended-join.
+    pub account: Option<String>,
+    /// Channel operator (`@`; also owners and admins, `~` and `&`).
+    pub op: bool,
+    /// Half-operator (`%`).
+    pub halfop: bool,
+    /// Voiced (`+`).
+    pub voiced: bool,
+    pub away: bool,
+}
+
+impl Member {
+    /// The highest channel prefix: `@`, `%`, `+` or empty.
+    pub fn prefix(&self) -> &'static str {
+        if self.op {
+            "@"
+        } else if self.halfop {
+            "%"
+        } else if self.voiced {
+            "+"
+        } else {
+            ""
+        }
+    }
+}
+
+/// A change to a channel's roster since it was last reported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RosterChange {
+    Added {
+        channel: String,
+        member: Member,
+    },
+    Removed {
+        channel: String,
+        nick: String,
+    },
+    /// The member's prefix, away state or account changed.
+    Changed {
+        channel: String,
+        member: Member,
+    },
+}
+
+impl From<RosterChange> for Event {
+    fn from(change: RosterChange) -> Self {
+        match change {
+            RosterChange::Added { channel, member } => Event::MemberAdded { channel, member },
+            RosterChange::Removed { channel, nick } => Event::MemberRemoved { channel, nick },
+            RosterChange::Changed { channel, member } => Event::MemberChanged { channel, member },
+        }
+    }
+}
+
+/// A channel as we currently know it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChannelState {
+    pub name: String,
+    pub topic: Option<String>,
+    pub topic_set_by: Option<String>,
+    /// Ordered operators first, then half-operators, voiced and everyone
+    /// else, each by nick.
+    pub members: Vec<Member>,
+}
+
+#[derive(Debug, Default)]
+struct Channel {
+    name: String,
+    topic: Option<String>,
+    topic_set_by: Option<String>,
+    /// Members by case-folded nick.
+    members: HashMap<String, Member>,
+    /// Nicks in the NAMES reply being received; members missing from it
+    /// are dropped when it ends.
+    names: Option<HashSet<String>>,
+    /// `members` as last reported in [`RosterChange`]s.
+    reported: HashMap<String, Member>,
+    /// Whether `members` may differ from `reported`.
+    dirty: bool,
+}
+
+impl Channel {
+    /// What changed since the last report, removals first, then each
+    /// kind by nick. Nothing while a NAMES reply is coming in.
+    fn changes(&mut self) -> Vec<RosterChange> {
+        if !self.dirty || self.names.is_some() {
+            return Vec::new();
+        }
+        self.dirty = false;
+        let mut removed: Vec<_> = self
+            .reported
+            .iter()
+            .filter(|(key, _)| !self.members.contains_key(*key))
+            .map(|(key, old)| (key.clone(), old.nick.clone()))
+            .collect();
+        removed.sort();
+        let mut added = Vec::new();
+        let mut changed = Vec::new();
+        for (key, member) in &self.members {
+            match self.reported.get(key) {
+                None => added.push((key.clone(), member.clone())),
+                Some(old) if old != member => changed.push((key.clone(), member.clone())),
+                Some(_) => {}
+            }
+        }
+        added.sort_by(|a, b| a.0.cmp(&b.0));
+        changed.sort_by(|a, b| a.0.cmp(&b.0));
+        if !removed.is_empty() || !added.is_empty() || !changed.is_empty() {
+            self.reported = self.members.clone();
+        }
+
+        let channel = &self.name;
+        let removed = removed.into_iter().map(|(_, nick)| RosterChange::Removed {
+            channel: channel.clone(),
+            nick,
+        });
+        let added = added.into_iter().map(|(_, member)| RosterChange::Added {
+            channel: channel.clone(),
+            member,
+        });
+        let changed = changed
+            .into_iter()
+            .map(|(_, member)| RosterChange::Changed {
+                channel: channel.clone(),
+                member,
+            });
+        removed.chain(added).chain(changed).collect()
+    }
+}
+
+fn fold(name: &str) -> String {
+    name.to_ascii_lowercase()
+}
+
+/// Follows the channels we're in from the event stream.
+#[derive(Debug, Default)]
+pub struct ChannelTracker {
+    /// Our own nick, case-folded.
+    me: Option<String>,
+    /// Channels by case-folded name.
+    channels: HashMap<String, Channel>,
+}
+
+impl ChannelTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The channels we're in, by name.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<_> = self.channels.values().map(|c| c.name.clone()).collect();
+        names.sort_by_key(|n| fold(n));
+        names
+    }
+
+    /// A channel's topic and members (case-insensitive), if we're in it.
+    pub fn get(&self, channel: &str) -> Option<ChannelState> {
+        let channel = self.channels.get(&fold(channel))?;
+        let mut members: Vec<Member> = channel.members.values().cloned().collect();
+        members.sort_by_key(|m| (!m.op, !m.halfop, !m.voiced, fold(&m.nick)));
+        Some(ChannelState {
+            name: channel.name.clone(),
+            topic: channel.topic.clone(),
+            topic_set_by: channel.topic_set_by.clone(),
+            members,
+        })
+    }
+
+    /// Feed one event, and get what it changed in the rosters.
+    pub fn observe(&mut self, event: &Event) -> Vec<RosterChange> {
+        self.update(event);
+        let mut changes: Vec<(&String, Vec<RosterChange>)> = self
+            .channels
+            .iter_mut()
+            .map(|(key, c)| (key, c.changes()))
+            .filter(|(_, changes)| !changes.is_empty())
+            .collect();
+        changes.sort_by(|a, b| a.0.cmp(b.0));
+        changes.into_iter().flat_map(|(_, c)| c).collect()
+    }
+
+    fn update(&mut self, event: &Event) {
+        match event {
+            Event::Registered { nick } | Event::NickAssigned { nick } => {
+                self.me = Some(fold(nick));
+            }
+            Event::Joined {
+                channel,
+                nick,
+                account,
+            } => {
+                if self.is_me(nick) {
+                    self.channels.insert(
+                        fold(channel),
+                        Channel {
+                            name: channel.clone(),
+                            ..Default::default()
+                        },
+                    );
+                }
+                if let Some(c) = self.channels.get_mut(&fold(channel)) {
+                    let member = c.members.entry(fold(nick)).or_default();
+                    member.nick = nick.clone();
+                    member.account = account.clone();
+                    c.dirty = true;
+                }
+            }
+            Event::Names { channel, nicks } => {
+                let Some(c) = self.channels.get_mut(&fold(channel)) else {
+                    return;
+                };
+                c.dirty = true;
+                let seen = c.names.get_or_insert_default();
+                for entry in nicks {
+                    let bare = entry.trim_start_matches(['~', '&', '@', '%', '+']);
+                    let prefixes = &entry[..entry.len() - bare.len()];
+                    let nick = bare.split('!').next().unwrap_or(bare);
+                    if nick.is_empty() {
+                        continue;
+                    }
+                    seen.insert(fold(nick));
+                    let member = c.members.entry(fold(nick)).or_default();
+                    member.nick = nick.to_string();
+                    member.op = prefixes.contains(['~', '&', '@']);
+                    member.halfop = prefixes.contains('%');
+                    member.voiced = prefixes.contains('+');
+                }
+            }
+            Event::NamesEnd { channel } => {
+                if let Some(c) = self.channels.get_mut(&fold(channel))
+                    && let Some(seen) = c.names.take()
+                {
+                    c.members.retain(|nick, _| seen.contains(nick));
+                    c.dirty = true;
+                }
+            }
+            Event::Parted { channel, nick } | Event::Kicked { channel, nick, .. } => {
+                if self.is_me(nick) {
+                    self.channels.remove(&fold(channel));
+                } else if let Some(c) = self.channels.get_mut(&fold(channel)) {
+                    c.dirty |= c.members.remove(&fold(nick)).is_some();
+                }
+            }
+            Event::UserQuit { nick, .. } => {
+                for c in self.channels.values_mut() {
+                    c.dirty |= c.members.remove(&fold(nick)).is_some();
+                }
+            }
+            Event::NickChanged { old_nick, new_nick } => {
+                if self.is_me(old_nick) {
+                    self.me = Some(fold(new_nick));
+                }
+                for c in self.channels.values_mut() {
+                    if let Some(mut member) = c.members.remove(&fold(old_nick)) {
+                        member.nick = new_nick.clone();
+                        c.members.insert(fold(new_nick), member);
+                        c.dirty = true;
+                    }
+                }
+            }
+            Event::AwayChanged { nick, away_msg } => {
+                for c in self.channels.values_mut() {
+                    if let Some(member) = c.members.get_mut(&fold(nick)) {
+                        member.away = away_msg.is_some();
+                        c.dirty = true;
+                    }
+                }
+            }
+            Event::TopicChanged {
+                channel,
+                topic,
+                set_by,
+            } => {
+                if let Some(c) = self.channels.get_mut(&fold(channel)) {
+                    c.topic = Some(topic.clone()).filter(|t| !t.is_empty());
+                    c.topic_set_by = set_by.clone();
+                }
+            }
+            Event::ModeChanged {
+                channel, mode, arg, ..
+            } => {
+                let (Some(c), Some(nick)) = (self.channels.get_mut(&fold(channel)), arg) else {
+                    return;
+                };
+                let Some(member) = c.members.get_mut(&fold(nick)) else {
+                    return;
+                };
+                // Only single-mode changes: `arg` is the first argument, so
+                // it can't be matched to the flags of `+ov a b`.
+                let mut flags = mode.chars();
+                let adding = match flags.next() {
+                    Some('+') => true,
+                    Some('-') => false,
+                    _ => return,
+                };
+                let (Some(flag), None) = (flags.next(), flags.next()) else {
+                    return;
+                };
+                match flag {
+                    'q' | 'a' | 'o' => member.op = adding,
+                    'h' => member.halfop = adding,
+                    'v' => member.voiced = adding,
+                    _ => return,
+                }
+                c.dirty = true;
+            }
+            Event::Disconnected { .. } => self.channels.clear(),
+            _ => {}
+        }
+    }
+
+    fn is_me(&self, nick: &str) -> bool {
+        self.me.as_deref() == Some(fold(nick).as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn joined(channel: &str, nick: &str, account: Option<&str>) -> Event {
+        Event::Joined {
+            channel: channel.to_string(),
+            nick: nick.to_string(),
+            account: account.map(str::to_string),
+        }
+    }
+
+    fn names(channel: &str, nicks: &[&str]) -> Event {
+        Event::Names {
+            channel: channel.to_string(),
+            nicks: nicks.iter().map(|n| n.to_string()).collect(),
+        }
+    }
+
+    fn roster(t: &ChannelTracker, channel: &str) -> Vec<String> {
+        t.get(channel)
+            .unwrap()
+            .members
+            .into_iter()
+            .map(|m| m.nick)
+            .collect()
+    }
+
+    #[test]
+    fn roster_follows_names_joins_and_departures() {
+        let mut t = ChannelTracker::new();
+        t.observe(&Event::Registered { nick: "me".into() });
+        t.observe(&joined("#Rust", "me", Some("did:plc:me")));
+        t.observe(&names("#rust", &["@me", "+bob", "carol"]));
+        t.observe(&names("#rust", &["%dave!d@host"]));
+        t.observe(&Event::NamesEnd {
+            channel: "#rust".into(),
+        });
+        assert_eq!(t.names(), ["#Rust"]);
+        assert_eq!(roster(&t, "#RUST"), ["me", "dave", "bob", "carol"]);
+
+        t.observe(&joined("#rust", "erin", Some("did:plc:erin")));
+        t.observe(&Event::NickChanged {
+            old_nick: "carol".into(),
+            new_nick: "caz".into(),
+        });
+        t.observe(&Event::Parted {
+            channel: "#rust".into(),
+            nick: "bob".into(),
+        });
+        t.observe(&Event::UserQuit {
+            nick: "dave".into(),
+            reason: String::new(),
+        });
+        assert_eq!(roster(&t, "#rust"), ["me", "caz", "erin"]);
+        let erin = &t.get("#rust").unwrap().members[2];
+        assert_eq!(erin.account.as_deref(), Some("did:plc:erin"));
+
+        // A later NAMES reply replaces the list, keeping known accounts.
+        t.observe(&names("#rust", &["@me", "zed", "erin"]));
+        t.observe(&Event::NamesEnd {
+            channel: "#rust".into(),
+        });
+        assert_eq!(roster(&t, "#rust"), ["me", "erin", "zed"]);
+        let erin = &t.get("#rust").unwrap().members[1];
+        assert_eq!(erin.account.as_deref(), Some("did:plc:erin"));
+
+        t.observe(&Event::Kicked {
+            channel: "#rust".into(),
+            nick: "me".into(),
+            by: "zed".into(),
+            reason: String::new(),
+        });
+        assert!(t.get("#rust").is_none());
+    }
+
+    #[test]
+    fn topic_modes_and_away() {
+        let mut t = ChannelTracker::new();
+        t.observe(&Event::Registered { nick: "me".into() });
+        t.observe(&joined("#a", "me", None));
+        t.observe(&names("#a", &["me", "bob"]));
+        t.observe(&Event::TopicChanged {
+            channel: "#a".into(),
+            topic: "hello".into(),
+            set_by: Some("bob".into()),
+        });
+        t.observe(&Event::ModeChanged {
+            channel: "#a".into(),
+            mode: "+o".into(),
+            arg: Some("bob".into()),
+            set_by: "server".into(),
+        });
+        t.observe(&Event::AwayChanged {
+            nick: "BOB".into(),
+            away_msg: Some("lunch".into()),
+        });
+
+        let state = t.get("#a").unwrap();
+        assert_eq!(state.topic.as_deref(), Some("hello"));
+        assert_eq!(state.topic_set_by.as_deref(), Some("bob"));
+        let bob = &state.members[0];
+        assert_eq!((bob.nick.as_str(), bob.op, bob.away), ("bob", true, true));
+
+        t.observe(&Event::Disconnected {
+            reason: String::new(),
+        });
+        assert!(t.names().is_empty());
+    }
+
+    /// Changes as `+nick`, `-nick` and `~prefixnick[ away]`.
+    fn deltas(changes: Vec<RosterChange>) -> Vec<String> {
+        changes
+            .into_iter()
+            .map(|c| match c {
+                RosterChange::Added { member, .. } => format!("+{}", member.nick),
+                RosterChange::Removed { nick, .. } => format!("-{nick}"),
+                RosterChange::Changed { member, .. } => format!(
+                    "~{}{}{}",
+                    member.prefix(),
+                    member.nick,
+                    if member.away { " away" } else { "" }
+                ),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn roster_changes_are_diffed_against_the_last_report() {
+        let mut t = ChannelTracker::new();
+        t.observe(&Event::Registered { nick: "me".into() });
+        assert_eq!(deltas(t.observe(&joined("#a", "me", None))), ["+me"]);
+
+        // A NAMES reply is reported once it ends.
+        assert!(t.observe(&names("#a", &["@me", "bob"])).is_empty());
+        assert!(t.observe(&names("#a", &["+carol"])).is_empty());
+        let end = Event::NamesEnd {
+            channel: "#a".into(),
+        };
+        assert_eq!(deltas(t.observe(&end)), ["+bob", "+carol", "~@me"]);
+
+        // Refreshing an unchanged roster reports nothing; a changed one
+        // only the difference.
+        t.observe(&names("#a", &["@me", "bob", "+carol"]));
+        assert!(t.observe(&end).is_empty());
+        t.observe(&names("#a", &["@me", "%bob", "dave"]));
+        assert_eq!(deltas(t.observe(&end)), ["-carol", "+dave", "~%bob"]);
+
+        let away = Event::AwayChanged {
+            nick: "dave".into(),
+            away_msg: Some("lunch".into()),
+        };
+        assert_eq!(deltas(t.observe(&away)), ["~dave away"]);
+        let voice = Event::ModeChanged {
+            channel: "#a".into(),
+            mode: "+v".into(),
+            arg: Some("dave".into()),
+            set_by: "me".into(),
+        };
+        assert_eq!(deltas(t.observe(&voice)), ["~+dave away"]);
+        let nick = Event::NickChanged {
+            old_nick: "bob".into(),
+            new_nick: "rob".into(),
+        };
+        assert_eq!(deltas(t.observe(&nick)), ["-bob", "+rob"]);
+
+        // Only the channels a quit touches report it.
+        t.observe(&joined("#b", "me", None));
+        t.observe(&joined("#b", "rob", None));
+        let quit = Event::UserQuit {
+            nick: "rob".into(),
+            reason: String::new(),
+        };
+        let changes = t.observe(&quit);
+        let channels: Vec<_> = changes
+            .iter()
+            .map(|c| match c {
+                RosterChange::Removed { channel, .. } => channel.as_str(),
+                _ => "",
+            })
+            .collect();
+        assert_eq!(channels, ["#a", "#b"]);
+        assert!(t.observe(&quit).is_empty());
+
+        let part = Event::Parted {
+            channel: "#a".into(),
+            nick: "me".into(),
+        };
+        assert!(t.observe(&part).is_empty());
+    }
+}
@@ -38,8 +38,21 @@ use tokio_rustls::TlsConnector;
 use tokio_rustls::rustls;
 
 use crate::auth::{self, ChallengeSigner};
+use crate::capabilities::Capabilities;
+use crate::channels::{ChannelState, ChannelTracker};
+use crate::diagnostics::{self, Stage};
+use crate::directory::{ChannelListing, ChannelLists, ListFilter};
 use crate::event::Event;
+use crate::history::HistoryCollectors;
+use crate::interceptor::{EventInterceptor, Interceptors};
 use crate::irc::Message;
+use crate::pending::{Awaiting, CallError, CallOptions, PendingReplies};
+use crate::pipeline::Pipeline;
+use crate::power::{self, LowPower};
+use crate::presence::{PresenceState, PresenceTracker};
+use crate::profile::{Negotiation, ServerProfile};
+use crate::proto::{caps, numeric, tags};
+use crate::tls::{self, TlsError, TlsOptions};
 
 /// Registry for pending echo-message callbacks.
 /// When a client sends a PRIVMSG with a `+freeq.at/echo-nonce` tag, the nonce
@@ -62,6 +75,8 @@ pub struct ConnectConfig {
     pub tls: bool,
     /// Skip TLS certificate verification (for self-signed certs).
     pub tls_insecure: bool,
+    /// Custom roots, SPKI pins and ALPN for TLS and `wss://` connections.
+    pub tls_options: TlsOptions,
     /// One-time web-token for SASL WEB-TOKEN authentication (from OAuth flow).
     pub web_token: Option<String>,
     /// WebSocket URL — `wss://host/path` or `ws://host/path`. When set, the
@@ -69,6 +84,83 @@ pub struct ConnectConfig {
     /// client's transport (`freeq-sdk-js/src/transport.ts`) so iOS can
     /// reach the server on networks that block port 6667.
     pub websocket_url: Option<String>,
+    /// What to do when `nick` is taken at registration, and whether to
+    /// take it back once it's free.
+    pub nick_fallback: NickFallback,
+    /// Capabilities to request; see [`crate::capabilities`].
+    pub capabilities: Capabilities,
+}
+
+/// How many generated nicks (`nick1`…, `nick_`…) to try before giving up.
+const MAX_GENERATED_NICKS: u32 = 5;
+
+/// How generated fallback nicks are formed from the desired one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NickSuffix {
+    /// `nick1`, `nick2`, …
+    #[default]
+    Numbered,
+    /// `nick_`, `nick__`, …
+    Underscore,
+    /// No generated nicks: give up once `alternatives` run out.
+    None,
+}
+
+/// Nick fallback and regain when the desired nick is taken (433).
+///
+/// During registration each 433 moves to the next candidate: the
+/// `alternatives` in order, then up to five generated nicks. If every
+/// candidate is taken the client disconnects with "Nick in use". When
+/// registration ends on a fallback nick and `regain` is set, the client
+/// MONITORs the desired nick and switches back to it as soon as the
+/// server reports it offline. [`Event::NickAssigned`] reports the
+/// effective nick each time it changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NickFallback {
+    /// Nicks to try, in order, before generated ones.
+    pub alternatives: Vec<String>,
+    /// How to generate further nicks once `alternatives` run out.
+    pub suffix: NickSuffix,
+    /// Take the desired nick back when MONITOR reports it free.
+    pub regain: bool,
+}
+
+impl Default for NickFallback {
+    fn default() -> Self {
+        Self {
+            alternatives: Vec::new(),
+            suffix: NickSuffix::Numbered,
+            regain: true,
+        }
+    }
+}
+
+impl NickFallback {
+    /// The nick to try after `attempt` (1-based) rejections of `nick`,
+    /// or `None` when there are no candidates left.
+    pub fn candidate(&self, nick: &str, attempt: u32) -> Option<String> {
+        let index = attempt.checked_sub(1)? as usize;
+        if let Some(alt) = self.alternatives.get(index) {
+            return Some(alt.clone());
+        }
+        let n = (index - self.alternatives.len()) as u32 + 1;
+        if n > MAX_GENERATED_NICKS {
+            return None;
+        }
+        match self.suffix {
+            NickSuffix::Numbered => Some(format!("{nick}{n}")),
+            NickSuffix::Underscore => Some(format!("{nick}{}", "_".repeat(n as usize))),
+            NickSuffix::None => None,
+        }
+    }
+
+    /// Check that every alternative is a usable nick.
+    pub fn validate(&self) -> Result<(), String> {
+        for alt in &self.alternatives {
+            validate_nick(alt).map_err(|e| format!("alternative nick {alt:?} {e}"))?;
+        }
+        Ok(())
+    }
 }
 
 impl Default for ConnectConfig {
@@ -80,8 +172,11 @@ impl Default for ConnectConfig {
             realname: "IRC AT SDK User".to_string(),
             tls: false,
             tls_insecure: false,
+            tls_options: TlsOptions::default(),
             web_token: None,
             websocket_url: None,
+            nick_fallback: NickFallback::default(),
+            capabilities: Capabilities::default(),
         }
     }
 }
@@ -92,31 +187,39 @@ impl ConnectConfig {
         if self.server_addr.is_empty() {
             return Err("server_addr must not be empty".into());
         }
-        if self.nick.is_empty() || self.nick.len() > 64 {
-            return Err("nick must be 1-64 characters".into());
-        }
-        if self.nick.contains(|c: char| {
-            c.is_control()
-                || c == ' '
-                || c == ','
-                || c == '*'
-                || c == '?'
-                || c == '!'
-                || c == '@'
-                || c == '#'
-        }) {
-            return Err("nick contains invalid characters".into());
-        }
+        validate_nick(&self.nick).map_err(|e| format!("nick {e}"))?;
+        self.nick_fallback.validate()?;
+        self.capabilities.validate().map_err(|e| e.to_string())?;
         if self.user.is_empty() {
             return Err("user must not be empty".into());
         }
         if self.tls_insecure && !self.tls {
             tracing::warn!("tls_insecure has no effect when tls is false");
         }
+        self.tls_options.validate().map_err(|e| e.to_string())?;
         Ok(())
     }
 }
 
+fn validate_nick(nick: &str) -> Result<(), &'static str> {
+    if nick.is_empty() || nick.len() > 64 {
+        return Err("must be 1-64 characters");
+    }
+    if nick.contains(|c: char| {
+        c.is_control()
+            || c == ' '
+            || c == ','
+            || c == '*'
+            || c == '?'
+            || c == '!'
+            || c == '@'
+            || c == '#'
+    }) {
+        return Err("contains invalid characters");
+    }
+    Ok(())
+}
+
 /// Commands the consumer can send to the client.
 #[derive(Debug)]
 pub enum Command {
@@ -174,6 +277,14 @@ pub struct ClientHandle {
     cmd_tx: mpsc::Sender<Command>,
     echo_registry: EchoRegistry,
     caps_acked: CapsAcked,
+    presence: Arc<parking_lot::Mutex<PresenceTracker>>,
+    pending: PendingReplies,
+    interceptors: Interceptors,
+    channels: Arc<parking_lot::Mutex<ChannelTracker>>,
+    history: HistoryCollectors,
+    channel_lists: ChannelLists,
+    low_power: LowPower,
+    negotiation: Negotiation,
 }
 
 impl ClientHandle {
@@ -182,6 +293,32 @@ impl ClientHandle {
         Ok(())
     }
 
+    /// Join `channel` and wait until the server confirms it (our own JOIN
+    /// comes back) or refuses it (banned, invite-only, bad key, …).
+    pub async fn join_with(&self, channel: &str, options: CallOptions) -> Result<(), CallError> {
+        self.call(
+            Awaiting::Join(channel.to_string()),
+            Command::Join(channel.to_string()),
+            &options,
+        )
+        .await
+    }
+
+    /// Send `cmd` and wait for the reply `awaiting` describes.
+    async fn call(
+        &self,
+        awaiting: Awaiting,
+        cmd: Command,
+        options: &CallOptions,
+    ) -> Result<(), CallError> {
+        let (id, rx) = self.pending.register(awaiting);
+        if self.cmd_tx.send(cmd).await.is_err() {
+            self.pending.forget(id);
+            return Err(CallError::Disconnected);
+        }
+        self.pending.wait(id, rx, options).await
+    }
+
     /// Send a PRIVMSG. If `text` contains `\n` and the server acked
     /// `draft/multiline` + `batch`, the SDK auto-routes the send to a
     /// `draft/multiline` BATCH (one chunk per source line) so the
@@ -223,6 +360,78 @@ impl ClientHandle {
         Ok(())
     }
 
+    /// Send a PRIVMSG at `priority` (see [`tags::Priority`]). Bots mark
+    /// status chatter `Low` so capable clients can fold runs of it; the
+    /// server only accepts that from a signed-in agent (`AGENT REGISTER`).
+    /// `Normal` sends an untagged PRIVMSG. Multi-line text is routed like
+    /// [`privmsg`](Self::privmsg).
+    pub async fn privmsg_with_priority(
+        &self,
+        target: &str,
+        text: &str,
+        priority: tags::Priority,
+    ) -> Result<()> {
+        if priority == tags::Priority::Normal {
+            return self.privmsg(target, text).await;
+        }
+        let tags = std::collections::HashMap::from([(
+            tags::PRIORITY.to_string(),
+            priority.as_str().to_string(),
+        )]);
+        self.send_tagged(target, text, tags).await
+    }
+
+    /// Send a PRIVMSG composed in markdown: bold, italic, inline code and
+    /// links go out as IRC formatting codes (see [`crate::format`]).
+    /// Multi-line text is routed like [`privmsg`](Self::privmsg).
+    pub async fn privmsg_formatted(&self, target: &str, markdown: &str) -> Result<()> {
+        self.privmsg(target, &crate::format::markdown_to_irc(markdown))
+            .await
+    }
+
+    /// Whether the server acknowledged capability `cap` (see
+    /// [`crate::proto::caps`]).
+    pub fn has_cap(&self, cap: &str) -> bool {
+        self.caps_acked.lock().contains(cap)
+    }
+
+    /// What the server supports, from the capabilities it acknowledged;
+    /// `None` until registration completes. See [`crate::profile`] for
+    /// how the SDK adapts to each.
+    pub fn server_profile(&self) -> Option<ServerProfile> {
+        self.negotiation
+            .done()
+            .then(|| ServerProfile::from_caps(&self.caps_acked.lock()))
+    }
+
+    /// Whether the history calls can work: `false` once registration has
+    /// shown the server has no `draft/chathistory`, after which they
+    /// fail with [`CallError::Unsupported`].
+    pub fn has_history(&self) -> bool {
+        self.require_history().is_ok()
+    }
+
+    /// The capabilities the server acknowledged, sorted. They can change
+    /// after registration, when the server announces `CAP NEW` or
+    /// `CAP DEL`.
+    pub fn caps(&self) -> Vec<String> {
+        let mut caps: Vec<String> = self.caps_acked.lock().iter().cloned().collect();
+        caps.sort();
+        caps
+    }
+
+    /// Switch low-power mode on or off: sparse keepalives, no typing or
+    /// away relays, batched events and deferred lookups. See
+    /// [`crate::power`].
+    pub fn set_low_power(&self, on: bool) {
+        self.low_power.set(on);
+    }
+
+    /// Whether low-power mode is on.
+    pub fn is_low_power(&self) -> bool {
+        self.low_power.is_on()
+    }
+
     /// Send a multi-line message via `draft/multiline` BATCH. Splits
     /// `text` on `\n` boundaries by default — each logical line becomes
     /// one wire chunk with `concat=false` so receivers reassemble with
@@ -280,6 +489,81 @@ impl ClientHandle {
         Ok(())
     }
 
+    /// Run every event through `interceptor` before the event receiver sees
+    /// it, after any interceptors added earlier. See [`crate::interceptor`].
+    pub fn add_interceptor(&self, interceptor: Box<dyn EventInterceptor>) {
+        self.interceptors.add(interceptor);
+    }
+
+    /// Aggregated presence of a contact, by DID or nick.
+    pub fn presence(&self, did_or_nick: &str) -> PresenceState {
+        self.presence.lock().get(did_or_nick)
+    }
+
+    /// Every contact currently online or away.
+    pub fn presence_snapshot(&self) -> Vec<(String, PresenceState)> {
+        self.presence.lock().snapshot()
+    }
+
+    /// Topic and members of a channel we're in (case-insensitive).
+    pub fn channel(&self, name: &str) -> Option<ChannelState> {
+        self.channels.lock().get(name)
+    }
+
+    /// The channels we're in.
+    pub fn channels(&self) -> Vec<String> {
+        self.channels.lock().names()
+    }
+
+    /// The server's channels that pass `filter`, busiest first, for a
+    /// channel directory. Answered from the cache when the same request
+    /// was made within [`crate::directory::CACHE_TTL`]; otherwise sends
+    /// `LIST` and waits for the listing, within `options`.
+    pub async fn list_channels(
+        &self,
+        filter: &ListFilter,
+        options: CallOptions,
+    ) -> Result<Vec<ChannelListing>, CallError> {
+        let command = self.channel_lists.command(filter);
+        if let Some(entries) = self.channel_lists.cached(&command) {
+            return Ok(crate::directory::select(entries, filter));
+        }
+        self.refresh_channel_list(filter, options).await
+    }
+
+    /// [`list_channels`](Self::list_channels), skipping the cache (pull to
+    /// refresh).
+    pub async fn refresh_channel_list(
+        &self,
+        filter: &ListFilter,
+        options: CallOptions,
+    ) -> Result<Vec<ChannelListing>, CallError> {
+        let command = self.channel_lists.command(filter);
+        let (id, rx) = self.channel_lists.register();
+        if self
+            .cmd_tx
+            .send(Command::Raw(command.clone()))
+            .await
+            .is_err()
+        {
+            self.channel_lists.forget(id);
+            return Err(CallError::Disconnected);
+        }
+        let entries = self.channel_lists.wait(id, rx, command, &options).await?;
+        Ok(crate::directory::select(entries, filter))
+    }
+
+    /// Ask the server to report when these nicks come online or go offline
+    /// (MONITOR), so contacts we share no channel with still get presence.
+    pub async fn monitor(&self, nicks: &[&str]) -> Result<()> {
+        self.raw(&format!("MONITOR + {}", nicks.join(","))).await
+    }
+
+    /// Stop monitoring these nicks.
+    pub async fn unmonitor(&self, nicks: &[&str]) -> Result<()> {
+        self.raw(&format!("MONITOR - {}", nicks.join(","))).await
+    }
+
     /// Send a tagged message and await the server-assigned msgid via echo-message.
     ///
     /// This inserts a unique nonce tag (`+freeq.at/echo-nonce`) that the client
@@ -398,6 +682,26 @@ impl ClientHandle {
             .await
     }
 
+    /// Offer `nick` a direct DM connection to our p2p endpoint. See
+    /// [`crate::p2p_dm`] for the rest of the negotiation.
+    pub async fn p2p_offer(&self, nick: &str, endpoint_id: &str) -> Result<()> {
+        self.send_tagmsg(nick, crate::p2p_dm::p2p_offer_tags(endpoint_id))
+            .await
+    }
+
+    /// Accept `nick`'s direct DM offer, telling them our p2p endpoint.
+    pub async fn p2p_answer(&self, nick: &str, endpoint_id: &str) -> Result<()> {
+        self.send_tagmsg(nick, crate::p2p_dm::p2p_answer_tags(endpoint_id))
+            .await
+    }
+
+    /// Tell `nick` we're dropping the direct connection and going back to
+    /// relaying DMs through the server.
+    pub async fn p2p_close(&self, nick: &str, reason: Option<&str>) -> Result<()> {
+        self.send_tagmsg(nick, crate::p2p_dm::p2p_close_tags(reason))
+            .await
+    }
+
     /// Send a reaction to a target (channel or user).
     /// Falls back to PRIVMSG for plain clients.
     pub async fn send_reaction(
@@ -441,15 +745,21 @@ impl ClientHandle {
         self.reply(target, parent_msgid, text).await
     }
 
-    /// Send a typing indicator start.
+    /// Send a typing indicator start. Sends nothing in low-power mode.
     pub async fn typing_start(&self, target: &str) -> Result<()> {
+        if self.low_power.is_on() {
+            return Ok(());
+        }
         let mut tags = std::collections::HashMap::new();
         tags.insert("+typing".to_string(), "active".to_string());
         self.send_tagmsg(target, tags).await
     }
 
-    /// Send a typing indicator stop.
+    /// Send a typing indicator stop. Sends nothing in low-power mode.
     pub async fn typing_stop(&self, target: &str) -> Result<()> {
+        if self.low_power.is_on() {
+            return Ok(());
+        }
         let mut tags = std::collections::HashMap::new();
         tags.insert("+typing".to_string(), "done".to_string());
         self.send_tagmsg(target, tags).await
@@ -475,12 +785,14 @@ impl ClientHandle {
 
     /// Request latest N messages of history (CHATHISTORY LATEST).
     pub async fn history_latest(&self, target: &str, count: usize) -> Result<()> {
+        self.require_history()?;
         self.raw(&format!("CHATHISTORY LATEST {target} * {count}"))
             .await
     }
 
     /// Request N messages before a given msgid (CHATHISTORY BEFORE).
     pub async fn history_before(&self, target: &str, msgid: &str, count: usize) -> Result<()> {
+        self.require_history()?;
         self.raw(&format!(
             "CHATHISTORY BEFORE {target} msgid={msgid} {count}"
         ))
@@ -489,12 +801,142 @@ impl ClientHandle {
 
     /// Request N messages after a given msgid (CHATHISTORY AFTER).
     pub async fn history_after(&self, target: &str, msgid: &str, count: usize) -> Result<()> {
+        self.require_history()?;
         self.raw(&format!("CHATHISTORY AFTER {target} msgid={msgid} {count}"))
             .await
     }
 
+    /// Request every message in the thread `msgid` belongs to, root first
+    /// (CHATHISTORY THREAD). Replies arrive as `Message` + `ThreadReply`.
+    pub async fn fetch_thread(&self, target: &str, msgid: &str) -> Result<()> {
+        self.require_history()?;
+        self.raw(&format!("CHATHISTORY THREAD {target} msgid={msgid}"))
+            .await
+    }
+
+    /// [`history_latest`](Self::history_latest), waiting until the
+    /// `chathistory` batch has been delivered. The messages themselves
+    /// arrive as events before this returns.
+    pub async fn history_latest_with(
+        &self,
+        target: &str,
+        count: usize,
+        options: CallOptions,
+    ) -> Result<(), CallError> {
+        self.history_call(
+            target,
+            format!("CHATHISTORY LATEST {target} * {count}"),
+            options,
+        )
+        .await
+    }
+
+    /// [`history_before`](Self::history_before), waiting for the batch.
+    pub async fn history_before_with(
+        &self,
+        target: &str,
+        msgid: &str,
+        count: usize,
+        options: CallOptions,
+    ) -> Result<(), CallError> {
+        self.history_call(
+            target,
+            format!("CHATHISTORY BEFORE {target} msgid={msgid} {count}"),
+            options,
+        )
+        .await
+    }
+
+    /// [`history_after`](Self::history_after), waiting for the batch.
+    pub async fn history_after_with(
+        &self,
+        target: &str,
+        msgid: &str,
+        count: usize,
+        options: CallOptions,
+    ) -> Result<(), CallError> {
+        self.history_call(
+            target,
+            format!("CHATHISTORY AFTER {target} msgid={msgid} {count}"),
+            options,
+        )
+        .await
+    }
+
+    /// [`fetch_thread`](Self::fetch_thread), waiting for the batch.
+    pub async fn fetch_thread_with(
+        &self,
+        target: &str,
+        msgid: &str,
+        options: CallOptions,
+    ) -> Result<(), CallError> {
+        self.history_call(
+            target,
+            format!("CHATHISTORY THREAD {target} msgid={msgid}"),
+            options,
+        )
+        .await
+    }
+
+    /// Fetch up to `limit` messages of `target` before `before` (the
+    /// latest when `None`) and return them, oldest first, once the batch
+    /// has been delivered. They also arrive as events, as usual.
+    pub async fn fetch_history(
+        &self,
+        target: &str,
+        before: Option<&str>,
+        limit: usize,
+        options: CallOptions,
+    ) -> Result<Vec<Event>, CallError> {
+        let line = match before {
+            Some(msgid) => format!("CHATHISTORY BEFORE {target} msgid={msgid} {limit}"),
+            None => format!("CHATHISTORY LATEST {target} * {limit}"),
+        };
+        self.require_history()?;
+        let (id, collected) = self.history.register(target);
+        if let Err(e) = self.history_call(target, line, options.clone()).await {
+            self.history.forget(id);
+            return Err(e);
+        }
+        // The batch has closed; its events are at most a relay hop behind.
+        match tokio::time::timeout(options.timeout, collected).await {
+            Ok(Ok(messages)) => Ok(messages),
+            Ok(Err(_)) => Err(CallError::Disconnected),
+            Err(_) => {
+                self.history.forget(id);
+                Err(CallError::Timeout(options.timeout))
+            }
+        }
+    }
+
+    async fn history_call(
+        &self,
+        target: &str,
+        line: String,
+        options: CallOptions,
+    ) -> Result<(), CallError> {
+        self.require_history()?;
+        self.call(
+            Awaiting::History(target.to_string()),
+            Command::Raw(line),
+            &options,
+        )
+        .await
+    }
+
+    /// Fail with [`CallError::Unsupported`] once registration has shown
+    /// the server has no chat history. Before that, the request is sent
+    /// and the server gets to decide.
+    fn require_history(&self) -> Result<(), CallError> {
+        if self.negotiation.done() && !self.has_cap(caps::CHATHISTORY) {
+            return Err(CallError::Unsupported(caps::CHATHISTORY));
+        }
+        Ok(())
+    }
+
     /// Request DM conversation list (CHATHISTORY TARGETS).
     pub async fn chathistory_targets(&self, limit: usize) -> Result<()> {
+        self.require_history()?;
         self.raw(&format!("CHATHISTORY TARGETS * * {limit}")).await
     }
 
@@ -888,56 +1330,112 @@ pub async fn establish_connection(config: &ConnectConfig) -> Result<EstablishedC
     // so it can reach the server on networks that block port 6667.
     #[cfg(feature = "websocket")]
     if let Some(ref ws_url) = config.websocket_url {
-        return establish_ws_connection(ws_url).await;
+        return establish_ws_connection(ws_url, config).await;
     }
 
     // Auto-detect TLS from port if not explicitly set
     let use_tls = config.tls || config.server_addr.ends_with(":6697");
     let mode = if use_tls { "TLS" } else { "plain" };
+    diagnostics::record(Stage::Connecting {
+        server: config.server_addr.clone(),
+        transport: if use_tls { "tls" } else { "tcp" },
+    });
+    let failed = |stage, started: std::time::Instant, error: &dyn std::fmt::Display| {
+        diagnostics::record(Stage::Failed {
+            stage,
+            ms: diagnostics::millis(started.elapsed()),
+            error: error.to_string(),
+        });
+    };
 
+    // Resolve first, under the same timeout as the connect, so DNS and
+    // TCP are timed separately in the diagnostics.
     tracing::debug!("Resolving {}...", config.server_addr);
+    let started = std::time::Instant::now();
+    let addrs: Vec<std::net::SocketAddr> = match tokio::time::timeout(
+        TRANSPORT_CONNECT_TIMEOUT,
+        tokio::net::lookup_host(&config.server_addr),
+    )
+    .await
+    {
+        Ok(Ok(addrs)) => addrs.collect(),
+        Ok(Err(e)) => {
+            let err = anyhow::anyhow!("DNS lookup of {} failed: {e}", config.server_addr);
+            failed("dns", started, &err);
+            return Err(err);
+        }
+        Err(_) => {
+            let err = anyhow::anyhow!(
+                "DNS lookup of {} timed out after {}s",
+                config.server_addr,
+                TRANSPORT_CONNECT_TIMEOUT.as_secs()
+            );
+            failed("dns", started, &err);
+            return Err(err);
+        }
+    };
+    diagnostics::record(Stage::Dns {
+        ms: diagnostics::millis(started.elapsed()),
+        addresses: addrs.len(),
+    });
+
+    let started = std::time::Instant::now();
     let tcp = match tokio::time::timeout(
         TRANSPORT_CONNECT_TIMEOUT,
-        TcpStream::connect(&config.server_addr),
+        TcpStream::connect(addrs.as_slice()),
     )
     .await
     {
         Ok(Ok(s)) => s,
         Ok(Err(e)) => {
-            return Err(anyhow::anyhow!(
-                "TCP connect to {} failed: {e}",
-                config.server_addr
-            ));
+            let err = anyhow::anyhow!("TCP connect to {} failed: {e}", config.server_addr);
+            failed("tcp", started, &err);
+            return Err(err);
         }
         Err(_) => {
-            return Err(anyhow::anyhow!(
+            let err = anyhow::anyhow!(
                 "TCP connect to {} timed out after {}s",
                 config.server_addr,
                 TRANSPORT_CONNECT_TIMEOUT.as_secs()
-            ));
+            );
+            failed("tcp", started, &err);
+            return Err(err);
         }
     };
+    diagnostics::record(Stage::Tcp {
+        ms: diagnostics::millis(started.elapsed()),
+    });
     tracing::debug!("TCP connected to {} ({mode})", config.server_addr);
 
     if use_tls {
-        let tls_config = if config.tls_insecure {
+        if config.tls_insecure {
             tracing::debug!("TLS: insecure mode (skipping cert verification)");
-            rustls_insecure_config()
         } else {
             tracing::debug!("TLS: verifying server certificate...");
-            rustls_default_config()
-        };
+        }
+        let started = std::time::Instant::now();
+        let tls_config = tls::client_config(&config.tls_options, config.tls_insecure)?;
         let connector = TlsConnector::from(Arc::new(tls_config));
         let server_name = config.server_addr.split(':').next().unwrap_or("localhost");
         let dns_name = rustls::pki_types::ServerName::try_from(server_name.to_string())?;
+        // The typed `TlsError` is the returned error, so callers can
+        // `downcast_ref::<TlsError>()` to tell a pin mismatch from expiry.
         let tls_stream = connector.connect(dns_name, tcp).await.map_err(|e| {
-            let hint = if format!("{e}").contains("UnknownIssuer") {
-                " (the server's certificate chain may be incomplete — try --tls-insecure to skip verification, or ensure the server sends its full certificate chain including intermediates)"
+            let err = tls::classify_io(&e);
+            if err == TlsError::UnknownIssuer {
+                tracing::warn!(
+                    "TLS handshake with {} failed: {err} (the server's certificate chain may be incomplete — try --tls-insecure to skip verification, add its CA as a custom root, or ensure the server sends its full certificate chain including intermediates)",
+                    config.server_addr
+                );
             } else {
-                ""
-            };
-            anyhow::anyhow!("TLS handshake with {} failed: {e}{hint}", config.server_addr)
+                tracing::warn!("TLS handshake with {} failed: {err}", config.server_addr);
+            }
+            failed("tls", started, &err);
+            anyhow::Error::from(err)
         })?;
+        diagnostics::record(Stage::Tls {
+            ms: diagnostics::millis(started.elapsed()),
+        });
         tracing::debug!("TLS handshake complete");
         Ok(EstablishedConnection::Tls(tls_stream))
     } else {
@@ -960,6 +1458,9 @@ pub enum EstablishedConnection {
     /// doesn't need to know which transport it's running over.
     #[cfg(feature = "websocket")]
     WebSocket(tokio::io::DuplexStream),
+    /// In-process stream with no network underneath, e.g. to a
+    /// [`crate::testing::MockServer`].
+    Memory(tokio::io::DuplexStream),
 }
 
 /// ALPN for IRC-over-iroh (must match server).
@@ -1060,11 +1561,7 @@ pub async fn discover_iroh_id(server_addr: &str, tls: bool, tls_insecure: bool)
         let tcp = TcpStream::connect(server_addr).await.ok()?;
 
         if use_tls {
-            let tls_config = if tls_insecure {
-                rustls_insecure_config()
-            } else {
-                rustls_default_config()
-            };
+            let tls_config = tls::client_config(&TlsOptions::default(), tls_insecure).ok()?;
             let connector = TlsConnector::from(Arc::new(tls_config));
             let host = server_addr.split(':').next().unwrap_or("localhost");
             let dns_name = rustls::pki_types::ServerName::try_from(host.to_string()).ok()?;
@@ -1128,15 +1625,26 @@ pub fn connect_with_stream(
     config: ConnectConfig,
     signer: Option<Arc<dyn ChallengeSigner>>,
 ) -> (ClientHandle, mpsc::Receiver<Event>) {
-    let (event_tx, event_rx) = mpsc::channel(4096);
+    let pipeline = Pipeline::default();
+    let (event_tx, event_rx) = pipeline.spawn();
+    let low_power = pipeline.low_power.clone();
     let (cmd_tx, cmd_rx) = mpsc::channel(256);
     let echo_registry: EchoRegistry = std::sync::Arc::new(parking_lot::Mutex::new(HashMap::new()));
     let caps_acked: CapsAcked = Arc::new(parking_lot::Mutex::new(HashSet::new()));
+    let pending = PendingReplies::default();
 
     let handle = ClientHandle {
         cmd_tx: cmd_tx.clone(),
         echo_registry: echo_registry.clone(),
         caps_acked: caps_acked.clone(),
+        presence: pipeline.presence,
+        pending: pending.clone(),
+        interceptors: pipeline.interceptors,
+        channels: pipeline.channels,
+        history: pipeline.history,
+        channel_lists: pipeline.channel_lists,
+        low_power: low_power.clone(),
+        negotiation: pipeline.negotiation,
     };
 
     let echo_reg = echo_registry.clone();
@@ -1155,10 +1663,14 @@ pub fn connect_with_stream(
                     cmd_rx,
                     echo_reg,
                     caps_for_loop,
+                    pending.clone(),
+                    low_power,
+                    None,
                 )
                 .await
             }
             EstablishedConnection::Tls(tls) => {
+                let tls_binding = tls::channel_binding(&tls);
                 let (reader, writer) = tokio::io::split(tls);
                 run_irc(
                     BufReader::new(reader),
@@ -1169,6 +1681,9 @@ pub fn connect_with_stream(
                     cmd_rx,
                     echo_reg,
                     caps_for_loop,
+                    pending.clone(),
+                    low_power,
+                    tls_binding,
                 )
                 .await
             }
@@ -1184,6 +1699,26 @@ pub fn connect_with_stream(
                     cmd_rx,
                     echo_reg,
                     caps_for_loop,
+                    pending.clone(),
+                    low_power,
+                    None,
+                )
+                .await
+            }
+            EstablishedConnection::Memory(duplex) => {
+                let (reader, writer) = tokio::io::split(duplex);
+                run_irc(
+                    BufReader::new(reader),
+                    writer,
+                    &config,
+                    signer,
+                    event_tx.clone(),
+                    cmd_rx,
+                    echo_reg,
+                    caps_for_loop,
+                    pending.clone(),
+                    low_power,
+                    None,
                 )
                 .await
             }
@@ -1199,10 +1734,14 @@ pub fn connect_with_stream(
                     cmd_rx,
                     echo_reg,
                     caps_for_loop,
+                    pending.clone(),
+                    low_power,
+                    None,
                 )
                 .await
             }
         };
+        pending.close();
         if let Err(e) = result {
             let _ = event_tx
                 .send(Event::Disconnected {
@@ -1226,30 +1765,44 @@ pub fn connect(
     config: ConnectConfig,
     signer: Option<Arc<dyn ChallengeSigner>>,
 ) -> (ClientHandle, mpsc::Receiver<Event>) {
-    let (event_tx, event_rx) = mpsc::channel(4096);
+    let pipeline = Pipeline::default();
+    let (event_tx, event_rx) = pipeline.spawn();
+    let low_power = pipeline.low_power.clone();
     let (cmd_tx, cmd_rx) = mpsc::channel(256);
     let echo_registry: EchoRegistry = std::sync::Arc::new(parking_lot::Mutex::new(HashMap::new()));
     let caps_acked: CapsAcked = Arc::new(parking_lot::Mutex::new(HashSet::new()));
+    let pending = PendingReplies::default();
 
     let handle = ClientHandle {
         cmd_tx: cmd_tx.clone(),
         echo_registry: echo_registry.clone(),
         caps_acked: caps_acked.clone(),
+        presence: pipeline.presence,
+        pending: pending.clone(),
+        interceptors: pipeline.interceptors,
+        channels: pipeline.channels,
+        history: pipeline.history,
+        channel_lists: pipeline.channel_lists,
+        low_power: low_power.clone(),
+        negotiation: pipeline.negotiation,
     };
 
     let echo_reg = echo_registry.clone();
     let caps_for_loop = caps_acked.clone();
     tokio::spawn(async move {
-        if let Err(e) = run_client(
+        let result = run_client(
             config,
             signer,
             event_tx.clone(),
             cmd_rx,
             echo_reg,
             caps_for_loop,
+            pending.clone(),
+            low_power,
         )
-        .await
-        {
+        .await;
+        pending.close();
+        if let Err(e) = result {
             let _ = event_tx
                 .send(Event::Disconnected {
                     reason: e.to_string(),
@@ -1268,6 +1821,8 @@ async fn run_client(
     cmd_rx: mpsc::Receiver<Command>,
     echo_registry: EchoRegistry,
     caps_acked: CapsAcked,
+    pending: PendingReplies,
+    low_power: LowPower,
 ) -> Result<()> {
     let conn = establish_connection(&config).await?;
     let _ = event_tx.send(Event::Connected).await;
@@ -1283,10 +1838,14 @@ async fn run_client(
                 cmd_rx,
                 echo_registry,
                 caps_acked,
+                pending,
+                low_power,
+                None,
             )
             .await
         }
         EstablishedConnection::Tls(tls) => {
+            let tls_binding = tls::channel_binding(&tls);
             let (reader, writer) = tokio::io::split(tls);
             run_irc(
                 BufReader::new(reader),
@@ -1297,6 +1856,9 @@ async fn run_client(
                 cmd_rx,
                 echo_registry,
                 caps_acked,
+                pending,
+                low_power,
+                tls_binding,
             )
             .await
         }
@@ -1312,6 +1874,26 @@ async fn run_client(
                 cmd_rx,
                 echo_registry,
                 caps_acked,
+                pending,
+                low_power,
+                None,
+            )
+            .await
+        }
+        EstablishedConnection::Memory(duplex) => {
+            let (reader, writer) = tokio::io::split(duplex);
+            run_irc(
+                BufReader::new(reader),
+                writer,
+                &config,
+                signer,
+                event_tx,
+                cmd_rx,
+                echo_registry,
+                caps_acked,
+                pending,
+                low_power,
+                None,
             )
             .await
         }
@@ -1327,6 +1909,9 @@ async fn run_client(
                 cmd_rx,
                 echo_registry,
                 caps_acked,
+                pending,
+                low_power,
+                None,
             )
             .await
         }
@@ -1342,34 +1927,78 @@ async fn run_client(
 /// `run_irc` are wrapped in `WsMessage::Text`, and inbound text/binary
 /// frames are written back into the duplex.
 #[cfg(feature = "websocket")]
-async fn establish_ws_connection(url: &str) -> Result<EstablishedConnection> {
+async fn establish_ws_connection(
+    url: &str,
+    config: &ConnectConfig,
+) -> Result<EstablishedConnection> {
     use futures_util::{SinkExt, StreamExt};
     use tokio::io::{AsyncReadExt, AsyncWriteExt};
     use tokio_tungstenite::tungstenite::Message as WsMessage;
-
-    // The rustls default crypto provider must be installed before any TLS
-    // handshake — including the one tokio-tungstenite does internally for
-    // wss://. The TCP-with-TLS path covers this in `rustls_default_config()`,
-    // but the WebSocket path bypasses that helper, so the install was being
-    // skipped and the wss handshake silently hung.
-    install_crypto_provider();
+    use tokio_tungstenite::tungstenite::{self, error::TlsError as WsTlsError};
+
+    // Same roots and pins as the TCP path. ALPN is left to the WebSocket
+    // handshake, which is HTTP/1.1 whatever IRC protocols are configured.
+    // Building the config also installs the rustls crypto provider, which
+    // must happen before tokio-tungstenite's internal wss:// handshake or
+    // it silently hangs.
+    let options = TlsOptions {
+        alpn: Vec::new(),
+        ..config.tls_options.clone()
+    };
+    let tls_config = tls::client_config(&options, config.tls_insecure)?;
+    let connector = tokio_tungstenite::Connector::Rustls(Arc::new(tls_config));
 
     tracing::debug!("Connecting WebSocket {url}...");
+    diagnostics::record(Stage::Connecting {
+        server: url.to_string(),
+        transport: "websocket",
+    });
+    let started = std::time::Instant::now();
     let connect_result = tokio::time::timeout(
         TRANSPORT_CONNECT_TIMEOUT,
-        tokio_tungstenite::connect_async(url),
+        tokio_tungstenite::connect_async_tls_with_config(url, None, false, Some(connector)),
     )
     .await;
+    let failed = |stage, error: &dyn std::fmt::Display| {
+        diagnostics::record(Stage::Failed {
+            stage,
+            ms: diagnostics::millis(started.elapsed()),
+            error: error.to_string(),
+        });
+    };
     let (ws, _resp) = match connect_result {
         Ok(Ok(pair)) => pair,
-        Ok(Err(e)) => return Err(anyhow::anyhow!("WebSocket connect to {url} failed: {e}")),
+        Ok(Err(tungstenite::Error::Tls(WsTlsError::Rustls(e)))) => {
+            let err = tls::classify(&e);
+            tracing::warn!("WebSocket TLS handshake with {url} failed: {err}");
+            failed("tls", &err);
+            return Err(err.into());
+        }
+        Ok(Err(tungstenite::Error::Io(e)))
+            if e.get_ref().is_some_and(|inner| inner.is::<rustls::Error>()) =>
+        {
+            let err = tls::classify_io(&e);
+            tracing::warn!("WebSocket TLS handshake with {url} failed: {err}");
+            failed("tls", &err);
+            return Err(err.into());
+        }
+        Ok(Err(e)) => {
+            let err = anyhow::anyhow!("WebSocket connect to {url} failed: {e}");
+            failed("websocket", &err);
+            return Err(err);
+        }
         Err(_) => {
-            return Err(anyhow::anyhow!(
+            let err = anyhow::anyhow!(
                 "WebSocket connect to {url} timed out after {}s",
                 TRANSPORT_CONNECT_TIMEOUT.as_secs()
-            ));
+            );
+            failed("websocket", &err);
+            return Err(err);
         }
     };
+    diagnostics::record(Stage::WebSocket {
+        ms: diagnostics::millis(started.elapsed()),
+    });
     tracing::debug!("WebSocket connected: {url}");
 
     // 64 KiB matches the JS transport's bufferedAmount threshold and gives
@@ -1466,93 +2095,6 @@ async fn establish_ws_connection(url: &str) -> Result<EstablishedConnection> {
     Ok(EstablishedConnection::WebSocket(client_side))
 }
 
-fn install_crypto_provider() {
-    // Install a crypto provider for rustls.
-    // ring is preferred (works on iOS); aws-lc-rs is the default on desktop.
-    #[cfg(feature = "ring")]
-    {
-        let _ = rustls::crypto::ring::default_provider().install_default();
-    }
-    #[cfg(all(feature = "aws-lc-rs", not(feature = "ring")))]
-    {
-        let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
-    }
-}
-
-fn rustls_default_config() -> rustls::ClientConfig {
-    install_crypto_provider();
-
-    let mut root_store =
-        rustls::RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
-
-    // Also load the system's native certificate store (covers CAs not in
-    // Mozilla's bundle, e.g. corporate/Sectigo intermediates).
-    let native = rustls_native_certs::load_native_certs();
-    if !native.errors.is_empty() {
-        tracing::warn!("Errors loading native certificates: {:?}", native.errors);
-    }
-    let before = root_store.len();
-    for cert in native.certs {
-        let _ = root_store.add(cert);
-    }
-    let added = root_store.len() - before;
-    if added > 0 {
-        tracing::debug!("Loaded {added} native root certificates");
-    }
-
-    rustls::ClientConfig::builder()
-        .with_root_certificates(root_store)
-        .with_no_client_auth()
-}
-
-fn rustls_insecure_config() -> rustls::ClientConfig {
-    install_crypto_provider();
-    rustls::ClientConfig::builder()
-        .dangerous()
-        .with_custom_certificate_verifier(Arc::new(InsecureVerifier))
-        .with_no_client_auth()
-}
-
-#[derive(Debug)]
-struct InsecureVerifier;
-
-impl rustls::client::danger::ServerCertVerifier for InsecureVerifier {
-    fn verify_server_cert(
-        &self,
-        _end_entity: &rustls::pki_types::CertificateDer<'_>,
-        _intermediates: &[rustls::pki_types::CertificateDer<'_>],
-        _server_name: &rustls::pki_types::ServerName<'_>,
-        _ocsp_response: &[u8],
-        _now: rustls::pki_types::UnixTime,
-    ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
-        Ok(rustls::client::danger::ServerCertVerified::assertion())
-    }
-
-    fn verify_tls12_signature(
-        &self,
-        _message: &[u8],
-        _cert: &rustls::pki_types::CertificateDer<'_>,
-        _dss: &rustls::DigitallySignedStruct,
-    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
-    }
-
-    fn verify_tls13_signature(
-        &self,
-        _message: &[u8],
-        _cert: &rustls::pki_types::CertificateDer<'_>,
-        _dss: &rustls::DigitallySignedStruct,
-    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
-    }
-
-    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
-        rustls::crypto::CryptoProvider::get_default()
-            .map(|p| p.signature_verification_algorithms.supported_schemes())
-            .unwrap_or_default()
-    }
-}
-
 async fn run_irc<R, W>(
     mut reader: R,
     mut writer: W,
@@ -1562,6 +2104,9 @@ async fn run_irc<R, W>(
     mut cmd_rx: mpsc::Receiver<Command>,
     echo_registry: EchoRegistry,
     caps_acked: CapsAcked,
+    pending: PendingReplies,
+    low_power: LowPower,
+    channel_binding: Option<String>,
 ) -> Result<()>
 where
     R: tokio::io::AsyncBufRead + Unpin,
@@ -1578,8 +2123,13 @@ where
         .await?;
 
     let mut sasl_in_progress = false;
+    let mut cap_negotiation = CapNegotiation::default();
     let mut registered = false;
     let mut nick_tries: u32 = 0;
+    // Our nick as the server knows it, and whether we're MONITORing the
+    // configured nick to take it back.
+    let mut current_nick = config.nick.clone();
+    let mut regaining = false;
     let mut web_token = config.web_token.clone();
     let mut authenticated_did: Option<String> = None;
     let mut pending_commands: Vec<Command> = Vec::new();
@@ -1593,13 +2143,16 @@ where
         std::collections::HashMap::new();
     let mut line_buf = String::new();
     let mut last_activity = tokio::time::Instant::now();
-    let ping_interval = tokio::time::Duration::from_secs(60);
-    let ping_timeout = tokio::time::Duration::from_secs(120);
+    let mut power_rx = low_power.subscribe();
+    let (mut ping_interval, mut ping_timeout) = power::keepalive(low_power.is_on());
     // Paced separately from `last_activity`: re-arming the timer off
     // `last_activity` alone busy-loops once the first keepalive fires
     // (the deadline stays in the past until inbound data arrives),
     // spamming PINGs for a full RTT — or for 60s into a dead socket.
     let mut next_ping = last_activity + ping_interval;
+    // Lookups held back in low-power mode, and when they go out.
+    let mut deferred: Vec<Command> = Vec::new();
+    let mut deferred_due: Option<tokio::time::Instant> = None;
 
     loop {
         tokio::select! {
@@ -1616,29 +2169,31 @@ where
                 let _ = event_tx.send(Event::RawLine(raw.clone())).await;
 
                 if let Some(msg) = Message::parse(&line_buf) {
+                    pending.observe(&msg, &current_nick);
                     match msg.command.as_str() {
                         // ERR_NICKNAMEINUSE
-                        "433" => {
-                            // Nickname is already in use; try a variant before registration completes.
-                            // Use base nick from config and append a short suffix.
-                            nick_tries = nick_tries.saturating_add(1);
-                            if nick_tries <= 5 {
-                                let base = &config.nick;
-                                let alt = if nick_tries == 1 {
-                                    format!("{}1", base)
+                        numeric::ERR_NICKNAMEINUSE => {
+                            // Nickname is already in use; try the next fallback before
+                            // registration completes. Afterwards a 433 only means a NICK
+                            // change (ours or a regain) failed, and we keep the nick we have.
+                            if !registered {
+                                nick_tries = nick_tries.saturating_add(1);
+                                if let Some(alt) = config.nick_fallback.candidate(&config.nick, nick_tries) {
+                                    writer.write_all(format!("NICK {alt}\r\n").as_bytes()).await?;
                                 } else {
-                                    format!("{}{}", base, nick_tries)
-                                };
-                                // Best-effort: attempt new nick immediately.
-                                writer.write_all(format!("NICK {}\r\n", alt).as_bytes()).await?;
-                            } else {
-                                // Give up; let reconnect logic handle it.
-                                let _ = event_tx.send(Event::Disconnected { reason: "Nick in use".to_string() }).await;
-                                break;
+                                    // Give up; let reconnect logic handle it.
+                                    let _ = event_tx.send(Event::Disconnected { reason: "Nick in use".to_string() }).await;
+                                    break;
+                                }
                             }
                         }
                         "CAP" => {
-                            handle_cap_response(&msg, &signer, &web_token, &mut writer, &mut sasl_in_progress, &caps_acked).await?;
+                            let subcmd = msg.params.get(1).map(|s| s.to_ascii_uppercase());
+                            if registered || subcmd.as_deref() == Some("DEL") {
+                                handle_cap_change(&msg, &config.capabilities, &mut writer, &caps_acked, &event_tx).await?;
+                            } else {
+                                handle_cap_response(&msg, &config.capabilities, &signer, &web_token, &mut writer, &mut sasl_in_progress, &mut cap_negotiation, &caps_acked, &event_tx).await?;
+                            }
                         }
                         "AUTHENTICATE" => {
                             if let Some(ref token) = web_token {
@@ -1664,7 +2219,7 @@ where
                                     writer.write_all(format!("AUTHENTICATE {encoded}\r\n").as_bytes()).await?;
                                 }
                             } else if let Some(ref signer) = signer {
-                                handle_authenticate_challenge(&msg, signer.as_ref(), &mut writer).await?;
+                                handle_authenticate_challenge(&msg, signer.as_ref(), channel_binding.as_deref(), &mut writer).await?;
                             }
                         }
                         // Handle DPOP_NONCE notice during SASL — update signer nonce
@@ -1677,7 +2232,7 @@ where
                                     // Server will re-issue AUTHENTICATE challenge next
                         }
                         // 900 RPL_LOGGEDIN — server tells us our authenticated DID
-                        "900" => {
+                        numeric::RPL_LOGGEDIN => {
                             // :server 900 nick :You are now logged in as did:plc:...
                             if let Some(text) = msg.params.last()
                                 && let Some(did) = text.split("as ").last() {
@@ -1687,7 +2242,7 @@ where
                                     }
                                 }
                         }
-                        "903" => {
+                        numeric::RPL_SASLSUCCESS => {
                             sasl_in_progress = false;
                             let did = authenticated_did.take()
                                 .or_else(|| signer.as_ref().map(|s| s.did().to_string()))
@@ -1706,7 +2261,7 @@ where
                             web_token = None;
                             writer.write_all(b"CAP END\r\n").await?;
                         }
-                        "904" => {
+                        numeric::ERR_SASLFAIL => {
                             sasl_in_progress = false;
                             let reason = msg.params.get(1).cloned().unwrap_or_else(|| "Unknown".to_string());
                             // eprintln!("  SASL authentication FAILED: {reason}");
@@ -1763,23 +2318,29 @@ where
                                 }
                             }
                         }
-                        "001" => {
+                        numeric::RPL_WELCOME => {
                             let nick = msg.params.first().cloned().unwrap_or_default();
-                            let _ = event_tx.send(Event::Registered { nick }).await;
+                            current_nick = nick.clone();
+                            let _ = event_tx.send(Event::Registered { nick: nick.clone() }).await;
+                            let _ = event_tx.send(Event::NickAssigned { nick: nick.clone() }).await;
                             registered = true;
+                            if config.nick_fallback.regain && !nick.eq_ignore_ascii_case(&config.nick) {
+                                writer.write_all(format!("MONITOR + {}\r\n", config.nick).as_bytes()).await?;
+                                regaining = true;
+                            }
                             // Flush any commands that were queued before registration
                             for cmd in pending_commands.drain(..) {
                                 execute_command(&mut writer, cmd, &msg_signing_key, &msg_signing_did).await?;
                             }
                         }
-                        "353" => {
+                        numeric::RPL_NAMREPLY => {
                             if msg.params.len() >= 4 {
                                 let channel = msg.params[2].clone();
                                 let nicks: Vec<String> = msg.params[3].split_whitespace().map(|s| s.to_string()).collect();
                                 let _ = event_tx.send(Event::Names { channel, nicks }).await;
                             }
                         }
-                        "366" => {
+                        numeric::RPL_ENDOFNAMES => {
                             // RPL_ENDOFNAMES
                             if msg.params.len() >= 2 {
                                 let channel = msg.params[1].clone();
@@ -1818,7 +2379,29 @@ where
                                 .to_string();
                             let new_nick = msg.params.first().cloned().unwrap_or_default();
                             if !old_nick.is_empty() && !new_nick.is_empty() {
-                                let _ = event_tx.send(Event::NickChanged { old_nick, new_nick }).await;
+                                let is_us = old_nick.eq_ignore_ascii_case(&current_nick);
+                                let _ = event_tx.send(Event::NickChanged { old_nick, new_nick: new_nick.clone() }).await;
+                                if is_us {
+                                    current_nick = new_nick.clone();
+                                    // Regained, or renamed on purpose: either way stop
+                                    // chasing the configured nick.
+                                    if regaining {
+                                        writer.write_all(format!("MONITOR - {}\r\n", config.nick).as_bytes()).await?;
+                                        regaining = false;
+                                    }
+                                    let _ = event_tx.send(Event::NickAssigned { nick: new_nick }).await;
+                                }
+                            }
+                        }
+                        numeric::RPL_MONOFFLINE if regaining => {
+                            // `<me> :nick[!user@host],…` — the configured nick is free.
+                            let freed = msg.params.get(1).is_some_and(|list| {
+                                list.split(',').any(|entry| {
+                                    entry.split('!').next().is_some_and(|n| n.eq_ignore_ascii_case(&config.nick))
+                                })
+                            });
+                            if freed {
+                                writer.write_all(format!("NICK {}\r\n", config.nick).as_bytes()).await?;
                             }
                         }
                         // MODE change
@@ -1894,7 +2477,7 @@ where
                             }
                         }
                         // RPL_TOPIC (on join or TOPIC query)
-                        "332" => {
+                        numeric::RPL_TOPIC => {
                             if msg.params.len() >= 3 {
                                 let channel = msg.params[1].clone();
                                 let topic = msg.params[2].clone();
@@ -1905,14 +2488,14 @@ where
                                 }).await;
                             }
                         }
-                        "331" => {
+                        numeric::RPL_NOTOPIC => {
                             // RPL_NOTOPIC — no topic set, ignore or clear
                         }
-                        "333" => {
+                        numeric::RPL_TOPICWHOTIME => {
                             // RPL_TOPICWHOTIME — ignore for now (info only)
                         }
                         // WHOIS numerics
-                        "311" => {
+                        numeric::RPL_WHOISUSER => {
                             // RPL_WHOISUSER: <nick> <user> <host> * :<realname>
                             if msg.params.len() >= 5 {
                                 let nick = msg.params[1].clone();
@@ -1923,7 +2506,7 @@ where
                                 let _ = event_tx.send(Event::WhoisReply { nick, info }).await;
                             }
                         }
-                        "312" => {
+                        numeric::RPL_WHOISSERVER => {
                             // RPL_WHOISSERVER: <nick> <server> :<server info>
                             if msg.params.len() >= 4 {
                                 let nick = msg.params[1].clone();
@@ -1933,7 +2516,7 @@ where
                                 let _ = event_tx.send(Event::WhoisReply { nick, info }).await;
                             }
                         }
-                        "319" => {
+                        numeric::RPL_WHOISCHANNELS => {
                             // RPL_WHOISCHANNELS: <nick> :<channels>
                             if msg.params.len() >= 3 {
                                 let nick = msg.params[1].clone();
@@ -1941,7 +2524,7 @@ where
                                 let _ = event_tx.send(Event::WhoisReply { nick, info }).await;
                             }
                         }
-                        "330" => {
+                        numeric::RPL_WHOISACCOUNT => {
                             // RPL_WHOISACCOUNT: <nick> <account> :is logged in as
                             if msg.params.len() >= 3 {
                                 let nick = msg.params[1].clone();
@@ -1951,10 +2534,10 @@ where
                                 let _ = event_tx.send(Event::WhoisReply { nick, info }).await;
                             }
                         }
-                        "318" => {
+                        numeric::RPL_ENDOFWHOIS => {
                             // RPL_ENDOFWHOIS — ignore silently
                         }
-                        "401" => {
+                        numeric::ERR_NOSUCHNICK => {
                             // ERR_NOSUCHNICK
                             if msg.params.len() >= 3 {
                                 let nick = msg.params[1].clone();
@@ -2009,7 +2592,8 @@ where
                                         .to_string();
                                     let target = msg.params[0].clone();
                                     let mut text = msg.params[1].clone();
-                                    let tags = msg.tags.clone();
+                                    let mut tags = msg.tags.clone();
+                                    crate::profile::ensure_msgid(&mut tags);
 
                                     // Legacy `+freeq.at/multiline`: pre-spec
                                     // wire encoded `\n` as the literal two
@@ -2029,7 +2613,12 @@ where
                                         let _ = tx.send(msgid.clone());
                                     }
 
-                                    let _ = event_tx.send(Event::Message { from, target, text, tags }).await;
+                                    let thread_reply = thread_reply_event(&from, &target, &text, &tags);
+                                    let formatted_text = crate::format::formatted_text(&text);
+                                    let _ = event_tx.send(Event::Message { from, target, text, tags, formatted_text, encrypted: false }).await;
+                                    if let Some(event) = thread_reply {
+                                        let _ = event_tx.send(event).await;
+                                    }
                                 }
                             }
                         }
@@ -2099,7 +2688,13 @@ where
                 line_buf.clear();
             }
             Some(cmd) = cmd_rx.recv() => {
-                if registered || matches!(cmd, Command::Quit(_)) {
+                if registered
+                    && low_power.is_on()
+                    && matches!(&cmd, Command::Raw(line) if power::is_deferrable(line))
+                {
+                    deferred.push(cmd);
+                    deferred_due.get_or_insert_with(|| tokio::time::Instant::now() + power::BATCH_INTERVAL);
+                } else if registered || matches!(cmd, Command::Quit(_)) {
                     execute_command(&mut writer, cmd, &msg_signing_key, &msg_signing_did).await?;
                     if !registered {
                         break; // Quit before registration
@@ -2118,12 +2713,48 @@ where
                 writer.write_all(b"PING :keepalive\r\n").await?;
                 next_ping = tokio::time::Instant::now() + ping_interval;
             }
+            _ = tokio::time::sleep_until(deferred_due.unwrap_or(next_ping)), if deferred_due.is_some() => {
+                deferred_due = None;
+                for cmd in std::mem::take(&mut deferred) {
+                    execute_command(&mut writer, cmd, &msg_signing_key, &msg_signing_did).await?;
+                }
+            }
+            Ok(()) = power_rx.changed() => {
+                let on = *power_rx.borrow_and_update();
+                (ping_interval, ping_timeout) = power::keepalive(on);
+                next_ping = last_activity + ping_interval;
+                if !on {
+                    deferred_due = None;
+                    for cmd in std::mem::take(&mut deferred) {
+                        execute_command(&mut writer, cmd, &msg_signing_key, &msg_signing_did).await?;
+                    }
+                }
+            }
         }
     }
 
     Ok(())
 }
 
+/// The `ThreadReply` for a message, if the server marked it as a reply in
+/// a thread (`+reply` plus `+freeq.at/thread`).
+fn thread_reply_event(
+    from: &str,
+    target: &str,
+    text: &str,
+    tags: &HashMap<String, String>,
+) -> Option<Event> {
+    let parent = tags.get("+reply").or_else(|| tags.get("+draft/reply"))?;
+    Some(Event::ThreadReply {
+        from: from.to_string(),
+        target: target.to_string(),
+        text: text.to_string(),
+        msgid: tags.get("msgid")?.clone(),
+        parent_msgid: parent.clone(),
+        root_msgid: tags.get("+freeq.at/thread")?.clone(),
+    })
+}
+
 /// Assemble a closed `draft/multiline` batch into a single
 /// `Event::Message` per the spec's concat rules — a chunk with
 /// `+draft/multiline-concat` joins its predecessor with no separator;
@@ -2144,14 +2775,21 @@ async fn dispatch_assembled_multiline(
     if let Some(parent_batch_id) = batch.parent_batch_id {
         tags.insert("batch".to_string(), parent_batch_id);
     }
+    crate::profile::ensure_msgid(&mut tags);
+    let thread_reply = thread_reply_event(&batch.from, &batch.target, &text, &tags);
     let _ = event_tx
         .send(Event::Message {
             from: batch.from,
             target: batch.target,
+            formatted_text: crate::format::formatted_text(&text),
             text,
             tags,
+            encrypted: false,
         })
         .await;
+    if let Some(event) = thread_reply {
+        let _ = event_tx.send(event).await;
+    }
     // Nested-batch parent (e.g. multiline inside CHATHISTORY) is
     // exposed to the consumer via the `batch` tag so UI layers can
     // attach the assembled message to the outer batch.
@@ -2296,73 +2934,204 @@ async fn execute_command<W: AsyncWrite + Unpin>(
     Ok(())
 }
 
+/// Registration-time capability negotiation: what the server offers
+/// (`CAP LS 302` may take several lines) and how many `CAP REQ`s are
+/// still unanswered.
+#[derive(Debug, Default)]
+struct CapNegotiation {
+    offered: HashSet<String>,
+    replies_pending: usize,
+    rejected: Vec<String>,
+    unavailable: Vec<String>,
+}
+
+impl CapNegotiation {
+    /// One `CAP REQ` was answered. After the last, end negotiation unless
+    /// SASL is under way (its outcome sends `CAP END`), and report.
+    async fn answered<W: AsyncWrite + Unpin>(
+        &mut self,
+        writer: &mut W,
+        sasl_in_progress: bool,
+        caps_acked: &CapsAcked,
+        event_tx: &mpsc::Sender<Event>,
+    ) -> Result<()> {
+        let Some(left) = self.replies_pending.checked_sub(1) else {
+            return Ok(());
+        };
+        self.replies_pending = left;
+        if left == 0 {
+            if !sasl_in_progress {
+                writer.write_all(b"CAP END\r\n").await?;
+            }
+            self.report(caps_acked, event_tx).await;
+        }
+        Ok(())
+    }
+
+    async fn report(&mut self, caps_acked: &CapsAcked, event_tx: &mpsc::Sender<Event>) {
+        let event = Event::CapabilitiesAcked {
+            acked: sorted_caps(caps_acked),
+            rejected: std::mem::take(&mut self.rejected),
+            unavailable: std::mem::take(&mut self.unavailable),
+        };
+        let _ = event_tx.send(event).await;
+    }
+}
+
+/// The acknowledged capabilities, sorted.
+fn sorted_caps(caps_acked: &CapsAcked) -> Vec<String> {
+    let mut acked: Vec<String> = caps_acked.lock().iter().cloned().collect();
+    acked.sort();
+    acked
+}
+
+/// `CAP NEW`, `CAP DEL`, and the `CAP ACK` or `NAK` answering a request
+/// made after registration (cap-notify). New capabilities in
+/// `capabilities` are requested; dropped ones stop counting as
+/// acknowledged. None of it touches registration: no `CAP END`, no SASL.
+async fn handle_cap_change<W: AsyncWrite + Unpin>(
+    msg: &Message,
+    capabilities: &Capabilities,
+    writer: &mut W,
+    caps_acked: &CapsAcked,
+    event_tx: &mpsc::Sender<Event>,
+) -> Result<()> {
+    let subcmd = msg.params.get(1).map(|s| s.to_ascii_uppercase());
+    let list = msg.params.last().map(|s| s.as_str()).unwrap_or("");
+    let names = crate::capabilities::offered(list);
+    match subcmd.as_deref() {
+        Some("NEW") => {
+            let wanted: Vec<&str> = {
+                let acked = caps_acked.lock();
+                names
+                    .filter(|cap| capabilities.contains(cap) && !acked.contains(*cap))
+                    .collect()
+            };
+            if !wanted.is_empty() {
+                let req = format!("CAP REQ :{}\r\n", wanted.join(" "));
+                writer.write_all(req.as_bytes()).await?;
+            }
+        }
+        Some("DEL") => {
+            let mut acked = caps_acked.lock();
+            for cap in names {
+                acked.remove(cap);
+            }
+        }
+        Some("ACK") => {
+            {
+                let mut acked = caps_acked.lock();
+                for cap in names {
+                    match cap.strip_prefix('-') {
+                        Some(disabled) => acked.remove(disabled),
+                        None => acked.insert(cap.to_string()),
+                    };
+                }
+            }
+            let event = Event::CapabilitiesAcked {
+                acked: sorted_caps(caps_acked),
+                rejected: Vec::new(),
+                unavailable: Vec::new(),
+            };
+            let _ = event_tx.send(event).await;
+        }
+        Some("NAK") => {
+            let event = Event::CapabilitiesAcked {
+                acked: sorted_caps(caps_acked),
+                rejected: names.map(str::to_string).collect(),
+                unavailable: Vec::new(),
+            };
+            let _ = event_tx.send(event).await;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
 async fn handle_cap_response<W: AsyncWrite + Unpin>(
     msg: &Message,
+    capabilities: &Capabilities,
     signer: &Option<Arc<dyn ChallengeSigner>>,
     web_token: &Option<String>,
     writer: &mut W,
     sasl_in_progress: &mut bool,
+    negotiation: &mut CapNegotiation,
     caps_acked: &CapsAcked,
+    event_tx: &mpsc::Sender<Event>,
 ) -> Result<()> {
     let subcmd = msg.params.get(1).map(|s| s.to_ascii_uppercase());
+    let list = msg.params.last().map(|s| s.as_str()).unwrap_or("");
     match subcmd.as_deref() {
         Some("LS") => {
-            let caps_str = msg.params.last().map(|s| s.as_str()).unwrap_or("");
-            let mut req_caps = Vec::new();
-            if caps_str.contains("message-tags") {
-                req_caps.push("message-tags");
+            let offered = &mut negotiation.offered;
+            offered.extend(crate::capabilities::offered(list).map(str::to_string));
+            // `CAP * LS * :…` — more lines follow
+            if msg.params.len() > 3 && msg.params[2] == "*" {
+                return Ok(());
+            }
+            // Ours and the app's go in separate requests, so a server
+            // refusing one the app added doesn't cost the SDK its own.
+            let mut sdk_caps = Vec::new();
+            let mut app_caps = Vec::new();
+            for cap in capabilities.iter() {
+                if !offered.contains(cap.name()) {
+                    negotiation.unavailable.push(cap.name().to_string());
+                } else if cap.is_other() {
+                    app_caps.push(cap.name());
+                } else {
+                    sdk_caps.push(cap.name());
+                }
             }
-            for cap in &[
-                "server-time",
-                "batch",
-                "echo-message",
-                "away-notify",
-                "account-notify",
-                "account-tag",
-                "extended-join",
-                "draft/chathistory",
-                "draft/multiline",
-            ] {
-                if caps_str.contains(cap) {
-                    req_caps.push(cap);
+            if offered.contains(caps::SASL) && (signer.is_some() || web_token.is_some()) {
+                sdk_caps.push(caps::SASL);
+                // Direct DM negotiation is only brokered between
+                // authenticated users; see `crate::p2p_dm`.
+                if offered.contains(caps::P2P_DM) {
+                    sdk_caps.push(caps::P2P_DM);
                 }
             }
-            if caps_str.contains("sasl") && (signer.is_some() || web_token.is_some()) {
-                req_caps.push("sasl");
+            for req_caps in [sdk_caps, app_caps] {
+                if !req_caps.is_empty() {
+                    let req = format!("CAP REQ :{}\r\n", req_caps.join(" "));
+                    writer.write_all(req.as_bytes()).await?;
+                    negotiation.replies_pending += 1;
+                }
             }
-            if req_caps.is_empty() {
-                // eprintln!("  No caps to request, sending CAP END");
+            if negotiation.replies_pending == 0 {
                 writer.write_all(b"CAP END\r\n").await?;
-            } else {
-                // eprintln!("  Requesting: {}", req_caps.join(" "));
-                let req = format!("CAP REQ :{}\r\n", req_caps.join(" "));
-                writer.write_all(req.as_bytes()).await?;
+                negotiation.report(caps_acked, event_tx).await;
             }
         }
         Some("ACK") => {
-            let caps = msg.params.last().map(|s| s.as_str()).unwrap_or("");
             // Record which caps the server ACKed so `ClientHandle::privmsg`
             // can route `\n`-bearing text to a draft/multiline BATCH.
+            let mut sasl = false;
             {
                 let mut acked = caps_acked.lock();
-                for cap in caps.split_whitespace() {
+                for cap in list.split_whitespace() {
+                    sasl |= cap == caps::SASL;
                     acked.insert(cap.to_string());
                 }
             }
-            if caps.contains("sasl") {
+            if sasl {
                 *sasl_in_progress = true;
                 // Both web-token and ATPROTO-CHALLENGE use the same SASL mechanism;
                 // the method field in the JSON payload distinguishes them.
                 writer
                     .write_all(b"AUTHENTICATE ATPROTO-CHALLENGE\r\n")
                     .await?;
-            } else {
-                writer.write_all(b"CAP END\r\n").await?;
             }
+            negotiation
+                .answered(writer, *sasl_in_progress, caps_acked, event_tx)
+                .await?;
         }
         Some("NAK") => {
-            // eprintln!("  Capabilities rejected, sending CAP END");
-            writer.write_all(b"CAP END\r\n").await?;
+            negotiation
+                .rejected
+                .extend(list.split_whitespace().map(str::to_string));
+            negotiation
+                .answered(writer, *sasl_in_progress, caps_acked, event_tx)
+                .await?;
         }
         _ => {}
     }
@@ -2372,6 +3141,7 @@ async fn handle_cap_response<W: AsyncWrite + Unpin>(
 async fn handle_authenticate_challenge<W: AsyncWrite + Unpin>(
     msg: &Message,
     signer: &dyn ChallengeSigner,
+    channel_binding: Option<&str>,
     writer: &mut W,
 ) -> Result<()> {
     let encoded_challenge = msg.params.first().map(|s| s.as_str()).unwrap_or("");
@@ -2381,8 +3151,15 @@ async fn handle_authenticate_challenge<W: AsyncWrite + Unpin>(
     let challenge_bytes = auth::decode_challenge_bytes(encoded_challenge)?;
     // eprintln!("  Challenge decoded ({} bytes), signing with {}...", challenge_bytes.len(), signer.did());
 
+    // Report our own view of the channel binding only to servers that
+    // check it (they say so by binding the challenge); older servers
+    // verify the signature over the bare challenge.
+    let server_binds = auth::decode_challenge(encoded_challenge)
+        .is_ok_and(|challenge| challenge.binding.is_some());
+    let binding = channel_binding.filter(|_| server_binds);
+
     // Produce the response using the signer
-    let response = signer.respond(&challenge_bytes)?;
+    let response = signer.respond_bound(&challenge_bytes, binding)?;
     let encoded = auth::encode_response(&response);
     // eprintln!("  Sending AUTHENTICATE response ({} bytes)", encoded.len());
 
@@ -2879,6 +3656,14 @@ mod multiline_tests {
             cmd_tx,
             echo_registry: Arc::new(parking_lot::Mutex::new(HashMap::new())),
             caps_acked,
+            presence: Default::default(),
+            pending: Default::default(),
+            interceptors: Default::default(),
+            channels: Default::default(),
+            history: Default::default(),
+            channel_lists: Default::default(),
+            low_power: Default::default(),
+            negotiation: Default::default(),
         };
         handle.privmsg("#test", "alpha\nbeta\ngamma").await.unwrap();
         match cmd_rx.recv().await.unwrap() {
@@ -2912,6 +3697,14 @@ mod multiline_tests {
             cmd_tx,
             echo_registry: Arc::new(parking_lot::Mutex::new(HashMap::new())),
             caps_acked,
+            presence: Default::default(),
+            pending: Default::default(),
+            interceptors: Default::default(),
+            channels: Default::default(),
+            history: Default::default(),
+            channel_lists: Default::default(),
+            low_power: Default::default(),
+            negotiation: Default::default(),
         };
         handle.privmsg("#test", "a\nb").await.unwrap();
         match cmd_rx.recv().await.unwrap() {
@@ -2923,6 +3716,46 @@ mod multiline_tests {
         }
     }
 
+    /// Once registration shows the server has no `draft/chathistory`, the
+    /// history calls fail up front instead of waiting for a batch.
+    #[tokio::test]
+    async fn history_is_unsupported_without_chathistory() {
+        let (cmd_tx, mut cmd_rx) = mpsc::channel(16);
+        let caps_acked: CapsAcked = Arc::new(parking_lot::Mutex::new(HashSet::new()));
+        caps_acked.lock().insert("message-tags".to_string());
+        let negotiation = Negotiation::default();
+        let handle = ClientHandle {
+            cmd_tx,
+            echo_registry: Arc::new(parking_lot::Mutex::new(HashMap::new())),
+            caps_acked: caps_acked.clone(),
+            presence: Default::default(),
+            pending: Default::default(),
+            interceptors: Default::default(),
+            channels: Default::default(),
+            history: Default::default(),
+            channel_lists: Default::default(),
+            low_power: Default::default(),
+            negotiation: negotiation.clone(),
+        };
+        assert_eq!(handle.server_profile(), None);
+        handle.history_latest("#test", 10).await.unwrap();
+        assert!(matches!(cmd_rx.recv().await, Some(Command::Raw(_))));
+
+        negotiation.observe(&Event::Registered { nick: "me".into() });
+        assert_eq!(handle.server_profile(), Some(ServerProfile::Ircv3Basic));
+        assert!(!handle.has_history());
+        let err = handle
+            .fetch_history("#test", None, 10, CallOptions::default())
+            .await
+            .unwrap_err();
+        assert_eq!(err, CallError::Unsupported(caps::CHATHISTORY));
+        assert!(handle.history_latest("#test", 10).await.is_err());
+        assert!(cmd_rx.try_recv().is_err(), "nothing was sent");
+
+        caps_acked.lock().insert("draft/chathistory".to_string());
+        assert!(handle.has_history());
+    }
+
     /// send_tagged with `\n`-bearing text auto-routes to SendMultiline
     /// with the caller's tags moved onto the BATCH opener — preserving
     /// the tag semantics (e.g. commit-reveal payloads) under the
@@ -2937,6 +3770,14 @@ mod multiline_tests {
             cmd_tx,
             echo_registry: Arc::new(parking_lot::Mutex::new(HashMap::new())),
             caps_acked,
+            presence: Default::default(),
+            pending: Default::default(),
+            interceptors: Default::default(),
+            channels: Default::default(),
+            history: Default::default(),
+            channel_lists: Default::default(),
+            low_power: Default::default(),
+            negotiation: Default::default(),
         };
         let mut tags = std::collections::HashMap::new();
         tags.insert("+freeq.at/event".to_string(), "reveal".to_string());
@@ -2975,6 +3816,14 @@ mod multiline_tests {
             cmd_tx,
             echo_registry: Arc::new(parking_lot::Mutex::new(HashMap::new())),
             caps_acked,
+            presence: Default::default(),
+            pending: Default::default(),
+            interceptors: Default::default(),
+            channels: Default::default(),
+            history: Default::default(),
+            channel_lists: Default::default(),
+            low_power: Default::default(),
+            negotiation: Default::default(),
         };
         handle.privmsg("#test", "hello world").await.unwrap();
         match cmd_rx.recv().await.unwrap() {
@@ -3008,8 +3857,11 @@ mod multiline_tests {
             realname: "tester".to_string(),
             tls: false,
             tls_insecure: false,
+            tls_options: Default::default(),
             web_token: None,
             websocket_url: None,
+            nick_fallback: Default::default(),
+            capabilities: Default::default(),
         };
         let (reader, writer) = tokio::io::split(client_side);
 
@@ -3023,6 +3875,9 @@ mod multiline_tests {
                 cmd_rx,
                 echo_registry,
                 caps_acked,
+                PendingReplies::default(),
+                Default::default(),
+                None,
             )
             .await;
         });
@@ -3121,8 +3976,11 @@ mod multiline_tests {
             realname: "tester".to_string(),
             tls: false,
             tls_insecure: false,
+            tls_options: Default::default(),
             web_token: None,
             websocket_url: None,
+            nick_fallback: Default::default(),
+            capabilities: Default::default(),
         };
         let (reader, writer) = tokio::io::split(client_side);
 
@@ -3136,6 +3994,9 @@ mod multiline_tests {
                 cmd_rx,
                 echo_registry,
                 caps_acked,
+                PendingReplies::default(),
+                Default::default(),
+                None,
             )
             .await;
         });
@@ -3271,8 +4132,11 @@ mod irc_loop_tests {
             realname: "Test User".to_string(),
             tls: false,
             tls_insecure: false,
+            tls_options: Default::default(),
             web_token: None,
             websocket_url: None,
+            nick_fallback: Default::default(),
+            capabilities: Default::default(),
         }
     }
 
@@ -3284,13 +4148,23 @@ mod irc_loop_tests {
         tokio::io::DuplexStream, // "server side" – we write IRC lines here
         mpsc::Receiver<Event>,
         mpsc::Sender<Command>,
+    ) {
+        start_run_irc_with(test_config(nick)).await
+    }
+
+    /// [`start_run_irc`] with a caller-built config.
+    async fn start_run_irc_with(
+        config: ConnectConfig,
+    ) -> (
+        tokio::io::DuplexStream,
+        mpsc::Receiver<Event>,
+        mpsc::Sender<Command>,
     ) {
         let (client_side, server_side) = tokio::io::duplex(16_384);
         let (event_tx, event_rx) = mpsc::channel::<Event>(64);
         let (cmd_tx, cmd_rx) = mpsc::channel::<Command>(64);
         let echo_registry: EchoRegistry = Arc::new(parking_lot::Mutex::new(HashMap::new()));
         let caps_acked: CapsAcked = Arc::new(parking_lot::Mutex::new(HashSet::new()));
-        let config = test_config(nick);
 
         let (reader, writer) = tokio::io::split(client_side);
         tokio::spawn(async move {
@@ -3303,6 +4177,9 @@ mod irc_loop_tests {
                 cmd_rx,
                 echo_registry,
                 caps_acked,
+                PendingReplies::default(),
+                Default::default(),
+                None,
             )
             .await;
         });
@@ -3515,6 +4392,138 @@ mod irc_loop_tests {
         );
     }
 
+    #[test]
+    fn nick_fallback_candidates() {
+        let fallback = NickFallback {
+            alternatives: vec!["frank_away".to_string()],
+            suffix: NickSuffix::Underscore,
+            regain: true,
+        };
+        assert_eq!(fallback.candidate("frank", 0), None);
+        assert_eq!(
+            fallback.candidate("frank", 1).as_deref(),
+            Some("frank_away")
+        );
+        assert_eq!(fallback.candidate("frank", 2).as_deref(), Some("frank_"));
+        assert_eq!(
+            fallback.candidate("frank", 6).as_deref(),
+            Some("frank_____")
+        );
+        assert_eq!(fallback.candidate("frank", 7), None);
+
+        let numbered = NickFallback::default();
+        assert_eq!(numbered.candidate("frank", 3).as_deref(), Some("frank3"));
+
+        let none = NickFallback {
+            suffix: NickSuffix::None,
+            ..fallback.clone()
+        };
+        assert_eq!(none.candidate("frank", 2), None);
+
+        let bad = NickFallback {
+            alternatives: vec!["no spaces".to_string()],
+            ..fallback
+        };
+        assert!(bad.validate().is_err());
+    }
+
+    /// Configured alternatives are tried before generated nicks.
+    #[tokio::test]
+    async fn nick_in_use_tries_alternatives_first() {
+        let mut config = test_config("grace");
+        config.nick_fallback.alternatives = vec!["grace_h".to_string()];
+        let (mut server, _events, _cmd) = start_run_irc_with(config).await;
+
+        server
+            .write_all(b":srv 433 * grace :Nickname is already in use\r\n")
+            .await
+            .unwrap();
+        server.flush().await.unwrap();
+
+        let mut buf = vec![0u8; 256];
+        let n = tokio::time::timeout(
+            tokio::time::Duration::from_millis(400),
+            server.read(&mut buf),
+        )
+        .await
+        .expect("timeout waiting for NICK retry")
+        .unwrap_or(0);
+
+        let wire = String::from_utf8_lossy(&buf[..n]);
+        assert!(
+            wire.contains("NICK grace_h"),
+            "expected NICK grace_h, got:\n{wire}"
+        );
+    }
+
+    /// Registered on a fallback nick: MONITOR the desired one, take it back
+    /// when it goes offline, then stop monitoring.
+    #[tokio::test]
+    async fn fallback_nick_is_regained_when_free() {
+        let (mut server, mut events, _cmd) = start_run_irc("heidi").await;
+
+        async fn read_wire(server: &mut tokio::io::DuplexStream) -> String {
+            let mut buf = vec![0u8; 512];
+            let n = tokio::time::timeout(
+                tokio::time::Duration::from_millis(400),
+                server.read(&mut buf),
+            )
+            .await
+            .expect("timeout waiting for client output")
+            .unwrap_or(0);
+            String::from_utf8_lossy(&buf[..n]).into_owned()
+        }
+
+        server
+            .write_all(b":srv 001 heidi1 :Welcome to IRC\r\n")
+            .await
+            .unwrap();
+        server.flush().await.unwrap();
+        let wire = read_wire(&mut server).await;
+        assert!(
+            wire.contains("MONITOR + heidi"),
+            "expected MONITOR + heidi, got:\n{wire}"
+        );
+
+        server
+            .write_all(b":srv 731 heidi1 :heidi\r\n")
+            .await
+            .unwrap();
+        server.flush().await.unwrap();
+        let wire = read_wire(&mut server).await;
+        assert!(
+            wire.contains("NICK heidi"),
+            "expected NICK heidi, got:\n{wire}"
+        );
+
+        server
+            .write_all(b":heidi1!u@h NICK :heidi\r\n")
+            .await
+            .unwrap();
+        server.flush().await.unwrap();
+        let wire = read_wire(&mut server).await;
+        assert!(
+            wire.contains("MONITOR - heidi"),
+            "expected MONITOR - heidi, got:\n{wire}"
+        );
+
+        let assigned = tokio::time::timeout(tokio::time::Duration::from_millis(400), async {
+            let mut nicks = Vec::new();
+            while let Some(ev) = events.recv().await {
+                if let Event::NickAssigned { nick } = ev {
+                    nicks.push(nick);
+                    if nicks.len() == 2 {
+                        break;
+                    }
+                }
+            }
+            nicks
+        })
+        .await
+        .expect("timeout waiting for NickAssigned");
+        assert_eq!(assigned, ["heidi1", "heidi"]);
+    }
+
     // ── 904 SASL failure ─────────────────────────────────────────────────────
 
     /// 904 must emit Event::AuthFailed and send CAP END so the server can
@@ -4183,6 +5192,53 @@ mod irc_loop_tests {
         );
     }
 
+    // ── threads ───────────────────────────────────────────────────────────────
+
+    /// A reply stamped with the server's thread root emits ThreadReply right
+    /// after its Message.
+    #[tokio::test]
+    async fn thread_tagged_privmsg_emits_thread_reply() {
+        let (mut server, mut events, _cmd) = start_run_irc("host15b").await;
+
+        server
+            .write_all(
+                b"@msgid=m3;+reply=m2;+freeq.at/thread=m1 :alice!u@h PRIVMSG #room :agreed\r\n",
+            )
+            .await
+            .unwrap();
+        server.flush().await.unwrap();
+
+        let got = tokio::time::timeout(tokio::time::Duration::from_millis(400), async {
+            while let Some(ev) = events.recv().await {
+                if let Event::ThreadReply { .. } = ev {
+                    return Some(ev);
+                }
+            }
+            None
+        })
+        .await
+        .unwrap_or(None);
+
+        match got.expect("expected ThreadReply event") {
+            Event::ThreadReply {
+                from,
+                target,
+                text,
+                msgid,
+                parent_msgid,
+                root_msgid,
+            } => {
+                assert_eq!(from, "alice");
+                assert_eq!(target, "#room");
+                assert_eq!(text, "agreed");
+                assert_eq!(msgid, "m3");
+                assert_eq!(parent_msgid, "m2");
+                assert_eq!(root_msgid, "m1");
+            }
+            _ => unreachable!(),
+        }
+    }
+
     // ── extended-join account field ───────────────────────────────────────────
 
     /// Extended JOIN with a DID account field emits Event::Joined with
@@ -0,0 +1,250 @@
+//! Inline decryption of end-to-end encrypted messages.
+//!
+//! [`Decrypt`] is an [`EventInterceptor`] that replaces the ciphertext of
+//! [`Event::Message`] with its plaintext and sets `encrypted: true`, using
+//! the keys in a [`SessionStore`]:
+//!
+//! - `ENC3:` direct messages, with the Double Ratchet [`Session`] of the
+//!   sender (keyed by the DID in the `account` tag, else by nick).
+//! - `EG1:` channel messages, with the [`GroupState`] of the channel for
+//!   the message's epoch.
+//!
+//! Messages it can't decrypt (no key, our own echoes, replays) pass
+//! through unchanged with `encrypted: false`. Ratchet state advances as
+//! messages are decrypted, so an app that installs the interceptor should
+//! not also decrypt the same messages itself, and should persist sessions
+//! from the store it shares with the interceptor.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use freeq_proto::tags as tag;
+
+use crate::e2ee_group::{self, GroupState};
+use crate::event::Event;
+use crate::interceptor::EventInterceptor;
+use crate::ratchet::{self, Session};
+
+/// Ratchet sessions and group keys, shared between the app and [`Decrypt`].
+#[derive(Clone, Default)]
+pub struct SessionStore(Arc<parking_lot::Mutex<Keys>>);
+
+#[derive(Default)]
+struct Keys {
+    /// Double Ratchet sessions by remote DID.
+    sessions: HashMap<String, Session>,
+    /// Group keys by lowercased channel, one per epoch we hold.
+    groups: HashMap<String, Vec<GroupState>>,
+}
+
+impl SessionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add or replace the session with `remote_did`.
+    pub fn insert_session(&self, remote_did: &str, session: Session) {
+        self.0
+            .lock()
+            .sessions
+            .insert(remote_did.to_string(), session);
+    }
+
+    pub fn remove_session(&self, remote_did: &str) -> Option<Session> {
+        self.0.lock().sessions.remove(remote_did)
+    }
+
+    pub fn has_session(&self, remote_did: &str) -> bool {
+        self.0.lock().sessions.contains_key(remote_did)
+    }
+
+    /// A copy of the session with `remote_did`, for persisting it.
+    pub fn session(&self, remote_did: &str) -> Option<Session> {
+        self.0.lock().sessions.get(remote_did).cloned()
+    }
+
+    /// Run `f` on the session with `remote_did`, e.g. to encrypt with it.
+    pub fn with_session<R>(
+        &self,
+        remote_did: &str,
+        f: impl FnOnce(&mut Session) -> R,
+    ) -> Option<R> {
+        self.0.lock().sessions.get_mut(remote_did).map(f)
+    }
+
+    /// Add a channel's group key. Keys for other epochs are kept, so
+    /// history from before a rotation still decrypts.
+    pub fn insert_group(&self, state: GroupState) {
+        let mut keys = self.0.lock();
+        let epochs = keys.groups.entry(state.channel.to_lowercase()).or_default();
+        epochs.retain(|s| s.epoch != state.epoch);
+        epochs.push(state);
+    }
+
+    /// Forget every group key for `channel`.
+    pub fn remove_group(&self, channel: &str) {
+        self.0.lock().groups.remove(&channel.to_lowercase());
+    }
+
+    /// Decrypt a message's text, or `None` if it isn't encrypted or we
+    /// hold no key that opens it.
+    pub fn decrypt(&self, sender: &str, target: &str, text: &str) -> Option<String> {
+        let mut keys = self.0.lock();
+        if ratchet::is_encrypted(text) {
+            let session = keys.sessions.get_mut(sender)?;
+            // A failed decrypt may leave the ratchet half-stepped; only keep
+            // the new state when it worked.
+            let mut next = session.clone();
+            match next.decrypt(text) {
+                Ok(plaintext) => {
+                    *session = next;
+                    Some(plaintext)
+                }
+                Err(e) => {
+                    tracing::debug!(%sender, error = %e, "ENC3 message did not decrypt");
+                    None
+                }
+            }
+        } else if e2ee_group::is_group_encrypted(text) {
+            let epoch = e2ee_group::parse_epoch(text)?;
+            let state = keys
+                .groups
+                .get(&target.to_lowercase())?
+                .iter()
+                .find(|s| s.epoch == epoch)?;
+            state
+                .decrypt(text)
+                .inspect_err(
+                    |e| tracing::debug!(%target, error = %e, "EG1 message did not decrypt"),
+                )
+                .ok()
+        } else {
+            None
+        }
+    }
+}
+
+/// Interceptor that decrypts messages with a [`SessionStore`]; add it with
+/// [`ClientHandle::add_interceptor`](crate::client::ClientHandle::add_interceptor).
+pub struct Decrypt {
+    store: SessionStore,
+}
+
+impl Decrypt {
+    pub fn new(store: SessionStore) -> Self {
+        Self { store }
+    }
+}
+
+impl EventInterceptor for Decrypt {
+    fn intercept(&mut self, mut event: Event, out: &mut Vec<Event>) {
+        if let Event::Message {
+            from,
+            target,
+            text,
+            tags,
+            formatted_text,
+            encrypted,
+        } = &mut event
+            && !*encrypted
+        {
+            let sender = tags.get(tag::ACCOUNT).unwrap_or(&*from);
+            if let Some(plaintext) = self.store.decrypt(sender, target, text) {
+                *formatted_text = crate::format::formatted_text(&plaintext);
+                *text = plaintext;
+                *encrypted = true;
+            }
+        }
+        out.push(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes_gcm::aead::OsRng;
+    use x25519_dalek::{PublicKey, StaticSecret};
+
+    fn message(from: &str, target: &str, text: &str, account: Option<&str>) -> Event {
+        let mut tags = HashMap::new();
+        if let Some(account) = account {
+            tags.insert(tag::ACCOUNT.to_string(), account.to_string());
+        }
+        Event::Message {
+            from: from.to_string(),
+            target: target.to_string(),
+            text: text.to_string(),
+            tags,
+            formatted_text: None,
+            encrypted: false,
+        }
+    }
+
+    fn run(decrypt: &mut Decrypt, event: Event) -> (String, bool) {
+        let mut out = Vec::new();
+        decrypt.intercept(event, &mut out);
+        match out.as_slice() {
+            [
+                Event::Message {
+                    text, encrypted, ..
+                },
+            ] => (text.clone(), *encrypted),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn direct_messages_decrypt_with_the_senders_session() {
+        let bob_secret = StaticSecret::random_from_rng(OsRng);
+        let bob_public = PublicKey::from(&bob_secret).to_bytes();
+        let mut alice = Session::init_alice([7; 32], bob_public);
+        let store = SessionStore::new();
+        store.insert_session(
+            "did:plc:alice",
+            Session::init_bob([7; 32], bob_secret.to_bytes()),
+        );
+        let mut decrypt = Decrypt::new(store.clone());
+
+        let wire = alice.encrypt("hi *bob*").unwrap();
+        let event = message("alice", "bob", &wire, Some("did:plc:alice"));
+        assert_eq!(
+            run(&mut decrypt, event.clone()),
+            ("hi *bob*".to_string(), true)
+        );
+
+        // A replay doesn't decrypt, and doesn't break the session.
+        assert_eq!(run(&mut decrypt, event), (wire, false));
+        let wire = alice.encrypt("again").unwrap();
+        let event = message("alice", "bob", &wire, Some("did:plc:alice"));
+        assert_eq!(run(&mut decrypt, event), ("again".to_string(), true));
+
+        // No session for the sender.
+        let wire = alice.encrypt("who?").unwrap();
+        let event = message("mallory", "bob", &wire, None);
+        assert_eq!(run(&mut decrypt, event), (wire, false));
+    }
+
+    #[test]
+    fn channel_messages_decrypt_with_the_epochs_group_key() {
+        let first = GroupState::create("#Secret");
+        let second = first.rotate();
+        let store = SessionStore::new();
+        store.insert_group(first.clone());
+        store.insert_group(second.clone());
+        let mut decrypt = Decrypt::new(store.clone());
+
+        for state in [&first, &second] {
+            let wire = state.encrypt("plans").unwrap();
+            let event = message("alice", "#secret", &wire, None);
+            assert_eq!(run(&mut decrypt, event), ("plans".to_string(), true));
+        }
+
+        store.remove_group("#SECRET");
+        let wire = second.encrypt("plans").unwrap();
+        let event = message("alice", "#secret", &wire, None);
+        assert_eq!(run(&mut decrypt, event), (wire, false));
+
+        let event = message("alice", "#secret", "plain", None);
+        assert_eq!(run(&mut decrypt, event), ("plain".to_string(), false));
+    }
+}
@@ -0,0 +1,220 @@
+//! Connection timeline for support reports.
+//!
+//! The SDK keeps the last [`CAPACITY`] connection lifecycle events of the
+//! process — DNS, TCP, TLS and WebSocket setup with their timings,
+//! registration, authentication and disconnects with their reasons — in a
+//! ring buffer that survives reconnects. [`export_diagnostics`] renders it
+//! as JSON an app can attach to a bug report when a user says "it won't
+//! connect".
+//!
+//! Only connection metadata is kept: no message content, nicks, channels
+//! or DIDs.
+
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::event::Event;
+
+/// How many events the timeline keeps; older ones are dropped.
+pub const CAPACITY: usize = 200;
+
+/// One step of a connection's life.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Stage {
+    /// A connection attempt started.
+    Connecting {
+        server: String,
+        transport: &'static str,
+    },
+    /// The server's name resolved.
+    Dns { ms: u64, addresses: usize },
+    /// The TCP connection is up.
+    Tcp { ms: u64 },
+    /// The TLS handshake completed.
+    Tls { ms: u64 },
+    /// The WebSocket (including its DNS, TCP and TLS) is up.
+    WebSocket { ms: u64 },
+    /// A setup step failed; `stage` is the step that did.
+    Failed {
+        stage: &'static str,
+        ms: u64,
+        error: String,
+    },
+    /// The transport is ready and IRC registration is starting.
+    Connected,
+    /// Registration completed, `ms` after [`Stage::Connected`].
+    Registered { ms: Option<u64> },
+    /// SASL succeeded.
+    Authenticated,
+    /// SASL failed.
+    AuthFailed { reason: String },
+    /// The connection ended, after `connected_ms` if it got that far.
+    Disconnected {
+        reason: String,
+        connected_ms: Option<u64>,
+    },
+}
+
+/// A [`Stage`] and when it happened.
+#[derive(Debug, Clone, Serialize)]
+pub struct Entry {
+    /// Unix time in milliseconds.
+    pub at_ms: u64,
+    #[serde(flatten)]
+    pub stage: Stage,
+}
+
+/// A bounded, oldest-first list of [`Entry`]s.
+#[derive(Debug, Default)]
+pub(crate) struct Timeline {
+    entries: VecDeque<Entry>,
+    connected_at: Option<std::time::Instant>,
+}
+
+impl Timeline {
+    pub(crate) fn push(&mut self, stage: Stage) {
+        if self.entries.len() == CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(Entry {
+            at_ms: unix_ms(),
+            stage,
+        });
+    }
+
+    /// Record the connection-state events among a connection's events,
+    /// timing registration and the connection's lifetime.
+    pub(crate) fn observe(&mut self, event: &Event) {
+        let since = |at: Option<std::time::Instant>| at.map(|t| millis(t.elapsed()));
+        let stage = match event {
+            Event::Connected => {
+                self.connected_at = Some(std::time::Instant::now());
+                Stage::Connected
+            }
+            Event::Registered { .. } => Stage::Registered {
+                ms: since(self.connected_at),
+            },
+            Event::Authenticated { .. } => Stage::Authenticated,
+            Event::AuthFailed { reason } => Stage::AuthFailed {
+                reason: reason.clone(),
+            },
+            Event::Disconnected { reason } => Stage::Disconnected {
+                reason: reason.clone(),
+                connected_ms: since(self.connected_at.take()),
+            },
+            _ => return,
+        };
+        self.push(stage);
+    }
+
+    fn export(&self) -> String {
+        serde_json::json!({
+            "sdk_version": env!("CARGO_PKG_VERSION"),
+            "os": std::env::consts::OS,
+            "arch": std::env::consts::ARCH,
+            "exported_at_ms": unix_ms(),
+            "events": self.entries,
+        })
+        .to_string()
+    }
+}
+
+static TIMELINE: parking_lot::Mutex<Timeline> = parking_lot::Mutex::new(Timeline {
+    entries: VecDeque::new(),
+    connected_at: None,
+});
+
+/// Add a stage to the process's timeline.
+pub(crate) fn record(stage: Stage) {
+    TIMELINE.lock().push(stage);
+}
+
+/// Record a connection event, if it is one the timeline keeps.
+pub(crate) fn observe(event: &Event) {
+    TIMELINE.lock().observe(event);
+}
+
+/// Milliseconds in `elapsed`, for a [`Stage`].
+pub(crate) fn millis(elapsed: Duration) -> u64 {
+    elapsed.as_millis().try_into().unwrap_or(u64::MAX)
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(millis)
+        .unwrap_or_default()
+}
+
+/// The recorded events, oldest first.
+pub fn timeline() -> Vec<Entry> {
+    TIMELINE.lock().entries.iter().cloned().collect()
+}
+
+/// The timeline as JSON, with the SDK version and platform:
+/// `{"sdk_version", "os", "arch", "exported_at_ms", "events": [...]}`,
+/// each event carrying `at_ms`, a `kind` and that kind's fields.
+pub fn export_diagnostics() -> String {
+    TIMELINE.lock().export()
+}
+
+/// Forget the recorded events.
+pub fn clear() {
+    *TIMELINE.lock() = Timeline::default();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_latest_events() {
+        let mut t = Timeline::default();
+        for ms in 0..CAPACITY as u64 + 5 {
+            t.push(Stage::Tcp { ms });
+        }
+        assert_eq!(t.entries.len(), CAPACITY);
+        assert_eq!(t.entries.front().unwrap().stage, Stage::Tcp { ms: 5 });
+    }
+
+    #[test]
+    fn export_times_the_connection_without_content() {
+        let mut t = Timeline::default();
+        t.push(Stage::Connecting {
+            server: "irc.freeq.at:6697".into(),
+            transport: "tls",
+        });
+        t.observe(&Event::Connected);
+        t.observe(&Event::Registered {
+            nick: "alice".into(),
+        });
+        t.observe(&Event::Message {
+            from: "bob".into(),
+            target: "#secret".into(),
+            text: "the password is hunter2".into(),
+            tags: Default::default(),
+            formatted_text: None,
+            encrypted: false,
+        });
+        t.observe(&Event::Disconnected {
+            reason: "Ping timeout".into(),
+        });
+
+        let json: serde_json::Value = serde_json::from_str(&t.export()).unwrap();
+        let events = json["events"].as_array().unwrap();
+        let kinds: Vec<_> = events.iter().map(|e| e["kind"].as_str().unwrap()).collect();
+        assert_eq!(
+            kinds,
+            ["connecting", "connected", "registered", "disconnected"]
+        );
+        assert_eq!(events[0]["transport"], "tls");
+        assert!(events[2]["ms"].is_u64());
+        assert_eq!(events[3]["reason"], "Ping timeout");
+        assert!(events[3]["connected_ms"].is_u64());
+        let text = json.to_string();
+        assert!(!text.contains("alice") && !text.contains("hunter2"));
+    }
+}
@@ -0,0 +1,361 @@
+//! The channel directory: `LIST` results as typed entries, cached.
+//!
+//! [`ClientHandle::list_channels`](crate::client::ClientHandle::list_channels)
+//! sends `LIST`, collects the `322` replies up to `323` and hands them over
+//! as [`ChannelListing`]s, busiest first. A [`ListFilter`] is sent to the
+//! server as ELIST conditions where it advertises support (`ELIST=U` for
+//! user counts, `ELIST=M` for masks) and always applied to the replies
+//! too, so it holds on servers that ignore it. Results are cached per
+//! request for [`CACHE_TTL`]; a directory screen re-opened within that
+//! doesn't hit the server again.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::oneshot;
+
+use crate::event::Event;
+use crate::irc::Message;
+use crate::pending::{CallError, CallOptions};
+use crate::proto::numeric;
+
+/// How long a listing is served from the cache.
+pub const CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// One channel in a `LIST` reply.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelListing {
+    pub name: String,
+    /// Visible users, as the server counts them.
+    pub users: u32,
+    /// The topic, empty when unset.
+    pub topic: String,
+}
+
+/// Which channels to list. The default lists them all.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ListFilter {
+    /// Only channels with at least this many users.
+    pub min_users: Option<u32>,
+    /// Only channels with at most this many users.
+    pub max_users: Option<u32>,
+    /// Only channels whose name matches this glob (`*`, `?`), ignoring
+    /// ASCII case, e.g. `*rust*`.
+    pub mask: Option<String>,
+}
+
+impl ListFilter {
+    /// Whether `listing` passes the filter.
+    pub fn matches(&self, listing: &ChannelListing) -> bool {
+        self.min_users.is_none_or(|min| listing.users >= min)
+            && self.max_users.is_none_or(|max| listing.users <= max)
+            && self.mask.as_deref().is_none_or(|mask| {
+                glob_match(
+                    &mask.to_ascii_lowercase(),
+                    &listing.name.to_ascii_lowercase(),
+                )
+            })
+    }
+
+    /// The `LIST` line for this filter, using the conditions the server's
+    /// `ELIST` tokens (`elist`) say it understands.
+    pub fn command(&self, elist: &str) -> String {
+        let has = |token: char| elist.chars().any(|c| c.eq_ignore_ascii_case(&token));
+        let mut conditions = Vec::new();
+        if has('U') {
+            if let Some(min) = self.min_users.filter(|m| *m > 0) {
+                conditions.push(format!(">{}", min - 1));
+            }
+            if let Some(max) = self.max_users {
+                conditions.push(format!("<{}", max.saturating_add(1)));
+            }
+        }
+        if has('M')
+            && let Some(mask) = self.mask.as_deref().filter(|m| !m.is_empty())
+        {
+            conditions.push(mask.to_string());
+        }
+        if conditions.is_empty() {
+            "LIST".to_string()
+        } else {
+            format!("LIST {}", conditions.join(","))
+        }
+    }
+}
+
+/// Whether `text` matches the glob `pattern` (`*` any run, `?` one char).
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((sp, st)) = star {
+            pi = sp + 1;
+            ti = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+struct Collector {
+    id: u64,
+    entries: Vec<ChannelListing>,
+    tx: oneshot::Sender<Vec<ChannelListing>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    next_id: u64,
+    /// The server's ELIST tokens, from ISUPPORT.
+    elist: String,
+    /// Requests in flight, oldest first; replies come back in order.
+    collectors: VecDeque<Collector>,
+    /// By `LIST` line: when it was answered, and the answer.
+    cache: HashMap<String, (Instant, Vec<ChannelListing>)>,
+}
+
+/// `LIST` requests waiting for their replies, and the cache of answers.
+#[derive(Clone, Default)]
+pub(crate) struct ChannelLists {
+    inner: Arc<parking_lot::Mutex<Inner>>,
+}
+
+impl ChannelLists {
+    /// The line to send for `filter`, given what the server supports.
+    pub(crate) fn command(&self, filter: &ListFilter) -> String {
+        filter.command(&self.inner.lock().elist)
+    }
+
+    /// A cached answer to `command` younger than [`CACHE_TTL`].
+    pub(crate) fn cached(&self, command: &str) -> Option<Vec<ChannelListing>> {
+        let inner = self.inner.lock();
+        let (at, entries) = inner.cache.get(command)?;
+        (at.elapsed() < CACHE_TTL).then(|| entries.clone())
+    }
+
+    /// Collect the replies to the next `LIST`. Register before sending it.
+    pub(crate) fn register(&self) -> (u64, oneshot::Receiver<Vec<ChannelListing>>) {
+        let (tx, rx) = oneshot::channel();
+        let mut inner = self.inner.lock();
+        inner.next_id += 1;
+        let id = inner.next_id;
+        inner.collectors.push_back(Collector {
+            id,
+            entries: Vec::new(),
+            tx,
+        });
+        (id, rx)
+    }
+
+    /// Stop collecting for request `id`.
+    pub(crate) fn forget(&self, id: u64) {
+        self.inner.lock().collectors.retain(|c| c.id != id);
+    }
+
+    /// Wait for request `id`'s replies within `options`, caching them
+    /// under `command`.
+    pub(crate) async fn wait(
+        &self,
+        id: u64,
+        rx: oneshot::Receiver<Vec<ChannelListing>>,
+        command: String,
+        options: &CallOptions,
+    ) -> Result<Vec<ChannelListing>, CallError> {
+        let cancelled = async {
+            match &options.cancel {
+                Some(token) => token.cancelled().await,
+                None => std::future::pending::<()>().await,
+            }
+        };
+        let result = tokio::select! {
+            entries = rx => entries.map_err(|_| CallError::Disconnected),
+            _ = tokio::time::sleep(options.timeout) => Err(CallError::Timeout(options.timeout)),
+            _ = cancelled => Err(CallError::Cancelled),
+        };
+        match &result {
+            Ok(entries) => {
+                self.inner
+                    .lock()
+                    .cache
+                    .insert(command, (Instant::now(), entries.clone()));
+            }
+            Err(_) => self.forget(id),
+        }
+        result
+    }
+
+    /// Feed one event on its way to the consumer: ISUPPORT and `LIST`
+    /// replies arrive as raw lines. A disconnect fails the requests in
+    /// flight.
+    pub(crate) fn observe(&self, event: &Event) {
+        let line = match event {
+            Event::RawLine(line) => line,
+            Event::Disconnected { .. } => {
+                self.inner.lock().collectors.clear();
+                return;
+            }
+            _ => return,
+        };
+        let Some(msg) = Message::parse(line) else {
+            return;
+        };
+        let mut inner = self.inner.lock();
+        match msg.command.as_str() {
+            numeric::RPL_ISUPPORT => {
+                if let Some(tokens) = msg.params.iter().find_map(|p| p.strip_prefix("ELIST=")) {
+                    inner.elist = tokens.to_string();
+                }
+            }
+            numeric::RPL_LIST => {
+                let (Some(name), Some(users)) = (msg.params.get(1), msg.params.get(2)) else {
+                    return;
+                };
+                if let Some(c) = inner.collectors.front_mut() {
+                    c.entries.push(ChannelListing {
+                        name: name.clone(),
+                        users: users.parse().unwrap_or(0),
+                        topic: msg.params.get(3).cloned().unwrap_or_default(),
+                    });
+                }
+            }
+            numeric::RPL_LISTEND => {
+                if let Some(c) = inner.collectors.pop_front() {
+                    let _ = c.tx.send(c.entries);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// `entries` that pass `filter`, busiest first, then by name.
+pub(crate) fn select(entries: Vec<ChannelListing>, filter: &ListFilter) -> Vec<ChannelListing> {
+    let mut selected: Vec<ChannelListing> =
+        entries.into_iter().filter(|e| filter.matches(e)).collect();
+    selected.sort_by(|a, b| {
+        b.users.cmp(&a.users).then_with(|| {
+            a.name
+                .to_ascii_lowercase()
+                .cmp(&b.name.to_ascii_lowercase())
+        })
+    });
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw(line: &str) -> Event {
+        Event::RawLine(line.to_string())
+    }
+
+    fn listing(name: &str, users: u32) -> ChannelListing {
+        ChannelListing {
+            name: name.into(),
+            users,
+            topic: String::new(),
+        }
+    }
+
+    #[test]
+    fn filters_become_elist_conditions_only_where_supported() {
+        let filter = ListFilter {
+            min_users: Some(5),
+            max_users: Some(100),
+            mask: Some("*rust*".into()),
+        };
+        assert_eq!(filter.command(""), "LIST");
+        assert_eq!(filter.command("U"), "LIST >4,<101");
+        assert_eq!(filter.command("CMNTU"), "LIST >4,<101,*rust*");
+        assert_eq!(ListFilter::default().command("MU"), "LIST");
+
+        assert!(filter.matches(&listing("#Rust-lang", 5)));
+        assert!(!filter.matches(&listing("#rust", 4)));
+        assert!(!filter.matches(&listing("#rust", 101)));
+        assert!(!filter.matches(&listing("#go", 50)));
+        assert!(glob_match("#a?c*", "#abcdef"));
+        assert!(!glob_match("#a?c", "#abcd"));
+    }
+
+    #[tokio::test]
+    async fn replies_are_collected_in_order_and_cached() {
+        let lists = ChannelLists::default();
+        lists.observe(&raw(":irc.test 005 me CHANTYPES=# ELIST=MU :are supported"));
+        let command = lists.command(&ListFilter {
+            min_users: Some(2),
+            ..Default::default()
+        });
+        assert_eq!(command, "LIST >1");
+
+        let (first, rx1) = lists.register();
+        let (_, rx2) = lists.register();
+        lists.observe(&raw(":irc.test 322 me #small 1 :"));
+        lists.observe(&raw(":irc.test 322 me #big 40 :[+nt] Big room"));
+        lists.observe(&raw(":irc.test 323 me :End of /LIST"));
+        lists.observe(&raw(":irc.test 323 me :End of /LIST"));
+
+        let options = CallOptions::default();
+        let entries = lists
+            .wait(first, rx1, command.clone(), &options)
+            .await
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].topic, "[+nt] Big room");
+        assert!(rx2.await.unwrap().is_empty());
+
+        let selected = select(
+            lists.cached(&command).unwrap(),
+            &ListFilter {
+                min_users: Some(2),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            selected,
+            vec![listing_with_topic("#big", 40, "[+nt] Big room")]
+        );
+
+        let (_, rx) = lists.register();
+        lists.observe(&Event::Disconnected {
+            reason: "EOF".into(),
+        });
+        assert!(rx.await.is_err(), "disconnecting fails requests in flight");
+    }
+
+    #[tokio::test]
+    async fn unanswered_requests_time_out_and_stop_collecting() {
+        let lists = ChannelLists::default();
+        let (id, rx) = lists.register();
+        let err = lists
+            .wait(
+                id,
+                rx,
+                "LIST".into(),
+                &CallOptions::timeout(Duration::from_millis(10)),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, CallError::Timeout(_)));
+        assert!(lists.inner.lock().collectors.is_empty());
+        assert!(lists.cached("LIST").is_none());
+    }
+
+    fn listing_with_topic(name: &str, users: u32, topic: &str) -> ChannelListing {
+        ChannelListing {
+            topic: topic.into(),
+            ..listing(name, users)
+        }
+    }
+}
@@ -11,6 +11,13 @@ pub enum Event {
         nick: String,
     },
 
+    /// Our effective nick: sent on registration and whenever it changes
+    /// afterwards (a NICK we asked for, a regain, or a server rename).
+    /// It may differ from the configured nick after a 433 fallback.
+    NickAssigned {
+        nick: String,
+    },
+
     /// SASL authentication result.
     Authenticated {
         did: String,
@@ -19,6 +26,19 @@ pub enum Event {
         reason: String,
     },
 
+    /// Capability negotiation finished, and again whenever the server
+    /// acknowledges a capability it offers later (`CAP NEW`). `acked` is
+    /// every capability now in effect, `rejected` those the server
+    /// refused, and `unavailable` those [`ConnectConfig::capabilities`]
+    /// asked for that the server doesn't offer.
+    ///
+    /// [`ConnectConfig::capabilities`]: crate::client::ConnectConfig
+    CapabilitiesAcked {
+        acked: Vec<String>,
+        rejected: Vec<String>,
+        unavailable: Vec<String>,
+    },
+
     /// Joined a channel.
     Joined {
         channel: String,
@@ -42,6 +62,25 @@ pub enum Event {
         text: String,
         /// IRCv3 message tags (empty if none).
         tags: std::collections::HashMap<String, String>,
+        /// `text` as markdown when it carries IRC formatting codes (see
+        /// [`crate::format`]); `None` for plain text.
+        formatted_text: Option<String>,
+        /// The message was end-to-end encrypted and `text` is the plaintext
+        /// recovered by [`crate::decrypt::Decrypt`].
+        encrypted: bool,
+    },
+
+    /// A message that is a reply in a thread. Emitted right after the
+    /// message's own [`Event::Message`]; `root_msgid` comes from the
+    /// server's `+freeq.at/thread` tag and is the same for every reply in
+    /// the thread.
+    ThreadReply {
+        from: String,
+        target: String,
+        text: String,
+        msgid: String,
+        parent_msgid: String,
+        root_msgid: String,
     },
 
     /// A TAGMSG (tags only, no body) — used for reactions, typing indicators, etc.
@@ -141,6 +180,31 @@ pub enum Event {
         reason: String,
     },
 
+    /// A contact's aggregated presence changed (see [`crate::presence`]).
+    /// `did_or_nick` is the contact's DID when known, otherwise its nick.
+    PresenceChanged {
+        did_or_nick: String,
+        state: crate::presence::PresenceState,
+    },
+
+    /// Someone is now in a channel's roster (see [`crate::channels`]).
+    MemberAdded {
+        channel: String,
+        member: crate::channels::Member,
+    },
+
+    /// Someone left a channel's roster.
+    MemberRemoved {
+        channel: String,
+        nick: String,
+    },
+
+    /// A roster member's prefix, away state or account changed.
+    MemberChanged {
+        channel: String,
+        member: crate::channels::Member,
+    },
+
     /// Connection was closed.
     Disconnected {
         reason: String,
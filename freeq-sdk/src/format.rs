@@ -0,0 +1,579 @@
+//! IRC formatting codes ⇄ a markdown subset.
+//!
+//! Clients compose in markdown; IRC carries formatting as control codes.
+//! [`markdown_to_irc`] converts outgoing text (what
+//! [`ClientHandle::privmsg_formatted`](crate::client::ClientHandle::privmsg_formatted)
+//! sends) and [`irc_to_markdown`] converts incoming text (the
+//! `formatted_text` of [`Event::Message`](crate::event::Event::Message)).
+//!
+//! | Markdown                    | IRC                    |
+//! |-----------------------------|------------------------|
+//! | `**bold**`, `__bold__`      | `\x02bold\x02`         |
+//! | `*italic*`, `_italic_`      | `\x1Ditalic\x1D`       |
+//! | `` `code` ``                | `\x11code\x11`         |
+//! | `[text](url)`, `<url>`      | `text (url)`, `url`    |
+//!
+//! Formatting never spans lines. Incoming underline, strikethrough,
+//! reverse and colours have no markdown form here and are dropped.
+
+/// Bold toggle.
+pub const BOLD: char = '\x02';
+/// Italic toggle.
+pub const ITALIC: char = '\x1D';
+/// Monospace toggle (IRCv3 formatting).
+pub const MONOSPACE: char = '\x11';
+/// Underline toggle.
+pub const UNDERLINE: char = '\x1F';
+/// Strikethrough toggle.
+pub const STRIKETHROUGH: char = '\x1E';
+/// Reverse-colour toggle.
+pub const REVERSE: char = '\x16';
+/// Colour: `\x03<fg>[,<bg>]`, one or two decimal digits each.
+pub const COLOR: char = '\x03';
+/// Hex colour: `\x04<RRGGBB>[,<RRGGBB>]`.
+pub const HEX_COLOR: char = '\x04';
+/// Reset all formatting.
+pub const RESET: char = '\x0F';
+
+/// Characters a backslash escapes in markdown input.
+const ESCAPABLE: &str = "\\`*_{}[]()#+-.!<>~|";
+
+/// Characters escaped in markdown output.
+const MARKDOWN_SPECIAL: &str = "\\`*_[]~";
+
+/// Whether `text` contains any IRC formatting code.
+pub fn has_formatting(text: &str) -> bool {
+    text.chars().any(|c| {
+        matches!(
+            c,
+            BOLD | ITALIC
+                | MONOSPACE
+                | UNDERLINE
+                | STRIKETHROUGH
+                | REVERSE
+                | COLOR
+                | HEX_COLOR
+                | RESET
+        )
+    })
+}
+
+/// `text` as markdown if it carries formatting codes, else `None` (the
+/// plain text is already what to show).
+pub fn formatted_text(text: &str) -> Option<String> {
+    has_formatting(text).then(|| irc_to_markdown(text))
+}
+
+// ── Markdown → IRC ──────────────────────────────────────────────────
+
+/// Convert markdown (the subset above) to IRC formatting codes. Anything
+/// else, including unmatched delimiters, passes through unchanged.
+pub fn markdown_to_irc(markdown: &str) -> String {
+    let chars: Vec<char> = markdown.chars().collect();
+    let mut out = String::with_capacity(markdown.len());
+    convert(&chars, &mut out);
+    out
+}
+
+fn convert(s: &[char], out: &mut String) {
+    let mut i = 0;
+    while i < s.len() {
+        let c = s[i];
+        match c {
+            '\\' if s.get(i + 1).is_some_and(|n| ESCAPABLE.contains(*n)) => {
+                out.push(s[i + 1]);
+                i += 2;
+                continue;
+            }
+            '`' => {
+                if let Some((content, end)) = code_span(s, i) {
+                    out.push(MONOSPACE);
+                    out.extend(content);
+                    out.push(MONOSPACE);
+                    i = end;
+                } else {
+                    // An unmatched run stays literal as a whole.
+                    let run = run_len(s, i, '`');
+                    out.extend(&s[i..i + run]);
+                    i += run;
+                }
+                continue;
+            }
+            '[' => {
+                if let Some((text, url, end)) = link(s, i) {
+                    let mut label = String::new();
+                    convert(text, &mut label);
+                    let url: String = url.iter().collect();
+                    if label.is_empty() || label == url {
+                        out.push_str(&url);
+                    } else {
+                        out.push_str(&format!("{label} ({url})"));
+                    }
+                    i = end;
+                    continue;
+                }
+            }
+            '<' => {
+                if let Some((url, end)) = autolink(s, i) {
+                    out.extend(url);
+                    i = end;
+                    continue;
+                }
+            }
+            '*' | '_' => {
+                if let Some((code, inner, end)) = emphasis(s, i) {
+                    out.push(code);
+                    convert(&s[inner.0..inner.1], out);
+                    out.push(code);
+                    i = end;
+                    continue;
+                }
+            }
+            _ => {
+                // Copy bare URLs verbatim so `_` in them isn't emphasis.
+                if let Some(len) = bare_url(s, i) {
+                    out.extend(&s[i..i + len]);
+                    i += len;
+                    continue;
+                }
+            }
+        }
+        out.push(c);
+        i += 1;
+    }
+}
+
+fn run_len(s: &[char], i: usize, c: char) -> usize {
+    s[i..].iter().take_while(|&&x| x == c).count()
+}
+
+/// A backtick code span at `i`: its content and the index past it.
+fn code_span(s: &[char], i: usize) -> Option<(&[char], usize)> {
+    let fence = run_len(s, i, '`');
+    let start = i + fence;
+    let mut j = start;
+    while j < s.len() && s[j] != '\n' {
+        if s[j] == '`' {
+            let run = run_len(s, j, '`');
+            if run == fence {
+                let mut content = &s[start..j];
+                if content.len() > 2
+                    && content[0] == ' '
+                    && content[content.len() - 1] == ' '
+                    && content.iter().any(|c| *c != ' ')
+                {
+                    content = &content[1..content.len() - 1];
+                }
+                return (!content.is_empty()).then_some((content, j + run));
+            }
+            j += run;
+        } else {
+            j += 1;
+        }
+    }
+    None
+}
+
+/// `[text](url)` at `i`: the text, the url and the index past it.
+fn link(s: &[char], i: usize) -> Option<(&[char], &[char], usize)> {
+    let close = i + s[i..].iter().position(|c| matches!(c, ']' | '\n'))?;
+    if s[close] != ']' || s[i + 1..close].contains(&'[') || s.get(close + 1) != Some(&'(') {
+        return None;
+    }
+    let url_start = close + 2;
+    let url_end = url_start + s[url_start..].iter().position(|c| *c == ')')?;
+    let url = &s[url_start..url_end];
+    if url.is_empty() || url.iter().any(|c| c.is_whitespace()) {
+        return None;
+    }
+    Some((&s[i + 1..close], url, url_end + 1))
+}
+
+/// `<scheme:…>` at `i`: the url and the index past it.
+fn autolink(s: &[char], i: usize) -> Option<(&[char], usize)> {
+    let end = i + s[i..].iter().position(|c| *c == '>' || c.is_whitespace())?;
+    let url = &s[i + 1..end];
+    let text: String = url.iter().collect();
+    let is_url = ["http://", "https://", "mailto:"]
+        .iter()
+        .any(|scheme| text.starts_with(scheme) && text.len() > scheme.len());
+    (s[end] == '>' && is_url).then_some((url, end + 1))
+}
+
+/// Length of a bare `http(s)://` URL starting at `i`, if one does.
+fn bare_url(s: &[char], i: usize) -> Option<usize> {
+    if s[i] != 'h' || (i > 0 && s[i - 1].is_alphanumeric()) {
+        return None;
+    }
+    let starts = |scheme: &str| {
+        scheme
+            .chars()
+            .enumerate()
+            .all(|(k, c)| s.get(i + k) == Some(&c))
+    };
+    if !starts("http://") && !starts("https://") {
+        return None;
+    }
+    Some(s[i..].iter().take_while(|c| !c.is_whitespace()).count())
+}
+
+/// Emphasis opening at `i`: the IRC code, the inner range and the index
+/// past the closing delimiter.
+fn emphasis(s: &[char], i: usize) -> Option<(char, (usize, usize), usize)> {
+    let d = s[i];
+    let width = if run_len(s, i, d) >= 2 { 2 } else { 1 };
+    let start = i + width;
+    if s.get(start).is_none_or(|c| c.is_whitespace()) {
+        return None;
+    }
+    // `_` doesn't open inside a word (snake_case).
+    if d == '_' && i > 0 && s[i - 1].is_alphanumeric() {
+        return None;
+    }
+    let close = closer(s, start, d, width)?;
+    let code = if width == 2 { BOLD } else { ITALIC };
+    Some((code, (start, close), close + width))
+}
+
+/// Find the delimiter closing emphasis that opened just before `from`.
+fn closer(s: &[char], from: usize, d: char, width: usize) -> Option<usize> {
+    let mut j = from;
+    while j < s.len() && s[j] != '\n' {
+        match s[j] {
+            '\\' => {
+                j += 2;
+                continue;
+            }
+            '`' => {
+                if let Some((_, end)) = code_span(s, j) {
+                    j = end;
+                    continue;
+                }
+            }
+            c if c == d => {
+                let run = run_len(s, j, d);
+                let after = s.get(j + run);
+                let closes = j > from
+                    && !s[j - 1].is_whitespace()
+                    && (d != '_' || after.is_none_or(|c| !c.is_alphanumeric()))
+                    && match width {
+                        1 => run != 2,
+                        _ => run >= 2,
+                    };
+                if closes {
+                    // A longer run closes inner emphasis first; ours is
+                    // the outermost (last) part of it.
+                    return Some(j + run - width);
+                }
+                j += run;
+                continue;
+            }
+            _ => {}
+        }
+        j += 1;
+    }
+    None
+}
+
+// ── IRC → Markdown ──────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+struct Style {
+    bold: bool,
+    italic: bool,
+    code: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mark {
+    Bold,
+    Italic,
+    Code,
+}
+
+impl Style {
+    /// Marks to have open, outermost first. Code is always innermost:
+    /// markdown has no emphasis inside a code span.
+    fn marks(self) -> Vec<Mark> {
+        let mut marks = Vec::new();
+        if self.bold {
+            marks.push(Mark::Bold);
+        }
+        if self.italic {
+            marks.push(Mark::Italic);
+        }
+        if self.code {
+            marks.push(Mark::Code);
+        }
+        marks
+    }
+}
+
+/// Convert IRC formatting codes to markdown. Literal markdown characters
+/// in the text are escaped (except inside code and URLs), so a markdown
+/// renderer shows exactly the IRC text.
+pub fn irc_to_markdown(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 8);
+    for (n, line) in text.split('\n').enumerate() {
+        if n > 0 {
+            out.push('\n');
+        }
+        render_line(&runs(line), &mut out);
+    }
+    out
+}
+
+/// Split a line into runs of text with one style each.
+fn runs(line: &str) -> Vec<(Style, String)> {
+    let mut runs = Vec::new();
+    let mut style = Style::default();
+    let mut current = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        let before = style;
+        match c {
+            BOLD => style.bold = !style.bold,
+            ITALIC => style.italic = !style.italic,
+            MONOSPACE => style.code = !style.code,
+            RESET => style = Style::default(),
+            UNDERLINE | STRIKETHROUGH | REVERSE => {}
+            COLOR => skip_color(&mut chars, |c| c.is_ascii_digit(), 2),
+            HEX_COLOR => skip_color(&mut chars, |c| c.is_ascii_hexdigit(), 6),
+            _ => {
+                current.push(c);
+                continue;
+            }
+        }
+        if style != before && !current.is_empty() {
+            runs.push((before, std::mem::take(&mut current)));
+        }
+    }
+    if !current.is_empty() {
+        runs.push((style, current));
+    }
+    runs
+}
+
+/// Skip a colour code's `fg[,bg]` parameters.
+fn skip_color(
+    chars: &mut std::iter::Peekable<std::str::Chars<'_>>,
+    digit: impl Fn(char) -> bool,
+    max: usize,
+) {
+    let take = |chars: &mut std::iter::Peekable<std::str::Chars<'_>>| {
+        let mut n = 0;
+        while n < max && chars.next_if(|c| digit(*c)).is_some() {
+            n += 1;
+        }
+        n
+    };
+    if take(chars) == 0 {
+        return;
+    }
+    let mut ahead = chars.clone();
+    if ahead.next() == Some(',') && ahead.next().is_some_and(&digit) {
+        chars.next();
+        take(chars);
+    }
+}
+
+fn render_line(runs: &[(Style, String)], out: &mut String) {
+    let mut open: Vec<Mark> = Vec::new();
+    let mut code_fence = "`";
+    // Whitespace that ended the previous run: it goes after any closing
+    // delimiters, since `**bold **` isn't bold in markdown.
+    let mut pending = String::new();
+
+    for (style, text) in runs {
+        let trimmed = text.trim_start();
+        let lead = &text[..text.len() - trimmed.len()];
+        let body = trimmed.trim_end();
+        let trail = &trimmed[body.len()..];
+        if body.is_empty() {
+            pending.push_str(text);
+            continue;
+        }
+
+        let want = style.marks();
+        let mut keep = open.iter().zip(&want).take_while(|(a, b)| a == b).count();
+        // Nothing opens inside a code span: close it and reopen.
+        if want.len() > keep && open[..keep].contains(&Mark::Code) {
+            keep = open.iter().position(|m| *m == Mark::Code).unwrap_or(keep);
+        }
+        for mark in open.drain(keep..).rev() {
+            out.push_str(close_delim(mark, code_fence));
+        }
+        out.push_str(&pending);
+        pending.clear();
+        out.push_str(lead);
+        for &mark in &want[keep..] {
+            if mark == Mark::Code {
+                code_fence = if body.contains('`') { "`` " } else { "`" };
+            }
+            out.push_str(open_delim(mark, code_fence));
+            open.push(mark);
+        }
+
+        if open.contains(&Mark::Code) {
+            out.push_str(body);
+        } else {
+            push_escaped(body, out);
+        }
+        pending.push_str(trail);
+    }
+    for mark in open.drain(..).rev() {
+        out.push_str(close_delim(mark, code_fence));
+    }
+    out.push_str(&pending);
+}
+
+fn open_delim(mark: Mark, code_fence: &'static str) -> &'static str {
+    match mark {
+        Mark::Bold => "**",
+        Mark::Italic => "*",
+        Mark::Code => code_fence,
+    }
+}
+
+fn close_delim(mark: Mark, code_fence: &'static str) -> &'static str {
+    match (mark, code_fence) {
+        (Mark::Code, "`` ") => " ``",
+        _ => open_delim(mark, code_fence),
+    }
+}
+
+/// Escape markdown syntax in plain text, leaving URLs intact.
+fn push_escaped(text: &str, out: &mut String) {
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let at_word_start =
+            out.is_empty() || out.ends_with(|p: char| p.is_whitespace() || p == '(');
+        if at_word_start && (rest.starts_with("http://") || rest.starts_with("https://")) {
+            let len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            out.push_str(&rest[..len]);
+            rest = &rest[len..];
+            continue;
+        }
+        if MARKDOWN_SPECIAL.contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markdown_to_irc_subset() {
+        assert_eq!(markdown_to_irc("**bold**"), "\x02bold\x02");
+        assert_eq!(markdown_to_irc("__bold__"), "\x02bold\x02");
+        assert_eq!(
+            markdown_to_irc("*it* and _it_"),
+            "\x1Dit\x1D and \x1Dit\x1D"
+        );
+        assert_eq!(
+            markdown_to_irc("run `cargo test`"),
+            "run \x11cargo test\x11"
+        );
+        assert_eq!(markdown_to_irc("***both***"), "\x02\x1Dboth\x1D\x02");
+        assert_eq!(markdown_to_irc("**a *b* c**"), "\x02a \x1Db\x1D c\x02");
+    }
+
+    #[test]
+    fn markdown_links() {
+        assert_eq!(
+            markdown_to_irc("see [the docs](https://freeq.at/docs)"),
+            "see the docs (https://freeq.at/docs)"
+        );
+        assert_eq!(
+            markdown_to_irc("[https://freeq.at](https://freeq.at)"),
+            "https://freeq.at"
+        );
+        assert_eq!(markdown_to_irc("<https://freeq.at>"), "https://freeq.at");
+        assert_eq!(
+            markdown_to_irc("[**bold** link](https://x.test)"),
+            "\x02bold\x02 link (https://x.test)"
+        );
+    }
+
+    #[test]
+    fn markdown_literals_pass_through() {
+        for text in [
+            "snake_case_name",
+            "2 * 3 * 4",
+            "* list item",
+            "**unclosed",
+            "a `` b",
+            "https://example.com/a_b_c",
+            "[not a link]",
+        ] {
+            assert_eq!(markdown_to_irc(text), text, "{text:?}");
+        }
+        assert_eq!(markdown_to_irc(r"\*not italic\*"), "*not italic*");
+        assert_eq!(markdown_to_irc("`**raw**`"), "\x11**raw**\x11");
+    }
+
+    #[test]
+    fn formatting_stays_on_its_line() {
+        assert_eq!(markdown_to_irc("**a\nb**"), "**a\nb**");
+        assert_eq!(irc_to_markdown("\x02a\nb"), "**a**\nb");
+    }
+
+    #[test]
+    fn irc_to_markdown_codes() {
+        assert_eq!(irc_to_markdown("\x02bold\x02 text"), "**bold** text");
+        assert_eq!(irc_to_markdown("\x1Dit\x1D"), "*it*");
+        assert_eq!(irc_to_markdown("\x11a_b\x11"), "`a_b`");
+        assert_eq!(
+            irc_to_markdown("\x02\x1Dboth\x0F plain"),
+            "***both*** plain"
+        );
+        assert_eq!(
+            irc_to_markdown("\x0304,12red\x03 and \x1Funder"),
+            "red and under"
+        );
+        assert_eq!(irc_to_markdown("\x04ff0000hex"), "hex");
+    }
+
+    #[test]
+    fn irc_to_markdown_moves_whitespace_outside_delimiters() {
+        assert_eq!(irc_to_markdown("\x02bold \x02next"), "**bold** next");
+        assert_eq!(irc_to_markdown("x\x02 bold\x02"), "x **bold**");
+        assert_eq!(irc_to_markdown("\x02a\x02 \x02b\x02"), "**a b**");
+    }
+
+    #[test]
+    fn irc_to_markdown_escapes_literals() {
+        assert_eq!(irc_to_markdown("\x02x\x02 2*3 [y]"), "**x** 2\\*3 \\[y\\]");
+        assert_eq!(
+            irc_to_markdown("\x02see\x02 https://x.test/a_b"),
+            "**see** https://x.test/a_b"
+        );
+        assert_eq!(irc_to_markdown("\x11a`b\x11"), "`` a`b ``");
+    }
+
+    #[test]
+    fn emphasis_inside_code_reopens_code() {
+        assert_eq!(irc_to_markdown("\x11a\x02b\x02\x11"), "`a`**`b`**");
+    }
+
+    #[test]
+    fn round_trip() {
+        for md in [
+            "**bold** and *italic* and `code`",
+            "***both*** then **bold *nested***",
+            "plain",
+        ] {
+            assert_eq!(irc_to_markdown(&markdown_to_irc(md)), md, "{md:?}");
+        }
+    }
+
+    #[test]
+    fn formatted_text_only_for_formatted_input() {
+        assert_eq!(formatted_text("plain *text*"), None);
+        assert_eq!(formatted_text("\x02hi\x02"), Some("**hi**".to_string()));
+    }
+}
@@ -0,0 +1,138 @@
+//! Highlight matching — which messages deserve a notification.
+//!
+//! A message highlights when it is a direct message to us, names our nick,
+//! or contains one of the user's keywords. Nicks and keywords match
+//! case-insensitively (ASCII) and only as whole words, so `al` doesn't
+//! fire on "also" and `alice` does fire on "alice:" or "@alice". Our own
+//! messages (including echoes) never highlight.
+//!
+//! Apps that raise local notifications while backgrounded use this rather
+//! than each re-implementing the rules; the FFI layer stamps the result
+//! on every live message.
+
+/// Why a message highlights.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Highlight {
+    /// A private message to us.
+    DirectMessage,
+    /// The message names our nick.
+    Mention,
+    /// The message contains this configured keyword.
+    Keyword(String),
+}
+
+/// The user's highlight rules, beyond their own nick.
+#[derive(Debug, Clone, Default)]
+pub struct Highlighter {
+    keywords: Vec<String>,
+}
+
+impl Highlighter {
+    /// A highlighter for `keywords`; blank entries are ignored.
+    pub fn new<S: Into<String>>(keywords: impl IntoIterator<Item = S>) -> Self {
+        let mut unique: Vec<String> = Vec::new();
+        for keyword in keywords {
+            let keyword = keyword.into().trim().to_string();
+            if !keyword.is_empty() && !unique.iter().any(|k| k.eq_ignore_ascii_case(&keyword)) {
+                unique.push(keyword);
+            }
+        }
+        Self { keywords: unique }
+    }
+
+    pub fn keywords(&self) -> &[String] {
+        &self.keywords
+    }
+
+    /// Why a message from `from` to `target` highlights for `own_nick`,
+    /// if it does. A direct message outranks a mention, which outranks a
+    /// keyword.
+    pub fn check(&self, own_nick: &str, from: &str, target: &str, text: &str) -> Option<Highlight> {
+        if own_nick.is_empty() || from.eq_ignore_ascii_case(own_nick) {
+            return None;
+        }
+        if !target.starts_with(['#', '&']) && target.eq_ignore_ascii_case(own_nick) {
+            return Some(Highlight::DirectMessage);
+        }
+        if contains_word(text, own_nick) {
+            return Some(Highlight::Mention);
+        }
+        self.keywords
+            .iter()
+            .find(|k| contains_word(text, k))
+            .map(|k| Highlight::Keyword(k.clone()))
+    }
+}
+
+/// Characters that can be part of a nick or keyword, so a match next to
+/// one isn't a whole word.
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || "-_[]\\`^{}|".contains(c)
+}
+
+/// Whether `needle` occurs in `haystack` as a whole word, ignoring ASCII
+/// case.
+fn contains_word(haystack: &str, needle: &str) -> bool {
+    if needle.is_empty() {
+        return false;
+    }
+    let haystack = haystack.to_ascii_lowercase();
+    let needle = needle.to_ascii_lowercase();
+    haystack.match_indices(&needle).any(|(start, _)| {
+        let before = haystack[..start].chars().next_back();
+        let after = haystack[start + needle.len()..].chars().next();
+        !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mentions_match_whole_nicks_in_any_case() {
+        let h = Highlighter::default();
+        let check = |text: &str| h.check("alice", "bob", "#freeq", text);
+        assert_eq!(check("alice: ping"), Some(Highlight::Mention));
+        assert_eq!(check("thanks @Alice!"), Some(Highlight::Mention));
+        assert_eq!(
+            check("\x01ACTION waves at ALICE\x01"),
+            Some(Highlight::Mention)
+        );
+        assert_eq!(check("malice aforethought"), None);
+        assert_eq!(check("alice_ is someone else"), None);
+        assert_eq!(check("nothing to see"), None);
+    }
+
+    #[test]
+    fn direct_messages_outrank_mentions_and_own_messages_never_highlight() {
+        let h = Highlighter::new(["deploy"]);
+        assert_eq!(
+            h.check("alice", "bob", "Alice", "hi alice, deploy?"),
+            Some(Highlight::DirectMessage)
+        );
+        assert_eq!(
+            h.check("alice", "alice", "#freeq", "I'm alice, deploying"),
+            None
+        );
+        assert_eq!(h.check("alice", "Alice", "bob", "deploy now"), None);
+        assert_eq!(h.check("", "bob", "#freeq", "deploy"), None);
+    }
+
+    #[test]
+    fn keywords_are_trimmed_deduplicated_and_word_bounded() {
+        let h = Highlighter::new([" Deploy ", "deploy", "", "release notes"]);
+        assert_eq!(h.keywords(), ["Deploy", "release notes"]);
+        let check = |text: &str| h.check("alice", "bob", "#ops", text);
+        assert_eq!(
+            check("starting the DEPLOY now"),
+            Some(Highlight::Keyword("Deploy".into()))
+        );
+        assert_eq!(
+            check("see the release notes."),
+            Some(Highlight::Keyword("release notes".into()))
+        );
+        assert_eq!(check("redeployed"), None);
+        assert_eq!(check("alice, deploy"), Some(Highlight::Mention));
+    }
+}
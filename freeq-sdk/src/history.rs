@@ -0,0 +1,173 @@
+//! Collecting a CHATHISTORY batch for a caller that wants it in one piece.
+//!
+//! [`ClientHandle::fetch_history`](crate::client::ClientHandle::fetch_history)
+//! registers a collector for the target before sending the request. The
+//! event relay feeds every event it delivers to [`HistoryCollectors`],
+//! which claims the next `chathistory` batch opened for that target and
+//! hands over its messages when the batch ends. The messages are still
+//! delivered as events too.
+
+use std::sync::Arc;
+
+use tokio::sync::oneshot;
+
+use crate::event::Event;
+
+struct Collector {
+    id: u64,
+    target: String,
+    /// The batch id once the server has opened it.
+    batch: Option<String>,
+    messages: Vec<Event>,
+    tx: oneshot::Sender<Vec<Event>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    next_id: u64,
+    collectors: Vec<Collector>,
+}
+
+/// History fetches waiting for their batch.
+#[derive(Clone, Default)]
+pub(crate) struct HistoryCollectors {
+    inner: Arc<parking_lot::Mutex<Inner>>,
+}
+
+impl HistoryCollectors {
+    /// Collect the next `chathistory` batch for `target`. Register before
+    /// sending the request.
+    pub(crate) fn register(&self, target: &str) -> (u64, oneshot::Receiver<Vec<Event>>) {
+        let (tx, rx) = oneshot::channel();
+        let mut inner = self.inner.lock();
+        inner.next_id += 1;
+        let id = inner.next_id;
+        inner.collectors.push(Collector {
+            id,
+            target: target.to_string(),
+            batch: None,
+            messages: Vec::new(),
+            tx,
+        });
+        (id, rx)
+    }
+
+    /// Stop collecting for fetch `id`.
+    pub(crate) fn forget(&self, id: u64) {
+        self.inner.lock().collectors.retain(|c| c.id != id);
+    }
+
+    /// Feed one event on its way to the consumer.
+    pub(crate) fn observe(&self, event: &Event) {
+        let mut inner = self.inner.lock();
+        if inner.collectors.is_empty() {
+            return;
+        }
+        match event {
+            Event::BatchStart {
+                id,
+                batch_type,
+                target,
+            } if batch_type == "chathistory" => {
+                if let Some(c) = inner
+                    .collectors
+                    .iter_mut()
+                    .find(|c| c.batch.is_none() && c.target.eq_ignore_ascii_case(target))
+                {
+                    c.batch = Some(id.clone());
+                }
+            }
+            Event::Message { tags, .. } => {
+                let Some(batch) = tags.get("batch") else {
+                    return;
+                };
+                if let Some(c) = inner
+                    .collectors
+                    .iter_mut()
+                    .find(|c| c.batch.as_ref() == Some(batch))
+                {
+                    c.messages.push(event.clone());
+                }
+            }
+            Event::BatchEnd { id } => {
+                if let Some(i) = inner
+                    .collectors
+                    .iter()
+                    .position(|c| c.batch.as_ref() == Some(id))
+                {
+                    let c = inner.collectors.remove(i);
+                    let _ = c.tx.send(c.messages);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn message(text: &str, batch: Option<&str>) -> Event {
+        Event::Message {
+            from: "alice".into(),
+            target: "#rust".into(),
+            text: text.into(),
+            tags: batch
+                .map(|b| HashMap::from([("batch".to_string(), b.to_string())]))
+                .unwrap_or_default(),
+            formatted_text: None,
+            encrypted: false,
+        }
+    }
+
+    fn start(id: &str, target: &str) -> Event {
+        Event::BatchStart {
+            id: id.into(),
+            batch_type: "chathistory".into(),
+            target: target.into(),
+        }
+    }
+
+    fn texts(events: Vec<Event>) -> Vec<String> {
+        events
+            .into_iter()
+            .map(|e| match e {
+                Event::Message { text, .. } => text,
+                other => panic!("unexpected {other:?}"),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn collects_the_targets_next_batch() {
+        let collectors = HistoryCollectors::default();
+        let (_, first) = collectors.register("#Rust");
+        let (_, second) = collectors.register("#rust");
+
+        collectors.observe(&start("other", "#go"));
+        collectors.observe(&start("a", "#rust"));
+        collectors.observe(&message("live", None));
+        collectors.observe(&message("one", Some("a")));
+        collectors.observe(&message("go", Some("other")));
+        collectors.observe(&start("b", "#rust"));
+        collectors.observe(&message("two", Some("b")));
+        collectors.observe(&Event::BatchEnd { id: "b".into() });
+        collectors.observe(&message("three", Some("a")));
+        collectors.observe(&Event::BatchEnd { id: "a".into() });
+
+        assert_eq!(texts(first.await.unwrap()), ["one", "three"]);
+        assert_eq!(texts(second.await.unwrap()), ["two"]);
+    }
+
+    #[tokio::test]
+    async fn forgotten_fetches_collect_nothing() {
+        let collectors = HistoryCollectors::default();
+        let (id, rx) = collectors.register("#rust");
+        collectors.forget(id);
+        collectors.observe(&start("a", "#rust"));
+        collectors.observe(&Event::BatchEnd { id: "a".into() });
+        assert!(rx.await.is_err());
+    }
+}
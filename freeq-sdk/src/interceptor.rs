@@ -0,0 +1,118 @@
+//! Event middleware between the client and its consumer.
+//!
+//! Interceptors see every event before the receiver returned by
+//! [`connect`](crate::client::connect) does, in the order they were added
+//! with [`ClientHandle::add_interceptor`](crate::client::ClientHandle::add_interceptor).
+//! Each one gets an event and pushes what should continue down the chain:
+//! nothing to swallow it, a changed event to modify it, or several to emit
+//! extra events. Whatever the last interceptor pushes reaches the consumer.
+//!
+//! The SDK's own state (presence, pending replies, echo matching) is
+//! updated from the raw events first, so interceptors can't hide events
+//! from it. Interceptors run on the event task and should not block.
+//!
+//! ```rust
+//! use freeq_sdk::event::Event;
+//! use freeq_sdk::interceptor::EventInterceptor;
+//!
+//! // Drop server notices, let everything else through.
+//! let mut quiet = |event: Event, out: &mut Vec<Event>| {
+//!     if !matches!(event, Event::ServerNotice { .. }) {
+//!         out.push(event);
+//!     }
+//! };
+//! let mut out = Vec::new();
+//! quiet.intercept(Event::ServerNotice { text: "hi".into() }, &mut out);
+//! assert!(out.is_empty());
+//! ```
+
+use std::sync::Arc;
+
+use crate::event::Event;
+
+/// One link in the event chain.
+pub trait EventInterceptor: Send {
+    /// Handle `event`, pushing the events that should continue onto `out`.
+    fn intercept(&mut self, event: Event, out: &mut Vec<Event>);
+}
+
+impl<F> EventInterceptor for F
+where
+    F: FnMut(Event, &mut Vec<Event>) + Send,
+{
+    fn intercept(&mut self, event: Event, out: &mut Vec<Event>) {
+        self(event, out)
+    }
+}
+
+/// The interceptors of one connection, in order.
+#[derive(Clone, Default)]
+pub(crate) struct Interceptors(Arc<parking_lot::Mutex<Vec<Box<dyn EventInterceptor>>>>);
+
+impl Interceptors {
+    pub(crate) fn add(&self, interceptor: Box<dyn EventInterceptor>) {
+        self.0.lock().push(interceptor);
+    }
+
+    /// Run `events` through every interceptor, returning what's left.
+    pub(crate) fn apply(&self, mut events: Vec<Event>) -> Vec<Event> {
+        let mut chain = self.0.lock();
+        for interceptor in chain.iter_mut() {
+            if events.is_empty() {
+                break;
+            }
+            let mut out = Vec::with_capacity(events.len());
+            for event in events {
+                interceptor.intercept(event, &mut out);
+            }
+            events = out;
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notice(text: &str) -> Event {
+        Event::ServerNotice {
+            text: text.to_string(),
+        }
+    }
+
+    fn texts(events: &[Event]) -> Vec<&str> {
+        events
+            .iter()
+            .map(|e| match e {
+                Event::ServerNotice { text } => text.as_str(),
+                other => panic!("unexpected {other:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn interceptors_swallow_modify_and_emit_in_order() {
+        let chain = Interceptors::default();
+        assert_eq!(texts(&chain.apply(vec![notice("a")])), ["a"]);
+
+        chain.add(Box::new(|event: Event, out: &mut Vec<Event>| {
+            if let Event::ServerNotice { text } = &event {
+                if text == "drop" {
+                    return;
+                }
+                out.push(notice(&text.to_uppercase()));
+            }
+        }));
+        chain.add(Box::new(|event: Event, out: &mut Vec<Event>| {
+            out.push(event.clone());
+            out.push(event);
+        }));
+
+        assert!(chain.apply(vec![notice("drop")]).is_empty());
+        assert_eq!(
+            texts(&chain.apply(vec![notice("a"), notice("b")])),
+            ["A", "A", "B", "B"]
+        );
+    }
+}
@@ -9,30 +9,65 @@
 //! - [`client`] — Async IRC client with SASL support
 //! - [`auth`] — Challenge signing traits and implementations
 //! - [`canonical`] — JCS (RFC 8785) canonicalization for hashing/signing
+//! - [`capabilities`] — Which IRCv3 capabilities the client requests
+//! - [`channels`] — Topic and member list of each joined channel
 //! - [`crypto`] — secp256k1 and ed25519 key operations
+//! - [`decrypt`] — Interceptor that decrypts E2EE messages inline
+//! - [`diagnostics`] — Connection timeline to attach to support reports
 //! - [`did`] — DID document resolution (did:plc, did:web)
+//! - [`directory`] — Channel directory: typed, cached `LIST` results
 //! - [`pds`] — AT Protocol PDS client (session creation/verification)
 //! - [`event`] — Events emitted by the client
+//! - [`format`] — IRC formatting codes ⇄ a markdown subset
+//! - [`highlight`] — Which messages mention us or match our keywords
+//! - [`interceptor`] — Middleware that filters or rewrites events
+//! - [`p2p_dm`] — Direct DM transport negotiation over iroh
+//! - [`pending`] — Timeouts and cancellation for calls that await a reply
+//! - [`power`] — Low-power mode: sparse keepalives, batched events
+//! - [`presence`] — Per-contact online/away/offline aggregation
+//! - [`profile`] — What the server supports, and how the SDK adapts
 //! - [`irc`] — IRC message parsing/formatting
+//! - [`proto`] — IRC message parser, numerics, capability and tag names
+//! - [`testing`] — In-process mock server for app integration tests
+//! - [`tls`] — TLS trust roots, certificate pinning and ALPN
 
 pub mod auth;
 pub mod av;
 pub mod bot;
 pub mod canonical;
+pub mod capabilities;
+pub mod channels;
 pub mod client;
 pub mod crypto;
+pub mod decrypt;
+pub mod diagnostics;
 pub mod did;
+pub mod directory;
 pub mod e2ee;
 pub mod e2ee_did;
 pub mod e2ee_group;
 pub mod event;
+pub mod format;
+pub mod highlight;
+mod history;
+pub mod interceptor;
 pub mod irc;
 pub mod media;
 pub mod oauth;
 #[cfg(feature = "iroh-transport")]
 pub mod p2p;
+pub mod p2p_dm;
 pub mod pds;
+pub mod pending;
+mod pipeline;
+pub mod power;
+pub mod presence;
+pub mod profile;
+pub mod proto;
 pub mod ratchet;
+pub mod session_reset;
 pub mod ssrf;
 pub mod streaming;
+pub mod testing;
+pub mod tls;
 pub mod x3dh;
@@ -0,0 +1,539 @@
+//! Direct DM transport — moving a DM off the server onto iroh.
+//!
+//! When both ends are authenticated and the server advertises
+//! `freeq.at/p2p-dm`, two clients can trade p2p endpoint IDs through the
+//! server and then talk over a direct [`crate::p2p`] connection:
+//!
+//! ```text
+//! A → B  @+freeq.at/p2p-offer=<A's endpoint ID>   TAGMSG B
+//! B → A  @+freeq.at/p2p-answer=<B's endpoint ID>  TAGMSG A
+//! A dials B; once connected, the DM is direct.
+//! ```
+//!
+//! The server stamps each relayed offer and answer with the sender's DID
+//! (`freeq.at/p2p-did`), so a peer knows whose endpoint it is dialing. The
+//! server remains the relay whenever there is no direct connection —
+//! before negotiation finishes, after a `+freeq.at/p2p-close`, or when the
+//! connection drops — so a DM is never lost to a failed dial.
+//!
+//! [`DirectDms`] tracks that per peer. Feed it the client's
+//! [`Event`](crate::event::Event)s and the p2p subsystem's
+//! [`P2pEvent`](crate::p2p::P2pEvent)s, and send DMs through
+//! [`DirectDms::send`]; it reports each switch between relayed and
+//! direct as a [`DmEvent::Transport`].
+
+use std::collections::HashMap;
+
+use crate::proto::tags;
+
+/// Tags for a `+freeq.at/p2p-offer` TAGMSG — offer a direct connection to
+/// our endpoint.
+pub fn p2p_offer_tags(endpoint_id: &str) -> HashMap<String, String> {
+    let mut t = HashMap::new();
+    t.insert(tags::P2P_OFFER.into(), endpoint_id.to_string());
+    t
+}
+
+/// Tags for a `+freeq.at/p2p-answer` TAGMSG — accept an offer, naming our
+/// endpoint.
+pub fn p2p_answer_tags(endpoint_id: &str) -> HashMap<String, String> {
+    let mut t = HashMap::new();
+    t.insert(tags::P2P_ANSWER.into(), endpoint_id.to_string());
+    t
+}
+
+/// Tags for a `+freeq.at/p2p-close` TAGMSG — go back to relaying.
+pub fn p2p_close_tags(reason: Option<&str>) -> HashMap<String, String> {
+    let mut t = HashMap::new();
+    t.insert(
+        tags::P2P_CLOSE.into(),
+        reason.unwrap_or_default().to_string(),
+    );
+    t
+}
+
+/// A parsed negotiation TAGMSG.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Negotiation {
+    /// The sender offers a direct connection to `endpoint_id`.
+    Offer { endpoint_id: String, did: String },
+    /// The sender accepted our offer; dial `endpoint_id`.
+    Answer { endpoint_id: String, did: String },
+    /// The sender went back to relaying.
+    Close { reason: Option<String> },
+}
+
+/// Parse negotiation tags from an incoming
+/// [`Event::TagMsg`](crate::event::Event::TagMsg). Offers and answers
+/// without the server's `freeq.at/p2p-did` stamp are ignored — they did
+/// not come through a server that vetted them.
+pub fn parse_negotiation(tags: &HashMap<String, String>) -> Option<Negotiation> {
+    let did = tags.get(tags::P2P_DID).filter(|d| !d.is_empty());
+    if let Some(endpoint_id) = tags.get(tags::P2P_OFFER) {
+        return Some(Negotiation::Offer {
+            endpoint_id: endpoint_id.clone(),
+            did: did?.clone(),
+        });
+    }
+    if let Some(endpoint_id) = tags.get(tags::P2P_ANSWER) {
+        return Some(Negotiation::Answer {
+            endpoint_id: endpoint_id.clone(),
+            did: did?.clone(),
+        });
+    }
+    tags.get(tags::P2P_CLOSE).map(|r| Negotiation::Close {
+        reason: (!r.is_empty()).then(|| r.clone()),
+    })
+}
+
+/// How DMs with a peer are currently carried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmTransport {
+    /// Through the IRC server.
+    Relayed,
+    /// Over a direct iroh connection.
+    Direct,
+}
+
+/// What [`DirectDms`] reports to the app.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DmEvent {
+    /// DMs with `nick` switched transport.
+    Transport {
+        nick: String,
+        transport: DmTransport,
+    },
+    /// A DM arrived over the direct connection. `did` is the DID the
+    /// server vouched for when the connection was negotiated.
+    Message {
+        nick: String,
+        did: String,
+        text: String,
+    },
+}
+
+/// What the caller must do after [`DirectDms::on_negotiation`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// Nothing.
+    Ignore,
+    /// Send these tags back to the peer in a TAGMSG.
+    Answer(HashMap<String, String>),
+    /// Dial the peer's endpoint.
+    Dial(String),
+    /// DMs with the peer are relayed again.
+    Fallback,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    /// We sent an offer and are waiting for the answer.
+    Offered,
+    /// We answered their offer and are waiting for them to dial.
+    Answered,
+    /// We have their endpoint and are dialing it.
+    Dialing,
+    Direct,
+}
+
+#[derive(Debug, Clone)]
+struct Peer {
+    phase: Phase,
+    endpoint_id: Option<String>,
+    did: Option<String>,
+}
+
+/// Per-peer direct DM state: who we've offered, who we're connected to,
+/// and which transport each DM should take.
+///
+/// The bookkeeping methods are synchronous and do no I/O; with the
+/// `iroh-transport` feature, [`DirectDms::handle_event`],
+/// [`DirectDms::handle_p2p_event`] and [`DirectDms::send`] drive them
+/// against a live client and p2p endpoint.
+#[derive(Debug, Clone)]
+pub struct DirectDms {
+    endpoint_id: String,
+    /// Keyed by lowercased nick.
+    peers: HashMap<String, Peer>,
+}
+
+impl DirectDms {
+    /// Track direct DMs for the local p2p endpoint `endpoint_id`.
+    pub fn new(endpoint_id: &str) -> Self {
+        Self {
+            endpoint_id: endpoint_id.to_string(),
+            peers: HashMap::new(),
+        }
+    }
+
+    /// Our p2p endpoint ID.
+    pub fn endpoint_id(&self) -> &str {
+        &self.endpoint_id
+    }
+
+    /// Start negotiating with `nick`; returns the offer tags to send. A
+    /// peer we're already direct with is left alone.
+    pub fn offer(&mut self, nick: &str) -> Option<HashMap<String, String>> {
+        let key = nick.to_lowercase();
+        if self
+            .peers
+            .get(&key)
+            .is_some_and(|p| p.phase == Phase::Direct)
+        {
+            return None;
+        }
+        self.peers.insert(
+            key,
+            Peer {
+                phase: Phase::Offered,
+                endpoint_id: None,
+                did: None,
+            },
+        );
+        Some(p2p_offer_tags(&self.endpoint_id))
+    }
+
+    /// Apply a negotiation TAGMSG from `from`.
+    ///
+    /// When both sides offer at once, the offer from the lower endpoint ID
+    /// wins: the other side answers it and drops its own.
+    pub fn on_negotiation(&mut self, from: &str, negotiation: Negotiation) -> Step {
+        let key = from.to_lowercase();
+        let phase = self.peers.get(&key).map(|p| p.phase);
+        match negotiation {
+            Negotiation::Offer { endpoint_id, did } => {
+                if endpoint_id == self.endpoint_id {
+                    return Step::Ignore;
+                }
+                if phase == Some(Phase::Offered) && self.endpoint_id < endpoint_id {
+                    return Step::Ignore;
+                }
+                self.peers.insert(
+                    key,
+                    Peer {
+                        phase: Phase::Answered,
+                        endpoint_id: Some(endpoint_id),
+                        did: Some(did),
+                    },
+                );
+                Step::Answer(p2p_answer_tags(&self.endpoint_id))
+            }
+            Negotiation::Answer { endpoint_id, did } => {
+                if phase != Some(Phase::Offered) {
+                    return Step::Ignore;
+                }
+                self.peers.insert(
+                    key,
+                    Peer {
+                        phase: Phase::Dialing,
+                        endpoint_id: Some(endpoint_id.clone()),
+                        did: Some(did),
+                    },
+                );
+                Step::Dial(endpoint_id)
+            }
+            Negotiation::Close { .. } => match self.peers.remove(&key) {
+                Some(p) if p.phase == Phase::Direct => Step::Fallback,
+                _ => Step::Ignore,
+            },
+        }
+    }
+
+    /// The server refused a negotiation TAGMSG to `nick`; forget it.
+    pub fn on_refused(&mut self, nick: &str) {
+        let key = nick.to_lowercase();
+        if self
+            .peers
+            .get(&key)
+            .is_some_and(|p| p.phase != Phase::Direct)
+        {
+            self.peers.remove(&key);
+        }
+    }
+
+    /// A peer changed nick.
+    pub fn on_nick_change(&mut self, old_nick: &str, new_nick: &str) {
+        if let Some(peer) = self.peers.remove(&old_nick.to_lowercase()) {
+            self.peers.insert(new_nick.to_lowercase(), peer);
+        }
+    }
+
+    /// The p2p endpoint connected to `peer_id`.
+    pub fn on_peer_connected(&mut self, peer_id: &str) -> Option<DmEvent> {
+        let (nick, peer) = self.by_endpoint(peer_id)?;
+        if peer.phase == Phase::Direct {
+            return None;
+        }
+        peer.phase = Phase::Direct;
+        Some(DmEvent::Transport {
+            nick,
+            transport: DmTransport::Direct,
+        })
+    }
+
+    /// The p2p connection to `peer_id` dropped.
+    pub fn on_peer_disconnected(&mut self, peer_id: &str) -> Option<DmEvent> {
+        let (nick, _) = self.by_endpoint(peer_id)?;
+        let peer = self.peers.remove(&nick)?;
+        (peer.phase == Phase::Direct).then_some(DmEvent::Transport {
+            nick,
+            transport: DmTransport::Relayed,
+        })
+    }
+
+    /// A direct message arrived from `peer_id`. Messages from endpoints we
+    /// never negotiated with are dropped.
+    pub fn on_direct_message(&mut self, peer_id: &str, text: &str) -> Option<DmEvent> {
+        let (nick, peer) = self.by_endpoint(peer_id)?;
+        Some(DmEvent::Message {
+            nick,
+            did: peer.did.clone()?,
+            text: text.to_string(),
+        })
+    }
+
+    /// Stop using the direct connection to `nick`; returns the close tags
+    /// to send if there was anything to close.
+    pub fn close(&mut self, nick: &str, reason: Option<&str>) -> Option<HashMap<String, String>> {
+        self.peers
+            .remove(&nick.to_lowercase())
+            .map(|_| p2p_close_tags(reason))
+    }
+
+    /// How DMs to `nick` are currently carried.
+    pub fn transport(&self, nick: &str) -> DmTransport {
+        match self.direct_endpoint(nick) {
+            Some(_) => DmTransport::Direct,
+            None => DmTransport::Relayed,
+        }
+    }
+
+    /// The endpoint to send DMs to `nick` on, when the connection is up.
+    pub fn direct_endpoint(&self, nick: &str) -> Option<&str> {
+        self.peers
+            .get(&nick.to_lowercase())
+            .filter(|p| p.phase == Phase::Direct)
+            .and_then(|p| p.endpoint_id.as_deref())
+    }
+
+    fn by_endpoint(&mut self, peer_id: &str) -> Option<(String, &mut Peer)> {
+        self.peers
+            .iter_mut()
+            .find(|(_, p)| p.endpoint_id.as_deref() == Some(peer_id))
+            .map(|(nick, p)| (nick.clone(), p))
+    }
+}
+
+#[cfg(feature = "iroh-transport")]
+impl DirectDms {
+    /// Drive negotiation from a client event: answer offers, dial on
+    /// answers, and fall back on closes and refusals.
+    pub async fn handle_event(
+        &mut self,
+        event: &crate::event::Event,
+        client: &crate::client::ClientHandle,
+        p2p: &crate::p2p::P2pHandle,
+    ) -> anyhow::Result<Option<DmEvent>> {
+        use crate::event::Event;
+
+        match event {
+            Event::TagMsg { from, tags, .. } => {
+                let Some(negotiation) = parse_negotiation(tags) else {
+                    return Ok(None);
+                };
+                match self.on_negotiation(from, negotiation) {
+                    Step::Ignore => {}
+                    Step::Answer(tags) => client.send_tagmsg(from, tags).await?,
+                    Step::Dial(endpoint_id) => p2p.connect_peer(&endpoint_id).await?,
+                    Step::Fallback => {
+                        return Ok(Some(DmEvent::Transport {
+                            nick: from.clone(),
+                            transport: DmTransport::Relayed,
+                        }));
+                    }
+                }
+            }
+            Event::NickChanged { old_nick, new_nick } => {
+                self.on_nick_change(old_nick, new_nick);
+            }
+            // FAIL TAGMSG P2P_* <nick> :text
+            Event::ServerNotice { text } => {
+                let mut words = text.split_whitespace();
+                if words.next() == Some("TAGMSG")
+                    && words
+                        .next()
+                        .is_some_and(|c| c.starts_with("P2P_") || c == "RATE_LIMITED")
+                    && let Some(nick) = words.next()
+                {
+                    self.on_refused(nick);
+                }
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    /// Track the p2p subsystem's connections and surface direct DMs.
+    pub fn handle_p2p_event(&mut self, event: &crate::p2p::P2pEvent) -> Option<DmEvent> {
+        use crate::p2p::P2pEvent;
+
+        match event {
+            P2pEvent::PeerConnected { peer_id } => self.on_peer_connected(peer_id),
+            P2pEvent::PeerDisconnected { peer_id } => self.on_peer_disconnected(peer_id),
+            P2pEvent::DirectMessage { peer_id, text } => self.on_direct_message(peer_id, text),
+            P2pEvent::EndpointReady { .. } | P2pEvent::Error { .. } => None,
+        }
+    }
+
+    /// Send a DM to `nick` directly when connected, through the server
+    /// otherwise. Returns the transport it went over.
+    pub async fn send(
+        &self,
+        client: &crate::client::ClientHandle,
+        p2p: &crate::p2p::P2pHandle,
+        nick: &str,
+        text: &str,
+    ) -> anyhow::Result<DmTransport> {
+        if let Some(endpoint_id) = self.direct_endpoint(nick)
+            && p2p.send_message(endpoint_id, text).await.is_ok()
+        {
+            return Ok(DmTransport::Direct);
+        }
+        client.privmsg(nick, text).await?;
+        Ok(DmTransport::Relayed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ep(c: char) -> String {
+        c.to_string().repeat(64)
+    }
+
+    fn stamped(mut t: HashMap<String, String>, did: &str) -> HashMap<String, String> {
+        t.insert(tags::P2P_DID.into(), did.into());
+        t
+    }
+
+    #[test]
+    fn tags_round_trip_through_parse() {
+        let offer = stamped(p2p_offer_tags(&ep('a')), "did:plc:a");
+        assert_eq!(
+            parse_negotiation(&offer),
+            Some(Negotiation::Offer {
+                endpoint_id: ep('a'),
+                did: "did:plc:a".into()
+            })
+        );
+        let answer = stamped(p2p_answer_tags(&ep('b')), "did:plc:b");
+        assert!(matches!(
+            parse_negotiation(&answer),
+            Some(Negotiation::Answer { .. })
+        ));
+        assert_eq!(
+            parse_negotiation(&p2p_close_tags(Some("bye"))),
+            Some(Negotiation::Close {
+                reason: Some("bye".into())
+            })
+        );
+        assert_eq!(
+            parse_negotiation(&p2p_close_tags(None)),
+            Some(Negotiation::Close { reason: None })
+        );
+    }
+
+    #[test]
+    fn unstamped_offers_are_ignored() {
+        assert_eq!(parse_negotiation(&p2p_offer_tags(&ep('a'))), None);
+        assert_eq!(parse_negotiation(&HashMap::new()), None);
+    }
+
+    #[test]
+    fn offer_answer_connect_and_drop() {
+        let mut alice = DirectDms::new(&ep('a'));
+        let mut bob = DirectDms::new(&ep('b'));
+
+        let offer = alice.offer("Bob").unwrap();
+        let Some(n) = parse_negotiation(&stamped(offer, "did:plc:a")) else {
+            panic!("offer didn't parse");
+        };
+        let Step::Answer(answer) = bob.on_negotiation("alice", n) else {
+            panic!("bob should answer");
+        };
+        let n = parse_negotiation(&stamped(answer, "did:plc:b")).unwrap();
+        assert_eq!(alice.on_negotiation("bob", n), Step::Dial(ep('b')));
+        assert_eq!(alice.transport("bob"), DmTransport::Relayed);
+
+        assert_eq!(
+            alice.on_peer_connected(&ep('b')),
+            Some(DmEvent::Transport {
+                nick: "bob".into(),
+                transport: DmTransport::Direct
+            })
+        );
+        assert_eq!(alice.direct_endpoint("BOB"), Some(ep('b').as_str()));
+        assert_eq!(
+            alice.on_direct_message(&ep('b'), "hi"),
+            Some(DmEvent::Message {
+                nick: "bob".into(),
+                did: "did:plc:b".into(),
+                text: "hi".into()
+            })
+        );
+        assert_eq!(alice.on_direct_message(&ep('c'), "who?"), None);
+
+        alice.on_nick_change("bob", "robert");
+        assert_eq!(alice.transport("robert"), DmTransport::Direct);
+        assert_eq!(
+            alice.on_peer_disconnected(&ep('b')),
+            Some(DmEvent::Transport {
+                nick: "robert".into(),
+                transport: DmTransport::Relayed
+            })
+        );
+        assert_eq!(alice.transport("robert"), DmTransport::Relayed);
+    }
+
+    #[test]
+    fn simultaneous_offers_resolve_to_the_lower_endpoint() {
+        let mut alice = DirectDms::new(&ep('a'));
+        let mut bob = DirectDms::new(&ep('b'));
+        let from_alice = stamped(alice.offer("bob").unwrap(), "did:plc:a");
+        let from_bob = stamped(bob.offer("alice").unwrap(), "did:plc:b");
+
+        // Alice's endpoint sorts first, so she ignores Bob's offer…
+        assert_eq!(
+            alice.on_negotiation("bob", parse_negotiation(&from_bob).unwrap()),
+            Step::Ignore
+        );
+        // …and Bob answers hers.
+        assert!(matches!(
+            bob.on_negotiation("alice", parse_negotiation(&from_alice).unwrap()),
+            Step::Answer(_)
+        ));
+    }
+
+    #[test]
+    fn close_and_refusal_fall_back_to_relay() {
+        let mut alice = DirectDms::new(&ep('a'));
+        alice.offer("bob");
+        alice.on_refused("bob");
+        let answer = stamped(p2p_answer_tags(&ep('b')), "did:plc:b");
+        assert_eq!(
+            alice.on_negotiation("bob", parse_negotiation(&answer).unwrap()),
+            Step::Ignore,
+            "answer to a refused offer"
+        );
+
+        alice.offer("bob");
+        alice.on_negotiation("bob", parse_negotiation(&answer).unwrap());
+        alice.on_peer_connected(&ep('b'));
+        assert!(alice.offer("bob").is_none(), "already direct");
+        let close = parse_negotiation(&p2p_close_tags(None)).unwrap();
+        assert_eq!(alice.on_negotiation("bob", close), Step::Fallback);
+        assert_eq!(alice.transport("bob"), DmTransport::Relayed);
+        assert!(alice.close("bob", None).is_none());
+    }
+}
@@ -0,0 +1,354 @@
+//! Waiting for the server's answer to a command.
+//!
+//! Most [`ClientHandle`](crate::client::ClientHandle) calls only queue a
+//! line and return. The `*_with` variants (`join_with`,
+//! `history_latest_with`, …) wait until the server confirms or refuses,
+//! bounded by the [`CallOptions`] timeout and an optional
+//! [`CancellationToken`], and fail with a typed [`CallError`]. A UI that
+//! navigates away cancels the token instead of leaving the call hanging.
+//!
+//! The read loop hands every inbound line to [`PendingReplies`], which
+//! completes the waiter the line answers.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::oneshot;
+pub use tokio_util::sync::CancellationToken;
+
+use crate::irc::Message;
+use crate::proto::numeric;
+
+/// How long a call waits when [`CallOptions`] doesn't say.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Per-call limits for the `*_with` methods.
+#[derive(Debug, Clone)]
+pub struct CallOptions {
+    /// Give up with [`CallError::Timeout`] after this long.
+    pub timeout: Duration,
+    /// Give up with [`CallError::Cancelled`] when this is cancelled.
+    pub cancel: Option<CancellationToken>,
+}
+
+impl Default for CallOptions {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_TIMEOUT,
+            cancel: None,
+        }
+    }
+}
+
+impl CallOptions {
+    /// Default options with a different timeout.
+    pub fn timeout(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            ..Default::default()
+        }
+    }
+
+    /// Also stop waiting when `token` is cancelled.
+    pub fn cancel_with(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+}
+
+/// Why a call that waits for the server didn't complete.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum CallError {
+    #[error("no reply from the server within {0:?}")]
+    Timeout(Duration),
+    #[error("cancelled")]
+    Cancelled,
+    /// The server answered with an error numeric or a `FAIL`.
+    #[error("server refused ({code}): {reason}")]
+    Rejected { code: String, reason: String },
+    #[error("not connected")]
+    Disconnected,
+    /// The server lacks the capability the call needs (see
+    /// [`crate::profile`]).
+    #[error("the server doesn't support {0}")]
+    Unsupported(&'static str),
+}
+
+/// The reply that completes a call.
+#[derive(Debug, Clone)]
+pub(crate) enum Awaiting {
+    /// Our own JOIN for this channel, or a numeric refusing it.
+    Join(String),
+    /// The `chathistory` batch for this target closing.
+    History(String),
+}
+
+/// Numerics that refuse a JOIN: `<me> <channel> :<reason>`.
+const JOIN_ERRORS: &[&str] = &[
+    numeric::ERR_NOSUCHCHANNEL,
+    numeric::ERR_TOOMANYCHANNELS,
+    numeric::ERR_CHANNELISFULL,
+    numeric::ERR_INVITEONLYCHAN,
+    numeric::ERR_BANNEDFROMCHAN,
+    numeric::ERR_BADCHANNELKEY,
+    numeric::ERR_NEEDREGGEDNICK,
+];
+
+type Reply = Result<(), CallError>;
+
+struct Waiter {
+    id: u64,
+    awaiting: Awaiting,
+    /// The history batch id once the server has opened it.
+    batch: Option<String>,
+    tx: oneshot::Sender<Reply>,
+}
+
+#[derive(Default)]
+struct Inner {
+    next_id: u64,
+    waiters: Vec<Waiter>,
+    closed: bool,
+}
+
+/// Calls waiting for a reply, shared between the `ClientHandle` and the
+/// read loop.
+#[derive(Clone, Default)]
+pub(crate) struct PendingReplies {
+    inner: Arc<parking_lot::Mutex<Inner>>,
+}
+
+impl PendingReplies {
+    /// Start waiting for `awaiting`. Register before sending the command,
+    /// so a fast reply can't slip past.
+    pub(crate) fn register(&self, awaiting: Awaiting) -> (u64, oneshot::Receiver<Reply>) {
+        let (tx, rx) = oneshot::channel();
+        let mut inner = self.inner.lock();
+        inner.next_id += 1;
+        let id = inner.next_id;
+        if inner.closed {
+            let _ = tx.send(Err(CallError::Disconnected));
+        } else {
+            inner.waiters.push(Waiter {
+                id,
+                awaiting,
+                batch: None,
+                tx,
+            });
+        }
+        (id, rx)
+    }
+
+    /// Wait for the reply to call `id`, within `options`.
+    pub(crate) async fn wait(
+        &self,
+        id: u64,
+        rx: oneshot::Receiver<Reply>,
+        options: &CallOptions,
+    ) -> Reply {
+        let cancelled = async {
+            match &options.cancel {
+                Some(token) => token.cancelled().await,
+                None => std::future::pending::<()>().await,
+            }
+        };
+        let result = tokio::select! {
+            reply = rx => reply.unwrap_or(Err(CallError::Disconnected)),
+            _ = tokio::time::sleep(options.timeout) => Err(CallError::Timeout(options.timeout)),
+            _ = cancelled => Err(CallError::Cancelled),
+        };
+        if result.is_err() {
+            self.forget(id);
+        }
+        result
+    }
+
+    /// Stop waiting for call `id`.
+    pub(crate) fn forget(&self, id: u64) {
+        self.inner.lock().waiters.retain(|w| w.id != id);
+    }
+
+    /// The connection is gone: fail every waiter, and any registered later.
+    pub(crate) fn close(&self) {
+        let mut inner = self.inner.lock();
+        inner.closed = true;
+        for waiter in inner.waiters.drain(..) {
+            let _ = waiter.tx.send(Err(CallError::Disconnected));
+        }
+    }
+
+    /// Complete whichever waiter `msg` answers. `me` is our current nick.
+    pub(crate) fn observe(&self, msg: &Message, me: &str) {
+        let mut inner = self.inner.lock();
+        if inner.waiters.is_empty() {
+            return;
+        }
+        let command = msg.command.as_str();
+        let param = |i: usize| msg.params.get(i).map(String::as_str).unwrap_or("");
+        let reason = || msg.params.last().cloned().unwrap_or_default();
+
+        let (index, reply) = if command.eq_ignore_ascii_case("JOIN") {
+            let nick = msg.prefix.as_deref().unwrap_or("");
+            let nick = nick.split('!').next().unwrap_or(nick);
+            if !nick.eq_ignore_ascii_case(me) {
+                return;
+            }
+            let Some(i) = find_join(&inner.waiters, param(0)) else {
+                return;
+            };
+            (i, Ok(()))
+        } else if JOIN_ERRORS.contains(&command) {
+            let Some(i) = find_join(&inner.waiters, param(1)) else {
+                return;
+            };
+            let rejected = CallError::Rejected {
+                code: command.to_string(),
+                reason: reason(),
+            };
+            (i, Err(rejected))
+        } else if command.eq_ignore_ascii_case("BATCH") {
+            let reference = param(0);
+            if let Some(id) = reference.strip_prefix('+') {
+                if param(1) != "chathistory" {
+                    return;
+                }
+                let target = param(2);
+                if let Some(waiter) = inner.waiters.iter_mut().find(|w| {
+                    w.batch.is_none()
+                        && matches!(&w.awaiting, Awaiting::History(t) if t.eq_ignore_ascii_case(target))
+                }) {
+                    waiter.batch = Some(id.to_string());
+                }
+                return;
+            }
+            let Some(id) = reference.strip_prefix('-') else {
+                return;
+            };
+            let Some(i) = inner
+                .waiters
+                .iter()
+                .position(|w| w.batch.as_deref() == Some(id))
+            else {
+                return;
+            };
+            (i, Ok(()))
+        } else if command.eq_ignore_ascii_case("FAIL")
+            && param(0).eq_ignore_ascii_case("CHATHISTORY")
+        {
+            // FAIL carries no batch id; the server answers in order, so
+            // it belongs to the oldest history call still unanswered.
+            let Some(i) = inner
+                .waiters
+                .iter()
+                .position(|w| w.batch.is_none() && matches!(w.awaiting, Awaiting::History(_)))
+            else {
+                return;
+            };
+            let rejected = CallError::Rejected {
+                code: param(1).to_string(),
+                reason: reason(),
+            };
+            (i, Err(rejected))
+        } else {
+            return;
+        };
+        let waiter = inner.waiters.remove(index);
+        let _ = waiter.tx.send(reply);
+    }
+}
+
+fn find_join(waiters: &[Waiter], channel: &str) -> Option<usize> {
+    waiters
+        .iter()
+        .position(|w| matches!(&w.awaiting, Awaiting::Join(c) if c.eq_ignore_ascii_case(channel)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(raw: &str) -> Message {
+        Message::parse(raw).unwrap()
+    }
+
+    #[tokio::test]
+    async fn join_completes_on_our_join_or_a_refusal() {
+        let pending = PendingReplies::default();
+        let opts = CallOptions::default();
+
+        let (id, rx) = pending.register(Awaiting::Join("#Rust".into()));
+        pending.observe(&line(":bob!b@h JOIN #rust"), "alice");
+        pending.observe(&line(":alice!a@h JOIN #rust"), "alice");
+        assert_eq!(pending.wait(id, rx, &opts).await, Ok(()));
+
+        let (id, rx) = pending.register(Awaiting::Join("#vip".into()));
+        pending.observe(
+            &line(":srv 474 alice #vip :Cannot join channel (+b)"),
+            "alice",
+        );
+        assert_eq!(
+            pending.wait(id, rx, &opts).await,
+            Err(CallError::Rejected {
+                code: "474".into(),
+                reason: "Cannot join channel (+b)".into(),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn history_completes_when_its_batch_closes() {
+        let pending = PendingReplies::default();
+        let (id, rx) = pending.register(Awaiting::History("#rust".into()));
+        pending.observe(&line(":srv BATCH +ml1 draft/multiline #rust"), "alice");
+        pending.observe(&line(":srv BATCH -ml1"), "alice");
+        pending.observe(&line(":srv BATCH +h1 chathistory #rust"), "alice");
+        pending.observe(&line("@batch=h1 :bob!b@h PRIVMSG #rust :hi"), "alice");
+        pending.observe(&line(":srv BATCH -h1"), "alice");
+        assert_eq!(pending.wait(id, rx, &CallOptions::default()).await, Ok(()));
+
+        let (id, rx) = pending.register(Awaiting::History("#secret".into()));
+        pending.observe(
+            &line(":srv FAIL CHATHISTORY INVALID_TARGET #secret :No access"),
+            "alice",
+        );
+        assert!(matches!(
+            pending.wait(id, rx, &CallOptions::default()).await,
+            Err(CallError::Rejected { code, .. }) if code == "INVALID_TARGET"
+        ));
+    }
+
+    #[tokio::test]
+    async fn unanswered_calls_time_out_or_cancel() {
+        let pending = PendingReplies::default();
+        let (id, rx) = pending.register(Awaiting::Join("#slow".into()));
+        let opts = CallOptions::timeout(Duration::from_millis(20));
+        assert_eq!(
+            pending.wait(id, rx, &opts).await,
+            Err(CallError::Timeout(Duration::from_millis(20)))
+        );
+        assert!(pending.inner.lock().waiters.is_empty());
+
+        let token = CancellationToken::new();
+        let (id, rx) = pending.register(Awaiting::Join("#slow".into()));
+        token.cancel();
+        let opts = CallOptions::default().cancel_with(token);
+        assert_eq!(pending.wait(id, rx, &opts).await, Err(CallError::Cancelled));
+    }
+
+    #[tokio::test]
+    async fn closing_fails_current_and_later_calls() {
+        let pending = PendingReplies::default();
+        let (id, rx) = pending.register(Awaiting::History("#rust".into()));
+        pending.close();
+        let opts = CallOptions::default();
+        assert_eq!(
+            pending.wait(id, rx, &opts).await,
+            Err(CallError::Disconnected)
+        );
+        let (id, rx) = pending.register(Awaiting::Join("#rust".into()));
+        assert_eq!(
+            pending.wait(id, rx, &opts).await,
+            Err(CallError::Disconnected)
+        );
+    }
+}
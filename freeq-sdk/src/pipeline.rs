@@ -0,0 +1,103 @@
+//! The event pipeline between the IRC read loop and the consumer.
+//!
+//! Every event the read loop produces passes through here on its way out:
+//! the observers (diagnostics, channel lists, capability negotiation)
+//! see it, the presence and roster trackers turn it into
+//! `PresenceChanged` and member events, the interceptors may rewrite or
+//! drop it, the history collectors pick out CHATHISTORY replies, and in
+//! low-power mode what's left is batched (see [`crate::power`]).
+
+use std::sync::Arc;
+
+use tokio::sync::mpsc;
+
+use crate::channels::ChannelTracker;
+use crate::directory::ChannelLists;
+use crate::event::Event;
+use crate::history::HistoryCollectors;
+use crate::interceptor::Interceptors;
+use crate::power::{Coalescer, LowPower};
+use crate::presence::PresenceTracker;
+use crate::profile::Negotiation;
+
+/// The state a pipeline shares with the [`ClientHandle`] that owns it.
+/// Cheap to clone; clones share state.
+///
+/// [`ClientHandle`]: crate::client::ClientHandle
+#[derive(Clone, Default)]
+pub(crate) struct Pipeline {
+    pub(crate) presence: Arc<parking_lot::Mutex<PresenceTracker>>,
+    pub(crate) channels: Arc<parking_lot::Mutex<ChannelTracker>>,
+    pub(crate) interceptors: Interceptors,
+    pub(crate) history: HistoryCollectors,
+    pub(crate) channel_lists: ChannelLists,
+    pub(crate) low_power: LowPower,
+    pub(crate) negotiation: Negotiation,
+}
+
+impl Pipeline {
+    /// Spawn the pipeline. Returns the sender the read loop writes to and
+    /// the receiver the consumer reads from; every event is forwarded,
+    /// followed by any `PresenceChanged` and roster events it caused, all
+    /// through the interceptors and then past the history collectors.
+    pub(crate) fn spawn(&self) -> (mpsc::Sender<Event>, mpsc::Receiver<Event>) {
+        let (inner_tx, mut inner_rx) = mpsc::channel::<Event>(4096);
+        let (outer_tx, outer_rx) = mpsc::channel(4096);
+        let pipeline = self.clone();
+        tokio::spawn(async move {
+            let mut power = pipeline.low_power.subscribe();
+            let mut batch = Coalescer::default();
+            loop {
+                let due = batch.due();
+                let ready = tokio::select! {
+                    event = inner_rx.recv() => {
+                        let Some(event) = event else { break };
+                        pipeline.process(event, &mut batch)
+                    }
+                    _ = tokio::time::sleep_until(due.unwrap_or_else(tokio::time::Instant::now)), if due.is_some() => {
+                        batch.flush()
+                    }
+                    Ok(()) = power.changed() => {
+                        if *power.borrow_and_update() { Vec::new() } else { batch.flush() }
+                    }
+                };
+                for event in ready {
+                    if outer_tx.send(event).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            for event in batch.flush() {
+                if outer_tx.send(event).await.is_err() {
+                    return;
+                }
+            }
+        });
+        (inner_tx, outer_rx)
+    }
+
+    /// Run one event from the read loop through the pipeline. Returns the
+    /// events ready for the consumer; in low-power mode some stay in
+    /// `batch` until it's due.
+    fn process(&self, event: Event, batch: &mut Coalescer) -> Vec<Event> {
+        crate::diagnostics::observe(&event);
+        self.channel_lists.observe(&event);
+        self.negotiation.observe(&event);
+        let changes = self.presence.lock().observe(&event);
+        let roster = self.channels.lock().observe(&event);
+        let mut events = vec![event];
+        events.extend(
+            changes
+                .into_iter()
+                .map(|(did_or_nick, state)| Event::PresenceChanged { did_or_nick, state }),
+        );
+        events.extend(roster.into_iter().map(Event::from));
+        let on = self.low_power.is_on();
+        let mut ready = Vec::new();
+        for event in self.interceptors.apply(events) {
+            self.history.observe(&event);
+            ready.extend(batch.push(event, on));
+        }
+        ready
+    }
+}
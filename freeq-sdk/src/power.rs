@@ -0,0 +1,212 @@
+//! Low-power mode: fewer wakeups on battery or cellular.
+//!
+//! Switch it with [`ClientHandle::set_low_power`](crate::client::ClientHandle::set_low_power),
+//! typically from the OS's low-power or metered-network state. It takes
+//! effect immediately and can be switched back at any time. While it is
+//! on:
+//!
+//! - keepalive PINGs go out every [`PING_INTERVAL`] instead of every
+//!   minute, and the connection is only declared dead after
+//!   [`PING_TIMEOUT`] of silence;
+//! - typing and away changes aren't relayed: `typing_start` and
+//!   `typing_stop` send nothing, and incoming typing TAGMSGs and
+//!   [`Event::AwayChanged`] are dropped (presence still tracks away);
+//! - events reach the receiver in batches every [`BATCH_INTERVAL`]
+//!   rather than one at a time, except connection-state events
+//!   (connect, registration, authentication, disconnect), which go out
+//!   straight away along with whatever was waiting;
+//! - non-essential queries ([`DEFERRED_COMMANDS`]) are held back and sent
+//!   together every [`BATCH_INTERVAL`].
+//!
+//! Turning it off delivers the pending batch and sends held queries at
+//! once.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+use crate::event::Event;
+use crate::irc::Message;
+
+/// Keepalive PING interval in low-power mode.
+pub const PING_INTERVAL: Duration = Duration::from_secs(240);
+
+/// Silence after which the connection is declared dead in low-power mode.
+pub const PING_TIMEOUT: Duration = Duration::from_secs(480);
+
+/// How often batched events are delivered and held queries sent.
+pub const BATCH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Commands held back in low-power mode: lookups the app can wait for.
+pub const DEFERRED_COMMANDS: &[&str] = &["WHO", "WHOIS", "WHOWAS", "LIST", "METADATA"];
+
+/// Keepalive PING interval and timeout for the current mode.
+pub(crate) fn keepalive(low_power: bool) -> (Duration, Duration) {
+    if low_power {
+        (PING_INTERVAL, PING_TIMEOUT)
+    } else {
+        (Duration::from_secs(60), Duration::from_secs(120))
+    }
+}
+
+/// The low-power switch of one connection, shared by its handle, read
+/// loop and event relay.
+#[derive(Clone)]
+pub(crate) struct LowPower(Arc<watch::Sender<bool>>);
+
+impl Default for LowPower {
+    fn default() -> Self {
+        Self(Arc::new(watch::channel(false).0))
+    }
+}
+
+impl LowPower {
+    pub(crate) fn set(&self, on: bool) {
+        self.0
+            .send_if_modified(|current| std::mem::replace(current, on) != on);
+    }
+
+    pub(crate) fn is_on(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<bool> {
+        self.0.subscribe()
+    }
+}
+
+/// Whether `line` is a query to hold back in low-power mode.
+pub(crate) fn is_deferrable(line: &str) -> bool {
+    Message::parse(line).is_some_and(|msg| {
+        DEFERRED_COMMANDS
+            .iter()
+            .any(|c| msg.command.eq_ignore_ascii_case(c))
+    })
+}
+
+/// Typing and away relays, dropped in low-power mode.
+fn is_relay(event: &Event) -> bool {
+    match event {
+        Event::AwayChanged { .. } => true,
+        Event::TagMsg { tags, .. } => tags.contains_key("+typing"),
+        _ => false,
+    }
+}
+
+/// Events that go out straight away even in low-power mode.
+fn is_urgent(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::Connected
+            | Event::Registered { .. }
+            | Event::Authenticated { .. }
+            | Event::AuthFailed { .. }
+            | Event::Disconnected { .. }
+    )
+}
+
+/// Holds events back in low-power mode and releases them in batches.
+#[derive(Default)]
+pub(crate) struct Coalescer {
+    held: Vec<Event>,
+    due: Option<Instant>,
+}
+
+impl Coalescer {
+    /// Take `event` and return what should be delivered now.
+    pub(crate) fn push(&mut self, event: Event, low_power: bool) -> Vec<Event> {
+        if low_power && is_relay(&event) {
+            return Vec::new();
+        }
+        if low_power && !is_urgent(&event) {
+            self.held.push(event);
+            self.due
+                .get_or_insert_with(|| Instant::now() + BATCH_INTERVAL);
+            return Vec::new();
+        }
+        let mut ready = self.flush();
+        ready.push(event);
+        ready
+    }
+
+    /// When the held events are due, if there are any.
+    pub(crate) fn due(&self) -> Option<Instant> {
+        self.due
+    }
+
+    /// Release every held event.
+    pub(crate) fn flush(&mut self) -> Vec<Event> {
+        self.due = None;
+        std::mem::take(&mut self.held)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(text: &str) -> Event {
+        Event::Message {
+            from: "bob".into(),
+            target: "#a".into(),
+            text: text.into(),
+            tags: Default::default(),
+            formatted_text: None,
+            encrypted: false,
+        }
+    }
+
+    #[test]
+    fn low_power_batches_and_drops_relays() {
+        let mut batch = Coalescer::default();
+        assert_eq!(batch.push(message("now"), false).len(), 1);
+        assert!(batch.due().is_none());
+
+        assert!(batch.push(message("one"), true).is_empty());
+        assert!(batch.push(message("two"), true).is_empty());
+        let typing = Event::TagMsg {
+            from: "bob".into(),
+            target: "#a".into(),
+            tags: [("+typing".to_string(), "active".to_string())].into(),
+        };
+        assert!(batch.push(typing, true).is_empty());
+        let away = Event::AwayChanged {
+            nick: "bob".into(),
+            away_msg: Some("lunch".into()),
+        };
+        assert!(batch.push(away, true).is_empty());
+        assert!(batch.due().is_some());
+
+        // A disconnect goes out at once, behind what was held.
+        let ready = batch.push(
+            Event::Disconnected {
+                reason: "EOF".into(),
+            },
+            true,
+        );
+        assert_eq!(ready.len(), 3);
+        assert!(matches!(&ready[0], Event::Message { text, .. } if text == "one"));
+        assert!(matches!(ready[2], Event::Disconnected { .. }));
+        assert!(batch.due().is_none() && batch.flush().is_empty());
+    }
+
+    #[test]
+    fn only_lookups_are_deferred() {
+        assert!(is_deferrable("WHOIS bob"));
+        assert!(is_deferrable("@label=1 who #a"));
+        assert!(!is_deferrable("PRIVMSG #a :hi"));
+        assert!(!is_deferrable("CHATHISTORY LATEST #a * 50"));
+
+        let power = LowPower::default();
+        let mut rx = power.subscribe();
+        assert!(!power.is_on());
+        power.set(true);
+        assert!(power.is_on() && rx.has_changed().unwrap());
+        rx.borrow_and_update();
+        power.set(true);
+        assert!(!rx.has_changed().unwrap());
+        assert_eq!(keepalive(true), (PING_INTERVAL, PING_TIMEOUT));
+    }
+}
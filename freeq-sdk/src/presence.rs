@@ -0,0 +1,416 @@
+//! Presence aggregation — one online/away/offline value per contact.
+//!
+//! Raw IRC spreads presence over many signals: JOIN/PART/KICK and NAMES
+//! tell us who shares a channel with us, away-notify reports AWAY changes,
+//! QUIT says someone left the network, and MONITOR (730/731) covers
+//! contacts we share no channel with. [`PresenceTracker`] folds all of
+//! these into a single [`PresenceState`] per contact and reports changes
+//! as [`Event::PresenceChanged`].
+//!
+//! Contacts are keyed by DID when the server told us one (extended-join
+//! account), otherwise by nick. Several sessions behind one DID aggregate
+//! to the "most present" state: any online session makes the DID online.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::event::Event;
+use crate::irc::Message;
+
+/// A contact's aggregated presence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PresenceState {
+    /// Not visible: quit, reported offline by MONITOR, or no longer sharing
+    /// a channel with us.
+    Offline,
+    /// Visible but marked AWAY.
+    Away,
+    /// Visible and not away.
+    Online,
+}
+
+impl PresenceState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PresenceState::Offline => "offline",
+            PresenceState::Away => "away",
+            PresenceState::Online => "online",
+        }
+    }
+}
+
+/// What we know about one nick.
+#[derive(Debug, Default)]
+struct Contact {
+    nick: String,
+    did: Option<String>,
+    /// Channels (case-folded) we share with this nick.
+    channels: HashSet<String>,
+    /// MONITOR reported the nick online.
+    monitored: bool,
+    away: bool,
+}
+
+impl Contact {
+    fn id(&self) -> &str {
+        self.did.as_deref().unwrap_or(&self.nick)
+    }
+
+    fn state(&self) -> PresenceState {
+        if self.channels.is_empty() && !self.monitored {
+            PresenceState::Offline
+        } else if self.away {
+            PresenceState::Away
+        } else {
+            PresenceState::Online
+        }
+    }
+}
+
+fn fold(name: &str) -> String {
+    name.to_ascii_lowercase()
+}
+
+/// Strip NAMES status prefixes (`@`, `%`, `+`, …) and any `!user@host`.
+fn names_nick(entry: &str) -> &str {
+    let nick = entry.trim_start_matches(['~', '&', '@', '%', '+']);
+    nick.split('!').next().unwrap_or(nick)
+}
+
+/// Aggregates presence signals from the event stream.
+#[derive(Debug, Default)]
+pub struct PresenceTracker {
+    /// Our own nick, case-folded. Never reported as a contact.
+    me: Option<String>,
+    /// Known nicks, keyed by case-folded nick.
+    contacts: HashMap<String, Contact>,
+    /// Last reported state per DID-or-nick. Offline contacts are absent.
+    reported: HashMap<String, PresenceState>,
+}
+
+impl PresenceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Current state of a contact, by DID or nick (case-insensitive).
+    pub fn get(&self, did_or_nick: &str) -> PresenceState {
+        if let Some(state) = self.reported.get(did_or_nick) {
+            return *state;
+        }
+        self.contacts
+            .get(&fold(did_or_nick))
+            .and_then(|c| self.reported.get(c.id()))
+            .copied()
+            .unwrap_or(PresenceState::Offline)
+    }
+
+    /// Every contact currently online or away, sorted by DID-or-nick.
+    pub fn snapshot(&self) -> Vec<(String, PresenceState)> {
+        let mut all: Vec<_> = self
+            .reported
+            .iter()
+            .map(|(id, state)| (id.clone(), *state))
+            .collect();
+        all.sort();
+        all
+    }
+
+    /// Feed one event. Returns the `(did_or_nick, state)` pairs whose
+    /// presence changed as a result.
+    pub fn observe(&mut self, event: &Event) -> Vec<(String, PresenceState)> {
+        let mut touched: Vec<String> = Vec::new();
+        match event {
+            Event::Registered { nick } => self.me = Some(fold(nick)),
+            Event::Joined {
+                channel,
+                nick,
+                account,
+            } if !self.is_me(nick) => {
+                let old_id = self.id_of(nick);
+                let contact = self.contact(nick);
+                contact.channels.insert(fold(channel));
+                if account.is_some() {
+                    contact.did = account.clone();
+                }
+                touched.extend(old_id);
+                touched.push(self.contact(nick).id().to_string());
+            }
+            Event::Names { channel, nicks } => {
+                for entry in nicks {
+                    let nick = names_nick(entry);
+                    if nick.is_empty() || self.is_me(nick) {
+                        continue;
+                    }
+                    let contact = self.contact(nick);
+                    contact.channels.insert(fold(channel));
+                    touched.push(contact.id().to_string());
+                }
+            }
+            Event::Parted { channel, nick } | Event::Kicked { channel, nick, .. } => {
+                let channel = fold(channel);
+                if self.is_me(nick) {
+                    for contact in self.contacts.values_mut() {
+                        if contact.channels.remove(&channel) {
+                            touched.push(contact.id().to_string());
+                        }
+                    }
+                } else if let Some(contact) = self.contacts.get_mut(&fold(nick)) {
+                    contact.channels.remove(&channel);
+                    touched.push(contact.id().to_string());
+                }
+            }
+            Event::AwayChanged { nick, away_msg } if !self.is_me(nick) => {
+                let contact = self.contact(nick);
+                contact.away = away_msg.is_some();
+                touched.push(contact.id().to_string());
+            }
+            Event::NickChanged { old_nick, new_nick } => {
+                if self.is_me(old_nick) {
+                    self.me = Some(fold(new_nick));
+                } else if let Some(mut contact) = self.contacts.remove(&fold(old_nick)) {
+                    touched.push(contact.id().to_string());
+                    contact.nick = new_nick.clone();
+                    touched.push(contact.id().to_string());
+                    self.contacts.insert(fold(new_nick), contact);
+                }
+            }
+            Event::UserQuit { nick, .. } => {
+                if let Some(contact) = self.contacts.remove(&fold(nick)) {
+                    touched.push(contact.id().to_string());
+                }
+            }
+            Event::Disconnected { .. } => {
+                // Nothing we knew is current any more.
+                touched.extend(self.reported.keys().cloned());
+                self.contacts.clear();
+            }
+            Event::RawLine(line) => {
+                if let Some(msg) = Message::parse(line) {
+                    touched.extend(self.observe_monitor(&msg));
+                }
+            }
+            _ => {}
+        }
+        self.settle(touched)
+    }
+
+    /// RPL_MONONLINE (730) / RPL_MONOFFLINE (731): `<me> :nick[!user@host],…`
+    fn observe_monitor(&mut self, msg: &Message) -> Vec<String> {
+        let online = match msg.command.as_str() {
+            "730" => true,
+            "731" => false,
+            _ => return Vec::new(),
+        };
+        let Some(targets) = msg.params.get(1) else {
+            return Vec::new();
+        };
+        let mut touched = Vec::new();
+        for target in targets.split(',') {
+            let nick = target.split('!').next().unwrap_or(target);
+            if nick.is_empty() || self.is_me(nick) {
+                continue;
+            }
+            let contact = self.contact(nick);
+            contact.monitored = online;
+            touched.push(contact.id().to_string());
+        }
+        touched
+    }
+
+    fn is_me(&self, nick: &str) -> bool {
+        self.me.as_deref() == Some(fold(nick).as_str())
+    }
+
+    fn id_of(&self, nick: &str) -> Option<String> {
+        self.contacts.get(&fold(nick)).map(|c| c.id().to_string())
+    }
+
+    fn contact(&mut self, nick: &str) -> &mut Contact {
+        self.contacts.entry(fold(nick)).or_insert_with(|| Contact {
+            nick: nick.to_string(),
+            ..Default::default()
+        })
+    }
+
+    /// Recompute the touched ids, forget contacts that went offline, and
+    /// return the ids whose aggregated state changed.
+    fn settle(&mut self, touched: Vec<String>) -> Vec<(String, PresenceState)> {
+        self.contacts
+            .retain(|_, c| c.state() != PresenceState::Offline);
+        let mut changes = Vec::new();
+        let mut seen = HashSet::new();
+        for id in touched {
+            if !seen.insert(id.clone()) {
+                continue;
+            }
+            let state = self
+                .contacts
+                .values()
+                .filter(|c| c.id() == id)
+                .map(Contact::state)
+                .max()
+                .unwrap_or(PresenceState::Offline);
+            let previous = self
+                .reported
+                .get(&id)
+                .copied()
+                .unwrap_or(PresenceState::Offline);
+            if state == previous {
+                continue;
+            }
+            if state == PresenceState::Offline {
+                self.reported.remove(&id);
+            } else {
+                self.reported.insert(id.clone(), state);
+            }
+            changes.push((id, state));
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn joined(channel: &str, nick: &str, account: Option<&str>) -> Event {
+        Event::Joined {
+            channel: channel.to_string(),
+            nick: nick.to_string(),
+            account: account.map(str::to_string),
+        }
+    }
+
+    fn parted(channel: &str, nick: &str) -> Event {
+        Event::Parted {
+            channel: channel.to_string(),
+            nick: nick.to_string(),
+        }
+    }
+
+    fn tracker() -> PresenceTracker {
+        let mut t = PresenceTracker::new();
+        t.observe(&Event::Registered { nick: "me".into() });
+        t
+    }
+
+    #[test]
+    fn channel_membership_drives_online_offline() {
+        let mut t = tracker();
+        assert!(t.observe(&joined("#a", "me", None)).is_empty());
+        let changes = t.observe(&Event::Names {
+            channel: "#a".into(),
+            nicks: vec!["@me".into(), "+bob".into(), "carol".into()],
+        });
+        assert_eq!(
+            changes,
+            vec![
+                ("bob".to_string(), PresenceState::Online),
+                ("carol".to_string(), PresenceState::Online)
+            ]
+        );
+        // Seen in a second channel: no change
+        assert!(t.observe(&joined("#b", "bob", None)).is_empty());
+        assert!(t.observe(&parted("#a", "bob")).is_empty());
+        assert_eq!(
+            t.observe(&parted("#b", "bob")),
+            vec![("bob".to_string(), PresenceState::Offline)]
+        );
+        // We leave: everyone only seen there goes offline
+        assert_eq!(
+            t.observe(&parted("#A", "me")),
+            vec![("carol".to_string(), PresenceState::Offline)]
+        );
+        assert!(t.snapshot().is_empty());
+    }
+
+    #[test]
+    fn away_quit_and_nick_changes() {
+        let mut t = tracker();
+        t.observe(&joined("#a", "bob", None));
+        assert_eq!(
+            t.observe(&Event::AwayChanged {
+                nick: "bob".into(),
+                away_msg: Some("lunch".into()),
+            }),
+            vec![("bob".to_string(), PresenceState::Away)]
+        );
+        assert_eq!(t.get("BOB"), PresenceState::Away);
+        assert_eq!(
+            t.observe(&Event::NickChanged {
+                old_nick: "bob".into(),
+                new_nick: "bobby".into(),
+            }),
+            vec![
+                ("bob".to_string(), PresenceState::Offline),
+                ("bobby".to_string(), PresenceState::Away)
+            ]
+        );
+        assert_eq!(
+            t.observe(&Event::UserQuit {
+                nick: "bobby".into(),
+                reason: "bye".into(),
+            }),
+            vec![("bobby".to_string(), PresenceState::Offline)]
+        );
+    }
+
+    #[test]
+    fn did_keys_aggregate_sessions() {
+        let mut t = tracker();
+        let did = "did:plc:alice";
+        assert_eq!(
+            t.observe(&joined("#a", "alice", Some(did))),
+            vec![(did.to_string(), PresenceState::Online)]
+        );
+        t.observe(&joined("#a", "alice-phone", Some(did)));
+        t.observe(&Event::AwayChanged {
+            nick: "alice-phone".into(),
+            away_msg: Some("idle".into()),
+        });
+        // One session still online: the DID stays online
+        assert_eq!(t.get(did), PresenceState::Online);
+        assert_eq!(t.get("alice-phone"), PresenceState::Online);
+        // A nick change doesn't change a DID-keyed contact
+        assert!(
+            t.observe(&Event::NickChanged {
+                old_nick: "alice".into(),
+                new_nick: "alice2".into(),
+            })
+            .is_empty()
+        );
+        assert_eq!(
+            t.observe(&Event::UserQuit {
+                nick: "alice2".into(),
+                reason: String::new(),
+            }),
+            vec![(did.to_string(), PresenceState::Away)]
+        );
+    }
+
+    #[test]
+    fn monitor_numerics_and_disconnect() {
+        let mut t = tracker();
+        let changes = t.observe(&Event::RawLine(
+            ":srv 730 me :dave!d@host,erin!e@host".into(),
+        ));
+        assert_eq!(
+            changes,
+            vec![
+                ("dave".to_string(), PresenceState::Online),
+                ("erin".to_string(), PresenceState::Online)
+            ]
+        );
+        assert_eq!(
+            t.observe(&Event::RawLine(":srv 731 me :dave".into())),
+            vec![("dave".to_string(), PresenceState::Offline)]
+        );
+        assert_eq!(
+            t.observe(&Event::Disconnected {
+                reason: "EOF".into(),
+            }),
+            vec![("erin".to_string(), PresenceState::Offline)]
+        );
+        assert_eq!(t.get("erin"), PresenceState::Offline);
+    }
+}
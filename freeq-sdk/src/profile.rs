@@ -0,0 +1,173 @@
+//! What the server can do, so the SDK degrades gracefully on plain IRC.
+//!
+//! Once registration completes, the capabilities the server acknowledged
+//! put it in one of three [`ServerProfile`]s. On anything short of
+//! [`ServerProfile::FreeqFull`] the SDK adjusts rather than misbehaving:
+//! messages that arrive without a `msgid` get a local one (see
+//! [`local_msgid`]) so replies, reactions and de-duplication in the app
+//! keep working, and without `draft/chathistory` the history calls fail
+//! straight away with [`CallError::Unsupported`](crate::pending::CallError)
+//! instead of waiting for a batch that never comes.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::event::Event;
+use crate::proto::caps;
+
+/// Prefix of the msgids the SDK makes up for messages that lack one.
+/// The server doesn't know them, so don't send them back in tags.
+pub const LOCAL_MSGID_PREFIX: &str = "local-";
+
+/// How much of the freeq protocol the server speaks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ServerProfile {
+    /// A freeq server: message tags, chat history and the `freeq.at/`
+    /// extensions.
+    FreeqFull,
+    /// An IRCv3 server with message tags but not all of the above.
+    Ircv3Basic,
+    /// Plain IRC: no message tags, so no msgids, history, reactions or
+    /// edits.
+    Legacy,
+}
+
+impl ServerProfile {
+    /// The profile the acknowledged capabilities `acked` describe.
+    pub fn from_caps(acked: &HashSet<String>) -> Self {
+        if !acked.contains(caps::MESSAGE_TAGS) {
+            Self::Legacy
+        } else if acked.contains(caps::CHATHISTORY)
+            && acked.iter().any(|c| c.starts_with("freeq.at/"))
+        {
+            Self::FreeqFull
+        } else {
+            Self::Ircv3Basic
+        }
+    }
+
+    /// `freeq-full`, `ircv3-basic` or `legacy`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::FreeqFull => "freeq-full",
+            Self::Ircv3Basic => "ircv3-basic",
+            Self::Legacy => "legacy",
+        }
+    }
+}
+
+impl std::fmt::Display for ServerProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A msgid for a message the server sent without one, unique within
+/// this process.
+pub fn local_msgid() -> String {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    format!(
+        "{LOCAL_MSGID_PREFIX}{millis:x}-{:x}",
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// Whether `msgid` was made up by [`local_msgid`].
+pub fn is_local_msgid(msgid: &str) -> bool {
+    msgid.starts_with(LOCAL_MSGID_PREFIX)
+}
+
+/// Give `tags` a local msgid if the server didn't send one.
+pub(crate) fn ensure_msgid(tags: &mut std::collections::HashMap<String, String>) {
+    tags.entry("msgid".to_string()).or_insert_with(local_msgid);
+}
+
+/// Whether capability negotiation has finished on the current
+/// connection, i.e. whether the acknowledged capabilities are final
+/// enough to pick a profile from.
+#[derive(Clone, Default)]
+pub(crate) struct Negotiation {
+    done: Arc<AtomicBool>,
+}
+
+impl Negotiation {
+    pub(crate) fn done(&self) -> bool {
+        self.done.load(Ordering::Relaxed)
+    }
+
+    /// Feed one event: registering finishes negotiation, disconnecting
+    /// starts it over.
+    pub(crate) fn observe(&self, event: &Event) {
+        match event {
+            Event::Registered { .. } => self.done.store(true, Ordering::Relaxed),
+            Event::Disconnected { .. } => self.done.store(false, Ordering::Relaxed),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn acked(list: &[&str]) -> HashSet<String> {
+        list.iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn profiles_follow_the_acknowledged_caps() {
+        assert_eq!(ServerProfile::from_caps(&acked(&[])), ServerProfile::Legacy);
+        assert_eq!(
+            ServerProfile::from_caps(&acked(&["multi-prefix", "away-notify"])),
+            ServerProfile::Legacy
+        );
+        assert_eq!(
+            ServerProfile::from_caps(&acked(&["message-tags", "draft/chathistory"])),
+            ServerProfile::Ircv3Basic
+        );
+        assert_eq!(
+            ServerProfile::from_caps(&acked(&["message-tags", "freeq.at/priority"])),
+            ServerProfile::Ircv3Basic
+        );
+        let full = ServerProfile::from_caps(&acked(&[
+            "message-tags",
+            "draft/chathistory",
+            "freeq.at/priority",
+        ]));
+        assert_eq!(full, ServerProfile::FreeqFull);
+        assert_eq!(full.to_string(), "freeq-full");
+    }
+
+    #[test]
+    fn missing_msgids_are_filled_in_locally() {
+        let mut tags = std::collections::HashMap::new();
+        ensure_msgid(&mut tags);
+        let first = tags["msgid"].clone();
+        assert!(is_local_msgid(&first));
+        ensure_msgid(&mut tags);
+        assert_eq!(tags["msgid"], first, "an existing msgid is kept");
+        assert_ne!(local_msgid(), local_msgid());
+
+        tags.insert("msgid".into(), "01HSERVER".into());
+        ensure_msgid(&mut tags);
+        assert_eq!(tags["msgid"], "01HSERVER");
+        assert!(!is_local_msgid("01HSERVER"));
+    }
+
+    #[test]
+    fn negotiation_finishes_at_registration_until_disconnect() {
+        let negotiation = Negotiation::default();
+        assert!(!negotiation.done());
+        negotiation.observe(&Event::Registered { nick: "me".into() });
+        assert!(negotiation.done());
+        negotiation.observe(&Event::Disconnected {
+            reason: "EOF".into(),
+        });
+        assert!(!negotiation.done());
+    }
+}
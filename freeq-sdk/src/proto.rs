@@ -0,0 +1,23 @@
+//! Zero-copy IRC message parsing, and the protocol constants the SDK and
+//! server share.
+//!
+//! This re-exports the `freeq-proto` crate. [`MessageRef`] borrows every
+//! part of a line from the input, so bots and bridges can inspect
+//! [`Event::RawLine`](crate::event::Event::RawLine) traffic without
+//! allocating; [`Message`] is the owned form for building lines. Match
+//! numerics, capabilities and tags against [`numeric`], [`caps`] and
+//! [`tags`] rather than string literals.
+//!
+//! ```
+//! use freeq_sdk::proto::{MessageRef, tags};
+//!
+//! let line = "@msgid=abc;+draft/reply=xyz :alice!a@host PRIVMSG #chan :hi there";
+//! let msg = MessageRef::parse(line).unwrap();
+//! assert!(msg.is("privmsg"));
+//! assert_eq!(msg.prefix().unwrap().nick, "alice");
+//! assert_eq!(msg.param(1), Some("hi there"));
+//! assert_eq!(msg.tag(tags::DRAFT_REPLY).unwrap().unescape(), "xyz");
+//! ```
+
+pub use freeq_proto::message::*;
+pub use freeq_proto::{caps, numeric, tags};
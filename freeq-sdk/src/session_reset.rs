@@ -0,0 +1,594 @@
+//! Session reset — recovering a Double Ratchet DM session whose two ends
+//! have diverged.
+//!
+//! A ratchet that falls out of step (a restored backup, a lost session
+//! file, a message applied on one device but not the other) fails every
+//! decrypt from then on, and nothing in [`crate::ratchet`] can repair it.
+//! Instead, one side throws the session away and starts a fresh one from
+//! the other's pre-key bundle, telling it so in a signed TAGMSG:
+//!
+//! ```text
+//! A → B  @+freeq.at/e2ee-reset=<base64url JSON ResetRequest>  TAGMSG B
+//! ```
+//!
+//! The request carries A's X3DH [`InitialMessage`] for B's current signed
+//! pre-key and a `confirm` ciphertext — the first message of A's new
+//! session — so B can rebuild the same session, prove it matches, and
+//! send on it straight away. The request is signed with A's ed25519 key,
+//! the one that signs A's pre-keys, over every field but the signature.
+//!
+//! Replays are refused: a request must be addressed to us, be no more than
+//! [`MAX_CLOCK_SKEW_MS`] from our clock, and be newer than the last one we
+//! accepted from that peer. When both sides reset at once, the request
+//! from the lower DID wins; the other side accepts it and drops its own.
+//!
+//! [`SessionResets`] keeps the keys and per-peer bookkeeping, and installs
+//! new sessions into the [`SessionStore`] shared with the
+//! [`Decrypt`](crate::decrypt::Decrypt) interceptor. Feed it the client's
+//! events with [`SessionResets::handle_event`]: after
+//! [`FAILURES_BEFORE_RESET`] consecutive ENC3 messages from a peer fail to
+//! decrypt, it resets the session on its own, at most once per
+//! [`RESET_COOLDOWN_MS`].
+
+use std::collections::HashMap;
+
+use aes_gcm::aead::OsRng;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as B64;
+use ed25519_dalek::{Signer, Verifier};
+use rand::RngCore;
+
+use crate::decrypt::SessionStore;
+use crate::event::Event;
+use crate::proto::tags;
+use crate::ratchet::{self, RatchetError, Session};
+use crate::x3dh::{self, IdentityKeyPair, InitialMessage, PreKeyBundle, SignedPreKey, X3dhError};
+
+/// Consecutive undecryptable messages from a peer before we reset.
+pub const FAILURES_BEFORE_RESET: u32 = 3;
+
+/// Minimum time between resets with the same peer.
+pub const RESET_COOLDOWN_MS: u64 = 60_000;
+
+/// How far a request's timestamp may be from our clock.
+pub const MAX_CLOCK_SKEW_MS: u64 = 5 * 60_000;
+
+/// The reset was triggered by messages that stopped decrypting.
+pub const REASON_DECRYPT_FAILED: &str = "decrypt-failed";
+
+/// Longest reason we send or accept.
+const MAX_REASON_LEN: usize = 64;
+
+/// Our side of the E2EE identity.
+#[derive(Clone)]
+pub struct LocalKeys {
+    pub did: String,
+    pub identity: IdentityKeyPair,
+    pub spk: SignedPreKey,
+    /// The key that signed `spk`; also signs our reset requests.
+    pub signing_key: ed25519_dalek::SigningKey,
+}
+
+/// A peer's published keys, as the app fetched and trusts them.
+#[derive(Clone)]
+pub struct PeerKeys {
+    pub bundle: PreKeyBundle,
+    /// Verifies the bundle's pre-key signature and the peer's reset
+    /// requests.
+    pub verify_key: ed25519_dalek::VerifyingKey,
+}
+
+/// The signed body of a `+freeq.at/e2ee-reset` TAGMSG.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ResetRequest {
+    /// DID of the side starting over.
+    pub from: String,
+    /// DID it is starting over with.
+    pub to: String,
+    pub reason: String,
+    /// Unix milliseconds when it was made.
+    pub ts: u64,
+    /// 16 random bytes, base64url; the `confirm` plaintext.
+    pub nonce: String,
+    /// X3DH initial message for the recipient's signed pre-key.
+    pub initial: InitialMessage,
+    /// `ENC3:` ciphertext of `nonce`, the first message of the new session.
+    pub confirm: String,
+    /// Ed25519 signature over every other field, base64url.
+    pub signature: String,
+}
+
+impl ResetRequest {
+    /// What the signature covers.
+    fn signed_bytes(&self) -> Vec<u8> {
+        let i = &self.initial;
+        format!(
+            "freeq-e2ee-reset-v1\0{}\0{}\0{}\0{}\0{}\0{}\0{}\0{}\0{}\0{}",
+            self.from,
+            self.to,
+            self.reason,
+            self.ts,
+            self.nonce,
+            i.did,
+            i.identity_key,
+            i.ephemeral_key,
+            i.spk_id,
+            self.confirm
+        )
+        .into_bytes()
+    }
+
+    fn sign(&mut self, key: &ed25519_dalek::SigningKey) {
+        self.signature = B64.encode(key.sign(&self.signed_bytes()).to_bytes());
+    }
+
+    fn verify(&self, key: &ed25519_dalek::VerifyingKey) -> Result<(), ResetError> {
+        let bytes = B64
+            .decode(&self.signature)
+            .map_err(|_| ResetError::BadSignature)?;
+        let signature =
+            ed25519_dalek::Signature::from_slice(&bytes).map_err(|_| ResetError::BadSignature)?;
+        key.verify(&self.signed_bytes(), &signature)
+            .map_err(|_| ResetError::BadSignature)
+    }
+
+    /// Tags for the TAGMSG that carries this request.
+    pub fn to_tags(&self) -> HashMap<String, String> {
+        let json = serde_json::to_vec(self).expect("ResetRequest is serializable");
+        let mut t = HashMap::new();
+        t.insert(tags::E2EE_RESET.into(), B64.encode(json));
+        t
+    }
+
+    /// Parse a request from an incoming
+    /// [`Event::TagMsg`](crate::event::Event::TagMsg)'s tags.
+    pub fn from_tags(tags: &HashMap<String, String>) -> Option<Self> {
+        let json = B64.decode(tags.get(tags::E2EE_RESET)?).ok()?;
+        serde_json::from_slice(&json).ok()
+    }
+}
+
+/// A session that was replaced with a fresh one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionReset {
+    /// The peer's DID.
+    pub did: String,
+    pub reason: String,
+    /// We started it, rather than accepting the peer's request.
+    pub initiated: bool,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ResetError {
+    #[error("no trusted keys for {0}")]
+    UnknownPeer(String),
+    #[error("reset request is addressed to someone else")]
+    NotForUs,
+    #[error("reset request is too old or from the future")]
+    Stale,
+    #[error("reset request was already seen")]
+    Replayed,
+    #[error("bad reset request signature")]
+    BadSignature,
+    #[error("reset request uses an identity key the peer didn't publish")]
+    IdentityMismatch,
+    #[error("our own concurrent reset takes precedence")]
+    Superseded,
+    #[error("reset session did not confirm")]
+    BadConfirmation,
+    #[error("X3DH: {0}")]
+    X3dh(#[from] X3dhError),
+    #[error("ratchet: {0}")]
+    Ratchet(#[from] RatchetError),
+}
+
+#[derive(Default)]
+struct Peer {
+    keys: Option<PeerKeys>,
+    /// Consecutive messages that didn't decrypt.
+    failures: u32,
+    /// When we last sent a reset request.
+    sent: Option<u64>,
+    /// When the session was last reset, either way.
+    reset: Option<u64>,
+    /// `ts` of the newest request we accepted.
+    accepted_ts: u64,
+}
+
+/// Per-peer reset state over a shared [`SessionStore`].
+///
+/// The bookkeeping methods take the current time and do no I/O;
+/// [`SessionResets::handle_event`] drives them against a live client.
+pub struct SessionResets {
+    local: LocalKeys,
+    store: SessionStore,
+    peers: HashMap<String, Peer>,
+}
+
+impl SessionResets {
+    pub fn new(local: LocalKeys, store: SessionStore) -> Self {
+        Self {
+            local,
+            store,
+            peers: HashMap::new(),
+        }
+    }
+
+    /// Our DID.
+    pub fn did(&self) -> &str {
+        &self.local.did
+    }
+
+    /// Set the keys we start sessions with and check requests from `did`
+    /// against. Call it whenever the app fetches a fresh bundle.
+    pub fn trust(&mut self, did: &str, keys: PeerKeys) {
+        self.peers.entry(did.to_string()).or_default().keys = Some(keys);
+    }
+
+    /// A message from `did` decrypted; the session is healthy.
+    pub fn on_decrypt_success(&mut self, did: &str) {
+        if let Some(peer) = self.peers.get_mut(did) {
+            peer.failures = 0;
+        }
+    }
+
+    /// A message from `did` didn't decrypt. Returns whether it's time to
+    /// reset: enough failures in a row, we hold the peer's keys, and we
+    /// haven't reset with them recently.
+    pub fn on_decrypt_failure(&mut self, did: &str, now_ms: u64) -> bool {
+        let peer = self.peers.entry(did.to_string()).or_default();
+        peer.failures += 1;
+        peer.failures >= FAILURES_BEFORE_RESET
+            && peer.keys.is_some()
+            && peer
+                .reset
+                .is_none_or(|at| now_ms.saturating_sub(at) >= RESET_COOLDOWN_MS)
+    }
+
+    /// Replace the session with `did` by a fresh one from their bundle,
+    /// and return the request to send them.
+    pub fn initiate(
+        &mut self,
+        did: &str,
+        reason: &str,
+        now_ms: u64,
+    ) -> Result<ResetRequest, ResetError> {
+        let peer = self.peers.entry(did.to_string()).or_default();
+        let keys = peer
+            .keys
+            .as_ref()
+            .ok_or_else(|| ResetError::UnknownPeer(did.to_string()))?;
+        let x3dh = x3dh::initiate(
+            &self.local.identity,
+            &self.local.did,
+            &keys.bundle,
+            &keys.verify_key,
+        )?;
+        let mut session = Session::init_alice(x3dh.shared_secret, x3dh.their_ratchet_key);
+        let mut nonce = [0u8; 16];
+        OsRng.fill_bytes(&mut nonce);
+        let nonce = B64.encode(nonce);
+        let confirm = session.encrypt(&nonce)?;
+        let mut request = ResetRequest {
+            from: self.local.did.clone(),
+            to: did.to_string(),
+            reason: clean_reason(reason),
+            ts: now_ms,
+            nonce,
+            initial: x3dh.initial_message,
+            confirm,
+            signature: String::new(),
+        };
+        request.sign(&self.local.signing_key);
+
+        self.store.insert_session(did, session);
+        peer.failures = 0;
+        peer.sent = Some(now_ms);
+        peer.reset = Some(now_ms);
+        tracing::info!(peer = %did, reason = %request.reason, "Reset E2EE session");
+        Ok(request)
+    }
+
+    /// Check a peer's request and, if it holds up, replace our session
+    /// with the one it starts.
+    pub fn accept(
+        &mut self,
+        request: &ResetRequest,
+        now_ms: u64,
+    ) -> Result<SessionReset, ResetError> {
+        if request.to != self.local.did {
+            return Err(ResetError::NotForUs);
+        }
+        if request.ts.abs_diff(now_ms) > MAX_CLOCK_SKEW_MS {
+            return Err(ResetError::Stale);
+        }
+        let peer = self.peers.entry(request.from.clone()).or_default();
+        let keys = peer
+            .keys
+            .as_ref()
+            .ok_or_else(|| ResetError::UnknownPeer(request.from.clone()))?;
+        request.verify(&keys.verify_key)?;
+        if request.ts <= peer.accepted_ts {
+            return Err(ResetError::Replayed);
+        }
+        if request.initial.identity_key != keys.bundle.identity_key
+            || request.initial.did != request.from
+        {
+            return Err(ResetError::IdentityMismatch);
+        }
+        if peer
+            .sent
+            .is_some_and(|at| request.ts.abs_diff(at) < RESET_COOLDOWN_MS)
+            && self.local.did < request.from
+        {
+            return Err(ResetError::Superseded);
+        }
+
+        let (shared_secret, ratchet_secret) =
+            x3dh::respond(&self.local.identity, &self.local.spk, &request.initial)?;
+        let mut session = Session::init_bob(shared_secret, ratchet_secret);
+        if session.decrypt(&request.confirm).ok().as_ref() != Some(&request.nonce) {
+            return Err(ResetError::BadConfirmation);
+        }
+
+        self.store.insert_session(&request.from, session);
+        peer.accepted_ts = request.ts;
+        peer.failures = 0;
+        peer.sent = None;
+        peer.reset = Some(now_ms);
+        let reason = clean_reason(&request.reason);
+        tracing::info!(peer = %request.from, %reason, "E2EE session reset by peer");
+        Ok(SessionReset {
+            did: request.from.clone(),
+            reason,
+            initiated: false,
+        })
+    }
+
+    /// Drive resets from a client event (after the
+    /// [`Decrypt`](crate::decrypt::Decrypt) interceptor has run): count
+    /// ENC3 messages that did and didn't decrypt, reset a session that
+    /// keeps failing, and accept reset requests addressed to us.
+    pub async fn handle_event(
+        &mut self,
+        event: &Event,
+        client: &crate::client::ClientHandle,
+    ) -> anyhow::Result<Option<SessionReset>> {
+        let now_ms = chrono::Utc::now().timestamp_millis().max(0) as u64;
+        match event {
+            Event::Message {
+                from,
+                target,
+                text,
+                tags,
+                encrypted,
+                ..
+            } if !target.starts_with(['#', '&']) && (ratchet::is_encrypted(text) || *encrypted) => {
+                // Our own echoes never decrypt; they don't count.
+                let Some(did) = tags.get(tags::ACCOUNT).filter(|d| **d != self.local.did) else {
+                    return Ok(None);
+                };
+                if *encrypted {
+                    self.on_decrypt_success(did);
+                    return Ok(None);
+                }
+                if !self.on_decrypt_failure(did, now_ms) {
+                    return Ok(None);
+                }
+                let request = self.initiate(did, REASON_DECRYPT_FAILED, now_ms)?;
+                client.send_tagmsg(from, request.to_tags()).await?;
+                Ok(Some(SessionReset {
+                    did: did.clone(),
+                    reason: request.reason,
+                    initiated: true,
+                }))
+            }
+            Event::TagMsg { tags, .. } => {
+                let Some(request) = ResetRequest::from_tags(tags) else {
+                    return Ok(None);
+                };
+                match self.accept(&request, now_ms) {
+                    Ok(reset) => Ok(Some(reset)),
+                    Err(e) => {
+                        tracing::debug!(peer = %request.from, error = %e, "Ignored E2EE reset");
+                        Ok(None)
+                    }
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// A reason safe to show: printable, no longer than [`MAX_REASON_LEN`].
+fn clean_reason(reason: &str) -> String {
+    reason
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_REASON_LEN)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const T0: u64 = 1_760_000_000_000;
+
+    struct Side {
+        resets: SessionResets,
+        store: SessionStore,
+        keys: PeerKeys,
+    }
+
+    fn side(did: &str) -> Side {
+        let signing_key = ed25519_dalek::SigningKey::generate(&mut OsRng);
+        let identity = IdentityKeyPair::generate();
+        let spk = SignedPreKey::generate(1, &signing_key);
+        let keys = PeerKeys {
+            bundle: PreKeyBundle::new(did, &identity, &spk),
+            verify_key: signing_key.verifying_key(),
+        };
+        let store = SessionStore::new();
+        let local = LocalKeys {
+            did: did.to_string(),
+            identity,
+            spk,
+            signing_key,
+        };
+        Side {
+            resets: SessionResets::new(local, store.clone()),
+            store,
+            keys,
+        }
+    }
+
+    /// Alice and Bob, each trusting the other's published keys.
+    fn pair() -> (Side, Side) {
+        let mut alice = side("did:plc:alice");
+        let mut bob = side("did:plc:bob");
+        alice.resets.trust("did:plc:bob", bob.keys.clone());
+        bob.resets.trust("did:plc:alice", alice.keys.clone());
+        (alice, bob)
+    }
+
+    #[test]
+    fn reset_starts_a_session_both_sides_can_use() {
+        let (mut alice, mut bob) = pair();
+        let request = alice.resets.initiate("did:plc:bob", "manual", T0).unwrap();
+        let request = ResetRequest::from_tags(&request.to_tags()).unwrap();
+        assert_eq!(
+            bob.resets.accept(&request, T0 + 1_000).unwrap(),
+            SessionReset {
+                did: "did:plc:alice".into(),
+                reason: "manual".into(),
+                initiated: false,
+            }
+        );
+
+        // Bob can answer at once; the confirm message gave him a send chain.
+        let wire = bob
+            .store
+            .with_session("did:plc:alice", |s| s.encrypt("back again"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            alice
+                .store
+                .decrypt("did:plc:bob", "alice", &wire)
+                .as_deref(),
+            Some("back again")
+        );
+        let wire = alice
+            .store
+            .with_session("did:plc:bob", |s| s.encrypt("hello"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            bob.store.decrypt("did:plc:alice", "bob", &wire).as_deref(),
+            Some("hello")
+        );
+    }
+
+    #[test]
+    fn replays_and_forgeries_are_refused() {
+        let (mut alice, mut bob) = pair();
+        let request = alice.resets.initiate("did:plc:bob", "x", T0).unwrap();
+        bob.resets.accept(&request, T0).unwrap();
+        assert!(matches!(
+            bob.resets.accept(&request, T0 + 1),
+            Err(ResetError::Replayed)
+        ));
+
+        let later = alice
+            .resets
+            .initiate("did:plc:bob", "x", T0 + RESET_COOLDOWN_MS)
+            .unwrap();
+        assert!(matches!(
+            bob.resets
+                .accept(&later, T0 + RESET_COOLDOWN_MS + MAX_CLOCK_SKEW_MS + 1),
+            Err(ResetError::Stale)
+        ));
+
+        let mut tampered = later.clone();
+        tampered.reason = "something else".into();
+        assert!(matches!(
+            bob.resets.accept(&tampered, T0 + RESET_COOLDOWN_MS),
+            Err(ResetError::BadSignature)
+        ));
+
+        // Mallory claims to be Alice but can only sign as herself.
+        let mut mallory = side("did:plc:mallory");
+        mallory.resets.trust("did:plc:bob", bob.keys.clone());
+        let mut forged = mallory.resets.initiate("did:plc:bob", "x", T0).unwrap();
+        assert!(matches!(
+            bob.resets.accept(&forged, T0),
+            Err(ResetError::UnknownPeer(_))
+        ));
+        forged.from = "did:plc:alice".into();
+        forged.sign(&mallory.resets.local.signing_key);
+        assert!(matches!(
+            bob.resets.accept(&forged, T0 + RESET_COOLDOWN_MS),
+            Err(ResetError::BadSignature)
+        ));
+
+        let mut carol = side("did:plc:carol");
+        carol.resets.trust("did:plc:alice", alice.keys.clone());
+        assert!(matches!(
+            carol.resets.accept(&later, T0 + RESET_COOLDOWN_MS),
+            Err(ResetError::NotForUs)
+        ));
+
+        // The fresh request still works after all that.
+        bob.resets.accept(&later, T0 + RESET_COOLDOWN_MS).unwrap();
+    }
+
+    #[test]
+    fn concurrent_resets_resolve_to_the_lower_did() {
+        let (mut alice, mut bob) = pair();
+        let from_alice = alice.resets.initiate("did:plc:bob", "x", T0).unwrap();
+        let from_bob = bob.resets.initiate("did:plc:alice", "x", T0 + 10).unwrap();
+
+        // Alice's DID sorts first: she keeps hers, Bob takes it.
+        assert!(matches!(
+            alice.resets.accept(&from_bob, T0 + 20),
+            Err(ResetError::Superseded)
+        ));
+        bob.resets.accept(&from_alice, T0 + 20).unwrap();
+
+        let wire = alice
+            .store
+            .with_session("did:plc:bob", |s| s.encrypt("settled"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            bob.store.decrypt("did:plc:alice", "bob", &wire).as_deref(),
+            Some("settled")
+        );
+    }
+
+    #[test]
+    fn persistent_failures_trigger_one_reset_per_cooldown() {
+        let (mut alice, _bob) = pair();
+        let bob = "did:plc:bob";
+        assert!(!alice.resets.on_decrypt_failure(bob, T0));
+        alice.resets.on_decrypt_success(bob);
+        assert!(!alice.resets.on_decrypt_failure(bob, T0));
+        assert!(!alice.resets.on_decrypt_failure(bob, T0));
+        assert!(alice.resets.on_decrypt_failure(bob, T0));
+
+        alice
+            .resets
+            .initiate(bob, REASON_DECRYPT_FAILED, T0)
+            .unwrap();
+        for _ in 0..FAILURES_BEFORE_RESET {
+            assert!(!alice.resets.on_decrypt_failure(bob, T0 + 1_000));
+        }
+        assert!(alice.resets.on_decrypt_failure(bob, T0 + RESET_COOLDOWN_MS));
+
+        // Nothing to reset from without the peer's keys.
+        for _ in 0..FAILURES_BEFORE_RESET {
+            assert!(!alice.resets.on_decrypt_failure("did:plc:nobody", T0));
+        }
+    }
+}
@@ -0,0 +1,692 @@
+//! In-process mock IRC server for testing apps built on the SDK.
+//!
+//! [`MockServer`] speaks enough IRC for a [`ClientHandle`] to register,
+//! join channels and get its messages echoed back, all over an in-memory
+//! duplex stream — no sockets, no live server. Tests can script replies
+//! per command and inject faults (disconnects, slow replies, malformed
+//! lines) through the returned [`MockHandle`].
+//!
+//! ```no_run
+//! # async fn demo() {
+//! use freeq_sdk::event::Event;
+//! use freeq_sdk::testing::{Fault, MockServer};
+//!
+//! let (client, mut events, mut server) = MockServer::new()
+//!     .on("TOPIC", |msg, nick| {
+//!         vec![format!(":mock.freeq 332 {nick} {} :scripted topic", msg.params[0])]
+//!     })
+//!     .connect("alice");
+//!
+//! client.join("#test").await.unwrap();
+//! server.expect("JOIN").await;
+//! server.fault(Fault::Disconnect);
+//! while let Some(event) = events.recv().await {
+//!     if let Event::Disconnected { reason } = event {
+//!         println!("dropped: {reason}");
+//!         break;
+//!     }
+//! }
+//! # }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader, DuplexStream};
+use tokio::sync::mpsc;
+
+use crate::auth::ChallengeSigner;
+use crate::client::{self, ClientHandle, ConnectConfig, EstablishedConnection};
+use crate::event::Event;
+use crate::irc::Message;
+use crate::proto::{caps, numeric};
+
+/// Server name used as the prefix of every reply.
+pub const SERVER_NAME: &str = "mock.freeq";
+
+/// How long [`MockHandle::expect`] waits before panicking.
+const EXPECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Scripted reply: given the client's line and its current nick, return
+/// raw lines (without CRLF) to send back.
+pub type Handler = Arc<dyn Fn(&Message, &str) -> Vec<String> + Send + Sync>;
+
+/// A fault to inject into a running mock connection.
+#[derive(Debug, Clone)]
+pub enum Fault {
+    /// Close the connection; the client sees EOF.
+    Disconnect,
+    /// Hold every subsequent reply this long (`Duration::ZERO` clears it).
+    Delay(Duration),
+    /// Write these bytes verbatim — no CRLF is added and they need not be
+    /// valid UTF-8.
+    Malformed(Vec<u8>),
+    /// Stop answering anything, PING included, while keeping the
+    /// connection open.
+    Stall,
+}
+
+/// A scriptable in-process IRC server. Configure it, then call
+/// [`connect`](Self::connect) to get a client wired to it.
+pub struct MockServer {
+    caps: Vec<String>,
+    handlers: HashMap<String, Handler>,
+}
+
+impl Default for MockServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockServer {
+    /// A server advertising message-tags, server-time, batch, echo-message,
+    /// draft/multiline and draft/chathistory, with no scripted replies.
+    pub fn new() -> Self {
+        Self {
+            caps: [
+                caps::MESSAGE_TAGS,
+                caps::SERVER_TIME,
+                caps::BATCH,
+                caps::ECHO_MESSAGE,
+                caps::MULTILINE,
+                caps::CHATHISTORY,
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+            handlers: HashMap::new(),
+        }
+    }
+
+    /// Replace the advertised capabilities.
+    pub fn caps(mut self, caps: &[&str]) -> Self {
+        self.caps = caps.iter().map(|c| c.to_string()).collect();
+        self
+    }
+
+    /// Script the reply to `command` (case-insensitive). The handler
+    /// replaces the built-in behaviour for that command, so scripting
+    /// `CAP`, `NICK` or `USER` takes over registration too.
+    pub fn on(
+        mut self,
+        command: &str,
+        handler: impl Fn(&Message, &str) -> Vec<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.handlers
+            .insert(command.to_ascii_uppercase(), Arc::new(handler));
+        self
+    }
+
+    /// Connect a guest client with `nick`. Must be called inside a Tokio
+    /// runtime.
+    pub fn connect(self, nick: &str) -> (ClientHandle, mpsc::Receiver<Event>, MockHandle) {
+        let config = ConnectConfig {
+            server_addr: format!("{SERVER_NAME}:6667"),
+            nick: nick.to_string(),
+            user: nick.to_string(),
+            realname: "freeq test client".to_string(),
+            ..Default::default()
+        };
+        self.connect_with(config, None)
+    }
+
+    /// Connect a client with an explicit config and optional signer.
+    /// `server_addr` and the TLS settings are ignored.
+    pub fn connect_with(
+        self,
+        config: ConnectConfig,
+        signer: Option<Arc<dyn ChallengeSigner>>,
+    ) -> (ClientHandle, mpsc::Receiver<Event>, MockHandle) {
+        let (client_side, server_side) = tokio::io::duplex(64 * 1024);
+        let (ctl_tx, ctl_rx) = mpsc::unbounded_channel();
+        let (seen_tx, seen_rx) = mpsc::unbounded_channel();
+        tokio::spawn(self.serve(server_side, ctl_rx, seen_tx));
+        let (handle, events) =
+            client::connect_with_stream(EstablishedConnection::Memory(client_side), config, signer);
+        let mock = MockHandle {
+            ctl: ctl_tx,
+            seen: seen_rx,
+        };
+        (handle, events, mock)
+    }
+
+    async fn serve(
+        self,
+        stream: DuplexStream,
+        mut ctl: mpsc::UnboundedReceiver<Control>,
+        seen: mpsc::UnboundedSender<Message>,
+    ) {
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut lines = BufReader::new(reader).lines();
+        let mut session = Session::default();
+        let mut delay = Duration::ZERO;
+        let mut stalled = false;
+
+        loop {
+            tokio::select! {
+                line = lines.next_line() => {
+                    let Ok(Some(line)) = line else { break };
+                    let Some(msg) = Message::parse(&line) else { continue };
+                    let _ = seen.send(msg.clone());
+                    if stalled {
+                        continue;
+                    }
+                    let quit = msg.command.eq_ignore_ascii_case("QUIT");
+                    let replies = self.respond(&mut session, &msg);
+                    if !delay.is_zero() && !replies.is_empty() {
+                        tokio::time::sleep(delay).await;
+                    }
+                    if write_lines(&mut writer, &replies).await.is_err() || quit {
+                        break;
+                    }
+                }
+                Some(control) = ctl.recv() => match control {
+                    Control::Line(line) => {
+                        if write_lines(&mut writer, &[line]).await.is_err() {
+                            break;
+                        }
+                    }
+                    Control::Fault(Fault::Disconnect) => break,
+                    Control::Fault(Fault::Delay(d)) => delay = d,
+                    Control::Fault(Fault::Malformed(bytes)) => {
+                        if writer.write_all(&bytes).await.is_err() {
+                            break;
+                        }
+                    }
+                    Control::Fault(Fault::Stall) => stalled = true,
+                },
+            }
+        }
+    }
+
+    /// Replies to one client line: the scripted handler if there is one,
+    /// the built-in behaviour otherwise.
+    fn respond(&self, session: &mut Session, msg: &Message) -> Vec<String> {
+        let command = msg.command.to_ascii_uppercase();
+        if let Some(handler) = self.handlers.get(&command) {
+            if command == "NICK"
+                && !session.registered
+                && let Some(nick) = msg.params.first()
+            {
+                session.nick = nick.clone();
+            }
+            return handler(msg, &session.nick);
+        }
+
+        let param = |i: usize| msg.params.get(i).map(String::as_str).unwrap_or("");
+        match command.as_str() {
+            "CAP" => match param(0).to_ascii_uppercase().as_str() {
+                "LS" => {
+                    session.negotiating = true;
+                    vec![format!(":{SERVER_NAME} CAP * LS :{}", self.caps.join(" "))]
+                }
+                "REQ" => {
+                    let wanted: Vec<&str> = param(1).split_whitespace().collect();
+                    if wanted.iter().all(|c| self.caps.iter().any(|a| a == c)) {
+                        session.acked.extend(wanted.iter().map(|c| c.to_string()));
+                        vec![format!(":{SERVER_NAME} CAP * ACK :{}", param(1))]
+                    } else {
+                        vec![format!(":{SERVER_NAME} CAP * NAK :{}", param(1))]
+                    }
+                }
+                "END" => {
+                    session.negotiating = false;
+                    session.try_register()
+                }
+                _ => Vec::new(),
+            },
+            "NICK" if session.registered => {
+                let line = format!(":{} NICK {}", session.prefix(), param(0));
+                session.nick = param(0).to_string();
+                vec![line]
+            }
+            "NICK" => {
+                session.nick = param(0).to_string();
+                session.try_register()
+            }
+            "USER" => {
+                session.user = param(0).to_string();
+                session.try_register()
+            }
+            "PING" => vec![format!(":{SERVER_NAME} PONG {SERVER_NAME} :{}", param(0))],
+            "JOIN" => param(0)
+                .split(',')
+                .filter(|c| !c.is_empty())
+                .flat_map(|channel| {
+                    let nick = &session.nick;
+                    [
+                        format!(":{} JOIN {channel}", session.prefix()),
+                        format!(
+                            ":{SERVER_NAME} {} {nick} = {channel} :{nick}",
+                            numeric::RPL_NAMREPLY
+                        ),
+                        format!(
+                            ":{SERVER_NAME} {} {nick} {channel} :End of /NAMES list",
+                            numeric::RPL_ENDOFNAMES
+                        ),
+                    ]
+                })
+                .collect(),
+            "PART" => param(0)
+                .split(',')
+                .filter(|c| !c.is_empty())
+                .map(|channel| format!(":{} PART {channel}", session.prefix()))
+                .collect(),
+            "PRIVMSG" | "NOTICE" | "TAGMSG" | "BATCH"
+                if session.acked.contains(caps::ECHO_MESSAGE) =>
+            {
+                vec![session.echo(msg)]
+            }
+            "QUIT" => vec!["ERROR :Closing link (Quit)".to_string()],
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Per-connection registration state of the mock.
+#[derive(Default)]
+struct Session {
+    nick: String,
+    user: String,
+    negotiating: bool,
+    registered: bool,
+    acked: HashSet<String>,
+    next_msgid: u64,
+}
+
+impl Session {
+    fn prefix(&self) -> String {
+        format!("{}!{}@{SERVER_NAME}", self.nick, self.user)
+    }
+
+    /// Welcome the client once NICK, USER and CAP END are all in.
+    fn try_register(&mut self) -> Vec<String> {
+        if self.registered || self.negotiating || self.nick.is_empty() || self.user.is_empty() {
+            return Vec::new();
+        }
+        self.registered = true;
+        let nick = &self.nick;
+        vec![
+            format!(
+                ":{SERVER_NAME} {} {nick} :Welcome to the mock freeq network",
+                numeric::RPL_WELCOME
+            ),
+            format!(":{SERVER_NAME} 422 {nick} :MOTD File is missing"),
+        ]
+    }
+
+    /// The client's own message, as echo-message sends it back: same
+    /// tags plus a msgid (and time, with server-time).
+    fn echo(&mut self, msg: &Message) -> String {
+        let mut echo = msg.clone();
+        echo.prefix = Some(self.prefix());
+        if msg.command != "BATCH" {
+            self.next_msgid += 1;
+            echo.tags
+                .insert("msgid".to_string(), format!("mock-{}", self.next_msgid));
+        }
+        if self.acked.contains(caps::SERVER_TIME) {
+            let now = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ");
+            echo.tags.insert("time".to_string(), now.to_string());
+        }
+        echo.to_string()
+    }
+}
+
+enum Control {
+    Line(String),
+    Fault(Fault),
+}
+
+async fn write_lines<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    lines: &[String],
+) -> std::io::Result<()> {
+    for line in lines {
+        writer.write_all(format!("{line}\r\n").as_bytes()).await?;
+    }
+    writer.flush().await
+}
+
+/// Test-side handle to a running [`MockServer`] connection.
+pub struct MockHandle {
+    ctl: mpsc::UnboundedSender<Control>,
+    seen: mpsc::UnboundedReceiver<Message>,
+}
+
+impl MockHandle {
+    /// Send a raw line (without CRLF) to the client, e.g. a PRIVMSG from
+    /// another user or a numeric the test needs.
+    pub fn send(&self, line: impl Into<String>) {
+        let _ = self.ctl.send(Control::Line(line.into()));
+    }
+
+    /// Inject a fault into the connection.
+    pub fn fault(&self, fault: Fault) {
+        let _ = self.ctl.send(Control::Fault(fault));
+    }
+
+    /// The next line the client sent, or `None` once the connection is
+    /// closed and every line has been read.
+    pub async fn recv(&mut self) -> Option<Message> {
+        self.seen.recv().await
+    }
+
+    /// Skip client lines until one with `command`, and return it.
+    ///
+    /// # Panics
+    ///
+    /// If none arrives within five seconds or the connection closes first.
+    pub async fn expect(&mut self, command: &str) -> Message {
+        let wait = async {
+            while let Some(msg) = self.seen.recv().await {
+                if msg.command.eq_ignore_ascii_case(command) {
+                    return Some(msg);
+                }
+            }
+            None
+        };
+        match tokio::time::timeout(EXPECT_TIMEOUT, wait).await {
+            Ok(Some(msg)) => msg,
+            Ok(None) => panic!("mock connection closed before the client sent {command}"),
+            Err(_) => panic!("client did not send {command} within {EXPECT_TIMEOUT:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pending::CallError;
+    use crate::profile::ServerProfile;
+    use std::time::Instant;
+
+    async fn next_matching(
+        events: &mut mpsc::Receiver<Event>,
+        f: impl Fn(&Event) -> bool,
+    ) -> Event {
+        tokio::time::timeout(EXPECT_TIMEOUT, async {
+            loop {
+                let event = events.recv().await.expect("event stream ended");
+                if f(&event) {
+                    return event;
+                }
+            }
+        })
+        .await
+        .expect("timed out waiting for event")
+    }
+
+    async fn registered(events: &mut mpsc::Receiver<Event>) {
+        next_matching(events, |e| matches!(e, Event::Registered { .. })).await;
+    }
+
+    #[tokio::test]
+    async fn registers_joins_and_echoes() {
+        let (client, mut events, mut server) = MockServer::new().connect("alice");
+        registered(&mut events).await;
+
+        client.join("#test").await.unwrap();
+        let join = server.expect("JOIN").await;
+        assert_eq!(join.params[0], "#test");
+        let event = next_matching(&mut events, |e| matches!(e, Event::Joined { .. })).await;
+        let Event::Joined { channel, nick, .. } = event else {
+            unreachable!()
+        };
+        assert_eq!((channel.as_str(), nick.as_str()), ("#test", "alice"));
+
+        let msgid = client
+            .send_and_await_echo("#test", "hello", HashMap::new())
+            .await
+            .unwrap();
+        assert!(msgid.starts_with("mock-"), "{msgid}");
+    }
+
+    #[tokio::test]
+    async fn follows_caps_announced_after_registration() {
+        let (client, mut events, mut server) = MockServer::new().connect("dave");
+        registered(&mut events).await;
+        async fn settled(f: impl Fn() -> bool) {
+            tokio::time::timeout(EXPECT_TIMEOUT, async {
+                while !f() {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .expect("caps did not change");
+        }
+
+        // Only wanted caps are requested, without re-entering registration.
+        server.send(format!(":{SERVER_NAME} CAP dave NEW :freeq.at/priority x-unknown"));
+        while server.expect("CAP").await.params[0] != "END" {}
+        let req = server.expect("CAP").await;
+        assert_eq!(req.params, ["REQ", caps::PRIORITY]);
+        server.send(format!(":{SERVER_NAME} CAP dave ACK :{}", caps::PRIORITY));
+        settled(|| client.has_cap(caps::PRIORITY)).await;
+        assert!(client.caps().contains(&caps::BATCH.to_string()));
+
+        server.send(format!(":{SERVER_NAME} CAP dave DEL :{}", caps::BATCH));
+        settled(&|| !client.has_cap(caps::BATCH)).await;
+        assert!(client.has_cap(caps::PRIORITY));
+    }
+
+    #[tokio::test]
+    async fn requests_configured_caps_and_reports_the_outcome() {
+        use crate::capabilities::{Capabilities, Capability};
+
+        let config = ConnectConfig {
+            server_addr: format!("{SERVER_NAME}:6667"),
+            nick: "fay".to_string(),
+            user: "fay".to_string(),
+            capabilities: Capabilities::default()
+                .without(Capability::AwayNotify)
+                .with("draft/read-marker".parse().unwrap())
+                .with("draft/absent".parse().unwrap()),
+            ..Default::default()
+        };
+        let (client, mut events, mut server) = MockServer::new()
+            .caps(&[
+                caps::MESSAGE_TAGS,
+                caps::BATCH,
+                caps::AWAY_NOTIFY,
+                "draft/read-marker",
+            ])
+            .connect_with(config, None);
+
+        // The app's own capability goes in a request of its own.
+        assert_eq!(server.expect("CAP").await.params[0], "LS");
+        let sdk = server.expect("CAP").await;
+        assert_eq!(sdk.params, ["REQ", "message-tags batch"]);
+        let app = server.expect("CAP").await;
+        assert_eq!(app.params, ["REQ", "draft/read-marker"]);
+
+        let event =
+            next_matching(&mut events, |e| matches!(e, Event::CapabilitiesAcked { .. })).await;
+        let Event::CapabilitiesAcked {
+            acked,
+            rejected,
+            unavailable,
+        } = event
+        else {
+            unreachable!()
+        };
+        assert_eq!(acked, ["batch", "draft/read-marker", "message-tags"]);
+        assert!(rejected.is_empty(), "{rejected:?}");
+        assert!(unavailable.contains(&"draft/absent".to_string()));
+        assert!(unavailable.contains(&caps::SERVER_TIME.to_string()));
+        registered(&mut events).await;
+        assert!(!client.has_cap(caps::AWAY_NOTIFY));
+    }
+
+    #[tokio::test]
+    async fn degrades_on_a_legacy_server() {
+        let (client, mut events, server) = MockServer::new().caps(&[]).connect("erin");
+        registered(&mut events).await;
+        assert_eq!(client.server_profile(), Some(ServerProfile::Legacy));
+
+        server.send(":carol!c@host PRIVMSG erin :no tags here");
+        let event = next_matching(&mut events, |e| matches!(e, Event::Message { .. })).await;
+        let Event::Message { tags, .. } = event else {
+            unreachable!()
+        };
+        assert!(crate::profile::is_local_msgid(&tags["msgid"]), "{tags:?}");
+
+        let err = client
+            .history_latest_with("#test", 10, Default::default())
+            .await
+            .unwrap_err();
+        assert_eq!(err, CallError::Unsupported(caps::CHATHISTORY));
+    }
+
+    #[tokio::test]
+    async fn scripted_reply_and_injected_line() {
+        let (client, mut events, server) = MockServer::new()
+            .on("TOPIC", |msg, nick| {
+                vec![format!(
+                    ":{SERVER_NAME} 332 {nick} {} :scripted",
+                    msg.params[0]
+                )]
+            })
+            .connect("bob");
+        registered(&mut events).await;
+
+        client.raw("TOPIC #test").await.unwrap();
+        next_matching(
+            &mut events,
+            |e| matches!(e, Event::RawLine(l) if l.ends_with(" 332 bob #test :scripted")),
+        )
+        .await;
+
+        server.send(":carol!c@host PRIVMSG bob :hi bob");
+        let event = next_matching(&mut events, |e| matches!(e, Event::Message { .. })).await;
+        let Event::Message { from, text, .. } = event else {
+            unreachable!()
+        };
+        assert_eq!((from.as_str(), text.as_str()), ("carol", "hi bob"));
+    }
+
+    #[tokio::test]
+    async fn channel_state_and_fetched_history() {
+        let (client, mut events, server) = MockServer::new()
+            .on("CHATHISTORY", |msg, _| {
+                let target = &msg.params[1];
+                vec![
+                    format!(":{SERVER_NAME} BATCH +h1 chathistory {target}"),
+                    format!("@batch=h1;msgid=m1 :carol!c@host PRIVMSG {target} :one"),
+                    format!("@batch=h1;msgid=m2 :carol!c@host PRIVMSG {target} :two"),
+                    format!(":{SERVER_NAME} BATCH -h1"),
+                ]
+            })
+            .connect("gina");
+        registered(&mut events).await;
+
+        client.join("#test").await.unwrap();
+        next_matching(&mut events, |e| matches!(e, Event::NamesEnd { .. })).await;
+        server.send(":carol!c@host JOIN #test");
+        next_matching(
+            &mut events,
+            |e| matches!(e, Event::Joined { nick, .. } if nick == "carol"),
+        )
+        .await;
+        let state = client.channel("#TEST").unwrap();
+        let nicks: Vec<_> = state.members.iter().map(|m| m.nick.as_str()).collect();
+        assert_eq!(nicks, ["carol", "gina"]);
+        assert_eq!(client.channels(), ["#test"]);
+
+        let history = client
+            .fetch_history("#test", Some("m3"), 50, Default::default())
+            .await
+            .unwrap();
+        let texts: Vec<_> = history
+            .iter()
+            .map(|e| match e {
+                Event::Message { text, .. } => text.as_str(),
+                other => panic!("unexpected {other:?}"),
+            })
+            .collect();
+        assert_eq!(texts, ["one", "two"]);
+    }
+
+    #[tokio::test]
+    async fn channel_directory_is_listed_and_cached() {
+        use crate::directory::ListFilter;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let lists = Arc::new(AtomicUsize::new(0));
+        let served = lists.clone();
+        let (client, mut events, _server) = MockServer::new()
+            .on("LIST", move |_, nick| {
+                served.fetch_add(1, Ordering::SeqCst);
+                vec![
+                    format!(":{SERVER_NAME} 322 {nick} #quiet 1 :"),
+                    format!(":{SERVER_NAME} 322 {nick} #rust 42 :Rust chat"),
+                    format!(":{SERVER_NAME} 322 {nick} #rustdoc 7 :"),
+                    format!(":{SERVER_NAME} 323 {nick} :End of /LIST"),
+                ]
+            })
+            .connect("hana");
+        registered(&mut events).await;
+
+        let all = client
+            .list_channels(&ListFilter::default(), Default::default())
+            .await
+            .unwrap();
+        let names: Vec<_> = all.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["#rust", "#rustdoc", "#quiet"]);
+        assert_eq!((all[0].users, all[0].topic.as_str()), (42, "Rust chat"));
+
+        // No ELIST advertised: the filter is applied to a plain LIST,
+        // which the cache already holds.
+        let filter = ListFilter {
+            min_users: Some(5),
+            mask: Some("#RUST*".into()),
+            ..Default::default()
+        };
+        let busy = client
+            .list_channels(&filter, Default::default())
+            .await
+            .unwrap();
+        assert_eq!(busy.len(), 2);
+        assert_eq!(lists.load(Ordering::SeqCst), 1);
+
+        client
+            .refresh_channel_list(&filter, Default::default())
+            .await
+            .unwrap();
+        assert_eq!(lists.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn disconnect_fault_ends_session() {
+        let (_client, mut events, server) = MockServer::new().connect("dave");
+        registered(&mut events).await;
+        server.fault(Fault::Disconnect);
+        next_matching(&mut events, |e| matches!(e, Event::Disconnected { .. })).await;
+    }
+
+    #[tokio::test]
+    async fn malformed_line_does_not_kill_session() {
+        let (client, mut events, mut server) = MockServer::new().connect("erin");
+        registered(&mut events).await;
+        server.fault(Fault::Malformed(b"@;;= :\r\n".to_vec()));
+        client.privmsg("#test", "still here").await.unwrap();
+        assert_eq!(server.expect("PRIVMSG").await.params[1], "still here");
+    }
+
+    #[tokio::test]
+    async fn delay_holds_replies() {
+        let (client, mut events, server) = MockServer::new().connect("frank");
+        registered(&mut events).await;
+        let delay = Duration::from_millis(200);
+        server.fault(Fault::Delay(delay));
+        // Give the control message a head start over the JOIN.
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let start = Instant::now();
+        client.join("#slow").await.unwrap();
+        next_matching(&mut events, |e| matches!(e, Event::Joined { .. })).await;
+        assert!(start.elapsed() >= delay);
+    }
+}
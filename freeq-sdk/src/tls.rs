@@ -0,0 +1,466 @@
+//! TLS client configuration: trust roots, certificate pinning, and ALPN.
+//!
+//! By default the SDK trusts Mozilla's root bundle plus the platform's
+//! native store. [`TlsOptions`] layers three things on top:
+//!
+//! - **Custom roots** — PEM certificates trusted in addition to the
+//!   defaults, for self-hosted servers behind a private CA.
+//! - **SPKI pins** — SHA-256 hashes of acceptable public keys, written
+//!   `sha256/<base64>` (the format `openssl x509 -pubkey | openssl pkey
+//!   -pubin -outform der | openssl dgst -sha256 -binary | base64` prints,
+//!   with the prefix). The chain must still validate; in addition, the
+//!   leaf or one of the intermediates the server sends must match a pin.
+//!   Pin the root only if the server sends it.
+//! - **ALPN** — protocols offered in the handshake, e.g. `irc`.
+//!
+//! Handshake failures are reported as [`TlsError`], so callers can tell
+//! a pin mismatch from an expired certificate without parsing strings.
+
+use std::sync::Arc;
+
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use tokio_rustls::rustls;
+use tokio_rustls::rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use tokio_rustls::rustls::{CertificateError, DigitallySignedStruct, SignatureScheme};
+
+/// Extra TLS settings for [`ConnectConfig`](crate::client::ConnectConfig).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsOptions {
+    /// Acceptable server keys as `sha256/<base64>` SPKI hashes. Empty
+    /// means no pinning.
+    pub spki_pins: Vec<String>,
+    /// PEM-encoded CA certificates to trust in addition to the defaults.
+    /// Each entry may hold several certificates.
+    pub root_certs_pem: Vec<String>,
+    /// ALPN protocol names to offer, most preferred first.
+    pub alpn: Vec<String>,
+}
+
+/// Why a TLS connection could not be set up.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TlsError {
+    /// The chain validated, but no certificate in it matches a pin.
+    #[error("TLS: server certificate does not match any pinned key")]
+    PinMismatch,
+    /// A certificate in the chain has expired.
+    #[error("TLS: server certificate has expired")]
+    Expired,
+    /// A certificate in the chain is not valid yet (check the clock).
+    #[error("TLS: server certificate is not valid yet")]
+    NotYetValid,
+    /// The chain doesn't lead to a trusted root. For a private CA, add
+    /// it to [`TlsOptions::root_certs_pem`].
+    #[error("TLS: server certificate is not trusted (unknown issuer)")]
+    UnknownIssuer,
+    /// The certificate is valid, but not for the host connected to.
+    #[error("TLS: server certificate is not valid for this host")]
+    NameMismatch,
+    /// A configured pin isn't `sha256/` followed by 32 base64 bytes.
+    #[error("invalid SPKI pin {0:?}: expected sha256/<base64 of 32 bytes>")]
+    InvalidPin(String),
+    /// A configured root certificate couldn't be parsed or used.
+    #[error("invalid root certificate: {0}")]
+    InvalidRootCert(String),
+    /// Any other handshake failure.
+    #[error("TLS handshake failed: {0}")]
+    Handshake(String),
+}
+
+impl TlsOptions {
+    /// Check that every pin and root certificate parses.
+    pub fn validate(&self) -> Result<(), TlsError> {
+        self.parsed_pins()?;
+        self.parsed_roots()?;
+        Ok(())
+    }
+
+    fn parsed_pins(&self) -> Result<Vec<[u8; 32]>, TlsError> {
+        self.spki_pins.iter().map(|pin| parse_pin(pin)).collect()
+    }
+
+    fn parsed_roots(&self) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+        let mut roots = Vec::new();
+        for pem in &self.root_certs_pem {
+            let before = roots.len();
+            for cert in CertificateDer::pem_slice_iter(pem.as_bytes()) {
+                roots.push(cert.map_err(|e| TlsError::InvalidRootCert(e.to_string()))?);
+            }
+            if roots.len() == before {
+                return Err(TlsError::InvalidRootCert(
+                    "no PEM certificate found".to_string(),
+                ));
+            }
+        }
+        Ok(roots)
+    }
+}
+
+/// Parse a `sha256/<base64>` pin into its hash.
+fn parse_pin(pin: &str) -> Result<[u8; 32], TlsError> {
+    let invalid = || TlsError::InvalidPin(pin.to_string());
+    let b64 = pin.trim().strip_prefix("sha256/").ok_or_else(invalid)?;
+    base64::engine::general_purpose::STANDARD
+        .decode(b64)
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .ok_or_else(invalid)
+}
+
+/// The `sha256/<base64>` pin for a DER certificate's public key, or
+/// `None` if the certificate can't be parsed.
+pub fn spki_pin(cert_der: &[u8]) -> Option<String> {
+    spki_sha256(cert_der).map(|hash| {
+        format!(
+            "sha256/{}",
+            base64::engine::general_purpose::STANDARD.encode(hash)
+        )
+    })
+}
+
+fn spki_sha256(cert_der: &[u8]) -> Option<[u8; 32]> {
+    Some(Sha256::digest(subject_public_key_info(cert_der)?).into())
+}
+
+/// One DER element: (tag, whole element, contents, remaining input).
+type DerElement<'a> = (u8, &'a [u8], &'a [u8], &'a [u8]);
+
+fn der_next(input: &[u8]) -> Option<DerElement<'_>> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let n = (first & 0x7f) as usize;
+        if n == 0 || n > 4 || rest.len() < n {
+            return None;
+        }
+        let len = rest[..n]
+            .iter()
+            .fold(0usize, |acc, &b| (acc << 8) | b as usize);
+        (len, &rest[n..])
+    };
+    if rest.len() < len {
+        return None;
+    }
+    let header = input.len() - rest.len();
+    Some((tag, &input[..header + len], &rest[..len], &rest[len..]))
+}
+
+/// The encoded SubjectPublicKeyInfo of an X.509 certificate: the seventh
+/// field of the TBSCertificate, counting the optional version.
+fn subject_public_key_info(cert_der: &[u8]) -> Option<&[u8]> {
+    const SEQUENCE: u8 = 0x30;
+    const VERSION: u8 = 0xa0;
+    let (SEQUENCE, _, cert, _) = der_next(cert_der)? else {
+        return None;
+    };
+    let (SEQUENCE, _, tbs, _) = der_next(cert)? else {
+        return None;
+    };
+    let mut fields = tbs;
+    if der_next(fields)?.0 == VERSION {
+        fields = der_next(fields)?.3;
+    }
+    // serialNumber, signature, issuer, validity, subject
+    for _ in 0..5 {
+        fields = der_next(fields)?.3;
+    }
+    match der_next(fields)? {
+        (SEQUENCE, spki, _, _) => Some(spki),
+        _ => None,
+    }
+}
+
+/// Map a rustls handshake error onto [`TlsError`].
+pub fn classify(err: &rustls::Error) -> TlsError {
+    match err {
+        rustls::Error::InvalidCertificate(cert) => match cert {
+            CertificateError::Expired | CertificateError::ExpiredContext { .. } => {
+                TlsError::Expired
+            }
+            CertificateError::NotValidYet | CertificateError::NotValidYetContext { .. } => {
+                TlsError::NotYetValid
+            }
+            CertificateError::UnknownIssuer => TlsError::UnknownIssuer,
+            CertificateError::NotValidForName | CertificateError::NotValidForNameContext { .. } => {
+                TlsError::NameMismatch
+            }
+            // Only `PinningVerifier` raises this one.
+            CertificateError::ApplicationVerificationFailure => TlsError::PinMismatch,
+            other => TlsError::Handshake(format!("invalid certificate: {other:?}")),
+        },
+        other => TlsError::Handshake(other.to_string()),
+    }
+}
+
+/// Map a failed `TlsConnector::connect` onto [`TlsError`].
+pub(crate) fn classify_io(err: &std::io::Error) -> TlsError {
+    err.get_ref()
+        .and_then(|inner| inner.downcast_ref::<rustls::Error>())
+        .map(classify)
+        .unwrap_or_else(|| TlsError::Handshake(err.to_string()))
+}
+
+/// RFC 9266 exporter label for the `tls-exporter` channel binding.
+const CHANNEL_BINDING_LABEL: &[u8] = b"EXPORTER-Channel-Binding";
+
+/// This end's `tls-exporter:<base64url>` channel binding, as reported in
+/// SASL responses. Both ends of one TLS session derive the same value; a
+/// relay that terminates TLS sees a different one on each side.
+pub fn channel_binding<IO>(stream: &tokio_rustls::client::TlsStream<IO>) -> Option<String> {
+    let (_, conn) = stream.get_ref();
+    conn.export_keying_material([0u8; 32], CHANNEL_BINDING_LABEL, None)
+        .ok()
+        .map(|ekm| {
+            format!(
+                "tls-exporter:{}",
+                base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(ekm)
+            )
+        })
+}
+
+/// Install the process-wide rustls crypto provider if none is yet.
+/// [`client_config`] calls this; anything else doing TLS through rustls
+/// before the first connection should too.
+pub fn install_crypto_provider() {
+    // ring is preferred (works on iOS); aws-lc-rs is the default on desktop.
+    #[cfg(feature = "ring")]
+    {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+    }
+    #[cfg(all(feature = "aws-lc-rs", not(feature = "ring")))]
+    {
+        let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+    }
+}
+
+/// Build the rustls client config for a connection. `insecure` skips
+/// chain validation (for self-signed certs); pins still apply.
+pub fn client_config(
+    options: &TlsOptions,
+    insecure: bool,
+) -> Result<rustls::ClientConfig, TlsError> {
+    install_crypto_provider();
+    let pins = options.parsed_pins()?;
+
+    let base: Arc<dyn ServerCertVerifier> = if insecure {
+        Arc::new(InsecureVerifier)
+    } else {
+        let mut root_store = default_roots();
+        for cert in options.parsed_roots()? {
+            root_store
+                .add(cert)
+                .map_err(|e| TlsError::InvalidRootCert(e.to_string()))?;
+        }
+        rustls::client::WebPkiServerVerifier::builder(Arc::new(root_store))
+            .build()
+            .map_err(|e| TlsError::Handshake(e.to_string()))?
+    };
+    let verifier: Arc<dyn ServerCertVerifier> = if pins.is_empty() {
+        base
+    } else {
+        Arc::new(PinningVerifier { inner: base, pins })
+    };
+
+    let mut config = rustls::ClientConfig::builder()
+        .dangerous()
+        .with_custom_certificate_verifier(verifier)
+        .with_no_client_auth();
+    config.alpn_protocols = options.alpn.iter().map(|p| p.as_bytes().to_vec()).collect();
+    Ok(config)
+}
+
+fn default_roots() -> rustls::RootCertStore {
+    let mut root_store =
+        rustls::RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+
+    // Also load the system's native certificate store (covers CAs not in
+    // Mozilla's bundle, e.g. corporate/Sectigo intermediates).
+    let native = rustls_native_certs::load_native_certs();
+    if !native.errors.is_empty() {
+        tracing::warn!("Errors loading native certificates: {:?}", native.errors);
+    }
+    let before = root_store.len();
+    for cert in native.certs {
+        let _ = root_store.add(cert);
+    }
+    let added = root_store.len() - before;
+    if added > 0 {
+        tracing::debug!("Loaded {added} native root certificates");
+    }
+    root_store
+}
+
+/// Runs the usual verification, then requires a pinned key in the chain.
+#[derive(Debug)]
+struct PinningVerifier {
+    inner: Arc<dyn ServerCertVerifier>,
+    pins: Vec<[u8; 32]>,
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+        let pinned = std::iter::once(end_entity)
+            .chain(intermediates)
+            .filter_map(|cert| spki_sha256(cert))
+            .any(|hash| self.pins.contains(&hash));
+        if pinned {
+            Ok(verified)
+        } else {
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure,
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+#[derive(Debug)]
+struct InsecureVerifier;
+
+impl ServerCertVerifier for InsecureVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        rustls::crypto::CryptoProvider::get_default()
+            .map(|p| p.signature_verification_algorithms.supported_schemes())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEAF_PEM: &str = include_str!("../tests/fixtures/tls/leaf.pem");
+    const CA_PEM: &str = include_str!("../tests/fixtures/tls/ca.pem");
+    /// From `openssl x509 -pubkey -noout | openssl pkey -pubin -outform der
+    /// | openssl dgst -sha256 -binary | base64`.
+    const LEAF_PIN: &str = "sha256//7PUol+ZPM9m0yc4auvfQHm1eZep3woPzRqLheW8ajw=";
+
+    fn der(pem: &str) -> CertificateDer<'static> {
+        CertificateDer::from_pem_slice(pem.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn spki_pin_matches_openssl() {
+        assert_eq!(spki_pin(&der(LEAF_PEM)).as_deref(), Some(LEAF_PIN));
+        assert_eq!(
+            spki_pin(&der(CA_PEM)).as_deref(),
+            Some("sha256/wEAHvHDM0aAJZsyLIBAp29P/GVoA5frR7CCe+MsA+Mo=")
+        );
+        assert_eq!(spki_pin(b"\x30\x03\x02\x01\x00"), None);
+        assert_eq!(spki_pin(&[]), None);
+    }
+
+    #[test]
+    fn options_validate_pins_and_roots() {
+        let mut options = TlsOptions {
+            spki_pins: vec![LEAF_PIN.to_string()],
+            root_certs_pem: vec![format!("{CA_PEM}{LEAF_PEM}")],
+            alpn: vec!["irc".to_string()],
+        };
+        assert_eq!(options.validate(), Ok(()));
+        assert_eq!(options.parsed_roots().unwrap().len(), 2);
+
+        options.spki_pins = vec!["sha256/dG9vIHNob3J0".to_string()];
+        assert!(matches!(options.validate(), Err(TlsError::InvalidPin(_))));
+        options.spki_pins = vec![LEAF_PIN.replace("sha256/", "sha1/")];
+        assert!(matches!(options.validate(), Err(TlsError::InvalidPin(_))));
+
+        options.spki_pins.clear();
+        options.root_certs_pem = vec!["not a certificate".to_string()];
+        assert!(matches!(
+            options.validate(),
+            Err(TlsError::InvalidRootCert(_))
+        ));
+    }
+
+    #[test]
+    fn classify_distinguishes_failures() {
+        let cert = |e| rustls::Error::InvalidCertificate(e);
+        assert_eq!(
+            classify(&cert(CertificateError::Expired)),
+            TlsError::Expired
+        );
+        assert_eq!(
+            classify(&cert(CertificateError::ApplicationVerificationFailure)),
+            TlsError::PinMismatch
+        );
+        assert_eq!(
+            classify(&cert(CertificateError::UnknownIssuer)),
+            TlsError::UnknownIssuer
+        );
+        assert!(matches!(
+            classify(&rustls::Error::HandshakeNotComplete),
+            TlsError::Handshake(_)
+        ));
+    }
+}
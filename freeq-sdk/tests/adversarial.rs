@@ -558,12 +558,16 @@ mod irc_canonical {
 
     #[test]
     fn many_params_no_blowup() {
-        let params = (0..500)
-            .map(|i| format!("p{i}"))
-            .collect::<Vec<_>>()
-            .join(" ");
-        let m = Message::parse(&format!(":n CMD {params}"));
-        assert!(m.is_some());
+        let params = |n: usize| {
+            (0..n)
+                .map(|i| format!("p{i}"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        // Past the RFC 2812 limit of 15 the line is refused, not parsed
+        assert!(Message::parse(&format!(":n CMD {}", params(500))).is_none());
+        let m = Message::parse(&format!(":n CMD {}", params(15))).unwrap();
+        assert_eq!(m.params.len(), 15);
     }
 
     #[test]
@@ -707,6 +711,7 @@ mod auth_adversarial {
             pds_url: None,
             dpop_proof: None,
             challenge_nonce: None,
+            binding: None,
         };
         let encoded = auth::encode_response(&r);
         assert!(!encoded.contains('+'), "Must be URL-safe base64");
@@ -0,0 +1,147 @@
+//! TLS trust and pinning against a local server presenting fixture
+//! certificates: a private CA, a `localhost` leaf it signed, and an
+//! expired copy of that leaf.
+
+use std::sync::Arc;
+
+use freeq_sdk::client::{self, ConnectConfig, EstablishedConnection};
+use freeq_sdk::tls::{self, TlsError, TlsOptions};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+const CA_PEM: &str = include_str!("fixtures/tls/ca.pem");
+const LEAF_PEM: &str = include_str!("fixtures/tls/leaf.pem");
+const EXPIRED_PEM: &str = include_str!("fixtures/tls/expired.pem");
+const LEAF_KEY: &str = include_str!("fixtures/tls/leaf.key");
+
+fn pin_of(pem: &str) -> String {
+    let der = CertificateDer::from_pem_slice(pem.as_bytes()).unwrap();
+    tls::spki_pin(&der).unwrap()
+}
+
+/// Accept TLS handshakes on localhost with `cert_pem`, forever.
+async fn serve(cert_pem: &'static str) -> u16 {
+    tls::install_crypto_provider();
+    let chain = vec![CertificateDer::from_pem_slice(cert_pem.as_bytes()).unwrap()];
+    let key = PrivateKeyDer::from_pem_slice(LEAF_KEY.as_bytes()).unwrap();
+    let config = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(chain, key)
+        .unwrap();
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((tcp, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                if let Ok(stream) = acceptor.accept(tcp).await {
+                    // Hold the connection until the client hangs up.
+                    let (mut r, _w) = tokio::io::split(stream);
+                    let _ = tokio::io::copy(&mut r, &mut tokio::io::sink()).await;
+                }
+            });
+        }
+    });
+    port
+}
+
+async fn connect(port: u16, insecure: bool, options: TlsOptions) -> Result<(), TlsError> {
+    let config = ConnectConfig {
+        server_addr: format!("localhost:{port}"),
+        tls: true,
+        tls_insecure: insecure,
+        tls_options: options,
+        ..Default::default()
+    };
+    match client::establish_connection(&config).await {
+        Ok(EstablishedConnection::Tls(_)) => Ok(()),
+        Ok(_) => panic!("expected a TLS connection"),
+        Err(e) => Err(e
+            .downcast_ref::<TlsError>()
+            .cloned()
+            .unwrap_or_else(|| panic!("not a TlsError: {e:#}"))),
+    }
+}
+
+fn with_ca(pins: Vec<String>) -> TlsOptions {
+    TlsOptions {
+        spki_pins: pins,
+        root_certs_pem: vec![CA_PEM.to_string()],
+        alpn: vec!["irc".to_string()],
+    }
+}
+
+#[tokio::test]
+async fn custom_root_and_matching_pin_connect() {
+    let port = serve(LEAF_PEM).await;
+    assert_eq!(connect(port, false, with_ca(vec![])).await, Ok(()));
+    assert_eq!(
+        connect(port, false, with_ca(vec![pin_of(LEAF_PEM)])).await,
+        Ok(())
+    );
+}
+
+#[tokio::test]
+async fn failures_are_told_apart() {
+    let port = serve(LEAF_PEM).await;
+    assert_eq!(
+        connect(port, false, TlsOptions::default()).await,
+        Err(TlsError::UnknownIssuer),
+        "private CA not trusted without a custom root"
+    );
+    // The server sends only its leaf, so a CA pin can't match.
+    assert_eq!(
+        connect(port, false, with_ca(vec![pin_of(CA_PEM)])).await,
+        Err(TlsError::PinMismatch)
+    );
+
+    let expired = serve(EXPIRED_PEM).await;
+    assert_eq!(
+        connect(expired, false, with_ca(vec![pin_of(EXPIRED_PEM)])).await,
+        Err(TlsError::Expired)
+    );
+}
+
+#[tokio::test]
+async fn pins_apply_even_when_verification_is_skipped() {
+    let port = serve(LEAF_PEM).await;
+    assert_eq!(
+        connect(port, true, TlsOptions::default()).await,
+        Ok(()),
+        "insecure mode accepts an untrusted chain"
+    );
+    assert_eq!(
+        connect(
+            port,
+            true,
+            TlsOptions {
+                spki_pins: vec![pin_of(CA_PEM)],
+                ..Default::default()
+            }
+        )
+        .await,
+        Err(TlsError::PinMismatch)
+    );
+}
+
+#[tokio::test]
+async fn bad_pin_is_rejected_before_connecting() {
+    let config = ConnectConfig {
+        server_addr: "localhost:1".to_string(),
+        tls: true,
+        tls_options: TlsOptions {
+            spki_pins: vec!["sha256/short".to_string()],
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let err = match client::establish_connection(&config).await {
+        Ok(_) => panic!("invalid pin must not connect"),
+        Err(e) => e,
+    };
+    assert!(err.to_string().contains("invalid SPKI pin"), "got: {err}");
+}
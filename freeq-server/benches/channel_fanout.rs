@@ -0,0 +1,225 @@
+//! Channel fan-out cost: heap allocations per PRIVMSG as a channel grows.
+//!
+//! Run with `cargo bench -p freeq-server --bench channel_fanout`.
+//!
+//! A counting global allocator measures two things:
+//!
+//! - the bare queueing step, handing one line to every member's
+//!   outbound queue as an owned `String` (the old representation) and
+//!   as a shared [`WireLine`];
+//! - a real server, where one TCP client speaks in a channel whose
+//!   other members are in-process mailboxes, reporting allocations per
+//!   message and the extra cost of each additional member.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use freeq_sdk::did::DidResolver;
+use freeq_server::send_queue::{self, QueueReceiver};
+use freeq_server::server::{SharedState, WireLine};
+use freeq_server::session::Cap;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+
+struct Counting;
+
+static ALLOCS: AtomicU64 = AtomicU64::new(0);
+static BYTES: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(new_size as u64, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn counters() -> (u64, u64) {
+    (
+        ALLOCS.load(Ordering::Relaxed),
+        BYTES.load(Ordering::Relaxed),
+    )
+}
+
+const SIZES: [usize; 3] = [1_000, 2_000, 4_000];
+/// Messages per size. Stays within the per-client flood burst so the
+/// server never throttles the speaker mid-run.
+const MESSAGES: usize = 8;
+const LINE: &str = "@time=2026-01-01T00:00:00.000Z;msgid=01JBENCH :speaker!~u@host PRIVMSG #fanout :the quick brown fox jumps over the lazy dog\r\n";
+
+/// Queue `LINE` once per member and return (allocations, bytes).
+fn queue_strings(members: usize) -> (u64, u64) {
+    let queues: Vec<_> = (0..members).map(|_| mpsc::channel::<String>(4)).collect();
+    let before = counters();
+    for (tx, _) in &queues {
+        let _ = tx.try_send(LINE.to_string());
+    }
+    let after = counters();
+    (after.0 - before.0, after.1 - before.1)
+}
+
+fn queue_wire_lines(members: usize) -> (u64, u64) {
+    let queues: Vec<_> = (0..members).map(|_| mpsc::channel::<WireLine>(4)).collect();
+    let before = counters();
+    let line = WireLine::copy_from_slice(LINE.as_bytes());
+    for (tx, _) in &queues {
+        let _ = tx.try_send(line.clone());
+    }
+    let after = counters();
+    (after.0 - before.0, after.1 - before.1)
+}
+
+/// Add `count` in-process members to `#fanout`, every third one with
+/// message-tags and server-time so all line variants are exercised.
+fn add_members(state: &Arc<SharedState>, first: usize, count: usize) -> Vec<QueueReceiver> {
+    let mut mailboxes = Vec::with_capacity(count);
+    for i in first..first + count {
+        let sid = format!("bench-{i}");
+        let (tx, rx) = send_queue::channel(MESSAGES * 2, state.metrics.send_queue.clone());
+        state.connections.insert(sid.clone(), tx);
+        state.nick_to_session.lock().insert(&format!("m{i}"), &sid);
+        if i % 3 == 0 {
+            state.sessions.enable_cap(&sid, Cap::MessageTags);
+            state.sessions.enable_cap(&sid, Cap::ServerTime);
+        }
+        state
+            .channels
+            .get_or_insert_with("#fanout", Default::default)
+            .members
+            .insert(sid);
+        mailboxes.push(rx);
+    }
+    mailboxes
+}
+
+struct Speaker {
+    reader: BufReader<tokio::net::tcp::OwnedReadHalf>,
+    writer: tokio::net::tcp::OwnedWriteHalf,
+}
+
+impl Speaker {
+    async fn connect(addr: std::net::SocketAddr) -> Self {
+        let (r, w) = TcpStream::connect(addr).await.unwrap().into_split();
+        let mut s = Self {
+            reader: BufReader::new(r),
+            writer: w,
+        };
+        s.send("NICK speaker").await;
+        s.send("USER speaker 0 * :bench").await;
+        s.wait_for("001").await;
+        s.send("JOIN #fanout").await;
+        s.wait_for("366").await;
+        s
+    }
+
+    async fn send(&mut self, line: &str) {
+        self.writer
+            .write_all(format!("{line}\r\n").as_bytes())
+            .await
+            .unwrap();
+    }
+
+    async fn wait_for(&mut self, numeric: &str) {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            assert!(self.reader.read_line(&mut line).await.unwrap() > 0, "EOF");
+            if line.split_whitespace().nth(1) == Some(numeric) {
+                return;
+            }
+        }
+    }
+}
+
+/// Send `MESSAGES` PRIVMSGs and wait for every mailbox to receive each
+/// one. Returns (allocations, bytes) per message.
+async fn run_messages(speaker: &mut Speaker, mailboxes: &mut [QueueReceiver]) -> (f64, f64) {
+    let before = counters();
+    for _ in 0..MESSAGES {
+        speaker
+            .send("PRIVMSG #fanout :the quick brown fox jumps over the lazy dog")
+            .await;
+        for rx in mailboxes.iter_mut() {
+            tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .expect("member never received the message")
+                .expect("mailbox closed");
+        }
+    }
+    let after = counters();
+    let n = MESSAGES as f64;
+    (
+        (after.0 - before.0) as f64 / n,
+        (after.1 - before.1) as f64 / n,
+    )
+}
+
+#[tokio::main]
+async fn main() {
+    println!("queueing one line per member");
+    println!(
+        "{:>8}  {:>20}  {:>20}",
+        "members", "String allocs/bytes", "WireLine allocs/bytes"
+    );
+    for members in SIZES {
+        let (sa, sb) = queue_strings(members);
+        let (wa, wb) = queue_wire_lines(members);
+        println!("{members:>8}  {sa:>10} / {sb:<9}  {wa:>10} / {wb:<9}");
+    }
+
+    let config = freeq_server::config::ServerConfig {
+        listen_addr: "127.0.0.1:0".to_string(),
+        server_name: "bench".to_string(),
+        challenge_timeout_secs: 60,
+        ..Default::default()
+    };
+    let server = freeq_server::server::Server::with_resolver(
+        config,
+        DidResolver::static_map(HashMap::new()),
+    );
+    let (addr, _web, _handle, state) = server.start_with_web_state().await.unwrap();
+    let mut speaker = Speaker::connect(addr).await;
+
+    println!();
+    println!("server PRIVMSG fan-out ({MESSAGES} messages per size)");
+    println!(
+        "{:>8}  {:>14}  {:>14}  {:>10}",
+        "members", "allocs/msg", "bytes/msg", "elapsed"
+    );
+    let mut mailboxes = Vec::new();
+    let mut previous: Option<(usize, f64)> = None;
+    for members in SIZES {
+        let grown = members - mailboxes.len();
+        mailboxes.extend(add_members(&state, mailboxes.len(), grown));
+        // Let the flood bucket refill between sizes.
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        let started = Instant::now();
+        let (allocs, bytes) = run_messages(&mut speaker, &mut mailboxes).await;
+        let elapsed = started.elapsed();
+        print!("{members:>8}  {allocs:>14.1}  {bytes:>14.0}  {elapsed:>10.2?}");
+        if let Some((prev_members, prev_allocs)) = previous {
+            let per_member = (allocs - prev_allocs) / (members - prev_members) as f64;
+            print!("  ({per_member:.2} allocs per extra member)");
+        }
+        println!();
+        previous = Some((members, allocs));
+    }
+}
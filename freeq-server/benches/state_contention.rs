@@ -0,0 +1,167 @@
+//! Shared-state lock contention: channel lookup plus fan-out under load.
+//!
+//! Run with `cargo bench -p freeq-server --bench state_contention`.
+//!
+//! Each worker thread repeatedly picks a channel, copies its member list
+//! out, and queues a line to every member, which is the access pattern
+//! of a PRIVMSG. The same workload runs against a [`ShardedMap`] (what
+//! `SharedState` uses) and against a single `Mutex<HashMap>` per map
+//! (what it used before), reporting throughput as threads are added.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use freeq_server::server::WireLine;
+use freeq_server::sharded::ShardedMap;
+use parking_lot::Mutex;
+use tokio::sync::mpsc;
+
+const CHANNELS: usize = 512;
+const MEMBERS_PER_CHANNEL: usize = 8;
+const SESSIONS: usize = 2_048;
+const RUN: Duration = Duration::from_secs(2);
+const LINE: &str = ":speaker!~u@host PRIVMSG #chan :the quick brown fox\r\n";
+
+/// The two map shapes under test.
+trait State: Send + Sync + 'static {
+    fn members(&self, channel: &str) -> Vec<String>;
+    fn queue(&self, session: &str, line: &WireLine);
+}
+
+struct Sharded {
+    channels: ShardedMap<HashSet<String>>,
+    connections: ShardedMap<mpsc::Sender<WireLine>>,
+}
+
+impl State for Sharded {
+    fn members(&self, channel: &str) -> Vec<String> {
+        self.channels
+            .get(channel)
+            .map(|m| m.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    fn queue(&self, session: &str, line: &WireLine) {
+        if let Some(tx) = self.connections.get(session) {
+            let _ = tx.try_send(line.clone());
+        }
+    }
+}
+
+struct Global {
+    channels: Mutex<HashMap<String, HashSet<String>>>,
+    connections: Mutex<HashMap<String, mpsc::Sender<WireLine>>>,
+}
+
+impl State for Global {
+    fn members(&self, channel: &str) -> Vec<String> {
+        self.channels
+            .lock()
+            .get(channel)
+            .map(|m| m.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    fn queue(&self, session: &str, line: &WireLine) {
+        if let Some(tx) = self.connections.lock().get(session) {
+            let _ = tx.try_send(line.clone());
+        }
+    }
+}
+
+/// Channel name -> members, and one (sender, receiver) per session. The
+/// receivers are kept alive but never drained, so once a queue fills
+/// `try_send` fails fast, as it does for a slow client.
+fn fixture() -> (
+    Vec<(String, HashSet<String>)>,
+    Vec<(String, mpsc::Sender<WireLine>)>,
+    Vec<mpsc::Receiver<WireLine>>,
+) {
+    let channels = (0..CHANNELS)
+        .map(|c| {
+            let members = (0..MEMBERS_PER_CHANNEL)
+                .map(|m| format!("s{}", (c * MEMBERS_PER_CHANNEL + m) % SESSIONS))
+                .collect();
+            (format!("#c{c}"), members)
+        })
+        .collect();
+    let (senders, receivers) = (0..SESSIONS)
+        .map(|s| {
+            let (tx, rx) = mpsc::channel(64);
+            ((format!("s{s}"), tx), rx)
+        })
+        .unzip();
+    (channels, senders, receivers)
+}
+
+/// Run the workload on `threads` threads for [`RUN`]; returns messages
+/// fanned out per second.
+fn run<S: State>(state: Arc<S>, threads: usize) -> f64 {
+    let stop = Arc::new(AtomicBool::new(false));
+    let done = Arc::new(AtomicU64::new(0));
+    let workers: Vec<_> = (0..threads)
+        .map(|t| {
+            let (state, stop, done) = (state.clone(), stop.clone(), done.clone());
+            std::thread::spawn(move || {
+                let line = WireLine::from_static(LINE.as_bytes());
+                let mut n = 0u64;
+                let mut c = t * 97;
+                while !stop.load(Ordering::Relaxed) {
+                    c = (c + 31) % CHANNELS;
+                    for member in state.members(&format!("#c{c}")) {
+                        state.queue(&member, &line);
+                    }
+                    n += 1;
+                }
+                done.fetch_add(n, Ordering::Relaxed);
+            })
+        })
+        .collect();
+    std::thread::sleep(RUN);
+    stop.store(true, Ordering::Relaxed);
+    for w in workers {
+        w.join().unwrap();
+    }
+    done.load(Ordering::Relaxed) as f64 / RUN.as_secs_f64()
+}
+
+fn main() {
+    let cores = std::thread::available_parallelism().map_or(4, |n| n.get());
+    let mut counts = vec![1];
+    while counts.last().unwrap() * 2 <= cores.max(8) {
+        counts.push(counts.last().unwrap() * 2);
+    }
+
+    println!(
+        "{CHANNELS} channels x {MEMBERS_PER_CHANNEL} members, {SESSIONS} sessions, {RUN:?} per run"
+    );
+    println!(
+        "{:>8}  {:>14}  {:>14}  {:>8}",
+        "threads", "global msg/s", "sharded msg/s", "speedup"
+    );
+    let mut base = None;
+    for threads in counts {
+        let (channels, senders, _global_rx) = fixture();
+        let global = Arc::new(Global {
+            channels: Mutex::new(channels.into_iter().collect()),
+            connections: Mutex::new(senders.into_iter().collect()),
+        });
+        let global_rate = run(global, threads);
+
+        let (channels, senders, _sharded_rx) = fixture();
+        let sharded = Arc::new(Sharded {
+            channels: channels.into_iter().collect(),
+            connections: senders.into_iter().collect(),
+        });
+        let sharded_rate = run(sharded, threads);
+
+        let single = *base.get_or_insert(sharded_rate);
+        println!(
+            "{threads:>8}  {global_rate:>14.0}  {sharded_rate:>14.0}  {:>7.2}x  (sharded scaling {:.2}x)",
+            sharded_rate / global_rate,
+            sharded_rate / single,
+        );
+    }
+}
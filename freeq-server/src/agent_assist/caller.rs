@@ -58,9 +58,9 @@ pub fn is_channel_member(caller: &Caller, state: &Arc<SharedState>, channel: &st
     let Some(sid) = &caller.session_id else {
         return false;
     };
-    let channels = state.channels.lock();
-    channels
-        .get(&channel.to_lowercase())
+    state
+        .channels
+        .get(&crate::casemap::fold(channel))
         .map(|ch| ch.members.contains(sid))
         .unwrap_or(false)
 }
@@ -70,9 +70,9 @@ pub fn is_channel_operator(caller: &Caller, state: &Arc<SharedState>, channel: &
     let Some(sid) = &caller.session_id else {
         return false;
     };
-    let channels = state.channels.lock();
-    channels
-        .get(&channel.to_lowercase())
+    state
+        .channels
+        .get(&crate::casemap::fold(channel))
         .map(|ch| ch.ops.contains(sid))
         .unwrap_or(false)
 }
@@ -25,6 +25,7 @@
 use super::caller;
 use super::types::*;
 use crate::server::SharedState;
+use crate::session::{Cap, Caps};
 use std::sync::Arc;
 
 // ─── Tool: validate_client_config ────────────────────────────────────────
@@ -451,7 +452,6 @@ pub fn diagnose_sync(
         let safe_channel = sanitize_label(&normalized);
         let joined: usize = state
             .channels
-            .lock()
             .get(&normalized)
             .map(|ch| {
                 sessions
@@ -623,14 +623,12 @@ pub fn inspect_my_session(
 
     safe_facts.push(format!("Negotiated capabilities: {caps_per_session}"));
 
-    let joined: Vec<String> = {
-        let channels = state.channels.lock();
-        channels
+    let joined: Vec<String> = state.channels.filter_map(|name, ch| {
+        session_ids
             .iter()
-            .filter(|(_, ch)| session_ids.iter().any(|sid| ch.members.contains(sid)))
-            .map(|(name, _)| name.clone())
-            .collect()
-    };
+            .any(|sid| ch.members.contains(sid))
+            .then(|| name.to_string())
+    });
     if joined.is_empty() {
         safe_facts.push("Joined channels: none.".into());
     } else {
@@ -698,41 +696,13 @@ pub fn inspect_my_session(
 /// how a bot developer thinks ("does the server see my caps?").
 fn collect_caps(state: &Arc<SharedState>, sids: &[String]) -> String {
     // Per-cap presence: if ANY session has the cap, count it.
-    fn any_in(
-        set: &parking_lot::Mutex<std::collections::HashSet<String>>,
-        sids: &[String],
-    ) -> bool {
-        let s = set.lock();
-        sids.iter().any(|sid| s.contains(sid))
-    }
-    let mut caps: Vec<&'static str> = Vec::new();
-    if any_in(&state.cap_message_tags, sids) {
-        caps.push("message-tags");
-    }
-    if any_in(&state.cap_server_time, sids) {
-        caps.push("server-time");
-    }
-    if any_in(&state.cap_batch, sids) {
-        caps.push("batch");
-    }
-    if any_in(&state.cap_echo_message, sids) {
-        caps.push("echo-message");
-    }
-    if any_in(&state.cap_account_notify, sids) {
-        caps.push("account-notify");
-    }
-    if any_in(&state.cap_extended_join, sids) {
-        caps.push("extended-join");
-    }
-    if any_in(&state.cap_away_notify, sids) {
-        caps.push("away-notify");
-    }
-    if any_in(&state.cap_account_tag, sids) {
-        caps.push("account-tag");
-    }
-    if any_in(&state.cap_multi_prefix, sids) {
-        caps.push("multi-prefix");
-    }
+    let caps: Vec<&'static str> = sids
+        .iter()
+        .flat_map(|sid| state.sessions.caps(sid).iter())
+        .collect::<Caps>()
+        .iter()
+        .map(Cap::name)
+        .collect();
     if caps.is_empty() {
         "(none — your client did not negotiate any IRCv3 capabilities)".into()
     } else {
@@ -762,8 +732,7 @@ pub fn diagnose_join_failure(
     let safe_channel = sanitize_label(&channel);
     let safe_account = sanitize_label(&input.account);
 
-    let channels = state.channels.lock();
-    let Some(ch) = channels.get(&channel) else {
+    let Some(ch) = state.channels.get(&channel) else {
         return FactBundle {
             ok: false,
             code: "CHANNEL_DOES_NOT_EXIST".into(),
@@ -877,6 +846,21 @@ pub fn diagnose_join_failure(
     if ch.encrypted_only {
         mode_chars.push("+E");
     }
+    if ch.archived {
+        mode_chars.push("+A");
+    }
+    if ch.auditorium {
+        mode_chars.push("+u");
+    }
+    if ch.no_stats {
+        mode_chars.push("+S");
+    }
+    if ch.secret {
+        mode_chars.push("+s");
+    }
+    if ch.temporary.is_some() {
+        mode_chars.push("+T");
+    }
     if ch.topic_locked {
         mode_chars.push("+t");
     }
@@ -1266,9 +1250,8 @@ pub fn predict_message_outcome(
     }
 
     if is_channel {
-        let channels = state.channels.lock();
         let normalized = target.to_lowercase();
-        let Some(ch) = channels.get(&normalized) else {
+        let Some(ch) = state.channels.get(&normalized) else {
             return FactBundle {
                 ok: false,
                 code: "PREDICT_CHANNEL_DOES_NOT_EXIST".into(),
@@ -1301,6 +1284,15 @@ pub fn predict_message_outcome(
                 "MODERATED: `{safe_target}` is +m; only voiced / op / halfop may send."
             ));
         }
+        let op_or_halfop = session_ids
+            .iter()
+            .any(|sid| ch.ops.contains(sid) || ch.halfops.contains(sid));
+        if !op_or_halfop && ch.quiets.iter().any(|q| q.mask == input.account) {
+            blockers.push(format!(
+                "QUIETED: `{safe_account}` is on the +q list of `{safe_target}`; an op or \
+                 moderator must remove it."
+            ));
+        }
         if ch.encrypted_only {
             safe_facts.push(format!(
                 "Note: `{safe_target}` is +E. Your client must include the `+encrypted` tag \
@@ -1890,7 +1882,7 @@ fn normalize_channel(channel: &str) -> String {
     } else {
         format!("#{channel}")
     };
-    with_hash.to_lowercase()
+    crate::casemap::fold(&with_hash)
 }
 
 /// Strip control chars + cap length on caller-provided identifiers so
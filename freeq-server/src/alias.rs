@@ -0,0 +1,290 @@
+//! Command aliases — services-style shortcuts for clients coming from
+//! other networks.
+//!
+//! freeq has no NickServ or ChanServ: nick ownership comes from AT Protocol
+//! login and channel authority from DIDs. Aliases rewrite what people type
+//! out of habit (`NS CLAIM alice.bsky.social`, `CS OP #chan bob`) into the
+//! native command before dispatch.
+//!
+//! An alias spec is `WORDS=EXPANSION`, e.g. `CS OP=MODE $1 +o $2`:
+//!
+//! - `WORDS` — the command plus any leading sub-command words, matched
+//!   case-insensitively. The longest match wins.
+//! - `$N` — the Nth argument after `WORDS` (required).
+//! - `$N-` — argument N and everything after it, space-joined (may be empty).
+//! - A token starting with `:` begins the trailing parameter.
+//!
+//! Sources, later ones replacing earlier ones with the same `WORDS`:
+//! [`builtin_aliases`], plugins ([`crate::plugin::Plugin::aliases`]), and
+//! `--alias`.
+
+use crate::irc::Message;
+use crate::plugin::PluginManager;
+
+/// Services-style shortcuts enabled by default, as `(service, sub, expansion)`.
+/// Each service is registered under its short and long names.
+const BUILTIN: &[(&str, &str, &str)] = &[
+    ("NS", "CLAIM", "LOGIN $1"),
+    ("NS", "IDENTIFY", "LOGIN $1"),
+    ("NS", "LOGIN", "LOGIN $1"),
+    ("CS", "OP", "MODE $1 +o $2"),
+    ("CS", "DEOP", "MODE $1 -o $2"),
+    ("CS", "VOICE", "MODE $1 +v $2"),
+    ("CS", "DEVOICE", "MODE $1 -v $2"),
+    ("CS", "BAN", "MODE $1 +b $2"),
+    ("CS", "UNBAN", "MODE $1 -b $2"),
+    ("CS", "KICK", "KICK $1 $2 :$3-"),
+    ("CS", "TOPIC", "TOPIC $1 :$2-"),
+];
+
+const SERVICE_NAMES: &[(&str, &str)] = &[("NS", "NICKSERV"), ("CS", "CHANSERV")];
+
+/// Built-in alias specs (`NS …`, `NICKSERV …`, `CS …`, `CHANSERV …`).
+pub fn builtin_aliases() -> Vec<String> {
+    let mut specs = Vec::new();
+    for (service, sub, expansion) in BUILTIN {
+        for (short, long) in SERVICE_NAMES {
+            if service == short {
+                specs.push(format!("{short} {sub}={expansion}"));
+                specs.push(format!("{long} {sub}={expansion}"));
+            }
+        }
+    }
+    specs
+}
+
+/// One parsed alias.
+#[derive(Debug, Clone)]
+struct Alias {
+    /// Uppercased command + sub-command words.
+    words: Vec<String>,
+    expansion: String,
+}
+
+impl Alias {
+    fn parse(spec: &str) -> Result<Self, String> {
+        let (lhs, rhs) = spec
+            .split_once('=')
+            .ok_or_else(|| format!("alias '{spec}' is missing '='"))?;
+        let words: Vec<String> = lhs
+            .split_whitespace()
+            .map(|w| w.to_ascii_uppercase())
+            .collect();
+        let expansion = rhs.trim().to_string();
+        if words.is_empty() {
+            return Err(format!("alias '{spec}' has no command"));
+        }
+        match expansion.split_whitespace().next() {
+            Some(cmd) if !cmd.contains('$') && !cmd.starts_with(':') => {}
+            _ => return Err(format!("alias '{spec}' must expand to a literal command")),
+        }
+        Ok(Self { words, expansion })
+    }
+
+    fn matches(&self, msg: &Message) -> bool {
+        self.words[0] == msg.command
+            && msg.params.len() >= self.words.len() - 1
+            && self.words[1..]
+                .iter()
+                .zip(&msg.params)
+                .all(|(w, p)| w.eq_ignore_ascii_case(p))
+    }
+
+    /// Substitute `args` into the expansion. Errors if a `$N` is missing.
+    fn expand(&self, args: &[String], tags: &Message) -> Result<Message, String> {
+        let mut params = Vec::new();
+        let mut tokens = self.expansion.split_whitespace();
+        let command = tokens.next().unwrap_or_default().to_ascii_uppercase();
+        let rest: Vec<&str> = tokens.collect();
+        for (i, token) in rest.iter().enumerate() {
+            if let Some(trailing) = token.strip_prefix(':') {
+                let text = std::iter::once(trailing)
+                    .chain(rest[i + 1..].iter().copied())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let text = substitute(&text, args)?;
+                if !text.is_empty() {
+                    params.push(text);
+                }
+                break;
+            }
+            let value = substitute(token, args)?;
+            if !value.is_empty() {
+                params.push(value);
+            }
+        }
+        Ok(Message {
+            tags: tags.tags.clone(),
+            prefix: None,
+            command,
+            params,
+        })
+    }
+}
+
+/// Replace `$N` / `$N-` in `template` with arguments (1-based).
+fn substitute(template: &str, args: &[String]) -> Result<String, String> {
+    let mut out = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' || !chars.peek().is_some_and(|d| d.is_ascii_digit()) {
+            out.push(c);
+            continue;
+        }
+        let mut n = 0usize;
+        while let Some(d) = chars.peek().and_then(|d| d.to_digit(10)) {
+            n = n.saturating_mul(10).saturating_add(d as usize);
+            chars.next();
+        }
+        if chars.peek() == Some(&'-') {
+            chars.next();
+            out.push_str(
+                &args
+                    .get(n.saturating_sub(1)..)
+                    .unwrap_or_default()
+                    .join(" "),
+            );
+        } else {
+            let arg = n
+                .checked_sub(1)
+                .and_then(|i| args.get(i))
+                .ok_or_else(|| format!("missing argument ${n}"))?;
+            out.push_str(arg);
+        }
+    }
+    Ok(out)
+}
+
+/// All active aliases, longest `words` first.
+#[derive(Debug, Clone, Default)]
+pub struct AliasTable {
+    aliases: Vec<Alias>,
+}
+
+impl AliasTable {
+    /// Build from specs in increasing priority; a later spec with the same
+    /// words replaces an earlier one. Invalid specs are logged and skipped.
+    pub fn from_specs<'a>(specs: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut aliases: Vec<Alias> = Vec::new();
+        for spec in specs {
+            match Alias::parse(spec) {
+                Ok(alias) => {
+                    aliases.retain(|a| a.words != alias.words);
+                    aliases.push(alias);
+                }
+                Err(e) => tracing::warn!("Ignoring {e}"),
+            }
+        }
+        aliases.sort_by_key(|a| std::cmp::Reverse(a.words.len()));
+        Self { aliases }
+    }
+
+    /// Built-in aliases, then plugin aliases, then `--alias` from config.
+    pub fn load(config_aliases: &[String], plugins: &PluginManager) -> Self {
+        let builtin = builtin_aliases();
+        let from_plugins = plugins.aliases();
+        Self::from_specs(
+            builtin
+                .iter()
+                .chain(&from_plugins)
+                .chain(config_aliases)
+                .map(String::as_str),
+        )
+    }
+
+    /// Rewrite `msg` if it matches an alias. `None` means no alias applies;
+    /// `Err` carries a usage message for a match with missing arguments.
+    pub fn expand(&self, msg: &Message) -> Option<Result<Message, String>> {
+        let alias = self.aliases.iter().find(|a| a.matches(msg))?;
+        let args = &msg.params[alias.words.len() - 1..];
+        Some(alias.expand(args, msg).map_err(|e| {
+            format!(
+                "{}: {e} (expands to {})",
+                alias.words.join(" "),
+                alias.expansion
+            )
+        }))
+    }
+
+    /// Sub-command words registered under `command`, for a hint when the
+    /// client typed an alias prefix with an unknown sub-command.
+    pub fn subcommands(&self, command: &str) -> Vec<String> {
+        let mut subs: Vec<String> = self
+            .aliases
+            .iter()
+            .filter(|a| a.words.len() > 1 && a.words[0].eq_ignore_ascii_case(command))
+            .map(|a| a.words[1..].join(" "))
+            .collect();
+        subs.sort();
+        subs.dedup();
+        subs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(table: &AliasTable, line: &str) -> Option<Result<String, String>> {
+        let msg = Message::parse(line).unwrap();
+        table
+            .expand(&msg)
+            .map(|r| r.map(|m| m.to_string().trim_end().to_string()))
+    }
+
+    #[test]
+    fn builtin_services_aliases() {
+        let table = AliasTable::from_specs(builtin_aliases().iter().map(String::as_str));
+        assert_eq!(
+            expand(&table, "CS OP #chan bob"),
+            Some(Ok("MODE #chan +o bob".to_string()))
+        );
+        assert_eq!(
+            expand(&table, "chanserv deop #chan bob"),
+            Some(Ok("MODE #chan -o bob".to_string()))
+        );
+        assert_eq!(
+            expand(&table, "NS CLAIM alice.bsky.social"),
+            Some(Ok("LOGIN alice.bsky.social".to_string()))
+        );
+        assert_eq!(
+            expand(&table, "CS KICK #chan bob go away"),
+            Some(Ok("KICK #chan bob :go away".to_string()))
+        );
+        // Empty rest-argument drops the trailing parameter
+        assert_eq!(
+            expand(&table, "CS KICK #chan bob"),
+            Some(Ok("KICK #chan bob".to_string()))
+        );
+        assert!(expand(&table, "MODE #chan +o bob").is_none());
+        assert!(expand(&table, "CS FROBNICATE #chan").is_none());
+    }
+
+    #[test]
+    fn missing_argument_is_a_usage_error() {
+        let table = AliasTable::from_specs(["CS OP=MODE $1 +o $2"]);
+        let err = expand(&table, "CS OP #chan").unwrap().unwrap_err();
+        assert!(err.contains("missing argument $2"), "{err}");
+    }
+
+    #[test]
+    fn later_specs_override_and_longest_match_wins() {
+        let table = AliasTable::from_specs([
+            "CS OP=MODE $1 +o $2",
+            "CS OP=MODE $1 +ov $2 $2",
+            "CS=NOTICE $1 :$2-",
+            "not an alias",
+            "X=$1",
+        ]);
+        assert_eq!(
+            expand(&table, "CS OP #c bob"),
+            Some(Ok("MODE #c +ov bob bob".to_string()))
+        );
+        assert_eq!(
+            expand(&table, "CS bob hi there"),
+            Some(Ok("NOTICE bob :hi there".to_string()))
+        );
+        assert!(expand(&table, "X foo").is_none());
+        assert_eq!(table.subcommands("cs"), vec!["OP".to_string()]);
+    }
+}
@@ -186,7 +186,10 @@ impl AvSessionManager {
         // If the existing session has no active participants (all left/disconnected),
         // auto-end it so a new session can start.
         if let Some(ch) = channel
-            && let Some(existing_id) = self.channel_sessions.get(&ch.to_lowercase()).cloned()
+            && let Some(existing_id) = self
+                .channel_sessions
+                .get(&crate::casemap::fold(ch))
+                .cloned()
             && let Some(existing) = self.sessions.get(&existing_id)
             && matches!(existing.state, AvSessionState::Active)
         {
@@ -245,7 +248,8 @@ impl AvSessionManager {
 
         self.sessions.insert(id.clone(), session);
         if let Some(ch) = channel {
-            self.channel_sessions.insert(ch.to_lowercase(), id.clone());
+            self.channel_sessions
+                .insert(crate::casemap::fold(ch), id.clone());
         }
 
         Ok(self.sessions.get(&id).unwrap().clone())
@@ -382,7 +386,7 @@ impl AvSessionManager {
             }
             // Remove from channel_sessions index
             if let Some(ch) = &session.channel {
-                self.channel_sessions.remove(&ch.to_lowercase());
+                self.channel_sessions.remove(&crate::casemap::fold(ch));
             }
         }
     }
@@ -394,7 +398,7 @@ impl AvSessionManager {
 
     /// Get active session for a channel.
     pub fn active_session_for_channel(&self, channel: &str) -> Option<&AvSession> {
-        let id = self.channel_sessions.get(&channel.to_lowercase())?;
+        let id = self.channel_sessions.get(&crate::casemap::fold(channel))?;
         let session = self.sessions.get(id)?;
         if matches!(session.state, AvSessionState::Active) {
             Some(session)
@@ -487,7 +491,7 @@ impl AvSessionManager {
 
         if let Some(ch) = channel {
             self.channel_sessions
-                .insert(ch.to_lowercase(), id.to_string());
+                .insert(crate::casemap::fold(ch), id.to_string());
         }
         self.sessions.insert(id.to_string(), session);
     }
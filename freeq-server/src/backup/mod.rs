@@ -0,0 +1,421 @@
+//! Snapshot-consistent backups of the server's SQLite stores.
+//!
+//! `BACKUP` (opers) or `POST /api/v1/admin/backup` (with an OPER-TOKEN
+//! as bearer) copies the main database — message history, nick claims,
+//! the E2EE key directory, channels — and the policy database into one
+//! timestamped directory under `--backup-dir`:
+//!
+//! ```text
+//! backups/20261017T071335.123Z/
+//!     freeq.db  freeq-policy.db  manifest.json
+//! ```
+//!
+//! Each store is read through its own read-only connection. Both open a
+//! read transaction while the live stores are briefly locked, so the
+//! snapshot is one point in time across them; the copy itself runs
+//! outside the locks through SQLite's online backup API, and in WAL
+//! mode the pinned readers don't hold writers back. The copy is
+//! assembled in a `.partial-`
+//! directory, synced and renamed into place, so a directory with a
+//! manifest is always a complete backup. Restoring is copying the files
+//! back over `--db-path` (and its `-policy.db`) while the server is
+//! stopped.
+//!
+//! With `--backup-s3-url`, the files are then uploaded to an
+//! S3-compatible bucket under the same name, manifest last (see [`s3`]).
+//! `--backup-keep` prunes the oldest backups, locally and in the bucket.
+
+mod s3;
+
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::Context;
+use rusqlite::backup::{Backup as SqliteBackup, StepResult};
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::ServerConfig;
+use crate::server::SharedState;
+
+/// Manifest format identifier.
+pub const FORMAT: &str = "freeq-backup";
+/// Current manifest format version.
+pub const VERSION: u32 = 1;
+/// Name of the manifest in each backup directory.
+pub const MANIFEST: &str = "manifest.json";
+/// Prefix of a backup directory still being written.
+const PARTIAL_PREFIX: &str = ".partial-";
+/// Backup names: UTC time to the millisecond, sortable as strings.
+const NAME_FORMAT: &str = "%Y%m%dT%H%M%S%.3fZ";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: String,
+    pub version: u32,
+    /// The backup's directory name.
+    pub name: String,
+    pub server_name: String,
+    pub created_at: String,
+    pub files: Vec<BackupFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupFile {
+    /// `"main"` or `"policy"`.
+    pub store: String,
+    pub name: String,
+    pub bytes: u64,
+    /// SHA-256 (hex) of the file.
+    pub sha256: String,
+}
+
+/// What a backup run did.
+#[derive(Debug, Serialize)]
+pub struct Backup {
+    pub manifest: Manifest,
+    pub dir: PathBuf,
+    /// Where the files were uploaded, if `--backup-s3-url` is set.
+    pub uploaded_to: Option<String>,
+    /// Backups removed under `--backup-keep`, locally and remotely.
+    pub pruned: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum BackupError {
+    /// The server has no `--db-path`, so nothing to back up.
+    NoDatabase,
+    /// Another backup is running.
+    Busy,
+    /// The snapshot couldn't be written.
+    Failed(anyhow::Error),
+    /// The snapshot was written locally but the upload failed.
+    UploadFailed { name: String, error: anyhow::Error },
+}
+
+impl BackupError {
+    /// Machine-readable code for `FAIL BACKUP` and the HTTP API.
+    pub fn code(&self) -> &'static str {
+        match self {
+            BackupError::NoDatabase => "NO_DATABASE",
+            BackupError::Busy => "IN_PROGRESS",
+            BackupError::Failed(_) => "FAILED",
+            BackupError::UploadFailed { .. } => "UPLOAD_FAILED",
+        }
+    }
+}
+
+impl std::fmt::Display for BackupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackupError::NoDatabase => write!(f, "The server has no database to back up"),
+            BackupError::Busy => write!(f, "A backup is already running"),
+            BackupError::Failed(e) => write!(f, "Backup failed: {e:#}"),
+            BackupError::UploadFailed { name, error } => {
+                write!(
+                    f,
+                    "Backup {name} was written locally but not uploaded: {error:#}"
+                )
+            }
+        }
+    }
+}
+
+impl From<anyhow::Error> for BackupError {
+    fn from(e: anyhow::Error) -> Self {
+        BackupError::Failed(e)
+    }
+}
+
+/// Where backups go: `--backup-dir`, or `backups/` in the data directory.
+pub fn backup_root(config: &ServerConfig) -> PathBuf {
+    config
+        .backup_dir
+        .as_ref()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| config.data_dir().join("backups"))
+}
+
+/// Take a backup, upload it if configured, and prune old ones.
+pub async fn run(state: &Arc<SharedState>) -> Result<Backup, BackupError> {
+    if state.config.db_path.is_none() || state.db.is_none() {
+        return Err(BackupError::NoDatabase);
+    }
+    if state.backup_running.swap(true, Ordering::SeqCst) {
+        return Err(BackupError::Busy);
+    }
+    // Run detached, so a caller that goes away mid-backup (a dropped HTTP
+    // request or connection) neither cancels it halfway nor leaves the
+    // flag set; the guard clears it even if the backup panics.
+    let state = Arc::clone(state);
+    let task = tokio::spawn(async move {
+        let _running = Running(&state.backup_running);
+        run_exclusive(&state).await
+    });
+    match task.await {
+        Ok(result) => result,
+        Err(e) => Err(BackupError::Failed(anyhow::anyhow!(
+            "backup task failed: {e}"
+        ))),
+    }
+}
+
+/// Clears `backup_running` when dropped.
+struct Running<'a>(&'a AtomicBool);
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+async fn run_exclusive(state: &Arc<SharedState>) -> Result<Backup, BackupError> {
+    let root = backup_root(&state.config);
+    let snapshot_state = Arc::clone(state);
+    let snapshot_root = root.clone();
+    let (manifest, dir) =
+        tokio::task::spawn_blocking(move || snapshot(&snapshot_state, &snapshot_root))
+            .await
+            .context("backup task panicked")??;
+    tracing::info!(backup = %manifest.name, dir = %dir.display(), "Backup written");
+
+    let keep = state.config.backup_keep;
+    let mut pruned = prune_local(&root, keep).context("pruning old backups")?;
+    let mut uploaded_to = None;
+    if let Some(bucket) = s3::Bucket::from_config(&state.config)? {
+        let upload = async {
+            bucket.upload(&manifest, &dir).await?;
+            bucket.prune(keep).await
+        };
+        match upload.await {
+            Ok(remote) => {
+                tracing::info!(backup = %manifest.name, to = %bucket, "Backup uploaded");
+                uploaded_to = Some(bucket.to_string());
+                pruned.extend(remote);
+            }
+            Err(error) => {
+                tracing::error!(backup = %manifest.name, "Backup upload failed: {error:#}");
+                return Err(BackupError::UploadFailed {
+                    name: manifest.name,
+                    error,
+                });
+            }
+        }
+    }
+    pruned.sort();
+    pruned.dedup();
+    Ok(Backup {
+        manifest,
+        dir,
+        uploaded_to,
+        pruned,
+    })
+}
+
+/// Copy every store into a new backup directory under `root`.
+fn snapshot(state: &SharedState, root: &Path) -> anyhow::Result<(Manifest, PathBuf)> {
+    let config = &state.config;
+    let db_path = config.db_path.as_deref().context("no --db-path")?;
+    let db = state.db.as_ref().context("no database")?;
+    let now = chrono::Utc::now();
+    let name = now.format(NAME_FORMAT).to_string();
+    let partial = root.join(format!("{PARTIAL_PREFIX}{name}"));
+    std::fs::create_dir_all(&partial)?;
+
+    let mut sources = vec![("main", db_path.to_string())];
+    if state.policy_engine.is_some()
+        && let Some(path) = config.policy_db_path()
+    {
+        sources.push(("policy", path));
+    }
+    let readers = sources
+        .iter()
+        .map(|(_, path)| open_reader(path))
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    {
+        // Main database first, then the policy store: policy code never
+        // takes the main lock, so this order can't deadlock. Holding both
+        // only while the readers pin their snapshots keeps either store
+        // from moving on in between.
+        let _db = db.lock();
+        let _policy = state.policy_engine.as_ref().map(|e| e.store().lock());
+        for reader in &readers {
+            pin_snapshot(reader)?;
+        }
+    }
+    let mut stores = Vec::new();
+    for ((store, path), reader) in sources.iter().zip(&readers) {
+        let name = file_name(path);
+        copy_snapshot(reader, &partial.join(&name))?;
+        stores.push((*store, name));
+    }
+    drop(readers);
+
+    let mut files = Vec::new();
+    for (store, file) in stores {
+        let path = partial.join(&file);
+        let (bytes, sha256) = hash_and_sync(&path)?;
+        files.push(BackupFile {
+            store: store.to_string(),
+            name: file,
+            bytes,
+            sha256,
+        });
+    }
+    let manifest = Manifest {
+        format: FORMAT.to_string(),
+        version: VERSION,
+        name: name.clone(),
+        server_name: config.server_name.clone(),
+        created_at: now.to_rfc3339(),
+        files,
+    };
+    let manifest_path = partial.join(MANIFEST);
+    std::fs::write(&manifest_path, serde_json::to_vec_pretty(&manifest)?)?;
+    hash_and_sync(&manifest_path)?;
+
+    let dir = root.join(&name);
+    std::fs::rename(&partial, &dir)?;
+    // Make the rename itself durable.
+    File::open(root).and_then(|d| d.sync_all())?;
+    Ok((manifest, dir))
+}
+
+/// A read-only connection of the backup's own to the store at `path`.
+fn open_reader(path: &str) -> rusqlite::Result<Connection> {
+    Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+}
+
+/// Start a read transaction on `reader`, fixing what it sees until it
+/// is dropped.
+fn pin_snapshot(reader: &Connection) -> rusqlite::Result<()> {
+    reader.execute_batch("BEGIN")?;
+    reader.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(()))
+}
+
+/// Pages copied per backup step.
+const PAGES_PER_STEP: i32 = 256;
+
+/// Copy the snapshot `reader` has pinned to a new database at `dest`.
+fn copy_snapshot(reader: &Connection, dest: &Path) -> rusqlite::Result<()> {
+    let mut out = Connection::open(dest)?;
+    let backup = SqliteBackup::new(reader, &mut out)?;
+    while backup.step(PAGES_PER_STEP)? != StepResult::Done {}
+    Ok(())
+}
+
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.to_string())
+}
+
+/// Size and SHA-256 of the file at `path`, after syncing it to disk.
+fn hash_and_sync(path: &Path) -> io::Result<(u64, String)> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let bytes = io::copy(&mut file, &mut hasher)?;
+    file.sync_all()?;
+    Ok((bytes, hex::encode(hasher.finalize())))
+}
+
+/// Whether `name` is a backup directory name.
+fn is_backup_name(name: &str) -> bool {
+    chrono::NaiveDateTime::parse_from_str(name, NAME_FORMAT).is_ok()
+}
+
+/// The backups to drop to keep the newest `keep` of `names` (0 keeps
+/// everything).
+fn to_prune(mut names: Vec<String>, keep: usize) -> Vec<String> {
+    names.retain(|n| is_backup_name(n));
+    names.sort();
+    if keep == 0 || names.len() <= keep {
+        return Vec::new();
+    }
+    names.truncate(names.len() - keep);
+    names
+}
+
+/// Remove local backups beyond the newest `keep`, and any `.partial-`
+/// directory an interrupted backup left behind.
+fn prune_local(root: &Path, keep: usize) -> io::Result<Vec<String>> {
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(root)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with(PARTIAL_PREFIX) {
+            std::fs::remove_dir_all(entry.path())?;
+        } else {
+            names.push(name);
+        }
+    }
+    let pruned = to_prune(names, keep);
+    for name in &pruned {
+        std::fs::remove_dir_all(root.join(name))?;
+        tracing::info!(backup = %name, "Pruned old backup");
+    }
+    Ok(pruned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prunes_the_oldest_backups_only() {
+        let names = [
+            "20261017T071335.123Z",
+            "notes",
+            "20261016T000000.000Z",
+            "20261018T120000.500Z",
+        ]
+        .map(String::from)
+        .to_vec();
+        assert_eq!(to_prune(names.clone(), 2), vec!["20261016T000000.000Z"]);
+        assert!(to_prune(names.clone(), 3).is_empty());
+        assert!(to_prune(names, 0).is_empty());
+    }
+
+    #[test]
+    fn snapshot_copies_a_live_wal_database() {
+        let dir = std::env::temp_dir().join(format!("freeq-backup-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let conn = Connection::open(dir.join("live.db")).unwrap();
+        conn.execute_batch(
+            "PRAGMA journal_mode=WAL;
+             CREATE TABLE t (v TEXT);
+             INSERT INTO t VALUES ('kept');",
+        )
+        .unwrap();
+        let reader = open_reader(dir.join("live.db").to_str().unwrap()).unwrap();
+        pin_snapshot(&reader).unwrap();
+        // A pinned reader doesn't block the writer, nor see what it wrote
+        conn.execute("INSERT INTO t VALUES ('later')", []).unwrap();
+        let dest = dir.join("copy.db");
+        copy_snapshot(&reader, &dest).unwrap();
+        let copy = Connection::open(&dest).unwrap();
+        let v: Vec<String> = copy
+            .prepare("SELECT v FROM t")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(v, ["kept"]);
+        let (bytes, sha256) = hash_and_sync(&dest).unwrap();
+        assert_eq!(bytes, std::fs::metadata(&dest).unwrap().len());
+        assert_eq!(sha256.len(), 64);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
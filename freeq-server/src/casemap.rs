@@ -0,0 +1,243 @@
+//! Nick and channel-name case mapping.
+//!
+//! Every case-insensitive comparison of a nick, channel name or ban mask
+//! goes through [`fold`], so the nick table, channel table, bans and
+//! persisted identities all agree on what "the same name" means. The
+//! mapping is advertised to clients as `CASEMAPPING=` in `RPL_ISUPPORT`.
+//!
+//! - `ascii`: only `A-Z` fold to `a-z`.
+//! - `rfc1459`: `ascii`, plus `[]\~` fold to `{}|^` (RFC 1459 §2.2).
+//! - `utf8` (default): drop default-ignorable code points (zero-width
+//!   space/joiners, soft hyphen, BOM, ...), map fullwidth forms
+//!   `U+FF01..U+FF5E` to their ASCII equivalents, then apply Unicode
+//!   simple lowercasing. This is the old `to_lowercase()` behaviour,
+//!   minus the invisible-character and fullwidth tricks.
+//!
+//! The mapping is process-wide and set once at startup from
+//! `--casemapping`. Federated peers must use the same mapping, or channel
+//! and nick keys will disagree across the link.
+//!
+//! [`skeleton`] is a separate, lossier projection used only to detect
+//! look-alike nicks (`chadfowIer.com` vs `chadfowler.com`, Cyrillic `а`
+//! vs Latin `a`). It never decides identity, only whether to refuse a
+//! nick that would impersonate one bound to another DID.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum CaseMapping {
+    Ascii,
+    Rfc1459,
+    #[default]
+    Utf8,
+}
+
+impl CaseMapping {
+    /// Token advertised in `CASEMAPPING=`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CaseMapping::Ascii => "ascii",
+            CaseMapping::Rfc1459 => "rfc1459",
+            CaseMapping::Utf8 => "utf8",
+        }
+    }
+
+    fn from_u8(v: u8) -> Self {
+        match v {
+            0 => CaseMapping::Ascii,
+            1 => CaseMapping::Rfc1459,
+            _ => CaseMapping::Utf8,
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            CaseMapping::Ascii => 0,
+            CaseMapping::Rfc1459 => 1,
+            CaseMapping::Utf8 => 2,
+        }
+    }
+
+    /// Fold `s` under this mapping.
+    pub fn fold(self, s: &str) -> String {
+        match self {
+            CaseMapping::Ascii => s.to_ascii_lowercase(),
+            CaseMapping::Rfc1459 => s
+                .chars()
+                .map(|c| match c {
+                    '[' => '{',
+                    ']' => '}',
+                    '\\' => '|',
+                    '~' => '^',
+                    c => c.to_ascii_lowercase(),
+                })
+                .collect(),
+            CaseMapping::Utf8 => s
+                .chars()
+                .filter(|c| !is_default_ignorable(*c))
+                .map(fullwidth_to_ascii)
+                .flat_map(char::to_lowercase)
+                .collect(),
+        }
+    }
+}
+
+static ACTIVE: AtomicU8 = AtomicU8::new(2);
+
+/// Set the process-wide mapping. Called once from server startup.
+pub fn set(mapping: CaseMapping) {
+    ACTIVE.store(mapping.to_u8(), Ordering::Relaxed);
+}
+
+/// The process-wide mapping.
+pub fn active() -> CaseMapping {
+    CaseMapping::from_u8(ACTIVE.load(Ordering::Relaxed))
+}
+
+/// Fold a nick, channel name or mask under the active mapping.
+pub fn fold(s: &str) -> String {
+    active().fold(s)
+}
+
+/// Case-insensitive equality under the active mapping.
+pub fn eq(a: &str, b: &str) -> bool {
+    a == b || fold(a) == fold(b)
+}
+
+/// Confusable skeleton: look-alike characters collapse to one ASCII
+/// representative, then the result is folded. Two nicks with the same
+/// skeleton are visually indistinguishable in most IRC client fonts.
+/// Owned nicks are stored folded, so "Ian" can only be compared as
+/// "ian": every i, I, l and 1 shares the skeleton letter `l`.
+pub fn skeleton(s: &str) -> String {
+    let mapped: String = s
+        .chars()
+        .filter(|c| !is_default_ignorable(*c))
+        .map(fullwidth_to_ascii)
+        .map(confusable)
+        .flat_map(char::to_lowercase)
+        .collect();
+    mapped.replace("rn", "m").replace("vv", "w")
+}
+
+/// Code points with no visible rendering that clients silently drop.
+fn is_default_ignorable(c: char) -> bool {
+    matches!(
+        c,
+        '\u{00AD}'
+            | '\u{034F}'
+            | '\u{061C}'
+            | '\u{115F}'
+            | '\u{1160}'
+            | '\u{17B4}'
+            | '\u{17B5}'
+            | '\u{180B}'..='\u{180F}'
+            | '\u{200B}'..='\u{200F}'
+            | '\u{202A}'..='\u{202E}'
+            | '\u{2060}'..='\u{206F}'
+            | '\u{3164}'
+            | '\u{FE00}'..='\u{FE0F}'
+            | '\u{FEFF}'
+            | '\u{FFA0}'
+    )
+}
+
+fn fullwidth_to_ascii(c: char) -> char {
+    match c {
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        c => c,
+    }
+}
+
+/// Common single-character confusables for Latin text: Cyrillic and Greek
+/// homoglyphs, and the digit/letter pairs IRC fonts render alike.
+fn confusable(c: char) -> char {
+    match c {
+        // Cyrillic
+        'а' | 'А' => 'a',
+        'В' | 'в' => 'b',
+        'с' | 'С' => 'c',
+        'ԁ' => 'd',
+        'е' | 'Е' | 'ё' => 'e',
+        'Н' | 'һ' => 'h',
+        'і' | 'І' | 'ӏ' => 'l',
+        'ј' | 'Ј' => 'j',
+        'К' | 'к' => 'k',
+        'М' | 'м' => 'm',
+        'о' | 'О' => 'o',
+        'р' | 'Р' => 'p',
+        'ԛ' => 'q',
+        'ѕ' | 'Ѕ' => 's',
+        'Т' | 'т' => 't',
+        'у' | 'У' => 'y',
+        'ԝ' => 'w',
+        'х' | 'Х' => 'x',
+        // Greek
+        'α' | 'Α' => 'a',
+        'Β' | 'β' => 'b',
+        'Ε' => 'e',
+        'Η' => 'h',
+        'ι' | 'Ι' => 'l',
+        'Κ' | 'κ' => 'k',
+        'Μ' => 'm',
+        'Ν' | 'ν' => 'v',
+        'ο' | 'Ο' | 'σ' => 'o',
+        'Ρ' | 'ρ' => 'p',
+        'Τ' | 'τ' => 't',
+        'υ' | 'Υ' => 'y',
+        'Χ' | 'χ' => 'x',
+        'Ζ' => 'z',
+        // Latin look-alikes
+        'i' | 'I' | 'ı' | '1' | '|' | 'ǀ' | 'ℓ' => 'l',
+        '0' => 'o',
+        c => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_only_folds_ascii() {
+        assert_eq!(CaseMapping::Ascii.fold("NiCk[]"), "nick[]");
+        assert_eq!(CaseMapping::Ascii.fold("ÉCOLE"), "École");
+    }
+
+    #[test]
+    fn rfc1459_folds_brackets() {
+        assert_eq!(CaseMapping::Rfc1459.fold("Nick[A]\\~"), "nick{a}|^");
+        assert_eq!(
+            CaseMapping::Rfc1459.fold("nick{a}|^"),
+            CaseMapping::Rfc1459.fold("NICK[A]\\~")
+        );
+    }
+
+    #[test]
+    fn utf8_strips_invisibles_and_fullwidth() {
+        let m = CaseMapping::Utf8;
+        assert_eq!(m.fold("ÉCOLE"), "école");
+        assert_eq!(m.fold("al\u{200B}ice"), "alice");
+        assert_eq!(m.fold("ＡＬＩＣＥ"), "alice");
+        assert_eq!(m.fold("\u{FEFF}#Chan"), "#chan");
+    }
+
+    #[test]
+    fn skeleton_catches_homoglyphs() {
+        let real = skeleton("chadfowler.com");
+        assert_eq!(skeleton("chadfowIer.com"), real);
+        assert_eq!(skeleton("chadf0wler.com"), real);
+        assert_eq!(skeleton("сhаdfowlеr.com"), real); // Cyrillic с, а, е
+        assert_eq!(skeleton("chadfow\u{200D}ler.com"), real);
+        assert_ne!(skeleton("chadfowlers.com"), real);
+        assert_eq!(skeleton("modern"), skeleton("rnodern"));
+    }
+
+    #[test]
+    fn skeleton_matches_folded_capital_i() {
+        // "Ian" is owned as "ian"; "lan" must still collide with it.
+        assert_eq!(skeleton("lan"), skeleton(&CaseMapping::Utf8.fold("Ian")));
+        assert_eq!(skeleton("lan"), skeleton("Ian"));
+        assert_eq!(skeleton("1an"), skeleton("ian"));
+    }
+}
@@ -0,0 +1,154 @@
+//! Server auto-join channels (`--auto-join`) and the AUTOJOIN command.
+//!
+//! AUTOJOIN        — Show the server's auto-join channels and your setting
+//! AUTOJOIN OFF    — Stop joining them when you connect
+//! AUTOJOIN ON     — Join them again (the default)
+//!
+//! When registration completes, the user joins each configured channel
+//! they aren't already in. A channel they couldn't JOIN by hand (+k, +b,
+//! +i without an invite, or a policy they haven't accepted) is skipped
+//! with a NOTICE instead of an error numeric. The opt-out is stored in
+//! the metadata store under `private/autojoin`, so for an authenticated
+//! user it belongs to the DID and persists; a guest's lasts for the
+//! session.
+
+use super::metadata;
+use crate::irc::Message;
+use crate::server::SharedState;
+use std::sync::Arc;
+
+/// Metadata key set to `off` when the user has opted out.
+const AUTOJOIN_KEY: &str = "private/autojoin";
+
+/// Whether whoever owns `session_id` has opted out of auto-join.
+fn opted_out(state: &SharedState, session_id: &str) -> bool {
+    metadata::own_key(state, session_id, AUTOJOIN_KEY).is_some_and(|v| v == "off")
+}
+
+/// Why the connection can't join `channel` (folded) right now, checked the
+/// way JOIN checks it. `None` if nothing stands in the way.
+fn join_blocker(
+    conn: &super::Connection,
+    state: &SharedState,
+    channel: &str,
+) -> Option<&'static str> {
+    let nick = conn.nick_or_star();
+    let did = conn.authenticated_did.as_deref();
+    let mut is_did_authority = false;
+    if let Some(ch) = state.channels.get(channel) {
+        let hostmask = conn.hostmask();
+        is_did_authority =
+            did.is_some_and(|d| ch.founder_did.as_deref() == Some(d) || ch.did_ops.contains(d));
+        if !is_did_authority {
+            if ch.key.is_some() {
+                return Some("it needs a key (+k)");
+            }
+            if ch.is_banned(&hostmask, did) {
+                return Some("you are banned (+b)");
+            }
+            let invited = ch.invites.contains(&format!("nick:{nick}"))
+                || did.is_some_and(|d| ch.invites.contains(d));
+            if ch.invite_only && !invited && !ch.is_invite_excepted(&hostmask, did) {
+                return Some("it is invite-only (+i)");
+            }
+        }
+    }
+
+    if let Some(ref engine) = state.policy_engine
+        && let Ok(Some(_policy)) = engine.get_policy(channel)
+        && !is_did_authority
+    {
+        let Some(did) = did else {
+            return Some("it requires authentication");
+        };
+        if matches!(engine.check_membership(channel, did), Ok(None)) {
+            return Some("it requires policy acceptance (POLICY <channel> ACCEPT)");
+        }
+    }
+    None
+}
+
+/// Join the server's auto-join channels, unless the user opted out.
+/// Called once registration completes, after saved channels are rejoined.
+pub(super) fn auto_join(
+    conn: &super::Connection,
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    send: &impl Fn(&Arc<SharedState>, &str, String),
+) {
+    if state.config.auto_join.is_empty() || opted_out(state, session_id) {
+        return;
+    }
+    let nick = conn.nick_or_star();
+    for name in &state.config.auto_join {
+        let channel = super::helpers::normalize_channel(name);
+        if state
+            .channels
+            .get(&channel)
+            .is_some_and(|ch| ch.members.contains(session_id))
+        {
+            continue;
+        }
+        if let Some(reason) = join_blocker(conn, state, &channel) {
+            tracing::debug!(%session_id, %channel, reason, "Auto-join skipped");
+            let reply = Message::from_server(
+                server_name,
+                "NOTICE",
+                vec![nick, &format!("Auto-join: skipped {name}: {reason}")],
+            );
+            send(state, session_id, format!("{reply}\r\n"));
+            continue;
+        }
+        super::channel::handle_join(conn, &channel, None, state, server_name, session_id, send);
+    }
+}
+
+pub(super) fn handle_autojoin(
+    conn: &super::Connection,
+    msg: &Message,
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    send_fn: &impl Fn(&Arc<SharedState>, &str, String),
+) {
+    let nick = conn.nick_or_star();
+    let notice = |text: &str| {
+        let reply = Message::from_server(server_name, "NOTICE", vec![nick, text]);
+        send_fn(state, session_id, format!("{reply}\r\n"));
+    };
+    let channels = state.config.auto_join.join(", ");
+
+    let off = match msg.params.first().map(|s| s.to_uppercase()).as_deref() {
+        None => {
+            if channels.is_empty() {
+                notice("AUTOJOIN: this server has no auto-join channels");
+            } else if opted_out(state, session_id) {
+                notice(&format!("AUTOJOIN: off (server channels: {channels})"));
+            } else {
+                notice(&format!(
+                    "AUTOJOIN: on — you join {channels} when you connect"
+                ));
+            }
+            return;
+        }
+        Some("ON") => false,
+        Some("OFF") => true,
+        Some(_) => {
+            notice("Usage: AUTOJOIN [ON|OFF]");
+            return;
+        }
+    };
+
+    metadata::set_own_key(state, conn, session_id, AUTOJOIN_KEY, off.then_some("off"));
+    tracing::info!(session = %session_id, off, "AUTOJOIN updated");
+    if off {
+        notice("AUTOJOIN: off — you won't be joined to the server's channels on connect");
+    } else if channels.is_empty() {
+        notice("AUTOJOIN: on (this server has no auto-join channels)");
+    } else {
+        notice(&format!(
+            "AUTOJOIN: on — you join {channels} when you connect"
+        ));
+    }
+}
@@ -0,0 +1,58 @@
+//! Oper command for backups (see [`crate::backup`]).
+//!
+//! BACKUP  — Snapshot every database into --backup-dir, upload it if
+//!           --backup-s3-url is set, and prune beyond --backup-keep
+//!
+//! The result comes back as a NOTICE; failures are
+//! `FAIL BACKUP <code> :<reason>`.
+
+use crate::backup;
+use crate::irc::Message;
+use crate::server::SharedState;
+use std::sync::Arc;
+
+pub(super) async fn handle_backup(
+    conn: &super::Connection,
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    send: &impl Fn(&Arc<SharedState>, &str, String),
+) {
+    let nick = conn.nick_or_star();
+    if !conn.is_oper {
+        let reply = Message::from_server(
+            server_name,
+            "481",
+            vec![nick, "Permission Denied - You're not an IRC operator"],
+        );
+        send(state, session_id, format!("{reply}\r\n"));
+        return;
+    }
+
+    match backup::run(state).await {
+        Ok(done) => {
+            tracing::info!(oper = %nick, backup = %done.manifest.name, "BACKUP");
+            let bytes: u64 = done.manifest.files.iter().map(|f| f.bytes).sum();
+            let mut text = format!(
+                "Backup {} written to {} ({} files, {bytes} bytes)",
+                done.manifest.name,
+                done.dir.display(),
+                done.manifest.files.len()
+            );
+            if let Some(to) = &done.uploaded_to {
+                text.push_str(&format!("; uploaded to {to}"));
+            }
+            if !done.pruned.is_empty() {
+                text.push_str(&format!("; pruned {}", done.pruned.join(", ")));
+            }
+            let reply = Message::from_server(server_name, "NOTICE", vec![nick, &text]);
+            send(state, session_id, format!("{reply}\r\n"));
+        }
+        Err(e) => {
+            let reason = e.to_string();
+            let reply =
+                Message::from_server(server_name, "FAIL", vec!["BACKUP", e.code(), &reason]);
+            send(state, session_id, format!("{reply}\r\n"));
+        }
+    }
+}
@@ -6,7 +6,9 @@ use super::helpers::broadcast_account_notify;
 use super::registration::try_complete_registration;
 use crate::irc::{self, Message};
 use crate::sasl;
-use crate::server::SharedState;
+use crate::server::{ChallengeRejection, SharedState};
+use crate::session::Cap;
+use freeq_proto::caps;
 use std::sync::Arc;
 
 pub(super) fn handle_cap(
@@ -22,22 +24,31 @@ pub(super) fn handle_cap(
         Some("LS") => {
             conn.cap_negotiating = true;
             // Build capability list, including iroh endpoint ID if available
-            let mut caps = String::from(
-                "sasl message-tags multi-prefix echo-message server-time batch draft/chathistory account-notify account-tag extended-join away-notify",
-            );
+            let mut advertised = caps::ADVERTISED.join(" ");
             // Advertise draft/multiline with our policy limits (spec requires
             // max-bytes; max-lines is recommended). See `draft_multiline` module
             // for the actual enforcement.
-            caps.push_str(&format!(
-                " draft/multiline=max-bytes={},max-lines={}",
+            advertised.push_str(&format!(
+                " {}=max-bytes={},max-lines={}",
+                caps::MULTILINE,
                 crate::connection::draft_multiline::MAX_BYTES,
                 crate::connection::draft_multiline::MAX_LINES,
             ));
+            advertised.push(' ');
+            advertised.push_str(&crate::connection::metadata::cap_value());
+            advertised.push_str(&format!(
+                " {}={}",
+                caps::LANGUAGE,
+                state.locales.codes().join(",")
+            ));
             if let Some(ref iroh_id) = *state.server_iroh_id.lock() {
-                caps.push_str(&format!(" iroh={iroh_id}"));
+                advertised.push_str(&format!(" {}={iroh_id}", caps::IROH));
             }
-            let reply =
-                Message::from_server(server_name, "CAP", vec![conn.nick_or_star(), "LS", &caps]);
+            let reply = Message::from_server(
+                server_name,
+                "CAP",
+                vec![conn.nick_or_star(), "LS", &advertised],
+            );
             send(state, session_id, format!("{reply}\r\n"));
         }
         Some("REQ") => {
@@ -47,80 +58,37 @@ pub(super) fn handle_cap(
                 let mut all_ok = true;
 
                 for cap in &requested {
-                    match cap.to_ascii_lowercase().as_str() {
-                        "sasl" => {
+                    let cap = cap.to_ascii_lowercase();
+                    // Per spec, `draft/multiline` depends on `batch`. We
+                    // don't enforce negotiation order: a client that gets
+                    // multiline without batch has it ACKed, but it won't
+                    // take effect until batch is negotiated too.
+                    if let Some(shared) = Cap::from_name(&cap) {
+                        state.sessions.enable_cap(session_id, shared);
+                        acked.push(shared.name());
+                        continue;
+                    }
+                    match cap.as_str() {
+                        caps::SASL => {
                             conn.cap_sasl_requested = true;
-                            acked.push("sasl");
-                        }
-                        "message-tags" => {
-                            conn.cap_message_tags = true;
-                            state.cap_message_tags.lock().insert(session_id.to_string());
-                            acked.push("message-tags");
-                        }
-                        "multi-prefix" => {
-                            conn.cap_multi_prefix = true;
-                            state.cap_multi_prefix.lock().insert(session_id.to_string());
-                            acked.push("multi-prefix");
-                        }
-                        "echo-message" => {
-                            conn.cap_echo_message = true;
-                            state.cap_echo_message.lock().insert(session_id.to_string());
-                            acked.push("echo-message");
+                            acked.push(caps::SASL);
                         }
-                        "server-time" => {
-                            conn.cap_server_time = true;
-                            state.cap_server_time.lock().insert(session_id.to_string());
-                            acked.push("server-time");
-                        }
-                        "batch" => {
-                            conn.cap_batch = true;
-                            state.cap_batch.lock().insert(session_id.to_string());
-                            acked.push("batch");
-                        }
-                        "draft/multiline" => {
-                            // Per spec, `draft/multiline` depends on `batch`.
-                            // The spec doesn't strictly require us to enforce
-                            // negotiation order, but we soft-warn: if the client
-                            // hasn't also requested batch in the same REQ
-                            // (or earlier), they can ACK multiline but it
-                            // won't work until batch is also negotiated. We
-                            // still ACK it so the client knows we support it.
-                            conn.cap_draft_multiline = true;
-                            state
-                                .cap_draft_multiline
-                                .lock()
-                                .insert(session_id.to_string());
-                            acked.push("draft/multiline");
-                        }
-                        "draft/chathistory" => {
+                        caps::CHATHISTORY => {
                             conn.cap_chathistory = true;
-                            acked.push("draft/chathistory");
+                            acked.push(caps::CHATHISTORY);
                         }
-                        "account-notify" => {
-                            conn.cap_account_notify = true;
-                            state
-                                .cap_account_notify
-                                .lock()
-                                .insert(session_id.to_string());
-                            acked.push("account-notify");
+                        caps::METADATA => {
+                            acked.push(caps::METADATA);
                         }
-                        "account-tag" => {
-                            conn.cap_account_tag = true;
-                            state.cap_account_tag.lock().insert(session_id.to_string());
-                            acked.push("account-tag");
+                        caps::PRIORITY => {
+                            acked.push(caps::PRIORITY);
                         }
-                        "extended-join" => {
-                            conn.cap_extended_join = true;
-                            state
-                                .cap_extended_join
-                                .lock()
-                                .insert(session_id.to_string());
-                            acked.push("extended-join");
+                        caps::LANGUAGE => {
+                            acked.push(caps::LANGUAGE);
                         }
-                        "away-notify" => {
-                            conn.cap_away_notify = true;
-                            state.cap_away_notify.lock().insert(session_id.to_string());
-                            acked.push("away-notify");
+                        caps::WHOIS_EXTENDED => {
+                            conn.cap_whois_extended = true;
+                            acked.push(caps::WHOIS_EXTENDED);
                         }
                         _ => {
                             all_ok = false;
@@ -172,6 +140,7 @@ pub(super) async fn handle_authenticate(
     if param == "*" {
         // SASL abort — client is cancelling the authentication attempt
         conn.sasl_in_progress = false;
+        conn.sasl_external = false;
         let fail = Message::from_server(
             server_name,
             irc::ERR_SASLFAIL,
@@ -181,36 +150,78 @@ pub(super) async fn handle_authenticate(
         return;
     }
 
+    let starts_mechanism =
+        param.eq_ignore_ascii_case("ATPROTO-CHALLENGE") || param.eq_ignore_ascii_case("EXTERNAL");
+    if starts_mechanism && conn.auth_source().is_some_and(|s| state.auth_blocked(&s)) {
+        conn.sasl_in_progress = false;
+        conn.sasl_external = false;
+        let fail = Message::from_server(
+            server_name,
+            irc::ERR_SASLFAIL,
+            vec![
+                conn.nick_or_star(),
+                "Too many failed authentications; try again later",
+            ],
+        );
+        send(state, session_id, format!("{fail}\r\n"));
+        return;
+    }
+
     if param.eq_ignore_ascii_case("ATPROTO-CHALLENGE") {
         conn.sasl_in_progress = true;
+        conn.sasl_external = false;
         conn.dpop_retries = 0; // Reset DPoP retry counter on new SASL attempt
-        let encoded = state.challenge_store.create(session_id);
+        let encoded = state.issue_sasl_challenge(session_id, conn.channel_binding().as_deref());
         let reply = Message::new("AUTHENTICATE", vec![&encoded]);
         send(state, session_id, format!("{reply}\r\n"));
+    } else if param.eq_ignore_ascii_case("EXTERNAL") {
+        // The QUIC handshake already proved the peer holds its endpoint
+        // key; EXTERNAL logs it in as the DID the endpoint is bound to.
+        let bound = conn
+            .iroh_endpoint_id
+            .as_deref()
+            .and_then(|id| state.iroh_endpoint_did(id));
+        if bound.is_none() {
+            let fail = Message::from_server(
+                server_name,
+                irc::ERR_SASLFAIL,
+                vec![
+                    conn.nick_or_star(),
+                    "SASL EXTERNAL needs an iroh endpoint bound with BIND",
+                ],
+            );
+            send(state, session_id, format!("{fail}\r\n"));
+            return;
+        }
+        conn.sasl_in_progress = true;
+        conn.sasl_external = true;
+        send(state, session_id, "AUTHENTICATE +\r\n".to_string());
+    } else if conn.sasl_in_progress && conn.sasl_external {
+        handle_external_response(conn, param, state, server_name, session_id, send);
     } else if conn.sasl_in_progress {
         if let Some(response) = sasl::decode_response(param) {
             // Check for web-token method first (server-side OAuth pre-verified)
             let web_token_result = if response.method.as_deref() == Some("web-token") {
-                let mut tokens = state.web_auth_tokens.lock();
-                if let Some((did, _handle, created)) = tokens.remove(&response.signature) {
-                    // Single-use: token consumed on first authentication.
-                    // 5-minute TTL limits exposure if a token is leaked.
-                    // Broker issues fresh tokens on each /session call for reconnects.
-                    if created.elapsed() < std::time::Duration::from_secs(300) {
-                        Some(Ok(did.clone()))
-                    } else {
-                        Some(Err("Web auth token expired".to_string()))
-                    }
-                } else {
-                    Some(Err("Invalid web auth token".to_string()))
-                }
+                // Single-use: token consumed on first authentication.
+                // 5-minute TTL limits exposure if a token is leaked, and a
+                // device-bound token only redeems from the bound device.
+                // Broker issues fresh tokens on each /session call for reconnects.
+                Some(state.redeem_web_auth_token(
+                    &response.signature,
+                    conn.iroh_endpoint_id.as_deref(),
+                    response.fingerprint.as_deref(),
+                ))
             } else {
                 None
             };
 
-            let taken = state.challenge_store.take(session_id);
+            let taken = state.take_sasl_challenge(
+                session_id,
+                response.binding.as_deref(),
+                conn.channel_binding().as_deref(),
+            );
             match taken {
-                Some((challenge, challenge_bytes)) => {
+                Ok((challenge, challenge_bytes)) => {
                     let verify_result = if let Some(result) = web_token_result {
                         result
                     } else {
@@ -224,148 +235,7 @@ pub(super) async fn handle_authenticate(
                     };
                     match verify_result {
                         Ok(did) => {
-                            conn.authenticated_did = Some(did.clone());
-                            conn.sasl_in_progress = false;
-                            state
-                                .session_dids
-                                .lock()
-                                .insert(session_id.to_string(), did.clone());
-
-                            // Attach to existing sessions with same DID (multi-device).
-                            // If no existing sessions, this just registers the nick normally.
-                            super::registration::attach_same_did(conn, state, session_id, send);
-
-                            // Bind nick to DID (in-memory + persistent),
-                            // ownership-preserving. A nick stashed during the
-                            // CAP/SASL negotiation window may be owned by a
-                            // different DID; bind_identity refuses that case
-                            // so the in-memory maps + DB stay consistent and
-                            // the existing registration force-rename handles
-                            // the session.
-                            if let Some(ref nick) = conn.nick {
-                                match state.bind_identity(&did, nick) {
-                                    crate::server::BindOutcome::Bound => {
-                                        let nick_l = nick.to_lowercase();
-                                        let did_c = did.clone();
-                                        let state_c = Arc::clone(state);
-                                        tokio::spawn(async move {
-                                            state_c.crdt_set_nick_owner(&nick_l, &did_c).await;
-                                        });
-                                    }
-                                    crate::server::BindOutcome::ConflictOwnedByOther {
-                                        owner_did,
-                                    } => {
-                                        tracing::warn!(
-                                            %session_id, %did, nick = %nick,
-                                            %owner_did,
-                                            "SASL bind refused: nick owned by another DID (will be force-renamed at registration)"
-                                        );
-                                    }
-                                }
-                            }
-
-                            // Resolve handle from DID document for WHOIS display,
-                            // then run plugins with the resolved handle.
-                            {
-                                let did_clone = did.clone();
-                                let state_clone = Arc::clone(state);
-                                let sid = session_id.to_string();
-                                let nick_for_plugin = conn.nick.clone().unwrap_or_default();
-                                tokio::spawn(async move {
-                                    let mut resolved_handle: Option<String> = None;
-                                    if let Ok(doc) =
-                                        state_clone.did_resolver.resolve(&did_clone).await
-                                    {
-                                        for aka in &doc.also_known_as {
-                                            if let Some(handle) = aka.strip_prefix("at://") {
-                                                resolved_handle = Some(handle.to_string());
-                                                state_clone
-                                                    .session_handles
-                                                    .lock()
-                                                    .insert(sid.clone(), handle.to_string());
-                                                break;
-                                            }
-                                        }
-                                    }
-
-                                    // Run plugins after handle resolution
-                                    let auth_event = crate::plugin::AuthEvent {
-                                        did: did_clone.clone(),
-                                        handle: resolved_handle,
-                                        nick: nick_for_plugin,
-                                        session_id: sid.clone(),
-                                    };
-                                    let result = state_clone.plugin_manager.on_auth(&auth_event);
-                                    if let Some(override_did) = result.override_did {
-                                        state_clone
-                                            .session_dids
-                                            .lock()
-                                            .insert(sid.clone(), override_did);
-                                    }
-                                    if let Some(override_handle) = result.override_handle {
-                                        state_clone
-                                            .session_handles
-                                            .lock()
-                                            .insert(sid.clone(), override_handle);
-                                    }
-                                });
-                            }
-
-                            let nick = conn.nick_or_star().to_string();
-
-                            // Auto-OPER for configured DIDs (before using nick ref)
-                            if state.config.oper_dids.iter().any(|d| d == &did) {
-                                conn.is_oper = true;
-                                state.server_opers.lock().insert(session_id.to_string());
-                                let oper_notice =
-                                    Message::from_server(server_name, "MODE", vec![&nick, "+o"]);
-                                send(state, session_id, format!("{oper_notice}\r\n"));
-                                tracing::info!(%did, nick = %nick, "Auto-OPER granted via oper_dids config");
-                            }
-
-                            let hostmask = conn.hostmask();
-                            let logged_in = Message::from_server(
-                                server_name,
-                                irc::RPL_LOGGEDIN,
-                                vec![
-                                    &nick,
-                                    &hostmask,
-                                    &did,
-                                    &format!("You are now logged in as {did}"),
-                                ],
-                            );
-                            send(state, session_id, format!("{logged_in}\r\n"));
-
-                            let success = Message::from_server(
-                                server_name,
-                                irc::RPL_SASLSUCCESS,
-                                vec![&nick, "SASL authentication successful"],
-                            );
-                            send(state, session_id, format!("{success}\r\n"));
-                            tracing::info!(%session_id, %did, nick = %nick, "SASL authentication successful");
-                            crate::server::Metrics::bump(&state.metrics.sasl_success_total);
-
-                            // Surface the API bearer for this connection so the
-                            // bot can hit /agent/tools/* with the same identity
-                            // it just authenticated to IRC with. Without this,
-                            // bots have no way to discover their own session_id
-                            // and every diagnostic call comes in as anonymous.
-                            //
-                            // Format: `NOTICE * :API-BEARER <session_id>` — chosen
-                            // so it's a single greppable line that doesn't collide
-                            // with any standard IRC numeric or NOTICE format.
-                            // Clients that don't need the bearer can ignore it
-                            // (their pre-existing notice handling will display
-                            // it as a server message; harmless).
-                            let bearer_notice = Message::from_server(
-                                server_name,
-                                "NOTICE",
-                                vec!["*", &format!("API-BEARER {session_id}")],
-                            );
-                            send(state, session_id, format!("{bearer_notice}\r\n"));
-
-                            // Broadcast account-notify to shared channels
-                            broadcast_account_notify(state, session_id, &nick, &did);
+                            sasl_succeeded(conn, state, server_name, session_id, send, did);
                         }
                         Err(reason) if reason.starts_with("DPOP_NONCE:") => {
                             conn.dpop_retries += 1;
@@ -374,6 +244,7 @@ pub(super) async fn handle_authenticate(
                                 conn.sasl_in_progress = false;
                                 conn.sasl_failures += 1;
                                 crate::server::Metrics::bump(&state.metrics.sasl_failure_total);
+                                record_source_failure(conn, state, false);
                                 let fail = Message::from_server(
                                     server_name,
                                     irc::ERR_SASLFAIL,
@@ -390,7 +261,7 @@ pub(super) async fn handle_authenticate(
                                         "ERROR :Too many SASL failures\r\n".to_string(),
                                     );
                                     // Drop the send channel to force-close the connection.
-                                    state.connections.lock().remove(session_id);
+                                    state.connections.remove(session_id);
                                 }
                             } else {
                                 // DPoP nonce rotation: PDS requires a fresh nonce.
@@ -408,7 +279,10 @@ pub(super) async fn handle_authenticate(
                                 );
 
                                 // Issue a new challenge for retry
-                                let encoded = state.challenge_store.create(session_id);
+                                let encoded = state.issue_sasl_challenge(
+                                    session_id,
+                                    conn.channel_binding().as_deref(),
+                                );
                                 send(state, session_id, format!("AUTHENTICATE {encoded}\r\n"));
                             }
                         }
@@ -417,6 +291,7 @@ pub(super) async fn handle_authenticate(
                             conn.sasl_in_progress = false;
                             conn.sasl_failures += 1;
                             crate::server::Metrics::bump(&state.metrics.sasl_failure_total);
+                            record_source_failure(conn, state, false);
                             let fail = Message::from_server(
                                 server_name,
                                 irc::ERR_SASLFAIL,
@@ -430,12 +305,12 @@ pub(super) async fn handle_authenticate(
                                     "ERROR :Too many SASL failures\r\n".to_string(),
                                 );
                                 // Drop the send channel to force-close the connection.
-                                state.connections.lock().remove(session_id);
+                                state.connections.remove(session_id);
                             }
                         }
                     }
                 }
-                None => {
+                Err(ChallengeRejection::Missing) => {
                     conn.sasl_in_progress = false;
                     let fail = Message::from_server(
                         server_name,
@@ -447,6 +322,32 @@ pub(super) async fn handle_authenticate(
                     );
                     send(state, session_id, format!("{fail}\r\n"));
                 }
+                Err(ChallengeRejection::BindingMismatch) => {
+                    // Never a client bug — a response signed on another
+                    // connection counts as a failure and toward the
+                    // per-source counters.
+                    conn.sasl_in_progress = false;
+                    conn.sasl_failures += 1;
+                    crate::server::Metrics::bump(&state.metrics.sasl_failure_total);
+                    record_source_failure(conn, state, true);
+                    let fail = Message::from_server(
+                        server_name,
+                        irc::ERR_SASLFAIL,
+                        vec![
+                            conn.nick_or_star(),
+                            "SASL authentication failed (challenge rejected)",
+                        ],
+                    );
+                    send(state, session_id, format!("{fail}\r\n"));
+                    if conn.sasl_failures >= 3 {
+                        send(
+                            state,
+                            session_id,
+                            "ERROR :Too many SASL failures\r\n".to_string(),
+                        );
+                        state.connections.remove(session_id);
+                    }
+                }
             }
         } else {
             conn.sasl_in_progress = false;
@@ -469,3 +370,234 @@ pub(super) async fn handle_authenticate(
         send(state, session_id, format!("{fail}\r\n"));
     }
 }
+
+/// Log the connection in as `did` once SASL has verified it: attach to
+/// the DID's other sessions, bind the nick and, when `bind_endpoint` is
+/// set, the iroh endpoint, then send `900`/`903` and the API bearer.
+fn sasl_succeeded(
+    conn: &mut Connection,
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    send: &impl Fn(&Arc<SharedState>, &str, String),
+    did: String,
+) {
+    conn.authenticated_did = Some(did.clone());
+    conn.sasl_in_progress = false;
+    state
+        .session_dids
+        .lock()
+        .insert(session_id.to_string(), did.clone());
+
+    // Attach to existing sessions with same DID (multi-device).
+    // If no existing sessions, this just registers the nick normally.
+    super::registration::attach_same_did(conn, state, session_id, send);
+
+    // Bind nick to DID (in-memory + persistent),
+    // ownership-preserving. A nick stashed during the
+    // CAP/SASL negotiation window may be owned by a
+    // different DID; bind_identity refuses that case
+    // so the in-memory maps + DB stay consistent and
+    // the existing registration force-rename handles
+    // the session. Nor is a reserved nick bound to a
+    // DID that isn't exempt; registration renames that
+    // session too.
+    if let Some(ref nick) = conn.nick
+        && (!crate::reserved::nick_reserved(&state.config, nick)
+            || crate::reserved::exempt(&state.config, Some(&did), conn.is_oper))
+    {
+        match state.bind_identity(&did, nick) {
+            crate::server::BindOutcome::Bound => {
+                let nick_l = crate::casemap::fold(nick);
+                let did_c = did.clone();
+                let state_c = Arc::clone(state);
+                tokio::spawn(async move {
+                    state_c.crdt_set_nick_owner(&nick_l, &did_c).await;
+                });
+            }
+            crate::server::BindOutcome::ConflictOwnedByOther { owner_did } => {
+                tracing::warn!(
+                    %session_id, %did, nick = %nick,
+                    %owner_did,
+                    "SASL bind refused: nick owned by another DID (will be force-renamed at registration)"
+                );
+            }
+        }
+    }
+
+    spawn_auth_hooks(
+        state,
+        session_id,
+        &did,
+        conn.nick.clone().unwrap_or_default(),
+    );
+
+    let nick = conn.nick_or_star().to_string();
+
+    // Auto-OPER for configured DIDs (before using nick ref)
+    if state.config.oper_dids.iter().any(|d| d == &did) {
+        conn.is_oper = true;
+        state.server_opers.lock().insert(session_id.to_string());
+        let oper_notice = Message::from_server(server_name, "MODE", vec![&nick, "+o"]);
+        send(state, session_id, format!("{oper_notice}\r\n"));
+        let token = super::oper_token_notice(state, server_name, &nick, session_id);
+        send(state, session_id, token);
+        tracing::info!(%did, nick = %nick, "Auto-OPER granted via oper_dids config");
+    }
+
+    let hostmask = conn.hostmask();
+    let logged_in = Message::from_server(
+        server_name,
+        irc::RPL_LOGGEDIN,
+        vec![
+            &nick,
+            &hostmask,
+            &did,
+            &format!("You are now logged in as {did}"),
+        ],
+    );
+    send(state, session_id, format!("{logged_in}\r\n"));
+
+    let success = Message::from_server(
+        server_name,
+        irc::RPL_SASLSUCCESS,
+        vec![&nick, "SASL authentication successful"],
+    );
+    send(state, session_id, format!("{success}\r\n"));
+    tracing::info!(%session_id, %did, nick = %nick, "SASL authentication successful");
+    crate::server::Metrics::bump(&state.metrics.sasl_success_total);
+
+    // Surface the API bearer for this connection so the
+    // bot can hit /agent/tools/* with the same identity
+    // it just authenticated to IRC with. Without this,
+    // bots have no way to discover their own session_id
+    // and every diagnostic call comes in as anonymous.
+    //
+    // Format: `NOTICE * :API-BEARER <session_id>` — chosen
+    // so it's a single greppable line that doesn't collide
+    // with any standard IRC numeric or NOTICE format.
+    // Clients that don't need the bearer can ignore it
+    // (their pre-existing notice handling will display
+    // it as a server message; harmless).
+    let bearer_notice = Message::from_server(
+        server_name,
+        "NOTICE",
+        vec!["*", &format!("API-BEARER {session_id}")],
+    );
+    send(state, session_id, format!("{bearer_notice}\r\n"));
+
+    // Broadcast account-notify to shared channels
+    broadcast_account_notify(state, session_id, &nick, &did);
+}
+
+/// The client's answer to `AUTHENTICATE +` under EXTERNAL: `+` for no
+/// authorization identity, or the base64 of the DID it expects to log in
+/// as, which must be the one its endpoint is bound to. The binding is
+/// looked up again in case it was revoked in between.
+fn handle_external_response(
+    conn: &mut Connection,
+    param: &str,
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    send: &impl Fn(&Arc<SharedState>, &str, String),
+) {
+    use base64::Engine;
+
+    conn.sasl_external = false;
+    let bound = conn
+        .iroh_endpoint_id
+        .as_deref()
+        .and_then(|id| state.iroh_endpoint_did(id));
+    let authzid = (param != "+").then(|| {
+        base64::engine::general_purpose::STANDARD
+            .decode(param)
+            .ok()
+            .and_then(|b| String::from_utf8(b).ok())
+    });
+    let authorized = |did: &str| authzid.as_ref().is_none_or(|a| a.as_deref() == Some(did));
+    match bound {
+        Some(did) if authorized(&did) => {
+            tracing::info!(%session_id, %did, "SASL EXTERNAL via bound iroh endpoint");
+            sasl_succeeded(conn, state, server_name, session_id, send, did);
+        }
+        _ => {
+            tracing::warn!(%session_id, ?authzid, "SASL EXTERNAL failed");
+            conn.sasl_in_progress = false;
+            conn.sasl_failures += 1;
+            crate::server::Metrics::bump(&state.metrics.sasl_failure_total);
+            record_source_failure(conn, state, false);
+            let fail = Message::from_server(
+                server_name,
+                irc::ERR_SASLFAIL,
+                vec![conn.nick_or_star(), "SASL authentication failed"],
+            );
+            send(state, session_id, format!("{fail}\r\n"));
+            if conn.sasl_failures >= 3 {
+                send(
+                    state,
+                    session_id,
+                    "ERROR :Too many SASL failures\r\n".to_string(),
+                );
+                state.connections.remove(session_id);
+            }
+        }
+    }
+}
+
+/// Feed a failed SASL attempt into the per-source failure counters.
+fn record_source_failure(conn: &Connection, state: &SharedState, replay: bool) {
+    if let Some(source) = conn.auth_source() {
+        state.record_auth_failure(&source, replay);
+    }
+}
+
+/// Resolve the handle from the DID document for WHOIS display, then run
+/// the plugin `on_auth` hooks with it. Called once a session's DID is
+/// established, by SASL or by `RESUME`.
+pub(super) fn spawn_auth_hooks(
+    state: &Arc<SharedState>,
+    session_id: &str,
+    did: &str,
+    nick_for_plugin: String,
+) {
+    let did_clone = did.to_string();
+    let state_clone = Arc::clone(state);
+    let sid = session_id.to_string();
+    tokio::spawn(async move {
+        let mut resolved_handle: Option<String> = None;
+        if let Ok(doc) = state_clone.did_resolver.resolve(&did_clone).await {
+            for aka in &doc.also_known_as {
+                if let Some(handle) = aka.strip_prefix("at://") {
+                    resolved_handle = Some(handle.to_string());
+                    state_clone
+                        .session_handles
+                        .lock()
+                        .insert(sid.clone(), handle.to_string());
+                    break;
+                }
+            }
+        }
+
+        // Run plugins after handle resolution
+        let auth_event = crate::plugin::AuthEvent {
+            did: did_clone.clone(),
+            handle: resolved_handle,
+            nick: nick_for_plugin,
+            session_id: sid.clone(),
+        };
+        let result = state_clone.plugin_manager.on_auth(&auth_event);
+        if let Some(override_did) = result.override_did {
+            state_clone
+                .session_dids
+                .lock()
+                .insert(sid.clone(), override_did);
+        }
+        if let Some(override_handle) = result.override_handle {
+            state_clone
+                .session_handles
+                .lock()
+                .insert(sid.clone(), override_handle);
+        }
+    });
+}
@@ -0,0 +1,291 @@
+//! IRC CASE command — moderation case files, kept per channel.
+//!
+//! CASE OPEN <channel> <did|nick> <summary>     — Open a case about a user
+//! CASE EVIDENCE <channel> <case> <msgid>...    — Attach messages from the channel's history
+//! CASE ACTION <channel> <case> <text>          — Record an action taken
+//! CASE CLOSE <channel> <case> [<resolution>]   — Close a case
+//! CASE LIST <channel> [ALL]                    — List open (or all) cases
+//! CASE SHOW <channel> <case>                   — Show a case with its evidence and actions
+//!
+//! Cases are only visible to the channel's authority: its ops, founder and
+//! DID-ops, and its policy admins. Every command needs a signed-in DID,
+//! which is recorded against what it does, and a database (`--db-path`).
+//! Evidence copies each message out of history when attached, so a case
+//! keeps it after the message is pruned, edited or deleted.
+
+use super::helpers::normalize_channel;
+use crate::db::{ModCase, ModCaseEntry, ModCaseEntryKind};
+use crate::irc::Message;
+use crate::server::SharedState;
+use std::sync::Arc;
+
+const USAGE: &str = "Usage: CASE OPEN|EVIDENCE|ACTION|CLOSE|LIST|SHOW <channel> ...";
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn when(secs: u64) -> String {
+    chrono::DateTime::from_timestamp(secs as i64, 0)
+        .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+        .unwrap_or_else(|| secs.to_string())
+}
+
+/// Whether `did` (on `session_id`) may see and edit `channel`'s cases.
+fn is_case_authority(state: &SharedState, channel: &str, session_id: &str, did: &str) -> bool {
+    let is_op = state.channels.get(channel).is_some_and(|ch| {
+        ch.ops.contains(session_id)
+            || ch.founder_did.as_deref() == Some(did)
+            || ch.did_ops.contains(did)
+    });
+    is_op || crate::server::is_channel_admin(state, channel, did)
+}
+
+/// The DID a case subject names: a DID as given, or the DID a nick is
+/// signed in as.
+fn subject_did(state: &SharedState, subject: &str) -> Option<String> {
+    if subject.starts_with("did:") {
+        return Some(subject.to_string());
+    }
+    let session = state
+        .nick_to_session
+        .lock()
+        .get_session(subject)
+        .map(str::to_string)?;
+    state.session_dids.lock().get(&session).cloned()
+}
+
+/// The rest of the params from `from` on, as one text.
+fn text_from(msg: &Message, from: usize) -> String {
+    msg.params.get(from..).unwrap_or_default().join(" ")
+}
+
+fn status(case: &ModCase) -> &'static str {
+    if case.closed_at.is_some() {
+        "closed"
+    } else {
+        "open"
+    }
+}
+
+fn header(case: &ModCase) -> String {
+    format!(
+        "CASE {} {} {} opened {} by {}: {}",
+        case.id,
+        status(case),
+        case.subject_did,
+        when(case.opened_at),
+        case.opened_by,
+        case.summary,
+    )
+}
+
+fn entry_line(entry: &ModCaseEntry) -> String {
+    match entry.kind {
+        ModCaseEntryKind::Evidence => format!(
+            "  evidence {} from {} at {}: {}",
+            entry.msgid.as_deref().unwrap_or("*"),
+            entry.sender.as_deref().unwrap_or("*"),
+            entry.sent_at.map(when).unwrap_or_default(),
+            entry.text,
+        ),
+        ModCaseEntryKind::Action => format!(
+            "  action by {} at {}: {}",
+            entry.added_by,
+            when(entry.added_at),
+            entry.text,
+        ),
+    }
+}
+
+pub(super) fn handle_case(
+    conn: &super::Connection,
+    msg: &Message,
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    send_fn: &impl Fn(&Arc<SharedState>, &str, String),
+) {
+    let nick = conn.nick_or_star();
+    let notice = |text: &str| {
+        let reply = Message::from_server(server_name, "NOTICE", vec![nick, text]);
+        send_fn(state, session_id, format!("{reply}\r\n"));
+    };
+
+    let (Some(sub), Some(channel)) = (msg.params.first(), msg.params.get(1)) else {
+        notice(USAGE);
+        return;
+    };
+    let sub = sub.to_uppercase();
+    if !matches!(
+        sub.as_str(),
+        "OPEN" | "EVIDENCE" | "ACTION" | "CLOSE" | "LIST" | "SHOW"
+    ) {
+        notice(USAGE);
+        return;
+    }
+    let channel = normalize_channel(channel);
+    let Some(did) = conn.authenticated_did.as_deref() else {
+        notice("CASE requires an authenticated identity");
+        return;
+    };
+    if !is_case_authority(state, &channel, session_id, did) {
+        notice(&format!(
+            "Only {channel}'s operators can use its case files"
+        ));
+        return;
+    }
+    if state.db.is_none() {
+        notice("Case files need persistence, which is off on this server");
+        return;
+    }
+
+    // Subcommands other than OPEN and LIST take a case number.
+    let case = match sub.as_str() {
+        "OPEN" | "LIST" => None,
+        _ => {
+            let Some(id) = msg.params.get(2).and_then(|n| n.parse::<i64>().ok()) else {
+                notice(&format!("Usage: CASE {sub} <channel> <case> ..."));
+                return;
+            };
+            match state.with_db(|db| db.mod_case(&channel, id)).flatten() {
+                Some(case) => Some(case),
+                None => {
+                    notice(&format!("No case {id} in {channel}"));
+                    return;
+                }
+            }
+        }
+    };
+
+    match (sub.as_str(), case) {
+        ("OPEN", _) => {
+            let summary = text_from(msg, 3);
+            let Some(subject) = msg.params.get(2).filter(|_| !summary.is_empty()) else {
+                notice("Usage: CASE OPEN <channel> <did|nick> <summary>");
+                return;
+            };
+            let Some(subject) = subject_did(state, subject) else {
+                notice(&format!(
+                    "{subject} isn't signed in; give their DID instead"
+                ));
+                return;
+            };
+            let opened =
+                state.with_db(|db| db.open_mod_case(&channel, &subject, &summary, did, now()));
+            if let Some(id) = opened {
+                tracing::info!(%channel, case = id, %subject, by = %did, "Moderation case opened");
+                notice(&format!("Opened case {id} in {channel} about {subject}"));
+            }
+        }
+        ("EVIDENCE", Some(case)) => {
+            let msgids = msg.params.get(3..).unwrap_or_default();
+            if msgids.is_empty() {
+                notice("Usage: CASE EVIDENCE <channel> <case> <msgid>...");
+                return;
+            }
+            let mut added = 0;
+            for msgid in msgids {
+                let found = state
+                    .with_db(|db| db.get_message_by_msgid(&channel, msgid))
+                    .flatten();
+                let Some(row) = found else {
+                    notice(&format!("No message {msgid} in {channel}'s history"));
+                    continue;
+                };
+                let entry = ModCaseEntry {
+                    kind: ModCaseEntryKind::Evidence,
+                    msgid: Some(msgid.clone()),
+                    sender: Some(row.sender),
+                    sent_at: Some(row.timestamp),
+                    text: row.text,
+                    added_by: did.to_string(),
+                    added_at: now(),
+                };
+                if state
+                    .with_db(|db| db.add_mod_case_entry(case.id, &entry))
+                    .is_some()
+                {
+                    added += 1;
+                }
+            }
+            notice(&format!(
+                "Case {}: attached {added} message(s) as evidence",
+                case.id
+            ));
+        }
+        ("ACTION", Some(case)) => {
+            let text = text_from(msg, 3);
+            if text.is_empty() {
+                notice("Usage: CASE ACTION <channel> <case> <text>");
+                return;
+            }
+            let entry = ModCaseEntry {
+                kind: ModCaseEntryKind::Action,
+                msgid: None,
+                sender: None,
+                sent_at: None,
+                text,
+                added_by: did.to_string(),
+                added_at: now(),
+            };
+            if state
+                .with_db(|db| db.add_mod_case_entry(case.id, &entry))
+                .is_some()
+            {
+                notice(&format!("Case {}: action recorded", case.id));
+            }
+        }
+        ("CLOSE", Some(case)) => {
+            let resolution = text_from(msg, 3);
+            let resolution = (!resolution.is_empty()).then_some(resolution.as_str());
+            match state.with_db(|db| db.close_mod_case(case.id, did, now(), resolution)) {
+                Some(true) => {
+                    tracing::info!(%channel, case = case.id, by = %did, "Moderation case closed");
+                    notice(&format!("Closed case {}", case.id));
+                }
+                Some(false) => notice(&format!("Case {} is already closed", case.id)),
+                None => {}
+            }
+        }
+        ("LIST", _) => {
+            let all = msg
+                .params
+                .get(2)
+                .is_some_and(|a| a.eq_ignore_ascii_case("ALL"));
+            let cases = state
+                .with_db(|db| db.mod_cases(&channel, all))
+                .unwrap_or_default();
+            if cases.is_empty() {
+                let which = if all { "" } else { "open " };
+                notice(&format!("No {which}cases in {channel}"));
+                return;
+            }
+            for case in &cases {
+                notice(&header(case));
+            }
+            notice("End of CASE LIST");
+        }
+        ("SHOW", Some(case)) => {
+            notice(&header(&case));
+            let entries = state
+                .with_db(|db| db.mod_case_entries(case.id))
+                .unwrap_or_default();
+            for entry in &entries {
+                notice(&entry_line(entry));
+            }
+            if let (Some(by), Some(at)) = (&case.closed_by, case.closed_at) {
+                notice(&format!(
+                    "  closed by {by} at {}: {}",
+                    when(at),
+                    case.resolution.as_deref().unwrap_or("no resolution given"),
+                ));
+            }
+            notice(&format!("End of CASE {}", case.id));
+        }
+        _ => notice(USAGE),
+    }
+}
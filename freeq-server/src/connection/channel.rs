@@ -3,11 +3,18 @@
 
 use super::Connection;
 use super::helpers::{
-    broadcast_to_channel, make_extended_join, make_extended_join_with_class, make_standard_join,
-    s2s_broadcast, s2s_broadcast_mode, s2s_next_event_id,
+    broadcast_to_channel, channel_history_access, make_extended_join,
+    make_extended_join_with_class, make_standard_join, s2s_broadcast, s2s_broadcast_mode,
+    s2s_next_event_id, send_low_priority,
 };
+use super::names;
 use crate::irc::{self, Message};
-use crate::server::SharedState;
+use crate::policy::types::HistoryVisibility;
+use crate::server::{
+    ChannelPrivilege, ChannelRole, MAX_BOT_ALLOWLIST_PER_CHANNEL, SharedState, WireLine,
+};
+use crate::session::Cap;
+use crate::temporary::Temporary;
 use std::sync::Arc;
 
 pub(super) fn handle_join(
@@ -27,7 +34,7 @@ pub(super) fn handle_join(
     if channel.len() > 64 {
         let reply = Message::from_server(
             server_name,
-            "479",
+            irc::ERR_BADCHANNAME,
             vec![nick, channel, "Channel name too long (max 64 characters)"],
         );
         send(state, session_id, format!("{reply}\r\n"));
@@ -37,16 +44,22 @@ pub(super) fn handle_join(
     // Per-user channel limit to prevent memory exhaustion
     const MAX_CHANNELS_PER_USER: usize = 100;
     if !conn.is_oper {
-        let channels = state.channels.lock();
-        let current_count = channels
-            .values()
-            .filter(|ch| ch.members.contains(session_id))
-            .count();
-        if current_count >= MAX_CHANNELS_PER_USER {
+        let (limit, reason) = if conn.on_probation() {
+            (
+                super::probation::MAX_CHANNELS,
+                "New guests can't join more channels yet; sign in to join more",
+            )
+        } else {
+            (MAX_CHANNELS_PER_USER, "You have joined too many channels")
+        };
+        let current_count = state
+            .channels
+            .count(|_, ch| ch.members.contains(session_id));
+        if current_count >= limit {
             let reply = Message::from_server(
                 server_name,
                 irc::ERR_TOOMANYCHANNELS,
-                vec![nick, channel, "You have joined too many channels"],
+                vec![nick, channel, reason],
             );
             send(state, session_id, format!("{reply}\r\n"));
             return;
@@ -57,25 +70,59 @@ pub(super) fn handle_join(
     // not via S2S. If remote members are present (from S2S sync), the
     // channel already exists on the federation and the joining user
     // should NOT get auto-ops (unless they have DID-based authority).
-    let is_new_channel = {
-        let channels = state.channels.lock();
-        match channels.get(channel) {
-            None => true,
-            Some(ch) => {
-                // Channel entry exists but has nobody and no persistent state —
-                // treat as effectively new (e.g. leftover from cleanup)
-                ch.members.is_empty()
-                    && ch.remote_members.is_empty()
-                    && ch.founder_did.is_none()
-                    && ch.topic.is_none()
-                    && ch.ops.is_empty()
-            }
+    let is_new_channel = match state.channels.get(channel) {
+        None => true,
+        Some(ch) => {
+            // Channel entry exists but has nobody and no persistent state —
+            // treat as effectively new (e.g. leftover from cleanup)
+            ch.members.is_empty()
+                && ch.remote_members.is_empty()
+                && ch.founder_did.is_none()
+                && ch.topic.is_none()
+                && ch.ops.is_empty()
         }
     };
 
+    // Reserved names can only be opened by those allowed them; after that
+    // the channel's own modes decide who gets in.
+    if is_new_channel
+        && crate::reserved::channel_reserved(&state.config, channel)
+        && !crate::reserved::exempt(&state.config, did, conn.is_oper)
+    {
+        let reply = Message::from_server(
+            server_name,
+            irc::ERR_BADCHANNAME,
+            vec![nick, channel, "Channel name is reserved"],
+        );
+        send(state, session_id, format!("{reply}\r\n"));
+        return;
+    }
+
+    // ─── Invite links ─────────────────────────────────────────────────
+    // A key that's a valid invite token for this channel gets past +k and
+    // +i, and waives what it names from the policy's join requirements.
+    // Anything that isn't one of our tokens is treated as a channel key.
+    // The invite is only spent once the join goes through.
+    let invite = supplied_key.filter(|_| !is_new_channel).and_then(|token| {
+        let now = chrono::Utc::now().timestamp();
+        let lookup = state
+            .invites
+            .lock()
+            .lookup(&state.invite_key(), token, Some(channel), now)
+            .cloned();
+        match lookup {
+            Ok(invite) => Some(invite),
+            Err(crate::invites::InviteError::Invalid) => None,
+            Err(e) => {
+                let reply = Message::from_server(server_name, "NOTICE", vec![nick, &e.to_string()]);
+                send(state, session_id, format!("{reply}\r\n"));
+                None
+            }
+        }
+    });
+
     if !is_new_channel {
-        let channels = state.channels.lock();
-        if let Some(ch) = channels.get(channel) {
+        if let Some(mut ch) = state.channels.get(channel) {
             // Already in channel — silently ignore (prevents double-join on reconnect)
             if ch.members.contains(session_id) {
                 return;
@@ -88,6 +135,7 @@ pub(super) fn handle_join(
                 did.is_some_and(|d| ch.founder_did.as_deref() == Some(d) || ch.did_ops.contains(d));
             // Check channel key (+k)
             if !is_did_authority
+                && invite.is_none()
                 && let Some(ref key) = ch.key
                 && supplied_key != Some(key.as_str())
             {
@@ -110,7 +158,7 @@ pub(super) fn handle_join(
                 return;
             }
             // Check invite-only
-            if !is_did_authority && ch.invite_only {
+            if !is_did_authority && invite.is_none() && ch.invite_only {
                 let has_invite = ch.invites.contains(session_id)
                     || did.is_some_and(|d| ch.invites.contains(d))
                     || ch.invites.contains(&format!("nick:{nick}"));
@@ -127,20 +175,35 @@ pub(super) fn handle_join(
                 // Consume the invite ONLY if that's how we got in (sticky +I
                 // entries are persistent and must NOT be consumed).
                 if has_invite {
-                    drop(channels);
-                    let mut channels = state.channels.lock();
-                    if let Some(ch) = channels.get_mut(channel) {
-                        ch.invites.remove(session_id);
-                        if let Some(d) = did {
-                            ch.invites.remove(d);
-                        }
-                        ch.invites.remove(&format!("nick:{nick}"));
+                    ch.invites.remove(session_id);
+                    if let Some(d) = did {
+                        ch.invites.remove(d);
                     }
+                    ch.invites.remove(&format!("nick:{nick}"));
                 }
             }
         }
     }
 
+    // Redeem before the policy check: accepting the rules through an invite
+    // persists an attestation, which must not outlive a use that was
+    // refused (expired, or used up by a concurrent join).
+    if let Some(invite) = &invite {
+        let now = chrono::Utc::now().timestamp();
+        let redeemed = state.invites.lock().redeem(&invite.id, now);
+        match redeemed {
+            Ok(invite) => {
+                state.with_db(|db| db.save_invite(&invite));
+                tracing::info!(id = %invite.id, channel, uses = invite.uses, "Invite link redeemed");
+            }
+            Err(e) => {
+                let reply = Message::from_server(server_name, "NOTICE", vec![nick, &e.to_string()]);
+                send(state, session_id, format!("{reply}\r\n"));
+                return;
+            }
+        }
+    }
+
     // ─── Policy check ─────────────────────────────────────────────────
     // If the channel has a policy, check if the user has a valid attestation.
     // Channels without policies are open (backwards compatible).
@@ -153,15 +216,12 @@ pub(super) fn handle_join(
         match did {
             Some(user_did) => {
                 // DID ops and founders bypass policy checks
-                let is_did_op = {
-                    let channels = state.channels.lock();
-                    channels
-                        .get(&channel.to_ascii_lowercase())
-                        .is_some_and(|ch| {
-                            ch.founder_did.as_deref() == Some(user_did)
-                                || ch.did_ops.contains(user_did)
-                        })
-                };
+                let is_did_op = state
+                    .channels
+                    .get(&crate::casemap::fold(channel))
+                    .is_some_and(|ch| {
+                        ch.founder_did.as_deref() == Some(user_did) || ch.did_ops.contains(user_did)
+                    });
                 if is_did_op {
                     policy_role = Some("op".to_string());
                 } else {
@@ -170,6 +230,42 @@ pub(super) fn handle_join(
                             // Valid attestation — allow join, capture role
                             policy_role = Some(attestation.role.clone());
                         }
+                        Ok(None) if invite.is_some() => {
+                            let waives = invite
+                                .as_ref()
+                                .map(|i| i.waives.as_slice())
+                                .unwrap_or_default();
+                            match engine.join_with_invite(channel, user_did, waives) {
+                                Ok(crate::policy::JoinResult::Confirmed {
+                                    attestation, ..
+                                }) => {
+                                    let note = format!(
+                                        "Joining {channel} with an invite accepts its rules — see POLICY {channel} INFO"
+                                    );
+                                    let reply = Message::from_server(
+                                        server_name,
+                                        "NOTICE",
+                                        vec![nick, &note],
+                                    );
+                                    send(state, session_id, format!("{reply}\r\n"));
+                                    policy_role = Some(attestation.role);
+                                }
+                                Ok(crate::policy::JoinResult::Failed(reason)) => {
+                                    let reason = format!("Cannot join with this invite: {reason}");
+                                    let reply = Message::from_server(
+                                        server_name,
+                                        "477",
+                                        vec![nick, channel, &reason],
+                                    );
+                                    send(state, session_id, format!("{reply}\r\n"));
+                                    return;
+                                }
+                                Ok(_) => {}
+                                Err(e) => {
+                                    tracing::warn!(channel, did = user_did, error = %e, "Invite join failed");
+                                }
+                            }
+                        }
                         Ok(None) => {
                             // No attestation — reject with informative message
                             let reply = Message::from_server(
@@ -210,9 +306,15 @@ pub(super) fn handle_join(
     }
 
     {
-        let mut channels = state.channels.lock();
-        let ch = channels.entry(channel.to_string()).or_default();
+        let mut ch = state.channels.get_or_insert_with(channel, Default::default);
         ch.members.insert(session_id.to_string());
+        let joined_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        ch.member_since
+            .entry(session_id.to_string())
+            .or_insert(joined_at);
         // NOTE: Presence is NOT in CRDT (avoids ghost users on crash).
         // It's tracked by S2S events + periodic resync only.
 
@@ -242,7 +344,7 @@ pub(super) fn handle_join(
             ch.no_ext_msg = true;
             ch.topic_locked = true;
             let ch_clone = ch.clone();
-            drop(channels);
+            drop(ch);
             state.with_db(|db| db.save_channel(channel, &ch_clone));
         } else {
             // Existing channel: auto-op if user's DID has persistent ops
@@ -272,12 +374,12 @@ pub(super) fn handle_join(
             }
         }
     }
+    crate::stats::record_members(state, channel);
 
     // ─── Policy role → IRC mode mapping ────────────────────────────────
     // If user joined via policy and has an elevated role, grant IRC modes.
     if let Some(ref role) = policy_role {
-        let mut channels = state.channels.lock();
-        if let Some(ch) = channels.get_mut(channel) {
+        if let Some(mut ch) = state.channels.get(channel) {
             match role.as_str() {
                 "op" | "admin" | "owner" => {
                     ch.ops.insert(session_id.to_string());
@@ -296,11 +398,26 @@ pub(super) fn handle_join(
         }
     }
 
+    // ─── Founder (~) and admin (&) ─────────────────────────────────────
+    // Derived from channel authority rather than set with MODE. Both imply
+    // ops; the roles add protection against lower-ranked ops.
+    if let Some(d) = did {
+        let is_admin = crate::server::is_channel_admin(state, channel, d);
+        if let Some(mut ch) = state.channels.get(channel) {
+            if ch.founder_did.as_deref() == Some(d) {
+                ch.founders.insert(session_id.to_string());
+                ch.ops.insert(session_id.to_string());
+            } else if is_admin {
+                ch.admins.insert(session_id.to_string());
+                ch.ops.insert(session_id.to_string());
+            }
+        }
+    }
+
     // Broadcast MODE +o/+h to existing channel members if the joiner was auto-opped/halfopped
     {
         let (is_op, is_halfop) = state
             .channels
-            .lock()
             .get(channel)
             .map(|ch| (ch.ops.contains(session_id), ch.halfops.contains(session_id)))
             .unwrap_or((false, false));
@@ -312,12 +429,12 @@ pub(super) fn handle_join(
             None
         };
         if let Some(mode) = auto_mode {
-            let mode_msg = format!(":{server_name} MODE {channel} {mode} {nick}\r\n");
-            let channels = state.channels.lock();
-            if let Some(ch) = channels.get(channel) {
+            let mode_msg =
+                WireLine::from(format!(":{server_name} MODE {channel} {mode} {nick}\r\n"));
+            if let Some(ch) = state.channels.get(channel) {
                 let members: Vec<String> = ch.members.iter().cloned().collect();
-                drop(channels);
-                let conns = state.connections.lock();
+                drop(ch);
+                let conns = &state.connections;
                 for member_session in &members {
                     if let Some(tx) = conns.get(member_session) {
                         let _ = tx.try_send(mode_msg.clone());
@@ -335,28 +452,39 @@ pub(super) fn handle_join(
         session_id: session_id.to_string(),
         is_new_channel,
     });
+    state
+        .firehose
+        .publish(crate::firehose::FirehoseEvent::Join {
+            session: session_id.to_string(),
+            nick: nick.to_string(),
+            channel: channel.to_string(),
+            did: did.map(|d| d.to_string()),
+        });
 
-    let std_join = make_standard_join(&hostmask, channel);
+    let std_join = WireLine::from(make_standard_join(&hostmask, channel));
     let realname = conn.realname.as_deref().unwrap_or(nick);
-    let ext_join = make_extended_join(&hostmask, channel, did, realname);
-    let ext_join_class =
-        make_extended_join_with_class(&hostmask, channel, did, realname, conn.actor_class);
+    let ext_join = WireLine::from(make_extended_join(&hostmask, channel, did, realname));
+    let ext_join_class = WireLine::from(make_extended_join_with_class(
+        &hostmask,
+        channel,
+        did,
+        realname,
+        conn.actor_class,
+    ));
 
     let members: Vec<String> = state
         .channels
-        .lock()
         .get(channel)
-        .map(|ch| ch.members.iter().cloned().collect())
+        .map(|ch| ch.audience(session_id))
         .unwrap_or_default();
 
-    let ext_set = state.cap_extended_join.lock();
-    let tag_set = state.cap_message_tags.lock();
-    let conns = state.connections.lock();
+    let conns = &state.connections;
     for member_session in &members {
+        let caps = state.sessions.caps(member_session);
         if let Some(tx) = conns.get(member_session) {
-            let result = if ext_set.contains(member_session) {
+            let result = if caps.has(Cap::ExtendedJoin) {
                 // Clients with message-tags get the actor class tag
-                if tag_set.contains(member_session) {
+                if caps.has(Cap::MessageTags) {
                     tx.try_send(ext_join_class.clone())
                 } else {
                     tx.try_send(ext_join.clone())
@@ -382,9 +510,6 @@ pub(super) fn handle_join(
             );
         }
     }
-    drop(conns);
-    drop(tag_set);
-    drop(ext_set);
 
     // Broadcast JOIN to S2S peers
     let origin = state.server_iroh_id.lock().clone().unwrap_or_default();
@@ -392,7 +517,6 @@ pub(super) fn handle_join(
     let handle = state.session_handles.lock().get(session_id).cloned();
     let user_is_op = state
         .channels
-        .lock()
         .get(channel)
         .map(|ch| ch.ops.contains(session_id))
         .unwrap_or(false);
@@ -417,8 +541,7 @@ pub(super) fn handle_join(
 
     // If this was a new channel creation, broadcast founder info
     if is_new_channel {
-        let channels = state.channels.lock();
-        if let Some(ch) = channels.get(channel) {
+        if let Some(ch) = state.channels.get(channel) {
             s2s_broadcast(
                 state,
                 crate::s2s::S2sMessage::ChannelCreated {
@@ -442,8 +565,7 @@ pub(super) fn handle_join(
 
     // Send topic if set (332 + 333)
     {
-        let channels = state.channels.lock();
-        if let Some(ch) = channels.get(channel)
+        if let Some(ch) = state.channels.get(channel)
             && let Some(ref topic) = ch.topic
         {
             let rpl_topic = Message::from_server(
@@ -464,21 +586,28 @@ pub(super) fn handle_join(
 
     // Replay recent message history with server-time + batch when supported
     {
-        let has_tags_cap = state.cap_message_tags.lock().contains(session_id);
-        let has_time_cap = state.cap_server_time.lock().contains(session_id);
-        let has_batch_cap = state.cap_batch.lock().contains(session_id);
-        let has_multiline_cap = state.cap_draft_multiline.lock().contains(session_id);
+        let caps = state.sessions.caps(session_id);
+        let has_tags_cap = caps.has(Cap::MessageTags);
+        let has_time_cap = caps.has(Cap::ServerTime);
+        let has_batch_cap = caps.has(Cap::Batch);
+        let has_multiline_cap = caps.has(Cap::DraftMultiline);
 
         // Clone the history out so the DB call (reactions lookup) can
         // happen without holding the channels lock — and so the per-row
-        // emit loop below isn't holding the lock either.
-        let history: Vec<crate::server::HistoryMessage> = {
-            let channels = state.channels.lock();
-            channels
-                .get(channel)
-                .map(|ch| ch.history.iter().cloned().collect())
-                .unwrap_or_default()
-        };
+        // emit loop below isn't holding the lock either. Only what the
+        // channel's history visibility (+H) lets this joiner see.
+        let access = channel_history_access(conn, state, session_id, channel);
+        let history: Vec<crate::server::HistoryMessage> = state
+            .channels
+            .get(channel)
+            .map(|ch| {
+                ch.history
+                    .iter()
+                    .filter(|h| access.as_ref().is_some_and(|a| a.allows(h.timestamp)))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
 
         if !history.is_empty() {
             // Fetch persisted reactions for this batch so they ride on
@@ -575,7 +704,7 @@ pub(super) fn handle_join(
                             command: "PRIVMSG".to_string(),
                             params: vec![channel.to_string(), body.to_string()],
                         };
-                        send(state, session_id, format!("{chunk}\r\n"));
+                        send_low_priority(state, session_id, format!("{chunk}\r\n"));
                     }
                     let mut closer_tags = std::collections::HashMap::new();
                     if let Some(b) = msg_tags.get("batch") {
@@ -612,10 +741,10 @@ pub(super) fn handle_join(
                                 command: "PRIVMSG".to_string(),
                                 params: vec![channel.to_string(), body.to_string()],
                             };
-                            send(state, session_id, format!("{chunk}\r\n"));
+                            send_low_priority(state, session_id, format!("{chunk}\r\n"));
                         } else {
                             let line = format!(":{} PRIVMSG {} :{}\r\n", hist.from, channel, body);
-                            send(state, session_id, line);
+                            send_low_priority(state, session_id, line);
                         }
                     }
                     continue;
@@ -628,10 +757,10 @@ pub(super) fn handle_join(
                         command: "PRIVMSG".to_string(),
                         params: vec![channel.to_string(), hist.text.clone()],
                     };
-                    send(state, session_id, format!("{tag_msg}\r\n"));
+                    send_low_priority(state, session_id, format!("{tag_msg}\r\n"));
                 } else {
                     let line = format!(":{} PRIVMSG {} :{}\r\n", hist.from, channel, hist.text);
-                    send(state, session_id, line);
+                    send_low_priority(state, session_id, line);
                 }
             }
 
@@ -643,85 +772,9 @@ pub(super) fn handle_join(
         }
     }
 
-    let nick_list: Vec<String> = {
-        let channels = state.channels.lock();
-        let (member_sessions, remote_members, ops, voiced) = match channels.get(channel) {
-            Some(ch) => (
-                ch.members.clone(),
-                ch.remote_members.clone(),
-                ch.ops.clone(),
-                ch.voiced.clone(),
-            ),
-            None => Default::default(),
-        };
-        drop(channels);
-        // Local members: look up nick from session ID (deduplicated for multi-device)
-        let nicks = state.nick_to_session.lock();
-        let mut seen_nicks = std::collections::HashSet::new();
-        let member_count = member_sessions.len();
-        let mut list: Vec<String> = member_sessions
-            .iter()
-            .filter_map(|s| {
-                let nick_result = nicks.get_nick(s);
-                if nick_result.is_none() {
-                    tracing::warn!(
-                        channel = %channel,
-                        session = %s,
-                        "NAMES: session in ch.members but not in nick_to_session"
-                    );
-                }
-                nick_result.and_then(|n| {
-                    let nick_lower = n.to_lowercase();
-                    if !seen_nicks.insert(nick_lower) {
-                        return None;
-                    }
-                    let prefix = if ops.contains(s) {
-                        "@"
-                    } else if voiced.contains(s) {
-                        "+"
-                    } else {
-                        ""
-                    };
-                    Some(format!("{prefix}{n}"))
-                })
-            })
-            .collect();
-        if list.is_empty() && member_count > 0 {
-            tracing::warn!(
-                channel = %channel,
-                member_count = member_count,
-                "NAMES: all members resolved to empty list!"
-            );
-        }
-        // Remote members from S2S peers (with @ prefix if op on home server or DID-based)
-        let channels_lock = state.channels.lock();
-        let ch_state = channels_lock.get(channel);
-        for (nick, rm) in &remote_members {
-            let is_op = rm.is_op
-                || rm.did.as_ref().is_some_and(|d| {
-                    ch_state.is_some_and(|ch| {
-                        ch.founder_did.as_deref() == Some(d.as_str()) || ch.did_ops.contains(d)
-                    })
-                });
-            let prefix = if is_op { "@" } else { "" };
-            list.push(format!("{prefix}{nick}"));
-        }
-        drop(channels_lock);
-        list
-    };
+    send_names(state, server_name, session_id, nick, channel, None);
 
-    let names = Message::from_server(
-        server_name,
-        irc::RPL_NAMREPLY,
-        vec![nick, "=", channel, &nick_list.join(" ")],
-    );
-    let end_names = Message::from_server(
-        server_name,
-        irc::RPL_ENDOFNAMES,
-        vec![nick, channel, "End of /NAMES list"],
-    );
-    send(state, session_id, format!("{names}\r\n"));
-    send(state, session_id, format!("{end_names}\r\n"));
+    super::metadata::sync_on_join(state, server_name, session_id, nick, channel, send);
 
     // Notify joining client about active AV session in this channel (if any)
     {
@@ -758,7 +811,7 @@ pub(super) fn handle_join(
                 params: vec![channel.to_string()],
             };
             // Only send if client supports message-tags
-            if state.cap_message_tags.lock().contains(session_id) {
+            if state.sessions.has_cap(session_id, Cap::MessageTags) {
                 send(state, session_id, format!("{tag_msg}\r\n"));
             } else {
                 // Fallback: human-readable notice
@@ -789,7 +842,6 @@ pub(super) fn handle_mode(
     // Verify user is in the channel
     let in_channel = state
         .channels
-        .lock()
         .get(channel)
         .map(|ch| ch.members.contains(session_id))
         .unwrap_or(false);
@@ -806,8 +858,8 @@ pub(super) fn handle_mode(
 
     let Some(mode_str) = mode_str else {
         // Query channel modes
-        let channels = state.channels.lock();
-        let modes = if let Some(ch) = channels.get(channel) {
+        let mut params = Vec::new();
+        let modes = if let Some(ch) = state.channels.get(channel) {
             let mut m = String::from("+");
             if ch.no_ext_msg {
                 m.push('n');
@@ -824,6 +876,29 @@ pub(super) fn handle_mode(
             if ch.encrypted_only {
                 m.push('E');
             }
+            if ch.archived {
+                m.push('A');
+            }
+            if ch.auditorium {
+                m.push('u');
+            }
+            if ch.no_stats {
+                m.push('S');
+            }
+            if ch.secret {
+                m.push('s');
+            }
+            if ch.bots_restricted {
+                m.push('B');
+            }
+            if let Some(temporary) = ch.temporary {
+                m.push('T');
+                params.push(temporary.to_string());
+            }
+            if ch.history_visibility != HistoryVisibility::default() {
+                m.push('H');
+                params.push(ch.history_visibility.as_str().to_string());
+            }
             if ch.key.is_some() {
                 m.push('k');
             }
@@ -831,26 +906,29 @@ pub(super) fn handle_mode(
         } else {
             "+".to_string()
         };
-        let reply = Message::from_server(
-            server_name,
-            irc::RPL_CHANNELMODEIS,
-            vec![nick, channel, &modes],
-        );
+        let mut reply_params = vec![nick, channel, &modes];
+        reply_params.extend(params.iter().map(String::as_str));
+        let reply = Message::from_server(server_name, irc::RPL_CHANNELMODEIS, reply_params);
         send(state, session_id, format!("{reply}\r\n"));
         return;
     };
 
-    // Check privileges: ops can do anything, halfops can set +v only
-    let (is_op, is_halfop) = state
-        .channels
-        .lock()
-        .get(channel)
-        .map(|ch| (ch.ops.contains(session_id), ch.halfops.contains(session_id)))
-        .unwrap_or((false, false));
+    // Check privileges against the permission matrix (ChannelRole::can)
+    let rank = super::helpers::channel_rank(state, channel, session_id);
 
     // Server operators (OPER) can always change modes
     let is_server_oper = state.server_opers.lock().contains(session_id);
-    if !is_op && !is_halfop && !is_server_oper {
+    // Holders of a moderator appointment credential may manage the quiet
+    // list (+q) even before their +h has been applied.
+    let is_quiet_only = mode_str.chars().all(|c| matches!(c, '+' | '-' | 'q'));
+    let is_credentialed_mod = is_quiet_only
+        && state
+            .session_dids
+            .lock()
+            .get(session_id)
+            .cloned()
+            .is_some_and(|did| crate::server::is_credentialed_moderator(state, channel, &did));
+    if rank < ChannelRole::Halfop && !is_server_oper && !is_credentialed_mod {
         let reply = Message::from_server(
             server_name,
             irc::ERR_CHANOPRIVSNEEDED,
@@ -860,20 +938,23 @@ pub(super) fn handle_mode(
         return;
     }
 
-    // Halfops can only set +v/-v — not +o, +h, +m, +t, +i, +k, +n
-    if is_halfop && !is_op && !is_server_oper {
-        let has_restricted = mode_str
-            .chars()
-            .any(|c| matches!(c, 'o' | 'h' | 'm' | 't' | 'i' | 'k' | 'n' | 'E'));
-        if has_restricted {
-            let reply = Message::from_server(
-                server_name,
-                irc::ERR_CHANOPRIVSNEEDED,
-                vec![nick, channel, "Moderators can only set +v/-v"],
-            );
-            send(state, session_id, format!("{reply}\r\n"));
-            return;
-        }
+    // Halfops can set +v and the +b/+q/+I lists — not +o, +h or settings
+    let denied = mode_str
+        .chars()
+        .filter(|c| !matches!(c, '+' | '-'))
+        .any(|c| !rank.can(ChannelPrivilege::for_mode(c)));
+    if denied && !is_server_oper && !is_credentialed_mod {
+        let reply = Message::from_server(
+            server_name,
+            irc::ERR_CHANOPRIVSNEEDED,
+            vec![
+                nick,
+                channel,
+                "Moderators can only set +v and +b/+q/+I (see HELPOP ROLES)",
+            ],
+        );
+        send(state, session_id, format!("{reply}\r\n"));
+        return;
     }
 
     // Parse mode string: +o, -o, +v, -v, +t, -t
@@ -894,15 +975,34 @@ pub(super) fn handle_mode(
                 };
 
                 // Resolve target via federated channel roster (local + remote)
-                use super::helpers::{ChannelTarget, resolve_channel_target};
-                match resolve_channel_target(state, channel, target_nick) {
+                use super::helpers::{
+                    ChannelTarget, protected_role, protected_role_name, resolve_channel_target,
+                };
+                let target = resolve_channel_target(state, channel, target_nick);
+                if !adding
+                    && !is_server_oper
+                    && let Some(role) = protected_role(state, channel, session_id, &target)
+                {
+                    let text = format!("Cannot change the status of {}", protected_role_name(role));
+                    let reply = Message::from_server(
+                        server_name,
+                        irc::ERR_CHANOPRIVSNEEDED,
+                        vec![nick, channel, &text],
+                    );
+                    send(state, session_id, format!("{reply}\r\n"));
+                    return;
+                }
+                match target {
                     ChannelTarget::Local {
                         session_id: target_session,
                     } => {
+                        let was_shown = state
+                            .channels
+                            .get(channel)
+                            .is_some_and(|c| c.rank(&target_session) >= ChannelRole::Voice);
                         // Apply the mode locally
                         {
-                            let mut channels = state.channels.lock();
-                            if let Some(chan) = channels.get_mut(channel) {
+                            if let Some(mut chan) = state.channels.get(channel) {
                                 let set = match ch {
                                     'o' => &mut chan.ops,
                                     'h' => &mut chan.halfops,
@@ -913,6 +1013,10 @@ pub(super) fn handle_mode(
                                 } else {
                                     set.remove(&target_session);
                                 }
+                                if ch == 'o' && !adding {
+                                    chan.admins.remove(&target_session);
+                                    chan.founders.remove(&target_session);
+                                }
 
                                 // DID-based persistent ops: +o/-o on an authenticated
                                 // user also updates did_ops, so ops survive reconnects
@@ -956,7 +1060,7 @@ pub(super) fn handle_mode(
                                         // Persist the updated DID ops
                                         let ch_clone = chan.clone();
                                         let channel_name = channel.to_string();
-                                        drop(channels);
+                                        drop(chan);
                                         state.with_db(|db| {
                                             db.save_channel(&channel_name, &ch_clone)
                                         });
@@ -965,12 +1069,20 @@ pub(super) fn handle_mode(
                             }
                         }
 
-                        // Broadcast mode change to local channel + S2S
+                        // Broadcast mode change to local channel + S2S. In +u,
+                        // ordinary members meet a newly voiced member before
+                        // the MODE and lose sight of a devoiced one after it.
+                        if adding {
+                            auditorium_reveal(state, channel, &target_session, was_shown);
+                        }
                         let sign = if adding { "+" } else { "-" };
                         let hostmask = conn.hostmask();
                         let mode_msg =
                             format!(":{hostmask} MODE {channel} {sign}{ch} {target_nick}\r\n");
                         broadcast_to_channel(state, channel, &mode_msg);
+                        if !adding {
+                            auditorium_reveal(state, channel, &target_session, was_shown);
+                        }
                         s2s_broadcast_mode(
                             state,
                             conn,
@@ -983,8 +1095,7 @@ pub(super) fn handle_mode(
                     ChannelTarget::Remote(rm) => {
                         // Apply ephemeral op/voice on the remote member locally
                         {
-                            let mut channels = state.channels.lock();
-                            if let Some(chan) = channels.get_mut(channel)
+                            if let Some(mut chan) = state.channels.get(channel)
                                 && ch == 'o'
                                 && let Some(remote) = chan.remote_members.get_mut(target_nick)
                             {
@@ -999,8 +1110,7 @@ pub(super) fn handle_mode(
                             && let Some(ref did) = rm.did
                         {
                             {
-                                let mut channels = state.channels.lock();
-                                if let Some(chan) = channels.get_mut(channel) {
+                                if let Some(mut chan) = state.channels.get(channel) {
                                     if !adding && chan.founder_did.as_deref() == Some(did.as_str())
                                     {
                                         // Founder can't be de-opped
@@ -1011,7 +1121,7 @@ pub(super) fn handle_mode(
                                     }
                                     let ch_clone = chan.clone();
                                     let channel_name = channel.to_string();
-                                    drop(channels);
+                                    drop(chan);
                                     state.with_db(|db| db.save_channel(&channel_name, &ch_clone));
                                 }
                             }
@@ -1076,8 +1186,7 @@ pub(super) fn handle_mode(
 
                 if adding && mode_arg.is_none() {
                     // +b with no arg: list bans
-                    let channels = state.channels.lock();
-                    if let Some(chan) = channels.get(channel) {
+                    if let Some(chan) = state.channels.get(channel) {
                         for ban in &chan.bans {
                             let reply = Message::from_server(
                                 server_name,
@@ -1108,12 +1217,11 @@ pub(super) fn handle_mode(
                 }
                 if adding {
                     let entry = BanEntry::new(mask.to_string(), conn.hostmask());
-                    let mut channels = state.channels.lock();
-                    if let Some(chan) = channels.get_mut(channel) {
+                    if let Some(mut chan) = state.channels.get(channel) {
                         // Per-channel ban limit to prevent resource exhaustion
                         const MAX_BANS_PER_CHANNEL: usize = 500;
                         if chan.bans.len() >= MAX_BANS_PER_CHANNEL {
-                            drop(channels);
+                            drop(chan);
                             let reply = Message::from_server(
                                 server_name,
                                 "478",
@@ -1125,16 +1233,14 @@ pub(super) fn handle_mode(
                         // Don't duplicate
                         if !chan.bans.iter().any(|b| b.mask == mask) {
                             chan.bans.push(entry.clone());
-                            drop(channels);
+                            drop(chan);
                             state.with_db(|db| db.add_ban(channel, &entry));
                         }
                     }
                 } else {
-                    let mut channels = state.channels.lock();
-                    if let Some(chan) = channels.get_mut(channel) {
+                    if let Some(mut chan) = state.channels.get(channel) {
                         chan.bans.retain(|b| b.mask != mask);
                     }
-                    drop(channels);
                     state.with_db(|db| db.remove_ban(channel, mask));
                 }
 
@@ -1159,6 +1265,107 @@ pub(super) fn handle_mode(
                     );
                 }
             }
+            'F' | 'a' => {
+                let reply = Message::from_server(
+                    server_name,
+                    irc::ERR_CHANOPRIVSNEEDED,
+                    vec![
+                        nick,
+                        channel,
+                        "Founder and admin status come from channel authority, not MODE",
+                    ],
+                );
+                send(state, session_id, format!("{reply}\r\n"));
+                return;
+            }
+            'q' => {
+                use crate::server::BanEntry;
+
+                if !adding && mode_arg.is_none() {
+                    // -q with no arg is invalid, ignore
+                    return;
+                }
+
+                if adding && mode_arg.is_none() {
+                    // +q with no arg: list quiets
+                    if let Some(chan) = state.channels.get(channel) {
+                        for quiet in &chan.quiets {
+                            let reply = Message::from_server(
+                                server_name,
+                                irc::RPL_QUIETLIST,
+                                vec![
+                                    nick,
+                                    channel,
+                                    "q",
+                                    &quiet.mask,
+                                    &quiet.set_by,
+                                    &quiet.set_at.to_string(),
+                                ],
+                            );
+                            send(state, session_id, format!("{reply}\r\n"));
+                        }
+                    }
+                    let end = Message::from_server(
+                        server_name,
+                        irc::RPL_ENDOFQUIETLIST,
+                        vec![nick, channel, "q", "End of channel quiet list"],
+                    );
+                    send(state, session_id, format!("{end}\r\n"));
+                    return;
+                }
+
+                let mask = mode_arg.unwrap().trim();
+                if mask.is_empty() {
+                    return;
+                }
+                if adding {
+                    let entry = BanEntry::new(mask.to_string(), conn.hostmask());
+                    if let Some(mut chan) = state.channels.get(channel) {
+                        const MAX_QUIETS_PER_CHANNEL: usize = 500;
+                        if chan.quiets.len() >= MAX_QUIETS_PER_CHANNEL {
+                            drop(chan);
+                            let reply = Message::from_server(
+                                server_name,
+                                "478",
+                                vec![nick, channel, "Channel quiet list is full"],
+                            );
+                            send(state, session_id, format!("{reply}\r\n"));
+                            return;
+                        }
+                        if !chan.quiets.iter().any(|q| q.mask == mask) {
+                            chan.quiets.push(entry.clone());
+                            drop(chan);
+                            state.with_db(|db| db.add_quiet(channel, &entry));
+                        }
+                    }
+                } else {
+                    if let Some(mut chan) = state.channels.get(channel) {
+                        chan.quiets.retain(|q| q.mask != mask);
+                    }
+                    state.with_db(|db| db.remove_quiet(channel, mask));
+                }
+
+                let sign = if adding { "+" } else { "-" };
+                let hostmask = conn.hostmask();
+                let mode_msg = format!(":{hostmask} MODE {channel} {sign}q {mask}\r\n");
+                broadcast_to_channel(state, channel, &mode_msg);
+
+                // S2S: propagate the quiet change to peers
+                {
+                    let origin = state.server_iroh_id.lock().clone().unwrap_or_default();
+                    s2s_broadcast(
+                        state,
+                        crate::s2s::S2sMessage::Quiet {
+                            event_id: s2s_next_event_id(state),
+                            channel: channel.to_string(),
+                            mask: mask.to_string(),
+                            set_by: nick.to_string(),
+                            adding,
+                            origin,
+                        },
+                    );
+                }
+            }
             'I' => {
                 use crate::server::InviteExceptionEntry;
 
@@ -1169,8 +1376,7 @@ pub(super) fn handle_mode(
 
                 if adding && mode_arg.is_none() {
                     // +I with no arg: list invite exceptions
-                    let channels = state.channels.lock();
-                    if let Some(chan) = channels.get(channel) {
+                    if let Some(chan) = state.channels.get(channel) {
                         for entry in &chan.invite_exceptions {
                             let reply = Message::from_server(
                                 server_name,
@@ -1201,11 +1407,10 @@ pub(super) fn handle_mode(
                 }
                 if adding {
                     let entry = InviteExceptionEntry::new(mask.to_string(), conn.hostmask());
-                    let mut channels = state.channels.lock();
-                    if let Some(chan) = channels.get_mut(channel) {
+                    if let Some(mut chan) = state.channels.get(channel) {
                         const MAX_INVITE_EXCEPTIONS_PER_CHANNEL: usize = 500;
                         if chan.invite_exceptions.len() >= MAX_INVITE_EXCEPTIONS_PER_CHANNEL {
-                            drop(channels);
+                            drop(chan);
                             let reply = Message::from_server(
                                 server_name,
                                 "478",
@@ -1216,16 +1421,14 @@ pub(super) fn handle_mode(
                         }
                         if !chan.invite_exceptions.iter().any(|e| e.mask == mask) {
                             chan.invite_exceptions.push(entry.clone());
-                            drop(channels);
+                            drop(chan);
                             state.with_db(|db| db.add_invite_exception(channel, &entry));
                         }
                     }
                 } else {
-                    let mut channels = state.channels.lock();
-                    if let Some(chan) = channels.get_mut(channel) {
+                    if let Some(mut chan) = state.channels.get(channel) {
                         chan.invite_exceptions.retain(|e| e.mask != mask);
                     }
-                    drop(channels);
                     state.with_db(|db| db.remove_invite_exception(channel, mask));
                 }
 
@@ -1252,14 +1455,13 @@ pub(super) fn handle_mode(
             }
             'i' => {
                 {
-                    let mut channels = state.channels.lock();
-                    if let Some(chan) = channels.get_mut(channel) {
+                    if let Some(mut chan) = state.channels.get(channel) {
                         chan.invite_only = adding;
                         if !adding {
                             chan.invites.clear();
                         }
                         let ch_clone = chan.clone();
-                        drop(channels);
+                        drop(chan);
                         state.with_db(|db| db.save_channel(channel, &ch_clone));
                     }
                 }
@@ -1271,11 +1473,10 @@ pub(super) fn handle_mode(
             }
             't' => {
                 {
-                    let mut channels = state.channels.lock();
-                    if let Some(chan) = channels.get_mut(channel) {
+                    if let Some(mut chan) = state.channels.get(channel) {
                         chan.topic_locked = adding;
                         let ch_clone = chan.clone();
-                        drop(channels);
+                        drop(chan);
                         state.with_db(|db| db.save_channel(channel, &ch_clone));
                     }
                 }
@@ -1297,11 +1498,10 @@ pub(super) fn handle_mode(
                         return;
                     };
                     {
-                        let mut channels = state.channels.lock();
-                        if let Some(chan) = channels.get_mut(channel) {
+                        if let Some(mut chan) = state.channels.get(channel) {
                             chan.key = Some(key.to_string());
                             let ch_clone = chan.clone();
-                            drop(channels);
+                            drop(chan);
                             state.with_db(|db| db.save_channel(channel, &ch_clone));
                         }
                     }
@@ -1311,11 +1511,10 @@ pub(super) fn handle_mode(
                     s2s_broadcast_mode(state, conn, channel, "+k", Some(key));
                 } else {
                     let old_key = {
-                        let mut channels = state.channels.lock();
-                        if let Some(chan) = channels.get_mut(channel) {
+                        if let Some(mut chan) = state.channels.get(channel) {
                             let k = chan.key.take();
                             let ch_clone = chan.clone();
-                            drop(channels);
+                            drop(chan);
                             state.with_db(|db| db.save_channel(channel, &ch_clone));
                             k
                         } else {
@@ -1332,11 +1531,10 @@ pub(super) fn handle_mode(
             }
             'n' => {
                 {
-                    let mut channels = state.channels.lock();
-                    if let Some(chan) = channels.get_mut(channel) {
+                    if let Some(mut chan) = state.channels.get(channel) {
                         chan.no_ext_msg = adding;
                         let ch_clone = chan.clone();
-                        drop(channels);
+                        drop(chan);
                         state.with_db(|db| db.save_channel(channel, &ch_clone));
                     }
                 }
@@ -1348,11 +1546,10 @@ pub(super) fn handle_mode(
             }
             'm' => {
                 {
-                    let mut channels = state.channels.lock();
-                    if let Some(chan) = channels.get_mut(channel) {
+                    if let Some(mut chan) = state.channels.get(channel) {
                         chan.moderated = adding;
                         let ch_clone = chan.clone();
-                        drop(channels);
+                        drop(chan);
                         state.with_db(|db| db.save_channel(channel, &ch_clone));
                     }
                 }
@@ -1364,11 +1561,10 @@ pub(super) fn handle_mode(
             }
             'E' => {
                 {
-                    let mut channels = state.channels.lock();
-                    if let Some(chan) = channels.get_mut(channel) {
+                    if let Some(mut chan) = state.channels.get(channel) {
                         chan.encrypted_only = adding;
                         let ch_clone = chan.clone();
-                        drop(channels);
+                        drop(chan);
                         state.with_db(|db| db.save_channel(channel, &ch_clone));
                     }
                 }
@@ -1378,57 +1574,298 @@ pub(super) fn handle_mode(
                 broadcast_to_channel(state, channel, &mode_msg);
                 s2s_broadcast_mode(state, conn, channel, &format!("{sign}E"), None);
             }
-            _ => {
-                let mode_char = ch.to_string();
-                let reply = Message::from_server(
-                    server_name,
-                    irc::ERR_UNKNOWNMODE,
-                    vec![nick, &mode_char, "is unknown mode char to me"],
-                );
-                send(state, session_id, format!("{reply}\r\n"));
+            'A' => {
+                {
+                    if let Some(mut chan) = state.channels.get(channel) {
+                        chan.archived = adding;
+                        let ch_clone = chan.clone();
+                        drop(chan);
+                        state.with_db(|db| db.save_channel(channel, &ch_clone));
+                    }
+                }
+                let sign = if adding { "+" } else { "-" };
+                let hostmask = conn.hostmask();
+                let mode_msg = format!(":{hostmask} MODE {channel} {sign}A\r\n");
+                broadcast_to_channel(state, channel, &mode_msg);
+                s2s_broadcast_mode(state, conn, channel, &format!("{sign}A"), None);
             }
-        }
-    }
-}
-
-pub(super) fn handle_kick(
-    conn: &Connection,
-    channel: &str,
-    target_nick: &str,
-    reason: &str,
-    state: &Arc<SharedState>,
-    server_name: &str,
-    session_id: &str,
-    send: &impl Fn(&Arc<SharedState>, &str, String),
-) {
-    let nick = conn.nick_or_star();
-
-    // Verify kicker is in the channel and is an op or halfop
-    let (in_channel, is_op, is_halfop) = state
-        .channels
-        .lock()
-        .get(channel)
-        .map(|ch| {
-            (
-                ch.members.contains(session_id),
-                ch.ops.contains(session_id),
-                ch.halfops.contains(session_id),
-            )
-        })
-        .unwrap_or((false, false, false));
-
-    if !in_channel {
-        let reply = Message::from_server(
-            server_name,
-            irc::ERR_NOTONCHANNEL,
-            vec![nick, channel, "You're not on that channel"],
+            'u' => {
+                {
+                    if let Some(mut chan) = state.channels.get(channel) {
+                        chan.auditorium = adding;
+                        let ch_clone = chan.clone();
+                        drop(chan);
+                        state.with_db(|db| db.save_channel(channel, &ch_clone));
+                    }
+                }
+                let sign = if adding { "+" } else { "-" };
+                let hostmask = conn.hostmask();
+                let mode_msg = format!(":{hostmask} MODE {channel} {sign}u\r\n");
+                broadcast_to_channel(state, channel, &mode_msg);
+                s2s_broadcast_mode(state, conn, channel, &format!("{sign}u"), None);
+            }
+            'S' => {
+                {
+                    if let Some(mut chan) = state.channels.get(channel) {
+                        chan.no_stats = adding;
+                        let ch_clone = chan.clone();
+                        drop(chan);
+                        state.with_db(|db| db.save_channel(channel, &ch_clone));
+                    }
+                }
+                if adding {
+                    crate::stats::forget(state, channel);
+                }
+                let sign = if adding { "+" } else { "-" };
+                let hostmask = conn.hostmask();
+                let mode_msg = format!(":{hostmask} MODE {channel} {sign}S\r\n");
+                broadcast_to_channel(state, channel, &mode_msg);
+                s2s_broadcast_mode(state, conn, channel, &format!("{sign}S"), None);
+            }
+            's' => {
+                {
+                    if let Some(mut chan) = state.channels.get(channel) {
+                        chan.secret = adding;
+                        let ch_clone = chan.clone();
+                        drop(chan);
+                        state.with_db(|db| db.save_channel(channel, &ch_clone));
+                    }
+                }
+                let sign = if adding { "+" } else { "-" };
+                let hostmask = conn.hostmask();
+                let mode_msg = format!(":{hostmask} MODE {channel} {sign}s\r\n");
+                broadcast_to_channel(state, channel, &mode_msg);
+                s2s_broadcast_mode(state, conn, channel, &format!("{sign}s"), None);
+            }
+            'B' => {
+                {
+                    if let Some(mut chan) = state.channels.get(channel) {
+                        chan.bots_restricted = adding;
+                        let ch_clone = chan.clone();
+                        drop(chan);
+                        state.with_db(|db| db.save_channel(channel, &ch_clone));
+                    }
+                }
+                let sign = if adding { "+" } else { "-" };
+                let hostmask = conn.hostmask();
+                let mode_msg = format!(":{hostmask} MODE {channel} {sign}B\r\n");
+                broadcast_to_channel(state, channel, &mode_msg);
+                s2s_broadcast_mode(state, conn, channel, &format!("{sign}B"), None);
+            }
+            'W' => {
+                use crate::server::BanEntry;
+
+                if !adding && mode_arg.is_none() {
+                    // -W with no arg is invalid, ignore
+                    return;
+                }
+
+                if adding && mode_arg.is_none() {
+                    // +W with no arg: list the bot allowlist
+                    if let Some(chan) = state.channels.get(channel) {
+                        for entry in &chan.bot_allowlist {
+                            let reply = Message::from_server(
+                                server_name,
+                                irc::RPL_QUIETLIST,
+                                vec![
+                                    nick,
+                                    channel,
+                                    "W",
+                                    &entry.mask,
+                                    &entry.set_by,
+                                    &entry.set_at.to_string(),
+                                ],
+                            );
+                            send(state, session_id, format!("{reply}\r\n"));
+                        }
+                    }
+                    let end = Message::from_server(
+                        server_name,
+                        irc::RPL_ENDOFQUIETLIST,
+                        vec![nick, channel, "W", "End of channel bot allowlist"],
+                    );
+                    send(state, session_id, format!("{end}\r\n"));
+                    return;
+                }
+
+                let mask = mode_arg.unwrap().trim();
+                if mask.is_empty() {
+                    return;
+                }
+                if adding {
+                    let entry = BanEntry::new(mask.to_string(), conn.hostmask());
+                    if let Some(mut chan) = state.channels.get(channel) {
+                        if chan.bot_allowlist.len() >= MAX_BOT_ALLOWLIST_PER_CHANNEL {
+                            drop(chan);
+                            let reply = Message::from_server(
+                                server_name,
+                                "478",
+                                vec![nick, channel, "Channel bot allowlist is full"],
+                            );
+                            send(state, session_id, format!("{reply}\r\n"));
+                            return;
+                        }
+                        if !chan.bot_allowlist.iter().any(|w| w.mask == mask) {
+                            chan.bot_allowlist.push(entry.clone());
+                            drop(chan);
+                            state.with_db(|db| db.add_bot_allow(channel, &entry));
+                        }
+                    }
+                } else {
+                    if let Some(mut chan) = state.channels.get(channel) {
+                        chan.bot_allowlist.retain(|w| w.mask != mask);
+                    }
+                    state.with_db(|db| db.remove_bot_allow(channel, mask));
+                }
+
+                let sign = if adding { "+" } else { "-" };
+                let hostmask = conn.hostmask();
+                let mode_msg = format!(":{hostmask} MODE {channel} {sign}W {mask}\r\n");
+                broadcast_to_channel(state, channel, &mode_msg);
+                s2s_broadcast_mode(state, conn, channel, &format!("{sign}W"), Some(mask));
+            }
+            'H' => {
+                let visibility = if adding {
+                    let Some(arg) = mode_arg else {
+                        let reply = Message::from_server(
+                            server_name,
+                            irc::ERR_NEEDMOREPARAMS,
+                            vec![nick, "MODE", "Not enough parameters"],
+                        );
+                        send(state, session_id, format!("{reply}\r\n"));
+                        return;
+                    };
+                    let Some(visibility) = HistoryVisibility::parse(arg) else {
+                        let reply = Message::from_server(
+                            server_name,
+                            irc::ERR_INVALIDMODEPARAM,
+                            vec![
+                                nick,
+                                channel,
+                                "H",
+                                arg,
+                                "Expected members-since-join, members-full or public",
+                            ],
+                        );
+                        send(state, session_id, format!("{reply}\r\n"));
+                        return;
+                    };
+                    visibility
+                } else {
+                    HistoryVisibility::default()
+                };
+                set_history_visibility(conn, channel, visibility, state);
+            }
+            'T' => {
+                let temporary = if adding {
+                    let Some(arg) = mode_arg else {
+                        let reply = Message::from_server(
+                            server_name,
+                            irc::ERR_NEEDMOREPARAMS,
+                            vec![nick, "MODE", "Not enough parameters"],
+                        );
+                        send(state, session_id, format!("{reply}\r\n"));
+                        return;
+                    };
+                    let Some(temporary) = Temporary::parse(arg) else {
+                        let reply = Message::from_server(
+                            server_name,
+                            irc::ERR_INVALIDMODEPARAM,
+                            vec![
+                                nick,
+                                channel,
+                                "T",
+                                arg,
+                                "Expected a TTL from 5m to 30d, e.g. 2h or 2h!",
+                            ],
+                        );
+                        send(state, session_id, format!("{reply}\r\n"));
+                        return;
+                    };
+                    Some(temporary)
+                } else {
+                    None
+                };
+                let exempt = {
+                    if let Some(mut chan) = state.channels.get(channel) {
+                        chan.temporary = temporary;
+                        chan.empty_since = None;
+                        chan.expiry_warned = false;
+                        let exempt = temporary.is_some_and(|t| !t.applies(&chan));
+                        let ch_clone = chan.clone();
+                        drop(chan);
+                        state.with_db(|db| db.save_channel(channel, &ch_clone));
+                        exempt
+                    } else {
+                        false
+                    }
+                };
+                let hostmask = conn.hostmask();
+                match temporary {
+                    Some(temporary) => {
+                        let arg = temporary.to_string();
+                        let mode_msg = format!(":{hostmask} MODE {channel} +T {arg}\r\n");
+                        broadcast_to_channel(state, channel, &mode_msg);
+                        s2s_broadcast_mode(state, conn, channel, "+T", Some(&arg));
+                    }
+                    None => {
+                        let mode_msg = format!(":{hostmask} MODE {channel} -T\r\n");
+                        broadcast_to_channel(state, channel, &mode_msg);
+                        s2s_broadcast_mode(state, conn, channel, "-T", None);
+                    }
+                }
+                if exempt {
+                    let text = format!(
+                        "{channel} has a founder, so it won't expire; set +T <ttl>! to expire it anyway"
+                    );
+                    let reply = Message::from_server(server_name, "NOTICE", vec![nick, &text]);
+                    send(state, session_id, format!("{reply}\r\n"));
+                }
+            }
+            _ => {
+                let mode_char = ch.to_string();
+                let reply = Message::from_server(
+                    server_name,
+                    irc::ERR_UNKNOWNMODE,
+                    vec![nick, &mode_char, "is unknown mode char to me"],
+                );
+                send(state, session_id, format!("{reply}\r\n"));
+            }
+        }
+    }
+}
+
+pub(super) fn handle_kick(
+    conn: &Connection,
+    channel: &str,
+    target_nick: &str,
+    reason: &str,
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    send: &impl Fn(&Arc<SharedState>, &str, String),
+) {
+    let nick = conn.nick_or_star();
+
+    // Verify kicker is in the channel and may kick (halfop or above)
+    let (in_channel, rank) = state
+        .channels
+        .get(channel)
+        .map(|ch| (ch.members.contains(session_id), ch.rank(session_id)))
+        .unwrap_or((false, ChannelRole::Member));
+
+    if !in_channel {
+        let reply = Message::from_server(
+            server_name,
+            irc::ERR_NOTONCHANNEL,
+            vec![nick, channel, "You're not on that channel"],
         );
         send(state, session_id, format!("{reply}\r\n"));
         return;
     }
 
     let is_server_oper = state.server_opers.lock().contains(session_id);
-    if !is_op && !is_halfop && !is_server_oper {
+    if !rank.can(ChannelPrivilege::Kick) && !is_server_oper {
         let reply = Message::from_server(
             server_name,
             irc::ERR_CHANOPRIVSNEEDED,
@@ -1438,35 +1875,26 @@ pub(super) fn handle_kick(
         return;
     }
 
-    // Halfops cannot kick ops or other halfops
-    if is_halfop && !is_op && !is_server_oper {
-        let target_is_protected = state
-            .channels
-            .lock()
-            .get(channel)
-            .map(|ch| {
-                // Find target session ID
-                let n2s = state.nick_to_session.lock();
-                n2s.get_session(target_nick)
-                    .map(|sid| ch.ops.contains(sid) || ch.halfops.contains(sid))
-                    .unwrap_or(false)
-            })
-            .unwrap_or(false);
+    // Resolve target via federated channel roster
+    use super::helpers::{
+        ChannelTarget, protected_role, protected_role_name, resolve_channel_target,
+    };
+    let target = resolve_channel_target(state, channel, target_nick);
 
-        if target_is_protected {
-            let reply = Message::from_server(
-                server_name,
-                irc::ERR_CHANOPRIVSNEEDED,
-                vec![nick, channel, "Cannot kick a channel operator or moderator"],
-            );
-            send(state, session_id, format!("{reply}\r\n"));
-            return;
-        }
+    // Role hierarchy: halfops only kick plain and voiced members, ops
+    // can't kick admins or founders, admins can't kick founders
+    if !is_server_oper && let Some(role) = protected_role(state, channel, session_id, &target) {
+        let text = format!("Cannot kick {}", protected_role_name(role));
+        let reply = Message::from_server(
+            server_name,
+            irc::ERR_CHANOPRIVSNEEDED,
+            vec![nick, channel, &text],
+        );
+        send(state, session_id, format!("{reply}\r\n"));
+        return;
     }
 
-    // Resolve target via federated channel roster
-    use super::helpers::{ChannelTarget, resolve_channel_target};
-    match resolve_channel_target(state, channel, target_nick) {
+    match target {
         ChannelTarget::Local {
             session_id: target_session,
         } => {
@@ -1477,12 +1905,14 @@ pub(super) fn handle_kick(
 
             // Remove target from channel
             {
-                let mut channels = state.channels.lock();
-                if let Some(ch) = channels.get_mut(channel) {
+                if let Some(mut ch) = state.channels.get(channel) {
                     ch.members.remove(&target_session);
+                    ch.member_since.remove(&target_session);
                     ch.ops.remove(&target_session);
                     ch.voiced.remove(&target_session);
                     ch.halfops.remove(&target_session);
+                    ch.founders.remove(&target_session);
+                    ch.admins.remove(&target_session);
                 }
             }
 
@@ -1493,16 +1923,13 @@ pub(super) fn handle_kick(
             // is still a member (multi-device: only this device was kicked).
             let victim_did = state.session_dids.lock().get(&target_session).cloned();
             if let Some(did) = victim_did {
-                let other_session_still_member = {
-                    let did_sessions = state.did_sessions.lock();
-                    let channels = state.channels.lock();
-                    match (did_sessions.get(&did), channels.get(channel)) {
-                        (Some(sessions), Some(ch)) => sessions
+                let other_session_still_member = state.channels.get(channel).is_some_and(|ch| {
+                    state.did_sessions.lock().get(&did).is_some_and(|sessions| {
+                        sessions
                             .iter()
-                            .any(|sid| sid != &target_session && ch.members.contains(sid)),
-                        _ => false,
-                    }
-                };
+                            .any(|sid| sid != &target_session && ch.members.contains(sid))
+                    })
+                });
                 if !other_session_still_member {
                     let did_owned = did.clone();
                     let channel_owned = channel.to_string();
@@ -1519,8 +1946,7 @@ pub(super) fn handle_kick(
 
             // Remove from our remote_members tracking (case-insensitive)
             {
-                let mut channels = state.channels.lock();
-                if let Some(ch) = channels.get_mut(channel) {
+                if let Some(mut ch) = state.channels.get(channel) {
                     ch.remove_remote_member(target_nick);
                 }
             }
@@ -1564,19 +1990,18 @@ pub(super) fn handle_invite(
 ) {
     let nick = conn.nick_or_star();
 
-    // Verify inviter is in the channel and is an op
-    let (in_channel, is_op, is_invite_only) = state
+    // Verify inviter is in the channel
+    let (in_channel, rank, is_invite_only) = state
         .channels
-        .lock()
         .get(channel)
         .map(|ch| {
             (
                 ch.members.contains(session_id),
-                ch.ops.contains(session_id),
+                ch.rank(session_id),
                 ch.invite_only,
             )
         })
-        .unwrap_or((false, false, false));
+        .unwrap_or((false, ChannelRole::Member, false));
 
     if !in_channel {
         let reply = Message::from_server(
@@ -1590,7 +2015,7 @@ pub(super) fn handle_invite(
 
     // If channel is +i, only ops can invite
     let is_server_oper = state.server_opers.lock().contains(session_id);
-    if is_invite_only && !is_op && !is_server_oper {
+    if is_invite_only && !rank.can(ChannelPrivilege::Invite) && !is_server_oper {
         let reply = Message::from_server(
             server_name,
             irc::ERR_CHANOPRIVSNEEDED,
@@ -1610,9 +2035,8 @@ pub(super) fn handle_invite(
         } => {
             // Add invite by session ID + DID (with limit)
             let s2s_invitee = {
-                let mut channels = state.channels.lock();
                 let did = state.session_dids.lock().get(&target_sid).cloned();
-                if let Some(ch) = channels.get_mut(channel) {
+                if let Some(mut ch) = state.channels.get(channel) {
                     const MAX_INVITES: usize = 500;
                     if ch.invites.len() < MAX_INVITES {
                         ch.invites.insert(target_sid.clone());
@@ -1632,8 +2056,8 @@ pub(super) fn handle_invite(
             // Notify target
             let hostmask = conn.hostmask();
             let invite_msg = format!(":{hostmask} INVITE {target_nick} {channel}\r\n");
-            if let Some(tx) = state.connections.lock().get(&target_sid) {
-                let _ = tx.try_send(invite_msg);
+            if let Some(tx) = state.connections.get(&target_sid) {
+                let _ = tx.try_send(invite_msg.into());
             }
 
             // Broadcast invite to S2S peers
@@ -1652,8 +2076,7 @@ pub(super) fn handle_invite(
         NetworkTarget::Remote(rm) => {
             // Add invite by DID if available (so it survives reconnect/rejoin)
             let s2s_invitee = {
-                let mut channels = state.channels.lock();
-                if let Some(ch) = channels.get_mut(channel) {
+                if let Some(mut ch) = state.channels.get(channel) {
                     if let Some(ref did) = rm.did {
                         ch.invites.insert(did.clone());
                     }
@@ -1709,7 +2132,6 @@ pub(super) fn handle_topic(
     // Verify user is in the channel
     let in_channel = state
         .channels
-        .lock()
         .get(channel)
         .map(|ch| ch.members.contains(session_id))
         .unwrap_or(false);
@@ -1726,6 +2148,58 @@ pub(super) fn handle_topic(
 
     match new_topic {
         Some(text) => {
+            let (may_set_locked, is_locked) = state
+                .channels
+                .get(channel)
+                .map(|ch| {
+                    (
+                        ch.rank(session_id).can(ChannelPrivilege::Topic),
+                        ch.topic_locked,
+                    )
+                })
+                .unwrap_or((false, false));
+            let is_server_oper = state.server_opers.lock().contains(session_id);
+
+            // `--revert <n>`: ops and halfops restore the nth previous
+            // topic, numbered as in TOPICHIST. Allowed even without +t.
+            let reverted;
+            let text = match text.strip_prefix("--revert") {
+                Some(arg) if arg.is_empty() || arg.starts_with(' ') => {
+                    if !may_set_locked && !is_server_oper {
+                        let reply = Message::from_server(
+                            server_name,
+                            irc::ERR_CHANOPRIVSNEEDED,
+                            vec![nick, channel, "You're not channel operator"],
+                        );
+                        send(state, session_id, format!("{reply}\r\n"));
+                        return;
+                    }
+                    let previous = arg.trim().parse::<usize>().ok().and_then(|n| {
+                        state
+                            .channels
+                            .get(channel)
+                            .and_then(|ch| ch.previous_topic(n).map(|t| t.text.clone()))
+                    });
+                    let Some(previous) = previous else {
+                        let reply = Message::from_server(
+                            server_name,
+                            "FAIL",
+                            vec![
+                                "TOPIC",
+                                "INVALID_INDEX",
+                                channel,
+                                "No such entry in TOPICHIST",
+                            ],
+                        );
+                        send(state, session_id, format!("{reply}\r\n"));
+                        return;
+                    };
+                    reverted = previous;
+                    reverted.as_str()
+                }
+                _ => text,
+            };
+
             // Enforce topic length limit to prevent memory abuse.
             if text.len() > 512 {
                 let reply = Message::from_server(
@@ -1736,16 +2210,8 @@ pub(super) fn handle_topic(
                 send(state, session_id, format!("{reply}\r\n"));
                 return;
             }
-            // Check +t: if topic_locked, only ops can set topic
-            let (is_op, is_locked) = {
-                let channels = state.channels.lock();
-                channels
-                    .get(channel)
-                    .map(|ch| (ch.ops.contains(session_id), ch.topic_locked))
-                    .unwrap_or((false, false))
-            };
-            let is_server_oper = state.server_opers.lock().contains(session_id);
-            if is_locked && !is_op && !is_server_oper {
+            // Check +t: if topic_locked, only halfops and above can set topic
+            if is_locked && !may_set_locked && !is_server_oper {
                 let reply = Message::from_server(
                     server_name,
                     irc::ERR_CHANOPRIVSNEEDED,
@@ -1759,13 +2225,13 @@ pub(super) fn handle_topic(
             let topic = TopicInfo::new(text.to_string(), conn.hostmask());
 
             // Store it
-            state
+            let history_changed = state
                 .channels
-                .lock()
-                .entry(channel.to_string())
-                .and_modify(|ch| {
-                    ch.topic = Some(topic);
-                });
+                .get(channel)
+                .is_some_and(|mut ch| ch.set_topic(topic));
+            if history_changed {
+                state.persist_topic_history(channel);
+            }
 
             // CRDT update (async, source of truth for topic convergence)
             {
@@ -1783,26 +2249,24 @@ pub(super) fn handle_topic(
 
             // Persist channel state
             {
-                let channels = state.channels.lock();
-                if let Some(ch) = channels.get(channel) {
+                if let Some(ch) = state.channels.get(channel) {
                     let ch_clone = ch.clone();
-                    drop(channels);
+                    drop(ch);
                     state.with_db(|db| db.save_channel(channel, &ch_clone));
                 }
             }
 
             // Broadcast TOPIC change to all channel members
             let hostmask = conn.hostmask();
-            let topic_msg = format!(":{hostmask} TOPIC {channel} :{text}\r\n");
+            let topic_msg = WireLine::from(format!(":{hostmask} TOPIC {channel} :{text}\r\n"));
 
             let members: Vec<String> = state
                 .channels
-                .lock()
                 .get(channel)
                 .map(|ch| ch.members.iter().cloned().collect())
                 .unwrap_or_default();
 
-            let conns = state.connections.lock();
+            let conns = &state.connections;
             for member_session in &members {
                 if let Some(tx) = conns.get(member_session) {
                     let _ = tx.try_send(topic_msg.clone());
@@ -1824,8 +2288,7 @@ pub(super) fn handle_topic(
         }
         None => {
             // Query the topic
-            let channels = state.channels.lock();
-            if let Some(ch) = channels.get(channel) {
+            if let Some(ch) = state.channels.get(channel) {
                 if let Some(ref topic) = ch.topic {
                     let rpl = Message::from_server(
                         server_name,
@@ -1853,6 +2316,125 @@ pub(super) fn handle_topic(
     }
 }
 
+/// Handle TOPICHIST: list the current and previous topics of a channel,
+/// numbered for `TOPIC <chan> --revert <n>`. Members only.
+pub(super) fn handle_topichist(
+    conn: &Connection,
+    channel: &str,
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    send: &impl Fn(&Arc<SharedState>, &str, String),
+) {
+    let nick = conn.nick_or_star();
+    let notice = |text: &str| {
+        let reply = Message::from_server(server_name, "NOTICE", vec![nick, text]);
+        send(state, session_id, format!("{reply}\r\n"));
+    };
+
+    let entries = match state.channels.get(channel) {
+        Some(ch) if ch.members.contains(session_id) => {
+            let mut entries: Vec<_> = ch.topic.iter().cloned().collect();
+            entries.extend(ch.topic_history.iter().rev().cloned());
+            Some((ch.topic.is_some(), entries))
+        }
+        _ => None,
+    };
+    let Some((has_current, entries)) = entries else {
+        let reply = Message::from_server(
+            server_name,
+            irc::ERR_NOTONCHANNEL,
+            vec![nick, channel, "You're not on that channel"],
+        );
+        send(state, session_id, format!("{reply}\r\n"));
+        return;
+    };
+
+    if entries.is_empty() {
+        notice(&format!("{channel} has no topic history"));
+        return;
+    }
+    // Entry 0 is the current topic; previous topics count up from 1.
+    let first = if has_current { 0 } else { 1 };
+    for (i, t) in entries.iter().enumerate() {
+        let when = chrono::DateTime::from_timestamp(t.set_at as i64, 0)
+            .map(|d| d.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+            .unwrap_or_default();
+        notice(&format!(
+            "{channel} [{}] {when} by {}: {}",
+            first + i,
+            t.set_by,
+            t.text
+        ));
+    }
+    notice(&format!(
+        "End of TOPICHIST {channel} — ops can use TOPIC {channel} --revert <n>"
+    ));
+}
+
+/// `STATS <channel>`: the channel's activity over the last
+/// [`STATS_DAYS`](crate::stats::STATS_DAYS) days, as NOTICEs. Channel ops
+/// and server opers only.
+pub(super) fn handle_stats(
+    conn: &Connection,
+    channel: &str,
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    send: &impl Fn(&Arc<SharedState>, &str, String),
+) {
+    let nick = conn.nick_or_star();
+    let notice = |text: &str| {
+        let reply = Message::from_server(server_name, "NOTICE", vec![nick, text]);
+        send(state, session_id, format!("{reply}\r\n"));
+    };
+
+    let Some((is_op, no_stats)) = state
+        .channels
+        .get(channel)
+        .map(|ch| (ch.ops.contains(session_id), ch.no_stats))
+    else {
+        let reply = Message::from_server(
+            server_name,
+            irc::ERR_NOSUCHCHANNEL,
+            vec![nick, channel, "No such channel"],
+        );
+        send(state, session_id, format!("{reply}\r\n"));
+        return;
+    };
+    let is_server_oper = state.server_opers.lock().contains(session_id);
+    if !is_op && !is_server_oper {
+        let reply = Message::from_server(
+            server_name,
+            irc::ERR_CHANOPRIVSNEEDED,
+            vec![nick, channel, "You're not channel operator"],
+        );
+        send(state, session_id, format!("{reply}\r\n"));
+        return;
+    }
+    if no_stats {
+        notice(&format!("Statistics are disabled for {channel} (+S)"));
+        return;
+    }
+    let Some(report) = crate::stats::report(state, channel, crate::stats::STATS_DAYS) else {
+        notice("Channel statistics need a database (--db-path)");
+        return;
+    };
+
+    for day in &report.days {
+        notice(&format!(
+            "{channel} {}: {} messages, {} active DIDs, peak {} members",
+            day.date, day.messages, day.active_dids, day.peak_members
+        ));
+    }
+    notice(&format!(
+        "{channel}: {} unique active DIDs in the last {} days",
+        report.active_dids_week,
+        crate::stats::ACTIVE_WINDOW_DAYS
+    ));
+    notice(&format!("End of STATS {channel}"));
+}
+
 pub(super) fn handle_part(
     conn: &Connection,
     channel: &str,
@@ -1866,7 +2448,6 @@ pub(super) fn handle_part(
     // Verify user is in the channel
     let in_channel = state
         .channels
-        .lock()
         .get(channel)
         .map(|ch| ch.members.contains(session_id))
         .unwrap_or(false);
@@ -1881,29 +2462,32 @@ pub(super) fn handle_part(
     }
 
     let hostmask = conn.hostmask();
-    let part_msg = format!(":{hostmask} PART {channel}\r\n");
+    let part_msg = WireLine::from(format!(":{hostmask} PART {channel}\r\n"));
 
     let members: Vec<String> = state
         .channels
-        .lock()
         .get(channel)
-        .map(|ch| ch.members.iter().cloned().collect())
+        .map(|ch| ch.audience(session_id))
         .unwrap_or_default();
 
-    let conns = state.connections.lock();
+    let conns = &state.connections;
     for member_session in &members {
         if let Some(tx) = conns.get(member_session) {
             let _ = tx.try_send(part_msg.clone());
         }
     }
-    drop(conns);
 
+    if let Some(mut ch) = state.channels.get(channel) {
+        ch.members.remove(session_id);
+        ch.member_since.remove(session_id);
+    }
     state
-        .channels
-        .lock()
-        .entry(channel.to_string())
-        .and_modify(|ch| {
-            ch.members.remove(session_id);
+        .firehose
+        .publish(crate::firehose::FirehoseEvent::Part {
+            session: session_id.to_string(),
+            nick: nick.to_string(),
+            channel: channel.to_string(),
+            did: conn.authenticated_did.clone(),
         });
 
     // NOTE: Presence is NOT in CRDT (avoids ghost users on crash)
@@ -1915,16 +2499,13 @@ pub(super) fn handle_part(
     // "I left on web but iOS keeps showing it / can't get rid of it"
     // failure mode).
     if let Some(ref did) = conn.authenticated_did {
-        let other_session_still_member = {
-            let did_sessions = state.did_sessions.lock();
-            let channels = state.channels.lock();
-            match (did_sessions.get(did), channels.get(channel)) {
-                (Some(sessions), Some(ch)) => sessions
+        let other_session_still_member = state.channels.get(channel).is_some_and(|ch| {
+            state.did_sessions.lock().get(did).is_some_and(|sessions| {
+                sessions
                     .iter()
-                    .any(|sid| sid != session_id && ch.members.contains(sid)),
-                _ => false,
-            }
-        };
+                    .any(|sid| sid != session_id && ch.members.contains(sid))
+            })
+        });
         if !other_session_still_member {
             let did_owned = did.clone();
             let channel_owned = channel.to_string();
@@ -1946,88 +2527,246 @@ pub(super) fn handle_part(
     );
 }
 
+/// In +u, a member whose rank crossed the voice line appears to or
+/// vanishes from ordinary members: send them a JOIN or PART for it.
+/// Apply a history visibility (+H) change: persist it and announce it
+/// locally and to peers. `members-full` is announced as `-H`. Shared by
+/// MODE and `POLICY <channel> HISTORY`.
+pub(super) fn set_history_visibility(
+    conn: &Connection,
+    channel: &str,
+    visibility: HistoryVisibility,
+    state: &Arc<SharedState>,
+) {
+    if let Some(mut chan) = state.channels.get(channel) {
+        chan.history_visibility = visibility;
+        let ch_clone = chan.clone();
+        drop(chan);
+        state.with_db(|db| db.save_channel(channel, &ch_clone));
+    }
+    let hostmask = conn.hostmask();
+    if visibility == HistoryVisibility::default() {
+        let mode_msg = format!(":{hostmask} MODE {channel} -H\r\n");
+        broadcast_to_channel(state, channel, &mode_msg);
+        s2s_broadcast_mode(state, conn, channel, "-H", None);
+    } else {
+        let arg = visibility.as_str();
+        let mode_msg = format!(":{hostmask} MODE {channel} +H {arg}\r\n");
+        broadcast_to_channel(state, channel, &mode_msg);
+        s2s_broadcast_mode(state, conn, channel, "+H", Some(arg));
+    }
+}
+
+pub(super) fn auditorium_reveal(
+    state: &Arc<SharedState>,
+    channel: &str,
+    target: &str,
+    was_shown: bool,
+) {
+    let Some(ch) = state.channels.get(channel) else {
+        return;
+    };
+    let shown = ch.rank(target) >= ChannelRole::Voice;
+    if !ch.auditorium || shown == was_shown {
+        return;
+    }
+    let viewers: Vec<String> = ch
+        .members
+        .iter()
+        .filter(|s| s.as_str() != target && !ch.sees_all_members(s))
+        .cloned()
+        .collect();
+    drop(ch);
+    let Some(nick) = state
+        .nick_to_session
+        .lock()
+        .get_nick(target)
+        .map(str::to_string)
+    else {
+        return;
+    };
+    let host = match state.session_dids.lock().get(target) {
+        Some(did) => super::cloak_did(did),
+        None => "freeq/guest".to_string(),
+    };
+    let hostmask = format!("{nick}!~u@{host}");
+    let line = WireLine::from(if shown {
+        make_standard_join(&hostmask, channel)
+    } else {
+        format!(":{hostmask} PART {channel}\r\n")
+    });
+    for viewer in &viewers {
+        if let Some(tx) = state.connections.get(viewer) {
+            let _ = tx.try_send(line.clone());
+        }
+    }
+}
+
+/// Most names an ordinary member of a +u channel is sent in NAMES.
+const AUDITORIUM_NAMES_MAX: usize = 100;
+
+/// A channel's NAMES roster as one viewer sees it.
+#[derive(Default)]
+struct Roster {
+    /// `(session, prefix)` for local members.
+    local: Vec<(String, String)>,
+    /// `(nick, prefix)` for remote members.
+    remote: Vec<(String, String)>,
+    /// The full member count, when +u left members out.
+    total: Option<usize>,
+}
+
+impl Roster {
+    fn end_text(&self, selection: &names::Selection) -> String {
+        match (self.total, selection.truncated_from) {
+            (Some(total), _) => format!("End of /NAMES list ({total} members)"),
+            (None, Some(total)) => format!(
+                "End of /NAMES list (showing {} of {total} members)",
+                selection.names.len()
+            ),
+            (None, None) => "End of /NAMES list".to_string(),
+        }
+    }
+}
+
+/// The NAMES roster of `channel` for `viewer`. In +u, members without
+/// voice see only voiced-and-above members and themselves, capped at
+/// [`AUDITORIUM_NAMES_MAX`], plus the total count. A +s channel is empty
+/// to non-members.
+fn roster(state: &SharedState, channel: &str, viewer: &str, multi_prefix: bool) -> Roster {
+    let Some(ch) = state
+        .channels
+        .get(channel)
+        .filter(|ch| !ch.hidden_from(viewer))
+    else {
+        return Roster::default();
+    };
+    let full = ch.sees_all_members(viewer);
+    let mut local: Vec<(String, String)> = ch
+        .members
+        .iter()
+        .filter(|s| full || s.as_str() == viewer || ch.rank(s) >= ChannelRole::Voice)
+        .map(|s| (s.clone(), ch.prefix(s, multi_prefix)))
+        .collect();
+    let mut remote: Vec<(String, String)> = ch
+        .remote_members
+        .iter()
+        .filter(|(_, rm)| full || ch.remote_rank(rm) >= ChannelRole::Voice)
+        .map(|(nick, rm)| {
+            let prefix = ch.remote_rank(rm).prefix();
+            (nick.clone(), prefix.map(String::from).unwrap_or_default())
+        })
+        .collect();
+    if full {
+        return Roster {
+            local,
+            remote,
+            total: None,
+        };
+    }
+    // The viewer first, so truncation never drops them from their own list.
+    local.sort_by_key(|(s, _)| s != viewer);
+    local.truncate(AUDITORIUM_NAMES_MAX);
+    remote.truncate(AUDITORIUM_NAMES_MAX - local.len());
+    Roster {
+        local,
+        remote,
+        total: Some(ch.members.len() + ch.remote_members.len()),
+    }
+}
+
+/// `NAMES <channel> [LIMIT <n>] [AFTER <nick>]` (see [`names`]).
 pub(super) fn handle_names(
     conn: &Connection,
     channel: &str,
+    params: &[String],
     state: &Arc<SharedState>,
     server_name: &str,
     session_id: &str,
     send: &impl Fn(&Arc<SharedState>, &str, String),
 ) {
     let nick = conn.nick_or_star();
-    let multi_prefix = state.cap_multi_prefix.lock().contains(session_id);
-
-    let nick_list: Vec<String> = {
-        let channels = state.channels.lock();
-        let (member_sessions, remote_members, ops, voiced) = match channels.get(channel) {
-            Some(ch) => (
-                ch.members.clone(),
-                ch.remote_members.clone(),
-                ch.ops.clone(),
-                ch.voiced.clone(),
-            ),
-            None => Default::default(),
-        };
-        drop(channels);
+    match names::Page::parse(params) {
+        Ok(page) => send_names(state, server_name, session_id, nick, channel, page.as_ref()),
+        Err(reason) => {
+            let reply = Message::from_server(
+                server_name,
+                "FAIL",
+                vec!["NAMES", "INVALID_PARAMS", channel, reason],
+            );
+            send(state, session_id, format!("{reply}\r\n"));
+        }
+    }
+}
+
+/// Send `channel`'s NAMES to `session_id`, whose nick is `nick`: one
+/// `page` of it, or without one as much as [`names::IMPLICIT_MAX`] allows.
+pub(super) fn send_names(
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    nick: &str,
+    channel: &str,
+    page: Option<&names::Page>,
+) {
+    let multi_prefix = state.sessions.has_cap(session_id, Cap::MultiPrefix);
+    let roster = roster(state, channel, session_id, multi_prefix);
+    let members: Vec<(String, String)> = {
+        // Local members: look up nick from session ID (deduplicated for multi-device)
         let nicks = state.nick_to_session.lock();
         let mut seen_nicks = std::collections::HashSet::new();
-        let mut list: Vec<String> = member_sessions
+        let mut list: Vec<(String, String)> = roster
+            .local
             .iter()
-            .filter_map(|s| {
-                nicks.get_nick(s).and_then(|n| {
-                    // Deduplicate by nick (multi-device: same nick, multiple sessions)
-                    let nick_lower = n.to_lowercase();
+            .filter_map(|(s, prefix)| {
+                let nick_result = nicks.get_nick(s);
+                if nick_result.is_none() {
+                    tracing::warn!(
+                        channel = %channel,
+                        session = %s,
+                        "NAMES: session in ch.members but not in nick_to_session"
+                    );
+                }
+                nick_result.and_then(|n| {
+                    let nick_lower = crate::casemap::fold(n);
                     if !seen_nicks.insert(nick_lower) {
                         return None;
                     }
-                    let prefix = if multi_prefix {
-                        let mut p = String::new();
-                        if ops.contains(s) {
-                            p.push('@');
-                        }
-                        if voiced.contains(s) {
-                            p.push('+');
-                        }
-                        p
-                    } else if ops.contains(s) {
-                        "@".to_string()
-                    } else if voiced.contains(s) {
-                        "+".to_string()
-                    } else {
-                        String::new()
-                    };
-                    Some(format!("{prefix}{n}"))
+                    Some((prefix.clone(), n.to_string()))
                 })
             })
             .collect();
-        let channels_lock = state.channels.lock();
-        let ch_state = channels_lock.get(channel);
-        for (nick, rm) in &remote_members {
-            let is_op = rm.is_op
-                || rm.did.as_ref().is_some_and(|d| {
-                    ch_state.is_some_and(|ch| {
-                        ch.founder_did.as_deref() == Some(d.as_str()) || ch.did_ops.contains(d)
-                    })
-                });
-            let prefix = if is_op { "@" } else { "" };
-            list.push(format!("{prefix}{nick}"));
+        if list.is_empty() && !roster.local.is_empty() {
+            tracing::warn!(
+                channel = %channel,
+                member_count = roster.local.len(),
+                "NAMES: all members resolved to empty list!"
+            );
         }
-        drop(channels_lock);
+        // Remote members from S2S peers
+        drop(nicks);
+        list.extend(
+            roster
+                .remote
+                .iter()
+                .map(|(n, prefix)| (prefix.clone(), n.clone())),
+        );
         list
     };
 
-    let names = irc::Message::from_server(
-        server_name,
-        irc::RPL_NAMREPLY,
-        vec![nick, "=", channel, &nick_list.join(" ")],
-    );
-    let end_names = irc::Message::from_server(
+    let selection = names::select(members, nick, page);
+    let lines = names::reply_lines(server_name, nick, channel, &selection.names);
+    let mut end = Vec::new();
+    if let Some((cursor, remaining)) = &selection.more {
+        end.push(names::more_note(server_name, channel, cursor, *remaining));
+    }
+    let end_names = Message::from_server(
         server_name,
         irc::RPL_ENDOFNAMES,
-        vec![nick, channel, "End of /NAMES list"],
+        vec![nick, channel, &roster.end_text(&selection)],
     );
-    send(state, session_id, format!("{names}\r\n"));
-    send(state, session_id, format!("{end_names}\r\n"));
+    end.push(format!("{end_names}\r\n"));
+    names::send_paced(state, session_id, lines, end);
 }
 
 pub(super) fn handle_list(
@@ -2038,14 +2777,11 @@ pub(super) fn handle_list(
     send: &impl Fn(&Arc<SharedState>, &str, String),
 ) {
     let nick = conn.nick_or_star();
-    let channels = state.channels.lock();
-    for (name, ch) in channels.iter() {
-        let count = ch.members.len() + ch.remote_members.len();
-        let topic = ch.topic.as_ref().map(|t| t.text.as_str()).unwrap_or("");
+    for row in crate::directory::list(state, session_id) {
         let reply = Message::from_server(
             server_name,
             irc::RPL_LIST,
-            vec![nick, name, &count.to_string(), topic],
+            vec![nick, &row.name, &row.members.to_string(), &row.topic],
         );
         send(state, session_id, format!("{reply}\r\n"));
     }
@@ -841,27 +841,24 @@ mod tests {
         Arc::new(SharedState {
             server_name: config.server_name.clone(),
             challenge_store: crate::sasl::ChallengeStore::new(60),
+            auth_failures: Mutex::new(HashMap::new()),
             did_resolver: freeq_sdk::did::DidResolver::static_map(HashMap::new()),
-            connections: Mutex::new(HashMap::new()),
+            connections: crate::sharded::ShardedMap::new(),
             nick_to_session: Mutex::new(crate::server::NickMap::new()),
             session_dids: Mutex::new(HashMap::new()),
             did_sessions: Mutex::new(HashMap::new()),
             did_nicks: Mutex::new(HashMap::new()),
             nick_owners: Mutex::new(HashMap::new()),
+            nick_skeletons: Mutex::new(HashMap::new()),
             session_handles: Mutex::new(HashMap::new()),
-            channels: Mutex::new(HashMap::new()),
-            cap_message_tags: Mutex::new(HashSet::new()),
-            cap_multi_prefix: Mutex::new(HashSet::new()),
-            cap_echo_message: Mutex::new(HashSet::new()),
-            cap_server_time: Mutex::new(HashSet::new()),
-            cap_batch: Mutex::new(HashSet::new()),
-            cap_draft_multiline: Mutex::new(HashSet::new()),
+            channels: crate::sharded::ShardedMap::new(),
+            sessions: crate::session::SessionRegistry::new(),
             open_batches: Mutex::new(HashMap::new()),
-            cap_account_notify: Mutex::new(HashSet::new()),
-            cap_extended_join: Mutex::new(HashSet::new()),
-            cap_away_notify: Mutex::new(HashSet::new()),
-            cap_account_tag: Mutex::new(HashSet::new()),
+            metadata: Mutex::new(HashMap::new()),
+            metadata_subs: Mutex::new(HashMap::new()),
             server_opers: Mutex::new(HashSet::new()),
+            oper_tokens: Mutex::new(HashMap::new()),
+            firehose: Default::default(),
             session_actor_class: Mutex::new(HashMap::new()),
             provenance_declarations: Mutex::new(HashMap::new()),
             agent_presence: Mutex::new(HashMap::new()),
@@ -871,10 +868,15 @@ mod tests {
             oauth_complete: Mutex::new(HashMap::new()),
             web_auth_tokens: Mutex::new(HashMap::new()),
             web_sessions: Mutex::new(HashMap::new()),
+            broker_devices: Mutex::new(HashMap::new()),
             login_pending: Mutex::new(HashMap::new()),
             linked_identities: Mutex::new(HashMap::new()),
             login_completions: Mutex::new(HashMap::new()),
             session_iroh_ids: Mutex::new(HashMap::new()),
+            iroh_bindings: Mutex::new(HashMap::new()),
+            content_filters: Mutex::new(crate::filters::Filters::default()),
+            invites: Mutex::new(crate::invites::Invites::default()),
+            email: crate::email::EmailNotifier::disabled(),
             session_away: Mutex::new(HashMap::new()),
             server_iroh_id: Mutex::new(Some("test-server-id".to_string())),
             iroh_endpoint: Mutex::new(None),
@@ -886,9 +888,11 @@ mod tests {
             db: None,
             config,
             plugin_manager: crate::plugin::PluginManager::new(),
+            command_aliases: crate::alias::AliasTable::default(),
             policy_engine: None,
             prekey_bundles: Mutex::new(HashMap::new()),
             msg_timestamps: Mutex::new(HashMap::new()),
+            ephemeral: Default::default(),
             ip_connections: Mutex::new(HashMap::new()),
             msg_signing_key: signing_key,
             boot_time: std::time::Instant::now(),
@@ -896,6 +900,8 @@ mod tests {
             session_msg_keys: Mutex::new(HashMap::new()),
             did_msg_keys: Mutex::new(HashMap::new()),
             session_client_info: Mutex::new(HashMap::new()),
+            session_activity: Mutex::new(HashMap::new()),
+            reclaim_attempts: Mutex::new(HashMap::new()),
             upload_tokens: Mutex::new(HashMap::new()),
             ghost_sessions: Mutex::new(HashMap::new()),
             spawned_agents: Mutex::new(HashMap::new()),
@@ -904,6 +910,7 @@ mod tests {
             liveness_probes: Mutex::new(HashMap::new()),
             session_kill: Mutex::new(HashMap::new()),
             metrics: crate::server::Metrics::default(),
+            listening: std::sync::atomic::AtomicBool::new(false),
         })
     }
 
@@ -0,0 +1,113 @@
+//! IRC EMAIL command — email notifications while offline (see
+//! [`crate::email`]).
+//!
+//! EMAIL [STATUS]
+//! EMAIL SET <address>
+//! EMAIL VERIFY <token>
+//! EMAIL OFF
+//!
+//! Needs an authenticated DID, since notifications follow the account
+//! rather than a nick. SET mails a verification link; nothing else is sent
+//! until it's followed (or its token given to VERIFY). OFF forgets the
+//! address.
+
+use crate::irc::Message;
+use crate::server::SharedState;
+use std::sync::Arc;
+
+const USAGE: &str = "Usage: EMAIL [STATUS] | SET <address> | VERIFY <token> | OFF";
+
+pub(super) fn handle_email(
+    conn: &super::Connection,
+    msg: &Message,
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    send_fn: &impl Fn(&Arc<SharedState>, &str, String),
+) {
+    let nick = conn.nick_or_star();
+    let notice = |text: &str| {
+        let reply = Message::from_server(server_name, "NOTICE", vec![nick, text]);
+        send_fn(state, session_id, format!("{reply}\r\n"));
+    };
+
+    if !state.email.enabled() {
+        notice("Email notifications are not enabled on this server");
+        return;
+    }
+    let Some(did) = conn.authenticated_did.as_deref() else {
+        notice("You must be signed in to get email notifications");
+        return;
+    };
+
+    let sub = msg
+        .params
+        .first()
+        .map(|s| s.to_uppercase())
+        .unwrap_or_else(|| "STATUS".to_string());
+    match (sub.as_str(), msg.params.get(1)) {
+        ("STATUS", _) => match state.email.subscription(did) {
+            Some(s) if s.verified => notice(&format!(
+                "Emailing {} about DMs and mentions while you're offline. EMAIL OFF to stop",
+                crate::email::mask(&s.address)
+            )),
+            Some(s) => notice(&format!(
+                "{} is waiting for verification; follow the link mailed to it",
+                crate::email::mask(&s.address)
+            )),
+            None => notice(&format!("No email address set. {USAGE}")),
+        },
+        ("SET", Some(address)) => {
+            let now = chrono::Utc::now().timestamp();
+            let subscription = match state.email.register(did, address, now) {
+                Ok(s) => s,
+                Err(e) => {
+                    notice(&e);
+                    return;
+                }
+            };
+            state.with_db(|db| db.save_email_subscription(&subscription));
+            tracing::info!(%did, "Email notification address set");
+
+            let email = crate::email::verification_email(server_name, &subscription);
+            let mail_state = Arc::clone(state);
+            tokio::spawn(async move {
+                if let Err(e) = mail_state.email.send(email).await {
+                    tracing::warn!(error = %e, "Verification email failed");
+                }
+            });
+            notice(&format!(
+                "Sent a verification link to {}; it expires in 24 hours",
+                crate::email::mask(address)
+            ));
+        }
+        ("VERIFY", Some(token)) => {
+            let now = chrono::Utc::now().timestamp();
+            match state.email.verify(token, now) {
+                Ok(subscription) if subscription.did == did => {
+                    state.with_db(|db| db.save_email_subscription(&subscription));
+                    notice(&format!(
+                        "Verified {}. You'll get a digest of DMs and mentions while you're offline",
+                        crate::email::mask(&subscription.address)
+                    ));
+                }
+                // Someone else's token: verifying it is harmless (it proves
+                // the link was received), but don't say whose it was.
+                Ok(subscription) => {
+                    state.with_db(|db| db.save_email_subscription(&subscription));
+                    notice("Verified");
+                }
+                Err(e) => notice(e),
+            }
+        }
+        ("OFF", _) => {
+            if state.email.remove(did).is_some() {
+                state.with_db(|db| db.delete_email_subscription(did));
+                notice("Email notifications turned off and your address forgotten");
+            } else {
+                notice("No email address was set");
+            }
+        }
+        _ => notice(USAGE),
+    }
+}
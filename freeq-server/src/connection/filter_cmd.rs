@@ -0,0 +1,175 @@
+//! IRC FILTER command — manage content filters (see [`crate::filters`]).
+//!
+//! FILTER LIST [<channel>|*]                            — List a channel's (or the global) filters
+//! FILTER ADD <channel>|* regex|glob <action> :<pattern> — Add a filter
+//! FILTER DEL <id>                                      — Remove a filter
+//!
+//! `<action>` is `block`, `replace[=<text>]`, `flag` or `notify`. A
+//! channel's filters are managed by its ops, founder, DID-ops and policy
+//! admins; global (`*`) filters by server operators, who may also manage
+//! any channel's.
+//! Filters persist when the server has a database.
+
+use super::helpers::normalize_channel;
+use crate::filters::{Action, GLOBAL, Rule, Syntax};
+use crate::irc::Message;
+use crate::server::SharedState;
+use std::sync::Arc;
+
+const USAGE: &str =
+    "Usage: FILTER LIST [<channel>|*] | ADD <channel>|* regex|glob <action> :<pattern> | DEL <id>";
+
+/// Whether the caller may manage `scope`'s filters.
+fn may_manage(conn: &super::Connection, state: &SharedState, scope: &str) -> bool {
+    if conn.is_oper {
+        return true;
+    }
+    if scope == GLOBAL {
+        return false;
+    }
+    let did = conn.authenticated_did.as_deref();
+    let is_op = state.channels.get(scope).is_some_and(|ch| {
+        ch.ops.contains(&conn.id)
+            || did.is_some_and(|d| ch.founder_did.as_deref() == Some(d) || ch.did_ops.contains(d))
+    });
+    is_op || did.is_some_and(|d| crate::server::is_channel_admin(state, scope, d))
+}
+
+fn describe(rule: &Rule) -> String {
+    let when = chrono::DateTime::from_timestamp(rule.created_at, 0)
+        .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+        .unwrap_or_else(|| rule.created_at.to_string());
+    format!(
+        "FILTER {} {} {} {} by {} at {when}: {}",
+        rule.id,
+        rule.scope,
+        rule.syntax.as_str(),
+        rule.action,
+        rule.created_by,
+        rule.pattern,
+    )
+}
+
+pub(super) fn handle_filter(
+    conn: &super::Connection,
+    msg: &Message,
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    send_fn: &impl Fn(&Arc<SharedState>, &str, String),
+) {
+    let nick = conn.nick_or_star();
+    let notice = |text: &str| {
+        let reply = Message::from_server(server_name, "NOTICE", vec![nick, text]);
+        send_fn(state, session_id, format!("{reply}\r\n"));
+    };
+    let scope_of = |s: &str| {
+        if s == GLOBAL {
+            GLOBAL.to_string()
+        } else {
+            normalize_channel(s)
+        }
+    };
+    let denied = |scope: &str| {
+        if scope == GLOBAL {
+            notice("Only server operators can manage global filters");
+        } else {
+            notice(&format!("Only {scope}'s operators can manage its filters"));
+        }
+    };
+
+    match msg.params.first().map(|s| s.to_uppercase()).as_deref() {
+        Some("LIST") => {
+            let scope = scope_of(msg.params.get(1).map_or(GLOBAL, |s| s.as_str()));
+            if !may_manage(conn, state, &scope) {
+                denied(&scope);
+                return;
+            }
+            let lines: Vec<String> = state
+                .content_filters
+                .lock()
+                .rules(&scope)
+                .into_iter()
+                .map(describe)
+                .collect();
+            if lines.is_empty() {
+                notice(&format!("No filters for {scope}"));
+                return;
+            }
+            for line in &lines {
+                notice(line);
+            }
+            notice(&format!("End of FILTER LIST {scope}"));
+        }
+        Some("ADD") => {
+            let (Some(scope), Some(syntax), Some(action), Some(pattern)) = (
+                msg.params.get(1),
+                msg.params.get(2),
+                msg.params.get(3),
+                msg.params.get(4),
+            ) else {
+                notice(USAGE);
+                return;
+            };
+            let scope = scope_of(scope);
+            if !may_manage(conn, state, &scope) {
+                denied(&scope);
+                return;
+            }
+            let (Some(syntax), Some(action)) = (Syntax::parse(syntax), Action::parse(action))
+            else {
+                notice("Filters are regex or glob, and block, replace[=<text>], flag or notify");
+                return;
+            };
+            let created_by = conn
+                .authenticated_did
+                .clone()
+                .unwrap_or_else(|| nick.to_string());
+            let added = {
+                let mut filters = state.content_filters.lock();
+                Rule::new(
+                    filters.next_id(),
+                    &scope,
+                    syntax,
+                    pattern,
+                    action,
+                    &created_by,
+                    chrono::Utc::now().timestamp(),
+                )
+                .and_then(|rule| filters.add(rule.clone()).map(|()| rule))
+            };
+            match added {
+                Ok(rule) => {
+                    state.with_db(|db| db.save_content_filter(&rule));
+                    tracing::info!(id = rule.id, %scope, by = %created_by, "Content filter added");
+                    notice(&format!("Added {}", describe(&rule)));
+                }
+                Err(e) => notice(&e),
+            }
+        }
+        Some("DEL") => {
+            let Some(id) = msg.params.get(1).and_then(|s| s.parse::<i64>().ok()) else {
+                notice(USAGE);
+                return;
+            };
+            let scope = state
+                .content_filters
+                .lock()
+                .get(id)
+                .map(|r| r.scope.clone());
+            let Some(scope) = scope else {
+                notice(&format!("No filter {id}"));
+                return;
+            };
+            if !may_manage(conn, state, &scope) {
+                denied(&scope);
+                return;
+            }
+            state.content_filters.lock().remove(id);
+            state.with_db(|db| db.delete_content_filter(id));
+            tracing::info!(id, %scope, by = %nick, "Content filter removed");
+            notice(&format!("Removed filter {id} from {scope}"));
+        }
+        _ => notice(USAGE),
+    }
+}
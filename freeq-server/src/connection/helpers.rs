@@ -1,7 +1,9 @@
 #![allow(clippy::too_many_arguments)]
 //! Helper functions for broadcasting, S2S relay, and utilities.
 
-use crate::server::{RemoteMember, SharedState};
+use crate::policy::types::HistoryVisibility;
+use crate::server::{ChannelRole, HistoryAccess, RemoteMember, SharedState, WireLine};
+use crate::session::Cap;
 use std::sync::Arc;
 
 /// Generate a cloaked hostname from an optional DID.
@@ -47,7 +49,7 @@ pub(super) fn resolve_channel_target(
     channel: &str,
     target_nick: &str,
 ) -> ChannelTarget {
-    let nick_lower = target_nick.to_lowercase();
+    let nick_lower = crate::casemap::fold(target_nick);
 
     // Check local: case-insensitive nick → session, session ∈ channel.members
     let local_session = {
@@ -57,7 +59,6 @@ pub(super) fn resolve_channel_target(
     if let Some(ref sid) = local_session {
         let in_channel = state
             .channels
-            .lock()
             .get(channel)
             .map(|ch| ch.members.contains(sid))
             .unwrap_or(false);
@@ -69,10 +70,10 @@ pub(super) fn resolve_channel_target(
     }
 
     // Check remote: case-insensitive nick ∈ channel.remote_members
-    let remote = state.channels.lock().get(channel).and_then(|ch| {
+    let remote = state.channels.get(channel).and_then(|ch| {
         ch.remote_members
             .iter()
-            .find(|(n, _)| n.to_lowercase() == nick_lower)
+            .find(|(n, _)| crate::casemap::fold(n) == nick_lower)
             .map(|(_, rm)| rm.clone())
     });
     if let Some(rm) = remote {
@@ -82,6 +83,47 @@ pub(super) fn resolve_channel_target(
     ChannelTarget::NotPresent
 }
 
+/// The target's role when the hierarchy stops `session_id` from acting on
+/// it (kick, de-op, devoice) — see [`ChannelRole::outranks`] — or `None`
+/// when it may. Acting on yourself is always allowed. Server operators are
+/// exempt; callers check that separately.
+pub(super) fn protected_role(
+    state: &SharedState,
+    channel: &str,
+    session_id: &str,
+    target: &ChannelTarget,
+) -> Option<ChannelRole> {
+    let ch = state.channels.get(channel)?;
+    let target_rank = match target {
+        ChannelTarget::Local {
+            session_id: target_session,
+        } if target_session == session_id => return None,
+        ChannelTarget::Local {
+            session_id: target_session,
+        } => ch.rank(target_session),
+        ChannelTarget::Remote(rm) => ch.remote_rank(rm),
+        ChannelTarget::NotPresent => ChannelRole::Member,
+    };
+    (!ch.rank(session_id).outranks(target_rank)).then_some(target_rank)
+}
+
+/// How a rejection names a protected member's role.
+pub(super) fn protected_role_name(role: ChannelRole) -> &'static str {
+    if role >= ChannelRole::Admin {
+        "a channel founder or admin"
+    } else {
+        "a channel operator or moderator"
+    }
+}
+
+/// `session_id`'s highest role in `channel` (`Member` if not there).
+pub(super) fn channel_rank(state: &SharedState, channel: &str, session_id: &str) -> ChannelRole {
+    state
+        .channels
+        .get(channel)
+        .map_or(ChannelRole::Member, |ch| ch.rank(session_id))
+}
+
 /// Resolved target of a nick anywhere on the network.
 ///
 /// Unlike `ChannelTarget`, this doesn't require the nick to be in a
@@ -100,7 +142,7 @@ pub(super) enum NetworkTarget {
 /// channels' remote_members. Used for operations like INVITE where
 /// the target doesn't need to be in a specific channel.
 pub(super) fn resolve_network_target(state: &SharedState, target_nick: &str) -> NetworkTarget {
-    let nick_lower = target_nick.to_lowercase();
+    let nick_lower = crate::casemap::fold(target_nick);
 
     // Check local first (case-insensitive — NickMap handles it)
     let local_sid = {
@@ -112,23 +154,45 @@ pub(super) fn resolve_network_target(state: &SharedState, target_nick: &str) ->
     }
 
     // Check all channels' remote_members (case-insensitive)
-    let channels = state.channels.lock();
-    for ch in channels.values() {
-        let rm = ch
-            .remote_members
+    let remote = state.channels.find_map(|_, ch| {
+        ch.remote_members
             .iter()
-            .find(|(n, _)| n.to_lowercase() == nick_lower)
-            .map(|(_, rm)| rm.clone());
-        if let Some(rm) = rm {
-            return NetworkTarget::Remote(rm);
-        }
+            .find(|(n, _)| crate::casemap::fold(n) == nick_lower)
+            .map(|(_, rm)| rm.clone())
+    });
+    match remote {
+        Some(rm) => NetworkTarget::Remote(rm),
+        None => NetworkTarget::Unknown,
     }
-
-    NetworkTarget::Unknown
 }
 
 pub(super) fn normalize_channel(name: &str) -> String {
-    name.to_lowercase()
+    crate::casemap::fold(name)
+}
+
+/// What stored history the connection may read in `channel` (folded), per
+/// the channel's ban list and history visibility (+H). `None` when it may
+/// read nothing or the channel doesn't exist.
+pub(super) fn channel_history_access(
+    conn: &Connection,
+    state: &SharedState,
+    session_id: &str,
+    channel: &str,
+) -> Option<HistoryAccess> {
+    let did = conn.authenticated_did.as_deref();
+    let since_join = state
+        .channels
+        .get(channel)
+        .is_some_and(|ch| ch.history_visibility == HistoryVisibility::MembersSinceJoin);
+    // Read the DID's membership windows before taking the channel lock.
+    let windows = match did {
+        Some(did) if since_join => state
+            .with_db(|db| db.membership_windows(did, channel))
+            .unwrap_or_default(),
+        _ => Vec::new(),
+    };
+    let ch = state.channels.get(channel)?;
+    ch.history_access(session_id, &conn.hostmask(), did, windows)
 }
 
 pub(super) fn s2s_broadcast(state: &Arc<SharedState>, msg: crate::s2s::S2sMessage) {
@@ -173,16 +237,17 @@ pub(super) fn s2s_broadcast_mode(
 pub(super) fn broadcast_to_channel(state: &Arc<SharedState>, channel: &str, msg: &str) {
     let members: Vec<String> = state
         .channels
-        .lock()
         .get(channel)
         .map(|ch| ch.members.iter().cloned().collect())
         .unwrap_or_default();
 
-    let conns = state.connections.lock();
+    let line = WireLine::copy_from_slice(msg.as_bytes());
+    let conns = &state.connections;
     for member_session in &members {
-        if let Some(tx) = conns.get(member_session)
-            && let Err(_e) = tx.try_send(msg.to_string())
-        {
+        let dropped = conns
+            .get(member_session)
+            .is_some_and(|tx| tx.try_send(line.clone()).is_err());
+        if dropped {
             let nick = state
                 .nick_to_session
                 .lock()
@@ -202,25 +267,23 @@ pub(crate) fn broadcast_account_notify(
 ) {
     let host = cloaked_host_for_did(Some(did));
     let hostmask = format!("{nick}!~u@{host}");
-    let line = format!(":{hostmask} ACCOUNT {did}\r\n");
+    let line = WireLine::from(format!(":{hostmask} ACCOUNT {did}\r\n"));
 
     // Collect targets first (release channels lock before acquiring connections)
     let mut targets = std::collections::HashSet::new();
-    {
-        let channels = state.channels.lock();
-        let cap_set = state.cap_account_notify.lock();
-        for ch in channels.values() {
-            if ch.members.contains(session_id) {
-                for member_sid in &ch.members {
-                    if member_sid != session_id && cap_set.contains(member_sid) {
-                        targets.insert(member_sid.clone());
-                    }
+    state.channels.for_each(|_, ch| {
+        if ch.members.contains(session_id) {
+            for member_sid in &ch.members {
+                if member_sid != session_id
+                    && state.sessions.has_cap(member_sid, Cap::AccountNotify)
+                {
+                    targets.insert(member_sid.clone());
                 }
             }
         }
-    }
+    });
     // Now send with only connections lock held
-    let conns = state.connections.lock();
+    let conns = &state.connections;
     for sid in &targets {
         if let Some(tx) = conns.get(sid) {
             let _ = tx.try_send(line.clone());
@@ -262,3 +325,12 @@ pub(crate) fn make_extended_join_with_class(
 pub(crate) fn make_standard_join(hostmask: &str, channel: &str) -> String {
     format!(":{hostmask} JOIN {channel}\r\n")
 }
+
+/// Queue a low-priority reply for `session_id`: bulk output the client
+/// can ask for again (NAMES lists, history replay), dropped first when
+/// its send queue backs up. See [`crate::send_queue`].
+pub(crate) fn send_low_priority(state: &Arc<SharedState>, session_id: &str, line: String) {
+    if let Some(tx) = state.connections.get(session_id) {
+        let _ = tx.try_send_low(line.into());
+    }
+}
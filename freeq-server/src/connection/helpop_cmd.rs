@@ -0,0 +1,116 @@
+//! IRC HELPOP command — server-side help topics.
+//!
+//! HELPOP ROLES [<channel>]
+//!
+//! `ROLES` spells out the channel permission matrix
+//! ([`ChannelRole::can`]): what each prefix may do and whom it may kick or
+//! demote. Given a channel the caller is in, it also says which role they
+//! hold there. Replies use the 704/705/706 help numerics; unknown topics
+//! get 524.
+
+use super::helpers::normalize_channel;
+use crate::irc::{self, Message};
+use crate::server::{ChannelPrivilege, ChannelRole, SharedState};
+use std::sync::Arc;
+
+/// Highest first, as the roles are listed.
+const ROLES: [ChannelRole; 6] = [
+    ChannelRole::Founder,
+    ChannelRole::Admin,
+    ChannelRole::Op,
+    ChannelRole::Halfop,
+    ChannelRole::Voice,
+    ChannelRole::Member,
+];
+
+/// One 705 line per role: `@ op: topic kick ...; kicks/demotes op, halfop, voice, member`.
+fn role_lines() -> Vec<String> {
+    ROLES
+        .iter()
+        .map(|&role| {
+            let prefix = role.prefix().map_or(" ".to_string(), String::from);
+            let privileges: Vec<&str> = ChannelPrivilege::ALL
+                .iter()
+                .filter(|p| role.can(**p))
+                .map(|p| p.as_str())
+                .collect();
+            if privileges.is_empty() {
+                return format!("{prefix} {}: none", role.as_str());
+            }
+            let reach: Vec<&str> = ROLES
+                .iter()
+                .filter(|t| role.outranks(**t))
+                .map(|t| t.as_str())
+                .collect();
+            format!(
+                "{prefix} {}: {}; kicks/demotes {}",
+                role.as_str(),
+                privileges.join(" "),
+                reach.join(", ")
+            )
+        })
+        .collect()
+}
+
+pub(super) fn handle_helpop(
+    conn: &super::Connection,
+    msg: &Message,
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    send_fn: &impl Fn(&Arc<SharedState>, &str, String),
+) {
+    let nick = conn.nick_or_star();
+    let subject = msg
+        .params
+        .first()
+        .map(|s| s.to_uppercase())
+        .unwrap_or_else(|| "ROLES".to_string());
+    let reply = |numeric: &str, text: &str| {
+        let reply = Message::from_server(server_name, numeric, vec![nick, &subject, text]);
+        send_fn(state, session_id, format!("{reply}\r\n"));
+    };
+
+    if subject != "ROLES" {
+        reply(irc::ERR_HELPNOTFOUND, "No help available on this topic");
+        return;
+    }
+
+    reply(irc::RPL_HELPSTART, "Channel roles and what they can do");
+    for line in role_lines() {
+        reply(irc::RPL_HELPTXT, &line);
+    }
+    reply(irc::RPL_HELPTXT, "Server operators bypass the matrix");
+    if let Some(channel) = msg.params.get(1).map(|c| normalize_channel(c)) {
+        let rank = state
+            .channels
+            .get(&channel)
+            .filter(|ch| ch.members.contains(session_id))
+            .map(|ch| ch.rank(session_id));
+        let text = match rank {
+            Some(rank) => format!("You are {} in {channel}", rank.as_str()),
+            None => format!("You are not in {channel}"),
+        };
+        reply(irc::RPL_HELPTXT, &text);
+    }
+    reply(irc::RPL_ENDOFHELP, "End of HELPOP");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn role_lines_follow_the_matrix() {
+        let lines = role_lines();
+        assert_eq!(
+            lines[3],
+            "% halfop: topic kick lists voice; kicks/demotes voice, member"
+        );
+        assert_eq!(
+            lines[2],
+            "@ op: topic kick lists voice invite halfop op settings; kicks/demotes op, halfop, voice, member"
+        );
+        assert_eq!(lines[5], "  member: none");
+    }
+}
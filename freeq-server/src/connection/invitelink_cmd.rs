@@ -0,0 +1,220 @@
+//! IRC INVITELINK command — mint and manage channel invite links (see
+//! [`crate::invites`]).
+//!
+//! INVITELINK <channel> CREATE [uses=<n>] [expires=<30m|12h|7d>] [waive=<type>[,<type>...]]
+//! INVITELINK <channel> LIST
+//! INVITELINK <channel> REVOKE <id>
+//!
+//! A channel's ops, founder, DID-ops and policy admins manage its
+//! invites. `waive=` names PRESENT credential types or PROVE proof types
+//! from the channel policy's join requirements; only DID-based channel
+//! authorities may mint invites that waive anything.
+
+use super::helpers::normalize_channel;
+use crate::invites::{Invite, Invites};
+use crate::irc::Message;
+use crate::server::SharedState;
+use std::sync::Arc;
+
+const USAGE: &str = "Usage: INVITELINK <channel> CREATE [uses=<n>] [expires=<30m|12h|7d>] [waive=<type>,...] | LIST | REVOKE <id>";
+
+/// Whether the caller may manage `channel`'s invites at all, and whether
+/// they hold DID-based authority there (needed to waive requirements).
+fn authority(conn: &super::Connection, state: &SharedState, channel: &str) -> (bool, bool) {
+    let did = conn.authenticated_did.as_deref();
+    let (is_op, is_did_authority) = state
+        .channels
+        .get(channel)
+        .map(|ch| {
+            let did_authority =
+                did.is_some_and(|d| ch.founder_did.as_deref() == Some(d) || ch.did_ops.contains(d));
+            (ch.ops.contains(&conn.id) || did_authority, did_authority)
+        })
+        .unwrap_or_default();
+    let is_admin = did.is_some_and(|d| crate::server::is_channel_admin(state, channel, d));
+    (is_op || is_admin, is_did_authority || is_admin)
+}
+
+fn describe(invite: &Invite) -> String {
+    let when = |t: i64| {
+        chrono::DateTime::from_timestamp(t, 0)
+            .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+            .unwrap_or_else(|| t.to_string())
+    };
+    let uses = match invite.max_uses {
+        Some(max) => format!("{}/{max}", invite.uses),
+        None => invite.uses.to_string(),
+    };
+    let mut line = format!(
+        "INVITE {} by {} uses={uses} expires={}",
+        invite.id,
+        invite.created_by,
+        invite.expires_at.map_or_else(|| "never".to_string(), when),
+    );
+    if !invite.waives.is_empty() {
+        line.push_str(&format!(" waives={}", invite.waives.join(",")));
+    }
+    line
+}
+
+pub(super) fn handle_invitelink(
+    conn: &super::Connection,
+    msg: &Message,
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    send_fn: &impl Fn(&Arc<SharedState>, &str, String),
+) {
+    let nick = conn.nick_or_star();
+    let notice = |text: &str| {
+        let reply = Message::from_server(server_name, "NOTICE", vec![nick, text]);
+        send_fn(state, session_id, format!("{reply}\r\n"));
+    };
+
+    let (Some(channel), Some(sub)) = (msg.params.first(), msg.params.get(1)) else {
+        notice(USAGE);
+        return;
+    };
+    let channel = normalize_channel(channel);
+    let (may_manage, is_did_authority) = authority(conn, state, &channel);
+    if !may_manage {
+        let reply = Message::from_server(
+            server_name,
+            "482",
+            vec![nick, &channel, "You're not channel operator"],
+        );
+        send_fn(state, session_id, format!("{reply}\r\n"));
+        return;
+    }
+
+    match sub.to_uppercase().as_str() {
+        "CREATE" => {
+            let mut max_uses = None;
+            let mut expires_in = None;
+            let mut waives: Vec<String> = Vec::new();
+            for arg in &msg.params[2..] {
+                let ok = match arg.split_once('=') {
+                    Some(("uses", n)) => {
+                        max_uses = n.parse::<u32>().ok().filter(|n| *n > 0);
+                        max_uses.is_some()
+                    }
+                    Some(("expires", d)) => {
+                        expires_in = crate::invites::parse_duration(d);
+                        expires_in.is_some()
+                    }
+                    Some(("waive", types)) => {
+                        waives.extend(
+                            types
+                                .split(',')
+                                .filter(|t| !t.is_empty())
+                                .map(str::to_string),
+                        );
+                        true
+                    }
+                    _ => false,
+                };
+                if !ok {
+                    notice(&format!("Bad option '{arg}'. {USAGE}"));
+                    return;
+                }
+            }
+            waives.sort();
+            waives.dedup();
+
+            if !waives.is_empty() {
+                if !is_did_authority {
+                    notice(&format!(
+                        "Only {channel}'s founder, DID-ops and policy admins can mint invites that waive requirements"
+                    ));
+                    return;
+                }
+                let policy = state
+                    .policy_engine
+                    .as_ref()
+                    .and_then(|engine| engine.get_policy(&channel).ok().flatten());
+                let Some(policy) = policy else {
+                    notice(&format!(
+                        "{channel} has no policy, so there's nothing to waive"
+                    ));
+                    return;
+                };
+                let waivable = crate::policy::engine::waivable(&policy.requirements);
+                let unknown: Vec<&str> = waives
+                    .iter()
+                    .filter(|t| !waivable.contains(*t))
+                    .map(String::as_str)
+                    .collect();
+                if !unknown.is_empty() {
+                    let allowed = if waivable.is_empty() {
+                        "none".to_string()
+                    } else {
+                        waivable.into_iter().collect::<Vec<_>>().join(", ")
+                    };
+                    notice(&format!(
+                        "{channel}'s policy has no waivable requirement {}; waivable: {allowed}",
+                        unknown.join(", ")
+                    ));
+                    return;
+                }
+            }
+
+            let now = chrono::Utc::now().timestamp();
+            let invite = Invite {
+                id: Invites::new_id(),
+                channel: channel.clone(),
+                created_by: conn
+                    .authenticated_did
+                    .clone()
+                    .unwrap_or_else(|| nick.to_string()),
+                created_at: now,
+                expires_at: expires_in.map(|d| now + d),
+                max_uses,
+                uses: 0,
+                waives,
+            };
+            if let Err(e) = state.invites.lock().add(invite.clone()) {
+                notice(&e);
+                return;
+            }
+            state.with_db(|db| db.save_invite(&invite));
+            tracing::info!(id = %invite.id, %channel, by = %invite.created_by, "Invite link created");
+
+            let token = invite.token(&state.invite_key());
+            notice(&format!("Created {}", describe(&invite)));
+            notice(&format!(
+                "Share https://{server_name}/invite/{token} or JOIN {channel} {token}"
+            ));
+        }
+        "LIST" => {
+            let lines: Vec<String> = state
+                .invites
+                .lock()
+                .for_channel(&channel)
+                .into_iter()
+                .map(describe)
+                .collect();
+            if lines.is_empty() {
+                notice(&format!("No invites for {channel}"));
+                return;
+            }
+            for line in &lines {
+                notice(line);
+            }
+            notice(&format!("End of INVITELINK LIST {channel}"));
+        }
+        "REVOKE" => {
+            let Some(id) = msg.params.get(2) else {
+                notice(USAGE);
+                return;
+            };
+            if state.invites.lock().revoke(&channel, id).is_none() {
+                notice(&format!("No invite {id} for {channel}"));
+                return;
+            }
+            state.with_db(|db| db.delete_invite(id));
+            tracing::info!(%id, %channel, by = %nick, "Invite link revoked");
+            notice(&format!("Revoked invite {id} for {channel}"));
+        }
+        _ => notice(USAGE),
+    }
+}
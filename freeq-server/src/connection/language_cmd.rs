@@ -0,0 +1,49 @@
+//! IRC LANGUAGE command — the language of human-readable text in
+//! numerics and notices (see [`crate::locale`]).
+//!
+//! LANGUAGE                      — The current language
+//! LANGUAGE <code>[,<code>...]   — Switch to the first code the server has
+//!
+//! Both reply `:server LANGUAGE <code> :<name>`, the reply already in the
+//! new language. Allowed before registration so the welcome burst comes
+//! out translated. An unknown code is
+//! `FAIL LANGUAGE UNKNOWN_LANGUAGE <codes> :<reason>`.
+
+use crate::irc::Message;
+use crate::locale;
+use crate::server::SharedState;
+use std::sync::Arc;
+
+pub(super) fn handle_language(
+    conn: &super::Connection,
+    msg: &Message,
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    send: &impl Fn(&Arc<SharedState>, &str, String),
+) {
+    if let Some(wanted) = msg.params.first() {
+        let Some((_, catalog)) = state.locales.pick(wanted) else {
+            let reason = format!(
+                "Unknown language; available: {}",
+                state.locales.codes().join(", ")
+            );
+            let reply = Message::from_server(
+                server_name,
+                "FAIL",
+                vec!["LANGUAGE", "UNKNOWN_LANGUAGE", wanted, &reason],
+            );
+            send(state, session_id, format!("{reply}\r\n"));
+            return;
+        };
+        *conn.language.write() = catalog;
+    }
+
+    let current = conn.language.read().clone();
+    let (code, name) = match current.as_deref() {
+        Some(catalog) => (catalog.code.as_str(), catalog.name.as_str()),
+        None => (locale::DEFAULT, "English"),
+    };
+    let reply = Message::from_server(server_name, "LANGUAGE", vec![code, name]);
+    send(state, session_id, format!("{reply}\r\n"));
+}
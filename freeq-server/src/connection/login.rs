@@ -9,7 +9,8 @@
 
 use super::Connection;
 use crate::irc::{self, Message};
-use crate::server::SharedState;
+use crate::server::{SharedState, WireLine};
+use crate::session::Cap;
 use std::sync::Arc;
 
 /// Handle the LOGIN command from an IRC client.
@@ -126,7 +127,7 @@ pub fn complete_irc_login(state: &Arc<SharedState>, session_id: &str, did: &str,
     // nick (persisted, resolves offline) instead of the previous
     // in-memory-only overwrite that silently hijacked the nick and was
     // lost on restart.
-    let nick_lower = nick.to_lowercase();
+    let nick_lower = crate::casemap::fold(&nick);
     let assigned = state.bind_identity_with_fallback(did, &nick_lower);
     let renamed = assigned != nick_lower;
 
@@ -148,9 +149,9 @@ pub fn complete_irc_login(state: &Arc<SharedState>, session_id: &str, did: &str,
                 ),
             ],
         );
-        if let Some(tx) = state.connections.lock().get(session_id) {
-            let _ = tx.try_send(nick_line);
-            let _ = tx.try_send(format!("{renamed_notice}\r\n"));
+        if let Some(tx) = state.connections.get(session_id) {
+            let _ = tx.try_send(nick_line.into());
+            let _ = tx.try_send(format!("{renamed_notice}\r\n").into());
         }
     }
 
@@ -169,9 +170,9 @@ pub fn complete_irc_login(state: &Arc<SharedState>, session_id: &str, did: &str,
         ],
     );
 
-    if let Some(tx) = state.connections.lock().get(session_id) {
-        let _ = tx.try_send(format!("{success}\r\n"));
-        let _ = tx.try_send(format!("{account_notice}\r\n"));
+    if let Some(tx) = state.connections.get(session_id) {
+        let _ = tx.try_send(format!("{success}\r\n").into());
+        let _ = tx.try_send(format!("{account_notice}\r\n").into());
     }
 
     // Store the completion so the connection loop can update
@@ -192,44 +193,44 @@ pub fn complete_irc_login(state: &Arc<SharedState>, session_id: &str, did: &str,
     // Broadcast account-notify to channels
     {
         let hostmask = format!("{assigned}!~u@{cloak}");
-        let account_line = format!(":{hostmask} ACCOUNT {did}\r\n");
-        let channels = state.channels.lock();
-        let account_caps = state.cap_account_notify.lock();
-        let conns = state.connections.lock();
-        for ch in channels.values() {
+        let account_line = WireLine::from(format!(":{hostmask} ACCOUNT {did}\r\n"));
+        let conns = &state.connections;
+        state.channels.for_each(|_, ch| {
             if ch.members.contains(session_id) {
                 for member_sid in &ch.members {
                     if member_sid != session_id
-                        && account_caps.contains(member_sid)
+                        && state.sessions.has_cap(member_sid, Cap::AccountNotify)
                         && let Some(tx) = conns.get(member_sid)
                     {
                         let _ = tx.try_send(account_line.clone());
                     }
                 }
             }
-        }
+        });
     }
 
     // Auto-op in channels where the DID is founder or in did_ops
     {
-        let mut channels = state.channels.lock();
-        let conns = state.connections.lock();
-        for (ch_name, ch) in channels.iter_mut() {
+        let conns = &state.connections;
+        state.channels.for_each_mut(|ch_name, ch| {
             if !ch.members.contains(session_id) {
-                continue;
+                return;
             }
             let should_op = ch.founder_did.as_deref() == Some(did) || ch.did_ops.contains(did);
             if should_op && !ch.ops.contains(session_id) {
                 ch.ops.insert(session_id.to_string());
                 // Broadcast MODE +o
-                let mode_line = format!(":{} MODE {} +o {}\r\n", server_name, ch_name, assigned);
+                let mode_line = WireLine::from(format!(
+                    ":{} MODE {} +o {}\r\n",
+                    server_name, ch_name, assigned
+                ));
                 for member_sid in &ch.members {
                     if let Some(tx) = conns.get(member_sid) {
                         let _ = tx.try_send(mode_line.clone());
                     }
                 }
             }
-        }
+        });
     }
 
     tracing::info!(nick = %assigned, did = %did, handle = %handle, session = %session_id, "LOGIN completed via browser OAuth");
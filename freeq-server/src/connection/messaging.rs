@@ -2,9 +2,13 @@
 //! Message handling: PRIVMSG, NOTICE, TAGMSG, CHATHISTORY.
 
 use super::Connection;
-use super::helpers::{normalize_channel, s2s_broadcast, s2s_next_event_id};
+use super::helpers::{
+    channel_history_access, normalize_channel, s2s_broadcast, s2s_next_event_id, send_low_priority,
+};
 use crate::irc::{self, Message};
-use crate::server::SharedState;
+use crate::server::{HistoryAccess, SharedState, WireLine};
+use crate::session::Cap;
+use freeq_proto::tags::Priority;
 use std::sync::Arc;
 
 /// Verify a client-provided signature, or server-sign as fallback.
@@ -188,11 +192,16 @@ pub(super) fn handle_tagmsg(
     // Normalize IRCv3 draft tags to their canonical forms so all downstream
     // code (persistence, relay, fallback) only needs to check one name.
     let mut tags = tags.clone();
-    for (draft, canonical) in [("+draft/react", "+react"), ("+draft/reply", "+reply")] {
+    for (draft, canonical) in freeq_proto::tags::DRAFT_ALIASES {
         if let Some(v) = tags.remove(draft) {
             tags.entry(canonical.to_string()).or_insert(v);
         }
     }
+    // `freeq.at/p2p-did` is the server's word on who sent an offer.
+    tags.remove(freeq_proto::tags::P2P_DID);
+    if super::p2p::is_negotiation(&tags) && !super::p2p::vet(conn, target, &mut tags, state) {
+        return;
+    }
     let tags = &tags;
 
     // ── Message deletion (+draft/delete=<msgid>) ──
@@ -233,8 +242,8 @@ pub(super) fn handle_tagmsg(
                         "event-storage TAGMSG flood: 5 events / 2s per session",
                     ],
                 );
-                if let Some(tx) = state.connections.lock().get(&conn.id) {
-                    let _ = tx.try_send(format!("{reply}\r\n"));
+                if let Some(tx) = state.connections.get(&conn.id) {
+                    let _ = tx.try_send(format!("{reply}\r\n").into());
                 }
                 tracing::warn!(
                     actor = %did, nick = %nick,
@@ -268,8 +277,8 @@ pub(super) fn handle_tagmsg(
                     ),
                 ],
             );
-            if let Some(tx) = state.connections.lock().get(&conn.id) {
-                let _ = tx.try_send(format!("{reply}\r\n"));
+            if let Some(tx) = state.connections.get(&conn.id) {
+                let _ = tx.try_send(format!("{reply}\r\n").into());
             }
             tracing::warn!(
                 actor = %did, nick = %nick, size = raw_payload.len(),
@@ -400,7 +409,7 @@ pub(super) fn handle_tagmsg(
         command: "TAGMSG".to_string(),
         params: vec![target.to_string()],
     };
-    let tagged_line = format!("{tag_msg}\r\n");
+    let tagged_line = WireLine::from(format!("{tag_msg}\r\n"));
 
     let mut tags_with_time = tags.clone();
     tags_with_time.insert("time".to_string(), time_tag);
@@ -410,22 +419,24 @@ pub(super) fn handle_tagmsg(
         command: "TAGMSG".to_string(),
         params: vec![target.to_string()],
     };
-    let tagged_line_with_time = format!("{tag_msg_with_time}\r\n");
+    let tagged_line_with_time = WireLine::from(format!("{tag_msg_with_time}\r\n"));
 
     // Generate a PRIVMSG fallback for plain clients (server-side downgrade).
     // Only for known tag types — unknown TAGMSGs are silently dropped for plain clients.
     let plain_fallback = tags.get("+react").map(|emoji| {
         format!(":{hostmask} PRIVMSG {target} :\x01ACTION reacted with {emoji}\x01\r\n")
     });
+    let fallback_line = plain_fallback.clone().map(WireLine::from);
 
     // Rich clients get TAGMSG, plain clients get fallback PRIVMSG (if any)
     if target.starts_with('#') || target.starts_with('&') {
-        // Channel TAGMSG — enforce +n (no external messages) and +m (moderated)
+        // Channel TAGMSG — enforce +n (no external messages), +m (moderated), +q (quiet)
+        // and +B (bot allowlist)
         // Resolve sender DID once, before taking the channels lock.
         let sender_did = state.session_dids.lock().get(&conn.id).cloned();
+        let sender_is_bot = state.is_bot(&conn.id);
         {
-            let channels = state.channels.lock();
-            if let Some(ch) = channels.get(target) {
+            if let Some(ch) = state.channels.get(target) {
                 // Founder + persistent DID-ops bypass +m. (+n is membership-based;
                 // a non-member can't be founder anyway, so no bypass needed there.)
                 let is_did_authority = sender_did.as_deref().is_some_and(|d| {
@@ -439,8 +450,8 @@ pub(super) fn handle_tagmsg(
                         irc::ERR_CANNOTSENDTOCHAN,
                         vec![nick, target, "Cannot send to channel (+n)"],
                     );
-                    if let Some(tx) = state.connections.lock().get(&conn.id) {
-                        let _ = tx.try_send(format!("{reply}\r\n"));
+                    if let Some(tx) = state.connections.get(&conn.id) {
+                        let _ = tx.try_send(format!("{reply}\r\n").into());
                     }
                     return;
                 }
@@ -457,39 +468,97 @@ pub(super) fn handle_tagmsg(
                         irc::ERR_CANNOTSENDTOCHAN,
                         vec![nick, target, "Cannot send to channel (+m)"],
                     );
-                    if let Some(tx) = state.connections.lock().get(&conn.id) {
-                        let _ = tx.try_send(format!("{reply}\r\n"));
+                    if let Some(tx) = state.connections.get(&conn.id) {
+                        let _ = tx.try_send(format!("{reply}\r\n").into());
+                    }
+                    return;
+                }
+                // +q: quieted users stay in the channel but can't send
+                if !is_did_authority
+                    && !ch.ops.contains(&conn.id)
+                    && !ch.halfops.contains(&conn.id)
+                    && ch.is_quieted(&conn.hostmask(), sender_did.as_deref())
+                {
+                    let nick = conn.nick_or_star();
+                    let reply = Message::from_server(
+                        &state.server_name,
+                        irc::ERR_QUIETED,
+                        vec![nick, target, "Cannot send to channel (+q)"],
+                    );
+                    if let Some(tx) = state.connections.get(&conn.id) {
+                        let _ = tx.try_send(format!("{reply}\r\n").into());
                     }
                     return;
                 }
+                // +B: bots need to be on the +W allowlist
+                if sender_is_bot
+                    && !is_did_authority
+                    && !ch.ops.contains(&conn.id)
+                    && !ch.halfops.contains(&conn.id)
+                    && !ch.bot_may_send(&conn.hostmask(), sender_did.as_deref())
+                {
+                    let nick = conn.nick_or_star();
+                    let reply = Message::from_server(
+                        &state.server_name,
+                        irc::ERR_CANNOTSENDTOCHAN,
+                        vec![
+                            nick,
+                            target,
+                            "Cannot send to channel (+B: bot not allowlisted)",
+                        ],
+                    );
+                    if let Some(tx) = state.connections.get(&conn.id) {
+                        let _ = tx.try_send(format!("{reply}\r\n").into());
+                    }
+                    return;
+                }
+            }
+        }
+
+        // Typing and status are presence, not messages: throttled, never
+        // stored, and coalesced in busy channels (see crate::ephemeral).
+        if crate::ephemeral::is_ephemeral(tags) {
+            let mut tags = tags.clone();
+            if crate::ephemeral::sanitize(&mut tags)
+                && state.ephemeral.admit(&conn.id, target, &tags)
+            {
+                crate::ephemeral::relay(state, target, &hostmask, Some(&conn.id), tags.clone());
+                super::helpers::s2s_broadcast(
+                    state,
+                    crate::s2s::S2sMessage::Tagmsg {
+                        event_id: super::helpers::s2s_next_event_id(state),
+                        from: conn.nick.as_deref().unwrap_or("*").to_string(),
+                        target: target.to_string(),
+                        tags,
+                        origin: state.server_iroh_id.lock().clone().unwrap_or_default(),
+                    },
+                );
             }
+            return;
         }
 
         let members: Vec<String> = state
             .channels
-            .lock()
             .get(target)
             .map(|ch| ch.members.iter().cloned().collect())
             .unwrap_or_default();
 
-        let tag_caps = state.cap_message_tags.lock();
-        let time_caps = state.cap_server_time.lock();
-        let echo_caps = state.cap_echo_message.lock();
-        let conns = state.connections.lock();
+        let conns = &state.connections;
         for member_session in &members {
+            let caps = state.sessions.caps(member_session);
             // Skip sender unless they have echo-message
-            if member_session == &conn.id && !echo_caps.contains(member_session) {
+            if member_session == &conn.id && !caps.has(Cap::EchoMessage) {
                 continue;
             }
             if let Some(tx) = conns.get(member_session) {
-                if tag_caps.contains(member_session) {
-                    let line = if time_caps.contains(member_session) {
+                if caps.has(Cap::MessageTags) {
+                    let line = if caps.has(Cap::ServerTime) {
                         &tagged_line_with_time
                     } else {
                         &tagged_line
                     };
                     let _ = tx.try_send(line.clone());
-                } else if let Some(ref fallback) = plain_fallback {
+                } else if let Some(ref fallback) = fallback_line {
                     let _ = tx.try_send(fallback.clone());
                 }
             }
@@ -524,9 +593,10 @@ pub(super) fn handle_tagmsg(
         ) {
             RouteResult::Local(ref session) => {
                 // Deliver locally
-                if let Some(tx) = state.connections.lock().get(session) {
-                    let has_tags = state.cap_message_tags.lock().contains(session);
-                    let has_time = state.cap_server_time.lock().contains(session);
+                let caps = state.sessions.caps(session);
+                let has_tags = caps.has(Cap::MessageTags);
+                let has_time = caps.has(Cap::ServerTime);
+                if let Some(tx) = state.connections.get(session) {
                     if has_tags {
                         let line = if has_time {
                             &tagged_line_with_time
@@ -534,7 +604,7 @@ pub(super) fn handle_tagmsg(
                             &tagged_line
                         };
                         let _ = tx.try_send(line.clone());
-                    } else if let Some(ref fallback) = plain_fallback {
+                    } else if let Some(ref fallback) = fallback_line {
                         let _ = tx.try_send(fallback.clone());
                     }
                 }
@@ -558,6 +628,173 @@ pub(super) fn handle_tagmsg(
     }
 }
 
+/// Vet a message's priority tag, in either form: it must name a
+/// [`Priority`], and only a signed-in agent may send `low` — so bots can
+/// mark their chatter, but nobody can get a human's message folded away.
+/// Returns the tags under the canonical name when there is one, or why the
+/// message is refused.
+fn vet_priority(
+    conn: &Connection,
+    tags: &std::collections::HashMap<String, String>,
+) -> Result<Option<std::collections::HashMap<String, String>>, &'static str> {
+    use freeq_proto::tags::{PRIORITY, SHORT_PRIORITY};
+
+    if !tags.contains_key(PRIORITY) && !tags.contains_key(SHORT_PRIORITY) {
+        return Ok(None);
+    }
+    let mut tags = tags.clone();
+    let canonical = tags.remove(PRIORITY);
+    let short = tags.remove(SHORT_PRIORITY);
+    let value = canonical.or(short).unwrap_or_default();
+    let priority = Priority::parse(&value).ok_or("Priority must be low, normal or high")?;
+    if priority == Priority::Low
+        && (conn.authenticated_did.is_none() || conn.actor_class == super::ActorClass::Human)
+    {
+        return Err("Only signed-in agents may send low-priority messages");
+    }
+    tags.insert(PRIORITY.to_string(), priority.to_string());
+    Ok(Some(tags))
+}
+
+/// A message after the content filters, where they changed it.
+struct Filtered {
+    /// The text after `replace` rules.
+    text: Option<String>,
+    /// The same, line by line, for a multi-line message.
+    lines: Option<Vec<super::draft_multiline::BatchLine>>,
+    /// The [`crate::filters::FLAG_TAG`] value, if `flag` rules matched.
+    flag: Option<String>,
+}
+
+/// Run the content filters (see [`crate::filters`]) over a message.
+/// Server operators skip them, a channel's authorities skip its own, and
+/// encrypted bodies aren't inspected. Returns `None` when a rule blocked
+/// the message, after telling the sender; `notify` rules tell the ops
+/// here.
+fn apply_filters(
+    conn: &Connection,
+    command: &str,
+    target: &str,
+    text: &str,
+    tags: &std::collections::HashMap<String, String>,
+    is_channel: bool,
+    multiline_lines: Option<&[super::draft_multiline::BatchLine]>,
+    state: &Arc<SharedState>,
+) -> Option<Filtered> {
+    use crate::filters::GLOBAL;
+
+    let unchanged = Filtered {
+        text: None,
+        lines: None,
+        flag: None,
+    };
+    if conn.is_oper || tags.contains_key("+encrypted") {
+        return Some(unchanged);
+    }
+    let channel = is_channel.then(|| normalize_channel(target));
+    let exempt = channel.as_deref().is_some_and(|name| {
+        let did = conn.authenticated_did.as_deref();
+        state.channels.get(name).is_some_and(|ch| {
+            ch.ops.contains(&conn.id)
+                || ch.halfops.contains(&conn.id)
+                || did
+                    .is_some_and(|d| ch.founder_did.as_deref() == Some(d) || ch.did_ops.contains(d))
+        })
+    });
+    let mut scopes = vec![GLOBAL];
+    if let Some(name) = channel.as_deref().filter(|_| !exempt) {
+        scopes.push(name);
+    }
+
+    let filters = state.content_filters.lock();
+    if filters.is_empty() {
+        return Some(unchanged);
+    }
+    let verdict = filters.check(&scopes, text);
+    if let Some(rule) = verdict.blocked {
+        drop(filters);
+        tracing::info!(session_id = %conn.id, target, rule, "Message blocked by content filter");
+        // NOTICE must never generate error replies (RFC 2812 3.3.2)
+        if command != "NOTICE" {
+            let reply = Message::from_server(
+                &state.server_name,
+                "FAIL",
+                vec![
+                    command,
+                    "FILTERED",
+                    target,
+                    "Message blocked by a content filter",
+                ],
+            );
+            send_to(state, &conn.id, format!("{reply}\r\n"));
+        }
+        return None;
+    }
+    let lines = match (multiline_lines, &verdict.rewritten) {
+        (Some(lines), Some(_)) => Some(
+            lines
+                .iter()
+                .map(|l| super::draft_multiline::BatchLine {
+                    body: filters.rewrite(&scopes, &l.body),
+                    ..l.clone()
+                })
+                .collect(),
+        ),
+        _ => None,
+    };
+    drop(filters);
+
+    for (rule, scope) in &verdict.notify {
+        notify_filter_ops(state, *rule, scope, conn.nick_or_star(), target, text);
+    }
+    let flag = verdict.flag_value();
+    Some(Filtered {
+        text: verdict.rewritten,
+        lines,
+        flag,
+    })
+}
+
+/// Tell the ops a `notify` filter matched: a channel's ops for its
+/// rules, server operators for global ones.
+fn notify_filter_ops(
+    state: &Arc<SharedState>,
+    rule: i64,
+    scope: &str,
+    sender: &str,
+    target: &str,
+    text: &str,
+) {
+    const EXCERPT_CHARS: usize = 200;
+
+    let recipients: Vec<String> = if scope == crate::filters::GLOBAL {
+        state.server_opers.lock().iter().cloned().collect()
+    } else {
+        state
+            .channels
+            .get(scope)
+            .map(|ch| ch.ops.iter().cloned().collect())
+            .unwrap_or_default()
+    };
+    let mut excerpt: String = text.chars().take(EXCERPT_CHARS).collect();
+    if excerpt.len() < text.len() {
+        excerpt.push('…');
+    }
+    let note = format!("[filter {rule}] {sender} to {target}: {excerpt}");
+    for session in recipients {
+        let Some(nick) = state
+            .nick_to_session
+            .lock()
+            .get_nick(&session)
+            .map(str::to_string)
+        else {
+            continue;
+        };
+        let reply = Message::from_server(&state.server_name, "NOTICE", vec![&nick, &note]);
+        send_to(state, &session, format!("{reply}\r\n"));
+    }
+}
+
 pub(super) fn handle_privmsg(
     conn: &Connection,
     command: &str,
@@ -590,6 +827,14 @@ pub(super) fn handle_privmsg_with_multiline(
     state: &Arc<SharedState>,
     multiline_lines: Option<&[super::draft_multiline::BatchLine]>,
 ) {
+    let span = tracing::debug_span!(
+        "handle",
+        session_id = %conn.id,
+        command,
+        target,
+        msgid = tracing::field::Empty,
+    );
+    let _handle = span.enter();
     crate::server::Metrics::bump(&state.metrics.messages_total);
     let hostmask = conn.hostmask();
 
@@ -624,6 +869,30 @@ pub(super) fn handle_privmsg_with_multiline(
     let is_channel = target.starts_with('#') || target.starts_with('&');
     let is_notice = command == "NOTICE";
 
+    // ── Priority (+freeq.at/priority) ──
+    let vetted;
+    let tags = match vet_priority(conn, tags) {
+        Ok(None) => tags,
+        Ok(Some(with_priority)) => {
+            vetted = with_priority;
+            &vetted
+        }
+        Err(reason) => {
+            // NOTICE must never generate error replies (RFC 2812 3.3.2)
+            if !is_notice {
+                let reply = Message::from_server(
+                    &state.server_name,
+                    "FAIL",
+                    vec![command, "INVALID_PRIORITY", target, reason],
+                );
+                if let Some(tx) = state.connections.get(&conn.id) {
+                    let _ = tx.try_send(format!("{reply}\r\n").into());
+                }
+            }
+            return;
+        }
+    };
+
     // Per-session flood protection: max 5 messages per 2 seconds (channels + DMs).
     {
         let now = std::time::SystemTime::now()
@@ -642,22 +911,51 @@ pub(super) fn handle_privmsg_with_multiline(
                     irc::ERR_CANNOTSENDTOCHAN,
                     vec![nick, target, "Flood protection: sending too fast"],
                 );
-                if let Some(tx) = state.connections.lock().get(&conn.id) {
-                    let _ = tx.try_send(format!("{reply}\r\n"));
+                if let Some(tx) = state.connections.get(&conn.id) {
+                    let _ = tx.try_send(format!("{reply}\r\n").into());
                 }
             }
             return;
         }
         ts.push(now);
     }
+    if let Some(activity) = state.session_activity.lock().get_mut(&conn.id) {
+        activity.last_message = std::time::Instant::now();
+    }
+
+    // ── Content filters ──
+    let Some(filtered) = apply_filters(
+        conn,
+        command,
+        target,
+        text,
+        tags,
+        is_channel,
+        multiline_lines,
+        state,
+    ) else {
+        return;
+    };
+    let text = filtered.text.as_deref().unwrap_or(text);
+    let multiline_lines = filtered.lines.as_deref().or(multiline_lines);
+    let flagged;
+    let tags = match filtered.flag {
+        Some(rules) => {
+            let mut with_flag = tags.clone();
+            with_flag.insert(crate::filters::FLAG_TAG.to_string(), rules);
+            flagged = with_flag;
+            &flagged
+        }
+        None => tags,
+    };
 
     if is_channel {
-        // Channel message — enforce +n (no external messages) and +m (moderated)
+        // Channel message — enforce +n (no external messages), +m (moderated) and +q (quiet)
         // Resolve sender DID once, before taking the channels lock.
         let sender_did = state.session_dids.lock().get(&conn.id).cloned();
+        let sender_is_bot = state.is_bot(&conn.id);
         {
-            let channels = state.channels.lock();
-            if let Some(ch) = channels.get(target) {
+            if let Some(ch) = state.channels.get(target) {
                 // Founder + persistent DID-ops bypass +m.
                 let is_did_authority = sender_did.as_deref().is_some_and(|d| {
                     ch.founder_did.as_deref() == Some(d) || ch.did_ops.contains(d)
@@ -672,8 +970,8 @@ pub(super) fn handle_privmsg_with_multiline(
                             irc::ERR_CANNOTSENDTOCHAN,
                             vec![nick, target, "Cannot send to channel (+n)"],
                         );
-                        if let Some(tx) = state.connections.lock().get(&conn.id) {
-                            let _ = tx.try_send(format!("{reply}\r\n"));
+                        if let Some(tx) = state.connections.get(&conn.id) {
+                            let _ = tx.try_send(format!("{reply}\r\n").into());
                         }
                     }
                     return;
@@ -692,8 +990,52 @@ pub(super) fn handle_privmsg_with_multiline(
                             irc::ERR_CANNOTSENDTOCHAN,
                             vec![nick, target, "Cannot send to channel (+m)"],
                         );
-                        if let Some(tx) = state.connections.lock().get(&conn.id) {
-                            let _ = tx.try_send(format!("{reply}\r\n"));
+                        if let Some(tx) = state.connections.get(&conn.id) {
+                            let _ = tx.try_send(format!("{reply}\r\n").into());
+                        }
+                    }
+                    return;
+                }
+                // +q: quieted users stay in the channel but can't send.
+                // Ops, halfops and DID authorities are never silenced.
+                if !is_did_authority
+                    && !ch.ops.contains(&conn.id)
+                    && !ch.halfops.contains(&conn.id)
+                    && ch.is_quieted(&conn.hostmask(), sender_did.as_deref())
+                {
+                    if !is_notice {
+                        let nick = conn.nick_or_star();
+                        let reply = Message::from_server(
+                            &state.server_name,
+                            irc::ERR_QUIETED,
+                            vec![nick, target, "Cannot send to channel (+q)"],
+                        );
+                        if let Some(tx) = state.connections.get(&conn.id) {
+                            let _ = tx.try_send(format!("{reply}\r\n").into());
+                        }
+                    }
+                    return;
+                }
+                // +B: bots need to be on the +W allowlist
+                if sender_is_bot
+                    && !is_did_authority
+                    && !ch.ops.contains(&conn.id)
+                    && !ch.halfops.contains(&conn.id)
+                    && !ch.bot_may_send(&conn.hostmask(), sender_did.as_deref())
+                {
+                    if !is_notice {
+                        let nick = conn.nick_or_star();
+                        let reply = Message::from_server(
+                            &state.server_name,
+                            irc::ERR_CANNOTSENDTOCHAN,
+                            vec![
+                                nick,
+                                target,
+                                "Cannot send to channel (+B: bot not allowlisted)",
+                            ],
+                        );
+                        if let Some(tx) = state.connections.get(&conn.id) {
+                            let _ = tx.try_send(format!("{reply}\r\n").into());
                         }
                     }
                     return;
@@ -726,8 +1068,8 @@ pub(super) fn handle_privmsg_with_multiline(
                             irc::ERR_CANNOTSENDTOCHAN,
                             vec![nick, target, reason],
                         );
-                        if let Some(tx) = state.connections.lock().get(&conn.id) {
-                            let _ = tx.try_send(format!("{reply}\r\n"));
+                        if let Some(tx) = state.connections.get(&conn.id) {
+                            let _ = tx.try_send(format!("{reply}\r\n").into());
                         }
                     }
                     return;
@@ -752,10 +1094,15 @@ pub(super) fn handle_privmsg_with_multiline(
 
         // Generate msgid for every PRIVMSG/NOTICE
         let msgid = crate::msgid::generate();
+        span.record("msgid", msgid.as_str());
+        publish_message_event(state, conn, command, target, &msgid, text, tags);
 
         // Build tags with msgid injected (for tag-capable clients)
         let mut full_tags = tags.clone();
         full_tags.insert("msgid".to_string(), msgid.clone());
+        stamp_thread_root(&mut full_tags, state, Some(target));
+        stamp_conversation(&mut full_tags, None);
+        stamp_bot(&mut full_tags, state, &conn.id);
 
         // Verify client signature or server-sign as fallback
         let client_sig = tags.get("+freeq.at/sig").map(|s| s.as_str());
@@ -803,7 +1150,7 @@ pub(super) fn handle_privmsg_with_multiline(
         full_tags_with_time.insert("time".to_string(), time_tag.clone());
 
         // Plain line (no tags) for clients that don't support message-tags
-        let plain_line = format!(":{hostmask} {command} {target} :{text}\r\n");
+        let plain_line = WireLine::from(format!(":{hostmask} {command} {target} :{text}\r\n"));
         // Tagged line for clients that negotiated message-tags (no server-time)
         let tagged_line = {
             let tag_msg = irc::Message {
@@ -812,7 +1159,7 @@ pub(super) fn handle_privmsg_with_multiline(
                 command: command.to_string(),
                 params: vec![target.to_string(), text.to_string()],
             };
-            format!("{tag_msg}\r\n")
+            WireLine::from(format!("{tag_msg}\r\n"))
         };
         // Tagged line with server-time
         let tagged_line_with_time = {
@@ -822,7 +1169,7 @@ pub(super) fn handle_privmsg_with_multiline(
                 command: command.to_string(),
                 params: vec![target.to_string(), text.to_string()],
             };
-            format!("{tag_msg}\r\n")
+            WireLine::from(format!("{tag_msg}\r\n"))
         };
 
         // Store in channel history
@@ -832,8 +1179,7 @@ pub(super) fn handle_privmsg_with_multiline(
             if let Some(did) = conn.authenticated_did.as_deref() {
                 history_tags.insert("account".to_string(), did.to_string());
             }
-            let mut channels = state.channels.lock();
-            if let Some(ch) = channels.get_mut(target) {
+            if let Some(mut ch) = state.channels.get(target) {
                 ch.history.push_back(HistoryMessage {
                     from: hostmask.clone(),
                     text: text.to_string(),
@@ -845,7 +1191,6 @@ pub(super) fn handle_privmsg_with_multiline(
                     ch.history.pop_front();
                 }
             }
-            drop(channels);
             let sender_did = conn.authenticated_did.as_deref();
             state.with_db(|db| {
                 db.insert_message(
@@ -864,21 +1209,24 @@ pub(super) fn handle_privmsg_with_multiline(
             if max > 0 {
                 state.with_db(|db| db.prune_messages(target, max));
             }
+            crate::stats::record_message(state, target, sender_did);
+            crate::email::note_mentions(
+                state,
+                target,
+                conn.nick_or_star(),
+                sender_did,
+                text,
+                tags.contains_key("+encrypted"),
+            );
         }
 
         let members: Vec<String> = state
             .channels
-            .lock()
             .get(target)
             .map(|ch| ch.members.iter().cloned().collect())
             .unwrap_or_default();
 
-        let tag_caps = state.cap_message_tags.lock();
-        let time_caps = state.cap_server_time.lock();
-        let account_caps = state.cap_account_tag.lock();
-        let echo_caps = state.cap_echo_message.lock();
-        let multiline_caps = state.cap_draft_multiline.lock();
-        let conns = state.connections.lock();
+        let conns = &state.connections;
         let sender_did = conn.authenticated_did.as_deref();
         // When the logical message arrived as a draft/multiline batch
         // we already have its per-line breakdown; reuse the same
@@ -886,22 +1234,26 @@ pub(super) fn handle_privmsg_with_multiline(
         // negotiated draft/multiline see BATCH frames; everyone else
         // sees the constituent PRIVMSGs (msgid on the first only).
         let outbound_batch_id = multiline_lines.map(|_| format!("ml{}", crate::msgid::generate()));
+        // Account-tagged variants, indexed by server-time.
+        let mut account_lines: [Option<WireLine>; 2] = [None, None];
+        let _fan_out = tracing::debug_span!("fan_out", recipients = members.len()).entered();
         for member_session in &members {
+            let member_caps = state.sessions.caps(member_session);
             // echo-message: include sender if they requested it
-            if member_session == &conn.id && !echo_caps.contains(member_session) {
+            if member_session == &conn.id && !member_caps.has(Cap::EchoMessage) {
                 continue;
             }
             if let Some(tx) = conns.get(member_session) {
-                let has_tags = tag_caps.contains(member_session);
-                let has_time = time_caps.contains(member_session);
-                let wants_account = sender_did.is_some() && account_caps.contains(member_session);
+                let has_tags = member_caps.has(Cap::MessageTags);
+                let has_time = member_caps.has(Cap::ServerTime);
+                let wants_account = sender_did.is_some() && member_caps.has(Cap::AccountTag);
                 if let (Some(lines), Some(batch_id)) =
                     (multiline_lines, outbound_batch_id.as_deref())
                 {
                     let caps = super::draft_multiline::ReceiverCaps {
                         has_tags,
                         has_time,
-                        has_multiline: multiline_caps.contains(member_session),
+                        has_multiline: member_caps.has(Cap::DraftMultiline),
                         wants_account,
                         sender_did,
                     };
@@ -918,11 +1270,11 @@ pub(super) fn handle_privmsg_with_multiline(
                     for frame in
                         super::draft_multiline::build_outbound_multiline_frames(&ctx, &caps)
                     {
-                        let _ = tx.try_send(frame);
+                        let _ = tx.try_send(frame.into());
                     }
                     continue;
                 }
-                let line: String = if !has_tags {
+                let line: WireLine = if !has_tags {
                     plain_line.clone()
                 } else if !wants_account {
                     if has_time {
@@ -931,21 +1283,27 @@ pub(super) fn handle_privmsg_with_multiline(
                         tagged_line.clone()
                     }
                 } else {
-                    // Per-recipient build with `account` tag injected.
-                    // IRCv3 account-tag spec requires this only for opted-in clients.
-                    let mut recip_tags = if has_time {
-                        full_tags_with_time.clone()
-                    } else {
-                        full_tags.clone()
-                    };
-                    recip_tags.insert("account".to_string(), sender_did.unwrap().to_string());
-                    let tag_msg = irc::Message {
-                        tags: recip_tags,
-                        prefix: Some(hostmask.clone()),
-                        command: command.to_string(),
-                        params: vec![target.to_string(), text.to_string()],
-                    };
-                    format!("{tag_msg}\r\n")
+                    // `account` tag injected: IRCv3 account-tag requires it
+                    // only for opted-in clients. Built on first use and
+                    // shared by every such member.
+                    account_lines[usize::from(has_time)]
+                        .get_or_insert_with(|| {
+                            let mut recip_tags = if has_time {
+                                full_tags_with_time.clone()
+                            } else {
+                                full_tags.clone()
+                            };
+                            recip_tags
+                                .insert("account".to_string(), sender_did.unwrap().to_string());
+                            let tag_msg = irc::Message {
+                                tags: recip_tags,
+                                prefix: Some(hostmask.clone()),
+                                command: command.to_string(),
+                                params: vec![target.to_string(), text.to_string()],
+                            };
+                            WireLine::from(format!("{tag_msg}\r\n"))
+                        })
+                        .clone()
                 };
                 let _ = tx.try_send(line);
             }
@@ -955,10 +1313,9 @@ pub(super) fn handle_privmsg_with_multiline(
         if command == "PRIVMSG" {
             let origin = state.server_iroh_id.lock().clone().unwrap_or_default();
             let sig = full_tags.get("+freeq.at/sig").cloned();
-            let (s2s_text, s2s_tags) = crate::s2s::encode_privmsg_text_for_s2s(
-                text,
-                crate::s2s::relay_coordination_tags(&full_tags),
-            );
+            let mut relay_tags = crate::s2s::relay_coordination_tags(&full_tags);
+            crate::s2s::start_hops(&mut relay_tags, &state.server_name);
+            let (s2s_text, s2s_tags) = crate::s2s::encode_privmsg_text_for_s2s(text, relay_tags);
             s2s_broadcast(
                 state,
                 crate::s2s::S2sMessage::Privmsg {
@@ -989,10 +1346,31 @@ pub(super) fn handle_privmsg_with_multiline(
             );
         }
     } else {
+        if conn.on_probation() && !super::probation::may_message(state, &conn.id, target) {
+            // NOTICE must never generate error replies (RFC 2812 3.3.2)
+            if !is_notice {
+                let fail = super::probation::fail(
+                    conn,
+                    &state.server_name,
+                    command,
+                    "message users other than operators",
+                );
+                send_to(state, &conn.id, fail);
+            }
+            return;
+        }
         // Private message — check RPL_AWAY and deliver
+        // DMs with a local user are persisted (and threadable) under the
+        // pair of DIDs, or nicks for guests; see `dm_key`.
+        let dm_key = dm_key(state, conn, target);
         let pm_msgid = crate::msgid::generate();
+        span.record("msgid", pm_msgid.as_str());
+        publish_message_event(state, conn, command, target, &pm_msgid, text, tags);
         let mut pm_tags = tags.clone();
         pm_tags.insert("msgid".to_string(), pm_msgid.clone());
+        stamp_thread_root(&mut pm_tags, state, dm_key.as_deref());
+        stamp_conversation(&mut pm_tags, dm_key.as_deref());
+        stamp_bot(&mut pm_tags, state, &conn.id);
 
         // Verify client signature or server-sign DMs
         let client_sig = tags.get("+freeq.at/sig").map(|s| s.as_str());
@@ -1036,17 +1414,17 @@ pub(super) fn handle_privmsg_with_multiline(
         let dm_outbound_batch_id =
             multiline_lines.map(|_| format!("ml{}", crate::msgid::generate()));
         let build_dm_frames = |recipient_session: &str| -> Vec<String> {
-            let has_tags = state.cap_message_tags.lock().contains(recipient_session);
-            let has_time = state.cap_server_time.lock().contains(recipient_session);
-            let wants_account = sender_did_for_dm.is_some()
-                && state.cap_account_tag.lock().contains(recipient_session);
+            let recipient_caps = state.sessions.caps(recipient_session);
+            let has_tags = recipient_caps.has(Cap::MessageTags);
+            let has_time = recipient_caps.has(Cap::ServerTime);
+            let wants_account = sender_did_for_dm.is_some() && recipient_caps.has(Cap::AccountTag);
             if let (Some(lines), Some(batch_id)) =
                 (multiline_lines, dm_outbound_batch_id.as_deref())
             {
                 let caps = super::draft_multiline::ReceiverCaps {
                     has_tags,
                     has_time,
-                    has_multiline: state.cap_draft_multiline.lock().contains(recipient_session),
+                    has_multiline: recipient_caps.has(Cap::DraftMultiline),
                     wants_account,
                     sender_did: sender_did_for_dm.as_deref(),
                 };
@@ -1091,6 +1469,19 @@ pub(super) fn handle_privmsg_with_multiline(
         // See routing.rs for why we NEVER gate on remote_members here.
         use super::routing::{RouteResult, relay_to_nick};
         let from_nick = conn.nick.as_deref().unwrap_or("*").to_string();
+        // A registered user who isn't connected here may want an email.
+        let note_offline_dm = || {
+            if command == "PRIVMSG" {
+                crate::email::note_direct_message(
+                    state,
+                    target,
+                    &from_nick,
+                    conn.authenticated_did.as_deref(),
+                    text,
+                    pm_tags.contains_key("+encrypted") || text.starts_with("ENC"),
+                );
+            }
+        };
         match relay_to_nick(
             state,
             &from_nick,
@@ -1104,10 +1495,10 @@ pub(super) fn handle_privmsg_with_multiline(
                 // Target is local — deliver to ALL sessions for target's DID (multi-device).
                 // Also relay via S2S so the DM is visible on other federated servers
                 // (e.g. sender logged into multiple servers).
-                let (s2s_text, s2s_tags) = crate::s2s::encode_privmsg_text_for_s2s(
-                    text,
-                    crate::s2s::relay_coordination_tags(&pm_tags),
-                );
+                let mut relay_tags = crate::s2s::relay_coordination_tags(&pm_tags);
+                crate::s2s::start_hops(&mut relay_tags, &state.server_name);
+                let (s2s_text, s2s_tags) =
+                    crate::s2s::encode_privmsg_text_for_s2s(text, relay_tags);
                 super::helpers::s2s_broadcast(
                     state,
                     crate::s2s::S2sMessage::Privmsg {
@@ -1139,8 +1530,8 @@ pub(super) fn handle_privmsg_with_multiline(
                         irc::RPL_AWAY,
                         vec![nick, target, away_msg],
                     );
-                    if let Some(tx) = state.connections.lock().get(&conn.id) {
-                        let _ = tx.try_send(format!("{reply}\r\n"));
+                    if let Some(tx) = state.connections.get(&conn.id) {
+                        let _ = tx.try_send(format!("{reply}\r\n").into());
                     }
                 }
 
@@ -1159,13 +1550,15 @@ pub(super) fn handle_privmsg_with_multiline(
                     }
                 };
 
-                let conns = state.connections.lock();
+                let conns = &state.connections;
+                let _fan_out =
+                    tracing::debug_span!("fan_out", recipients = target_sessions.len()).entered();
                 // Deliver to all target sessions
                 for target_session in &target_sessions {
                     let frames = build_dm_frames(target_session);
                     if let Some(tx) = conns.get(target_session) {
                         for frame in frames {
-                            if let Err(_e) = tx.try_send(frame) {
+                            if let Err(_e) = tx.try_send(frame.into()) {
                                 let target_nick = state
                                     .nick_to_session
                                     .lock()
@@ -1200,12 +1593,12 @@ pub(super) fn handle_privmsg_with_multiline(
                 for sender_session in &sender_sessions {
                     if sender_session == &conn.id {
                         // Original sender — use echo-message cap
-                        let sender_has_echo = state.cap_echo_message.lock().contains(&conn.id);
+                        let sender_has_echo = state.sessions.has_cap(&conn.id, Cap::EchoMessage);
                         if sender_has_echo {
                             let frames = build_dm_frames(&conn.id);
                             if let Some(tx) = conns.get(&conn.id) {
                                 for frame in frames {
-                                    let _ = tx.try_send(frame);
+                                    let _ = tx.try_send(frame.into());
                                 }
                             }
                         }
@@ -1214,27 +1607,29 @@ pub(super) fn handle_privmsg_with_multiline(
                         let frames = build_dm_frames(sender_session);
                         if let Some(tx) = conns.get(sender_session) {
                             for frame in frames {
-                                let _ = tx.try_send(frame);
+                                let _ = tx.try_send(frame.into());
                             }
                         }
                     }
                 }
             }
             RouteResult::Relayed => {
+                note_offline_dm();
                 // Sent to S2S peers — receiving server will deliver.
                 // No ERR_NOSUCHNICK: we can't know if it arrived (same as email).
                 // echo-message: echo DM back to sender even for relayed messages
-                let sender_has_echo = state.cap_echo_message.lock().contains(&conn.id);
+                let sender_has_echo = state.sessions.has_cap(&conn.id, Cap::EchoMessage);
                 if sender_has_echo {
                     let frames = build_dm_frames(&conn.id);
-                    if let Some(tx) = state.connections.lock().get(&conn.id) {
+                    if let Some(tx) = state.connections.get(&conn.id) {
                         for frame in frames {
-                            let _ = tx.try_send(frame);
+                            let _ = tx.try_send(frame.into());
                         }
                     }
                 }
             }
             RouteResult::Unreachable => {
+                note_offline_dm();
                 // No federation, nick doesn't exist locally
                 let nick = conn.nick_or_star();
                 let reply = Message::from_server(
@@ -1242,22 +1637,15 @@ pub(super) fn handle_privmsg_with_multiline(
                     irc::ERR_NOSUCHNICK,
                     vec![nick, target, "No such nick/channel"],
                 );
-                if let Some(tx) = state.connections.lock().get(&conn.id) {
-                    let _ = tx.try_send(format!("{reply}\r\n"));
+                if let Some(tx) = state.connections.get(&conn.id) {
+                    let _ = tx.try_send(format!("{reply}\r\n").into());
                 }
             }
         }
 
-        // Persist DM if both sender and recipient have DIDs
-        let sender_did = conn.authenticated_did.as_deref();
-        let recipient_did = state
-            .nick_owners
-            .lock()
-            .get(&target.to_lowercase())
-            .cloned();
-        if let (Some(s_did), Some(r_did)) = (sender_did, recipient_did.as_deref()) {
-            let dm_key = crate::db::canonical_dm_key(s_did, r_did);
-            let did_for_db = Some(s_did);
+        // Persist the DM under its conversation key
+        if let Some(dm_key) = dm_key {
+            let did_for_db = conn.authenticated_did.as_deref();
             state.with_db(|db| {
                 db.insert_message(
                     &dm_key,
@@ -1273,6 +1661,126 @@ pub(super) fn handle_privmsg_with_multiline(
     }
 }
 
+/// Server-assigned tag carrying a reply's thread root msgid.
+pub(crate) const THREAD_TAG: &str = "+freeq.at/thread";
+
+/// Set [`THREAD_TAG`] on an outgoing reply: the root of the thread its
+/// parent belongs to in `history_key`'s stored history, or the parent
+/// itself when that isn't stored. Any client-supplied value is dropped.
+/// Firehose `message` event: who sent what where, without the body.
+fn publish_message_event(
+    state: &SharedState,
+    conn: &Connection,
+    command: &str,
+    target: &str,
+    msgid: &str,
+    text: &str,
+    tags: &std::collections::HashMap<String, String>,
+) {
+    if !state.firehose.is_active() {
+        return;
+    }
+    state
+        .firehose
+        .publish(crate::firehose::FirehoseEvent::Message {
+            session: conn.id.clone(),
+            nick: conn.nick_or_star().to_string(),
+            did: conn.authenticated_did.clone(),
+            command: command.to_string(),
+            target: target.to_string(),
+            msgid: msgid.to_string(),
+            bytes: text.len(),
+            encrypted: tags.contains_key("+encrypted"),
+        });
+}
+
+fn stamp_thread_root(
+    tags: &mut std::collections::HashMap<String, String>,
+    state: &Arc<SharedState>,
+    history_key: Option<&str>,
+) {
+    tags.remove(THREAD_TAG);
+    let Some(parent) = crate::db::reply_parent(tags).map(str::to_string) else {
+        return;
+    };
+    let root = history_key
+        .and_then(|key| state.with_db(|db| db.thread_root_of(key, &parent)))
+        .flatten()
+        .unwrap_or(parent);
+    tags.insert(THREAD_TAG.to_string(), root);
+}
+
+/// Server-assigned tag carrying a direct message's conversation ID: its
+/// [`crate::db::canonical_dm_key`], the same for both parties on every
+/// device and across nick changes.
+pub(crate) const CONVERSATION_TAG: &str = "+freeq.at/conversation";
+
+/// Marks a message as sent by a bot (user mode +B).
+const BOT_TAG: &str = "bot";
+
+/// Set [`CONVERSATION_TAG`] to `dm_key`, dropping any client-supplied value.
+fn stamp_conversation(tags: &mut std::collections::HashMap<String, String>, dm_key: Option<&str>) {
+    tags.remove(CONVERSATION_TAG);
+    if let Some(key) = dm_key {
+        tags.insert(CONVERSATION_TAG.to_string(), key.to_string());
+    }
+}
+
+/// Tag messages from bots with the IRCv3 `bot` tag, dropping any
+/// client-supplied one.
+fn stamp_bot(
+    tags: &mut std::collections::HashMap<String, String>,
+    state: &SharedState,
+    session_id: &str,
+) {
+    tags.remove(BOT_TAG);
+    if state.is_bot(session_id) {
+        tags.insert(BOT_TAG.to_string(), String::new());
+    }
+}
+
+/// Who `nick` is in a DM key: the DID of the user on it (the live
+/// session's, else the nick's registered owner's), or a guest stand-in
+/// when a local guest is on it. `None` for a nick nobody here holds.
+fn dm_party(state: &SharedState, nick: &str) -> Option<String> {
+    let session = state
+        .nick_to_session
+        .lock()
+        .get_session(nick)
+        .map(str::to_string);
+    if let Some(did) = session
+        .as_ref()
+        .and_then(|sid| state.session_dids.lock().get(sid).cloned())
+    {
+        return Some(did);
+    }
+    if let Some(did) = state
+        .nick_owners
+        .lock()
+        .get(&crate::casemap::fold(nick))
+        .cloned()
+    {
+        return Some(did);
+    }
+    session.map(|_| crate::db::guest_dm_party(nick))
+}
+
+/// History key for the DM between `conn` and `target` (a nick or DID):
+/// the [`crate::db::canonical_dm_key`] of their DIDs, with guests keyed by
+/// nick. `None` when `target` is nobody we know.
+fn dm_key(state: &SharedState, conn: &Connection, target: &str) -> Option<String> {
+    let own = match &conn.authenticated_did {
+        Some(did) => did.clone(),
+        None => crate::db::guest_dm_party(conn.nick.as_deref()?),
+    };
+    let peer = if target.starts_with("did:") {
+        target.to_string()
+    } else {
+        dm_party(state, target)?
+    };
+    Some(crate::db::canonical_dm_key(&own, &peer))
+}
+
 // ── LIST command ────────────────────────────────────────────────────
 
 fn parse_chathistory_ts(s: &str) -> Option<u64> {
@@ -1283,9 +1791,11 @@ fn parse_chathistory_ts(s: &str) -> Option<u64> {
 }
 
 /// Resolve a CHATHISTORY/SEARCH target and authorize access.
-/// For channels: membership check. For DMs: auth check + canonical key.
-/// Returns (db_key, display_target); None means a FAIL was already sent.
-/// `cmd` names the failing command in FAIL replies.
+/// For channels: ban check plus the channel's history visibility (+H).
+/// For DMs: the canonical key; guests only see their own session's.
+/// Returns (db_key, display_target, what the requester may see); None
+/// means a FAIL was already sent. `cmd` names the failing command in FAIL
+/// replies.
 fn resolve_history_target(
     conn: &Connection,
     raw_target: &str,
@@ -1294,84 +1804,65 @@ fn resolve_history_target(
     server_name: &str,
     session_id: &str,
     send: &dyn Fn(&Arc<SharedState>, &str, String),
-) -> Option<(String, String)> {
+) -> Option<(String, String, HistoryAccess)> {
     let is_channel = raw_target.starts_with('#') || raw_target.starts_with('&');
 
     if is_channel {
         let target = normalize_channel(raw_target);
-        {
-            let channels = state.channels.lock();
-            if let Some(ch) = channels.get(&target) {
-                if !ch.members.contains(session_id) {
-                    let reply = Message::from_server(
-                        server_name,
-                        "FAIL",
-                        vec![
-                            cmd,
-                            "INVALID_TARGET",
-                            &target,
-                            "You are not in that channel",
-                        ],
-                    );
-                    send(state, session_id, format!("{reply}\r\n"));
-                    return None;
-                }
-            } else {
-                let reply = Message::from_server(
-                    server_name,
-                    "FAIL",
-                    vec![cmd, "INVALID_TARGET", &target, "No such channel"],
-                );
-                send(state, session_id, format!("{reply}\r\n"));
-                return None;
-            }
+        let Some(member) = state
+            .channels
+            .get(&target)
+            .map(|ch| ch.members.contains(session_id))
+        else {
+            let reply = Message::from_server(
+                server_name,
+                "FAIL",
+                vec![cmd, "INVALID_TARGET", &target, "No such channel"],
+            );
+            send(state, session_id, format!("{reply}\r\n"));
+            return None;
+        };
+        if let Some(access) = channel_history_access(conn, state, session_id, &target) {
+            return Some((target.clone(), target, access));
         }
-        Some((target.clone(), target))
+        // Visibility allows members; a member refused here is banned.
+        let reason = if member {
+            "You are banned from that channel"
+        } else {
+            "You are not in that channel"
+        };
+        let reply = Message::from_server(
+            server_name,
+            "FAIL",
+            vec![cmd, "INVALID_TARGET", &target, reason],
+        );
+        send(state, session_id, format!("{reply}\r\n"));
+        None
     } else {
-        // DM target — require DID authentication
-        let requester_did = match conn.authenticated_did.as_deref() {
-            Some(did) => did.to_string(),
-            None => {
-                let reply = Message::from_server(
-                    server_name,
-                    "FAIL",
-                    vec![
-                        cmd,
-                        "ACCOUNT_REQUIRED",
-                        raw_target,
-                        "You must be authenticated to access DM history",
-                    ],
-                );
-                send(state, session_id, format!("{reply}\r\n"));
-                return None;
-            }
+        // DM target — a DID or the nick of someone we know, keyed by DID
+        // pair so nick changes and other devices see the same history.
+        let Some(dm_key) = dm_key(state, conn, raw_target) else {
+            let reply = Message::from_server(
+                server_name,
+                "FAIL",
+                vec![cmd, "INVALID_TARGET", raw_target, "Unknown target"],
+            );
+            send(state, session_id, format!("{reply}\r\n"));
+            return None;
         };
-
-        // Resolve target to DID — accept DID directly or resolve nick
-        let target_did = if raw_target.starts_with("did:") {
-            raw_target.to_string()
+        // A guest's nick may have been someone else's before, so guests
+        // only see what was sent since they signed on.
+        let access = if conn.authenticated_did.is_some() {
+            HistoryAccess::Full
         } else {
-            match state
-                .nick_owners
+            let signon = state
+                .session_activity
                 .lock()
-                .get(&raw_target.to_lowercase())
-                .cloned()
-            {
-                Some(did) => did,
-                None => {
-                    let reply = Message::from_server(
-                        server_name,
-                        "FAIL",
-                        vec![cmd, "INVALID_TARGET", raw_target, "Unknown target"],
-                    );
-                    send(state, session_id, format!("{reply}\r\n"));
-                    return None;
-                }
-            }
+                .get(session_id)
+                .map_or(i64::MAX, |a| a.signon);
+            HistoryAccess::Windows(vec![(signon.max(0) as u64, None)])
         };
-
-        let dm_key = crate::db::canonical_dm_key(&requester_did, &target_did);
-        Some((dm_key, raw_target.to_string()))
+        Some((dm_key, raw_target.to_string(), access))
     }
 }
 
@@ -1405,7 +1896,7 @@ pub(super) fn handle_chathistory(
         return;
     }
 
-    let Some((db_key, target)) = resolve_history_target(
+    let Some((db_key, target, access)) = resolve_history_target(
         conn,
         &msg.params[1],
         "CHATHISTORY",
@@ -1417,13 +1908,14 @@ pub(super) fn handle_chathistory(
         return;
     };
 
-    let has_tags = state.cap_message_tags.lock().contains(session_id);
-    let has_time = state.cap_server_time.lock().contains(session_id);
-    let has_batch = state.cap_batch.lock().contains(session_id);
-    let has_multiline = state.cap_draft_multiline.lock().contains(session_id);
+    let caps = state.sessions.caps(session_id);
+    let has_tags = caps.has(Cap::MessageTags);
+    let has_time = caps.has(Cap::ServerTime);
+    let has_batch = caps.has(Cap::Batch);
+    let has_multiline = caps.has(Cap::DraftMultiline);
 
     // Fetch messages from DB based on subcommand
-    let messages: Vec<crate::db::MessageRow> = match subcmd.as_str() {
+    let mut messages: Vec<crate::db::MessageRow> = match subcmd.as_str() {
         "BEFORE" => {
             if msg.params.len() < 4 {
                 vec![]
@@ -1475,8 +1967,28 @@ pub(super) fn handle_chathistory(
                     .unwrap_or_default()
             }
         }
+        "THREAD" => {
+            // CHATHISTORY THREAD <target> <msgid> [<limit>] — the whole
+            // thread the message belongs to, root first.
+            let msgid = msg.params[2]
+                .strip_prefix("msgid=")
+                .unwrap_or(&msg.params[2]);
+            let limit = msg
+                .params
+                .get(3)
+                .and_then(|l| l.parse::<usize>().ok())
+                .unwrap_or(100)
+                .min(500);
+            state
+                .with_db(|db| match db.thread_root_of(&db_key, msgid)? {
+                    Some(root) => db.get_thread(&db_key, &root, limit),
+                    None => Ok(vec![]),
+                })
+                .unwrap_or_default()
+        }
         _ => vec![],
     };
+    messages.retain(|m| access.allows(m.timestamp));
 
     replay_rows_as_batch(
         messages,
@@ -1494,8 +2006,8 @@ pub(super) fn handle_chathistory(
 }
 
 /// SEARCH <target> :<query> — full-text search over stored history.
-/// Authorization matches CHATHISTORY: channel search requires membership,
-/// DM search requires DID authentication. Results are replayed newest-last
+/// Authorization matches CHATHISTORY: channel search follows the channel's
+/// history visibility (+H), DM search requires DID authentication. Results are replayed newest-last
 /// inside a `freeq.at/search` batch, capped at 25 messages.
 pub(super) fn handle_search(
     conn: &Connection,
@@ -1519,7 +2031,7 @@ pub(super) fn handle_search(
         return;
     }
 
-    let Some((db_key, target)) = resolve_history_target(
+    let Some((db_key, target, access)) = resolve_history_target(
         conn,
         &msg.params[0],
         "SEARCH",
@@ -1536,14 +2048,16 @@ pub(super) fn handle_search(
     let mut messages: Vec<crate::db::MessageRow> = state
         .with_db(|db| db.search_messages(&db_key, &query, SEARCH_LIMIT, None))
         .unwrap_or_default();
+    messages.retain(|m| access.allows(m.timestamp));
     // search_messages returns newest-first; replay oldest-first so the
     // batch reads like CHATHISTORY output.
     messages.reverse();
 
-    let has_tags = state.cap_message_tags.lock().contains(session_id);
-    let has_time = state.cap_server_time.lock().contains(session_id);
-    let has_batch = state.cap_batch.lock().contains(session_id);
-    let has_multiline = state.cap_draft_multiline.lock().contains(session_id);
+    let caps = state.sessions.caps(session_id);
+    let has_tags = caps.has(Cap::MessageTags);
+    let has_time = caps.has(Cap::ServerTime);
+    let has_batch = caps.has(Cap::Batch);
+    let has_multiline = caps.has(Cap::DraftMultiline);
 
     replay_rows_as_batch(
         messages,
@@ -1626,6 +2140,16 @@ fn replay_rows_as_batch(
                 tags.entry("+draft/edit".to_string())
                     .or_insert_with(|| replaces.clone());
             }
+            tags.remove(THREAD_TAG);
+            if let Some(ref root) = row.thread_root {
+                tags.insert(THREAD_TAG.to_string(), root.clone());
+            }
+            stamp_conversation(
+                &mut tags,
+                row.channel
+                    .starts_with("dm:")
+                    .then_some(row.channel.as_str()),
+            );
             if let Some(ref did) = row.sender_did {
                 tags.insert("account".to_string(), did.clone());
             }
@@ -1685,7 +2209,7 @@ fn replay_rows_as_batch(
                     command: "PRIVMSG".to_string(),
                     params: vec![target.clone(), body.to_string()],
                 };
-                send(state, session_id, format!("{chunk_msg}\r\n"));
+                send_low_priority(state, session_id, format!("{chunk_msg}\r\n"));
             }
             let mut closer_tags = std::collections::HashMap::new();
             if let Some(b) = tags.get("batch") {
@@ -1723,9 +2247,9 @@ fn replay_rows_as_batch(
                         command: "PRIVMSG".to_string(),
                         params: vec![target.clone(), body.to_string()],
                     };
-                    send(state, session_id, format!("{tag_msg}\r\n"));
+                    send_low_priority(state, session_id, format!("{tag_msg}\r\n"));
                 } else {
-                    send(
+                    send_low_priority(
                         state,
                         session_id,
                         format!(":{} PRIVMSG {} :{}\r\n", row.sender, target, body),
@@ -1741,9 +2265,9 @@ fn replay_rows_as_batch(
                 command: "PRIVMSG".to_string(),
                 params: vec![target.clone(), row.text.clone()],
             };
-            send(state, session_id, format!("{tag_msg}\r\n"));
+            send_low_priority(state, session_id, format!("{tag_msg}\r\n"));
         } else {
-            send(
+            send_low_priority(
                 state,
                 session_id,
                 format!(":{} PRIVMSG {} :{}\r\n", row.sender, target, row.text),
@@ -1805,8 +2329,9 @@ fn handle_chathistory_targets(
         50
     };
 
-    let has_batch = state.cap_batch.lock().contains(session_id);
-    let has_time = state.cap_server_time.lock().contains(session_id);
+    let caps = state.sessions.caps(session_id);
+    let has_batch = caps.has(Cap::Batch);
+    let has_time = caps.has(Cap::ServerTime);
 
     let dm_conversations = state
         .with_db(|db| db.dm_conversations(requester_did, limit))
@@ -1846,7 +2371,10 @@ fn handle_chathistory_targets(
             // (did_nicks → live session → identities table → raw DID),
             // so an offline agent with a persisted/known binding still
             // shows a name instead of the raw did:key.
-            let display_nick = state.display_nick_for_did(partner);
+            let display_nick = match partner.strip_prefix(crate::db::GUEST_DM_PREFIX) {
+                Some(guest_nick) => guest_nick.to_string(),
+                None => state.display_nick_for_did(partner),
+            };
 
             let mut tags = std::collections::HashMap::new();
             if has_batch {
@@ -1922,23 +2450,13 @@ fn handle_edit(
             _ => {
                 // Channel lookup failed — try DM key if this is a DM
                 if !is_channel {
-                    if let Some(sender_did) = conn.authenticated_did.as_deref() {
-                        if let Some(recipient_did) = state
-                            .nick_owners
-                            .lock()
-                            .get(&target.to_lowercase())
-                            .cloned()
-                        {
-                            let dm_key = crate::db::canonical_dm_key(sender_did, &recipient_did);
-                            let by_dm = state
-                                .with_db(|db| db.get_message_by_msgid(&dm_key, original_msgid));
-                            if matches!(&by_dm, Some(Some(_))) {
-                                by_dm
-                            } else {
-                                // Final fallback: global msgid search
-                                state.with_db(|db| db.find_message_by_msgid(original_msgid))
-                            }
+                    if let Some(dm_key) = dm_key(state, conn, target) {
+                        let by_dm =
+                            state.with_db(|db| db.get_message_by_msgid(&dm_key, original_msgid));
+                        if matches!(&by_dm, Some(Some(_))) {
+                            by_dm
                         } else {
+                            // Final fallback: global msgid search
                             state.with_db(|db| db.find_message_by_msgid(original_msgid))
                         }
                     } else {
@@ -1964,7 +2482,7 @@ fn handle_edit(
             } else {
                 // Fallback to nick comparison for guest (non-DID) messages
                 let original_nick = row.sender.split('!').next().unwrap_or("");
-                original_nick.eq_ignore_ascii_case(nick)
+                crate::casemap::eq(original_nick, nick)
             };
             if !is_author {
                 let reply = Message::from_server(
@@ -1976,8 +2494,8 @@ fn handle_edit(
                         "You can only edit your own messages",
                     ],
                 );
-                if let Some(tx) = state.connections.lock().get(&conn.id) {
-                    let _ = tx.try_send(format!("{reply}\r\n"));
+                if let Some(tx) = state.connections.get(&conn.id) {
+                    let _ = tx.try_send(format!("{reply}\r\n").into());
                 }
                 return;
             }
@@ -1992,8 +2510,8 @@ fn handle_edit(
                 "FAIL",
                 vec!["EDIT", "MESSAGE_NOT_FOUND", "Original message not found"],
             );
-            if let Some(tx) = state.connections.lock().get(&conn.id) {
-                let _ = tx.try_send(format!("{reply}\r\n"));
+            if let Some(tx) = state.connections.get(&conn.id) {
+                let _ = tx.try_send(format!("{reply}\r\n").into());
             }
             return;
         }
@@ -2057,7 +2575,7 @@ fn handle_edit(
         .unwrap_or(new_text);
 
     // Plain line for non-tag clients (they see it as a new message)
-    let plain_line = format!(":{hostmask} PRIVMSG {target} :{fallback_text}\r\n");
+    let plain_line = WireLine::from(format!(":{hostmask} PRIVMSG {target} :{fallback_text}\r\n"));
     // Tagged line with edit reference
     let tagged_line = {
         let tag_msg = irc::Message {
@@ -2066,7 +2584,7 @@ fn handle_edit(
             command: "PRIVMSG".to_string(),
             params: vec![target.to_string(), fallback_text.to_string()],
         };
-        format!("{tag_msg}\r\n")
+        WireLine::from(format!("{tag_msg}\r\n"))
     };
 
     let timestamp = std::time::SystemTime::now()
@@ -2086,7 +2604,7 @@ fn handle_edit(
             command: "PRIVMSG".to_string(),
             params: vec![target.to_string(), fallback_text.to_string()],
         };
-        format!("{tag_msg}\r\n")
+        WireLine::from(format!("{tag_msg}\r\n"))
     };
 
     // Store in DB
@@ -2099,19 +2617,8 @@ fn handle_edit(
     // edits appear in CHATHISTORY alongside the original message.
     let store_channel = if is_channel {
         target.to_string()
-    } else if let Some(sender_did) = conn.authenticated_did.as_deref() {
-        if let Some(recipient_did) = state
-            .nick_owners
-            .lock()
-            .get(&target.to_lowercase())
-            .cloned()
-        {
-            crate::db::canonical_dm_key(sender_did, &recipient_did)
-        } else {
-            target.to_string()
-        }
     } else {
-        target.to_string()
+        dm_key(state, conn, target).unwrap_or_else(|| target.to_string())
     };
     let editor_did = conn.authenticated_did.as_deref();
     state.with_db(|db| {
@@ -2131,8 +2638,7 @@ fn handle_edit(
     // Note: we keep the original msgid stable so that subsequent edits
     // (e.g., streaming) can still find the message by original_msgid.
     if is_channel {
-        let mut channels = state.channels.lock();
-        if let Some(ch) = channels.get_mut(target) {
+        if let Some(mut ch) = state.channels.get(target) {
             for hist in ch.history.iter_mut() {
                 if hist.msgid.as_deref() == Some(original_msgid) {
                     hist.text = new_text.to_string();
@@ -2148,18 +2654,14 @@ fn handle_edit(
         // Channel: deliver to all members
         let members: Vec<String> = state
             .channels
-            .lock()
             .get(target)
             .map(|ch| ch.members.iter().cloned().collect())
             .unwrap_or_default();
 
-        let tag_caps = state.cap_message_tags.lock();
-        let time_caps = state.cap_server_time.lock();
-        let echo_caps = state.cap_echo_message.lock();
-        let multiline_caps = state.cap_draft_multiline.lock();
-        let conns = state.connections.lock();
+        let conns = &state.connections;
         for sid in &members {
-            if sid == &conn.id && !echo_caps.contains(sid) {
+            let member_caps = state.sessions.caps(sid);
+            if sid == &conn.id && !member_caps.has(Cap::EchoMessage) {
                 continue;
             }
             if let Some(tx) = conns.get(sid) {
@@ -2167,11 +2669,11 @@ fn handle_edit(
                 // emit BATCH-wrapped edit (opener carries +draft/edit + msgid).
                 if let (Some(lines), Some(batch_id)) =
                     (multiline_lines.as_deref(), outbound_batch_id.as_deref())
-                    && multiline_caps.contains(sid)
+                    && member_caps.has(Cap::DraftMultiline)
                 {
                     let caps = super::draft_multiline::ReceiverCaps {
-                        has_tags: tag_caps.contains(sid),
-                        has_time: time_caps.contains(sid),
+                        has_tags: member_caps.has(Cap::MessageTags),
+                        has_time: member_caps.has(Cap::ServerTime),
                         has_multiline: true,
                         wants_account: false,
                         sender_did: None,
@@ -2189,13 +2691,13 @@ fn handle_edit(
                     for frame in
                         super::draft_multiline::build_outbound_multiline_frames(&ctx, &caps)
                     {
-                        let _ = tx.try_send(frame);
+                        let _ = tx.try_send(frame.into());
                     }
                     continue;
                 }
                 // Fallback: single PRIVMSG (line1 only for multi-line edits).
-                let line = if tag_caps.contains(sid) {
-                    if time_caps.contains(sid) {
+                let line = if member_caps.has(Cap::MessageTags) {
+                    if member_caps.has(Cap::ServerTime) {
                         &tagged_line_with_time
                     } else {
                         &tagged_line
@@ -2247,10 +2749,14 @@ fn handle_edit(
 
         // Per-session deliver helper: BATCH frames for multiline-capable
         // receivers, fallback single-PRIVMSG (line1 only) otherwise.
-        let deliver_to_session = |tx: &tokio::sync::mpsc::Sender<String>, sid: &str| {
-            let has_tags = state.cap_message_tags.lock().contains(sid);
-            let has_time = state.cap_server_time.lock().contains(sid);
-            let has_multiline = state.cap_draft_multiline.lock().contains(sid);
+        let deliver_to_session = |sid: &str| {
+            let caps = state.sessions.caps(sid);
+            let has_tags = caps.has(Cap::MessageTags);
+            let has_time = caps.has(Cap::ServerTime);
+            let has_multiline = caps.has(Cap::DraftMultiline);
+            let Some(tx) = state.connections.get(sid) else {
+                return;
+            };
             if let (Some(lines), Some(batch_id)) =
                 (multiline_lines.as_deref(), outbound_batch_id.as_deref())
                 && has_multiline
@@ -2273,7 +2779,7 @@ fn handle_edit(
                     lines,
                 };
                 for frame in super::draft_multiline::build_outbound_multiline_frames(&ctx, &caps) {
-                    let _ = tx.try_send(frame);
+                    let _ = tx.try_send(frame.into());
                 }
                 return;
             }
@@ -2316,28 +2822,21 @@ fn handle_edit(
                     }
                 };
 
-                let conns = state.connections.lock();
                 // Deliver to all target sessions
                 for target_session in &target_sessions {
-                    if let Some(tx) = conns.get(target_session) {
-                        deliver_to_session(tx, target_session);
-                    }
+                    deliver_to_session(target_session);
                 }
 
                 // Echo to sender if echo-message enabled
-                if state.cap_echo_message.lock().contains(&conn.id)
-                    && let Some(tx) = conns.get(&conn.id)
-                {
-                    deliver_to_session(tx, &conn.id);
+                if state.sessions.has_cap(&conn.id, Cap::EchoMessage) {
+                    deliver_to_session(&conn.id);
                 }
             }
             RouteResult::Relayed => {
                 // Target is on a federated peer — edit was relayed
                 // Echo to sender
-                if state.cap_echo_message.lock().contains(&conn.id)
-                    && let Some(tx) = state.connections.lock().get(&conn.id)
-                {
-                    deliver_to_session(tx, &conn.id);
+                if state.sessions.has_cap(&conn.id, Cap::EchoMessage) {
+                    deliver_to_session(&conn.id);
                 }
             }
             RouteResult::Unreachable => {
@@ -2347,8 +2846,8 @@ fn handle_edit(
                     irc::ERR_NOSUCHNICK,
                     vec![&nick, target, "No such nick"],
                 );
-                if let Some(tx) = state.connections.lock().get(&conn.id) {
-                    let _ = tx.try_send(format!("{reply}\r\n"));
+                if let Some(tx) = state.connections.get(&conn.id) {
+                    let _ = tx.try_send(format!("{reply}\r\n").into());
                 }
             }
         }
@@ -2380,14 +2879,13 @@ fn handle_delete(conn: &Connection, target: &str, original_msgid: &str, state: &
             } else {
                 // Fallback to nick comparison for guest (non-DID) messages
                 let original_nick = row.sender.split('!').next().unwrap_or("");
-                original_nick.eq_ignore_ascii_case(nick)
+                crate::casemap::eq(original_nick, nick)
             };
             if !is_author {
                 // Also allow ops to delete messages (channels only)
                 let is_op = is_channel
                     && state
                         .channels
-                        .lock()
                         .get(target)
                         .map(|ch| ch.ops.contains(&conn.id))
                         .unwrap_or(false);
@@ -2401,8 +2899,8 @@ fn handle_delete(conn: &Connection, target: &str, original_msgid: &str, state: &
                             "You can only delete your own messages",
                         ],
                     );
-                    if let Some(tx) = state.connections.lock().get(&conn.id) {
-                        let _ = tx.try_send(format!("{reply}\r\n"));
+                    if let Some(tx) = state.connections.get(&conn.id) {
+                        let _ = tx.try_send(format!("{reply}\r\n").into());
                     }
                     return;
                 }
@@ -2418,8 +2916,8 @@ fn handle_delete(conn: &Connection, target: &str, original_msgid: &str, state: &
                 "FAIL",
                 vec!["DELETE", "MESSAGE_NOT_FOUND", "Original message not found"],
             );
-            if let Some(tx) = state.connections.lock().get(&conn.id) {
-                let _ = tx.try_send(format!("{reply}\r\n"));
+            if let Some(tx) = state.connections.get(&conn.id) {
+                let _ = tx.try_send(format!("{reply}\r\n").into());
             }
             return;
         }
@@ -2430,8 +2928,7 @@ fn handle_delete(conn: &Connection, target: &str, original_msgid: &str, state: &
 
     // Remove from in-memory history and pins (channels only)
     if is_channel {
-        let mut channels = state.channels.lock();
-        if let Some(ch) = channels.get_mut(target) {
+        if let Some(mut ch) = state.channels.get(target) {
             ch.history
                 .retain(|h| h.msgid.as_deref() != Some(original_msgid));
             ch.pins.retain(|p| p.msgid != original_msgid);
@@ -2448,7 +2945,7 @@ fn handle_delete(conn: &Connection, target: &str, original_msgid: &str, state: &
             command: "TAGMSG".to_string(),
             params: vec![target.to_string()],
         };
-        format!("{tag_msg}\r\n")
+        WireLine::from(format!("{tag_msg}\r\n"))
     };
 
     // Deliver delete notification
@@ -2456,18 +2953,16 @@ fn handle_delete(conn: &Connection, target: &str, original_msgid: &str, state: &
         // Channel: deliver to tag-capable members only (plain clients can't see deletes)
         let members: Vec<String> = state
             .channels
-            .lock()
             .get(target)
             .map(|ch| ch.members.iter().cloned().collect())
             .unwrap_or_default();
 
-        let tag_caps = state.cap_message_tags.lock();
-        let conns = state.connections.lock();
+        let conns = &state.connections;
         for sid in &members {
             if sid == &conn.id {
                 continue; // Don't echo delete back to sender
             }
-            if tag_caps.contains(sid)
+            if state.sessions.has_cap(sid, Cap::MessageTags)
                 && let Some(tx) = conns.get(sid)
             {
                 let _ = tx.try_send(tagged_line.clone());
@@ -2498,10 +2993,9 @@ fn handle_delete(conn: &Connection, target: &str, original_msgid: &str, state: &
                 }
             };
 
-            let tag_caps = state.cap_message_tags.lock();
-            let conns = state.connections.lock();
+            let conns = &state.connections;
             for target_session in &target_sessions {
-                if tag_caps.contains(target_session)
+                if state.sessions.has_cap(target_session, Cap::MessageTags)
                     && let Some(tx) = conns.get(target_session)
                 {
                     let _ = tx.try_send(tagged_line.clone());
@@ -2516,8 +3010,8 @@ fn handle_delete(conn: &Connection, target: &str, original_msgid: &str, state: &
 
 /// Send a line to a specific session.
 fn send_to(state: &Arc<SharedState>, session_id: &str, line: String) {
-    if let Some(tx) = state.connections.lock().get(session_id) {
-        let _ = tx.try_send(line);
+    if let Some(tx) = state.connections.get(session_id) {
+        let _ = tx.try_send(line.into());
     }
 }
 
@@ -2960,17 +3454,17 @@ fn handle_av_tagmsg(
             let mgr = state.av_sessions.lock();
             let can_end = mgr.can_end_session(&session_id, &did)
                 || state.server_opers.lock().contains(&conn.id);
+            drop(mgr);
             // Also check if user is channel op
             let is_chan_op = if target.starts_with('#') || target.starts_with('&') {
-                let channels = state.channels.lock();
-                channels
+                state
+                    .channels
                     .get(target)
                     .map(|ch| ch.ops.contains(&conn.id) || ch.did_ops.contains(&did))
                     .unwrap_or(false)
             } else {
                 false
             };
-            drop(mgr);
 
             if !can_end && !is_chan_op {
                 let reply = Message::from_server(
@@ -3048,14 +3542,13 @@ fn handle_av_tagmsg(
 /// Broadcast a plain NOTICE to all channel members (used for AV session events from S2S).
 pub fn broadcast_av_notice(state: &Arc<SharedState>, channel: &str, text: &str) {
     let notice = Message::from_server(&state.server_name, "NOTICE", vec![channel, text]);
-    let line = format!("{notice}\r\n");
+    let line = WireLine::from(format!("{notice}\r\n"));
     let members: Vec<String> = state
         .channels
-        .lock()
         .get(channel)
         .map(|ch| ch.members.iter().cloned().collect())
         .unwrap_or_default();
-    let conns = state.connections.lock();
+    let conns = &state.connections;
     for member in &members {
         if let Some(tx) = conns.get(member) {
             let _ = tx.try_send(line.clone());
@@ -3116,7 +3609,7 @@ fn broadcast_av_state(
         command: "TAGMSG".to_string(),
         params: vec![target.to_string()],
     };
-    let line = format!("{tag_msg}\r\n");
+    let line = WireLine::from(format!("{tag_msg}\r\n"));
 
     // Also send a human-readable NOTICE for clients that don't parse tags
     let notice_text = match action {
@@ -3136,22 +3629,21 @@ fn broadcast_av_state(
         _ => return,
     };
     let notice = Message::from_server(&state.server_name, "NOTICE", vec![target, &notice_text]);
-    let notice_line = format!("{notice}\r\n");
+    let notice_line = WireLine::from(format!("{notice}\r\n"));
 
     // Broadcast to channel members
     if target.starts_with('#') || target.starts_with('&') {
         let members: Vec<String> = state
             .channels
-            .lock()
             .get(target)
             .map(|ch| ch.members.iter().cloned().collect())
             .unwrap_or_default();
 
-        let tag_caps = state.cap_message_tags.lock();
-        let conns = state.connections.lock();
+        let conns = &state.connections;
         for member in &members {
+            let has_tags = state.sessions.has_cap(member, Cap::MessageTags);
             if let Some(tx) = conns.get(member) {
-                if tag_caps.contains(member) {
+                if has_tags {
                     let _ = tx.try_send(line.clone());
                 } else {
                     let _ = tx.try_send(notice_line.clone());
@@ -0,0 +1,739 @@
+//! IRCv3 `draft/metadata-2`: key/value metadata on users and channels.
+//!
+//! See https://ircv3.net/specs/extensions/metadata.
+//!
+//! - User metadata belongs to the DID when the session is authenticated
+//!   (persisted, shared by all of that DID's sessions) and to the session
+//!   otherwise (dropped on disconnect).
+//! - Channel metadata is set by channel ops and persisted with the channel.
+//! - Keys under `private/` have visibility `private`: only the owner (for
+//!   channels, the ops) can read them, and they are never broadcast.
+//! - Metadata lives on this server only; it is not federated over S2S.
+//! - `bot` is read-only and not stored: it's the actor class of users
+//!   flagged as bots (`MODE <nick> +B`, or `AGENT REGISTER`), so clients
+//!   can mark them in member lists.
+
+use super::Connection;
+use super::helpers::normalize_channel;
+use crate::irc::{self, Message};
+use crate::server::SharedState;
+use crate::session::Cap;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::sync::Arc;
+
+/// Maximum keys per user or channel.
+pub const MAX_KEYS: usize = 25;
+/// Maximum value length in bytes.
+pub const MAX_VALUE_BYTES: usize = 300;
+/// Maximum subscriptions per session.
+pub const MAX_SUBS: usize = 50;
+const MAX_KEY_LEN: usize = 64;
+const PRIVATE_PREFIX: &str = "private/";
+/// The read-only key the server sets on bots.
+const BOT_KEY: &str = "bot";
+
+/// Value advertised in CAP LS.
+pub fn cap_value() -> String {
+    format!(
+        "{}=max-subs={MAX_SUBS},max-keys={MAX_KEYS},max-value-bytes={MAX_VALUE_BYTES}",
+        freeq_proto::caps::METADATA
+    )
+}
+
+/// Key names: lowercase letters, digits and `_ . / : -`, not starting with `:`.
+fn valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && key.len() <= MAX_KEY_LEN
+        && !key.starts_with(':')
+        && key
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b"_./:-".contains(&b))
+}
+
+fn visibility(key: &str) -> &'static str {
+    if key.starts_with(PRIVATE_PREFIX) {
+        "private"
+    } else {
+        "*"
+    }
+}
+
+/// Metadata owner for a session: its DID, or the session itself for guests.
+fn session_owner(state: &SharedState, session_id: &str) -> String {
+    state
+        .session_dids
+        .lock()
+        .get(session_id)
+        .cloned()
+        .unwrap_or_else(|| session_id.to_string())
+}
+
+/// Resolved METADATA target.
+enum Target {
+    User {
+        owner: String,
+        nick: String,
+        session: String,
+    },
+    Channel {
+        name: String,
+    },
+}
+
+impl Target {
+    fn owner(&self) -> &str {
+        match self {
+            Target::User { owner, .. } => owner,
+            Target::Channel { name } => name,
+        }
+    }
+
+    /// How the target is named in replies and notifications.
+    fn display(&self) -> &str {
+        match self {
+            Target::User { nick, .. } => nick,
+            Target::Channel { name } => name,
+        }
+    }
+
+    /// Only DIDs and channels outlive the session.
+    fn persistent(&self) -> bool {
+        match self {
+            Target::User { owner, .. } => owner.starts_with("did:"),
+            Target::Channel { .. } => true,
+        }
+    }
+}
+
+fn resolve_target(
+    state: &SharedState,
+    conn: &Connection,
+    session_id: &str,
+    target: &str,
+) -> Option<Target> {
+    if target == "*" {
+        return Some(Target::User {
+            owner: session_owner(state, session_id),
+            nick: conn.nick_or_star().to_string(),
+            session: session_id.to_string(),
+        });
+    }
+    if target.starts_with('#') || target.starts_with('&') {
+        let name = normalize_channel(target);
+        return state
+            .channels
+            .contains_key(&name)
+            .then_some(Target::Channel { name });
+    }
+    let (session, nick) = {
+        let nicks = state.nick_to_session.lock();
+        let session = nicks.get_session(target)?.to_string();
+        let nick = nicks.get_nick(&session).unwrap_or(target).to_string();
+        (session, nick)
+    };
+    Some(Target::User {
+        owner: session_owner(state, &session),
+        nick,
+        session,
+    })
+}
+
+/// `target`'s stored keys, plus `bot` when it's a bot.
+fn values(state: &SharedState, target: &Target) -> BTreeMap<String, String> {
+    let mut values = state
+        .metadata
+        .lock()
+        .get(target.owner())
+        .cloned()
+        .unwrap_or_default();
+    if let Target::User { session, .. } = target
+        && let Some(class) = bot_class(state, session)
+    {
+        values.insert(BOT_KEY.to_string(), class);
+    }
+    values
+}
+
+/// The actor class of `session` when it's a bot.
+fn bot_class(state: &SharedState, session: &str) -> Option<String> {
+    state
+        .session_actor_class
+        .lock()
+        .get(session)
+        .filter(|c| **c != super::ActorClass::Human)
+        .map(|c| c.to_string())
+}
+
+/// Can `session_id` change `target`'s metadata? Users edit their own;
+/// channel ops and server opers edit a channel's.
+fn can_write(state: &SharedState, session_id: &str, target: &Target) -> bool {
+    match target {
+        Target::User { owner, .. } => *owner == session_owner(state, session_id),
+        Target::Channel { name } => {
+            state
+                .channels
+                .get(name)
+                .is_some_and(|ch| ch.ops.contains(session_id))
+                || state.server_opers.lock().contains(session_id)
+        }
+    }
+}
+
+/// Can `session_id` read `key` on `target`? Public keys are readable by
+/// anyone who can see the target, which for a +s or +i channel means its
+/// members; private ones only by those who could write them.
+fn can_read(state: &SharedState, session_id: &str, target: &Target, key: &str) -> bool {
+    if can_write(state, session_id, target) {
+        return true;
+    }
+    visibility(key) == "*"
+        && match target {
+            Target::User { .. } => true,
+            Target::Channel { name } => state.channels.get(name).is_some_and(|ch| {
+                ch.members.contains(session_id) || !(ch.secret || ch.invite_only)
+            }),
+        }
+}
+
+/// `:server METADATA <target> <key> <visibility> [:<value>]`.
+fn notification(server_name: &str, target: &str, key: &str, value: Option<&str>) -> Message {
+    let mut params = vec![target, key, visibility(key)];
+    params.extend(value);
+    Message::from_server(server_name, "METADATA", params)
+}
+
+/// Sessions that share a channel with `session_id`, plus the session itself.
+fn sessions_sharing_channels(state: &SharedState, session_id: &str) -> HashSet<String> {
+    let mut out = HashSet::new();
+    state.channels.for_each(|_, ch| {
+        if ch.members.contains(session_id) {
+            out.extend(ch.members.iter().cloned());
+        }
+    });
+    out.insert(session_id.to_string());
+    out
+}
+
+/// Tell subscribers of `key` who can see `target` that it changed.
+/// The session that made the change gets a numeric reply instead.
+fn notify_change(
+    state: &Arc<SharedState>,
+    server_name: &str,
+    origin_session: &str,
+    target: &Target,
+    key: &str,
+    value: Option<&str>,
+    send: &impl Fn(&Arc<SharedState>, &str, String),
+) {
+    let audience: HashSet<String> = match target {
+        Target::User { owner, session, .. } => {
+            // The owner's other devices always hear about it.
+            let mut audience = state
+                .did_sessions
+                .lock()
+                .get(owner)
+                .cloned()
+                .unwrap_or_default();
+            if visibility(key) == "*" {
+                audience.extend(sessions_sharing_channels(state, session));
+            }
+            audience
+        }
+        Target::Channel { name } => match state.channels.get(name) {
+            Some(ch) if visibility(key) == "*" => ch.members.clone(),
+            Some(ch) => ch.ops.clone(),
+            None => HashSet::new(),
+        },
+    };
+    let recipients: Vec<String> = {
+        let subs = state.metadata_subs.lock();
+        audience
+            .into_iter()
+            .filter(|sid| sid != origin_session)
+            .filter(|sid| subs.get(sid).is_some_and(|keys| keys.contains(key)))
+            .collect()
+    };
+    if recipients.is_empty() {
+        return;
+    }
+    let line = notification(server_name, target.display(), key, value);
+    for sid in recipients {
+        send(state, &sid, format!("{line}\r\n"));
+    }
+}
+
+/// Store or remove one key, persisting it when the owner outlives the session.
+fn store(state: &SharedState, target: &Target, key: &str, value: Option<&str>) {
+    {
+        let mut metadata = state.metadata.lock();
+        match value {
+            Some(v) => {
+                metadata
+                    .entry(target.owner().to_string())
+                    .or_default()
+                    .insert(key.to_string(), v.to_string());
+            }
+            None => {
+                if let Some(keys) = metadata.get_mut(target.owner()) {
+                    keys.remove(key);
+                    if keys.is_empty() {
+                        metadata.remove(target.owner());
+                    }
+                }
+            }
+        }
+    }
+    if target.persistent() {
+        state.with_db(|db| db.set_metadata(target.owner(), key, value));
+    }
+}
+
+/// Replies to one METADATA command, wrapped in a `metadata` batch when the
+/// client negotiated `batch`.
+struct Replies<'a, F: Fn(&Arc<SharedState>, &str, String)> {
+    state: &'a Arc<SharedState>,
+    server_name: &'a str,
+    session_id: &'a str,
+    send: &'a F,
+    batch_id: Option<String>,
+}
+
+impl<'a, F: Fn(&Arc<SharedState>, &str, String)> Replies<'a, F> {
+    fn start(
+        state: &'a Arc<SharedState>,
+        server_name: &'a str,
+        session_id: &'a str,
+        send: &'a F,
+    ) -> Self {
+        let batch_id = state
+            .sessions
+            .has_cap(session_id, Cap::Batch)
+            .then(|| format!("md{}", crate::msgid::generate()));
+        if let Some(ref id) = batch_id {
+            send(
+                state,
+                session_id,
+                format!(":{server_name} BATCH +{id} metadata\r\n"),
+            );
+        }
+        Self {
+            state,
+            server_name,
+            session_id,
+            send,
+            batch_id,
+        }
+    }
+
+    fn line(&self, mut msg: Message) {
+        if let Some(ref id) = self.batch_id {
+            msg.tags.insert("batch".to_string(), id.clone());
+        }
+        (self.send)(self.state, self.session_id, format!("{msg}\r\n"));
+    }
+
+    fn numeric(&self, numeric: &str, params: Vec<&str>) {
+        self.line(Message::from_server(self.server_name, numeric, params));
+    }
+}
+
+impl<F: Fn(&Arc<SharedState>, &str, String)> Drop for Replies<'_, F> {
+    fn drop(&mut self) {
+        if let Some(ref id) = self.batch_id {
+            (self.send)(
+                self.state,
+                self.session_id,
+                format!(":{} BATCH -{id}\r\n", self.server_name),
+            );
+        }
+    }
+}
+
+/// One of the caller's own keys (owned by their DID, or the session for
+/// guests).
+pub(super) fn own_key(state: &SharedState, session_id: &str, key: &str) -> Option<String> {
+    let owner = session_owner(state, session_id);
+    state.metadata.lock().get(&owner)?.get(key).cloned()
+}
+
+/// Set or clear one of the caller's own keys, for commands that keep
+/// their settings in the metadata store. Subscribers are not notified.
+pub(super) fn set_own_key(
+    state: &SharedState,
+    conn: &Connection,
+    session_id: &str,
+    key: &str,
+    value: Option<&str>,
+) {
+    let target = Target::User {
+        owner: session_owner(state, session_id),
+        nick: conn.nick_or_star().to_string(),
+        session: session_id.to_string(),
+    };
+    store(state, &target, key, value);
+}
+
+/// Tell `bot` subscribers that `session_id` was flagged or unflagged as
+/// a bot.
+pub(super) fn notify_bot(
+    state: &Arc<SharedState>,
+    server_name: &str,
+    conn: &Connection,
+    session_id: &str,
+    send: &impl Fn(&Arc<SharedState>, &str, String),
+) {
+    let target = Target::User {
+        owner: session_owner(state, session_id),
+        nick: conn.nick_or_star().to_string(),
+        session: session_id.to_string(),
+    };
+    let value = bot_class(state, session_id);
+    notify_change(
+        state,
+        server_name,
+        session_id,
+        &target,
+        BOT_KEY,
+        value.as_deref(),
+        send,
+    );
+}
+
+/// `METADATA <target> <subcommand> [params...]`.
+pub(super) fn handle_metadata(
+    conn: &Connection,
+    msg: &Message,
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    send: &impl Fn(&Arc<SharedState>, &str, String),
+) {
+    let nick = conn.nick_or_star();
+    let fail = |params: &[&str]| {
+        let mut p = vec!["METADATA"];
+        p.extend_from_slice(params);
+        let reply = Message::from_server(server_name, "FAIL", p);
+        send(state, session_id, format!("{reply}\r\n"));
+    };
+
+    let (Some(target), Some(sub)) = (msg.params.first(), msg.params.get(1)) else {
+        let reply = Message::from_server(
+            server_name,
+            irc::ERR_NEEDMOREPARAMS,
+            vec![nick, "METADATA", "Not enough parameters"],
+        );
+        send(state, session_id, format!("{reply}\r\n"));
+        return;
+    };
+    let args = &msg.params[2..];
+    let sub = sub.to_ascii_uppercase();
+
+    // Subscriptions are per-session and ignore the target.
+    match sub.as_str() {
+        "SUB" | "UNSUB" => {
+            let mut changed = Vec::new();
+            for key in args.iter().flat_map(|a| a.split_whitespace()) {
+                if !valid_key(key) {
+                    fail(&["KEY_INVALID", key, "Invalid key"]);
+                    continue;
+                }
+                let mut subs = state.metadata_subs.lock();
+                let keys = subs.entry(session_id.to_string()).or_default();
+                if sub == "SUB" {
+                    if !keys.contains(key) && keys.len() >= MAX_SUBS {
+                        drop(subs);
+                        fail(&["TOO_MANY_SUBS", key, "Too many subscriptions"]);
+                        break;
+                    }
+                    keys.insert(key.to_string());
+                } else {
+                    keys.remove(key);
+                }
+                changed.push(key);
+            }
+            if !changed.is_empty() {
+                let numeric = if sub == "SUB" {
+                    irc::RPL_METADATASUBOK
+                } else {
+                    irc::RPL_METADATAUNSUBOK
+                };
+                let keys = changed.join(" ");
+                let reply = Message::from_server(server_name, numeric, vec![nick, &keys]);
+                send(state, session_id, format!("{reply}\r\n"));
+            }
+            return;
+        }
+        "SUBS" => {
+            let keys: Vec<String> = state
+                .metadata_subs
+                .lock()
+                .get(session_id)
+                .map(|k| k.iter().cloned().collect())
+                .unwrap_or_default();
+            let replies = Replies::start(state, server_name, session_id, send);
+            if !keys.is_empty() {
+                replies.numeric(irc::RPL_METADATASUBS, vec![nick, &keys.join(" ")]);
+            }
+            return;
+        }
+        _ => {}
+    }
+
+    let Some(target) = resolve_target(state, conn, session_id, target) else {
+        fail(&["INVALID_TARGET", target, "Invalid metadata target"]);
+        return;
+    };
+    let shown = target.display();
+    let current = state
+        .metadata
+        .lock()
+        .get(target.owner())
+        .cloned()
+        .unwrap_or_default();
+    let readable = values(state, &target);
+
+    match sub.as_str() {
+        "GET" => {
+            if args.is_empty() {
+                fail(&["KEY_INVALID", "*", "No keys given"]);
+                return;
+            }
+            let replies = Replies::start(state, server_name, session_id, send);
+            for key in args {
+                if !valid_key(key) {
+                    fail(&["KEY_INVALID", key, "Invalid key"]);
+                } else if !can_read(state, session_id, &target, key) {
+                    fail(&["KEY_NO_PERMISSION", shown, key, "Permission denied"]);
+                } else if let Some(value) = readable.get(key.as_str()) {
+                    replies.numeric(
+                        irc::RPL_KEYVALUE,
+                        vec![nick, shown, key, visibility(key), value],
+                    );
+                } else {
+                    replies.numeric(irc::RPL_KEYNOTSET, vec![nick, shown, key, "Key not set"]);
+                }
+            }
+        }
+        "LIST" => {
+            let replies = Replies::start(state, server_name, session_id, send);
+            for (key, value) in &readable {
+                if can_read(state, session_id, &target, key) {
+                    replies.numeric(
+                        irc::RPL_KEYVALUE,
+                        vec![nick, shown, key, visibility(key), value],
+                    );
+                }
+            }
+        }
+        "SYNC" => {
+            // Everything the client subscribed to, for the target and (for
+            // channels) each local member.
+            let subs = state
+                .metadata_subs
+                .lock()
+                .get(session_id)
+                .cloned()
+                .unwrap_or_default();
+            let replies = Replies::start(state, server_name, session_id, send);
+            for line in sync_lines(state, server_name, session_id, &target, &subs) {
+                replies.line(line);
+            }
+        }
+        "SET" => {
+            let Some(key) = args.first() else {
+                fail(&["KEY_INVALID", "*", "No key given"]);
+                return;
+            };
+            let value = args.get(1).map(String::as_str);
+            if !valid_key(key) {
+                fail(&["KEY_INVALID", key, "Invalid key"]);
+                return;
+            }
+            if !can_write(state, session_id, &target) || key == BOT_KEY {
+                fail(&["KEY_NO_PERMISSION", shown, key, "Permission denied"]);
+                return;
+            }
+            if let Some(v) = value {
+                if v.len() > MAX_VALUE_BYTES || v.chars().any(|c| c.is_control()) {
+                    fail(&[
+                        "VALUE_INVALID",
+                        "Value is too long or contains control characters",
+                    ]);
+                    return;
+                }
+                if !current.contains_key(key.as_str()) && current.len() >= MAX_KEYS {
+                    fail(&["LIMIT_REACHED", shown, "Too many metadata keys"]);
+                    return;
+                }
+            }
+            store(state, &target, key, value);
+            match value {
+                Some(v) => {
+                    let reply = Message::from_server(
+                        server_name,
+                        irc::RPL_KEYVALUE,
+                        vec![nick, shown, key, visibility(key), v],
+                    );
+                    send(state, session_id, format!("{reply}\r\n"));
+                }
+                None => {
+                    let reply = Message::from_server(
+                        server_name,
+                        irc::RPL_KEYNOTSET,
+                        vec![nick, shown, key, "Key not set"],
+                    );
+                    send(state, session_id, format!("{reply}\r\n"));
+                }
+            }
+            if current.get(key.as_str()).map(String::as_str) != value {
+                notify_change(state, server_name, session_id, &target, key, value, send);
+            }
+        }
+        "CLEAR" => {
+            if !can_write(state, session_id, &target) {
+                fail(&["KEY_NO_PERMISSION", shown, "*", "Permission denied"]);
+                return;
+            }
+            let replies = Replies::start(state, server_name, session_id, send);
+            for key in current.keys() {
+                store(state, &target, key, None);
+                replies.numeric(irc::RPL_KEYVALUE, vec![nick, shown, key, visibility(key)]);
+                notify_change(state, server_name, session_id, &target, key, None, send);
+            }
+        }
+        _ => fail(&["SUBCOMMAND_INVALID", &sub, "Unknown METADATA subcommand"]),
+    }
+}
+
+/// METADATA lines for the subscribed keys of `target` (and, for a channel,
+/// of its local members) that `session_id` may read.
+fn sync_lines(
+    state: &SharedState,
+    server_name: &str,
+    session_id: &str,
+    target: &Target,
+    subs: &BTreeSet<String>,
+) -> Vec<Message> {
+    let mut targets = Vec::new();
+    match target {
+        Target::Channel { name } => {
+            targets.push(Target::Channel { name: name.clone() });
+            let members: Vec<(String, String)> = state
+                .channels
+                .get(name)
+                .map(|ch| {
+                    let nicks = state.nick_to_session.lock();
+                    ch.members
+                        .iter()
+                        .filter_map(|sid| Some((sid.clone(), nicks.get_nick(sid)?.to_string())))
+                        .collect()
+                })
+                .unwrap_or_default();
+            for (session, nick) in members {
+                targets.push(Target::User {
+                    owner: session_owner(state, &session),
+                    nick,
+                    session,
+                });
+            }
+        }
+        Target::User {
+            owner,
+            nick,
+            session,
+        } => targets.push(Target::User {
+            owner: owner.clone(),
+            nick: nick.clone(),
+            session: session.clone(),
+        }),
+    }
+
+    let mut lines = Vec::new();
+    for t in &targets {
+        let values = values(state, t);
+        for (key, value) in values.iter().filter(|(k, _)| subs.contains(*k)) {
+            if can_read(state, session_id, t, key) {
+                lines.push(notification(server_name, t.display(), key, Some(value)));
+            }
+        }
+    }
+    lines
+}
+
+/// After a JOIN: send the joiner the channel's and members' subscribed
+/// metadata, and send subscribed members the joiner's.
+pub(super) fn sync_on_join(
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    nick: &str,
+    channel: &str,
+    send: &impl Fn(&Arc<SharedState>, &str, String),
+) {
+    let subs = state
+        .metadata_subs
+        .lock()
+        .get(session_id)
+        .cloned()
+        .unwrap_or_default();
+    if !subs.is_empty() {
+        let target = Target::Channel {
+            name: channel.to_string(),
+        };
+        for line in sync_lines(state, server_name, session_id, &target, &subs) {
+            send(state, session_id, format!("{line}\r\n"));
+        }
+    }
+
+    let joiner = values(
+        state,
+        &Target::User {
+            owner: session_owner(state, session_id),
+            nick: nick.to_string(),
+            session: session_id.to_string(),
+        },
+    );
+    if joiner.is_empty() {
+        return;
+    }
+    let members: Vec<String> = state
+        .channels
+        .get(channel)
+        .map(|ch| ch.members.iter().cloned().collect())
+        .unwrap_or_default();
+    let all_subs = state.metadata_subs.lock().clone();
+    for sid in members.iter().filter(|s| *s != session_id) {
+        let Some(keys) = all_subs.get(sid) else {
+            continue;
+        };
+        for (key, value) in joiner.iter().filter(|(k, _)| keys.contains(*k)) {
+            if visibility(key) == "*" {
+                let line = notification(server_name, nick, key, Some(value));
+                send(state, sid, format!("{line}\r\n"));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_names() {
+        assert!(valid_key("pronouns"));
+        assert!(valid_key("client/prefs.theme"));
+        assert!(valid_key("private/notes"));
+        assert!(!valid_key(""));
+        assert!(!valid_key("Upper"));
+        assert!(!valid_key(":leading-colon"));
+        assert!(!valid_key("has space"));
+        assert!(!valid_key(&"k".repeat(MAX_KEY_LEN + 1)));
+    }
+
+    #[test]
+    fn private_keys_are_private() {
+        assert_eq!(visibility("url"), "*");
+        assert_eq!(visibility("private/notes"), "private");
+    }
+}
@@ -16,17 +16,37 @@
 //! - [`queries`] — WHOIS, WHO, LUSERS, AWAY
 //! - [`helpers`] — S2S broadcast, channel delivery, utility functions
 
+mod autojoin_cmd;
+mod backup_cmd;
 mod cap;
+mod case_cmd;
 mod channel;
 pub(crate) mod draft_multiline;
+mod email_cmd;
+mod filter_cmd;
 pub mod helpers;
+mod helpop_cmd;
+mod invitelink_cmd;
+mod language_cmd;
 pub(crate) mod login;
 pub(crate) mod messaging;
+mod metadata;
+pub(crate) mod names;
+pub(crate) mod mydata;
+mod p2p;
 mod policy_cmd;
+mod pref_cmd;
+mod privacy_cmd;
+mod probation;
 mod provenance;
 mod queries;
+mod reclaim_cmd;
 mod registration;
 pub(crate) mod routing;
+mod s2s_cmd;
+mod sessions_cmd;
+mod user_mode;
+pub mod webirc;
 
 use std::sync::Arc;
 
@@ -36,19 +56,35 @@ use tokio::net::TcpStream;
 use tokio::sync::mpsc;
 
 use crate::irc::{self, Message};
-use crate::server::SharedState;
+use crate::server::{SharedState, WireLine};
+use crate::session::Cap;
 use base64::Engine;
 
+use autojoin_cmd::handle_autojoin;
+use backup_cmd::handle_backup;
 use cap::{handle_authenticate, handle_cap};
+use case_cmd::handle_case;
 use channel::{
     handle_invite, handle_join, handle_kick, handle_list, handle_mode, handle_names, handle_part,
-    handle_topic,
+    handle_stats, handle_topic, handle_topichist,
 };
+use email_cmd::handle_email;
+use filter_cmd::handle_filter;
 use helpers::{normalize_channel, s2s_broadcast, s2s_next_event_id};
+use helpop_cmd::handle_helpop;
+use invitelink_cmd::handle_invitelink;
+use language_cmd::handle_language;
 use messaging::{handle_chathistory, handle_privmsg, handle_search, handle_tagmsg};
+use mydata::handle_mydata;
 use policy_cmd::handle_policy;
+use pref_cmd::handle_pref;
+use privacy_cmd::handle_privacy;
 use queries::{handle_away, handle_lusers, handle_who, handle_whois};
-use registration::try_complete_registration;
+use reclaim_cmd::handle_reclaim;
+use registration::{handle_bind, handle_resume, try_complete_registration};
+use s2s_cmd::handle_squit;
+use sessions_cmd::handle_sessions;
+use user_mode::handle_user_mode;
 
 // Re-export items used by other modules in the crate
 
@@ -143,35 +179,38 @@ pub struct Connection {
     pub registered: bool,
     /// Actor class: human (default), agent, or external_agent.
     pub(crate) actor_class: ActorClass,
+    /// Whether `actor_class` came from user mode +B, which is the only
+    /// class -B may drop.
+    pub(crate) bot_umode: bool,
 
     /// Iroh endpoint ID of the remote peer (if connected via iroh).
     /// This is a cryptographic public key, giving us verified identity.
     pub iroh_endpoint_id: Option<String>,
+    /// RFC 9266 `tls-exporter` channel binding (base64url), if the
+    /// connection arrived over the TLS listener.
+    pub(crate) tls_exporter: Option<String>,
+    /// Remote IP, when the transport exposes one; the client's own when
+    /// a gateway passed it on with WEBIRC.
+    pub(crate) peer_ip: Option<std::net::IpAddr>,
+    /// Name of the WEBIRC gateway the client connected through.
+    pub(crate) webirc_gateway: Option<String>,
 
     // CAP negotiation state
     pub(crate) cap_negotiating: bool,
     pub(crate) cap_sasl_requested: bool,
-    pub(crate) cap_message_tags: bool,
-    pub(crate) cap_multi_prefix: bool,
-    pub(crate) cap_echo_message: bool,
-    pub(crate) cap_server_time: bool,
-    pub(crate) cap_batch: bool,
-    /// Client supports IRCv3 `draft/multiline` for sending and receiving
-    /// logical messages split across multiple PRIVMSG/NOTICE lines, grouped
-    /// inside a BATCH frame. Required for the agent debate flow where LLM
-    /// openings/rebuttals routinely exceed the single-PRIVMSG ceiling.
-    /// Spec: https://ircv3.net/specs/extensions/multiline
-    pub(crate) cap_draft_multiline: bool,
+    // Capabilities other sessions act on live in `state.sessions`.
     pub(crate) cap_chathistory: bool,
-    pub(crate) cap_account_notify: bool,
-    pub(crate) cap_extended_join: bool,
-    pub(crate) cap_away_notify: bool,
-    pub(crate) cap_account_tag: bool,
+    /// Client wants the freeq WHOIS extension numerics (credential types,
+    /// channel roles, E2EE key fingerprint). Off by default so legacy
+    /// clients never see unfamiliar numerics.
+    pub(crate) cap_whois_extended: bool,
     /// Client understands E2EE messages (won't get synthetic notices instead).
     #[allow(dead_code)]
     pub(crate) cap_e2ee: bool,
     /// Server operator (OPER) status.
     pub(crate) is_oper: bool,
+    /// End of the guest probation period; see [`probation`].
+    pub(crate) probation_until: Option<tokio::time::Instant>,
     /// Client software identifier (derived from USER realname).
     pub(crate) client_info: Option<String>,
     /// Channels reclaimed from a ghost session, pending synthetic state after registration.
@@ -179,8 +218,14 @@ pub struct Connection {
 
     // SASL state
     pub(crate) sasl_in_progress: bool,
+    /// The SASL exchange in progress is EXTERNAL.
+    pub(crate) sasl_external: bool,
     pub(crate) sasl_failures: u8,
     pub(crate) dpop_retries: u8,
+
+    /// Language for human-readable text, shared with the writer task;
+    /// see [`crate::locale`].
+    pub(crate) language: crate::locale::Language,
 }
 
 impl Connection {
@@ -193,34 +238,63 @@ impl Connection {
             authenticated_did: None,
             registered: false,
             actor_class: ActorClass::Human,
+            bot_umode: false,
             iroh_endpoint_id: None,
+            tls_exporter: None,
+            peer_ip: None,
+            webirc_gateway: None,
             cap_negotiating: false,
             cap_sasl_requested: false,
-            cap_message_tags: false,
-            cap_multi_prefix: false,
-            cap_echo_message: false,
-            cap_server_time: false,
-            cap_batch: false,
-            cap_draft_multiline: false,
             cap_chathistory: false,
-            cap_account_notify: false,
-            cap_extended_join: false,
-            cap_away_notify: false,
-            cap_account_tag: false,
+            cap_whois_extended: false,
             cap_e2ee: false,
             is_oper: false,
+            probation_until: None,
             client_info: None,
             ghost_channels: None,
             sasl_in_progress: false,
+            sasl_external: false,
             sasl_failures: 0,
             dpop_retries: 0,
+            language: Default::default(),
         }
     }
 
+    /// A guest still on probation: not authenticated, not a server
+    /// operator, and within the probation period.
+    pub(crate) fn on_probation(&self) -> bool {
+        self.authenticated_did.is_none()
+            && !self.is_oper
+            && self
+                .probation_until
+                .is_some_and(|until| tokio::time::Instant::now() < until)
+    }
+
     pub(crate) fn nick_or_star(&self) -> &str {
         self.nick.as_deref().unwrap_or("*")
     }
 
+    /// Channel binding embedded in SASL challenges. The TLS exporter is
+    /// unique per connection so it wins over the iroh endpoint ID, which
+    /// is stable per peer.
+    pub(crate) fn channel_binding(&self) -> Option<String> {
+        if let Some(ref exporter) = self.tls_exporter {
+            return Some(format!("tls-exporter:{exporter}"));
+        }
+        self.iroh_endpoint_id
+            .as_ref()
+            .map(|id| format!("iroh:{id}"))
+    }
+
+    /// Key for per-source auth failure counters: the iroh endpoint ID
+    /// when connected over iroh, else the peer IP.
+    pub(crate) fn auth_source(&self) -> Option<String> {
+        if let Some(ref id) = self.iroh_endpoint_id {
+            return Some(format!("iroh:{id}"));
+        }
+        self.peer_ip.map(|ip| ip.to_string())
+    }
+
     pub(crate) fn hostmask(&self) -> String {
         let nick = self.nick.as_deref().unwrap_or("*");
         let user = self.user.as_deref().unwrap_or("~u");
@@ -232,23 +306,60 @@ impl Connection {
     /// Authenticated users: shortened DID (e.g. "did/plc/4qsy..xmns")
     /// Guests: "freeq/guest"
     pub(crate) fn cloaked_host(&self) -> String {
-        if let Some(ref did) = self.authenticated_did {
-            // e.g. did:plc:4qsyxmnsblo4luuycm3572bq → plc/4qsyxmns
-            let short = did.strip_prefix("did:").unwrap_or(did);
-            let parts: Vec<&str> = short.splitn(2, ':').collect();
-            if parts.len() == 2 {
-                let method = parts[0];
-                let id = &parts[1][..parts[1].len().min(8)];
-                format!("freeq/{method}/{id}")
-            } else {
-                "freeq/did".to_string()
-            }
-        } else {
-            "freeq/guest".to_string()
+        match self.authenticated_did {
+            Some(ref did) => cloak_did(did),
+            None => "freeq/guest".to_string(),
         }
     }
 }
 
+/// Cloaked hostname for an authenticated DID.
+pub(crate) fn cloak_did(did: &str) -> String {
+    // e.g. did:plc:4qsyxmnsblo4luuycm3572bq → plc/4qsyxmns
+    let short = did.strip_prefix("did:").unwrap_or(did);
+    let parts: Vec<&str> = short.splitn(2, ':').collect();
+    if parts.len() == 2 {
+        let method = parts[0];
+        let id = &parts[1][..parts[1].len().min(8)];
+        format!("freeq/{method}/{id}")
+    } else {
+        "freeq/did".to_string()
+    }
+}
+
+/// Decode and parse one raw client line, or quarantine it.
+///
+/// Lines that aren't UTF-8, or that `MessageRef::parse` rejects (bad tag
+/// keys, NUL, oversized tag section, too many params, ...), are logged and
+/// dropped instead of being acted on as something the client didn't
+/// mean. Only a session's first quarantined line is a warning, so a
+/// misbehaving client can't flood the log; every one is counted in
+/// `/metrics`. Blank lines are skipped silently.
+fn parse_client_line(
+    state: &SharedState,
+    session_id: &str,
+    raw: &[u8],
+    quarantined: &mut u64,
+) -> Option<Message> {
+    let reason = match std::str::from_utf8(raw) {
+        Ok(line) => match irc::MessageRef::parse(line) {
+            Ok(msg) => return Some(msg.to_message()),
+            Err(irc::ParseError::Empty) => return None,
+            Err(e) => e.to_string(),
+        },
+        Err(_) => "invalid UTF-8".to_string(),
+    };
+    *quarantined += 1;
+    crate::server::Metrics::bump(&state.metrics.malformed_lines_total);
+    let sample = String::from_utf8_lossy(&raw[..raw.len().min(80)]);
+    if *quarantined == 1 {
+        tracing::warn!(%session_id, %reason, ?sample, "Quarantined malformed line");
+    } else {
+        tracing::debug!(%session_id, %reason, count = *quarantined, ?sample, "Quarantined malformed line");
+    }
+    None
+}
+
 fn is_draft_multiline_rate_exempt(
     msg: &Message,
     state: &Arc<SharedState>,
@@ -302,9 +413,8 @@ fn is_draft_multiline_batch_open_rate_exempt(
         return false;
     }
 
-    let has_batch = state.cap_batch.lock().contains(session_id);
-    let has_multiline = state.cap_draft_multiline.lock().contains(session_id);
-    if !has_batch || !has_multiline {
+    let caps = state.sessions.caps(session_id);
+    if !caps.has(Cap::Batch) || !caps.has(Cap::DraftMultiline) {
         return false;
     }
 
@@ -350,13 +460,56 @@ fn is_draft_multiline_chunk_rate_exempt(
         })
 }
 
+/// Transport-level facts about a connection, captured before the stream
+/// is handed to the generic IRC loop.
+#[derive(Debug, Default)]
+struct TransportMeta {
+    iroh_endpoint_id: Option<String>,
+    tls_exporter: Option<String>,
+    peer_ip: Option<std::net::IpAddr>,
+}
+
+/// RFC 9266 exporter label for TLS 1.3 channel bindings.
+const TLS_EXPORTER_LABEL: &[u8] = b"EXPORTER-Channel-Binding";
+
 /// Handle a plain TCP connection.
 pub async fn handle(stream: TcpStream, state: Arc<SharedState>) -> Result<()> {
     let peer = stream.peer_addr()?;
     let session_id = format!("{peer}");
     tracing::info!(%session_id, "New connection (plain)");
     let (reader, writer) = tokio::io::split(stream);
-    handle_io(BufReader::new(reader), writer, session_id, state).await
+    let meta = TransportMeta {
+        peer_ip: Some(peer.ip()),
+        ..Default::default()
+    };
+    handle_io_with_meta(BufReader::new(reader), writer, session_id, state, meta).await
+}
+
+/// Handle a connection from the TLS listener. Captures the peer IP and
+/// the `tls-exporter` channel binding before handing off.
+pub async fn handle_tls(
+    stream: tokio_rustls::server::TlsStream<TcpStream>,
+    state: Arc<SharedState>,
+) -> Result<()> {
+    use base64::Engine;
+    let (tcp, tls) = stream.get_ref();
+    let peer_ip = tcp.peer_addr().ok().map(|a| a.ip());
+    let tls_exporter = tls
+        .export_keying_material([0u8; 32], TLS_EXPORTER_LABEL, None)
+        .ok()
+        .map(|ekm| base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(ekm));
+
+    static COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+    let id = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let session_id = format!("tls-{id}");
+    tracing::info!(%session_id, peer = ?peer_ip, "New connection (TLS)");
+    let (reader, writer) = tokio::io::split(stream);
+    let meta = TransportMeta {
+        tls_exporter,
+        peer_ip,
+        ..Default::default()
+    };
+    handle_io_with_meta(BufReader::new(reader), writer, session_id, state, meta).await
 }
 
 /// Handle a generic async stream (for TLS, WebSocket, or other wrappers).
@@ -367,6 +520,28 @@ where
     handle_generic_with_meta(stream, state, None).await
 }
 
+/// Handle a WebSocket stream from the web listener, which knows the
+/// peer IP.
+pub async fn handle_websocket<S>(
+    stream: S,
+    state: Arc<SharedState>,
+    peer_ip: std::net::IpAddr,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    static COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+    let id = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let session_id = format!("ws-{id}");
+    tracing::info!(%session_id, peer = %peer_ip, "New connection (WebSocket)");
+    let (reader, writer) = tokio::io::split(stream);
+    let meta = TransportMeta {
+        peer_ip: Some(peer_ip),
+        ..Default::default()
+    };
+    handle_io_with_meta(BufReader::new(reader), writer, session_id, state, meta).await
+}
+
 /// Handle a generic async stream with optional connection metadata.
 ///
 /// `iroh_endpoint_id` is set when the connection comes via iroh transport,
@@ -384,42 +559,52 @@ where
     let session_id = format!("stream-{id}");
     tracing::info!(%session_id, iroh_id = ?iroh_endpoint_id, "New connection (generic stream)");
     let (reader, writer) = tokio::io::split(stream);
-    handle_io_with_meta(
-        BufReader::new(reader),
-        writer,
-        session_id,
-        state,
+    let meta = TransportMeta {
         iroh_endpoint_id,
-    )
-    .await
-}
-
-async fn handle_io<R, W>(
-    reader: BufReader<R>,
-    writer: W,
-    session_id: String,
-    state: Arc<SharedState>,
-) -> Result<()>
-where
-    R: AsyncRead + Unpin + Send + 'static,
-    W: AsyncWrite + Unpin + Send + 'static,
-{
-    handle_io_with_meta(reader, writer, session_id, state, None).await
+        ..Default::default()
+    };
+    handle_io_with_meta(BufReader::new(reader), writer, session_id, state, meta).await
 }
 
 async fn handle_io_with_meta<R, W>(
     mut reader: BufReader<R>,
-    writer: W,
+    mut writer: W,
     session_id: String,
     state: Arc<SharedState>,
-    iroh_endpoint_id: Option<String>,
+    meta: TransportMeta,
 ) -> Result<()>
 where
     R: AsyncRead + Unpin + Send + 'static,
     W: AsyncWrite + Unpin + Send + 'static,
 {
     let mut conn = Connection::new(session_id.clone());
-    conn.iroh_endpoint_id = iroh_endpoint_id;
+    conn.iroh_endpoint_id = meta.iroh_endpoint_id;
+    conn.tls_exporter = meta.tls_exporter;
+    conn.peer_ip = meta.peer_ip;
+
+    // A source that keeps failing SASL is turned away until its failure
+    // window runs out. WEBIRC gateways speak for many users, so they
+    // aren't; their users are counted by their own IPs.
+    let gateway = conn
+        .peer_ip
+        .is_some_and(|ip| webirc::is_gateway(&state.config, ip));
+    if !gateway
+        && let Some(source) = conn.auth_source()
+        && state.auth_blocked(&source)
+    {
+        use tokio::io::AsyncWriteExt;
+        tracing::warn!(
+            %session_id,
+            %source,
+            "Connection refused: too many failed authentications"
+        );
+        writer
+            .write_all(b"ERROR :Too many failed authentications; try again later\r\n")
+            .await?;
+        writer.flush().await?;
+        return Ok(());
+    }
+    conn.probation_until = probation::deadline(&state.config);
 
     // Plugin on_connect hook
     state
@@ -428,28 +613,49 @@ where
             session_id: session_id.clone(),
             remote_addr: session_id.clone(),
         });
+    state
+        .firehose
+        .publish(crate::firehose::FirehoseEvent::Connect {
+            session: session_id.clone(),
+            ip: conn.peer_ip.map(|ip| ip.to_string()),
+            iroh: conn.iroh_endpoint_id.is_some(),
+        });
 
-    // Channel for sending messages TO this client
-    let (tx, mut rx) = mpsc::channel::<String>(16384);
-    state.connections.lock().insert(session_id.clone(), tx);
+    // Queue for sending messages TO this client
+    let (tx, mut rx) = crate::send_queue::channel(
+        crate::send_queue::QUEUE_LINES,
+        state.metrics.send_queue.clone(),
+    );
+    let own_queue = tx.clone();
+    state.connections.insert(session_id.clone(), tx);
 
     let server_name = state.server_name.clone();
 
     // Spawn writer task
     let write_session_id = session_id.clone();
+    let write_language = conn.language.clone();
+    let write_server_name = server_name.clone();
     let mut write_half = writer;
     let write_handle = tokio::spawn(async move {
         use tokio::io::AsyncWriteExt;
+        let localize =
+            |line| crate::locale::for_connection(line, &write_language, &write_server_name);
         while let Some(line) = rx.recv().await {
+            // One span per batch, from the first line to the flush.
+            let write_span = tracing::debug_span!(
+                "write",
+                session_id = %write_session_id,
+                lines = tracing::field::Empty,
+            );
             // Write the first message
-            if let Err(e) = write_half.write_all(line.as_bytes()).await {
+            if let Err(e) = write_half.write_all(&localize(line)).await {
                 tracing::warn!(session_id = %write_session_id, "Write error: {e}");
                 break;
             }
             // Drain any queued messages and batch-write them (reduces syscalls)
             let mut batch_count = 0;
             while let Ok(queued) = rx.try_recv() {
-                if let Err(e) = write_half.write_all(queued.as_bytes()).await {
+                if let Err(e) = write_half.write_all(&localize(queued)).await {
                     tracing::warn!(session_id = %write_session_id, "Write error: {e}");
                     return;
                 }
@@ -463,22 +669,26 @@ where
                 tracing::warn!(session_id = %write_session_id, "Flush error: {e}");
                 break;
             }
+            write_span.record("lines", batch_count + 1);
         }
     });
 
-    // Track whether our own send channel is healthy
+    // Track whether our own send channel is healthy. A full queue is
+    // handled by the backpressure tiers in `send_queue`; a closed one
+    // means the writer task has died.
     let send_healthy = Arc::new(std::sync::atomic::AtomicBool::new(true));
     let send_healthy_ref = send_healthy.clone();
     let send = move |state: &Arc<SharedState>, session_id: &str, msg: String| {
-        if let Some(tx) = state.connections.lock().get(session_id)
-            && tx.try_send(msg).is_err()
+        if let Some(tx) = state.connections.get(session_id)
+            && let Err(mpsc::error::TrySendError::Closed(_)) = tx.try_send(msg.into())
         {
-            tracing::warn!(session_id, "Send buffer full or closed");
+            tracing::warn!(session_id, "Send queue closed");
             send_healthy_ref.store(false, std::sync::atomic::Ordering::Relaxed);
         }
     };
 
-    let mut line_buf = String::new();
+    let mut line_buf: Vec<u8> = Vec::new();
+    let mut quarantined: u64 = 0;
     let mut last_activity = tokio::time::Instant::now();
     let ping_interval = tokio::time::Duration::from_secs(30);
     let ping_timeout = tokio::time::Duration::from_secs(60);
@@ -502,11 +712,24 @@ where
     let rate_refill: f64 = 10.0; // tokens per second
 
     loop {
-        // Check if our send channel is dead (buffer full = stuck client)
+        // Check if our send channel is dead
         if !send_healthy.load(std::sync::atomic::Ordering::Relaxed) {
             tracing::info!(%session_id, "Send channel unhealthy, disconnecting");
             break;
         }
+        // A client that hasn't read its backlog within the grace period is
+        // gone for practical purposes: leave through the normal QUIT path.
+        if let Some(stuck) = own_queue.congested_for()
+            && stuck >= crate::send_queue::CONGESTION_GRACE
+        {
+            tracing::info!(
+                %session_id,
+                queued_bytes = own_queue.queued_bytes(),
+                "Send queue congested for {stuck:?}, disconnecting"
+            );
+            own_queue.record_congestion_quit();
+            break;
+        }
 
         line_buf.clear();
         // Cap line length to 8KB to prevent OOM from malicious clients.
@@ -525,18 +748,18 @@ where
                 // Look for newline in the available buffer
                 let len = if let Some(pos) = buf.iter().position(|&b| b == b'\n') {
                     // Found newline — take up to and including it
-                    let chunk = &buf[..=pos];
-                    line_buf.push_str(&String::from_utf8_lossy(chunk));
+                    line_buf.extend_from_slice(&buf[..=pos]);
                     pos + 1
                 } else {
-                    // No newline yet — take the whole buffer
-                    let chunk = buf;
-                    line_buf.push_str(&String::from_utf8_lossy(chunk));
-                    chunk.len()
+                    // No newline yet — take the whole buffer. Bytes are
+                    // decoded once the line is complete, so a multi-byte
+                    // character split across reads survives intact.
+                    line_buf.extend_from_slice(buf);
+                    buf.len()
                 };
                 reader.consume(len);
                 // If we found a newline (line_buf ends with \n), we're done
-                if line_buf.ends_with('\n') {
+                if line_buf.ends_with(b"\n") {
                     return Ok(line_buf.len());
                 }
                 // If accumulated data exceeds limit, reject
@@ -603,10 +826,41 @@ where
 
         last_activity = tokio::time::Instant::now();
 
-        let Some(msg) = Message::parse(&line_buf) else {
+        // One span per command, from the line arriving until it has been
+        // handled. The message path's stages (parse, handle, fan-out) nest
+        // under it; see `telemetry`.
+        let command_span = tracing::debug_span!(
+            "command",
+            session_id = %session_id,
+            command = tracing::field::Empty,
+        );
+        let parsed = command_span.in_scope(|| {
+            let _parse = tracing::debug_span!("parse", bytes = line_buf.len()).entered();
+            parse_client_line(&state, &session_id, &line_buf, &mut quarantined)
+        });
+        let Some(msg) = parsed else {
             continue;
         };
 
+        // Services-style shortcuts (`CS OP #chan nick` → MODE) are rewritten
+        // into the native command before anything else looks at them.
+        let msg = match state.command_aliases.expand(&msg) {
+            None => msg,
+            Some(Ok(expanded)) => expanded,
+            Some(Err(usage)) => {
+                if conn.registered {
+                    let reply = Message::from_server(
+                        &server_name,
+                        irc::ERR_NEEDMOREPARAMS,
+                        vec![conn.nick_or_star(), &msg.command, &usage],
+                    );
+                    send(&state, &session_id, format!("{reply}\r\n"));
+                }
+                continue;
+            }
+        };
+        command_span.record("command", msg.command.as_str());
+
         // Rate limiting (skip during registration — clients burst on connect)
         // Exempt read-only and join commands — they burst legitimately on connect
         // when auto-rejoin + client-side JOIN overlap.
@@ -615,6 +869,11 @@ where
             "JOIN" | "CHATHISTORY" | "WHOIS" | "PING" | "PONG" | "MODE" | "WHO" | "NAMES" | "LOGIN"
         ) || is_draft_multiline_rate_exempt(&msg, &state, &session_id);
         if conn.registered && !exempt_from_rate_limit {
+            let (rate_max, rate_refill) = if conn.on_probation() {
+                (probation::RATE_BURST, probation::RATE_PER_SEC)
+            } else {
+                (rate_max, rate_refill)
+            };
             let now = tokio::time::Instant::now();
             let elapsed = now.duration_since(rate_last).as_secs_f64();
             rate_tokens = (rate_tokens + elapsed * rate_refill).min(rate_max);
@@ -635,7 +894,7 @@ where
             rate_tokens -= 1.0;
         }
 
-        tracing::debug!(%session_id, "<- {}", line_buf.trim());
+        tracing::debug!(%session_id, "<- {}", String::from_utf8_lossy(&line_buf).trim());
 
         // Check for pending LOGIN completion (from browser OAuth callback)
         if conn.authenticated_did.is_none()
@@ -650,7 +909,18 @@ where
             // Trigger auto-op etc. in channels (already handled by complete_irc_login)
         }
 
+        if conn.registered && conn.on_probation() && !probation::allows(&msg.command) {
+            let fail = probation::fail(&conn, &server_name, &msg.command, "use this command");
+            send(&state, &session_id, fail);
+            continue;
+        }
+
         match msg.command.as_str() {
+            "WEBIRC" => {
+                if !webirc::handle_webirc(&mut conn, &msg, &state, &session_id, &send) {
+                    break;
+                }
+            }
             "CAP" => {
                 handle_cap(&mut conn, &msg, &state, &server_name, &session_id, &send);
             }
@@ -658,6 +928,18 @@ where
                 handle_authenticate(&mut conn, &msg, &state, &server_name, &session_id, &send)
                     .await;
             }
+            "RESUME" => {
+                handle_resume(&mut conn, &state, &server_name, &session_id, &send);
+            }
+            "LANGUAGE" => {
+                handle_language(&conn, &msg, &state, &server_name, &session_id, &send);
+            }
+            "BIND" => {
+                if !conn.registered {
+                    continue;
+                }
+                handle_bind(&conn, &state, &server_name, &session_id, &send);
+            }
             "NICK" => {
                 if let Some(nick) = msg.params.first() {
                     // Validate nick: 1-64 chars, allowed chars for IRC + AT handles
@@ -681,13 +963,31 @@ where
                     {
                         let reply = Message::from_server(
                             &server_name,
-                            "432",
+                            irc::ERR_ERRONEUSNICKNAME,
                             vec![conn.nick_or_star(), nick, "Erroneous Nickname"],
                         );
                         send(&state, &session_id, format!("{reply}\r\n"));
                         continue;
                     }
-                    let nick_lower = nick.to_lowercase();
+                    // Reserved names. Mid-negotiation the nick is only
+                    // stashed: SASL may yet show an exempt DID, and
+                    // registration checks again.
+                    if crate::reserved::nick_reserved(&state.config, nick)
+                        && !(conn.cap_negotiating || conn.sasl_in_progress)
+                        && !crate::reserved::exempt(
+                            &state.config,
+                            conn.authenticated_did.as_deref(),
+                            conn.is_oper,
+                        )
+                    {
+                        let reply = Message::from_server(
+                            &server_name,
+                            irc::ERR_ERRONEUSNICKNAME,
+                            vec![conn.nick_or_star(), nick, "Nickname is reserved"],
+                        );
+                        send(&state, &session_id, format!("{reply}\r\n"));
+                        continue;
+                    }
                     let in_use_by_session = state
                         .nick_to_session
                         .lock()
@@ -710,8 +1010,10 @@ where
                             }
                         });
 
-                    let owner_did = state.nick_owners.lock().get(&nick_lower).cloned();
                     let my_did = conn.authenticated_did.as_deref();
+                    // A look-alike of someone else's nick (Cyrillic homoglyphs,
+                    // `I` for `l`, zero-width joiners) counts as theirs too.
+                    let owner_did = state.nick_owner_or_lookalike(nick, my_did);
                     let nick_stolen = if conn.cap_negotiating || conn.sasl_in_progress {
                         false
                     } else {
@@ -771,12 +1073,12 @@ where
                             };
                             let nick_msg = format!(":{hostmask} NICK :{nick}\r\n");
                             send(&state, &session_id, nick_msg.clone());
+                            let nick_msg = WireLine::from(nick_msg);
 
                             let mut notified = std::collections::HashSet::new();
                             notified.insert(session_id.clone());
-                            let channels = state.channels.lock();
-                            let conns = state.connections.lock();
-                            for ch in channels.values() {
+                            let conns = &state.connections;
+                            state.channels.for_each(|_, ch| {
                                 if ch.members.contains(&session_id) {
                                     for member in &ch.members {
                                         if notified.insert(member.clone())
@@ -786,9 +1088,7 @@ where
                                         }
                                     }
                                 }
-                            }
-                            drop(conns);
-                            drop(channels);
+                            });
 
                             // Plugin on_nick_change hook
                             if let Some(ref old) = old_nick {
@@ -915,12 +1215,16 @@ where
                             &send,
                         );
                     } else {
-                        let reply = Message::from_server(
+                        let mode_str = msg.params.get(1).map(|s| s.as_str());
+                        handle_user_mode(
+                            &mut conn,
+                            target,
+                            mode_str,
+                            &state,
                             &server_name,
-                            "221",
-                            vec![conn.nick_or_star(), "+"],
+                            &session_id,
+                            &send,
                         );
-                        send(&state, &session_id, format!("{reply}\r\n"));
                     }
                 }
             }
@@ -972,7 +1276,15 @@ where
                 }
                 if let Some(channel) = msg.params.first() {
                     let channel = normalize_channel(channel);
-                    let new_topic = msg.params.get(1).map(|s| s.as_str());
+                    // `TOPIC #chan --revert 2` arrives as separate middle
+                    // params; rejoin so handle_topic sees `--revert 2`.
+                    let joined;
+                    let new_topic = if msg.params.get(1).is_some_and(|p| p == "--revert") {
+                        joined = msg.params[1..].join(" ");
+                        Some(joined.as_str())
+                    } else {
+                        msg.params.get(1).map(|s| s.as_str())
+                    };
                     handle_topic(
                         &conn,
                         &channel,
@@ -984,6 +1296,35 @@ where
                     );
                 }
             }
+            "TOPICHIST" => {
+                if !conn.registered {
+                    continue;
+                }
+                if let Some(channel) = msg.params.first() {
+                    let channel = normalize_channel(channel);
+                    handle_topichist(&conn, &channel, &state, &server_name, &session_id, &send);
+                }
+            }
+            "STATS" => {
+                if !conn.registered {
+                    continue;
+                }
+                match msg.params.first() {
+                    Some(channel) if channel.starts_with('#') || channel.starts_with('&') => {
+                        let channel = normalize_channel(channel);
+                        handle_stats(&conn, &channel, &state, &server_name, &session_id, &send);
+                    }
+                    _ => {
+                        let nick = conn.nick_or_star();
+                        let reply = Message::from_server(
+                            &server_name,
+                            "NOTICE",
+                            vec![nick, "Usage: STATS <channel>"],
+                        );
+                        send(&state, &session_id, format!("{reply}\r\n"));
+                    }
+                }
+            }
             "PIN" | "UNPIN" => {
                 if !conn.registered {
                     continue;
@@ -1005,7 +1346,6 @@ where
                 // Check op status (or server oper)
                 let is_op = state
                     .channels
-                    .lock()
                     .get(&channel)
                     .map(|ch| ch.ops.contains(&session_id))
                     .unwrap_or(false);
@@ -1020,8 +1360,7 @@ where
                     continue;
                 }
 
-                let mut channels = state.channels.lock();
-                if let Some(ch) = channels.get_mut(&channel) {
+                if let Some(mut ch) = state.channels.get(&channel) {
                     if is_pin {
                         if ch.pins.iter().any(|p| p.msgid == *msgid) {
                             let reply = Message::from_server(
@@ -1048,14 +1387,13 @@ where
                             );
                             // Cap at 50 pins
                             ch.pins.truncate(50);
-                            drop(channels);
+                            drop(ch);
                             // Persist to DB (ensure channel exists first)
                             let ch_name = channel.clone();
                             let mid = msgid.to_string();
                             let pinner = nick.to_string();
                             {
-                                let channels = state.channels.lock();
-                                if let Some(ch) = channels.get(&ch_name) {
+                                if let Some(ch) = state.channels.get(&ch_name) {
                                     let ch_clone = ch.clone();
                                     state.with_db(|db| db.save_channel(&ch_name, &ch_clone));
                                 }
@@ -1084,7 +1422,7 @@ where
                         let before = ch.pins.len();
                         ch.pins.retain(|p| p.msgid != *msgid);
                         if ch.pins.len() < before {
-                            drop(channels);
+                            drop(ch);
                             // Persist to DB
                             let ch_name = channel.clone();
                             let mid = msgid.to_string();
@@ -1125,8 +1463,7 @@ where
                 let nick = conn.nick_or_star();
                 if let Some(channel) = msg.params.first() {
                     let channel = normalize_channel(channel);
-                    let channels = state.channels.lock();
-                    if let Some(ch) = channels.get(&channel) {
+                    if let Some(ch) = state.channels.get(&channel) {
                         if ch.pins.is_empty() {
                             let reply = Message::from_server(
                                 &server_name,
@@ -1159,7 +1496,15 @@ where
                 }
                 if let Some(channel) = msg.params.first() {
                     let channel = normalize_channel(channel);
-                    handle_names(&conn, &channel, &state, &server_name, &session_id, &send);
+                    handle_names(
+                        &conn,
+                        &channel,
+                        &msg.params[1..],
+                        &state,
+                        &server_name,
+                        &session_id,
+                        &send,
+                    );
                 }
             }
             "WHOIS" => {
@@ -1300,7 +1645,16 @@ where
                             continue;
                         }
                         draft_multiline::RouteOutcome::NotInBatch => {
-                            handle_privmsg(&conn, &msg.command, &target, text, &msg.tags, &state);
+                            command_span.in_scope(|| {
+                                handle_privmsg(
+                                    &conn,
+                                    &msg.command,
+                                    &target,
+                                    text,
+                                    &msg.tags,
+                                    &state,
+                                )
+                            });
                         }
                     }
                 }
@@ -1309,14 +1663,16 @@ where
                 if !conn.registered {
                     continue;
                 }
-                draft_multiline::handle_batch_command(
-                    &conn,
-                    &msg,
-                    &state,
-                    &server_name,
-                    &session_id,
-                    &send,
-                );
+                command_span.in_scope(|| {
+                    draft_multiline::handle_batch_command(
+                        &conn,
+                        &msg,
+                        &state,
+                        &server_name,
+                        &session_id,
+                        &send,
+                    )
+                });
             }
             "TAGMSG" => {
                 if !conn.registered {
@@ -1343,7 +1699,16 @@ where
                     continue;
                 }
                 let target = msg.params.first().map(|s| s.as_str()).unwrap_or("*");
-                handle_who(&conn, target, &state, &server_name, &session_id, &send);
+                let options = msg.params.get(1).map(|s| s.as_str());
+                handle_who(
+                    &conn,
+                    target,
+                    options,
+                    &state,
+                    &server_name,
+                    &session_id,
+                    &send,
+                );
             }
             "AWAY" => {
                 if !conn.registered {
@@ -1443,13 +1808,13 @@ where
                 }
                 let mut replies = Vec::new();
                 for nick in msg.params.iter().take(5) {
-                    let n2s = state.nick_to_session.lock();
-                    if let Some(sid) = n2s.get_session(nick) {
-                        let sid = sid.to_string();
-                        let is_op = {
-                            let channels = state.channels.lock();
-                            channels.values().any(|ch| ch.ops.contains(&sid))
-                        };
+                    let sid = state
+                        .nick_to_session
+                        .lock()
+                        .get_session(nick)
+                        .map(|s| s.to_string());
+                    if let Some(sid) = sid {
+                        let is_op = state.channels.any(|_, ch| ch.ops.contains(&sid));
                         let prefix = if is_op { "*" } else { "" };
                         let did = state.session_dids.lock().get(&sid).cloned();
                         let host = helpers::cloaked_host_for_did(did.as_deref());
@@ -1543,12 +1908,84 @@ where
                 let handle = msg.params.first().map(|s| s.as_str()).unwrap_or("");
                 login::handle_login(&mut conn, handle, &state, &server_name, &session_id, &send);
             }
+            "METADATA" => {
+                if !conn.registered {
+                    continue;
+                }
+                metadata::handle_metadata(&conn, &msg, &state, &server_name, &session_id, &send);
+            }
             "POLICY" => {
                 if !conn.registered {
                     continue;
                 }
                 handle_policy(&conn, &msg, &state, &server_name, &session_id, &send);
             }
+            "SESSIONS" => {
+                if !conn.registered {
+                    continue;
+                }
+                handle_sessions(&conn, &msg, &state, &server_name, &session_id, &send);
+            }
+            "AUTOJOIN" => {
+                if !conn.registered {
+                    continue;
+                }
+                handle_autojoin(&conn, &msg, &state, &server_name, &session_id, &send);
+            }
+            "PRIVACY" => {
+                if !conn.registered {
+                    continue;
+                }
+                handle_privacy(&conn, &msg, &state, &server_name, &session_id, &send);
+            }
+            "MYDATA" => {
+                if !conn.registered {
+                    continue;
+                }
+                handle_mydata(&conn, &msg, &state, &server_name, &session_id, &send);
+            }
+            "RECLAIM" => {
+                if !conn.registered {
+                    continue;
+                }
+                handle_reclaim(&conn, &msg, &state, &server_name, &session_id, &send).await;
+            }
+            "CASE" => {
+                if !conn.registered {
+                    continue;
+                }
+                handle_case(&conn, &msg, &state, &server_name, &session_id, &send);
+            }
+            "FILTER" => {
+                if !conn.registered {
+                    continue;
+                }
+                handle_filter(&conn, &msg, &state, &server_name, &session_id, &send);
+            }
+            "INVITELINK" => {
+                if !conn.registered {
+                    continue;
+                }
+                handle_invitelink(&conn, &msg, &state, &server_name, &session_id, &send);
+            }
+            "EMAIL" => {
+                if !conn.registered {
+                    continue;
+                }
+                handle_email(&conn, &msg, &state, &server_name, &session_id, &send);
+            }
+            "PREF" => {
+                if !conn.registered {
+                    continue;
+                }
+                handle_pref(&conn, &msg, &state, &server_name, &session_id, &send);
+            }
+            "HELPOP" => {
+                if !conn.registered {
+                    continue;
+                }
+                handle_helpop(&conn, &msg, &state, &server_name, &session_id, &send);
+            }
             "OPER" => {
                 if !conn.registered {
                     continue;
@@ -1579,6 +2016,8 @@ where
                         vec![&nick, "You are now an IRC operator"],
                     );
                     send(&state, &session_id, format!("{reply}\r\n"));
+                    let token = oper_token_notice(&state, &server_name, &nick, &session_id);
+                    send(&state, &session_id, token);
                     tracing::info!(nick = %nick, session = %session_id, "OPER granted");
                 } else {
                     let reply = Message::from_server(
@@ -1618,6 +2057,7 @@ where
                         match class_str.parse::<ActorClass>() {
                             Ok(class) => {
                                 conn.actor_class = class;
+                                conn.bot_umode = false;
                                 // Store in shared state for WHOIS / member list lookups
                                 state
                                     .session_actor_class
@@ -1632,30 +2072,27 @@ where
                                 tracing::info!(nick = %nick, session = %session_id, actor_class = %class, "AGENT REGISTER");
 
                                 // Broadcast actor class to shared channels if they support the cap
-                                if conn.cap_message_tags {
+                                if state.sessions.has_cap(&session_id, Cap::MessageTags) {
                                     let hostmask = conn.hostmask();
                                     // Collect targets first, then send (avoid holding multiple locks)
-                                    let targets: Vec<(String, Vec<String>)> = {
-                                        let channels = state.channels.lock();
-                                        channels
-                                            .iter()
-                                            .filter(|(_, ch)| ch.members.contains(&session_id))
-                                            .map(|(ch_name, ch)| {
-                                                let members: Vec<String> = ch
-                                                    .members
-                                                    .iter()
-                                                    .filter(|sid| *sid != &session_id)
-                                                    .cloned()
-                                                    .collect();
-                                                (ch_name.clone(), members)
-                                            })
-                                            .collect()
-                                    };
-                                    let conns = state.connections.lock();
+                                    let targets: Vec<(String, Vec<String>)> =
+                                        state.channels.filter_map(|ch_name, ch| {
+                                            if !ch.members.contains(&session_id) {
+                                                return None;
+                                            }
+                                            let members: Vec<String> = ch
+                                                .members
+                                                .iter()
+                                                .filter(|sid| *sid != &session_id)
+                                                .cloned()
+                                                .collect();
+                                            Some((ch_name.to_string(), members))
+                                        });
+                                    let conns = &state.connections;
                                     for (ch_name, members) in &targets {
-                                        let msg_line = format!(
+                                        let msg_line = WireLine::from(format!(
                                             "@+freeq.at/actor-class={class} :{hostmask} NOTICE {ch_name} :registered as {class}\r\n"
-                                        );
+                                        ));
                                         for member_sid in members {
                                             if let Some(tx) = conns.get(member_sid) {
                                                 let _ = tx.try_send(msg_line.clone());
@@ -1717,14 +2154,11 @@ where
 
                         // Verify sender is op in a shared channel OR server oper
                         let is_oper = conn.is_oper;
-                        let is_op_in_shared = {
-                            let channels = state.channels.lock();
-                            channels.values().any(|ch| {
-                                ch.members.contains(&session_id)
-                                    && ch.members.contains(&target_session)
-                                    && ch.ops.contains(&session_id)
-                            })
-                        };
+                        let is_op_in_shared = state.channels.any(|_, ch| {
+                            ch.members.contains(&session_id)
+                                && ch.members.contains(&target_session)
+                                && ch.ops.contains(&session_id)
+                        });
                         if !is_oper && !is_op_in_shared {
                             let reply = Message::from_server(
                                 &server_name,
@@ -1745,8 +2179,8 @@ where
                             "@+freeq.at/governance={action};+freeq.at/issued-by={}{reason_tag} :{hostmask} TAGMSG {target_nick}\r\n",
                             nick
                         );
-                        if let Some(tx) = state.connections.lock().get(&target_session) {
-                            let _ = tx.try_send(gov_msg);
+                        if let Some(tx) = state.connections.get(&target_session) {
+                            let _ = tx.try_send(gov_msg.into());
                         }
 
                         // Broadcast human-readable notice to shared channels
@@ -1763,17 +2197,12 @@ where
                         let notice_text =
                             format!("{emoji} {target_nick} {action}d by {nick}{reason_str}");
                         {
-                            let shared_channels: Vec<String> = {
-                                let channels = state.channels.lock();
-                                channels
-                                    .iter()
-                                    .filter(|(_, ch)| {
-                                        ch.members.contains(&session_id)
-                                            && ch.members.contains(&target_session)
-                                    })
-                                    .map(|(name, _)| name.clone())
-                                    .collect()
-                            };
+                            let shared_channels: Vec<String> =
+                                state.channels.filter_map(|name, ch| {
+                                    (ch.members.contains(&session_id)
+                                        && ch.members.contains(&target_session))
+                                    .then(|| name.to_string())
+                                });
                             for ch_name in &shared_channels {
                                 helpers::broadcast_to_channel(
                                     &state,
@@ -1795,21 +2224,21 @@ where
                         // For REVOKE: also revoke all capabilities and force part
                         if action == "revoke" {
                             if let Some(ref did) = target_did {
-                                let channels: Vec<String> = {
-                                    let chs = state.channels.lock();
-                                    chs.iter()
-                                        .filter(|(_, ch)| ch.members.contains(&target_session))
-                                        .map(|(name, _)| name.clone())
-                                        .collect()
-                                };
+                                let channels: Vec<String> =
+                                    state.channels.filter_map(|name, ch| {
+                                        ch.members
+                                            .contains(&target_session)
+                                            .then(|| name.to_string())
+                                    });
                                 for ch in &channels {
                                     state.with_db(|db| db.revoke_all_capabilities(ch, did));
                                 }
                             }
                             // Send ERROR to force disconnect
-                            if let Some(tx) = state.connections.lock().get(&target_session) {
-                                let _ = tx
-                                    .try_send(format!("ERROR :Revoked by {nick}{reason_str}\r\n"));
+                            if let Some(tx) = state.connections.get(&target_session) {
+                                let _ = tx.try_send(
+                                    format!("ERROR :Revoked by {nick}{reason_str}\r\n").into(),
+                                );
                             }
                         }
 
@@ -1849,10 +2278,7 @@ where
                             let issuer_did = conn.authenticated_did.as_deref().unwrap_or(&nick);
                             // Find pending approval in any shared channel
                             let approval = {
-                                let shared: Vec<String> = {
-                                    let channels = state.channels.lock();
-                                    channels.keys().cloned().collect()
-                                };
+                                let shared = state.channels.keys();
                                 shared.into_iter().find_map(|ch| {
                                     state
                                         .with_db(|db| {
@@ -1882,8 +2308,8 @@ where
                                             "@+freeq.at/governance=approval_granted;+freeq.at/capability={} :{server_name} TAGMSG {target_nick}\r\n",
                                             irc::escape_tag_value(&capability)
                                         );
-                                        if let Some(tx) = state.connections.lock().get(ts) {
-                                            let _ = tx.try_send(line);
+                                        if let Some(tx) = state.connections.get(ts) {
+                                            let _ = tx.try_send(line.into());
                                         }
                                     }
                                     // Notify channel
@@ -1962,8 +2388,7 @@ where
 
                         if let Some(ref did) = target_did {
                             let issuer_did = conn.authenticated_did.as_deref().unwrap_or(&nick);
-                            let shared: Vec<String> =
-                                state.channels.lock().keys().cloned().collect();
+                            let shared = state.channels.keys();
                             let approval = shared.into_iter().find_map(|ch| {
                                 state
                                     .with_db(|db| {
@@ -2006,8 +2431,8 @@ where
                                             "@+freeq.at/governance=approval_denied;+freeq.at/capability={}{reason_tag} :{server_name} TAGMSG {target_nick}\r\n",
                                             irc::escape_tag_value(&capability)
                                         );
-                                        if let Some(tx) = state.connections.lock().get(ts) {
-                                            let _ = tx.try_send(line);
+                                        if let Some(tx) = state.connections.get(ts) {
+                                            let _ = tx.try_send(line.into());
                                         }
                                     }
                                     let reason_str = reason
@@ -2087,6 +2512,7 @@ where
                                 if let Ok(class) = manifest.agent.actor_class.parse::<ActorClass>()
                                 {
                                     conn.actor_class = class;
+                                    conn.bot_umode = false;
                                     state
                                         .session_actor_class
                                         .lock()
@@ -2310,7 +2736,7 @@ where
                             let found = spawned
                                 .iter()
                                 .find(|(_, sa)| {
-                                    sa.nick.eq_ignore_ascii_case(&child_nick)
+                                    crate::casemap::eq(&sa.nick, &child_nick)
                                         && sa.parent_session == session_id
                                 })
                                 .map(|(k, v)| (k.clone(), v.clone()));
@@ -2371,7 +2797,7 @@ where
 
                         // Verify child is owned by this session
                         let child_exists = state.spawned_agents.lock().values().any(|sa| {
-                            sa.nick.eq_ignore_ascii_case(&child_nick)
+                            crate::casemap::eq(&sa.nick, &child_nick)
                                 && sa.parent_session == session_id
                         });
 
@@ -2654,13 +3080,11 @@ where
 
                 if let Some(raw) = msg.params.get(1) {
                     // Set budget — require op or oper
-                    let is_op = {
-                        let channels = state.channels.lock();
-                        channels
-                            .get(&channel.to_lowercase())
-                            .map(|ch| ch.ops.contains(&session_id))
-                            .unwrap_or(false)
-                    };
+                    let is_op = state
+                        .channels
+                        .get(&crate::casemap::fold(&channel))
+                        .map(|ch| ch.ops.contains(&session_id))
+                        .unwrap_or(false);
                     if !is_op && !conn.is_oper {
                         let reply = Message::from_server(
                             &server_name,
@@ -2964,21 +3388,21 @@ where
                 {
                     // Collect targets first, then send (avoid holding multiple locks)
                     let targets: Vec<String> = {
-                        let channels = state.channels.lock();
-                        let away_caps = state.cap_away_notify.lock();
                         let mut sids = Vec::new();
-                        for ch in channels.values() {
+                        state.channels.for_each(|_, ch| {
                             if ch.members.contains(&session_id) {
                                 for member_sid in &ch.members {
-                                    if member_sid != &session_id && away_caps.contains(member_sid) {
+                                    if member_sid != &session_id
+                                        && state.sessions.has_cap(member_sid, Cap::AwayNotify)
+                                    {
                                         sids.push(member_sid.clone());
                                     }
                                 }
                             }
-                        }
+                        });
                         sids
                     };
-                    let conns = state.connections.lock();
+                    let conns = &state.connections;
                     // For active/online/idle: send AWAY with no parameter (= back from away)
                     // For other states: send human-readable AWAY text
                     let is_clear = ps == PresenceState::Online
@@ -2988,14 +3412,14 @@ where
                         (Some(status), _) => format!("{ps}: {status}"),
                         (None, _) => ps.to_string(),
                     };
-                    let line = if is_clear {
+                    let line = WireLine::from(if is_clear {
                         format!(":{hostmask} AWAY\r\n")
                     } else {
                         format!(":{hostmask} AWAY :{away_text}\r\n")
-                    };
+                    });
                     for sid in &targets {
                         if let Some(tx) = conns.get(sid) {
-                            let _ = tx.try_send(line.clone());
+                            tx.send_presence(&session_id, line.clone());
                         }
                     }
                 }
@@ -3112,15 +3536,41 @@ where
                     send(&state, &session_id, notice);
                 }
             }
+            "SQUIT" => {
+                if !conn.registered {
+                    continue;
+                }
+                handle_squit(&conn, &msg, &state, &server_name, &session_id, &send).await;
+            }
+            "BACKUP" => {
+                if !conn.registered {
+                    continue;
+                }
+                handle_backup(&conn, &state, &server_name, &session_id, &send).await;
+            }
+            #[cfg(feature = "s2s-faults")]
+            "S2SFAULT" => {
+                if !conn.registered {
+                    continue;
+                }
+                s2s_cmd::handle_s2sfault(&conn, &msg, &state, &server_name, &session_id, &send)
+                    .await;
+            }
             "QUIT" => {
                 break;
             }
             _ => {
                 if conn.registered {
+                    let subs = state.command_aliases.subcommands(&msg.command);
+                    let text = if subs.is_empty() {
+                        "Unknown command".to_string()
+                    } else {
+                        format!("Unknown command; try {} {}", msg.command, subs.join("|"))
+                    };
                     let reply = Message::from_server(
                         &server_name,
                         irc::ERR_UNKNOWNCOMMAND,
-                        vec![conn.nick_or_star(), &msg.command, "Unknown command"],
+                        vec![conn.nick_or_star(), &msg.command, &text],
                     );
                     send(&state, &session_id, format!("{reply}\r\n"));
                 }
@@ -3128,6 +3578,14 @@ where
         }
     }
 
+    state
+        .firehose
+        .publish(crate::firehose::FirehoseEvent::Disconnect {
+            session: session_id.clone(),
+            nick: conn.nick.clone(),
+            did: conn.authenticated_did.clone(),
+        });
+
     // Check if this DID has other active sessions (multi-device)
     let did = conn.authenticated_did.as_deref();
     let is_last_session_for_did = if let Some(d) = did {
@@ -3241,26 +3699,20 @@ where
                     .unwrap_or_default()
                     .into_iter()
                     .collect();
-                let ghost_channels: Vec<(String, bool, bool, bool)> = {
-                    let channels = state.channels.lock();
-                    channels
-                        .iter()
-                        .filter(|(name, ch)| {
-                            ch.members.contains(&session_id)
-                                && (state.db.is_none() || subscribed.contains(name.as_str()))
-                        })
-                        .map(|(name, ch)| {
-                            (
-                                name.clone(),
-                                ch.ops.contains(&session_id),
-                                ch.voiced.contains(&session_id),
-                                ch.halfops.contains(&session_id),
-                            )
-                        })
-                        .collect()
-                };
-
-                let (cancel_tx, cancel_rx) = tokio::sync::oneshot::channel::<()>();
+                let ghost_channels: Vec<(String, bool, bool, bool)> =
+                    state.channels.filter_map(|name, ch| {
+                        if !(ch.members.contains(&session_id)
+                            && (state.db.is_none() || subscribed.contains(name)))
+                        {
+                            return None;
+                        }
+                        Some((
+                            name.to_string(),
+                            ch.ops.contains(&session_id),
+                            ch.voiced.contains(&session_id),
+                            ch.halfops.contains(&session_id),
+                        ))
+                    });
 
                 // Remove from channels/state now, but hold nick and don't broadcast
                 cleanup_session_state(&state, &session_id);
@@ -3268,72 +3720,19 @@ where
                 // Don't remove the nick — ghost holds it
                 // state.nick_to_session.lock().remove_by_nick(nick); // <-- NOT here
 
-                let ghost = crate::server::GhostSession {
-                    nick: nick.clone(),
-                    hostmask: hostmask.clone(),
-                    session_id: session_id.clone(),
-                    channels: ghost_channels,
-                    disconnect_time: std::time::Instant::now(),
-                    cancel: cancel_tx,
-                };
-                state.ghost_sessions.lock().insert(did.clone(), ghost);
-
+                park_ghost(
+                    &state,
+                    did,
+                    nick,
+                    &hostmask,
+                    &session_id,
+                    ghost_channels,
+                    std::time::Duration::from_secs(QUIT_GRACE_SECS),
+                );
                 tracing::info!(
                     %session_id, nick = %nick, did = %did,
                     "Entered ghost mode ({}s grace period)", QUIT_GRACE_SECS
                 );
-
-                // Spawn deferred QUIT broadcast
-                let state_clone = state.clone();
-                let did_clone = did.clone();
-                let nick_clone = nick.clone();
-                let hostmask_clone = hostmask.clone();
-                tokio::spawn(async move {
-                    tokio::select! {
-                        _ = tokio::time::sleep(std::time::Duration::from_secs(QUIT_GRACE_SECS)) => {
-                            // Grace period expired — broadcast QUIT now
-                            let ghost = state_clone.ghost_sessions.lock().remove(&did_clone);
-                            if let Some(ghost) = ghost {
-                                let quit_msg = format!(":{hostmask_clone} QUIT :Connection closed\r\n");
-                                let channels = state_clone.channels.lock();
-                                let conns = state_clone.connections.lock();
-                                for ch in channels.values() {
-                                    for member in &ch.members {
-                                        if let Some(tx) = conns.get(member) {
-                                            let _ = tx.try_send(quit_msg.clone());
-                                        }
-                                    }
-                                }
-                                drop(conns);
-                                drop(channels);
-                                state_clone.nick_to_session.lock().remove_by_nick(&nick_clone);
-                                // Evict the ghost's stale session_id from ch.members.
-                                // cleanup_session_state (called at disconnect) intentionally
-                                // skips cleanup_channel_membership to preserve ghost membership
-                                // during the grace window. Now that grace has expired, clean up
-                                // to prevent the old session_id from being a ghost member forever.
-                                cleanup_channel_membership(&state_clone, &ghost.session_id);
-                                tracing::info!(
-                                    nick = %nick_clone, did = %did_clone,
-                                    "Ghost grace expired — broadcasting QUIT"
-                                );
-                                // S2S
-                                let origin = state_clone.server_iroh_id.lock().clone().unwrap_or_default();
-                                s2s_broadcast(&state_clone, crate::s2s::S2sMessage::Quit {
-                                    event_id: s2s_next_event_id(&state_clone),
-                                    nick: nick_clone,
-                                    reason: "Connection closed".to_string(),
-                                    origin,
-                                });
-                            }
-                        }
-                        _ = cancel_rx => {
-                            // Reconnected — ghost was reclaimed by attach_same_did.
-                            // Stale session_id was already cleaned up from ch.members
-                            // and nick_to_session during reclaim. No QUIT needed.
-                        }
-                    }
-                });
             } else {
                 // Guest user — immediate QUIT (no grace period)
                 let hostmask = conn.hostmask();
@@ -3363,6 +3762,8 @@ where
         cleanup_channel_membership(&state, &session_id);
     }
 
+    webirc::release(&conn, &state);
+
     tracing::info!(
         %session_id,
         nick = conn.nick.as_deref().unwrap_or("-"),
@@ -3375,8 +3776,25 @@ where
     Ok(())
 }
 
+/// `NOTICE <nick> :OPER-TOKEN <token>` with a fresh bearer for the
+/// oper-only HTTP endpoints; sent whenever a session gains OPER.
+pub(crate) fn oper_token_notice(
+    state: &SharedState,
+    server_name: &str,
+    nick: &str,
+    session_id: &str,
+) -> String {
+    let token = state.issue_oper_token(session_id);
+    let notice = Message::from_server(
+        server_name,
+        "NOTICE",
+        vec![nick, &format!("OPER-TOKEN {token}")],
+    );
+    format!("{notice}\r\n")
+}
+
 /// Constant-time byte comparison to prevent timing side-channel attacks (M-16).
-fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
     if a.len() != b.len() {
         return false;
     }
@@ -3446,12 +3864,11 @@ fn detect_client(realname: &str) -> String {
 
 /// Broadcast QUIT to all channels the session is in.
 fn broadcast_quit(state: &Arc<SharedState>, session_id: &str, hostmask: &str) {
-    let quit_msg = format!(":{hostmask} QUIT :Connection closed\r\n");
-    let channels = state.channels.lock();
-    let conns = state.connections.lock();
-    for ch in channels.values() {
+    let quit_msg = WireLine::from(format!(":{hostmask} QUIT :Connection closed\r\n"));
+    let conns = &state.connections;
+    state.channels.for_each(|_, ch| {
         if ch.members.contains(session_id) {
-            for member in &ch.members {
+            for member in &ch.audience(session_id) {
                 if member != session_id
                     && let Some(tx) = conns.get(member)
                 {
@@ -3459,7 +3876,7 @@ fn broadcast_quit(state: &Arc<SharedState>, session_id: &str, hostmask: &str) {
                 }
             }
         }
-    }
+    });
 }
 
 /// Broadcast QUIT to S2S peers.
@@ -3476,35 +3893,112 @@ fn broadcast_quit_s2s(state: &Arc<SharedState>, nick: &str) {
     );
 }
 
+/// Hold `nick` and the channel membership of the departed `session_id`
+/// for `did` until `grace` runs out, then broadcast its QUIT.
+/// Reconnecting as `did` within the window reclaims the ghost (see
+/// `registration::attach_same_did`) and cancels the QUIT.
+///
+/// `session_id` must already be in the member sets of `channels` and
+/// in `nick_to_session`; it is evicted from both when the grace period
+/// expires.
+pub(crate) fn park_ghost(
+    state: &Arc<SharedState>,
+    did: &str,
+    nick: &str,
+    hostmask: &str,
+    session_id: &str,
+    channels: Vec<(String, bool, bool, bool)>,
+    grace: std::time::Duration,
+) {
+    let (cancel_tx, cancel_rx) = tokio::sync::oneshot::channel::<()>();
+    let ghost = crate::server::GhostSession {
+        nick: nick.to_string(),
+        hostmask: hostmask.to_string(),
+        session_id: session_id.to_string(),
+        channels,
+        disconnect_time: std::time::Instant::now(),
+        cancel: cancel_tx,
+    };
+    state.ghost_sessions.lock().insert(did.to_string(), ghost);
+
+    let state = state.clone();
+    let did = did.to_string();
+    let nick = nick.to_string();
+    let hostmask = hostmask.to_string();
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = tokio::time::sleep(grace) => {
+                // Grace period expired — broadcast QUIT now
+                let ghost = state.ghost_sessions.lock().remove(&did);
+                if let Some(ghost) = ghost {
+                    let quit_msg = WireLine::from(format!(":{hostmask} QUIT :Connection closed\r\n"));
+                    let conns = &state.connections;
+                    state.channels.for_each(|_, ch| {
+                        for member in &ch.members {
+                            if let Some(tx) = conns.get(member) {
+                                let _ = tx.try_send(quit_msg.clone());
+                            }
+                        }
+                    });
+                    state.nick_to_session.lock().remove_by_nick(&nick);
+                    // Evict the ghost's stale session_id from ch.members.
+                    // cleanup_session_state (called at disconnect) intentionally
+                    // skips cleanup_channel_membership to preserve ghost membership
+                    // during the grace window. Now that grace has expired, clean up
+                    // to prevent the old session_id from being a ghost member forever.
+                    cleanup_channel_membership(&state, &ghost.session_id);
+                    tracing::info!(
+                        nick = %nick, did = %did,
+                        "Ghost grace expired — broadcasting QUIT"
+                    );
+                    // S2S
+                    let origin = state.server_iroh_id.lock().clone().unwrap_or_default();
+                    s2s_broadcast(&state, crate::s2s::S2sMessage::Quit {
+                        event_id: s2s_next_event_id(&state),
+                        nick,
+                        reason: "Connection closed".to_string(),
+                        origin,
+                    });
+                }
+            }
+            _ = cancel_rx => {
+                // Reconnected — ghost was reclaimed by attach_same_did.
+                // Stale session_id was already cleaned up from ch.members
+                // and nick_to_session during reclaim. No QUIT needed.
+            }
+        }
+    });
+}
+
 /// Clean up per-session state (connections, caps, etc.) but NOT channel membership.
 fn cleanup_session_state(state: &Arc<SharedState>, session_id: &str) {
-    state.connections.lock().remove(session_id);
+    state.connections.remove(session_id);
     state.session_kill.lock().remove(session_id);
     state.liveness_probes.lock().remove(session_id);
     state.session_dids.lock().remove(session_id);
     state.session_handles.lock().remove(session_id);
     state.session_iroh_ids.lock().remove(session_id);
     state.session_away.lock().remove(session_id);
-    state.msg_timestamps.lock().remove(session_id);
+    {
+        let mut timestamps = state.msg_timestamps.lock();
+        timestamps.remove(session_id);
+        timestamps.remove(&format!("p2p:{session_id}"));
+    }
     state.session_msg_keys.lock().remove(session_id);
     state.session_client_info.lock().remove(session_id);
-    state.cap_message_tags.lock().remove(session_id);
-    state.cap_multi_prefix.lock().remove(session_id);
-    state.cap_echo_message.lock().remove(session_id);
-    state.cap_server_time.lock().remove(session_id);
-    state.cap_batch.lock().remove(session_id);
-    state.cap_draft_multiline.lock().remove(session_id);
+    state.session_activity.lock().remove(session_id);
+    state.sessions.remove(session_id);
     // Drop any open BATCH state for this session — the client is gone,
     // those batches will never be closed.
     state
         .open_batches
         .lock()
         .retain(|(sid, _bid), _| sid != session_id);
-    state.cap_account_notify.lock().remove(session_id);
-    state.cap_extended_join.lock().remove(session_id);
-    state.cap_away_notify.lock().remove(session_id);
-    state.cap_account_tag.lock().remove(session_id);
+    // Guest metadata is owned by the session; DID metadata outlives it.
+    state.metadata.lock().remove(session_id);
+    state.metadata_subs.lock().remove(session_id);
     state.server_opers.lock().remove(session_id);
+    state.oper_tokens.lock().retain(|_, sid| sid != session_id);
     state.session_actor_class.lock().remove(session_id);
     state.agent_presence.lock().remove(session_id);
     state.agent_heartbeats.lock().remove(session_id);
@@ -3535,19 +4029,20 @@ fn cleanup_session_state(state: &Arc<SharedState>, session_id: &str) {
 
 /// Remove a session from all channels. Retains channels that still have content.
 fn cleanup_channel_membership(state: &Arc<SharedState>, session_id: &str) {
-    let mut channels = state.channels.lock();
-    for ch in channels.values_mut() {
+    state.channels.retain(|_, ch| {
         ch.members.remove(session_id);
+        ch.member_since.remove(session_id);
         ch.ops.remove(session_id);
         ch.voiced.remove(session_id);
         ch.halfops.remove(session_id);
-    }
-    channels.retain(|_, ch| {
+        ch.founders.remove(session_id);
+        ch.admins.remove(session_id);
         !ch.members.is_empty()
             || !ch.remote_members.is_empty()
             || ch.founder_did.is_some()
             || ch.topic.is_some()
             || !ch.bans.is_empty()
+            || ch.temporary.is_some()
     });
 }
 
@@ -0,0 +1,435 @@
+//! IRC MYDATA command — export or erase what the server holds for your DID.
+//!
+//! MYDATA                  — Summarise what the server holds for your DID
+//! MYDATA EXPORT           — List it: profile, credentials, sessions and messages
+//! MYDATA ERASE CONFIRM    — Erase the content of every message you authored
+//!
+//! The same export is served as JSON by `GET /api/v1/me/export`, and
+//! `POST /api/v1/me/erase` erases, both with a `Bearer <session-id>` of an
+//! authenticated session. Over IRC only the latest [`EXPORT_MAX_MESSAGES`]
+//! messages are listed; the HTTP export has all of them.
+//!
+//! Erasure blanks each message's text and tags in the message store and
+//! marks it deleted, drops it from in-memory history and pins, sends
+//! `+draft/delete` to connected clients and an `Erase` event to S2S peers,
+//! which do the same for their copies. Profile metadata, preferences,
+//! credentials and sessions are left alone: they have their own commands
+//! (METADATA, PREF, SESSIONS) and the owner may still need them.
+
+use crate::irc::{self, Message};
+use crate::server::{SharedState, WEB_SESSION_IDLE_TTL};
+use crate::session::Cap;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// Most messages `MYDATA EXPORT` lists over IRC.
+pub const EXPORT_MAX_MESSAGES: usize = 200;
+/// Most msgids carried by one S2S `Erase` event.
+pub const ERASE_BATCH: usize = 500;
+
+/// Everything the server holds about one DID.
+#[derive(Debug, Serialize)]
+pub struct DataExport {
+    pub did: String,
+    pub server: String,
+    /// RFC 3339 time the export was made.
+    pub generated_at: String,
+    pub profile: Profile,
+    /// Channels the DID is rejoined to on reconnect.
+    pub channels: Vec<String>,
+    pub credentials: Vec<crate::policy::store::StoredCredential>,
+    pub sessions: Sessions,
+    /// Messages authored, oldest first. Direct messages have a `dm:` channel.
+    pub messages: Vec<ExportedMessage>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Profile {
+    /// Registered nick, if the DID has one.
+    pub nick: Option<String>,
+    /// `draft/metadata-2` keys, private ones included.
+    pub metadata: BTreeMap<String, String>,
+    /// Preferences set with PREF (defaults aren't stored).
+    pub preferences: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Sessions {
+    pub irc: Vec<IrcSession>,
+    pub web: Vec<WebSessionInfo>,
+    pub iroh_endpoints: Vec<IrohEndpoint>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IrcSession {
+    pub session_id: String,
+    pub nick: Option<String>,
+    /// Unix time registration completed.
+    pub signon: Option<i64>,
+    pub client: Option<String>,
+}
+
+/// A web session, without its PDS credentials.
+#[derive(Debug, Serialize)]
+pub struct WebSessionInfo {
+    pub id: String,
+    pub purpose: String,
+    pub handle: String,
+    pub age_secs: u64,
+    pub idle_secs: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IrohEndpoint {
+    pub endpoint_id: String,
+    /// Unix time it was bound.
+    pub bound_at: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportedMessage {
+    pub channel: String,
+    pub msgid: Option<String>,
+    pub timestamp: u64,
+    pub text: String,
+    pub tags: HashMap<String, String>,
+    /// Unix time it was deleted, if it was.
+    pub deleted_at: Option<u64>,
+}
+
+/// Collect the export for `did`.
+pub fn build_export(state: &SharedState, did: &str) -> DataExport {
+    let nick = state.did_nicks.lock().get(did).cloned().or_else(|| {
+        state
+            .with_db(|db| db.get_identity_by_did(did))
+            .flatten()
+            .map(|i| i.nick)
+    });
+    let metadata = state.metadata.lock().get(did).cloned().unwrap_or_default();
+    let preferences = state.prefs.lock().stored(did);
+    let mut channels = state
+        .with_db(|db| db.get_user_channels(did))
+        .unwrap_or_default();
+    channels.sort();
+    let credentials = state
+        .policy_engine
+        .as_ref()
+        .and_then(|engine| engine.store().get_credentials(did).ok())
+        .unwrap_or_default();
+
+    let mut irc_sessions: Vec<String> = state
+        .did_sessions
+        .lock()
+        .get(did)
+        .map(|s| s.iter().cloned().collect())
+        .unwrap_or_default();
+    irc_sessions.sort();
+    let irc = irc_sessions
+        .into_iter()
+        .map(|session_id| IrcSession {
+            nick: state
+                .nick_to_session
+                .lock()
+                .get_nick(&session_id)
+                .map(str::to_string),
+            signon: state
+                .session_activity
+                .lock()
+                .get(&session_id)
+                .map(|a| a.signon),
+            client: state.session_client_info.lock().get(&session_id).cloned(),
+            session_id,
+        })
+        .collect();
+    let mut web: Vec<_> = state
+        .web_sessions
+        .lock()
+        .iter()
+        .filter(|((d, _), s)| d == did && !s.is_expired())
+        .map(|((_, purpose), s)| WebSessionInfo {
+            id: s.id.clone(),
+            purpose: purpose.as_str().to_string(),
+            handle: s.handle.clone(),
+            age_secs: s.created_at.elapsed().as_secs(),
+            idle_secs: s.last_used.elapsed().as_secs(),
+        })
+        .collect();
+    web.sort_by_key(|s| std::cmp::Reverse(s.age_secs));
+    let iroh_endpoints = state
+        .iroh_endpoints_for_did(did)
+        .into_iter()
+        .map(|(endpoint_id, binding)| IrohEndpoint {
+            endpoint_id,
+            bound_at: binding.bound_at,
+        })
+        .collect();
+
+    let messages = state
+        .with_db(|db| db.messages_by_sender_did(did))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|r| ExportedMessage {
+            channel: r.channel,
+            msgid: r.msgid,
+            timestamp: r.timestamp,
+            text: r.text,
+            tags: r.tags,
+            deleted_at: r.deleted_at,
+        })
+        .collect();
+
+    DataExport {
+        did: did.to_string(),
+        server: state.server_name.clone(),
+        generated_at: chrono::Utc::now().to_rfc3339(),
+        profile: Profile {
+            nick,
+            metadata,
+            preferences,
+        },
+        channels,
+        credentials,
+        sessions: Sessions {
+            irc,
+            web,
+            iroh_endpoints,
+        },
+        messages,
+    }
+}
+
+/// Erase every message `did` authored, here and on S2S peers. Returns how
+/// many were erased locally.
+pub fn erase(state: &Arc<SharedState>, did: &str) -> usize {
+    let erased = state
+        .with_db(|db| db.erase_messages_by_did(did, None))
+        .unwrap_or_default();
+    apply_erasure(state, did, &erased);
+
+    let origin = state.server_iroh_id.lock().clone().unwrap_or_default();
+    let msgids: Vec<String> = erased.iter().map(|(_, m)| m.clone()).collect();
+    for chunk in msgids.chunks(ERASE_BATCH) {
+        super::helpers::s2s_broadcast(
+            state,
+            crate::s2s::S2sMessage::Erase {
+                event_id: super::helpers::s2s_next_event_id(state),
+                did: did.to_string(),
+                msgids: chunk.to_vec(),
+                origin: origin.clone(),
+            },
+        );
+    }
+    tracing::info!(%did, erased = erased.len(), "Erased messages for DID");
+    erased.len()
+}
+
+/// Drop erased `(channel, msgid)` messages from in-memory history and
+/// pins, and tell tag-capable clients with `+draft/delete`: channel
+/// members for channel messages, both parties' sessions for DMs.
+pub fn apply_erasure(state: &Arc<SharedState>, did: &str, erased: &[(String, String)]) {
+    let mut by_channel: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (channel, msgid) in erased {
+        by_channel
+            .entry(channel.as_str())
+            .or_default()
+            .push(msgid.as_str());
+    }
+    let nick_of = |d: &str| state.did_nicks.lock().get(d).cloned();
+    let sessions_of = |d: &str| -> Vec<String> {
+        state
+            .did_sessions
+            .lock()
+            .get(d)
+            .map(|s| s.iter().cloned().collect())
+            .unwrap_or_default()
+    };
+    let send = |sessions: &[String], prefix: &str, target: &str, msgid: &str| {
+        let mut tags = HashMap::new();
+        tags.insert("+draft/delete".to_string(), msgid.to_string());
+        let line = format!(
+            "{}\r\n",
+            irc::Message {
+                tags,
+                prefix: Some(prefix.to_string()),
+                command: "TAGMSG".to_string(),
+                params: vec![target.to_string()],
+            }
+        );
+        for sid in sessions {
+            if state.sessions.has_cap(sid, Cap::MessageTags)
+                && let Some(tx) = state.connections.get(sid)
+            {
+                let _ = tx.try_send(line.clone().into());
+            }
+        }
+    };
+
+    for (channel, msgids) in by_channel {
+        if let Some(pair) = channel.strip_prefix("dm:") {
+            // Each side sees the DM under the other's nick.
+            let Some(peer_did) = pair.split(',').find(|d| *d != did) else {
+                continue;
+            };
+            let (Some(nick), Some(peer_nick)) = (nick_of(did), nick_of(peer_did)) else {
+                continue;
+            };
+            let prefix = format!("{nick}!~u@{}", state.server_name);
+            let mut sessions = sessions_of(did);
+            sessions.extend(sessions_of(peer_did));
+            for msgid in msgids {
+                send(&sessions, &prefix, &peer_nick, msgid);
+            }
+            continue;
+        }
+
+        let Some(mut ch) = state.channels.get(&crate::casemap::fold(channel)) else {
+            continue;
+        };
+        ch.history
+            .retain(|h| !h.msgid.as_deref().is_some_and(|m| msgids.contains(&m)));
+        let unpinned: Vec<String> = ch
+            .pins
+            .iter()
+            .filter(|p| msgids.contains(&p.msgid.as_str()))
+            .map(|p| p.msgid.clone())
+            .collect();
+        ch.pins.retain(|p| !unpinned.contains(&p.msgid));
+        let members: Vec<String> = ch.members.iter().cloned().collect();
+        drop(ch);
+        for msgid in &unpinned {
+            state.with_db(|db| db.remove_pin(channel, msgid));
+        }
+        for msgid in msgids {
+            send(&members, &state.server_name, channel, msgid);
+        }
+    }
+}
+
+pub(super) fn handle_mydata(
+    conn: &super::Connection,
+    msg: &Message,
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    send_fn: &impl Fn(&Arc<SharedState>, &str, String),
+) {
+    let nick = conn.nick_or_star();
+    let notice = |text: &str| {
+        let reply = Message::from_server(server_name, "NOTICE", vec![nick, text]);
+        send_fn(state, session_id, format!("{reply}\r\n"));
+    };
+    let usage = "Usage: MYDATA [EXPORT] | MYDATA ERASE CONFIRM";
+
+    let Some(did) = conn.authenticated_did.as_deref() else {
+        notice("MYDATA requires an authenticated identity");
+        return;
+    };
+
+    match msg.params.first().map(|s| s.to_uppercase()).as_deref() {
+        None => {
+            let export = build_export(state, did);
+            let live = export
+                .messages
+                .iter()
+                .filter(|m| m.deleted_at.is_none())
+                .count();
+            notice(&format!(
+                "MYDATA {did}: {} message(s) ({live} visible), {} metadata key(s), {} credential(s), {} IRC / {} web session(s), {} iroh endpoint(s)",
+                export.messages.len(),
+                export.profile.metadata.len(),
+                export.credentials.len(),
+                export.sessions.irc.len(),
+                export.sessions.web.len(),
+                export.sessions.iroh_endpoints.len(),
+            ));
+            notice("Use MYDATA EXPORT to list it, or MYDATA ERASE CONFIRM to erase your messages");
+        }
+        Some("EXPORT") => {
+            let export = build_export(state, did);
+            notice(&format!(
+                "PROFILE nick={}",
+                export.profile.nick.as_deref().unwrap_or("*")
+            ));
+            for (key, value) in &export.profile.metadata {
+                notice(&format!("METADATA {key} :{value}"));
+            }
+            for (key, value) in &export.profile.preferences {
+                notice(&format!("PREF {key} :{value}"));
+            }
+            if !export.channels.is_empty() {
+                notice(&format!("CHANNELS {}", export.channels.join(" ")));
+            }
+            for c in &export.credentials {
+                notice(&format!(
+                    "CREDENTIAL {} issuer={} issued={}",
+                    c.credential_type, c.issuer, c.issued_at
+                ));
+            }
+            for s in &export.sessions.irc {
+                notice(&format!(
+                    "SESSION irc {} nick={} signon={} client=\"{}\"",
+                    s.session_id,
+                    s.nick.as_deref().unwrap_or("*"),
+                    s.signon.unwrap_or(0),
+                    s.client.as_deref().unwrap_or(""),
+                ));
+            }
+            for s in &export.sessions.web {
+                notice(&format!(
+                    "SESSION web {} {} age={}s idle={}s expires_in={}s",
+                    s.id,
+                    s.purpose,
+                    s.age_secs,
+                    s.idle_secs,
+                    WEB_SESSION_IDLE_TTL.as_secs().saturating_sub(s.idle_secs),
+                ));
+            }
+            for e in &export.sessions.iroh_endpoints {
+                notice(&format!(
+                    "SESSION iroh {} bound_at={}",
+                    e.endpoint_id, e.bound_at
+                ));
+            }
+            let skip = export.messages.len().saturating_sub(EXPORT_MAX_MESSAGES);
+            if skip > 0 {
+                notice(&format!(
+                    "{skip} older message(s) not listed — GET /api/v1/me/export has them all"
+                ));
+            }
+            for m in &export.messages[skip..] {
+                let when = chrono::DateTime::from_timestamp(m.timestamp as i64, 0)
+                    .map(|t| t.to_rfc3339())
+                    .unwrap_or_default();
+                let state_tag = if m.deleted_at.is_some() {
+                    " deleted"
+                } else {
+                    ""
+                };
+                notice(&format!(
+                    "MESSAGE {} {} {when}{state_tag} :{}",
+                    m.channel,
+                    m.msgid.as_deref().unwrap_or("*"),
+                    m.text.replace(['\r', '\n'], " "),
+                ));
+            }
+            notice("End of MYDATA EXPORT");
+        }
+        Some("ERASE") => {
+            if !msg
+                .params
+                .get(1)
+                .is_some_and(|p| p.eq_ignore_ascii_case("CONFIRM"))
+            {
+                notice(
+                    "MYDATA ERASE blanks every message you have sent, here and on federated \
+                     servers, and cannot be undone. Send MYDATA ERASE CONFIRM to proceed",
+                );
+                return;
+            }
+            let n = erase(state, did);
+            notice(&format!("Erased {n} message(s)"));
+        }
+        Some(_) => notice(usage),
+    }
+}
@@ -0,0 +1,370 @@
+//! NAMES and WHO for large channels.
+//!
+//! A channel's NAMES used to be one 353 line however long it got: for a
+//! 10k-member channel, a ~150 KB line queued in one go, past the IRC line
+//! limit and the client's low-priority budget. Member lists now go out as
+//! 353 lines of at most [`LINE_MAX`] bytes, and [`send_paced`] hands them
+//! (and channel WHO replies) to the client's queue only as fast as it
+//! drains. On top of that:
+//!
+//! - `NAMES <channel> LIMIT <n> [AFTER <nick>]` asks for one page of at
+//!   most [`PAGE_MAX`] members, in casefolded nick order. A page that
+//!   doesn't reach the end is followed, before the 366, by
+//!   `NOTE NAMES MORE <channel> <nick> :<count> more`, `<nick>` being the
+//!   `AFTER` for the next page.
+//! - Without `LIMIT`, lists longer than [`IMPLICIT_MAX`] are cut there,
+//!   the viewer, then ops and voiced members kept first, and the 366 reads
+//!   `End of /NAMES list (showing <n> of <total> members)`.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::irc::{self, Message};
+use crate::send_queue::ClientQueue;
+use crate::server::SharedState;
+
+/// Longest 353 line, CRLF excluded.
+pub(crate) const LINE_MAX: usize = 510;
+/// Members listed by a NAMES without `LIMIT`.
+pub(crate) const IMPLICIT_MAX: usize = 1000;
+/// Largest page `LIMIT` may ask for, and the page size of `AFTER` alone.
+pub(crate) const PAGE_MAX: usize = 1000;
+/// Queued bytes at which [`send_paced`] stops to let the client read.
+pub(crate) const HIGH_WATER: usize = 64 * 1024;
+/// How often a paused delivery checks the queue again.
+const PACE_POLL: Duration = Duration::from_millis(20);
+/// How long a paused delivery waits for a client that reads nothing
+/// before dropping the rest of the list.
+const PACE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// One page of a channel's NAMES.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Page {
+    pub limit: usize,
+    /// List members whose casefolded nick sorts after this one.
+    pub after: Option<String>,
+}
+
+impl Page {
+    /// Parse the parameters after the channel. `Ok(None)` unless they
+    /// start with `LIMIT` or `AFTER`, so RFC 1459's `NAMES <channel>
+    /// <server>` still gets the whole (implicit) list.
+    pub(crate) fn parse(words: &[String]) -> Result<Option<Page>, &'static str> {
+        let keyword =
+            |w: &String| w.eq_ignore_ascii_case("LIMIT") || w.eq_ignore_ascii_case("AFTER");
+        if !words.first().is_some_and(keyword) {
+            return Ok(None);
+        }
+        let mut page = Page {
+            limit: PAGE_MAX,
+            after: None,
+        };
+        for pair in words.chunks(2) {
+            let [word, value] = pair else {
+                return Err("Expected LIMIT <count> and/or AFTER <nick>");
+            };
+            if word.eq_ignore_ascii_case("LIMIT") {
+                page.limit = value
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or("LIMIT must be a positive number")?
+                    .min(PAGE_MAX);
+            } else if word.eq_ignore_ascii_case("AFTER") {
+                page.after = Some(value.clone());
+            } else {
+                return Err("Expected LIMIT <count> and/or AFTER <nick>");
+            }
+        }
+        Ok(Some(page))
+    }
+}
+
+/// The members one NAMES reply lists.
+#[derive(Debug, Default)]
+pub(crate) struct Selection {
+    /// `prefix` + `nick`, in reply order.
+    pub names: Vec<String>,
+    /// How many members there were, when the list was cut at
+    /// [`IMPLICIT_MAX`].
+    pub truncated_from: Option<usize>,
+    /// The `AFTER` cursor and count of what is left, when a page stops
+    /// short of the end.
+    pub more: Option<(String, usize)>,
+}
+
+/// Choose which of `members` (`(prefix, nick)`, without duplicates) a
+/// NAMES for `viewer` lists: `page`, or at most [`IMPLICIT_MAX`] of them.
+pub(crate) fn select(
+    mut members: Vec<(String, String)>,
+    viewer: &str,
+    page: Option<&Page>,
+) -> Selection {
+    let format = |(prefix, nick): (String, String)| format!("{prefix}{nick}");
+    let Some(page) = page else {
+        let total = members.len();
+        if total <= IMPLICIT_MAX {
+            return Selection {
+                names: members.into_iter().map(format).collect(),
+                ..Default::default()
+            };
+        }
+        let viewer = crate::casemap::fold(viewer);
+        members.sort_by_key(|(prefix, nick)| {
+            (crate::casemap::fold(nick) != viewer, prefix.is_empty())
+        });
+        members.truncate(IMPLICIT_MAX);
+        return Selection {
+            names: members.into_iter().map(format).collect(),
+            truncated_from: Some(total),
+            more: None,
+        };
+    };
+
+    let mut members: Vec<(String, String, String)> = members
+        .into_iter()
+        .map(|(prefix, nick)| (crate::casemap::fold(&nick), prefix, nick))
+        .collect();
+    members.sort_by(|a, b| a.0.cmp(&b.0));
+    let start = match &page.after {
+        Some(after) => {
+            let after = crate::casemap::fold(after);
+            members.partition_point(|(folded, _, _)| *folded <= after)
+        }
+        None => 0,
+    };
+    let end = (start + page.limit).min(members.len());
+    let remaining = members.len() - end;
+    let more = (remaining > 0 && end > start).then(|| (members[end - 1].2.clone(), remaining));
+    Selection {
+        names: members
+            .drain(start..end)
+            .map(|(_, prefix, nick)| format((prefix, nick)))
+            .collect(),
+        truncated_from: None,
+        more,
+    }
+}
+
+/// `names` as 353 lines for `nick`, each at most [`LINE_MAX`] bytes
+/// before its CRLF. No names, no lines: the 366 alone ends an empty list.
+pub(crate) fn reply_lines(
+    server_name: &str,
+    nick: &str,
+    channel: &str,
+    names: &[String],
+) -> Vec<String> {
+    let budget = LINE_MAX.saturating_sub(format!(":{server_name} 353 {nick} = {channel} :").len());
+    let mut lines = Vec::new();
+    let mut chunk = String::new();
+    for name in names {
+        if !chunk.is_empty() && chunk.len() + 1 + name.len() > budget {
+            lines.push(namreply(server_name, nick, channel, &chunk));
+            chunk.clear();
+        }
+        if !chunk.is_empty() {
+            chunk.push(' ');
+        }
+        chunk.push_str(name);
+    }
+    if !chunk.is_empty() {
+        lines.push(namreply(server_name, nick, channel, &chunk));
+    }
+    lines
+}
+
+fn namreply(server_name: &str, nick: &str, channel: &str, names: &str) -> String {
+    let reply = Message::from_server(
+        server_name,
+        irc::RPL_NAMREPLY,
+        vec![nick, "=", channel, names],
+    );
+    format!("{reply}\r\n")
+}
+
+/// `NOTE NAMES MORE` for a page that stopped at `cursor`.
+pub(crate) fn more_note(
+    server_name: &str,
+    channel: &str,
+    cursor: &str,
+    remaining: usize,
+) -> String {
+    let text = format!("{remaining} more");
+    let note = Message::from_server(
+        server_name,
+        "NOTE",
+        vec!["NAMES", "MORE", channel, cursor, &text],
+    );
+    format!("{note}\r\n")
+}
+
+/// Queue `lines` for `session_id` at low priority no faster than its
+/// client reads them, then `end` at normal priority.
+///
+/// Lines go straight to the queue while it holds less than
+/// [`HIGH_WATER`] bytes. The rest are handed over by a task that waits
+/// for it to drain, so a long list neither runs into the low-priority
+/// budget (where its lines would be dropped) nor holds up the read loop.
+/// A client that reads nothing for [`PACE_TIMEOUT`] loses the rest of
+/// the list but still gets `end`.
+pub(crate) fn send_paced(
+    state: &Arc<SharedState>,
+    session_id: &str,
+    lines: Vec<String>,
+    end: Vec<String>,
+) {
+    let Some(queue) = state.connections.get(session_id).map(|q| q.clone()) else {
+        return;
+    };
+    let mut lines = lines.into_iter();
+    let mut rest = Vec::new();
+    for line in lines.by_ref() {
+        if queue.queued_bytes() >= HIGH_WATER {
+            rest.push(line);
+            break;
+        }
+        let _ = queue.try_send_low(line.into());
+    }
+    rest.extend(lines);
+    if rest.is_empty() {
+        finish(&queue, end);
+        return;
+    }
+    match tokio::runtime::Handle::try_current() {
+        Ok(runtime) => {
+            runtime.spawn(drain(queue, rest, end));
+        }
+        // Nothing to wait on outside a runtime: queue the lot.
+        Err(_) => {
+            for line in rest {
+                let _ = queue.try_send_low(line.into());
+            }
+            finish(&queue, end);
+        }
+    }
+}
+
+async fn drain(queue: ClientQueue, lines: Vec<String>, end: Vec<String>) {
+    let mut lines = lines.into_iter().peekable();
+    let mut progress = Instant::now();
+    while lines.peek().is_some() {
+        if queue.is_closed() {
+            return;
+        }
+        if queue.queued_bytes() >= HIGH_WATER {
+            if progress.elapsed() >= PACE_TIMEOUT {
+                tracing::debug!(dropped = lines.len(), "Client stopped reading a paced list");
+                break;
+            }
+            tokio::time::sleep(PACE_POLL).await;
+            continue;
+        }
+        progress = Instant::now();
+        while queue.queued_bytes() < HIGH_WATER
+            && let Some(line) = lines.next()
+        {
+            let _ = queue.try_send_low(line.into());
+        }
+    }
+    finish(&queue, end);
+}
+
+fn finish(queue: &ClientQueue, end: Vec<String>) {
+    for line in end {
+        let _ = queue.try_send(line.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    fn members(n: usize) -> Vec<(String, String)> {
+        (0..n)
+            .map(|i| (String::new(), format!("user{i:05}")))
+            .collect()
+    }
+
+    #[test]
+    fn page_parameters() {
+        assert_eq!(Page::parse(&[]), Ok(None));
+        assert_eq!(Page::parse(&words("irc.example.com")), Ok(None));
+        assert_eq!(
+            Page::parse(&words("limit 50 AFTER bob")),
+            Ok(Some(Page {
+                limit: 50,
+                after: Some("bob".to_string())
+            }))
+        );
+        assert_eq!(
+            Page::parse(&words("AFTER bob")),
+            Ok(Some(Page {
+                limit: PAGE_MAX,
+                after: Some("bob".to_string())
+            }))
+        );
+        assert_eq!(
+            Page::parse(&words("LIMIT 99999")).unwrap().unwrap().limit,
+            PAGE_MAX
+        );
+        for bad in ["LIMIT 0", "LIMIT many", "LIMIT", "LIMIT 5 SINCE x"] {
+            assert!(Page::parse(&words(bad)).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn pages_walk_the_list_in_nick_order() {
+        let mut list = members(25);
+        list.reverse();
+        let page = |after: Option<&str>| {
+            select(
+                list.clone(),
+                "user00000",
+                Some(&Page {
+                    limit: 10,
+                    after: after.map(String::from),
+                }),
+            )
+        };
+        let first = page(None);
+        assert_eq!(first.names.first().map(String::as_str), Some("user00000"));
+        assert_eq!(first.more, Some(("user00009".to_string(), 15)));
+        let last = page(Some("USER00019"));
+        assert_eq!(last.names.len(), 5);
+        assert_eq!(last.more, None);
+        assert!(page(Some("zzz")).names.is_empty());
+    }
+
+    #[test]
+    fn implicit_lists_are_cut_keeping_the_viewer_and_ops() {
+        let mut list = members(IMPLICIT_MAX + 500);
+        list[1200].0 = "@".to_string();
+        let selection = select(list, "user01400", None);
+        assert_eq!(selection.names.len(), IMPLICIT_MAX);
+        assert_eq!(selection.truncated_from, Some(IMPLICIT_MAX + 500));
+        assert_eq!(selection.names[0], "user01400");
+        assert_eq!(selection.names[1], "@user01200");
+
+        let small = select(members(3), "user00000", None);
+        assert_eq!(small.names.len(), 3);
+        assert_eq!(small.truncated_from, None);
+    }
+
+    #[test]
+    fn reply_lines_stay_under_the_line_limit() {
+        let names: Vec<String> = members(2000).into_iter().map(|(_, n)| n).collect();
+        let lines = reply_lines("irc.example.com", "alice", "#big", &names);
+        assert!(lines.len() > 1);
+        assert!(lines.iter().all(|l| l.len() <= LINE_MAX + 2));
+        let listed: usize = lines
+            .iter()
+            .map(|l| l.trim_end().rsplit_once(':').unwrap().1.split(' ').count())
+            .sum();
+        assert_eq!(listed, 2000);
+        assert!(reply_lines("irc.example.com", "alice", "#empty", &[]).is_empty());
+    }
+}
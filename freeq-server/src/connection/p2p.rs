@@ -0,0 +1,145 @@
+//! Brokering direct DM transport negotiation (`freeq.at/p2p-dm`).
+//!
+//! Two clients can move a DM off the server onto a direct iroh
+//! connection by trading p2p endpoint IDs in TAGMSGs:
+//!
+//! ```text
+//! A → B  @+freeq.at/p2p-offer=<A's endpoint ID>   TAGMSG B
+//! B → A  @+freeq.at/p2p-answer=<B's endpoint ID>  TAGMSG A
+//! A dials B. Either side sends +freeq.at/p2p-close to fall back.
+//! ```
+//!
+//! The server only relays these, but vets each one first: DMs only, both
+//! ends authenticated, the recipient has negotiated the cap, the endpoint
+//! ID is well-formed, and the sender isn't flooding. A relayed offer or
+//! answer carries the sender's DID in the server tag `freeq.at/p2p-did`,
+//! so the recipient knows whose endpoint it is about to dial; the QUIC
+//! handshake then proves the peer holds that endpoint's key. The server
+//! stays the relay for the DM whenever there is no direct connection.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use freeq_proto::tags;
+
+use super::Connection;
+use crate::irc::Message;
+use crate::server::SharedState;
+use crate::session::Cap;
+
+/// Negotiation TAGMSGs one session may send per [`WINDOW_MS`].
+const MAX_PER_WINDOW: usize = 10;
+const WINDOW_MS: u64 = 10_000;
+
+/// Whether `tags` carry any negotiation tag.
+pub(super) fn is_negotiation(tags: &HashMap<String, String>) -> bool {
+    [tags::P2P_OFFER, tags::P2P_ANSWER, tags::P2P_CLOSE]
+        .iter()
+        .any(|t| tags.contains_key(*t))
+}
+
+/// Check a negotiation TAGMSG from `conn` to `target`, stamping offers and
+/// answers with the sender's DID. On `false` the sender has been sent a
+/// `FAIL TAGMSG <code> <target>` and the TAGMSG must not be relayed.
+pub(super) fn vet(
+    conn: &Connection,
+    target: &str,
+    tags: &mut HashMap<String, String>,
+    state: &Arc<SharedState>,
+) -> bool {
+    let fail = |code: &str, text: &str| {
+        let reply = Message::from_server(
+            &state.server_name,
+            "FAIL",
+            vec!["TAGMSG", code, target, text],
+        );
+        if let Some(tx) = state.connections.get(&conn.id) {
+            let _ = tx.try_send(format!("{reply}\r\n").into());
+        }
+    };
+
+    if target.starts_with('#') || target.starts_with('&') {
+        fail("P2P_DM_ONLY", "Direct transport is only negotiated for DMs");
+        return false;
+    }
+    let Some(did) = conn.authenticated_did.as_deref() else {
+        fail(
+            "P2P_AUTH_REQUIRED",
+            "Authenticate to negotiate direct transport",
+        );
+        return false;
+    };
+    for key in [tags::P2P_OFFER, tags::P2P_ANSWER] {
+        if tags.get(key).is_some_and(|id| !is_endpoint_id(id)) {
+            fail(
+                "P2P_INVALID_ENDPOINT",
+                "Endpoint ID must be 64 lowercase hex characters",
+            );
+            return false;
+        }
+    }
+    let peer = state
+        .nick_to_session
+        .lock()
+        .get_session(target)
+        .map(str::to_string);
+    let reachable = peer.is_some_and(|sid| {
+        let authenticated = state.session_dids.lock().contains_key(&sid);
+        authenticated && state.sessions.has_cap(&sid, Cap::P2pDm)
+    });
+    if !reachable {
+        fail(
+            "P2P_UNAVAILABLE",
+            "No authenticated user by that nick accepts direct transport",
+        );
+        return false;
+    }
+    if rate_limited(conn, state) {
+        fail(
+            "RATE_LIMITED",
+            "Too many direct transport negotiations, slow down",
+        );
+        return false;
+    }
+
+    if tags.contains_key(tags::P2P_OFFER) || tags.contains_key(tags::P2P_ANSWER) {
+        tags.insert(tags::P2P_DID.to_string(), did.to_string());
+    }
+    true
+}
+
+/// iroh endpoint IDs are ed25519 public keys, written as lowercase hex.
+fn is_endpoint_id(id: &str) -> bool {
+    id.len() == 64
+        && id
+            .chars()
+            .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+}
+
+fn rate_limited(conn: &Connection, state: &SharedState) -> bool {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    let mut ts_map = state.msg_timestamps.lock();
+    let ts = ts_map.entry(format!("p2p:{}", conn.id)).or_default();
+    ts.retain(|&t| now.saturating_sub(t) < WINDOW_MS);
+    if ts.len() >= MAX_PER_WINDOW {
+        return true;
+    }
+    ts.push(now);
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endpoint_ids_are_64_lowercase_hex() {
+        assert!(is_endpoint_id(&"ab".repeat(32)));
+        assert!(!is_endpoint_id(&"AB".repeat(32)));
+        assert!(!is_endpoint_id(&"ab".repeat(31)));
+        assert!(!is_endpoint_id(&format!("{}zz", "ab".repeat(31))));
+    }
+}
@@ -6,10 +6,12 @@
 //! POLICY <channel> INFO                               — Show current policy
 //! POLICY <channel> ACCEPT                             — Accept policy + present credentials
 //! POLICY <channel> CLEAR                              — Remove policy (ops only)
+//! POLICY <channel> HISTORY <visibility>               — Who may read history (ops only)
+//! POLICY <channel> TEST <did>                         — Dry-run a join by <did> (ops only)
 
 use crate::irc::Message;
 use crate::policy::canonical;
-use crate::policy::eval::UserEvidence;
+use crate::policy::eval::{TraceNode, UserEvidence};
 use crate::policy::types::*;
 use crate::server::SharedState;
 use std::collections::{BTreeMap, HashSet};
@@ -46,7 +48,7 @@ pub(super) fn handle_policy(
             "NOTICE",
             vec![
                 nick,
-                "Usage: POLICY <channel> SET|SET-ROLE|VERIFY|INFO|ACCEPT|CLEAR",
+                "Usage: POLICY <channel> SET|SET-ROLE|VERIFY|INFO|ACCEPT|CLEAR|HISTORY|TEST",
             ],
         );
         send_fn(state, session_id, format!("{reply}\r\n"));
@@ -112,10 +114,15 @@ pub(super) fn handle_policy(
                     )
                 }
                 Ok(None) => {
-                    // Create new
+                    // Create new, recording the founder in the signed chain
+                    let founder = state
+                        .channels
+                        .get(&crate::casemap::fold(channel))
+                        .and_then(|ch| ch.founder_did.clone());
                     engine
                         .create_channel_policy(
                             channel,
+                            founder.as_deref(),
                             Requirement::Accept {
                                 hash: rules_hash.clone(),
                             },
@@ -497,9 +504,9 @@ pub(super) fn handle_policy(
                     };
 
                     let reply = Message::from_server(&server_c, "NOTICE", vec![&nick_c, &msg_text]);
-                    let conns = state_c.connections.lock();
+                    let conns = &state_c.connections;
                     if let Some(tx) = conns.get(&session_c) {
-                        let _ = tx.try_send(format!("{reply}\r\n"));
+                        let _ = tx.try_send(format!("{reply}\r\n").into());
                     }
                 });
             }
@@ -701,6 +708,174 @@ pub(super) fn handle_policy(
             }
         }
 
+        "HISTORY" => {
+            // POLICY #channel HISTORY members-since-join|members-full|public
+            // Same effect as MODE +H; also recorded in the policy document
+            // when the channel has one.
+            if !is_channel_op(
+                state,
+                channel,
+                session_id,
+                conn.authenticated_did.as_deref(),
+            ) {
+                let reply = Message::from_server(
+                    server_name,
+                    "482",
+                    vec![nick, channel, "You're not channel operator"],
+                );
+                send_fn(state, session_id, format!("{reply}\r\n"));
+                return;
+            }
+
+            let Some(visibility) = msg.params.get(2).and_then(|v| HistoryVisibility::parse(v))
+            else {
+                let reply = Message::from_server(
+                    server_name,
+                    "NOTICE",
+                    vec![
+                        nick,
+                        "Usage: POLICY <channel> HISTORY members-since-join|members-full|public",
+                    ],
+                );
+                send_fn(state, session_id, format!("{reply}\r\n"));
+                return;
+            };
+
+            let recorded = match engine.get_policy(channel) {
+                Ok(Some(_)) => match engine.set_history_visibility(channel, visibility) {
+                    Ok(policy) => Some(policy),
+                    Err(e) => {
+                        let reply = Message::from_server(
+                            server_name,
+                            "NOTICE",
+                            vec![nick, &format!("Failed: {e}")],
+                        );
+                        send_fn(state, session_id, format!("{reply}\r\n"));
+                        return;
+                    }
+                },
+                _ => None,
+            };
+
+            super::channel::set_history_visibility(
+                conn,
+                &crate::casemap::fold(channel),
+                visibility,
+                state,
+            );
+
+            let notice = match &recorded {
+                Some(policy) => format!(
+                    "History visibility for {channel} is now {} (policy version {})",
+                    visibility.as_str(),
+                    policy.version
+                ),
+                None => format!(
+                    "History visibility for {channel} is now {}",
+                    visibility.as_str()
+                ),
+            };
+            let reply = Message::from_server(server_name, "NOTICE", vec![nick, &notice]);
+            send_fn(state, session_id, format!("{reply}\r\n"));
+
+            if let Some(policy) = recorded {
+                let origin = state.server_iroh_id.lock().clone().unwrap_or_default();
+                s2s_broadcast(
+                    state,
+                    crate::s2s::S2sMessage::PolicySync {
+                        event_id: s2s_next_event_id(state),
+                        channel: channel.to_string(),
+                        policy_json: serde_json::to_string(&policy).ok(),
+                        authority_set_json: None,
+                        origin,
+                    },
+                );
+            }
+        }
+
+        "TEST" => {
+            // POLICY #channel TEST <did> — evaluate without side effects
+            if !is_channel_op(
+                state,
+                channel,
+                session_id,
+                conn.authenticated_did.as_deref(),
+            ) {
+                let reply = Message::from_server(
+                    server_name,
+                    "482",
+                    vec![nick, channel, "You're not channel operator"],
+                );
+                send_fn(state, session_id, format!("{reply}\r\n"));
+                return;
+            }
+            let Some(did) = msg.params.get(2).filter(|d| d.starts_with("did:")) else {
+                let reply = Message::from_server(
+                    server_name,
+                    "NOTICE",
+                    vec![nick, "Usage: POLICY <channel> TEST <did>"],
+                );
+                send_fn(state, session_id, format!("{reply}\r\n"));
+                return;
+            };
+
+            let trace = match engine.dry_run(channel, did) {
+                Ok(Some(trace)) => trace,
+                Ok(None) => {
+                    let reply = Message::from_server(
+                        server_name,
+                        "NOTICE",
+                        vec![nick, &format!("{channel} has no policy (open join)")],
+                    );
+                    send_fn(state, session_id, format!("{reply}\r\n"));
+                    return;
+                }
+                Err(e) => {
+                    let reply = Message::from_server(
+                        server_name,
+                        "NOTICE",
+                        vec![nick, &format!("Policy error: {e}")],
+                    );
+                    send_fn(state, session_id, format!("{reply}\r\n"));
+                    return;
+                }
+            };
+
+            let mut lines = vec![format!(
+                "Policy test for {did} on {channel} (version {}, nothing recorded):",
+                trace.policy_version
+            )];
+            if trace.credentials.is_empty() {
+                lines.push("  Credentials: none".to_string());
+            }
+            for c in &trace.credentials {
+                lines.push(format!(
+                    "  Credential: {} from {} (issued {})",
+                    c.credential_type, c.issuer, c.issued_at
+                ));
+            }
+            lines.push("  Requirements (ACCEPT assumed):".to_string());
+            trace_lines(&trace.requirements, 2, &mut lines);
+            for r in &trace.roles {
+                lines.push(format!("  Role '{}':", r.role));
+                trace_lines(&r.trace, 2, &mut lines);
+            }
+            if let Some(role) = &trace.attested_role {
+                lines.push(format!(
+                    "  Already attested as {role}; a real join reuses that without evaluating"
+                ));
+            }
+            lines.push(match (&trace.role, &trace.refusal) {
+                (Some(role), _) => format!("  Result: would join as {role}"),
+                (None, Some(reason)) => format!("  Result: would be refused: {reason}"),
+                (None, None) => "  Result: would be refused".to_string(),
+            });
+            for line in &lines {
+                let reply = Message::from_server(server_name, "NOTICE", vec![nick, line]);
+                send_fn(state, session_id, format!("{reply}\r\n"));
+            }
+        }
+
         "REQUIRE" => {
             // POLICY #channel REQUIRE <credential_type> issuer=<did> url=<verify_url> label=<Button Text>
             // Adds a credential endpoint to the policy (UX metadata).
@@ -890,7 +1065,7 @@ pub(super) fn handle_policy(
                 "NOTICE",
                 vec![
                     nick,
-                    "Usage: POLICY <channel> SET|SET-ROLE|REQUIRE|VERIFY|INFO|ACCEPT|CLEAR",
+                    "Usage: POLICY <channel> SET|SET-ROLE|REQUIRE|VERIFY|INFO|ACCEPT|CLEAR|HISTORY|TEST",
                 ],
             );
             send_fn(state, session_id, format!("{reply}\r\n"));
@@ -900,8 +1075,7 @@ pub(super) fn handle_policy(
 
 /// Check if session is a channel op.
 fn is_channel_op(state: &SharedState, channel: &str, session_id: &str, did: Option<&str>) -> bool {
-    let channels = state.channels.lock();
-    if let Some(ch) = channels.get(channel) {
+    if let Some(ch) = state.channels.get(channel) {
         if ch.ops.contains(session_id) {
             return true;
         }
@@ -960,6 +1134,19 @@ fn extract_accept_hash(req: &Requirement) -> HashSet<String> {
     hashes
 }
 
+/// Render a dry-run trace as indented `[pass]`/`[FAIL]` lines.
+fn trace_lines(node: &TraceNode, depth: usize, out: &mut Vec<String>) {
+    let mark = if node.satisfied { "[pass]" } else { "[FAIL]" };
+    let mut line = format!("{}{mark} {}", "  ".repeat(depth), node.requirement);
+    if let Some(reason) = node.reason.as_deref().filter(|_| node.children.is_empty()) {
+        line.push_str(&format!(" — {reason}"));
+    }
+    out.push(line);
+    for child in &node.children {
+        trace_lines(child, depth + 1, out);
+    }
+}
+
 /// Human-readable description of a requirement.
 fn describe_requirement(req: &Requirement) -> String {
     match req {
@@ -0,0 +1,121 @@
+//! IRC PREF command — preferences that follow your DID (see
+//! [`crate::prefs`]).
+//!
+//! PREF [LIST]               — Every preference, set or default, then `PREF * END`
+//! PREF GET <key>            — One preference
+//! PREF SET <key> [<value>]  — Change one; without a value, reset it to its default
+//!
+//! Values come back as `:server PREF <key> :<value>`, the same line the
+//! DID's other sessions get when it changes. A value may span several
+//! parameters (`PREF SET muted #a #b`). Failures are
+//! `FAIL PREF <code> [<key>] :<reason>`; guests get `ACCOUNT_REQUIRED`.
+
+use crate::irc::{self, Message};
+use crate::prefs;
+use crate::server::SharedState;
+use std::sync::Arc;
+
+fn fail(server_name: &str, code: &str, key: Option<&str>, reason: &str) -> String {
+    let mut params = vec!["PREF", code];
+    params.extend(key);
+    params.push(reason);
+    let reply = Message::from_server(server_name, "FAIL", params);
+    format!("{reply}\r\n")
+}
+
+pub(super) fn handle_pref(
+    conn: &super::Connection,
+    msg: &Message,
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    send: &impl Fn(&Arc<SharedState>, &str, String),
+) {
+    let Some(did) = conn.authenticated_did.as_deref() else {
+        send(
+            state,
+            session_id,
+            fail(
+                server_name,
+                "ACCOUNT_REQUIRED",
+                None,
+                "Preferences need a signed-in account",
+            ),
+        );
+        return;
+    };
+
+    let sub = msg
+        .params
+        .first()
+        .map(|s| s.to_uppercase())
+        .unwrap_or_else(|| "LIST".to_string());
+    match (sub.as_str(), msg.params.get(1)) {
+        ("LIST", _) => {
+            let all = state.prefs.lock().all(did);
+            for (key, value) in all {
+                send(
+                    state,
+                    session_id,
+                    prefs::line(server_name, key.name, &value),
+                );
+            }
+            send(state, session_id, prefs::line(server_name, "*", "END"));
+        }
+        ("GET", Some(name)) => match prefs::key(name) {
+            Some(key) => {
+                let value = state.prefs.lock().get(did, key);
+                send(
+                    state,
+                    session_id,
+                    prefs::line(server_name, key.name, &value),
+                );
+            }
+            None => send(
+                state,
+                session_id,
+                fail(
+                    server_name,
+                    "UNKNOWN_KEY",
+                    Some(name.as_str()),
+                    "No such preference",
+                ),
+            ),
+        },
+        ("SET", Some(name)) => {
+            let value = (msg.params.len() > 2).then(|| msg.params[2..].join(" "));
+            match prefs::update(state, did, name, value.as_deref(), Some(session_id)) {
+                Ok((key, value)) => {
+                    send(
+                        state,
+                        session_id,
+                        prefs::line(server_name, key.name, &value),
+                    );
+                }
+                Err(e) => send(
+                    state,
+                    session_id,
+                    fail(server_name, e.code(), Some(name.as_str()), &e.to_string()),
+                ),
+            }
+        }
+        ("GET" | "SET", None) => {
+            let reply = Message::from_server(
+                server_name,
+                irc::ERR_NEEDMOREPARAMS,
+                vec![conn.nick_or_star(), "PREF", "Not enough parameters"],
+            );
+            send(state, session_id, format!("{reply}\r\n"));
+        }
+        _ => send(
+            state,
+            session_id,
+            fail(
+                server_name,
+                "SUBCOMMAND_INVALID",
+                None,
+                "Usage: PREF [LIST] | GET <key> | SET <key> [<value>]",
+            ),
+        ),
+    }
+}
@@ -0,0 +1,172 @@
+//! IRC PRIVACY command — control what WHOIS and WHO reveal about you.
+//!
+//! PRIVACY                         — Show your current settings
+//! PRIVACY HIDE <field>[,<field>]  — Hide CHANNELS, IDLE, PLATFORM, or ALL
+//! PRIVACY SHOW <field>[,<field>]  — Reveal them again
+//!
+//! - CHANNELS: WHOIS lists only channels the requester shares with you,
+//!   and WHO on a channel leaves you out unless the requester is in it.
+//! - IDLE: no 317 idle/signon reply.
+//! - PLATFORM: no client software or iroh endpoint lines.
+//!
+//! You, your other devices and server operators always see everything.
+//! Settings are stored in the metadata store under `private/privacy`, so
+//! for an authenticated user they belong to the DID and persist; a
+//! guest's last for the session.
+
+use super::metadata;
+use crate::irc::Message;
+use crate::server::SharedState;
+use std::sync::Arc;
+
+/// Metadata key holding the comma-separated hidden fields.
+pub(super) const PRIVACY_KEY: &str = "private/privacy";
+
+/// What a user hides from WHOIS and WHO.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(super) struct Privacy {
+    pub hide_channels: bool,
+    pub hide_idle: bool,
+    pub hide_platform: bool,
+}
+
+impl Privacy {
+    /// Parse the stored value; unknown fields are ignored.
+    fn parse(value: &str) -> Self {
+        let mut privacy = Self::default();
+        for field in value.split(',') {
+            privacy.set(field.trim(), true);
+        }
+        privacy
+    }
+
+    fn to_value(self) -> String {
+        self.hidden().join(",")
+    }
+
+    fn hidden(self) -> Vec<&'static str> {
+        let mut fields = Vec::new();
+        if self.hide_channels {
+            fields.push("channels");
+        }
+        if self.hide_idle {
+            fields.push("idle");
+        }
+        if self.hide_platform {
+            fields.push("platform");
+        }
+        fields
+    }
+
+    /// Set `field` (case-insensitive); returns false for an unknown one.
+    fn set(&mut self, field: &str, hide: bool) -> bool {
+        match field.to_ascii_lowercase().as_str() {
+            "channels" => self.hide_channels = hide,
+            "idle" => self.hide_idle = hide,
+            "platform" => self.hide_platform = hide,
+            "all" => {
+                self.hide_channels = hide;
+                self.hide_idle = hide;
+                self.hide_platform = hide;
+            }
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// The privacy settings of whoever owns `session_id`.
+pub(super) fn privacy_of(state: &SharedState, session_id: &str) -> Privacy {
+    metadata::own_key(state, session_id, PRIVACY_KEY)
+        .map(|v| Privacy::parse(&v))
+        .unwrap_or_default()
+}
+
+/// Whether `viewer` bypasses `target`'s privacy settings: the same
+/// session, another session of the same DID, or a server operator.
+pub(super) fn sees_everything(state: &SharedState, viewer: &str, target: &str) -> bool {
+    if viewer == target || state.server_opers.lock().contains(viewer) {
+        return true;
+    }
+    let dids = state.session_dids.lock();
+    matches!((dids.get(viewer), dids.get(target)), (Some(a), Some(b)) if a == b)
+}
+
+pub(super) fn handle_privacy(
+    conn: &super::Connection,
+    msg: &Message,
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    send_fn: &impl Fn(&Arc<SharedState>, &str, String),
+) {
+    let nick = conn.nick_or_star();
+    let notice = |text: &str| {
+        let reply = Message::from_server(server_name, "NOTICE", vec![nick, text]);
+        send_fn(state, session_id, format!("{reply}\r\n"));
+    };
+    let usage = "Usage: PRIVACY [HIDE|SHOW <channels|idle|platform|all>[,...]]";
+
+    let mut privacy = privacy_of(state, session_id);
+    let hide = match msg.params.first().map(|s| s.to_uppercase()).as_deref() {
+        None => {
+            let hidden = privacy.hidden();
+            if hidden.is_empty() {
+                notice("PRIVACY: WHOIS shows everything");
+            } else {
+                notice(&format!(
+                    "PRIVACY: hidden from WHOIS/WHO: {}",
+                    hidden.join(", ")
+                ));
+            }
+            return;
+        }
+        Some("HIDE") => true,
+        Some("SHOW") => false,
+        Some(_) => {
+            notice(usage);
+            return;
+        }
+    };
+    let Some(fields) = msg.params.get(1) else {
+        notice(usage);
+        return;
+    };
+    for field in fields.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+        if !privacy.set(field, hide) {
+            notice(&format!("PRIVACY: unknown field {field}. {usage}"));
+            return;
+        }
+    }
+
+    let value = privacy.to_value();
+    let value = (!value.is_empty()).then_some(value.as_str());
+    metadata::set_own_key(state, conn, session_id, PRIVACY_KEY, value);
+    tracing::info!(session = %session_id, hidden = value.unwrap_or(""), "PRIVACY updated");
+
+    match value {
+        Some(v) => notice(&format!(
+            "PRIVACY: hidden from WHOIS/WHO: {}",
+            v.replace(',', ", ")
+        )),
+        None => notice("PRIVACY: WHOIS shows everything"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_set_round_trip() {
+        let mut p = Privacy::parse("idle, bogus,channels");
+        assert!(p.hide_idle && p.hide_channels && !p.hide_platform);
+        assert_eq!(p.to_value(), "channels,idle");
+        assert!(p.set("ALL", true));
+        assert_eq!(p.to_value(), "channels,idle,platform");
+        assert!(p.set("channels", false));
+        assert_eq!(p.to_value(), "idle,platform");
+        assert!(!p.set("host", true));
+        assert_eq!(Privacy::parse(""), Privacy::default());
+    }
+}
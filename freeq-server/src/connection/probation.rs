@@ -0,0 +1,176 @@
+//! Probation for brand-new guests.
+//!
+//! Most abuse comes from connections that have only just arrived and
+//! haven't signed in. With `--guest-probation-secs N`, a guest spends its
+//! first N seconds after connecting on probation. Until then it:
+//!
+//! - may only use the commands in [`ALLOWED`]; anything else gets
+//!   `FAIL <command> GUEST_PROBATION`;
+//! - may only send direct messages to server operators and the
+//!   operators of channels it's in;
+//! - may be in at most [`MAX_CHANNELS`] channels;
+//! - gets [`RATE_BURST`] commands refilled at [`RATE_PER_SEC`] a second,
+//!   instead of 10 and 10.
+//!
+//! Probation ends as soon as the connection authenticates (SASL or
+//! LOGIN) or becomes a server operator, or when the period runs out.
+//! A period of 0, the default, turns it off.
+
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use super::Connection;
+use crate::config::ServerConfig;
+use crate::irc::Message;
+use crate::server::SharedState;
+
+/// Most channels a guest on probation can be in.
+pub(crate) const MAX_CHANNELS: usize = 3;
+
+/// Command burst for a guest on probation.
+pub(crate) const RATE_BURST: f64 = 5.0;
+
+/// Commands per second a guest on probation gets back.
+pub(crate) const RATE_PER_SEC: f64 = 2.0;
+
+/// Commands a registered guest on probation may use.
+const ALLOWED: &[&str] = &[
+    "CAP",
+    "AUTHENTICATE",
+    "LOGIN",
+    "OPER",
+    "NICK",
+    "PING",
+    "PONG",
+    "QUIT",
+    "JOIN",
+    "PART",
+    "PRIVMSG",
+    "NOTICE",
+    "TAGMSG",
+    "MODE",
+    "TOPIC",
+    "NAMES",
+    "WHO",
+    "WHOIS",
+    "LIST",
+    "AWAY",
+    "CHATHISTORY",
+    "MOTD",
+    "VERSION",
+    "TIME",
+    "LUSERS",
+    "LANGUAGE",
+    "USERHOST",
+    "ISON",
+    "ADMIN",
+    "INFO",
+];
+
+/// When probation ends for a connection made now; `None` when it's off.
+pub(crate) fn deadline(config: &ServerConfig) -> Option<Instant> {
+    (config.guest_probation_secs > 0)
+        .then(|| Instant::now() + Duration::from_secs(config.guest_probation_secs))
+}
+
+/// Whether a guest on probation may use `command`.
+pub(crate) fn allows(command: &str) -> bool {
+    ALLOWED.contains(&command)
+}
+
+/// Whether a guest on probation, `session_id`, may send a direct message
+/// to `nick`: only server operators and ops of a channel they share.
+pub(crate) fn may_message(state: &SharedState, session_id: &str, nick: &str) -> bool {
+    let Some(target) = state
+        .nick_to_session
+        .lock()
+        .get_session(nick)
+        .map(str::to_string)
+    else {
+        return false;
+    };
+    state.server_opers.lock().contains(&target)
+        || state
+            .channels
+            .any(|_, ch| ch.members.contains(session_id) && ch.ops.contains(&target))
+}
+
+/// Whole seconds of probation `conn` has left, rounded up.
+fn remaining_secs(conn: &Connection) -> u64 {
+    conn.probation_until.map_or(0, |until| {
+        let left = until.saturating_duration_since(Instant::now());
+        left.as_secs() + u64::from(left.subsec_nanos() > 0)
+    })
+}
+
+/// `FAIL <command> GUEST_PROBATION` for something a guest on probation
+/// can't do yet.
+pub(crate) fn fail(conn: &Connection, server_name: &str, command: &str, what: &str) -> String {
+    let reason = format!(
+        "New guests can't {what} yet; sign in or wait {}s",
+        remaining_secs(conn)
+    );
+    let reply = Message::from_server(
+        server_name,
+        "FAIL",
+        vec![command, "GUEST_PROBATION", &reason],
+    );
+    format!("{reply}\r\n")
+}
+
+/// Notice telling a guest that has just registered what probation means.
+pub(crate) fn welcome_notice(conn: &Connection, server_name: &str) -> String {
+    let text = format!(
+        "New guests are on probation for {}s: fewer commands, direct messages to operators \
+         only, and at most {MAX_CHANNELS} channels. Sign in to lift it now.",
+        remaining_secs(conn)
+    );
+    let notice = Message::from_server(server_name, "NOTICE", vec![conn.nick_or_star(), &text]);
+    format!("{notice}\r\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn off_by_default() {
+        assert!(deadline(&ServerConfig::default()).is_none());
+        let config = ServerConfig {
+            guest_probation_secs: 60,
+            ..Default::default()
+        };
+        assert!(deadline(&config).is_some_and(|d| d > Instant::now()));
+    }
+
+    #[test]
+    fn probation_lifts_on_auth_oper_or_time() {
+        let mut conn = Connection::new("s1".to_string());
+        assert!(!conn.on_probation());
+
+        conn.probation_until = Some(Instant::now() + Duration::from_secs(60));
+        assert!(conn.on_probation());
+        assert_eq!(remaining_secs(&conn), 60);
+
+        conn.is_oper = true;
+        assert!(!conn.on_probation());
+        conn.is_oper = false;
+        conn.authenticated_did = Some("did:plc:alice".to_string());
+        assert!(!conn.on_probation());
+        conn.authenticated_did = None;
+
+        conn.probation_until = Some(Instant::now());
+        assert!(!conn.on_probation());
+    }
+
+    #[test]
+    fn only_everyday_commands_are_allowed() {
+        for command in ["PRIVMSG", "JOIN", "AUTHENTICATE", "QUIT"] {
+            assert!(allows(command), "{command}");
+        }
+        for command in ["INVITE", "KICK", "BATCH", "METADATA", "SEARCH", "AGENT"] {
+            assert!(!allows(command), "{command}");
+        }
+    }
+}
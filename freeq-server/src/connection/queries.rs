@@ -3,8 +3,11 @@
 
 use super::Connection;
 use super::helpers::normalize_channel;
+use super::privacy_cmd::{privacy_of, sees_everything};
 use crate::irc::{self, Message};
-use crate::server::SharedState;
+use crate::server::{ChannelRole, SharedState, WireLine};
+use crate::session::Cap;
+use std::collections::HashSet;
 use std::sync::Arc;
 
 pub(super) fn handle_whois(
@@ -26,12 +29,9 @@ pub(super) fn handle_whois(
 
     let Some(target_session) = target_session else {
         // Check if this is a remote user (from S2S)
-        let remote_info: Option<crate::server::RemoteMember> = {
-            let channels = state.channels.lock();
-            channels
-                .values()
-                .find_map(|ch| ch.remote_members.get(target_nick).cloned())
-        };
+        let remote_info: Option<crate::server::RemoteMember> = state
+            .channels
+            .find_map(|_, ch| ch.remote_members.get(target_nick).cloned());
 
         if let Some(rm) = remote_info {
             // Remote user — show what we know
@@ -86,24 +86,21 @@ pub(super) fn handle_whois(
                 send(state, session_id, format!("{did_line}\r\n"));
             }
 
-            // Show channels they're in
-            let user_channels: Vec<String> = {
-                let channels = state.channels.lock();
-                channels
-                    .iter()
-                    .filter(|(_, ch)| ch.remote_members.contains_key(target_nick))
-                    .map(|(name, ch)| {
-                        let is_op = rm.did.as_ref().is_some_and(|d| {
-                            ch.founder_did.as_deref() == Some(d) || ch.did_ops.contains(d)
-                        });
-                        if is_op {
-                            format!("@{name}")
-                        } else {
-                            name.clone()
-                        }
-                    })
-                    .collect()
-            };
+            // Show channels they're in, but +s ones only to members and opers
+            let is_oper = state.server_opers.lock().contains(session_id);
+            let user_channels: Vec<String> = state.channels.filter_map(|name, ch| {
+                if !ch.remote_members.contains_key(target_nick) {
+                    return None;
+                }
+                if ch.hidden_from(session_id) && !is_oper {
+                    return None;
+                }
+                let prefix = ch.remote_rank(rm).prefix();
+                Some(format!(
+                    "{}{name}",
+                    prefix.map(String::from).unwrap_or_default()
+                ))
+            });
             if !user_channels.is_empty() {
                 let channels_line = Message::from_server(
                     server_name,
@@ -125,7 +122,7 @@ pub(super) fn handle_whois(
                 .spawned_agents
                 .lock()
                 .values()
-                .find(|sa| sa.nick.eq_ignore_ascii_case(target_nick))
+                .find(|sa| crate::casemap::eq(&sa.nick, target_nick))
                 .cloned();
 
             if let Some(sa) = spawned {
@@ -224,6 +221,47 @@ pub(super) fn handle_whois(
     );
     send(state, session_id, format!("{whoisserver}\r\n"));
 
+    let privacy = privacy_of(state, &target_session);
+    let full_view = sees_everything(state, session_id, &target_session);
+
+    // 319 RPL_WHOISCHANNELS — with channels hidden, only shared ones; +s
+    // channels only to members and opers
+    let is_oper = state.server_opers.lock().contains(session_id);
+    let mut user_channels: Vec<String> = state.channels.filter_map(|name, ch| {
+        if !ch.members.contains(&target_session) {
+            return None;
+        }
+        if ch.hidden_from(session_id) && !is_oper {
+            return None;
+        }
+        if !(full_view || !privacy.hide_channels || ch.members.contains(session_id)) {
+            return None;
+        }
+        Some(format!("{}{name}", ch.prefix(&target_session, false)))
+    });
+    user_channels.sort();
+    if !user_channels.is_empty() {
+        let channels_line = Message::from_server(
+            server_name,
+            irc::RPL_WHOISCHANNELS,
+            vec![my_nick, target_nick, &user_channels.join(" ")],
+        );
+        send(state, session_id, format!("{channels_line}\r\n"));
+    }
+
+    let activity = state.session_activity.lock().get(&target_session).cloned();
+
+    // 338 RPL_WHOISACTUALLY — the real IP, for the user and opers only
+    if full_view && let Some(ip) = activity.as_ref().and_then(|a| a.ip) {
+        let ip = ip.to_string();
+        let actually = Message::from_server(
+            server_name,
+            irc::RPL_WHOISACTUALLY,
+            vec![my_nick, target_nick, &ip, "Is actually using host"],
+        );
+        send(state, session_id, format!("{actually}\r\n"));
+    }
+
     // 330 RPL_WHOISACCOUNT — show DID if authenticated
     let did = state.session_dids.lock().get(&target_session).cloned();
 
@@ -254,17 +292,37 @@ pub(super) fn handle_whois(
         }
     }
 
-    // Show iroh endpoint ID if connected via iroh
+    // Show iroh endpoint ID if connected via iroh, marked verified when
+    // the endpoint is bound to the DID the session is logged in as.
     let iroh_id = state.session_iroh_ids.lock().get(&target_session).cloned();
-    if let Some(iroh_id) = iroh_id {
+    if let Some(iroh_id) = iroh_id
+        && (full_view || !privacy.hide_platform)
+    {
+        let session_did = state.session_dids.lock().get(&target_session).cloned();
+        let verified = session_did.is_some() && state.iroh_endpoint_did(&iroh_id) == session_did;
+        let text = if verified {
+            format!("iroh endpoint: {iroh_id} (verified)")
+        } else {
+            format!("iroh endpoint: {iroh_id}")
+        };
         let iroh_notice = Message::from_server(
             server_name,
             "672", // Custom numeric for iroh info
-            vec![my_nick, target_nick, &format!("iroh endpoint: {iroh_id}")],
+            vec![my_nick, target_nick, &text],
         );
         send(state, session_id, format!("{iroh_notice}\r\n"));
     }
 
+    // Bots (user mode +B, or a registered agent) are marked as such
+    if state.is_bot(&target_session) {
+        let bot_line = Message::from_server(
+            server_name,
+            irc::RPL_WHOISBOT,
+            vec![my_nick, target_nick, "is a bot"],
+        );
+        send(state, session_id, format!("{bot_line}\r\n"));
+    }
+
     // Show actor class if not human (673 = custom RPL_ACTORCLASS)
     {
         let actor_class = state
@@ -303,11 +361,28 @@ pub(super) fn handle_whois(
         }
     }
 
+    if conn.cap_whois_extended
+        && let Some(ref did) = did
+    {
+        send_whois_extended(
+            conn,
+            target_nick,
+            &target_session,
+            did,
+            state,
+            server_name,
+            session_id,
+            send,
+        );
+    }
+
     // Show client software
     // Look up the target connection to get client_info
     // We need to find the connection object — it's not in shared state directly,
     // so we'll store client_info per-session.
-    if let Some(ref client) = state.session_client_info.lock().get(&target_session) {
+    if (full_view || !privacy.hide_platform)
+        && let Some(ref client) = state.session_client_info.lock().get(&target_session)
+    {
         let client_line = Message::from_server(
             server_name,
             "671", // RPL_WHOISSECURE (informational)
@@ -326,6 +401,26 @@ pub(super) fn handle_whois(
         send(state, session_id, format!("{away}\r\n"));
     }
 
+    // 317 RPL_WHOISIDLE
+    if let Some(activity) = activity
+        && (full_view || !privacy.hide_idle)
+    {
+        let idle = activity.last_message.elapsed().as_secs().to_string();
+        let signon = activity.signon.to_string();
+        let idle_line = Message::from_server(
+            server_name,
+            irc::RPL_WHOISIDLE,
+            vec![
+                my_nick,
+                target_nick,
+                &idle,
+                &signon,
+                "seconds idle, signon time",
+            ],
+        );
+        send(state, session_id, format!("{idle_line}\r\n"));
+    }
+
     // 318 RPL_ENDOFWHOIS
     let end = Message::from_server(
         server_name,
@@ -335,57 +430,272 @@ pub(super) fn handle_whois(
     send(state, session_id, format!("{end}\r\n"));
 }
 
+/// freeq WHOIS extensions for an authenticated target:
+///
+/// - 674: verifiable credential *types* the target holds. Claims are never
+///   shown — "github_membership", not which org.
+/// - 675: the target's founder / op / policy role in channels the
+///   requester shares with them. Channels the requester isn't in stay private.
+/// - 676: fingerprint of the target's published E2EE identity key, when
+///   both sides have published pre-key bundles. The server can't see local
+///   safety-number verification; clients compare this against the key
+///   they verified to tell whether that verification still holds.
+fn send_whois_extended(
+    conn: &Connection,
+    target_nick: &str,
+    target_session: &str,
+    did: &str,
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    send: &impl Fn(&Arc<SharedState>, &str, String),
+) {
+    let my_nick = conn.nick_or_star();
+
+    // Credential types
+    if let Some(ref engine) = state.policy_engine {
+        let mut types: Vec<String> = engine
+            .store()
+            .get_credentials(did)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|c| c.credential_type)
+            .collect();
+        types.sort();
+        types.dedup();
+        if !types.is_empty() {
+            let line = Message::from_server(
+                server_name,
+                irc::RPL_WHOISCREDENTIALS,
+                vec![
+                    my_nick,
+                    target_nick,
+                    &format!("credentials: {}", types.join(" ")),
+                ],
+            );
+            send(state, session_id, format!("{line}\r\n"));
+        }
+    }
+
+    // Roles in shared channels
+    let mut shared: Vec<(String, Vec<String>)> = state.channels.filter_map(|name, ch| {
+        if !(ch.members.contains(session_id) && ch.members.contains(target_session)) {
+            return None;
+        }
+        let mut roles = Vec::new();
+        if ch.founder_did.as_deref() == Some(did) {
+            roles.push("founder".to_string());
+        }
+        if ch.did_ops.contains(did) {
+            roles.push("op".to_string());
+        }
+        Some((name.to_string(), roles))
+    });
+    if let Some(ref engine) = state.policy_engine {
+        for (name, roles) in &mut shared {
+            if let Ok(Some(role)) = engine.get_member_role(name, did) {
+                roles.push(format!("policy:{role}"));
+            }
+        }
+    }
+    shared.retain(|(_, roles)| !roles.is_empty());
+    shared.sort();
+    if !shared.is_empty() {
+        let entries: Vec<String> = shared
+            .iter()
+            .map(|(name, roles)| format!("{name}={}", roles.join(",")))
+            .collect();
+        let line = Message::from_server(
+            server_name,
+            irc::RPL_WHOISROLES,
+            vec![
+                my_nick,
+                target_nick,
+                &format!("roles: {}", entries.join(" ")),
+            ],
+        );
+        send(state, session_id, format!("{line}\r\n"));
+    }
+
+    // E2EE identity key fingerprint
+    if let Some(ref my_did) = conn.authenticated_did
+        && my_did != did
+        && prekey_bundle(state, my_did).is_some()
+        && let Some(fp) = prekey_bundle(state, did)
+            .as_ref()
+            .and_then(identity_key_fingerprint)
+    {
+        let line = Message::from_server(
+            server_name,
+            irc::RPL_WHOISE2EE,
+            vec![my_nick, target_nick, &format!("e2ee identity key: {fp}")],
+        );
+        send(state, session_id, format!("{line}\r\n"));
+    }
+}
+
+fn prekey_bundle(state: &SharedState, did: &str) -> Option<serde_json::Value> {
+    let cached = state.prekey_bundles.lock().get(did).cloned();
+    cached.or_else(|| state.with_db(|db| db.get_prekey_bundle(did)).flatten())
+}
+
+/// First 80 bits of SHA-256 over the bundle's identity key, as four-hex
+/// groups. Long enough to compare by eye, short enough for one line.
+fn identity_key_fingerprint(bundle: &serde_json::Value) -> Option<String> {
+    use base64::Engine;
+    use sha2::{Digest, Sha256};
+    let key = bundle.get("identity_key")?.as_str()?;
+    let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(key)
+        .ok()?;
+    let hash = Sha256::digest(&bytes);
+    Some(
+        hash[..10]
+            .chunks(2)
+            .map(|c| format!("{:02x}{:02x}", c[0], c[1]))
+            .collect::<Vec<_>>()
+            .join(" "),
+    )
+}
+
+/// WHOX field selection: `WHO <mask> %<fields>[,<token>]`.
+struct Whox {
+    fields: String,
+    token: String,
+}
+
+impl Whox {
+    fn parse(options: Option<&str>) -> Option<Self> {
+        let (_, spec) = options?.split_once('%')?;
+        let (fields, token) = spec.split_once(',').unwrap_or((spec, ""));
+        Some(Self {
+            fields: fields.to_string(),
+            token: token.to_string(),
+        })
+    }
+}
+
+/// One WHO row, formatted as RPL_WHOREPLY (352) or, for WHOX, as
+/// RPL_WHOSPCRPL (354) with the requested fields in canonical order.
+fn who_reply(
+    server_name: &str,
+    nick: &str,
+    channel: &str,
+    target: &str,
+    flags: &str,
+    did: Option<&str>,
+    whox: Option<&Whox>,
+) -> Message {
+    let Some(whox) = whox else {
+        // Include DID in realname if authenticated
+        let realname = match did {
+            Some(did) => format!("0 {did}"),
+            None => "0 IRC User".to_string(),
+        };
+        return Message::from_server(
+            server_name,
+            irc::RPL_WHOREPLY,
+            vec![
+                nick,
+                channel,
+                "~u",
+                "host",
+                server_name,
+                target,
+                flags,
+                &realname,
+            ],
+        );
+    };
+    let mut params = vec![nick];
+    for field in "tcuihsnfdlaor".chars() {
+        if !whox.fields.contains(field) {
+            continue;
+        }
+        params.push(match field {
+            't' => whox.token.as_str(),
+            'c' => channel,
+            'u' => "~u",
+            'i' => "255.255.255.255",
+            'h' => "host",
+            's' => server_name,
+            'n' => target,
+            'f' => flags,
+            'd' | 'l' => "0",
+            'a' => did.unwrap_or("0"),
+            'o' => "n/a",
+            _ => did.unwrap_or("IRC User"),
+        });
+    }
+    Message::from_server(server_name, irc::RPL_WHOSPCRPL, params)
+}
+
 pub(super) fn handle_who(
     conn: &Connection,
     target: &str,
+    options: Option<&str>,
     state: &Arc<SharedState>,
     server_name: &str,
     session_id: &str,
     send: &impl Fn(&Arc<SharedState>, &str, String),
 ) {
     let nick = conn.nick_or_star();
+    let whox = Whox::parse(options);
+    let multi_prefix = state.sessions.has_cap(session_id, Cap::MultiPrefix);
 
     if target.starts_with('#') || target.starts_with('&') {
         let channel = normalize_channel(target);
-        let channels = state.channels.lock();
-        if let Some(ch) = channels.get(&channel) {
+        // Members hiding their channels are only listed to people who can
+        // already see them in the channel.
+        let hidden: HashSet<String> = {
+            let outside: Vec<String> = state
+                .channels
+                .get(&channel)
+                .filter(|ch| !ch.members.contains(session_id))
+                .map(|ch| ch.members.iter().cloned().collect())
+                .unwrap_or_default();
+            outside
+                .into_iter()
+                .filter(|s| {
+                    privacy_of(state, s).hide_channels && !sees_everything(state, session_id, s)
+                })
+                .collect()
+        };
+        let mut replies = Vec::new();
+        // A +s channel has no visible members to non-members.
+        if let Some(ch) = state
+            .channels
+            .get(&channel)
+            .filter(|ch| !ch.hidden_from(session_id))
+        {
             let n2s = state.nick_to_session.lock();
             let away = state.session_away.lock();
+            // In +u, ordinary members only see voiced-and-above members.
+            let sees_all = ch.sees_all_members(session_id);
 
             for session in &ch.members {
+                if hidden.contains(session) {
+                    continue;
+                }
+                if !sees_all && session != session_id && ch.rank(session) < ChannelRole::Voice {
+                    continue;
+                }
                 if let Some(member_nick) = n2s.get_nick(session) {
-                    let user = "~u";
-                    let host = "host";
                     let away_flag = if away.contains_key(session) { "G" } else { "H" };
-                    let op_flag = if ch.ops.contains(session) {
-                        "@"
-                    } else if ch.voiced.contains(session) {
-                        "+"
-                    } else {
-                        ""
-                    };
-                    let flags = format!("{away_flag}{op_flag}");
-                    // Include DID in realname if authenticated
+                    let bot_flag = if state.is_bot(session) { "B" } else { "" };
+                    let flags =
+                        format!("{away_flag}{bot_flag}{}", ch.prefix(session, multi_prefix));
                     let did_info = state.session_dids.lock().get(session).cloned();
-                    let realname = match did_info {
-                        Some(did) => format!("0 {did}"),
-                        None => "0 IRC User".to_string(),
-                    };
-                    let reply = Message::from_server(
+                    let reply = who_reply(
                         server_name,
-                        irc::RPL_WHOREPLY,
-                        vec![
-                            nick,
-                            &channel,
-                            user,
-                            host,
-                            server_name,
-                            member_nick,
-                            &flags,
-                            &realname,
-                        ],
+                        nick,
+                        &channel,
+                        member_nick,
+                        &flags,
+                        did_info.as_deref(),
+                        whox.as_ref(),
                     );
-                    send(state, session_id, format!("{reply}\r\n"));
+                    replies.push(format!("{reply}\r\n"));
                 }
             }
         }
@@ -394,7 +704,8 @@ pub(super) fn handle_who(
             irc::RPL_ENDOFWHO,
             vec![nick, &channel, "End of /WHO list"],
         );
-        send(state, session_id, format!("{end}\r\n"));
+        // A big channel's WHO is paced like its NAMES.
+        super::names::send_paced(state, session_id, replies, vec![format!("{end}\r\n")]);
     } else {
         // WHO for a nick
         let target_session = state
@@ -405,24 +716,16 @@ pub(super) fn handle_who(
         if let Some(ref session) = target_session {
             let away = state.session_away.lock();
             let away_flag = if away.contains_key(session) { "G" } else { "H" };
+            let bot_flag = if state.is_bot(session) { "B" } else { "" };
             let did_info = state.session_dids.lock().get(session).cloned();
-            let realname = match did_info {
-                Some(did) => format!("0 {did}"),
-                None => "0 IRC User".to_string(),
-            };
-            let reply = Message::from_server(
+            let reply = who_reply(
                 server_name,
-                irc::RPL_WHOREPLY,
-                vec![
-                    nick,
-                    "*",
-                    "~u",
-                    "host",
-                    server_name,
-                    target,
-                    away_flag,
-                    &realname,
-                ],
+                nick,
+                "*",
+                target,
+                &format!("{away_flag}{bot_flag}"),
+                did_info.as_deref(),
+                whox.as_ref(),
             );
             send(state, session_id, format!("{reply}\r\n"));
         }
@@ -445,18 +748,17 @@ pub(super) fn handle_lusers(
     send: &impl Fn(&Arc<SharedState>, &str, String),
 ) {
     let nick = conn.nick_or_star();
-    let user_count = state.connections.lock().len();
-    let channel_count = state.channels.lock().len();
+    let user_count = state.connections.len();
+    let channel_count = state.channels.len();
 
     // Count remote users across all channels (deduplicated)
     let remote_count = {
-        let channels = state.channels.lock();
         let mut remote_nicks = std::collections::HashSet::new();
-        for ch in channels.values() {
+        state.channels.for_each(|_, ch| {
             for nick in ch.remote_members.keys() {
                 remote_nicks.insert(nick.clone());
             }
-        }
+        });
         remote_nicks.len()
     };
 
@@ -538,35 +840,32 @@ fn broadcast_away(
     hostmask: &str,
     away_msg: Option<&str>,
 ) {
-    let away_caps = state.cap_away_notify.lock();
-    if away_caps.is_empty() {
+    if !state.sessions.any_with_cap(Cap::AwayNotify) {
         return;
     }
 
     // Build the AWAY line: ":nick!user@host AWAY :reason" or ":nick!user@host AWAY"
-    let line = match away_msg {
+    let line = WireLine::from(match away_msg {
         Some(msg) => format!(":{hostmask} AWAY :{msg}\r\n"),
         None => format!(":{hostmask} AWAY\r\n"),
-    };
+    });
 
     // Find all channels this user is in, collect their members
     let mut targets = std::collections::HashSet::new();
-    let channels = state.channels.lock();
-    for ch in channels.values() {
+    state.channels.for_each(|_, ch| {
         if ch.members.contains(session_id) {
             for member in &ch.members {
-                if member != session_id && away_caps.contains(member) {
+                if member != session_id && state.sessions.has_cap(member, Cap::AwayNotify) {
                     targets.insert(member.clone());
                 }
             }
         }
-    }
-    drop(channels);
+    });
 
-    let conns = state.connections.lock();
+    let conns = &state.connections;
     for sid in &targets {
         if let Some(tx) = conns.get(sid) {
-            let _ = tx.try_send(line.clone());
+            tx.send_presence(session_id, line.clone());
         }
     }
 }
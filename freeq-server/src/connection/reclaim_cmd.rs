@@ -0,0 +1,228 @@
+//! RECLAIM command — a founder proves control of their DID to get the
+//! channel back.
+//!
+//! RECLAIM <channel>             — Ask for a challenge
+//! RECLAIM <channel> <response>  — Answer it; restores founder status
+//!
+//! The server replies `RECLAIM <channel> CHALLENGE <challenge>`, issued
+//! and answered exactly like SASL ATPROTO-CHALLENGE (any of its methods:
+//! a signature with a key from the DID document, or a PDS session), so
+//! the proof is fresh and bound to this connection. It must come from the
+//! channel's recorded founder DID — the local record, or the cluster's if
+//! this server has lost it — and the connection must be signed in as
+//! that DID. On success the founder record and the DID's persistent ops
+//! are restored, the connection gets founder (~) and ops in the channel
+//! if it is a member (otherwise on its next JOIN), and the server answers
+//! `RECLAIM <channel> SUCCESS`.
+//!
+//! Every proof, good or bad, goes to the channel's governance log
+//! (`founder_reclaim` / `founder_reclaim_failed`), and each source (peer
+//! IP, else session) gets one proof per channel per
+//! `--reclaim-cooldown-secs`. Failures are `FAIL RECLAIM <code> <channel>`.
+
+use super::Connection;
+use super::helpers::{broadcast_to_channel, normalize_channel, s2s_broadcast_mode};
+use crate::irc::Message;
+use crate::sasl;
+use crate::server::{ChallengeRejection, ChannelRole, SharedState};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+fn fail(server_name: &str, code: &str, channel: &str, reason: &str) -> String {
+    let reply = Message::from_server(server_name, "FAIL", vec!["RECLAIM", code, channel, reason]);
+    format!("{reply}\r\n")
+}
+
+/// The DID recorded as founder of `channel`: this server's record, else
+/// the cluster's.
+async fn recorded_founder(state: &SharedState, channel: &str) -> Option<String> {
+    let local = state
+        .channels
+        .get(channel)
+        .and_then(|ch| ch.founder_did.clone());
+    match local {
+        Some(did) => Some(did),
+        None => state.cluster_doc.founder(channel).await,
+    }
+}
+
+/// Cooldown key for proofs about `channel` from this connection.
+fn attempt_key(conn: &Connection, session_id: &str, channel: &str) -> String {
+    let source = conn
+        .peer_ip
+        .map_or_else(|| session_id.to_string(), |ip| ip.to_string());
+    format!("{channel} {source}")
+}
+
+/// Seconds left before `key` may try again, if it's cooling down.
+fn cooling_down(state: &SharedState, key: &str) -> Option<u64> {
+    let cooldown = Duration::from_secs(state.config.reclaim_cooldown_secs);
+    let last = *state.reclaim_attempts.lock().get(key)?;
+    let left = cooldown.checked_sub(last.elapsed())?;
+    Some(left.as_secs().max(1))
+}
+
+fn record_attempt(state: &SharedState, key: String) {
+    let cooldown = Duration::from_secs(state.config.reclaim_cooldown_secs);
+    let mut attempts = state.reclaim_attempts.lock();
+    attempts.retain(|_, at| at.elapsed() < cooldown);
+    attempts.insert(key, Instant::now());
+}
+
+pub(super) async fn handle_reclaim(
+    conn: &Connection,
+    msg: &Message,
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    send: &impl Fn(&Arc<SharedState>, &str, String),
+) {
+    let Some(channel) = msg.params.first().map(|c| normalize_channel(c)) else {
+        let reply = fail(
+            server_name,
+            "NEED_MORE_PARAMS",
+            "*",
+            "Usage: RECLAIM <channel>",
+        );
+        send(state, session_id, reply);
+        return;
+    };
+    let reject = |code: &str, reason: &str| {
+        send(state, session_id, fail(server_name, code, &channel, reason));
+    };
+
+    let Some(did) = conn.authenticated_did.clone() else {
+        reject("ACCOUNT_REQUIRED", "Sign in as the channel's founder first");
+        return;
+    };
+    if !state.channels.contains_key(&channel) {
+        reject("NO_SUCH_CHANNEL", "No such channel");
+        return;
+    }
+    let Some(founder) = recorded_founder(state, &channel).await else {
+        reject("NO_FOUNDER", "This channel has no recorded founder");
+        return;
+    };
+    if founder != did {
+        reject("NOT_FOUNDER", "You are not this channel's recorded founder");
+        return;
+    }
+    let key = attempt_key(conn, session_id, &channel);
+    if let Some(secs) = cooling_down(state, &key) {
+        reject(
+            "RATE_LIMITED",
+            &format!("Too many reclaim attempts; try again in {secs}s"),
+        );
+        return;
+    }
+
+    let Some(encoded) = msg.params.get(1) else {
+        let challenge = state.issue_sasl_challenge(session_id, conn.channel_binding().as_deref());
+        let reply = Message::from_server(
+            server_name,
+            "RECLAIM",
+            vec![&channel, "CHALLENGE", &challenge],
+        );
+        send(state, session_id, format!("{reply}\r\n"));
+        return;
+    };
+
+    record_attempt(state, key);
+    let response = sasl::decode_response(encoded);
+    let reported = response.as_ref().and_then(|r| r.binding.as_deref());
+    let proof = match (
+        &response,
+        state.take_sasl_challenge(session_id, reported, conn.channel_binding().as_deref()),
+    ) {
+        (None, _) => Err("Malformed response".to_string()),
+        (_, Err(ChallengeRejection::Missing)) => {
+            Err("No pending challenge, or it expired".to_string())
+        }
+        (_, Err(ChallengeRejection::BindingMismatch)) => {
+            Err("Response was signed on another connection".to_string())
+        }
+        (Some(response), Ok((challenge, bytes))) => {
+            sasl::verify_response(&challenge, &bytes, response, &state.did_resolver).await
+        }
+    };
+    let proven = proof.and_then(|proven| {
+        if proven == founder {
+            Ok(proven)
+        } else {
+            Err("Proof is for a different DID".to_string())
+        }
+    });
+    if let Err(e) = proven {
+        tracing::warn!(%session_id, %channel, %did, "Founder reclaim failed: {e}");
+        state.with_db(|db| {
+            db.log_governance(
+                Some(&channel),
+                &did,
+                "founder_reclaim_failed",
+                &did,
+                Some(e.as_str()),
+            )
+        });
+        reject("INVALID_PROOF", &e);
+        return;
+    }
+
+    restore_founder(conn, state, server_name, session_id, &channel, &did);
+    state.with_db(|db| db.log_governance(Some(&channel), &did, "founder_reclaim", &did, None));
+    tracing::info!(%session_id, %channel, %did, "Founder reclaimed channel");
+    let reply = Message::from_server(
+        server_name,
+        "RECLAIM",
+        vec![&channel, "SUCCESS", "You are this channel's founder again"],
+    );
+    send(state, session_id, format!("{reply}\r\n"));
+}
+
+/// Put `did` back as founder of `channel`, with persistent ops, and give
+/// the session founder and ops there if it is a member.
+fn restore_founder(
+    conn: &Connection,
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    channel: &str,
+    did: &str,
+) {
+    let (snapshot, newly_opped, was_shown) = {
+        let Some(mut ch) = state.channels.get(channel) else {
+            return;
+        };
+        ch.founder_did = Some(did.to_string());
+        ch.did_ops.insert(did.to_string());
+        let member = ch.members.contains(session_id);
+        let was_shown = ch.rank(session_id) >= ChannelRole::Voice;
+        let newly_opped = member && ch.ops.insert(session_id.to_string());
+        if member {
+            ch.founders.insert(session_id.to_string());
+        }
+        (ch.clone(), newly_opped, was_shown)
+    };
+    state.with_db(|db| db.save_channel(channel, &snapshot));
+
+    let state_c = Arc::clone(state);
+    let channel_c = channel.to_string();
+    let did_c = did.to_string();
+    tokio::spawn(async move {
+        state_c.crdt_set_founder(&channel_c, &did_c).await;
+        state_c.crdt_grant_op(&channel_c, &did_c, None).await;
+        state_c.crdt_broadcast_sync().await;
+    });
+
+    if !snapshot.members.contains(session_id) {
+        return;
+    }
+    let nick = conn.nick_or_star();
+    if newly_opped {
+        super::channel::auditorium_reveal(state, channel, session_id, was_shown);
+        let mode = format!(":{server_name} MODE {channel} +o {nick}\r\n");
+        broadcast_to_channel(state, channel, &mode);
+        s2s_broadcast_mode(state, conn, channel, "+o", Some(nick));
+    }
+    let notice = format!(":{server_name} NOTICE {channel} :{nick} reclaimed founder status\r\n");
+    broadcast_to_channel(state, channel, &notice);
+}
@@ -85,6 +85,8 @@ pub(super) fn attach_same_did(
         .entry(did.clone())
         .or_default()
         .insert(session_id.to_string());
+    // Back online: anything queued for an email digest is in their history
+    state.email.clear(&did);
 
     // Check for ghost session (recently disconnected — reclaim without join/part churn)
     let ghost = state.ghost_sessions.lock().remove(&did);
@@ -100,7 +102,9 @@ pub(super) fn attach_same_did(
         );
 
         // Adopt the ghost's nick
-        if conn.nick.as_ref().map(|n| n.to_lowercase()) != Some(ghost.nick.to_lowercase()) {
+        if conn.nick.as_ref().map(|n| crate::casemap::fold(n))
+            != Some(crate::casemap::fold(&ghost.nick))
+        {
             if let Some(ref old_nick) = conn.nick {
                 state.nick_to_session.lock().remove_by_nick(old_nick);
             }
@@ -111,17 +115,22 @@ pub(super) fn attach_same_did(
 
         // Re-join all channels the ghost was in (silently — no broadcast).
         // Remove the stale ghost session_id and replace with the new one.
-        let mut channels = state.channels.lock();
         for (ch_name, was_op, was_voiced, was_halfop) in &ghost.channels {
-            if let Some(ch) = channels.get_mut(&ch_name.to_lowercase()) {
+            if let Some(mut ch) = state.channels.get(&crate::casemap::fold(ch_name)) {
                 // Remove the ghost's stale session_id from all membership sets
                 ch.members.remove(&ghost.session_id);
                 ch.ops.remove(&ghost.session_id);
                 ch.voiced.remove(&ghost.session_id);
                 ch.halfops.remove(&ghost.session_id);
+                let was_founder = ch.founders.remove(&ghost.session_id);
+                let was_admin = ch.admins.remove(&ghost.session_id);
+                let since = ch.member_since.remove(&ghost.session_id);
 
                 // Insert the new session_id
                 ch.members.insert(session_id.to_string());
+                if let Some(since) = since {
+                    ch.member_since.insert(session_id.to_string(), since);
+                }
                 // Restore ops from ghost state, OR grant via DID authority
                 let should_op = *was_op
                     || ch.founder_did.as_deref() == Some(did.as_str())
@@ -135,9 +144,14 @@ pub(super) fn attach_same_did(
                 if *was_halfop {
                     ch.halfops.insert(session_id.to_string());
                 }
+                if was_founder {
+                    ch.founders.insert(session_id.to_string());
+                }
+                if was_admin {
+                    ch.admins.insert(session_id.to_string());
+                }
             }
         }
-        drop(channels);
 
         // Also clean up the ghost's stale sid_to_nick entry
         state
@@ -255,7 +269,8 @@ pub(super) fn attach_same_did(
     // Adopt the canonical nick and ensure this session is in nick_to_session
     if let Some(ref canon) = canonical_nick {
         let mut nts = state.nick_to_session.lock();
-        if conn.nick.as_ref().map(|n| n.to_lowercase()) != Some(canon.to_lowercase()) {
+        if conn.nick.as_ref().map(|n| crate::casemap::fold(n)) != Some(crate::casemap::fold(canon))
+        {
             // Remove this session's old nick mapping (not all sessions with that nick)
             nts.remove_by_session(session_id);
             conn.nick = Some(canon.clone());
@@ -279,21 +294,25 @@ pub(super) fn attach_same_did(
     }
 
     // Find all channels the DID is in via existing sessions
-    let channels_to_join: Vec<String> = {
-        let channels = state.channels.lock();
-        channels
+    let channels_to_join: Vec<String> = state.channels.filter_map(|name, ch| {
+        existing_sessions
             .iter()
-            .filter(|(_, ch)| existing_sessions.iter().any(|sid| ch.members.contains(sid)))
-            .map(|(name, _)| name.clone())
-            .collect()
-    };
+            .any(|sid| ch.members.contains(sid))
+            .then(|| name.to_string())
+    });
 
     // Add this session to those channels (silently — no JOIN broadcast)
     {
-        let mut channels = state.channels.lock();
         for ch_name in &channels_to_join {
-            if let Some(ch) = channels.get_mut(ch_name) {
+            if let Some(mut ch) = state.channels.get(ch_name) {
                 ch.members.insert(session_id.to_string());
+                let since = existing_sessions
+                    .iter()
+                    .filter_map(|s| ch.member_since.get(s).copied())
+                    .min();
+                if let Some(since) = since {
+                    ch.member_since.insert(session_id.to_string(), since);
+                }
                 // Copy op/voice status from existing session, OR grant via DID authority
                 let is_op = existing_sessions.iter().any(|s| ch.ops.contains(s))
                     || ch.founder_did.as_deref() == Some(did.as_str())
@@ -322,59 +341,176 @@ pub(super) fn attach_same_did(
         );
 
         // Send topic
-        let channels = state.channels.lock();
-        if let Some(ch) = channels.get(ch_name) {
-            if let Some(ref topic) = ch.topic {
-                let topic_msg = crate::irc::Message::from_server(
-                    server_name,
-                    crate::irc::RPL_TOPIC,
-                    vec![nick, ch_name, &topic.text],
-                );
-                send(state, session_id, format!("{topic_msg}\r\n"));
-            }
-            // Send NAMES
-            let nts = state.nick_to_session.lock();
-            let mut names: Vec<String> = Vec::new();
-            let mut seen_nicks = std::collections::HashSet::new();
-            for member_sid in &ch.members {
-                if let Some(member_nick) = nts.get_nick(member_sid) {
-                    let nick_lower = member_nick.to_lowercase();
-                    if seen_nicks.contains(&nick_lower) {
-                        continue;
-                    }
-                    seen_nicks.insert(nick_lower);
-                    let prefix = if ch.ops.contains(member_sid) {
-                        "@"
-                    } else if ch.voiced.contains(member_sid) {
-                        "+"
-                    } else {
-                        ""
-                    };
-                    names.push(format!("{prefix}{member_nick}"));
-                }
-            }
-            drop(channels);
-            let names_str = names.join(" ");
-            let names_msg = crate::irc::Message::from_server(
-                server_name,
-                crate::irc::RPL_NAMREPLY,
-                vec![nick, "=", ch_name, &names_str],
-            );
-            let end_msg = crate::irc::Message::from_server(
+        if let Some(ch) = state.channels.get(ch_name)
+            && let Some(ref topic) = ch.topic
+        {
+            let topic_msg = crate::irc::Message::from_server(
                 server_name,
-                crate::irc::RPL_ENDOFNAMES,
-                vec![nick, ch_name, "End of /NAMES list"],
+                crate::irc::RPL_TOPIC,
+                vec![nick, ch_name, &topic.text],
             );
-            send(state, session_id, format!("{names_msg}\r\n{end_msg}\r\n"));
-        } else {
-            drop(channels);
+            send(state, session_id, format!("{topic_msg}\r\n"));
         }
+        // Send NAMES
+        super::channel::send_names(state, server_name, session_id, nick, ch_name, None);
     }
 
     tracing::info!(did = %did, channels = ?channels_to_join.len(),
                    "Session attached to {} existing channels", channels_to_join.len());
 }
 
+/// `RESUME` — log in and register in one step over iroh.
+///
+/// The QUIC handshake proves the peer holds its endpoint key, and an
+/// endpoint bound by an earlier SASL or [`handle_bind`] acts for that DID, so a client
+/// reconnecting from a bound endpoint can skip CAP/SASL/NICK/USER: it
+/// sends `RESUME` as its first line (after any `CAP REQ` it wants) and is
+/// welcomed straight away, reclaiming its ghost session if one is held.
+/// On `FAIL RESUME` it falls back to normal registration.
+pub(super) fn handle_resume(
+    conn: &mut Connection,
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    send: &impl Fn(&Arc<SharedState>, &str, String),
+) {
+    let fail = |code: &str, text: &str| {
+        let reply = Message::from_server(server_name, "FAIL", vec!["RESUME", code, text]);
+        send(state, session_id, format!("{reply}\r\n"));
+    };
+    if conn.registered {
+        fail("ALREADY_REGISTERED", "You are already registered");
+        return;
+    }
+    let Some(endpoint_id) = conn.iroh_endpoint_id.clone() else {
+        fail("NOT_IROH", "RESUME is only available over iroh");
+        return;
+    };
+    let Some(did) = state.iroh_endpoint_did(&endpoint_id) else {
+        fail(
+            "NOT_BOUND",
+            "This endpoint is not bound to an identity; authenticate with SASL",
+        );
+        return;
+    };
+    if conn.authenticated_did.as_ref().is_some_and(|d| d != &did) {
+        fail(
+            "ACCOUNT_MISMATCH",
+            "Already authenticated as a different identity",
+        );
+        return;
+    }
+    // A held ghost's nick is adopted by attach_same_did regardless.
+    let ghost_nick = state
+        .ghost_sessions
+        .lock()
+        .get(&did)
+        .map(|g| g.nick.clone());
+    let nick = conn
+        .nick
+        .clone()
+        .or(ghost_nick)
+        .or_else(|| state.did_nicks.lock().get(&did).cloned());
+    let Some(nick) = nick else {
+        fail(
+            "NEED_NICK",
+            "No nick is bound to this identity; send NICK first",
+        );
+        return;
+    };
+
+    conn.nick = Some(nick.clone());
+    if conn.user.is_none() {
+        conn.user = Some(nick.clone());
+    }
+    conn.authenticated_did = Some(did.clone());
+    conn.cap_negotiating = false;
+    conn.sasl_in_progress = false;
+    state
+        .session_dids
+        .lock()
+        .insert(session_id.to_string(), did.clone());
+    attach_same_did(conn, state, session_id, send);
+    super::cap::spawn_auth_hooks(state, session_id, &did, nick);
+
+    let nick = conn.nick_or_star().to_string();
+    if state.config.oper_dids.iter().any(|d| d == &did) {
+        conn.is_oper = true;
+        state.server_opers.lock().insert(session_id.to_string());
+        let oper_notice = Message::from_server(server_name, "MODE", vec![&nick, "+o"]);
+        send(state, session_id, format!("{oper_notice}\r\n"));
+        let token = super::oper_token_notice(state, server_name, &nick, session_id);
+        send(state, session_id, token);
+    }
+    let hostmask = conn.hostmask();
+    let logged_in = Message::from_server(
+        server_name,
+        irc::RPL_LOGGEDIN,
+        vec![
+            &nick,
+            &hostmask,
+            &did,
+            &format!("You are now logged in as {did}"),
+        ],
+    );
+    send(state, session_id, format!("{logged_in}\r\n"));
+    tracing::info!(%session_id, %did, %endpoint_id, nick = %nick, "Resumed over iroh");
+
+    try_complete_registration(conn, state, server_name, session_id, send);
+}
+
+/// `BIND` — bind this iroh endpoint to the session's DID.
+///
+/// The only way an endpoint gets bound: once logged in (by any SASL
+/// method), a client binds its endpoint explicitly, once, so its later
+/// connections from the endpoint can log in with SASL EXTERNAL or
+/// `RESUME` instead of signing a challenge or fetching a fresh token. An
+/// endpoint bound to another DID stays bound until that DID revokes it
+/// with `SESSIONS REVOKE`.
+pub(super) fn handle_bind(
+    conn: &Connection,
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    send: &impl Fn(&Arc<SharedState>, &str, String),
+) {
+    let nick = conn.nick_or_star();
+    let fail = |code: &str, text: &str| {
+        let reply = Message::from_server(server_name, "FAIL", vec!["BIND", code, text]);
+        send(state, session_id, format!("{reply}\r\n"));
+    };
+    let Some(endpoint_id) = conn.iroh_endpoint_id.as_deref() else {
+        fail("NOT_IROH", "BIND is only available over iroh");
+        return;
+    };
+    let Some(did) = conn.authenticated_did.as_deref() else {
+        fail(
+            "NOT_AUTHENTICATED",
+            "Authenticate before binding this endpoint",
+        );
+        return;
+    };
+    let text = match state.iroh_endpoint_did(endpoint_id) {
+        Some(bound) if bound == did => format!("Endpoint {endpoint_id} is already bound to {did}"),
+        Some(_) => {
+            fail(
+                "ALREADY_BOUND",
+                "This endpoint is bound to another identity; revoke it there first",
+            );
+            return;
+        }
+        None => {
+            state.bind_iroh_endpoint(endpoint_id, did);
+            tracing::info!(%session_id, %did, %endpoint_id, "Iroh endpoint bound with BIND");
+            format!(
+                "Endpoint {endpoint_id} is now bound to {did}; reconnect with SASL EXTERNAL or RESUME"
+            )
+        }
+    };
+    let reply = Message::from_server(server_name, "NOTICE", vec![nick, &text]);
+    send(state, session_id, format!("{reply}\r\n"));
+}
+
 pub(super) fn try_complete_registration(
     conn: &mut Connection,
     state: &Arc<SharedState>,
@@ -389,14 +525,32 @@ pub(super) fn try_complete_registration(
         return;
     }
 
+    // With an iroh allowlist, an iroh connection only gets this far as an
+    // allowed DID; an unbound endpoint that doesn't authenticate is dropped.
+    if conn.iroh_endpoint_id.is_some() && !state.iroh_did_allowed(conn.authenticated_did.as_deref())
+    {
+        tracing::warn!(
+            %session_id, did = ?conn.authenticated_did,
+            "Refusing iroh registration: identity not in iroh_allowed_dids"
+        );
+        send(
+            state,
+            session_id,
+            "ERROR :This server only accepts iroh connections from allowed identities\r\n"
+                .to_string(),
+        );
+        state.connections.remove(session_id);
+        return;
+    }
+
     // Enforce nick ownership at registration time.
     // If the user claimed a registered nick during CAP negotiation
     // but didn't authenticate as the owner, force-rename them.
     if let Some(nick) = conn.nick.clone() {
-        let nick_lower = nick.to_lowercase();
-        let owner_did = state.nick_owners.lock().get(&nick_lower).cloned();
+        let nick_lower = crate::casemap::fold(&nick);
+        let auth_did = conn.authenticated_did.clone();
+        let owner_did = state.nick_owner_or_lookalike(&nick, auth_did.as_deref());
         if let Some(owner) = owner_did {
-            let auth_did = conn.authenticated_did.clone();
             let is_owner = auth_did.as_deref() == Some(owner.as_str());
             if !is_owner {
                 if let Some(did) = auth_did {
@@ -446,6 +600,32 @@ pub(super) fn try_complete_registration(
         }
     }
 
+    // A reserved nick stashed during CAP negotiation: if SASL didn't
+    // show an exempt identity, the connection gets a Guest nick instead.
+    if let Some(nick) = conn.nick.clone()
+        && crate::reserved::nick_reserved(&state.config, &nick)
+        && !crate::reserved::exempt(
+            &state.config,
+            conn.authenticated_did.as_deref(),
+            conn.is_oper,
+        )
+    {
+        let guest_id: u32 = rand::random::<u32>() % 100000;
+        let guest_nick = format!("Guest{guest_id}");
+        let notice = Message::from_server(
+            server_name,
+            "NOTICE",
+            vec![
+                "*",
+                &format!("Nick {nick} is reserved — renamed to {guest_nick}."),
+            ],
+        );
+        send(state, session_id, format!("{notice}\r\n"));
+        state.nick_to_session.lock().remove_by_nick(&nick);
+        state.nick_to_session.lock().insert(&guest_nick, session_id);
+        conn.nick = Some(guest_nick);
+    }
+
     // Multi-device attach is handled at SASL success time (cap.rs).
     // This catch-all covers edge cases where registration completes
     // without going through the SASL path.
@@ -453,6 +633,22 @@ pub(super) fn try_complete_registration(
 
     conn.registered = true;
     let nick = conn.nick.as_deref().unwrap();
+    state
+        .firehose
+        .publish(crate::firehose::FirehoseEvent::Register {
+            session: session_id.to_string(),
+            nick: nick.to_string(),
+            did: conn.authenticated_did.clone(),
+        });
+
+    state.session_activity.lock().insert(
+        session_id.to_string(),
+        crate::server::SessionActivity {
+            signon: chrono::Utc::now().timestamp(),
+            last_message: std::time::Instant::now(),
+            ip: conn.peer_ip,
+        },
+    );
 
     // Store iroh endpoint ID in shared state for WHOIS lookups
     if let Some(ref iroh_id) = conn.iroh_endpoint_id {
@@ -495,10 +691,24 @@ pub(super) fn try_complete_registration(
     let myinfo = Message::from_server(
         server_name,
         irc::RPL_MYINFO,
-        vec![nick, server_name, "freeq-0.1", "o", "o"],
+        vec![nick, server_name, "freeq-0.1", "Bo", "o"],
+    );
+    let casemapping = format!("CASEMAPPING={}", crate::casemap::active().as_str());
+    let isupport = Message::from_server(
+        server_name,
+        irc::RPL_ISUPPORT,
+        vec![
+            nick,
+            &casemapping,
+            "NICKLEN=64",
+            crate::server::ISUPPORT_PREFIX,
+            "WHOX",
+            "BOT=B",
+            "are supported by this server",
+        ],
     );
 
-    for msg in [welcome, yourhost, created, myinfo] {
+    for msg in [welcome, yourhost, created, myinfo, isupport] {
         send(state, session_id, format!("{msg}\r\n"));
     }
 
@@ -530,6 +740,14 @@ pub(super) fn try_complete_registration(
         send(state, session_id, format!("{no_motd}\r\n"));
     }
 
+    if conn.on_probation() {
+        send(
+            state,
+            session_id,
+            super::probation::welcome_notice(conn, server_name),
+        );
+    }
+
     // Send server restart notice if the server booted recently (within 5 minutes)
     {
         let uptime = state.boot_time.elapsed();
@@ -558,8 +776,7 @@ pub(super) fn try_complete_registration(
 
             // Topic
             {
-                let channels = state.channels.lock();
-                if let Some(ch) = channels.get(&ch_name.to_lowercase())
+                if let Some(ch) = state.channels.get(&crate::casemap::fold(ch_name))
                     && let Some(ref topic) = ch.topic
                 {
                     let topic_msg = crate::irc::Message::from_server(
@@ -572,7 +789,7 @@ pub(super) fn try_complete_registration(
             }
 
             // Names (sends NAMREPLY + ENDOFNAMES → triggers client CHATHISTORY request)
-            super::channel::handle_names(conn, ch_name, state, server_name, session_id, send);
+            super::channel::handle_names(conn, ch_name, &[], state, server_name, session_id, send);
         }
     }
 
@@ -582,16 +799,18 @@ pub(super) fn try_complete_registration(
         let did = did.clone();
         if let Some(channels) = state.with_db(|db| db.get_user_channels(&did)) {
             // Filter out channels this session is already in (from multi-device attach)
-            let already_in: std::collections::HashSet<String> = {
-                let chs = state.channels.lock();
-                chs.iter()
-                    .filter(|(_, ch)| ch.members.contains(session_id))
-                    .map(|(name, _)| name.to_lowercase())
-                    .collect()
-            };
+            let already_in: std::collections::HashSet<String> = state
+                .channels
+                .filter_map(|name, ch| {
+                    ch.members
+                        .contains(session_id)
+                        .then(|| crate::casemap::fold(name))
+                })
+                .into_iter()
+                .collect();
             let to_join: Vec<String> = channels
                 .into_iter()
-                .filter(|ch| !already_in.contains(&ch.to_lowercase()))
+                .filter(|ch| !already_in.contains(&crate::casemap::fold(ch)))
                 .collect();
             if !to_join.is_empty() {
                 tracing::info!(%session_id, %did, count = to_join.len(), "Auto-rejoining saved channels");
@@ -609,4 +828,7 @@ pub(super) fn try_complete_registration(
             }
         }
     }
+
+    // Server auto-join channels (--auto-join), unless the user opted out.
+    super::autojoin_cmd::auto_join(conn, state, server_name, session_id, send);
 }
@@ -0,0 +1,213 @@
+//! Oper commands for S2S links.
+//!
+//! SQUIT <peer> [:reason]        — Drop the link to a peer now (it reconnects if in --s2s-peers)
+//! S2SFAULT                      — List links with injected faults (feature `s2s-faults`)
+//! S2SFAULT <peer>               — Show the faults and counters on the link to <peer>
+//! S2SFAULT <peer> PAUSE|RESUME  — Hold / release everything sent to <peer>
+//! S2SFAULT <peer> DELAY <ms>    — Delay each message sent to <peer>
+//! S2SFAULT <peer> DROP <pct>    — Drop that share of messages sent to <peer>
+//! S2SFAULT <peer> REORDER <n>   — Send to <peer> in reversed batches of n
+//! S2SFAULT <peer> CLEAR         — Remove every fault on the link
+//!
+//! <peer> is a server name, an endpoint ID, or a unique ID prefix. Faults
+//! apply to what this server sends; set them on both servers to fault both
+//! directions.
+
+use crate::irc::{self, Message};
+use crate::s2s::S2sManager;
+use crate::server::SharedState;
+use std::sync::Arc;
+
+/// Shortest endpoint ID prefix accepted for a peer.
+const MIN_PREFIX: usize = 6;
+
+/// Resolve `arg` to a connected peer's endpoint ID.
+async fn resolve_peer(manager: &S2sManager, arg: &str) -> Option<String> {
+    let peers: Vec<String> = manager.peers.lock().await.keys().cloned().collect();
+    if peers.iter().any(|p| p == arg) {
+        return Some(arg.to_string());
+    }
+    let by_name = manager
+        .peer_names
+        .lock()
+        .await
+        .iter()
+        .find(|(id, name)| name.eq_ignore_ascii_case(arg) && peers.contains(id))
+        .map(|(id, _)| id.clone());
+    if by_name.is_some() {
+        return by_name;
+    }
+    if arg.len() < MIN_PREFIX {
+        return None;
+    }
+    let mut matches = peers.into_iter().filter(|p| p.starts_with(arg));
+    match (matches.next(), matches.next()) {
+        (Some(id), None) => Some(id),
+        _ => None,
+    }
+}
+
+/// Reply 481 unless the caller is an oper; true if they are.
+fn require_oper(
+    conn: &super::Connection,
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    send_fn: &impl Fn(&Arc<SharedState>, &str, String),
+) -> bool {
+    if !conn.is_oper {
+        let reply = Message::from_server(
+            server_name,
+            "481",
+            vec![
+                conn.nick_or_star(),
+                "Permission Denied - You're not an IRC operator",
+            ],
+        );
+        send_fn(state, session_id, format!("{reply}\r\n"));
+    }
+    conn.is_oper
+}
+
+pub(super) async fn handle_squit(
+    conn: &super::Connection,
+    msg: &Message,
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    send_fn: &impl Fn(&Arc<SharedState>, &str, String),
+) {
+    let nick = conn.nick_or_star();
+    let notice = |text: &str| {
+        let reply = Message::from_server(server_name, "NOTICE", vec![nick, text]);
+        send_fn(state, session_id, format!("{reply}\r\n"));
+    };
+
+    if !require_oper(conn, state, server_name, session_id, send_fn) {
+        return;
+    }
+    let Some(target) = msg.params.first() else {
+        let reply = Message::from_server(
+            server_name,
+            irc::ERR_NEEDMOREPARAMS,
+            vec![nick, "SQUIT", "Not enough parameters"],
+        );
+        send_fn(state, session_id, format!("{reply}\r\n"));
+        return;
+    };
+    let reason = msg.params.get(1).map(String::as_str).unwrap_or("no reason");
+
+    let manager = state.s2s_manager.lock().clone();
+    let Some(manager) = manager else {
+        notice("S2S not active");
+        return;
+    };
+    let Some(peer_id) = resolve_peer(&manager, target).await else {
+        notice(&format!("SQUIT: no linked peer matches {target}"));
+        return;
+    };
+    let name = manager.peer_display_name(&peer_id).await;
+    if manager.drop_link(&peer_id, None).await {
+        tracing::warn!(oper = %nick, peer = %peer_id, %reason, "S2S link dropped via SQUIT");
+        notice(&format!(
+            "SQUIT: link to {name} ({peer_id}) dropped: {reason}"
+        ));
+    } else {
+        notice(&format!("SQUIT: {name} is no longer linked"));
+    }
+}
+
+#[cfg(feature = "s2s-faults")]
+pub(super) async fn handle_s2sfault(
+    conn: &super::Connection,
+    msg: &Message,
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    send_fn: &impl Fn(&Arc<SharedState>, &str, String),
+) {
+    use crate::s2s_faults;
+
+    let nick = conn.nick_or_star();
+    let notice = |text: &str| {
+        let reply = Message::from_server(server_name, "NOTICE", vec![nick, text]);
+        send_fn(state, session_id, format!("{reply}\r\n"));
+    };
+
+    if !require_oper(conn, state, server_name, session_id, send_fn) {
+        return;
+    }
+    let manager = state.s2s_manager.lock().clone();
+    let Some(manager) = manager else {
+        notice("S2S not active");
+        return;
+    };
+    let us = manager.server_id.clone();
+
+    let Some(target) = msg.params.first() else {
+        let active = s2s_faults::active_from(&us);
+        if active.is_empty() {
+            notice("S2SFAULT: no links have faults");
+        }
+        for (peer_id, faults) in active {
+            let name = manager.peer_display_name(&peer_id).await;
+            notice(&format!("S2SFAULT {name}: {}", faults.describe()));
+        }
+        return;
+    };
+    let Some(peer_id) = resolve_peer(&manager, target).await else {
+        notice(&format!("S2SFAULT: no linked peer matches {target}"));
+        return;
+    };
+    let name = manager.peer_display_name(&peer_id).await;
+
+    let action = msg.params.get(1).map(|s| s.to_uppercase());
+    let value = msg.params.get(2).map(String::as_str);
+    let number = |what: &str| -> Option<u64> {
+        let parsed = value.and_then(|v| v.parse().ok());
+        if parsed.is_none() {
+            notice(&format!("Usage: S2SFAULT <peer> {what} <number>"));
+        }
+        parsed
+    };
+    match action.as_deref() {
+        None => {
+            let stats = s2s_faults::stats(&us, &peer_id);
+            notice(&format!(
+                "S2SFAULT {name}: {} ({} delivered, {} dropped)",
+                s2s_faults::faults(&us, &peer_id).describe(),
+                stats.delivered,
+                stats.dropped
+            ));
+            return;
+        }
+        Some("PAUSE") => s2s_faults::update(&us, &peer_id, |f| f.paused = true),
+        Some("RESUME") => s2s_faults::update(&us, &peer_id, |f| f.paused = false),
+        Some("DELAY") => {
+            let Some(ms) = number("DELAY") else { return };
+            s2s_faults::update(&us, &peer_id, |f| {
+                f.delay = std::time::Duration::from_millis(ms)
+            });
+        }
+        Some("DROP") => {
+            let Some(pct) = number("DROP") else { return };
+            s2s_faults::update(&us, &peer_id, |f| f.drop_rate = pct.min(100) as f64 / 100.0);
+        }
+        Some("REORDER") => {
+            let Some(n) = number("REORDER") else { return };
+            s2s_faults::update(&us, &peer_id, |f| f.reorder_window = n as usize);
+        }
+        Some("CLEAR") => s2s_faults::clear(&us, &peer_id),
+        Some(other) => {
+            notice(&format!(
+                "S2SFAULT: unknown action {other} (PAUSE, RESUME, DELAY, DROP, REORDER, CLEAR)"
+            ));
+            return;
+        }
+    }
+    tracing::warn!(oper = %nick, peer = %peer_id, "S2S fault injection changed via S2SFAULT");
+    notice(&format!(
+        "S2SFAULT {name}: {}",
+        s2s_faults::faults(&us, &peer_id).describe()
+    ));
+}
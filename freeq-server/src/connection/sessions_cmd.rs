@@ -0,0 +1,135 @@
+//! IRC SESSIONS command — view and revoke your own web credentials and
+//! iroh endpoint bindings.
+//!
+//! SESSIONS                — List active web sessions, unredeemed web-auth tokens, bound iroh endpoints
+//!                           and devices logged in through the auth broker
+//! SESSIONS REVOKE <id>    — Revoke one session, token or endpoint by the ID shown in the list
+//! SESSIONS REVOKE ALL     — Revoke every web session, token and endpoint binding for your DID
+//!
+//! Only the caller's own DID is ever listed or touched. Revoking a web
+//! session drops the server's copy of the PDS grant (media upload, Bluesky
+//! cross-post); unbinding an iroh endpoint stops it from `RESUME`ing or
+//! logging in with SASL EXTERNAL.
+//! Neither disconnects IRC connections.
+//!
+//! Broker devices are listed for information only: they are logged out
+//! from the device itself, and the broker tells the server when that
+//! happens.
+
+use crate::irc::Message;
+use crate::server::{SharedState, WEB_AUTH_TOKEN_TTL, WEB_SESSION_IDLE_TTL, WEB_SESSION_MAX_AGE};
+use std::sync::Arc;
+
+pub(super) fn handle_sessions(
+    conn: &super::Connection,
+    msg: &Message,
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    send_fn: &impl Fn(&Arc<SharedState>, &str, String),
+) {
+    let nick = conn.nick_or_star();
+    let notice = |text: &str| {
+        let reply = Message::from_server(server_name, "NOTICE", vec![nick, text]);
+        send_fn(state, session_id, format!("{reply}\r\n"));
+    };
+
+    let Some(did) = conn.authenticated_did.as_deref() else {
+        notice("SESSIONS requires an authenticated identity");
+        return;
+    };
+
+    match msg.params.first().map(|s| s.to_uppercase()).as_deref() {
+        None | Some("LIST") => {
+            let mut sessions: Vec<_> = state
+                .web_sessions
+                .lock()
+                .iter()
+                .filter(|((d, _), s)| d == did && !s.is_expired())
+                .map(|((_, purpose), s)| (*purpose, s.clone()))
+                .collect();
+            sessions.sort_by_key(|(_, s)| s.created_at);
+            let mut tokens: Vec<_> = state
+                .web_auth_tokens
+                .lock()
+                .values()
+                .filter(|t| t.did == did && t.created_at.elapsed() < WEB_AUTH_TOKEN_TTL)
+                .cloned()
+                .collect();
+            tokens.sort_by_key(|t| t.created_at);
+            let endpoints = state.iroh_endpoints_for_did(did);
+            let devices = state.broker_devices_for_did(did);
+
+            if sessions.is_empty()
+                && tokens.is_empty()
+                && endpoints.is_empty()
+                && devices.is_empty()
+            {
+                notice("No active web sessions");
+                return;
+            }
+            for (purpose, s) in &sessions {
+                let expires_in = WEB_SESSION_IDLE_TTL
+                    .saturating_sub(s.last_used.elapsed())
+                    .min(WEB_SESSION_MAX_AGE.saturating_sub(s.created_at.elapsed()));
+                notice(&format!(
+                    "SESSION {} {} scope=\"{}\" age={}s idle={}s expires_in={}s",
+                    s.id,
+                    purpose.as_str(),
+                    s.granted_scope,
+                    s.created_at.elapsed().as_secs(),
+                    s.last_used.elapsed().as_secs(),
+                    expires_in.as_secs(),
+                ));
+            }
+            for t in &tokens {
+                let binding = t
+                    .binding
+                    .as_ref()
+                    .map(|b| b.to_string())
+                    .unwrap_or_else(|| "none".to_string());
+                notice(&format!(
+                    "TOKEN {} age={}s binding={}",
+                    t.id,
+                    t.created_at.elapsed().as_secs(),
+                    binding,
+                ));
+            }
+            let now = chrono::Utc::now().timestamp();
+            for (endpoint_id, binding) in &endpoints {
+                notice(&format!(
+                    "ENDPOINT {endpoint_id} age={}s",
+                    (now - binding.bound_at).max(0),
+                ));
+            }
+            for (session, d) in &devices {
+                notice(&format!(
+                    "DEVICE {session} \"{}\" age={}s last_seen={}s",
+                    d.device.as_deref().unwrap_or("unknown"),
+                    (now - d.created_at).max(0),
+                    (now - d.last_seen).max(0),
+                ));
+            }
+            notice("End of SESSIONS — use SESSIONS REVOKE <id|ALL> to revoke");
+        }
+        Some("REVOKE") => {
+            let Some(target) = msg.params.get(1) else {
+                notice("Usage: SESSIONS REVOKE <id|ALL>");
+                return;
+            };
+            if target.eq_ignore_ascii_case("ALL") {
+                let n = state.revoke_all_web_credentials(did) + state.revoke_all_iroh_bindings(did);
+                tracing::info!(%did, revoked = n, "SESSIONS REVOKE ALL");
+                notice(&format!("Revoked {n} credential(s)"));
+            } else if state.revoke_web_credential(did, target)
+                || state.revoke_iroh_binding(did, target)
+            {
+                tracing::info!(%did, id = %target, "SESSIONS REVOKE");
+                notice(&format!("Revoked {target}"));
+            } else {
+                notice(&format!("No session, token or endpoint with ID {target}"));
+            }
+        }
+        Some(_) => notice("Usage: SESSIONS [LIST] | SESSIONS REVOKE <id|ALL>"),
+    }
+}
@@ -0,0 +1,95 @@
+//! User modes: `MODE <own nick> [+B|-B]`.
+//!
+//! `+B` flags the connection as a bot (advertised as `BOT=B` in
+//! ISUPPORT). It's the same flag `AGENT REGISTER` sets, so a bot shows up
+//! with a `B` in WHO, "is a bot" in WHOIS and the read-only `bot`
+//! metadata key, and channels with `+B` only let it speak if it's on
+//! their `+W` allowlist. `-B` drops the flag again, but only if `+B` set
+//! it: a class from `AGENT REGISTER` or a manifest stays put.
+
+use super::Connection;
+use crate::irc::{self, Message};
+use crate::server::SharedState;
+use std::sync::Arc;
+
+pub(super) fn handle_user_mode(
+    conn: &mut Connection,
+    target: &str,
+    mode_str: Option<&str>,
+    state: &Arc<SharedState>,
+    server_name: &str,
+    session_id: &str,
+    send: &impl Fn(&Arc<SharedState>, &str, String),
+) {
+    let nick = conn.nick_or_star().to_string();
+    if !crate::casemap::eq(target, &nick) {
+        let reply = Message::from_server(
+            server_name,
+            irc::ERR_USERSDONTMATCH,
+            vec![&nick, "Can't change or view modes for other users"],
+        );
+        send(state, session_id, format!("{reply}\r\n"));
+        return;
+    }
+
+    let Some(mode_str) = mode_str else {
+        let mut modes = String::from("+");
+        if state.is_bot(session_id) {
+            modes.push('B');
+        }
+        if state.server_opers.lock().contains(session_id) {
+            modes.push('o');
+        }
+        let reply = Message::from_server(server_name, irc::RPL_UMODEIS, vec![&nick, &modes]);
+        send(state, session_id, format!("{reply}\r\n"));
+        return;
+    };
+
+    let mut adding = true;
+    let mut bot = None;
+    let mut unknown = false;
+    for c in mode_str.chars() {
+        match c {
+            '+' => adding = true,
+            '-' => adding = false,
+            'B' => bot = Some(adding),
+            _ => unknown = true,
+        }
+    }
+    if unknown {
+        let reply = Message::from_server(
+            server_name,
+            irc::ERR_UMODEUNKNOWNFLAG,
+            vec![&nick, "Unknown MODE flag"],
+        );
+        send(state, session_id, format!("{reply}\r\n"));
+        return;
+    }
+    let Some(bot) = bot else {
+        return;
+    };
+    if bot == state.is_bot(session_id) || (!bot && !conn.bot_umode) {
+        return;
+    }
+
+    conn.bot_umode = bot;
+    if bot {
+        conn.actor_class = super::ActorClass::Agent;
+        state
+            .session_actor_class
+            .lock()
+            .insert(session_id.to_string(), conn.actor_class);
+    } else {
+        conn.actor_class = super::ActorClass::Human;
+        state.session_actor_class.lock().remove(session_id);
+    }
+    let sign = if bot { '+' } else { '-' };
+    let hostmask = conn.hostmask();
+    send(
+        state,
+        session_id,
+        format!(":{hostmask} MODE {nick} :{sign}B\r\n"),
+    );
+    super::metadata::notify_bot(state, server_name, conn, session_id, send);
+    tracing::info!(nick = %nick, session = %session_id, bot, "User MODE B");
+}
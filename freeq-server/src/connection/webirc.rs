@@ -0,0 +1,210 @@
+//! WEBIRC: trusted gateways pass on their users' real address.
+//!
+//! WEBIRC <password> <gateway> <hostname> <ip> [:<options>]
+//!
+//! A web gateway terminates its users' connections, so without WEBIRC they
+//! all share the gateway's address. Gateways are configured with
+//! `--webirc-gateways PASSWORD@SOURCE`, SOURCE being the IP or CIDR block
+//! the gateway connects from; those sources skip the per-IP connection
+//! limit. WEBIRC must come before NICK, USER and SASL. Once accepted, the
+//! connection's address is the client's: the per-IP connection limit, SASL
+//! failure counters and oper WHOIS all use it. A refused WEBIRC closes the
+//! connection rather than leaving every user behind one address.
+
+use super::Connection;
+use crate::config::ServerConfig;
+use crate::irc::Message;
+use crate::server::SharedState;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+/// One `--webirc-gateways` entry.
+struct Gateway<'a> {
+    password: &'a str,
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl<'a> Gateway<'a> {
+    /// Parse `PASSWORD@IP` or `PASSWORD@IP/PREFIX`.
+    fn parse(entry: &'a str) -> Result<Self, String> {
+        let (password, source) = entry
+            .rsplit_once('@')
+            .filter(|(password, _)| !password.is_empty())
+            .ok_or("expected PASSWORD@SOURCE")?;
+        let (addr, prefix) = match source.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (source, None),
+        };
+        let network: IpAddr = addr
+            .parse()
+            .map_err(|_| format!("{addr:?} is not an IP address"))?;
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => p
+                .parse()
+                .ok()
+                .filter(|p| *p <= max)
+                .ok_or_else(|| format!("{p:?} is not a prefix length"))?,
+            None => max,
+        };
+        Ok(Self {
+            password,
+            network: network.to_canonical(),
+            prefix,
+        })
+    }
+
+    /// Whether `ip` is in this gateway's source block.
+    fn contains(&self, ip: IpAddr) -> bool {
+        let mask = |bits: u32| -> u128 {
+            u128::MAX
+                .checked_shl(bits - u32::from(self.prefix))
+                .unwrap_or(0)
+        };
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = mask(32) as u32;
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = mask(128);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+fn gateways(config: &ServerConfig) -> impl Iterator<Item = Gateway<'_>> {
+    config
+        .webirc_gateways
+        .iter()
+        .filter_map(|entry| Gateway::parse(entry).ok())
+}
+
+/// Check the `--webirc-gateways` entries, for startup.
+pub fn validate(config: &ServerConfig) -> Result<(), String> {
+    for entry in &config.webirc_gateways {
+        Gateway::parse(entry).map_err(|e| {
+            let source = entry.rsplit_once('@').map_or("", |(_, s)| s);
+            format!("bad --webirc-gateways entry for {source:?}: {e}")
+        })?;
+    }
+    Ok(())
+}
+
+/// Whether connections from `ip` come from a configured gateway.
+pub(crate) fn is_gateway(config: &ServerConfig, ip: IpAddr) -> bool {
+    gateways(config).any(|g| g.contains(ip))
+}
+
+/// Apply a WEBIRC command. Returns false when it was refused and the
+/// connection should close.
+pub(super) fn handle_webirc(
+    conn: &mut Connection,
+    msg: &Message,
+    state: &Arc<SharedState>,
+    session_id: &str,
+    send_fn: &impl Fn(&Arc<SharedState>, &str, String),
+) -> bool {
+    let refuse = |reason: &str| {
+        tracing::warn!(%session_id, peer = ?conn.peer_ip, reason, "WEBIRC refused");
+        send_fn(state, session_id, format!("ERROR :WEBIRC: {reason}\r\n"));
+        false
+    };
+    if conn.registered
+        || conn.nick.is_some()
+        || conn.user.is_some()
+        || conn.authenticated_did.is_some()
+        || conn.webirc_gateway.is_some()
+    {
+        return refuse("must be sent before NICK, USER and SASL");
+    }
+    let [password, gateway, hostname, ip, ..] = msg.params.as_slice() else {
+        return refuse("expected <password> <gateway> <hostname> <ip>");
+    };
+    let trusted = conn.peer_ip.is_some_and(|peer| {
+        gateways(&state.config).any(|g| {
+            g.contains(peer) && super::constant_time_eq(g.password.as_bytes(), password.as_bytes())
+        })
+    });
+    if !trusted {
+        return refuse("not a trusted gateway");
+    }
+    let Ok(ip) = ip.parse::<IpAddr>().map(|ip| ip.to_canonical()) else {
+        return refuse("invalid client IP");
+    };
+
+    // The client's own connections count towards its address's limit.
+    {
+        let mut ip_conns = state.ip_connections.lock();
+        let count = ip_conns.entry(ip).or_insert(0);
+        if *count >= crate::server::MAX_CONNS_PER_IP {
+            drop(ip_conns);
+            return refuse("too many connections from this address");
+        }
+        *count += 1;
+    }
+    tracing::info!(
+        %session_id, %gateway, %hostname, %ip, gateway_ip = ?conn.peer_ip,
+        "WEBIRC accepted"
+    );
+    conn.peer_ip = Some(ip);
+    conn.webirc_gateway = Some(gateway.clone());
+    true
+}
+
+/// Give back the client address's connection slot taken by WEBIRC.
+pub(super) fn release(conn: &Connection, state: &SharedState) {
+    if conn.webirc_gateway.is_none() {
+        return;
+    }
+    let Some(ip) = conn.peer_ip else {
+        return;
+    };
+    let mut ip_conns = state.ip_connections.lock();
+    if let Some(count) = ip_conns.get_mut(&ip) {
+        *count = count.saturating_sub(1);
+        if *count == 0 {
+            ip_conns.remove(&ip);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sources_match_by_address_and_block() {
+        let one = Gateway::parse("pw@192.0.2.7").unwrap();
+        assert!(one.contains("192.0.2.7".parse().unwrap()));
+        assert!(one.contains("::ffff:192.0.2.7".parse().unwrap()));
+        assert!(!one.contains("192.0.2.8".parse().unwrap()));
+
+        let block = Gateway::parse("p@ss@10.1.0.0/16").unwrap();
+        assert_eq!(block.password, "p@ss");
+        assert!(block.contains("10.1.200.3".parse().unwrap()));
+        assert!(!block.contains("10.2.0.1".parse().unwrap()));
+
+        let v6 = Gateway::parse("pw@2001:db8::/32").unwrap();
+        assert!(v6.contains("2001:db8:1::1".parse().unwrap()));
+        assert!(!v6.contains("10.1.200.3".parse().unwrap()));
+
+        let any = Gateway::parse("pw@0.0.0.0/0").unwrap();
+        assert!(any.contains("203.0.113.9".parse().unwrap()));
+    }
+
+    #[test]
+    fn bad_entries_are_rejected() {
+        for entry in [
+            "192.0.2.7",
+            "@192.0.2.7",
+            "pw@gateway.example",
+            "pw@10.0.0.0/33",
+        ] {
+            assert!(Gateway::parse(entry).is_err(), "{entry}");
+        }
+    }
+}
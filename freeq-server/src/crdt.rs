@@ -383,6 +383,31 @@ impl ClusterDoc {
         self.metrics.lock().await.change_count += 1;
     }
 
+    /// Replace the channel founder unconditionally, keeping the existing
+    /// entry's actor_id so min-actor resolution doesn't undo it. Used when
+    /// policy chain arbitration settles who owns the channel; every server
+    /// that adopts the winning chain writes the same DID.
+    pub async fn replace_founder(&self, channel: &str, did: &str) {
+        let current_actor_id = self.actor_id.lock().await.clone();
+        let mut doc = self.doc.lock().await;
+        let key = format!("founder:{channel}");
+
+        let actor_id = doc
+            .get(automerge::ROOT, &key)
+            .ok()
+            .flatten()
+            .and_then(|(val, _)| value_to_string(&val))
+            .and_then(|raw| serde_json::from_str::<serde_json::Value>(&raw).ok())
+            .and_then(|existing| Some(existing.get("actor_id")?.as_str()?.to_string()))
+            .unwrap_or(current_actor_id);
+        let value = serde_json::json!({
+            "did": did,
+            "actor_id": actor_id,
+        });
+        let _ = doc.put(automerge::ROOT, &key, value.to_string());
+        self.metrics.lock().await.change_count += 1;
+    }
+
     /// Get the channel founder's DID.
     pub async fn founder(&self, channel: &str) -> Option<String> {
         let doc = self.doc.lock().await;
@@ -737,6 +762,37 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn replaced_founder_survives_sync() {
+        let doc1 = ClusterDoc::new("server-1");
+        let doc2 = ClusterDoc::new("server-2");
+
+        doc1.set_founder("#test", "did:plc:alice").await;
+        for _ in 0..10 {
+            if let Some(msg) = doc1.generate_sync_message("server-2").await {
+                doc2.receive_sync_message("server-1", &msg).await.unwrap();
+            }
+            if let Some(msg) = doc2.generate_sync_message("server-1").await {
+                doc1.receive_sync_message("server-2", &msg).await.unwrap();
+            }
+        }
+
+        // Server-2 adopts a chain owned by bob; server-1 can't take it back
+        // with an ordinary set_founder.
+        doc2.replace_founder("#test", "did:plc:bob").await;
+        for _ in 0..10 {
+            if let Some(msg) = doc1.generate_sync_message("server-2").await {
+                doc2.receive_sync_message("server-1", &msg).await.unwrap();
+            }
+            if let Some(msg) = doc2.generate_sync_message("server-1").await {
+                doc1.receive_sync_message("server-2", &msg).await.unwrap();
+            }
+        }
+        doc1.set_founder("#test", "did:plc:alice").await;
+        assert_eq!(doc1.founder("#test").await, Some("did:plc:bob".to_string()));
+        assert_eq!(doc2.founder("#test").await, Some("did:plc:bob".to_string()));
+    }
+
     #[tokio::test]
     async fn did_ops_sync() {
         let doc1 = ClusterDoc::new("server-1");
@@ -8,6 +8,7 @@ use std::path::Path;
 
 use rusqlite::{Connection, Result as SqlResult, params};
 
+use crate::policy::types::HistoryVisibility;
 use crate::server::{BanEntry, ChannelState, TopicInfo};
 
 /// Prefix for encrypted-at-rest message content.
@@ -97,6 +98,15 @@ pub fn canonical_dm_key(did_a: &str, did_b: &str) -> String {
     }
 }
 
+/// Prefix of the stand-in a guest (no DID) gets in place of a DID in a
+/// [`canonical_dm_key`].
+pub const GUEST_DM_PREFIX: &str = "guest:";
+
+/// A guest's stand-in for a DID in a [`canonical_dm_key`]: its folded nick.
+pub fn guest_dm_party(nick: &str) -> String {
+    format!("{GUEST_DM_PREFIX}{}", crate::casemap::fold(nick))
+}
+
 /// Database handle wrapping a SQLite connection.
 pub struct Db {
     conn: Connection,
@@ -133,6 +143,9 @@ pub struct MessageRow {
     pub deleted_at: Option<u64>,
     /// DID of the sender (if authenticated at send time).
     pub sender_did: Option<String>,
+    /// For replies (`+reply`), the msgid of the message that started the
+    /// thread. `None` for messages that aren't replies.
+    pub thread_root: Option<String>,
 }
 
 /// A persisted private-media metadata row. The bytes themselves live
@@ -151,6 +164,74 @@ pub struct MediaRow {
     pub deleted_at: Option<u64>,
 }
 
+/// One day of a channel's activity rollup (see `stats`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelStatsRow {
+    /// Days since the Unix epoch, UTC.
+    pub day: u64,
+    pub messages: u64,
+    /// DIDs that sent at least one message that day.
+    pub active_dids: u64,
+    pub peak_members: u64,
+}
+
+/// A moderation case file (see `CASE`).
+#[derive(Debug, Clone)]
+pub struct ModCase {
+    /// Case number, unique across channels.
+    pub id: i64,
+    pub channel: String,
+    /// DID the case is about.
+    pub subject_did: String,
+    pub summary: String,
+    /// DID of the moderator who opened it.
+    pub opened_by: String,
+    pub opened_at: u64,
+    pub closed_by: Option<String>,
+    pub closed_at: Option<u64>,
+    pub resolution: Option<String>,
+}
+
+/// What a [`ModCaseEntry`] records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModCaseEntryKind {
+    /// A message from the channel's history, copied into the case.
+    Evidence,
+    /// Something a moderator did about the case.
+    Action,
+}
+
+impl ModCaseEntryKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Evidence => "evidence",
+            Self::Action => "action",
+        }
+    }
+
+    fn parse(s: &str) -> Self {
+        match s {
+            "evidence" => Self::Evidence,
+            _ => Self::Action,
+        }
+    }
+}
+
+/// Evidence or an action attached to a [`ModCase`].
+#[derive(Debug, Clone)]
+pub struct ModCaseEntry {
+    pub kind: ModCaseEntryKind,
+    /// For evidence: the message's msgid, sender hostmask and send time.
+    pub msgid: Option<String>,
+    pub sender: Option<String>,
+    pub sent_at: Option<u64>,
+    /// The message text (evidence) or what was done (action).
+    pub text: String,
+    /// DID of the moderator who added it.
+    pub added_by: String,
+    pub added_at: u64,
+}
+
 /// A persisted identity (DID-nick binding).
 #[derive(Debug, Clone)]
 pub struct IdentityRow {
@@ -203,6 +284,16 @@ impl Db {
         Ok(db)
     }
 
+    /// Read the message table, to check the database is reachable.
+    pub fn ping(&self) -> SqlResult<()> {
+        self.conn
+            .query_row("SELECT 1 FROM messages LIMIT 1", [], |_| Ok(()))
+            .or_else(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => Ok(()),
+                e => Err(e),
+            })
+    }
+
     fn init(&self) -> SqlResult<()> {
         self.conn.execute_batch("PRAGMA journal_mode=WAL;")?;
         self.conn.execute_batch("PRAGMA foreign_keys=ON;")?;
@@ -219,7 +310,14 @@ impl Db {
                 moderated    INTEGER NOT NULL DEFAULT 0,
                 key          TEXT,
                 founder_did  TEXT,
-                did_ops_json TEXT NOT NULL DEFAULT '[]'
+                did_ops_json TEXT NOT NULL DEFAULT '[]',
+                archived     INTEGER NOT NULL DEFAULT 0,
+                auditorium   INTEGER NOT NULL DEFAULT 0,
+                history_visibility TEXT,
+                no_stats     INTEGER NOT NULL DEFAULT 0,
+                temporary    TEXT,
+                bots_restricted INTEGER NOT NULL DEFAULT 0,
+                secret       INTEGER NOT NULL DEFAULT 0
             );
 
             CREATE TABLE IF NOT EXISTS bans (
@@ -240,6 +338,24 @@ impl Db {
                 UNIQUE(channel, mask)
             );
 
+            CREATE TABLE IF NOT EXISTS quiets (
+                id       INTEGER PRIMARY KEY AUTOINCREMENT,
+                channel  TEXT NOT NULL,
+                mask     TEXT NOT NULL,
+                set_by   TEXT NOT NULL,
+                set_at   INTEGER NOT NULL,
+                UNIQUE(channel, mask)
+            );
+
+            CREATE TABLE IF NOT EXISTS bot_allowlist (
+                id       INTEGER PRIMARY KEY AUTOINCREMENT,
+                channel  TEXT NOT NULL,
+                mask     TEXT NOT NULL,
+                set_by   TEXT NOT NULL,
+                set_at   INTEGER NOT NULL,
+                UNIQUE(channel, mask)
+            );
+
             CREATE TABLE IF NOT EXISTS messages (
                 id        INTEGER PRIMARY KEY AUTOINCREMENT,
                 channel   TEXT NOT NULL,
@@ -288,6 +404,19 @@ impl Db {
                 channel TEXT NOT NULL,
                 PRIMARY KEY (did, channel)
             );
+
+            -- Spans during which a DID was a channel member (JOIN until
+            -- PART/KICK; left_at NULL while still in). Bounds what
+            -- history a `members-since-join` channel shows them.
+            CREATE TABLE IF NOT EXISTS membership_windows (
+                did       TEXT NOT NULL,
+                channel   TEXT NOT NULL,
+                joined_at INTEGER NOT NULL,
+                left_at   INTEGER
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_membership_windows
+                ON membership_windows(did, channel);
             ",
         )?;
 
@@ -298,16 +427,28 @@ impl Db {
             "ALTER TABLE channels ADD COLUMN moderated INTEGER NOT NULL DEFAULT 0",
             "ALTER TABLE channels ADD COLUMN founder_did TEXT",
             "ALTER TABLE channels ADD COLUMN did_ops_json TEXT NOT NULL DEFAULT '[]'",
+            "ALTER TABLE channels ADD COLUMN archived INTEGER NOT NULL DEFAULT 0",
+            "ALTER TABLE channels ADD COLUMN auditorium INTEGER NOT NULL DEFAULT 0",
+            "ALTER TABLE channels ADD COLUMN history_visibility TEXT",
+            "ALTER TABLE channels ADD COLUMN no_stats INTEGER NOT NULL DEFAULT 0",
+            "ALTER TABLE channels ADD COLUMN temporary TEXT",
+            "ALTER TABLE channels ADD COLUMN bots_restricted INTEGER NOT NULL DEFAULT 0",
+            "ALTER TABLE channels ADD COLUMN secret INTEGER NOT NULL DEFAULT 0",
             "ALTER TABLE messages ADD COLUMN msgid TEXT",
             "ALTER TABLE messages ADD COLUMN replaces_msgid TEXT",
             "ALTER TABLE messages ADD COLUMN deleted_at INTEGER",
             "ALTER TABLE messages ADD COLUMN sender_did TEXT",
+            "ALTER TABLE messages ADD COLUMN thread_root TEXT",
             "ALTER TABLE identities ADD COLUMN last_auth_at INTEGER",
         ];
         for sql in &migrations {
             // Ignore "duplicate column name" errors — means column already exists
             let _ = self.conn.execute(sql, []);
         }
+        self.conn.execute_batch(
+            "CREATE INDEX IF NOT EXISTS idx_messages_thread_root
+                ON messages(channel, thread_root);",
+        )?;
 
         self.init_fts()?;
 
@@ -437,6 +578,28 @@ impl Db {
             CREATE INDEX IF NOT EXISTS idx_pins_channel ON pins(channel, pinned_at DESC);
             ",
         )?;
+        // Previous topics per channel (TOPICHIST / TOPIC --revert).
+        self.conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS topic_history (
+                channel TEXT NOT NULL,
+                text    TEXT NOT NULL,
+                set_by  TEXT NOT NULL,
+                set_at  INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_topic_history_channel ON topic_history(channel);
+            ",
+        )?;
+        // draft/metadata-2 key/value pairs. `target` is a DID (user
+        // metadata) or a channel name; guest sessions are never persisted.
+        self.conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS metadata (
+                target TEXT NOT NULL,
+                key    TEXT NOT NULL,
+                value  TEXT NOT NULL,
+                PRIMARY KEY (target, key)
+            );
+            ",
+        )?;
         // Private media: metadata for blobs stored encrypted-at-rest on local
         // disk and served via signed capability URLs. The bytes live on disk
         // (see `media_store`), not in this table — only metadata is recorded.
@@ -512,6 +675,127 @@ impl Db {
             ",
         )?;
 
+        // Iroh endpoint bindings: endpoints that completed SASL over iroh,
+        // and the DID they authenticated as.
+        self.conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS iroh_bindings (
+                endpoint_id  TEXT PRIMARY KEY,
+                did          TEXT NOT NULL,
+                bound_at     INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_iroh_bindings_did ON iroh_bindings(did);
+            ",
+        )?;
+
+        // Channel activity rollups (see `stats`). `day` counts days since
+        // the Unix epoch, UTC. The DIDs that spoke are only kept long
+        // enough to count unique weekly speakers.
+        self.conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS channel_stats (
+                channel      TEXT NOT NULL,
+                day          INTEGER NOT NULL,
+                messages     INTEGER NOT NULL DEFAULT 0,
+                active_dids  INTEGER NOT NULL DEFAULT 0,
+                peak_members INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (channel, day)
+            );
+            CREATE TABLE IF NOT EXISTS channel_stats_actors (
+                channel TEXT NOT NULL,
+                day     INTEGER NOT NULL,
+                did     TEXT NOT NULL,
+                PRIMARY KEY (channel, day, did)
+            );
+            CREATE INDEX IF NOT EXISTS idx_channel_stats_actors_day ON channel_stats_actors(day);
+            ",
+        )?;
+        // Moderation case files (CASE). Evidence keeps a copy of each
+        // message, so it outlives history pruning and deletion; entry text
+        // is encrypted at rest like message text.
+        self.conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS mod_cases (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                channel     TEXT NOT NULL,
+                subject_did TEXT NOT NULL,
+                summary     TEXT NOT NULL,
+                opened_by   TEXT NOT NULL,
+                opened_at   INTEGER NOT NULL,
+                closed_by   TEXT,
+                closed_at   INTEGER,
+                resolution  TEXT
+            );
+            CREATE INDEX IF NOT EXISTS idx_mod_cases_channel ON mod_cases(channel, id);
+            CREATE TABLE IF NOT EXISTS mod_case_entries (
+                case_id   INTEGER NOT NULL REFERENCES mod_cases(id),
+                kind      TEXT NOT NULL,   -- 'evidence' or 'action'
+                msgid     TEXT,
+                sender    TEXT,
+                sent_at   INTEGER,
+                text      TEXT NOT NULL,
+                added_by  TEXT NOT NULL,
+                added_at  INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_mod_case_entries_case ON mod_case_entries(case_id);
+            ",
+        )?;
+        // Content filters (FILTER). `scope` is a channel or '*' for every
+        // message; `replacement` is only set for the replace action.
+        self.conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS content_filters (
+                id          INTEGER PRIMARY KEY,
+                scope       TEXT NOT NULL,
+                syntax      TEXT NOT NULL,   -- 'regex' or 'glob'
+                pattern     TEXT NOT NULL,
+                action      TEXT NOT NULL,   -- 'block', 'replace', 'flag' or 'notify'
+                replacement TEXT,
+                created_by  TEXT NOT NULL,
+                created_at  INTEGER NOT NULL
+            );
+            ",
+        )?;
+        // Channel invite links (INVITELINK). `waives_json` is a JSON array
+        // of the policy credential/proof types the invite waives.
+        self.conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS channel_invites (
+                id          TEXT PRIMARY KEY,
+                channel     TEXT NOT NULL,
+                created_by  TEXT NOT NULL,
+                created_at  INTEGER NOT NULL,
+                expires_at  INTEGER,
+                max_uses    INTEGER,
+                uses        INTEGER NOT NULL DEFAULT 0,
+                waives_json TEXT NOT NULL DEFAULT '[]'
+            );
+            CREATE INDEX IF NOT EXISTS idx_channel_invites_channel ON channel_invites(channel);
+            ",
+        )?;
+        // Email notification addresses (EMAIL), one per DID. The
+        // `window_*` columns count digests for the daily cap.
+        self.conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS email_subscriptions (
+                did               TEXT PRIMARY KEY,
+                address           TEXT NOT NULL,
+                verified          INTEGER NOT NULL DEFAULT 0,
+                verify_token      TEXT,
+                verify_expires_at INTEGER,
+                unsubscribe_token TEXT NOT NULL UNIQUE,
+                last_sent_at      INTEGER,
+                window_start      INTEGER NOT NULL,
+                window_sent       INTEGER NOT NULL DEFAULT 0
+            );
+            ",
+        )?;
+        // Per-DID preferences (PREF); only values that differ from the
+        // key's default are stored.
+        self.conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS preferences (
+                did   TEXT NOT NULL,
+                key   TEXT NOT NULL,
+                value TEXT NOT NULL,
+                PRIMARY KEY (did, key)
+            );
+            ",
+        )?;
+
         Ok(())
     }
 
@@ -579,7 +863,7 @@ impl Db {
             let before_ts = before.map(|b| b as i64).unwrap_or(i64::MAX);
             let mut stmt = self.conn.prepare(
                 "SELECT m.id, m.channel, m.sender, m.text, m.timestamp, m.tags_json,
-                        m.msgid, m.replaces_msgid, m.deleted_at, m.sender_did
+                        m.msgid, m.replaces_msgid, m.deleted_at, m.sender_did, m.thread_root
                  FROM messages_fts
                  JOIN messages m ON m.id = messages_fts.rowid
                  WHERE messages_fts MATCH ?1
@@ -605,7 +889,7 @@ impl Db {
         let before_ts = before.map(|b| b as i64).unwrap_or(i64::MAX);
         let mut stmt = self.conn.prepare(
             "SELECT id, channel, sender, text, timestamp, tags_json,
-                    msgid, replaces_msgid, deleted_at, sender_did
+                    msgid, replaces_msgid, deleted_at, sender_did, thread_root
              FROM messages
              WHERE channel = ?1 AND deleted_at IS NULL AND timestamp < ?2
              ORDER BY timestamp DESC, id DESC
@@ -637,8 +921,8 @@ impl Db {
         let did_ops_json = serde_json::to_string(&ch.did_ops.iter().collect::<Vec<_>>())
             .unwrap_or_else(|_| "[]".to_string());
         self.conn.execute(
-            "INSERT INTO channels (name, topic_text, topic_set_by, topic_set_at, topic_locked, invite_only, no_ext_msg, moderated, key, founder_did, did_ops_json)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+            "INSERT INTO channels (name, topic_text, topic_set_by, topic_set_at, topic_locked, invite_only, no_ext_msg, moderated, key, founder_did, did_ops_json, archived, auditorium, history_visibility, no_stats, temporary, bots_restricted, secret)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)
              ON CONFLICT(name) DO UPDATE SET
                 topic_text=excluded.topic_text,
                 topic_set_by=excluded.topic_set_by,
@@ -649,7 +933,14 @@ impl Db {
                 moderated=excluded.moderated,
                 key=excluded.key,
                 founder_did=excluded.founder_did,
-                did_ops_json=excluded.did_ops_json",
+                did_ops_json=excluded.did_ops_json,
+                archived=excluded.archived,
+                auditorium=excluded.auditorium,
+                history_visibility=excluded.history_visibility,
+                no_stats=excluded.no_stats,
+                temporary=excluded.temporary,
+                bots_restricted=excluded.bots_restricted,
+                secret=excluded.secret",
             params![
                 name,
                 ch.topic.as_ref().map(|t| &t.text),
@@ -662,6 +953,13 @@ impl Db {
                 ch.key.as_deref(),
                 ch.founder_did.as_deref(),
                 did_ops_json,
+                ch.archived as i32,
+                ch.auditorium as i32,
+                ch.history_visibility.as_str(),
+                ch.no_stats as i32,
+                ch.temporary.map(|t| t.to_string()),
+                ch.bots_restricted as i32,
+                ch.secret as i32,
             ],
         )?;
         Ok(())
@@ -677,6 +975,56 @@ impl Db {
             "DELETE FROM invite_exceptions WHERE channel = ?1",
             params![name],
         )?;
+        self.conn
+            .execute("DELETE FROM quiets WHERE channel = ?1", params![name])?;
+        self.conn.execute(
+            "DELETE FROM bot_allowlist WHERE channel = ?1",
+            params![name],
+        )?;
+        self.conn.execute(
+            "DELETE FROM topic_history WHERE channel = ?1",
+            params![name],
+        )?;
+        self.conn
+            .execute("DELETE FROM metadata WHERE target = ?1", params![name])?;
+        Ok(())
+    }
+
+    /// Delete everything stored for a channel: what [`Db::delete_channel`]
+    /// removes plus its messages, pins, reactions, membership records,
+    /// statistics and content filters. For expired temporary (+T)
+    /// channels.
+    pub fn purge_channel(&self, name: &str) -> SqlResult<()> {
+        self.delete_channel(name)?;
+        self.delete_channel_stats(name)?;
+        if self.fts_enabled() {
+            self.conn.execute(
+                "DELETE FROM messages_fts WHERE rowid IN (
+                    SELECT id FROM messages WHERE channel = ?1
+                )",
+                params![name],
+            )?;
+        }
+        for table in [
+            "messages",
+            "pins",
+            "reactions",
+            "user_channels",
+            "membership_windows",
+        ] {
+            self.conn.execute(
+                &format!("DELETE FROM {table} WHERE channel = ?1"),
+                params![name],
+            )?;
+        }
+        self.conn.execute(
+            "DELETE FROM content_filters WHERE scope = ?1",
+            params![name],
+        )?;
+        self.conn.execute(
+            "DELETE FROM channel_invites WHERE channel = ?1",
+            params![name],
+        )?;
         Ok(())
     }
 
@@ -686,7 +1034,7 @@ impl Db {
         let mut channels = HashMap::new();
 
         let mut stmt = self.conn.prepare(
-            "SELECT name, topic_text, topic_set_by, topic_set_at, topic_locked, invite_only, key, no_ext_msg, moderated, founder_did, did_ops_json
+            "SELECT name, topic_text, topic_set_by, topic_set_at, topic_locked, invite_only, key, no_ext_msg, moderated, founder_did, did_ops_json, archived, auditorium, history_visibility, no_stats, temporary, bots_restricted, secret
              FROM channels"
         )?;
         let rows = stmt.query_map([], |row| {
@@ -703,6 +1051,18 @@ impl Db {
             let did_ops_json: String = row
                 .get::<_, Option<String>>(10)?
                 .unwrap_or_else(|| "[]".to_string());
+            let archived: bool = row.get::<_, Option<i32>>(11)?.unwrap_or(0) != 0;
+            let auditorium: bool = row.get::<_, Option<i32>>(12)?.unwrap_or(0) != 0;
+            let history_visibility = row
+                .get::<_, Option<String>>(13)?
+                .and_then(|v| HistoryVisibility::parse(&v))
+                .unwrap_or_default();
+            let no_stats: bool = row.get::<_, Option<i32>>(14)?.unwrap_or(0) != 0;
+            let temporary = row
+                .get::<_, Option<String>>(15)?
+                .and_then(|v| crate::temporary::Temporary::parse(&v));
+            let bots_restricted: bool = row.get::<_, Option<i32>>(16)?.unwrap_or(0) != 0;
+            let secret: bool = row.get::<_, Option<i32>>(17)?.unwrap_or(0) != 0;
 
             let topic = match (topic_text, topic_set_by, topic_set_at) {
                 (Some(text), Some(set_by), Some(set_at)) => Some(TopicInfo {
@@ -725,6 +1085,13 @@ impl Db {
                 key,
                 founder_did,
                 did_ops,
+                archived,
+                auditorium,
+                history_visibility,
+                no_stats,
+                temporary,
+                bots_restricted,
+                secret,
                 ..Default::default()
             };
             Ok((name, ch))
@@ -787,6 +1154,58 @@ impl Db {
             }
         }
 
+        // Load quiets (+q)
+        let mut stmt = self
+            .conn
+            .prepare("SELECT channel, mask, set_by, set_at FROM quiets")?;
+        let quiet_rows = stmt.query_map([], |row| {
+            let channel: String = row.get(0)?;
+            let mask: String = row.get(1)?;
+            let set_by: String = row.get(2)?;
+            let set_at: i64 = row.get(3)?;
+            Ok((
+                channel,
+                BanEntry {
+                    mask,
+                    set_by,
+                    set_at: set_at as u64,
+                },
+            ))
+        })?;
+
+        for row in quiet_rows {
+            let (channel, quiet) = row?;
+            if let Some(ch) = channels.get_mut(&channel) {
+                ch.quiets.push(quiet);
+            }
+        }
+
+        // Load the bot allowlist (+W)
+        let mut stmt = self
+            .conn
+            .prepare("SELECT channel, mask, set_by, set_at FROM bot_allowlist")?;
+        let allow_rows = stmt.query_map([], |row| {
+            let channel: String = row.get(0)?;
+            let mask: String = row.get(1)?;
+            let set_by: String = row.get(2)?;
+            let set_at: i64 = row.get(3)?;
+            Ok((
+                channel,
+                BanEntry {
+                    mask,
+                    set_by,
+                    set_at: set_at as u64,
+                },
+            ))
+        })?;
+
+        for row in allow_rows {
+            let (channel, entry) = row?;
+            if let Some(ch) = channels.get_mut(&channel) {
+                ch.bot_allowlist.push(entry);
+            }
+        }
+
         // Load pins
         let mut stmt = self.conn.prepare(
             "SELECT channel, msgid, pinned_by, pinned_at FROM pins ORDER BY pinned_at DESC",
@@ -813,6 +1232,27 @@ impl Db {
             }
         }
 
+        // Load topic history (oldest first, rowid breaks set_at ties)
+        let mut stmt = self.conn.prepare(
+            "SELECT channel, text, set_by, set_at FROM topic_history ORDER BY set_at, rowid",
+        )?;
+        let topic_rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                TopicInfo {
+                    text: row.get(1)?,
+                    set_by: row.get(2)?,
+                    set_at: row.get::<_, i64>(3)? as u64,
+                },
+            ))
+        })?;
+        for row in topic_rows {
+            let (channel, topic) = row?;
+            if let Some(ch) = channels.get_mut(&channel) {
+                ch.topic_history.push_back(topic);
+            }
+        }
+
         Ok(channels)
     }
 
@@ -860,6 +1300,46 @@ impl Db {
         Ok(())
     }
 
+    // ── Quiets (+q) ────────────────────────────────────────────────────
+
+    /// Add a quiet entry to a channel.
+    pub fn add_quiet(&self, channel: &str, quiet: &BanEntry) -> SqlResult<()> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO quiets (channel, mask, set_by, set_at) VALUES (?1, ?2, ?3, ?4)",
+            params![channel, quiet.mask, quiet.set_by, quiet.set_at as i64],
+        )?;
+        Ok(())
+    }
+
+    /// Remove a quiet entry from a channel.
+    pub fn remove_quiet(&self, channel: &str, mask: &str) -> SqlResult<()> {
+        self.conn.execute(
+            "DELETE FROM quiets WHERE channel = ?1 AND mask = ?2",
+            params![channel, mask],
+        )?;
+        Ok(())
+    }
+
+    // ── Bot allowlist (+W) ─────────────────────────────────────────────
+
+    /// Allow a bot to speak in a channel under +B.
+    pub fn add_bot_allow(&self, channel: &str, entry: &BanEntry) -> SqlResult<()> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO bot_allowlist (channel, mask, set_by, set_at) VALUES (?1, ?2, ?3, ?4)",
+            params![channel, entry.mask, entry.set_by, entry.set_at as i64],
+        )?;
+        Ok(())
+    }
+
+    /// Remove a bot allowlist entry from a channel.
+    pub fn remove_bot_allow(&self, channel: &str, mask: &str) -> SqlResult<()> {
+        self.conn.execute(
+            "DELETE FROM bot_allowlist WHERE channel = ?1 AND mask = ?2",
+            params![channel, mask],
+        )?;
+        Ok(())
+    }
+
     // ── Messages ───────────────────────────────────────────────────────
 
     /// Store a message.
@@ -879,9 +1359,16 @@ impl Db {
         } else {
             text.to_string()
         };
+        let thread_root = match reply_parent(tags) {
+            Some(parent) => Some(
+                self.thread_root_of(channel, parent)?
+                    .unwrap_or_else(|| parent.to_string()),
+            ),
+            None => None,
+        };
         self.conn.execute(
-            "INSERT INTO messages (channel, sender, text, timestamp, tags_json, msgid, sender_did)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            "INSERT INTO messages (channel, sender, text, timestamp, tags_json, msgid, sender_did, thread_root)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
             params![
                 channel,
                 sender,
@@ -889,7 +1376,8 @@ impl Db {
                 timestamp as i64,
                 tags_json,
                 msgid,
-                sender_did
+                sender_did,
+                thread_root
             ],
         )?;
         // Record into the agent-assist diagnostic ring buffer. We
@@ -919,7 +1407,7 @@ impl Db {
     ) -> SqlResult<Vec<MessageRow>> {
         let mut rows_vec = if let Some(before_ts) = before {
             let mut stmt = self.conn.prepare(
-                "SELECT id, channel, sender, text, timestamp, tags_json, msgid, replaces_msgid, deleted_at, sender_did
+                "SELECT id, channel, sender, text, timestamp, tags_json, msgid, replaces_msgid, deleted_at, sender_did, thread_root
                  FROM messages
                  WHERE channel = ?1 AND deleted_at IS NULL AND timestamp < ?2
                  ORDER BY timestamp DESC, id DESC
@@ -932,7 +1420,7 @@ impl Db {
             rows.collect::<SqlResult<Vec<_>>>()?
         } else {
             let mut stmt = self.conn.prepare(
-                "SELECT id, channel, sender, text, timestamp, tags_json, msgid, replaces_msgid, deleted_at, sender_did
+                "SELECT id, channel, sender, text, timestamp, tags_json, msgid, replaces_msgid, deleted_at, sender_did, thread_root
                  FROM messages
                  WHERE channel = ?1 AND deleted_at IS NULL
                  ORDER BY timestamp DESC, id DESC
@@ -960,7 +1448,7 @@ impl Db {
         limit: usize,
     ) -> SqlResult<Vec<MessageRow>> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, channel, sender, text, timestamp, tags_json, msgid, replaces_msgid, deleted_at, sender_did
+            "SELECT id, channel, sender, text, timestamp, tags_json, msgid, replaces_msgid, deleted_at, sender_did, thread_root
              FROM messages
              WHERE channel = ?1 AND deleted_at IS NULL AND timestamp > ?2
              ORDER BY timestamp ASC, id ASC
@@ -988,7 +1476,7 @@ impl Db {
         limit: usize,
     ) -> SqlResult<Vec<MessageRow>> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, channel, sender, text, timestamp, tags_json, msgid, replaces_msgid, deleted_at, sender_did
+            "SELECT id, channel, sender, text, timestamp, tags_json, msgid, replaces_msgid, deleted_at, sender_did, thread_root
              FROM messages
              WHERE channel = ?1 AND deleted_at IS NULL AND timestamp > ?2 AND timestamp < ?3
              ORDER BY timestamp ASC, id ASC
@@ -1007,6 +1495,81 @@ impl Db {
         Ok(result)
     }
 
+    /// Thread root of a stored message: its own `thread_root` if it is a
+    /// reply, otherwise its msgid. `None` if `msgid` isn't stored for `channel`.
+    pub fn thread_root_of(&self, channel: &str, msgid: &str) -> SqlResult<Option<String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT COALESCE(thread_root, msgid) FROM messages
+             WHERE channel = ?1 AND msgid = ?2
+             LIMIT 1",
+        )?;
+        let mut rows = stmt.query_map(params![channel, msgid], |row| row.get(0))?;
+        rows.next().transpose()
+    }
+
+    /// A thread: the root message followed by every reply in it, oldest first.
+    pub fn get_thread(
+        &self,
+        channel: &str,
+        root: &str,
+        limit: usize,
+    ) -> SqlResult<Vec<MessageRow>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, channel, sender, text, timestamp, tags_json, msgid, replaces_msgid, deleted_at, sender_did, thread_root
+             FROM messages
+             WHERE channel = ?1 AND deleted_at IS NULL AND (msgid = ?2 OR thread_root = ?2)
+             ORDER BY timestamp ASC, id ASC
+             LIMIT ?3",
+        )?;
+        let rows = stmt.query_map(params![channel, root, limit as i64], map_message_row)?;
+        let mut result = rows.collect::<SqlResult<Vec<_>>>()?;
+        if let Some(ref key) = self.encryption_key {
+            for row in &mut result {
+                row.text = decrypt_at_rest(key, &row.text);
+            }
+        }
+        Ok(result)
+    }
+
+    /// A page of a public (+A) channel archive, oldest first, paginated by
+    /// row id: `before` gives the `limit` rows preceding that id, `after`
+    /// the `limit` rows following it, neither the latest `limit`. Deleted
+    /// and `+encrypted` messages are left out.
+    pub fn get_archive_page(
+        &self,
+        channel: &str,
+        before: Option<i64>,
+        after: Option<i64>,
+        limit: usize,
+    ) -> SqlResult<Vec<MessageRow>> {
+        let (bound, order) = match (before, after) {
+            (_, Some(_)) => ("id > ?2", "ASC"),
+            (Some(_), None) => ("id < ?2", "DESC"),
+            // Row ids start at 1, so this is every row.
+            (None, None) => ("id > ?2", "DESC"),
+        };
+        let cursor = after.or(before).unwrap_or(0);
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT id, channel, sender, text, timestamp, tags_json, msgid, replaces_msgid, deleted_at, sender_did, thread_root
+             FROM messages
+             WHERE channel = ?1 AND deleted_at IS NULL AND {bound}
+               AND tags_json NOT LIKE '%\"+encrypted\":%'
+             ORDER BY id {order}
+             LIMIT ?3"
+        ))?;
+        let rows = stmt.query_map(params![channel, cursor, limit as i64], map_message_row)?;
+        let mut result = rows.collect::<SqlResult<Vec<_>>>()?;
+        if order == "DESC" {
+            result.reverse();
+        }
+        if let Some(ref key) = self.encryption_key {
+            for row in &mut result {
+                row.text = decrypt_at_rest(key, &row.text);
+            }
+        }
+        Ok(result)
+    }
+
     /// Prune old messages for a channel, keeping only the most recent `max_keep`.
     pub fn prune_messages(&self, channel: &str, max_keep: usize) -> SqlResult<()> {
         if self.fts_enabled() {
@@ -1035,7 +1598,7 @@ impl Db {
         msgid: &str,
     ) -> SqlResult<Option<MessageRow>> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, channel, sender, text, timestamp, tags_json, msgid, replaces_msgid, deleted_at, sender_did
+            "SELECT id, channel, sender, text, timestamp, tags_json, msgid, replaces_msgid, deleted_at, sender_did, thread_root
              FROM messages
              WHERE channel = ?1 AND msgid = ?2
              LIMIT 1"
@@ -1056,7 +1619,7 @@ impl Db {
     /// Find a message by msgid across all channels.
     pub fn find_message_by_msgid(&self, msgid: &str) -> SqlResult<Option<MessageRow>> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, channel, sender, text, timestamp, tags_json, msgid, replaces_msgid, deleted_at, sender_did
+            "SELECT id, channel, sender, text, timestamp, tags_json, msgid, replaces_msgid, deleted_at, sender_did, thread_root
              FROM messages
              WHERE msgid = ?1 AND deleted_at IS NULL
              LIMIT 1",
@@ -1095,16 +1658,84 @@ impl Db {
         Ok(changed)
     }
 
-    /// Record metadata for a privately-stored media object.
-    #[allow(clippy::too_many_arguments)]
-    pub fn insert_media(
-        &self,
-        id: &str,
-        uploader_did: &str,
-        scope: &str,
-        mime: &str,
-        size: u64,
-        alt: Option<&str>,
+    /// Every message `did` authored, oldest first — deleted ones too, with
+    /// whatever text they still hold. Erased messages come back empty.
+    pub fn messages_by_sender_did(&self, did: &str) -> SqlResult<Vec<MessageRow>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, channel, sender, text, timestamp, tags_json, msgid, replaces_msgid, deleted_at, sender_did, thread_root
+             FROM messages
+             WHERE sender_did = ?1
+             ORDER BY timestamp ASC, id ASC",
+        )?;
+        let rows = stmt.query_map(params![did], map_message_row)?;
+        let mut rows_vec = rows.collect::<SqlResult<Vec<_>>>()?;
+        if let Some(ref key) = self.encryption_key {
+            for row in &mut rows_vec {
+                row.text = decrypt_at_rest(key, &row.text);
+            }
+        }
+        Ok(rows_vec)
+    }
+
+    /// Erase the content of messages `did` authored: the text and tags are
+    /// blanked, the search index entry dropped and the row marked deleted.
+    /// The row itself stays so msgid references (replies, pins, reactions)
+    /// resolve to a tombstone. `only` limits erasure to those msgids.
+    ///
+    /// Returns `(channel, msgid)` for each message that still had content.
+    pub fn erase_messages_by_did(
+        &self,
+        did: &str,
+        only: Option<&[String]>,
+    ) -> SqlResult<Vec<(String, String)>> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let targets: Vec<(i64, String, Option<String>)> = {
+            let mut stmt = self.conn.prepare(
+                "SELECT id, channel, msgid FROM messages
+                 WHERE sender_did = ?1 AND (text != '' OR tags_json != '{}')",
+            )?;
+            let rows = stmt.query_map(params![did], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })?;
+            rows.collect::<SqlResult<Vec<_>>>()?
+        };
+        let tx = self.conn.unchecked_transaction()?;
+        let mut erased = Vec::new();
+        for (id, channel, msgid) in targets {
+            if let Some(only) = only
+                && !msgid.as_ref().is_some_and(|m| only.contains(m))
+            {
+                continue;
+            }
+            if self.fts_enabled() {
+                tx.execute("DELETE FROM messages_fts WHERE rowid = ?1", params![id])?;
+            }
+            tx.execute(
+                "UPDATE messages SET text = '', tags_json = '{}', deleted_at = COALESCE(deleted_at, ?1)
+                 WHERE id = ?2",
+                params![now as i64, id],
+            )?;
+            if let Some(msgid) = msgid {
+                erased.push((channel, msgid));
+            }
+        }
+        tx.commit()?;
+        Ok(erased)
+    }
+
+    /// Record metadata for a privately-stored media object.
+    #[allow(clippy::too_many_arguments)]
+    pub fn insert_media(
+        &self,
+        id: &str,
+        uploader_did: &str,
+        scope: &str,
+        mime: &str,
+        size: u64,
+        alt: Option<&str>,
         filename: &str,
         created_at: u64,
     ) -> SqlResult<()> {
@@ -1312,6 +1943,30 @@ impl Db {
         rows.collect()
     }
 
+    // ── Topic history ─────────────────────────────────────────────────
+
+    /// Replace a channel's stored topic history with `history` (oldest
+    /// first). The list is bounded by `TOPIC_HISTORY_MAX`, so rewriting
+    /// it is cheaper than tracking individual inserts and prunes.
+    pub fn save_topic_history<'a>(
+        &self,
+        channel: &str,
+        history: impl IntoIterator<Item = &'a TopicInfo>,
+    ) -> SqlResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "DELETE FROM topic_history WHERE channel = ?1",
+            params![channel],
+        )?;
+        for t in history {
+            tx.execute(
+                "INSERT INTO topic_history (channel, text, set_by, set_at) VALUES (?1, ?2, ?3, ?4)",
+                params![channel, t.text, t.set_by, t.set_at as i64],
+            )?;
+        }
+        tx.commit()
+    }
+
     /// Get raw (potentially encrypted) message text for testing.
     /// Returns the stored text without decryption.
     pub fn get_raw_message_text(&self, channel: &str, timestamp: u64) -> SqlResult<String> {
@@ -1377,6 +2032,33 @@ impl Db {
         Ok(())
     }
 
+    // ── Metadata (draft/metadata-2) ─────────────────────────────────
+
+    /// Set (`Some`) or remove (`None`) one metadata key for a DID or channel.
+    pub fn set_metadata(&self, target: &str, key: &str, value: Option<&str>) -> SqlResult<()> {
+        match value {
+            Some(value) => self.conn.execute(
+                "INSERT INTO metadata (target, key, value) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(target, key) DO UPDATE SET value=excluded.value",
+                params![target, key, value],
+            )?,
+            None => self.conn.execute(
+                "DELETE FROM metadata WHERE target = ?1 AND key = ?2",
+                params![target, key],
+            )?,
+        };
+        Ok(())
+    }
+
+    /// Load every persisted metadata entry as `(target, key, value)`.
+    pub fn load_all_metadata(&self) -> SqlResult<Vec<(String, String, String)>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT target, key, value FROM metadata")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        rows.collect()
+    }
+
     // ── Pre-key bundles (E2EE) ────────────────────────────────────────
 
     /// Store or update a pre-key bundle for a DID.
@@ -1426,7 +2108,7 @@ impl Db {
              ON CONFLICT(channel, member_did, epoch)
              DO UPDATE SET sealed_wire=excluded.sealed_wire, updated_at=excluded.updated_at",
             params![
-                channel.to_lowercase(),
+                crate::casemap::fold(channel),
                 member_did,
                 epoch,
                 sealed_wire,
@@ -1447,7 +2129,7 @@ impl Db {
             "SELECT epoch, sealed_wire FROM group_keys
              WHERE channel = ?1 AND member_did = ?2 ORDER BY epoch DESC",
         )?;
-        let rows = stmt.query_map(params![channel.to_lowercase(), member_did], |row| {
+        let rows = stmt.query_map(params![crate::casemap::fold(channel), member_did], |row| {
             Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
         })?;
         rows.collect()
@@ -1512,24 +2194,68 @@ impl Db {
 
     // ── User channel persistence (auto-rejoin) ────────────────────────
 
-    /// Record that a DID-authenticated user has joined a channel.
+    /// Record that a DID-authenticated user has joined a channel, opening
+    /// a membership window unless one is already open.
     pub fn add_user_channel(&self, did: &str, channel: &str) -> SqlResult<()> {
         self.conn.execute(
             "INSERT OR IGNORE INTO user_channels (did, channel) VALUES (?1, ?2)",
             params![did, channel],
         )?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        self.conn.execute(
+            "INSERT INTO membership_windows (did, channel, joined_at)
+             SELECT ?1, ?2, ?3
+             WHERE NOT EXISTS (
+                 SELECT 1 FROM membership_windows
+                 WHERE did = ?1 AND channel = ?2 AND left_at IS NULL
+             )",
+            params![did, channel, now],
+        )?;
         Ok(())
     }
 
-    /// Record that a DID-authenticated user has left a channel.
+    /// Record that a DID-authenticated user has left a channel, closing
+    /// their open membership window.
     pub fn remove_user_channel(&self, did: &str, channel: &str) -> SqlResult<()> {
         self.conn.execute(
             "DELETE FROM user_channels WHERE did = ?1 AND channel = ?2",
             params![did, channel],
         )?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        self.conn.execute(
+            "UPDATE membership_windows SET left_at = ?3
+             WHERE did = ?1 AND channel = ?2 AND left_at IS NULL",
+            params![did, channel, now],
+        )?;
         Ok(())
     }
 
+    /// Every span `did` has been a member of `channel`, oldest first, as
+    /// `(joined_at, left_at)` unix seconds; `None` while still a member.
+    pub fn membership_windows(
+        &self,
+        did: &str,
+        channel: &str,
+    ) -> SqlResult<Vec<(u64, Option<u64>)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT joined_at, left_at FROM membership_windows
+             WHERE did = ?1 AND channel = ?2 ORDER BY joined_at",
+        )?;
+        let rows = stmt.query_map(params![did, channel], |row| {
+            Ok((
+                row.get::<_, i64>(0)? as u64,
+                row.get::<_, Option<i64>>(1)?.map(|t| t as u64),
+            ))
+        })?;
+        rows.collect()
+    }
+
     /// Get all channels a DID-authenticated user was last in.
     pub fn get_user_channels(&self, did: &str) -> SqlResult<Vec<String>> {
         let mut stmt = self
@@ -1600,6 +2326,458 @@ impl Db {
             None => Ok(None),
         }
     }
+
+    // ── Channel statistics ─────────────────────────────────────────────
+
+    /// Add one flush of a channel's counters to its rollup for `day`.
+    pub fn add_channel_stats(
+        &self,
+        channel: &str,
+        day: u64,
+        pending: &crate::stats::Pending,
+    ) -> SqlResult<()> {
+        for did in &pending.dids {
+            self.conn.execute(
+                "INSERT OR IGNORE INTO channel_stats_actors (channel, day, did) VALUES (?1, ?2, ?3)",
+                params![channel, day as i64, did],
+            )?;
+        }
+        self.conn.execute(
+            "INSERT INTO channel_stats (channel, day, messages, active_dids, peak_members)
+             VALUES (?1, ?2, ?3,
+                     (SELECT COUNT(*) FROM channel_stats_actors WHERE channel = ?1 AND day = ?2),
+                     ?4)
+             ON CONFLICT(channel, day) DO UPDATE SET
+                messages = messages + excluded.messages,
+                active_dids = MAX(active_dids, excluded.active_dids),
+                peak_members = MAX(peak_members, excluded.peak_members)",
+            params![
+                channel,
+                day as i64,
+                pending.messages as i64,
+                pending.peak_members as i64
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// A channel's rollups from `since_day` on, oldest first.
+    pub fn channel_stats(&self, channel: &str, since_day: u64) -> SqlResult<Vec<ChannelStatsRow>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT day, messages, active_dids, peak_members FROM channel_stats
+             WHERE channel = ?1 AND day >= ?2 ORDER BY day",
+        )?;
+        let rows = stmt.query_map(params![channel, since_day as i64], |row| {
+            Ok(ChannelStatsRow {
+                day: row.get::<_, i64>(0)? as u64,
+                messages: row.get::<_, i64>(1)? as u64,
+                active_dids: row.get::<_, i64>(2)? as u64,
+                peak_members: row.get::<_, i64>(3)? as u64,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Unique DIDs that spoke in `channel` from `since_day` on.
+    pub fn channel_active_dids(&self, channel: &str, since_day: u64) -> SqlResult<u64> {
+        self.conn.query_row(
+            "SELECT COUNT(DISTINCT did) FROM channel_stats_actors WHERE channel = ?1 AND day >= ?2",
+            params![channel, since_day as i64],
+            |row| Ok(row.get::<_, i64>(0)? as u64),
+        )
+    }
+
+    /// Forget which DIDs spoke before `before_day`.
+    pub fn prune_channel_stats_actors(&self, before_day: u64) -> SqlResult<()> {
+        self.conn.execute(
+            "DELETE FROM channel_stats_actors WHERE day < ?1",
+            params![before_day as i64],
+        )?;
+        Ok(())
+    }
+
+    /// Delete everything stored about a channel's activity.
+    pub fn delete_channel_stats(&self, channel: &str) -> SqlResult<()> {
+        self.conn.execute(
+            "DELETE FROM channel_stats WHERE channel = ?1",
+            params![channel],
+        )?;
+        self.conn.execute(
+            "DELETE FROM channel_stats_actors WHERE channel = ?1",
+            params![channel],
+        )?;
+        Ok(())
+    }
+
+    // ── Moderation cases ───────────────────────────────────────────────
+
+    fn seal(&self, text: &str) -> String {
+        match &self.encryption_key {
+            Some(key) => encrypt_at_rest(key, text),
+            None => text.to_string(),
+        }
+    }
+
+    fn unseal(&self, stored: String) -> String {
+        match &self.encryption_key {
+            Some(key) => decrypt_at_rest(key, &stored),
+            None => stored,
+        }
+    }
+
+    /// Open a case in `channel` about `subject_did`; returns its number.
+    pub fn open_mod_case(
+        &self,
+        channel: &str,
+        subject_did: &str,
+        summary: &str,
+        opened_by: &str,
+        opened_at: u64,
+    ) -> SqlResult<i64> {
+        self.conn.execute(
+            "INSERT INTO mod_cases (channel, subject_did, summary, opened_by, opened_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                channel,
+                subject_did,
+                self.seal(summary),
+                opened_by,
+                opened_at as i64
+            ],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    fn map_mod_case(&self, row: &rusqlite::Row) -> SqlResult<ModCase> {
+        Ok(ModCase {
+            id: row.get(0)?,
+            channel: row.get(1)?,
+            subject_did: row.get(2)?,
+            summary: self.unseal(row.get(3)?),
+            opened_by: row.get(4)?,
+            opened_at: row.get::<_, i64>(5)? as u64,
+            closed_by: row.get(6)?,
+            closed_at: row.get::<_, Option<i64>>(7)?.map(|t| t as u64),
+            resolution: row.get::<_, Option<String>>(8)?.map(|r| self.unseal(r)),
+        })
+    }
+
+    /// Case `id`, if it belongs to `channel`.
+    pub fn mod_case(&self, channel: &str, id: i64) -> SqlResult<Option<ModCase>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, channel, subject_did, summary, opened_by, opened_at,
+                    closed_by, closed_at, resolution
+             FROM mod_cases WHERE channel = ?1 AND id = ?2",
+        )?;
+        let mut rows = stmt.query_map(params![channel, id], |row| self.map_mod_case(row))?;
+        rows.next().transpose()
+    }
+
+    /// A channel's cases, newest first; closed ones only with
+    /// `include_closed`.
+    pub fn mod_cases(&self, channel: &str, include_closed: bool) -> SqlResult<Vec<ModCase>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, channel, subject_did, summary, opened_by, opened_at,
+                    closed_by, closed_at, resolution
+             FROM mod_cases WHERE channel = ?1 AND (?2 OR closed_at IS NULL)
+             ORDER BY id DESC",
+        )?;
+        let rows = stmt.query_map(params![channel, include_closed], |row| {
+            self.map_mod_case(row)
+        })?;
+        rows.collect()
+    }
+
+    /// Close case `id`. Returns false if it is already closed.
+    pub fn close_mod_case(
+        &self,
+        id: i64,
+        closed_by: &str,
+        closed_at: u64,
+        resolution: Option<&str>,
+    ) -> SqlResult<bool> {
+        let changed = self.conn.execute(
+            "UPDATE mod_cases SET closed_by = ?2, closed_at = ?3, resolution = ?4
+             WHERE id = ?1 AND closed_at IS NULL",
+            params![
+                id,
+                closed_by,
+                closed_at as i64,
+                resolution.map(|r| self.seal(r))
+            ],
+        )?;
+        Ok(changed > 0)
+    }
+
+    /// Attach evidence or an action to case `id`.
+    pub fn add_mod_case_entry(&self, id: i64, entry: &ModCaseEntry) -> SqlResult<()> {
+        self.conn.execute(
+            "INSERT INTO mod_case_entries
+                (case_id, kind, msgid, sender, sent_at, text, added_by, added_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                id,
+                entry.kind.as_str(),
+                entry.msgid,
+                entry.sender,
+                entry.sent_at.map(|t| t as i64),
+                self.seal(&entry.text),
+                entry.added_by,
+                entry.added_at as i64
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Everything attached to case `id`, oldest first.
+    pub fn mod_case_entries(&self, id: i64) -> SqlResult<Vec<ModCaseEntry>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT kind, msgid, sender, sent_at, text, added_by, added_at
+             FROM mod_case_entries WHERE case_id = ?1 ORDER BY rowid",
+        )?;
+        let rows = stmt.query_map(params![id], |row| {
+            Ok(ModCaseEntry {
+                kind: ModCaseEntryKind::parse(&row.get::<_, String>(0)?),
+                msgid: row.get(1)?,
+                sender: row.get(2)?,
+                sent_at: row.get::<_, Option<i64>>(3)?.map(|t| t as u64),
+                text: self.unseal(row.get(4)?),
+                added_by: row.get(5)?,
+                added_at: row.get::<_, i64>(6)? as u64,
+            })
+        })?;
+        rows.collect()
+    }
+
+    // ── Iroh endpoint bindings ─────────────────────────────────────────
+
+    /// Bind an iroh endpoint to a DID, replacing any previous binding.
+    pub fn save_iroh_binding(&self, endpoint_id: &str, did: &str, bound_at: i64) -> SqlResult<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO iroh_bindings (endpoint_id, did, bound_at) VALUES (?1, ?2, ?3)",
+            params![endpoint_id, did, bound_at],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_iroh_binding(&self, endpoint_id: &str) -> SqlResult<()> {
+        self.conn.execute(
+            "DELETE FROM iroh_bindings WHERE endpoint_id = ?1",
+            params![endpoint_id],
+        )?;
+        Ok(())
+    }
+
+    /// Every binding as `(endpoint_id, did, bound_at)`.
+    pub fn load_iroh_bindings(&self) -> SqlResult<Vec<(String, String, i64)>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT endpoint_id, did, bound_at FROM iroh_bindings")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        rows.collect()
+    }
+
+    // ── Content filters ────────────────────────────────────────────────
+
+    pub fn save_content_filter(&self, rule: &crate::filters::Rule) -> SqlResult<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO content_filters
+                (id, scope, syntax, pattern, action, replacement, created_by, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                rule.id,
+                rule.scope,
+                rule.syntax.as_str(),
+                rule.pattern,
+                rule.action.name(),
+                rule.action.replacement(),
+                rule.created_by,
+                rule.created_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_content_filter(&self, id: i64) -> SqlResult<()> {
+        self.conn
+            .execute("DELETE FROM content_filters WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    /// Every stored filter that still compiles, oldest first. Rows that
+    /// don't (say, after a regex engine upgrade) are skipped with a warning.
+    pub fn load_content_filters(&self) -> SqlResult<Vec<crate::filters::Rule>> {
+        use crate::filters::{Action, Rule, Syntax};
+
+        let mut stmt = self.conn.prepare(
+            "SELECT id, scope, syntax, pattern, action, replacement, created_by, created_at
+             FROM content_filters ORDER BY id",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, Option<String>>(5)?,
+                row.get::<_, String>(6)?,
+                row.get::<_, i64>(7)?,
+            ))
+        })?;
+        let mut rules = Vec::new();
+        for row in rows {
+            let (id, scope, syntax, pattern, action, replacement, created_by, created_at) = row?;
+            let action = match replacement {
+                Some(text) if action == "replace" => Some(Action::Replace(text)),
+                _ => Action::parse(&action),
+            };
+            let rule = match (Syntax::parse(&syntax), action) {
+                (Some(syntax), Some(action)) => Rule::new(
+                    id,
+                    &scope,
+                    syntax,
+                    &pattern,
+                    action,
+                    &created_by,
+                    created_at,
+                ),
+                _ => Err(format!("unknown syntax {syntax} or action {action}")),
+            };
+            match rule {
+                Ok(rule) => rules.push(rule),
+                Err(e) => tracing::warn!(id, %scope, "Skipping stored content filter: {e}"),
+            }
+        }
+        Ok(rules)
+    }
+
+    // ── Channel invites ────────────────────────────────────────────────
+
+    pub fn save_invite(&self, invite: &crate::invites::Invite) -> SqlResult<()> {
+        let waives = serde_json::to_string(&invite.waives).unwrap_or_else(|_| "[]".into());
+        self.conn.execute(
+            "INSERT OR REPLACE INTO channel_invites
+                (id, channel, created_by, created_at, expires_at, max_uses, uses, waives_json)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                invite.id,
+                invite.channel,
+                invite.created_by,
+                invite.created_at,
+                invite.expires_at,
+                invite.max_uses,
+                invite.uses,
+                waives,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_invite(&self, id: &str) -> SqlResult<()> {
+        self.conn
+            .execute("DELETE FROM channel_invites WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    pub fn load_invites(&self) -> SqlResult<Vec<crate::invites::Invite>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, channel, created_by, created_at, expires_at, max_uses, uses, waives_json
+             FROM channel_invites ORDER BY created_at",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let waives: String = row.get(7)?;
+            Ok(crate::invites::Invite {
+                id: row.get(0)?,
+                channel: row.get(1)?,
+                created_by: row.get(2)?,
+                created_at: row.get(3)?,
+                expires_at: row.get(4)?,
+                max_uses: row.get(5)?,
+                uses: row.get(6)?,
+                waives: serde_json::from_str(&waives).unwrap_or_default(),
+            })
+        })?;
+        rows.collect()
+    }
+
+    // ── Email subscriptions ────────────────────────────────────────────
+
+    pub fn save_email_subscription(&self, sub: &crate::email::Subscription) -> SqlResult<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO email_subscriptions
+                (did, address, verified, verify_token, verify_expires_at, unsubscribe_token,
+                 last_sent_at, window_start, window_sent)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                sub.did,
+                sub.address,
+                sub.verified,
+                sub.verify_token,
+                sub.verify_expires_at,
+                sub.unsubscribe_token,
+                sub.last_sent_at,
+                sub.window_start,
+                sub.window_sent,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_email_subscription(&self, did: &str) -> SqlResult<()> {
+        self.conn
+            .execute("DELETE FROM email_subscriptions WHERE did = ?1", params![did])?;
+        Ok(())
+    }
+
+    pub fn load_email_subscriptions(&self) -> SqlResult<Vec<crate::email::Subscription>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT did, address, verified, verify_token, verify_expires_at, unsubscribe_token,
+                    last_sent_at, window_start, window_sent
+             FROM email_subscriptions",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(crate::email::Subscription {
+                did: row.get(0)?,
+                address: row.get(1)?,
+                verified: row.get(2)?,
+                verify_token: row.get(3)?,
+                verify_expires_at: row.get(4)?,
+                unsubscribe_token: row.get(5)?,
+                last_sent_at: row.get(6)?,
+                window_start: row.get(7)?,
+                window_sent: row.get(8)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    // ── Preferences ────────────────────────────────────────────────────
+
+    /// Store one preference, or forget it when `value` is `None`.
+    pub fn set_pref(&self, did: &str, key: &str, value: Option<&str>) -> SqlResult<()> {
+        match value {
+            Some(value) => self.conn.execute(
+                "INSERT INTO preferences (did, key, value) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(did, key) DO UPDATE SET value=excluded.value",
+                params![did, key, value],
+            )?,
+            None => self.conn.execute(
+                "DELETE FROM preferences WHERE did = ?1 AND key = ?2",
+                params![did, key],
+            )?,
+        };
+        Ok(())
+    }
+
+    /// Load every stored preference as `(did, key, value)`.
+    pub fn load_prefs(&self) -> SqlResult<Vec<(String, String, String)>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT did, key, value FROM preferences")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        rows.collect()
+    }
 }
 
 fn map_message_row(row: &rusqlite::Row) -> SqlResult<MessageRow> {
@@ -1613,6 +2791,7 @@ fn map_message_row(row: &rusqlite::Row) -> SqlResult<MessageRow> {
         .unwrap_or(None)
         .map(|v| v as u64);
     let sender_did: Option<String> = row.get(9).unwrap_or(None);
+    let thread_root: Option<String> = row.get(10).unwrap_or(None);
     Ok(MessageRow {
         id: row.get(0)?,
         channel: row.get(1)?,
@@ -1624,9 +2803,17 @@ fn map_message_row(row: &rusqlite::Row) -> SqlResult<MessageRow> {
         replaces_msgid,
         deleted_at,
         sender_did,
+        thread_root,
     })
 }
 
+/// The msgid a message replies to (`+reply`, or its `+draft/reply` draft form).
+pub fn reply_parent(tags: &HashMap<String, String>) -> Option<&str> {
+    tags.get(freeq_proto::tags::REPLY)
+        .or_else(|| tags.get(freeq_proto::tags::DRAFT_REPLY))
+        .map(String::as_str)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1700,6 +2887,59 @@ mod tests {
         );
     }
 
+    #[test]
+    fn erasure_blanks_only_the_dids_messages() {
+        let db = Db::open_memory().unwrap();
+        msg(&db, "#dev", "alice secret", 100, "m1");
+        msg(&db, "#ops", "alice again", 200, "m2");
+        db.insert_message(
+            "#dev",
+            "bob!b@host",
+            "bob secret",
+            300,
+            &HashMap::new(),
+            Some("m3"),
+            Some("did:plc:bob"),
+        )
+        .unwrap();
+        db.soft_delete_message("#dev", "m1").unwrap();
+
+        let mine = db.messages_by_sender_did("did:plc:alice").unwrap();
+        assert_eq!(mine.len(), 2);
+        assert_eq!(mine[0].text, "alice secret");
+        assert!(mine[0].deleted_at.is_some());
+
+        let only = ["m2".to_string()];
+        assert_eq!(
+            db.erase_messages_by_did("did:plc:alice", Some(&only[..]))
+                .unwrap(),
+            [("#ops".to_string(), "m2".to_string())]
+        );
+        let mut erased = db.erase_messages_by_did("did:plc:alice", None).unwrap();
+        erased.sort();
+        assert_eq!(erased, [("#dev".to_string(), "m1".to_string())]);
+        assert!(
+            db.erase_messages_by_did("did:plc:alice", None)
+                .unwrap()
+                .is_empty()
+        );
+
+        let mine = db.messages_by_sender_did("did:plc:alice").unwrap();
+        assert!(
+            mine.iter()
+                .all(|m| m.text.is_empty() && m.deleted_at.is_some())
+        );
+        assert!(
+            db.search_messages("#ops", "again", 50, None)
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            db.search_messages("#dev", "bob", 50, None).unwrap().len(),
+            1
+        );
+    }
+
     #[test]
     fn search_pagination_with_before() {
         let db = Db::open_memory().unwrap();
@@ -1831,6 +3071,11 @@ mod tests {
         ch.topic_locked = true;
         ch.invite_only = false;
         ch.key = Some("secret".to_string());
+        ch.archived = true;
+        ch.auditorium = true;
+        ch.history_visibility = HistoryVisibility::MembersSinceJoin;
+        ch.no_stats = true;
+        ch.secret = true;
 
         db.save_channel("#test", &ch).unwrap();
 
@@ -1844,11 +3089,210 @@ mod tests {
         assert!(loaded_ch.topic_locked);
         assert!(!loaded_ch.invite_only);
         assert_eq!(loaded_ch.key.as_deref(), Some("secret"));
+        assert!(loaded_ch.archived);
+        assert!(loaded_ch.auditorium);
+        assert!(loaded_ch.no_stats);
+        assert!(loaded_ch.secret);
+        assert_eq!(
+            loaded_ch.history_visibility,
+            HistoryVisibility::MembersSinceJoin
+        );
         // Runtime state should be empty
         assert!(loaded_ch.members.is_empty());
         assert!(loaded_ch.ops.is_empty());
     }
 
+    #[test]
+    fn membership_windows_follow_join_and_part() {
+        let db = Db::open_memory().unwrap();
+        db.add_user_channel("did:plc:alice", "#test").unwrap();
+        // Auto-rejoin while still a member doesn't open a second window.
+        db.add_user_channel("did:plc:alice", "#test").unwrap();
+        let windows = db.membership_windows("did:plc:alice", "#test").unwrap();
+        assert_eq!(windows.len(), 1);
+        assert_eq!(windows[0].1, None);
+
+        db.remove_user_channel("did:plc:alice", "#test").unwrap();
+        db.add_user_channel("did:plc:alice", "#test").unwrap();
+        let windows = db.membership_windows("did:plc:alice", "#test").unwrap();
+        assert_eq!(windows.len(), 2);
+        assert!(windows[0].1.is_some());
+        assert_eq!(windows[1].1, None);
+        assert!(
+            db.membership_windows("did:plc:bob", "#test")
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn channel_stats_accumulate_per_day() {
+        let db = Db::open_memory().unwrap();
+        let pending = |messages, dids: &[&str], peak_members| crate::stats::Pending {
+            messages,
+            dids: dids.iter().map(|d| d.to_string()).collect(),
+            peak_members,
+        };
+        db.add_channel_stats("#test", 10, &pending(3, &["did:plc:a"], 4))
+            .unwrap();
+        db.add_channel_stats("#test", 10, &pending(2, &["did:plc:a", "did:plc:b"], 2))
+            .unwrap();
+        db.add_channel_stats("#test", 12, &pending(1, &["did:plc:c"], 1))
+            .unwrap();
+        db.add_channel_stats("#other", 12, &pending(9, &["did:plc:d"], 9))
+            .unwrap();
+
+        let rows = db.channel_stats("#test", 0).unwrap();
+        let summary: Vec<_> = rows
+            .iter()
+            .map(|r| (r.day, r.messages, r.active_dids, r.peak_members))
+            .collect();
+        assert_eq!(summary, [(10, 5, 2, 4), (12, 1, 1, 1)]);
+        assert_eq!(db.channel_stats("#test", 11).unwrap().len(), 1);
+        assert_eq!(db.channel_active_dids("#test", 0).unwrap(), 3);
+        assert_eq!(db.channel_active_dids("#test", 11).unwrap(), 1);
+
+        // Pruned speakers leave the daily counts alone.
+        db.prune_channel_stats_actors(11).unwrap();
+        assert_eq!(db.channel_active_dids("#test", 0).unwrap(), 1);
+        assert_eq!(db.channel_stats("#test", 0).unwrap()[0].active_dids, 2);
+
+        db.delete_channel_stats("#test").unwrap();
+        assert!(db.channel_stats("#test", 0).unwrap().is_empty());
+        assert_eq!(db.channel_active_dids("#test", 0).unwrap(), 0);
+        assert_eq!(db.channel_stats("#other", 0).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn roundtrip_topic_history() {
+        let db = Db::open_memory().unwrap();
+        let mut ch = ChannelState::default();
+        for (i, text) in ["first", "second", "third"].into_iter().enumerate() {
+            ch.set_topic(TopicInfo {
+                text: text.to_string(),
+                set_by: "alice!a@host".to_string(),
+                set_at: 1700000000 + i as u64,
+            });
+        }
+        db.save_channel("#test", &ch).unwrap();
+        db.save_topic_history("#test", &ch.topic_history).unwrap();
+        // Rewriting replaces rather than appends
+        db.save_topic_history("#test", &ch.topic_history).unwrap();
+
+        let loaded = db.load_channels().unwrap();
+        let loaded_ch = loaded.get("#test").unwrap();
+        let texts: Vec<_> = loaded_ch
+            .topic_history
+            .iter()
+            .map(|t| t.text.as_str())
+            .collect();
+        assert_eq!(texts, ["first", "second"]);
+        assert_eq!(loaded_ch.previous_topic(1).unwrap().text, "second");
+        assert!(loaded_ch.previous_topic(3).is_none());
+
+        db.delete_channel("#test").unwrap();
+        db.save_channel("#test", &ChannelState::default()).unwrap();
+        assert!(
+            db.load_channels().unwrap()["#test"]
+                .topic_history
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn thread_roots_follow_reply_chains() {
+        let db = Db::open_memory().unwrap();
+        let reply = |parent: &str| HashMap::from([("+reply".to_string(), parent.to_string())]);
+        db.insert_message("#t", "a", "root", 1, &HashMap::new(), Some("m1"), None)
+            .unwrap();
+        db.insert_message("#t", "b", "re: root", 2, &reply("m1"), Some("m2"), None)
+            .unwrap();
+        db.insert_message("#t", "a", "re: re", 3, &reply("m2"), Some("m3"), None)
+            .unwrap();
+        db.insert_message("#t", "c", "unrelated", 4, &HashMap::new(), Some("m4"), None)
+            .unwrap();
+        // A parent stored under another target doesn't leak its thread.
+        db.insert_message("#u", "c", "elsewhere", 5, &reply("m2"), Some("m5"), None)
+            .unwrap();
+
+        assert_eq!(
+            db.thread_root_of("#t", "m3").unwrap().as_deref(),
+            Some("m1")
+        );
+        assert_eq!(
+            db.thread_root_of("#t", "m4").unwrap().as_deref(),
+            Some("m4")
+        );
+        assert_eq!(
+            db.thread_root_of("#u", "m5").unwrap().as_deref(),
+            Some("m2")
+        );
+        assert_eq!(db.thread_root_of("#u", "m1").unwrap(), None);
+
+        let thread = db.get_thread("#t", "m1", 10).unwrap();
+        let ids: Vec<_> = thread.iter().filter_map(|r| r.msgid.as_deref()).collect();
+        assert_eq!(ids, ["m1", "m2", "m3"]);
+        assert_eq!(thread[2].thread_root.as_deref(), Some("m1"));
+        assert_eq!(thread[0].thread_root, None);
+    }
+
+    #[test]
+    fn archive_pages_skip_deleted_and_encrypted() {
+        let db = Db::open_memory().unwrap();
+        let encrypted = HashMap::from([("+encrypted".to_string(), String::new())]);
+        for i in 1..=6 {
+            let tags = if i == 3 { &encrypted } else { &HashMap::new() };
+            let msgid = format!("m{i}");
+            db.insert_message("#a", "a", &format!("msg {i}"), i, tags, Some(&msgid), None)
+                .unwrap();
+        }
+        db.soft_delete_message("#a", "m5").unwrap();
+
+        let ids = |rows: Vec<MessageRow>| -> Vec<String> {
+            rows.into_iter().filter_map(|r| r.msgid).collect()
+        };
+        let latest = db.get_archive_page("#a", None, None, 2).unwrap();
+        assert_eq!(ids(latest.clone()), ["m4", "m6"]);
+        let older = db
+            .get_archive_page("#a", Some(latest[0].id), None, 2)
+            .unwrap();
+        assert_eq!(ids(older.clone()), ["m1", "m2"]);
+        let newer = db
+            .get_archive_page("#a", None, Some(older[1].id), 2)
+            .unwrap();
+        assert_eq!(ids(newer), ["m4", "m6"]);
+    }
+
+    #[test]
+    fn roundtrip_metadata() {
+        let db = Db::open_memory().unwrap();
+        db.set_metadata("did:plc:alice", "pronouns", Some("she/her"))
+            .unwrap();
+        db.set_metadata("did:plc:alice", "pronouns", Some("they/them"))
+            .unwrap();
+        db.set_metadata("did:plc:alice", "url", Some("https://example.com"))
+            .unwrap();
+        db.set_metadata("#test", "rules", Some("be nice")).unwrap();
+        db.set_metadata("did:plc:alice", "url", None).unwrap();
+
+        let mut all = db.load_all_metadata().unwrap();
+        all.sort();
+        assert_eq!(
+            all,
+            [
+                ("#test".into(), "rules".into(), "be nice".into()),
+                (
+                    "did:plc:alice".into(),
+                    "pronouns".into(),
+                    "they/them".into()
+                ),
+            ]
+        );
+
+        db.delete_channel("#test").unwrap();
+        assert_eq!(db.load_all_metadata().unwrap().len(), 1);
+    }
+
     #[test]
     fn roundtrip_bans() {
         let db = Db::open_memory().unwrap();
@@ -1999,6 +3443,149 @@ mod tests {
         assert!(db.get_identity_by_nick("alice").unwrap().is_none());
     }
 
+    #[test]
+    fn iroh_bindings_round_trip() {
+        let db = Db::open_memory().unwrap();
+        db.save_iroh_binding("ep1", "did:plc:alice", 1_700_000_000)
+            .unwrap();
+        db.save_iroh_binding("ep1", "did:plc:bob", 1_700_000_100)
+            .unwrap();
+        db.save_iroh_binding("ep2", "did:plc:alice", 1_700_000_200)
+            .unwrap();
+        let mut rows = db.load_iroh_bindings().unwrap();
+        rows.sort();
+        assert_eq!(
+            rows,
+            [
+                ("ep1".to_string(), "did:plc:bob".to_string(), 1_700_000_100),
+                (
+                    "ep2".to_string(),
+                    "did:plc:alice".to_string(),
+                    1_700_000_200
+                ),
+            ]
+        );
+        db.delete_iroh_binding("ep1").unwrap();
+        assert_eq!(db.load_iroh_bindings().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn content_filters_round_trip() {
+        use crate::filters::{Action, Rule, Syntax};
+
+        let db = Db::open_memory().unwrap();
+        let rule = |id, action| {
+            Rule::new(
+                id,
+                "#chat",
+                Syntax::Glob,
+                "*spam*",
+                action,
+                "did:plc:op",
+                1_700_000_000,
+            )
+            .unwrap()
+        };
+        db.save_content_filter(&rule(1, Action::Block)).unwrap();
+        db.save_content_filter(&rule(2, Action::Replace("[removed]".into())))
+            .unwrap();
+        db.save_content_filter(&rule(3, Action::Notify)).unwrap();
+        db.delete_content_filter(3).unwrap();
+
+        let rules = db.load_content_filters().unwrap();
+        let loaded: Vec<_> = rules
+            .iter()
+            .map(|r| (r.id, r.scope.as_str(), r.action.to_string()))
+            .collect();
+        assert_eq!(
+            loaded,
+            [
+                (1, "#chat", "block".to_string()),
+                (2, "#chat", "replace=[removed]".to_string())
+            ]
+        );
+        assert_eq!(rules[1].pattern, "*spam*");
+        assert_eq!(rules[1].syntax, Syntax::Glob);
+    }
+
+    #[test]
+    fn purge_channel_deletes_everything_stored_for_it() {
+        use crate::filters::{Action, Rule, Syntax};
+
+        let db = Db::open_memory().unwrap();
+        let ch = ChannelState {
+            temporary: crate::temporary::Temporary::parse("2h!"),
+            ..Default::default()
+        };
+        for (id, channel) in [(1, "#breakout"), (2, "#lobby")] {
+            db.save_channel(channel, &ch).unwrap();
+            db.insert_message(channel, "u", "hi", 1000, &HashMap::new(), Some("m1"), None)
+                .unwrap();
+            db.store_pin(channel, "m1", "u", 1001).unwrap();
+            db.add_user_channel("did:plc:u", channel).unwrap();
+            let rule = Rule::new(id, channel, Syntax::Glob, "*x*", Action::Flag, "u", 0).unwrap();
+            db.save_content_filter(&rule).unwrap();
+        }
+        assert_eq!(
+            db.load_channels().unwrap()["#breakout"].temporary,
+            crate::temporary::Temporary::parse("2h!")
+        );
+
+        db.purge_channel("#breakout").unwrap();
+
+        let channels = db.load_channels().unwrap();
+        assert!(!channels.contains_key("#breakout"));
+        assert!(channels.contains_key("#lobby"));
+        assert!(db.get_messages("#breakout", 10, None).unwrap().is_empty());
+        assert_eq!(db.get_messages("#lobby", 10, None).unwrap().len(), 1);
+        assert!(db.get_pins("#breakout").unwrap().is_empty());
+        assert_eq!(db.get_user_channels("did:plc:u").unwrap(), ["#lobby"]);
+        let scopes: Vec<String> = db
+            .load_content_filters()
+            .unwrap()
+            .into_iter()
+            .map(|r| r.scope)
+            .collect();
+        assert_eq!(scopes, ["#lobby"]);
+    }
+
+    #[test]
+    fn invites_round_trip_and_go_with_their_channel() {
+        let db = Db::open_memory().unwrap();
+        let invite = |id: &str, channel: &str| crate::invites::Invite {
+            id: id.into(),
+            channel: channel.into(),
+            created_by: "did:plc:op".into(),
+            created_at: 1_700_000_000,
+            expires_at: Some(1_700_003_600),
+            max_uses: Some(5),
+            uses: 0,
+            waives: vec!["email".into()],
+        };
+        db.save_invite(&invite("a", "#chat")).unwrap();
+        db.save_invite(&invite("b", "#lobby")).unwrap();
+        db.save_invite(&crate::invites::Invite {
+            uses: 2,
+            ..invite("a", "#chat")
+        })
+        .unwrap();
+
+        let loaded = db.load_invites().unwrap();
+        assert_eq!(loaded.len(), 2);
+        let a = loaded.iter().find(|i| i.id == "a").unwrap();
+        assert_eq!(
+            *a,
+            crate::invites::Invite {
+                uses: 2,
+                ..invite("a", "#chat")
+            }
+        );
+
+        db.purge_channel("#chat").unwrap();
+        db.delete_invite("b").unwrap();
+        assert!(db.load_invites().unwrap().is_empty());
+    }
+
     #[test]
     fn save_identity_records_last_auth_at() {
         let db = Db::open_memory().unwrap();
@@ -2117,6 +3704,83 @@ mod tests {
         assert!(loaded_ch.invite_exceptions.is_empty());
     }
 
+    #[test]
+    fn roundtrip_quiets() {
+        let db = Db::open_memory().unwrap();
+        let ch = ChannelState::default();
+        db.save_channel("#test", &ch).unwrap();
+
+        let quiet = BanEntry {
+            mask: "did:plc:noisy".to_string(),
+            set_by: "op!o@host".to_string(),
+            set_at: 1700000000,
+        };
+        db.add_quiet("#test", &quiet).unwrap();
+        db.add_quiet("#test", &quiet).unwrap();
+
+        let loaded = db.load_channels().unwrap();
+        let loaded_ch = loaded.get("#test").unwrap();
+        assert_eq!(loaded_ch.quiets.len(), 1);
+        assert_eq!(loaded_ch.quiets[0].mask, "did:plc:noisy");
+        assert!(loaded_ch.bans.is_empty(), "quiets are not bans");
+
+        db.remove_quiet("#test", "did:plc:noisy").unwrap();
+        let loaded = db.load_channels().unwrap();
+        assert!(loaded.get("#test").unwrap().quiets.is_empty());
+
+        // Deleting the channel drops its quiets too.
+        db.add_quiet("#test", &quiet).unwrap();
+        db.delete_channel("#test").unwrap();
+        db.save_channel("#test", &ChannelState::default()).unwrap();
+        let loaded = db.load_channels().unwrap();
+        assert!(loaded.get("#test").unwrap().quiets.is_empty());
+    }
+
+    #[test]
+    fn roundtrip_bot_allowlist() {
+        let db = Db::open_memory().unwrap();
+        let ch = ChannelState {
+            bots_restricted: true,
+            ..Default::default()
+        };
+        db.save_channel("#test", &ch).unwrap();
+        let entry = BanEntry {
+            mask: "did:plc:helper".to_string(),
+            set_by: "op!o@host".to_string(),
+            set_at: 1700000000,
+        };
+        db.add_bot_allow("#test", &entry).unwrap();
+
+        let loaded = db.load_channels().unwrap();
+        let loaded_ch = loaded.get("#test").unwrap();
+        assert!(loaded_ch.bots_restricted);
+        assert_eq!(loaded_ch.bot_allowlist.len(), 1);
+        assert!(loaded_ch.quiets.is_empty());
+
+        db.remove_bot_allow("#test", "did:plc:helper").unwrap();
+        let loaded = db.load_channels().unwrap();
+        assert!(loaded.get("#test").unwrap().bot_allowlist.is_empty());
+    }
+
+    #[test]
+    fn roundtrip_prefs() {
+        let db = Db::open_memory().unwrap();
+        db.set_pref("did:plc:alice", "muted", Some("#a")).unwrap();
+        db.set_pref("did:plc:alice", "muted", Some("#a #b"))
+            .unwrap();
+        db.set_pref("did:plc:alice", "show-joins", Some("off"))
+            .unwrap();
+        db.set_pref("did:plc:alice", "show-joins", None).unwrap();
+        assert_eq!(
+            db.load_prefs().unwrap(),
+            vec![(
+                "did:plc:alice".to_string(),
+                "muted".to_string(),
+                "#a #b".to_string()
+            )]
+        );
+    }
+
     #[test]
     fn messages_different_channels() {
         let db = Db::open_memory().unwrap();
@@ -2321,6 +3985,55 @@ mod tests {
         assert_eq!(loaded.pins[0].msgid, "msg002"); // most recent first
     }
 
+    // ── Moderation case tests ──
+
+    #[test]
+    fn mod_cases_are_channel_scoped_and_close_once() {
+        let db = Db::open_encrypted_memory([3u8; 32]).unwrap();
+        let id = db
+            .open_mod_case("#test", "did:plc:troll", "spam wave", "did:plc:mod", 1000)
+            .unwrap();
+        let other = db
+            .open_mod_case("#other", "did:plc:troll", "raid", "did:plc:mod", 1001)
+            .unwrap();
+        db.add_mod_case_entry(
+            id,
+            &ModCaseEntry {
+                kind: ModCaseEntryKind::Evidence,
+                msgid: Some("m1".into()),
+                sender: Some("troll!t@host".into()),
+                sent_at: Some(990),
+                text: "buy now".into(),
+                added_by: "did:plc:mod".into(),
+                added_at: 1002,
+            },
+        )
+        .unwrap();
+
+        assert!(db.mod_case("#test", other).unwrap().is_none());
+        let case = db.mod_case("#test", id).unwrap().unwrap();
+        assert_eq!(case.summary, "spam wave");
+        let entries = db.mod_case_entries(id).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].kind, ModCaseEntryKind::Evidence);
+        assert_eq!(entries[0].text, "buy now");
+        let raw: String = db
+            .conn
+            .query_row("SELECT text FROM mod_case_entries", [], |r| r.get(0))
+            .unwrap();
+        assert!(raw.starts_with(EAR_PREFIX));
+
+        assert!(
+            db.close_mod_case(id, "did:plc:mod", 1003, Some("banned"))
+                .unwrap()
+        );
+        assert!(!db.close_mod_case(id, "did:plc:mod", 1004, None).unwrap());
+        assert!(db.mod_cases("#test", false).unwrap().is_empty());
+        let all = db.mod_cases("#test", true).unwrap();
+        assert_eq!(all[0].resolution.as_deref(), Some("banned"));
+        assert_eq!(all[0].closed_at, Some(1003));
+    }
+
     #[test]
     fn signing_key_roundtrip_and_upsert() {
         let db = Db::open_memory().unwrap();
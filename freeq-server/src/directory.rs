@@ -0,0 +1,275 @@
+//! Federation-wide channel directory for LIST.
+//!
+//! When a link comes up, and every [`ANNOUNCE_INTERVAL`] after that, each
+//! server tells its peers which public channels it has local members in
+//! ([`S2sMessage::ChannelDirectory`]), with member counts and topics.
+//! LIST merges those summaries with the local channels and marks channels
+//! that only have members elsewhere with the names of the servers they
+//! live on.
+//!
+//! Secret (`+s`) and local-only (`&`) channels are never announced. A
+//! channel that is `+s` here stays out of LIST for non-members even if a
+//! peer announces it. A peer's summary is dropped when its link goes down
+//! or after [`DIRECTORY_TTL`] without a fresh one.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+use crate::s2s::{DirectoryEntry, S2sManager, S2sMessage};
+use crate::server::SharedState;
+
+/// How often the local summary is sent to peers.
+pub const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long a peer's summary is used without a fresh one.
+pub const DIRECTORY_TTL: Duration = Duration::from_secs(3 * 60);
+
+/// Most channels sent or accepted in one summary, busiest first.
+pub const MAX_ENTRIES: usize = 500;
+
+/// Whether `channel` is local-only: `&` channels belong to one server and
+/// are never advertised to peers.
+pub fn is_local_only(channel: &str) -> bool {
+    channel.starts_with('&')
+}
+
+/// A channel as the peers' summaries describe it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Listing {
+    /// The most members any peer reported.
+    pub members: usize,
+    pub topic: Option<String>,
+    /// Names of the servers with local members in the channel.
+    pub hosts: Vec<String>,
+}
+
+/// One row of a LIST reply.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListRow {
+    pub name: String,
+    pub members: usize,
+    /// The topic, prefixed with `[server, …]` for channels with no
+    /// members here.
+    pub topic: String,
+}
+
+struct PeerDirectory {
+    server_name: String,
+    received: Instant,
+    channels: Vec<DirectoryEntry>,
+}
+
+/// The latest summary from each peer, keyed by peer ID.
+#[derive(Default)]
+pub struct ChannelDirectory {
+    peers: Mutex<HashMap<String, PeerDirectory>>,
+}
+
+impl ChannelDirectory {
+    /// Replace `peer_id`'s summary. Local-only and malformed channel names
+    /// are dropped, and names are case-folded to match local channels.
+    pub fn record(&self, peer_id: &str, server_name: &str, channels: Vec<DirectoryEntry>) {
+        self.record_at(peer_id, server_name, channels, Instant::now());
+    }
+
+    fn record_at(
+        &self,
+        peer_id: &str,
+        server_name: &str,
+        channels: Vec<DirectoryEntry>,
+        now: Instant,
+    ) {
+        let channels = channels
+            .into_iter()
+            .filter(|entry| entry.name.starts_with('#'))
+            .take(MAX_ENTRIES)
+            .map(|entry| DirectoryEntry {
+                name: crate::casemap::fold(&entry.name),
+                ..entry
+            })
+            .collect();
+        self.peers.lock().insert(
+            peer_id.to_string(),
+            PeerDirectory {
+                server_name: server_name.to_string(),
+                received: now,
+                channels,
+            },
+        );
+    }
+
+    /// Drop `peer_id`'s summary (its link went down).
+    pub fn forget(&self, peer_id: &str) {
+        self.peers.lock().remove(peer_id);
+    }
+
+    /// Every channel in a fresh summary, by folded name.
+    pub fn listings(&self) -> BTreeMap<String, Listing> {
+        self.listings_at(Instant::now())
+    }
+
+    fn listings_at(&self, now: Instant) -> BTreeMap<String, Listing> {
+        let mut peers = self.peers.lock();
+        peers.retain(|_, peer| now.duration_since(peer.received) < DIRECTORY_TTL);
+        let mut listings: BTreeMap<String, Listing> = BTreeMap::new();
+        for peer in peers.values() {
+            for entry in &peer.channels {
+                let listing = listings.entry(entry.name.clone()).or_default();
+                listing.members = listing.members.max(entry.members as usize);
+                if listing.topic.is_none() {
+                    listing.topic = entry.topic.clone();
+                }
+                if entry.local_members > 0 && !listing.hosts.contains(&peer.server_name) {
+                    listing.hosts.push(peer.server_name.clone());
+                }
+            }
+        }
+        for listing in listings.values_mut() {
+            listing.hosts.sort();
+        }
+        listings
+    }
+}
+
+/// The public channels with local members, busiest first.
+pub fn local_summary(state: &SharedState) -> Vec<DirectoryEntry> {
+    let mut channels = state.channels.filter_map(|name, ch| {
+        (!ch.secret && !is_local_only(name) && !ch.members.is_empty()).then(|| DirectoryEntry {
+            name: name.to_string(),
+            members: (ch.members.len() + ch.remote_members.len()) as u32,
+            local_members: ch.members.len() as u32,
+            topic: ch.topic.as_ref().map(|t| t.text.clone()),
+        })
+    });
+    channels.sort_by(|a, b| {
+        b.local_members
+            .cmp(&a.local_members)
+            .then(a.name.cmp(&b.name))
+    });
+    channels.truncate(MAX_ENTRIES);
+    channels
+}
+
+/// Send the local summary to every peer.
+pub fn announce(state: &SharedState, manager: &S2sManager) {
+    manager.broadcast(S2sMessage::ChannelDirectory {
+        channels: local_summary(state),
+        origin: manager.server_id.clone(),
+    });
+}
+
+/// The LIST reply for `session_id`: local channels merged with the peers'
+/// summaries. Secret channels are shown to their members only, and other
+/// servers' local-only channels not at all.
+pub fn list(state: &SharedState, session_id: &str) -> Vec<ListRow> {
+    let mut remote = state.channel_directory.listings();
+    let mut rows = Vec::new();
+    state.channels.for_each(|name, ch| {
+        let listing = remote.remove(name);
+        if ch.hidden_from(session_id) {
+            return;
+        }
+        if is_local_only(name) && ch.members.is_empty() {
+            return;
+        }
+        let mut members = ch.members.len() + ch.remote_members.len();
+        let mut topic = ch.topic.as_ref().map(|t| t.text.clone());
+        let mut hosts = Vec::new();
+        if let Some(listing) = listing {
+            members = members.max(listing.members);
+            topic = topic.or(listing.topic);
+            if ch.members.is_empty() {
+                hosts = listing.hosts;
+            }
+        }
+        rows.push(row(name, members, topic, &hosts));
+    });
+    rows.extend(
+        remote
+            .into_iter()
+            .map(|(name, listing)| row(&name, listing.members, listing.topic, &listing.hosts)),
+    );
+    rows
+}
+
+fn row(name: &str, members: usize, topic: Option<String>, hosts: &[String]) -> ListRow {
+    let topic = topic.unwrap_or_default();
+    let topic = if hosts.is_empty() {
+        topic
+    } else {
+        format!("[{}] {topic}", hosts.join(", "))
+            .trim_end()
+            .to_string()
+    };
+    ListRow {
+        name: name.to_string(),
+        members,
+        topic,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, members: u32, local_members: u32, topic: Option<&str>) -> DirectoryEntry {
+        DirectoryEntry {
+            name: name.to_string(),
+            members,
+            local_members,
+            topic: topic.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn summaries_merge_and_expire() {
+        let directory = ChannelDirectory::default();
+        let start = Instant::now();
+        directory.record_at(
+            "peer-b",
+            "b.test",
+            vec![
+                entry("#Rust", 7, 4, Some("crabs")),
+                entry("&b-only", 2, 2, None),
+                entry("nonsense", 1, 1, None),
+            ],
+            start,
+        );
+        directory.record_at(
+            "peer-c",
+            "c.test",
+            vec![entry("#rust", 9, 3, None)],
+            start + Duration::from_secs(120),
+        );
+
+        let listings = directory.listings_at(start + Duration::from_secs(150));
+        assert_eq!(listings.keys().collect::<Vec<_>>(), ["#rust"]);
+        assert_eq!(
+            listings["#rust"],
+            Listing {
+                members: 9,
+                topic: Some("crabs".to_string()),
+                hosts: vec!["b.test".to_string(), "c.test".to_string()],
+            }
+        );
+
+        // b.test's summary goes stale first
+        let listings = directory.listings_at(start + DIRECTORY_TTL);
+        assert_eq!(listings["#rust"].hosts, ["c.test"]);
+        directory.forget("peer-c");
+        assert!(directory.listings_at(start + DIRECTORY_TTL).is_empty());
+    }
+
+    #[test]
+    fn rows_name_the_hosting_servers() {
+        let hosts = ["b.test".to_string(), "c.test".to_string()];
+        assert_eq!(
+            row("#rust", 9, Some("crabs".to_string()), &hosts).topic,
+            "[b.test, c.test] crabs"
+        );
+        assert_eq!(row("#rust", 9, None, &hosts[..1]).topic, "[b.test]");
+        assert_eq!(row("#rust", 9, None, &[]).topic, "");
+    }
+}
@@ -0,0 +1,491 @@
+//! Ephemeral channel presence: typing (`+typing`) and status
+//! (`+freeq.at/status`, e.g. "playing …") sent as channel TAGMSGs.
+//!
+//! These are presence, not messages. They are never stored, go only to
+//! members that negotiated `message-tags` (plain clients get no fallback),
+//! and are throttled per sender: a repeat of the same value within
+//! [`REPEAT_INTERVAL`] is dropped, as is anything past [`MAX_UPDATES`] per
+//! [`WINDOW`].
+//!
+//! Channels with [`AGGREGATE_MIN_MEMBERS`] or more local members get
+//! coalesced updates instead of one fan-out per keystroke: updates queue
+//! per channel and flush every [`FLUSH_INTERVAL`], keeping each sender's
+//! latest state. While more than [`MAX_NAMED_TYPERS`] people are typing,
+//! the per-user typing tags are replaced by one server TAGMSG carrying
+//! `freeq.at/typing-count=<n>`, which like `+typing=active` lapses after
+//! six seconds unless repeated.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use freeq_proto::tags as tag;
+use parking_lot::Mutex;
+
+use crate::server::{SharedState, WireLine};
+use crate::session::Cap;
+
+/// Client tags that make a channel TAGMSG ephemeral.
+pub const EPHEMERAL_TAGS: [&str; 2] = [tag::TYPING, tag::STATUS];
+
+/// `+typing` values from the IRCv3 typing spec.
+const TYPING_VALUES: [&str; 3] = ["active", "paused", "done"];
+
+/// Longest `+freeq.at/status` relayed; longer values are truncated.
+pub const MAX_STATUS_LEN: usize = 128;
+
+/// Clients resend `+typing=active` every 3s; a sender repeating a value
+/// sooner than this is dropped.
+pub const REPEAT_INTERVAL: Duration = Duration::from_millis(2500);
+
+/// Updates a sender may make per channel within [`WINDOW`].
+pub const MAX_UPDATES: usize = 8;
+pub const WINDOW: Duration = Duration::from_secs(10);
+
+/// Channels this large (local members) get coalesced updates.
+pub const AGGREGATE_MIN_MEMBERS: usize = 50;
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Above this many concurrent typers, typing is sent as a count.
+pub const MAX_NAMED_TYPERS: usize = 5;
+
+/// How long `+typing=active` stands (IRCv3 typing spec).
+const TYPING_TTL: Duration = Duration::from_secs(6);
+
+/// Sender windows kept before idle ones are pruned.
+const PRUNE_AT: usize = 4096;
+
+/// Whether a TAGMSG is pure presence: it has client tags, and all of
+/// them are [`EPHEMERAL_TAGS`].
+pub fn is_ephemeral(tags: &HashMap<String, String>) -> bool {
+    let mut client_tags = tags.keys().filter(|k| k.starts_with('+')).peekable();
+    client_tags.peek().is_some() && client_tags.all(|k| EPHEMERAL_TAGS.contains(&k.as_str()))
+}
+
+/// Drop invalid `+typing` values and truncate long statuses. Returns
+/// whether anything ephemeral is left to relay.
+pub fn sanitize(tags: &mut HashMap<String, String>) -> bool {
+    if tags
+        .get(tag::TYPING)
+        .is_some_and(|v| !TYPING_VALUES.contains(&v.as_str()))
+    {
+        tags.remove(tag::TYPING);
+    }
+    if let Some(status) = tags.get_mut(tag::STATUS)
+        && status.len() > MAX_STATUS_LEN
+    {
+        let mut end = MAX_STATUS_LEN;
+        while !status.is_char_boundary(end) {
+            end -= 1;
+        }
+        status.truncate(end);
+    }
+    EPHEMERAL_TAGS.iter().any(|t| tags.contains_key(*t))
+}
+
+/// Rate limits and per-channel queues.
+#[derive(Default)]
+pub struct Ephemeral {
+    /// Keyed by (sender session, folded channel).
+    senders: Mutex<HashMap<(String, String), SenderWindow>>,
+    /// Keyed by folded channel; only busy channels have one.
+    channels: Mutex<HashMap<String, ChannelQueue>>,
+}
+
+#[derive(Default)]
+struct SenderWindow {
+    /// Last value sent per tag, and when.
+    last: HashMap<String, (String, Instant)>,
+    recent: VecDeque<Instant>,
+}
+
+#[derive(Default)]
+struct ChannelQueue {
+    /// The channel as clients named it, for the TAGMSG target.
+    target: String,
+    /// Latest pending update per sender, in arrival order.
+    pending: Vec<Update>,
+    /// Nicks typing, until when.
+    typers: HashMap<String, Instant>,
+    scheduled: bool,
+}
+
+struct Update {
+    prefix: String,
+    /// Local session that sent it (None when it came over S2S).
+    sender: Option<String>,
+    tags: HashMap<String, String>,
+}
+
+/// What one flush sends.
+struct Flush {
+    target: String,
+    updates: Vec<Update>,
+    typing_count: Option<usize>,
+}
+
+impl Ephemeral {
+    /// Rate-limit an update from `sender` to `channel`. False means drop
+    /// it silently.
+    pub fn admit(&self, sender: &str, channel: &str, tags: &HashMap<String, String>) -> bool {
+        self.admit_at(sender, channel, tags, Instant::now())
+    }
+
+    fn admit_at(
+        &self,
+        sender: &str,
+        channel: &str,
+        tags: &HashMap<String, String>,
+        now: Instant,
+    ) -> bool {
+        let mut senders = self.senders.lock();
+        if senders.len() > PRUNE_AT {
+            senders.retain(|_, w| w.recent.back().is_some_and(|t| now - *t < WINDOW));
+        }
+        let key = (sender.to_string(), crate::casemap::fold(channel));
+        let window = senders.entry(key).or_default();
+        while window.recent.front().is_some_and(|t| now - *t >= WINDOW) {
+            window.recent.pop_front();
+        }
+        let updates = || {
+            tags.iter()
+                .filter(|(k, _)| EPHEMERAL_TAGS.contains(&k.as_str()))
+        };
+        let repeat = updates().all(|(k, v)| {
+            window
+                .last
+                .get(k)
+                .is_some_and(|(last, at)| last == v && now - *at < REPEAT_INTERVAL)
+        });
+        if repeat || window.recent.len() >= MAX_UPDATES {
+            return false;
+        }
+        window.recent.push_back(now);
+        for (k, v) in updates() {
+            window.last.insert(k.clone(), (v.clone(), now));
+        }
+        true
+    }
+
+    /// Queue an update for a busy channel. True if the caller must
+    /// schedule the flush.
+    fn queue(
+        &self,
+        key: &str,
+        target: &str,
+        prefix: &str,
+        sender: Option<&str>,
+        tags: HashMap<String, String>,
+        now: Instant,
+    ) -> bool {
+        let mut channels = self.channels.lock();
+        let queue = channels
+            .entry(key.to_string())
+            .or_insert_with(|| ChannelQueue {
+                target: target.to_string(),
+                ..Default::default()
+            });
+        let nick = prefix.split('!').next().unwrap_or(prefix);
+        match tags.get(tag::TYPING).map(String::as_str) {
+            Some("active") => {
+                queue.typers.insert(nick.to_string(), now + TYPING_TTL);
+            }
+            Some(_) => {
+                queue.typers.remove(nick);
+            }
+            None => {}
+        }
+        match queue.pending.iter_mut().find(|u| u.prefix == prefix) {
+            Some(update) => update.tags.extend(tags),
+            None => queue.pending.push(Update {
+                prefix: prefix.to_string(),
+                sender: sender.map(str::to_string),
+                tags,
+            }),
+        }
+        !std::mem::replace(&mut queue.scheduled, true)
+    }
+
+    /// Take a busy channel's pending updates and decide what to send.
+    fn take(&self, key: &str, now: Instant) -> Option<Flush> {
+        let mut channels = self.channels.lock();
+        let queue = channels.get_mut(key)?;
+        queue.scheduled = false;
+        queue.typers.retain(|_, until| *until > now);
+        let typers = queue.typers.len();
+        let mut updates = std::mem::take(&mut queue.pending);
+        let target = queue.target.clone();
+        if queue.typers.is_empty() {
+            channels.remove(key);
+        }
+
+        let mut typing_count = None;
+        if typers > MAX_NAMED_TYPERS {
+            let mut any_typing = false;
+            for update in &mut updates {
+                any_typing |= update.tags.remove(tag::TYPING).is_some();
+            }
+            updates.retain(|u| is_ephemeral(&u.tags));
+            typing_count = any_typing.then_some(typers);
+        }
+        Some(Flush {
+            target,
+            updates,
+            typing_count,
+        })
+    }
+}
+
+/// Deliver an admitted update to `channel`'s local members: at once in
+/// small channels, coalesced in busy ones.
+pub fn relay(
+    state: &Arc<SharedState>,
+    channel: &str,
+    prefix: &str,
+    sender: Option<&str>,
+    tags: HashMap<String, String>,
+) {
+    let key = crate::casemap::fold(channel);
+    let members = state
+        .channels
+        .get(&key)
+        .map(|ch| ch.members.len())
+        .unwrap_or(0);
+    if members < AGGREGATE_MIN_MEMBERS {
+        deliver(state, channel, prefix, sender, tags);
+        return;
+    }
+    let now = Instant::now();
+    if state
+        .ephemeral
+        .queue(&key, channel, prefix, sender, tags, now)
+    {
+        let state = Arc::clone(state);
+        tokio::spawn(async move {
+            tokio::time::sleep(FLUSH_INTERVAL).await;
+            flush(&state, &key);
+        });
+    }
+}
+
+fn flush(state: &Arc<SharedState>, key: &str) {
+    let Some(flush) = state.ephemeral.take(key, Instant::now()) else {
+        return;
+    };
+    for update in flush.updates {
+        deliver(
+            state,
+            &flush.target,
+            &update.prefix,
+            update.sender.as_deref(),
+            update.tags,
+        );
+    }
+    if let Some(count) = flush.typing_count {
+        let tags = HashMap::from([(tag::TYPING_COUNT.to_string(), count.to_string())]);
+        deliver(state, &flush.target, &state.server_name, None, tags);
+    }
+}
+
+/// Send a TAGMSG to every local member with `message-tags`; the sender
+/// gets it back only with `echo-message`.
+fn deliver(
+    state: &SharedState,
+    channel: &str,
+    prefix: &str,
+    sender: Option<&str>,
+    tags: HashMap<String, String>,
+) {
+    let mut msg = crate::irc::Message {
+        tags,
+        prefix: Some(prefix.to_string()),
+        command: "TAGMSG".to_string(),
+        params: vec![channel.to_string()],
+    };
+    let line = WireLine::from(format!("{msg}\r\n"));
+    let time = chrono::Utc::now()
+        .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+        .to_string();
+    msg.tags.insert("time".to_string(), time);
+    let line_with_time = WireLine::from(format!("{msg}\r\n"));
+
+    let members: Vec<String> = state
+        .channels
+        .get(&crate::casemap::fold(channel))
+        .map(|ch| ch.members.iter().cloned().collect())
+        .unwrap_or_default();
+    for session in &members {
+        let caps = state.sessions.caps(session);
+        if !caps.has(Cap::MessageTags)
+            || (sender == Some(session.as_str()) && !caps.has(Cap::EchoMessage))
+        {
+            continue;
+        }
+        if let Some(tx) = state.connections.get(session) {
+            let line = if caps.has(Cap::ServerTime) {
+                &line_with_time
+            } else {
+                &line
+            };
+            let _ = tx.try_send(line.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn only_pure_presence_is_ephemeral() {
+        assert!(is_ephemeral(&tags(&[("+typing", "active")])));
+        assert!(is_ephemeral(&tags(&[
+            ("+freeq.at/status", "playing chess"),
+            ("label", "x")
+        ])));
+        assert!(!is_ephemeral(&tags(&[
+            ("+typing", "active"),
+            ("+react", "👍")
+        ])));
+        assert!(!is_ephemeral(&tags(&[("label", "x")])));
+    }
+
+    #[test]
+    fn sanitize_drops_bad_typing_and_truncates_status() {
+        let mut t = tags(&[("+typing", "furiously")]);
+        assert!(!sanitize(&mut t));
+
+        let long = "é".repeat(MAX_STATUS_LEN);
+        let mut t = tags(&[("+freeq.at/status", &long)]);
+        assert!(sanitize(&mut t));
+        let status = &t["+freeq.at/status"];
+        assert!(status.len() <= MAX_STATUS_LEN);
+        assert!(status.chars().all(|c| c == 'é'));
+    }
+
+    #[test]
+    fn repeats_are_dropped_until_the_interval_passes() {
+        let e = Ephemeral::default();
+        let active = tags(&[("+typing", "active")]);
+        let t0 = Instant::now();
+        assert!(e.admit_at("s1", "#c", &active, t0));
+        assert!(!e.admit_at("s1", "#c", &active, t0 + Duration::from_secs(1)));
+        // A change of state goes straight through.
+        assert!(e.admit_at(
+            "s1",
+            "#c",
+            &tags(&[("+typing", "done")]),
+            t0 + Duration::from_secs(1)
+        ));
+        assert!(e.admit_at("s1", "#c", &active, t0 + Duration::from_secs(2)));
+        assert!(e.admit_at("s1", "#c", &active, t0 + Duration::from_secs(5)));
+        // Other senders and channels have their own windows.
+        assert!(e.admit_at("s2", "#c", &active, t0));
+        assert!(e.admit_at("s1", "#other", &active, t0));
+    }
+
+    #[test]
+    fn flip_flopping_hits_the_window_cap() {
+        let e = Ephemeral::default();
+        let t0 = Instant::now();
+        let admitted = (0..MAX_UPDATES * 2)
+            .filter(|i| {
+                let value = if i % 2 == 0 { "active" } else { "paused" };
+                e.admit_at("s1", "#c", &tags(&[("+typing", value)]), t0)
+            })
+            .count();
+        assert_eq!(admitted, MAX_UPDATES);
+        assert!(e.admit_at("s1", "#c", &tags(&[("+typing", "done")]), t0 + WINDOW));
+    }
+
+    #[test]
+    fn queue_coalesces_per_sender() {
+        let e = Ephemeral::default();
+        let t0 = Instant::now();
+        let a = "alice!a@host";
+        assert!(e.queue(
+            "#c",
+            "#C",
+            a,
+            Some("s1"),
+            tags(&[("+typing", "active")]),
+            t0
+        ));
+        assert!(!e.queue(
+            "#c",
+            "#C",
+            a,
+            Some("s1"),
+            tags(&[("+typing", "paused")]),
+            t0
+        ));
+        assert!(!e.queue(
+            "#c",
+            "#C",
+            a,
+            Some("s1"),
+            tags(&[("+freeq.at/status", "afk")]),
+            t0
+        ));
+
+        let flush = e.take("#c", t0).unwrap();
+        assert_eq!(flush.target, "#C");
+        assert_eq!(flush.updates.len(), 1);
+        assert_eq!(flush.updates[0].tags["+typing"], "paused");
+        assert_eq!(flush.updates[0].tags["+freeq.at/status"], "afk");
+        assert_eq!(flush.typing_count, None);
+        // Nothing left: the queue is gone and the next update reschedules.
+        assert!(e.take("#c", t0).is_none());
+    }
+
+    #[test]
+    fn many_typers_become_a_count() {
+        let e = Ephemeral::default();
+        let t0 = Instant::now();
+        for n in 0..=MAX_NAMED_TYPERS {
+            let prefix = format!("user{n}!u@host");
+            e.queue(
+                "#c",
+                "#c",
+                &prefix,
+                None,
+                tags(&[("+typing", "active")]),
+                t0,
+            );
+        }
+        e.queue(
+            "#c",
+            "#c",
+            "bob!b@host",
+            None,
+            tags(&[("+freeq.at/status", "playing chess")]),
+            t0,
+        );
+
+        let flush = e.take("#c", t0).unwrap();
+        assert_eq!(flush.typing_count, Some(MAX_NAMED_TYPERS + 1));
+        // Only the status update survives, as a named update.
+        assert_eq!(flush.updates.len(), 1);
+        assert_eq!(flush.updates[0].prefix, "bob!b@host");
+
+        // Typers lapse after the TTL.
+        e.queue(
+            "#c",
+            "#c",
+            "late!l@host",
+            None,
+            tags(&[("+typing", "active")]),
+            t0,
+        );
+        let flush = e.take("#c", t0 + TYPING_TTL).unwrap();
+        assert_eq!(flush.typing_count, None);
+        assert_eq!(flush.updates.len(), 1);
+    }
+}
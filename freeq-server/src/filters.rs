@@ -0,0 +1,418 @@
+//! Content filters: operator rules applied to PRIVMSG and NOTICE text
+//! before delivery.
+//!
+//! A rule has a scope — one channel, or [`GLOBAL`] for every message on
+//! the server, channel or direct — a pattern and an action:
+//!
+//! - `block`: the message is dropped and the sender told;
+//! - `replace[=<text>]`: matches are replaced, by [`DEFAULT_REPLACEMENT`]
+//!   unless given (a glob matches, and so replaces, the whole message);
+//! - `flag`: the message is delivered tagged [`FLAG_TAG`]`=<rule ids>`,
+//!   so clients can collapse it;
+//! - `notify`: the message is delivered as is and the ops are told — the
+//!   channel's ops for a channel rule, server operators for a global one.
+//!
+//! Patterns are regexes, or globs (`*`, `?`) matched case-insensitively
+//! against the whole message. Global rules run first, then the channel's,
+//! each in the order they were added; a `block` stops the rest, and later
+//! rules see earlier replacements.
+//!
+//! Rules are managed at runtime with `FILTER` and persisted when the
+//! server has a database. Server operators are exempt from every rule,
+//! and a channel's ops from its rules. Encrypted messages are never
+//! inspected, and messages from federation peers are left to their
+//! origin server's filters.
+
+use std::fmt;
+
+use regex::{Regex, RegexBuilder};
+
+/// The scope of rules that apply to every message.
+pub const GLOBAL: &str = "*";
+
+/// Longest pattern accepted.
+pub const MAX_PATTERN_LEN: usize = 256;
+
+/// Most rules one scope can hold.
+pub const MAX_RULES_PER_SCOPE: usize = 64;
+
+/// What `replace` substitutes when no text is given.
+pub const DEFAULT_REPLACEMENT: &str = "***";
+
+/// Server tag naming the `flag` rules a delivered message matched.
+pub const FLAG_TAG: &str = "freeq.at/filter";
+
+/// Compiled-program budget per pattern, so a rule can't be made to eat
+/// memory.
+const REGEX_SIZE_LIMIT: usize = 256 * 1024;
+
+/// How a rule's pattern is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    Regex,
+    Glob,
+}
+
+impl Syntax {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Syntax::Regex => "regex",
+            Syntax::Glob => "glob",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "regex" => Some(Syntax::Regex),
+            "glob" => Some(Syntax::Glob),
+            _ => None,
+        }
+    }
+}
+
+/// What a rule does to a matching message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Block,
+    Replace(String),
+    Flag,
+    Notify,
+}
+
+impl Action {
+    /// `block`, `flag`, `notify` (or `notify-ops`), `replace` or
+    /// `replace=<text>`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let (name, arg) = match s.split_once('=') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (s, None),
+        };
+        match (name.to_ascii_lowercase().as_str(), arg) {
+            ("block", None) => Some(Action::Block),
+            ("flag", None) => Some(Action::Flag),
+            ("notify" | "notify-ops", None) => Some(Action::Notify),
+            ("replace", None) => Some(Action::Replace(DEFAULT_REPLACEMENT.to_string())),
+            ("replace", Some(text)) => Some(Action::Replace(text.to_string())),
+            _ => None,
+        }
+    }
+
+    /// The action's name, without a replacement.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Block => "block",
+            Action::Replace(_) => "replace",
+            Action::Flag => "flag",
+            Action::Notify => "notify",
+        }
+    }
+
+    /// The replacement text, for `replace`.
+    pub fn replacement(&self) -> Option<&str> {
+        match self {
+            Action::Replace(text) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Replace(text) => write!(f, "replace={text}"),
+            other => f.write_str(other.name()),
+        }
+    }
+}
+
+/// One filter rule.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub id: i64,
+    /// A channel name, or [`GLOBAL`].
+    pub scope: String,
+    pub syntax: Syntax,
+    pub pattern: String,
+    pub action: Action,
+    /// The DID, or for a guest operator the nick, that added the rule.
+    pub created_by: String,
+    /// Unix seconds.
+    pub created_at: i64,
+    regex: Regex,
+}
+
+impl Rule {
+    /// Compile a rule, or say why its pattern is unusable.
+    pub fn new(
+        id: i64,
+        scope: &str,
+        syntax: Syntax,
+        pattern: &str,
+        action: Action,
+        created_by: &str,
+        created_at: i64,
+    ) -> Result<Self, String> {
+        Ok(Self {
+            id,
+            scope: scope.to_string(),
+            syntax,
+            pattern: pattern.to_string(),
+            action,
+            created_by: created_by.to_string(),
+            created_at,
+            regex: compile(syntax, pattern)?,
+        })
+    }
+
+    fn applies_to(&self, scopes: &[&str]) -> bool {
+        scopes.iter().any(|s| s.eq_ignore_ascii_case(&self.scope))
+    }
+}
+
+/// Compile a pattern to the regex it is matched with.
+pub fn compile(syntax: Syntax, pattern: &str) -> Result<Regex, String> {
+    if pattern.is_empty() {
+        return Err("Pattern is empty".to_string());
+    }
+    if pattern.len() > MAX_PATTERN_LEN {
+        return Err(format!("Pattern is longer than {MAX_PATTERN_LEN} bytes"));
+    }
+    let source = match syntax {
+        Syntax::Regex => pattern.to_string(),
+        Syntax::Glob => {
+            let mut re = String::from("^");
+            for c in pattern.chars() {
+                match c {
+                    '*' => re.push_str(".*"),
+                    '?' => re.push('.'),
+                    c => re.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+                }
+            }
+            re.push('$');
+            re
+        }
+    };
+    RegexBuilder::new(&source)
+        .case_insensitive(syntax == Syntax::Glob)
+        .dot_matches_new_line(syntax == Syntax::Glob)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+        .map_err(|e| format!("Invalid {}: {e}", syntax.as_str()))
+}
+
+/// What the filters decided about a message.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Verdict {
+    /// The `block` rule that stopped the message.
+    pub blocked: Option<i64>,
+    /// The text after replacements, when a `replace` rule matched.
+    pub rewritten: Option<String>,
+    /// `flag` rules that matched.
+    pub flagged: Vec<i64>,
+    /// `notify` rules that matched, with their scopes.
+    pub notify: Vec<(i64, String)>,
+}
+
+impl Verdict {
+    /// The [`FLAG_TAG`] value: matched `flag` rule IDs, comma-separated.
+    pub fn flag_value(&self) -> Option<String> {
+        (!self.flagged.is_empty()).then(|| {
+            self.flagged
+                .iter()
+                .map(i64::to_string)
+                .collect::<Vec<_>>()
+                .join(",")
+        })
+    }
+}
+
+/// The server's rules, global and per channel.
+#[derive(Debug, Default)]
+pub struct Filters {
+    rules: Vec<Rule>,
+    /// The highest ID handed out, so a removed rule's ID isn't reused
+    /// while the server runs.
+    last_id: i64,
+}
+
+impl Filters {
+    pub fn from_rules(mut rules: Vec<Rule>) -> Self {
+        rules.sort_by_key(|r| r.id);
+        let last_id = rules.last().map_or(0, |r| r.id);
+        Self { rules, last_id }
+    }
+
+    /// The ID the next rule should get.
+    pub fn next_id(&self) -> i64 {
+        self.last_id + 1
+    }
+
+    /// Add a rule, unless its scope is full.
+    pub fn add(&mut self, rule: Rule) -> Result<(), String> {
+        if self.rules(&rule.scope).len() >= MAX_RULES_PER_SCOPE {
+            return Err(format!(
+                "{} already has {MAX_RULES_PER_SCOPE} filters",
+                rule.scope
+            ));
+        }
+        self.last_id = self.last_id.max(rule.id);
+        self.rules.push(rule);
+        self.rules.sort_by_key(|r| r.id);
+        Ok(())
+    }
+
+    pub fn get(&self, id: i64) -> Option<&Rule> {
+        self.rules.iter().find(|r| r.id == id)
+    }
+
+    pub fn remove(&mut self, id: i64) -> Option<Rule> {
+        let at = self.rules.iter().position(|r| r.id == id)?;
+        Some(self.rules.remove(at))
+    }
+
+    /// Drop every rule in `scope` (a channel that no longer exists).
+    pub fn remove_scope(&mut self, scope: &str) {
+        self.rules.retain(|r| r.scope != scope);
+    }
+
+    /// The rules in `scope`, oldest first.
+    pub fn rules(&self, scope: &str) -> Vec<&Rule> {
+        self.rules
+            .iter()
+            .filter(|r| r.applies_to(&[scope]))
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Run the rules in `scopes` over `text`: global ones first, then the
+    /// rest, each in the order they were added.
+    pub fn check(&self, scopes: &[&str], text: &str) -> Verdict {
+        let mut verdict = Verdict::default();
+        let global = self.rules.iter().filter(|r| r.scope == GLOBAL);
+        let local = self.rules.iter().filter(|r| r.scope != GLOBAL);
+        for rule in global.chain(local).filter(|r| r.applies_to(scopes)) {
+            let current = verdict.rewritten.as_deref().unwrap_or(text);
+            if !rule.regex.is_match(current) {
+                continue;
+            }
+            match &rule.action {
+                Action::Block => {
+                    verdict.blocked = Some(rule.id);
+                    return verdict;
+                }
+                Action::Replace(with) => {
+                    let replaced = rule
+                        .regex
+                        .replace_all(current, regex::NoExpand(with))
+                        .into_owned();
+                    verdict.rewritten = Some(replaced);
+                }
+                Action::Flag => verdict.flagged.push(rule.id),
+                Action::Notify => verdict.notify.push((rule.id, rule.scope.clone())),
+            }
+        }
+        verdict
+    }
+
+    /// Apply just the `replace` rules in `scopes`, in order: for the
+    /// separate lines of a multi-line message whose whole text was
+    /// already checked.
+    pub fn rewrite(&self, scopes: &[&str], text: &str) -> String {
+        let global = self.rules.iter().filter(|r| r.scope == GLOBAL);
+        let local = self.rules.iter().filter(|r| r.scope != GLOBAL);
+        let mut text = text.to_string();
+        for rule in global.chain(local).filter(|r| r.applies_to(scopes)) {
+            if let Action::Replace(with) = &rule.action {
+                text = rule
+                    .regex
+                    .replace_all(&text, regex::NoExpand(with))
+                    .into_owned();
+            }
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(id: i64, scope: &str, syntax: Syntax, pattern: &str, action: &str) -> Rule {
+        Rule::new(
+            id,
+            scope,
+            syntax,
+            pattern,
+            Action::parse(action).unwrap(),
+            "did:plc:op",
+            0,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn globs_match_the_whole_message_case_insensitively() {
+        let re = compile(Syntax::Glob, "*discord.gg/*").unwrap();
+        assert!(re.is_match("join DISCORD.GG/free now"));
+        assert!(!re.is_match("discord.ggx"));
+        assert!(compile(Syntax::Glob, "a?c").unwrap().is_match("a.c"));
+        assert!(!compile(Syntax::Glob, "a.c").unwrap().is_match("abc"));
+        assert!(compile(Syntax::Regex, "(unclosed").is_err());
+        assert!(compile(Syntax::Regex, &"a".repeat(MAX_PATTERN_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn global_rules_run_first_and_block_stops_the_rest() {
+        let filters = Filters::from_rules(vec![
+            rule(3, "#ops", Syntax::Regex, "deploy", "flag"),
+            rule(2, "#ops", Syntax::Glob, "*secret*", "notify"),
+            rule(1, GLOBAL, Syntax::Regex, r"hunter\d", "replace"),
+            rule(4, "#other", Syntax::Regex, ".", "block"),
+        ]);
+
+        let v = filters.check(&[GLOBAL, "#OPS"], "the secret is hunter2, deploy it");
+        assert_eq!(v.rewritten.as_deref(), Some("the secret is ***, deploy it"));
+        assert_eq!(v.notify, [(2, "#ops".to_string())]);
+        assert_eq!(v.flag_value().as_deref(), Some("3"));
+        assert_eq!(v.blocked, None);
+
+        // Channel rules don't reach other channels or exempt senders.
+        assert_eq!(filters.check(&[GLOBAL], "deploy"), Verdict::default());
+        let v = filters.check(&[GLOBAL, "#other"], "hunter2 deploy");
+        assert_eq!(v.blocked, Some(4));
+        assert_eq!(filters.rewrite(&[GLOBAL, "#other"], "hunter2"), "***");
+    }
+
+    #[test]
+    fn actions_round_trip() {
+        for s in ["block", "flag", "notify", "replace=[removed]"] {
+            assert_eq!(Action::parse(s).unwrap().to_string(), s);
+        }
+        assert_eq!(Action::parse("notify-ops"), Some(Action::Notify));
+        assert_eq!(
+            Action::parse("replace"),
+            Some(Action::Replace(DEFAULT_REPLACEMENT.to_string()))
+        );
+        assert_eq!(Action::parse("block=x"), None);
+
+        let mut filters = Filters::default();
+        assert_eq!(filters.next_id(), 1);
+        for id in 1..=MAX_RULES_PER_SCOPE as i64 {
+            filters
+                .add(rule(id, "#full", Syntax::Glob, "*", "flag"))
+                .unwrap();
+        }
+        assert!(
+            filters
+                .add(rule(99, "#full", Syntax::Glob, "*", "flag"))
+                .is_err()
+        );
+        assert_eq!(filters.next_id(), MAX_RULES_PER_SCOPE as i64 + 1);
+        let last = MAX_RULES_PER_SCOPE as i64;
+        assert!(filters.remove(last).is_some() && filters.get(last).is_none());
+        assert_eq!(filters.next_id(), last + 1);
+    }
+}
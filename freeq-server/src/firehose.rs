@@ -0,0 +1,250 @@
+//! Structured event firehose for observability tooling.
+//!
+//! Server code publishes [`FirehoseEvent`]s to [`Firehose`]; the
+//! oper-only `/firehose` WebSocket on the web listener streams them as
+//! JSON, one event per text frame, for dashboards and anomaly detection.
+//!
+//! Messages are metadata only — sender, target, msgid and size, never the
+//! body. Publishing is a no-op while nobody is subscribed, and a slow
+//! subscriber loses events (it is told how many) instead of holding the
+//! server back.
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+/// Events buffered per subscriber before it starts lagging.
+const CAPACITY: usize = 1024;
+
+/// Something the server did.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FirehoseEvent {
+    /// New client connection (before registration).
+    Connect {
+        session: String,
+        ip: Option<String>,
+        iroh: bool,
+    },
+    /// Connection completed registration.
+    Register {
+        session: String,
+        nick: String,
+        did: Option<String>,
+    },
+    Join {
+        session: String,
+        nick: String,
+        channel: String,
+        did: Option<String>,
+    },
+    Part {
+        session: String,
+        nick: String,
+        channel: String,
+        did: Option<String>,
+    },
+    /// Connection closed.
+    Disconnect {
+        session: String,
+        nick: Option<String>,
+        did: Option<String>,
+    },
+    /// PRIVMSG/NOTICE accepted for delivery.
+    Message {
+        session: String,
+        nick: String,
+        did: Option<String>,
+        command: String,
+        target: String,
+        msgid: String,
+        bytes: usize,
+        encrypted: bool,
+    },
+    /// Message received from a federation peer.
+    S2s {
+        peer: String,
+        /// The S2S message type (`privmsg`, `join`, `sync_request`, ...).
+        kind: String,
+        channel: Option<String>,
+    },
+}
+
+impl FirehoseEvent {
+    /// The `type` field.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Connect { .. } => "connect",
+            Self::Register { .. } => "register",
+            Self::Join { .. } => "join",
+            Self::Part { .. } => "part",
+            Self::Disconnect { .. } => "disconnect",
+            Self::Message { .. } => "message",
+            Self::S2s { .. } => "s2s",
+        }
+    }
+
+    fn channel(&self) -> Option<&str> {
+        match self {
+            Self::Join { channel, .. } | Self::Part { channel, .. } => Some(channel),
+            Self::Message { target, .. } if target.starts_with('#') => Some(target),
+            Self::S2s { channel, .. } => channel.as_deref(),
+            _ => None,
+        }
+    }
+
+    fn did(&self) -> Option<&str> {
+        match self {
+            Self::Register { did, .. }
+            | Self::Join { did, .. }
+            | Self::Part { did, .. }
+            | Self::Disconnect { did, .. }
+            | Self::Message { did, .. } => did.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// A published event with its timestamp.
+#[derive(Debug, Serialize)]
+pub struct FirehoseRecord {
+    /// Unix milliseconds.
+    pub ts: u64,
+    #[serde(flatten)]
+    pub event: FirehoseEvent,
+}
+
+/// Server-side subscription filter, from the `/firehose` query string.
+/// Every given field must match.
+#[derive(Debug, Default, Deserialize)]
+pub struct FirehoseFilter {
+    /// Comma-separated event types, e.g. `join,part,message`.
+    pub types: Option<String>,
+    /// Only events about this channel.
+    pub channel: Option<String>,
+    /// Only events caused by this DID.
+    pub did: Option<String>,
+}
+
+impl FirehoseFilter {
+    pub fn matches(&self, event: &FirehoseEvent) -> bool {
+        if let Some(ref types) = self.types
+            && !types.split(',').any(|t| t.trim() == event.kind())
+        {
+            return false;
+        }
+        if let Some(ref channel) = self.channel
+            && event.channel().map(crate::casemap::fold) != Some(crate::casemap::fold(channel))
+        {
+            return false;
+        }
+        if let Some(ref did) = self.did
+            && event.did() != Some(did.as_str())
+        {
+            return false;
+        }
+        true
+    }
+}
+
+pub struct Firehose {
+    tx: broadcast::Sender<Arc<FirehoseRecord>>,
+}
+
+impl Default for Firehose {
+    fn default() -> Self {
+        Self {
+            tx: broadcast::channel(CAPACITY).0,
+        }
+    }
+}
+
+impl Firehose {
+    /// True while at least one subscriber is connected. Lets callers skip
+    /// building events nobody will read.
+    pub fn is_active(&self) -> bool {
+        self.tx.receiver_count() > 0
+    }
+
+    pub fn publish(&self, event: FirehoseEvent) {
+        if !self.is_active() {
+            return;
+        }
+        let ts = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let _ = self.tx.send(Arc::new(FirehoseRecord { ts, event }));
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<FirehoseRecord>> {
+        self.tx.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn join(channel: &str, did: Option<&str>) -> FirehoseEvent {
+        FirehoseEvent::Join {
+            session: "s1".into(),
+            nick: "alice".into(),
+            channel: channel.into(),
+            did: did.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn publish_without_subscribers_is_dropped() {
+        let hose = Firehose::default();
+        hose.publish(join("#a", None));
+        let mut rx = hose.subscribe();
+        assert!(rx.try_recv().is_err());
+        hose.publish(join("#a", None));
+        let rec = rx.try_recv().unwrap();
+        let json = serde_json::to_value(&*rec).unwrap();
+        assert_eq!(json["type"], "join");
+        assert_eq!(json["channel"], "#a");
+        assert!(json["ts"].as_u64().unwrap() > 0);
+    }
+
+    #[test]
+    fn filters_combine() {
+        let all = FirehoseFilter::default();
+        assert!(all.matches(&join("#a", None)));
+
+        let filter = FirehoseFilter {
+            types: Some("part, join".into()),
+            channel: Some("#A".into()),
+            did: Some("did:plc:alice".into()),
+        };
+        assert!(filter.matches(&join("#a", Some("did:plc:alice"))));
+        assert!(!filter.matches(&join("#b", Some("did:plc:alice"))));
+        assert!(!filter.matches(&join("#a", Some("did:plc:bob"))));
+        assert!(!filter.matches(&join("#a", None)));
+        assert!(!filter.matches(&FirehoseEvent::Disconnect {
+            session: "s1".into(),
+            nick: None,
+            did: Some("did:plc:alice".into()),
+        }));
+
+        // DMs have no channel
+        let dm = FirehoseEvent::Message {
+            session: "s1".into(),
+            nick: "alice".into(),
+            did: None,
+            command: "PRIVMSG".into(),
+            target: "bob".into(),
+            msgid: "m1".into(),
+            bytes: 2,
+            encrypted: false,
+        };
+        let channel_only = FirehoseFilter {
+            channel: Some("bob".into()),
+            ..Default::default()
+        };
+        assert!(!channel_only.matches(&dm));
+    }
+}
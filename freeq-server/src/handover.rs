@@ -0,0 +1,403 @@
+//! Zero-downtime restarts: one server process taking over from another.
+//!
+//! With `--handover-socket PATH`, a running server listens on a Unix
+//! socket at `PATH`, and binds its TCP listeners (plain, TLS, HTTP) with
+//! SO_REUSEPORT. To upgrade, start the new binary with the same flags.
+//! It binds the same addresses alongside the old process, then connects
+//! to `PATH` and asks for a takeover:
+//!
+//! 1. the old server stops accepting on its listeners, so the kernel
+//!    routes every new connection to the new one;
+//! 2. it shuts down its iroh endpoint, freeing the UDP port so the new
+//!    server can bind it with the same key (and endpoint ID);
+//! 3. it sends a [`HandoverState`]: every authenticated user's nick and
+//!    channel membership;
+//! 4. it tells its clients to reconnect and closes their connections
+//!    over `--handover-drain-secs` ([`drain`]), then exits.
+//!
+//! The new server parks each handed-over user as a ghost session
+//! ([`install`]), the same mechanism that covers a dropped connection:
+//! nick and channels are held, and a client that reconnects with the
+//! same DID gets them back without QUIT/JOIN churn in its channels.
+//! Guests have nothing to reclaim and simply reconnect.
+//!
+//! Connections still in the old listener's accept backlog when it closes
+//! are reset by the kernel; clients retry them like any failed connect.
+//! The socket is created mode 0600: whoever can connect to it can stop
+//! the server.
+
+use std::collections::HashMap;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpSocket, UnixListener, UnixStream};
+use tokio::sync::watch;
+
+use crate::server::{SharedState, WireLine};
+
+/// Handover protocol version, sent with the takeover request and in
+/// [`HandoverState`].
+pub const VERSION: u32 = 1;
+/// How long handed-over users have to reconnect once the old server has
+/// closed their connection. Matches the grace for a dropped connection.
+pub const RECONNECT_GRACE: Duration = Duration::from_secs(30);
+/// How long a client gets to read the ERROR telling it to reconnect
+/// before its connection is closed.
+const FLUSH_GRACE: Duration = Duration::from_millis(250);
+/// How long the new server waits for the old one's state.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What the old server hands to the new one.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HandoverState {
+    pub version: u32,
+    pub server_name: String,
+    /// The old server's `--handover-drain-secs`: its clients may take
+    /// this long to be disconnected, on top of [`RECONNECT_GRACE`].
+    pub drain_secs: u64,
+    pub sessions: Vec<HandoverSession>,
+}
+
+/// One authenticated user, keyed by DID. A user connected from several
+/// devices is handed over once.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HandoverSession {
+    pub did: String,
+    pub nick: String,
+    pub hostmask: String,
+    /// The old server's session ID, which stands in for the user in
+    /// channel member sets until they reconnect.
+    pub session_id: String,
+    pub channels: Vec<HandoverChannel>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HandoverChannel {
+    pub name: String,
+    pub op: bool,
+    pub voice: bool,
+    pub halfop: bool,
+}
+
+/// Fires once this server has been taken over. Accept loops select on
+/// [`wait`](Self::wait) and drop their listener when it resolves.
+#[derive(Clone)]
+pub struct HandedOver(watch::Receiver<bool>);
+
+impl HandedOver {
+    /// Resolves once the takeover has started. Never resolves if no
+    /// handover socket is being served.
+    pub async fn wait(&mut self) {
+        let closed = self.0.wait_for(|done| *done).await.is_err();
+        if closed {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+/// The trigger for [`serve`] and the signal it fires.
+pub fn signal() -> (watch::Sender<bool>, HandedOver) {
+    let (tx, rx) = watch::channel(false);
+    (tx, HandedOver(rx))
+}
+
+/// Bind a TCP listener on `addr`. With `reuse_port`, the socket gets
+/// SO_REUSEPORT so a server taking over can bind the same address while
+/// this one still holds it.
+pub async fn bind_tcp(addr: &str, reuse_port: bool) -> Result<TcpListener> {
+    if !reuse_port {
+        return Ok(TcpListener::bind(addr).await?);
+    }
+    let resolved = tokio::net::lookup_host(addr)
+        .await?
+        .next()
+        .with_context(|| format!("{addr} did not resolve"))?;
+    let socket = if resolved.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    socket.set_reuseaddr(true)?;
+    socket.set_reuseport(true)?;
+    socket.bind(resolved)?;
+    Ok(socket.listen(1024)?)
+}
+
+/// Bind the handover socket at `path`, replacing a file left there by a
+/// previous server.
+pub fn listen(path: &Path) -> Result<UnixListener> {
+    match std::fs::remove_file(path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e).with_context(|| format!("removing {}", path.display())),
+    }
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("binding handover socket {}", path.display()))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Ask the server listening on `path` to hand over. `Ok(None)` if none
+/// is (first start, or a socket file left by a server that has exited).
+pub async fn take_over(path: &Path) -> Result<Option<HandoverState>> {
+    let stream = match UnixStream::connect(path).await {
+        Ok(stream) => stream,
+        Err(e)
+            if matches!(
+                e.kind(),
+                std::io::ErrorKind::NotFound | std::io::ErrorKind::ConnectionRefused
+            ) =>
+        {
+            return Ok(None);
+        }
+        Err(e) => return Err(e).with_context(|| format!("connecting to {}", path.display())),
+    };
+    let (read, mut write) = stream.into_split();
+    write
+        .write_all(format!("TAKEOVER {VERSION}\n").as_bytes())
+        .await?;
+
+    let mut reply = String::new();
+    tokio::time::timeout(REPLY_TIMEOUT, BufReader::new(read).read_line(&mut reply))
+        .await
+        .context("timed out waiting for handover state")??;
+    if reply.is_empty() {
+        bail!("old server closed the handover socket without replying");
+    }
+    if let Some(error) = reply.strip_prefix("ERROR ") {
+        bail!("old server refused the handover: {}", error.trim_end());
+    }
+    let handover: HandoverState =
+        serde_json::from_str(&reply).context("malformed handover state")?;
+    if handover.version != VERSION {
+        bail!("unsupported handover version {}", handover.version);
+    }
+    Ok(Some(handover))
+}
+
+/// Serve takeover requests on `listener` until one succeeds. Fires
+/// `handed_over` before snapshotting, so nothing new connects in between.
+pub async fn serve(
+    state: Arc<SharedState>,
+    listener: UnixListener,
+    handed_over: watch::Sender<bool>,
+) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                tracing::warn!("Handover socket accept error: {e}");
+                continue;
+            }
+        };
+        let (read, mut write) = stream.into_split();
+        let mut request = String::new();
+        let read =
+            tokio::time::timeout(REPLY_TIMEOUT, BufReader::new(read).read_line(&mut request));
+        if !matches!(read.await, Ok(Ok(_))) {
+            continue;
+        }
+        if request.trim_end() != format!("TAKEOVER {VERSION}") {
+            tracing::warn!(request = request.trim_end(), "Refused handover request");
+            let _ = write
+                .write_all(format!("ERROR expected TAKEOVER {VERSION}\n").as_bytes())
+                .await;
+            continue;
+        }
+
+        // Past this point there's no going back: the listeners are gone.
+        tracing::info!("Handing over to a new server");
+        handed_over.send_replace(true);
+        release_iroh(&state).await;
+        let drain_secs = state.config.handover_drain_secs;
+        let handover = snapshot(&state, drain_secs);
+        let sessions = handover.sessions.len();
+        let mut json = serde_json::to_vec(&handover).unwrap_or_default();
+        json.push(b'\n');
+        match write.write_all(&json).await {
+            Ok(()) => tracing::info!(sessions, "Handover state sent"),
+            Err(e) => tracing::error!("Failed to send handover state: {e}"),
+        }
+        return;
+    }
+}
+
+/// Shut down the iroh router and endpoint so the new server can bind
+/// the endpoint's port.
+async fn release_iroh(state: &SharedState) {
+    let router = state.iroh_router.lock().take();
+    if let Some(router) = router {
+        let _ = router.shutdown().await;
+    }
+    let endpoint = state.iroh_endpoint.lock().take();
+    if let Some(endpoint) = endpoint {
+        endpoint.close().await;
+    }
+}
+
+/// Every authenticated user on this server, connected or ghosted.
+pub fn snapshot(state: &SharedState, drain_secs: u64) -> HandoverState {
+    // Per-session tables first, then the channel walk, per the
+    // SharedState lock order.
+    let session_dids = state.session_dids.lock().clone();
+    let mut users: HashMap<String, HandoverSession> = HashMap::new();
+    {
+        let nicks = state.nick_to_session.lock();
+        for (sid, did) in &session_dids {
+            let Some(nick) = nicks.get_nick(sid) else {
+                continue;
+            };
+            users.entry(did.clone()).or_insert_with(|| HandoverSession {
+                did: did.clone(),
+                nick: nick.to_string(),
+                hostmask: format!("{nick}!~u@{}", crate::connection::cloak_did(did)),
+                session_id: sid.clone(),
+                channels: Vec::new(),
+            });
+        }
+    }
+    state.channels.for_each(|name, ch| {
+        // A user on several devices may be in a channel more than once;
+        // merge their modes into one entry.
+        for sid in &ch.members {
+            let Some(user) = session_dids.get(sid).and_then(|did| users.get_mut(did)) else {
+                continue;
+            };
+            if user.channels.last().is_none_or(|last| last.name != name) {
+                user.channels.push(HandoverChannel {
+                    name: name.to_string(),
+                    op: false,
+                    voice: false,
+                    halfop: false,
+                });
+            }
+            let member = user.channels.last_mut().expect("pushed above");
+            member.op |= ch.ops.contains(sid);
+            member.voice |= ch.voiced.contains(sid);
+            member.halfop |= ch.halfops.contains(sid);
+        }
+    });
+    for (did, ghost) in state.ghost_sessions.lock().iter() {
+        users.entry(did.clone()).or_insert_with(|| HandoverSession {
+            did: did.clone(),
+            nick: ghost.nick.clone(),
+            hostmask: ghost.hostmask.clone(),
+            session_id: ghost.session_id.clone(),
+            channels: ghost
+                .channels
+                .iter()
+                .map(|(name, op, voice, halfop)| HandoverChannel {
+                    name: name.clone(),
+                    op: *op,
+                    voice: *voice,
+                    halfop: *halfop,
+                })
+                .collect(),
+        });
+    }
+
+    HandoverState {
+        version: VERSION,
+        server_name: state.server_name.clone(),
+        drain_secs,
+        sessions: users.into_values().collect(),
+    }
+}
+
+/// Park every handed-over user as a ghost session. Users that are
+/// already here (they reconnected first) or whose nick has been taken
+/// are skipped. Returns how many were parked.
+pub fn install(state: &Arc<SharedState>, handover: HandoverState) -> usize {
+    if handover.server_name != state.server_name {
+        tracing::warn!(
+            from = %handover.server_name, to = %state.server_name,
+            "Taking over from a server with a different name"
+        );
+    }
+    let grace = Duration::from_secs(handover.drain_secs) + RECONNECT_GRACE;
+    let mut installed = 0;
+    for user in handover.sessions {
+        let online = state.did_sessions.lock().contains_key(&user.did);
+        if online || state.ghost_sessions.lock().contains_key(&user.did) {
+            continue;
+        }
+        {
+            let mut nicks = state.nick_to_session.lock();
+            if nicks.contains_nick(&user.nick) {
+                continue;
+            }
+            nicks.insert(&user.nick, &user.session_id);
+        }
+        let mut channels = Vec::with_capacity(user.channels.len());
+        for member in user.channels {
+            let mut ch = state
+                .channels
+                .get_or_insert_with(&crate::casemap::fold(&member.name), Default::default);
+            ch.members.insert(user.session_id.clone());
+            if member.op {
+                ch.ops.insert(user.session_id.clone());
+            }
+            if member.voice {
+                ch.voiced.insert(user.session_id.clone());
+            }
+            if member.halfop {
+                ch.halfops.insert(user.session_id.clone());
+            }
+            drop(ch);
+            channels.push((member.name, member.op, member.voice, member.halfop));
+        }
+        crate::connection::park_ghost(
+            state,
+            &user.did,
+            &user.nick,
+            &user.hostmask,
+            &user.session_id,
+            channels,
+            grace,
+        );
+        installed += 1;
+    }
+    installed
+}
+
+/// Close every client connection of a server that has been taken over.
+/// Each client is told to reconnect, then disconnected; the closes are
+/// spread across `window` so the new server isn't hit by every
+/// reconnect at once. Returns once the connections are gone, or after
+/// a further `window` if some never finish closing.
+pub async fn drain(state: &Arc<SharedState>, window: Duration) {
+    let error = WireLine::from_static(b"ERROR :Server restarting, reconnect to continue\r\n");
+    let sessions: Vec<(String, Arc<tokio::sync::Notify>)> = state
+        .session_kill
+        .lock()
+        .iter()
+        .map(|(sid, kill)| (sid.clone(), kill.clone()))
+        .collect();
+    tracing::info!(
+        connections = sessions.len(),
+        "Draining connections over {}s",
+        window.as_secs()
+    );
+    let pause = window / (sessions.len() as u32).max(1);
+    for (sid, kill) in sessions {
+        if let Some(tx) = state.connections.get(&sid) {
+            let _ = tx.try_send(error.clone());
+        }
+        tokio::spawn(async move {
+            tokio::time::sleep(FLUSH_GRACE).await;
+            kill.notify_one();
+        });
+        tokio::time::sleep(pause).await;
+    }
+
+    let deadline = tokio::time::Instant::now() + FLUSH_GRACE + window;
+    while !state.connections.is_empty() && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    tracing::info!(remaining = state.connections.len(), "Drain complete");
+}
@@ -0,0 +1,370 @@
+//! Channel invite links.
+//!
+//! A channel's ops mint invites with `INVITELINK`. Each one has an
+//! optional use limit and expiry, and may waive some of the channel
+//! policy's PRESENT/PROVE requirements (see
+//! [`crate::policy::engine::waivable`]). The invite is shared as a token
+//! `{id}.{sig}`, where `sig` is an HMAC over the id and channel under a key
+//! derived from the server's signing key, so tokens can't be guessed or
+//! moved to another channel.
+//!
+//! A token is redeemed by passing it as the key in `JOIN #chan <token>`,
+//! or by opening `/invite/{token}`, a landing page that signs the user in
+//! and auto-joins them. A valid invite gets past +k and +i, but not bans.
+//! On a policy channel the user still needs a DID; the waived
+//! requirements count as met. Each join through an invite uses it once.
+//!
+//! Invites persist when the server has a database.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Most live invites a channel can hold.
+pub const MAX_INVITES_PER_CHANNEL: usize = 50;
+
+/// Longest expiry an invite can be given (90 days).
+pub const MAX_EXPIRY_SECS: i64 = 90 * 24 * 3600;
+
+/// Signature bytes kept in a token; plenty against forgery, and keeps
+/// tokens short enough to type as a JOIN key.
+const SIG_LEN: usize = 16;
+
+/// Derive the invite signing key from the server signing seed.
+pub fn derive_key(signing_seed: &[u8; 32]) -> [u8; 32] {
+    let mut mac = HmacSha256::new_from_slice(signing_seed).expect("HMAC accepts any key length");
+    mac.update(b"freeq-invite-v1");
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&mac.finalize().into_bytes());
+    key
+}
+
+fn mac(key: &[u8; 32], id: &str, channel: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(id.as_bytes());
+    mac.update(b"\n");
+    mac.update(crate::casemap::fold(channel).as_bytes());
+    mac
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invite {
+    pub id: String,
+    pub channel: String,
+    /// DID, or nick for a guest op.
+    pub created_by: String,
+    /// Unix seconds.
+    pub created_at: i64,
+    pub expires_at: Option<i64>,
+    pub max_uses: Option<u32>,
+    pub uses: u32,
+    /// Credential and proof types the invite waives.
+    pub waives: Vec<String>,
+}
+
+impl Invite {
+    /// The token to share for this invite.
+    pub fn token(&self, key: &[u8; 32]) -> String {
+        let tag = mac(key, &self.id, &self.channel).finalize().into_bytes();
+        let sig = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(&tag[..SIG_LEN]);
+        format!("{}.{sig}", self.id)
+    }
+
+    /// Why the invite can't be used at `now`, if it can't.
+    pub fn check(&self, now: i64) -> Result<(), InviteError> {
+        if self.expires_at.is_some_and(|t| now >= t) {
+            return Err(InviteError::Expired);
+        }
+        if self.max_uses.is_some_and(|max| self.uses >= max) {
+            return Err(InviteError::UsedUp);
+        }
+        Ok(())
+    }
+
+    /// Uses left, if limited.
+    pub fn remaining(&self) -> Option<u32> {
+        self.max_uses.map(|max| max.saturating_sub(self.uses))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InviteError {
+    /// Not a token we signed, or the invite was revoked.
+    Invalid,
+    /// Signed for another channel.
+    WrongChannel,
+    Expired,
+    UsedUp,
+}
+
+impl fmt::Display for InviteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            InviteError::Invalid => "Invalid or revoked invite",
+            InviteError::WrongChannel => "That invite is for another channel",
+            InviteError::Expired => "That invite has expired",
+            InviteError::UsedUp => "That invite has been used up",
+        })
+    }
+}
+
+/// Every live invite, by id.
+#[derive(Debug, Default)]
+pub struct Invites {
+    by_id: HashMap<String, Invite>,
+}
+
+impl Invites {
+    pub fn from_invites(invites: Vec<Invite>) -> Self {
+        Self {
+            by_id: invites.into_iter().map(|i| (i.id.clone(), i)).collect(),
+        }
+    }
+
+    /// A fresh, unguessable invite id (64 bits, base64url).
+    pub fn new_id() -> String {
+        let bytes: [u8; 8] = rand::random();
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+    }
+
+    /// Store a new invite, if its channel has room.
+    pub fn add(&mut self, invite: Invite) -> Result<(), String> {
+        let channel = crate::casemap::fold(&invite.channel);
+        let count = self
+            .by_id
+            .values()
+            .filter(|i| crate::casemap::fold(&i.channel) == channel)
+            .count();
+        if count >= MAX_INVITES_PER_CHANNEL {
+            return Err(format!(
+                "{} already has {MAX_INVITES_PER_CHANNEL} invites; revoke some first",
+                invite.channel
+            ));
+        }
+        self.by_id.insert(invite.id.clone(), invite);
+        Ok(())
+    }
+
+    /// The invite a token names, if the signature holds and it's usable
+    /// at `now`. `channel`, when given, must be the invite's.
+    pub fn lookup(
+        &self,
+        key: &[u8; 32],
+        token: &str,
+        channel: Option<&str>,
+        now: i64,
+    ) -> Result<&Invite, InviteError> {
+        let (id, sig) = token.split_once('.').ok_or(InviteError::Invalid)?;
+        let sig = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(sig)
+            .map_err(|_| InviteError::Invalid)?;
+        if sig.len() != SIG_LEN {
+            return Err(InviteError::Invalid);
+        }
+        let invite = self.by_id.get(id).ok_or(InviteError::Invalid)?;
+        mac(key, id, &invite.channel)
+            .verify_truncated_left(&sig)
+            .map_err(|_| InviteError::Invalid)?;
+        if channel.is_some_and(|c| crate::casemap::fold(c) != crate::casemap::fold(&invite.channel))
+        {
+            return Err(InviteError::WrongChannel);
+        }
+        invite.check(now)?;
+        Ok(invite)
+    }
+
+    /// Use invite `id` once, returning it with the use counted.
+    pub fn redeem(&mut self, id: &str, now: i64) -> Result<Invite, InviteError> {
+        let invite = self.by_id.get_mut(id).ok_or(InviteError::Invalid)?;
+        invite.check(now)?;
+        invite.uses += 1;
+        Ok(invite.clone())
+    }
+
+    /// A channel's invites, oldest first.
+    pub fn for_channel(&self, channel: &str) -> Vec<&Invite> {
+        let channel = crate::casemap::fold(channel);
+        let mut invites: Vec<&Invite> = self
+            .by_id
+            .values()
+            .filter(|i| crate::casemap::fold(&i.channel) == channel)
+            .collect();
+        invites.sort_by(|a, b| (a.created_at, &a.id).cmp(&(b.created_at, &b.id)));
+        invites
+    }
+
+    /// Revoke one of `channel`'s invites.
+    pub fn revoke(&mut self, channel: &str, id: &str) -> Option<Invite> {
+        let channel = crate::casemap::fold(channel);
+        if self
+            .by_id
+            .get(id)
+            .is_some_and(|i| crate::casemap::fold(&i.channel) == channel)
+        {
+            self.by_id.remove(id)
+        } else {
+            None
+        }
+    }
+
+    /// Forget every invite to `channel`.
+    pub fn remove_channel(&mut self, channel: &str) {
+        let channel = crate::casemap::fold(channel);
+        self.by_id
+            .retain(|_, i| crate::casemap::fold(&i.channel) != channel);
+    }
+
+    /// Drop invites that can no longer be used, returning their ids.
+    pub fn prune(&mut self, now: i64) -> Vec<String> {
+        let dead: Vec<String> = self
+            .by_id
+            .values()
+            .filter(|i| i.check(now).is_err())
+            .map(|i| i.id.clone())
+            .collect();
+        for id in &dead {
+            self.by_id.remove(id);
+        }
+        dead
+    }
+}
+
+/// Parse an expiry like `30m`, `12h` or `7d` into seconds.
+pub fn parse_duration(s: &str) -> Option<i64> {
+    let split = s.find(|c: char| !c.is_ascii_digit())?;
+    let (n, unit) = s.split_at(split);
+    let n: i64 = n.parse().ok()?;
+    let secs = match unit.to_ascii_lowercase().as_str() {
+        "m" => n.checked_mul(60)?,
+        "h" => n.checked_mul(3600)?,
+        "d" => n.checked_mul(86_400)?,
+        _ => return None,
+    };
+    (secs > 0 && secs <= MAX_EXPIRY_SECS).then_some(secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 32] = [7; 32];
+
+    fn invite(id: &str, channel: &str) -> Invite {
+        Invite {
+            id: id.into(),
+            channel: channel.into(),
+            created_by: "did:plc:op".into(),
+            created_at: 1_000,
+            expires_at: None,
+            max_uses: None,
+            uses: 0,
+            waives: vec![],
+        }
+    }
+
+    #[test]
+    fn tokens_verify_only_for_their_channel() {
+        let mut invites = Invites::default();
+        let inv = invite("abc", "#Rust");
+        let token = inv.token(&KEY);
+        invites.add(inv).unwrap();
+
+        assert_eq!(
+            invites.lookup(&KEY, &token, Some("#rust"), 0).unwrap().id,
+            "abc"
+        );
+        assert_eq!(invites.lookup(&KEY, &token, None, 0).unwrap().id, "abc");
+        assert_eq!(
+            invites.lookup(&KEY, &token, Some("#other"), 0),
+            Err(InviteError::WrongChannel)
+        );
+        assert_eq!(
+            invites.lookup(&[8; 32], &token, None, 0),
+            Err(InviteError::Invalid)
+        );
+        let (id, sig) = token.split_once('.').unwrap();
+        for forged in [
+            format!("{id}.AAAAAAAAAAAAAAAAAAAAAA"),
+            format!("{id}.{}", &sig[..4]),
+            id.to_string(),
+            "nope.xyz".to_string(),
+        ] {
+            assert_eq!(
+                invites.lookup(&KEY, &forged, None, 0),
+                Err(InviteError::Invalid),
+                "{forged}"
+            );
+        }
+    }
+
+    #[test]
+    fn uses_and_expiry_are_enforced() {
+        let mut invites = Invites::default();
+        invites
+            .add(Invite {
+                max_uses: Some(2),
+                expires_at: Some(5_000),
+                ..invite("lim", "#a")
+            })
+            .unwrap();
+        let token = invites.for_channel("#a")[0].token(&KEY);
+
+        assert_eq!(invites.redeem("lim", 1_000).unwrap().remaining(), Some(1));
+        assert_eq!(invites.redeem("lim", 1_000).unwrap().remaining(), Some(0));
+        assert_eq!(invites.redeem("lim", 1_000), Err(InviteError::UsedUp));
+        assert_eq!(
+            invites.lookup(&KEY, &token, None, 1_000),
+            Err(InviteError::UsedUp)
+        );
+
+        invites
+            .add(Invite {
+                expires_at: Some(5_000),
+                ..invite("exp", "#a")
+            })
+            .unwrap();
+        assert!(invites.redeem("exp", 4_999).is_ok());
+        assert_eq!(invites.redeem("exp", 5_000), Err(InviteError::Expired));
+
+        invites.add(invite("live", "#a")).unwrap();
+        let mut pruned = invites.prune(5_000);
+        pruned.sort();
+        assert_eq!(pruned, ["exp", "lim"]);
+        assert_eq!(invites.for_channel("#a").len(), 1);
+    }
+
+    #[test]
+    fn per_channel_limit_revoke_and_removal() {
+        let mut invites = Invites::default();
+        for n in 0..MAX_INVITES_PER_CHANNEL {
+            invites.add(invite(&n.to_string(), "#full")).unwrap();
+        }
+        assert!(invites.add(invite("one-more", "#full")).is_err());
+        invites.add(invite("x", "#other")).unwrap();
+
+        assert!(invites.revoke("#other", "0").is_none(), "not #other's");
+        assert!(invites.revoke("#FULL", "0").is_some());
+        assert!(invites.add(invite("one-more", "#full")).is_ok());
+
+        invites.remove_channel("#full");
+        assert!(invites.for_channel("#full").is_empty());
+        assert_eq!(invites.for_channel("#other").len(), 1);
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("30m"), Some(1_800));
+        assert_eq!(parse_duration("12H"), Some(43_200));
+        assert_eq!(parse_duration("7d"), Some(604_800));
+        assert_eq!(parse_duration("91d"), None);
+        assert_eq!(parse_duration("0h"), None);
+        assert_eq!(parse_duration("h"), None);
+        assert_eq!(parse_duration("10"), None);
+        assert_eq!(parse_duration("5s"), None);
+    }
+}
@@ -10,6 +10,13 @@
 //! - NAT hole-punching + relay fallback
 //! - Public-key identity per endpoint
 //! - Path toward P2P and mesh topologies
+//!
+//! An endpoint that completes SASL over iroh is bound to the DID it
+//! authenticated as (the signed challenge carries the endpoint ID as its
+//! channel binding). A bound endpoint can later `RESUME` instead of
+//! registering, and with `iroh_allowed_dids` set, only endpoints bound
+//! to an allowed DID — or unbound ones that go on to authenticate as
+//! one — can use the transport.
 
 use std::sync::Arc;
 
@@ -35,6 +42,11 @@ pub const ALPN: &[u8] = b"freeq/iroh/1";
 /// session cleanup (QUIT broadcast, channel removal, etc.).
 pub async fn handle_connection(conn: Connection, state: Arc<SharedState>) {
     let remote_id = conn.remote_id();
+    if !state.iroh_endpoint_admitted(&remote_id.to_string()) {
+        tracing::warn!(%remote_id, "Iroh endpoint bound to a DID not in iroh_allowed_dids, refusing");
+        conn.close(1u32.into(), b"endpoint not authorized");
+        return;
+    }
     tracing::info!(%remote_id, "Iroh connection accepted");
 
     let (send, recv) = match conn.accept_bi().await {
@@ -2,25 +2,49 @@
 //! IRC server with AT Protocol SASL authentication.
 
 pub mod agent_assist;
+pub mod alias;
 pub mod av;
 pub mod av_artifacts;
 pub mod av_bridge;
 pub mod av_media;
 pub mod av_sfu;
+pub mod backup;
+pub mod casemap;
 pub mod config;
 pub mod connection;
 pub mod crdt;
 pub mod db;
+pub mod directory;
+pub mod email;
+pub mod ephemeral;
+pub mod filters;
+pub mod firehose;
+#[cfg(unix)]
+pub mod handover;
+pub mod invites;
 pub mod irc;
 pub mod iroh;
+pub mod locale;
 pub mod manifest;
 pub mod media_store;
+pub mod migrate;
 pub mod msgid;
 pub mod plugin;
 pub mod policy;
+pub mod prefs;
+pub mod reserved;
 pub mod s2s;
+#[cfg(feature = "s2s-faults")]
+pub mod s2s_faults;
 pub mod sasl;
 pub mod secrets;
+pub mod send_queue;
 pub mod server;
+pub mod session;
+pub mod sharded;
+pub mod stats;
+pub mod telemetry;
+pub mod temporary;
+pub mod testing;
 pub mod verifiers;
 pub mod web;
@@ -0,0 +1,496 @@
+//! Localized human-readable text in numerics and notices.
+//!
+//! Message catalogs are keyed by the English text the server sends, the
+//! way gettext catalogs are, so handlers keep writing English and nothing
+//! changes for clients that don't ask for another language. A catalog is a
+//! TOML file named after its language code (`fr.toml`, `pt-BR.toml`):
+//!
+//! ```toml
+//! language = "Français"
+//!
+//! [messages]
+//! "No such nick" = "Pseudo inconnu"
+//! "Welcome to {}, {}" = "Bienvenue sur {}, {}"
+//! "You are now logged in as {}" = "Vous êtes connecté en tant que {1}"
+//! ```
+//!
+//! `{}` in a key stands for text that varies (a nick, a channel, a
+//! number); the translation repeats the captured text with `{}` in the
+//! same order, or `{1}`, `{2}`, … to reorder it. Text with no entry is
+//! sent in English.
+//!
+//! A client picks a language with `LANGUAGE <code>[,<code>…]` (the first
+//! one the server has wins; `fr-CA` falls back to `fr`), and the
+//! `freeq.at/language` capability's value lists the codes on offer. Only
+//! the trailing parameter of this server's numerics and of its `NOTICE`,
+//! `FAIL`, `WARN` and `NOTE` lines is translated: commands, numeric codes,
+//! standard-reply codes and every other parameter stay as they are, and
+//! numerics whose trailing parameter is user content (topics, away
+//! messages, real names, MOTD lines) are left alone.
+//!
+//! Catalogs are built in ([`BUILTIN`]) and read from `--locale-dir`,
+//! whose files replace built-in ones with the same code.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+
+use crate::irc;
+use crate::server::WireLine;
+
+/// The language the server's text is written in.
+pub const DEFAULT: &str = "en";
+
+/// Catalogs compiled into the server, as `(code, toml)`.
+pub const BUILTIN: &[(&str, &str)] = &[("fr", include_str!("../locales/fr.toml"))];
+
+/// Numerics whose trailing parameter is user content, never translated.
+const USER_TEXT_NUMERICS: &[&str] = &[
+    irc::RPL_AWAY,
+    irc::RPL_WHOISUSER,
+    irc::RPL_LIST,
+    irc::RPL_TOPIC,
+    irc::RPL_WHOREPLY,
+    irc::RPL_NAMREPLY,
+    irc::RPL_WHOSPCRPL,
+    irc::RPL_MOTD,
+    irc::RPL_KEYVALUE,
+];
+
+/// A connection's chosen catalog, shared with its writer task. `None` is
+/// [`DEFAULT`].
+pub type Language = Arc<RwLock<Option<Arc<Catalog>>>>;
+
+/// One language's translations.
+#[derive(Debug)]
+pub struct Catalog {
+    pub code: String,
+    /// The language's own name for itself.
+    pub name: String,
+    exact: HashMap<String, String>,
+    /// Keys with placeholders, most specific first.
+    patterns: Vec<Pattern>,
+}
+
+#[derive(Debug)]
+struct Pattern {
+    /// The key split at its placeholders.
+    literals: Vec<String>,
+    translation: Vec<Piece>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Piece {
+    Text(String),
+    /// A captured placeholder, 0-based.
+    Arg(usize),
+}
+
+#[derive(serde::Deserialize)]
+struct CatalogFile {
+    language: Option<String>,
+    #[serde(default)]
+    messages: BTreeMap<String, String>,
+}
+
+impl Catalog {
+    /// Parse a catalog file for language `code`.
+    pub fn parse(code: &str, text: &str) -> Result<Self, String> {
+        let file: CatalogFile = toml::from_str(text).map_err(|e| format!("catalog {code}: {e}"))?;
+        let mut exact = HashMap::new();
+        let mut patterns = Vec::new();
+        for (key, value) in file.messages {
+            if !key.contains("{}") {
+                exact.insert(key, value);
+                continue;
+            }
+            let literals: Vec<String> = key.split("{}").map(str::to_string).collect();
+            let translation = parse_translation(&value, literals.len() - 1)
+                .map_err(|e| format!("catalog {code}: \"{key}\": {e}"))?;
+            patterns.push(Pattern {
+                literals,
+                translation,
+            });
+        }
+        // Longer fixed text first, so "Cannot join channel {} (+i)" wins
+        // over "Cannot join channel {}".
+        patterns
+            .sort_by_key(|p| std::cmp::Reverse(p.literals.iter().map(String::len).sum::<usize>()));
+        Ok(Self {
+            code: code.to_string(),
+            name: file.language.unwrap_or_else(|| code.to_string()),
+            exact,
+            patterns,
+        })
+    }
+
+    /// `text` in this language, if the catalog has it.
+    pub fn translate(&self, text: &str) -> Option<String> {
+        if let Some(t) = self.exact.get(text) {
+            return Some(t.clone());
+        }
+        self.patterns.iter().find_map(|p| {
+            let args = p.captures(text)?;
+            Some(
+                p.translation
+                    .iter()
+                    .map(|piece| match piece {
+                        Piece::Text(s) => s.as_str(),
+                        Piece::Arg(i) => args[*i],
+                    })
+                    .collect(),
+            )
+        })
+    }
+
+    /// Entries in the catalog.
+    pub fn len(&self) -> usize {
+        self.exact.len() + self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Pattern {
+    /// The text `text` has at each placeholder, if it matches. Placeholders
+    /// match as little as they can, and never nothing.
+    fn captures<'a>(&self, text: &'a str) -> Option<Vec<&'a str>> {
+        let (first, rest) = self.literals.split_first()?;
+        let (last, middle) = rest.split_last()?;
+        let mut pos = first.len();
+        if !text.starts_with(first.as_str()) {
+            return None;
+        }
+        let end = text.len().checked_sub(last.len())?;
+        if end < pos || !text.ends_with(last.as_str()) {
+            return None;
+        }
+        let mut args = Vec::with_capacity(rest.len());
+        for literal in middle {
+            // +1: the placeholder before it takes at least one character
+            let start = pos + text[pos..end].chars().next()?.len_utf8();
+            let found = start + text[start..end].find(literal.as_str())?;
+            args.push(&text[pos..found]);
+            pos = found + literal.len();
+        }
+        if pos >= end {
+            return None;
+        }
+        args.push(&text[pos..end]);
+        Some(args)
+    }
+}
+
+/// Split a translation into text and placeholders: `{}` takes the next
+/// argument, `{N}` argument N (1-based).
+fn parse_translation(value: &str, args: usize) -> Result<Vec<Piece>, String> {
+    let mut pieces = Vec::new();
+    let mut next = 0;
+    let mut rest = value;
+    while let Some(open) = rest.find('{') {
+        let Some(close) = rest[open..].find('}').map(|c| open + c) else {
+            break;
+        };
+        let inner = &rest[open + 1..close];
+        let index = if inner.is_empty() {
+            next += 1;
+            next - 1
+        } else if let Ok(n) = inner.parse::<usize>() {
+            if n == 0 {
+                return Err("placeholders count from {1}".to_string());
+            }
+            n - 1
+        } else {
+            // Not a placeholder; keep the brace as text
+            pieces.push(Piece::Text(rest[..=open].to_string()));
+            rest = &rest[open + 1..];
+            continue;
+        };
+        if index >= args {
+            return Err(format!("the key has only {args} placeholder(s)"));
+        }
+        if open > 0 {
+            pieces.push(Piece::Text(rest[..open].to_string()));
+        }
+        pieces.push(Piece::Arg(index));
+        rest = &rest[close + 1..];
+    }
+    if !rest.is_empty() {
+        pieces.push(Piece::Text(rest.to_string()));
+    }
+    Ok(pieces)
+}
+
+/// Every catalog the server has, by lowercased code.
+#[derive(Debug, Default)]
+pub struct Locales {
+    catalogs: BTreeMap<String, Arc<Catalog>>,
+}
+
+impl Locales {
+    /// The built-in catalogs plus those in `dir`. Bad files are logged and
+    /// skipped.
+    pub fn load(dir: Option<&str>) -> Self {
+        let mut locales = Self::default();
+        for (code, text) in BUILTIN {
+            match Catalog::parse(code, text) {
+                Ok(catalog) => locales.insert(catalog),
+                Err(e) => tracing::error!("Built-in {e}"),
+            }
+        }
+        if let Some(dir) = dir {
+            locales.load_dir(Path::new(dir));
+        }
+        locales
+    }
+
+    fn load_dir(&mut self, dir: &Path) {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                tracing::warn!("Locale dir {}: {e}", dir.display());
+                return;
+            }
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("toml") {
+                continue;
+            }
+            let Some(code) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            if code.eq_ignore_ascii_case(DEFAULT) {
+                tracing::warn!("{}: English is the server's own text", path.display());
+                continue;
+            }
+            let parsed = std::fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|text| Catalog::parse(code, &text));
+            match parsed {
+                Ok(catalog) => {
+                    tracing::info!("Loaded {} message(s) for {code}", catalog.len());
+                    self.insert(catalog);
+                }
+                Err(e) => tracing::warn!("{}: {e}", path.display()),
+            }
+        }
+    }
+
+    fn insert(&mut self, catalog: Catalog) {
+        self.catalogs
+            .insert(catalog.code.to_ascii_lowercase(), Arc::new(catalog));
+    }
+
+    /// The language codes on offer, [`DEFAULT`] first.
+    pub fn codes(&self) -> Vec<&str> {
+        std::iter::once(DEFAULT)
+            .chain(self.catalogs.values().map(|c| c.code.as_str()))
+            .collect()
+    }
+
+    /// The first of the comma-separated `wanted` codes on offer, as its
+    /// code and catalog (`None` for [`DEFAULT`]). A regional code falls back
+    /// to its base language.
+    pub fn pick(&self, wanted: &str) -> Option<(String, Option<Arc<Catalog>>)> {
+        wanted
+            .split(',')
+            .map(|code| code.trim().to_ascii_lowercase())
+            .filter(|code| !code.is_empty())
+            .find_map(|code| {
+                let base = code.split(['-', '_']).next().unwrap_or_default();
+                if base == DEFAULT {
+                    return Some((DEFAULT.to_string(), None));
+                }
+                let catalog = self
+                    .catalogs
+                    .get(&code)
+                    .or_else(|| self.catalogs.get(base))?;
+                Some((catalog.code.clone(), Some(catalog.clone())))
+            })
+    }
+}
+
+/// `line` as a connection speaking `language` should see it.
+pub fn for_connection(line: WireLine, language: &Language, server_name: &str) -> WireLine {
+    let Some(catalog) = language.read().clone() else {
+        return line;
+    };
+    match localize(&line, server_name, &catalog) {
+        Some(localized) => localized.into(),
+        None => line,
+    }
+}
+
+/// Translate the lines in `wire` (one or more CRLF-terminated lines) that
+/// `server_name` sent, or None if nothing changed.
+pub fn localize(wire: &[u8], server_name: &str, catalog: &Catalog) -> Option<Vec<u8>> {
+    let text = std::str::from_utf8(wire).ok()?;
+    let mut changed = false;
+    let mut out = String::with_capacity(text.len() + 16);
+    for line in text.split_inclusive('\n') {
+        let (body, ending) = match line.strip_suffix("\r\n") {
+            Some(body) => (body, "\r\n"),
+            None => match line.strip_suffix('\n') {
+                Some(body) => (body, "\n"),
+                None => (line, ""),
+            },
+        };
+        match localize_line(body, server_name, catalog) {
+            Some(localized) => {
+                changed = true;
+                out.push_str(&localized);
+            }
+            None => out.push_str(body),
+        }
+        out.push_str(ending);
+    }
+    changed.then(|| out.into_bytes())
+}
+
+/// One line without its CRLF.
+fn localize_line(line: &str, server_name: &str, catalog: &Catalog) -> Option<String> {
+    // Skip tags, then require our own prefix
+    let rest_start = if line.starts_with('@') {
+        line.find(' ')? + 1
+    } else {
+        0
+    };
+    let rest = line[rest_start..].strip_prefix(':')?;
+    let rest = rest.strip_prefix(server_name)?.strip_prefix(' ')?;
+    let command = rest.split(' ').next()?;
+    let translatable = match command {
+        "NOTICE" | "FAIL" | "WARN" | "NOTE" => true,
+        _ => {
+            command.len() == 3
+                && command.bytes().all(|b| b.is_ascii_digit())
+                && !USER_TEXT_NUMERICS.contains(&command)
+        }
+    };
+    if !translatable {
+        return None;
+    }
+    let trailing_at = line.len() - rest.len() + rest.find(" :")? + 2;
+    let translated = catalog.translate(&line[trailing_at..])?;
+    Some(format!("{}{translated}", &line[..trailing_at]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn catalog() -> Catalog {
+        Catalog::parse(
+            "fr",
+            r#"
+            language = "Français"
+            [messages]
+            "No such nick" = "Pseudo inconnu"
+            "Welcome to {}, {}" = "Bienvenue sur {}, {}"
+            "{} has been invited to {}" = "{2} : {1} a été invité"
+            "Cannot join channel {}" = "Impossible de rejoindre {}"
+            "Cannot join channel {} (+i)" = "Impossible de rejoindre {} (sur invitation)"
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn exact_and_placeholder_entries_translate() {
+        let c = catalog();
+        assert_eq!(c.name, "Français");
+        assert_eq!(c.translate("No such nick").unwrap(), "Pseudo inconnu");
+        assert_eq!(c.translate("No such nick!"), None);
+        assert_eq!(
+            c.translate("Welcome to freeq, alice (did:plc:abc)")
+                .unwrap(),
+            "Bienvenue sur freeq, alice (did:plc:abc)"
+        );
+        assert_eq!(
+            c.translate("bob has been invited to #rust").unwrap(),
+            "#rust : bob a été invité"
+        );
+        // The more specific key wins
+        assert_eq!(
+            c.translate("Cannot join channel #a (+i)").unwrap(),
+            "Impossible de rejoindre #a (sur invitation)"
+        );
+        assert_eq!(
+            c.translate("Cannot join channel #a").unwrap(),
+            "Impossible de rejoindre #a"
+        );
+        // Placeholders never match nothing
+        assert_eq!(c.translate("Welcome to , alice"), None);
+    }
+
+    #[test]
+    fn bad_translations_are_rejected() {
+        let bad = r#"[messages]
+            "Hello {}" = "Bonjour {2}""#;
+        assert!(Catalog::parse("fr", bad).is_err());
+        let braces = r#"[messages]
+            "Use {}" = "Utilisez {x} {}""#;
+        let c = Catalog::parse("fr", braces).unwrap();
+        assert_eq!(c.translate("Use HELP").unwrap(), "Utilisez {x} HELP");
+    }
+
+    #[test]
+    fn only_our_human_text_is_translated() {
+        let c = catalog();
+        let wire = b"@time=x :irc.test 401 alice bob :No such nick\r\n\
+                     :irc.test 332 alice #a :No such nick\r\n\
+                     :other.test 401 alice bob :No such nick\r\n\
+                     :irc.test FAIL PREF UNKNOWN No such nick\r\n\
+                     :bob!b@h PRIVMSG alice :No such nick\r\n";
+        let out = localize(wire, "irc.test", &c).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "@time=x :irc.test 401 alice bob :Pseudo inconnu\r\n\
+             :irc.test 332 alice #a :No such nick\r\n\
+             :other.test 401 alice bob :No such nick\r\n\
+             :irc.test FAIL PREF UNKNOWN No such nick\r\n\
+             :bob!b@h PRIVMSG alice :No such nick\r\n"
+        );
+        assert_eq!(
+            localize(b":irc.test NOTICE * :unknown text\r\n", "irc.test", &c),
+            None
+        );
+    }
+
+    #[test]
+    fn languages_are_negotiated_in_order() {
+        let mut locales = Locales::default();
+        locales.insert(catalog());
+        assert_eq!(locales.codes(), ["en", "fr"]);
+        assert_eq!(locales.pick("de, fr-CA").unwrap().0, "fr");
+        let (code, catalog) = locales.pick("en-GB,fr").unwrap();
+        assert_eq!(code, "en");
+        assert!(catalog.is_none());
+        assert!(locales.pick("de").is_none());
+        assert!(locales.pick("").is_none());
+    }
+
+    #[test]
+    fn builtin_catalogs_parse() {
+        let locales = Locales::load(None);
+        for (code, _) in BUILTIN {
+            let (_, catalog) = locales.pick(code).unwrap();
+            assert!(!catalog.unwrap().is_empty(), "{code}");
+        }
+        let (_, fr) = locales.pick("fr").unwrap();
+        let fr = fr.unwrap();
+        assert_eq!(
+            fr.translate("Welcome to irc.test, alice (guest)").unwrap(),
+            "Bienvenue sur irc.test, alice (invité)"
+        );
+        assert_eq!(
+            fr.translate("Welcome to irc.test, alice (authenticated as did:plc:a)")
+                .unwrap(),
+            "Bienvenue sur irc.test, alice (authentifié en tant que did:plc:a)"
+        );
+    }
+}
@@ -1,6 +1,8 @@
 use anyhow::Result;
 use clap::Parser;
-use tracing_subscriber::EnvFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -10,16 +12,27 @@ async fn main() -> Result<()> {
     // Use JSON logs in production (FREEQ_LOG_JSON=1), human-readable otherwise
     let json_logs = std::env::var("FREEQ_LOG_JSON").unwrap_or_default() == "1";
     let filter = EnvFilter::from_default_env().add_directive("freeq_server=info".parse()?);
-    if json_logs {
-        tracing_subscriber::fmt()
-            .with_env_filter(filter)
-            .json()
-            .init();
+    let logs = if json_logs {
+        tracing_subscriber::fmt::layer().json().boxed()
     } else {
-        tracing_subscriber::fmt().with_env_filter(filter).init();
-    }
+        tracing_subscriber::fmt::layer().boxed()
+    };
+    let registry = tracing_subscriber::registry().with(logs.with_filter(filter));
+    // Message-path spans over OTLP, when OTEL_EXPORTER_OTLP_ENDPOINT is set
+    // (see freeq_server::telemetry).
+    #[cfg(feature = "otel")]
+    let (otlp, _otlp_guard) = freeq_server::telemetry::otlp_layer()?.unzip();
+    #[cfg(feature = "otel")]
+    let registry = registry.with(otlp);
+    registry.init();
 
     let mut config = freeq_server::config::ServerConfig::parse();
+    if let Some(ref path) = config.export_state {
+        return freeq_server::migrate::export_to_file(&config, path);
+    }
+    if let Some(ref path) = config.import_state {
+        return freeq_server::migrate::import_from_file(&config, path);
+    }
     tracing::info!("Starting IRC server on {}", config.listen_addr);
     if config.tls_enabled() {
         tracing::info!("TLS enabled on {}", config.tls_listen_addr);
@@ -41,6 +54,19 @@ async fn main() -> Result<()> {
             Err(e) => tracing::warn!("Failed to read MOTD file {path}: {e}"),
         }
     }
+    config.auto_join = std::mem::take(&mut config.auto_join)
+        .iter()
+        .map(|ch| ch.trim())
+        .filter(|ch| {
+            let valid = ch.starts_with('#') || ch.starts_with('&');
+            if !valid && !ch.is_empty() {
+                tracing::warn!("Ignoring --auto-join entry {ch:?}: not a channel name");
+            }
+            valid
+        })
+        .map(String::from)
+        .collect();
+    freeq_server::connection::webirc::validate(&config).map_err(anyhow::Error::msg)?;
     let server = freeq_server::server::Server::new(config);
     server.run().await
 }
@@ -130,6 +130,12 @@ pub trait Plugin: Send + Sync {
     fn on_nick_change(&self, event: &NickChangeEvent) {
         let _ = event;
     }
+
+    /// Command aliases this plugin adds, as `WORDS=EXPANSION` specs
+    /// (see [`crate::alias`]). `--alias` entries take precedence.
+    fn aliases(&self) -> Vec<String> {
+        Vec::new()
+    }
 }
 
 /// A factory function that creates a plugin instance from config.
@@ -141,6 +147,9 @@ fn builtin_plugins() -> HashMap<&'static str, PluginFactory> {
     m.insert("identity-override", |config| {
         Box::new(IdentityOverridePlugin::from_config(config))
     });
+    m.insert("aliases", |config| {
+        Box::new(AliasPlugin::from_config(config))
+    });
     m
 }
 
@@ -272,6 +281,11 @@ impl PluginManager {
         }
     }
 
+    /// Command alias specs contributed by all plugins, in load order.
+    pub fn aliases(&self) -> Vec<String> {
+        self.plugins.iter().flat_map(|p| p.aliases()).collect()
+    }
+
     /// Returns true if any plugins are loaded.
     pub fn has_plugins(&self) -> bool {
         !self.plugins.is_empty()
@@ -375,6 +389,36 @@ impl Plugin for IdentityOverridePlugin {
     }
 }
 
+/// Plugin that adds command aliases from its config. Each key is the
+/// alias words and each value the expansion:
+///
+/// ```toml
+/// name = "aliases"
+/// "NS REGISTER" = "LOGIN $1"
+/// "CS PROTECT" = "MODE $1 +o $2"
+/// ```
+struct AliasPlugin {
+    specs: Vec<String>,
+}
+
+impl AliasPlugin {
+    fn from_config(config: &HashMap<String, String>) -> Self {
+        let mut specs: Vec<String> = config.iter().map(|(k, v)| format!("{k}={v}")).collect();
+        specs.sort();
+        Self { specs }
+    }
+}
+
+impl Plugin for AliasPlugin {
+    fn name(&self) -> &str {
+        "aliases"
+    }
+
+    fn aliases(&self) -> Vec<String> {
+        self.specs.clone()
+    }
+}
+
 // ── Parsing helpers ─────────────────────────────────────────────
 
 /// Parse a CLI plugin arg like "name:key=val,key2=val2" into (name, config).
@@ -38,6 +38,7 @@ pub fn routes() -> Router<Arc<SharedState>> {
         .route("/api/v1/credentials/{did}", get(get_credentials))
         .route("/api/v1/credentials/present", post(present_credential))
         .route("/api/v1/policy/{channel}/check", post(check_requirements))
+        .route("/api/v1/policy/{channel}/test/{did}", get(test_policy))
 }
 
 // ─── Request/Response Types ──────────────────────────────────────────────────
@@ -99,7 +100,7 @@ fn normalize_channel(channel: &str) -> String {
     } else {
         format!("#{channel}")
     };
-    ch.to_lowercase()
+    crate::casemap::fold(&ch)
 }
 
 // ─── Handlers ────────────────────────────────────────────────────────────────
@@ -240,6 +241,40 @@ async fn check_membership(
     }
 }
 
+/// Dry-run a join by `did` (see [`super::PolicyEngine::dry_run`]) and
+/// return the evaluation trace. Bearer session of the channel founder or a
+/// DID-op.
+async fn test_policy(
+    State(state): State<Arc<SharedState>>,
+    Path((channel, did)): Path<(String, String)>,
+    headers: axum::http::HeaderMap,
+) -> impl IntoResponse {
+    let engine = match get_engine(&state) {
+        Ok(e) => e,
+        Err(e) => return e.into_response(),
+    };
+    let channel_id = normalize_channel(&channel);
+    let Some(caller) = crate::web::caller_did_from_bearer(&state, &headers) else {
+        return (StatusCode::UNAUTHORIZED, "Bearer session required").into_response();
+    };
+    let is_authority = state.channels.get(&channel_id).is_some_and(|ch| {
+        ch.founder_did.as_deref() == Some(caller.as_str()) || ch.did_ops.contains(&caller)
+    });
+    if !is_authority {
+        return (
+            StatusCode::FORBIDDEN,
+            "Only the channel founder or a DID-op may test its policy",
+        )
+            .into_response();
+    }
+
+    match engine.dry_run(&channel_id, &did) {
+        Ok(Some(trace)) => Json(trace).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "Channel has no policy").into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
 async fn get_transparency_log(
     State(state): State<Arc<SharedState>>,
     Path(channel): Path<String>,
@@ -0,0 +1,459 @@
+//! Policy chain arbitration between federated servers.
+//!
+//! Every policy version names its predecessor's hash, so a channel's
+//! policies form a hash chain. When two servers were partitioned and both
+//! changed a channel's policy, their chains fork. On relink each side
+//! announces its head during the sync burst; a side that doesn't know the
+//! peer's head fetches the peer's whole chain and [`arbitrate`]s. The
+//! longer valid chain wins, and equal lengths go to the lower head hash, so
+//! every server picks the same winner without coordinating.
+//!
+//! A valid chain is signed as well as linked: each version carries
+//! Ed25519 signatures over its `policy_id` from at least the threshold of
+//! the authority set in force at the version before it (version 1, from
+//! its own). A peer can replay a chain but not write a version the
+//! channel's authorities didn't sign, and the founder travels inside the
+//! signed documents rather than alongside them.
+
+use std::collections::BTreeSet;
+
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+
+use super::canonical;
+use super::types::{AuthoritySet, PolicyDocument, PolicySignature};
+
+/// Longest chain accepted from a peer.
+pub const MAX_CHAIN_LEN: usize = 1000;
+
+/// What to do with a peer's chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    /// Ours is the same, ahead, or wins the fork.
+    Keep,
+    /// Theirs extends ours (or we have none): adopt it.
+    FastForward,
+    /// The chains forked and theirs wins: adopt it, replacing ours.
+    Supersede,
+    /// Their chain is broken.
+    Invalid(String),
+}
+
+/// The `policy_id` a document should have.
+pub fn policy_hash(doc: &PolicyDocument) -> Result<String, String> {
+    let mut doc = doc.clone();
+    doc.policy_id = None;
+    doc.signatures.clear();
+    canonical::hash_canonical(&doc).map_err(|e| e.to_string())
+}
+
+/// The `authority_set_hash` an authority set should have.
+pub fn authority_set_hash(set: &AuthoritySet) -> Result<String, String> {
+    let mut set = set.clone();
+    set.authority_set_hash = None;
+    canonical::hash_canonical(&set).map_err(|e| e.to_string())
+}
+
+/// How an authority set lists `key` among its signers.
+pub fn public_key(key: &VerifyingKey) -> String {
+    let key = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(key.as_bytes());
+    format!("ed25519:{key}")
+}
+
+/// Sign `doc` as `signer`, replacing any signatures it had. `doc` must
+/// carry its `policy_id`.
+pub fn sign(doc: &mut PolicyDocument, signer: &str, key: &SigningKey) {
+    let policy_id = doc.policy_id.as_deref().unwrap_or_default();
+    let signature = key.sign(policy_id.as_bytes());
+    doc.signatures = vec![PolicySignature {
+        signer: signer.to_string(),
+        signature: base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(signature.to_bytes()),
+    }];
+}
+
+/// Whether `signature` is `public_key`'s signature over `policy_id`.
+fn signature_verifies(public_key: &str, policy_id: &str, signature: &str) -> bool {
+    let engine = &base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    let Some(key) = public_key
+        .strip_prefix("ed25519:")
+        .and_then(|k| engine.decode(k).ok())
+        .and_then(|k| <[u8; 32]>::try_from(k).ok())
+        .and_then(|k| VerifyingKey::from_bytes(&k).ok())
+    else {
+        return false;
+    };
+    let Some(signature) = engine
+        .decode(signature)
+        .ok()
+        .and_then(|s| Signature::from_slice(&s).ok())
+    else {
+        return false;
+    };
+    key.verify(policy_id.as_bytes(), &signature).is_ok()
+}
+
+/// Check that `doc` is a valid next version after `previous` (`None` for
+/// version 1) for `channel`: it counts up, names `previous` as
+/// `previous_policy_hash`, its `policy_id` is its hash, and it is signed
+/// by at least the threshold of the authority set in force at `previous`
+/// (its own set for version 1). `authority_sets` looks sets up by hash.
+pub fn verify_next(
+    channel: &str,
+    previous: Option<&PolicyDocument>,
+    doc: &PolicyDocument,
+    authority_sets: &dyn Fn(&str) -> Option<AuthoritySet>,
+) -> Result<(), String> {
+    let version = previous.map_or(0, |p| p.version) + 1;
+    let channel = crate::casemap::fold(channel);
+    if crate::casemap::fold(&doc.channel_id) != channel {
+        return Err(format!("version {version} is for {}", doc.channel_id));
+    }
+    if doc.version != version {
+        return Err(format!("expected version {version}, got {}", doc.version));
+    }
+    if doc.previous_policy_hash.as_deref() != previous.and_then(|p| p.policy_id.as_deref()) {
+        return Err(format!(
+            "version {version} doesn't follow version {}",
+            version - 1
+        ));
+    }
+    let hash = policy_hash(doc)?;
+    if doc.policy_id.as_deref() != Some(hash.as_str()) {
+        return Err(format!("version {version} has the wrong policy_id"));
+    }
+
+    let in_force = previous.unwrap_or(doc).authority_set_hash.as_str();
+    let set = authority_sets(in_force)
+        .filter(|set| authority_set_hash(set).is_ok_and(|h| h == in_force))
+        .filter(|set| crate::casemap::fold(&set.channel_id) == channel)
+        .ok_or_else(|| format!("version {version}'s authority set is unknown"))?;
+    let signed: BTreeSet<&str> = doc
+        .signatures
+        .iter()
+        .filter(|s| {
+            set.signers.iter().any(|signer| {
+                signer.did == s.signer
+                    && signature_verifies(&signer.public_key, &hash, &s.signature)
+            })
+        })
+        .map(|s| s.signer.as_str())
+        .collect();
+    let threshold = set.policy_threshold.max(1) as usize;
+    if signed.len() < threshold {
+        return Err(format!(
+            "version {version} has {} of {threshold} authority signatures",
+            signed.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Check that `chain` (oldest first) is a valid chain for `channel`: each
+/// version passes [`verify_next`] after the one before it.
+pub fn verify(
+    channel: &str,
+    chain: &[PolicyDocument],
+    authority_sets: &dyn Fn(&str) -> Option<AuthoritySet>,
+) -> Result<(), String> {
+    if chain.is_empty() {
+        return Err("empty chain".into());
+    }
+    if chain.len() > MAX_CHAIN_LEN {
+        return Err(format!("chain longer than {MAX_CHAIN_LEN}"));
+    }
+    let mut previous = None;
+    for doc in chain {
+        verify_next(channel, previous, doc, authority_sets)?;
+        previous = Some(doc);
+    }
+    Ok(())
+}
+
+/// Decide between our chain and a peer's, both oldest first. The peer's
+/// is [`verify`]d with `authority_sets`.
+pub fn arbitrate(
+    channel: &str,
+    ours: &[PolicyDocument],
+    theirs: &[PolicyDocument],
+    authority_sets: &dyn Fn(&str) -> Option<AuthoritySet>,
+) -> Verdict {
+    if let Err(e) = verify(channel, theirs, authority_sets) {
+        return Verdict::Invalid(e);
+    }
+    let head = |chain: &[PolicyDocument]| chain.last().and_then(|d| d.policy_id.clone());
+    let (Some(our_head), Some(their_head)) = (head(ours), head(theirs)) else {
+        return Verdict::FastForward;
+    };
+    let contains = |chain: &[PolicyDocument], hash: &str| {
+        chain.iter().any(|d| d.policy_id.as_deref() == Some(hash))
+    };
+    if contains(theirs, &our_head) {
+        return if our_head == their_head {
+            Verdict::Keep
+        } else {
+            Verdict::FastForward
+        };
+    }
+    if contains(ours, &their_head) {
+        return Verdict::Keep;
+    }
+    // Forked: longer wins, then the lower head hash.
+    if (theirs.len(), std::cmp::Reverse(&their_head)) > (ours.len(), std::cmp::Reverse(&our_head)) {
+        Verdict::Supersede
+    } else {
+        Verdict::Keep
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::types::{AuthoritySigner, ReceiptEmbedding, Requirement, ValidityModel};
+
+    fn key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+
+    fn did(key: &SigningKey) -> String {
+        format!("did:web:s{}.test", key.to_bytes()[0])
+    }
+
+    /// #chan's authority set with `keys` as signers.
+    fn authority(keys: &[SigningKey], policy_threshold: i32) -> AuthoritySet {
+        let mut set = AuthoritySet {
+            authority_set_hash: None,
+            channel_id: "#chan".into(),
+            signers: keys
+                .iter()
+                .map(|k| AuthoritySigner {
+                    did: did(k),
+                    public_key: public_key(&k.verifying_key()),
+                    label: None,
+                    endpoint: None,
+                })
+                .collect(),
+            policy_threshold,
+            authority_refresh_ttl_seconds: 3600,
+            transparency: None,
+            previous_authority_set_hash: None,
+        };
+        set.authority_set_hash = Some(authority_set_hash(&set).unwrap());
+        set
+    }
+
+    /// The sets the tests sign under: key 1 alone (the default), key 9
+    /// alone, and keys 1 and 2 with a threshold of two.
+    fn sets(hash: &str) -> Option<AuthoritySet> {
+        [
+            authority(&[key(1)], 1),
+            authority(&[key(9)], 1),
+            authority(&[key(1), key(2)], 2),
+        ]
+        .into_iter()
+        .find(|set| set.authority_set_hash.as_deref() == Some(hash))
+    }
+
+    fn next(chain: &[PolicyDocument], rules: &str) -> PolicyDocument {
+        next_under(chain, rules, &authority(&[key(1)], 1), &key(1))
+    }
+
+    /// The next version naming `set` as its authority, signed by `signer`.
+    fn next_under(
+        chain: &[PolicyDocument],
+        rules: &str,
+        set: &AuthoritySet,
+        signer: &SigningKey,
+    ) -> PolicyDocument {
+        let mut doc = PolicyDocument {
+            channel_id: "#Chan".into(),
+            policy_id: None,
+            version: chain.len() as i64 + 1,
+            effective_at: "2026-01-01T00:00:00Z".into(),
+            previous_policy_hash: chain.last().and_then(|d| d.policy_id.clone()),
+            authority_set_hash: set.authority_set_hash.clone().unwrap(),
+            requirements: Requirement::Accept { hash: rules.into() },
+            role_requirements: Default::default(),
+            validity_model: ValidityModel::JoinTime,
+            receipt_embedding: ReceiptEmbedding::Require,
+            policy_locations: vec![],
+            limits: None,
+            transparency: None,
+            credential_endpoints: Default::default(),
+            agent_budget: None,
+            agent_budgets: Default::default(),
+            history_visibility: None,
+            founder_did: Some("did:plc:founder".into()),
+            signatures: vec![],
+        };
+        doc.policy_id = Some(policy_hash(&doc).unwrap());
+        sign(&mut doc, &did(signer), signer);
+        doc
+    }
+
+    fn chain(rules: &[&str]) -> Vec<PolicyDocument> {
+        fork(&[], rules)
+    }
+
+    fn fork(base: &[PolicyDocument], rules: &[&str]) -> Vec<PolicyDocument> {
+        let mut chain = base.to_vec();
+        for rules in rules {
+            let doc = next(&chain, rules);
+            chain.push(doc);
+        }
+        chain
+    }
+
+    #[test]
+    fn well_formed_chains_verify() {
+        let good = chain(&["a", "b", "c"]);
+        assert_eq!(verify("#chan", &good, &sets), Ok(()));
+        assert!(verify("#other", &good, &sets).is_err());
+        assert!(verify("#chan", &[], &sets).is_err());
+
+        let mut tampered = good.clone();
+        tampered[1].requirements = Requirement::Accept { hash: "x".into() };
+        assert!(
+            verify("#chan", &tampered, &sets)
+                .unwrap_err()
+                .contains("policy_id")
+        );
+
+        let skipped = vec![good[0].clone(), good[2].clone()];
+        assert!(verify("#chan", &skipped, &sets).is_err());
+
+        let mut relinked = good.clone();
+        relinked[2].previous_policy_hash = good[0].policy_id.clone();
+        relinked[2].policy_id = Some(policy_hash(&relinked[2]).unwrap());
+        assert!(
+            verify("#chan", &relinked, &sets)
+                .unwrap_err()
+                .contains("follow")
+        );
+    }
+
+    #[test]
+    fn versions_need_the_signatures_of_the_set_in_force_before_them() {
+        let good = chain(&["a", "b"]);
+        let mut unsigned = good.clone();
+        unsigned[1].signatures.clear();
+        assert!(
+            verify("#chan", &unsigned, &sets)
+                .unwrap_err()
+                .contains("0 of 1")
+        );
+
+        // Nobody else can write a version, nor re-hash one with another
+        // founder.
+        let one = authority(&[key(1)], 1);
+        let stranger = next_under(&good[..1], "b", &one, &key(9));
+        assert!(verify("#chan", &[good[0].clone(), stranger], &sets).is_err());
+        let mut usurped = good.clone();
+        usurped[1].founder_did = Some("did:plc:usurper".into());
+        usurped[1].policy_id = Some(policy_hash(&usurped[1]).unwrap());
+        assert!(
+            verify("#chan", &usurped, &sets)
+                .unwrap_err()
+                .contains("signatures")
+        );
+
+        // Handing over to another authority takes the old one's
+        // signature; after that the new one signs.
+        let nine = authority(&[key(9)], 1);
+        let seized = next_under(&good[..1], "b", &nine, &key(9));
+        assert!(verify("#chan", &[good[0].clone(), seized], &sets).is_err());
+        let mut handed = vec![good[0].clone(), next_under(&good[..1], "b", &nine, &key(1))];
+        handed.push(next_under(&handed, "c", &nine, &key(9)));
+        assert_eq!(verify("#chan", &handed, &sets), Ok(()));
+
+        // A threshold of two takes two distinct signers.
+        let pair = authority(&[key(1), key(2)], 2);
+        let mut two = vec![good[0].clone(), next_under(&good[..1], "b", &pair, &key(1))];
+        let mut third = next_under(&two, "c", &pair, &key(1));
+        third.signatures.push(third.signatures[0].clone());
+        let short = [two.clone(), vec![third.clone()]].concat();
+        assert!(
+            verify("#chan", &short, &sets)
+                .unwrap_err()
+                .contains("1 of 2")
+        );
+        let mut cosigned = third.clone();
+        sign(&mut cosigned, &did(&key(2)), &key(2));
+        third.signatures.extend(cosigned.signatures);
+        two.push(third);
+        assert_eq!(verify("#chan", &two, &sets), Ok(()));
+    }
+
+    #[test]
+    fn extensions_fast_forward_and_prefixes_are_kept() {
+        let short = chain(&["a", "b"]);
+        let long = fork(&short, &["c"]);
+        assert_eq!(
+            arbitrate("#chan", &short, &long, &sets),
+            Verdict::FastForward
+        );
+        assert_eq!(arbitrate("#chan", &[], &long, &sets), Verdict::FastForward);
+        assert_eq!(arbitrate("#chan", &long, &short, &sets), Verdict::Keep);
+        assert_eq!(arbitrate("#chan", &long, &long, &sets), Verdict::Keep);
+    }
+
+    #[test]
+    fn forks_go_to_the_longer_chain_then_the_lower_head() {
+        let base = chain(&["a"]);
+        let ours = fork(&base, &["ours"]);
+        let longer = fork(&base, &["theirs", "more"]);
+        assert_eq!(
+            arbitrate("#chan", &ours, &longer, &sets),
+            Verdict::Supersede
+        );
+        assert_eq!(arbitrate("#chan", &longer, &ours, &sets), Verdict::Keep);
+
+        let theirs = fork(&base, &["theirs"]);
+        let verdicts = (
+            arbitrate("#chan", &ours, &theirs, &sets),
+            arbitrate("#chan", &theirs, &ours, &sets),
+        );
+        // Exactly one side gives way, and it's the one with the higher head.
+        let ours_lower = ours[1].policy_id < theirs[1].policy_id;
+        let expected = if ours_lower {
+            (Verdict::Keep, Verdict::Supersede)
+        } else {
+            (Verdict::Supersede, Verdict::Keep)
+        };
+        assert_eq!(verdicts, expected);
+
+        // Unrelated chains (both created during a partition) fork at the root.
+        let unrelated = chain(&["x", "y", "z"]);
+        assert_eq!(
+            arbitrate("#chan", &ours, &unrelated, &sets),
+            Verdict::Supersede
+        );
+        assert_eq!(arbitrate("#chan", &unrelated, &ours, &sets), Verdict::Keep);
+
+        let mut broken = longer.clone();
+        broken.remove(1);
+        assert!(matches!(
+            arbitrate("#chan", &ours, &broken, &sets),
+            Verdict::Invalid(_)
+        ));
+    }
+
+    #[test]
+    fn adopted_chains_replace_the_stored_one() {
+        let store = crate::policy::store::PolicyStore::open(":memory:").unwrap();
+        let base = chain(&["a"]);
+        for doc in fork(&base, &["ours"]) {
+            store.store_policy(doc).unwrap();
+        }
+        let theirs = fork(&base, &["theirs", "more"]);
+        store.replace_policy_chain("#chan", &theirs).unwrap();
+        assert_eq!(store.get_policy_chain("#Chan").unwrap(), theirs);
+        assert_eq!(
+            store
+                .get_current_policy("#Chan")
+                .unwrap()
+                .unwrap()
+                .policy_id,
+            theirs[2].policy_id
+        );
+    }
+}
@@ -4,11 +4,13 @@
 //! This is the "authority server" logic that runs inside freeq-server.
 
 use super::canonical;
-use super::eval::{self, Credential, EvalResult, UserEvidence};
+use super::chain;
+use super::eval::{self, Credential, EvalResult, TraceNode, UserEvidence};
 use super::store::{PolicyError, PolicyStore};
 use super::types::*;
 use chrono::Utc;
-use std::collections::HashSet;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashSet};
 
 /// The policy engine — evaluates requirements and issues attestations.
 pub struct PolicyEngine {
@@ -17,6 +19,9 @@ pub struct PolicyEngine {
     authority_did: String,
     /// HMAC signing key for attestations (32 bytes, generated at startup).
     signing_key: [u8; 32],
+    /// Signs the policy versions this server issues, as the signer its
+    /// authority sets list.
+    policy_key: ed25519_dalek::SigningKey,
 }
 
 /// Result of a join attempt.
@@ -38,14 +43,49 @@ pub enum JoinResult {
     Failed(String),
 }
 
+/// How a join by `subject_did` would go under a channel's current policy,
+/// and why. Produced by [`PolicyEngine::dry_run`].
+#[derive(Debug, Clone, Serialize)]
+pub struct PolicyTrace {
+    pub channel_id: String,
+    pub subject_did: String,
+    pub policy_id: String,
+    pub policy_version: i64,
+    /// Role of an unexpired attestation for this policy. A real join
+    /// reuses it without evaluating anything.
+    pub attested_role: Option<String>,
+    /// The subject's stored credentials, as consulted by the evaluation.
+    pub credentials: Vec<ConsultedCredential>,
+    /// The join requirements. ACCEPT counts as satisfied, as it is once
+    /// the subject runs `POLICY ACCEPT`.
+    pub requirements: TraceNode,
+    /// Each role's requirements, in the order roles are tried.
+    pub roles: Vec<RoleTrace>,
+    /// Whether the join would be confirmed.
+    pub would_join: bool,
+    /// The role it would be granted.
+    pub role: Option<String>,
+    /// Why it would be refused.
+    pub refusal: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConsultedCredential {
+    pub credential_type: String,
+    pub issuer: String,
+    pub issued_at: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RoleTrace {
+    pub role: String,
+    pub trace: TraceNode,
+}
+
 impl PolicyEngine {
     pub fn new(store: PolicyStore, authority_did: String) -> Self {
         let signing_key: [u8; 32] = rand::random();
-        PolicyEngine {
-            store,
-            authority_did,
-            signing_key,
-        }
+        Self::with_key(store, authority_did, signing_key)
     }
 
     /// Create with a specific signing key (for testing/persistence).
@@ -54,9 +94,18 @@ impl PolicyEngine {
             store,
             authority_did,
             signing_key,
+            policy_key: ed25519_dalek::SigningKey::generate(&mut rand::rngs::OsRng),
         }
     }
 
+    /// Sign policies with `key` instead of a key of the engine's own. A
+    /// server passes its persistent key, so versions it issues after a
+    /// restart still verify against the authority sets it published.
+    pub fn with_policy_key(mut self, key: ed25519_dalek::SigningKey) -> Self {
+        self.policy_key = key;
+        self
+    }
+
     /// Access the underlying store.
     pub fn store(&self) -> &PolicyStore {
         &self.store
@@ -64,11 +113,12 @@ impl PolicyEngine {
 
     // ─── Channel Setup ───────────────────────────────────────────────────
 
-    /// Create an initial policy and authority set for a channel.
-    /// Returns (policy, authority_set).
+    /// Create an initial policy and authority set for a channel, recording
+    /// `founder_did` in it. Returns (policy, authority_set).
     pub fn create_channel_policy(
         &self,
         channel_id: &str,
+        founder_did: Option<&str>,
         requirements: Requirement,
         role_requirements: std::collections::BTreeMap<String, Requirement>,
     ) -> Result<(PolicyDocument, AuthoritySet), PolicyError> {
@@ -85,7 +135,7 @@ impl PolicyEngine {
             channel_id: channel_id.to_string(),
             signers: vec![AuthoritySigner {
                 did: self.authority_did.clone(),
-                public_key: format!("hmac-sha256:{}", canonical::sha256_hex(&self.signing_key)),
+                public_key: chain::public_key(&self.policy_key.verifying_key()),
                 label: Some("Primary authority".into()),
                 endpoint: None,
             }],
@@ -118,12 +168,22 @@ impl PolicyEngine {
             credential_endpoints: std::collections::BTreeMap::new(),
             agent_budget: None,
             agent_budgets: std::collections::BTreeMap::new(),
+            history_visibility: None,
+            founder_did: founder_did.map(str::to_string),
+            signatures: vec![],
         };
-        let policy = self.store.store_policy(policy)?;
+        let policy = self.issue(policy)?;
 
         Ok((policy, auth_set))
     }
 
+    /// Sign `policy` as this server's authority and store it.
+    fn issue(&self, mut policy: PolicyDocument) -> Result<PolicyDocument, PolicyError> {
+        policy.policy_id = Some(chain::policy_hash(&policy).map_err(PolicyError::Serialization)?);
+        chain::sign(&mut policy, &self.authority_did, &self.policy_key);
+        self.store.store_policy(policy)
+    }
+
     /// Update a channel's policy (creates a new version, chained to previous).
     pub fn update_channel_policy(
         &self,
@@ -156,8 +216,11 @@ impl PolicyEngine {
             credential_endpoints: current.credential_endpoints.clone(),
             agent_budget: current.agent_budget.clone(),
             agent_budgets: current.agent_budgets.clone(),
+            history_visibility: current.history_visibility,
+            founder_did: current.founder_did.clone(),
+            signatures: vec![],
         };
-        self.store.store_policy(policy)
+        self.issue(policy)
     }
 
     /// Update a channel's policy with explicit credential endpoints.
@@ -195,8 +258,33 @@ impl PolicyEngine {
             credential_endpoints,
             agent_budget: current.agent_budget.clone(),
             agent_budgets: current.agent_budgets.clone(),
+            history_visibility: current.history_visibility,
+            founder_did: current.founder_did.clone(),
+            signatures: vec![],
         };
-        self.store.store_policy(policy)
+        self.issue(policy)
+    }
+
+    /// Record who may read the channel's history as a new policy version.
+    pub fn set_history_visibility(
+        &self,
+        channel_id: &str,
+        visibility: HistoryVisibility,
+    ) -> Result<PolicyDocument, PolicyError> {
+        let current = self
+            .store
+            .get_current_policy(channel_id)?
+            .ok_or_else(|| PolicyError::Validation("No existing policy to update".into()))?;
+
+        let policy = PolicyDocument {
+            policy_id: None,
+            version: current.version + 1,
+            effective_at: Utc::now().to_rfc3339(),
+            previous_policy_hash: current.policy_id.clone(),
+            history_visibility: Some(visibility),
+            ..current
+        };
+        self.issue(policy)
     }
 
     // ─── Join Flow ───────────────────────────────────────────────────────
@@ -217,35 +305,48 @@ impl PolicyEngine {
             Some(p) => p,
             None => return Ok(JoinResult::NoPolicy),
         };
+        self.join(channel_id, subject_did, &policy, evidence, evidence)
+    }
+
+    /// Join through an invite that waives some requirements.
+    ///
+    /// `waived` names PRESENT credential types and PROVE proof types the
+    /// invite vouches for (see [`waivable`]). They count toward the join
+    /// requirements only, never toward a role. Redeeming an invite accepts
+    /// the channel's rules, as `POLICY ACCEPT` does.
+    pub fn join_with_invite(
+        &self,
+        channel_id: &str,
+        subject_did: &str,
+        waived: &[String],
+    ) -> Result<JoinResult, PolicyError> {
+        let policy = match self.store.get_current_policy(channel_id)? {
+            Some(p) => p,
+            None => return Ok(JoinResult::NoPolicy),
+        };
+        let role_evidence = self.build_evidence(subject_did, accept_hashes(&policy))?;
+        let mut evidence = role_evidence.clone();
+        waive(&policy.requirements, waived, &mut evidence);
+        self.join(channel_id, subject_did, &policy, &evidence, &role_evidence)
+    }
 
+    fn join(
+        &self,
+        channel_id: &str,
+        subject_did: &str,
+        policy: &PolicyDocument,
+        evidence: &UserEvidence,
+        role_evidence: &UserEvidence,
+    ) -> Result<JoinResult, PolicyError> {
         let policy_id = policy.policy_id.clone().unwrap_or_default();
 
-        // Check if user already has a valid attestation
-        if let Some(existing) = self.store.get_attestation(channel_id, subject_did)? {
-            // Check if attestation is for current policy
-            if existing.policy_id == policy_id {
-                // Check expiry for continuous validity
-                if let Some(ref expires_at) = existing.expires_at {
-                    if let Ok(exp) = chrono::DateTime::parse_from_rfc3339(expires_at)
-                        && exp > Utc::now()
-                    {
-                        let jid = existing.join_id.clone().unwrap_or_default();
-                        return Ok(JoinResult::Confirmed {
-                            attestation: existing,
-                            join_id: jid,
-                        });
-                    }
-                    // Expired — fall through to re-evaluate
-                } else {
-                    // No expiry (join_time model) — still valid
-                    let jid = existing.join_id.clone().unwrap_or_default();
-                    return Ok(JoinResult::Confirmed {
-                        attestation: existing,
-                        join_id: jid,
-                    });
-                }
-            }
-            // Policy changed — need to re-evaluate
+        // Reuse a still-valid attestation for this policy
+        if let Some(existing) = self.reusable_attestation(channel_id, subject_did, &policy_id)? {
+            let jid = existing.join_id.clone().unwrap_or_default();
+            return Ok(JoinResult::Confirmed {
+                attestation: existing,
+                join_id: jid,
+            });
         }
 
         // Evaluate requirements
@@ -273,7 +374,7 @@ impl PolicyEngine {
                 self.store.store_join_receipt(&receipt)?;
 
                 // Determine role
-                let role = self.evaluate_role(subject_did, &policy, evidence);
+                let role = self.evaluate_role(subject_did, policy, role_evidence);
 
                 // Issue attestation
                 let attestation = self.issue_attestation(
@@ -300,6 +401,103 @@ impl PolicyEngine {
         }
     }
 
+    /// The subject's attestation, if it's for `policy_id` and hasn't
+    /// expired (continuous validity). Otherwise the policy changed or the
+    /// attestation lapsed, and a join has to re-evaluate.
+    fn reusable_attestation(
+        &self,
+        channel_id: &str,
+        subject_did: &str,
+        policy_id: &str,
+    ) -> Result<Option<MembershipAttestation>, PolicyError> {
+        let Some(existing) = self.store.get_attestation(channel_id, subject_did)? else {
+            return Ok(None);
+        };
+        if existing.policy_id != policy_id {
+            return Ok(None);
+        }
+        let unexpired = match existing.expires_at.as_deref() {
+            Some(expires_at) => {
+                chrono::DateTime::parse_from_rfc3339(expires_at).is_ok_and(|exp| exp > Utc::now())
+            }
+            // No expiry (join_time model) — still valid
+            None => true,
+        };
+        Ok(unexpired.then_some(existing))
+    }
+
+    /// Evaluate `subject_did` against a channel's current policy the way
+    /// [`process_join`](Self::process_join) would, using their stored
+    /// credentials, but change nothing: no receipt, no attestation. For
+    /// channel admins testing a policy. `None` if the channel has no
+    /// policy.
+    pub fn dry_run(
+        &self,
+        channel_id: &str,
+        subject_did: &str,
+    ) -> Result<Option<PolicyTrace>, PolicyError> {
+        let Some(policy) = self.store.get_current_policy(channel_id)? else {
+            return Ok(None);
+        };
+        let policy_id = policy.policy_id.clone().unwrap_or_default();
+        let attested_role = self
+            .reusable_attestation(channel_id, subject_did, &policy_id)?
+            .map(|a| a.role);
+
+        let stored = self.store.get_credentials(subject_did)?;
+        let evidence = UserEvidence {
+            accepted_hashes: accept_hashes(&policy),
+            credentials: stored
+                .iter()
+                .map(|c| Credential {
+                    credential_type: c.credential_type.clone(),
+                    issuer: c.issuer.clone(),
+                })
+                .collect(),
+            proofs: HashSet::new(),
+        };
+        let credentials = stored
+            .into_iter()
+            .map(|c| ConsultedCredential {
+                credential_type: c.credential_type,
+                issuer: c.issuer,
+                issued_at: c.issued_at,
+            })
+            .collect();
+
+        let requirements = eval::trace(&policy.requirements, &evidence);
+        let roles: Vec<RoleTrace> = policy
+            .role_requirements
+            .iter()
+            .rev()
+            .map(|(role, req)| RoleTrace {
+                role: role.clone(),
+                trace: eval::trace(req, &evidence),
+            })
+            .collect();
+        // The verdict comes from the real evaluator, not the trace.
+        let (would_join, refusal) = match eval::evaluate(&policy.requirements, &evidence) {
+            EvalResult::Satisfied => (true, None),
+            EvalResult::Failed(reason) => (false, Some(reason)),
+            EvalResult::Error(err) => (false, Some(format!("Evaluation error: {err}"))),
+        };
+        let role = would_join.then(|| self.evaluate_role(subject_did, &policy, &evidence));
+
+        Ok(Some(PolicyTrace {
+            channel_id: channel_id.to_string(),
+            subject_did: subject_did.to_string(),
+            policy_id,
+            policy_version: policy.version,
+            attested_role,
+            credentials,
+            requirements,
+            roles,
+            would_join,
+            role,
+            refusal,
+        }))
+    }
+
     /// Evaluate which role a user qualifies for.
     fn evaluate_role(
         &self,
@@ -460,6 +658,73 @@ impl PolicyEngine {
 
 // ─── Helpers ─────────────────────────────────────────────────────────────────
 
+/// Every ACCEPT hash in a policy's join and role requirements.
+fn accept_hashes(policy: &PolicyDocument) -> HashSet<String> {
+    fn collect(req: &Requirement, out: &mut HashSet<String>) {
+        match req {
+            Requirement::Accept { hash } => {
+                out.insert(hash.clone());
+            }
+            Requirement::All { requirements } | Requirement::Any { requirements } => {
+                requirements.iter().for_each(|r| collect(r, out));
+            }
+            Requirement::Not { requirement } => collect(requirement, out),
+            Requirement::Present { .. } | Requirement::Prove { .. } => {}
+        }
+    }
+    let mut hashes = HashSet::new();
+    collect(&policy.requirements, &mut hashes);
+    for req in policy.role_requirements.values() {
+        collect(req, &mut hashes);
+    }
+    hashes
+}
+
+/// The PRESENT credential types and PROVE proof types in a requirement
+/// tree that an invite may waive. Anything under a NOT is left out:
+/// waiving it could only make the join harder.
+pub fn waivable(req: &Requirement) -> BTreeSet<String> {
+    fn collect(req: &Requirement, out: &mut BTreeSet<String>) {
+        match req {
+            Requirement::Present {
+                credential_type, ..
+            } => {
+                out.insert(credential_type.clone());
+            }
+            Requirement::Prove { proof_type } => {
+                out.insert(proof_type.clone());
+            }
+            Requirement::All { requirements } | Requirement::Any { requirements } => {
+                requirements.iter().for_each(|r| collect(r, out));
+            }
+            Requirement::Accept { .. } | Requirement::Not { .. } => {}
+        }
+    }
+    let mut names = BTreeSet::new();
+    collect(req, &mut names);
+    names
+}
+
+/// Add evidence satisfying every [`waivable`] requirement named in `waived`.
+fn waive(req: &Requirement, waived: &[String], evidence: &mut UserEvidence) {
+    match req {
+        Requirement::Present {
+            credential_type,
+            issuer,
+        } if waived.contains(credential_type) => evidence.credentials.push(Credential {
+            credential_type: credential_type.clone(),
+            issuer: issuer.clone().unwrap_or_default(),
+        }),
+        Requirement::Prove { proof_type } if waived.contains(proof_type) => {
+            evidence.proofs.insert(proof_type.clone());
+        }
+        Requirement::All { requirements } | Requirement::Any { requirements } => {
+            requirements.iter().for_each(|r| waive(r, waived, evidence));
+        }
+        _ => {}
+    }
+}
+
 fn generate_join_id() -> String {
     let bytes: [u8; 16] = rand::random();
     hex::encode(bytes)
@@ -493,6 +758,7 @@ mod tests {
         let (policy, _auth) = engine
             .create_channel_policy(
                 "#test",
+                None,
                 Requirement::Accept {
                     hash: rules_hash.clone(),
                 },
@@ -567,6 +833,7 @@ mod tests {
         engine
             .create_channel_policy(
                 "#project",
+                None,
                 Requirement::Accept {
                     hash: rules_hash.clone(),
                 },
@@ -614,6 +881,7 @@ mod tests {
         let (p1, _) = engine
             .create_channel_policy(
                 "#versioned",
+                None,
                 Requirement::Accept {
                     hash: hash1.clone(),
                 },
@@ -650,6 +918,7 @@ mod tests {
         engine
             .create_channel_policy(
                 "#idem",
+                None,
                 Requirement::Accept { hash: hash.clone() },
                 std::collections::BTreeMap::new(),
             )
@@ -692,6 +961,7 @@ mod tests {
         engine
             .create_channel_policy(
                 "#logged",
+                None,
                 Requirement::Accept { hash: hash.clone() },
                 std::collections::BTreeMap::new(),
             )
@@ -724,6 +994,7 @@ mod tests {
         engine
             .create_channel_policy(
                 "#signed",
+                None,
                 Requirement::Accept { hash: hash.clone() },
                 std::collections::BTreeMap::new(),
             )
@@ -761,6 +1032,7 @@ mod tests {
         engine
             .create_channel_policy(
                 "#removable",
+                None,
                 Requirement::Accept { hash: hash.clone() },
                 std::collections::BTreeMap::new(),
             )
@@ -790,6 +1062,7 @@ mod tests {
         engine
             .create_channel_policy(
                 "#roles",
+                None,
                 Requirement::Accept { hash: hash.clone() },
                 role_reqs,
             )
@@ -814,6 +1087,47 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_history_visibility_is_a_new_version() {
+        let engine = test_engine();
+        let hash = canonical::sha256_hex(b"rules");
+        let v1 = engine
+            .create_channel_policy(
+                "#hist",
+                None,
+                Requirement::Accept { hash },
+                Default::default(),
+            )
+            .unwrap()
+            .0;
+        assert_eq!(v1.history_visibility, None);
+
+        let v2 = engine
+            .set_history_visibility("#hist", HistoryVisibility::MembersSinceJoin)
+            .unwrap();
+        assert_eq!(v2.version, 2);
+        assert_eq!(v2.previous_policy_hash, v1.policy_id);
+        assert_eq!(v2.requirements, v1.requirements);
+        assert_eq!(
+            v2.history_visibility,
+            Some(HistoryVisibility::MembersSinceJoin)
+        );
+
+        // Later edits keep the setting.
+        let v3 = engine
+            .update_channel_policy("#hist", v2.requirements.clone(), Default::default())
+            .unwrap();
+        assert_eq!(
+            v3.history_visibility,
+            Some(HistoryVisibility::MembersSinceJoin)
+        );
+        assert!(
+            engine
+                .set_history_visibility("#none", HistoryVisibility::Public)
+                .is_err()
+        );
+    }
+
     #[test]
     fn test_continuous_validity_expiry() {
         let engine = test_engine();
@@ -849,6 +1163,9 @@ mod tests {
             credential_endpoints: std::collections::BTreeMap::new(),
             agent_budget: None,
             agent_budgets: std::collections::BTreeMap::new(),
+            history_visibility: None,
+            founder_did: None,
+            signatures: vec![],
         };
         engine.store.store_policy(policy).unwrap();
 
@@ -922,6 +1239,7 @@ mod tests {
         engine
             .create_channel_policy(
                 "#project",
+                None,
                 Requirement::Accept {
                     hash: rules_hash.clone(),
                 },
@@ -963,4 +1281,132 @@ mod tests {
             other => panic!("Expected Confirmed with member, got {:?}", other),
         }
     }
+
+    #[test]
+    fn test_dry_run_traces_without_side_effects() {
+        let engine = test_engine();
+        let rules_hash = canonical::sha256_hex(b"rules");
+        let mut role_reqs = std::collections::BTreeMap::new();
+        role_reqs.insert(
+            "op".to_string(),
+            Requirement::Present {
+                credential_type: "github_membership".into(),
+                issuer: Some("github".into()),
+            },
+        );
+        let join_req = Requirement::All {
+            requirements: vec![
+                Requirement::Accept {
+                    hash: rules_hash.clone(),
+                },
+                Requirement::Present {
+                    credential_type: "email".into(),
+                    issuer: None,
+                },
+            ],
+        };
+        engine
+            .create_channel_policy("#dry", None, join_req, role_reqs)
+            .unwrap();
+        assert!(engine.dry_run("#open", "did:plc:x").unwrap().is_none());
+
+        let t = engine.dry_run("#dry", "did:plc:dev").unwrap().unwrap();
+        assert!(!t.would_join);
+        assert_eq!(t.role, None);
+        assert_eq!(t.refusal.as_deref(), Some("Missing credential: email"));
+        assert!(t.requirements.children[0].satisfied, "ACCEPT assumed");
+        assert!(!t.requirements.children[1].satisfied);
+        assert!(t.credentials.is_empty());
+
+        let metadata = serde_json::json!({});
+        engine
+            .store_credential("did:plc:dev", "email", "mailer", &metadata)
+            .unwrap();
+        engine
+            .store_credential("did:plc:dev", "github_membership", "github", &metadata)
+            .unwrap();
+        let t = engine.dry_run("#dry", "did:plc:dev").unwrap().unwrap();
+        assert!(t.would_join);
+        assert_eq!(t.role.as_deref(), Some("op"));
+        assert_eq!(t.roles.len(), 1);
+        assert!(t.roles[0].trace.satisfied);
+        assert_eq!(t.credentials.len(), 2);
+        assert_eq!(t.attested_role, None);
+
+        // Nothing was recorded.
+        assert!(
+            engine
+                .check_membership("#dry", "did:plc:dev")
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_invite_waives_join_requirements_but_not_roles() {
+        let engine = test_engine();
+        let mut role_reqs = std::collections::BTreeMap::new();
+        role_reqs.insert(
+            "op".to_string(),
+            Requirement::Prove {
+                proof_type: "kyc".into(),
+            },
+        );
+        let join_req = Requirement::All {
+            requirements: vec![
+                Requirement::Accept {
+                    hash: canonical::sha256_hex(b"rules"),
+                },
+                Requirement::Any {
+                    requirements: vec![
+                        Requirement::Present {
+                            credential_type: "email".into(),
+                            issuer: Some("did:web:mail.example".into()),
+                        },
+                        Requirement::Prove {
+                            proof_type: "kyc".into(),
+                        },
+                    ],
+                },
+                Requirement::Not {
+                    requirement: Box::new(Requirement::Present {
+                        credential_type: "banned".into(),
+                        issuer: None,
+                    }),
+                },
+            ],
+        };
+        assert_eq!(
+            waivable(&join_req).into_iter().collect::<Vec<_>>(),
+            ["email", "kyc"]
+        );
+        engine
+            .create_channel_policy("#inv", None, join_req, role_reqs)
+            .unwrap();
+
+        let result = engine
+            .join_with_invite("#inv", "did:plc:a", &["other".into()])
+            .unwrap();
+        assert!(matches!(result, JoinResult::Failed(_)));
+
+        // Waiving the proof lets them in, but doesn't make them an op.
+        let result = engine
+            .join_with_invite("#inv", "did:plc:a", &["kyc".into()])
+            .unwrap();
+        let JoinResult::Confirmed { attestation, .. } = result else {
+            panic!("expected a join, got {result:?}");
+        };
+        assert_eq!(attestation.role, "member");
+
+        let result = engine
+            .join_with_invite("#inv", "did:plc:b", &["email".into()])
+            .unwrap();
+        assert!(matches!(result, JoinResult::Confirmed { .. }));
+        assert!(
+            engine
+                .check_membership("#inv", "did:plc:b")
+                .unwrap()
+                .is_some()
+        );
+    }
 }
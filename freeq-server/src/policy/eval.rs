@@ -2,12 +2,15 @@
 //!
 //! Evaluates requirements against a user's provided evidence.
 //! Deterministic, side-effect free, fail-closed on unknown types.
+//! [`trace`] evaluates the same way but records every node's result, for
+//! dry runs.
 //!
 //! Constraints enforced:
 //! - Max depth: 8
 //! - Max nodes: 64
 
 use super::types::Requirement;
+use serde::Serialize;
 use std::collections::HashSet;
 
 /// Evidence provided by a user during a join or role request.
@@ -47,6 +50,117 @@ impl EvalResult {
 const MAX_DEPTH: u32 = 8;
 const MAX_NODES: u32 = 64;
 
+/// One node of an evaluation [`trace`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TraceNode {
+    /// The requirement, e.g. `PRESENT(github_membership, issuer=github)`
+    /// or `ALL`.
+    pub requirement: String,
+    pub satisfied: bool,
+    /// Why it wasn't satisfied, or the evaluation error.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<TraceNode>,
+    /// Hit an evaluation error (depth/node limit) here or below.
+    #[serde(skip)]
+    errored: bool,
+}
+
+impl TraceNode {
+    fn new(requirement: String, result: EvalResult, children: Vec<TraceNode>) -> Self {
+        let (satisfied, reason, errored) = match result {
+            EvalResult::Satisfied => (true, None, false),
+            EvalResult::Failed(reason) => (false, Some(reason), false),
+            EvalResult::Error(err) => (false, Some(format!("Evaluation error: {err}")), true),
+        };
+        TraceNode {
+            requirement,
+            satisfied,
+            reason,
+            children,
+            errored,
+        }
+    }
+}
+
+/// Evaluate like [`evaluate`], but without short-circuiting: every node is
+/// visited and its result recorded, so a policy author sees everything
+/// that failed rather than the first thing.
+pub fn trace(requirement: &Requirement, evidence: &UserEvidence) -> TraceNode {
+    let mut node_count = 0;
+    trace_inner(requirement, evidence, 0, &mut node_count)
+}
+
+fn trace_inner(
+    req: &Requirement,
+    evidence: &UserEvidence,
+    depth: u32,
+    node_count: &mut u32,
+) -> TraceNode {
+    let (label, subs): (&str, Vec<&Requirement>) = match req {
+        Requirement::All { requirements } => ("ALL", requirements.iter().collect()),
+        Requirement::Any { requirements } => ("ANY", requirements.iter().collect()),
+        Requirement::Not { requirement } => ("NOT", vec![requirement.as_ref()]),
+        leaf => {
+            let result = eval_inner(leaf, evidence, depth, node_count);
+            return TraceNode::new(describe(leaf), result, Vec::new());
+        }
+    };
+    let label = label.to_string();
+    if depth > MAX_DEPTH {
+        let err = EvalResult::Error(format!("Max depth {MAX_DEPTH} exceeded"));
+        return TraceNode::new(label, err, Vec::new());
+    }
+    *node_count += 1;
+    if *node_count > MAX_NODES {
+        let err = EvalResult::Error(format!("Max node count {MAX_NODES} exceeded"));
+        return TraceNode::new(label, err, Vec::new());
+    }
+
+    let children: Vec<TraceNode> = subs
+        .into_iter()
+        .map(|r| trace_inner(r, evidence, depth + 1, node_count))
+        .collect();
+    let result = if let Some(err) = children.iter().find(|c| c.errored) {
+        EvalResult::Error(err.reason.clone().unwrap_or_default())
+    } else {
+        match req {
+            Requirement::All { .. } if children.iter().all(|c| c.satisfied) => {
+                EvalResult::Satisfied
+            }
+            Requirement::All { .. } => EvalResult::Failed("A requirement failed".to_string()),
+            Requirement::Any { .. } if children.iter().any(|c| c.satisfied) => {
+                EvalResult::Satisfied
+            }
+            Requirement::Any { .. } => EvalResult::Failed("No alternative satisfied".to_string()),
+            _ if children[0].satisfied => {
+                EvalResult::Failed("NOT condition: inner requirement was satisfied".to_string())
+            }
+            _ => EvalResult::Satisfied,
+        }
+    };
+    TraceNode::new(label, result, children)
+}
+
+/// Short label for a leaf requirement.
+fn describe(req: &Requirement) -> String {
+    match req {
+        Requirement::Accept { hash } => format!("ACCEPT({}...)", &hash[..12.min(hash.len())]),
+        Requirement::Present {
+            credential_type,
+            issuer: Some(iss),
+        } => format!("PRESENT({credential_type}, issuer={iss})"),
+        Requirement::Present {
+            credential_type, ..
+        } => format!("PRESENT({credential_type})"),
+        Requirement::Prove { proof_type } => format!("PROVE({proof_type})"),
+        Requirement::All { .. } => "ALL".to_string(),
+        Requirement::Any { .. } => "ANY".to_string(),
+        Requirement::Not { .. } => "NOT".to_string(),
+    }
+}
+
 /// Evaluate a requirement tree against user evidence.
 pub fn evaluate(requirement: &Requirement, evidence: &UserEvidence) -> EvalResult {
     let mut node_count = 0;
@@ -370,6 +484,49 @@ mod tests {
         assert!(evaluate(&op_req, &committer).is_satisfied());
     }
 
+    #[test]
+    fn test_trace_visits_every_node() {
+        let req = Requirement::All {
+            requirements: vec![
+                Requirement::Prove {
+                    proof_type: "kyc".into(),
+                },
+                Requirement::Any {
+                    requirements: vec![
+                        Requirement::Accept { hash: "a".into() },
+                        Requirement::Present {
+                            credential_type: "email".into(),
+                            issuer: None,
+                        },
+                    ],
+                },
+                Requirement::Not {
+                    requirement: Box::new(Requirement::Accept {
+                        hash: "banned".into(),
+                    }),
+                },
+            ],
+        };
+        let mut ev = empty_evidence();
+        ev.accepted_hashes.insert("a".into());
+
+        let t = trace(&req, &ev);
+        assert_eq!(t.satisfied, evaluate(&req, &ev).is_satisfied());
+        assert!(!t.satisfied);
+        // Evaluation stops at the failed PROVE; the trace keeps going.
+        let [prove, any, not] = &t.children[..] else {
+            panic!("{t:?}");
+        };
+        assert_eq!(prove.requirement, "PROVE(kyc)");
+        assert_eq!(prove.reason.as_deref(), Some("Missing proof: kyc"));
+        assert!(any.satisfied);
+        assert!(any.children[0].satisfied && !any.children[1].satisfied);
+        assert!(not.satisfied);
+
+        ev.proofs.insert("kyc".into());
+        assert!(trace(&req, &ev).satisfied);
+    }
+
     #[test]
     fn test_validate_depth_limit() {
         // Build a deeply nested NOT chain
@@ -6,6 +6,7 @@
 //!
 //! - `types` — Core data structures (PolicyDocument, AuthoritySet, etc.)
 //! - `canonical` — JCS (RFC 8785) canonicalization and SHA-256 hashing
+//! - `chain` — Arbitrating forked policy chains between federated servers
 //! - `eval` — Requirement DSL evaluator
 //! - `store` — SQLite storage for all policy objects
 //! - `engine` — Join flow orchestration and attestation issuance
@@ -13,6 +14,7 @@
 
 pub mod api;
 pub mod canonical;
+pub mod chain;
 pub mod credentials;
 pub mod engine;
 pub mod eval;
@@ -23,6 +23,12 @@ impl PolicyStore {
         Ok(store)
     }
 
+    /// Hold the store's connection, for [`crate::backup`]; every other
+    /// store operation waits until the guard is dropped.
+    pub(crate) fn lock(&self) -> parking_lot::MutexGuard<'_, Connection> {
+        self.db.lock()
+    }
+
     fn migrate(&self) -> Result<(), rusqlite::Error> {
         let db = self.db.lock();
         db.execute_batch(
@@ -131,10 +137,9 @@ impl PolicyStore {
 
     /// Store a policy document. Computes policy_id from JCS hash.
     pub fn store_policy(&self, mut policy: PolicyDocument) -> Result<PolicyDocument, PolicyError> {
-        // Compute policy_id by hashing the document without the policy_id field
-        policy.policy_id = None;
-        let policy_id = canonical::hash_canonical(&policy)
-            .map_err(|e| PolicyError::Serialization(e.to_string()))?;
+        // Compute policy_id by hashing the document without the policy_id
+        // and signatures fields
+        let policy_id = super::chain::policy_hash(&policy).map_err(PolicyError::Serialization)?;
         policy.policy_id = Some(policy_id.clone());
 
         let json = serde_json::to_string(&policy)
@@ -231,6 +236,51 @@ impl PolicyStore {
         Ok(docs)
     }
 
+    /// Replace a channel's policy versions with `chain` (oldest first),
+    /// e.g. a peer's chain that won arbitration. Documents keep the
+    /// `policy_id` they carry, so check them with
+    /// [`super::chain::verify`] first.
+    pub fn replace_policy_chain(
+        &self,
+        channel_id: &str,
+        chain: &[PolicyDocument],
+    ) -> Result<(), PolicyError> {
+        let db_err = |e: rusqlite::Error| PolicyError::Database(e.to_string());
+        let db = self.db.lock();
+        let tx = db.unchecked_transaction().map_err(db_err)?;
+        let spellings: std::collections::BTreeSet<&str> = chain
+            .iter()
+            .map(|p| p.channel_id.as_str())
+            .chain([channel_id])
+            .collect();
+        for spelling in spellings {
+            tx.execute(
+                "DELETE FROM policies WHERE channel_id = ?1",
+                params![spelling],
+            )
+            .map_err(db_err)?;
+        }
+        for policy in chain {
+            let json = serde_json::to_string(policy)
+                .map_err(|e| PolicyError::Serialization(e.to_string()))?;
+            tx.execute(
+                "INSERT INTO policies (policy_id, channel_id, version, effective_at, previous_policy_hash, authority_set_hash, document_json)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    policy.policy_id,
+                    policy.channel_id,
+                    policy.version,
+                    policy.effective_at,
+                    policy.previous_policy_hash,
+                    policy.authority_set_hash,
+                    json,
+                ],
+            )
+            .map_err(db_err)?;
+        }
+        tx.commit().map_err(db_err)
+    }
+
     // ─── Authority Sets ──────────────────────────────────────────────────
 
     /// Store an authority set. Computes hash from JCS.
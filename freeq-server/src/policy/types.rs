@@ -75,6 +75,66 @@ pub struct PolicyDocument {
     /// Per-agent budget overrides (DID → budget).
     #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
     pub agent_budgets: BTreeMap<String, BudgetPolicy>,
+
+    /// Who may read the channel's stored history. `None` leaves the
+    /// channel's `+H` mode as it is.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history_visibility: Option<HistoryVisibility>,
+
+    /// The channel's founder when this version was issued. Federated
+    /// servers adopting a chain take the founder from here.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub founder_did: Option<String>,
+
+    /// Authority signatures over `policy_id` (not part of the hash).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub signatures: Vec<PolicySignature>,
+}
+
+/// An authority's Ed25519 signature over a policy's `policy_id`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PolicySignature {
+    /// DID of the signer, as listed in the authority set.
+    pub signer: String,
+    /// Base64url signature over the `policy_id` string.
+    pub signature: String,
+}
+
+/// Who may read a channel's stored history (CHATHISTORY, SEARCH and the
+/// web API). Set with channel mode `+H` or the policy document.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum HistoryVisibility {
+    /// Current members see everything, including what was said before
+    /// they joined. The historical behaviour.
+    #[default]
+    MembersFull,
+    /// Members see only what was said while they were in the channel:
+    /// this membership and, for DID accounts, earlier ones.
+    MembersSinceJoin,
+    /// Anyone not banned may read history without joining.
+    Public,
+}
+
+impl HistoryVisibility {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HistoryVisibility::MembersFull => "members-full",
+            HistoryVisibility::MembersSinceJoin => "members-since-join",
+            HistoryVisibility::Public => "public",
+        }
+    }
+
+    /// Parse a `+H` argument. Accepts the short forms `members` and
+    /// `since-join`.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "members-full" | "members" => Some(HistoryVisibility::MembersFull),
+            "members-since-join" | "since-join" => Some(HistoryVisibility::MembersSinceJoin),
+            "public" => Some(HistoryVisibility::Public),
+            _ => None,
+        }
+    }
 }
 
 /// Budget constraints for agent spending in a channel.
@@ -251,7 +311,8 @@ fn default_authority_ttl() -> i64 {
 pub struct AuthoritySigner {
     /// DID of the signing authority.
     pub did: String,
-    /// Public key (multibase or JWK).
+    /// Public key: `ed25519:` and the base64url key for the keys policy
+    /// signatures are checked against.
     pub public_key: String,
     /// Human-readable label.
     #[serde(skip_serializing_if = "Option::is_none")]
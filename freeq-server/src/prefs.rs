@@ -0,0 +1,384 @@
+//! Per-DID preferences that follow the user to every device.
+//!
+//! Settings like the ignore list, highlight keywords and muted channels
+//! are kept by the server under the user's DID, so a new device picks
+//! them up instead of starting over. They are read and written with
+//! `PREF GET/SET/LIST` (see `connection::pref_cmd`) or
+//! `GET /api/v1/me/prefs` and `PUT`/`DELETE /api/v1/me/prefs/{key}`.
+//! Every change is pushed to the DID's other connected sessions as
+//! `:server PREF <key> :<value>`.
+//!
+//! Keys are typed ([`KEYS`]): a value is checked and normalized before
+//! it's stored, and a key that was never set (or set back to its
+//! default) reads as its default and takes no space. A DID holds at most
+//! [`MAX_TOTAL_BYTES`] of values, a list at most [`MAX_LIST_ITEMS`]
+//! items. Guests have no DID and so no preferences. Preferences persist
+//! when the server has a database; they are not federated over S2S.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use crate::server::{SharedState, WireLine};
+
+/// Most bytes of values one DID may store.
+pub const MAX_TOTAL_BYTES: usize = 8192;
+
+/// Most items in a list preference.
+pub const MAX_LIST_ITEMS: usize = 100;
+
+/// Longest item in a list preference, in bytes.
+const MAX_ITEM_BYTES: usize = 100;
+
+/// What a preference holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// `on` or `off`.
+    Bool,
+    /// Space-separated nick masks (`nick!user@host` globs) or DIDs.
+    Masks,
+    /// Space-separated words, compared ignoring case.
+    Words,
+    /// Space-separated channel names.
+    Channels,
+}
+
+impl Kind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Kind::Bool => "bool",
+            Kind::Masks => "masks",
+            Kind::Words => "words",
+            Kind::Channels => "channels",
+        }
+    }
+}
+
+/// One known preference.
+#[derive(Debug)]
+pub struct PrefKey {
+    pub name: &'static str,
+    pub kind: Kind,
+    pub default: &'static str,
+    pub description: &'static str,
+}
+
+/// Every preference a user can set.
+pub const KEYS: &[PrefKey] = &[
+    PrefKey {
+        name: "ignore",
+        kind: Kind::Masks,
+        default: "",
+        description: "Nick masks and DIDs whose messages are hidden",
+    },
+    PrefKey {
+        name: "highlights",
+        kind: Kind::Words,
+        default: "",
+        description: "Words that highlight a message, besides your nick",
+    },
+    PrefKey {
+        name: "muted",
+        kind: Kind::Channels,
+        default: "",
+        description: "Channels that never notify",
+    },
+    PrefKey {
+        name: "show-joins",
+        kind: Kind::Bool,
+        default: "on",
+        description: "Show joins, parts and quits in channels",
+    },
+    PrefKey {
+        name: "dm-notify",
+        kind: Kind::Bool,
+        default: "on",
+        description: "Notify for direct messages",
+    },
+];
+
+/// The preference called `name` (case-insensitive).
+pub fn key(name: &str) -> Option<&'static PrefKey> {
+    KEYS.iter().find(|k| k.name.eq_ignore_ascii_case(name))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrefError {
+    /// No preference has this name.
+    UnknownKey(String),
+    /// The value doesn't fit the preference's type.
+    Invalid(String),
+    /// Storing it would take the DID over [`MAX_TOTAL_BYTES`].
+    Quota,
+}
+
+impl PrefError {
+    /// `FAIL PREF` code.
+    pub fn code(&self) -> &'static str {
+        match self {
+            PrefError::UnknownKey(_) => "UNKNOWN_KEY",
+            PrefError::Invalid(_) => "INVALID_VALUE",
+            PrefError::Quota => "QUOTA_EXCEEDED",
+        }
+    }
+}
+
+impl fmt::Display for PrefError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrefError::UnknownKey(name) => write!(f, "No such preference: {name}"),
+            PrefError::Invalid(why) => f.write_str(why),
+            PrefError::Quota => write!(
+                f,
+                "Preferences are limited to {MAX_TOTAL_BYTES} bytes per account"
+            ),
+        }
+    }
+}
+
+impl PrefKey {
+    /// `value` in canonical form: `on`/`off` for booleans, list items
+    /// deduplicated and joined by single spaces (commas are accepted as
+    /// separators too).
+    pub fn normalize(&self, value: &str) -> Result<String, PrefError> {
+        if self.kind == Kind::Bool {
+            return match value.trim().to_ascii_lowercase().as_str() {
+                "on" | "true" | "yes" | "1" => Ok("on".to_string()),
+                "off" | "false" | "no" | "0" => Ok("off".to_string()),
+                _ => Err(PrefError::Invalid(format!("{} is on or off", self.name))),
+            };
+        }
+        let mut items: Vec<String> = Vec::new();
+        for item in value.split(|c: char| c == ',' || c.is_whitespace()) {
+            if item.is_empty() {
+                continue;
+            }
+            if item.len() > MAX_ITEM_BYTES || item.chars().any(char::is_control) {
+                return Err(PrefError::Invalid(format!(
+                    "{} items are at most {MAX_ITEM_BYTES} bytes, without control characters",
+                    self.name
+                )));
+            }
+            let item = match self.kind {
+                Kind::Channels if !item.starts_with(['#', '&']) => {
+                    return Err(PrefError::Invalid(format!(
+                        "{} lists channels; {item} isn't one",
+                        self.name
+                    )));
+                }
+                Kind::Channels => crate::casemap::fold(item),
+                _ => item.to_string(),
+            };
+            let duplicate = items.iter().any(|seen| match self.kind {
+                Kind::Masks if item.starts_with("did:") => *seen == item,
+                _ => crate::casemap::eq(seen, &item),
+            });
+            if !duplicate {
+                items.push(item);
+            }
+        }
+        if items.len() > MAX_LIST_ITEMS {
+            return Err(PrefError::Invalid(format!(
+                "{} holds at most {MAX_LIST_ITEMS} items",
+                self.name
+            )));
+        }
+        Ok(items.join(" "))
+    }
+}
+
+/// Every DID's stored preferences.
+#[derive(Debug, Default)]
+pub struct Preferences {
+    by_did: HashMap<String, BTreeMap<String, String>>,
+}
+
+impl Preferences {
+    /// From persisted `(did, key, value)` rows. Rows for keys that no
+    /// longer exist are ignored.
+    pub fn from_rows(rows: Vec<(String, String, String)>) -> Self {
+        let mut prefs = Self::default();
+        for (did, name, value) in rows {
+            if key(&name).is_some() {
+                prefs.by_did.entry(did).or_default().insert(name, value);
+            }
+        }
+        prefs
+    }
+
+    /// `did`'s value for `key`, or its default.
+    pub fn get(&self, did: &str, key: &PrefKey) -> String {
+        self.by_did
+            .get(did)
+            .and_then(|values| values.get(key.name))
+            .map_or_else(|| key.default.to_string(), Clone::clone)
+    }
+
+    /// Every preference for `did`, defaults included, in [`KEYS`] order.
+    pub fn all(&self, did: &str) -> Vec<(&'static PrefKey, String)> {
+        KEYS.iter().map(|k| (k, self.get(did, k))).collect()
+    }
+
+    /// Just the values `did` has set, for data exports.
+    pub fn stored(&self, did: &str) -> BTreeMap<String, String> {
+        self.by_did.get(did).cloned().unwrap_or_default()
+    }
+
+    /// Bytes of values `did` stores, counted against [`MAX_TOTAL_BYTES`].
+    pub fn used_bytes(&self, did: &str) -> usize {
+        self.by_did
+            .get(did)
+            .map_or(0, |values| values.values().map(String::len).sum())
+    }
+
+    /// Set `name` for `did`, or reset it to its default when `value` is
+    /// `None`. Returns the preference and its new value, and whether it's
+    /// now stored (`false` when it's back to the default).
+    pub fn set(
+        &mut self,
+        did: &str,
+        name: &str,
+        value: Option<&str>,
+    ) -> Result<(&'static PrefKey, String, bool), PrefError> {
+        let key = key(name).ok_or_else(|| PrefError::UnknownKey(name.to_string()))?;
+        let value = match value {
+            Some(value) => key.normalize(value)?,
+            None => key.default.to_string(),
+        };
+        let stored = value != key.default;
+        if stored {
+            let current = self
+                .by_did
+                .get(did)
+                .and_then(|values| values.get(key.name))
+                .map_or(0, String::len);
+            if self.used_bytes(did) - current + value.len() > MAX_TOTAL_BYTES {
+                return Err(PrefError::Quota);
+            }
+            self.by_did
+                .entry(did.to_string())
+                .or_default()
+                .insert(key.name.to_string(), value.clone());
+        } else if let Some(values) = self.by_did.get_mut(did) {
+            values.remove(key.name);
+            if values.is_empty() {
+                self.by_did.remove(did);
+            }
+        }
+        Ok((key, value, stored))
+    }
+}
+
+/// `:server PREF <key> :<value>`.
+pub fn line(server_name: &str, key: &str, value: &str) -> String {
+    let reply = crate::irc::Message::from_server(server_name, "PREF", vec![key, value]);
+    format!("{reply}\r\n")
+}
+
+/// Set (or with `None`, reset) `name` for `did`, persist it and push the
+/// new value to `did`'s sessions other than `origin_session`. Returns the
+/// preference and its new value.
+pub fn update(
+    state: &SharedState,
+    did: &str,
+    name: &str,
+    value: Option<&str>,
+    origin_session: Option<&str>,
+) -> Result<(&'static PrefKey, String), PrefError> {
+    let (key, value, stored) = state.prefs.lock().set(did, name, value)?;
+    state.with_db(|db| db.set_pref(did, key.name, stored.then_some(value.as_str())));
+    tracing::info!(%did, key = key.name, "Preference updated");
+
+    let sessions = state
+        .did_sessions
+        .lock()
+        .get(did)
+        .cloned()
+        .unwrap_or_default();
+    let wire = WireLine::from(line(&state.server_name, key.name, &value));
+    for sid in sessions {
+        if Some(sid.as_str()) != origin_session
+            && let Some(tx) = state.connections.get(&sid)
+        {
+            let _ = tx.try_send(wire.clone());
+        }
+    }
+    Ok((key, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_are_checked_and_normalized_by_type() {
+        let bool_key = key("Show-Joins").unwrap();
+        assert_eq!(bool_key.normalize(" Yes ").unwrap(), "on");
+        assert_eq!(bool_key.normalize("0").unwrap(), "off");
+        assert!(bool_key.normalize("maybe").is_err());
+
+        let muted = key("muted").unwrap();
+        assert_eq!(
+            muted.normalize("#Rust, &local #rust").unwrap(),
+            "#rust &local"
+        );
+        assert!(muted.normalize("#ok notachannel").is_err());
+
+        let ignore = key("ignore").unwrap();
+        assert_eq!(
+            ignore
+                .normalize("spam*!*@* did:plc:Ab did:plc:ab SPAM*!*@*")
+                .unwrap(),
+            "spam*!*@* did:plc:Ab did:plc:ab"
+        );
+        let too_many = (0..=MAX_LIST_ITEMS)
+            .map(|i| format!("w{i} "))
+            .collect::<String>();
+        assert!(key("highlights").unwrap().normalize(&too_many).is_err());
+        assert!(key("nope").is_none());
+    }
+
+    #[test]
+    fn defaults_take_no_space_and_quota_is_enforced() {
+        let mut prefs = Preferences::default();
+        let did = "did:plc:alice";
+        assert_eq!(prefs.get(did, key("show-joins").unwrap()), "on");
+
+        let (_, value, stored) = prefs.set(did, "show-joins", Some("off")).unwrap();
+        assert_eq!((value.as_str(), stored), ("off", true));
+        let (_, _, stored) = prefs.set(did, "show-joins", Some("on")).unwrap();
+        assert!(!stored, "the default isn't stored");
+        assert_eq!(prefs.used_bytes(did), 0);
+
+        assert_eq!(
+            prefs.set(did, "colour", Some("red")).unwrap_err(),
+            PrefError::UnknownKey("colour".into())
+        );
+
+        // 90 items of 99 bytes is within the list limit but over quota.
+        let words: Vec<String> = (0..90).map(|i| format!("{i:0>99}")).collect();
+        assert_eq!(
+            prefs
+                .set(did, "highlights", Some(&words.join(" ")))
+                .unwrap_err(),
+            PrefError::Quota
+        );
+        prefs
+            .set(did, "highlights", Some(&words[..40].join(" ")))
+            .unwrap();
+        // Replacing a value only counts the difference.
+        prefs
+            .set(did, "highlights", Some(&words[..80].join(" ")))
+            .unwrap();
+        assert!(prefs.used_bytes(did) <= MAX_TOTAL_BYTES);
+
+        prefs.set(did, "highlights", None).unwrap();
+        assert!(prefs.stored(did).is_empty());
+
+        let loaded = Preferences::from_rows(vec![
+            (did.into(), "muted".into(), "#a".into()),
+            (did.into(), "retired".into(), "x".into()),
+        ]);
+        assert_eq!(loaded.stored(did).len(), 1);
+        assert_eq!(loaded.all(did).len(), KEYS.len());
+    }
+}
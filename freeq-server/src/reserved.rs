@@ -0,0 +1,73 @@
+//! Reserved nick and channel namespaces.
+//!
+//! `--reserved-nicks` and `--reserved-channels` list names (exact, or
+//! globs with `*` and `?`, compared case-insensitively) that only
+//! operators and `--reserved-exempt-dids` may use, so a guest can't turn
+//! up as `admin` or open `#freeq-staff`.
+//!
+//! A reserved nick is refused at NICK with 432. One stashed before SASL
+//! finishes is checked again when registration completes, and the
+//! connection renamed to a Guest nick if it still isn't allowed. A
+//! reserved channel can't be created by JOIN (479); once someone allowed
+//! has created it, its own modes decide who may join.
+
+use crate::config::ServerConfig;
+use crate::server::wildcard_match;
+
+/// Whether `pattern` names `name`: an exact match or a glob.
+fn matches(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.trim();
+    !pattern.is_empty() && wildcard_match(pattern, name)
+}
+
+/// Whether `nick` is in the reserved nick namespace.
+pub fn nick_reserved(config: &ServerConfig, nick: &str) -> bool {
+    config.reserved_nicks.iter().any(|p| matches(p, nick))
+}
+
+/// Whether `channel` is in the reserved channel namespace.
+pub fn channel_reserved(config: &ServerConfig, channel: &str) -> bool {
+    config.reserved_channels.iter().any(|p| matches(p, channel))
+}
+
+/// Whether a connection may use reserved names: operators, and DIDs in
+/// `reserved_exempt_dids` or `oper_dids` (which may not have been granted
+/// operator status yet mid-registration).
+pub fn exempt(config: &ServerConfig, did: Option<&str>, is_oper: bool) -> bool {
+    is_oper
+        || did.is_some_and(|did| {
+            config
+                .reserved_exempt_dids
+                .iter()
+                .chain(&config.oper_dids)
+                .any(|d| d == did)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_names_and_globs_are_reserved_ignoring_case() {
+        let config = ServerConfig {
+            reserved_nicks: vec!["admin".into(), " *serv".into(), "".into()],
+            reserved_channels: vec!["#freeq-*".into()],
+            reserved_exempt_dids: vec!["did:plc:staff".into()],
+            oper_dids: vec!["did:plc:oper".into()],
+            ..Default::default()
+        };
+        assert!(nick_reserved(&config, "Admin"));
+        assert!(nick_reserved(&config, "NickServ"));
+        assert!(!nick_reserved(&config, "admins"));
+        assert!(!nick_reserved(&config, "alice"));
+        assert!(channel_reserved(&config, "#FreeQ-Staff"));
+        assert!(!channel_reserved(&config, "#freeq"));
+
+        assert!(exempt(&config, None, true));
+        assert!(exempt(&config, Some("did:plc:staff"), false));
+        assert!(exempt(&config, Some("did:plc:oper"), false));
+        assert!(!exempt(&config, Some("did:plc:guest"), false));
+        assert!(!exempt(&config, None, false));
+    }
+}
@@ -36,14 +36,17 @@ use std::sync::atomic::{AtomicU64, Ordering};
 
 use anyhow::Result;
 use base64::Engine;
+use freeq_proto::tags;
 use serde::{Deserialize, Serialize};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::sync::mpsc;
 
 use crate::server::SharedState;
 
-/// ALPN for server-to-server links.
-pub const S2S_ALPN: &[u8] = b"freeq/s2s/1";
+pub use freeq_proto::s2s::{
+    ChannelInfo, DirectoryEntry, MultilineLine, PolicyHead, S2S_ALPN, S2sMessage, SyncNick,
+    SyncTopic,
+};
 
 /// Maximum number of event IDs to remember per peer for dedup.
 const DEDUP_CAPACITY: usize = 10_000;
@@ -119,7 +122,7 @@ pub fn parse_trust_config(entries: &[String]) -> HashMap<String, TrustLevel> {
 /// locally; not `+freeq.at/`-prefixed so excluded anyway).
 pub fn relay_coordination_tags(full: &HashMap<String, String>) -> HashMap<String, String> {
     full.iter()
-        .filter(|(k, _)| k.starts_with("+freeq.at/") && k.as_str() != "+freeq.at/sig")
+        .filter(|(k, _)| k.starts_with(tags::FREEQ_PREFIX) && k.as_str() != tags::SIG)
         .map(|(k, v)| (k.clone(), v.clone()))
         .collect()
 }
@@ -150,481 +153,48 @@ pub fn encode_privmsg_text_for_s2s(
     mut tags: HashMap<String, String>,
 ) -> (String, HashMap<String, String>) {
     if text.contains('\n') {
-        tags.insert("+freeq.at/multiline".to_string(), String::new());
+        tags.insert(freeq_proto::tags::MULTILINE.to_string(), String::new());
         (text.replace('\n', "\\n"), tags)
     } else {
         (text.to_string(), tags)
     }
 }
 
-/// One line of a draft/multiline batch, serialized for S2S relay.
-/// Kept minimal so the wire size doesn't balloon for typical agent
-/// turns: just the body and the concat flag.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct MultilineLine {
-    pub body: String,
-    /// True if this line carried `draft/multiline-concat` (join to
-    /// previous with no separator). Serialized only when non-default
-    /// to keep typical-case wire small.
-    #[serde(default, skip_serializing_if = "is_false")]
-    pub concat: bool,
-}
-
-fn is_false(b: &bool) -> bool {
-    !*b
-}
+/// Most entries kept in a relayed message's hop list; servers past this
+/// don't append.
+const MAX_HOPS: usize = 8;
 
-/// Messages exchanged between servers.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(tag = "type")]
-pub enum S2sMessage {
-    /// Identity handshake — sent immediately on link establishment.
-    /// Binds the transport identity (iroh endpoint ID) to the logical
-    /// server_name. The peer_id is verified against the QUIC connection's
-    /// remote_id() — spoofing is impossible.
-    #[serde(rename = "hello")]
-    Hello {
-        /// Iroh endpoint ID (must match connection's remote_id).
-        peer_id: String,
-        /// Human-readable server name (untrusted display metadata).
-        server_name: String,
-        /// Protocol version for capability negotiation.
-        #[serde(default)]
-        protocol_version: u32,
-        /// Trust level this server offers to the peer (informational).
-        #[serde(default)]
-        trust_level: Option<String>,
-    },
-
-    /// Phase 1: Mutual auth acknowledgment — sent after receiving Hello.
-    /// Confirms the peer is in our allowlist and we accept them.
-    #[serde(rename = "hello_ack")]
-    HelloAck {
-        /// Our endpoint ID (for verification).
-        peer_id: String,
-        /// Whether we accept this peer (in our allowlist).
-        accepted: bool,
-        /// Our trust level for this peer.
-        #[serde(default)]
-        trust_level: Option<String>,
-    },
-
-    /// Phase 2: Signed message envelope. All messages after Hello/HelloAck
-    /// are wrapped in this envelope for non-repudiation.
-    #[serde(rename = "signed")]
-    Signed {
-        /// Base64url-encoded serialized inner S2sMessage.
-        payload: String,
-        /// Base64url-encoded ed25519 signature over the payload bytes.
-        signature: String,
-        /// Signing server's endpoint ID (for key lookup).
-        signer: String,
-    },
-
-    /// Phase 4: Key rotation announcement. Signed by the OLD key to prove
-    /// continuity. Peers update their allowlists to accept the new ID.
-    #[serde(rename = "key_rotation")]
-    KeyRotation {
-        /// The old endpoint ID (must match current transport identity).
-        old_id: String,
-        /// The new endpoint ID that will replace it.
-        new_id: String,
-        /// Unix timestamp of the rotation.
-        timestamp: u64,
-        /// Signature by the old key over "rotate:{old_id}:{new_id}:{timestamp}".
-        signature: String,
-    },
-
-    /// A PRIVMSG or NOTICE relayed between servers.
-    #[serde(rename = "privmsg")]
-    Privmsg {
-        /// Stable event ID for dedup: "{origin_peer_id}:{counter}".
-        #[serde(default)]
-        event_id: String,
-        from: String,
-        target: String,
-        text: String,
-        /// Origin iroh endpoint ID (to prevent relay loops).
-        origin: String,
-        /// ULID message ID (IRCv3 `msgid` tag).
-        #[serde(default)]
-        msgid: Option<String>,
-        /// Server-attested message signature (`+freeq.at/sig`).
-        #[serde(default)]
-        sig: Option<String>,
-        /// Sender's DID — the value of the IRCv3 `account` tag. Carried
-        /// so the receiving server can stamp `account` for a remote
-        /// sender it has no local session for; without it the receiver
-        /// has no DID and federated clients show no identity. Always
-        /// origin-stamped from the authenticated session, never
-        /// client-set (preserves the anti-spoof rule across S2S).
-        /// `serde(default)` → older peers omit it (wire back-compat).
-        #[serde(default)]
-        account: Option<String>,
-        /// Application coordination tags (`+freeq.at/event` etc.) that ride
-        /// with the message so federated clients render the same card the
-        /// origin shows. `serde(default)` → older peers omit it, deserialize
-        /// to empty (wire back-compat). See `relay_coordination_tags`.
-        #[serde(default)]
-        tags: HashMap<String, String>,
-        /// When the relayed message originated as a `draft/multiline`
-        /// batch, the per-line breakdown so the receiving peer can
-        /// re-emit BATCH-wrapped frames to its own multiline-capable
-        /// clients (and N PRIVMSGs to fallback clients) instead of a
-        /// single PRIVMSG with `\n` in the body. Absent for normal
-        /// single-PRIVMSG sends; `text` always carries the assembled
-        /// body so peers without multiline-aware fan-out still get
-        /// the content (even if wire-broken on their own clients).
-        ///
-        /// # Why one event with a per-line breakdown, not N events
-        ///
-        /// An alternative shape would have been to ship a multiline
-        /// message as a sequence of N `S2sMessage::Privmsg` events,
-        /// mirroring the local wire shape (N PRIVMSGs grouped by
-        /// BATCH). That was rejected because:
-        ///
-        /// - **Atomicity**: a logical message is a single delivery
-        ///   unit; receiving peers shouldn't have to wait for N events
-        ///   to arrive (in order, with no drops) before they can fan
-        ///   out to their local channel members.
-        /// - **Dedup**: each event carries an `event_id`. Splitting
-        ///   into N events means N dedup entries and a separate
-        ///   grouping mechanism so a partial re-sync doesn't
-        ///   half-deliver.
-        /// - **Signatures**: `+freeq.at/sig` is signed over the
-        ///   assembled body. One sig per logical message keeps the
-        ///   verification cheap and unambiguous; N separately-signed
-        ///   chunks would either expensive (N sigs) or leak unverified
-        ///   payload (sig on first chunk only).
-        /// - **msgid placement**: a multiline message has one msgid
-        ///   per the IRCv3 spec. Bundling keeps "one event = one
-        ///   msgid" intact; splitting forces a non-obvious choice
-        ///   about which event owns the msgid.
-        ///
-        /// This bundling pattern is consistent with how the rest of
-        /// freeq's S2S layer transmits atomic-application units:
-        /// `SyncResponse` carries `Vec<ChannelInfo>` (the cluster's
-        /// channel view as of now, not N per-channel events);
-        /// `PolicySync` ships the full PolicyDocument JSON in one
-        /// event; `CrdtSync` bundles many CRDT ops into one payload.
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        multiline_lines: Option<Vec<MultilineLine>>,
-    },
-
-    /// A PIN/UNPIN relayed between servers.
-    #[serde(rename = "pin")]
-    Pin {
-        #[serde(default)]
-        event_id: String,
-        channel: String,
-        /// The ULID msgid of the pinned/unpinned message.
-        msgid: String,
-        /// Who pinned/unpinned it.
-        pinned_by: String,
-        /// true = pin added, false = pin removed.
-        #[serde(default)]
-        adding: bool,
-        origin: String,
-    },
-
-    /// A TAGMSG relayed between servers (reactions, typing, etc.).
-    #[serde(rename = "tagmsg")]
-    Tagmsg {
-        #[serde(default)]
-        event_id: String,
-        from: String,
-        target: String,
-        /// IRCv3 tags (e.g. +react, +reply, +typing).
-        tags: HashMap<String, String>,
-        origin: String,
-    },
-
-    /// A user joined a channel.
-    #[serde(rename = "join")]
-    Join {
-        #[serde(default)]
-        event_id: String,
-        nick: String,
-        channel: String,
-        /// Authenticated DID (if any) — used for DID-based ops.
-        did: Option<String>,
-        /// Resolved AT Protocol handle (e.g. "chadfowler.com").
-        handle: Option<String>,
-        /// Whether this user is an operator on their home server.
-        #[serde(default)]
-        is_op: bool,
-        /// Actor class: "human", "agent", or "external_agent".
-        #[serde(default)]
-        actor_class: Option<String>,
-        origin: String,
-    },
-
-    /// A channel was created (carries founder info for authority resolution).
-    #[serde(rename = "channel_created")]
-    ChannelCreated {
-        #[serde(default)]
-        event_id: String,
-        channel: String,
-        /// DID of the channel founder.
-        founder_did: Option<String>,
-        /// DIDs with operator status.
-        did_ops: Vec<String>,
-        /// Unix timestamp of channel creation (informational only).
-        created_at: u64,
-        origin: String,
-    },
-
-    /// A user left a channel.
-    #[serde(rename = "part")]
-    Part {
-        #[serde(default)]
-        event_id: String,
-        nick: String,
-        channel: String,
-        origin: String,
-    },
-
-    /// A user quit.
-    #[serde(rename = "quit")]
-    Quit {
-        #[serde(default)]
-        event_id: String,
-        nick: String,
-        reason: String,
-        origin: String,
-    },
-
-    /// A user changed nick.
-    #[serde(rename = "nick_change")]
-    NickChange {
-        #[serde(default)]
-        event_id: String,
-        old: String,
-        new: String,
-        origin: String,
-    },
-
-    /// Channel topic changed.
-    #[serde(rename = "topic")]
-    Topic {
-        #[serde(default)]
-        event_id: String,
-        channel: String,
-        topic: String,
-        set_by: String,
-        origin: String,
-    },
-
-    /// Channel mode changed.
-    #[serde(rename = "mode")]
-    Mode {
-        #[serde(default)]
-        event_id: String,
-        channel: String,
-        mode: String,
-        arg: Option<String>,
-        set_by: String,
-        origin: String,
-    },
-
-    /// Request full state sync (sent on initial link).
-    #[serde(rename = "sync_request")]
-    SyncRequest,
-
-    /// Response with current server state.
-    #[serde(rename = "sync_response")]
-    SyncResponse {
-        /// Server's iroh endpoint ID.
-        server_id: String,
-        /// Active channels and their topics.
-        channels: Vec<ChannelInfo>,
-    },
-
-    /// Automerge CRDT sync message for convergent state.
-    #[serde(rename = "crdt_sync")]
-    CrdtSync {
-        /// Base64-encoded Automerge sync message.
-        data: String,
-        /// Origin iroh endpoint ID (used to key sync state).
-        origin: String,
-    },
-
-    /// A user was kicked from a channel.
-    #[serde(rename = "kick")]
-    Kick {
-        #[serde(default)]
-        event_id: String,
-        /// Nick of the user being kicked.
-        nick: String,
-        channel: String,
-        /// Nick of the op who kicked them.
-        by: String,
-        reason: String,
-        origin: String,
-    },
-
-    /// A ban was set or removed on a channel.
-    #[serde(rename = "ban")]
-    Ban {
-        #[serde(default)]
-        event_id: String,
-        channel: String,
-        /// The ban mask (nick!user@host or DID).
-        mask: String,
-        /// Who set/removed the ban.
-        set_by: String,
-        /// true = ban added, false = ban removed.
-        adding: bool,
-        origin: String,
-    },
-
-    /// An invite-exception (+I) entry was set or removed on a channel.
-    #[serde(rename = "invite_exception")]
-    InviteException {
-        #[serde(default)]
-        event_id: String,
-        channel: String,
-        /// The mask (nick!user@host or DID).
-        mask: String,
-        /// Who set/removed the entry.
-        set_by: String,
-        /// true = entry added, false = entry removed.
-        adding: bool,
-        origin: String,
-    },
-
-    /// Policy sync — share a channel's policy document with peers.
-    /// Sent when a policy is created/updated/cleared.
-    #[serde(rename = "policy_sync")]
-    PolicySync {
-        #[serde(default)]
-        event_id: String,
-        channel: String,
-        /// JSON-serialized PolicyDocument (None = policy cleared).
-        policy_json: Option<String>,
-        /// JSON-serialized AuthoritySet.
-        authority_set_json: Option<String>,
-        origin: String,
-    },
-
-    /// An invite was issued for a user on a channel.
-    #[serde(rename = "invite")]
-    Invite {
-        #[serde(default)]
-        event_id: String,
-        channel: String,
-        /// The invitee identifier (DID, nick:XXX, or session ID).
-        invitee: String,
-        /// Nick of the user who issued the invite.
-        invited_by: String,
-        origin: String,
-    },
-
-    // ── AV session federation ───────────────────────────────────────
-    /// An AV session was created (voice/video call started).
-    #[serde(rename = "av_session_created")]
-    AvSessionCreated {
-        #[serde(default)]
-        event_id: String,
-        session_id: String,
-        channel: String,
-        created_by_did: String,
-        created_by_nick: String,
-        title: Option<String>,
-        iroh_ticket: Option<String>,
-        origin: String,
-    },
-
-    /// A user joined an AV session.
-    #[serde(rename = "av_session_joined")]
-    AvSessionJoined {
-        #[serde(default)]
-        event_id: String,
-        session_id: String,
-        did: String,
-        nick: String,
-        origin: String,
-    },
-
-    /// A user left an AV session.
-    #[serde(rename = "av_session_left")]
-    AvSessionLeft {
-        #[serde(default)]
-        event_id: String,
-        session_id: String,
-        did: String,
-        origin: String,
-    },
-
-    /// An AV session ended.
-    #[serde(rename = "av_session_ended")]
-    AvSessionEnded {
-        #[serde(default)]
-        event_id: String,
-        session_id: String,
-        ended_by: Option<String>,
-        origin: String,
-    },
-
-    /// Internal event: a peer's S2S link has disconnected.
-    /// Not sent over the wire — synthesized locally so the event processor
-    /// can clean up remote_members for that peer's origin.
-    #[serde(rename = "peer_disconnected")]
-    PeerDisconnected {
-        /// The iroh endpoint ID of the peer that disconnected.
-        peer_id: String,
-    },
+fn unix_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
 }
 
-/// Per-user info in a channel sync.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SyncNick {
-    pub nick: String,
-    #[serde(default)]
-    pub is_op: bool,
-    pub did: Option<String>,
-    /// Actor class: "human", "agent", or "external_agent".
-    #[serde(default)]
-    pub actor_class: Option<String>,
+/// Start a message's hop list ([`tags::HOPS`]) at its origin server,
+/// replacing anything a client put there.
+pub fn start_hops(tags: &mut HashMap<String, String>, server_name: &str) {
+    tags.remove(tags::HOPS);
+    add_hop(tags, server_name);
 }
 
-/// Channel info for sync.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ChannelInfo {
-    pub name: String,
-    pub topic: Option<String>,
-    /// Legacy field: plain nick list (for backward compat with old servers).
-    #[serde(default)]
-    pub nicks: Vec<String>,
-    /// Rich nick list with per-user metadata (preferred over `nicks`).
-    #[serde(default)]
-    pub nick_info: Vec<SyncNick>,
-    /// Channel founder DID.
-    pub founder_did: Option<String>,
-    /// DIDs with persistent operator status.
-    pub did_ops: Vec<String>,
-    /// Channel creation timestamp.
-    pub created_at: u64,
-    /// Channel modes: topic_locked, invite_only, no_ext_msg, moderated
-    #[serde(default)]
-    pub topic_locked: bool,
-    #[serde(default)]
-    pub invite_only: bool,
-    #[serde(default)]
-    pub no_ext_msg: bool,
-    #[serde(default)]
-    pub moderated: bool,
-    #[serde(default)]
-    pub key: Option<String>,
-    /// Active bans (mask strings).
-    #[serde(default)]
-    pub bans: Vec<String>,
-    /// Active invites (DIDs, nick:XXX tokens).
-    #[serde(default)]
-    pub invites: Vec<String>,
-    /// Active +I invite-exception entries (mask strings, hostmask or DID).
-    #[serde(default)]
-    pub invite_exceptions: Vec<String>,
+/// Append `server_name` and the current time to a relayed message's hop
+/// list. Returns the milliseconds since the previous hop, if there is
+/// one: the link's latency, plus any clock skew between the two servers.
+pub fn add_hop(tags: &mut HashMap<String, String>, server_name: &str) -> Option<u64> {
+    let now = unix_ms();
+    let hops = tags.entry(tags::HOPS.to_string()).or_default();
+    let previous = hops
+        .rsplit(',')
+        .next()
+        .and_then(|hop| hop.rsplit_once('@'))
+        .and_then(|(_, ms)| ms.parse::<u64>().ok());
+    if hops.is_empty() {
+        *hops = format!("{server_name}@{now}");
+    } else if hops.split(',').count() < MAX_HOPS {
+        hops.push_str(&format!(",{server_name}@{now}"));
+    }
+    previous.map(|ms| now.saturating_sub(ms))
 }
 
 /// Bounded set for event dedup. Uses two layers:
@@ -791,6 +361,36 @@ impl S2sManager {
         }
     }
 
+    /// Drop the link to `peer_id` — only if its entry is still generation
+    /// `conn_gen`, when given. Closing the entry's sender ends the link's
+    /// writer, which closes the connection. Returns whether a link was
+    /// dropped.
+    pub async fn drop_link(&self, peer_id: &str, conn_gen: Option<u64>) -> bool {
+        let mut peers = self.peers.lock().await;
+        match peers.get(peer_id) {
+            Some(entry) if conn_gen.is_none_or(|g| g == entry.conn_gen) => {}
+            _ => return false,
+        }
+        peers.remove(peer_id);
+        drop(peers);
+        self.peer_names.lock().await.remove(peer_id);
+        self.dedup.remove_peer(peer_id).await;
+
+        // Emit PeerDisconnected so the event processor can clean up
+        // remote_members for this peer's origin. This prevents ghost
+        // users lingering in channel rosters after a link drop.
+        let _ = self
+            .event_tx
+            .send(AuthenticatedS2sEvent {
+                authenticated_peer_id: peer_id.to_string(),
+                msg: S2sMessage::PeerDisconnected {
+                    peer_id: peer_id.to_string(),
+                },
+            })
+            .await;
+        true
+    }
+
     /// Look up the human-readable name for a peer (from Hello handshake).
     pub async fn peer_display_name(&self, peer_id: &str) -> String {
         self.peer_names
@@ -817,6 +417,48 @@ impl S2sManager {
         }
     }
 
+    /// Prepare `msg` for the wire: everything but the handshake and key
+    /// management messages goes out in a Signed envelope.
+    pub fn seal(&self, msg: S2sMessage) -> S2sMessage {
+        match msg {
+            S2sMessage::Hello { .. }
+            | S2sMessage::HelloAck { .. }
+            | S2sMessage::Signed { .. }
+            | S2sMessage::KeyRotation { .. } => msg,
+            _ => self.sign_message(&msg),
+        }
+    }
+
+    /// Unwrap a message read off the link from `peer_id`.
+    /// C-7 fix: Reject unsigned operational messages.
+    /// Only Hello/HelloAck/KeyRotation are exempt (handshake/key mgmt).
+    pub fn open(&self, msg: S2sMessage, peer_id: &str) -> Option<S2sMessage> {
+        match msg {
+            S2sMessage::Signed {
+                ref payload,
+                ref signature,
+                ref signer,
+            } => {
+                let inner = self.verify_signed(payload, signature, signer, peer_id);
+                if inner.is_none() {
+                    tracing::warn!(peer = %peer_id, "S2S: dropped message with invalid signature");
+                }
+                inner
+            }
+            S2sMessage::Hello { .. }
+            | S2sMessage::HelloAck { .. }
+            | S2sMessage::KeyRotation { .. } => Some(msg),
+            other => {
+                tracing::warn!(
+                    peer = %peer_id,
+                    msg_type = %serde_json::to_string(&other).unwrap_or_default().chars().take(60).collect::<String>(),
+                    "S2S: rejected unsigned message — signing required"
+                );
+                None
+            }
+        }
+    }
+
     /// Verify and unwrap a Signed envelope. Returns the inner message if valid.
     pub fn verify_signed(
         &self,
@@ -965,9 +607,20 @@ pub async fn start(
     state: Arc<SharedState>,
     endpoint: iroh::Endpoint,
 ) -> Result<(Arc<S2sManager>, mpsc::Receiver<AuthenticatedS2sEvent>)> {
-    let (event_tx, event_rx) = mpsc::channel(1024);
     let server_id = endpoint.id().to_string();
     let signing_key = endpoint.secret_key().clone();
+    Ok(start_with_identity(state, server_id, signing_key))
+}
+
+/// Start the S2S subsystem under an explicit identity, without an iroh
+/// endpoint. Links are attached by inserting into `peers` — the in-process
+/// test links in [`crate::testing`] use this.
+pub fn start_with_identity(
+    state: Arc<SharedState>,
+    server_id: String,
+    signing_key: iroh::SecretKey,
+) -> (Arc<S2sManager>, mpsc::Receiver<AuthenticatedS2sEvent>) {
+    let (event_tx, event_rx) = mpsc::channel(1024);
     let trust_config = parse_trust_config(&state.config.s2s_peer_trust);
 
     let (broadcast_tx, mut broadcast_rx) = mpsc::channel::<S2sMessage>(1024);
@@ -1007,7 +660,7 @@ pub async fn start(
         }
     });
 
-    Ok((manager, event_rx))
+    (manager, event_rx)
 }
 
 /// `ProtocolHandler` that dispatches accepted S2S connections.
@@ -1140,22 +793,18 @@ async fn handle_s2s_connection_from_manager(
     incoming: bool,
 ) {
     let peers = Arc::clone(&manager.peers);
-    let peer_names = Arc::clone(&manager.peer_names);
     let event_tx: mpsc::Sender<AuthenticatedS2sEvent> = manager.event_tx.clone();
     let server_id = manager.server_id.clone();
     let server_name = manager.server_name.clone();
     let conn_gen = Arc::clone(&manager.conn_gen);
-    let dedup = Arc::clone(&manager.dedup);
     let mgr = Arc::clone(manager);
     handle_s2s_connection(
         conn,
         peers,
-        peer_names,
         event_tx,
         server_id,
         server_name,
         conn_gen,
-        dedup,
         incoming,
         mgr,
     )
@@ -1166,12 +815,10 @@ async fn handle_s2s_connection_from_manager(
 async fn handle_s2s_connection(
     conn: iroh::endpoint::Connection,
     peers: Arc<tokio::sync::Mutex<HashMap<String, PeerEntry>>>,
-    peer_names: Arc<tokio::sync::Mutex<HashMap<String, String>>>,
     event_tx: mpsc::Sender<AuthenticatedS2sEvent>,
     server_id: String,
     server_name: String,
     conn_gen: Arc<AtomicU64>,
-    dedup: Arc<DedupSet>,
     incoming: bool,
     manager: Arc<S2sManager>,
 ) {
@@ -1203,6 +850,9 @@ async fn handle_s2s_connection(
     //   The peer with the HIGHER endpoint ID keeps the INCOMING connection.
     // This means: drop if `incoming == (our_id < peer_id)`.
     let (write_tx, mut write_rx) = mpsc::channel::<S2sMessage>(256);
+    // Fault-injection builds can pause, delay, drop or reorder this link.
+    #[cfg(feature = "s2s-faults")]
+    let write_tx = crate::s2s_faults::stage(&server_id, &peer_id, write_tx);
     let my_gen = conn_gen.fetch_add(1, Ordering::Relaxed);
     {
         let mut peers_guard = peers.lock().await;
@@ -1274,38 +924,8 @@ async fn handle_s2s_connection(
                         tracing::debug!(peer = %read_peer, msg_count, "S2S received: {}", line.chars().take(120).collect::<String>());
 
                         // Phase 2: Unwrap signed envelopes
-                        // C-7 fix: Reject unsigned operational messages.
-                        // Only Hello/HelloAck/KeyRotation are exempt (handshake/key mgmt).
-                        let msg = match msg {
-                            S2sMessage::Signed {
-                                ref payload,
-                                ref signature,
-                                ref signer,
-                            } => {
-                                match read_manager.verify_signed(
-                                    payload,
-                                    signature,
-                                    signer,
-                                    &authenticated_peer_id,
-                                ) {
-                                    Some(inner) => inner,
-                                    None => {
-                                        tracing::warn!(peer = %read_peer, "S2S: dropped message with invalid signature");
-                                        continue;
-                                    }
-                                }
-                            }
-                            S2sMessage::Hello { .. }
-                            | S2sMessage::HelloAck { .. }
-                            | S2sMessage::KeyRotation { .. } => msg,
-                            other => {
-                                tracing::warn!(
-                                    peer = %read_peer,
-                                    msg_type = %serde_json::to_string(&other).unwrap_or_default().chars().take(60).collect::<String>(),
-                                    "S2S: rejected unsigned message — signing required"
-                                );
-                                continue;
-                            }
+                        let Some(msg) = read_manager.open(msg, &authenticated_peer_id) else {
+                            continue;
                         };
 
                         let event = AuthenticatedS2sEvent {
@@ -1342,13 +962,7 @@ async fn handle_s2s_connection(
         let mut msg_count: u64 = 0;
         while let Some(msg) = write_rx.recv().await {
             // Sign non-handshake messages for non-repudiation
-            let msg_to_send = match &msg {
-                S2sMessage::Hello { .. }
-                | S2sMessage::HelloAck { .. }
-                | S2sMessage::Signed { .. }
-                | S2sMessage::KeyRotation { .. } => msg,
-                _ => write_manager.sign_message(&msg),
-            };
+            let msg_to_send = write_manager.seal(msg);
             match serde_json::to_string(&msg_to_send) {
                 Ok(json) => {
                     msg_count += 1;
@@ -1407,33 +1021,13 @@ async fn handle_s2s_connection(
 
     // Only remove peer entry if it's still ours (same generation).
     // A replacement connection may have already inserted a new entry.
-    {
-        let mut peers_guard = peers.lock().await;
-        if let Some(entry) = peers_guard.get(&peer_id) {
-            if entry.conn_gen == my_gen {
-                peers_guard.remove(&peer_id);
-                peer_names.lock().await.remove(&peer_id);
-                dedup.remove_peer(&peer_id).await;
-                tracing::info!(peer = %peer_id, gen = my_gen, "S2S link closed (entry removed)");
-
-                // Emit PeerDisconnected so the event processor can clean up
-                // remote_members for this peer's origin. This prevents ghost
-                // users lingering in channel rosters after a link drop.
-                let _ = event_tx
-                    .send(AuthenticatedS2sEvent {
-                        authenticated_peer_id: peer_id.clone(),
-                        msg: S2sMessage::PeerDisconnected {
-                            peer_id: peer_id.clone(),
-                        },
-                    })
-                    .await;
-            } else {
-                tracing::info!(
-                    peer = %peer_id, my_gen, current_gen = entry.conn_gen,
-                    "S2S link closed (entry kept — newer connection exists)"
-                );
-            }
-        }
+    if manager.drop_link(&peer_id, Some(my_gen)).await {
+        tracing::info!(peer = %peer_id, gen = my_gen, "S2S link closed (entry removed)");
+    } else {
+        tracing::info!(
+            peer = %peer_id, my_gen,
+            "S2S link closed (entry kept — gone or newer connection exists)"
+        );
     }
 }
 
@@ -1441,6 +1035,26 @@ async fn handle_s2s_connection(
 mod tests {
     use super::*;
 
+    #[test]
+    fn hops_start_at_the_origin_and_grow_per_server() {
+        let mut tags = HashMap::new();
+        tags.insert(tags::HOPS.to_string(), "forged@1".to_string());
+        start_hops(&mut tags, "a.test");
+        let first = tags[tags::HOPS].clone();
+        assert!(first.starts_with("a.test@") && !first.contains(','));
+
+        let hop_ms = add_hop(&mut tags, "b.test").expect("previous hop");
+        assert!(hop_ms < 60_000);
+        let hops: Vec<&str> = tags[tags::HOPS].split(',').collect();
+        assert_eq!(hops.len(), 2);
+        assert!(hops[1].starts_with("b.test@"));
+
+        for _ in 0..MAX_HOPS {
+            add_hop(&mut tags, "c.test");
+        }
+        assert_eq!(tags[tags::HOPS].split(',').count(), MAX_HOPS);
+    }
+
     #[test]
     fn trust_level_parse() {
         assert_eq!(TrustLevel::parse_level("full"), TrustLevel::Full);
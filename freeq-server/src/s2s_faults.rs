@@ -0,0 +1,379 @@
+//! S2S fault injection (feature `s2s-faults`; never enable in production).
+//!
+//! With the feature on, every S2S link's outbound queue passes through a
+//! [`stage`] that applies the [`LinkFaults`] set for that direction: hold
+//! everything (pause), delay each message, drop a share of them, or deliver
+//! them in reversed batches. Faults are keyed by `(from, to)` server IDs in
+//! a process-wide table, so tests driving several in-process servers (see
+//! [`crate::testing`]) and the `S2SFAULT` oper command reach the same links.
+//! Changes apply to live links immediately.
+
+use parking_lot::Mutex;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+
+use crate::s2s::S2sMessage;
+
+/// Faults applied to one direction of a link. The default is a clean link.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LinkFaults {
+    /// Hold every message until unpaused. Nothing is lost.
+    pub paused: bool,
+    /// Wait this long before passing on each message.
+    pub delay: Duration,
+    /// Share of messages silently dropped, 0.0–1.0. 1.0 partitions the link.
+    pub drop_rate: f64,
+    /// Pass messages on in reversed batches of this size; 0 or 1 keeps
+    /// order. Shrinking the window releases a partial batch in order.
+    pub reorder_window: usize,
+}
+
+impl LinkFaults {
+    pub fn is_clear(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// e.g. "paused, delay 200ms, drop 50%, reorder 3", or "clear".
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if self.paused {
+            parts.push("paused".to_string());
+        }
+        if !self.delay.is_zero() {
+            parts.push(format!("delay {}ms", self.delay.as_millis()));
+        }
+        if self.drop_rate > 0.0 {
+            parts.push(format!("drop {}%", (self.drop_rate * 100.0).round()));
+        }
+        if self.reorder_window > 1 {
+            parts.push(format!("reorder {}", self.reorder_window));
+        }
+        if parts.is_empty() {
+            "clear".to_string()
+        } else {
+            parts.join(", ")
+        }
+    }
+}
+
+/// Messages a link direction has passed on or dropped since the process
+/// started (across reconnects).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LinkStats {
+    pub delivered: u64,
+    pub dropped: u64,
+}
+
+struct Control {
+    faults: watch::Sender<LinkFaults>,
+    delivered: AtomicU64,
+    dropped: AtomicU64,
+}
+
+type LinkKey = (String, String);
+
+static LINKS: LazyLock<Mutex<HashMap<LinkKey, Arc<Control>>>> = LazyLock::new(Default::default);
+
+fn control(from: &str, to: &str) -> Arc<Control> {
+    let mut links = LINKS.lock();
+    let ctl = links
+        .entry((from.to_string(), to.to_string()))
+        .or_insert_with(|| {
+            Arc::new(Control {
+                faults: watch::channel(LinkFaults::default()).0,
+                delivered: AtomicU64::new(0),
+                dropped: AtomicU64::new(0),
+            })
+        });
+    Arc::clone(ctl)
+}
+
+/// Replace the faults on messages from `from` to `to`.
+pub fn set(from: &str, to: &str, faults: LinkFaults) {
+    control(from, to).faults.send_replace(faults);
+}
+
+/// Change the faults on messages from `from` to `to` in place.
+pub fn update(from: &str, to: &str, change: impl FnOnce(&mut LinkFaults)) {
+    control(from, to).faults.send_modify(change);
+}
+
+/// The faults currently set from `from` to `to`.
+pub fn faults(from: &str, to: &str) -> LinkFaults {
+    control(from, to).faults.borrow().clone()
+}
+
+/// Remove every fault from `from` to `to`; held messages are released.
+pub fn clear(from: &str, to: &str) {
+    set(from, to, LinkFaults::default());
+}
+
+/// Clear every link in the process.
+pub fn clear_all() {
+    for ctl in LINKS.lock().values() {
+        ctl.faults.send_replace(LinkFaults::default());
+    }
+}
+
+/// Drop everything between `a` and `b`, both ways. Unlike cutting the
+/// link, neither side notices: it is a silent partition.
+pub fn partition(a: &str, b: &str) {
+    update(a, b, |f| f.drop_rate = 1.0);
+    update(b, a, |f| f.drop_rate = 1.0);
+}
+
+/// Undo every fault between `a` and `b`, both ways.
+pub fn heal(a: &str, b: &str) {
+    clear(a, b);
+    clear(b, a);
+}
+
+pub fn stats(from: &str, to: &str) -> LinkStats {
+    let ctl = control(from, to);
+    LinkStats {
+        delivered: ctl.delivered.load(Ordering::Relaxed),
+        dropped: ctl.dropped.load(Ordering::Relaxed),
+    }
+}
+
+/// Links from `from` that currently have faults set, as (peer, faults).
+pub fn active_from(from: &str) -> Vec<(String, LinkFaults)> {
+    let mut active: Vec<_> = LINKS
+        .lock()
+        .iter()
+        .filter(|((f, _), _)| f == from)
+        .map(|((_, to), ctl)| (to.clone(), ctl.faults.borrow().clone()))
+        .filter(|(_, faults)| !faults.is_clear())
+        .collect();
+    active.sort_by(|a, b| a.0.cmp(&b.0));
+    active
+}
+
+/// Put a fault stage in front of `downstream` (a link's writer queue) for
+/// messages from `from` to `to`; returns the sender to use instead. The
+/// stage ends when the returned sender is dropped, closing `downstream`.
+pub(crate) fn stage(
+    from: &str,
+    to: &str,
+    downstream: mpsc::Sender<S2sMessage>,
+) -> mpsc::Sender<S2sMessage> {
+    let (tx, mut rx) = mpsc::channel::<S2sMessage>(256);
+    let ctl = control(from, to);
+    let mut changes = ctl.faults.subscribe();
+    tokio::spawn(async move {
+        let mut shaper = Shaper {
+            ctl,
+            downstream,
+            queued: VecDeque::new(),
+            held: Vec::new(),
+        };
+        loop {
+            let faults = changes.borrow_and_update().clone();
+            if !faults.paused && !shaper.release(&faults).await {
+                return;
+            }
+            tokio::select! {
+                changed = changes.changed() => {
+                    if changed.is_err() {
+                        return;
+                    }
+                }
+                msg = rx.recv() => {
+                    // Upstream gone: the link is down, and anything still
+                    // queued dies with it, as on a closed connection.
+                    let Some(msg) = msg else { return };
+                    shaper.queued.push_back(msg);
+                }
+            }
+        }
+    });
+    tx
+}
+
+struct Shaper {
+    ctl: Arc<Control>,
+    downstream: mpsc::Sender<S2sMessage>,
+    /// Received but not yet shaped (everything, while paused).
+    queued: VecDeque<S2sMessage>,
+    /// Shaped, waiting to fill a reorder batch.
+    held: Vec<S2sMessage>,
+}
+
+impl Shaper {
+    /// Shape everything queued and pass on whatever is due. False once
+    /// the link's writer is gone.
+    async fn release(&mut self, faults: &LinkFaults) -> bool {
+        while let Some(msg) = self.queued.pop_front() {
+            if faults.drop_rate > 0.0 && rand::random::<f64>() < faults.drop_rate {
+                self.ctl.dropped.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+            if !faults.delay.is_zero() {
+                tokio::time::sleep(faults.delay).await;
+            }
+            self.held.push(msg);
+            if self.held.len() >= faults.reorder_window.max(1) && !self.flush(faults).await {
+                return false;
+            }
+        }
+        // A window that shrank below what is held releases it now.
+        if !self.held.is_empty()
+            && self.held.len() >= faults.reorder_window.max(1)
+            && !self.flush(faults).await
+        {
+            return false;
+        }
+        true
+    }
+
+    async fn flush(&mut self, faults: &LinkFaults) -> bool {
+        let mut batch = std::mem::take(&mut self.held);
+        if faults.reorder_window > 1 {
+            batch.reverse();
+        }
+        for msg in batch {
+            if self.downstream.send(msg).await.is_err() {
+                return false;
+            }
+            self.ctl.delivered.fetch_add(1, Ordering::Relaxed);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A numbered message; any variant does, the stage never looks inside.
+    fn msg(n: u64) -> S2sMessage {
+        S2sMessage::PeerDisconnected {
+            peer_id: n.to_string(),
+        }
+    }
+
+    fn nonce(m: &S2sMessage) -> u64 {
+        match m {
+            S2sMessage::PeerDisconnected { peer_id } => peer_id.parse().unwrap(),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    async fn recv(rx: &mut mpsc::Receiver<S2sMessage>) -> u64 {
+        let m = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .expect("message")
+            .expect("open");
+        nonce(&m)
+    }
+
+    /// Each test uses its own link key; the table is process-wide.
+    fn link(name: &str) -> (String, String) {
+        (format!("{name}-from"), format!("{name}-to"))
+    }
+
+    #[tokio::test]
+    async fn pause_holds_and_resume_releases_in_order() {
+        let (from, to) = link("pause");
+        let (down_tx, mut down_rx) = mpsc::channel(16);
+        let tx = stage(&from, &to, down_tx);
+        update(&from, &to, |f| f.paused = true);
+        for n in 0..3 {
+            tx.send(msg(n)).await.unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(down_rx.try_recv().is_err(), "paused link delivers nothing");
+
+        clear(&from, &to);
+        for n in 0..3 {
+            assert_eq!(recv(&mut down_rx).await, n);
+        }
+    }
+
+    #[tokio::test]
+    async fn drop_rate_one_partitions_and_counts() {
+        let (from, to) = link("drop");
+        let (down_tx, mut down_rx) = mpsc::channel(16);
+        let tx = stage(&from, &to, down_tx);
+        update(&from, &to, |f| f.drop_rate = 1.0);
+        for n in 0..4 {
+            tx.send(msg(n)).await.unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(down_rx.try_recv().is_err());
+        assert_eq!(stats(&from, &to).dropped, 4);
+
+        clear(&from, &to);
+        tx.send(msg(9)).await.unwrap();
+        assert_eq!(recv(&mut down_rx).await, 9);
+        assert_eq!(stats(&from, &to).delivered, 1);
+    }
+
+    #[tokio::test]
+    async fn reorder_reverses_batches_and_flushes_on_shrink() {
+        let (from, to) = link("reorder");
+        let (down_tx, mut down_rx) = mpsc::channel(16);
+        let tx = stage(&from, &to, down_tx);
+        update(&from, &to, |f| f.reorder_window = 3);
+        for n in 0..5 {
+            tx.send(msg(n)).await.unwrap();
+        }
+        assert_eq!(recv(&mut down_rx).await, 2);
+        assert_eq!(recv(&mut down_rx).await, 1);
+        assert_eq!(recv(&mut down_rx).await, 0);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(down_rx.try_recv().is_err(), "partial batch is held");
+
+        clear(&from, &to);
+        assert_eq!(recv(&mut down_rx).await, 3);
+        assert_eq!(recv(&mut down_rx).await, 4);
+    }
+
+    #[tokio::test]
+    async fn delay_postpones_delivery() {
+        let (from, to) = link("delay");
+        let (down_tx, mut down_rx) = mpsc::channel(16);
+        let tx = stage(&from, &to, down_tx);
+        update(&from, &to, |f| f.delay = Duration::from_millis(150));
+        let start = std::time::Instant::now();
+        tx.send(msg(1)).await.unwrap();
+        assert_eq!(recv(&mut down_rx).await, 1);
+        assert!(start.elapsed() >= Duration::from_millis(150));
+    }
+
+    #[tokio::test]
+    async fn dropping_the_sender_closes_downstream() {
+        let (from, to) = link("close");
+        let (down_tx, mut down_rx) = mpsc::channel(16);
+        let tx = stage(&from, &to, down_tx);
+        drop(tx);
+        let closed = tokio::time::timeout(Duration::from_secs(2), down_rx.recv()).await;
+        assert!(matches!(closed, Ok(None)));
+    }
+
+    #[test]
+    fn describe_and_active_links() {
+        let (from, to) = link("describe");
+        assert_eq!(faults(&from, &to).describe(), "clear");
+        set(
+            &from,
+            &to,
+            LinkFaults {
+                paused: true,
+                delay: Duration::from_millis(200),
+                drop_rate: 0.5,
+                reorder_window: 3,
+            },
+        );
+        assert_eq!(
+            faults(&from, &to).describe(),
+            "paused, delay 200ms, drop 50%, reorder 3"
+        );
+        assert_eq!(active_from(&from).len(), 1);
+        heal(&from, &to);
+        assert!(active_from(&from).is_empty());
+    }
+}
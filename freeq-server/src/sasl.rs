@@ -6,10 +6,20 @@
 //!
 //! Flow:
 //! 1. Client sends AUTHENTICATE ATPROTO-CHALLENGE
-//! 2. Server sends challenge: base64(json { session_id, nonce, timestamp })
+//! 2. Server sends challenge: base64(json { session_id, nonce, timestamp, [binding] })
 //! 3. Client sends response: base64(json { did, signature, [method], [pds_url] })
 //! 4. Server verifies via crypto or PDS session
 //! 5. Server sends 903 (success) or 904 (failure)
+//!
+//! Challenges are single-use and short-lived, and live only in memory, so
+//! a restart voids every outstanding one. When the transport offers a
+//! channel binding (TLS exporter, iroh endpoint ID) the challenge carries
+//! it as `binding`, asking the client to report its own view of the
+//! binding in the response. `crypto` responses sign that report along
+//! with the challenge, and
+//! [`crate::server::SharedState::take_sasl_challenge`] rejects one whose
+//! report differs from the connection's: it was signed for a connection
+//! a relay held open to the client, not this one.
 
 use base64::Engine;
 use base64::engine::general_purpose::URL_SAFE_NO_PAD;
@@ -27,6 +37,12 @@ pub struct Challenge {
     pub session_id: String,
     pub nonce: String,
     pub timestamp: i64,
+    /// Channel binding of the connection the challenge was issued on:
+    /// `tls-exporter:<base64url>` or `iroh:<endpoint-id>`. Absent on
+    /// transports without one (plain TCP, WebSocket). Tells the client
+    /// the server checks [`ChallengeResponse::binding`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub binding: Option<String>,
 }
 
 /// A client's response to a challenge.
@@ -44,6 +60,14 @@ pub struct ChallengeResponse {
     /// bind the response to the specific challenge that was issued.
     #[serde(default)]
     pub challenge_nonce: Option<String>,
+    /// Device fingerprint for `web-token` responses whose token was minted
+    /// with a fingerprint binding.
+    #[serde(default)]
+    pub fingerprint: Option<String>,
+    /// Channel binding as the client sees it. Signed along with the
+    /// challenge by the `crypto` method.
+    #[serde(default)]
+    pub binding: Option<String>,
 }
 
 /// Stored challenge data: the struct for validation + raw bytes for signature verification.
@@ -68,6 +92,12 @@ impl ChallengeStore {
 
     /// Generate a new challenge for a session. Returns the base64url-encoded challenge.
     pub fn create(&self, session_id: &str) -> String {
+        self.create_bound(session_id, None).0
+    }
+
+    /// Generate a new challenge carrying the connection's channel binding.
+    /// Returns the base64url-encoded challenge and the challenge itself.
+    pub fn create_bound(&self, session_id: &str, binding: Option<&str>) -> (String, Challenge) {
         let mut nonce_bytes = [0u8; 32];
         rand::thread_rng().fill_bytes(&mut nonce_bytes);
         let nonce = URL_SAFE_NO_PAD.encode(nonce_bytes);
@@ -76,6 +106,7 @@ impl ChallengeStore {
             session_id: session_id.to_string(),
             nonce,
             timestamp: Utc::now().timestamp(),
+            binding: binding.map(str::to_string),
         };
 
         let raw_bytes = serde_json::to_vec(&challenge).expect("challenge serialization");
@@ -84,12 +115,17 @@ impl ChallengeStore {
         self.pending.lock().insert(
             session_id.to_string(),
             StoredChallenge {
-                challenge,
+                challenge: challenge.clone(),
                 raw_bytes,
             },
         );
 
-        encoded
+        (encoded, challenge)
+    }
+
+    /// Challenge lifetime in seconds.
+    pub fn timeout_secs(&self) -> u64 {
+        self.timeout_secs
     }
 
     /// Consume a challenge for verification. Returns None if the challenge
@@ -193,8 +229,9 @@ async fn verify_crypto(
         return Err("No authentication keys found in DID document".to_string());
     }
 
+    let payload = freeq_sdk::auth::signed_payload(challenge_bytes, response.binding.as_deref());
     for (key_id, public_key) in &auth_keys {
-        match public_key.verify(challenge_bytes, &sig_bytes) {
+        match public_key.verify(&payload, &sig_bytes) {
             Ok(()) => {
                 tracing::info!(
                     did = %response.did,
@@ -437,6 +474,7 @@ mod tests {
             session_id: "sess-old".to_string(),
             nonce: URL_SAFE_NO_PAD.encode(nonce_bytes),
             timestamp: Utc::now().timestamp() - 120, // 2 minutes ago
+            binding: None,
         };
         let raw = serde_json::to_vec(&old_challenge).unwrap();
         store.pending.lock().insert(
@@ -461,6 +499,22 @@ mod tests {
         assert!(store.take("sess-a").is_some());
     }
 
+    #[test]
+    fn bound_challenge_carries_binding() {
+        let store = ChallengeStore::new(60);
+        let (encoded, issued) = store.create_bound("sess-1", Some("iroh:abc"));
+        let bytes = URL_SAFE_NO_PAD.decode(&encoded).unwrap();
+        let challenge: Challenge = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(challenge.binding.as_deref(), Some("iroh:abc"));
+        assert_eq!(challenge.nonce, issued.nonce);
+
+        // Unbound challenges omit the field entirely so older clients see
+        // the same JSON shape as before.
+        let encoded = store.create("sess-2");
+        let bytes = URL_SAFE_NO_PAD.decode(&encoded).unwrap();
+        assert!(!String::from_utf8(bytes).unwrap().contains("binding"));
+    }
+
     #[test]
     fn decode_response_roundtrip() {
         let resp = ChallengeResponse {
@@ -470,6 +524,8 @@ mod tests {
             pds_url: None,
             dpop_proof: None,
             challenge_nonce: None,
+            fingerprint: None,
+            binding: None,
         };
         let json = serde_json::to_vec(&resp).unwrap();
         let encoded = URL_SAFE_NO_PAD.encode(&json);
@@ -486,6 +542,8 @@ mod tests {
             pds_url: Some("https://pds.example.com".to_string()),
             dpop_proof: None,
             challenge_nonce: Some("test-nonce".to_string()),
+            fingerprint: None,
+            binding: None,
         };
         let json = serde_json::to_vec(&resp).unwrap();
         let encoded = URL_SAFE_NO_PAD.encode(&json);
@@ -521,6 +579,8 @@ mod tests {
             pds_url: None,
             dpop_proof: None,
             challenge_nonce: None,
+            fingerprint: None,
+            binding: None,
         };
 
         let result = verify_response(&challenge, &challenge_bytes, &response, &resolver).await;
@@ -555,6 +615,8 @@ mod tests {
             pds_url: None,
             dpop_proof: None,
             challenge_nonce: None,
+            fingerprint: None,
+            binding: None,
         };
 
         let result = verify_response(&challenge, &challenge_bytes, &response, &resolver).await;
@@ -587,6 +649,8 @@ mod tests {
             pds_url: None,
             dpop_proof: None,
             challenge_nonce: None,
+            fingerprint: None,
+            binding: None,
         };
 
         let result = verify_response(&challenge, &challenge_bytes, &response, &resolver).await;
@@ -619,6 +683,8 @@ mod tests {
             pds_url: None,
             dpop_proof: None,
             challenge_nonce: None,
+            fingerprint: None,
+            binding: None,
         };
 
         let result = verify_response(&challenge, &challenge_bytes, &response, &resolver).await;
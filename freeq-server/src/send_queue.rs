@@ -0,0 +1,331 @@
+//! Outbound client queues with tiered backpressure.
+//!
+//! Every connection gets one [`ClientQueue`] in `SharedState::connections`
+//! and a writer task draining the matching [`QueueReceiver`] into the
+//! socket. A client that stops reading used to fill its queue and then be
+//! disconnected the next time *its own* reply failed to queue, while every
+//! other sender silently lost lines. Now a backed-up client degrades in
+//! steps, by bytes waiting to be written:
+//!
+//! 1. past [`LOW_PRIORITY_BUDGET`], [`Priority::Low`] traffic (NAMES
+//!    lists, history replay) is dropped, and presence updates are
+//!    coalesced so only the latest one per source is kept;
+//! 2. past [`BYTE_BUDGET`], everything is dropped and the queue is marked
+//!    congested;
+//! 3. a queue that stays congested for [`CONGESTION_GRACE`] gets its
+//!    connection closed by the read loop.
+//!
+//! Drops and closes are counted in [`QueueMetrics`] for `/metrics`.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::{TryRecvError, TrySendError};
+
+use crate::server::WireLine;
+
+/// Lines a client queue holds. Large enough that the byte budgets, not
+/// the line count, are what normally bite.
+pub const QUEUE_LINES: usize = 16384;
+/// Queued bytes past which low-priority lines are dropped and presence
+/// updates coalesced.
+pub const LOW_PRIORITY_BUDGET: usize = 256 * 1024;
+/// Queued bytes past which every line is dropped.
+pub const BYTE_BUDGET: usize = 2 * 1024 * 1024;
+/// How long a queue may stay congested before its client is disconnected.
+pub const CONGESTION_GRACE: Duration = Duration::from_secs(30);
+/// Distinct presence sources held while coalescing; beyond this, further
+/// sources' updates are dropped.
+const MAX_COALESCED: usize = 1024;
+
+/// How much a line matters to the client receiving it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// Bulk replies the client can ask for again (NAMES, history).
+    Low,
+    Normal,
+}
+
+/// Process-wide backpressure counters, shared by every queue.
+#[derive(Debug, Default)]
+pub struct QueueMetrics {
+    pub dropped_low_total: AtomicU64,
+    pub dropped_total: AtomicU64,
+    pub coalesced_total: AtomicU64,
+    pub congestion_quits_total: AtomicU64,
+}
+
+impl QueueMetrics {
+    fn bump(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+struct Shared {
+    queued_bytes: AtomicUsize,
+    congested: AtomicBool,
+    congested_since: Mutex<Option<Instant>>,
+    /// Coalesced presence updates, oldest source first.
+    presence: Mutex<Vec<(String, WireLine)>>,
+    metrics: Arc<QueueMetrics>,
+}
+
+impl Shared {
+    fn mark_congested(&self) {
+        if !self.congested.swap(true, Ordering::Relaxed) {
+            *self.congested_since.lock() = Some(Instant::now());
+        }
+    }
+
+    /// Account for a line leaving the queue.
+    fn release(&self, len: usize) {
+        let before = self.queued_bytes.fetch_sub(len, Ordering::Relaxed);
+        if before - len < LOW_PRIORITY_BUDGET && self.congested.swap(false, Ordering::Relaxed) {
+            *self.congested_since.lock() = None;
+        }
+    }
+
+    fn take_presence(&self) -> Option<WireLine> {
+        let mut presence = self.presence.lock();
+        (!presence.is_empty()).then(|| presence.remove(0).1)
+    }
+}
+
+/// Sending half of a client's outbound queue.
+#[derive(Clone)]
+pub struct ClientQueue {
+    tx: mpsc::Sender<WireLine>,
+    shared: Arc<Shared>,
+}
+
+/// Receiving half, owned by the connection's writer task.
+pub struct QueueReceiver {
+    rx: mpsc::Receiver<WireLine>,
+    shared: Arc<Shared>,
+}
+
+/// A queue holding up to `lines` lines, counting into `metrics`.
+pub fn channel(lines: usize, metrics: Arc<QueueMetrics>) -> (ClientQueue, QueueReceiver) {
+    let (tx, rx) = mpsc::channel(lines);
+    let shared = Arc::new(Shared {
+        queued_bytes: AtomicUsize::new(0),
+        congested: AtomicBool::new(false),
+        congested_since: Mutex::new(None),
+        presence: Mutex::new(Vec::new()),
+        metrics,
+    });
+    (
+        ClientQueue {
+            tx,
+            shared: shared.clone(),
+        },
+        QueueReceiver { rx, shared },
+    )
+}
+
+impl ClientQueue {
+    /// Queue a [`Priority::Normal`] line.
+    pub fn try_send(&self, line: WireLine) -> Result<(), TrySendError<WireLine>> {
+        self.try_send_with(line, Priority::Normal)
+    }
+
+    /// Queue a [`Priority::Low`] line.
+    pub fn try_send_low(&self, line: WireLine) -> Result<(), TrySendError<WireLine>> {
+        self.try_send_with(line, Priority::Low)
+    }
+
+    pub fn try_send_with(
+        &self,
+        line: WireLine,
+        priority: Priority,
+    ) -> Result<(), TrySendError<WireLine>> {
+        let len = line.len();
+        let queued = self.shared.queued_bytes.fetch_add(len, Ordering::Relaxed) + len;
+        let over_budget = match priority {
+            Priority::Low => queued > LOW_PRIORITY_BUDGET,
+            Priority::Normal => queued > BYTE_BUDGET,
+        };
+        let result = if over_budget {
+            Err(TrySendError::Full(line))
+        } else {
+            self.tx.try_send(line)
+        };
+        if let Err(ref e) = result {
+            self.shared.queued_bytes.fetch_sub(len, Ordering::Relaxed);
+            if matches!(e, TrySendError::Full(_)) {
+                match priority {
+                    Priority::Low => QueueMetrics::bump(&self.shared.metrics.dropped_low_total),
+                    Priority::Normal => {
+                        self.shared.mark_congested();
+                        QueueMetrics::bump(&self.shared.metrics.dropped_total);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// Queue a presence update (AWAY and the like) from `source`. Sent
+    /// straight through while the queue is healthy; once it backs up,
+    /// only the newest update per source is kept, delivered after the
+    /// backlog drains.
+    pub fn send_presence(&self, source: &str, line: WireLine) {
+        let mut presence = self.shared.presence.lock();
+        if presence.is_empty()
+            && self.shared.queued_bytes.load(Ordering::Relaxed) + line.len() <= LOW_PRIORITY_BUDGET
+        {
+            drop(presence);
+            let _ = self.try_send(line);
+            return;
+        }
+        if let Some(slot) = presence.iter_mut().find(|(s, _)| s == source) {
+            slot.1 = line;
+            QueueMetrics::bump(&self.shared.metrics.coalesced_total);
+            return;
+        }
+        if presence.len() >= MAX_COALESCED {
+            QueueMetrics::bump(&self.shared.metrics.dropped_low_total);
+            return;
+        }
+        let was_empty = presence.is_empty();
+        presence.push((source.to_string(), line));
+        drop(presence);
+        if was_empty {
+            // Wake the writer if it is parked on an empty queue. If the
+            // queue is full it isn't, and it checks on its way out.
+            let _ = self.tx.try_send(WireLine::new());
+        }
+    }
+
+    /// Bytes queued and not yet taken by the writer.
+    pub fn queued_bytes(&self) -> usize {
+        self.shared.queued_bytes.load(Ordering::Relaxed)
+    }
+
+    /// How long the queue has been over [`BYTE_BUDGET`] without draining
+    /// back below [`LOW_PRIORITY_BUDGET`].
+    pub fn congested_for(&self) -> Option<Duration> {
+        self.shared.congested_since.lock().map(|t| t.elapsed())
+    }
+
+    /// Record that this queue's connection is being closed for staying
+    /// congested past [`CONGESTION_GRACE`].
+    pub fn record_congestion_quit(&self) {
+        QueueMetrics::bump(&self.shared.metrics.congestion_quits_total);
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+}
+
+impl QueueReceiver {
+    /// Next line to write: queued lines in order, then any coalesced
+    /// presence updates. `None` once every sender is gone and both are
+    /// empty.
+    pub async fn recv(&mut self) -> Option<WireLine> {
+        loop {
+            let line = match self.rx.try_recv() {
+                Ok(line) => line,
+                Err(TryRecvError::Empty) => {
+                    if let Some(line) = self.shared.take_presence() {
+                        return Some(line);
+                    }
+                    match self.rx.recv().await {
+                        Some(line) => line,
+                        None => return self.shared.take_presence(),
+                    }
+                }
+                Err(TryRecvError::Disconnected) => return self.shared.take_presence(),
+            };
+            // Empty lines are presence wake-ups, not traffic.
+            if !line.is_empty() {
+                self.shared.release(line.len());
+                return Some(line);
+            }
+        }
+    }
+
+    /// Like [`recv`](Self::recv) without waiting.
+    pub fn try_recv(&mut self) -> Result<WireLine, TryRecvError> {
+        loop {
+            match self.rx.try_recv() {
+                Ok(line) if line.is_empty() => continue,
+                Ok(line) => {
+                    self.shared.release(line.len());
+                    return Ok(line);
+                }
+                Err(e) => return self.shared.take_presence().ok_or(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(n: usize) -> WireLine {
+        WireLine::from(vec![b'x'; n])
+    }
+
+    #[test]
+    fn low_priority_is_dropped_first() {
+        let metrics = Arc::new(QueueMetrics::default());
+        let (tx, mut rx) = channel(QUEUE_LINES, metrics.clone());
+        for _ in 0..LOW_PRIORITY_BUDGET / 1024 {
+            tx.try_send(line(1024)).unwrap();
+        }
+        assert!(tx.try_send_low(line(10)).is_err());
+        assert!(tx.try_send(line(10)).is_ok());
+        assert_eq!(metrics.dropped_low_total.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.dropped_total.load(Ordering::Relaxed), 0);
+        assert!(tx.congested_for().is_none());
+
+        while rx.try_recv().is_ok() {}
+        assert_eq!(tx.queued_bytes(), 0);
+        assert!(tx.try_send_low(line(10)).is_ok());
+    }
+
+    #[test]
+    fn byte_budget_marks_congestion_until_drained() {
+        let metrics = Arc::new(QueueMetrics::default());
+        let (tx, mut rx) = channel(QUEUE_LINES, metrics.clone());
+        while tx.try_send(line(4096)).is_ok() {}
+        assert!(tx.queued_bytes() <= BYTE_BUDGET);
+        assert_eq!(metrics.dropped_total.load(Ordering::Relaxed), 1);
+        assert!(tx.congested_for().is_some());
+
+        while tx.queued_bytes() >= LOW_PRIORITY_BUDGET {
+            rx.try_recv().unwrap();
+        }
+        assert!(tx.congested_for().is_none());
+    }
+
+    #[tokio::test]
+    async fn presence_coalesces_per_source_while_backed_up() {
+        let metrics = Arc::new(QueueMetrics::default());
+        let (tx, mut rx) = channel(QUEUE_LINES, metrics.clone());
+        tx.send_presence("alice", WireLine::from_static(b"alice here\r\n"));
+        assert_eq!(rx.recv().await.unwrap(), "alice here\r\n");
+
+        for _ in 0..LOW_PRIORITY_BUDGET / 1024 {
+            tx.try_send(line(1024)).unwrap();
+        }
+        tx.send_presence("alice", WireLine::from_static(b"alice away 1\r\n"));
+        tx.send_presence("bob", WireLine::from_static(b"bob away\r\n"));
+        tx.send_presence("alice", WireLine::from_static(b"alice away 2\r\n"));
+        assert_eq!(metrics.coalesced_total.load(Ordering::Relaxed), 1);
+
+        let mut tail = Vec::new();
+        while let Ok(l) = rx.try_recv() {
+            if l.len() != 1024 {
+                tail.push(l);
+            }
+        }
+        assert_eq!(tail, ["alice away 2\r\n", "bob away\r\n"]);
+    }
+}
@@ -1,7 +1,7 @@
 //! Server state and TCP listener.
 
 use parking_lot::Mutex;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::SystemTime;
@@ -9,7 +9,6 @@ use std::time::SystemTime;
 use anyhow::{Context, Result};
 use freeq_sdk::did::DidResolver;
 use tokio::net::TcpListener;
-use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
 use tokio_rustls::TlsAcceptor;
 use tokio_rustls::rustls;
@@ -18,7 +17,11 @@ use crate::config::ServerConfig;
 use crate::connection;
 use crate::db::Db;
 use crate::plugin::PluginManager;
+use crate::policy::types::HistoryVisibility;
 use crate::sasl::ChallengeStore;
+use crate::send_queue::ClientQueue;
+use crate::session::Cap;
+use crate::sharded::ShardedMap;
 
 /// State for a single channel.
 #[derive(Debug, Clone, Default)]
@@ -29,10 +32,17 @@ pub struct ChannelState {
     pub remote_members: HashMap<String, RemoteMember>,
     /// Session IDs of channel operators (ephemeral, per-session).
     pub ops: HashSet<String>,
-    /// Session IDs of halfops/moderators (+h). Can kick/ban regular users, set +v.
+    /// Session IDs of halfops/moderators (+h). See [`ChannelRole::can`]
+    /// for what they may do.
     pub halfops: HashSet<String>,
     /// Session IDs of voiced users.
     pub voiced: HashSet<String>,
+    /// Session IDs authenticated as the founder (~). Always also in `ops`.
+    pub founders: HashSet<String>,
+    /// Session IDs of channel admins (&): signers in the channel policy's
+    /// authority set, or holders of the "admin" policy role. Always also
+    /// in `ops`.
+    pub admins: HashSet<String>,
 
     // ── DID-based persistent authority ──────────────────────────
     /// Channel founder's DID. Set once on channel creation.
@@ -58,10 +68,23 @@ pub struct ChannelState {
     /// requiring an explicit INVITE. Persistent (unlike `invites`, which
     /// are consumed on join).
     pub invite_exceptions: Vec<InviteExceptionEntry>,
+    /// Quiet list (+q): hostmasks/DIDs that may stay in the channel but
+    /// cannot send to it. Same shape and matching as a ban.
+    pub quiets: Vec<BanEntry>,
+    /// Channel mode: +B = bots restricted. Bots (see
+    /// [`SharedState::is_bot`]) may only send if they're on the allowlist.
+    pub bots_restricted: bool,
+    /// Bot allowlist (+W): hostmasks/DIDs of the bots that may speak under
+    /// +B. Same shape and matching as a ban.
+    pub bot_allowlist: Vec<BanEntry>,
     /// Recent message history for replay on join.
     pub history: std::collections::VecDeque<HistoryMessage>,
     /// Channel topic, if set.
     pub topic: Option<TopicInfo>,
+    /// Topics this channel had before the current one, oldest first,
+    /// bounded to [`TOPIC_HISTORY_MAX`]. Backs TOPICHIST and
+    /// `TOPIC <chan> --revert <n>`.
+    pub topic_history: std::collections::VecDeque<TopicInfo>,
     /// Channel modes: +t = only ops can set topic.
     pub topic_locked: bool,
     /// Channel mode: +n = no external messages (only members can send).
@@ -70,12 +93,65 @@ pub struct ChannelState {
     pub moderated: bool,
     /// Channel mode: +E = encrypted only (messages must have +encrypted tag).
     pub encrypted_only: bool,
+    /// Channel mode: +A = public archive. History is readable on the web
+    /// listener without joining (`/archive/{channel}`). Never served for
+    /// +E channels.
+    pub archived: bool,
+    /// Channel mode: +u = auditorium. JOIN/PART/QUIT of members without
+    /// voice are only shown to voiced-and-above members, and ordinary
+    /// members get a truncated NAMES roster. For very large channels.
+    pub auditorium: bool,
+    /// Channel mode: +H <visibility> — who may read stored history
+    /// (CHATHISTORY, SEARCH, the web API). Also settable from the policy.
+    pub history_visibility: HistoryVisibility,
+    /// Channel mode: +S = no statistics. Ops' activity rollups (see
+    /// `stats`) aren't collected, and setting it deletes stored ones.
+    pub no_stats: bool,
+    /// Channel mode: +s = secret. Left out of LIST, NAMES, WHO and WHOIS
+    /// for non-members and never advertised in the federation channel
+    /// directory.
+    pub secret: bool,
+    /// Channel mode: +T <ttl> = temporary. Purged with its history once
+    /// empty for the TTL (see `temporary`).
+    pub temporary: Option<crate::temporary::Temporary>,
+    /// When the temporary-channel sweep first saw the channel empty (unix
+    /// secs). `None` while it has members or isn't temporary.
+    pub empty_since: Option<u64>,
+    /// Whether the founder and DID-ops have been warned about the
+    /// current empty spell's expiry.
+    pub expiry_warned: bool,
+    /// When each local member joined (session ID → unix secs). Bounds
+    /// what a guest sees under `members-since-join`; DID accounts use
+    /// their persisted membership windows.
+    pub member_since: HashMap<String, u64>,
     /// Channel key (+k) — password required to join.
     pub key: Option<String>,
     /// Pinned message IDs (msgid strings), most recent first.
     pub pins: Vec<PinnedMessage>,
 }
 
+/// What stored history one reader may see in a channel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HistoryAccess {
+    /// All of it.
+    Full,
+    /// Only messages sent within these `(from, until)` spans, unix secs;
+    /// `until` is `None` for a span still open.
+    Windows(Vec<(u64, Option<u64>)>),
+}
+
+impl HistoryAccess {
+    /// Whether a message sent at `ts` is visible.
+    pub fn allows(&self, ts: u64) -> bool {
+        match self {
+            HistoryAccess::Full => true,
+            HistoryAccess::Windows(spans) => spans
+                .iter()
+                .any(|&(from, until)| ts >= from && until.is_none_or(|u| ts <= u)),
+        }
+    }
+}
+
 /// A pinned message reference.
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct PinnedMessage {
@@ -88,40 +164,163 @@ pub struct PinnedMessage {
 }
 
 impl ChannelState {
+    /// The roles `session_id` holds in this channel, highest first.
+    pub fn roles(&self, session_id: &str) -> Vec<ChannelRole> {
+        let mut roles = Vec::new();
+        if self.founders.contains(session_id) {
+            roles.push(ChannelRole::Founder);
+        }
+        if self.admins.contains(session_id) {
+            roles.push(ChannelRole::Admin);
+        }
+        if self.ops.contains(session_id) {
+            roles.push(ChannelRole::Op);
+        }
+        if self.halfops.contains(session_id) {
+            roles.push(ChannelRole::Halfop);
+        }
+        if self.voiced.contains(session_id) {
+            roles.push(ChannelRole::Voice);
+        }
+        roles
+    }
+
+    /// The highest role `session_id` holds in this channel.
+    pub fn rank(&self, session_id: &str) -> ChannelRole {
+        self.roles(session_id)
+            .first()
+            .copied()
+            .unwrap_or(ChannelRole::Member)
+    }
+
+    /// The highest role of a remote member. Only founder and op status
+    /// travel over S2S.
+    pub fn remote_rank(&self, rm: &RemoteMember) -> ChannelRole {
+        let did = rm.did.as_deref();
+        if did.is_some_and(|d| self.founder_did.as_deref() == Some(d)) {
+            ChannelRole::Founder
+        } else if rm.is_op || did.is_some_and(|d| self.did_ops.contains(d)) {
+            ChannelRole::Op
+        } else {
+            ChannelRole::Member
+        }
+    }
+
+    /// Re-derive `founders` from `founder_did`, given session → DID, after
+    /// the founder changed under us (CRDT reconciliation, S2S sync).
+    pub fn refresh_founders(&mut self, session_dids: &HashMap<String, String>) {
+        let founder = self.founder_did.clone();
+        let is_founder =
+            |sid: &String| founder.is_some() && session_dids.get(sid) == founder.as_ref();
+        self.founders = self.members.iter().filter(|&s| is_founder(s)).cloned().collect();
+        for sid in &self.founders {
+            self.ops.insert(sid.clone());
+        }
+    }
+
+    /// NAMES/WHO prefix for `session_id`: the highest role's symbol, or
+    /// every symbol in order with `multi-prefix`.
+    pub fn prefix(&self, session_id: &str, multi_prefix: bool) -> String {
+        let symbols = self.roles(session_id).into_iter().filter_map(ChannelRole::prefix);
+        if multi_prefix {
+            symbols.collect()
+        } else {
+            symbols.take(1).collect()
+        }
+    }
+
+    /// Whether `session_id` sees every member of the channel. In +u only
+    /// voiced-and-above members do; everyone else sees just them.
+    pub fn sees_all_members(&self, session_id: &str) -> bool {
+        !self.auditorium || self.rank(session_id) >= ChannelRole::Voice
+    }
+
+    /// Whether the channel is +s and `session_id` isn't in it.
+    pub fn hidden_from(&self, session_id: &str) -> bool {
+        self.secret && !self.members.contains(session_id)
+    }
+
+    /// Local members who should see JOIN/PART/QUIT for `session_id`,
+    /// including `session_id` itself.
+    pub fn audience(&self, session_id: &str) -> Vec<String> {
+        self.audience_for(self.rank(session_id), Some(session_id))
+    }
+
+    /// Local members who should see JOIN/PART/QUIT for a remote member.
+    pub fn remote_audience(&self, rm: &RemoteMember) -> Vec<String> {
+        self.audience_for(self.remote_rank(rm), None)
+    }
+
+    fn audience_for(&self, rank: ChannelRole, subject: Option<&str>) -> Vec<String> {
+        let hidden = self.auditorium && rank < ChannelRole::Voice;
+        self.members
+            .iter()
+            .filter(|s| !hidden || subject == Some(s.as_str()) || self.sees_all_members(s))
+            .cloned()
+            .collect()
+    }
+
+    /// Replace the topic, moving the old one onto `topic_history`.
+    /// Returns true if the history changed (i.e. there was a different
+    /// topic to displace), so callers know whether to persist it.
+    pub fn set_topic(&mut self, topic: TopicInfo) -> bool {
+        let prev = self.topic.replace(topic);
+        match prev {
+            Some(prev) if self.topic.as_ref().is_some_and(|t| t.text != prev.text) => {
+                self.topic_history.push_back(prev);
+                while self.topic_history.len() > TOPIC_HISTORY_MAX {
+                    self.topic_history.pop_front();
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The `n`th most recent previous topic (1 = the one just before the
+    /// current topic), as numbered by TOPICHIST.
+    pub fn previous_topic(&self, n: usize) -> Option<&TopicInfo> {
+        let len = self.topic_history.len();
+        if n == 0 || n > len {
+            return None;
+        }
+        self.topic_history.get(len - n)
+    }
+
     /// Case-insensitive lookup in remote_members.
     /// IRC nicks are case-insensitive, but HashMap keys preserve original case.
     pub fn remote_member(&self, nick: &str) -> Option<&RemoteMember> {
-        let lower = nick.to_lowercase();
+        let lower = crate::casemap::fold(nick);
         self.remote_members
             .iter()
-            .find(|(k, _)| k.to_lowercase() == lower)
+            .find(|(k, _)| crate::casemap::fold(k) == lower)
             .map(|(_, v)| v)
     }
 
     /// Case-insensitive mutable lookup in remote_members.
     pub fn remote_member_mut(&mut self, nick: &str) -> Option<&mut RemoteMember> {
-        let lower = nick.to_lowercase();
+        let lower = crate::casemap::fold(nick);
         self.remote_members
             .iter_mut()
-            .find(|(k, _)| k.to_lowercase() == lower)
+            .find(|(k, _)| crate::casemap::fold(k) == lower)
             .map(|(_, v)| v)
     }
 
     /// Case-insensitive check if nick is in remote_members.
     pub fn has_remote_member(&self, nick: &str) -> bool {
-        let lower = nick.to_lowercase();
+        let lower = crate::casemap::fold(nick);
         self.remote_members
             .keys()
-            .any(|k| k.to_lowercase() == lower)
+            .any(|k| crate::casemap::fold(k) == lower)
     }
 
     /// Case-insensitive removal from remote_members. Returns the removed entry.
     pub fn remove_remote_member(&mut self, nick: &str) -> Option<RemoteMember> {
-        let lower = nick.to_lowercase();
+        let lower = crate::casemap::fold(nick);
         let key = self
             .remote_members
             .keys()
-            .find(|k| k.to_lowercase() == lower)
+            .find(|k| crate::casemap::fold(k) == lower)
             .cloned();
         key.and_then(|k| self.remote_members.remove(&k))
     }
@@ -177,14 +376,29 @@ pub struct LinkedIdentity {
     pub linked_at: u64,
 }
 
+/// An iroh endpoint that proved itself by completing SASL over iroh: the
+/// challenge it signed carried its endpoint ID as channel binding.
+#[derive(Debug, Clone)]
+pub struct IrohBinding {
+    pub did: String,
+    /// Unix seconds.
+    pub bound_at: i64,
+}
+
 /// Active web session with credentials for PDS operations (e.g., media upload).
 /// Keyed by `(DID, purpose)` in SharedState.web_sessions where `purpose` is
 /// [`OauthPurpose`]. The default `Login` session is the one created at first
 /// login (narrow scope: `atproto`); additional purposes are created by the
 /// step-up flow at `/auth/step-up?purpose=…` with broader scopes layered on
 /// only when the user actually triggers a feature that needs them.
+///
+/// Sessions expire after [`WEB_SESSION_IDLE_TTL`] without use (every lookup
+/// through [`SharedState::web_session`] renews them) and unconditionally
+/// after [`WEB_SESSION_MAX_AGE`].
 #[derive(Debug, Clone)]
 pub struct WebSession {
+    /// Short opaque ID shown by the `SESSIONS` command and used to revoke.
+    pub id: String,
     pub did: String,
     pub handle: String,
     pub pds_url: String,
@@ -192,6 +406,8 @@ pub struct WebSession {
     pub dpop_key_b64: String,
     pub dpop_nonce: Option<String>,
     pub created_at: std::time::Instant,
+    /// Last time the session was used for a server-proxied PDS call.
+    pub last_used: std::time::Instant,
     /// The actual scope string the PDS granted (read from the token-endpoint
     /// `scope` field). May differ from what we requested — older PDSes may
     /// downgrade granular requests to `transition:generic`. Used by per-purpose
@@ -199,6 +415,91 @@ pub struct WebSession {
     pub granted_scope: String,
 }
 
+/// A device logged in through the auth broker, as reported by the
+/// broker's session webhooks (`POST /auth/broker/events`). Keyed by the
+/// broker's session ID in SharedState.broker_devices and listed by
+/// `SESSIONS`. Times are unix seconds as reported by the broker.
+#[derive(Debug, Clone)]
+pub struct BrokerDevice {
+    pub did: String,
+    /// The User-Agent the broker saw, if any.
+    pub device: Option<String>,
+    pub created_at: i64,
+    pub last_seen: i64,
+    /// ID of the [`WebSession`] last pushed for this device; revoking the
+    /// device drops it.
+    pub web_session: Option<String>,
+}
+
+/// A broker device not heard from for this long is forgotten.
+pub const BROKER_DEVICE_TTL: i64 = 30 * 86400;
+/// Broker devices tracked per DID. Beyond this the least recently seen
+/// is forgotten.
+pub const MAX_BROKER_DEVICES_PER_DID: usize = 20;
+
+/// A web session unused for this long is dropped.
+pub const WEB_SESSION_IDLE_TTL: std::time::Duration = std::time::Duration::from_secs(24 * 3600);
+/// A web session older than this is dropped even if in active use — the
+/// client must log in again.
+pub const WEB_SESSION_MAX_AGE: std::time::Duration = std::time::Duration::from_secs(7 * 24 * 3600);
+/// One-time SASL web-auth tokens must be redeemed within this window.
+pub const WEB_AUTH_TOKEN_TTL: std::time::Duration = std::time::Duration::from_secs(300);
+/// Outstanding (unredeemed) web-auth tokens per DID. Minting beyond this
+/// evicts the oldest, so a misbehaving client looping on `/session` can't
+/// stockpile valid tokens.
+pub const MAX_WEB_AUTH_TOKENS_PER_DID: usize = 5;
+
+/// Why [`SharedState::take_sasl_challenge`] refused a challenge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChallengeRejection {
+    /// No pending challenge for the session, or it expired.
+    Missing,
+    /// The response reported a channel binding other than the
+    /// connection's own: it was signed for another connection.
+    BindingMismatch,
+}
+
+impl WebSession {
+    pub fn is_expired(&self) -> bool {
+        self.last_used.elapsed() >= WEB_SESSION_IDLE_TTL
+            || self.created_at.elapsed() >= WEB_SESSION_MAX_AGE
+    }
+}
+
+/// One-time token for SASL `web-token` auth, minted after a completed
+/// OAuth flow and consumed on first use.
+#[derive(Debug, Clone)]
+pub struct WebAuthToken {
+    /// Short opaque ID shown by the `SESSIONS` command (never the token itself).
+    pub id: String,
+    pub did: String,
+    pub handle: String,
+    pub created_at: std::time::Instant,
+    /// When set, only a connection presenting the same binding can redeem
+    /// the token — a token lifted from a log or URL is useless elsewhere.
+    pub binding: Option<TokenBinding>,
+}
+
+/// What a [`WebAuthToken`] is bound to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenBinding {
+    /// Client-chosen device fingerprint, echoed back in the SASL response's
+    /// `fingerprint` field.
+    Fingerprint(String),
+    /// Iroh endpoint ID. The redeeming connection must arrive over iroh
+    /// from this endpoint (authenticated by the QUIC handshake).
+    IrohEndpoint(String),
+}
+
+impl std::fmt::Display for TokenBinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenBinding::Fingerprint(fp) => write!(f, "fingerprint:{fp}"),
+            TokenBinding::IrohEndpoint(id) => write!(f, "iroh:{id}"),
+        }
+    }
+}
+
 /// Distinguishes which OAuth grant a [`WebSession`] is for. Each purpose has
 /// its own scope set and lives in its own slot, so escalating to a broader
 /// permission (e.g. blob upload) only happens when the user actually triggers
@@ -326,9 +627,137 @@ pub struct HistoryMessage {
     pub msgid: Option<String>,
 }
 
+/// IRC-visible channel roles, lowest to highest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ChannelRole {
+    Member,
+    Voice,
+    Halfop,
+    Op,
+    Admin,
+    Founder,
+}
+
+/// The `PREFIX` ISUPPORT token. Founder (`F`) and admin (`a`) are derived
+/// from channel authority, not set with MODE; the letters only name them.
+pub const ISUPPORT_PREFIX: &str = "PREFIX=(Faohv)~&@%+";
+
+impl ChannelRole {
+    /// The NAMES/WHO prefix symbol, if the role has one.
+    pub fn prefix(self) -> Option<char> {
+        match self {
+            ChannelRole::Founder => Some('~'),
+            ChannelRole::Admin => Some('&'),
+            ChannelRole::Op => Some('@'),
+            ChannelRole::Halfop => Some('%'),
+            ChannelRole::Voice => Some('+'),
+            ChannelRole::Member => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChannelRole::Founder => "founder",
+            ChannelRole::Admin => "admin",
+            ChannelRole::Op => "op",
+            ChannelRole::Halfop => "halfop",
+            ChannelRole::Voice => "voice",
+            ChannelRole::Member => "member",
+        }
+    }
+
+    /// The channel permission matrix. Halfops moderate members (topic
+    /// under +t, kicks, bans/quiets, voice); ops and above do everything.
+    /// Server operators bypass it.
+    pub fn can(self, privilege: ChannelPrivilege) -> bool {
+        use ChannelPrivilege::*;
+        match self {
+            ChannelRole::Member | ChannelRole::Voice => false,
+            ChannelRole::Halfop => matches!(privilege, Topic | Kick | Lists | Voice),
+            ChannelRole::Op | ChannelRole::Admin | ChannelRole::Founder => true,
+        }
+    }
+
+    /// Whether this role may kick, or take status from, a member whose
+    /// highest role is `target`: halfops only reach plain and voiced
+    /// members, ops reach other ops, admins and founders their peers.
+    pub fn outranks(self, target: ChannelRole) -> bool {
+        match self {
+            ChannelRole::Member | ChannelRole::Voice => false,
+            ChannelRole::Halfop => target < ChannelRole::Halfop,
+            ChannelRole::Op => target <= ChannelRole::Op,
+            ChannelRole::Admin | ChannelRole::Founder => target <= self,
+        }
+    }
+}
+
+/// Something a channel role may be allowed to do; see [`ChannelRole::can`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelPrivilege {
+    /// Set the topic while the channel is +t (or revert it).
+    Topic,
+    /// Kick members the kicker [outranks](ChannelRole::outranks).
+    Kick,
+    /// Edit the ban, quiet and invite-exception lists (+b/+q/+I).
+    Lists,
+    /// Give or take voice (+v).
+    Voice,
+    /// Invite into a +i channel.
+    Invite,
+    /// Give or take halfop (+h).
+    Halfop,
+    /// Give or take op (+o).
+    Op,
+    /// Change channel settings (+t/+i/+k/+n/+m and the other flag modes).
+    Settings,
+}
+
+impl ChannelPrivilege {
+    pub const ALL: [ChannelPrivilege; 8] = [
+        ChannelPrivilege::Topic,
+        ChannelPrivilege::Kick,
+        ChannelPrivilege::Lists,
+        ChannelPrivilege::Voice,
+        ChannelPrivilege::Invite,
+        ChannelPrivilege::Halfop,
+        ChannelPrivilege::Op,
+        ChannelPrivilege::Settings,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChannelPrivilege::Topic => "topic",
+            ChannelPrivilege::Kick => "kick",
+            ChannelPrivilege::Lists => "lists",
+            ChannelPrivilege::Voice => "voice",
+            ChannelPrivilege::Invite => "invite",
+            ChannelPrivilege::Halfop => "halfop",
+            ChannelPrivilege::Op => "op",
+            ChannelPrivilege::Settings => "settings",
+        }
+    }
+
+    /// The privilege a MODE letter needs.
+    pub fn for_mode(mode: char) -> Self {
+        match mode {
+            'v' => ChannelPrivilege::Voice,
+            'h' => ChannelPrivilege::Halfop,
+            'o' => ChannelPrivilege::Op,
+            'b' | 'q' | 'I' => ChannelPrivilege::Lists,
+            _ => ChannelPrivilege::Settings,
+        }
+    }
+}
+
 /// Maximum number of history messages to keep per channel.
 pub const MAX_HISTORY: usize = 100;
 
+/// Maximum number of previous topics kept per channel.
+pub const TOPIC_HISTORY_MAX: usize = 20;
+
+/// Maximum number of +W entries per channel, local or from a peer.
+pub const MAX_BOT_ALLOWLIST_PER_CHANNEL: usize = 500;
+
 /// A ban entry — can be a traditional hostmask or a DID.
 #[derive(Debug, Clone)]
 pub struct BanEntry {
@@ -367,9 +796,9 @@ impl BanEntry {
 }
 
 /// Simple wildcard matching (* and ?).
-fn wildcard_match(pattern: &str, text: &str) -> bool {
-    let pattern = pattern.to_lowercase();
-    let text = text.to_lowercase();
+pub(crate) fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern = crate::casemap::fold(pattern);
+    let text = crate::casemap::fold(text);
     wildcard_match_inner(pattern.as_bytes(), text.as_bytes())
 }
 
@@ -400,6 +829,92 @@ impl ChannelState {
             .iter()
             .any(|e| e.matches(hostmask, did))
     }
+
+    /// What stored history a reader may see here; `None` for nothing.
+    /// Banned readers see nothing. `windows` are a DID reader's
+    /// membership spans (see [`crate::db::Db::membership_windows`]),
+    /// only consulted under `members-since-join`.
+    pub fn history_access(
+        &self,
+        session_id: &str,
+        hostmask: &str,
+        did: Option<&str>,
+        mut windows: Vec<(u64, Option<u64>)>,
+    ) -> Option<HistoryAccess> {
+        if self.is_banned(hostmask, did) {
+            return None;
+        }
+        let member = self.members.contains(session_id);
+        match self.history_visibility {
+            HistoryVisibility::Public => Some(HistoryAccess::Full),
+            HistoryVisibility::MembersFull => member.then_some(HistoryAccess::Full),
+            HistoryVisibility::MembersSinceJoin => {
+                if !member {
+                    return None;
+                }
+                if let Some(&since) = self.member_since.get(session_id) {
+                    windows.push((since, None));
+                }
+                Some(HistoryAccess::Windows(windows))
+            }
+        }
+    }
+
+    /// Whether the unauthenticated web API may serve this channel's
+    /// history: always under `public`, never under `members-since-join`,
+    /// and under `members-full` only without +i/+k.
+    pub fn history_readable_anonymously(&self) -> bool {
+        match self.history_visibility {
+            HistoryVisibility::Public => true,
+            HistoryVisibility::MembersSinceJoin => false,
+            HistoryVisibility::MembersFull => !self.invite_only && self.key.is_none(),
+        }
+    }
+
+    /// Check if a user is on the +q quiet list. Ops and halfops are never
+    /// silenced; callers check that separately.
+    pub fn is_quieted(&self, hostmask: &str, did: Option<&str>) -> bool {
+        self.quiets.iter().any(|q| q.matches(hostmask, did))
+    }
+
+    /// Whether a bot may send here: always without +B, otherwise only if
+    /// it's on the +W allowlist. Ops and halfops are never restricted;
+    /// callers check that separately.
+    pub fn bot_may_send(&self, hostmask: &str, did: Option<&str>) -> bool {
+        !self.bots_restricted || self.bot_allowlist.iter().any(|w| w.matches(hostmask, did))
+    }
+}
+
+/// Whether `did` holds a moderator appointment for `channel` — a
+/// `channel_moderator` credential from the moderation verifier, which the
+/// channel policy maps to the "moderator" role. Such users get +h on join;
+/// this lets their +q changes be honoured even where halfop status isn't
+/// known (S2S) or hasn't been applied yet.
+pub(crate) fn is_credentialed_moderator(state: &SharedState, channel: &str, did: &str) -> bool {
+    state.policy_engine.as_ref().is_some_and(|engine| {
+        matches!(
+            engine.get_member_role(channel, did),
+            Ok(Some(role)) if role == "moderator" || role == "halfop"
+        )
+    })
+}
+
+/// Whether `did` is a channel admin: a signer in the authority set of the
+/// channel's current policy, or a holder of the "admin" policy role.
+pub(crate) fn is_channel_admin(state: &SharedState, channel: &str, did: &str) -> bool {
+    let Some(engine) = state.policy_engine.as_ref() else {
+        return false;
+    };
+    if matches!(engine.get_member_role(channel, did), Ok(Some(role)) if role == "admin") {
+        return true;
+    }
+    let Ok(Some(policy)) = engine.get_policy(channel) else {
+        return false;
+    };
+    matches!(
+        engine.store().get_authority_set(&policy.authority_set_hash),
+        Ok(Some(set)) if set.signers.iter().any(|s| s.did == did)
+    )
 }
 
 /// An entry on the +I (invite-exception) list — same shape as a BanEntry,
@@ -483,10 +998,10 @@ impl NickMap {
     /// The nick→sid mapping points to the most recent session, but all
     /// sessions are tracked in sid→nick for NAMES resolution.
     pub fn insert(&mut self, display_nick: &str, session_id: &str) {
-        let lower = display_nick.to_lowercase();
+        let lower = crate::casemap::fold(display_nick);
         // Remove old mapping for this session if it had a different nick
         if let Some(old_nick) = self.sid_to_nick.remove(session_id) {
-            let old_lower = old_nick.to_lowercase();
+            let old_lower = crate::casemap::fold(&old_nick);
             if old_lower != lower {
                 // Only remove nick→sid if this session was the primary for that old nick
                 if self.nick_to_sid.get(&old_lower).map(|s| s.as_str()) == Some(session_id) {
@@ -505,7 +1020,7 @@ impl NickMap {
     /// Returns the primary (most recently inserted) session for this nick.
     pub fn get_session(&self, nick: &str) -> Option<&str> {
         self.nick_to_sid
-            .get(&nick.to_lowercase())
+            .get(&crate::casemap::fold(nick))
             .map(|s| s.as_str())
     }
 
@@ -516,22 +1031,23 @@ impl NickMap {
 
     /// Check if a nick is in use (case-insensitive).
     pub fn contains_nick(&self, nick: &str) -> bool {
-        self.nick_to_sid.contains_key(&nick.to_lowercase())
+        self.nick_to_sid.contains_key(&crate::casemap::fold(nick))
     }
 
     /// Remove by nick (case-insensitive). Returns the primary session_id if found.
     /// Also removes ALL sid→nick entries for sessions that had this nick.
     pub fn remove_by_nick(&mut self, nick: &str) -> Option<String> {
-        let lower = nick.to_lowercase();
+        let lower = crate::casemap::fold(nick);
         // Remove all sid→nick entries pointing to this nick
-        self.sid_to_nick.retain(|_, n| n.to_lowercase() != lower);
+        self.sid_to_nick
+            .retain(|_, n| crate::casemap::fold(n) != lower);
         self.nick_to_sid.remove(&lower)
     }
 
     /// Remove by session_id. Returns the display nick if found.
     pub fn remove_by_session(&mut self, session_id: &str) -> Option<String> {
         if let Some(nick) = self.sid_to_nick.remove(session_id) {
-            let lower = nick.to_lowercase();
+            let lower = crate::casemap::fold(&nick);
             // Only remove nick→sid if this session was the primary
             if self.nick_to_sid.get(&lower).map(|s| s.as_str()) == Some(session_id) {
                 self.nick_to_sid.remove(&lower);
@@ -539,7 +1055,7 @@ impl NickMap {
                 if let Some((other_sid, _)) = self
                     .sid_to_nick
                     .iter()
-                    .find(|(_, n)| n.to_lowercase() == lower)
+                    .find(|(_, n)| crate::casemap::fold(n) == lower)
                 {
                     self.nick_to_sid.insert(lower, other_sid.clone());
                 }
@@ -570,17 +1086,40 @@ impl NickMap {
     /// Check if a nick is held by a specific session.
     pub fn nick_belongs_to(&self, nick: &str, session_id: &str) -> bool {
         self.nick_to_sid
-            .get(&nick.to_lowercase())
+            .get(&crate::casemap::fold(nick))
             .is_some_and(|sid| sid == session_id)
     }
 }
 
+/// One outbound wire line, CRLF included. Built once and shared: every
+/// recipient's queue holds a refcounted view of the same buffer, so a
+/// channel fan-out costs no per-member copy.
+pub type WireLine = bytes::Bytes;
+
+/// Process-wide server state, shared by every connection task.
+///
+/// # Lock order
+///
+/// Take locks in this order, never the reverse:
+///
+/// 1. one [`channels`](Self::channels) shard;
+/// 2. the per-session tables (`nick_to_session`, `session_dids`,
+///    [`sessions`](Self::sessions) and the rest), each held briefly;
+/// 3. one [`connections`](Self::connections) shard, only to queue a line.
+///
+/// Hold at most one shard of a [`ShardedMap`] at a time. A fan-out copies
+/// the member list out of the channel first and then looks up each
+/// member's sender on its own.
 pub struct SharedState {
     pub server_name: String,
     pub challenge_store: ChallengeStore,
+    /// Failed authentication attempts per source (peer IP or iroh
+    /// endpoint), over a sliding [`AUTH_FAILURE_WINDOW`]. Input for
+    /// server-level bans; see [`SharedState::record_auth_failure`].
+    pub auth_failures: Mutex<HashMap<String, AuthFailures>>,
     pub did_resolver: DidResolver,
-    /// session_id -> sender for writing lines to that client
-    pub connections: Mutex<HashMap<String, mpsc::Sender<String>>>,
+    /// session_id -> outbound queue for writing lines to that client
+    pub connections: ShardedMap<ClientQueue>,
     /// nick -> session_id (case-insensitive: keys are always lowercase)
     pub nick_to_session: Mutex<NickMap>,
     /// session_id -> authenticated DID (for WHOIS lookups by other connections)
@@ -593,25 +1132,18 @@ pub struct SharedState {
     pub did_nicks: Mutex<HashMap<String, String>>,
     /// nick -> DID (reverse lookup for nick enforcement).
     pub nick_owners: Mutex<HashMap<String, String>>,
+    /// Confusable skeleton -> owned nicks with it, kept alongside
+    /// `nick_owners` so look-alike checks don't scan every owned nick.
+    /// May hold nicks that have since lost their owner; readers check
+    /// `nick_owners`.
+    pub nick_skeletons: Mutex<HashMap<String, HashSet<String>>>,
     /// session_id -> resolved Bluesky handle (for WHOIS display).
     pub session_handles: Mutex<HashMap<String, String>>,
     /// channel name -> channel state (keys are always lowercase)
-    pub channels: Mutex<HashMap<String, ChannelState>>,
-    /// Sessions that have negotiated message-tags capability.
-    pub cap_message_tags: Mutex<HashSet<String>>,
-    /// Sessions that have negotiated multi-prefix capability.
-    pub cap_multi_prefix: Mutex<HashSet<String>>,
-    /// Sessions that have negotiated echo-message capability.
-    pub cap_echo_message: Mutex<HashSet<String>>,
-    /// Sessions that have negotiated server-time capability.
-    pub cap_server_time: Mutex<HashSet<String>>,
-    /// Sessions that have negotiated batch capability.
-    pub cap_batch: Mutex<HashSet<String>>,
-    /// Sessions that have negotiated the `draft/multiline` capability —
-    /// they can send and receive logical messages split across multiple
-    /// PRIVMSG/NOTICE lines via BATCH frames. See
-    /// https://ircv3.net/specs/extensions/multiline.
-    pub cap_draft_multiline: Mutex<HashSet<String>>,
+    pub channels: ShardedMap<ChannelState>,
+    /// Per-session entries (negotiated capabilities) that fan-out code
+    /// reads for every recipient.
+    pub sessions: crate::session::SessionRegistry,
     /// In-flight BATCH frames per session. Keyed by `(session_id,
     /// batch_id)`. Populated when a client sends `BATCH +<id> <type>
     /// <target>`, drained when it sends `BATCH -<id>`. PRIVMSG/NOTICE
@@ -620,14 +1152,18 @@ pub struct SharedState {
     /// on disconnect.
     pub open_batches:
         Mutex<HashMap<(String, String), crate::connection::draft_multiline::OpenBatch>>,
-    pub cap_account_notify: Mutex<HashSet<String>>,
-    pub cap_extended_join: Mutex<HashSet<String>>,
-    pub cap_away_notify: Mutex<HashSet<String>>,
-    /// Sessions that have negotiated account-tag capability (IRCv3).
-    /// When set, outbound PRIVMSG/NOTICE includes `account=<did>` if sender is authenticated.
-    pub cap_account_tag: Mutex<HashSet<String>>,
+    /// draft/metadata-2 key/value pairs by owner: a DID, a guest session ID,
+    /// or a (folded) channel name. See `connection::metadata`.
+    pub metadata: Mutex<HashMap<String, BTreeMap<String, String>>>,
+    /// draft/metadata-2 key subscriptions per session.
+    pub metadata_subs: Mutex<HashMap<String, BTreeSet<String>>>,
     /// Sessions that have OPER (server operator) status.
     pub server_opers: Mutex<HashSet<String>>,
+    /// Credentials for the oper-only HTTP endpoints: token -> the oper
+    /// session it was issued to. See [`SharedState::issue_oper_token`].
+    pub oper_tokens: Mutex<HashMap<String, String>>,
+    /// Observability event stream behind the oper-only `/firehose`.
+    pub firehose: crate::firehose::Firehose,
     /// Actor class per session (default: Human, omitted from map).
     pub session_actor_class: Mutex<HashMap<String, crate::connection::ActorClass>>,
     /// Provenance declarations: DID → provenance JSON.
@@ -645,9 +1181,9 @@ pub struct SharedState {
     pub oauth_pending: Mutex<HashMap<String, OAuthPending>>,
     /// Completed OAuth sessions: state → OAuthResult.
     pub oauth_complete: Mutex<HashMap<String, OAuthResult>>,
-    /// One-time web auth tokens: token → (DID, handle, created_at).
+    /// One-time web auth tokens: token → [`WebAuthToken`].
     /// Generated during OAuth callback, consumed during SASL.
-    pub web_auth_tokens: Mutex<HashMap<String, (String, String, std::time::Instant)>>,
+    pub web_auth_tokens: Mutex<HashMap<String, WebAuthToken>>,
     /// Active web sessions with PDS credentials, keyed by DID.
     /// Used for server-proxied operations like media upload.
     /// Active web sessions keyed by `(DID, purpose)`. Each entry holds an
@@ -655,6 +1191,9 @@ pub struct SharedState {
     /// has two PDS-level tokens, with the upload one only obtained when
     /// they actually clicked an upload button. See [`OauthPurpose`].
     pub web_sessions: Mutex<HashMap<(String, OauthPurpose), WebSession>>,
+    /// Devices logged in through the auth broker: broker session ID →
+    /// [`BrokerDevice`].
+    pub broker_devices: Mutex<HashMap<String, BrokerDevice>>,
     /// Pending IRC LOGIN commands: oauth_state → session_id.
     /// When the OAuth callback fires, the server completes auth on the IRC connection.
     pub login_pending: Mutex<HashMap<String, String>>,
@@ -666,6 +1205,21 @@ pub struct SharedState {
     pub login_completions: Mutex<HashMap<String, crate::connection::login::LoginCompletion>>,
     /// session_id -> iroh endpoint ID (for connections via iroh transport).
     pub session_iroh_ids: Mutex<HashMap<String, String>>,
+    /// iroh endpoint ID -> the DID it is bound to. Persisted; lets a
+    /// bound endpoint RESUME without SASL and drives iroh gating.
+    pub iroh_bindings: Mutex<HashMap<String, IrohBinding>>,
+    /// Content filter rules, global and per channel (see [`crate::filters`]).
+    /// Persisted; managed with FILTER.
+    pub content_filters: Mutex<crate::filters::Filters>,
+    /// Channel invite links (see [`crate::invites`]). Persisted; managed
+    /// with INVITELINK.
+    pub invites: Mutex<crate::invites::Invites>,
+    /// Email notifications for offline users (see [`crate::email`]).
+    /// Subscriptions persisted; managed with EMAIL.
+    pub email: crate::email::EmailNotifier,
+    /// Per-DID preferences (see [`crate::prefs`]). Persisted; managed
+    /// with PREF and `/api/v1/me/prefs`.
+    pub prefs: Mutex<crate::prefs::Preferences>,
     /// session_id -> away message (None = not away).
     pub session_away: Mutex<HashMap<String, String>>,
     /// This server's own iroh endpoint ID (advertised in CAP LS).
@@ -695,6 +1249,10 @@ pub struct SharedState {
     pub config: ServerConfig,
     /// Plugin manager for server extensions.
     pub plugin_manager: PluginManager,
+    /// Command aliases (`NS CLAIM`, `CS OP`, …) applied before dispatch.
+    pub command_aliases: crate::alias::AliasTable,
+    /// Message catalogs for LANGUAGE.
+    pub locales: crate::locale::Locales,
     /// Policy engine for channel governance (if enabled).
     pub policy_engine: Option<Arc<crate::policy::PolicyEngine>>,
     /// E2EE pre-key bundles: DID → PreKeyBundle JSON.
@@ -703,6 +1261,12 @@ pub struct SharedState {
     /// Per-session message timestamps for channel flood protection.
     /// Key: session_id, Value: ring buffer of recent message timestamps.
     pub msg_timestamps: Mutex<HashMap<String, Vec<u64>>>,
+    /// Typing/status TAGMSG rate limits and busy-channel queues.
+    pub ephemeral: crate::ephemeral::Ephemeral,
+    /// Today's per-channel activity counters, flushed to the rollups.
+    pub channel_stats: crate::stats::ChannelStats,
+    /// S2S peers' channel summaries, merged into LIST.
+    pub channel_directory: crate::directory::ChannelDirectory,
     /// Per-IP active connection count (for connection limiting).
     pub ip_connections: Mutex<HashMap<std::net::IpAddr, u32>>,
     /// Ed25519 signing key for server-attested message signatures.
@@ -719,6 +1283,12 @@ pub struct SharedState {
     pub did_msg_keys: Mutex<HashMap<String, String>>,
     /// session_id → client software identifier (from USER realname).
     pub session_client_info: Mutex<HashMap<String, String>>,
+    /// session_id → signon time, last message and remote IP, for WHOIS
+    /// 317 (idle) and 338 (actual host).
+    pub session_activity: Mutex<HashMap<String, SessionActivity>>,
+    /// "channel source" → last founder proof attempted with RECLAIM, for
+    /// the cooldown.
+    pub reclaim_attempts: Mutex<HashMap<String, std::time::Instant>>,
     /// Upload tokens: token → (DID, created_at). Short-lived proof of upload authorization.
     pub upload_tokens: Mutex<HashMap<String, (String, std::time::Instant)>>,
     /// Ghost sessions: DID users who disconnected recently.
@@ -743,6 +1313,11 @@ pub struct SharedState {
     pub session_kill: Mutex<HashMap<String, Arc<tokio::sync::Notify>>>,
     /// Process-lifetime counters exposed at /metrics.
     pub metrics: Metrics,
+    /// Whether the client listeners are bound and accepting, for
+    /// `/readyz`. Cleared when this server is taken over.
+    pub listening: std::sync::atomic::AtomicBool,
+    /// Whether a BACKUP is running (see [`crate::backup`]); one at a time.
+    pub backup_running: std::sync::atomic::AtomicBool,
 }
 
 /// Process-lifetime counters for the Prometheus /metrics endpoint.
@@ -752,6 +1327,10 @@ pub struct Metrics {
     pub messages_total: std::sync::atomic::AtomicU64,
     pub sasl_success_total: std::sync::atomic::AtomicU64,
     pub sasl_failure_total: std::sync::atomic::AtomicU64,
+    /// Client lines dropped by the parser's quarantine.
+    pub malformed_lines_total: std::sync::atomic::AtomicU64,
+    /// Outbound queue drops, coalescing and congestion disconnects.
+    pub send_queue: Arc<crate::send_queue::QueueMetrics>,
     pub started_at: std::time::Instant,
 }
 
@@ -761,6 +1340,8 @@ impl Default for Metrics {
             messages_total: std::sync::atomic::AtomicU64::new(0),
             sasl_success_total: std::sync::atomic::AtomicU64::new(0),
             sasl_failure_total: std::sync::atomic::AtomicU64::new(0),
+            malformed_lines_total: std::sync::atomic::AtomicU64::new(0),
+            send_queue: Arc::default(),
             started_at: std::time::Instant::now(),
         }
     }
@@ -772,6 +1353,27 @@ impl Metrics {
     }
 }
 
+/// Window over which [`AuthFailures`] accumulate before resetting.
+pub const AUTH_FAILURE_WINDOW: std::time::Duration = std::time::Duration::from_secs(600);
+/// Failures within one window at which a source is refused further SASL
+/// attempts and new connections, until the window runs out.
+pub const AUTH_FAILURE_LIMIT: u32 = 10;
+
+/// Concurrent connections allowed from one address (TCP and WebSocket).
+/// WEBIRC gateway sources are exempt; their users count by their own.
+pub const MAX_CONNS_PER_IP: u32 = 20;
+
+/// Failed-authentication counter for one source.
+#[derive(Debug, Clone)]
+pub struct AuthFailures {
+    pub count: u32,
+    pub first_at: std::time::Instant,
+    pub last_at: std::time::Instant,
+    /// Of `count`, how many were replayed or wrongly-bound challenges
+    /// rather than plain bad credentials.
+    pub replays: u32,
+}
+
 /// A spawned virtual agent (child of a real agent session).
 #[derive(Debug, Clone)]
 pub struct SpawnedAgent {
@@ -786,6 +1388,16 @@ pub struct SpawnedAgent {
     pub spawned_at: i64,
 }
 
+/// When a session signed on and last spoke, and where it connects from.
+#[derive(Debug, Clone)]
+pub struct SessionActivity {
+    /// Unix time registration completed.
+    pub signon: i64,
+    /// Last PRIVMSG or NOTICE sent; WHOIS idle time counts from here.
+    pub last_message: std::time::Instant,
+    pub ip: Option<std::net::IpAddr>,
+}
+
 /// A ghost session represents a recently-disconnected DID user.
 /// Their channel membership is preserved for a grace period.
 pub struct GhostSession {
@@ -830,6 +1442,20 @@ impl SharedState {
         })
     }
 
+    /// The key channel invite tokens are signed with.
+    pub fn invite_key(&self) -> [u8; 32] {
+        crate::invites::derive_key(&self.msg_signing_key.to_bytes())
+    }
+
+    /// Whether `session_id` is a bot: flagged with user mode `+B`, or
+    /// registered with any actor class other than human.
+    pub fn is_bot(&self, session_id: &str) -> bool {
+        self.session_actor_class
+            .lock()
+            .get(session_id)
+            .is_some_and(|c| *c != crate::connection::ActorClass::Human)
+    }
+
     /// Bind a DID to a nick: the single authority for updating the
     /// in-memory `did_nicks`/`nick_owners` maps AND persisting the
     /// durable `identities` row. Replaces ad-hoc inserts at SASL
@@ -842,7 +1468,7 @@ impl SharedState {
     /// claimed during the CAP/SASL negotiation window silently hijacked
     /// in-memory ownership even though the DB `UNIQUE(nick)` rejected it.
     pub fn bind_identity(&self, did: &str, nick: &str) -> BindOutcome {
-        let nick_lower = nick.to_lowercase();
+        let nick_lower = crate::casemap::fold(nick);
         {
             let owners = self.nick_owners.lock();
             if let Some(existing) = owners.get(&nick_lower)
@@ -853,6 +1479,9 @@ impl SharedState {
                 };
             }
         }
+        if let Some((_, owner_did)) = self.confusable_owner(nick, Some(did)) {
+            return BindOutcome::ConflictOwnedByOther { owner_did };
+        }
         // If this DID previously held a different nick, drop the stale
         // nick_owners entry so it isn't orphaned. (Without this, the old
         // nick stayed owned in memory and diverged from the durable
@@ -864,6 +1493,15 @@ impl SharedState {
             let mut owners = self.nick_owners.lock();
             if owners.get(&prev).is_some_and(|d| d == did) {
                 owners.remove(&prev);
+                drop(owners);
+                let mut skeletons = self.nick_skeletons.lock();
+                let skeleton = crate::casemap::skeleton(&prev);
+                if let Some(nicks) = skeletons.get_mut(&skeleton) {
+                    nicks.remove(&prev);
+                    if nicks.is_empty() {
+                        skeletons.remove(&skeleton);
+                    }
+                }
             }
         }
         self.did_nicks
@@ -872,6 +1510,11 @@ impl SharedState {
         self.nick_owners
             .lock()
             .insert(nick_lower.clone(), did.to_string());
+        self.nick_skeletons
+            .lock()
+            .entry(crate::casemap::skeleton(&nick_lower))
+            .or_default()
+            .insert(nick_lower.clone());
         // Persist durably. with_db logs on error; we additionally surface
         // a warning so a swallowed UNIQUE(nick) (shouldn't happen now the
         // in-memory gate above runs first) is not silent.
@@ -885,6 +1528,60 @@ impl SharedState {
         BindOutcome::Bound
     }
 
+    /// The DID that owns `nick`, or owns a nick that `nick` is a
+    /// look-alike of (see [`Self::confusable_owner`]). Look-alikes of
+    /// `requester_did`'s own nick don't count.
+    pub fn nick_owner_or_lookalike(
+        &self,
+        nick: &str,
+        requester_did: Option<&str>,
+    ) -> Option<String> {
+        let exact = self
+            .nick_owners
+            .lock()
+            .get(&crate::casemap::fold(nick))
+            .cloned();
+        exact.or_else(|| {
+            self.confusable_owner(nick, requester_did)
+                .map(|(_, did)| did)
+        })
+    }
+
+    /// Write a channel's in-memory topic history through to the database.
+    pub fn persist_topic_history(&self, channel: &str) {
+        let history: Vec<TopicInfo> = match self.channels.get(channel) {
+            Some(ch) => ch.topic_history.iter().cloned().collect(),
+            None => return,
+        };
+        self.with_db(|db| db.save_topic_history(channel, &history));
+    }
+
+    /// A nick bound to some DID other than `exclude_did` that `nick` is a
+    /// look-alike of: same [`crate::casemap::skeleton`], different folded
+    /// form (an exact match is plain ownership, handled by the caller).
+    /// Returns `(owned_nick, owner_did)`.
+    pub fn confusable_owner(
+        &self,
+        nick: &str,
+        exclude_did: Option<&str>,
+    ) -> Option<(String, String)> {
+        let folded = crate::casemap::fold(nick);
+        let candidates = self
+            .nick_skeletons
+            .lock()
+            .get(&crate::casemap::skeleton(nick))
+            .cloned()
+            .unwrap_or_default();
+        let owners = self.nick_owners.lock();
+        candidates
+            .into_iter()
+            .filter(|owned| *owned != folded)
+            .find_map(|owned| {
+                let owner = owners.get(&owned)?;
+                (exclude_did != Some(owner.as_str())).then(|| (owned, owner.clone()))
+            })
+    }
+
     /// Bind `did` to `requested`; if `requested` is owned by a
     /// *different* DID, bind a deterministic derived nick
     /// `<base>-<didfrag>` instead and return it. Always returns the nick
@@ -902,7 +1599,7 @@ impl SharedState {
     /// keep the `Guest<rand>` path in registration.
     pub fn bind_identity_with_fallback(&self, did: &str, requested: &str) -> String {
         const MAX_NICK: usize = 64;
-        let requested_lower = requested.to_lowercase();
+        let requested_lower = crate::casemap::fold(requested);
         if let BindOutcome::Bound = self.bind_identity(did, &requested_lower) {
             return requested_lower;
         }
@@ -963,6 +1660,396 @@ impl SharedState {
         did.to_string()
     }
 
+    // ── SASL challenges and auth failure counters ──────────────────
+
+    /// Issue an ATPROTO-CHALLENGE for `session_id`. The connection's
+    /// channel binding, if it has one, goes into the challenge to tell
+    /// the client to report its own view of it.
+    pub fn issue_sasl_challenge(&self, session_id: &str, binding: Option<&str>) -> String {
+        self.challenge_store.create_bound(session_id, binding).0
+    }
+
+    /// Consume the pending challenge for `session_id`. Fails if there is
+    /// none (or it expired), or if the response reported a channel
+    /// binding (`reported`) other than the connection's own (`binding`).
+    /// Responses that report none are from clients that can't see one
+    /// and are left to the signature check.
+    pub fn take_sasl_challenge(
+        &self,
+        session_id: &str,
+        reported: Option<&str>,
+        binding: Option<&str>,
+    ) -> Result<(crate::sasl::Challenge, Vec<u8>), ChallengeRejection> {
+        let taken = self
+            .challenge_store
+            .take(session_id)
+            .ok_or(ChallengeRejection::Missing)?;
+        if reported.is_some() && reported != binding {
+            tracing::warn!(
+                session_id,
+                "SASL response signed for a different channel binding"
+            );
+            return Err(ChallengeRejection::BindingMismatch);
+        }
+        Ok(taken)
+    }
+
+    /// Count a failed authentication from `source`. Returns the number of
+    /// failures from that source in the current window.
+    pub fn record_auth_failure(&self, source: &str, replay: bool) -> u32 {
+        let now = std::time::Instant::now();
+        let mut failures = self.auth_failures.lock();
+        let entry = failures
+            .entry(source.to_string())
+            .or_insert_with(|| AuthFailures {
+                count: 0,
+                first_at: now,
+                last_at: now,
+                replays: 0,
+            });
+        if entry.first_at.elapsed() >= AUTH_FAILURE_WINDOW {
+            *entry = AuthFailures {
+                count: 0,
+                first_at: now,
+                last_at: now,
+                replays: 0,
+            };
+        }
+        entry.count += 1;
+        entry.last_at = now;
+        if replay {
+            entry.replays += 1;
+        }
+        if entry.count == AUTH_FAILURE_LIMIT {
+            tracing::warn!(
+                %source,
+                failures = entry.count,
+                replays = entry.replays,
+                "Authentication failure limit reached; refusing the source"
+            );
+        }
+        entry.count
+    }
+
+    /// Failures recorded for `source` in the current window.
+    pub fn auth_failure_count(&self, source: &str) -> u32 {
+        self.auth_failures
+            .lock()
+            .get(source)
+            .filter(|f| f.first_at.elapsed() < AUTH_FAILURE_WINDOW)
+            .map_or(0, |f| f.count)
+    }
+
+    /// Whether `source` has failed to authenticate [`AUTH_FAILURE_LIMIT`]
+    /// times in the current window, and is refused until it runs out.
+    pub fn auth_blocked(&self, source: &str) -> bool {
+        self.auth_failure_count(source) >= AUTH_FAILURE_LIMIT
+    }
+
+    // ── Oper HTTP tokens ───────────────────────────────────────────
+
+    /// Mint the bearer an oper session presents to `/firehose` and
+    /// `/api/v1/admin/backup`. Session ids are sequential or the peer
+    /// address, so they are never accepted as a credential there.
+    pub fn issue_oper_token(&self, session_id: &str) -> String {
+        use base64::Engine;
+        let bytes: [u8; 32] = rand::random();
+        let token = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes);
+        self.oper_tokens
+            .lock()
+            .insert(token.clone(), session_id.to_string());
+        token
+    }
+
+    /// The session `token` was issued to, while it still has OPER.
+    pub fn oper_session(&self, token: &str) -> Option<String> {
+        let session_id = self.oper_tokens.lock().get(token).cloned()?;
+        self.server_opers
+            .lock()
+            .contains(&session_id)
+            .then_some(session_id)
+    }
+
+    // ── Web sessions and web-auth tokens ───────────────────────────
+
+    /// Mint a one-time SASL web-auth token for `did`. Enforces
+    /// [`MAX_WEB_AUTH_TOKENS_PER_DID`] by evicting the DID's oldest
+    /// outstanding tokens.
+    pub fn mint_web_auth_token(
+        &self,
+        did: &str,
+        handle: &str,
+        binding: Option<TokenBinding>,
+    ) -> String {
+        let token = crate::web::generate_random_string(32);
+        let mut tokens = self.web_auth_tokens.lock();
+        let mut mine: Vec<(String, std::time::Instant)> = tokens
+            .iter()
+            .filter(|(_, t)| t.did == did)
+            .map(|(k, t)| (k.clone(), t.created_at))
+            .collect();
+        if mine.len() >= MAX_WEB_AUTH_TOKENS_PER_DID {
+            mine.sort_by_key(|(_, created)| *created);
+            let excess = mine.len() + 1 - MAX_WEB_AUTH_TOKENS_PER_DID;
+            for (k, _) in mine.into_iter().take(excess) {
+                tokens.remove(&k);
+            }
+            tracing::info!(%did, "Web-auth token cap reached — evicted oldest");
+        }
+        tokens.insert(
+            token.clone(),
+            WebAuthToken {
+                id: crate::web::generate_random_string(6),
+                did: did.to_string(),
+                handle: handle.to_string(),
+                created_at: std::time::Instant::now(),
+                binding,
+            },
+        );
+        token
+    }
+
+    /// Consume a web-auth token. Single-use: the token is removed whether
+    /// or not redemption succeeds, so a wrong-device attempt burns it.
+    /// Returns the DID on success.
+    pub fn redeem_web_auth_token(
+        &self,
+        token: &str,
+        iroh_endpoint_id: Option<&str>,
+        fingerprint: Option<&str>,
+    ) -> Result<String, String> {
+        let entry = self
+            .web_auth_tokens
+            .lock()
+            .remove(token)
+            .ok_or_else(|| "Invalid web auth token".to_string())?;
+        if entry.created_at.elapsed() >= WEB_AUTH_TOKEN_TTL {
+            return Err("Web auth token expired".to_string());
+        }
+        let bound_ok = match &entry.binding {
+            None => true,
+            Some(TokenBinding::IrohEndpoint(id)) => iroh_endpoint_id == Some(id.as_str()),
+            Some(TokenBinding::Fingerprint(fp)) => fingerprint
+                .is_some_and(|f| crate::connection::constant_time_eq(f.as_bytes(), fp.as_bytes())),
+        };
+        if !bound_ok {
+            tracing::warn!(did = %entry.did, "Web auth token presented from unbound device");
+            return Err("Web auth token is bound to a different device".to_string());
+        }
+        Ok(entry.did)
+    }
+
+    /// Store a web session in the `(did, purpose)` slot, replacing any
+    /// previous grant for that purpose.
+    pub fn insert_web_session(&self, purpose: OauthPurpose, session: WebSession) {
+        self.web_sessions
+            .lock()
+            .insert((session.did.clone(), purpose), session);
+    }
+
+    /// Look up a live web session and renew its idle timer. Expired
+    /// sessions are removed and reported as absent.
+    pub fn web_session(&self, did: &str, purpose: OauthPurpose) -> Option<WebSession> {
+        let mut sessions = self.web_sessions.lock();
+        let key = (did.to_string(), purpose);
+        let session = sessions.get_mut(&key)?;
+        if session.is_expired() {
+            sessions.remove(&key);
+            return None;
+        }
+        session.last_used = std::time::Instant::now();
+        Some(session.clone())
+    }
+
+    /// Revoke a DID's web session or outstanding web-auth token by the ID
+    /// shown in `SESSIONS`. Returns true if something was removed.
+    pub fn revoke_web_credential(&self, did: &str, id: &str) -> bool {
+        let mut removed = false;
+        self.web_sessions.lock().retain(|(d, _), s| {
+            let hit = d == did && s.id == id;
+            removed |= hit;
+            !hit
+        });
+        self.web_auth_tokens.lock().retain(|_, t| {
+            let hit = t.did == did && t.id == id;
+            removed |= hit;
+            !hit
+        });
+        removed
+    }
+
+    /// Revoke every web session and outstanding web-auth token for a DID.
+    /// Returns how many were removed.
+    pub fn revoke_all_web_credentials(&self, did: &str) -> usize {
+        let mut sessions = self.web_sessions.lock();
+        let before = sessions.len();
+        sessions.retain(|(d, _), _| d != did);
+        let mut n = before - sessions.len();
+        drop(sessions);
+        let mut tokens = self.web_auth_tokens.lock();
+        let before = tokens.len();
+        tokens.retain(|_, t| t.did != did);
+        n += before - tokens.len();
+        n
+    }
+
+    // ── Iroh endpoint bindings ─────────────────────────────────────
+
+    /// Record that `endpoint_id` authenticated as `did` over iroh.
+    pub fn bind_iroh_endpoint(&self, endpoint_id: &str, did: &str) {
+        let bound_at = chrono::Utc::now().timestamp();
+        self.iroh_bindings.lock().insert(
+            endpoint_id.to_string(),
+            IrohBinding {
+                did: did.to_string(),
+                bound_at,
+            },
+        );
+        self.with_db(|db| db.save_iroh_binding(endpoint_id, did, bound_at));
+    }
+
+    /// The DID `endpoint_id` is bound to, if any.
+    pub fn iroh_endpoint_did(&self, endpoint_id: &str) -> Option<String> {
+        self.iroh_bindings
+            .lock()
+            .get(endpoint_id)
+            .map(|b| b.did.clone())
+    }
+
+    /// Record a broker session lifecycle event for `did` at `at`: create
+    /// the device or update when it was last seen.
+    pub fn record_broker_device(&self, session: &str, did: &str, device: Option<String>, at: i64) {
+        let mut devices = self.broker_devices.lock();
+        let fresh = || BrokerDevice {
+            did: did.to_string(),
+            device: None,
+            created_at: at,
+            last_seen: at,
+            web_session: None,
+        };
+        let entry = devices.entry(session.to_string()).or_insert_with(fresh);
+        if entry.did != did {
+            // A broker session never changes hands; don't merge.
+            *entry = fresh();
+        }
+        entry.last_seen = entry.last_seen.max(at);
+        if device.is_some() {
+            entry.device = device;
+        }
+        let mut mine: Vec<(String, i64)> = devices
+            .iter()
+            .filter(|(_, d)| d.did == did)
+            .map(|(id, d)| (id.clone(), d.last_seen))
+            .collect();
+        if mine.len() > MAX_BROKER_DEVICES_PER_DID {
+            mine.sort_by_key(|(_, seen)| std::cmp::Reverse(*seen));
+            for (id, _) in &mine[MAX_BROKER_DEVICES_PER_DID..] {
+                devices.remove(id);
+            }
+        }
+    }
+
+    /// Note which web session the broker just pushed for `session`.
+    pub fn link_broker_web_session(&self, session: &str, did: &str, web_session: &str) {
+        let now = chrono::Utc::now().timestamp();
+        self.record_broker_device(session, did, None, now);
+        if let Some(d) = self.broker_devices.lock().get_mut(session) {
+            d.web_session = Some(web_session.to_string());
+        }
+    }
+
+    /// Forget a broker device of `did` that the broker revoked, and drop
+    /// the web session pushed for it. Returns true if the device was known.
+    pub fn revoke_broker_device(&self, session: &str, did: &str) -> bool {
+        let removed = {
+            let mut devices = self.broker_devices.lock();
+            if devices.get(session).is_none_or(|d| d.did != did) {
+                return false;
+            }
+            devices.remove(session)
+        };
+        if let Some(web_session) = removed.and_then(|d| d.web_session) {
+            self.revoke_web_credential(did, &web_session);
+        }
+        true
+    }
+
+    /// Broker devices of `did`, oldest first.
+    pub fn broker_devices_for_did(&self, did: &str) -> Vec<(String, BrokerDevice)> {
+        let mut devices: Vec<_> = self
+            .broker_devices
+            .lock()
+            .iter()
+            .filter(|(_, d)| d.did == did)
+            .map(|(id, d)| (id.clone(), d.clone()))
+            .collect();
+        devices.sort_by_key(|(_, d)| d.created_at);
+        devices
+    }
+
+    /// Endpoints bound to `did`, oldest binding first.
+    pub fn iroh_endpoints_for_did(&self, did: &str) -> Vec<(String, IrohBinding)> {
+        let mut endpoints: Vec<_> = self
+            .iroh_bindings
+            .lock()
+            .iter()
+            .filter(|(_, b)| b.did == did)
+            .map(|(id, b)| (id.clone(), b.clone()))
+            .collect();
+        endpoints.sort_by_key(|(_, b)| b.bound_at);
+        endpoints
+    }
+
+    /// Whether `did` may use the iroh transport under `iroh_allowed_dids`.
+    /// Anyone may when the list is empty; no guest may when it isn't.
+    pub fn iroh_did_allowed(&self, did: Option<&str>) -> bool {
+        let allowed = &self.config.iroh_allowed_dids;
+        allowed.is_empty() || did.is_some_and(|d| allowed.iter().any(|a| a == d))
+    }
+
+    /// The gate applied at the QUIC handshake: refuse an endpoint bound
+    /// to a DID that isn't allowed. Unbound endpoints get through, but
+    /// must authenticate as an allowed DID before registration completes.
+    pub fn iroh_endpoint_admitted(&self, endpoint_id: &str) -> bool {
+        match self.iroh_endpoint_did(endpoint_id) {
+            Some(did) => self.iroh_did_allowed(Some(&did)),
+            None => true,
+        }
+    }
+
+    /// Unbind one of `did`'s endpoints. Returns true if it was bound to
+    /// `did`. Live connections from the endpoint are left alone.
+    pub fn revoke_iroh_binding(&self, did: &str, endpoint_id: &str) -> bool {
+        let mut bindings = self.iroh_bindings.lock();
+        if bindings.get(endpoint_id).is_none_or(|b| b.did != did) {
+            return false;
+        }
+        bindings.remove(endpoint_id);
+        drop(bindings);
+        self.with_db(|db| db.delete_iroh_binding(endpoint_id));
+        true
+    }
+
+    /// Unbind every endpoint bound to `did`. Returns how many were removed.
+    pub fn revoke_all_iroh_bindings(&self, did: &str) -> usize {
+        let endpoints: Vec<String> = {
+            let mut bindings = self.iroh_bindings.lock();
+            let ids: Vec<String> = bindings
+                .iter()
+                .filter(|(_, b)| b.did == did)
+                .map(|(id, _)| id.clone())
+                .collect();
+            for id in &ids {
+                bindings.remove(id);
+            }
+            ids
+        };
+        for id in &endpoints {
+            self.with_db(|db| db.delete_iroh_binding(id));
+        }
+        endpoints.len()
+    }
+
     // ── CRDT operations ────────────────────────────────────────────
     //
     // NOTE: Presence (join/part) is NOT in CRDT. It's handled by S2S events
@@ -1003,6 +2090,11 @@ impl SharedState {
         self.cluster_doc.set_founder(channel, did).await;
     }
 
+    /// Overwrite a channel founder in the CRDT (policy chain arbitration).
+    pub async fn crdt_replace_founder(&self, channel: &str, did: &str) {
+        self.cluster_doc.replace_founder(channel, did).await;
+    }
+
     /// Record a DID op grant in the CRDT with provenance.
     pub async fn crdt_grant_op(&self, channel: &str, did: &str, granted_by_did: Option<&str>) {
         let origin = self.crdt_origin_peer();
@@ -1205,6 +2297,31 @@ fn install_llm_provider(config: &ServerConfig) {
     }
 }
 
+/// Bind a client-facing TCP listener. With a handover socket (Unix only)
+/// it gets SO_REUSEPORT; see [`crate::handover::bind_tcp`].
+async fn bind_listener(addr: &str, reuse_port: bool) -> Result<TcpListener> {
+    #[cfg(unix)]
+    return crate::handover::bind_tcp(addr, reuse_port).await;
+    #[cfg(not(unix))]
+    {
+        let _ = reuse_port;
+        Ok(TcpListener::bind(addr).await?)
+    }
+}
+
+/// Stands in for `handover::HandedOver` where there are no handovers:
+/// never fires.
+#[cfg(not(unix))]
+#[derive(Clone)]
+struct NeverHandedOver;
+
+#[cfg(not(unix))]
+impl NeverHandedOver {
+    async fn wait(&mut self) {
+        std::future::pending::<()>().await;
+    }
+}
+
 pub struct Server {
     config: ServerConfig,
     resolver: DidResolver,
@@ -1229,6 +2346,9 @@ impl Server {
         // not configured). Lives in a process-wide slot rather than
         // SharedState so existing constructors don't need to change.
         install_llm_provider(&self.config);
+        // Same for the nick/channel case mapping: NickMap and the channel
+        // key helpers have no handle on the config.
+        crate::casemap::set(self.config.casemapping);
 
         // Load message signing key early — it's used to derive DB encryption key
         let msg_signing_key = load_msg_signing_key(self.config.data_dir.as_deref().unwrap_or("."));
@@ -1300,10 +2420,29 @@ impl Server {
             None
         };
 
+        let metadata = {
+            let mut metadata: HashMap<String, BTreeMap<String, String>> = HashMap::new();
+            if let Some(ref db) = db
+                && let Ok(saved) = db.load_all_metadata()
+            {
+                for (target, key, value) in saved {
+                    metadata.entry(target).or_default().insert(key, value);
+                }
+            }
+            metadata
+        };
+
         // Load persisted state from DB
         let mut channels = HashMap::new();
         let mut did_nicks = HashMap::new();
+        let mut iroh_bindings = HashMap::new();
+        let mut content_filters = crate::filters::Filters::default();
+        let mut invites = crate::invites::Invites::default();
+        let mut prefs = crate::prefs::Preferences::default();
+        let email = crate::email::EmailNotifier::from_config(&self.config)
+            .map_err(|e| anyhow::anyhow!("Invalid email settings: {e}"))?;
         let mut nick_owners = HashMap::new();
+        let mut nick_skeletons: HashMap<String, HashSet<String>> = HashMap::new();
 
         if let Some(ref db) = db {
             // Load channels (metadata + bans)
@@ -1341,6 +2480,10 @@ impl Server {
                     && !ch.moderated
                     && ch.key.is_none()
                     && ch.bans.is_empty()
+                    && ch.quiets.is_empty()
+                    && !ch.bots_restricted
+                    && ch.bot_allowlist.is_empty()
+                    && !metadata.contains_key(name)
                 {
                     // Don't prune if channel has policy (check later)
                     let _ = db.delete_channel(name);
@@ -1366,13 +2509,62 @@ impl Server {
                 identities.len()
             );
             for id in identities {
-                nick_owners.insert(id.nick.clone(), id.did.clone());
-                did_nicks.insert(id.did, id.nick);
+                // Re-fold so a changed --casemapping still finds the owner.
+                let nick = crate::casemap::fold(&id.nick);
+                nick_owners.insert(nick.clone(), id.did.clone());
+                nick_skeletons
+                    .entry(crate::casemap::skeleton(&nick))
+                    .or_default()
+                    .insert(nick.clone());
+                did_nicks.insert(id.did, nick);
+            }
+
+            let bindings = db
+                .load_iroh_bindings()
+                .map_err(|e| anyhow::anyhow!("Failed to load iroh bindings: {e}"))?;
+            for (endpoint_id, did, bound_at) in bindings {
+                iroh_bindings.insert(endpoint_id, IrohBinding { did, bound_at });
+            }
+
+            let filters = db
+                .load_content_filters()
+                .map_err(|e| anyhow::anyhow!("Failed to load content filters: {e}"))?;
+            if !filters.is_empty() {
+                tracing::info!("Loaded {} content filters from database", filters.len());
             }
+            content_filters = crate::filters::Filters::from_rules(filters);
+
+            let saved = db
+                .load_invites()
+                .map_err(|e| anyhow::anyhow!("Failed to load channel invites: {e}"))?;
+            if !saved.is_empty() {
+                tracing::info!("Loaded {} channel invites from database", saved.len());
+            }
+            invites = crate::invites::Invites::from_invites(saved);
+
+            let subscriptions = db
+                .load_email_subscriptions()
+                .map_err(|e| anyhow::anyhow!("Failed to load email subscriptions: {e}"))?;
+            if !subscriptions.is_empty() {
+                tracing::info!(
+                    "Loaded {} email subscriptions from database",
+                    subscriptions.len()
+                );
+            }
+            email.load(subscriptions);
+
+            let rows = db
+                .load_prefs()
+                .map_err(|e| anyhow::anyhow!("Failed to load preferences: {e}"))?;
+            if !rows.is_empty() {
+                tracing::info!("Loaded {} preferences from database", rows.len());
+            }
+            prefs = crate::prefs::Preferences::from_rows(rows);
         }
 
         let plugin_manager =
             PluginManager::load(&self.config.plugins, self.config.plugin_dir.as_deref());
+        let command_aliases = crate::alias::AliasTable::load(&self.config.aliases, &plugin_manager);
 
         // msg_signing_key already loaded above (needed for DB encryption key derivation)
 
@@ -1393,27 +2585,24 @@ impl Server {
         Ok(Arc::new(SharedState {
             server_name: self.config.server_name.clone(),
             challenge_store: ChallengeStore::new(self.config.challenge_timeout_secs),
+            auth_failures: Mutex::new(HashMap::new()),
             did_resolver: self.resolver.clone(),
-            connections: Mutex::new(HashMap::new()),
+            connections: ShardedMap::new(),
             nick_to_session: Mutex::new(NickMap::new()),
             session_dids: Mutex::new(HashMap::new()),
             did_sessions: Mutex::new(HashMap::new()),
-            channels: Mutex::new(channels),
+            channels: channels.into_iter().collect(),
             did_nicks: Mutex::new(did_nicks),
             nick_owners: Mutex::new(nick_owners),
+            nick_skeletons: Mutex::new(nick_skeletons),
             session_handles: Mutex::new(HashMap::new()),
-            cap_message_tags: Mutex::new(HashSet::new()),
-            cap_multi_prefix: Mutex::new(HashSet::new()),
-            cap_echo_message: Mutex::new(HashSet::new()),
-            cap_server_time: Mutex::new(HashSet::new()),
-            cap_batch: Mutex::new(HashSet::new()),
-            cap_draft_multiline: Mutex::new(HashSet::new()),
+            sessions: crate::session::SessionRegistry::new(),
             open_batches: Mutex::new(HashMap::new()),
-            cap_account_notify: Mutex::new(HashSet::new()),
-            cap_extended_join: Mutex::new(HashSet::new()),
-            cap_away_notify: Mutex::new(HashSet::new()),
-            cap_account_tag: Mutex::new(HashSet::new()),
+            metadata: Mutex::new(metadata),
+            metadata_subs: Mutex::new(HashMap::new()),
             server_opers: Mutex::new(HashSet::new()),
+            oper_tokens: Mutex::new(HashMap::new()),
+            firehose: Default::default(),
             session_actor_class: Mutex::new(HashMap::new()),
             provenance_declarations: Mutex::new(HashMap::new()),
             agent_presence: Mutex::new(HashMap::new()),
@@ -1423,10 +2612,16 @@ impl Server {
             oauth_complete: Mutex::new(HashMap::new()),
             web_auth_tokens: Mutex::new(HashMap::new()),
             web_sessions: Mutex::new(HashMap::new()),
+            broker_devices: Mutex::new(HashMap::new()),
             login_pending: Mutex::new(HashMap::new()),
             linked_identities: Mutex::new(HashMap::new()),
             login_completions: Mutex::new(HashMap::new()),
             session_iroh_ids: Mutex::new(HashMap::new()),
+            iroh_bindings: Mutex::new(iroh_bindings),
+            content_filters: Mutex::new(content_filters),
+            invites: Mutex::new(invites),
+            email,
+            prefs: Mutex::new(prefs),
             session_away: Mutex::new(HashMap::new()),
             server_iroh_id: Mutex::new(None),
             iroh_endpoint: Mutex::new(None),
@@ -1442,21 +2637,21 @@ impl Server {
             db: db.map(Mutex::new),
             config: self.config.clone(),
             plugin_manager,
+            command_aliases,
+            locales: crate::locale::Locales::load(self.config.locale_dir.as_deref()),
             policy_engine: {
                 // Initialize policy engine alongside the main DB
                 let policy_db_path = self
                     .config
-                    .db_path
-                    .as_ref()
-                    .map(|p| p.replace(".db", "-policy.db"))
+                    .policy_db_path()
                     .unwrap_or_else(|| ":memory:".to_string());
                 match crate::policy::PolicyStore::open(&policy_db_path) {
                     Ok(store) => {
                         let authority_did = format!("did:web:{}", self.config.server_name);
-                        Some(Arc::new(crate::policy::PolicyEngine::new(
-                            store,
-                            authority_did,
-                        )))
+                        Some(Arc::new(
+                            crate::policy::PolicyEngine::new(store, authority_did)
+                                .with_policy_key(msg_signing_key.clone()),
+                        ))
                     }
                     Err(e) => {
                         tracing::warn!("Failed to initialize policy engine: {e}");
@@ -1468,11 +2663,16 @@ impl Server {
             boot_timestamp: chrono::Utc::now(),
             prekey_bundles: Mutex::new(prekey_bundles),
             msg_timestamps: Mutex::new(HashMap::new()),
+            ephemeral: Default::default(),
+            channel_stats: Default::default(),
+            channel_directory: Default::default(),
             ip_connections: Mutex::new(HashMap::new()),
             msg_signing_key,
             session_msg_keys: Mutex::new(HashMap::new()),
             did_msg_keys: Mutex::new(HashMap::new()),
             session_client_info: Mutex::new(HashMap::new()),
+            session_activity: Mutex::new(HashMap::new()),
+            reclaim_attempts: Mutex::new(HashMap::new()),
             upload_tokens: Mutex::new(HashMap::new()),
             ghost_sessions: Mutex::new(HashMap::new()),
             spawned_agents: Mutex::new(HashMap::new()),
@@ -1482,6 +2682,8 @@ impl Server {
             liveness_probes: Mutex::new(HashMap::new()),
             session_kill: Mutex::new(HashMap::new()),
             metrics: Metrics::default(),
+            listening: std::sync::atomic::AtomicBool::new(false),
+            backup_running: std::sync::atomic::AtomicBool::new(false),
         }))
     }
 
@@ -1534,7 +2736,7 @@ impl Server {
                     }
                     if let Some(ch) = &session.channel {
                         mgr.channel_sessions
-                            .insert(ch.to_lowercase(), session.id.clone());
+                            .insert(crate::casemap::fold(ch), session.id.clone());
                     }
                     mgr.sessions.insert(session.id.clone(), session);
                     count += 1;
@@ -1545,20 +2747,38 @@ impl Server {
             }
         }
 
+        // With a handover socket, listeners are bound with SO_REUSEPORT so
+        // this server can bind them while the one it takes over from still
+        // holds them, and so a later server can do the same to this one.
+        let reuse_port = self.config.handover_socket.is_some();
+        #[cfg(unix)]
+        let (handover_tx, handed_over) = crate::handover::signal();
+        #[cfg(not(unix))]
+        let handed_over = NeverHandedOver;
+        #[cfg(not(unix))]
+        if reuse_port {
+            tracing::warn!("--handover-socket needs Unix domain sockets; ignored on this platform");
+        }
+
         // Start plain listener
-        let plain_listener = TcpListener::bind(&self.config.listen_addr).await?;
+        let plain_listener = bind_listener(&self.config.listen_addr, reuse_port).await?;
         tracing::info!("Plain listener on {}", self.config.listen_addr);
 
         // Start TLS listener if configured
         if let Some(ref acceptor) = tls_acceptor {
-            let tls_listener = TcpListener::bind(&self.config.tls_listen_addr).await?;
+            let tls_listener = bind_listener(&self.config.tls_listen_addr, reuse_port).await?;
             tracing::info!("TLS listener on {}", self.config.tls_listen_addr);
 
             let tls_state = Arc::clone(&state);
             let tls_acc = acceptor.clone();
+            let mut tls_stop = handed_over.clone();
             tokio::spawn(async move {
                 loop {
-                    match tls_listener.accept().await {
+                    let accepted = tokio::select! {
+                        accepted = tls_listener.accept() => accepted,
+                        _ = tls_stop.wait() => break,
+                    };
+                    match accepted {
                         Ok((stream, _)) => {
                             let state = Arc::clone(&tls_state);
                             let acceptor = tls_acc.clone();
@@ -1566,7 +2786,7 @@ impl Server {
                                 match acceptor.accept(stream).await {
                                     Ok(tls_stream) => {
                                         if let Err(e) =
-                                            connection::handle_generic(tls_stream, state).await
+                                            connection::handle_tls(tls_stream, state).await
                                         {
                                             tracing::error!("TLS connection error: {e}");
                                         }
@@ -1583,6 +2803,26 @@ impl Server {
             });
         }
 
+        let web_listener = match web_addr {
+            Some(ref addr) => Some(bind_listener(addr, reuse_port).await?),
+            None => None,
+        };
+
+        // Take over from a running server, if there is one. It stops
+        // accepting and shuts down its iroh endpoint before replying, so
+        // ours can bind the same port below with the same endpoint ID.
+        #[cfg(unix)]
+        if let Some(ref path) = self.config.handover_socket {
+            match crate::handover::take_over(std::path::Path::new(path)).await {
+                Ok(Some(handover)) => {
+                    let held = crate::handover::install(&state, handover);
+                    tracing::info!("Took over from the running server ({held} users held)");
+                }
+                Ok(None) => {}
+                Err(e) => tracing::error!("Handover from the running server failed: {e:#}"),
+            }
+        }
+
         // Warn if iroh is enabled without an S2S allowlist (open federation)
         if (self.config.iroh || !self.config.s2s_peers.is_empty())
             && self.config.s2s_allowed_peers.is_empty()
@@ -1626,7 +2866,7 @@ impl Server {
         if let Some(ref endpoint) = iroh_endpoint {
             let s2s_state = Arc::clone(&state);
             match crate::s2s::start(s2s_state, endpoint.clone()).await {
-                Ok((manager, mut s2s_rx)) => {
+                Ok((manager, s2s_rx)) => {
                     // Store manager in shared state so iroh accept loop can route S2S
                     *state.s2s_manager.lock() = Some(Arc::clone(&manager));
 
@@ -1640,19 +2880,7 @@ impl Server {
                     }
 
                     // Spawn S2S event processor
-                    let s2s_state = Arc::clone(&state);
-                    let s2s_manager = Arc::clone(&manager);
-                    tokio::spawn(async move {
-                        while let Some(event) = s2s_rx.recv().await {
-                            process_s2s_message(
-                                &s2s_state,
-                                &s2s_manager,
-                                &event.authenticated_peer_id,
-                                event.msg,
-                            )
-                            .await;
-                        }
-                    });
+                    spawn_s2s_processor(Arc::clone(&state), Arc::clone(&manager), s2s_rx);
 
                     if self.config.s2s_peers.is_empty() {
                         tracing::info!("S2S ready (accepting incoming peer connections)");
@@ -1729,62 +2957,117 @@ impl Server {
                 interval.tick().await; // skip first immediate tick
                 loop {
                     interval.tick().await;
-                    let metrics = compact_state.cluster_doc.get_metrics().await;
-                    tracing::info!(
-                        "CRDT metrics: {} changes, {} sync msgs sent, {} recv, last save {}B",
-                        metrics.change_count,
-                        metrics.sync_messages_sent,
-                        metrics.sync_messages_received,
-                        metrics.last_save_size,
-                    );
-                    if let Err(e) = compact_state.cluster_doc.compact().await {
-                        tracing::error!("CRDT compaction failed: {e}");
-                    } else {
-                        tracing::info!("CRDT compacted successfully");
+                    let metrics = compact_state.cluster_doc.get_metrics().await;
+                    tracing::info!(
+                        "CRDT metrics: {} changes, {} sync msgs sent, {} recv, last save {}B",
+                        metrics.change_count,
+                        metrics.sync_messages_sent,
+                        metrics.sync_messages_received,
+                        metrics.last_save_size,
+                    );
+                    if let Err(e) = compact_state.cluster_doc.compact().await {
+                        tracing::error!("CRDT compaction failed: {e}");
+                    } else {
+                        tracing::info!("CRDT compacted successfully");
+                    }
+                }
+            });
+        }
+
+        // CRDT→local reconciliation: periodically apply CRDT state to local
+        // channel state. This ensures the CRDT is the single source of truth
+        // for topics, founder, and DID ops — even if S2S events and CRDT
+        // diverge due to timing/partitions.
+        {
+            let reconcile_state = Arc::clone(&state);
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+                interval.tick().await; // skip first tick
+                loop {
+                    interval.tick().await;
+                    reconcile_crdt_to_local(&reconcile_state).await;
+                    // Prune expired web auth tokens
+                    reconcile_state
+                        .web_auth_tokens
+                        .lock()
+                        .retain(|_, t| t.created_at.elapsed() < WEB_AUTH_TOKEN_TTL);
+                }
+            });
+        }
+
+        // Policy revalidation: periodically invalidate expired attestations
+        // and kick users whose continuous validity has expired.
+        if state.policy_engine.is_some() {
+            let policy_state = Arc::clone(&state);
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+                interval.tick().await; // skip first tick
+                loop {
+                    interval.tick().await;
+                    if let Some(ref engine) = policy_state.policy_engine {
+                        match engine.revalidate_expired() {
+                            Ok(0) => {}
+                            Ok(n) => tracing::info!("Invalidated {n} expired policy attestations"),
+                            Err(e) => tracing::warn!("Policy revalidation error: {e}"),
+                        }
+                    }
+                }
+            });
+        }
+
+        // Channel statistics: write today's activity counters to the
+        // daily rollups.
+        {
+            let stats_state = Arc::clone(&state);
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(crate::stats::FLUSH_INTERVAL);
+                interval.tick().await; // skip first tick
+                loop {
+                    interval.tick().await;
+                    crate::stats::flush(&stats_state);
+                }
+            });
+        }
+
+        // Federated LIST: tell peers which public channels live here.
+        {
+            let directory_state = Arc::clone(&state);
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(crate::directory::ANNOUNCE_INTERVAL);
+                interval.tick().await; // skip first tick
+                loop {
+                    interval.tick().await;
+                    let manager = directory_state.s2s_manager.lock().clone();
+                    if let Some(manager) = manager {
+                        crate::directory::announce(&directory_state, &manager);
                     }
                 }
             });
         }
 
-        // CRDT→local reconciliation: periodically apply CRDT state to local
-        // channel state. This ensures the CRDT is the single source of truth
-        // for topics, founder, and DID ops — even if S2S events and CRDT
-        // diverge due to timing/partitions.
+        // Temporary channels (+T): purge the ones left empty past their TTL.
         {
-            let reconcile_state = Arc::clone(&state);
+            let temp_state = Arc::clone(&state);
             tokio::spawn(async move {
-                let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+                let mut interval = tokio::time::interval(crate::temporary::SWEEP_INTERVAL);
                 interval.tick().await; // skip first tick
                 loop {
                     interval.tick().await;
-                    reconcile_crdt_to_local(&reconcile_state).await;
-                    // Prune expired web auth tokens (TTL 30 min)
-                    reconcile_state
-                        .web_auth_tokens
-                        .lock()
-                        .retain(|_, (_, _, created)| {
-                            created.elapsed() < std::time::Duration::from_secs(1800)
-                        });
+                    let now = chrono::Utc::now().timestamp().max(0) as u64;
+                    crate::temporary::sweep(&temp_state, now);
                 }
             });
         }
 
-        // Policy revalidation: periodically invalidate expired attestations
-        // and kick users whose continuous validity has expired.
-        if state.policy_engine.is_some() {
-            let policy_state = Arc::clone(&state);
+        // Email digests for offline users, once their oldest item is due.
+        if state.email.enabled() {
+            let email_state = Arc::clone(&state);
             tokio::spawn(async move {
-                let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+                let mut interval = tokio::time::interval(email_state.email.flush_interval());
                 interval.tick().await; // skip first tick
                 loop {
                     interval.tick().await;
-                    if let Some(ref engine) = policy_state.policy_engine {
-                        match engine.revalidate_expired() {
-                            Ok(0) => {}
-                            Ok(n) => tracing::info!("Invalidated {n} expired policy attestations"),
-                            Err(e) => tracing::warn!("Policy revalidation error: {e}"),
-                        }
-                    }
+                    crate::email::flush(&email_state).await;
                 }
             });
         }
@@ -1814,8 +3097,10 @@ impl Server {
                             hb_state.agent_heartbeats.lock().remove(&session_id);
                             hb_state.agent_presence.lock().remove(&session_id);
                             // Send ERROR to the connection
-                            if let Some(tx) = hb_state.connections.lock().get(&session_id) {
-                                let _ = tx.try_send("ERROR :Heartbeat timeout\r\n".to_string());
+                            if let Some(tx) = hb_state.connections.get(&session_id) {
+                                let _ = tx.try_send(WireLine::from_static(
+                                    b"ERROR :Heartbeat timeout\r\n",
+                                ));
                             }
                         } else if elapsed > ttl * 2 {
                             // Transition to offline
@@ -1845,16 +3130,17 @@ impl Server {
         }
 
         // Start HTTP/WebSocket listener if configured
-        if let Some(ref addr) = web_addr {
+        if let (Some(addr), Some(listener)) = (&web_addr, web_listener) {
             let web_state = Arc::clone(&state);
             let router = crate::web::router(web_state);
-            let listener = tokio::net::TcpListener::bind(addr).await?;
             tracing::info!("HTTP/WebSocket listener on {addr}");
+            let mut web_stop = handed_over.clone();
             tokio::spawn(async move {
                 if let Err(e) = axum::serve(
                     listener,
                     router.into_make_service_with_connect_info::<std::net::SocketAddr>(),
                 )
+                .with_graceful_shutdown(async move { web_stop.wait().await })
                 .await
                 {
                     tracing::error!("HTTP server error: {e}");
@@ -1869,11 +3155,11 @@ impl Server {
                 let mut interval = tokio::time::interval(std::time::Duration::from_secs(300));
                 loop {
                     interval.tick().await;
-                    // Prune expired web-auth tokens (30 min TTL)
+                    // Prune expired web-auth tokens
                     {
                         let mut tokens = cleanup_state.web_auth_tokens.lock();
                         let before = tokens.len();
-                        tokens.retain(|_, (_, _, created)| created.elapsed().as_secs() < 1800);
+                        tokens.retain(|_, t| t.created_at.elapsed() < WEB_AUTH_TOKEN_TTL);
                         let pruned = before - tokens.len();
                         if pruned > 0 {
                             tracing::info!("Pruned {pruned} expired web-auth tokens");
@@ -1897,9 +3183,7 @@ impl Server {
                         let mut completions = cleanup_state.login_completions.lock();
                         let before = completions.len();
                         // Check if the session still exists
-                        let conns = cleanup_state.connections.lock();
-                        completions.retain(|sid, _| conns.contains_key(sid));
-                        drop(conns);
+                        completions.retain(|sid, _| cleanup_state.connections.contains_key(sid));
                         let pruned = before - completions.len();
                         if pruned > 0 {
                             tracing::info!("Pruned {pruned} stale login completions");
@@ -1927,21 +3211,46 @@ impl Server {
                             tracing::info!("Pruned {pruned} stale OAuth complete entries");
                         }
                     }
-                    // Prune stale web sessions (24h TTL — PDS tokens expire anyway)
+                    // Prune idle / over-age web sessions
                     {
                         let mut sessions = cleanup_state.web_sessions.lock();
                         let before = sessions.len();
-                        sessions.retain(|_, s| s.created_at.elapsed().as_secs() < 86400);
+                        sessions.retain(|_, s| !s.is_expired());
                         let pruned = before - sessions.len();
                         if pruned > 0 {
                             tracing::info!("Pruned {pruned} stale web sessions");
                         }
+                        drop(sessions);
+                        let cutoff = chrono::Utc::now().timestamp() - BROKER_DEVICE_TTL;
+                        cleanup_state
+                            .broker_devices
+                            .lock()
+                            .retain(|_, d| d.last_seen > cutoff);
+                    }
+                    // Prune lapsed auth failure counters
+                    {
+                        cleanup_state
+                            .auth_failures
+                            .lock()
+                            .retain(|_, f| f.first_at.elapsed() < AUTH_FAILURE_WINDOW);
+                    }
+                    // Drop channel invites that expired or were used up
+                    {
+                        let dead = cleanup_state
+                            .invites
+                            .lock()
+                            .prune(chrono::Utc::now().timestamp());
+                        if !dead.is_empty() {
+                            cleanup_state.with_db(|db| {
+                                dead.iter().try_for_each(|id| db.delete_invite(id))
+                            });
+                            tracing::info!("Pruned {} dead channel invites", dead.len());
+                        }
                     }
                     // Prune old messages per channel (keep last 50K per channel)
                     {
                         const MAX_MESSAGES_PER_CHANNEL: usize = 50_000;
-                        let channel_names: Vec<String> =
-                            cleanup_state.channels.lock().keys().cloned().collect();
+                        let channel_names = cleanup_state.channels.keys();
                         for ch in &channel_names {
                             let ch = ch.clone();
                             cleanup_state
@@ -2008,35 +3317,55 @@ impl Server {
             });
         }
 
+        // Accept takeovers from the next server
+        #[cfg(unix)]
+        if let Some(ref path) = self.config.handover_socket {
+            let listener = crate::handover::listen(std::path::Path::new(path))?;
+            tracing::info!("Handover socket on {path}");
+            tokio::spawn(crate::handover::serve(
+                Arc::clone(&state),
+                listener,
+                handover_tx,
+            ));
+        }
+
         // Graceful shutdown on SIGTERM/SIGINT
         let shutdown_state = Arc::clone(&state);
         let shutdown = async move {
+            #[cfg(unix)]
             let mut sigterm =
                 tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
                     .expect("failed to install SIGTERM handler");
+            #[cfg(unix)]
+            let terminate = sigterm.recv();
+            #[cfg(not(unix))]
+            let terminate = std::future::pending::<Option<()>>();
             tokio::select! {
                 _ = tokio::signal::ctrl_c() => tracing::info!("Received SIGINT, shutting down..."),
-                _ = sigterm.recv() => tracing::info!("Received SIGTERM, shutting down..."),
+                _ = terminate => tracing::info!("Received SIGTERM, shutting down..."),
             }
             // Broadcast ERROR to all connected clients
-            let conns = shutdown_state.connections.lock();
-            for tx in conns.values() {
-                let _ = tx.try_send("ERROR :Server shutting down\r\n".to_string());
-            }
-            drop(conns);
+            shutdown_state.connections.for_each(|_, tx| {
+                let _ = tx.try_send(WireLine::from_static(b"ERROR :Server shutting down\r\n"));
+            });
             // Give clients a moment to receive the ERROR
             tokio::time::sleep(tokio::time::Duration::from_millis(250)).await;
             tracing::info!(
                 "Shutdown complete ({} connections closed)",
-                shutdown_state.connections.lock().len()
+                shutdown_state.connections.len()
             );
         };
 
+        state
+            .listening
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+
         // Accept plain connections
-        const MAX_CONNS_PER_IP: u32 = 20;
         const MAX_GLOBAL_CONNS: u32 = 10_000;
-        tokio::select! {
-            _ = shutdown => {}
+        let mut stop_accepting = handed_over.clone();
+        let taken_over = tokio::select! {
+            _ = shutdown => false,
+            _ = stop_accepting.wait() => true,
             result = async {
                 loop {
                     let (stream, addr) = plain_listener.accept().await?;
@@ -2055,7 +3384,9 @@ impl Server {
                     {
                         let mut ip_conns = state.ip_connections.lock();
                         let count = ip_conns.entry(ip).or_insert(0);
-                        if *count >= MAX_CONNS_PER_IP {
+                        if *count >= MAX_CONNS_PER_IP
+                            && !connection::webirc::is_gateway(&state.config, ip)
+                        {
                             tracing::warn!(%ip, "Connection rejected: per-IP limit reached");
                             continue;
                         }
@@ -2080,8 +3411,22 @@ impl Server {
                 if let Err(e) = result {
                     tracing::error!("Accept loop error: {e}");
                 }
+                false
             }
+        };
+        state
+            .listening
+            .store(false, std::sync::atomic::Ordering::Relaxed);
+        #[cfg(unix)]
+        if taken_over {
+            // Stop the kernel queueing connections for us, then hand our
+            // clients to the new server.
+            drop(plain_listener);
+            let window = std::time::Duration::from_secs(self.config.handover_drain_secs);
+            crate::handover::drain(&state, window).await;
         }
+        #[cfg(not(unix))]
+        let _ = taken_over;
         Ok(())
     }
 
@@ -2092,6 +3437,9 @@ impl Server {
         tracing::info!("Listening on {addr}");
 
         let state = self.build_state()?;
+        state
+            .listening
+            .store(true, std::sync::atomic::Ordering::Relaxed);
 
         // Periodic phantom-session sweeper. Defense-in-depth: even if
         // close handlers leak some bookkeeping (the multi-device path used
@@ -2140,6 +3488,9 @@ impl Server {
         let web_addr = web_listener.local_addr()?;
 
         let state = self.build_state()?;
+        state
+            .listening
+            .store(true, std::sync::atomic::Ordering::Relaxed);
         let state_for_caller = Arc::clone(&state);
 
         // Phantom-session sweeper (defense-in-depth).
@@ -2189,6 +3540,9 @@ impl Server {
         tracing::info!("Plain on {plain_addr}, TLS on {tls_addr}");
 
         let state = self.build_state()?;
+        state
+            .listening
+            .store(true, std::sync::atomic::Ordering::Relaxed);
 
         let handle = tokio::spawn(async move {
             let tls_state = Arc::clone(&state);
@@ -2203,7 +3557,7 @@ impl Server {
                                 match acceptor.accept(stream).await {
                                     Ok(tls_stream) => {
                                         if let Err(e) =
-                                            connection::handle_generic(tls_stream, state).await
+                                            connection::handle_tls(tls_stream, state).await
                                         {
                                             tracing::error!("TLS connection error: {e}");
                                         }
@@ -2291,7 +3645,7 @@ fn spawn_phantom_sweeper(state: Arc<SharedState>) {
 
             // Snapshot the live session_ids the WS layer knows about.
             let live: std::collections::HashSet<String> =
-                { state.connections.lock().keys().cloned().collect() };
+                state.connections.keys().into_iter().collect();
 
             // session_dids: drop entries whose session_id isn't live.
             let leaked_dids: Vec<String> = {
@@ -2348,6 +3702,37 @@ fn spawn_phantom_sweeper(state: Arc<SharedState>) {
     });
 }
 
+/// Union a peer's topic history into `ch`, keyed by (set_at, text), and
+/// keep the newest [`TOPIC_HISTORY_MAX`]. Entries matching the current
+/// topic are skipped. Returns true if anything was added.
+fn merge_topic_history(ch: &mut ChannelState, remote: &[crate::s2s::SyncTopic]) -> bool {
+    let mut added = false;
+    for t in remote.iter().rev().take(TOPIC_HISTORY_MAX) {
+        let text = sanitize_s2s_str(&t.text, 512);
+        let is_current = ch.topic.as_ref().is_some_and(|cur| cur.text == text);
+        let known = ch
+            .topic_history
+            .iter()
+            .any(|h| h.set_at == t.set_at && h.text == text);
+        if is_current || known {
+            continue;
+        }
+        ch.topic_history.push_back(TopicInfo {
+            text,
+            set_by: sanitize_s2s_str(&t.set_by, 200),
+            set_at: t.set_at,
+        });
+        added = true;
+    }
+    if added {
+        ch.topic_history.make_contiguous().sort_by_key(|t| t.set_at);
+        while ch.topic_history.len() > TOPIC_HISTORY_MAX {
+            ch.topic_history.pop_front();
+        }
+    }
+    added
+}
+
 fn sanitize_s2s_str(s: &str, max_len: usize) -> String {
     s.chars()
         .filter(|c| *c != '\r' && *c != '\n' && *c != '\0')
@@ -2355,6 +3740,47 @@ fn sanitize_s2s_str(s: &str, max_len: usize) -> String {
         .collect()
 }
 
+/// Apply S2S events from `rx` to server state, in arrival order.
+pub(crate) fn spawn_s2s_processor(
+    state: Arc<SharedState>,
+    manager: Arc<crate::s2s::S2sManager>,
+    mut rx: tokio::sync::mpsc::Receiver<crate::s2s::AuthenticatedS2sEvent>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            process_s2s_message(&state, &manager, &event.authenticated_peer_id, event.msg).await;
+        }
+    })
+}
+
+/// The latest version of `channel`'s policy chain, announced in sync so
+/// peers can tell when their chains diverged.
+fn policy_head(state: &SharedState, channel: &str) -> Option<crate::s2s::PolicyHead> {
+    let policy = state.policy_engine.as_ref()?.get_policy(channel).ok()??;
+    Some(crate::s2s::PolicyHead {
+        hash: policy.policy_id?,
+        version: policy.version,
+    })
+}
+
+/// Ask `peer_id` for its policy chain for `channel`, to arbitrate.
+async fn request_policy_chain(manager: &crate::s2s::S2sManager, peer_id: &str, channel: &str) {
+    let tx = manager
+        .peers
+        .lock()
+        .await
+        .get(peer_id)
+        .map(|entry| entry.tx.clone());
+    if let Some(tx) = tx {
+        let request = crate::s2s::S2sMessage::PolicyChainRequest {
+            event_id: String::new(),
+            channel: channel.to_string(),
+            origin: manager.server_id.clone(),
+        };
+        let _ = tx.send(request).await;
+    }
+}
+
 /// Process an incoming S2S message. Exposed as pub(crate) for adversarial testing.
 pub(crate) async fn process_s2s_message(
     state: &Arc<SharedState>,
@@ -2406,27 +3832,62 @@ pub(crate) async fn process_s2s_message(
         }
     }
 
+    if state.firehose.is_active() {
+        // The wire form carries the type tag and the channel (or a
+        // channel `target`) — no need to match every variant here.
+        let wire = serde_json::to_value(&msg).unwrap_or_default();
+        let field = |name: &str| wire.get(name).and_then(|v| v.as_str()).map(str::to_string);
+        let channel = field("channel").or_else(|| field("target").filter(|t| t.starts_with('#')));
+        state.firehose.publish(crate::firehose::FirehoseEvent::S2s {
+            peer: authenticated_peer_id.to_string(),
+            kind: field("type").unwrap_or_default(),
+            channel,
+        });
+    }
+
     /// Deliver a raw IRC line to all local members of a channel.
     fn deliver_to_channel(state: &SharedState, channel: &str, line: &str) {
-        let channel_key = channel.to_lowercase();
-        let channels = state.channels.lock();
-        if let Some(ch) = channels.get(&channel_key) {
-            let conns = state.connections.lock();
+        let channel_key = crate::casemap::fold(channel);
+        if let Some(ch) = state.channels.get(&channel_key) {
+            let line = WireLine::copy_from_slice(line.as_bytes());
             for session_id in &ch.members {
-                if let Some(tx) = conns.get(session_id) {
-                    let _ = tx.try_send(line.to_string());
+                if let Some(tx) = state.connections.get(session_id) {
+                    let _ = tx.try_send(line.clone());
                 }
             }
         }
     }
 
+    /// Deliver a remote member's JOIN/PART/QUIT to the local members who
+    /// should see it (everyone, unless +u hides them).
+    fn deliver_membership(state: &SharedState, channel: &str, rm: &RemoteMember, line: &str) {
+        let audience = match state.channels.get(channel) {
+            Some(ch) => ch.remote_audience(rm),
+            None => return,
+        };
+        let line = WireLine::copy_from_slice(line.as_bytes());
+        for session_id in &audience {
+            if let Some(tx) = state.connections.get(session_id) {
+                let _ = tx.try_send(line.clone());
+            }
+        }
+    }
+
     /// Send NAMES update to all local members of a channel (for nick list refresh).
     fn send_names_update(state: &SharedState, channel: &str) {
-        let channels = state.channels.lock();
-        let ch = match channels.get(channel) {
-            Some(ch) => ch,
-            None => return,
+        let Some(ch) = state.channels.get(channel) else {
+            return;
         };
+        // A +u roster is per-viewer and too big to resend on every join;
+        // members rely on the JOIN/PART/QUIT lines they are shown instead.
+        if ch.auditorium {
+            return;
+        }
+        // Same for a roster too big for one implicit NAMES: every member
+        // would get the whole list again, per join.
+        if ch.members.len() + ch.remote_members.len() > crate::connection::names::IMPLICIT_MAX {
+            return;
+        }
 
         // Build nick list (local + remote)
         let n2s = state.nick_to_session.lock();
@@ -2435,49 +3896,36 @@ pub(crate) async fn process_s2s_message(
             .iter()
             .filter_map(|s| {
                 n2s.get_nick(s).map(|n| {
-                    let prefix = if ch.ops.contains(s) {
-                        "@"
-                    } else if ch.halfops.contains(s) {
-                        "%"
-                    } else if ch.voiced.contains(s) {
-                        "+"
-                    } else {
-                        ""
-                    };
+                    let prefix = ch.prefix(s, false);
                     format!("{prefix}{n}")
                 })
             })
             .collect();
         for (nick, rm) in &ch.remote_members {
-            let is_op = rm.is_op
-                || rm.did.as_ref().is_some_and(|d| {
-                    ch.founder_did.as_deref() == Some(d) || ch.did_ops.contains(d)
-                });
-            let prefix = if is_op { "@" } else { "" };
-            nick_list.push(format!("{prefix}{nick}"));
+            let prefix = ch.remote_rank(rm).prefix();
+            nick_list.push(format!("{}{nick}", prefix.map(String::from).unwrap_or_default()));
         }
-        let nick_str = nick_list.join(" ");
 
         // Send to each local member
         let local_members: Vec<String> = ch.members.iter().cloned().collect();
-        drop(channels);
+        drop(ch);
 
-        let conns = state.connections.lock();
         for session_id in &local_members {
             // Look up this member's nick for the reply prefix
             let member_nick = n2s.get_nick(session_id).unwrap_or("*");
-            let names_line = format!(
-                ":{} 353 {} = {} :{}\r\n:{} 366 {} {} :End of /NAMES list\r\n",
-                state.server_name,
-                member_nick,
-                channel,
-                nick_str,
-                state.server_name,
+            let mut names_line = crate::connection::names::reply_lines(
+                &state.server_name,
                 member_nick,
                 channel,
-            );
-            if let Some(tx) = conns.get(session_id) {
-                let _ = tx.try_send(names_line);
+                &nick_list,
+            )
+            .concat();
+            names_line.push_str(&format!(
+                ":{} 366 {} {} :End of /NAMES list\r\n",
+                state.server_name, member_nick, channel,
+            ));
+            if let Some(tx) = state.connections.get(session_id) {
+                let _ = tx.try_send(names_line.into());
             }
         }
     }
@@ -2525,12 +3973,24 @@ pub(crate) async fn process_s2s_message(
         S2sMessage::InviteException {
             event_id, origin, ..
         } => (event_id.clone(), origin.clone()),
+        S2sMessage::Quiet {
+            event_id, origin, ..
+        } => (event_id.clone(), origin.clone()),
         S2sMessage::Invite {
             event_id, origin, ..
         } => (event_id.clone(), origin.clone()),
         S2sMessage::PolicySync {
             event_id, origin, ..
         } => (event_id.clone(), origin.clone()),
+        S2sMessage::PolicyChainRequest {
+            event_id, origin, ..
+        } => (event_id.clone(), origin.clone()),
+        S2sMessage::PolicyChain {
+            event_id, origin, ..
+        } => (event_id.clone(), origin.clone()),
+        S2sMessage::Erase {
+            event_id, origin, ..
+        } => (event_id.clone(), origin.clone()),
         S2sMessage::AvSessionCreated {
             event_id, origin, ..
         } => (event_id.clone(), origin.clone()),
@@ -2544,6 +4004,7 @@ pub(crate) async fn process_s2s_message(
             event_id, origin, ..
         } => (event_id.clone(), origin.clone()),
         S2sMessage::CrdtSync { origin, .. } => (String::new(), origin.clone()),
+        S2sMessage::ChannelDirectory { origin, .. } => (String::new(), origin.clone()),
         S2sMessage::PeerDisconnected { .. } => (String::new(), String::new()),
         S2sMessage::Hello { .. }
         | S2sMessage::HelloAck { .. }
@@ -2581,7 +4042,10 @@ pub(crate) async fn process_s2s_message(
             | S2sMessage::Kick { .. }
             | S2sMessage::Ban { .. }
             | S2sMessage::InviteException { .. }
+            | S2sMessage::Quiet { .. }
             | S2sMessage::Invite { .. }
+            | S2sMessage::Erase { .. }
+            | S2sMessage::PolicyChain { .. }
             | S2sMessage::ChannelCreated { .. }
             | S2sMessage::AvSessionCreated { .. }
             | S2sMessage::AvSessionJoined { .. }
@@ -2602,6 +4066,9 @@ pub(crate) async fn process_s2s_message(
             | S2sMessage::Kick { .. }
             | S2sMessage::Ban { .. }
             | S2sMessage::InviteException { .. }
+            | S2sMessage::Quiet { .. }
+            | S2sMessage::Erase { .. }
+            | S2sMessage::PolicyChain { .. }
             | S2sMessage::ChannelCreated { .. },
             crate::s2s::TrustLevel::Relay,
         ) => {
@@ -2781,13 +4248,17 @@ pub(crate) async fn process_s2s_message(
             // (only peer-trusted) `account` as if this server had verified it.
             let origin_name = sanitize_s2s_str(&manager.peer_display_name(&origin).await, 64);
             relay_tags.insert("+freeq.at/origin".to_string(), origin_name);
+            // Stamp our hop on the message's route, so recipients can
+            // measure cross-server latency.
+            let hop_ms = crate::s2s::add_hop(&mut relay_tags, &state.server_name);
+            tracing::debug!(%origin, %msgid, ?hop_ms, "S2S PRIVMSG received");
 
             // Plain line for non-tag clients, tagged line with msgid + sig for
             // tag clients. `tagged_line_account` additionally carries the
             // `account` tag and is sent only to clients that negotiated
             // `account-tag` (per IRCv3, mirroring local delivery).
-            let plain_line = format!(":{from} PRIVMSG {target} :{text}\r\n");
-            let build_tagged = |with_account: bool| -> String {
+            let plain_line = WireLine::from(format!(":{from} PRIVMSG {target} :{text}\r\n"));
+            let build_tagged = |with_account: bool| -> WireLine {
                 let mut tags = HashMap::new();
                 tags.extend(relay_tags.iter().map(|(k, v)| (k.clone(), v.clone())));
                 tags.insert("msgid".to_string(), msgid.clone());
@@ -2805,16 +4276,15 @@ pub(crate) async fn process_s2s_message(
                     command: "PRIVMSG".to_string(),
                     params: vec![target.clone(), text.clone()],
                 };
-                format!("{tag_msg}\r\n")
+                WireLine::from(format!("{tag_msg}\r\n"))
             };
             let tagged_line = build_tagged(false);
             let tagged_line_account = account.as_ref().map(|_| build_tagged(true));
 
             if target.starts_with('#') || target.starts_with('&') {
-                // Enforce +n and +m on incoming S2S messages
-                let channel_key = target.to_lowercase();
-                let channels = state.channels.lock();
-                if let Some(ch) = channels.get(&channel_key) {
+                // Enforce +n, +m and +q on incoming S2S messages
+                let channel_key = crate::casemap::fold(&target);
+                if let Some(ch) = state.channels.get(&channel_key) {
                     if ch.no_ext_msg {
                         let nick = from.split('!').next().unwrap_or(&from);
                         let is_member = ch.has_remote_member(nick)
@@ -2836,8 +4306,17 @@ pub(crate) async fn process_s2s_message(
                             return;
                         }
                     }
+                    {
+                        let nick = from.split('!').next().unwrap_or(&from);
+                        let rm = ch.remote_member(nick);
+                        let is_privileged = rm.is_some_and(|rm| rm.is_op);
+                        let did = rm.and_then(|rm| rm.did.as_deref());
+                        if !is_privileged && ch.is_quieted(&from, did) {
+                            tracing::debug!(channel = %target, from = %from, "S2S PRIVMSG blocked by +q");
+                            return;
+                        }
+                    }
                 }
-                drop(channels);
 
                 // Store in history + DB
                 {
@@ -2854,8 +4333,7 @@ pub(crate) async fn process_s2s_message(
                     if let Some(ref acct) = account {
                         tags.insert("account".to_string(), acct.clone());
                     }
-                    let mut channels = state.channels.lock();
-                    if let Some(ch) = channels.get_mut(&channel_key) {
+                    if let Some(mut ch) = state.channels.get(&channel_key) {
                         ch.history.push_back(HistoryMessage {
                             from: from.clone(),
                             text: text.clone(),
@@ -2867,7 +4345,6 @@ pub(crate) async fn process_s2s_message(
                             ch.history.pop_front();
                         }
                     }
-                    drop(channels);
                     // Prefer the DID carried from the origin; fall back to a
                     // local nick_owners lookup for peers that didn't send one.
                     let sender_nick = from.split('!').next().unwrap_or(&from);
@@ -2887,20 +4364,16 @@ pub(crate) async fn process_s2s_message(
                             s2s_sender_did.as_deref(),
                         )
                     });
+                    crate::stats::record_message(state, &channel_key, s2s_sender_did.as_deref());
                 }
 
                 // Deliver to local members with tag-awareness
                 let members: Vec<String> = state
                     .channels
-                    .lock()
                     .get(&channel_key)
                     .map(|ch| ch.members.iter().cloned().collect())
                     .unwrap_or_default();
-                let tag_caps = state.cap_message_tags.lock();
-                let time_caps = state.cap_server_time.lock();
-                let account_caps = state.cap_account_tag.lock();
-                let multiline_caps = state.cap_draft_multiline.lock();
-                let conns = state.connections.lock();
+                let conns = &state.connections;
                 // If the peer told us this is a draft/multiline batch,
                 // re-emit per-receiver wire frames (BATCH for capable
                 // receivers, individual PRIVMSGs for fallback) just
@@ -2929,15 +4402,17 @@ pub(crate) async fn process_s2s_message(
                 // origin) for clients that negotiated account-tag, the same
                 // as the single-PRIVMSG path and local delivery.
                 for sid in &members {
+                    let member_caps = state.sessions.caps(sid);
+                    let wants_account = account.is_some() && member_caps.has(Cap::AccountTag);
                     if let Some(tx) = conns.get(sid) {
                         if let (Some(lines), Some(batch_id)) =
                             (local_lines.as_ref(), outbound_batch_id.as_deref())
                         {
                             let caps = crate::connection::draft_multiline::ReceiverCaps {
-                                has_tags: tag_caps.contains(sid),
-                                has_time: time_caps.contains(sid),
-                                has_multiline: multiline_caps.contains(sid),
-                                wants_account: account.is_some() && account_caps.contains(sid),
+                                has_tags: member_caps.has(Cap::MessageTags),
+                                has_time: member_caps.has(Cap::ServerTime),
+                                has_multiline: member_caps.has(Cap::DraftMultiline),
+                                wants_account,
                                 sender_did: account.as_deref(),
                             };
                             // Opener tags here are the relayed
@@ -2965,12 +4440,12 @@ pub(crate) async fn process_s2s_message(
                                     &ctx, &caps,
                                 )
                             {
-                                let _ = tx.try_send(frame);
+                                let _ = tx.try_send(frame.into());
                             }
                         } else {
-                            let line = if !tag_caps.contains(sid) {
+                            let line = if !member_caps.has(Cap::MessageTags) {
                                 &plain_line
-                            } else if account.is_some() && account_caps.contains(sid) {
+                            } else if wants_account {
                                 tagged_line_account.as_ref().unwrap_or(&tagged_line)
                             } else {
                                 &tagged_line
@@ -2987,9 +4462,9 @@ pub(crate) async fn process_s2s_message(
                     .get_session(&target)
                     .map(|s| s.to_string());
                 if let Some(sid) = sid {
-                    let has_tags = state.cap_message_tags.lock().contains(&sid);
-                    let wants_account =
-                        account.is_some() && state.cap_account_tag.lock().contains(&sid);
+                    let caps = state.sessions.caps(&sid);
+                    let has_tags = caps.has(Cap::MessageTags);
+                    let wants_account = account.is_some() && caps.has(Cap::AccountTag);
                     let line = if !has_tags {
                         &plain_line
                     } else if wants_account {
@@ -2997,7 +4472,7 @@ pub(crate) async fn process_s2s_message(
                     } else {
                         &tagged_line
                     };
-                    let conns = state.connections.lock();
+                    let conns = &state.connections;
                     if let Some(tx) = conns.get(&sid) {
                         let _ = tx.try_send(line.clone());
                     }
@@ -3050,12 +4525,11 @@ pub(crate) async fn process_s2s_message(
             adding,
             ..
         } => {
-            let channel = sanitize_s2s_str(&channel, 200).to_lowercase();
+            let channel = crate::casemap::fold(&sanitize_s2s_str(&channel, 200));
             let msgid = sanitize_s2s_str(&msgid, 100);
             let pinned_by = sanitize_s2s_str(&pinned_by, 64);
 
-            let mut channels = state.channels.lock();
-            if let Some(ch) = channels.get_mut(&channel) {
+            if let Some(mut ch) = state.channels.get(&channel) {
                 if adding {
                     if !ch.pins.iter().any(|p| p.msgid == msgid) {
                         let now = std::time::SystemTime::now()
@@ -3071,14 +4545,14 @@ pub(crate) async fn process_s2s_message(
                             },
                         );
                         ch.pins.truncate(50);
-                        drop(channels);
+                        drop(ch);
                         state.with_db(|db| db.store_pin(&channel, &msgid, &pinned_by, now));
                     } else {
-                        drop(channels);
+                        drop(ch);
                     }
                 } else {
                     ch.pins.retain(|p| p.msgid != msgid);
-                    drop(channels);
+                    drop(ch);
                     state.with_db(|db| db.remove_pin(&channel, &msgid));
                 }
 
@@ -3089,17 +4563,16 @@ pub(crate) async fn process_s2s_message(
                     "+freeq.at/unpin"
                 };
                 let action = if adding { "pinned" } else { "unpinned" };
-                let notice = format!(
+                let notice = WireLine::from(format!(
                     "@{tag}={} :{pinned_by}!~u@s2s NOTICE {channel} :\x01ACTION {action} a message\x01\r\n",
                     crate::irc::escape_tag_value(&msgid)
-                );
+                ));
                 let members: Vec<String> = state
                     .channels
-                    .lock()
                     .get(&channel)
                     .map(|ch| ch.members.iter().cloned().collect())
                     .unwrap_or_default();
-                let conns = state.connections.lock();
+                let conns = &state.connections;
                 for sid in &members {
                     if let Some(tx) = conns.get(sid) {
                         let _ = tx.try_send(notice.clone());
@@ -3116,16 +4589,33 @@ pub(crate) async fn process_s2s_message(
 
             // Normalize draft tags
             let mut tags = tags.clone();
-            for (draft, canonical) in [("+draft/react", "+react"), ("+draft/reply", "+reply")] {
+            for (draft, canonical) in freeq_proto::tags::DRAFT_ALIASES {
                 if let Some(v) = tags.remove(draft) {
                     tags.entry(canonical.to_string()).or_insert(v);
                 }
             }
 
+            // Typing / status: relayed like a local update, coalesced in
+            // busy channels. Throttled again per remote nick in case the
+            // peer doesn't.
+            let is_channel = target.starts_with('#') || target.starts_with('&');
+            if is_channel && crate::ephemeral::is_ephemeral(&tags) {
+                if crate::ephemeral::sanitize(&mut tags)
+                    && state.ephemeral.admit(&format!("s2s:{from}"), &target, &tags)
+                {
+                    crate::ephemeral::relay(state, &target, &from, None, tags);
+                }
+                return;
+            }
+
             // Persist reactions
             if let (Some(emoji), Some(target_msgid)) = (tags.get("+react"), tags.get("+reply")) {
                 let nick = from.split('!').next().unwrap_or(&from).to_string();
-                let did = state.nick_owners.lock().get(&nick.to_lowercase()).cloned();
+                let did = state
+                    .nick_owners
+                    .lock()
+                    .get(&crate::casemap::fold(&nick))
+                    .cloned();
                 let ts = std::time::SystemTime::now()
                     .duration_since(std::time::UNIX_EPOCH)
                     .unwrap_or_default()
@@ -3146,23 +4636,24 @@ pub(crate) async fn process_s2s_message(
                     command: "TAGMSG".to_string(),
                     params: vec![target.clone()],
                 };
-                let tagged_line = format!("{tag_msg}\r\n");
+                let tagged_line = WireLine::from(format!("{tag_msg}\r\n"));
 
                 let plain_fallback = tags.get("+react").map(|emoji| {
-                    format!(":{from} PRIVMSG {target} :\x01ACTION reacted with {emoji}\x01\r\n")
+                    WireLine::from(format!(
+                        ":{from} PRIVMSG {target} :\x01ACTION reacted with {emoji}\x01\r\n"
+                    ))
                 });
 
                 let members: Vec<String> = state
                     .channels
-                    .lock()
-                    .get(&target.to_lowercase())
+                    .get(&crate::casemap::fold(&target))
                     .map(|ch| ch.members.iter().cloned().collect())
                     .unwrap_or_default();
-                let tag_caps = state.cap_message_tags.lock();
-                let conns = state.connections.lock();
+                let conns = &state.connections;
                 for sid in &members {
+                    let has_tags = state.sessions.has_cap(sid, Cap::MessageTags);
                     if let Some(tx) = conns.get(sid) {
-                        if tag_caps.contains(sid) {
+                        if has_tags {
                             let _ = tx.try_send(tagged_line.clone());
                         } else if let Some(ref fallback) = plain_fallback {
                             let _ = tx.try_send(fallback.clone());
@@ -3184,34 +4675,31 @@ pub(crate) async fn process_s2s_message(
         } => {
             // Sanitize peer-provided strings to prevent IRC protocol injection.
             let nick = sanitize_s2s_str(&nick, 64);
-            let channel = sanitize_s2s_str(&channel, 200).to_lowercase();
+            let channel = crate::casemap::fold(&sanitize_s2s_str(&channel, 200));
 
             // ── S2S authorization: enforce bans and +i ──
-            {
-                let channels = state.channels.lock();
-                if let Some(ch) = channels.get(&channel) {
-                    // Check +i (invite only) — but allow if user has an invite
-                    if ch.invite_only {
-                        let has_invite = did.as_ref().is_some_and(|d| ch.invites.contains(d))
-                            || ch.invites.contains(&format!("nick:{nick}"));
-                        if !has_invite {
-                            tracing::info!(
-                                channel = %channel, nick = %nick,
-                                "S2S Join rejected: channel is +i (invite only)"
-                            );
-                            return;
-                        }
-                    }
-                    // Check bans
-                    let hostmask = format!("{nick}!{nick}@s2s");
-                    if ch.is_banned(&hostmask, did.as_deref()) {
+            if let Some(ch) = state.channels.get(&channel) {
+                // Check +i (invite only) — but allow if user has an invite
+                if ch.invite_only {
+                    let has_invite = did.as_ref().is_some_and(|d| ch.invites.contains(d))
+                        || ch.invites.contains(&format!("nick:{nick}"));
+                    if !has_invite {
                         tracing::info!(
                             channel = %channel, nick = %nick,
-                            "S2S Join rejected: user is banned"
+                            "S2S Join rejected: channel is +i (invite only)"
                         );
                         return;
                     }
                 }
+                // Check bans
+                let hostmask = format!("{nick}!{nick}@s2s");
+                if ch.is_banned(&hostmask, did.as_deref()) {
+                    tracing::info!(
+                        channel = %channel, nick = %nick,
+                        "S2S Join rejected: user is banned"
+                    );
+                    return;
+                }
             }
 
             // Validate DID format if provided — reject obviously bogus values
@@ -3235,8 +4723,7 @@ pub(crate) async fn process_s2s_message(
             // Presence is S2S-event-only (NOT in CRDT — avoids ghost users)
             // Idempotent: set-based, don't assume not present
             {
-                let mut channels = state.channels.lock();
-                let ch = channels.entry(channel.clone()).or_default();
+                let mut ch = state.channels.get_or_insert_with(&channel, Default::default);
                 // Consume invite (all forms: DID, nick)
                 if let Some(ref d) = did {
                     ch.invites.remove(d);
@@ -3259,6 +4746,13 @@ pub(crate) async fn process_s2s_message(
                     },
                 );
             }
+            let Some(member) = state
+                .channels
+                .get(&channel)
+                .and_then(|ch| ch.remote_members.get(&nick).cloned())
+            else {
+                return;
+            };
 
             // Include actor_class tag for tag-capable clients
             let line = if let Some(ref ac) = actor_class {
@@ -3266,40 +4760,38 @@ pub(crate) async fn process_s2s_message(
             } else {
                 format!(":{nick}!{nick}@s2s JOIN {channel}\r\n")
             };
-            deliver_to_channel(state, &channel, &line);
+            deliver_membership(state, &channel, &member, &line);
             send_names_update(state, &channel);
         }
 
         S2sMessage::Part { nick, channel, .. } => {
-            let channel = channel.to_lowercase();
+            let channel = crate::casemap::fold(&channel);
             // Presence is S2S-event-only. Idempotent: remove if present.
-            {
-                let mut channels = state.channels.lock();
-                if let Some(ch) = channels.get_mut(&channel) {
-                    ch.remove_remote_member(&nick);
-                }
-            }
+            let removed = state
+                .channels
+                .get(&channel)
+                .and_then(|mut ch| ch.remove_remote_member(&nick));
 
             let line = format!(":{nick}!{nick}@s2s PART {channel}\r\n");
-            deliver_to_channel(state, &channel, &line);
+            match removed {
+                Some(member) => deliver_membership(state, &channel, &member, &line),
+                None => deliver_to_channel(state, &channel, &line),
+            }
             send_names_update(state, &channel);
         }
 
         S2sMessage::Quit { nick, reason, .. } => {
             // Remove remote member from all channels (idempotent)
             let mut affected_channels = Vec::new();
-            {
-                let mut channels = state.channels.lock();
-                for (name, ch) in channels.iter_mut() {
-                    if ch.remove_remote_member(&nick).is_some() {
-                        affected_channels.push(name.clone());
-                    }
+            state.channels.for_each_mut(|name, ch| {
+                if let Some(member) = ch.remove_remote_member(&nick) {
+                    affected_channels.push((name.to_string(), member));
                 }
-            }
+            });
 
             let line = format!(":{nick}!{nick}@s2s QUIT :{reason}\r\n");
-            for ch_name in &affected_channels {
-                deliver_to_channel(state, ch_name, &line);
+            for (ch_name, member) in &affected_channels {
+                deliver_membership(state, ch_name, member, &line);
                 send_names_update(state, ch_name);
             }
         }
@@ -3310,7 +4802,7 @@ pub(crate) async fn process_s2s_message(
             set_by,
             ..
         } => {
-            let channel = sanitize_s2s_str(&channel, 200).to_lowercase();
+            let channel = crate::casemap::fold(&sanitize_s2s_str(&channel, 200));
             let topic = sanitize_s2s_str(&topic, 512);
             let set_by = sanitize_s2s_str(&set_by, 200);
             // CRDT is the single source of truth for topic convergence.
@@ -3320,8 +4812,7 @@ pub(crate) async fn process_s2s_message(
 
             // ── S2S authorization: enforce +t locally ──
             {
-                let channels = state.channels.lock();
-                if let Some(ch) = channels.get(&channel)
+                if let Some(ch) = state.channels.get(&channel)
                     && ch.topic_locked
                 {
                     let is_authorized = ch.remote_member(&set_by).is_some_and(|rm| {
@@ -3341,21 +4832,21 @@ pub(crate) async fn process_s2s_message(
             }
 
             // Write to CRDT (source of truth)
-            let setter_did = {
-                let channels = state.channels.lock();
-                channels
-                    .get(&channel)
-                    .and_then(|ch| ch.remote_member(&set_by).and_then(|rm| rm.did.clone()))
-            };
+            let setter_did = state
+                .channels
+                .get(&channel)
+                .and_then(|ch| ch.remote_member(&set_by).and_then(|rm| rm.did.clone()));
             state
                 .crdt_set_topic(&channel, &topic, &set_by, setter_did.as_deref())
                 .await;
 
             // Apply locally for immediate UX (CRDT is authoritative if they diverge)
-            {
-                let mut channels = state.channels.lock();
-                let ch = channels.entry(channel.clone()).or_default();
-                ch.topic = Some(TopicInfo::new(topic.clone(), set_by.clone()));
+            let history_changed = state
+                .channels
+                .get_or_insert_with(&channel, Default::default)
+                .set_topic(TopicInfo::new(topic.clone(), set_by.clone()));
+            if history_changed {
+                state.persist_topic_history(&channel);
             }
 
             let line = format!(":{set_by}!remote@s2s TOPIC {channel} :{topic}\r\n");
@@ -3369,12 +4860,10 @@ pub(crate) async fn process_s2s_message(
             origin,
             ..
         } => {
-            let channel = channel.to_lowercase();
+            let channel = crate::casemap::fold(&channel);
             let has_local_members;
             {
-                let mut channels = state.channels.lock();
-                let is_new = !channels.contains_key(&channel);
-                let ch = channels.entry(channel.clone()).or_default();
+                let (mut ch, is_new) = state.channels.get_or_create(&channel, Default::default);
                 // New channels get +nt defaults
                 if is_new {
                     ch.no_ext_msg = true;
@@ -3473,57 +4962,72 @@ pub(crate) async fn process_s2s_message(
 
         S2sMessage::SyncRequest => {
             let response = {
-                let channels = state.channels.lock();
-                let n2s = state.nick_to_session.lock();
-
-                let dids = state.session_dids.lock();
-                let actor_classes = state.session_actor_class.lock();
-                let channel_info: Vec<crate::s2s::ChannelInfo> = channels
-                    .iter()
-                    .map(|(name, ch)| {
-                        let nicks: Vec<String> = ch
-                            .members
+                // The per-session tables come after a channel shard in the
+                // lock order, so take them per channel.
+                let mut channel_info = Vec::new();
+                state.channels.for_each(|name, ch| {
+                    let n2s = state.nick_to_session.lock();
+                    let dids = state.session_dids.lock();
+                    let actor_classes = state.session_actor_class.lock();
+                    let nicks: Vec<String> = ch
+                        .members
+                        .iter()
+                        .filter_map(|sid| n2s.get_nick(sid).map(|n| n.to_string()))
+                        .collect();
+                    let nick_info: Vec<crate::s2s::SyncNick> = ch
+                        .members
+                        .iter()
+                        .filter_map(|sid| {
+                            n2s.get_nick(sid).map(|n| {
+                                let ac = actor_classes.get(sid).map(|c| c.to_string());
+                                crate::s2s::SyncNick {
+                                    nick: n.to_string(),
+                                    is_op: ch.ops.contains(sid),
+                                    did: dids.get(sid).cloned(),
+                                    actor_class: ac,
+                                }
+                            })
+                        })
+                        .collect();
+                    channel_info.push(crate::s2s::ChannelInfo {
+                        name: name.to_string(),
+                        topic: ch.topic.as_ref().map(|t| t.text.clone()),
+                        nicks,
+                        nick_info,
+                        founder_did: ch.founder_did.clone(),
+                        did_ops: ch.did_ops.iter().cloned().collect(),
+                        created_at: ch.created_at,
+                        topic_locked: ch.topic_locked,
+                        invite_only: ch.invite_only,
+                        no_ext_msg: ch.no_ext_msg,
+                        moderated: ch.moderated,
+                        archived: ch.archived,
+                        auditorium: ch.auditorium,
+                        no_stats: ch.no_stats,
+                        secret: ch.secret,
+                        temporary: ch.temporary.map(|t| t.to_string()),
+                        history_visibility: Some(ch.history_visibility.as_str().to_string()),
+                        key: ch.key.clone(),
+                        bans: ch.bans.iter().map(|b| b.mask.clone()).collect(),
+                        invites: ch.invites.iter().cloned().collect(),
+                        invite_exceptions: ch
+                            .invite_exceptions
                             .iter()
-                            .filter_map(|sid| n2s.get_nick(sid).map(|n| n.to_string()))
-                            .collect();
-                        let nick_info: Vec<crate::s2s::SyncNick> = ch
-                            .members
+                            .map(|e| e.mask.clone())
+                            .collect(),
+                        quiets: ch.quiets.iter().map(|q| q.mask.clone()).collect(),
+                        topic_history: ch
+                            .topic_history
                             .iter()
-                            .filter_map(|sid| {
-                                n2s.get_nick(sid).map(|n| {
-                                    let ac = actor_classes.get(sid).map(|c| c.to_string());
-                                    crate::s2s::SyncNick {
-                                        nick: n.to_string(),
-                                        is_op: ch.ops.contains(sid),
-                                        did: dids.get(sid).cloned(),
-                                        actor_class: ac,
-                                    }
-                                })
+                            .map(|t| crate::s2s::SyncTopic {
+                                text: t.text.clone(),
+                                set_by: t.set_by.clone(),
+                                set_at: t.set_at,
                             })
-                            .collect();
-                        crate::s2s::ChannelInfo {
-                            name: name.clone(),
-                            topic: ch.topic.as_ref().map(|t| t.text.clone()),
-                            nicks,
-                            nick_info,
-                            founder_did: ch.founder_did.clone(),
-                            did_ops: ch.did_ops.iter().cloned().collect(),
-                            created_at: ch.created_at,
-                            topic_locked: ch.topic_locked,
-                            invite_only: ch.invite_only,
-                            no_ext_msg: ch.no_ext_msg,
-                            moderated: ch.moderated,
-                            key: ch.key.clone(),
-                            bans: ch.bans.iter().map(|b| b.mask.clone()).collect(),
-                            invites: ch.invites.iter().cloned().collect(),
-                            invite_exceptions: ch
-                                .invite_exceptions
-                                .iter()
-                                .map(|e| e.mask.clone())
-                                .collect(),
-                        }
-                    })
-                    .collect();
+                            .collect(),
+                        policy_head: policy_head(state, name),
+                    });
+                });
 
                 S2sMessage::SyncResponse {
                     server_id: manager.server_id.clone(),
@@ -3531,6 +5035,7 @@ pub(crate) async fn process_s2s_message(
                 }
             };
             manager.broadcast(response);
+            crate::directory::announce(state, manager);
             state.crdt_broadcast_sync().await;
         }
 
@@ -3555,21 +5060,35 @@ pub(crate) async fn process_s2s_message(
                 "Received sync: {} channel(s) from peer {peer_id}",
                 remote_channels.len()
             );
+            // Policy heads we don't hold mean the peer is ahead of us or the
+            // chains forked during a partition: fetch its chain to arbitrate.
+            let wanted_chains: Vec<String> = match state.policy_engine {
+                Some(ref engine) => remote_channels
+                    .iter()
+                    .filter(|info| {
+                        info.policy_head.as_ref().is_some_and(|head| {
+                            matches!(engine.store().get_policy_by_hash(&head.hash), Ok(None))
+                        })
+                    })
+                    .map(|info| info.name.clone())
+                    .collect(),
+                None => Vec::new(),
+            };
             let mut updated_channels = Vec::new();
             // Topics adopted from this snapshot get seeded into the CRDT
             // (after the lock drops) so topic state has exactly one
             // authority. (channel, topic, set_by)
             let mut adopted_topics: Vec<(String, String, String)> = Vec::new();
+            // Channels whose topic history grew from this snapshot.
+            let mut merged_histories = Vec::new();
             {
-                let mut channels = state.channels.lock();
-
                 // Clear stale remote members from this peer before merging.
                 // SyncResponse is a full state snapshot — any remote members
                 // from this peer that aren't in the response are gone.
                 // This prevents ghost users after a peer restarts with fewer members.
                 let synced_channel_names: std::collections::HashSet<String> =
                     remote_channels.iter().map(|i| i.name.clone()).collect();
-                for (name, ch) in channels.iter_mut() {
+                state.channels.for_each_mut(|name, ch| {
                     if synced_channel_names.contains(name) {
                         // Will be replaced below per-channel
                         ch.remote_members.retain(|_nick, rm| rm.origin != peer_id);
@@ -3577,11 +5096,11 @@ pub(crate) async fn process_s2s_message(
                         // Peer didn't mention this channel — remove their members from it
                         ch.remote_members.retain(|_nick, rm| rm.origin != peer_id);
                     }
-                }
+                });
 
                 for info in remote_channels {
-                    let is_new = !channels.contains_key(&info.name);
-                    let ch = channels.entry(info.name.clone()).or_default();
+                    let (mut ch, is_new) = state.channels.get_or_create(&info.name, Default::default);
+                    let ch = &mut *ch;
                     // New channels created via sync get +nt by default
                     if is_new {
                         ch.no_ext_msg = true;
@@ -3674,6 +5193,11 @@ pub(crate) async fn process_s2s_message(
                         adopted_topics.push((info.name.clone(), topic.clone(), set_by));
                     }
 
+                    let remote_visibility = info
+                        .history_visibility
+                        .as_deref()
+                        .and_then(HistoryVisibility::parse);
+
                     // Only adopt remote channel modes if channel has no local
                     // members. If locals are present, they set modes authoritatively
                     // and a SyncResponse shouldn't overwrite them (e.g., a peer
@@ -3683,6 +5207,17 @@ pub(crate) async fn process_s2s_message(
                         ch.invite_only = info.invite_only;
                         ch.no_ext_msg = info.no_ext_msg;
                         ch.moderated = info.moderated;
+                        ch.archived = info.archived;
+                        ch.auditorium = info.auditorium;
+                        ch.no_stats = info.no_stats;
+                        ch.secret = info.secret;
+                        ch.temporary = info
+                            .temporary
+                            .as_deref()
+                            .and_then(crate::temporary::Temporary::parse);
+                        if let Some(visibility) = remote_visibility {
+                            ch.history_visibility = visibility;
+                        }
                         // Full snapshot adoption includes key REMOVAL: with no
                         // local members there is no local authority to protect,
                         // and refusing None here is what made -k unable to
@@ -3707,6 +5242,9 @@ pub(crate) async fn process_s2s_message(
                         if info.key.is_some() && ch.key.is_none() {
                             ch.key = info.key.clone();
                         }
+                        if remote_visibility == Some(HistoryVisibility::MembersSinceJoin) {
+                            ch.history_visibility = HistoryVisibility::MembersSinceJoin;
+                        }
                     }
 
                     // Merge bans from remote (additive — don't remove local bans)
@@ -3738,6 +5276,24 @@ pub(crate) async fn process_s2s_message(
                         }
                     }
 
+                    // Merge quiets (+q) from remote (additive)
+                    for mask in &info.quiets {
+                        if !ch.quiets.iter().any(|q| q.mask == *mask) {
+                            ch.quiets.push(BanEntry {
+                                mask: mask.clone(),
+                                set_by: format!("s2s:{}", peer_id),
+                                set_at: std::time::SystemTime::now()
+                                    .duration_since(std::time::UNIX_EPOCH)
+                                    .unwrap_or_default()
+                                    .as_secs(),
+                            });
+                        }
+                    }
+
+                    if merge_topic_history(ch, &info.topic_history) {
+                        merged_histories.push(info.name.clone());
+                    }
+
                     // Merge invites from remote (additive — don't remove local
                     // invites). Only accept when the peer demonstrates authority
                     // over the channel: its snapshot must name the founder we
@@ -3762,6 +5318,7 @@ pub(crate) async fn process_s2s_message(
                     }
 
                     let dids = state.session_dids.lock();
+                    ch.refresh_founders(&dids);
                     let members: Vec<String> = ch.members.iter().cloned().collect();
 
                     // First pass: grant ops to DID-backed users with authority
@@ -3785,7 +5342,7 @@ pub(crate) async fn process_s2s_message(
                             let has_did_auth = dids.get(session_id).is_some_and(|did| {
                                 ch.founder_did.as_deref() == Some(did) || ch.did_ops.contains(did)
                             });
-                            if !has_did_auth {
+                            if !has_did_auth && !ch.admins.contains(session_id) {
                                 ch.ops.remove(session_id);
                             }
                         }
@@ -3806,6 +5363,10 @@ pub(crate) async fn process_s2s_message(
                 }
             }
 
+            for channel in &merged_histories {
+                state.persist_topic_history(channel);
+            }
+
             // Seed sync-adopted topics into the CRDT — but never compete with
             // an existing CRDT topic (reconciliation will adopt that one).
             for (channel, topic, set_by) in adopted_topics {
@@ -3816,20 +5377,22 @@ pub(crate) async fn process_s2s_message(
 
             for channel in &updated_channels {
                 send_names_update(state, channel);
-                let topic_info = state.channels.lock().get(channel).and_then(|ch| {
+                let topic_info = state.channels.get(channel).and_then(|ch| {
                     ch.topic
                         .as_ref()
                         .map(|t| (t.text.clone(), t.set_by.clone()))
                 });
                 if let Some((topic, _set_by)) = topic_info {
-                    let line = format!(":{} 332 * {} :{}\r\n", state.server_name, channel, topic,);
+                    let line = WireLine::from(format!(
+                        ":{} 332 * {} :{}\r\n",
+                        state.server_name, channel, topic,
+                    ));
                     let members: Vec<String> = state
                         .channels
-                        .lock()
                         .get(channel)
                         .map(|ch| ch.members.iter().cloned().collect())
                         .unwrap_or_default();
-                    let conns = state.connections.lock();
+                    let conns = &state.connections;
                     for session_id in &members {
                         if let Some(tx) = conns.get(session_id) {
                             let _ = tx.try_send(line.clone());
@@ -3837,6 +5400,10 @@ pub(crate) async fn process_s2s_message(
                     }
                 }
             }
+
+            for channel in wanted_chains {
+                request_policy_chain(manager, authenticated_peer_id, &channel).await;
+            }
         }
 
         S2sMessage::Mode {
@@ -3846,12 +5413,11 @@ pub(crate) async fn process_s2s_message(
             set_by,
             ..
         } => {
-            let channel = channel.to_lowercase();
+            let channel = crate::casemap::fold(&channel);
 
             // ── S2S authorization: verify the setter is an op ──
             {
-                let channels = state.channels.lock();
-                if let Some(ch) = channels.get(&channel) {
+                if let Some(ch) = state.channels.get(&channel) {
                     let is_authorized = ch.remote_member(&set_by).is_some_and(|rm| {
                         rm.is_op
                             || rm.did.as_ref().is_some_and(|d| {
@@ -3869,8 +5435,7 @@ pub(crate) async fn process_s2s_message(
             }
 
             {
-                let mut channels = state.channels.lock();
-                if let Some(ch) = channels.get_mut(&channel) {
+                if let Some(mut ch) = state.channels.get(&channel) {
                     let adding = mode.starts_with('+');
                     let mode_char = mode.chars().last().unwrap_or(' ');
                     match mode_char {
@@ -3878,6 +5443,39 @@ pub(crate) async fn process_s2s_message(
                         'i' => ch.invite_only = adding,
                         'n' => ch.no_ext_msg = adding,
                         'm' => ch.moderated = adding,
+                        'A' => ch.archived = adding,
+                        'u' => ch.auditorium = adding,
+                        'S' => ch.no_stats = adding,
+                        's' => ch.secret = adding,
+                        'B' => ch.bots_restricted = adding,
+                        'W' => {
+                            if let Some(mask) = arg.as_deref() {
+                                if !adding {
+                                    ch.bot_allowlist.retain(|w| w.mask != mask);
+                                } else if ch.bot_allowlist.len() < MAX_BOT_ALLOWLIST_PER_CHANNEL
+                                    && !ch.bot_allowlist.iter().any(|w| w.mask == mask)
+                                {
+                                    ch.bot_allowlist
+                                        .push(BanEntry::new(mask.to_string(), set_by.clone()));
+                                }
+                            }
+                        }
+                        'T' => {
+                            ch.temporary = if adding {
+                                arg.as_deref().and_then(crate::temporary::Temporary::parse)
+                            } else {
+                                None
+                            };
+                        }
+                        'H' => {
+                            ch.history_visibility = if adding {
+                                arg.as_deref()
+                                    .and_then(HistoryVisibility::parse)
+                                    .unwrap_or(ch.history_visibility)
+                            } else {
+                                HistoryVisibility::default()
+                            };
+                        }
                         'k' => {
                             if adding {
                                 ch.key = arg.clone();
@@ -3951,6 +5549,9 @@ pub(crate) async fn process_s2s_message(
                     }
                 }
             }
+            if mode == "+S" {
+                crate::stats::forget(state, &channel);
+            }
             let mode_line = if let Some(ref a) = arg {
                 format!(":{set_by}!remote@s2s MODE {channel} {mode} {a}\r\n")
             } else {
@@ -3969,12 +5570,11 @@ pub(crate) async fn process_s2s_message(
             // A remote op kicked a user — if the user is local, remove them
             // from the channel and notify them. If the user is a remote member
             // from yet another server, remove from remote_members.
-            let channel_key = channel.to_lowercase();
+            let channel_key = crate::casemap::fold(&channel);
 
             // ── S2S authorization: verify the kicker is an op ──
             {
-                let channels = state.channels.lock();
-                if let Some(ch) = channels.get(&channel_key) {
+                if let Some(ch) = state.channels.get(&channel_key) {
                     let is_authorized = ch.remote_member(&by).is_some_and(|rm| {
                         rm.is_op
                             || rm.did.as_ref().is_some_and(|d| {
@@ -4003,12 +5603,14 @@ pub(crate) async fn process_s2s_message(
             if let Some(ref sid) = target_session {
                 // Target is local — broadcast KICK to channel, remove member
                 deliver_to_channel(state, &channel_key, &kick_line);
-                let mut channels = state.channels.lock();
-                if let Some(ch) = channels.get_mut(&channel_key) {
+                if let Some(mut ch) = state.channels.get(&channel_key) {
                     let removed = ch.members.remove(sid);
+                    ch.member_since.remove(sid);
                     ch.ops.remove(sid);
                     ch.voiced.remove(sid);
                     ch.halfops.remove(sid);
+                    ch.founders.remove(sid);
+                    ch.admins.remove(sid);
                     tracing::info!(
                         nick = %nick, channel = %channel_key, removed = removed,
                         "S2S Kick: removed local user from channel"
@@ -4021,13 +5623,11 @@ pub(crate) async fn process_s2s_message(
                 }
             } else {
                 // Target is a remote member from another peer — remove and notify locals
-                let removed = {
-                    let mut channels = state.channels.lock();
-                    channels
-                        .get_mut(&channel_key)
-                        .and_then(|ch| ch.remove_remote_member(&nick))
-                        .is_some()
-                };
+                let removed = state
+                    .channels
+                    .get(&channel_key)
+                    .and_then(|mut ch| ch.remove_remote_member(&nick))
+                    .is_some();
                 if removed {
                     deliver_to_channel(state, &channel_key, &kick_line);
                 }
@@ -4041,12 +5641,11 @@ pub(crate) async fn process_s2s_message(
             adding,
             ..
         } => {
-            let channel_key = channel.to_lowercase();
+            let channel_key = crate::casemap::fold(&channel);
 
             // Authorization: verify set_by is an op
             {
-                let channels = state.channels.lock();
-                if let Some(ch) = channels.get(&channel_key) {
+                if let Some(ch) = state.channels.get(&channel_key) {
                     let is_authorized = ch.remote_member(&set_by).is_some_and(|rm| {
                         rm.is_op
                             || rm.did.as_ref().is_some_and(|d| {
@@ -4067,8 +5666,7 @@ pub(crate) async fn process_s2s_message(
             let mode_line = format!(":{set_by}!remote@s2s MODE {channel} {mode_char} {mask}\r\n");
 
             {
-                let mut channels = state.channels.lock();
-                if let Some(ch) = channels.get_mut(&channel_key) {
+                if let Some(mut ch) = state.channels.get(&channel_key) {
                     if adding {
                         if !ch.bans.iter().any(|b| b.mask == mask) {
                             ch.bans.push(crate::server::BanEntry {
@@ -4081,7 +5679,61 @@ pub(crate) async fn process_s2s_message(
                             });
                         }
                     } else {
-                        ch.bans.retain(|b| b.mask != mask);
+                        ch.bans.retain(|b| b.mask != mask);
+                    }
+                }
+            }
+
+            deliver_to_channel(state, &channel_key, &mode_line);
+        }
+
+        S2sMessage::InviteException {
+            channel,
+            mask,
+            set_by,
+            adding,
+            ..
+        } => {
+            let channel_key = crate::casemap::fold(&channel);
+
+            // Authorization: verify set_by is an op (mirror of Ban)
+            {
+                if let Some(ch) = state.channels.get(&channel_key) {
+                    let is_authorized = ch.remote_member(&set_by).is_some_and(|rm| {
+                        rm.is_op
+                            || rm.did.as_ref().is_some_and(|d| {
+                                ch.founder_did.as_deref() == Some(d) || ch.did_ops.contains(d)
+                            })
+                    });
+                    if !is_authorized {
+                        tracing::warn!(
+                            channel = %channel_key, set_by = %set_by,
+                            "S2S InviteException rejected: setter is not an authorized op"
+                        );
+                        return;
+                    }
+                }
+            }
+
+            let mode_char = if adding { "+I" } else { "-I" };
+            let mode_line = format!(":{set_by}!remote@s2s MODE {channel} {mode_char} {mask}\r\n");
+
+            {
+                if let Some(mut ch) = state.channels.get(&channel_key) {
+                    if adding {
+                        if !ch.invite_exceptions.iter().any(|e| e.mask == mask) {
+                            ch.invite_exceptions
+                                .push(crate::server::InviteExceptionEntry {
+                                    mask: mask.clone(),
+                                    set_by: set_by.clone(),
+                                    set_at: std::time::SystemTime::now()
+                                        .duration_since(std::time::UNIX_EPOCH)
+                                        .unwrap_or_default()
+                                        .as_secs(),
+                                });
+                        }
+                    } else {
+                        ch.invite_exceptions.retain(|e| e.mask != mask);
                     }
                 }
             }
@@ -4089,55 +5741,56 @@ pub(crate) async fn process_s2s_message(
             deliver_to_channel(state, &channel_key, &mode_line);
         }
 
-        S2sMessage::InviteException {
+        S2sMessage::Quiet {
             channel,
             mask,
             set_by,
             adding,
             ..
         } => {
-            let channel_key = channel.to_lowercase();
+            let channel_key = crate::casemap::fold(&channel);
 
-            // Authorization: verify set_by is an op (mirror of Ban)
+            // Authorization: verify set_by is an op, or a moderator holding
+            // an appointment credential for this channel (they're +h locally,
+            // but halfop status doesn't travel over S2S).
             {
-                let channels = state.channels.lock();
-                if let Some(ch) = channels.get(&channel_key) {
+                if let Some(ch) = state.channels.get(&channel_key) {
                     let is_authorized = ch.remote_member(&set_by).is_some_and(|rm| {
                         rm.is_op
                             || rm.did.as_ref().is_some_and(|d| {
-                                ch.founder_did.as_deref() == Some(d) || ch.did_ops.contains(d)
+                                ch.founder_did.as_deref() == Some(d)
+                                    || ch.did_ops.contains(d)
+                                    || is_credentialed_moderator(state, &channel_key, d)
                             })
                     });
                     if !is_authorized {
                         tracing::warn!(
                             channel = %channel_key, set_by = %set_by,
-                            "S2S InviteException rejected: setter is not an authorized op"
+                            "S2S Quiet rejected: setter is not an authorized op or moderator"
                         );
                         return;
                     }
                 }
             }
 
-            let mode_char = if adding { "+I" } else { "-I" };
+            let mode_char = if adding { "+q" } else { "-q" };
             let mode_line = format!(":{set_by}!remote@s2s MODE {channel} {mode_char} {mask}\r\n");
 
             {
-                let mut channels = state.channels.lock();
-                if let Some(ch) = channels.get_mut(&channel_key) {
+                if let Some(mut ch) = state.channels.get(&channel_key) {
                     if adding {
-                        if !ch.invite_exceptions.iter().any(|e| e.mask == mask) {
-                            ch.invite_exceptions
-                                .push(crate::server::InviteExceptionEntry {
-                                    mask: mask.clone(),
-                                    set_by: set_by.clone(),
-                                    set_at: std::time::SystemTime::now()
-                                        .duration_since(std::time::UNIX_EPOCH)
-                                        .unwrap_or_default()
-                                        .as_secs(),
-                                });
+                        if !ch.quiets.iter().any(|q| q.mask == mask) {
+                            ch.quiets.push(crate::server::BanEntry {
+                                mask: mask.clone(),
+                                set_by: set_by.clone(),
+                                set_at: std::time::SystemTime::now()
+                                    .duration_since(std::time::UNIX_EPOCH)
+                                    .unwrap_or_default()
+                                    .as_secs(),
+                            });
                         }
                     } else {
-                        ch.invite_exceptions.retain(|e| e.mask != mask);
+                        ch.quiets.retain(|q| q.mask != mask);
                     }
                 }
             }
@@ -4151,12 +5804,11 @@ pub(crate) async fn process_s2s_message(
             invited_by,
             ..
         } => {
-            let channel_key = channel.to_lowercase();
+            let channel_key = crate::casemap::fold(&channel);
 
             // Authorization: verify invited_by is a member (and op if +i)
             {
-                let channels = state.channels.lock();
-                if let Some(ch) = channels.get(&channel_key) {
+                if let Some(ch) = state.channels.get(&channel_key) {
                     let rm = ch.remote_member(&invited_by);
                     let is_member = rm.is_some();
                     if !is_member {
@@ -4186,8 +5838,7 @@ pub(crate) async fn process_s2s_message(
 
             // Add the invite
             {
-                let mut channels = state.channels.lock();
-                if let Some(ch) = channels.get_mut(&channel_key) {
+                if let Some(mut ch) = state.channels.get(&channel_key) {
                     ch.invites.insert(invitee.clone());
                     tracing::debug!(
                         channel = %channel_key, invitee = %invitee,
@@ -4199,21 +5850,19 @@ pub(crate) async fn process_s2s_message(
         }
 
         S2sMessage::NickChange { old, new, .. } => {
-            let line = format!(":{old}!remote@s2s NICK :{new}\r\n");
+            let line = WireLine::from(format!(":{old}!remote@s2s NICK :{new}\r\n"));
 
-            let mut channels = state.channels.lock();
             let mut affected_sessions = std::collections::HashSet::new();
-            for ch in channels.values_mut() {
+            state.channels.for_each_mut(|_, ch| {
                 if let Some(rm) = ch.remove_remote_member(&old) {
                     ch.remote_members.insert(new.clone(), rm);
                     for s in &ch.members {
                         affected_sessions.insert(s.clone());
                     }
                 }
-            }
-            drop(channels);
+            });
 
-            let conns = state.connections.lock();
+            let conns = &state.connections;
             for session_id in &affected_sessions {
                 if let Some(tx) = conns.get(session_id) {
                     let _ = tx.try_send(line.clone());
@@ -4229,15 +5878,50 @@ pub(crate) async fn process_s2s_message(
         } => {
             // A peer has created/updated/cleared a policy — apply locally
             if let Some(ref engine) = state.policy_engine {
-                let channel_key = channel.to_lowercase();
+                let channel_key = crate::casemap::fold(&channel);
                 if let Some(ref pj) = policy_json {
                     // Policy created or updated
                     if let Ok(policy) = serde_json::from_str::<crate::policy::PolicyDocument>(pj) {
+                        let current = engine.get_policy(&channel_key).ok().flatten();
+                        let extends_ours = policy.previous_policy_hash
+                            == current.as_ref().and_then(|c| c.policy_id.clone())
+                            && policy.version == current.as_ref().map_or(0, |c| c.version) + 1;
+                        if !extends_ours {
+                            // Not the next version of our chain: either we
+                            // already have it, or the chains forked.
+                            let known = policy.policy_id.as_ref().is_some_and(|id| {
+                                matches!(engine.store().get_policy_by_hash(id), Ok(Some(_)))
+                            });
+                            if !known {
+                                tracing::info!(channel = %channel_key, "S2S PolicySync: policy doesn't extend ours, fetching peer's chain");
+                                request_policy_chain(manager, authenticated_peer_id, &channel_key)
+                                    .await;
+                            }
+                            return;
+                        }
+                        // It must be signed by the authority set in force
+                        let sent = authority_set_json.as_deref().and_then(|asj| {
+                            serde_json::from_str::<crate::policy::AuthoritySet>(asj).ok()
+                        });
+                        let lookup = |hash: &str| {
+                            sent.clone()
+                                .filter(|set| {
+                                    crate::policy::chain::authority_set_hash(set)
+                                        .is_ok_and(|h| h == hash)
+                                })
+                                .or_else(|| engine.store().get_authority_set(hash).ok().flatten())
+                        };
+                        if let Err(e) = crate::policy::chain::verify_next(
+                            &channel_key,
+                            current.as_ref(),
+                            &policy,
+                            &lookup,
+                        ) {
+                            tracing::warn!(channel = %channel_key, peer = %authenticated_peer_id, "S2S PolicySync rejected: {e}");
+                            return;
+                        }
                         // Store the authority set if provided
-                        if let Some(ref asj) = authority_set_json
-                            && let Ok(auth_set) =
-                                serde_json::from_str::<crate::policy::AuthoritySet>(asj)
-                        {
+                        if let Some(auth_set) = sent {
                             let _ = engine.store().store_authority_set(auth_set);
                         }
                         // Store the policy
@@ -4252,6 +5936,208 @@ pub(crate) async fn process_s2s_message(
             }
         }
 
+        S2sMessage::PolicyChainRequest { channel, .. } => {
+            // A peer's sync or PolicySync couldn't place our policy head:
+            // send it our whole chain to arbitrate with.
+            let Some(ref engine) = state.policy_engine else {
+                return;
+            };
+            let channel = crate::casemap::fold(&channel);
+            let chain = engine.store().get_policy_chain(&channel).unwrap_or_default();
+            if chain.is_empty() {
+                return;
+            }
+            let authority_sets: Vec<String> = chain
+                .iter()
+                .map(|p| p.authority_set_hash.as_str())
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .filter_map(|hash| engine.store().get_authority_set(hash).ok().flatten())
+                .filter_map(|a| serde_json::to_string(&a).ok())
+                .collect();
+            let reply = S2sMessage::PolicyChain {
+                event_id: String::new(),
+                channel,
+                policies: chain
+                    .iter()
+                    .filter_map(|p| serde_json::to_string(p).ok())
+                    .collect(),
+                authority_sets,
+                origin: manager.server_id.clone(),
+            };
+            let tx = manager
+                .peers
+                .lock()
+                .await
+                .get(authenticated_peer_id)
+                .map(|entry| entry.tx.clone());
+            if let Some(tx) = tx {
+                let _ = tx.send(reply).await;
+            }
+        }
+
+        S2sMessage::PolicyChain {
+            channel,
+            policies,
+            authority_sets,
+            ..
+        } => {
+            use crate::policy::chain::{self, Verdict};
+            let Some(ref engine) = state.policy_engine else {
+                return;
+            };
+            let channel = crate::casemap::fold(&channel);
+            let theirs: Result<Vec<crate::policy::PolicyDocument>, _> = policies
+                .iter()
+                .take(chain::MAX_CHAIN_LEN + 1)
+                .map(|p| serde_json::from_str(p))
+                .collect();
+            let Ok(theirs) = theirs else {
+                tracing::warn!(%channel, peer = %authenticated_peer_id, "S2S PolicyChain: unparseable policy");
+                return;
+            };
+            // The peer's authority sets, under the hashes they really have;
+            // ours fill in any it left out.
+            let sent: HashMap<String, crate::policy::AuthoritySet> = authority_sets
+                .iter()
+                .take(chain::MAX_CHAIN_LEN)
+                .filter_map(|asj| serde_json::from_str(asj).ok())
+                .filter_map(|set| Some((chain::authority_set_hash(&set).ok()?, set)))
+                .collect();
+            let lookup = |hash: &str| {
+                sent.get(hash)
+                    .cloned()
+                    .or_else(|| engine.store().get_authority_set(hash).ok().flatten())
+            };
+            let ours = engine.store().get_policy_chain(&channel).unwrap_or_default();
+            let verdict = chain::arbitrate(&channel, &ours, &theirs, &lookup);
+            match &verdict {
+                Verdict::Keep => return,
+                Verdict::Invalid(e) => {
+                    tracing::warn!(%channel, peer = %authenticated_peer_id, "S2S PolicyChain rejected: {e}");
+                    return;
+                }
+                Verdict::FastForward | Verdict::Supersede => {}
+            }
+
+            for auth_set in sent.into_values() {
+                let _ = engine.store().store_authority_set(auth_set);
+            }
+            if let Err(e) = engine.store().replace_policy_chain(&channel, &theirs) {
+                tracing::warn!(%channel, "S2S PolicyChain: failed to store chain: {e}");
+                return;
+            }
+
+            // The winning chain also records who founded the channel.
+            let founder = theirs
+                .last()
+                .and_then(|p| p.founder_did.clone())
+                .filter(|did| did.starts_with("did:"));
+            let previous_founder = match founder {
+                Some(ref did) => {
+                    let snapshot = state.channels.get(&channel).and_then(|mut ch| {
+                        if ch.founder_did.as_ref() == Some(did) {
+                            return None;
+                        }
+                        let previous = ch.founder_did.replace(did.clone());
+                        ch.did_ops.insert(did.clone());
+                        let dids = state.session_dids.lock();
+                        ch.refresh_founders(&dids);
+                        Some((previous, ch.clone()))
+                    });
+                    match snapshot {
+                        Some((previous, snapshot)) => {
+                            state.with_db(|db| db.save_channel(&channel, &snapshot));
+                            state.crdt_replace_founder(&channel, did).await;
+                            state.crdt_broadcast_sync().await;
+                            previous
+                        }
+                        None => None,
+                    }
+                }
+                None => None,
+            };
+
+            let head = |chain: &[crate::policy::PolicyDocument]| {
+                chain
+                    .last()
+                    .map(|p| {
+                        let hash = p.policy_id.as_deref().unwrap_or("?");
+                        format!("v{} {}", p.version, &hash[..12.min(hash.len())])
+                    })
+                    .unwrap_or_else(|| "none".to_string())
+            };
+            if verdict == Verdict::FastForward {
+                tracing::info!(%channel, peer = %authenticated_peer_id, "S2S PolicyChain: fast-forwarded to {}", head(&theirs));
+                return;
+            }
+
+            let peer_name = manager
+                .peer_names
+                .lock()
+                .await
+                .get(authenticated_peer_id)
+                .cloned()
+                .unwrap_or_else(|| authenticated_peer_id.to_string());
+            let mut reason = format!(
+                "policy {} replaced by {} from {peer_name}",
+                head(&ours),
+                head(&theirs)
+            );
+            if let (Some(previous), Some(founder)) = (&previous_founder, &founder) {
+                reason.push_str(&format!("; founder {previous} replaced by {founder}"));
+            }
+            tracing::warn!(%channel, peer = %authenticated_peer_id, "S2S PolicyChain: local view superseded: {reason}");
+            state.with_db(|db| {
+                db.log_governance(
+                    Some(&channel),
+                    founder.as_deref().unwrap_or(""),
+                    "policy_superseded",
+                    &format!("s2s:{authenticated_peer_id}"),
+                    Some(&reason),
+                )
+            });
+
+            let ops: Vec<String> = state
+                .channels
+                .get(&channel)
+                .map(|ch| ch.ops.iter().cloned().collect())
+                .unwrap_or_default();
+            let note = format!("{channel}'s {reason} (the chains forked and the peer's won)");
+            for session_id in ops {
+                let Some(nick) = state
+                    .nick_to_session
+                    .lock()
+                    .get_nick(&session_id)
+                    .map(str::to_string)
+                else {
+                    continue;
+                };
+                let line =
+                    crate::irc::Message::from_server(&state.server_name, "NOTICE", vec![&nick, &note]);
+                if let Some(tx) = state.connections.get(&session_id) {
+                    let _ = tx.try_send(format!("{line}\r\n").into());
+                }
+            }
+        }
+
+        S2sMessage::Erase { did, msgids, .. } => {
+            let did = sanitize_s2s_str(&did, 256);
+            if !did.starts_with("did:") {
+                return;
+            }
+            let msgids: Vec<String> = msgids
+                .iter()
+                .take(crate::connection::mydata::ERASE_BATCH)
+                .map(|m| sanitize_s2s_str(m, 100))
+                .collect();
+            let erased = state
+                .with_db(|db| db.erase_messages_by_did(&did, Some(msgids.as_slice())))
+                .unwrap_or_default();
+            tracing::info!(%did, erased = erased.len(), "S2S Erase: messages erased for peer");
+            crate::connection::mydata::apply_erasure(&state, &did, &erased);
+        }
+
         S2sMessage::CrdtSync { data, origin, .. } => {
             // SECURITY: Use authenticated_peer_id (from QUIC transport) to key
             // the Automerge sync state, NOT the `origin` field from the JSON
@@ -4392,23 +6278,40 @@ pub(crate) async fn process_s2s_message(
             tracing::info!(session_id = %session_id, "S2S: AV session ended");
         }
 
+        S2sMessage::ChannelDirectory { channels, .. } => {
+            let server_name = manager
+                .peer_names
+                .lock()
+                .await
+                .get(authenticated_peer_id)
+                .cloned()
+                .unwrap_or_else(|| authenticated_peer_id.to_string());
+            tracing::debug!(
+                peer = %authenticated_peer_id,
+                "Received channel directory: {} channel(s)",
+                channels.len()
+            );
+            state
+                .channel_directory
+                .record(authenticated_peer_id, &server_name, channels);
+        }
+
         S2sMessage::PeerDisconnected { peer_id } => {
+            state.channel_directory.forget(&peer_id);
             // Clean up all remote_members whose origin matches this peer.
             // Without this, users from a disconnected server linger as ghosts
             // in channel rosters until they individually Part/Quit.
-            let mut channels = state.channels.lock();
             let mut cleaned = 0usize;
             let mut affected_channels = Vec::new();
-            for (name, ch) in channels.iter_mut() {
+            state.channels.for_each_mut(|name, ch| {
                 let before = ch.remote_members.len();
                 ch.remote_members.retain(|_nick, rm| rm.origin != peer_id);
                 let removed = before - ch.remote_members.len();
                 if removed > 0 {
                     cleaned += removed;
-                    affected_channels.push(name.clone());
+                    affected_channels.push(name.to_string());
                 }
-            }
-            drop(channels);
+            });
 
             if cleaned > 0 {
                 tracing::info!(
@@ -4432,7 +6335,7 @@ pub(crate) async fn process_s2s_message(
 /// truth — even when S2S events and CRDT diverge due to timing or partitions.
 async fn reconcile_crdt_to_local(state: &Arc<SharedState>) {
     // Get list of channels
-    let channel_names: Vec<String> = { state.channels.lock().keys().cloned().collect() };
+    let channel_names = state.channels.keys();
 
     let mut reconciled = 0u32;
 
@@ -4440,39 +6343,36 @@ async fn reconcile_crdt_to_local(state: &Arc<SharedState>) {
         // Reconcile topic: if CRDT has a topic and it differs from local, adopt CRDT's
         if let Some((crdt_topic, crdt_setter)) = state.cluster_doc.channel_topic(channel_name).await
         {
-            let needs_update = {
-                let channels = state.channels.lock();
-                channels
-                    .get(channel_name)
-                    .map(|ch| {
-                        ch.topic
-                            .as_ref()
-                            .map(|t| t.text != crdt_topic)
-                            .unwrap_or(true) // no local topic, CRDT has one → adopt
-                    })
-                    .unwrap_or(false)
-            };
+            let needs_update = state
+                .channels
+                .get(channel_name)
+                .map(|ch| {
+                    ch.topic
+                        .as_ref()
+                        .map(|t| t.text != crdt_topic)
+                        .unwrap_or(true) // no local topic, CRDT has one → adopt
+                })
+                .unwrap_or(false);
             if needs_update {
-                let mut channels = state.channels.lock();
-                if let Some(ch) = channels.get_mut(channel_name) {
-                    ch.topic = Some(TopicInfo::new(crdt_topic, crdt_setter));
+                let history_changed = state.channels.get(channel_name).map(|mut ch| {
                     reconciled += 1;
+                    ch.set_topic(TopicInfo::new(crdt_topic, crdt_setter))
+                });
+                if history_changed == Some(true) {
+                    state.persist_topic_history(channel_name);
                 }
             }
         }
 
         // Reconcile founder
         if let Some(crdt_founder) = state.cluster_doc.founder(channel_name).await {
-            let needs_update = {
-                let channels = state.channels.lock();
-                channels
-                    .get(channel_name)
-                    .map(|ch| ch.founder_did.as_deref() != Some(&crdt_founder))
-                    .unwrap_or(false)
-            };
+            let needs_update = state
+                .channels
+                .get(channel_name)
+                .map(|ch| ch.founder_did.as_deref() != Some(&crdt_founder))
+                .unwrap_or(false);
             if needs_update {
-                let mut channels = state.channels.lock();
-                if let Some(ch) = channels.get_mut(channel_name) {
+                if let Some(mut ch) = state.channels.get(channel_name) {
                     tracing::info!(
                         channel = %channel_name,
                         "CRDT reconciliation: updating founder to {crdt_founder}"
@@ -4484,6 +6384,7 @@ async fn reconcile_crdt_to_local(state: &Arc<SharedState>) {
                     // Only revoke guest auto-ops if an authority-backed user is now
                     // opped (locally or remotely) — don't orphan the channel.
                     let dids = state.session_dids.lock();
+                    ch.refresh_founders(&dids);
                     let members: Vec<String> = ch.members.iter().cloned().collect();
                     let mut did_ops_granted = false;
                     for session_id in &members {
@@ -4501,7 +6402,7 @@ async fn reconcile_crdt_to_local(state: &Arc<SharedState>) {
                             let has_did_auth = dids.get(session_id).is_some_and(|did| {
                                 ch.founder_did.as_deref() == Some(did) || ch.did_ops.contains(did)
                             });
-                            if !has_did_auth {
+                            if !has_did_auth && !ch.admins.contains(session_id) {
                                 ch.ops.remove(session_id);
                             }
                         }
@@ -4513,8 +6414,7 @@ async fn reconcile_crdt_to_local(state: &Arc<SharedState>) {
         // Reconcile DID ops: CRDT is additive authority
         let crdt_ops = state.cluster_doc.channel_did_ops(channel_name).await;
         if !crdt_ops.is_empty() {
-            let mut channels = state.channels.lock();
-            if let Some(ch) = channels.get_mut(channel_name) {
+            if let Some(mut ch) = state.channels.get(channel_name) {
                 for did in &crdt_ops {
                     if ch.did_ops.insert(did.clone()) {
                         reconciled += 1;
@@ -4541,7 +6441,7 @@ async fn reconcile_crdt_to_local(state: &Arc<SharedState>) {
                         let has_did_auth = dids.get(session_id).is_some_and(|did| {
                             ch.founder_did.as_deref() == Some(did) || ch.did_ops.contains(did)
                         });
-                        if !has_did_auth {
+                        if !has_did_auth && !ch.admins.contains(session_id) {
                             ch.ops.remove(session_id);
                         }
                     }
@@ -4588,27 +6488,24 @@ mod s2s_adversarial_tests {
         Arc::new(SharedState {
             server_name: config.server_name.clone(),
             challenge_store: crate::sasl::ChallengeStore::new(60),
+            auth_failures: Mutex::new(HashMap::new()),
             did_resolver: freeq_sdk::did::DidResolver::static_map(HashMap::new()),
-            connections: Mutex::new(HashMap::new()),
+            connections: ShardedMap::new(),
             nick_to_session: Mutex::new(NickMap::new()),
             session_dids: Mutex::new(HashMap::new()),
             did_sessions: Mutex::new(HashMap::new()),
             did_nicks: Mutex::new(HashMap::new()),
             nick_owners: Mutex::new(HashMap::new()),
+            nick_skeletons: Mutex::new(HashMap::new()),
             session_handles: Mutex::new(HashMap::new()),
-            channels: Mutex::new(HashMap::new()),
-            cap_message_tags: Mutex::new(HashSet::new()),
-            cap_multi_prefix: Mutex::new(HashSet::new()),
-            cap_echo_message: Mutex::new(HashSet::new()),
-            cap_server_time: Mutex::new(HashSet::new()),
-            cap_batch: Mutex::new(HashSet::new()),
-            cap_draft_multiline: Mutex::new(HashSet::new()),
+            channels: ShardedMap::new(),
+            sessions: crate::session::SessionRegistry::new(),
             open_batches: Mutex::new(HashMap::new()),
-            cap_account_notify: Mutex::new(HashSet::new()),
-            cap_extended_join: Mutex::new(HashSet::new()),
-            cap_away_notify: Mutex::new(HashSet::new()),
-            cap_account_tag: Mutex::new(HashSet::new()),
+            metadata: Mutex::new(HashMap::new()),
+            metadata_subs: Mutex::new(HashMap::new()),
             server_opers: Mutex::new(HashSet::new()),
+            oper_tokens: Mutex::new(HashMap::new()),
+            firehose: Default::default(),
             session_actor_class: Mutex::new(HashMap::new()),
             provenance_declarations: Mutex::new(HashMap::new()),
             agent_presence: Mutex::new(HashMap::new()),
@@ -4618,10 +6515,16 @@ mod s2s_adversarial_tests {
             oauth_complete: Mutex::new(HashMap::new()),
             web_auth_tokens: Mutex::new(HashMap::new()),
             web_sessions: Mutex::new(HashMap::new()),
+            broker_devices: Mutex::new(HashMap::new()),
             login_pending: Mutex::new(HashMap::new()),
             linked_identities: Mutex::new(HashMap::new()),
             login_completions: Mutex::new(HashMap::new()),
             session_iroh_ids: Mutex::new(HashMap::new()),
+            iroh_bindings: Mutex::new(HashMap::new()),
+            content_filters: Mutex::new(crate::filters::Filters::default()),
+            invites: Mutex::new(crate::invites::Invites::default()),
+            email: crate::email::EmailNotifier::disabled(),
+            prefs: Mutex::new(crate::prefs::Preferences::default()),
             session_away: Mutex::new(HashMap::new()),
             server_iroh_id: Mutex::new(Some("test-server-id".to_string())),
             iroh_endpoint: Mutex::new(None),
@@ -4633,9 +6536,14 @@ mod s2s_adversarial_tests {
             db: db.map(Mutex::new),
             config,
             plugin_manager: crate::plugin::PluginManager::new(),
+            command_aliases: crate::alias::AliasTable::default(),
+            locales: crate::locale::Locales::load(None),
             policy_engine: None,
             prekey_bundles: Mutex::new(HashMap::new()),
             msg_timestamps: Mutex::new(HashMap::new()),
+            ephemeral: Default::default(),
+            channel_stats: Default::default(),
+            channel_directory: Default::default(),
             ip_connections: Mutex::new(HashMap::new()),
             msg_signing_key: signing_key,
             boot_time: std::time::Instant::now(),
@@ -4643,6 +6551,8 @@ mod s2s_adversarial_tests {
             session_msg_keys: Mutex::new(HashMap::new()),
             did_msg_keys: Mutex::new(HashMap::new()),
             session_client_info: Mutex::new(HashMap::new()),
+            session_activity: Mutex::new(HashMap::new()),
+            reclaim_attempts: Mutex::new(HashMap::new()),
             upload_tokens: Mutex::new(HashMap::new()),
             ghost_sessions: Mutex::new(HashMap::new()),
             spawned_agents: Mutex::new(HashMap::new()),
@@ -4651,6 +6561,8 @@ mod s2s_adversarial_tests {
             liveness_probes: Mutex::new(HashMap::new()),
             session_kill: Mutex::new(HashMap::new()),
             metrics: Metrics::default(),
+            listening: std::sync::atomic::AtomicBool::new(false),
+            backup_running: std::sync::atomic::AtomicBool::new(false),
         })
     }
 
@@ -4700,12 +6612,11 @@ mod s2s_adversarial_tests {
     }
 
     fn setup_channel(state: &SharedState, name: &str) {
-        state.channels.lock().entry(name.to_string()).or_default();
+        state.channels.insert(name.to_string(), ChannelState::default());
     }
 
     fn add_remote_member(state: &SharedState, channel: &str, nick: &str, is_op: bool) {
-        let mut channels = state.channels.lock();
-        if let Some(ch) = channels.get_mut(channel) {
+        if let Some(mut ch) = state.channels.get(channel) {
             ch.remote_members.insert(
                 nick.to_string(),
                 crate::server::RemoteMember {
@@ -4749,8 +6660,7 @@ mod s2s_adversarial_tests {
         .await;
 
         // Check: was the remote member added with is_op?
-        let channels = state.channels.lock();
-        let ch = channels.get("#test").unwrap();
+        let ch = state.channels.get("#test").unwrap();
         let rm = ch.remote_members.get("evil_op");
         assert!(rm.is_some(), "Remote member should be added");
         // BUG CHECK: is_op should ideally be validated against founder/did_ops
@@ -4791,8 +6701,7 @@ mod s2s_adversarial_tests {
         .await;
 
         // Check: was the mode applied?
-        let channels = state.channels.lock();
-        let ch = channels.get("#secure").unwrap();
+        let ch = state.channels.get("#secure").unwrap();
         let did_ops_has_target = ch.did_ops.iter().any(|d| d.contains("target"));
         // If did_ops was modified, that's a privilege escalation
         if did_ops_has_target {
@@ -4813,16 +6722,14 @@ mod s2s_adversarial_tests {
 
         // Add a local user "alice" to the channel
         {
-            let (tx, _rx) = mpsc::channel(16);
+            let (tx, _rx) = crate::send_queue::channel(16, state.metrics.send_queue.clone());
             state
                 .connections
-                .lock()
                 .insert("local-sess".to_string(), tx);
             state.nick_to_session.lock().insert("alice", "local-sess");
             state
                 .channels
-                .lock()
-                .get_mut("#chat")
+                .get("#chat")
                 .unwrap()
                 .members
                 .insert("local-sess".to_string());
@@ -4867,19 +6774,15 @@ mod s2s_adversarial_tests {
 
         // Add a local member to receive
         {
-            let (tx, mut rx) = mpsc::channel(16);
-            state.connections.lock().insert("recv-sess".to_string(), tx);
+            let (tx, mut rx) = crate::send_queue::channel(16, state.metrics.send_queue.clone());
+            state.connections.insert("recv-sess".to_string(), tx);
             state
                 .channels
-                .lock()
-                .get_mut("#inject")
+                .get("#inject")
                 .unwrap()
                 .members
                 .insert("recv-sess".to_string());
-            state
-                .cap_message_tags
-                .lock()
-                .insert("recv-sess".to_string());
+            state.sessions.enable_cap("recv-sess", Cap::MessageTags);
 
             // Peer sends PRIVMSG with CRLF in text
             process_s2s_message(
@@ -4905,7 +6808,7 @@ mod s2s_adversarial_tests {
             if let Ok(line) =
                 tokio::time::timeout(std::time::Duration::from_millis(500), rx.recv()).await
             {
-                if let Some(line) = line {
+                if let Some(line) = line.map(wire_text) {
                     assert!(
                         !line.contains("\r\nQUIT"),
                         "BUG: CRLF injection in S2S privmsg text: {line}"
@@ -4934,18 +6837,22 @@ mod s2s_adversarial_tests {
     /// collected frames in order. The deadline is generous enough that
     /// we don't false-fail on slow CI but short enough that test
     /// time stays small.
-    async fn drain_mailbox(rx: &mut mpsc::Receiver<String>) -> Vec<String> {
+    async fn drain_mailbox(rx: &mut crate::send_queue::QueueReceiver) -> Vec<String> {
         let mut frames = Vec::new();
         let deadline = std::time::Instant::now() + std::time::Duration::from_millis(200);
         while std::time::Instant::now() < deadline {
             match tokio::time::timeout(std::time::Duration::from_millis(50), rx.recv()).await {
-                Ok(Some(line)) => frames.push(line),
+                Ok(Some(line)) => frames.push(wire_text(line)),
                 _ => break,
             }
         }
         frames
     }
 
+    fn wire_text(line: WireLine) -> String {
+        String::from_utf8(line.to_vec()).expect("wire line is UTF-8")
+    }
+
     #[tokio::test]
     async fn s2s_multiline_capable_local_member_receives_batch_frames() {
         let state = test_state();
@@ -4953,20 +6860,16 @@ mod s2s_adversarial_tests {
         setup_authenticated_peer(&state, &mgr).await;
         setup_channel(&state, "#mlchan");
 
-        let (tx, mut rx) = mpsc::channel(16);
-        state.connections.lock().insert("ml-recv".to_string(), tx);
+        let (tx, mut rx) = crate::send_queue::channel(16, state.metrics.send_queue.clone());
+        state.connections.insert("ml-recv".to_string(), tx);
         state
             .channels
-            .lock()
-            .get_mut("#mlchan")
+            .get("#mlchan")
             .unwrap()
             .members
             .insert("ml-recv".to_string());
-        state.cap_message_tags.lock().insert("ml-recv".to_string());
-        state
-            .cap_draft_multiline
-            .lock()
-            .insert("ml-recv".to_string());
+        state.sessions.enable_cap("ml-recv", Cap::MessageTags);
+        state.sessions.enable_cap("ml-recv", Cap::DraftMultiline);
 
         process_s2s_message(
             &state,
@@ -5011,17 +6914,16 @@ mod s2s_adversarial_tests {
         setup_authenticated_peer(&state, &mgr).await;
         setup_channel(&state, "#acct");
 
-        let (tx, mut rx) = mpsc::channel(16);
-        state.connections.lock().insert("acct-recv".to_string(), tx);
+        let (tx, mut rx) = crate::send_queue::channel(16, state.metrics.send_queue.clone());
+        state.connections.insert("acct-recv".to_string(), tx);
         state
             .channels
-            .lock()
-            .get_mut("#acct")
+            .get("#acct")
             .unwrap()
             .members
             .insert("acct-recv".to_string());
-        state.cap_message_tags.lock().insert("acct-recv".to_string());
-        state.cap_account_tag.lock().insert("acct-recv".to_string());
+        state.sessions.enable_cap("acct-recv", Cap::MessageTags);
+        state.sessions.enable_cap("acct-recv", Cap::AccountTag);
 
         process_s2s_message(
             &state,
@@ -5060,19 +6962,15 @@ mod s2s_adversarial_tests {
         setup_authenticated_peer(&state, &mgr).await;
         setup_channel(&state, "#acct2");
 
-        let (tx, mut rx) = mpsc::channel(16);
-        state.connections.lock().insert("plain-recv".to_string(), tx);
+        let (tx, mut rx) = crate::send_queue::channel(16, state.metrics.send_queue.clone());
+        state.connections.insert("plain-recv".to_string(), tx);
         state
             .channels
-            .lock()
-            .get_mut("#acct2")
+            .get("#acct2")
             .unwrap()
             .members
             .insert("plain-recv".to_string());
-        state
-            .cap_message_tags
-            .lock()
-            .insert("plain-recv".to_string());
+        state.sessions.enable_cap("plain-recv", Cap::MessageTags);
 
         process_s2s_message(
             &state,
@@ -5116,16 +7014,15 @@ mod s2s_adversarial_tests {
             .await
             .insert(PEER.to_string(), "zerosum".to_string());
 
-        let (tx, mut rx) = mpsc::channel(16);
-        state.connections.lock().insert("prov-recv".to_string(), tx);
+        let (tx, mut rx) = crate::send_queue::channel(16, state.metrics.send_queue.clone());
+        state.connections.insert("prov-recv".to_string(), tx);
         state
             .channels
-            .lock()
-            .get_mut("#prov")
+            .get("#prov")
             .unwrap()
             .members
             .insert("prov-recv".to_string());
-        state.cap_message_tags.lock().insert("prov-recv".to_string());
+        state.sessions.enable_cap("prov-recv", Cap::MessageTags);
 
         process_s2s_message(
             &state,
@@ -5164,16 +7061,15 @@ mod s2s_adversarial_tests {
         setup_authenticated_peer(&state, &mgr).await;
         setup_channel(&state, "#prov2");
 
-        let (tx, mut rx) = mpsc::channel(16);
-        state.connections.lock().insert("prov2-recv".to_string(), tx);
+        let (tx, mut rx) = crate::send_queue::channel(16, state.metrics.send_queue.clone());
+        state.connections.insert("prov2-recv".to_string(), tx);
         state
             .channels
-            .lock()
-            .get_mut("#prov2")
+            .get("#prov2")
             .unwrap()
             .members
             .insert("prov2-recv".to_string());
-        state.cap_message_tags.lock().insert("prov2-recv".to_string());
+        state.sessions.enable_cap("prov2-recv", Cap::MessageTags);
 
         // Note: no peer_names entry for PEER.
         process_s2s_message(
@@ -5212,17 +7108,16 @@ mod s2s_adversarial_tests {
         setup_authenticated_peer(&state, &mgr).await;
         setup_channel(&state, "#mlchan2");
 
-        let (tx, mut rx) = mpsc::channel(16);
-        state.connections.lock().insert("fb-recv".to_string(), tx);
+        let (tx, mut rx) = crate::send_queue::channel(16, state.metrics.send_queue.clone());
+        state.connections.insert("fb-recv".to_string(), tx);
         state
             .channels
-            .lock()
-            .get_mut("#mlchan2")
+            .get("#mlchan2")
             .unwrap()
             .members
             .insert("fb-recv".to_string());
-        state.cap_message_tags.lock().insert("fb-recv".to_string());
-        // Deliberately do NOT add to cap_draft_multiline.
+        state.sessions.enable_cap("fb-recv", Cap::MessageTags);
+        // Deliberately do NOT enable draft/multiline.
 
         process_s2s_message(
             &state,
@@ -5442,26 +7337,18 @@ mod s2s_adversarial_tests {
         setup_authenticated_peer(&state, &mgr).await;
         setup_channel(&state, "#plain");
 
-        let (tx, mut rx) = mpsc::channel(16);
+        let (tx, mut rx) = crate::send_queue::channel(16, state.metrics.send_queue.clone());
         state
             .connections
-            .lock()
             .insert("plain-recv".to_string(), tx);
         state
             .channels
-            .lock()
-            .get_mut("#plain")
+            .get("#plain")
             .unwrap()
             .members
             .insert("plain-recv".to_string());
-        state
-            .cap_message_tags
-            .lock()
-            .insert("plain-recv".to_string());
-        state
-            .cap_draft_multiline
-            .lock()
-            .insert("plain-recv".to_string());
+        state.sessions.enable_cap("plain-recv", Cap::MessageTags);
+        state.sessions.enable_cap("plain-recv", Cap::DraftMultiline);
 
         process_s2s_message(
             &state,
@@ -5503,8 +7390,7 @@ mod s2s_adversarial_tests {
         // Set +t on channel
         state
             .channels
-            .lock()
-            .get_mut("#locked")
+            .get("#locked")
             .unwrap()
             .topic_locked = true;
 
@@ -5512,7 +7398,7 @@ mod s2s_adversarial_tests {
         add_remote_member(&state, "#locked", "nonop", false);
 
         // Set existing topic
-        state.channels.lock().get_mut("#locked").unwrap().topic = Some(TopicInfo {
+        state.channels.get("#locked").unwrap().topic = Some(TopicInfo {
             text: "original topic".to_string(),
             set_by: "founder".to_string(),
             set_at: 1000,
@@ -5534,8 +7420,8 @@ mod s2s_adversarial_tests {
         .await;
 
         // Topic should NOT have changed
-        let channels = state.channels.lock();
-        let topic = channels.get("#locked").unwrap().topic.as_ref().unwrap();
+        let ch = state.channels.get("#locked").unwrap();
+        let topic = ch.topic.as_ref().unwrap();
         assert_eq!(
             topic.text, "original topic",
             "BUG: Non-op changed topic on +t channel via S2S"
@@ -5575,8 +7461,7 @@ mod s2s_adversarial_tests {
         .await;
 
         // Victim should still be in the channel
-        let channels = state.channels.lock();
-        let ch = channels.get("#kicktest").unwrap();
+        let ch = state.channels.get("#kicktest").unwrap();
         assert!(
             ch.remote_members.contains_key("victim"),
             "BUG: Non-op kicked user via S2S — authorization check failed"
@@ -5614,8 +7499,7 @@ mod s2s_adversarial_tests {
         .await;
 
         // Ban list should be empty (unauthorized)
-        let channels = state.channels.lock();
-        let ch = channels.get("#bantest").unwrap();
+        let ch = state.channels.get("#bantest").unwrap();
         assert!(
             ch.bans.is_empty(),
             "BUG: Non-op set ban via S2S — {} bans in list",
@@ -5623,6 +7507,36 @@ mod s2s_adversarial_tests {
         );
     }
 
+    #[tokio::test]
+    async fn s2s_quiet_requires_op() {
+        let state = test_state();
+        let mgr = test_manager();
+        setup_authenticated_peer(&state, &mgr).await;
+        setup_channel(&state, "#quiettest");
+        add_remote_member(&state, "#quiettest", "regular", false);
+        add_remote_member(&state, "#quiettest", "remote_op", true);
+
+        let quiet = |event: u32, set_by: &str| S2sMessage::Quiet {
+            event_id: format!("{PEER}:{event}"),
+            channel: "#quiettest".to_string(),
+            mask: "did:plc:noisy".to_string(),
+            set_by: set_by.to_string(),
+            adding: true,
+            origin: PEER.to_string(),
+        };
+
+        process_s2s_message(&state, &mgr, PEER, quiet(1, "regular")).await;
+        assert!(
+            state.channels.get("#quiettest").unwrap().quiets.is_empty(),
+            "BUG: non-op set a quiet via S2S"
+        );
+
+        process_s2s_message(&state, &mgr, PEER, quiet(2, "remote_op")).await;
+        let ch = state.channels.get("#quiettest").unwrap();
+        assert!(ch.is_quieted("noisy!u@host", Some("did:plc:noisy")));
+        assert!(ch.bans.is_empty(), "a quiet is not a ban");
+    }
+
     // ═══════════════════════════════════════════════════════════
     // S2S DEDUP: replay rejection
     // ═══════════════════════════════════════════════════════════
@@ -5634,15 +7548,13 @@ mod s2s_adversarial_tests {
         setup_authenticated_peer(&state, &mgr).await;
         setup_channel(&state, "#dedup");
 
-        let (tx, mut rx) = mpsc::channel(16);
+        let (tx, mut rx) = crate::send_queue::channel(16, state.metrics.send_queue.clone());
         state
             .connections
-            .lock()
             .insert("dedup-sess".to_string(), tx);
         state
             .channels
-            .lock()
-            .get_mut("#dedup")
+            .get("#dedup")
             .unwrap()
             .members
             .insert("dedup-sess".to_string());
@@ -5714,10 +7626,9 @@ mod s2s_adversarial_tests {
         .await;
 
         // Channel name should be truncated by sanitize_s2s_str(200)
-        let channels = state.channels.lock();
         // The full 300-char name should NOT exist as-is
         assert!(
-            !channels.contains_key(&long_name),
+            !state.channels.contains_key(&long_name),
             "S2S channel name should be truncated to max 200 chars"
         );
     }
@@ -5746,12 +7657,11 @@ mod s2s_adversarial_tests {
         S2S_RATE_LIMITS.lock().remove(RL_PEER);
         setup_channel(&state, "#ratelimit");
 
-        let (tx, mut rx) = mpsc::channel(256);
-        state.connections.lock().insert("rl-sess".to_string(), tx);
+        let (tx, mut rx) = crate::send_queue::channel(256, state.metrics.send_queue.clone());
+        state.connections.insert("rl-sess".to_string(), tx);
         state
             .channels
-            .lock()
-            .get_mut("#ratelimit")
+            .get("#ratelimit")
             .unwrap()
             .members
             .insert("rl-sess".to_string());
@@ -5818,8 +7728,7 @@ mod s2s_adversarial_tests {
         )
         .await;
 
-        let channels = state.channels.lock();
-        let ch = channels.get("#agenttest").unwrap();
+        let ch = state.channels.get("#agenttest").unwrap();
         let rm = ch
             .remote_members
             .get("testbot")
@@ -5839,10 +7748,9 @@ mod s2s_adversarial_tests {
         setup_channel(&state, "#agentdeliver");
 
         // Add a local member to receive the JOIN
-        let (tx, mut rx) = mpsc::channel(16);
+        let (tx, mut rx) = crate::send_queue::channel(16, state.metrics.send_queue.clone());
         state
             .connections
-            .lock()
             .insert("local-sess".to_string(), tx);
         state
             .nick_to_session
@@ -5850,15 +7758,11 @@ mod s2s_adversarial_tests {
             .insert("localuser", "local-sess");
         state
             .channels
-            .lock()
-            .get_mut("#agentdeliver")
+            .get("#agentdeliver")
             .unwrap()
             .members
             .insert("local-sess".to_string());
-        state
-            .cap_message_tags
-            .lock()
-            .insert("local-sess".to_string());
+        state.sessions.enable_cap("local-sess", Cap::MessageTags);
 
         // Remote agent joins
         process_s2s_message(
@@ -5883,6 +7787,7 @@ mod s2s_adversarial_tests {
         while let Ok(Some(msg)) =
             tokio::time::timeout(std::time::Duration::from_secs(1), rx.recv()).await
         {
+            let msg = wire_text(msg);
             if msg.contains("JOIN") && msg.contains("remotebot") {
                 assert!(
                     msg.contains("+freeq.at/actor-class=agent"),
@@ -5909,23 +7814,18 @@ mod s2s_adversarial_tests {
         setup_authenticated_peer(&state, &mgr).await;
         setup_channel(&state, "#react-test");
 
-        let (tx, mut rx) = mpsc::channel(16);
+        let (tx, mut rx) = crate::send_queue::channel(16, state.metrics.send_queue.clone());
         state
             .connections
-            .lock()
             .insert("react-sess".to_string(), tx);
         state.nick_to_session.lock().insert("reactor", "react-sess");
         state
             .channels
-            .lock()
-            .get_mut("#react-test")
+            .get("#react-test")
             .unwrap()
             .members
             .insert("react-sess".to_string());
-        state
-            .cap_message_tags
-            .lock()
-            .insert("react-sess".to_string());
+        state.sessions.enable_cap("react-sess", Cap::MessageTags);
 
         let mut tags = HashMap::new();
         tags.insert("+react".to_string(), "👍".to_string());
@@ -5949,6 +7849,7 @@ mod s2s_adversarial_tests {
             .await
             .expect("timeout")
             .expect("channel closed");
+        let msg = wire_text(msg);
         assert!(msg.contains("TAGMSG"), "Should be TAGMSG, got: {msg}");
         assert!(
             msg.contains("+react="),
@@ -5963,23 +7864,18 @@ mod s2s_adversarial_tests {
         setup_authenticated_peer(&state, &mgr).await;
         setup_channel(&state, "#draft-test");
 
-        let (tx, mut rx) = mpsc::channel(16);
+        let (tx, mut rx) = crate::send_queue::channel(16, state.metrics.send_queue.clone());
         state
             .connections
-            .lock()
             .insert("draft-sess".to_string(), tx);
         state.nick_to_session.lock().insert("drafter", "draft-sess");
         state
             .channels
-            .lock()
-            .get_mut("#draft-test")
+            .get("#draft-test")
             .unwrap()
             .members
             .insert("draft-sess".to_string());
-        state
-            .cap_message_tags
-            .lock()
-            .insert("draft-sess".to_string());
+        state.sessions.enable_cap("draft-sess", Cap::MessageTags);
 
         // Send with +draft/ prefixed tags
         let mut tags = HashMap::new();
@@ -6004,6 +7900,7 @@ mod s2s_adversarial_tests {
             .await
             .expect("timeout")
             .expect("channel closed");
+        let msg = wire_text(msg);
         assert!(msg.contains("TAGMSG"), "Should be TAGMSG, got: {msg}");
         // Should be normalized to +react, not +draft/react
         assert!(
@@ -6027,10 +7924,10 @@ mod s2s_adversarial_tests {
         setup_authenticated_peer(&state, &mgr).await;
 
         // Set up local user "bob" who will receive the DM
-        let (tx, mut rx) = mpsc::channel(16);
-        state.connections.lock().insert("bob-sess".to_string(), tx);
+        let (tx, mut rx) = crate::send_queue::channel(16, state.metrics.send_queue.clone());
+        state.connections.insert("bob-sess".to_string(), tx);
         state.nick_to_session.lock().insert("bob", "bob-sess");
-        state.cap_message_tags.lock().insert("bob-sess".to_string());
+        state.sessions.enable_cap("bob-sess", Cap::MessageTags);
 
         // Remote user sends DM to local bob
         process_s2s_message(
@@ -6057,6 +7954,7 @@ mod s2s_adversarial_tests {
             .await
             .expect("timeout waiting for DM")
             .expect("channel closed");
+        let msg = wire_text(msg);
         assert!(
             msg.contains("hey bob, private msg"),
             "Bob should receive DM text, got: {msg}"
@@ -6075,11 +7973,11 @@ mod s2s_adversarial_tests {
         let mgr = test_manager();
         setup_authenticated_peer(&state, &mgr).await;
 
-        let (tx, mut rx) = mpsc::channel(16);
-        state.connections.lock().insert("bob-sess".to_string(), tx);
+        let (tx, mut rx) = crate::send_queue::channel(16, state.metrics.send_queue.clone());
+        state.connections.insert("bob-sess".to_string(), tx);
         state.nick_to_session.lock().insert("bob", "bob-sess");
-        state.cap_message_tags.lock().insert("bob-sess".to_string());
-        state.cap_account_tag.lock().insert("bob-sess".to_string());
+        state.sessions.enable_cap("bob-sess", Cap::MessageTags);
+        state.sessions.enable_cap("bob-sess", Cap::AccountTag);
 
         process_s2s_message(
             &state,
@@ -6240,6 +8138,84 @@ mod s2s_adversarial_tests {
         );
     }
 
+    #[test]
+    fn channel_roles_rank_and_prefix() {
+        let mut ch = ChannelState::default();
+        for sid in ["founder", "admin", "op", "voice", "member"] {
+            ch.members.insert(sid.to_string());
+        }
+        ch.founder_did = Some("did:plc:f".to_string());
+        ch.admins.insert("admin".to_string());
+        ch.ops.insert("admin".to_string());
+        ch.ops.insert("op".to_string());
+        ch.voiced.insert("op".to_string());
+        ch.voiced.insert("voice".to_string());
+        let dids = HashMap::from([("founder".to_string(), "did:plc:f".to_string())]);
+        ch.refresh_founders(&dids);
+
+        assert_eq!(ch.rank("founder"), ChannelRole::Founder);
+        assert!(ch.ops.contains("founder"), "founders are always ops");
+        assert_eq!(ch.rank("admin"), ChannelRole::Admin);
+        assert_eq!(ch.rank("member"), ChannelRole::Member);
+        assert_eq!(ch.prefix("founder", false), "~");
+        assert_eq!(ch.prefix("founder", true), "~@");
+        assert_eq!(ch.prefix("admin", true), "&@");
+        assert_eq!(ch.prefix("op", false), "@");
+        assert_eq!(ch.prefix("op", true), "@+");
+        assert_eq!(ch.prefix("member", true), "");
+
+        // The founder DID moves: the old session loses ~ on refresh.
+        ch.founder_did = Some("did:plc:other".to_string());
+        ch.refresh_founders(&dids);
+        assert_eq!(ch.rank("founder"), ChannelRole::Op);
+    }
+
+    #[test]
+    fn auditorium_hides_ordinary_members_from_each_other() {
+        let mut ch = ChannelState::default();
+        for sid in ["op", "voice", "a", "b"] {
+            ch.members.insert(sid.to_string());
+        }
+        ch.ops.insert("op".to_string());
+        ch.voiced.insert("voice".to_string());
+        let sorted = |mut v: Vec<String>| {
+            v.sort();
+            v
+        };
+
+        assert_eq!(sorted(ch.audience("a")).len(), 4);
+        ch.auditorium = true;
+        assert_eq!(sorted(ch.audience("a")), ["a", "op", "voice"]);
+        assert_eq!(sorted(ch.audience("voice")).len(), 4);
+        assert!(ch.sees_all_members("op"));
+        assert!(!ch.sees_all_members("b"));
+    }
+
+    #[test]
+    fn history_access_follows_visibility_and_bans() {
+        let mut ch = ChannelState::default();
+        ch.members.insert("member".to_string());
+        ch.member_since.insert("member".to_string(), 100);
+        let access = |ch: &ChannelState, sid: &str, windows| {
+            ch.history_access(sid, &format!("{sid}!u@h"), None, windows)
+        };
+
+        assert_eq!(access(&ch, "member", vec![]), Some(HistoryAccess::Full));
+        assert_eq!(access(&ch, "outsider", vec![]), None);
+
+        ch.history_visibility = HistoryVisibility::MembersSinceJoin;
+        let since = access(&ch, "member", vec![(10, Some(20))]).unwrap();
+        assert!(since.allows(15) && since.allows(100) && since.allows(500));
+        assert!(!since.allows(5) && !since.allows(50));
+        assert!(!ch.history_readable_anonymously());
+
+        ch.history_visibility = HistoryVisibility::Public;
+        assert_eq!(access(&ch, "outsider", vec![]), Some(HistoryAccess::Full));
+        assert!(ch.history_readable_anonymously());
+        ch.bans.push(BanEntry::new("outsider!*@*".into(), "op".into()));
+        assert_eq!(access(&ch, "outsider", vec![]), None);
+    }
+
     #[test]
     fn bind_identity_binds_then_updates_same_did() {
         let state = test_state();
@@ -6290,6 +8266,52 @@ mod s2s_adversarial_tests {
         assert_eq!(state.bind_identity("did:key:B", "foo"), BindOutcome::Bound);
     }
 
+    #[test]
+    fn bind_identity_refuses_confusable_nick() {
+        let state = test_state();
+        assert_eq!(
+            state.bind_identity("did:key:A", "chadfowler.com"),
+            BindOutcome::Bound
+        );
+        // Capital I for l, Cyrillic а, zero-width joiner: all refused.
+        for spoof in ["chadfowIer.com", "chаdfowler.com", "chad\u{200D}fowler.com"] {
+            assert_eq!(
+                state.bind_identity("did:key:B", spoof),
+                BindOutcome::ConflictOwnedByOther {
+                    owner_did: "did:key:A".to_string()
+                },
+                "{spoof}"
+            );
+        }
+        // The owner may use a look-alike of their own nick.
+        assert_eq!(
+            state.bind_identity("did:key:A", "chadfowIer.com"),
+            BindOutcome::Bound
+        );
+    }
+
+    #[test]
+    fn bind_identity_refuses_lowercase_l_for_owned_capital_i() {
+        let state = test_state();
+        // Stored folded as "ian".
+        assert_eq!(state.bind_identity("did:key:A", "Ian"), BindOutcome::Bound);
+        assert_eq!(
+            state.bind_identity("did:key:B", "lan"),
+            BindOutcome::ConflictOwnedByOther {
+                owner_did: "did:key:A".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn renamed_nick_stops_blocking_its_look_alikes() {
+        let state = test_state();
+        assert_eq!(state.bind_identity("did:key:A", "Ian"), BindOutcome::Bound);
+        assert_eq!(state.bind_identity("did:key:A", "zed"), BindOutcome::Bound);
+        assert!(!state.nick_skeletons.lock().values().any(|n| n.contains("ian")));
+        assert_eq!(state.bind_identity("did:key:B", "lan"), BindOutcome::Bound);
+    }
+
     /// Going-forward contract for the DM partner name resolution bug:
     /// an authenticated DID colliding on an owned nick gets a
     /// deterministic, identity-derived nick that is durably persisted
@@ -6859,10 +8881,19 @@ mod s2s_adversarial_tests {
             invite_only: false,
             no_ext_msg: false,
             moderated: false,
+            archived: false,
+            auditorium: false,
+            no_stats: false,
+            secret: false,
+            temporary: None,
+            history_visibility: None,
             key: None,
             bans: vec![],
             invites: vec![],
             invite_exceptions: vec![],
+            quiets: vec![],
+            topic_history: vec![],
+            policy_head: None,
         }
     }
 
@@ -6885,12 +8916,12 @@ mod s2s_adversarial_tests {
         let mgr = test_manager();
         setup_authenticated_peer(&state, &mgr).await;
         setup_channel(&state, "#kchan");
-        state.channels.lock().get_mut("#kchan").unwrap().key = Some("sekrit".to_string());
+        state.channels.get("#kchan").unwrap().key = Some("sekrit".to_string());
 
         // Peer snapshot says the key was removed (-k). No local members →
         // adopt the full snapshot, including removal.
         sync(&state, &mgr, sync_info("#kchan")).await;
-        assert_eq!(state.channels.lock().get("#kchan").unwrap().key, None);
+        assert_eq!(state.channels.get("#kchan").unwrap().key, None);
     }
 
     #[tokio::test]
@@ -6900,8 +8931,7 @@ mod s2s_adversarial_tests {
         setup_authenticated_peer(&state, &mgr).await;
         setup_channel(&state, "#kchan2");
         {
-            let mut channels = state.channels.lock();
-            let ch = channels.get_mut("#kchan2").unwrap();
+            let mut ch = state.channels.get("#kchan2").unwrap();
             ch.key = Some("sekrit".to_string());
             ch.members.insert("local-session".to_string());
         }
@@ -6909,7 +8939,7 @@ mod s2s_adversarial_tests {
         // Locals set modes authoritatively — a snapshot must never weaken them.
         sync(&state, &mgr, sync_info("#kchan2")).await;
         assert_eq!(
-            state.channels.lock().get("#kchan2").unwrap().key.as_deref(),
+            state.channels.get("#kchan2").unwrap().key.as_deref(),
             Some("sekrit")
         );
     }
@@ -6920,7 +8950,7 @@ mod s2s_adversarial_tests {
         let mgr = test_manager();
         setup_authenticated_peer(&state, &mgr).await;
         setup_channel(&state, "#ichan");
-        state.channels.lock().get_mut("#ichan").unwrap().founder_did =
+        state.channels.get("#ichan").unwrap().founder_did =
             Some("did:plc:realfounder".to_string());
 
         let mut info = sync_info("#ichan");
@@ -6931,7 +8961,6 @@ mod s2s_adversarial_tests {
         assert!(
             state
                 .channels
-                .lock()
                 .get("#ichan")
                 .unwrap()
                 .invites
@@ -6948,8 +8977,7 @@ mod s2s_adversarial_tests {
         setup_channel(&state, "#ichan2");
         state
             .channels
-            .lock()
-            .get_mut("#ichan2")
+            .get("#ichan2")
             .unwrap()
             .founder_did = Some("did:plc:realfounder".to_string());
 
@@ -6961,7 +8989,6 @@ mod s2s_adversarial_tests {
         assert!(
             state
                 .channels
-                .lock()
                 .get("#ichan2")
                 .unwrap()
                 .invites
@@ -6984,7 +9011,6 @@ mod s2s_adversarial_tests {
         assert_eq!(
             state
                 .channels
-                .lock()
                 .get("#tchan")
                 .unwrap()
                 .topic
@@ -7000,4 +9026,41 @@ mod s2s_adversarial_tests {
             "sync-adopted topic must be seeded into the CRDT"
         );
     }
+
+    #[tokio::test]
+    async fn sync_merges_topic_history() {
+        let state = test_state();
+        let mgr = test_manager();
+        setup_authenticated_peer(&state, &mgr).await;
+        setup_channel(&state, "#hchan");
+        state
+            .channels
+            .get("#hchan")
+            .unwrap()
+            .topic_history = std::collections::VecDeque::from(vec![TopicInfo {
+            text: "local old".to_string(),
+            set_by: "alice".to_string(),
+            set_at: 200,
+        }]);
+
+        let topic = |text: &str, set_at| crate::s2s::SyncTopic {
+            text: text.to_string(),
+            set_by: "bob".to_string(),
+            set_at,
+        };
+        let mut info = sync_info("#hchan");
+        info.topic_history = vec![
+            topic("remote oldest", 100),
+            topic("local old", 200),
+            topic("remote\r\nnewer", 300),
+        ];
+        sync(&state, &mgr, info).await;
+
+        let texts: Vec<String> = state.channels.get("#hchan").unwrap()
+            .topic_history
+            .iter()
+            .map(|t| t.text.clone())
+            .collect();
+        assert_eq!(texts, ["remote oldest", "local old", "remotenewer"]);
+    }
 }
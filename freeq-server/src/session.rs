@@ -0,0 +1,215 @@
+//! Per-session state that other connections need to read.
+//!
+//! Fan-out code asks "does this member want message tags? server-time?
+//! multiline?" for every recipient. That used to be one
+//! `Mutex<HashSet<String>>` per capability on `SharedState`, each of
+//! which disconnect cleanup had to remember to clear. A
+//! [`SessionRegistry`] keeps one [`SessionEntry`] per session instead:
+//! a single lookup answers every capability question, and
+//! [`SessionRegistry::remove`] is the one place a session's entry goes
+//! away.
+
+use freeq_proto::caps;
+
+use crate::sharded::ShardedMap;
+
+/// Capabilities whose state other sessions act on when sending to this
+/// one. Capabilities that only change how the session's own commands are
+/// answered (`sasl`, `draft/chathistory`, the WHOIS extension) stay on
+/// the connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Cap {
+    MessageTags,
+    MultiPrefix,
+    EchoMessage,
+    ServerTime,
+    Batch,
+    /// `draft/multiline`. Only useful alongside [`Cap::Batch`]; see
+    /// `connection::draft_multiline`.
+    DraftMultiline,
+    AccountNotify,
+    ExtendedJoin,
+    AwayNotify,
+    AccountTag,
+    /// `freeq.at/p2p-dm`: direct DM negotiation may be relayed to this
+    /// session; see `connection::p2p`.
+    P2pDm,
+}
+
+impl Cap {
+    pub const ALL: [Cap; 11] = [
+        Cap::MessageTags,
+        Cap::MultiPrefix,
+        Cap::EchoMessage,
+        Cap::ServerTime,
+        Cap::Batch,
+        Cap::DraftMultiline,
+        Cap::AccountNotify,
+        Cap::ExtendedJoin,
+        Cap::AwayNotify,
+        Cap::AccountTag,
+        Cap::P2pDm,
+    ];
+
+    /// The IRCv3 name, as advertised in `CAP LS`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Cap::MessageTags => caps::MESSAGE_TAGS,
+            Cap::MultiPrefix => caps::MULTI_PREFIX,
+            Cap::EchoMessage => caps::ECHO_MESSAGE,
+            Cap::ServerTime => caps::SERVER_TIME,
+            Cap::Batch => caps::BATCH,
+            Cap::DraftMultiline => caps::MULTILINE,
+            Cap::AccountNotify => caps::ACCOUNT_NOTIFY,
+            Cap::ExtendedJoin => caps::EXTENDED_JOIN,
+            Cap::AwayNotify => caps::AWAY_NOTIFY,
+            Cap::AccountTag => caps::ACCOUNT_TAG,
+            Cap::P2pDm => caps::P2P_DM,
+        }
+    }
+
+    /// Look up a capability by its (lowercase) IRCv3 name.
+    pub fn from_name(name: &str) -> Option<Cap> {
+        Cap::ALL.into_iter().find(|cap| cap.name() == name)
+    }
+
+    fn bit(self) -> u16 {
+        1 << self as u8
+    }
+}
+
+/// A set of [`Cap`]s. Cheap to copy out of the registry, so callers can
+/// read every flag they need from one lookup.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Caps(u16);
+
+impl Caps {
+    pub fn has(self, cap: Cap) -> bool {
+        self.0 & cap.bit() != 0
+    }
+
+    pub fn insert(&mut self, cap: Cap) {
+        self.0 |= cap.bit();
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn iter(self) -> impl Iterator<Item = Cap> {
+        Cap::ALL.into_iter().filter(move |cap| self.has(*cap))
+    }
+}
+
+impl FromIterator<Cap> for Caps {
+    fn from_iter<I: IntoIterator<Item = Cap>>(iter: I) -> Self {
+        let mut caps = Caps::default();
+        for cap in iter {
+            caps.insert(cap);
+        }
+        caps
+    }
+}
+
+/// What the registry knows about one session.
+#[derive(Debug, Default)]
+pub struct SessionEntry {
+    /// Negotiated capabilities. Capabilities are only ever added: IRCv3
+    /// `CAP REQ -cap` is not supported.
+    pub caps: Caps,
+}
+
+/// Session ID -> [`SessionEntry`]. Entries are created on first use and
+/// dropped by [`remove`](Self::remove) when the connection is cleaned up.
+///
+/// Sits with the per-session tables in the `SharedState` lock order:
+/// take it after any channel shard and before any connection shard.
+#[derive(Default)]
+pub struct SessionRegistry {
+    entries: ShardedMap<SessionEntry>,
+}
+
+impl SessionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Capabilities negotiated by `session_id`; empty for unknown
+    /// sessions.
+    pub fn caps(&self, session_id: &str) -> Caps {
+        self.entries
+            .get(session_id)
+            .map(|entry| entry.caps)
+            .unwrap_or_default()
+    }
+
+    pub fn has_cap(&self, session_id: &str, cap: Cap) -> bool {
+        self.caps(session_id).has(cap)
+    }
+
+    pub fn enable_cap(&self, session_id: &str, cap: Cap) {
+        self.entries
+            .get_or_insert_with(session_id, SessionEntry::default)
+            .caps
+            .insert(cap);
+    }
+
+    /// Whether any session at all has negotiated `cap`. Lets broadcasts
+    /// that only go to opted-in sessions skip their channel walk.
+    pub fn any_with_cap(&self, cap: Cap) -> bool {
+        self.entries.any(|_, entry| entry.caps.has(cap))
+    }
+
+    /// Forget `session_id`. The single cleanup point for everything in
+    /// its [`SessionEntry`].
+    pub fn remove(&self, session_id: &str) -> Option<SessionEntry> {
+        self.entries.remove(session_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cap_names_round_trip() {
+        for cap in Cap::ALL {
+            assert_eq!(Cap::from_name(cap.name()), Some(cap));
+        }
+        assert_eq!(Cap::from_name(caps::SASL), None);
+        assert_eq!(Cap::from_name("MESSAGE-TAGS"), None);
+    }
+
+    #[test]
+    fn registry_tracks_and_forgets_caps() {
+        let sessions = SessionRegistry::new();
+        assert!(sessions.caps("a").is_empty());
+        assert!(!sessions.any_with_cap(Cap::AwayNotify));
+
+        sessions.enable_cap("a", Cap::MessageTags);
+        sessions.enable_cap("a", Cap::ServerTime);
+        sessions.enable_cap("b", Cap::AwayNotify);
+        let caps = sessions.caps("a");
+        assert!(caps.has(Cap::MessageTags) && caps.has(Cap::ServerTime));
+        assert!(!caps.has(Cap::AwayNotify));
+        assert_eq!(
+            caps.iter().collect::<Vec<_>>(),
+            [Cap::MessageTags, Cap::ServerTime]
+        );
+        assert!(sessions.any_with_cap(Cap::AwayNotify));
+
+        assert!(sessions.remove("b").is_some());
+        assert!(!sessions.any_with_cap(Cap::AwayNotify));
+        assert!(!sessions.has_cap("b", Cap::AwayNotify));
+        assert_eq!(sessions.len(), 1);
+    }
+}
@@ -0,0 +1,249 @@
+//! String-keyed maps split across independently locked shards.
+//!
+//! `SharedState::channels` and `SharedState::connections` used to sit
+//! behind one mutex each, so every JOIN, PRIVMSG and disconnect on the
+//! server queued on the same two locks. A [`ShardedMap`] hashes each key
+//! to one of [`SHARDS`] parking_lot mutexes: operations on different
+//! channels (or sessions) proceed in parallel, and only whole-map walks
+//! visit every shard, one at a time.
+//!
+//! # Lock discipline
+//!
+//! [`ShardedMap::get`] returns a guard over a single shard. While it is
+//! alive:
+//!
+//! - don't touch the same map again: a second key can land on the same
+//!   shard, and parking_lot mutexes are not reentrant;
+//! - only take locks that come *after* it in the order documented on
+//!   `SharedState`.
+//!
+//! The closure-taking walks ([`for_each`](ShardedMap::for_each) and
+//! friends) hold one shard for the duration of each call, under the same
+//! rules.
+
+use std::collections::HashMap;
+use std::hash::{BuildHasher, RandomState};
+
+use parking_lot::{MappedMutexGuard, Mutex, MutexGuard};
+
+/// Number of shards per map. A power of two comfortably above the core
+/// count of the machines freeq runs on, so unrelated keys rarely share
+/// a lock.
+pub const SHARDS: usize = 64;
+
+pub struct ShardedMap<V> {
+    shards: Box<[Mutex<HashMap<String, V>>]>,
+    hasher: RandomState,
+}
+
+impl<V> Default for ShardedMap<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> ShardedMap<V> {
+    pub fn new() -> Self {
+        Self {
+            shards: (0..SHARDS).map(|_| Mutex::new(HashMap::new())).collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    fn shard(&self, key: &str) -> &Mutex<HashMap<String, V>> {
+        let hash = self.hasher.hash_one(key) as usize;
+        &self.shards[hash % SHARDS]
+    }
+
+    /// Lock the shard holding `key` and borrow its value, if present.
+    /// The guard derefs mutably; see the module docs before holding it
+    /// across other lock acquisitions.
+    pub fn get(&self, key: &str) -> Option<MappedMutexGuard<'_, V>> {
+        MutexGuard::try_map(self.shard(key).lock(), |m| m.get_mut(key)).ok()
+    }
+
+    /// Like [`get`](Self::get), inserting `default()` first if `key` is
+    /// absent.
+    pub fn get_or_insert_with(
+        &self,
+        key: &str,
+        default: impl FnOnce() -> V,
+    ) -> MappedMutexGuard<'_, V> {
+        MutexGuard::map(self.shard(key).lock(), |m| {
+            m.entry(key.to_string()).or_insert_with(default)
+        })
+    }
+
+    /// [`get_or_insert_with`](Self::get_or_insert_with), also reporting
+    /// whether the entry was created by this call.
+    pub fn get_or_create(
+        &self,
+        key: &str,
+        default: impl FnOnce() -> V,
+    ) -> (MappedMutexGuard<'_, V>, bool) {
+        let shard = self.shard(key).lock();
+        let created = !shard.contains_key(key);
+        let value = MutexGuard::map(shard, |m| m.entry(key.to_string()).or_insert_with(default));
+        (value, created)
+    }
+
+    pub fn insert(&self, key: String, value: V) -> Option<V> {
+        self.shard(&key).lock().insert(key, value)
+    }
+
+    pub fn remove(&self, key: &str) -> Option<V> {
+        self.shard(key).lock().remove(key)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.shard(key).lock().contains_key(key)
+    }
+
+    /// Entry count. Shards are counted one after another, so under
+    /// concurrent inserts this is approximate.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|s| s.lock().len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|s| s.lock().is_empty())
+    }
+
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys = Vec::new();
+        for shard in self.shards.iter() {
+            keys.extend(shard.lock().keys().cloned());
+        }
+        keys
+    }
+
+    /// Visit every entry, one shard at a time.
+    pub fn for_each(&self, mut f: impl FnMut(&str, &V)) {
+        for shard in self.shards.iter() {
+            for (key, value) in shard.lock().iter() {
+                f(key, value);
+            }
+        }
+    }
+
+    pub fn for_each_mut(&self, mut f: impl FnMut(&str, &mut V)) {
+        for shard in self.shards.iter() {
+            for (key, value) in shard.lock().iter_mut() {
+                f(key, value);
+            }
+        }
+    }
+
+    /// Whether `f` holds for any entry. Stops at the first match.
+    pub fn any(&self, mut f: impl FnMut(&str, &V) -> bool) -> bool {
+        self.shards
+            .iter()
+            .any(|shard| shard.lock().iter().any(|(key, value)| f(key, value)))
+    }
+
+    /// First `Some` result of `f`, in no particular entry order.
+    pub fn find_map<T>(&self, mut f: impl FnMut(&str, &V) -> Option<T>) -> Option<T> {
+        self.shards
+            .iter()
+            .find_map(|shard| shard.lock().iter().find_map(|(key, value)| f(key, value)))
+    }
+
+    /// Number of entries `f` holds for.
+    pub fn count(&self, mut f: impl FnMut(&str, &V) -> bool) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| {
+                shard
+                    .lock()
+                    .iter()
+                    .filter(|(key, value)| f(key, value))
+                    .count()
+            })
+            .sum()
+    }
+
+    /// Collect `f`'s `Some` results over every entry.
+    pub fn filter_map<T>(&self, mut f: impl FnMut(&str, &V) -> Option<T>) -> Vec<T> {
+        let mut out = Vec::new();
+        self.for_each(|key, value| out.extend(f(key, value)));
+        out
+    }
+
+    pub fn retain(&self, mut f: impl FnMut(&str, &mut V) -> bool) {
+        for shard in self.shards.iter() {
+            shard.lock().retain(|key, value| f(key, value));
+        }
+    }
+}
+
+impl<V> FromIterator<(String, V)> for ShardedMap<V> {
+    fn from_iter<I: IntoIterator<Item = (String, V)>>(iter: I) -> Self {
+        let map = Self::new();
+        for (key, value) in iter {
+            map.insert(key, value);
+        }
+        map
+    }
+}
+
+impl<V: Clone> ShardedMap<V> {
+    /// Copy of the whole map. Not a consistent cut: each shard is copied
+    /// under its own lock.
+    pub fn snapshot(&self) -> HashMap<String, V> {
+        let mut out = HashMap::new();
+        self.for_each(|key, value| {
+            out.insert(key.to_string(), value.clone());
+        });
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn basic_operations() {
+        let map = ShardedMap::new();
+        assert!(map.is_empty());
+        assert_eq!(map.insert("#a".to_string(), 1), None);
+        assert_eq!(map.insert("#a".to_string(), 2), Some(1));
+        *map.get_or_insert_with("#b", || 10) += 1;
+        *map.get("#a").unwrap() += 5;
+        assert_eq!(*map.get("#a").unwrap(), 7);
+        assert_eq!(*map.get("#b").unwrap(), 11);
+        assert!(map.get("#c").is_none());
+        assert_eq!(map.len(), 2);
+
+        let mut keys = map.keys();
+        keys.sort();
+        assert_eq!(keys, ["#a", "#b"]);
+        map.retain(|_, v| *v > 10);
+        assert!(!map.contains_key("#a"));
+        assert_eq!(map.remove("#b"), Some(11));
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn concurrent_writers_on_distinct_keys() {
+        let map = Arc::new(ShardedMap::new());
+        let threads: Vec<_> = (0..8)
+            .map(|t| {
+                let map = Arc::clone(&map);
+                std::thread::spawn(move || {
+                    for i in 0..1000 {
+                        *map.get_or_insert_with(&format!("{t}-{}", i % 50), || 0) += 1;
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(map.len(), 8 * 50);
+        let mut total = 0;
+        map.for_each(|_, v| total += *v);
+        assert_eq!(total, 8 * 1000);
+    }
+}
@@ -0,0 +1,236 @@
+//! Channel activity statistics for operators: messages per day, unique
+//! active DIDs per week and peak concurrent members.
+//!
+//! Counters for the current UTC day are kept in memory
+//! ([`ChannelStats`]) and added to daily rollups in the database every
+//! [`FLUSH_INTERVAL`]. Rollups hold counts only, never message content.
+//! To count unique weekly speakers, the DIDs that spoke on a day are
+//! stored for [`ACTIVE_WINDOW_DAYS`] days and then pruned. Guests (no
+//! DID) count towards messages but not towards active DIDs.
+//!
+//! Channels set `+S` opt out. Nothing is collected for them, and setting
+//! the mode deletes what was already stored.
+//!
+//! Ops read the rollups with `STATS <channel>` or
+//! `GET /api/v1/channels/{name}/stats`.
+
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use crate::server::SharedState;
+
+/// How often in-memory counters are written to the rollup tables.
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Days counted as "this week" for unique active DIDs. Speaker DIDs
+/// older than this are deleted.
+pub const ACTIVE_WINDOW_DAYS: u64 = 7;
+
+/// Days shown by `STATS <channel>`.
+pub const STATS_DAYS: u64 = 7;
+
+/// Most days the HTTP endpoint returns.
+pub const MAX_DAYS: u64 = 366;
+
+const SECS_PER_DAY: u64 = 86_400;
+
+/// Days since the Unix epoch (UTC) at `unix_secs`.
+pub fn day(unix_secs: u64) -> u64 {
+    unix_secs / SECS_PER_DAY
+}
+
+/// Today, as days since the Unix epoch (UTC).
+pub fn today() -> u64 {
+    day(chrono::Utc::now().timestamp().max(0) as u64)
+}
+
+/// `YYYY-MM-DD` for a day number.
+pub fn date(day: u64) -> String {
+    chrono::DateTime::from_timestamp((day * SECS_PER_DAY) as i64, 0)
+        .map(|d| d.format("%Y-%m-%d").to_string())
+        .unwrap_or_default()
+}
+
+/// One channel's activity on one day, not yet written out.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Pending {
+    pub messages: u64,
+    pub dids: HashSet<String>,
+    pub peak_members: u64,
+}
+
+/// In-memory counters, keyed by (folded channel, day).
+#[derive(Default)]
+pub struct ChannelStats {
+    pending: Mutex<HashMap<(String, u64), Pending>>,
+}
+
+impl ChannelStats {
+    pub fn record_message(&self, channel: &str, did: Option<&str>, day: u64) {
+        let mut pending = self.pending.lock();
+        let entry = pending.entry((channel.to_string(), day)).or_default();
+        entry.messages += 1;
+        if let Some(did) = did {
+            entry.dids.insert(did.to_string());
+        }
+    }
+
+    pub fn record_members(&self, channel: &str, members: u64, day: u64) {
+        let mut pending = self.pending.lock();
+        let entry = pending.entry((channel.to_string(), day)).or_default();
+        entry.peak_members = entry.peak_members.max(members);
+    }
+
+    /// Drop the unwritten counters for `channel`.
+    pub fn forget(&self, channel: &str) {
+        self.pending.lock().retain(|(c, _), _| c != channel);
+    }
+
+    /// Take every unwritten counter.
+    pub fn take(&self) -> Vec<(String, u64, Pending)> {
+        std::mem::take(&mut *self.pending.lock())
+            .into_iter()
+            .map(|((channel, day), p)| (channel, day, p))
+            .collect()
+    }
+}
+
+/// Whether `channel` exists and hasn't opted out with `+S`.
+fn collecting(state: &SharedState, channel: &str) -> bool {
+    state.channels.get(channel).is_some_and(|ch| !ch.no_stats)
+}
+
+fn member_count(ch: &crate::server::ChannelState) -> u64 {
+    (ch.members.len() + ch.remote_members.len()) as u64
+}
+
+/// Count a message sent to `channel` (folded) by `did`.
+pub fn record_message(state: &SharedState, channel: &str, did: Option<&str>) {
+    if collecting(state, channel) {
+        state.channel_stats.record_message(channel, did, today());
+    }
+}
+
+/// Note `channel`'s (folded) current member count, after a join.
+pub fn record_members(state: &SharedState, channel: &str) {
+    let members = match state.channels.get(channel) {
+        Some(ch) if !ch.no_stats => member_count(&ch),
+        _ => return,
+    };
+    state
+        .channel_stats
+        .record_members(channel, members, today());
+}
+
+/// Sample every channel's member count and write the counters out.
+/// Without a database the counters are discarded.
+pub fn flush(state: &SharedState) {
+    let day = today();
+    let sizes = state.channels.filter_map(|name, ch| {
+        (!ch.no_stats && !ch.members.is_empty()).then(|| (name.to_string(), member_count(ch)))
+    });
+    for (channel, members) in sizes {
+        state.channel_stats.record_members(&channel, members, day);
+    }
+    let pending = state.channel_stats.take();
+    state.with_db(|db| {
+        for (channel, day, p) in &pending {
+            db.add_channel_stats(channel, *day, p)?;
+        }
+        db.prune_channel_stats_actors(day.saturating_sub(ACTIVE_WINDOW_DAYS - 1))
+    });
+}
+
+/// Stop collecting for `channel` (folded) and delete its statistics.
+pub fn forget(state: &SharedState, channel: &str) {
+    state.channel_stats.forget(channel);
+    state.with_db(|db| db.delete_channel_stats(channel));
+}
+
+/// One day of a [`Report`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct DayStats {
+    /// `YYYY-MM-DD`, UTC.
+    pub date: String,
+    pub messages: u64,
+    pub active_dids: u64,
+    pub peak_members: u64,
+}
+
+/// A channel's statistics over the last few days.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Report {
+    pub channel: String,
+    /// Unique DIDs that spoke in the last [`ACTIVE_WINDOW_DAYS`] days.
+    pub active_dids_week: u64,
+    /// Oldest first, ending today; days without activity are zero.
+    pub days: Vec<DayStats>,
+}
+
+/// The last `days` days of `channel` (folded), including today's counts
+/// so far. `None` without a database.
+pub fn report(state: &SharedState, channel: &str, days: u64) -> Option<Report> {
+    flush(state);
+    let today = today();
+    let first = today.saturating_sub(days.clamp(1, MAX_DAYS) - 1);
+    let (rows, active_dids_week) = state.with_db(|db| {
+        Ok((
+            db.channel_stats(channel, first)?,
+            db.channel_active_dids(channel, today.saturating_sub(ACTIVE_WINDOW_DAYS - 1))?,
+        ))
+    })?;
+    let rows: HashMap<u64, _> = rows.into_iter().map(|r| (r.day, r)).collect();
+    let days = (first..=today)
+        .map(|day| {
+            let row = rows.get(&day);
+            DayStats {
+                date: date(day),
+                messages: row.map_or(0, |r| r.messages),
+                active_dids: row.map_or(0, |r| r.active_dids),
+                peak_members: row.map_or(0, |r| r.peak_members),
+            }
+        })
+        .collect();
+    Some(Report {
+        channel: channel.to_string(),
+        active_dids_week,
+        days,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counters_are_per_channel_and_day() {
+        let stats = ChannelStats::default();
+        stats.record_message("#a", Some("did:plc:alice"), 10);
+        stats.record_message("#a", Some("did:plc:alice"), 10);
+        stats.record_message("#a", None, 10);
+        stats.record_message("#a", Some("did:plc:bob"), 11);
+        stats.record_members("#a", 5, 10);
+        stats.record_members("#a", 3, 10);
+        stats.record_message("#b", Some("did:plc:bob"), 10);
+        stats.forget("#b");
+
+        let mut pending = stats.take();
+        pending.sort_by_key(|(c, d, _)| (c.clone(), *d));
+        let summary: Vec<_> = pending
+            .iter()
+            .map(|(c, d, p)| (c.as_str(), *d, p.messages, p.dids.len(), p.peak_members))
+            .collect();
+        assert_eq!(summary, [("#a", 10, 3, 1, 5), ("#a", 11, 1, 1, 0)]);
+        assert!(stats.take().is_empty());
+    }
+
+    #[test]
+    fn dates_are_utc_days() {
+        assert_eq!(day(0), 0);
+        assert_eq!(day(86_399), 0);
+        assert_eq!(day(86_400), 1);
+        assert_eq!(date(day(1_700_000_000)), "2023-11-14");
+    }
+}
@@ -0,0 +1,80 @@
+//! Latency tracing for the message path.
+//!
+//! Every client line gets a `command` span (with `session_id` and the
+//! command) from the moment it has been read until it has been handled.
+//! PRIVMSG and NOTICE nest their stages under it:
+//!
+//! - `parse` — decoding the line;
+//! - `handle` — checks, history and the DB write, carrying `msgid` once
+//!   one has been assigned;
+//! - `fan_out` — queueing the message to each recipient and the S2S relay.
+//!
+//! Each connection's writer records a `write` span per batch of lines it
+//! writes and flushes. The spans are at `DEBUG`, so they cost nothing
+//! unless something subscribes to them: set
+//! `RUST_LOG=freeq_server=debug` to see them in the logs, or build with
+//! the `otel` feature to export them over OTLP (see [`otlp_layer`]).
+//!
+//! Across federation, relayed PRIVMSGs carry a `+freeq.at/hops` tag that
+//! each server appends `<server>@<unix-ms>` to (see
+//! [`crate::s2s::add_hop`]), so a recipient can see how long each link
+//! took, give or take clock skew.
+
+#[cfg(feature = "otel")]
+use tracing_subscriber::Layer;
+
+/// Keeps the OTLP exporter running; dropping it exports what is still
+/// buffered and stops it.
+#[cfg(feature = "otel")]
+pub struct OtlpGuard(opentelemetry_sdk::trace::SdkTracerProvider);
+
+#[cfg(feature = "otel")]
+impl Drop for OtlpGuard {
+    fn drop(&mut self) {
+        if let Err(e) = self.0.shutdown() {
+            tracing::warn!("OTLP exporter shutdown failed: {e}");
+        }
+    }
+}
+
+/// A layer exporting the server's spans over OTLP/HTTP, when
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` is set; `None` otherwise.
+///
+/// The exporter reads the standard `OTEL_EXPORTER_OTLP_*` variables; the
+/// service is named by `OTEL_SERVICE_NAME` (default `freeq-server`). Only
+/// spans are exported, from `DEBUG` up, whatever `RUST_LOG` says about
+/// the logs.
+#[cfg(feature = "otel")]
+pub fn otlp_layer<S>() -> anyhow::Result<Option<(impl Layer<S>, OtlpGuard)>>
+where
+    S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+{
+    use opentelemetry::trace::TracerProvider as _;
+
+    if std::env::var_os("OTEL_EXPORTER_OTLP_ENDPOINT").is_none() {
+        return Ok(None);
+    }
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .build()?;
+    let service = std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| "freeq-server".to_string());
+    let provider = opentelemetry_sdk::trace::SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(
+            opentelemetry_sdk::Resource::builder()
+                .with_service_name(service)
+                .build(),
+        )
+        .build();
+    let tracer = provider.tracer("freeq-server");
+
+    let spans_only = tracing_subscriber::filter::filter_fn(|meta| {
+        meta.is_span()
+            && meta.target().starts_with("freeq_server")
+            && *meta.level() <= tracing::Level::DEBUG
+    });
+    let layer = tracing_opentelemetry::layer()
+        .with_tracer(tracer)
+        .with_filter(spans_only);
+    Ok(Some((layer, OtlpGuard(provider))))
+}
@@ -0,0 +1,215 @@
+//! Temporary channels (`+T <ttl>`): event and breakout channels that
+//! clean up after themselves.
+//!
+//! A temporary channel that has had no members, local or remote, for its
+//! TTL is purged: the in-memory state and everything stored for it
+//! (history, pins, reactions, topic history, metadata, statistics and
+//! content filters) are deleted, as if it had never existed. Shortly
+//! before that ([`warn_before`]), its founder and DID-ops who are online
+//! get a NOTICE so they can rejoin to keep it.
+//!
+//! Channels with a founder DID are exempt unless the mode was set with a
+//! trailing `!` (`+T 2h!`): a founded channel is usually meant to last,
+//! so expiring one has to be asked for.
+//!
+//! [`sweep`] runs every [`SWEEP_INTERVAL`], so expiry is accurate to
+//! about that. Each server purges its own copy of a channel.
+
+use std::fmt;
+use std::time::Duration;
+
+use crate::irc::Message;
+use crate::server::{ChannelState, SharedState, WireLine};
+
+/// How often temporary channels are checked.
+pub const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Shortest TTL `+T` accepts, in seconds.
+pub const MIN_TTL: u64 = 5 * 60;
+
+/// Longest TTL `+T` accepts, in seconds.
+pub const MAX_TTL: u64 = 30 * 86_400;
+
+/// The `+T` setting: purge after `ttl` seconds empty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Temporary {
+    pub ttl: u64,
+    /// Set with a trailing `!`: expire even if the channel has a founder.
+    pub force: bool,
+}
+
+impl Temporary {
+    /// Parse a mode argument: a number of seconds or a number with an
+    /// `s`, `m`, `h` or `d` suffix, optionally followed by `!`. Out of
+    /// [`MIN_TTL`]..=[`MAX_TTL`] is rejected.
+    pub fn parse(s: &str) -> Option<Self> {
+        let (s, force) = match s.strip_suffix('!') {
+            Some(rest) => (rest, true),
+            None => (s, false),
+        };
+        let (digits, unit) = match s.char_indices().last()? {
+            (i, c) if c.is_ascii_alphabetic() => (&s[..i], c.to_ascii_lowercase()),
+            _ => (s, 's'),
+        };
+        let scale = match unit {
+            's' => 1,
+            'm' => 60,
+            'h' => 3_600,
+            'd' => 86_400,
+            _ => return None,
+        };
+        let ttl = digits.parse::<u64>().ok()?.checked_mul(scale)?;
+        (MIN_TTL..=MAX_TTL)
+            .contains(&ttl)
+            .then_some(Self { ttl, force })
+    }
+
+    /// Whether `ch` expires under this setting (see the module docs).
+    pub fn applies(&self, ch: &ChannelState) -> bool {
+        self.force || ch.founder_did.is_none()
+    }
+}
+
+impl fmt::Display for Temporary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&human(self.ttl))?;
+        if self.force {
+            f.write_str("!")?;
+        }
+        Ok(())
+    }
+}
+
+/// `secs` in the largest unit that divides it: `2h`, `90m`, `45s`.
+fn human(secs: u64) -> String {
+    match secs {
+        s if s >= 86_400 && s % 86_400 == 0 => format!("{}d", s / 86_400),
+        s if s >= 3_600 && s % 3_600 == 0 => format!("{}h", s / 3_600),
+        s if s >= 60 && s % 60 == 0 => format!("{}m", s / 60),
+        s => format!("{s}s"),
+    }
+}
+
+/// How long before expiry the warning goes out: half the TTL, at most
+/// ten minutes. Always longer than [`SWEEP_INTERVAL`], so a sweep lands
+/// in the window.
+pub fn warn_before(ttl: u64) -> u64 {
+    (ttl / 2).min(10 * 60)
+}
+
+/// Check every temporary channel at `now` (unix secs): start or reset
+/// its empty clock, warn its founder and DID-ops when expiry is close,
+/// and purge the ones that expired.
+pub fn sweep(state: &SharedState, now: u64) {
+    let mut warnings = Vec::new();
+    let mut expiring = Vec::new();
+    state.channels.for_each_mut(|name, ch| {
+        let Some(temp) = ch.temporary else {
+            return;
+        };
+        if !ch.members.is_empty() || !ch.remote_members.is_empty() || !temp.applies(ch) {
+            ch.empty_since = None;
+            ch.expiry_warned = false;
+            return;
+        }
+        let empty_for = now.saturating_sub(*ch.empty_since.get_or_insert(now));
+        if empty_for >= temp.ttl {
+            expiring.push(name.to_string());
+        } else if !ch.expiry_warned && temp.ttl - empty_for <= warn_before(temp.ttl) {
+            ch.expiry_warned = true;
+            let dids: Vec<String> = ch
+                .founder_did
+                .iter()
+                .chain(ch.did_ops.iter())
+                .cloned()
+                .collect();
+            warnings.push((name.to_string(), dids, temp.ttl - empty_for));
+        }
+    });
+
+    for (channel, dids, left) in warnings {
+        warn(state, &channel, &dids, left);
+    }
+
+    // Re-check emptiness under the shard lock: someone may have joined
+    // since the pass above.
+    let mut purged = Vec::new();
+    state.channels.retain(|name, ch| {
+        let expired = expiring.iter().any(|c| c == name)
+            && ch.members.is_empty()
+            && ch.remote_members.is_empty();
+        if expired {
+            purged.push(name.to_string());
+        }
+        !expired
+    });
+    for channel in purged {
+        purge(state, &channel);
+    }
+}
+
+fn warn(state: &SharedState, channel: &str, dids: &[String], left: u64) {
+    let sessions: Vec<String> = {
+        let did_sessions = state.did_sessions.lock();
+        dids.iter()
+            .filter_map(|did| did_sessions.get(did))
+            .flatten()
+            .cloned()
+            .collect()
+    };
+    let note = format!(
+        "{channel} is temporary and empty; it will be deleted with its history in about {}. Join it to keep it.",
+        human(left.div_ceil(60) * 60)
+    );
+    for session in sessions {
+        let Some(nick) = state
+            .nick_to_session
+            .lock()
+            .get_nick(&session)
+            .map(str::to_string)
+        else {
+            continue;
+        };
+        let reply = Message::from_server(&state.server_name, "NOTICE", vec![&nick, &note]);
+        if let Some(tx) = state.connections.get(&session) {
+            let _ = tx.try_send(WireLine::from(format!("{reply}\r\n")));
+        }
+    }
+}
+
+/// Delete what's left of an expired channel once it's out of
+/// `state.channels`.
+fn purge(state: &SharedState, channel: &str) {
+    state.channel_stats.forget(channel);
+    state.content_filters.lock().remove_scope(channel);
+    state.invites.lock().remove_channel(channel);
+    state.metadata.lock().remove(channel);
+    state.with_db(|db| db.purge_channel(channel));
+    tracing::info!(%channel, "Temporary channel expired and was purged");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_display() {
+        let t = Temporary::parse("2h").unwrap();
+        assert_eq!((t.ttl, t.force), (7_200, false));
+        assert_eq!(t.to_string(), "2h");
+        let t = Temporary::parse("90M!").unwrap();
+        assert_eq!((t.ttl, t.force), (5_400, true));
+        assert_eq!(t.to_string(), "90m!");
+        assert_eq!(Temporary::parse("600").unwrap().to_string(), "10m");
+        assert_eq!(Temporary::parse("1d").unwrap().ttl, 86_400);
+        for bad in ["", "!", "h", "59s", "4m", "31d", "2w", "-5m", "1.5h"] {
+            assert_eq!(Temporary::parse(bad), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn warning_window_outlasts_a_sweep() {
+        assert!(warn_before(MIN_TTL) > SWEEP_INTERVAL.as_secs());
+        assert_eq!(warn_before(MAX_TTL), 600);
+    }
+}
@@ -0,0 +1,510 @@
+//! In-process servers for tests.
+//!
+//! [`TestServer`] runs a full server inside the test process: IRC and HTTP
+//! listeners on random local ports, with its own database and data
+//! directory under the system temp dir (removed on drop). Servers started
+//! with [`TestServer::start_federated`] also get an S2S identity, and
+//! [`link`] joins two of them with an in-memory S2S link. The link carries
+//! the same signed JSON envelopes, Hello handshake and sync as the iroh
+//! transport, so federation invariants run in CI without live servers (see
+//! `tests/s2s_acceptance.rs` for the live-server suite). [`LineClient`]
+//! is a blocking IRC client for driving them line by line.
+//!
+//! ```ignore
+//! let (a, b, link) = freeq_server::testing::pair().await?;
+//! // connect clients to a.irc_addr and b.irc_addr, then...
+//! link.cut().await; // ...simulate a netsplit
+//! ```
+
+use anyhow::{Result, bail};
+use freeq_sdk::auth::{self, ChallengeSigner, KeySigner};
+use freeq_sdk::crypto::PrivateKey;
+use freeq_sdk::did::DidResolver;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::config::ServerConfig;
+use crate::s2s::{AuthenticatedS2sEvent, PeerEntry, S2sManager, S2sMessage};
+use crate::server::{Server, SharedState};
+
+/// How long [`link`] waits for the Hello handshake to complete.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long [`LineClient::rx`] waits for a line before panicking.
+const LINE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The config [`TestServer::start`] uses: random port, no TLS, no iroh.
+pub fn config(server_name: &str) -> ServerConfig {
+    ServerConfig {
+        listen_addr: "127.0.0.1:0".to_string(),
+        server_name: server_name.to_string(),
+        challenge_timeout_secs: 60,
+        ..Default::default()
+    }
+}
+
+/// A server running in this process. Dropping it stops the accept loop
+/// and deletes its data directory.
+pub struct TestServer {
+    pub irc_addr: SocketAddr,
+    pub web_addr: SocketAddr,
+    pub state: Arc<SharedState>,
+    s2s: Option<Arc<S2sManager>>,
+    tasks: Vec<JoinHandle<()>>,
+    accept: JoinHandle<Result<()>>,
+    data_dir: TempDir,
+}
+
+impl TestServer {
+    /// Start a server named `server_name` with a DID resolver that knows
+    /// no one (guests only, unless the test injects sessions).
+    pub async fn start(server_name: &str) -> Result<Self> {
+        Self::start_with(config(server_name), DidResolver::static_map(HashMap::new())).await
+    }
+
+    /// Start a server from `config`. `data_dir` and `db_path` default to a
+    /// fresh temp directory.
+    pub async fn start_with(mut config: ServerConfig, resolver: DidResolver) -> Result<Self> {
+        let data_dir = TempDir::new()?;
+        if config.data_dir.is_none() {
+            config.data_dir = Some(data_dir.path().to_string_lossy().to_string());
+        }
+        if config.db_path.is_none() {
+            let db = data_dir.path().join("freeq.db");
+            config.db_path = Some(db.to_string_lossy().to_string());
+        }
+        let server = Server::with_resolver(config, resolver);
+        let (irc_addr, web_addr, accept, state) = server.start_with_web_state().await?;
+        Ok(Self {
+            irc_addr,
+            web_addr,
+            state,
+            s2s: None,
+            tasks: Vec::new(),
+            accept,
+            data_dir,
+        })
+    }
+
+    /// Start a server with S2S enabled under a fresh random identity,
+    /// ready for [`link`].
+    pub async fn start_federated(server_name: &str) -> Result<Self> {
+        let mut server = Self::start(server_name).await?;
+        server.enable_s2s().await;
+        Ok(server)
+    }
+
+    /// Give the server an S2S identity and event processor, as `--iroh`
+    /// would, but with no transport: links come from [`link`].
+    pub async fn enable_s2s(&mut self) -> Arc<S2sManager> {
+        if let Some(manager) = &self.s2s {
+            return Arc::clone(manager);
+        }
+        let secret = iroh::SecretKey::from_bytes(&rand::random::<[u8; 32]>());
+        let server_id = secret.public().to_string();
+        *self.state.server_iroh_id.lock() = Some(server_id.clone());
+        // Same order as startup: the CRDT actor is the S2S identity before
+        // any link exists.
+        self.state.cluster_doc.rekey_actor(&server_id).await;
+
+        let (manager, events) =
+            crate::s2s::start_with_identity(Arc::clone(&self.state), server_id, secret);
+        *self.state.s2s_manager.lock() = Some(Arc::clone(&manager));
+        self.tasks.push(crate::server::spawn_s2s_processor(
+            Arc::clone(&self.state),
+            Arc::clone(&manager),
+            events,
+        ));
+        self.s2s = Some(Arc::clone(&manager));
+        manager
+    }
+
+    /// The S2S manager, if S2S is enabled.
+    pub fn s2s(&self) -> Option<&Arc<S2sManager>> {
+        self.s2s.as_ref()
+    }
+
+    /// The server's S2S identity (its origin on federated events).
+    pub fn server_id(&self) -> Option<String> {
+        self.s2s.as_ref().map(|m| m.server_id.clone())
+    }
+
+    /// `host:port` of the plain IRC listener, for `ConnectConfig`.
+    pub fn irc_addr_string(&self) -> String {
+        self.irc_addr.to_string()
+    }
+
+    /// Base URL of the HTTP listener, e.g. `http://127.0.0.1:41234`.
+    pub fn web_url(&self) -> String {
+        format!("http://{}", self.web_addr)
+    }
+
+    pub fn data_dir(&self) -> &Path {
+        self.data_dir.path()
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.accept.abort();
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// Start two federated servers (`a.test`, `b.test`) and link them.
+pub async fn pair() -> Result<(TestServer, TestServer, S2sLink)> {
+    let a = TestServer::start_federated("a.test").await?;
+    let b = TestServer::start_federated("b.test").await?;
+    let link = link(&a, &b).await?;
+    Ok((a, b, link))
+}
+
+/// An in-memory S2S link between two [`TestServer`]s. Dropping it stops
+/// the traffic without telling either side; [`S2sLink::cut`] tears it
+/// down the way a dropped connection does.
+pub struct S2sLink {
+    ends: [LinkEnd; 2],
+    forwarders: [JoinHandle<()>; 2],
+}
+
+#[derive(Clone)]
+struct LinkEnd {
+    manager: Arc<S2sManager>,
+    peer_id: String,
+    conn_gen: u64,
+}
+
+/// Link two federated servers and wait for the Hello handshake. Both sides
+/// then exchange a SyncRequest, as on a new iroh connection.
+pub async fn link(a: &TestServer, b: &TestServer) -> Result<S2sLink> {
+    let (Some(ma), Some(mb)) = (a.s2s(), b.s2s()) else {
+        bail!("link needs two servers started with TestServer::start_federated");
+    };
+    let (a_out, a_to_b) = mpsc::channel(256);
+    let (b_out, b_to_a) = mpsc::channel(256);
+    let ends = [
+        LinkEnd::attach(ma, &mb.server_id, a_out).await,
+        LinkEnd::attach(mb, &ma.server_id, b_out).await,
+    ];
+    let forwarders = [
+        forward(Arc::clone(ma), ends[1].clone(), a_to_b),
+        forward(Arc::clone(mb), ends[0].clone(), b_to_a),
+    ];
+    let link = S2sLink { ends, forwarders };
+
+    for end in &link.ends {
+        end.greet().await;
+    }
+    tokio::time::timeout(HANDSHAKE_TIMEOUT, async {
+        while !link.is_established().await {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .map_err(|_| anyhow::anyhow!("S2S handshake timed out"))?;
+    Ok(link)
+}
+
+impl S2sLink {
+    /// Whether both sides have authenticated each other.
+    pub async fn is_established(&self) -> bool {
+        for end in &self.ends {
+            let authenticated = end.manager.authenticated_peers.lock().await;
+            if !authenticated.contains(&end.peer_id) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Drop the link: both sides forget the peer and process a
+    /// PeerDisconnected, so remote members disappear as in a netsplit.
+    pub async fn cut(self) {
+        for forwarder in &self.forwarders {
+            forwarder.abort();
+        }
+        for end in &self.ends {
+            end.detach().await;
+        }
+    }
+}
+
+impl Drop for S2sLink {
+    fn drop(&mut self) {
+        for forwarder in &self.forwarders {
+            forwarder.abort();
+        }
+    }
+}
+
+impl LinkEnd {
+    async fn attach(
+        manager: &Arc<S2sManager>,
+        peer_id: &str,
+        tx: mpsc::Sender<S2sMessage>,
+    ) -> Self {
+        // Fault-injection builds shape this link like an iroh one.
+        #[cfg(feature = "s2s-faults")]
+        let tx = crate::s2s_faults::stage(&manager.server_id, peer_id, tx);
+        let conn_gen = manager.conn_gen.fetch_add(1, Ordering::Relaxed);
+        manager
+            .peers
+            .lock()
+            .await
+            .insert(peer_id.to_string(), PeerEntry { tx, conn_gen });
+        Self {
+            manager: Arc::clone(manager),
+            peer_id: peer_id.to_string(),
+            conn_gen,
+        }
+    }
+
+    /// Send Hello and SyncRequest, as `handle_s2s_connection` does.
+    async fn greet(&self) {
+        let trust = self.manager.get_trust(&self.peer_id).await;
+        let hello = S2sMessage::Hello {
+            peer_id: self.manager.server_id.clone(),
+            server_name: self.manager.server_name.clone(),
+            protocol_version: 2,
+            trust_level: Some(trust.to_string()),
+        };
+        if let Some(entry) = self.manager.peers.lock().await.get(&self.peer_id) {
+            let _ = entry.tx.send(hello).await;
+            let _ = entry.tx.send(S2sMessage::SyncRequest).await;
+        }
+    }
+
+    /// The teardown half of `handle_s2s_connection`.
+    async fn detach(&self) {
+        self.manager
+            .drop_link(&self.peer_id, Some(self.conn_gen))
+            .await;
+    }
+}
+
+/// Carry `from`'s outbound messages to the other side (`to`, its end of
+/// the link): sealed and serialized to a JSON line by the sender, parsed
+/// and opened by the receiver, exactly as over QUIC. When `from` drops the
+/// link (e.g. `SQUIT`), `to` sees the connection close.
+fn forward(
+    from: Arc<S2sManager>,
+    to_end: LinkEnd,
+    mut outbound: mpsc::Receiver<S2sMessage>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let to = &to_end.manager;
+        while let Some(msg) = outbound.recv().await {
+            let line = match serde_json::to_string(&from.seal(msg)) {
+                Ok(line) => line,
+                Err(e) => {
+                    tracing::warn!("test S2S link: serialize error: {e}");
+                    continue;
+                }
+            };
+            let msg = match serde_json::from_str::<S2sMessage>(&line) {
+                Ok(msg) => msg,
+                Err(e) => {
+                    tracing::warn!("test S2S link: invalid JSON: {e}");
+                    continue;
+                }
+            };
+            let Some(msg) = to.open(msg, &from.server_id) else {
+                continue;
+            };
+            let event = AuthenticatedS2sEvent {
+                authenticated_peer_id: from.server_id.clone(),
+                msg,
+            };
+            if to.event_tx.send(event).await.is_err() {
+                return;
+            }
+        }
+        to_end.detach().await;
+    })
+}
+
+/// A directory under the system temp dir, removed on drop.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> std::io::Result<Self> {
+        let path = std::env::temp_dir().join(format!(
+            "freeq-test-{}-{:016x}",
+            std::process::id(),
+            rand::random::<u64>()
+        ));
+        std::fs::create_dir_all(&path)?;
+        Ok(Self(path))
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A blocking, line-at-a-time IRC client for integration tests. Run it
+/// inside `spawn_blocking`; every read and write panics on failure, so a
+/// test reads as the conversation it checks.
+///
+/// ```ignore
+/// let mut alice = LineClient::with_sasl(addr, "alice", DID, key);
+/// alice.tx("JOIN #dev");
+/// alice.num("366");
+/// ```
+pub struct LineClient {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl LineClient {
+    /// Open a connection without registering.
+    pub fn connect(addr: SocketAddr) -> Self {
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(LINE_TIMEOUT)).ok();
+        let writer = stream.try_clone().unwrap();
+        Self {
+            reader: BufReader::new(stream),
+            writer,
+        }
+    }
+
+    /// Register as an unauthenticated guest and wait for the welcome.
+    pub fn guest(addr: SocketAddr, nick: &str) -> Self {
+        let mut c = Self::connect(addr);
+        c.tx(&format!("NICK {nick}"));
+        c.tx(&format!("USER {nick} 0 * :test"));
+        c.num("001");
+        c
+    }
+
+    /// Register as a guest with `caps` negotiated.
+    pub fn guest_with_caps(addr: SocketAddr, nick: &str, caps: &str) -> Self {
+        let mut c = Self::connect(addr);
+        c.tx("CAP LS 302");
+        c.tx(&format!("NICK {nick}"));
+        c.tx(&format!("USER {nick} 0 * :test"));
+        c.tx(&format!("CAP REQ :{caps}"));
+        c.rx(|l| l.contains("ACK"), "CAP ACK");
+        c.tx("CAP END");
+        c.num("001");
+        c
+    }
+
+    /// Sign in as `did` over SASL ATPROTO-CHALLENGE and wait for the
+    /// welcome.
+    pub fn with_sasl(addr: SocketAddr, nick: &str, did: &str, key: PrivateKey) -> Self {
+        Self::with_sasl_caps(addr, nick, did, key, "")
+    }
+
+    /// [`LineClient::with_sasl`], with `caps` negotiated alongside `sasl`.
+    pub fn with_sasl_caps(
+        addr: SocketAddr,
+        nick: &str,
+        did: &str,
+        key: PrivateKey,
+        caps: &str,
+    ) -> Self {
+        let mut c = Self::connect(addr);
+        c.tx("CAP LS 302");
+        c.tx(&format!("NICK {nick}"));
+        c.tx(&format!("USER {nick} 0 * :test"));
+        c.tx(format!("CAP REQ :sasl {caps}").trim_end());
+        c.rx(|l| l.contains("ACK"), "CAP ACK");
+        c.authenticate(did, key);
+        c.tx("CAP END");
+        c.num("001");
+        c
+    }
+
+    /// Answer an ATPROTO-CHALLENGE as `did` and wait for 903. The `sasl`
+    /// cap must already be acknowledged.
+    pub fn authenticate(&mut self, did: &str, key: PrivateKey) {
+        self.tx("AUTHENTICATE ATPROTO-CHALLENGE");
+        let line = self.rx(|l| l.starts_with("AUTHENTICATE "), "challenge");
+        let challenge = line.strip_prefix("AUTHENTICATE ").unwrap();
+        let bytes = auth::decode_challenge_bytes(challenge).unwrap();
+        let response = KeySigner::new(did.to_string(), key)
+            .respond(&bytes)
+            .unwrap();
+        self.tx(&format!(
+            "AUTHENTICATE {}",
+            auth::encode_response(&response)
+        ));
+        self.num("903");
+    }
+
+    /// Send one line.
+    pub fn tx(&mut self, line: &str) {
+        write!(self.writer, "{line}\r\n").unwrap();
+        self.writer.flush().ok();
+    }
+
+    /// Read until a line matches `pred`, answering PINGs on the way, and
+    /// return it. Panics with `what` on EOF or timeout.
+    pub fn rx(&mut self, pred: impl Fn(&str) -> bool, what: &str) -> String {
+        let mut buf = String::new();
+        loop {
+            buf.clear();
+            match self.reader.read_line(&mut buf) {
+                Ok(0) => panic!("EOF: {what}"),
+                Ok(_) => {
+                    let line = buf.trim_end();
+                    if let Some(token) = line.strip_prefix("PING ") {
+                        let _ = write!(self.writer, "PONG {token}\r\n");
+                        continue;
+                    }
+                    if pred(line) {
+                        return line.to_string();
+                    }
+                }
+                Err(e) => panic!("{what}: {e}"),
+            }
+        }
+    }
+
+    /// Read until the numeric `code`.
+    pub fn num(&mut self, code: &str) -> String {
+        self.rx(|l| l.split_whitespace().nth(1) == Some(code), code)
+    }
+
+    /// Join `channel` and wait for the end of its NAMES.
+    pub fn join(&mut self, channel: &str) {
+        self.tx(&format!("JOIN {channel}"));
+        self.num("366");
+    }
+
+    /// Discard whatever arrives in the next 300ms.
+    pub fn drain(&mut self) {
+        self.writer
+            .set_read_timeout(Some(Duration::from_millis(300)))
+            .ok();
+        let mut buf = String::new();
+        loop {
+            buf.clear();
+            match self.reader.read_line(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    if let Some(token) = buf.trim_end().strip_prefix("PING ") {
+                        let _ = write!(self.writer, "PONG {token}\r\n");
+                    }
+                }
+            }
+        }
+        self.writer.set_read_timeout(Some(LINE_TIMEOUT)).ok();
+    }
+}
@@ -15,6 +15,7 @@ pub mod bluesky;
 pub mod github;
 pub mod moderation;
 pub mod oidc;
+pub mod role;
 
 use axum::Router;
 use ed25519_dalek::SigningKey;
@@ -34,6 +35,8 @@ pub struct VerifierState {
     pub pending: parking_lot::Mutex<std::collections::HashMap<String, PendingVerification>>,
     /// Moderator roster: channel → active appointments.
     pub mod_roster: parking_lot::Mutex<moderation::ModRoster>,
+    /// Secret services present to issue role credentials (if configured).
+    pub role_secret: Option<String>,
 }
 
 #[derive(Clone)]
@@ -82,6 +85,7 @@ fn load_or_generate_signing_key(path: &std::path::Path) -> SigningKey {
 pub fn router(
     issuer_did: String,
     github: Option<GitHubConfig>,
+    role_secret: Option<String>,
     data_dir: &std::path::Path,
 ) -> Option<(Router<()>, Arc<VerifierState>)> {
     let key_path = data_dir.join("verifier-signing-key.secret");
@@ -112,6 +116,7 @@ pub fn router(
         mod_roster: parking_lot::Mutex::new(moderation::ModRoster {
             channels: std::collections::HashMap::new(),
         }),
+        role_secret,
     });
 
     let mut app = Router::new()
@@ -134,6 +139,11 @@ pub fn router(
         app = app.merge(oidc::routes());
     }
 
+    // Role verifier — only if a role issuer secret is configured
+    if state.role_secret.is_some() {
+        app = app.merge(role::routes());
+    }
+
     // GitHub verifier — only if OAuth credentials are configured
     if state.github.is_some() {
         app = app.merge(github::routes());
@@ -99,6 +99,7 @@ async fn start_page(Query(params): Query<HashMap<String, String>>) -> impl IntoR
     <div class="info">
       The moderator will receive <strong>+h (halfop)</strong> status:<br>
       • Can kick and ban regular users<br>
+      • Can quiet (+q) users without removing them<br>
       • Can voice/unvoice users (+v)<br>
       • Cannot kick other moderators or operators<br>
       • Cannot change channel modes (+m, +t, etc.)
@@ -146,7 +147,7 @@ async fn appoint(
         }
     };
 
-    let channel_lower = req.channel.to_lowercase();
+    let channel_lower = crate::casemap::fold(&req.channel);
 
     // Build and sign the credential
     let mut credential = VerifiableCredential {
@@ -244,7 +245,7 @@ async fn revoke(
     State(state): State<Arc<VerifierState>>,
     Json(req): Json<RevokeRequest>,
 ) -> impl IntoResponse {
-    let channel_lower = req.channel.to_lowercase();
+    let channel_lower = crate::casemap::fold(&req.channel);
     let mut roster = state.mod_roster.lock();
     if let Some(entries) = roster.channels.get_mut(&channel_lower) {
         for entry in entries.iter_mut() {
@@ -266,7 +267,7 @@ async fn roster(
     State(state): State<Arc<VerifierState>>,
     Query(query): Query<RosterQuery>,
 ) -> impl IntoResponse {
-    let channel_lower = query.channel.to_lowercase();
+    let channel_lower = crate::casemap::fold(&query.channel);
     let roster = state.mod_roster.lock();
     let now = chrono::Utc::now();
     let active: Vec<&ModAppointment> = roster
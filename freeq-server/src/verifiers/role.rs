@@ -0,0 +1,190 @@
+//! Role appointment verifier.
+//!
+//! Lets a trusted service appoint users to a named role, such as
+//! `factory-operator` for freeq-bots, by issuing a signed credential of
+//! that type. The service decides who may appoint whom — freeq-bots only
+//! lets existing operators grant the role — and this verifier signs what
+//! it is asked to, so the route is only mounted when `--role-issuer-secret`
+//! is set, and callers must send it as `Authorization: Bearer <secret>`.
+//!
+//! Like the moderation verifier, the credential is POSTed to the callback
+//! (normally `/api/v1/credentials/present`), where the server checks the
+//! signature and stores it for `GET /api/v1/credentials/{did}`.
+//!
+//! Routes:
+//!   POST /verify/role/appoint  — Issue a role credential
+
+use super::VerifierState;
+use crate::policy::credentials;
+use crate::policy::types::VerifiableCredential;
+use axum::{
+    Json, Router,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    routing::post,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Longest role name.
+const MAX_ROLE_LEN: usize = 64;
+
+/// Default lifetime of a role credential, in days.
+const DEFAULT_DAYS: u64 = 365;
+
+/// Longest lifetime a caller may ask for, in days.
+const MAX_DAYS: u64 = 3650;
+
+pub fn routes() -> Router<Arc<VerifierState>> {
+    Router::new().route("/verify/role/appoint", post(appoint))
+}
+
+#[derive(Deserialize)]
+struct AppointRequest {
+    role: String,
+    subject_did: String,
+    appointed_by: String,
+    #[serde(default)]
+    callback: String,
+    duration_days: Option<u64>,
+}
+
+/// Whether `role` can be issued here: lowercase letters, digits, `-` and
+/// `_`. `channel_moderator` belongs to the moderation verifier.
+fn valid_role(role: &str) -> bool {
+    !role.is_empty()
+        && role.len() <= MAX_ROLE_LEN
+        && role
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+        && role != "channel_moderator"
+}
+
+/// Whether `headers` carry the role issuer secret.
+fn authorized(state: &VerifierState, headers: &HeaderMap) -> bool {
+    let Some(secret) = &state.role_secret else {
+        return false;
+    };
+    headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|given| {
+            crate::connection::constant_time_eq(given.as_bytes(), secret.as_bytes())
+        })
+}
+
+fn error(status: StatusCode, message: &str) -> axum::response::Response {
+    (status, Json(serde_json::json!({ "error": message }))).into_response()
+}
+
+/// Appoint `subject_did` to `role` — issue a signed credential.
+async fn appoint(
+    State(state): State<Arc<VerifierState>>,
+    headers: HeaderMap,
+    Json(req): Json<AppointRequest>,
+) -> axum::response::Response {
+    if !authorized(&state, &headers) {
+        return error(StatusCode::UNAUTHORIZED, "Role issuer secret required");
+    }
+    if !valid_role(&req.role) {
+        return error(StatusCode::BAD_REQUEST, "Invalid role name");
+    }
+    if !req.subject_did.starts_with("did:") || !req.appointed_by.starts_with("did:") {
+        return error(
+            StatusCode::BAD_REQUEST,
+            "subject_did and appointed_by must be DIDs",
+        );
+    }
+
+    let duration_days = req.duration_days.unwrap_or(DEFAULT_DAYS).clamp(1, MAX_DAYS);
+    let now = chrono::Utc::now();
+    let expires = now + chrono::Duration::days(duration_days as i64);
+
+    let mut credential = VerifiableCredential {
+        credential_type_tag: "FreeqCredential/v1".to_string(),
+        issuer: state.issuer_did.clone(),
+        subject: req.subject_did.clone(),
+        credential_type: req.role.clone(),
+        claims: serde_json::json!({
+            "role": req.role,
+            "appointed_by": req.appointed_by,
+        }),
+        issued_at: now.to_rfc3339(),
+        expires_at: Some(expires.to_rfc3339()),
+        signature: String::new(),
+    };
+    if let Err(e) = credentials::sign_credential(&mut credential, &state.signing_key) {
+        return error(StatusCode::INTERNAL_SERVER_ERROR, &e);
+    }
+
+    tracing::info!(
+        role = %req.role, subject = %req.subject_did, appointed_by = %req.appointed_by,
+        "Role credential issued"
+    );
+
+    // If callback is provided, POST the credential there
+    if !req.callback.is_empty() {
+        let http = reqwest::Client::new();
+        let payload = serde_json::json!({ "credential": credential });
+        let delivered = http
+            .post(&req.callback)
+            .json(&payload)
+            .timeout(std::time::Duration::from_secs(10))
+            .send()
+            .await
+            .is_ok_and(|resp| resp.status().is_success());
+        if !delivered {
+            return error(
+                StatusCode::BAD_GATEWAY,
+                "Credential issued but the callback did not accept it",
+            );
+        }
+    }
+
+    Json(serde_json::json!({ "ok": true, "credential": credential })).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn role_names() {
+        assert!(valid_role("factory-operator"));
+        assert!(valid_role("release_manager2"));
+        assert!(!valid_role(""));
+        assert!(!valid_role("Factory-Operator"));
+        assert!(!valid_role("op erator"));
+        assert!(!valid_role("channel_moderator"));
+        assert!(!valid_role(&"a".repeat(MAX_ROLE_LEN + 1)));
+    }
+
+    #[test]
+    fn appointing_needs_the_secret() {
+        let state = VerifierState {
+            signing_key: ed25519_dalek::SigningKey::from_bytes(&[7; 32]),
+            issuer_did: "did:web:example.com:verify".to_string(),
+            github: None,
+            oidc: None,
+            pending: Default::default(),
+            mod_roster: parking_lot::Mutex::new(super::super::moderation::ModRoster {
+                channels: Default::default(),
+            }),
+            role_secret: Some("s3cret".to_string()),
+        };
+        let mut headers = HeaderMap::new();
+        assert!(!authorized(&state, &headers));
+        headers.insert("authorization", "Bearer wrong".parse().unwrap());
+        assert!(!authorized(&state, &headers));
+        headers.insert("authorization", "Bearer s3cret".parse().unwrap());
+        assert!(authorized(&state, &headers));
+
+        let unset = VerifierState {
+            role_secret: None,
+            ..state
+        };
+        assert!(!authorized(&unset, &headers));
+    }
+}
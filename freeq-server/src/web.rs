@@ -22,6 +22,7 @@ use serde::{Deserialize, Serialize};
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
 use tower_http::cors::CorsLayer;
 
+use crate::policy::types::HistoryVisibility;
 use crate::server::SharedState;
 
 // ── WebSocket ↔ IRC bridge ─────────────────────────────────────────────
@@ -171,6 +172,7 @@ pub fn router(state: Arc<SharedState>) -> Router {
     let mut app = Router::new()
         // WebSocket IRC transport
         .route("/irc", get(ws_upgrade))
+        .route("/firehose", get(firehose_upgrade))
         // OAuth endpoints for web client
         .route("/auth/login", get(auth_login))
         .route("/auth/callback", get(auth_callback))
@@ -180,8 +182,12 @@ pub fn router(state: Arc<SharedState>) -> Router {
         .route("/auth/step-up", get(auth_step_up))
         .route("/auth/broker/web-token", post(auth_broker_web_token))
         .route("/auth/broker/session", post(auth_broker_session))
+        .route("/auth/broker/events", post(auth_broker_events))
         .route("/client-metadata.json", get(client_metadata))
         // REST API (read-only, v1)
+        // Orchestrator probes: liveness and readiness
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
         .route("/api/v1/health", get(api_health))
         .route("/metrics", get(api_metrics))
         .route("/api/v1/channels", get(api_channels))
@@ -189,10 +195,19 @@ pub fn router(state: Arc<SharedState>) -> Router {
         .route("/api/v1/search", get(api_search))
         .route("/api/v1/messages/{msgid}", get(api_message_by_id))
         .route("/api/v1/channels/{name}/export", get(api_channel_export))
+        .route("/api/v1/archive/{name}", get(api_channel_archive))
         .route("/api/v1/channels/{name}/topic", get(api_channel_topic))
         .route("/api/v1/channels/{name}/pins", get(api_channel_pins))
         .route("/api/v1/users/{nick}", get(api_user))
         .route("/api/v1/users/{nick}/whois", get(api_user_whois))
+        .route("/api/v1/me/export", get(api_me_export))
+        .route("/api/v1/me/erase", post(api_me_erase))
+        .route("/api/v1/me/prefs", get(api_me_prefs))
+        .route(
+            "/api/v1/me/prefs/{key}",
+            axum::routing::put(api_me_pref_set).delete(api_me_pref_reset),
+        )
+        .route("/api/v1/admin/backup", post(api_admin_backup))
         .route("/api/v1/upload", axum::routing::post(api_upload))
         .route("/api/v1/blob", get(api_blob_proxy))
         // Private media: serve an encrypted-at-rest blob via a signed capability
@@ -226,6 +241,7 @@ pub fn router(state: Arc<SharedState>) -> Router {
         .route("/api/v1/agents/spawned", get(api_spawned_agents))
         .route("/api/v1/channels/{name}/budget", get(api_channel_budget))
         .route("/api/v1/channels/{name}/spend", get(api_channel_spend))
+        .route("/api/v1/channels/{name}/stats", get(api_channel_stats))
         // AV call page + assets (served here so it's accessible through Miren's HTTPS)
         .route("/av/call", get(av_call_page))
         .route("/av/call.html", get(av_call_page))
@@ -246,6 +262,13 @@ pub fn router(state: Arc<SharedState>) -> Router {
         )
         .route("/auth/mobile", get(auth_mobile_redirect))
         .route("/join/{channel}", get(channel_invite_page))
+        .route("/invite/{token}", get(invite_link_page))
+        .route("/email/verify/{token}", get(email_verify_page))
+        .route(
+            "/email/unsubscribe/{token}",
+            get(email_unsubscribe_page).post(email_unsubscribe),
+        )
+        .route("/archive/{name}", get(channel_archive_page))
         .layer(axum::extract::DefaultBodyLimit::max(12 * 1024 * 1024)) // 12MB
         .layer({
             use axum::http::{Method, header};
@@ -304,7 +327,13 @@ pub fn router(state: Arc<SharedState>) -> Router {
                     .unwrap_or(std::path::Path::new("."))
             })
             .unwrap_or(std::path::Path::new("."));
-        crate::verifiers::router(issuer_did, github_config, data_dir).map(|(r, _)| r)
+        crate::verifiers::router(
+            issuer_did,
+            github_config,
+            state.config.role_issuer_secret.clone(),
+            data_dir,
+        )
+        .map(|(r, _)| r)
     };
 
     // Serve static web client files if the directory exists
@@ -342,10 +371,9 @@ async fn ws_upgrade(
 ) -> impl IntoResponse {
     let ip = addr.ip();
     // Per-IP connection limit for WebSocket (same limit as TCP)
-    const MAX_CONNS_PER_IP: u32 = 20;
-    {
+    if !crate::connection::webirc::is_gateway(&state.config, ip) {
         let ip_conns = state.ip_connections.lock();
-        if ip_conns.get(&ip).copied().unwrap_or(0) >= MAX_CONNS_PER_IP {
+        if ip_conns.get(&ip).copied().unwrap_or(0) >= crate::server::MAX_CONNS_PER_IP {
             tracing::warn!(%ip, "WebSocket connection rejected: per-IP limit reached");
             return axum::http::StatusCode::TOO_MANY_REQUESTS.into_response();
         }
@@ -360,7 +388,7 @@ async fn handle_ws(socket: WebSocket, state: Arc<SharedState>, ip: std::net::IpA
         *ip_conns.entry(ip).or_insert(0) += 1;
     }
     let stream = bridge_ws(socket);
-    if let Err(e) = crate::connection::handle_generic(stream, state.clone()).await {
+    if let Err(e) = crate::connection::handle_websocket(stream, state.clone(), ip).await {
         tracing::error!("WebSocket connection error: {e}");
     }
     // Decrement on disconnect
@@ -373,6 +401,67 @@ async fn handle_ws(socket: WebSocket, state: Arc<SharedState>, ip: std::net::IpA
     }
 }
 
+/// GET /firehose — oper-only WebSocket streaming [`crate::firehose`]
+/// events as JSON text frames. Auth is `Authorization: Bearer <token>`
+/// with the OPER-TOKEN a session got on gaining OPER; the stream closes
+/// soon after that session loses it. Query parameters (`types`, `channel`, `did`)
+/// filter server-side.
+async fn firehose_upgrade(
+    ws: WebSocketUpgrade,
+    headers: axum::http::HeaderMap,
+    Query(filter): Query<crate::firehose::FirehoseFilter>,
+    State(state): State<Arc<SharedState>>,
+) -> axum::response::Response {
+    let session_id = match oper_from_bearer(&state, &headers) {
+        Ok(session_id) => session_id,
+        Err(status) => return status.into_response(),
+    };
+    ws.on_upgrade(move |socket| stream_firehose(socket, state, session_id, filter))
+        .into_response()
+}
+
+async fn stream_firehose(
+    mut socket: WebSocket,
+    state: Arc<SharedState>,
+    session_id: String,
+    filter: crate::firehose::FirehoseFilter,
+) {
+    use tokio::sync::broadcast::error::RecvError;
+
+    let mut events = state.firehose.subscribe();
+    let mut recheck = tokio::time::interval(tokio::time::Duration::from_secs(5));
+    tracing::info!(%session_id, "Firehose subscriber connected");
+    loop {
+        let frame = tokio::select! {
+            event = events.recv() => match event {
+                Ok(record) if filter.matches(&record.event) => {
+                    serde_json::to_string(&*record).unwrap_or_default()
+                }
+                Ok(_) => continue,
+                Err(RecvError::Lagged(skipped)) => {
+                    serde_json::json!({ "type": "lagged", "skipped": skipped }).to_string()
+                }
+                Err(RecvError::Closed) => break,
+            },
+            _ = recheck.tick() => {
+                if !state.server_opers.lock().contains(&session_id) {
+                    break;
+                }
+                continue;
+            }
+            incoming = socket.recv() => match incoming {
+                Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+        };
+        if socket.send(WsMessage::Text(frame.into())).await.is_err() {
+            break;
+        }
+    }
+    let _ = socket.send(WsMessage::Close(None)).await;
+    tracing::info!(%session_id, "Firehose subscriber disconnected");
+}
+
 // ── REST types ─────────────────────────────────────────────────────────
 
 #[derive(Serialize)]
@@ -493,13 +582,11 @@ async fn api_agent_capabilities(
     let grants: Vec<serde_json::Value> = state
         .with_db(|db| {
             // Get all agents in the channel
-            let members: Vec<String> = {
-                let channels = state.channels.lock();
-                channels
-                    .get(&channel.to_lowercase())
-                    .map(|ch| ch.members.iter().cloned().collect())
-                    .unwrap_or_default()
-            };
+            let members: Vec<String> = state
+                .channels
+                .get(&crate::casemap::fold(&channel))
+                .map(|ch| ch.members.iter().cloned().collect())
+                .unwrap_or_default();
             let dids: Vec<String> = {
                 let sd = state.session_dids.lock();
                 members
@@ -509,7 +596,7 @@ async fn api_agent_capabilities(
             };
             let mut all = Vec::new();
             for did in &dids {
-                for g in db.get_capabilities(&channel.to_lowercase(), did) {
+                for g in db.get_capabilities(&crate::casemap::fold(&channel), did) {
                     all.push(serde_json::json!({
                         "id": g.id,
                         "agent_did": g.agent_did,
@@ -539,7 +626,7 @@ async fn api_pending_approvals(
     let approvals: Vec<serde_json::Value> = state
         .with_db(|db| {
             Ok(db
-                .get_pending_approvals(&channel.to_lowercase())
+                .get_pending_approvals(&crate::casemap::fold(&channel))
                 .into_iter()
                 .map(|a| {
                     serde_json::json!({
@@ -580,7 +667,7 @@ async fn api_channel_events(
 
     let events: Vec<serde_json::Value> = state
         .with_db(|db| {
-            Ok(db.query_coordination_events(&channel.to_lowercase(), event_type, ref_id, actor, since, limit)
+            Ok(db.query_coordination_events(&crate::casemap::fold(&channel), event_type, ref_id, actor, since, limit)
                 .into_iter()
                 .map(|e| serde_json::json!({
                     "event_id": e.event_id,
@@ -675,7 +762,14 @@ async fn api_channel_audit(
 
     // 1. Coordination events
     if let Some(events) = state.with_db(|db| {
-        Ok(db.query_coordination_events(&channel.to_lowercase(), None, None, actor, since, limit))
+        Ok(db.query_coordination_events(
+            &crate::casemap::fold(&channel),
+            None,
+            None,
+            actor,
+            since,
+            limit,
+        ))
     }) {
         for e in events {
             timeline.push(serde_json::json!({
@@ -792,7 +886,7 @@ async fn api_channel_budget(
 ) -> Json<serde_json::Value> {
     let channel = format!("#{name}");
     let budget_json = state
-        .with_db(|db| Ok(db.get_budget(&channel.to_lowercase(), None)))
+        .with_db(|db| Ok(db.get_budget(&crate::casemap::fold(&channel), None)))
         .flatten();
     match budget_json {
         Some(bj) => {
@@ -800,13 +894,22 @@ async fn api_channel_budget(
                 let period_start = crate::connection::budget_period_start(&budget.period);
                 let total_spent = state
                     .with_db(|db| {
-                        Ok(db.sum_spend(&channel.to_lowercase(), None, &budget.unit, period_start))
+                        Ok(db.sum_spend(
+                            &crate::casemap::fold(&channel),
+                            None,
+                            &budget.unit,
+                            period_start,
+                        ))
                     })
                     .unwrap_or(0.0);
                 let by_agent: Vec<serde_json::Value> = state
                     .with_db(|db| {
                         Ok(db
-                            .spend_by_agent(&channel.to_lowercase(), &budget.unit, period_start)
+                            .spend_by_agent(
+                                &crate::casemap::fold(&channel),
+                                &budget.unit,
+                                period_start,
+                            )
                             .into_iter()
                             .map(|(did, spent, count)| {
                                 serde_json::json!({
@@ -864,7 +967,7 @@ async fn api_channel_spend(
     let records: Vec<serde_json::Value> = state
         .with_db(|db| {
             Ok(db
-                .query_spend(&channel.to_lowercase(), agent, since, limit)
+                .query_spend(&crate::casemap::fold(&channel), agent, since, limit)
                 .into_iter()
                 .map(|r| {
                     serde_json::json!({
@@ -883,6 +986,59 @@ async fn api_channel_spend(
     Json(serde_json::json!({ "channel": channel, "spend": records }))
 }
 
+/// GET /api/v1/channels/{name}/stats?days=30 — daily activity rollups
+/// for charts (see `stats`). Bearer session of the channel founder or a
+/// DID-op. Channels set `+S` return 404.
+async fn api_channel_stats(
+    State(state): State<Arc<SharedState>>,
+    axum::extract::Path(name): axum::extract::Path<String>,
+    axum::extract::Query(params): axum::extract::Query<std::collections::HashMap<String, String>>,
+    headers: axum::http::HeaderMap,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let channel = crate::casemap::fold(&format!("#{}", name.trim_start_matches('#')));
+    let Some(caller) = caller_did_from_bearer(&state, &headers) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(serde_json::json!({ "error": "Bearer session required" })),
+        );
+    };
+    {
+        let Some(ch) = state.channels.get(&channel) else {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(serde_json::json!({ "error": "Unknown channel" })),
+            );
+        };
+        if ch.no_stats {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(serde_json::json!({ "error": "Statistics are disabled for this channel" })),
+            );
+        }
+        let is_authority =
+            ch.founder_did.as_deref() == Some(caller.as_str()) || ch.did_ops.contains(&caller);
+        if !is_authority {
+            return (
+                StatusCode::FORBIDDEN,
+                Json(serde_json::json!({
+                    "error": "Only the channel founder or a DID-op may read its statistics"
+                })),
+            );
+        }
+    }
+    let days = params
+        .get("days")
+        .and_then(|d| d.parse().ok())
+        .unwrap_or(30u64);
+    match crate::stats::report(&state, &channel, days) {
+        Some(report) => (StatusCode::OK, Json(serde_json::json!(report))),
+        None => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({ "error": "Channel statistics need a database" })),
+        ),
+    }
+}
+
 /// GET /api/v1/actors/{did} — identity card for any actor (human or agent).
 async fn api_actor_identity(
     State(state): State<Arc<SharedState>>,
@@ -937,13 +1093,12 @@ async fn api_actor_identity(
         .find_map(|sid| state.session_handles.lock().get(sid).cloned());
 
     // Channels
-    let channels: Vec<String> = {
-        let chs = state.channels.lock();
-        chs.iter()
-            .filter(|(_, ch)| sessions.iter().any(|sid| ch.members.contains(sid)))
-            .map(|(name, _)| name.clone())
-            .collect()
-    };
+    let channels: Vec<String> = state.channels.filter_map(|name, ch| {
+        sessions
+            .iter()
+            .any(|sid| ch.members.contains(sid))
+            .then(|| name.to_string())
+    });
 
     // Provenance
     let provenance = state.provenance_declarations.lock().get(&did).cloned();
@@ -1041,22 +1196,15 @@ async fn api_verify_message(
     axum::extract::Path(msgid): axum::extract::Path<String>,
 ) -> Result<Json<serde_json::Value>, (axum::http::StatusCode, String)> {
     // Search all channel histories for this msgid
-    let channels = state.channels.lock();
-    let mut found = None;
-    let mut found_channel = String::new();
-    for (ch_name, ch) in channels.iter() {
-        for msg in ch.history.iter() {
-            if msg.msgid.as_deref() == Some(&msgid) {
-                found = Some(msg.clone());
-                found_channel = ch_name.clone();
-                break;
-            }
-        }
-        if found.is_some() {
-            break;
-        }
-    }
-    drop(channels);
+    let (mut found, mut found_channel) = state
+        .channels
+        .find_map(|ch_name, ch| {
+            ch.history
+                .iter()
+                .find(|msg| msg.msgid.as_deref() == Some(&msgid))
+                .map(|msg| (Some(msg.clone()), ch_name.to_string()))
+        })
+        .unwrap_or_default();
 
     // Fall back to database if not in memory
     if found.is_none()
@@ -1184,14 +1332,11 @@ async fn api_verify_message(
 async fn api_health(State(state): State<Arc<SharedState>>) -> Json<HealthResponse> {
     let start = START_TIME.get_or_init(SystemTime::now);
     let uptime = start.elapsed().unwrap_or_default().as_secs();
-    let connections = state.connections.lock().len();
+    let connections = state.connections.len();
     // Count only channels with members (not empty shells)
     let channels = state
         .channels
-        .lock()
-        .values()
-        .filter(|ch| !ch.members.is_empty() || !ch.remote_members.is_empty())
-        .count();
+        .count(|_, ch| !ch.members.is_empty() || !ch.remote_members.is_empty());
     Json(HealthResponse {
         server_name: state.server_name.clone(),
         version: env!("CARGO_PKG_VERSION"),
@@ -1202,29 +1347,118 @@ async fn api_health(State(state): State<Arc<SharedState>>) -> Json<HealthRespons
     })
 }
 
+/// GET /healthz — liveness: the process is up and serving HTTP.
+async fn healthz() -> Json<serde_json::Value> {
+    let start = START_TIME.get_or_init(SystemTime::now);
+    Json(serde_json::json!({
+        "status": "ok",
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_commit": env!("GIT_HASH"),
+        "uptime_secs": start.elapsed().unwrap_or_default().as_secs(),
+    }))
+}
+
+/// Whether `dir` takes a write: create and remove a probe file.
+async fn data_dir_writable(dir: &std::path::Path) -> std::io::Result<()> {
+    let probe = dir.join(format!(".readyz-{}", std::process::id()));
+    tokio::fs::write(&probe, b"ok").await?;
+    tokio::fs::remove_file(&probe).await
+}
+
+/// GET /readyz — readiness: 200 when this server should get traffic, 503
+/// (with the same detail) when it shouldn't. Fails while the client
+/// listeners aren't bound or after a handover, when the data directory
+/// can't be written, or when the history database can't be read. S2S
+/// peers are reported but never fail the probe: a server with no peers
+/// still serves its own clients.
+async fn readyz(State(state): State<Arc<SharedState>>) -> impl IntoResponse {
+    use std::sync::atomic::Ordering::Relaxed;
+
+    let listening = state.listening.load(Relaxed);
+
+    let dir = std::path::Path::new(state.config.data_dir.as_deref().unwrap_or("."));
+    let data_dir = match data_dir_writable(dir).await {
+        Ok(()) => serde_json::json!({ "ok": true, "path": dir }),
+        Err(e) => serde_json::json!({ "ok": false, "path": dir, "error": e.to_string() }),
+    };
+
+    let history_db = match &state.db {
+        None => serde_json::json!({ "ok": true, "enabled": false }),
+        Some(_) => {
+            let ok = state.with_db(|db| db.ping()).is_some();
+            serde_json::json!({ "ok": ok, "enabled": true })
+        }
+    };
+
+    let s2s = state.s2s_manager.lock().clone();
+    let s2s = match s2s {
+        None => serde_json::json!({ "ok": true, "enabled": false }),
+        Some(mgr) => {
+            // Live links that finished the Hello handshake.
+            let linked: Vec<String> = mgr.peers.lock().await.keys().cloned().collect();
+            let authenticated = mgr.authenticated_peers.lock().await;
+            let connected = linked.iter().filter(|p| authenticated.contains(*p)).count();
+            serde_json::json!({
+                "ok": true,
+                "enabled": true,
+                "configured": state.config.s2s_peers.len(),
+                "connected": connected,
+            })
+        }
+    };
+
+    let ready = listening && data_dir["ok"] == true && history_db["ok"] == true;
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = serde_json::json!({
+        "status": if ready { "ready" } else { "not_ready" },
+        "checks": {
+            "listeners": { "ok": listening },
+            "data_dir": data_dir,
+            "history_db": history_db,
+            "s2s": s2s,
+        },
+    });
+    (status, Json(body))
+}
+
 async fn api_channels(State(state): State<Arc<SharedState>>) -> Json<Vec<ChannelInfo>> {
-    let channels = state.channels.lock();
-    let mut list: Vec<ChannelInfo> = channels
-        .iter()
-        .filter(|(_name, ch)| {
-            // Show channels with members, or with a topic set
-            let has_members = !ch.members.is_empty() || !ch.remote_members.is_empty();
-            let has_topic = ch.topic.is_some();
-            has_members || has_topic
-        })
-        .map(|(name, ch)| ChannelInfo {
-            name: name.clone(),
+    let mut list: Vec<ChannelInfo> = state.channels.filter_map(|name, ch| {
+        // Show channels with members, or with a topic set
+        let has_members = !ch.members.is_empty() || !ch.remote_members.is_empty();
+        let has_topic = ch.topic.is_some();
+        (!ch.secret && (has_members || has_topic)).then(|| ChannelInfo {
+            name: name.to_string(),
             members: ch.members.len() + ch.remote_members.len(),
             topic: ch.topic.as_ref().map(|t| t.text.clone()),
         })
-        .collect();
+    });
     // Sort: most members first, then alphabetically
     list.sort_by(|a, b| b.members.cmp(&a.members).then(a.name.cmp(&b.name)));
     Json(list)
 }
 
+/// The oper session behind an `Authorization: Bearer <OPER-TOKEN>`
+/// header: 401 without one, 403 if it isn't a live oper's token.
+fn oper_from_bearer(
+    state: &SharedState,
+    headers: &axum::http::HeaderMap,
+) -> Result<String, StatusCode> {
+    let token = headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    state.oper_session(token).ok_or(StatusCode::FORBIDDEN)
+}
+
 /// Resolve the authenticated caller DID from a `Bearer <session-id>` header.
-fn caller_did_from_bearer(
+pub(crate) fn caller_did_from_bearer(
     state: &crate::server::SharedState,
     headers: &axum::http::HeaderMap,
 ) -> Option<String> {
@@ -1237,6 +1471,202 @@ fn caller_did_from_bearer(
     state.session_dids.lock().get(sid).cloned()
 }
 
+/// GET /api/v1/me/export — everything the server holds for the caller's
+/// DID: profile, channels, credentials, sessions and authored messages.
+async fn api_me_export(
+    State(state): State<Arc<SharedState>>,
+    headers: axum::http::HeaderMap,
+) -> axum::response::Response {
+    let Some(did) = caller_did_from_bearer(&state, &headers) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(serde_json::json!({ "error": "Bearer session required" })),
+        )
+            .into_response();
+    };
+    let export = crate::connection::mydata::build_export(&state, &did);
+    (
+        [(
+            axum::http::header::CONTENT_DISPOSITION,
+            "attachment; filename=\"freeq-export.json\"",
+        )],
+        Json(export),
+    )
+        .into_response()
+}
+
+#[derive(Deserialize)]
+struct EraseRequest {
+    #[serde(default)]
+    confirm: bool,
+}
+
+/// POST /api/v1/me/erase — erase every message the caller's DID authored,
+/// here and on S2S peers. Body: `{ "confirm": true }`.
+async fn api_me_erase(
+    State(state): State<Arc<SharedState>>,
+    headers: axum::http::HeaderMap,
+    Json(body): Json<EraseRequest>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let Some(did) = caller_did_from_bearer(&state, &headers) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(serde_json::json!({ "error": "Bearer session required" })),
+        );
+    };
+    if !body.confirm {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(
+                serde_json::json!({ "error": "Erasure cannot be undone; send {\"confirm\": true}" }),
+            ),
+        );
+    }
+    let erased = crate::connection::mydata::erase(&state, &did);
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({ "erased": erased })),
+    )
+}
+
+/// GET /api/v1/me/prefs — the caller's preferences (see [`crate::prefs`]),
+/// defaults included, with each key's type and the storage quota.
+async fn api_me_prefs(
+    State(state): State<Arc<SharedState>>,
+    headers: axum::http::HeaderMap,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let Some(did) = caller_did_from_bearer(&state, &headers) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(serde_json::json!({ "error": "Bearer session required" })),
+        );
+    };
+    let prefs = state.prefs.lock();
+    let values: serde_json::Map<String, serde_json::Value> = prefs
+        .all(&did)
+        .into_iter()
+        .map(|(key, value)| (key.name.to_string(), value.into()))
+        .collect();
+    let keys: Vec<_> = crate::prefs::KEYS
+        .iter()
+        .map(|k| {
+            serde_json::json!({
+                "name": k.name,
+                "type": k.kind.as_str(),
+                "default": k.default,
+                "description": k.description,
+            })
+        })
+        .collect();
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "prefs": values,
+            "keys": keys,
+            "used_bytes": prefs.used_bytes(&did),
+            "max_bytes": crate::prefs::MAX_TOTAL_BYTES,
+        })),
+    )
+}
+
+#[derive(Deserialize)]
+struct PrefRequest {
+    value: String,
+}
+
+/// PUT /api/v1/me/prefs/{key} — set one preference. Body: `{ "value": "..." }`.
+/// The caller's connected IRC sessions are told with `PREF <key> :<value>`.
+async fn api_me_pref_set(
+    State(state): State<Arc<SharedState>>,
+    Path(key): Path<String>,
+    headers: axum::http::HeaderMap,
+    Json(body): Json<PrefRequest>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    pref_update(&state, &headers, &key, Some(&body.value))
+}
+
+/// DELETE /api/v1/me/prefs/{key} — reset one preference to its default.
+async fn api_me_pref_reset(
+    State(state): State<Arc<SharedState>>,
+    Path(key): Path<String>,
+    headers: axum::http::HeaderMap,
+) -> (StatusCode, Json<serde_json::Value>) {
+    pref_update(&state, &headers, &key, None)
+}
+
+fn pref_update(
+    state: &SharedState,
+    headers: &axum::http::HeaderMap,
+    key: &str,
+    value: Option<&str>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let Some(did) = caller_did_from_bearer(state, headers) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(serde_json::json!({ "error": "Bearer session required" })),
+        );
+    };
+    match crate::prefs::update(state, &did, key, value, None) {
+        Ok((key, value)) => (
+            StatusCode::OK,
+            Json(serde_json::json!({ "key": key.name, "value": value })),
+        ),
+        Err(e) => {
+            let status = match &e {
+                crate::prefs::PrefError::UnknownKey(_) => StatusCode::NOT_FOUND,
+                crate::prefs::PrefError::Invalid(_) => StatusCode::BAD_REQUEST,
+                crate::prefs::PrefError::Quota => StatusCode::PAYLOAD_TOO_LARGE,
+            };
+            (
+                status,
+                Json(serde_json::json!({ "error": e.to_string(), "code": e.code() })),
+            )
+        }
+    }
+}
+
+/// POST /api/v1/admin/backup — oper-only; takes a backup (see
+/// [`crate::backup`]) and returns what it did. Auth is the OPER-TOKEN
+/// bearer, as for `/firehose`.
+async fn api_admin_backup(
+    State(state): State<Arc<SharedState>>,
+    headers: axum::http::HeaderMap,
+) -> (StatusCode, Json<serde_json::Value>) {
+    match oper_from_bearer(&state, &headers) {
+        Ok(_) => {}
+        Err(StatusCode::UNAUTHORIZED) => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({ "error": "Bearer token required" })),
+            );
+        }
+        Err(status) => {
+            return (
+                status,
+                Json(serde_json::json!({ "error": "Operator token required" })),
+            );
+        }
+    }
+    match crate::backup::run(&state).await {
+        Ok(done) => (
+            StatusCode::OK,
+            Json(serde_json::to_value(&done).unwrap_or_default()),
+        ),
+        Err(e) => {
+            let status = match &e {
+                crate::backup::BackupError::NoDatabase => StatusCode::SERVICE_UNAVAILABLE,
+                crate::backup::BackupError::Busy => StatusCode::CONFLICT,
+                crate::backup::BackupError::Failed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+                crate::backup::BackupError::UploadFailed { .. } => StatusCode::BAD_GATEWAY,
+            };
+            (
+                status,
+                Json(serde_json::json!({ "error": e.to_string(), "code": e.code() })),
+            )
+        }
+    }
+}
+
 /// POST /api/v1/channels/{name}/groupkeys — a channel steward (founder or
 /// DID-op) uploads group secrets sealed to each member's X25519 key. The server
 /// stores opaque `EGK1:` blobs; it can never open them (server-blind key
@@ -1264,8 +1694,7 @@ async fn api_put_group_keys(
     // Steward authorization: only the channel founder or a DID-op may distribute
     // group keys — the same DID authorities the policy layer already trusts.
     {
-        let channels = state.channels.lock();
-        let Some(ch) = channels.get(&channel.to_lowercase()) else {
+        let Some(ch) = state.channels.get(&crate::casemap::fold(&channel)) else {
             return (
                 axum::http::StatusCode::NOT_FOUND,
                 axum::Json(serde_json::json!({ "error": "Unknown channel" })),
@@ -1358,12 +1787,12 @@ async fn api_channel_history(
         format!("#{name}")
     };
 
-    // Restrict history for channels with access controls (+i, +k).
-    // These channels require membership to read history — use IRC CHATHISTORY instead.
+    // Restrict history for channels with access controls (+i, +k) or a
+    // members-only +H. These require membership to read history — use
+    // IRC CHATHISTORY instead.
     {
-        let channels = state.channels.lock();
-        if let Some(ch) = channels.get(&channel.to_lowercase())
-            && (ch.invite_only || ch.key.is_some())
+        if let Some(ch) = state.channels.get(&crate::casemap::fold(&channel))
+            && !ch.history_readable_anonymously()
         {
             return Err(StatusCode::FORBIDDEN);
         }
@@ -1391,8 +1820,7 @@ async fn api_channel_history(
         }
         None => {
             // No database — fall back to in-memory history
-            let channels = state.channels.lock();
-            match channels.get(&channel) {
+            match state.channels.get(&channel) {
                 Some(ch) => {
                     let resp: Vec<MessageResponse> = ch
                         .history
@@ -1422,19 +1850,18 @@ async fn api_channel_history(
 }
 
 /// True when REST may serve this channel's history: real channel (not a DM
-/// key) with no +i/+k access controls. Restricted content goes through the
-/// membership-checked IRC commands instead.
+/// key) whose +i/+k/+H settings allow anonymous readers. Restricted content
+/// goes through the membership-checked IRC commands instead.
 fn rest_readable_channel(state: &SharedState, channel: &str) -> Result<(), StatusCode> {
     if channel.to_lowercase().starts_with("dm:") || channel.contains("dm:") {
         return Err(StatusCode::FORBIDDEN);
     }
-    let channels = state.channels.lock();
-    match channels.get(&channel.to_lowercase()) {
+    match state.channels.get(&crate::casemap::fold(channel)) {
         Some(ch) => {
-            if ch.invite_only || ch.key.is_some() {
-                Err(StatusCode::FORBIDDEN)
-            } else {
+            if ch.history_readable_anonymously() {
                 Ok(())
+            } else {
+                Err(StatusCode::FORBIDDEN)
             }
         }
         None => Err(StatusCode::NOT_FOUND),
@@ -1462,6 +1889,7 @@ async fn api_message_by_id(
         "timestamp": row.timestamp,
         "tags": row.tags,
         "replaces_msgid": row.replaces_msgid,
+        "thread_root": row.thread_root,
     })))
 }
 
@@ -1535,6 +1963,181 @@ async fn api_channel_export(
     }
 }
 
+#[derive(Deserialize)]
+struct ArchiveQuery {
+    limit: Option<usize>,
+    /// Row id cursor: the page of messages before this one.
+    before: Option<i64>,
+    /// Row id cursor: the page of messages after this one.
+    after: Option<i64>,
+}
+
+/// One page of a public (+A) channel archive.
+struct ArchivePage {
+    channel: String,
+    topic: Option<String>,
+    rows: Vec<crate::db::MessageRow>,
+    /// Cursor for the previous page, if there may be one.
+    older: Option<i64>,
+    /// Cursor for the next page, if this isn't the latest one.
+    newer: Option<i64>,
+}
+
+/// Load an archive page. Only channels flagged +A are served, never +E
+/// or `+H members-since-join` ones, and only what the database still
+/// holds — pruned and deleted messages are gone from the archive too.
+fn archive_page(
+    state: &SharedState,
+    name: &str,
+    params: &ArchiveQuery,
+) -> Result<ArchivePage, StatusCode> {
+    let channel = if name.starts_with('#') {
+        name.to_string()
+    } else {
+        format!("#{name}")
+    };
+    let topic = {
+        match state.channels.get(&crate::casemap::fold(&channel)) {
+            // 404 rather than 403: don't advertise which channels exist.
+            Some(ch)
+                if ch.archived
+                    && !ch.encrypted_only
+                    && ch.history_visibility != HistoryVisibility::MembersSinceJoin =>
+            {
+                ch.topic.as_ref().map(|t| t.text.clone())
+            }
+            _ => return Err(StatusCode::NOT_FOUND),
+        }
+    };
+
+    let limit = params.limit.unwrap_or(100).clamp(1, 500);
+    let rows = state
+        .with_db(|db| db.get_archive_page(&channel, params.before, params.after, limit))
+        .ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    let full = rows.len() == limit;
+    let (first, last) = (rows.first().map(|r| r.id), rows.last().map(|r| r.id));
+    let (older, newer) = match (params.before, params.after) {
+        (_, Some(after)) => (first.or(Some(after + 1)), if full { last } else { None }),
+        (Some(before), None) => (if full { first } else { None }, last.or(Some(before - 1))),
+        (None, None) => (if full { first } else { None }, None),
+    };
+    Ok(ArchivePage {
+        channel,
+        topic,
+        rows,
+        older,
+        newer,
+    })
+}
+
+/// GET /api/v1/archive/{name}?before=|after=&limit= — JSON view of a
+/// public archive channel, oldest-first, paginated by message `id`.
+async fn api_channel_archive(
+    Path(name): Path<String>,
+    Query(params): Query<ArchiveQuery>,
+    State(state): State<Arc<SharedState>>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let page = archive_page(&state, &name, &params)?;
+    let messages: Vec<MessageResponse> = page
+        .rows
+        .into_iter()
+        .map(|r| MessageResponse {
+            id: r.id,
+            sender: r.sender,
+            text: r.text,
+            timestamp: r.timestamp,
+            msgid: r.msgid,
+            tags: r.tags,
+        })
+        .collect();
+    Ok(Json(serde_json::json!({
+        "channel": page.channel,
+        "topic": page.topic,
+        "messages": messages,
+        "older": page.older,
+        "newer": page.newer,
+    })))
+}
+
+/// GET /archive/{name} — read-only HTML view of a public archive channel.
+/// Every message is anchored by its msgid (`/archive/rust#01H…`).
+async fn channel_archive_page(
+    Path(name): Path<String>,
+    Query(params): Query<ArchiveQuery>,
+    State(state): State<Arc<SharedState>>,
+) -> Result<Html<String>, StatusCode> {
+    let page = archive_page(&state, &name, &params)?;
+    let channel_escaped = html_escape(&page.channel);
+    let path = format!(
+        "/archive/{}",
+        urlencoding::encode(page.channel.trim_start_matches('#'))
+    );
+    let topic_html = page
+        .topic
+        .as_deref()
+        .map(|t| format!(r#"<p class="topic">{}</p>"#, html_escape(t)))
+        .unwrap_or_default();
+
+    let mut messages = String::new();
+    for r in &page.rows {
+        let ts = chrono::DateTime::from_timestamp(r.timestamp as i64, 0)
+            .unwrap_or_default()
+            .format("%Y-%m-%d %H:%M");
+        let sender = html_escape(r.sender.split('!').next().unwrap_or(&r.sender));
+        let text = html_escape(&r.text);
+        match r.msgid.as_deref().map(html_escape) {
+            Some(id) => messages.push_str(&format!(
+                r#"<div class="msg" id="{id}"><a class="ts" href="#{id}">{ts}</a> <b>{sender}</b> <span>{text}</span></div>"#
+            )),
+            None => messages.push_str(&format!(
+                r#"<div class="msg"><span class="ts">{ts}</span> <b>{sender}</b> <span>{text}</span></div>"#
+            )),
+        }
+        messages.push('\n');
+    }
+    if page.rows.is_empty() {
+        messages.push_str(r#"<p class="empty">No messages.</p>"#);
+    }
+
+    let mut nav = Vec::new();
+    if let Some(id) = page.older {
+        nav.push(format!(r#"<a href="{path}?before={id}">&larr; Older</a>"#));
+    }
+    if let Some(id) = page.newer {
+        nav.push(format!(r#"<a href="{path}?after={id}">Newer &rarr;</a>"#));
+        nav.push(format!(r#"<a href="{path}">Latest</a>"#));
+    }
+    let nav = nav.join(" ");
+
+    Ok(Html(format!(
+        r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{channel_escaped} archive — freeq</title>
+<style>
+body{{font-family:-apple-system,BlinkMacSystemFont,'Segoe UI',sans-serif;background:#0c0c0f;color:#e8e8ed;max-width:900px;margin:0 auto;padding:24px}}
+h1{{color:#00d4aa}}
+.topic{{color:#9898b0}}
+.msg{{padding:4px 0;line-height:1.5;white-space:pre-wrap;word-wrap:break-word}}
+.msg:target{{background:#00d4aa22}}
+.ts{{color:#555570;font-size:12px;text-decoration:none}}
+nav{{margin:16px 0}}
+nav a{{color:#00d4aa;margin-right:16px}}
+.empty{{color:#555570}}
+</style>
+</head>
+<body>
+<h1>{channel_escaped}</h1>
+{topic_html}
+<nav>{nav}</nav>
+{messages}<nav>{nav}</nav>
+</body>
+</html>"##
+    )))
+}
+
 /// Render Prometheus text exposition format (version 0.0.4).
 fn format_metrics(
     connections: usize,
@@ -1543,6 +2146,11 @@ fn format_metrics(
     messages_total: u64,
     sasl_success_total: u64,
     sasl_failure_total: u64,
+    malformed_lines_total: u64,
+    send_queue_dropped_low_total: u64,
+    send_queue_dropped_total: u64,
+    send_queue_coalesced_total: u64,
+    send_queue_congestion_quits_total: u64,
     uptime_seconds: u64,
 ) -> String {
     format!(
@@ -1564,6 +2172,21 @@ fn format_metrics(
          # HELP freeq_sasl_failure_total Failed SASL authentications since start\n\
          # TYPE freeq_sasl_failure_total counter\n\
          freeq_sasl_failure_total {sasl_failure_total}\n\
+         # HELP freeq_malformed_lines_total Client lines quarantined as malformed since start\n\
+         # TYPE freeq_malformed_lines_total counter\n\
+         freeq_malformed_lines_total {malformed_lines_total}\n\
+         # HELP freeq_send_queue_dropped_low_total Low-priority lines dropped for backed-up clients\n\
+         # TYPE freeq_send_queue_dropped_low_total counter\n\
+         freeq_send_queue_dropped_low_total {send_queue_dropped_low_total}\n\
+         # HELP freeq_send_queue_dropped_total Lines dropped for clients over their byte budget\n\
+         # TYPE freeq_send_queue_dropped_total counter\n\
+         freeq_send_queue_dropped_total {send_queue_dropped_total}\n\
+         # HELP freeq_send_queue_coalesced_total Presence updates replaced by a newer one before delivery\n\
+         # TYPE freeq_send_queue_coalesced_total counter\n\
+         freeq_send_queue_coalesced_total {send_queue_coalesced_total}\n\
+         # HELP freeq_send_queue_congestion_quits_total Clients disconnected for staying over their byte budget\n\
+         # TYPE freeq_send_queue_congestion_quits_total counter\n\
+         freeq_send_queue_congestion_quits_total {send_queue_congestion_quits_total}\n\
          # HELP freeq_uptime_seconds Seconds since process start\n\
          # TYPE freeq_uptime_seconds gauge\n\
          freeq_uptime_seconds {uptime_seconds}\n"
@@ -1573,13 +2196,14 @@ fn format_metrics(
 /// GET /metrics — Prometheus scrape endpoint.
 async fn api_metrics(State(state): State<Arc<SharedState>>) -> impl axum::response::IntoResponse {
     use std::sync::atomic::Ordering::Relaxed;
-    let connections = state.connections.lock().len();
-    let channels = state.channels.lock().len();
+    let connections = state.connections.len();
+    let channels = state.channels.len();
     let s2s = state.s2s_manager.lock().clone();
     let s2s_peers = match s2s {
         Some(mgr) => mgr.authenticated_peers.lock().await.len(),
         None => 0,
     };
+    let send_queue = &state.metrics.send_queue;
     let body = format_metrics(
         connections,
         channels,
@@ -1587,6 +2211,11 @@ async fn api_metrics(State(state): State<Arc<SharedState>>) -> impl axum::respon
         state.metrics.messages_total.load(Relaxed),
         state.metrics.sasl_success_total.load(Relaxed),
         state.metrics.sasl_failure_total.load(Relaxed),
+        state.metrics.malformed_lines_total.load(Relaxed),
+        send_queue.dropped_low_total.load(Relaxed),
+        send_queue.dropped_total.load(Relaxed),
+        send_queue.coalesced_total.load(Relaxed),
+        send_queue.congestion_quits_total.load(Relaxed),
         state.metrics.started_at.elapsed().as_secs(),
     );
     (
@@ -1601,7 +2230,7 @@ async fn api_metrics(State(state): State<Arc<SharedState>>) -> impl axum::respon
 /// GET /api/v1/search?channel=#name&q=terms — full-text history search.
 /// Channels only: DM search requires DID auth and goes through the IRC
 /// SEARCH command. Access rules mirror /channels/{name}/history: channels
-/// with +i or +k return 403.
+/// closed to anonymous readers (+i, +k, members-only +H) return 403.
 async fn api_search(
     Query(params): Query<SearchQuery>,
     State(state): State<Arc<SharedState>>,
@@ -1617,10 +2246,9 @@ async fn api_search(
     }
 
     {
-        let channels = state.channels.lock();
-        match channels.get(&channel.to_lowercase()) {
+        match state.channels.get(&crate::casemap::fold(&channel)) {
             Some(ch) => {
-                if ch.invite_only || ch.key.is_some() {
+                if !ch.history_readable_anonymously() {
                     return Err(StatusCode::FORBIDDEN);
                 }
             }
@@ -1657,8 +2285,7 @@ async fn api_channel_topic(
         format!("#{name}")
     };
 
-    let channels = state.channels.lock();
-    match channels.get(&channel) {
+    match state.channels.get(&channel) {
         Some(ch) => Ok(Json(ChannelTopicResponse {
             channel,
             topic: ch.topic.as_ref().map(|t| t.text.clone()),
@@ -1679,8 +2306,7 @@ async fn api_channel_pins(
         format!("#{name}")
     };
     let pin_list = {
-        let channels = state.channels.lock();
-        match channels.get(&channel) {
+        match state.channels.get(&channel) {
             Some(ch) => ch.pins.clone(),
             None => return Err(StatusCode::NOT_FOUND),
         }
@@ -1690,9 +2316,7 @@ async fn api_channel_pins(
     for p in &pin_list {
         // Try in-memory history first, fall back to DB
         let (from, text, timestamp) = {
-            let channels = state.channels.lock();
-            let ch = channels.get(&channel);
-            ch.and_then(|c| {
+            state.channels.get(&channel).and_then(|c| {
                 c.history
                     .iter()
                     .find(|m| m.msgid.as_deref() == Some(&p.msgid))
@@ -1746,7 +2370,11 @@ async fn api_user(
         let handle = state.session_handles.lock().get(session_id).cloned();
         (did, handle)
     } else {
-        let did = state.nick_owners.lock().get(&nick.to_lowercase()).cloned();
+        let did = state
+            .nick_owners
+            .lock()
+            .get(&crate::casemap::fold(&nick))
+            .cloned();
         (did, None)
     };
 
@@ -1778,7 +2406,11 @@ async fn api_user_whois(
         let handle = state.session_handles.lock().get(session_id).cloned();
         (did, handle)
     } else {
-        let did = state.nick_owners.lock().get(&nick.to_lowercase()).cloned();
+        let did = state
+            .nick_owners
+            .lock()
+            .get(&crate::casemap::fold(&nick))
+            .cloned();
         (did, None)
     };
 
@@ -1787,12 +2419,9 @@ async fn api_user_whois(
     }
 
     let channels = if let Some(ref session_id) = session {
-        let chans = state.channels.lock();
-        chans
-            .iter()
-            .filter(|(_, ch)| ch.members.contains(session_id))
-            .map(|(name, _)| name.clone())
-            .collect()
+        state
+            .channels
+            .filter_map(|name, ch| ch.members.contains(session_id).then(|| name.to_string()))
     } else {
         vec![]
     };
@@ -1812,6 +2441,26 @@ async fn api_user_whois(
 struct BrokerTokenRequest {
     did: String,
     handle: String,
+    /// Bind the minted token to a client-chosen device fingerprint; the
+    /// client must echo it in its SASL response.
+    #[serde(default)]
+    client_fingerprint: Option<String>,
+    /// Bind the minted token to an iroh endpoint ID; only a connection
+    /// from that endpoint can redeem it.
+    #[serde(default)]
+    iroh_endpoint_id: Option<String>,
+}
+
+impl BrokerTokenRequest {
+    fn binding(&self) -> Option<crate::server::TokenBinding> {
+        if let Some(id) = self.iroh_endpoint_id.as_ref().filter(|s| !s.is_empty()) {
+            return Some(crate::server::TokenBinding::IrohEndpoint(id.clone()));
+        }
+        self.client_fingerprint
+            .as_ref()
+            .filter(|s| !s.is_empty())
+            .map(|fp| crate::server::TokenBinding::Fingerprint(fp.clone()))
+    }
 }
 
 #[derive(Deserialize, Serialize)]
@@ -1828,6 +2477,31 @@ struct BrokerSessionRequest {
     /// always asked for the legacy broad scope so this is conservative.
     #[serde(default = "default_legacy_scope")]
     granted_scope: String,
+    /// The broker session this was pushed for, so revoking it later drops
+    /// this web session. Absent from older broker builds.
+    #[serde(default)]
+    broker_session: Option<String>,
+}
+
+/// A broker session lifecycle webhook.
+#[derive(Deserialize)]
+struct BrokerEventRequest {
+    event: BrokerEvent,
+    did: String,
+    /// The broker's public ID for the session (a hash of its token).
+    session: String,
+    #[serde(default)]
+    device: Option<String>,
+    /// Unix seconds.
+    at: i64,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum BrokerEvent {
+    Created,
+    Refreshed,
+    Revoked,
 }
 
 fn default_legacy_scope() -> String {
@@ -1855,15 +2529,7 @@ async fn auth_broker_web_token(
     let req: BrokerTokenRequest = serde_json::from_slice(&body)
         .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid JSON: {e}")))?;
 
-    let token = generate_random_string(32);
-    state.web_auth_tokens.lock().insert(
-        token.clone(),
-        (
-            req.did.clone(),
-            req.handle.clone(),
-            std::time::Instant::now(),
-        ),
-    );
+    let token = state.mint_web_auth_token(&req.did, &req.handle, req.binding());
     let nick = mobile_nick_from_handle(&req.handle);
     Ok(Json(BrokerTokenResponse {
         token,
@@ -1887,16 +2553,23 @@ async fn auth_broker_session(
         .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid JSON: {e}")))?;
 
     tracing::info!(did = %req.did, scope = %req.granted_scope, "Broker pushed web session");
-    state.web_sessions.lock().insert(
-        (req.did.clone(), crate::server::OauthPurpose::Login),
+    let now = std::time::Instant::now();
+    let web_session_id = generate_random_string(6);
+    if let Some(broker_session) = req.broker_session.as_deref() {
+        state.link_broker_web_session(broker_session, &req.did, &web_session_id);
+    }
+    state.insert_web_session(
+        crate::server::OauthPurpose::Login,
         crate::server::WebSession {
+            id: web_session_id,
             did: req.did.clone(),
             handle: req.handle.clone(),
             pds_url: req.pds_url.clone(),
             access_token: req.access_token.clone(),
             dpop_key_b64: req.dpop_key_b64.clone(),
             dpop_nonce: req.dpop_nonce.clone(),
-            created_at: std::time::Instant::now(),
+            created_at: now,
+            last_used: now,
             granted_scope: req.granted_scope.clone(),
         },
     );
@@ -1914,6 +2587,33 @@ async fn auth_broker_session(
     ))
 }
 
+/// Broker session lifecycle webhook: track the user's broker devices for
+/// `SESSIONS`, and drop a revoked device's web session immediately.
+async fn auth_broker_events(
+    State(state): State<Arc<SharedState>>,
+    headers: axum::http::HeaderMap,
+    body: axum::body::Bytes,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let secret = state.config.broker_shared_secret.clone().ok_or((
+        StatusCode::FORBIDDEN,
+        "Broker auth not configured".to_string(),
+    ))?;
+    verify_broker_signature_raw(&secret, &headers, &body)?;
+    let req: BrokerEventRequest = serde_json::from_slice(&body)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid JSON: {e}")))?;
+
+    tracing::info!(did = %req.did, session = %req.session, event = ?req.event, "Broker session event");
+    match req.event {
+        BrokerEvent::Created | BrokerEvent::Refreshed => {
+            state.record_broker_device(&req.session, &req.did, req.device, req.at);
+        }
+        BrokerEvent::Revoked => {
+            state.revoke_broker_device(&req.session, &req.did);
+        }
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
 /// Verify HMAC-SHA256 signature over raw request bytes with replay protection.
 /// The broker must include X-Broker-Timestamp (unix seconds). Requests older
 /// than 60 seconds are rejected.
@@ -2495,11 +3195,7 @@ async fn auth_step_up(
 
     // Require an existing Login session for this DID so step-up can't be
     // used as a primary login backdoor by an unauthenticated caller.
-    let login_session = state
-        .web_sessions
-        .lock()
-        .get(&(q.did.clone(), crate::server::OauthPurpose::Login))
-        .cloned();
+    let login_session = state.web_session(&q.did, crate::server::OauthPurpose::Login);
     let login_session = login_session.ok_or((
         StatusCode::UNAUTHORIZED,
         "Step-up requires an active login session for this DID.".to_string(),
@@ -2892,16 +3588,7 @@ async fn auth_callback(
     let web_token = if is_step_up {
         None
     } else {
-        let token = generate_random_string(32);
-        state.web_auth_tokens.lock().insert(
-            token.clone(),
-            (
-                pending.did.clone(),
-                pending.handle.clone(),
-                std::time::Instant::now(),
-            ),
-        );
-        Some(token)
+        Some(state.mint_web_auth_token(&pending.did, &pending.handle, None))
     };
 
     let result = crate::server::OAuthResult {
@@ -2919,16 +3606,19 @@ async fn auth_callback(
     // Store web session for server-proxied operations under the purpose
     // this OAuth flow was started for (Login, BlobUpload, etc.). A user
     // with both Login and BlobUpload sessions has two independent grants.
-    state.web_sessions.lock().insert(
-        (pending.did.clone(), pending.purpose),
+    let now = std::time::Instant::now();
+    state.insert_web_session(
+        pending.purpose,
         crate::server::WebSession {
+            id: generate_random_string(6),
             did: pending.did.clone(),
             handle: pending.handle.clone(),
             pds_url: pending.pds_url.clone(),
             access_token: access_token.to_string(),
             dpop_key_b64: pending.dpop_key_b64.clone(),
             dpop_nonce: dpop_nonce.clone(),
-            created_at: std::time::Instant::now(),
+            created_at: now,
+            last_used: now,
             granted_scope: granted_scope.clone(),
         },
     );
@@ -3364,26 +4054,17 @@ async fn api_upload(
         // Prefer the dedicated BlobUpload session (Phase 2 step-up); fall back
         // to the primary Login session only when its granted scope already
         // covers blob upload (legacy wide grant).
-        let session = {
-            let sessions = state.web_sessions.lock();
-            let purpose = crate::server::OauthPurpose::BlobUpload;
-            if let Some(s) = sessions.get(&(did.clone(), purpose)) {
-                Some(s.clone())
-            } else if let Some(s) = sessions.get(&(did.clone(), crate::server::OauthPurpose::Login))
-                && crate::server::scope_satisfies_purpose(&s.granted_scope, purpose)
-            {
-                Some(s.clone())
-            } else {
-                None
-            }
-        };
+        let purpose = crate::server::OauthPurpose::BlobUpload;
+        let login = state.web_session(&did, crate::server::OauthPurpose::Login);
+        let session = state.web_session(&did, purpose).or_else(|| {
+            login
+                .clone()
+                .filter(|s| crate::server::scope_satisfies_purpose(&s.granted_scope, purpose))
+        });
         match session {
             Some(s) => Some(s),
             None => {
-                let has_login = state
-                    .web_sessions
-                    .lock()
-                    .contains_key(&(did.clone(), crate::server::OauthPurpose::Login));
+                let has_login = login.is_some();
                 let body = if has_login {
                     serde_json::json!({
                         "error": "step_up_required",
@@ -3714,11 +4395,186 @@ async fn channel_invite_page(
         format!("#{channel}")
     };
 
+    let channel_escaped = html_escape(&channel);
+    let join_href = format!(
+        "https://{}/#auto-join={channel_escaped}",
+        state.config.server_name
+    );
+    let og_path = format!("join/{}", html_escape(channel.trim_start_matches('#')));
+    channel_card(&state, &channel, &og_path, "", &join_href).into_response()
+}
+
+/// GET /invite/{token} — landing page for an invite link (see
+/// [`crate::invites`]). Shows what the invite grants and hands the token
+/// to the web client, which signs the user in and joins with it.
+async fn invite_link_page(
+    Path(token): Path<String>,
+    State(state): State<Arc<SharedState>>,
+) -> impl IntoResponse {
+    let now = chrono::Utc::now().timestamp();
+    let invite = state
+        .invites
+        .lock()
+        .lookup(&state.invite_key(), &token, None, now)
+        .cloned();
+    let invite = match invite {
+        Ok(invite) => invite,
+        Err(e) => {
+            let status = match e {
+                crate::invites::InviteError::Invalid => StatusCode::NOT_FOUND,
+                _ => StatusCode::GONE,
+            };
+            let msg = html_escape(&e.to_string());
+            return (
+                status,
+                Html(format!(
+                    "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Invite — freeq</title></head><body style=\"font-family:sans-serif;background:#0c0c0f;color:#e8e8ed;text-align:center;padding-top:20vh\"><h1>{msg}</h1><p>Ask the channel's operators for a new one.</p></body></html>"
+                )),
+            )
+                .into_response();
+        }
+    };
+
+    let mut details = Vec::new();
+    if let Some(left) = invite.remaining() {
+        let word = if left == 1 { "use" } else { "uses" };
+        details.push(format!("{left} {word} left"));
+    }
+    if let Some(expires_at) = invite.expires_at {
+        let hours = (expires_at - now).max(0).unsigned_abs().div_ceil(3600);
+        details.push(if hours > 48 {
+            format!("expires in {} days", hours.div_ceil(24))
+        } else {
+            format!("expires in {hours}h")
+        });
+    }
+    let has_policy = state
+        .policy_engine
+        .as_ref()
+        .is_some_and(|engine| matches!(engine.get_policy(&invite.channel), Ok(Some(_))));
+    let mut details_html = String::new();
+    if !details.is_empty() {
+        details_html.push_str(&format!(
+            "<div class=\"stats\">Invite · {}</div>",
+            html_escape(&details.join(" · "))
+        ));
+    }
+    if has_policy {
+        let mut note = "Members agree to this channel's rules and sign in with an AT Protocol identity to join.".to_string();
+        if !invite.waives.is_empty() {
+            note.push_str(&format!(
+                " This invite vouches for: {}.",
+                invite.waives.join(", ")
+            ));
+        }
+        details_html.push_str(&format!(
+            "<div class=\"stats\">{}</div>",
+            html_escape(&note)
+        ));
+    }
+
+    let join_href = format!(
+        "https://{}/#auto-join={}&amp;invite={}",
+        state.config.server_name,
+        html_escape(&invite.channel),
+        html_escape(&token)
+    );
+    let og_path = format!("invite/{}", html_escape(&token));
+    channel_card(&state, &invite.channel, &og_path, &details_html, &join_href).into_response()
+}
+
+/// A bare notice page for the email links (see [`crate::email`]).
+fn email_page(status: StatusCode, heading: &str, body_html: &str) -> axum::response::Response {
+    (
+        status,
+        Html(format!(
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><meta name=\"robots\" content=\"noindex\"><title>Email — freeq</title></head><body style=\"font-family:sans-serif;background:#0c0c0f;color:#e8e8ed;text-align:center;padding-top:20vh\"><h1>{}</h1>{body_html}</body></html>",
+            html_escape(heading)
+        )),
+    )
+        .into_response()
+}
+
+/// GET /email/verify/{token} — confirm an address set with EMAIL SET.
+async fn email_verify_page(
+    Path(token): Path<String>,
+    State(state): State<Arc<SharedState>>,
+) -> impl IntoResponse {
+    let now = chrono::Utc::now().timestamp();
+    match state.email.verify(&token, now) {
+        Ok(subscription) => {
+            state.with_db(|db| db.save_email_subscription(&subscription));
+            email_page(
+                StatusCode::OK,
+                "Email confirmed",
+                &format!(
+                    "<p>You'll get a digest of direct messages and mentions on {} while you're offline.</p>",
+                    html_escape(&state.config.server_name)
+                ),
+            )
+        }
+        Err(e) => email_page(
+            StatusCode::NOT_FOUND,
+            e,
+            "<p>Send <code>EMAIL SET &lt;address&gt;</code> for a new link.</p>",
+        ),
+    }
+}
+
+/// GET /email/unsubscribe/{token} — asks before unsubscribing, so link
+/// scanners that follow every URL in a mail don't.
+async fn email_unsubscribe_page(
+    Path(token): Path<String>,
+    State(state): State<Arc<SharedState>>,
+) -> impl IntoResponse {
+    if state.email.unsubscribe_token_owner(&token).is_none() {
+        return email_page(
+            StatusCode::NOT_FOUND,
+            "Already unsubscribed",
+            "<p>This address gets no more notifications.</p>",
+        );
+    }
+    email_page(
+        StatusCode::OK,
+        "Stop notification emails?",
+        &format!(
+            "<form method=\"post\" action=\"/email/unsubscribe/{}\"><button style=\"font-size:1.1em;padding:.5em 1.5em\">Unsubscribe</button></form>",
+            html_escape(&token)
+        ),
+    )
+}
+
+/// POST /email/unsubscribe/{token} — the unsubscribe form and one-click
+/// `List-Unsubscribe-Post` (RFC 8058).
+async fn email_unsubscribe(
+    Path(token): Path<String>,
+    State(state): State<Arc<SharedState>>,
+) -> impl IntoResponse {
+    if let Some(subscription) = state.email.unsubscribe(&token) {
+        state.with_db(|db| db.delete_email_subscription(&subscription.did));
+        tracing::info!(did = %subscription.did, "Unsubscribed from email notifications");
+    }
+    email_page(
+        StatusCode::OK,
+        "Unsubscribed",
+        "<p>This address gets no more notifications. <code>EMAIL SET</code> turns them back on.</p>",
+    )
+}
+
+/// The shareable card for a channel: topic, member count and a join
+/// button. `details_html` goes under the stats; `og_path` is this page's
+/// path, for link previews.
+fn channel_card(
+    state: &SharedState,
+    channel: &str,
+    og_path: &str,
+    details_html: &str,
+    join_href: &str,
+) -> Html<String> {
     // Get channel info
     let (member_count, topic_text) = {
-        let channels = state.channels.lock();
-        let key = channel.to_lowercase();
-        match channels.get(&key) {
+        let key = crate::casemap::fold(channel);
+        match state.channels.get(&key) {
             Some(ch) => (ch.members.len(), ch.topic.as_ref().map(|t| t.text.clone())),
             None => (0, None),
         }
@@ -3727,14 +4583,15 @@ async fn channel_invite_page(
     let server = &state.config.server_name;
     let topic_html = html_escape(topic_text.as_deref().unwrap_or("No topic set"));
     let channel_display = html_escape(channel.trim_start_matches('#'));
-    let channel_escaped = html_escape(&channel);
+    let channel_escaped = html_escape(channel);
     let member_word = if member_count == 1 {
         "member"
     } else {
         "members"
     };
 
-    Html(format!(r##"<!DOCTYPE html>
+    Html(format!(
+        r##"<!DOCTYPE html>
 <html lang="en">
 <head>
 <meta charset="utf-8">
@@ -3743,7 +4600,7 @@ async fn channel_invite_page(
 <meta property="og:title" content="{channel_escaped} on freeq">
 <meta property="og:description" content="{topic_html} — {member_count} {member_word} online">
 <meta property="og:type" content="website">
-<meta property="og:url" content="https://{server}/join/{channel_display}">
+<meta property="og:url" content="https://{server}/{og_path}">
 <meta property="og:image" content="https://{server}/freeq.png">
 <meta name="twitter:card" content="summary">
 <meta name="twitter:title" content="{channel_escaped} on freeq">
@@ -3776,14 +4633,16 @@ h1 .accent{{color:#00d4aa}}
   <div class="channel">#{channel_display}</div>
   <div class="topic">{topic_html}</div>
   <div class="stats"><span>{member_count}</span> {member_word} online on <span>{server}</span></div>
-  <a href="https://{server}/#auto-join={channel_escaped}" class="btn">Join Channel</a>
+  {details_html}
+  <a href="{join_href}" class="btn">Join Channel</a>
   <div class="alt">
     Or connect with any IRC client: <code>{server}:6667</code><br>
     <a href="https://freeq.at" target="_blank">Learn more about freeq</a>
   </div>
 </div>
 </body>
-</html>"##)).into_response()
+</html>"##
+    ))
 }
 
 // ── OG metadata proxy (replaces allorigins.win privacy leak) ──────────
@@ -4544,6 +5403,7 @@ mod export_tests {
             replaces_msgid: None,
             deleted_at: None,
             sender_did: None,
+            thread_root: None,
         }
     }
 
@@ -4568,13 +5428,16 @@ mod metrics_tests {
 
     #[test]
     fn exposition_format_is_well_formed() {
-        let out = format_metrics(3, 7, 2, 100, 5, 1, 42);
+        let out = format_metrics(3, 7, 2, 100, 5, 1, 9, 4, 3, 2, 1, 42);
         assert!(out.contains("freeq_connections 3\n"));
         assert!(out.contains("freeq_channels 7\n"));
         assert!(out.contains("freeq_s2s_peers 2\n"));
         assert!(out.contains("freeq_messages_total 100\n"));
         assert!(out.contains("freeq_sasl_success_total 5\n"));
         assert!(out.contains("freeq_sasl_failure_total 1\n"));
+        assert!(out.contains("freeq_malformed_lines_total 9\n"));
+        assert!(out.contains("freeq_send_queue_dropped_low_total 4\n"));
+        assert!(out.contains("freeq_send_queue_congestion_quits_total 1\n"));
         assert!(out.contains("freeq_uptime_seconds 42\n"));
         // Every metric line is preceded by HELP + TYPE comments.
         for name in [
@@ -4584,6 +5447,11 @@ mod metrics_tests {
             "freeq_messages_total",
             "freeq_sasl_success_total",
             "freeq_sasl_failure_total",
+            "freeq_malformed_lines_total",
+            "freeq_send_queue_dropped_low_total",
+            "freeq_send_queue_dropped_total",
+            "freeq_send_queue_coalesced_total",
+            "freeq_send_queue_congestion_quits_total",
             "freeq_uptime_seconds",
         ] {
             assert!(
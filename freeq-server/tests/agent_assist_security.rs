@@ -124,8 +124,7 @@ fn insert_message(state: &Arc<SharedState>, channel: &str, sender: &str, msgid:
 
 /// Make `#chan` exist with no remote members + admin session as a member.
 fn make_channel(state: &Arc<SharedState>, name: &str) {
-    let mut channels = state.channels.lock();
-    channels.entry(name.to_lowercase()).or_insert_with(|| {
+    state.channels.insert(name.to_lowercase(), {
         let mut members = HashSet::new();
         members.insert(ADMIN_SESSION.to_string());
         // Construct a minimal ChannelState by going through the public
@@ -139,6 +138,8 @@ fn make_channel(state: &Arc<SharedState>, name: &str) {
             ops: HashSet::new(),
             halfops: HashSet::new(),
             voiced: HashSet::new(),
+            founders: HashSet::new(),
+            admins: HashSet::new(),
             founder_did: Some(ADMIN_DID.to_string()),
             did_ops: HashSet::new(),
             created_at: 0,
@@ -146,12 +147,25 @@ fn make_channel(state: &Arc<SharedState>, name: &str) {
             invite_only: false,
             invites: HashSet::new(),
             invite_exceptions: vec![],
+            quiets: vec![],
+            bots_restricted: false,
+            bot_allowlist: vec![],
             history: std::collections::VecDeque::new(),
             topic: None,
+            topic_history: std::collections::VecDeque::new(),
             topic_locked: false,
             no_ext_msg: false,
             moderated: false,
             encrypted_only: false,
+            archived: false,
+            auditorium: false,
+            no_stats: false,
+            secret: false,
+            temporary: None,
+            empty_since: None,
+            expiry_warned: false,
+            history_visibility: Default::default(),
+            member_since: HashMap::new(),
             key: None,
             pins: vec![],
         }
@@ -416,6 +430,7 @@ async fn ctf_05_step_up_does_not_distinguish_logged_in_from_not() {
             freeq_server::server::OauthPurpose::Login,
         ),
         freeq_server::server::WebSession {
+            id: "sess1".into(),
             did: known_did.to_string(),
             handle: "known.example".into(),
             pds_url: "https://pds.example".into(),
@@ -423,6 +438,7 @@ async fn ctf_05_step_up_does_not_distinguish_logged_in_from_not() {
             dpop_key_b64: freeq_sdk::oauth::DpopKey::generate().to_base64url(),
             dpop_nonce: None,
             created_at: std::time::Instant::now(),
+            last_used: std::time::Instant::now(),
             granted_scope: "atproto".into(),
         },
     );
@@ -0,0 +1,115 @@
+//! Public archive channels (+A): `/api/v1/archive/{chan}` and
+//! `/archive/{chan}`.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use freeq_sdk::did::DidResolver;
+use freeq_server::testing::LineClient;
+
+fn register(addr: SocketAddr, nick: &str) -> LineClient {
+    LineClient::guest_with_caps(addr, nick, "message-tags echo-message")
+}
+
+async fn start(
+    db_path: &str,
+) -> (
+    SocketAddr,
+    SocketAddr,
+    tokio::task::JoinHandle<anyhow::Result<()>>,
+) {
+    let config = freeq_server::config::ServerConfig {
+        listen_addr: "127.0.0.1:0".to_string(),
+        server_name: "test-archive".to_string(),
+        db_path: Some(db_path.to_string()),
+        ..Default::default()
+    };
+    let resolver = DidResolver::static_map(HashMap::new());
+    freeq_server::server::Server::with_resolver(config, resolver)
+        .start_with_web()
+        .await
+        .unwrap()
+}
+
+async fn archive(http: SocketAddr, query: &str) -> (u16, serde_json::Value) {
+    let resp = reqwest::get(format!("http://{http}/api/v1/archive/arch{query}"))
+        .await
+        .unwrap();
+    let status = resp.status().as_u16();
+    (status, resp.json().await.unwrap_or_default())
+}
+
+fn texts(page: &serde_json::Value) -> Vec<String> {
+    page["messages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| m["text"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn archive_serves_flagged_channels_only() {
+    let dir = tempfile::tempdir().unwrap();
+    let db_path = dir.path().join("archive.db").to_string_lossy().to_string();
+    let (addr, http, _handle) = start(&db_path).await;
+
+    let mut alice = tokio::task::spawn_blocking(move || {
+        let mut alice = register(addr, "alice");
+        alice.tx("JOIN #arch");
+        alice.rx(|l| l.split_whitespace().nth(1) == Some("366"), "names");
+        for i in 1..=3 {
+            alice.tx(&format!("PRIVMSG #arch :hello {i}"));
+            alice.rx(|l| l.ends_with(&format!(":hello {i}")), "echo");
+        }
+        alice.tx("@+encrypted PRIVMSG #arch :ENC1:opaque");
+        alice.rx(|l| l.ends_with(":ENC1:opaque"), "echo");
+        alice
+    })
+    .await
+    .unwrap();
+
+    // Not flagged yet
+    assert_eq!(archive(http, "").await.0, 404);
+
+    alice = tokio::task::spawn_blocking(move || {
+        alice.tx("MODE #arch +A");
+        alice.rx(|l| l.contains("MODE #arch +A"), "mode");
+        alice
+    })
+    .await
+    .unwrap();
+
+    let (status, page) = archive(http, "").await;
+    assert_eq!(status, 200);
+    assert_eq!(texts(&page), ["hello 1", "hello 2", "hello 3"]);
+    assert!(page["older"].is_null());
+
+    // Pagination by id
+    let (_, latest) = archive(http, "?limit=2").await;
+    assert_eq!(texts(&latest), ["hello 2", "hello 3"]);
+    let older = latest["older"].as_i64().unwrap();
+    let (_, page) = archive(http, &format!("?limit=2&before={older}")).await;
+    assert_eq!(texts(&page), ["hello 1"]);
+    assert!(page["older"].is_null());
+
+    // HTML view anchors messages by msgid
+    let msgid = latest["messages"][0]["msgid"].as_str().unwrap().to_string();
+    let html = reqwest::get(format!("http://{http}/archive/arch"))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains(&format!(r#"id="{msgid}""#)), "{html}");
+    assert!(!html.contains("ENC1"));
+
+    // E2EE channels are never archived
+    tokio::task::spawn_blocking(move || {
+        alice.tx("MODE #arch +E");
+        alice.rx(|l| l.contains("MODE #arch +E"), "mode");
+    })
+    .await
+    .unwrap();
+    assert_eq!(archive(http, "").await.0, 404);
+}
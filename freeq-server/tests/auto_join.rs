@@ -0,0 +1,65 @@
+//! Server auto-join channels (`--auto-join`) and the AUTOJOIN opt-out.
+
+use std::net::SocketAddr;
+
+use freeq_server::testing::{self, LineClient, TestServer};
+
+/// Send NICK and USER without waiting: auto-joins arrive during registration.
+fn connect(addr: SocketAddr, nick: &str) -> LineClient {
+    let mut c = LineClient::connect(addr);
+    c.tx(&format!("NICK {nick}"));
+    c.tx(&format!("USER {nick} 0 * :{nick}"));
+    c
+}
+
+fn joined(c: &mut LineClient, channel: &str) {
+    let end = format!(" JOIN {channel}");
+    c.rx(|l| l.ends_with(&end), &end);
+}
+
+fn autojoin(c: &mut LineClient, args: &str) -> String {
+    c.tx(format!("AUTOJOIN {args}").trim_end());
+    c.rx(
+        |l| l.contains("NOTICE") && l.contains("AUTOJOIN"),
+        "AUTOJOIN",
+    )
+}
+
+#[tokio::test]
+async fn new_users_join_configured_channels_and_skip_gated_ones() {
+    let mut config = testing::config("test-autojoin");
+    config.auto_join = vec!["#welcome".to_string(), "#gated".to_string()];
+    let server = TestServer::start_with(
+        config,
+        freeq_sdk::did::DidResolver::static_map(Default::default()),
+    )
+    .await
+    .unwrap();
+    let addr = server.irc_addr;
+
+    tokio::task::spawn_blocking(move || {
+        // The first user creates both channels and makes one invite-only.
+        let mut alice = connect(addr, "alice");
+        joined(&mut alice, "#welcome");
+        joined(&mut alice, "#gated");
+        alice.tx("MODE #gated +i");
+        alice.rx(|l| l.contains("MODE #gated +i"), "+i");
+
+        // The next one lands in #welcome and is told why #gated was skipped.
+        let mut bob = connect(addr, "bob");
+        joined(&mut bob, "#welcome");
+        let skipped = bob.rx(|l| l.contains("Auto-join:"), "skip notice");
+        assert!(
+            skipped.contains("#gated") && skipped.contains("+i"),
+            "{skipped}"
+        );
+
+        assert!(autojoin(&mut bob, "").contains("on — you join #welcome, #gated"));
+        assert!(autojoin(&mut bob, "OFF").contains("AUTOJOIN: off"));
+        assert!(autojoin(&mut bob, "").contains("off (server channels"));
+        assert!(autojoin(&mut bob, "maybe").contains("Usage"));
+        assert!(autojoin(&mut bob, "ON").contains("AUTOJOIN: on"));
+    })
+    .await
+    .unwrap();
+}
@@ -0,0 +1,180 @@
+//! BACKUP: oper-only snapshots of every database into timestamped
+//! directories, uploaded to an S3-compatible bucket and pruned to
+//! --backup-keep in both places.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::body::Bytes;
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, Method, StatusCode, Uri};
+use freeq_server::testing::{self, LineClient, TestServer};
+
+type Objects = Arc<Mutex<BTreeMap<String, Vec<u8>>>>;
+
+/// A bucket named `bkt` that keeps objects in memory. It checks requests
+/// are signed but not the signatures (the SigV4 unit tests cover those).
+async fn fake_s3(
+    State(objects): State<Objects>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    Query(query): Query<BTreeMap<String, String>>,
+    body: Bytes,
+) -> (StatusCode, String) {
+    let auth = headers
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if !auth.starts_with("AWS4-HMAC-SHA256 Credential=test-key/") {
+        return (StatusCode::FORBIDDEN, String::new());
+    }
+    let key = uri
+        .path()
+        .trim_start_matches("/bkt")
+        .trim_start_matches('/');
+    let mut objects = objects.lock().unwrap();
+    match method {
+        Method::PUT => {
+            objects.insert(key.to_string(), body.to_vec());
+            (StatusCode::OK, String::new())
+        }
+        Method::DELETE => {
+            objects.remove(key);
+            (StatusCode::NO_CONTENT, String::new())
+        }
+        _ => {
+            let prefix = query.get("prefix").cloned().unwrap_or_default();
+            let contents: String = objects
+                .keys()
+                .filter(|k| k.starts_with(&prefix))
+                .map(|k| format!("<Contents><Key>{k}</Key></Contents>"))
+                .collect();
+            (
+                StatusCode::OK,
+                format!(
+                    "<ListBucketResult><IsTruncated>false</IsTruncated>{contents}</ListBucketResult>"
+                ),
+            )
+        }
+    }
+}
+
+#[tokio::test]
+async fn backups_are_consistent_uploaded_and_pruned() {
+    let objects = Objects::default();
+    let app = axum::Router::new()
+        .fallback(fake_s3)
+        .with_state(Arc::clone(&objects));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let s3_addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let mut config = testing::config("test-backup");
+    config.oper_password = Some("hunter2".to_string());
+    config.backup_keep = 2;
+    config.backup_s3_url = Some(format!("http://{s3_addr}/bkt/nightly"));
+    config.backup_s3_access_key = Some("test-key".to_string());
+    config.backup_s3_secret_key = Some("test-secret".to_string());
+    let server = TestServer::start_with(
+        config,
+        freeq_sdk::did::DidResolver::static_map(Default::default()),
+    )
+    .await
+    .unwrap();
+    let addr = server.irc_addr;
+    let state = Arc::clone(&server.state);
+
+    let (bob_sid, oper_sid, token, _oper) = tokio::task::spawn_blocking(move || {
+        let mut bob = LineClient::guest(addr, "bob");
+        bob.tx("BACKUP");
+        bob.rx(|l| l.contains(" 481 "), "opers only");
+
+        let mut oper = LineClient::guest(addr, "oper");
+        oper.tx("OPER oper hunter2");
+        oper.rx(|l| l.contains(" 381 "), "381");
+        let token = oper.rx(|l| l.contains(" :OPER-TOKEN "), "oper token");
+        let token = token.rsplit(' ').next().unwrap().to_string();
+        oper.tx("JOIN #vault");
+        oper.rx(|l| l.contains(" 366 "), "joined");
+        oper.tx("PRIVMSG #vault :keep me");
+        oper.tx("BACKUP");
+        let done = oper.rx(|l| l.contains("NOTICE oper :Backup "), "first backup");
+        assert!(done.contains("(2 files, "), "{done}");
+        assert!(
+            done.contains(&format!("uploaded to http://{s3_addr}/bkt/nightly")),
+            "{done}"
+        );
+        std::thread::sleep(Duration::from_millis(5));
+        oper.tx("BACKUP");
+        oper.rx(|l| l.contains("NOTICE oper :Backup "), "second backup");
+
+        let bob_sid = state
+            .nick_to_session
+            .lock()
+            .get_session("bob")
+            .unwrap()
+            .to_string();
+        let oper_sid = state.server_opers.lock().iter().next().unwrap().clone();
+        (bob_sid, oper_sid, token, oper)
+    })
+    .await
+    .unwrap();
+
+    let http = reqwest::Client::new();
+    let url = format!("{}/api/v1/admin/backup", server.web_url());
+    assert_eq!(http.post(&url).send().await.unwrap().status(), 401);
+    let resp = http.post(&url).bearer_auth(&bob_sid).send().await.unwrap();
+    assert_eq!(resp.status(), 403);
+    let resp = http.post(&url).bearer_auth(&oper_sid).send().await.unwrap();
+    assert_eq!(resp.status(), 403);
+    let resp = http.post(&url).bearer_auth(&token).send().await.unwrap();
+    assert_eq!(resp.status(), 200);
+    let third: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(third["pruned"].as_array().unwrap().len(), 1, "{third}");
+    let name = third["manifest"]["name"].as_str().unwrap().to_string();
+    let files = third["manifest"]["files"].as_array().unwrap();
+    assert_eq!(files[0]["store"], "main");
+    assert_eq!(files[1]["store"], "policy");
+
+    // Local: the newest two, complete, no leftovers
+    let root = server.data_dir().join("backups");
+    let mut local: Vec<String> = std::fs::read_dir(&root)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    local.sort();
+    assert_eq!(local.len(), 2, "{local:?}");
+    assert_eq!(local[1], name);
+    let dir = root.join(&name);
+    let manifest: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(dir.join("manifest.json")).unwrap()).unwrap();
+    assert_eq!(manifest["format"], "freeq-backup");
+    assert_eq!(manifest["server_name"], "test-backup");
+    for file in manifest["files"].as_array().unwrap() {
+        let path = dir.join(file["name"].as_str().unwrap());
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().len(),
+            file["bytes"].as_u64().unwrap()
+        );
+    }
+    let copy = rusqlite::Connection::open(dir.join("freeq.db")).unwrap();
+    let messages: i64 = copy
+        .query_row(
+            "SELECT COUNT(*) FROM messages WHERE channel = '#vault'",
+            [],
+            |r| r.get(0),
+        )
+        .unwrap();
+    assert_eq!(messages, 1);
+
+    // Remote: the same two, manifest included
+    let keys: Vec<String> = objects.lock().unwrap().keys().cloned().collect();
+    assert_eq!(keys.len(), 6, "{keys:?}");
+    for backup in &local {
+        assert!(keys.contains(&format!("nightly/{backup}/manifest.json")));
+        assert!(keys.contains(&format!("nightly/{backup}/freeq.db")));
+        assert!(keys.contains(&format!("nightly/{backup}/freeq-policy.db")));
+    }
+}
@@ -0,0 +1,107 @@
+//! Bots: user mode +B marks a connection as a bot in WHO, WHOIS, metadata
+//! and message tags; channel mode +B only lets bots on the +W allowlist
+//! speak.
+
+use std::net::SocketAddr;
+
+use freeq_server::testing::{LineClient, TestServer};
+
+/// Register as a guest with message tags and metadata.
+fn guest(addr: SocketAddr, nick: &str) -> LineClient {
+    LineClient::guest_with_caps(addr, nick, "message-tags draft/metadata-2")
+}
+
+#[tokio::test]
+async fn bots_are_flagged_and_need_the_allowlist_under_plus_b() {
+    let server = TestServer::start("test-bots").await.unwrap();
+    let addr = server.irc_addr;
+
+    tokio::task::spawn_blocking(move || {
+        let mut op = guest(addr, "op");
+        op.tx("JOIN #bots");
+        op.rx(|l| l.contains(" 366 "), "op joined");
+        let mut helper = guest(addr, "helper");
+        helper.tx("JOIN #bots");
+        helper.rx(|l| l.contains(" 366 "), "helper joined");
+        let mut alice = guest(addr, "alice");
+        alice.tx("JOIN #bots");
+        alice.rx(|l| l.contains(" 366 "), "alice joined");
+
+        // Self-identification
+        helper.tx("MODE helper");
+        helper.rx(|l| l.contains(" 221 helper +"), "no modes");
+        helper.tx("MODE op +B");
+        helper.rx(|l| l.contains(" 502 "), "others' modes");
+        helper.tx("MODE helper +Bx");
+        helper.rx(|l| l.contains(" 501 "), "unknown flag");
+        helper.tx("MODE helper");
+        helper.rx(|l| l.ends_with(" 221 helper +"), "whole MODE refused");
+        helper.tx("MODE helper +B");
+        helper.rx(|l| l.contains(" MODE helper :+B"), "flagged");
+        helper.tx("MODE helper");
+        helper.rx(|l| l.contains(" 221 helper +B"), "flag shown");
+
+        // Shown distinctly to everyone else
+        op.tx("WHO #bots");
+        let who = op.rx(|l| l.contains(" 352 ") && l.contains(" helper "), "WHO");
+        assert!(who.contains(" HB"), "{who}");
+        op.tx("WHO alice");
+        let who = op.rx(|l| l.contains(" 352 "), "WHO human");
+        assert!(who.contains(" H :"), "{who}");
+        op.tx("WHOIS helper");
+        op.rx(|l| l.contains(" 335 op helper :is a bot"), "WHOIS");
+        op.tx("METADATA helper GET bot");
+        op.rx(
+            |l| l.contains(" 761 ") && l.ends_with("helper bot * agent"),
+            "metadata",
+        );
+        helper.tx("METADATA * SET bot :no");
+        helper.rx(
+            |l| l.contains("FAIL METADATA KEY_NO_PERMISSION"),
+            "read-only",
+        );
+        helper.tx("PRIVMSG #bots :beep");
+        let line = op.rx(|l| l.contains("PRIVMSG #bots :beep"), "bot message");
+        let tags = line.split(' ').next().unwrap();
+        assert!(
+            tags.starts_with('@') && tags[1..].split(';').any(|t| t == "bot"),
+            "{line}"
+        );
+
+        // +B: bots need to be allowlisted; people don't
+        op.tx("MODE #bots +B");
+        helper.rx(|l| l.contains("MODE #bots +B"), "+B");
+        helper.tx("PRIVMSG #bots :beep again");
+        helper.rx(|l| l.contains(" 404 ") && l.contains("+B"), "blocked");
+        alice.tx("PRIVMSG #bots :humans still talk");
+        op.rx(|l| l.contains("humans still talk"), "human message");
+
+        op.tx("MODE #bots +W helper!*@*");
+        helper.rx(|l| l.contains("MODE #bots +W helper!*@*"), "+W");
+        op.tx("MODE #bots +W");
+        op.rx(|l| l.contains(" 728 op #bots W helper!*@* "), "allowlist");
+        op.rx(|l| l.contains(" 729 "), "end of allowlist");
+        helper.tx("PRIVMSG #bots :allowed now");
+        op.rx(|l| l.contains("allowed now"), "allowlisted bot message");
+
+        // Dropping the flag lifts the restriction
+        op.tx("MODE #bots -W helper!*@*");
+        helper.rx(|l| l.contains("MODE #bots -W"), "-W");
+        helper.tx("MODE helper -B");
+        helper.rx(|l| l.contains(" MODE helper :-B"), "unflagged");
+        helper.tx("PRIVMSG #bots :just a person");
+        op.rx(|l| l.contains("just a person"), "unflagged message");
+
+        // -B can't undo AGENT REGISTER
+        let mut robot = guest(addr, "robot");
+        robot.tx("JOIN #bots");
+        robot.rx(|l| l.contains(" 366 "), "robot joined");
+        robot.tx("AGENT REGISTER class=agent");
+        robot.rx(|l| l.contains("Agent registered as agent"), "registered");
+        robot.tx("MODE robot -B");
+        robot.tx("PRIVMSG #bots :not a bot, honest");
+        robot.rx(|l| l.contains(" 404 ") && l.contains("+B"), "still blocked");
+    })
+    .await
+    .unwrap();
+}
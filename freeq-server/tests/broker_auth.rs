@@ -532,3 +532,247 @@ async fn missing_did_field_rejected() {
         .unwrap();
     assert_eq!(resp.status(), 400, "Missing 'did' field should return 400");
 }
+
+// ═══════════════════════════════════════════════════════════════
+// TOKEN BINDING, CAPS AND REVOCATION
+// ═══════════════════════════════════════════════════════════════
+
+async fn start_state() -> (
+    std::net::SocketAddr,
+    std::sync::Arc<freeq_server::server::SharedState>,
+    tokio::task::JoinHandle<anyhow::Result<()>>,
+) {
+    let resolver = DidResolver::static_map(HashMap::new());
+    let config = freeq_server::config::ServerConfig {
+        listen_addr: "127.0.0.1:0".to_string(),
+        server_name: "test-broker".to_string(),
+        challenge_timeout_secs: 60,
+        broker_shared_secret: Some(BROKER_SECRET.to_string()),
+        ..Default::default()
+    };
+    let server = freeq_server::server::Server::with_resolver(config, resolver);
+    let (_irc, http, handle, state) = server.start_with_web_state().await.unwrap();
+    (http, state, handle)
+}
+
+async fn mint_via_broker(http: std::net::SocketAddr, body: serde_json::Value) -> String {
+    let body_bytes = serde_json::to_vec(&body).unwrap();
+    let (sig, ts) = sign_request(&body_bytes);
+    let resp = reqwest::Client::new()
+        .post(format!("http://{http}/auth/broker/web-token"))
+        .header("X-Broker-Signature", &sig)
+        .header("X-Broker-Timestamp", &ts)
+        .header("Content-Type", "application/json")
+        .body(body_bytes)
+        .send()
+        .await
+        .unwrap();
+    assert!(resp.status().is_success());
+    let json: serde_json::Value = resp.json().await.unwrap();
+    json["token"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn fingerprint_bound_token_rejected_from_other_device() {
+    let (http, state, _h) = start_state().await;
+    let body = serde_json::json!({
+        "did": "did:plc:bound", "handle": "bound.bsky", "client_fingerprint": "device-a"
+    });
+
+    let token = mint_via_broker(http, body.clone()).await;
+    let err = state
+        .redeem_web_auth_token(&token, None, Some("device-b"))
+        .unwrap_err();
+    assert!(err.contains("different device"), "got: {err}");
+    // Single-use: the failed attempt burned the token
+    assert!(
+        state
+            .redeem_web_auth_token(&token, None, Some("device-a"))
+            .is_err()
+    );
+
+    let token = mint_via_broker(http, body.clone()).await;
+    assert!(state.redeem_web_auth_token(&token, None, None).is_err());
+    let token = mint_via_broker(http, body).await;
+    assert_eq!(
+        state
+            .redeem_web_auth_token(&token, None, Some("device-a"))
+            .unwrap(),
+        "did:plc:bound"
+    );
+}
+
+#[tokio::test]
+async fn iroh_bound_token_requires_matching_endpoint() {
+    let (http, state, _h) = start_state().await;
+    let body = serde_json::json!({
+        "did": "did:plc:iroh", "handle": "iroh.bsky", "iroh_endpoint_id": "abc123"
+    });
+    let token = mint_via_broker(http, body.clone()).await;
+    assert!(
+        state
+            .redeem_web_auth_token(&token, Some("def456"), None)
+            .is_err()
+    );
+    let token = mint_via_broker(http, body).await;
+    assert!(
+        state
+            .redeem_web_auth_token(&token, Some("abc123"), None)
+            .is_ok()
+    );
+}
+
+#[tokio::test]
+async fn token_cap_evicts_oldest() {
+    let (_http, state, _h) = start_state().await;
+    let tokens: Vec<String> = (0..=freeq_server::server::MAX_WEB_AUTH_TOKENS_PER_DID)
+        .map(|_| state.mint_web_auth_token("did:plc:cap", "cap.bsky", None))
+        .collect();
+    assert!(
+        state.redeem_web_auth_token(&tokens[0], None, None).is_err(),
+        "oldest token should have been evicted"
+    );
+    for t in &tokens[1..] {
+        assert!(state.redeem_web_auth_token(t, None, None).is_ok());
+    }
+}
+
+#[tokio::test]
+async fn revoke_web_credentials() {
+    use freeq_server::server::{OauthPurpose, WebSession};
+    let (_http, state, _h) = start_state().await;
+    let now = std::time::Instant::now();
+    state.insert_web_session(
+        OauthPurpose::Login,
+        WebSession {
+            id: "sess1".into(),
+            did: "did:plc:rev".into(),
+            handle: "rev.bsky".into(),
+            pds_url: "https://pds.example.com".into(),
+            access_token: "at".into(),
+            dpop_key_b64: String::new(),
+            dpop_nonce: None,
+            created_at: now,
+            last_used: now,
+            granted_scope: "atproto".into(),
+        },
+    );
+    state.mint_web_auth_token("did:plc:rev", "rev.bsky", None);
+    state.mint_web_auth_token("did:plc:other", "other.bsky", None);
+
+    // Another DID can't revoke by guessing the ID
+    assert!(!state.revoke_web_credential("did:plc:other", "sess1"));
+    assert!(state.revoke_web_credential("did:plc:rev", "sess1"));
+    assert!(
+        state
+            .web_session("did:plc:rev", OauthPurpose::Login)
+            .is_none()
+    );
+
+    assert_eq!(state.revoke_all_web_credentials("did:plc:rev"), 1);
+    assert_eq!(state.web_auth_tokens.lock().len(), 1);
+}
+
+// ═══════════════════════════════════════════════════════════════
+// SESSION LIFECYCLE WEBHOOKS
+// ═══════════════════════════════════════════════════════════════
+
+async fn post_signed(
+    http: std::net::SocketAddr,
+    path: &str,
+    body: serde_json::Value,
+) -> reqwest::StatusCode {
+    let body_bytes = serde_json::to_vec(&body).unwrap();
+    let (sig, ts) = sign_request(&body_bytes);
+    reqwest::Client::new()
+        .post(format!("http://{http}{path}"))
+        .header("X-Broker-Signature", &sig)
+        .header("X-Broker-Timestamp", &ts)
+        .header("Content-Type", "application/json")
+        .body(body_bytes)
+        .send()
+        .await
+        .unwrap()
+        .status()
+}
+
+#[tokio::test]
+async fn session_events_track_devices_and_revoke_drops_the_web_session() {
+    use freeq_server::server::OauthPurpose;
+    let (http, state, _h) = start_state().await;
+    let did = "did:plc:devices";
+    let now = now_secs() as i64;
+
+    let event = |event: &str, session: &str, at: i64| {
+        serde_json::json!({
+            "event": event, "did": did, "session": session,
+            "device": "freeq-ios/1.0", "at": at,
+        })
+    };
+    assert!(
+        post_signed(http, "/auth/broker/events", event("created", "phone", now))
+            .await
+            .is_success()
+    );
+    let push = serde_json::json!({
+        "did": did, "handle": "devices.bsky", "pds_url": "https://pds.example.com",
+        "access_token": "at", "dpop_key_b64": "dGVzdA", "broker_session": "phone",
+    });
+    assert!(
+        post_signed(http, "/auth/broker/session", push)
+            .await
+            .is_success()
+    );
+    assert!(
+        post_signed(
+            http,
+            "/auth/broker/events",
+            event("refreshed", "phone", now + 60)
+        )
+        .await
+        .is_success()
+    );
+
+    let devices = state.broker_devices_for_did(did);
+    assert_eq!(devices.len(), 1);
+    let (session, device) = &devices[0];
+    assert_eq!(session, "phone");
+    assert_eq!(device.device.as_deref(), Some("freeq-ios/1.0"));
+    assert_eq!((device.created_at, device.last_seen), (now, now + 60));
+    let web_session = state.web_session(did, OauthPurpose::Login).unwrap();
+    assert_eq!(device.web_session.as_deref(), Some(web_session.id.as_str()));
+
+    // Revoking another DID's device (or an unknown one) does nothing.
+    let forged = serde_json::json!({
+        "event": "revoked", "did": "did:plc:other", "session": "phone", "at": now,
+    });
+    assert!(
+        post_signed(http, "/auth/broker/events", forged)
+            .await
+            .is_success()
+    );
+    assert!(state.web_session(did, OauthPurpose::Login).is_some());
+
+    assert!(
+        post_signed(http, "/auth/broker/events", event("revoked", "phone", now))
+            .await
+            .is_success()
+    );
+    assert!(state.broker_devices_for_did(did).is_empty());
+    assert!(state.web_session(did, OauthPurpose::Login).is_none());
+}
+
+#[tokio::test]
+async fn session_events_require_a_signature() {
+    let (http, state, _h) = start_state().await;
+    let resp = reqwest::Client::new()
+        .post(format!("http://{http}/auth/broker/events"))
+        .json(&serde_json::json!({
+            "event": "created", "did": "did:plc:x", "session": "s", "at": 0,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 401);
+    assert!(state.broker_devices.lock().is_empty());
+}
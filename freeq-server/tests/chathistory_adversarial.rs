@@ -1000,6 +1000,126 @@ async fn chathistory_after_kick_denied() {
     .await;
 }
 
+// ═══════════════════════════════════════════════════════════════
+// HISTORY VISIBILITY (+H) and bans
+// ═══════════════════════════════════════════════════════════════
+
+#[tokio::test]
+async fn since_join_visibility_hides_earlier_history() {
+    let r = resolver(vec![]);
+    let (addr, _h) = start(r).await;
+    run(addr, |addr| {
+        let mut alice = C::with_caps(addr, "sj_alice");
+        alice.reg();
+        alice.drain();
+        alice.tx("JOIN #sincejoin");
+        alice.num("366");
+        alice.tx("MODE #sincejoin +H members-since-join");
+        alice.rx(|l| l.contains("MODE #sincejoin +H"), "+H");
+        alice.tx("PRIVMSG #sincejoin :said before bob");
+        alice.drain();
+        // History is bounded in whole seconds.
+        std::thread::sleep(Duration::from_millis(1100));
+
+        let mut bob = C::with_caps(addr, "sj_bob");
+        bob.reg();
+        bob.drain();
+        bob.tx("JOIN #sincejoin");
+        loop {
+            let line = bob.rx(|_| true, "join");
+            assert!(
+                !line.contains("said before bob"),
+                "JOIN replay leaked earlier history: {line}"
+            );
+            if line.split_whitespace().nth(1) == Some("366") {
+                break;
+            }
+        }
+
+        alice.tx("PRIVMSG #sincejoin :said after bob");
+        bob.rx(|l| l.contains("said after bob"), "live message");
+        bob.tx("CHATHISTORY LATEST #sincejoin * 50");
+        let msgs = bob.collect_batch_messages();
+        assert!(
+            msgs.iter().any(|m| m.contains("said after bob")),
+            "{msgs:?}"
+        );
+        assert!(
+            !msgs.iter().any(|m| m.contains("said before bob")),
+            "{msgs:?}"
+        );
+
+        // Back to members-full: bob sees what came before him too.
+        alice.tx("MODE #sincejoin -H");
+        alice.rx(|l| l.contains("MODE #sincejoin -H"), "-H");
+        bob.drain();
+        bob.tx("CHATHISTORY LATEST #sincejoin * 50");
+        let msgs = bob.collect_batch_messages();
+        assert!(
+            msgs.iter().any(|m| m.contains("said before bob")),
+            "{msgs:?}"
+        );
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn public_visibility_serves_non_members_but_not_banned_users() {
+    let r = resolver(vec![]);
+    let (addr, _h) = start(r).await;
+    run(addr, |addr| {
+        let mut alice = C::with_caps(addr, "pub_alice");
+        alice.reg();
+        alice.drain();
+        alice.tx("JOIN #publichist");
+        alice.num("366");
+        alice.tx("MODE #publichist +H public");
+        alice.rx(|l| l.contains("MODE #publichist +H public"), "+H");
+        alice.tx("PRIVMSG #publichist :open to all");
+        alice.drain();
+
+        alice.tx("MODE #publichist");
+        let modes = alice.num("324");
+        assert!(modes.contains("H public"), "{modes}");
+
+        let mut bob = C::with_caps(addr, "pub_bob");
+        bob.reg();
+        bob.drain();
+        bob.tx("CHATHISTORY LATEST #publichist * 50");
+        let msgs = bob.collect_batch_messages();
+        assert!(msgs.iter().any(|m| m.contains("open to all")), "{msgs:?}");
+
+        alice.tx("MODE #publichist +b pub_bob!*@*");
+        alice.rx(|l| l.contains("+b"), "+b");
+        bob.tx("CHATHISTORY LATEST #publichist * 50");
+        let fail = bob.rx(|l| l.contains("FAIL") || l.contains("BATCH"), "denied");
+        assert!(fail.contains("FAIL CHATHISTORY INVALID_TARGET"), "{fail}");
+        bob.tx("SEARCH #publichist :open");
+        let fail = bob.rx(|l| l.contains("FAIL") || l.contains("BATCH"), "denied");
+        assert!(fail.contains("FAIL SEARCH INVALID_TARGET"), "{fail}");
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn invalid_history_visibility_is_rejected() {
+    let r = resolver(vec![]);
+    let (addr, _h) = start(r).await;
+    run(addr, |addr| {
+        let mut alice = C::with_caps(addr, "hv_alice");
+        alice.reg();
+        alice.drain();
+        alice.tx("JOIN #hvbad");
+        alice.num("366");
+        alice.drain();
+        alice.tx("MODE #hvbad +H everyone");
+        alice.num("696");
+        alice.tx("MODE #hvbad +H");
+        alice.num("461");
+    })
+    .await;
+}
+
 // ═══════════════════════════════════════════════════════════════
 // JOIN-time history replay: multi-line entries
 // ═══════════════════════════════════════════════════════════════
@@ -0,0 +1,59 @@
+//! Services-style command aliases (`CS OP`, `NS CLAIM`, `--alias`).
+
+use std::collections::HashMap;
+
+use freeq_sdk::did::DidResolver;
+use freeq_server::testing::LineClient;
+
+#[tokio::test]
+async fn services_aliases_rewrite_to_native_commands() {
+    let config = freeq_server::config::ServerConfig {
+        listen_addr: "127.0.0.1:0".to_string(),
+        server_name: "test-alias".to_string(),
+        aliases: vec!["CS HUSH=MODE $1 +m".to_string()],
+        ..Default::default()
+    };
+    let resolver = DidResolver::static_map(HashMap::new());
+    let (addr, _handle) = freeq_server::server::Server::with_resolver(config, resolver)
+        .start()
+        .await
+        .unwrap();
+
+    tokio::task::spawn_blocking(move || {
+        let mut op = LineClient::guest(addr, "opnick");
+        op.join("#alias");
+        let mut bob = LineClient::guest(addr, "bob");
+        bob.join("#alias");
+        op.rx(|l| l.contains("JOIN") && l.contains("bob"), "bob join");
+
+        op.tx("CS OP #alias bob");
+        bob.rx(|l| l.contains("MODE #alias +o bob"), "cs op");
+
+        op.tx("chanserv topic #alias hello from chanserv");
+        bob.rx(
+            |l| l.contains(" TOPIC #alias ") && l.ends_with("hello from chanserv"),
+            "cs topic",
+        );
+
+        // Configured alias
+        op.tx("CS HUSH #alias");
+        bob.rx(|l| l.contains("MODE #alias +m"), "configured alias");
+
+        // NS CLAIM starts the LOGIN flow
+        bob.tx("NS CLAIM bob.example.com");
+        bob.rx(
+            |l| l.contains(" NOTICE ") && l.contains("/auth/login"),
+            "login url",
+        );
+
+        // Missing argument → usage, unknown sub-command → hint
+        op.tx("CS OP #alias");
+        let l = op.rx(|l| l.split_whitespace().nth(1) == Some("461"), "461");
+        assert!(l.contains("MODE $1 +o $2"), "{l}");
+        op.tx("CS FROB #alias");
+        let l = op.rx(|l| l.split_whitespace().nth(1) == Some("421"), "421");
+        assert!(l.contains("OP") && l.contains("HUSH"), "{l}");
+    })
+    .await
+    .unwrap();
+}
@@ -0,0 +1,96 @@
+//! Content filters: managed with FILTER, applied to PRIVMSG before
+//! delivery, persisted.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use freeq_sdk::did::DidResolver;
+use freeq_server::testing::{self, LineClient, TestServer};
+
+fn guest(addr: SocketAddr, nick: &str) -> LineClient {
+    LineClient::guest_with_caps(addr, nick, "message-tags")
+}
+
+#[tokio::test]
+async fn channel_and_global_filters() {
+    let mut config = testing::config("test-filters");
+    config.oper_password = Some("letmein".to_string());
+    let server = TestServer::start_with(config, DidResolver::static_map(HashMap::new()))
+        .await
+        .unwrap();
+    let addr = server.irc_addr;
+
+    let state = server.state.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut alice = guest(addr, "alice");
+        alice.join("#chat");
+        let mut bob = guest(addr, "bob");
+        bob.join("#chat");
+
+        // Only the channel's ops manage its filters.
+        bob.tx("FILTER ADD #chat glob block :*discord.gg/*");
+        bob.rx(|l| l.contains("operators can manage"), "refused");
+        for rule in [
+            "#chat glob block :*discord.gg/*",
+            r"#chat regex replace=[link] :https?://\S+",
+            "#chat regex flag :(?i)crypto",
+            "#chat glob notify :*refund*",
+        ] {
+            alice.tx(&format!("FILTER ADD {rule}"));
+            alice.rx(|l| l.contains("Added FILTER"), "added");
+        }
+        alice.tx("FILTER ADD #chat regex block :(unclosed");
+        alice.rx(|l| l.contains("Invalid regex"), "bad pattern");
+
+        bob.tx("PRIVMSG #chat :join discord.gg/free");
+        let fail = bob.rx(|l| l.contains(" FAIL "), "blocked");
+        assert!(fail.contains("PRIVMSG FILTERED #chat"), "{fail}");
+
+        bob.tx("PRIVMSG #chat :see https://evil.example now");
+        let line = alice.rx(|l| l.contains("PRIVMSG #chat :see"), "replaced");
+        assert!(line.ends_with(":see [link] now"), "{line}");
+
+        bob.tx("PRIVMSG #chat :buy crypto");
+        let line = alice.rx(|l| l.contains("PRIVMSG #chat :buy"), "flagged");
+        assert!(line.contains("freeq.at/filter=3"), "{line}");
+
+        bob.tx("PRIVMSG #chat :I want a refund");
+        let note = alice.rx(|l| l.contains("[filter 4]"), "ops notified");
+        assert!(note.ends_with("bob to #chat: I want a refund"), "{note}");
+        alice.rx(
+            |l| l.contains("PRIVMSG #chat :I want a refund"),
+            "delivered",
+        );
+
+        // The channel's ops are exempt from its filters.
+        alice.tx("PRIVMSG #chat :discord.gg/ops-only");
+        bob.rx(|l| l.contains("PRIVMSG #chat :discord.gg/ops-only"), "op");
+
+        // Global filters apply to DMs too, and are for server operators.
+        alice.tx("FILTER ADD * glob block :*free nitro*");
+        alice.rx(|l| l.contains("Only server operators"), "not oper");
+        let mut carol = guest(addr, "carol");
+        carol.tx("OPER carol letmein");
+        carol.rx(|l| l.contains(" 381 "), "oper");
+        // Let bob's flood window (5 messages per 2s) pass.
+        std::thread::sleep(Duration::from_secs(2));
+        carol.tx("FILTER ADD * glob block :*free nitro*");
+        carol.rx(|l| l.contains("Added FILTER 5 *"), "global");
+        bob.tx("PRIVMSG alice :get free nitro here");
+        bob.rx(|l| l.contains("FAIL PRIVMSG FILTERED alice"), "DM blocked");
+
+        carol.tx("FILTER LIST *");
+        carol.rx(|l| l.contains("FILTER 5 * glob block"), "listed");
+        carol.tx("FILTER DEL 5");
+        carol.rx(|l| l.contains("Removed filter 5"), "removed");
+        bob.tx("PRIVMSG alice :get free nitro here");
+        alice.rx(|l| l.contains("PRIVMSG alice :get free nitro"), "DM");
+    })
+    .await
+    .unwrap();
+
+    let stored = state.with_db(|db| db.load_content_filters()).unwrap();
+    let ids: Vec<i64> = stored.iter().map(|r| r.id).collect();
+    assert_eq!(ids, [1, 2, 3, 4]);
+}
@@ -0,0 +1,141 @@
+//! DM history keyed by conversation: the unordered DID pair, or nick for
+//! guests, so CHATHISTORY for a DM survives nick changes and other
+//! devices, and messages carry the `+freeq.at/conversation` tag.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use freeq_sdk::crypto::PrivateKey;
+use freeq_sdk::did::{self, DidResolver};
+use freeq_server::testing::LineClient;
+
+const DID_A: &str = "did:plc:dmc_alice";
+const DID_B: &str = "did:plc:dmc_bob";
+
+fn resolver(entries: Vec<(&str, &PrivateKey)>) -> DidResolver {
+    let mut docs = HashMap::new();
+    for (did, key) in entries {
+        docs.insert(
+            did.to_string(),
+            did::make_test_did_document(did, &key.public_key_multibase()),
+        );
+    }
+    DidResolver::static_map(docs)
+}
+
+async fn start(r: DidResolver) -> (SocketAddr, tokio::task::JoinHandle<anyhow::Result<()>>) {
+    let tmp = tempfile::NamedTempFile::new().unwrap();
+    let db = tmp.path().to_str().unwrap().to_string();
+    std::mem::forget(tmp);
+    let config = freeq_server::config::ServerConfig {
+        listen_addr: "127.0.0.1:0".to_string(),
+        server_name: "test-dm-conversations".to_string(),
+        challenge_timeout_secs: 60,
+        db_path: Some(db),
+        ..Default::default()
+    };
+    freeq_server::server::Server::with_resolver(config, r)
+        .start()
+        .await
+        .unwrap()
+}
+
+async fn run(addr: SocketAddr, f: impl FnOnce(SocketAddr) + Send + 'static) {
+    tokio::task::spawn_blocking(move || f(addr)).await.unwrap();
+}
+
+const CAPS: &str = "message-tags server-time batch";
+
+/// Collect all PRIVMSG lines from a search batch response.
+fn collect_batch_messages(c: &mut LineClient) -> Vec<String> {
+    let mut msgs = Vec::new();
+    c.rx(|l| l.contains("BATCH +"), "BATCH start");
+    loop {
+        let line = c.rx(|_| true, "batch line");
+        if line.contains("BATCH -") {
+            break;
+        }
+        if line.contains("PRIVMSG") {
+            msgs.push(line);
+        }
+    }
+    msgs
+}
+
+fn settle() {
+    std::thread::sleep(Duration::from_millis(300));
+}
+
+#[tokio::test]
+async fn dm_history_follows_dids_across_nick_changes_and_devices() {
+    let ka = PrivateKey::generate_ed25519();
+    let kb = PrivateKey::generate_ed25519();
+    let ka2 = PrivateKey::ed25519_from_bytes(&ka.secret_bytes()).unwrap();
+    let r = resolver(vec![(DID_A, &ka), (DID_B, &kb)]);
+    let (addr, _h) = start(r).await;
+    run(addr, move |addr| {
+        let conversation = format!("+freeq.at/conversation=dm:{DID_A},{DID_B}");
+        let mut alice = LineClient::with_sasl_caps(addr, "alice", DID_A, ka, CAPS);
+        let mut bob = LineClient::with_sasl_caps(addr, "bob", DID_B, kb, CAPS);
+
+        alice.tx("PRIVMSG bob :before the rename");
+        let dm = bob.rx(|l| l.contains("before the rename"), "first DM");
+        assert!(dm.contains(&conversation), "{dm}");
+
+        bob.tx("NICK robert");
+        alice.rx(|l| l.contains("NICK") && l.contains("robert"), "rename");
+        alice.tx("PRIVMSG robert :after the rename");
+        let dm = bob.rx(|l| l.contains("after the rename"), "second DM");
+        assert!(dm.contains(&conversation), "{dm}");
+        settle();
+
+        // Alice's other device sees the whole conversation, by nick or DID.
+        let mut phone = LineClient::with_sasl_caps(addr, "alice_phone", DID_A, ka2, CAPS);
+        phone.drain();
+        for target in ["robert", DID_B] {
+            phone.tx(&format!("CHATHISTORY LATEST {target} * 50"));
+            let msgs = collect_batch_messages(&mut phone);
+            assert_eq!(msgs.len(), 2, "{target}: {msgs:?}");
+            assert!(msgs[0].contains("before the rename"), "{msgs:?}");
+            assert!(msgs.iter().all(|m| m.contains(&conversation)), "{msgs:?}");
+        }
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn guest_dm_history_is_keyed_by_nick_and_limited_to_the_session() {
+    let ka = PrivateKey::generate_ed25519();
+    let r = resolver(vec![(DID_A, &ka)]);
+    let (addr, _h) = start(r).await;
+    run(addr, move |addr| {
+        let conversation = format!("+freeq.at/conversation=dm:{DID_A},guest:gus");
+        let mut alice = LineClient::with_sasl_caps(addr, "alice", DID_A, ka, CAPS);
+        let mut gus = LineClient::guest_with_caps(addr, "Gus", CAPS);
+
+        gus.tx("PRIVMSG alice :hello from a guest");
+        let dm = alice.rx(|l| l.contains("hello from a guest"), "guest DM");
+        assert!(dm.contains(&conversation), "{dm}");
+        settle();
+        alice.drain();
+        gus.drain();
+
+        alice.tx("CHATHISTORY LATEST gus * 50");
+        assert_eq!(collect_batch_messages(&mut alice).len(), 1);
+        gus.tx("CHATHISTORY LATEST alice * 50");
+        let msgs = collect_batch_messages(&mut gus);
+        assert_eq!(msgs.len(), 1, "{msgs:?}");
+        assert!(msgs[0].contains(&conversation), "{msgs:?}");
+
+        // The next guest to take the nick doesn't inherit the history.
+        gus.tx("QUIT");
+        std::thread::sleep(Duration::from_millis(1100));
+        let mut next = LineClient::guest_with_caps(addr, "gus", CAPS);
+        next.drain();
+        next.tx("CHATHISTORY LATEST alice * 50");
+        let msgs = collect_batch_messages(&mut next);
+        assert!(msgs.is_empty(), "{msgs:?}");
+    })
+    .await;
+}
@@ -748,6 +748,88 @@ async fn guest_cannot_edit_authenticated_users_message() {
     .await;
 }
 
+// ═══════════════════════════════════════════════════════════════
+// MYDATA: PER-DID EXPORT AND ERASURE
+// ═══════════════════════════════════════════════════════════════
+
+#[tokio::test]
+async fn mydata_erase_deletes_every_message_of_the_did() {
+    let key_a = PrivateKey::generate_ed25519();
+    let key_b = PrivateKey::generate_ed25519();
+    let resolver = resolver_with(vec![(DID_ALICE, &key_a), (DID_BOB, &key_b)]);
+    let (addr, _h) = start(resolver).await;
+    run(addr, move |addr| {
+        let mut alice = C::with_sasl(addr, "er_alice", DID_ALICE, key_a);
+        alice.reg();
+        alice.drain();
+        let mut bob = C::with_sasl(addr, "er_bob", DID_BOB, key_b);
+        bob.reg();
+        bob.drain();
+        alice.tx("JOIN #erase");
+        alice.num("366");
+        alice.drain();
+        bob.tx("JOIN #erase");
+        bob.num("366");
+        bob.drain();
+
+        let mut msgids = Vec::new();
+        for text in ["first words", "second words"] {
+            alice.tx(&format!("PRIVMSG #erase :{text}"));
+            let l = bob.rx(|l| l.contains("PRIVMSG") && l.contains(text), text);
+            msgids.push(C::extract_msgid(&l));
+        }
+        bob.tx("PRIVMSG #erase :bob stays");
+        alice.drain();
+
+        alice.tx("MYDATA EXPORT");
+        let listed = alice.rx(|l| l.contains("MESSAGE #erase"), "export line");
+        assert!(listed.contains(&msgids[0]) && listed.contains("first words"));
+        alice.rx(|l| l.contains("End of MYDATA EXPORT"), "export end");
+
+        // Without CONFIRM nothing is erased.
+        alice.tx("MYDATA ERASE");
+        alice.rx(|l| l.contains("MYDATA ERASE CONFIRM"), "confirm prompt");
+        assert!(
+            bob.maybe(|l| l.contains("draft/delete"), 500).is_none(),
+            "ERASE without CONFIRM must not delete"
+        );
+
+        alice.tx("MYDATA ERASE CONFIRM");
+        alice.rx(|l| l.contains("Erased 2 message(s)"), "erase reply");
+        for _ in &msgids {
+            let del = bob.rx(
+                |l| l.contains("TAGMSG #erase") && l.contains("draft/delete"),
+                "delete",
+            );
+            assert!(
+                msgids.iter().any(|m| del.contains(m.as_str())),
+                "delete names an erased msgid: {del}"
+            );
+        }
+
+        // The export still lists them, blanked.
+        alice.tx("MYDATA EXPORT");
+        let listed = alice.rx(|l| l.contains("MESSAGE #erase"), "export line");
+        assert!(listed.contains(" deleted :"), "{listed}");
+        assert!(!listed.contains("first words"), "{listed}");
+        alice.drain();
+
+        // Bob's message is untouched; a guest can't use MYDATA at all.
+        bob.tx("MYDATA");
+        let summary = bob.rx(|l| l.contains("MYDATA did:"), "summary");
+        assert!(summary.contains("1 message(s) (1 visible)"), "{summary}");
+        let mut guest = C::with_caps(addr, "er_guest");
+        guest.reg();
+        guest.drain();
+        guest.tx("MYDATA EXPORT");
+        guest.rx(
+            |l| l.contains("requires an authenticated identity"),
+            "guest refusal",
+        );
+    })
+    .await;
+}
+
 // ═══════════════════════════════════════════════════════════════
 // DM EDITS AND DELETES
 // ═══════════════════════════════════════════════════════════════
@@ -0,0 +1,138 @@
+//! EMAIL: verified addresses get a digest of DMs and mentions sent while
+//! the user was offline, with a working unsubscribe link.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use freeq_sdk::crypto::PrivateKey;
+use freeq_sdk::did::{self, DidResolver};
+use freeq_server::testing::{self, LineClient, TestServer};
+
+const DID_ALICE: &str = "did:plc:email_alice";
+
+fn alice_key() -> PrivateKey {
+    PrivateKey::ed25519_from_bytes(&[7; 32]).unwrap()
+}
+
+async fn wait_for(what: &str, mut done: impl FnMut() -> bool) {
+    for _ in 0..100 {
+        if done() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("timed out waiting for {what}");
+}
+
+#[tokio::test]
+async fn offline_users_get_a_digest_and_can_unsubscribe() {
+    let mut docs = HashMap::new();
+    docs.insert(
+        DID_ALICE.to_string(),
+        did::make_test_did_document(DID_ALICE, &alice_key().public_key_multibase()),
+    );
+    let mut config = testing::config("test-email");
+    config.smtp_url = Some("memory:".into());
+    config.email_digest_secs = 1;
+    let server = TestServer::start_with(config, DidResolver::static_map(docs))
+        .await
+        .unwrap();
+    let addr = server.irc_addr;
+    let state = server.state.clone();
+
+    tokio::task::spawn_blocking(move || {
+        let mut guest = LineClient::guest(addr, "guest");
+        guest.tx("EMAIL SET guest@example.com");
+        guest.rx(|l| l.contains("must be signed in"), "guests can't");
+
+        let mut alice = LineClient::with_sasl(addr, "alice", DID_ALICE, alice_key());
+        alice.tx("JOIN #dev");
+        alice.rx(|l| l.contains(" 366 "), "joined");
+        alice.tx("EMAIL SET not-an-address");
+        alice.rx(|l| l.contains("doesn't look like an email"), "invalid");
+        alice.tx("EMAIL SET alice@example.com");
+        alice.rx(
+            |l| l.contains("Sent a verification link to a***@example.com"),
+            "set",
+        );
+        alice.tx("EMAIL");
+        alice.rx(|l| l.contains("waiting for verification"), "status");
+        alice.tx("QUIT");
+    })
+    .await
+    .unwrap();
+
+    wait_for("verification mail", || state.email.sent().len() == 1).await;
+    let verification = state.email.sent().remove(0);
+    assert_eq!(verification.to, "alice@example.com");
+    let link = verification
+        .text
+        .split_whitespace()
+        .find(|w| w.contains("/email/verify/"))
+        .unwrap()
+        .to_string();
+    let path = link.split_once("test-email").unwrap().1.to_string();
+    let resp = reqwest::get(format!("{}{path}", server.web_url()))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert!(resp.text().await.unwrap().contains("Email confirmed"));
+    let again = reqwest::get(format!("{}{path}", server.web_url()))
+        .await
+        .unwrap();
+    assert_eq!(again.status(), 404, "tokens are single use");
+    wait_for("alice offline", || {
+        !state.did_sessions.lock().contains_key(DID_ALICE)
+    })
+    .await;
+
+    tokio::task::spawn_blocking(move || {
+        let mut bob = LineClient::guest(addr, "bob");
+        bob.tx("JOIN #dev");
+        bob.rx(|l| l.contains(" 366 "), "joined");
+        bob.tx("PRIVMSG #dev :alice: the build is <red>");
+        bob.tx("PRIVMSG #dev :nobody mentioned here");
+        bob.tx("PRIVMSG alice :are you around?");
+        bob.rx(|l| l.contains(" 401 "), "alice is offline");
+    })
+    .await
+    .unwrap();
+
+    wait_for("digest", || state.email.sent().len() == 2).await;
+    let digest = state.email.sent().remove(1);
+    assert_eq!(digest.subject, "2 new messages on test-email");
+    assert!(
+        digest.text.contains("bob mentioned you in #dev"),
+        "{}",
+        digest.text
+    );
+    assert!(digest.text.contains("alice: the build is <red>"));
+    assert!(digest.html.contains("the build is &lt;red&gt;"));
+    assert!(digest.text.contains("Direct message from bob"));
+    assert!(digest.text.contains("are you around?"));
+    assert!(!digest.text.contains("nobody mentioned"));
+
+    // Back online: nothing queued goes out
+    tokio::task::spawn_blocking(move || {
+        let mut alice = LineClient::with_sasl(addr, "alice", DID_ALICE, alice_key());
+        let mut bob = LineClient::guest(addr, "bob2");
+        bob.tx("PRIVMSG alice :you there now?");
+        alice.rx(|l| l.contains("you there now?"), "delivered live");
+        alice.tx("EMAIL STATUS");
+        alice.rx(|l| l.contains("Emailing a***@example.com"), "status");
+    })
+    .await
+    .unwrap();
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    assert_eq!(state.email.sent().len(), 2);
+
+    let unsubscribe = digest.unsubscribe_url.unwrap();
+    let path = unsubscribe.split_once("test-email").unwrap().1.to_string();
+    let url = format!("{}{path}", server.web_url());
+    let page = reqwest::get(&url).await.unwrap().text().await.unwrap();
+    assert!(page.contains("<form method=\"post\""), "{page}");
+    assert!(state.email.subscription(DID_ALICE).is_some(), "GET is safe");
+    let resp = reqwest::Client::new().post(&url).send().await.unwrap();
+    assert_eq!(resp.status(), 200);
+    assert!(state.email.subscription(DID_ALICE).is_none());
+}
@@ -0,0 +1,134 @@
+//! LIST across an S2S link: peers' channel directories are merged in and
+//! tagged with the server the channel lives on, while secret (+s) and
+//! local-only (`&`) channels stay off other servers' lists. Secret
+//! channels are likewise empty in NAMES and WHO, and missing from WHOIS,
+//! for non-members.
+
+use std::time::Duration;
+
+use freeq_server::testing::{self, LineClient, TestServer};
+
+/// The 322 lines of a LIST reply, without the `:server 322 nick ` prefix.
+fn list(c: &mut LineClient) -> Vec<String> {
+    c.tx("LIST");
+    let mut rows = Vec::new();
+    loop {
+        let l = c.rx(|l| l.contains(" 322 ") || l.contains(" 323 "), "LIST");
+        if l.contains(" 323 ") {
+            return rows;
+        }
+        let row = l.splitn(4, ' ').nth(3).unwrap();
+        rows.push(row.to_string());
+    }
+}
+
+#[tokio::test]
+async fn list_includes_public_channels_from_peers() {
+    let a = TestServer::start_federated("a.test").await.unwrap();
+    let b = TestServer::start_federated("b.test").await.unwrap();
+    let a_addr = a.irc_addr;
+    let b_addr = b.irc_addr;
+
+    let alice = tokio::task::spawn_blocking(move || {
+        let mut alice = LineClient::guest(a_addr, "alice");
+        alice.tx("JOIN #public");
+        alice.rx(|l| l.contains(" 366 "), "joined #public");
+        alice.tx("TOPIC #public :hello there");
+        alice.rx(|l| l.contains("TOPIC #public"), "topic");
+        alice.tx("JOIN #hidden");
+        alice.rx(|l| l.contains(" 366 "), "joined #hidden");
+        alice.tx("MODE #hidden +s");
+        alice.rx(|l| l.contains("MODE #hidden +s"), "+s");
+        alice.tx("JOIN &local");
+        alice.rx(|l| l.contains(" 366 "), "joined &local");
+        alice
+    })
+    .await
+    .unwrap();
+
+    // The link's SyncRequests carry each side's directory
+    let link = testing::link(&a, &b).await.unwrap();
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !b.state.channel_directory.listings().contains_key("#public") {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("directory from a.test");
+    let listings = b.state.channel_directory.listings();
+    assert_eq!(listings.len(), 1, "{listings:?}");
+    assert_eq!(listings["#public"].hosts, ["a.test"]);
+
+    tokio::task::spawn_blocking(move || {
+        let mut alice = alice;
+        let mut bob = LineClient::guest(b_addr, "bob");
+        assert_eq!(list(&mut bob), ["#public 1 :[a.test] hello there"]);
+
+        let mut carol = LineClient::guest(a_addr, "carol");
+        let mut rows = list(&mut carol);
+        rows.sort();
+        assert_eq!(rows, ["#public 1 :hello there", "&local 1 :"]);
+
+        let rows = list(&mut alice);
+        assert!(rows.contains(&"#hidden 1 :".to_string()), "{rows:?}");
+    })
+    .await
+    .unwrap();
+
+    link.cut().await;
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !b.state.channel_directory.listings().is_empty() {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("directory dropped with the link");
+}
+
+#[tokio::test]
+async fn secret_channels_are_empty_to_non_members() {
+    let mut config = testing::config("test-secret");
+    config.oper_password = Some("hunter2".to_string());
+    let server = TestServer::start_with(
+        config,
+        freeq_sdk::did::DidResolver::static_map(Default::default()),
+    )
+    .await
+    .unwrap();
+    let addr = server.irc_addr;
+
+    tokio::task::spawn_blocking(move || {
+        let mut alice = LineClient::guest(addr, "alice");
+        alice.join("#hidden");
+        alice.tx("MODE #hidden +s");
+        alice.rx(|l| l.contains("MODE #hidden +s"), "+s");
+        alice.join("#open");
+
+        // Outsiders get the end numerics and nothing before them
+        let mut bob = LineClient::guest(addr, "bob");
+        bob.tx("NAMES #hidden");
+        let reply = bob.rx(|l| l.contains(" 353 ") || l.contains(" 366 "), "NAMES");
+        assert!(reply.contains(" 366 "), "{reply}");
+        bob.tx("WHO #hidden");
+        let reply = bob.rx(|l| l.contains(" 352 ") || l.contains(" 315 "), "WHO");
+        assert!(reply.contains(" 315 "), "{reply}");
+        bob.tx("WHOIS alice");
+        let channels = bob.num("319");
+        assert!(channels.contains("#open"), "{channels}");
+        assert!(!channels.contains("#hidden"), "{channels}");
+
+        // Members and opers still see it
+        alice.tx("NAMES #hidden");
+        assert!(alice.num("353").ends_with("@alice"));
+        alice.tx("WHO #hidden");
+        alice.num("352");
+        let mut oper = LineClient::guest(addr, "oper");
+        oper.tx("OPER oper hunter2");
+        oper.num("381");
+        oper.tx("WHOIS alice");
+        let channels = oper.num("319");
+        assert!(channels.contains("#hidden"), "{channels}");
+    })
+    .await
+    .unwrap();
+}
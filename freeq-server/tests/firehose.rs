@@ -0,0 +1,130 @@
+//! Oper-only `/firehose` WebSocket: auth and filtered JSON events.
+//!
+//! There's no WebSocket client among the dev-dependencies, so the
+//! handshake and frame decoding are done by hand over a TcpStream.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+use freeq_sdk::did::DidResolver;
+use freeq_server::testing::LineClient;
+
+/// Send a WebSocket upgrade for `path`; return the stream and status code.
+fn upgrade(http: SocketAddr, path: &str, bearer: Option<&str>) -> (TcpStream, u16) {
+    let mut s = TcpStream::connect(http).unwrap();
+    s.set_read_timeout(Some(Duration::from_secs(5))).ok();
+    let auth = bearer
+        .map(|b| format!("Authorization: Bearer {b}\r\n"))
+        .unwrap_or_default();
+    write!(
+        s,
+        "GET {path} HTTP/1.1\r\nHost: {http}\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n\
+         Sec-WebSocket-Version: 13\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n{auth}\r\n"
+    )
+    .unwrap();
+    // Read the response head byte by byte so no frame data is consumed.
+    let mut head = Vec::new();
+    let mut byte = [0u8; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        s.read_exact(&mut byte).unwrap();
+        head.push(byte[0]);
+    }
+    let head = String::from_utf8(head).unwrap();
+    let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+    (s, status)
+}
+
+/// Read one unfragmented server→client text frame.
+fn read_text_frame(s: &mut TcpStream) -> serde_json::Value {
+    let mut hdr = [0u8; 2];
+    s.read_exact(&mut hdr).unwrap();
+    assert_eq!(hdr[0], 0x81, "expected a final text frame");
+    let len = match hdr[1] & 0x7f {
+        126 => {
+            let mut ext = [0u8; 2];
+            s.read_exact(&mut ext).unwrap();
+            u16::from_be_bytes(ext) as usize
+        }
+        127 => panic!("frame too large"),
+        n => n as usize,
+    };
+    let mut payload = vec![0u8; len];
+    s.read_exact(&mut payload).unwrap();
+    serde_json::from_slice(&payload).unwrap()
+}
+
+#[tokio::test]
+async fn firehose_requires_oper_and_filters() {
+    let config = freeq_server::config::ServerConfig {
+        listen_addr: "127.0.0.1:0".to_string(),
+        server_name: "test-firehose".to_string(),
+        oper_password: Some("hunter2".to_string()),
+        ..Default::default()
+    };
+    let resolver = DidResolver::static_map(HashMap::new());
+    let (addr, http, _handle, state) =
+        freeq_server::server::Server::with_resolver(config, resolver)
+            .start_with_web_state()
+            .await
+            .unwrap();
+
+    tokio::task::spawn_blocking(move || {
+        let mut oper = LineClient::guest(addr, "oper");
+        let mut bob = LineClient::guest(addr, "bob");
+
+        assert_eq!(upgrade(http, "/firehose", None).1, 401);
+        let bob_sid = state
+            .nick_to_session
+            .lock()
+            .get_session("bob")
+            .unwrap()
+            .to_string();
+        assert_eq!(upgrade(http, "/firehose", Some(&bob_sid)).1, 403);
+
+        oper.tx("OPER oper hunter2");
+        oper.rx(|l| l.contains(" 381 "), "381");
+        let notice = oper.rx(|l| l.contains("OPER-TOKEN "), "oper token");
+        let token = notice.rsplit(' ').next().unwrap().to_string();
+        // The oper's session id is no credential; only the token is.
+        let oper_sid = state.server_opers.lock().iter().next().unwrap().clone();
+        assert_eq!(upgrade(http, "/firehose", Some(&oper_sid)).1, 403);
+
+        let (mut hose, status) = upgrade(
+            http,
+            "/firehose?types=join,message&channel=%23watched",
+            Some(&token),
+        );
+        assert_eq!(status, 101);
+        // The subscription starts just after the 101 goes out.
+        while !state.firehose.is_active() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        // Filtered out: other channel, and an event type not asked for
+        bob.tx("JOIN #other");
+        bob.rx(|l| l.split_whitespace().nth(1) == Some("366"), "names");
+        bob.tx("PART #other");
+        bob.rx(|l| l.contains("PART #other"), "part");
+
+        bob.tx("JOIN #watched");
+        bob.rx(|l| l.split_whitespace().nth(1) == Some("366"), "names");
+        bob.tx("PRIVMSG #watched :secret body");
+
+        let join = read_text_frame(&mut hose);
+        assert_eq!(join["type"], "join");
+        assert_eq!(join["channel"], "#watched");
+        assert_eq!(join["nick"], "bob");
+        assert_eq!(join["session"], bob_sid.as_str());
+
+        let msg = read_text_frame(&mut hose);
+        assert_eq!(msg["type"], "message");
+        assert_eq!(msg["target"], "#watched");
+        assert_eq!(msg["bytes"], 11);
+        assert!(msg["msgid"].is_string());
+        assert!(!msg.to_string().contains("secret"), "{msg}");
+    })
+    .await
+    .unwrap();
+}
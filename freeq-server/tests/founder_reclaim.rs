@@ -0,0 +1,116 @@
+//! RECLAIM: a founder proves control of the recorded founder DID and gets
+//! the channel back after this server has lost its local record.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use freeq_sdk::auth::{self, ChallengeSigner, KeySigner};
+use freeq_sdk::crypto::PrivateKey;
+use freeq_sdk::did::{self, DidResolver};
+use freeq_server::testing::{self, LineClient, TestServer};
+
+const DID_A: &str = "did:plc:reclaim_alice";
+const DID_B: &str = "did:plc:reclaim_bob";
+
+fn resolver(entries: Vec<(&str, &PrivateKey)>) -> DidResolver {
+    let mut docs = HashMap::new();
+    for (did, key) in entries {
+        docs.insert(
+            did.to_string(),
+            did::make_test_did_document(did, &key.public_key_multibase()),
+        );
+    }
+    DidResolver::static_map(docs)
+}
+
+fn reclaim_fail(c: &mut LineClient, d: &str) -> String {
+    c.rx(|l| l.contains(" FAIL RECLAIM "), d)
+}
+
+/// Ask for a RECLAIM challenge and answer it as `did` with `key`.
+fn reclaim(c: &mut LineClient, channel: &str, did: &str, key: PrivateKey) {
+    c.tx(&format!("RECLAIM {channel}"));
+    let line = c.rx(|l| l.contains(" CHALLENGE "), "reclaim challenge");
+    let challenge = line.rsplit(' ').next().unwrap().trim_start_matches(':');
+    let bytes = auth::decode_challenge_bytes(challenge).unwrap();
+    let resp = KeySigner::new(did.to_string(), key)
+        .respond(&bytes)
+        .unwrap();
+    c.tx(&format!(
+        "RECLAIM {channel} {}",
+        auth::encode_response(&resp)
+    ));
+}
+
+fn copy(key: &PrivateKey) -> PrivateKey {
+    PrivateKey::ed25519_from_bytes(&key.secret_bytes()).unwrap()
+}
+
+#[tokio::test]
+async fn founder_reclaims_channel_with_a_fresh_proof() {
+    let ka = PrivateKey::generate_ed25519();
+    let kb = PrivateKey::generate_ed25519();
+    let mut config = testing::config("test-reclaim");
+    config.reclaim_cooldown_secs = 2;
+    let server = TestServer::start_with(config, resolver(vec![(DID_A, &ka), (DID_B, &kb)]))
+        .await
+        .unwrap();
+    let addr = server.irc_addr;
+    let state = server.state.clone();
+
+    tokio::task::spawn_blocking(move || {
+        let mut alice = LineClient::with_sasl(addr, "alice", DID_A, copy(&ka));
+        alice.tx("JOIN #club");
+        alice.rx(|l| l.contains(" 366 "), "alice joined");
+        let mut bob = LineClient::with_sasl(addr, "bob", DID_B, copy(&kb));
+        bob.tx("JOIN #club");
+        bob.rx(|l| l.contains(" 366 "), "bob joined");
+        std::thread::sleep(Duration::from_millis(300));
+
+        // The server loses its founder record; the cluster still has it.
+        {
+            let mut ch = state.channels.get("#club").unwrap();
+            ch.founder_did = None;
+            ch.did_ops.clear();
+            ch.founders.clear();
+            ch.ops.clear();
+        }
+
+        bob.tx("RECLAIM #club");
+        let fail = reclaim_fail(&mut bob, "bob isn't the founder");
+        assert!(fail.contains("NOT_FOUNDER #club"), "{fail}");
+        let mut carol = LineClient::guest(addr, "carol");
+        carol.tx("RECLAIM #club");
+        let fail = reclaim_fail(&mut carol, "guests can't reclaim");
+        assert!(fail.contains("ACCOUNT_REQUIRED"), "{fail}");
+
+        // A proof signed with someone else's key fails and starts the cooldown.
+        reclaim(&mut alice, "#club", DID_A, copy(&kb));
+        let fail = reclaim_fail(&mut alice, "bad proof");
+        assert!(fail.contains("INVALID_PROOF"), "{fail}");
+        alice.tx("RECLAIM #club");
+        let fail = reclaim_fail(&mut alice, "cooldown");
+        assert!(fail.contains("RATE_LIMITED"), "{fail}");
+
+        std::thread::sleep(Duration::from_millis(2100));
+        reclaim(&mut alice, "#club", DID_A, copy(&ka));
+        alice.rx(|l| l.contains("RECLAIM #club SUCCESS"), "reclaimed");
+        bob.rx(|l| l.contains("MODE #club +o alice"), "alice opped");
+
+        let ch = state.channels.get("#club").unwrap();
+        assert_eq!(ch.founder_did.as_deref(), Some(DID_A));
+        assert!(ch.did_ops.contains(DID_A));
+        assert_eq!(ch.founders.len(), 1);
+        drop(ch);
+
+        let actions: Vec<String> = state
+            .with_db(|db| Ok(db.query_governance_log(Some("#club"), 10)))
+            .unwrap()
+            .into_iter()
+            .map(|e| e.action)
+            .collect();
+        assert_eq!(actions, ["founder_reclaim_failed", "founder_reclaim"]);
+    })
+    .await
+    .unwrap();
+}
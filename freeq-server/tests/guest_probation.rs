@@ -0,0 +1,74 @@
+//! Probation for new guests (`--guest-probation-secs`).
+
+use std::time::Duration;
+
+use freeq_server::testing::{self, LineClient, TestServer};
+
+/// Register and wait for the probation notice.
+fn register(c: &mut LineClient, nick: &str) {
+    c.tx(&format!("NICK {nick}"));
+    c.tx(&format!("USER {nick} 0 * :{nick}"));
+    c.rx(|l| l.contains(" 001 "), "welcome");
+    c.rx(
+        |l| l.contains("NOTICE") && l.contains("probation"),
+        "probation notice",
+    );
+}
+
+#[tokio::test]
+async fn new_guests_are_restricted_until_probation_ends() {
+    let mut config = testing::config("test-probation");
+    config.guest_probation_secs = 4;
+    config.oper_password = Some("opersecret".to_string());
+    let server = TestServer::start_with(
+        config,
+        freeq_sdk::did::DidResolver::static_map(Default::default()),
+    )
+    .await
+    .unwrap();
+    let addr = server.irc_addr;
+
+    tokio::task::spawn_blocking(move || {
+        // OPER lifts probation.
+        let mut bob = LineClient::connect(addr);
+        register(&mut bob, "bob");
+        bob.tx("OPER bob opersecret");
+        bob.rx(|l| l.contains(" 381 "), "oper");
+
+        let mut carol = LineClient::connect(addr);
+        register(&mut carol, "carol");
+        let mut alice = LineClient::connect(addr);
+        register(&mut alice, "alice");
+
+        alice.tx("INVITE carol #a");
+        let fail = alice.rx(|l| l.contains(" FAIL "), "INVITE");
+        assert!(fail.contains("FAIL INVITE GUEST_PROBATION"), "{fail}");
+
+        alice.tx("JOIN #a,#b,#c,#d");
+        let full = alice.rx(|l| l.contains(" 405 "), "join limit");
+        assert!(full.contains("#d"), "{full}");
+
+        // Direct messages only reach operators.
+        alice.tx("PRIVMSG carol :hello");
+        let fail = alice.rx(|l| l.contains(" FAIL "), "DM to a guest");
+        assert!(fail.contains("FAIL PRIVMSG GUEST_PROBATION"), "{fail}");
+        alice.tx("PRIVMSG bob :hello");
+        bob.rx(|l| l.contains("PRIVMSG bob :hello"), "DM to a server oper");
+        carol.tx("PRIVMSG alice :not yet");
+        let fail = carol.rx(|l| l.contains(" FAIL "), "DM to an op elsewhere");
+        assert!(fail.contains("FAIL PRIVMSG GUEST_PROBATION"), "{fail}");
+        carol.tx("JOIN #a");
+        carol.rx(|l| l.contains(" 366 "), "carol joined");
+        carol.tx("PRIVMSG alice :hi op");
+        alice.rx(|l| l.contains("PRIVMSG alice :hi op"), "DM to a channel op");
+
+        // After the period, the restrictions are gone.
+        std::thread::sleep(Duration::from_millis(4500));
+        alice.tx("PRIVMSG carol :later");
+        carol.rx(|l| l.contains("PRIVMSG carol :later"), "DM after probation");
+        alice.tx("JOIN #d");
+        alice.rx(|l| l.contains(" JOIN #d"), "fourth channel");
+    })
+    .await
+    .unwrap();
+}
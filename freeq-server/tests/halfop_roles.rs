@@ -0,0 +1,87 @@
+//! The halfop permission matrix: what `%` may do in a channel, and
+//! `HELPOP ROLES` reporting it.
+
+use freeq_server::testing::{LineClient, TestServer};
+
+#[tokio::test]
+async fn halfops_moderate_members_but_not_ops_or_settings() {
+    let server = TestServer::start("test-halfop-roles").await.unwrap();
+    let addr = server.irc_addr;
+
+    tokio::task::spawn_blocking(move || {
+        let mut op = LineClient::guest(addr, "op");
+        op.tx("JOIN #roles");
+        op.rx(|l| l.contains(" 366 "), "op joined");
+        let mut half = LineClient::guest(addr, "half");
+        half.tx("JOIN #roles");
+        half.rx(|l| l.contains(" 366 "), "half joined");
+        let mut member = LineClient::guest(addr, "member");
+        member.tx("JOIN #roles");
+        member.rx(|l| l.contains(" 366 "), "member joined");
+
+        op.tx("MODE #roles +it");
+        op.rx(|l| l.contains("MODE #roles +it"), "settings");
+        op.tx("MODE #roles +h half");
+        half.rx(|l| l.contains("MODE #roles +h half"), "halfopped");
+
+        // Topic under +t: halfops yes, members no
+        half.tx("TOPIC #roles :set by a halfop");
+        member.rx(|l| l.contains("TOPIC #roles :set by a halfop"), "topic");
+        member.tx("TOPIC #roles :nope");
+        member.rx(|l| l.contains(" 482 "), "member can't set topic");
+
+        // Bans and voice yes, ops, halfops, settings and +i invites no
+        half.tx("MODE #roles +v member");
+        member.rx(|l| l.contains("MODE #roles +v member"), "voiced");
+        half.tx("MODE #roles +b troll!*@*");
+        half.rx(|l| l.contains("MODE #roles +b troll!*@*"), "banned");
+        for mode in ["+o member", "+h member", "-t", "+k secret"] {
+            half.tx(&format!("MODE #roles {mode}"));
+            let refused = half.rx(|l| l.contains(" 482 "), mode);
+            assert!(refused.contains("HELPOP ROLES"), "{refused}");
+        }
+        half.tx("INVITE someone #roles");
+        half.rx(|l| l.contains(" 482 "), "halfops can't invite to +i");
+
+        // Kicks reach plain and voiced members only
+        half.tx("KICK #roles op :nope");
+        let refused = half.rx(|l| l.contains(" 482 "), "can't kick op");
+        assert!(
+            refused.contains("Cannot kick a channel operator"),
+            "{refused}"
+        );
+        half.tx("KICK #roles member :bye");
+        member.rx(|l| l.contains("KICK #roles member"), "kicked");
+
+        // And an op may take halfop status away again
+        op.tx("MODE #roles -h half");
+        half.rx(|l| l.contains("MODE #roles -h half"), "dehalfopped");
+
+        half.tx("HELPOP ROLES #roles");
+        half.rx(|l| l.contains(" 704 "), "help start");
+        let mut lines = Vec::new();
+        loop {
+            let l = half.rx(|l| l.contains(" 705 ") || l.contains(" 706 "), "help");
+            if l.contains(" 706 ") {
+                break;
+            }
+            lines.push(l);
+        }
+        assert!(
+            lines
+                .iter()
+                .any(|l| l
+                    .ends_with("% halfop: topic kick lists voice; kicks/demotes voice, member")),
+            "{lines:?}"
+        );
+        assert!(
+            lines.last().unwrap().ends_with("You are member in #roles"),
+            "{lines:?}"
+        );
+
+        half.tx("HELPOP NOPE");
+        half.rx(|l| l.contains(" 524 "), "unknown topic");
+    })
+    .await
+    .unwrap();
+}
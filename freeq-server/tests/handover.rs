@@ -0,0 +1,128 @@
+//! Zero-downtime restart handover, driven in-process: the old server
+//! serves its handover socket, the new one takes over from it, and a
+//! client drained from the old server reclaims its channels on the new.
+
+#![cfg(unix)]
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use freeq_sdk::crypto::PrivateKey;
+use freeq_sdk::did::{self, DidResolver};
+use freeq_server::handover;
+use freeq_server::server::SharedState;
+use freeq_server::testing::LineClient;
+
+const DID: &str = "did:plc:handover_user";
+
+fn resolver(key: &PrivateKey) -> DidResolver {
+    let mut docs = HashMap::new();
+    docs.insert(
+        DID.to_string(),
+        did::make_test_did_document(DID, &key.public_key_multibase()),
+    );
+    DidResolver::static_map(docs)
+}
+
+async fn start(r: DidResolver) -> (SocketAddr, Arc<SharedState>) {
+    let config = freeq_server::config::ServerConfig {
+        listen_addr: "127.0.0.1:0".to_string(),
+        server_name: "test-handover".to_string(),
+        challenge_timeout_secs: 60,
+        ..Default::default()
+    };
+    let (addr, _web, _handle, state) = freeq_server::server::Server::with_resolver(config, r)
+        .start_with_web_state()
+        .await
+        .unwrap();
+    (addr, state)
+}
+
+fn key_copy(key: &PrivateKey) -> PrivateKey {
+    PrivateKey::ed25519_from_bytes(&key.secret_bytes()).unwrap()
+}
+
+#[tokio::test]
+async fn new_server_takes_over_users_and_channels() {
+    let key = PrivateKey::generate_ed25519();
+    let (k1, k2) = (key_copy(&key), key_copy(&key));
+    let (old_addr, old_state) = start(resolver(&key)).await;
+    let (new_addr, new_state) = start(resolver(&key)).await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let sock = dir.path().join("handover.sock");
+    let (trigger, mut handed_over) = handover::signal();
+    let listener = handover::listen(&sock).unwrap();
+    tokio::spawn(handover::serve(Arc::clone(&old_state), listener, trigger));
+
+    let client = tokio::task::spawn_blocking(move || {
+        let mut c = LineClient::with_sasl(old_addr, "mover", DID, k1);
+        c.tx("JOIN #upgrade");
+        c.rx(|l| l.split_whitespace().nth(1) == Some("366"), "366");
+        c
+    })
+    .await
+    .unwrap();
+
+    let taken = handover::take_over(&sock)
+        .await
+        .unwrap()
+        .expect("old server is serving the handover socket");
+    handed_over.wait().await;
+    assert_eq!(taken.version, handover::VERSION);
+    assert_eq!(taken.sessions.len(), 1);
+    let user = &taken.sessions[0];
+    assert_eq!((user.did.as_str(), user.nick.as_str()), (DID, "mover"));
+    assert!(
+        user.channels.iter().any(|c| c.name == "#upgrade" && c.op),
+        "channel membership and op handed over: {:?}",
+        user.channels
+    );
+    assert_eq!(handover::install(&new_state, taken), 1);
+    assert!(new_state.ghost_sessions.lock().contains_key(DID));
+
+    // Only one takeover: the old server no longer listens.
+    assert!(handover::take_over(&sock).await.unwrap().is_none());
+
+    let drain_state = Arc::clone(&old_state);
+    let drain = tokio::spawn(async move {
+        handover::drain(&drain_state, Duration::from_millis(200)).await;
+    });
+    tokio::task::spawn_blocking(move || {
+        let mut c = client;
+        c.rx(
+            |l| l.starts_with("ERROR"),
+            "ERROR telling the client to reconnect",
+        );
+    })
+    .await
+    .unwrap();
+    drain.await.unwrap();
+    assert!(old_state.connections.is_empty());
+
+    // Reconnecting to the new server reclaims the channel without JOINing.
+    tokio::task::spawn_blocking(move || {
+        let mut c = LineClient::with_sasl(new_addr, "mover", DID, k2);
+        c.rx(|l| l.contains("JOIN #upgrade"), "restored JOIN");
+        c.rx(
+            |l| l.split_whitespace().nth(1) == Some("366"),
+            "restored NAMES",
+        );
+    })
+    .await
+    .unwrap();
+    assert!(new_state.ghost_sessions.lock().is_empty());
+}
+
+#[tokio::test]
+async fn takeover_without_a_running_server_is_a_fresh_start() {
+    let dir = tempfile::tempdir().unwrap();
+    let sock = dir.path().join("handover.sock");
+    assert!(handover::take_over(&sock).await.unwrap().is_none());
+
+    // A socket file left behind by a server that has exited.
+    drop(handover::listen(&sock).unwrap());
+    assert!(handover::take_over(&sock).await.unwrap().is_none());
+}
@@ -0,0 +1,243 @@
+//! Federation invariants on two in-process servers joined by an in-memory
+//! S2S link (`freeq_server::testing`). Unlike `s2s_acceptance.rs`, these
+//! need no running servers and run with the rest of the suite.
+
+use std::time::Duration;
+
+use freeq_sdk::client::{self, ClientHandle, ConnectConfig};
+use freeq_sdk::event::Event;
+use freeq_server::testing::{self, TestServer};
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+async fn connect(server: &TestServer, nick: &str) -> (ClientHandle, mpsc::Receiver<Event>) {
+    let config = ConnectConfig {
+        server_addr: server.irc_addr_string(),
+        nick: nick.to_string(),
+        user: nick.to_string(),
+        realname: nick.to_string(),
+        ..Default::default()
+    };
+    let (handle, mut events) = client::connect(config, None);
+    wait_for(
+        &mut events,
+        |e| matches!(e, Event::Registered { .. }),
+        "registered",
+    )
+    .await;
+    (handle, events)
+}
+
+async fn wait_for(
+    events: &mut mpsc::Receiver<Event>,
+    predicate: impl Fn(&Event) -> bool,
+    desc: &str,
+) -> Event {
+    timeout(TIMEOUT, async {
+        loop {
+            match events.recv().await {
+                Some(e) if predicate(&e) => return e,
+                Some(_) => continue,
+                None => panic!("event stream closed waiting for: {desc}"),
+            }
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("timed out waiting for: {desc}"))
+}
+
+/// Poll `check` until it holds or [`TIMEOUT`] passes.
+async fn eventually(desc: &str, check: impl Fn() -> bool) {
+    timeout(TIMEOUT, async {
+        while !check() {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("timed out waiting for: {desc}"));
+}
+
+fn remote_members(server: &TestServer, channel: &str) -> Vec<String> {
+    server
+        .state
+        .channels
+        .get(channel)
+        .map(|ch| ch.remote_members.keys().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// `(version, policy_id)` of the channel's current policy on `server`.
+fn policy_head(server: &TestServer, channel: &str) -> Option<(i64, String)> {
+    let engine = server.state.policy_engine.as_ref()?;
+    let policy = engine.get_policy(channel).ok()??;
+    Some((policy.version, policy.policy_id?))
+}
+
+async fn set_policy(
+    handle: &ClientHandle,
+    events: &mut mpsc::Receiver<Event>,
+    channel: &str,
+    rules: &str,
+) {
+    handle
+        .raw(&format!("POLICY {channel} SET {rules}"))
+        .await
+        .unwrap();
+    wait_for(
+        events,
+        |e| matches!(e, Event::ServerNotice { text } if text.starts_with("Policy set for")),
+        "policy set",
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn link_completes_the_handshake() {
+    let (a, b, link) = testing::pair().await.unwrap();
+    assert!(link.is_established().await);
+    assert_ne!(a.server_id(), b.server_id());
+    assert_ne!(a.data_dir(), b.data_dir());
+    assert!(a.data_dir().join("freeq.db").exists());
+}
+
+#[tokio::test]
+async fn channel_message_crosses_the_link() {
+    let (a, b, _link) = testing::pair().await.unwrap();
+    let (alice, mut alice_events) = connect(&a, "alice").await;
+    let (bob, mut bob_events) = connect(&b, "bob").await;
+
+    alice.join("#fed").await.unwrap();
+    wait_for(
+        &mut alice_events,
+        |e| matches!(e, Event::Joined { nick, .. } if nick == "alice"),
+        "alice joined",
+    )
+    .await;
+    eventually("alice is a remote member on b", || {
+        remote_members(&b, "#fed").contains(&"alice".to_string())
+    })
+    .await;
+
+    bob.join("#fed").await.unwrap();
+    wait_for(
+        &mut bob_events,
+        |e| matches!(e, Event::Joined { nick, .. } if nick == "bob"),
+        "bob joined",
+    )
+    .await;
+    wait_for(
+        &mut alice_events,
+        |e| matches!(e, Event::Joined { nick, .. } if nick == "bob"),
+        "alice sees bob join across the link",
+    )
+    .await;
+
+    alice.privmsg("#fed", "hello from a").await.unwrap();
+    let msg = wait_for(
+        &mut bob_events,
+        |e| matches!(e, Event::Message { from, .. } if from == "alice"),
+        "bob receives alice's message",
+    )
+    .await;
+    let Event::Message {
+        target, text, tags, ..
+    } = msg
+    else {
+        unreachable!()
+    };
+    assert_eq!(target, "#fed");
+    assert_eq!(text, "hello from a");
+    // Each server on the way stamps its hop, origin first.
+    let hops: Vec<&str> = tags["+freeq.at/hops"].split(',').collect();
+    assert_eq!(hops.len(), 2, "{hops:?}");
+    assert!(hops[0].starts_with("a.test@") && hops[1].starts_with("b.test@"));
+}
+
+#[tokio::test]
+async fn cutting_the_link_removes_remote_members() {
+    let (a, b, link) = testing::pair().await.unwrap();
+    let (alice, mut alice_events) = connect(&a, "alice").await;
+
+    alice.join("#split").await.unwrap();
+    wait_for(
+        &mut alice_events,
+        |e| matches!(e, Event::Joined { nick, .. } if nick == "alice"),
+        "alice joined",
+    )
+    .await;
+    eventually("alice is a remote member on b", || {
+        remote_members(&b, "#split").contains(&"alice".to_string())
+    })
+    .await;
+
+    link.cut().await;
+    eventually("b forgets alice after the split", || {
+        remote_members(&b, "#split").is_empty()
+    })
+    .await;
+    assert!(b.s2s().unwrap().peers.lock().await.is_empty());
+}
+
+#[tokio::test]
+async fn unlinked_servers_are_isolated() {
+    let a = TestServer::start_federated("a.test").await.unwrap();
+    let b = TestServer::start_federated("b.test").await.unwrap();
+    let (alice, mut alice_events) = connect(&a, "alice").await;
+
+    alice.join("#alone").await.unwrap();
+    wait_for(
+        &mut alice_events,
+        |e| matches!(e, Event::Joined { nick, .. } if nick == "alice"),
+        "alice joined",
+    )
+    .await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(remote_members(&b, "#alone").is_empty());
+
+    let dir = a.data_dir().to_path_buf();
+    drop(a);
+    assert!(!dir.exists(), "data dir is removed on drop");
+}
+
+#[tokio::test]
+async fn forked_policy_chains_converge_on_the_longer_one() {
+    let a = TestServer::start_federated("a.test").await.unwrap();
+    let b = TestServer::start_federated("b.test").await.unwrap();
+    let (alice, mut alice_events) = connect(&a, "alice").await;
+    let (bob, mut bob_events) = connect(&b, "bob").await;
+
+    // Partitioned: each side founds #club and writes its own policy.
+    for (handle, events) in [(&alice, &mut alice_events), (&bob, &mut bob_events)] {
+        handle.join("#club").await.unwrap();
+        wait_for(events, |e| matches!(e, Event::Joined { .. }), "joined").await;
+    }
+    set_policy(&alice, &mut alice_events, "#club", "Rules from a").await;
+    set_policy(&bob, &mut bob_events, "#club", "Rules from b").await;
+    set_policy(&bob, &mut bob_events, "#club", "Stricter rules from b").await;
+    let winner = policy_head(&b, "#club").unwrap();
+    assert_eq!(winner.0, 2);
+
+    let _link = testing::link(&a, &b).await.unwrap();
+    eventually("a adopts b's longer chain", || {
+        policy_head(&a, "#club").as_ref() == Some(&winner)
+    })
+    .await;
+    wait_for(
+        &mut alice_events,
+        |e| matches!(e, Event::ServerNotice { text } if text.contains("replaced by v2")),
+        "alice is told her policy was superseded",
+    )
+    .await;
+    assert_eq!(policy_head(&b, "#club"), Some(winner));
+
+    let audit = a
+        .state
+        .with_db(|db| Ok(db.query_governance_log(Some("#club"), 10)))
+        .unwrap();
+    assert!(
+        audit.iter().any(|e| e.action == "policy_superseded"),
+        "{audit:?}"
+    );
+}
@@ -1961,6 +1961,329 @@ async fn invite_exception_empty_mask_dropped() {
     server_handle.abort();
 }
 
+// ── Test: Quiet (+q) silences without kicking ──────────────────────
+//
+// Alice (op) quiets Bob by hostmask. Bob stays in the channel and keeps
+// receiving messages, but his PRIVMSG is rejected with 677 until the
+// quiet is lifted. The list is queryable via MODE +q (728/729).
+
+#[tokio::test]
+async fn quiet_silences_without_kicking() {
+    let (addr, server_handle) = start_test_server(empty_resolver()).await;
+
+    let alice_cfg = ConnectConfig {
+        server_addr: addr.to_string(),
+        nick: "alice".to_string(),
+        user: "alice".to_string(),
+        realname: "Alice".to_string(),
+        ..Default::default()
+    };
+    let (alice_handle, mut alice_events) = client::connect(alice_cfg, None);
+    expect_event(
+        &mut alice_events,
+        2000,
+        |e| matches!(e, Event::Registered { .. }),
+        "Alice registered",
+    )
+    .await;
+    alice_handle.join("#quiet").await.unwrap();
+    expect_event(
+        &mut alice_events,
+        2000,
+        |e| matches!(e, Event::Joined { .. }),
+        "Alice joined",
+    )
+    .await;
+
+    let bob_cfg = ConnectConfig {
+        server_addr: addr.to_string(),
+        nick: "bob".to_string(),
+        user: "bob".to_string(),
+        realname: "Bob".to_string(),
+        ..Default::default()
+    };
+    let (bob_handle, mut bob_events) = client::connect(bob_cfg, None);
+    expect_event(
+        &mut bob_events,
+        2000,
+        |e| matches!(e, Event::Registered { .. }),
+        "Bob registered",
+    )
+    .await;
+    bob_handle.join("#quiet").await.unwrap();
+    expect_event(
+        &mut bob_events,
+        2000,
+        |e| matches!(e, Event::Joined { .. }),
+        "Bob joined",
+    )
+    .await;
+
+    alice_handle.raw("MODE #quiet +q bob!*@*").await.unwrap();
+    expect_event(
+        &mut bob_events,
+        2000,
+        |e| matches!(e, Event::ModeChanged { mode, arg, .. } if mode == "+q" && arg.as_deref() == Some("bob!*@*")),
+        "Bob sees +q",
+    )
+    .await;
+
+    // Bob is silenced with the dedicated numeric...
+    bob_handle
+        .privmsg("#quiet", "can you hear me")
+        .await
+        .unwrap();
+    expect_event(
+        &mut bob_events,
+        2000,
+        |e| matches!(e, Event::RawLine(line) if line.contains(" 677 ") && line.contains("#quiet")),
+        "Bob gets 677 ERR_QUIETED",
+    )
+    .await;
+
+    // ...but is still in the channel and still reads it.
+    alice_handle.privmsg("#quiet", "still here?").await.unwrap();
+    expect_event(
+        &mut bob_events,
+        2000,
+        |e| matches!(e, Event::Message { from, text, .. } if from == "alice" && text == "still here?"),
+        "Bob still receives channel messages",
+    )
+    .await;
+
+    alice_handle.raw("MODE #quiet +q").await.unwrap();
+    expect_event(
+        &mut alice_events,
+        2000,
+        |e| matches!(e, Event::RawLine(line) if line.contains(" 728 ") && line.contains("bob!*@*")),
+        "728 lists bob",
+    )
+    .await;
+    expect_event(
+        &mut alice_events,
+        2000,
+        |e| matches!(e, Event::RawLine(line) if line.contains(" 729 ") && line.contains("#quiet")),
+        "729 end-of-list",
+    )
+    .await;
+
+    alice_handle.raw("MODE #quiet -q bob!*@*").await.unwrap();
+    expect_event(
+        &mut bob_events,
+        2000,
+        |e| matches!(e, Event::ModeChanged { mode, .. } if mode == "-q"),
+        "Bob sees -q",
+    )
+    .await;
+
+    bob_handle.privmsg("#quiet", "back again").await.unwrap();
+    expect_event(
+        &mut alice_events,
+        2000,
+        |e| matches!(e, Event::Message { from, text, .. } if from == "bob" && text == "back again"),
+        "Bob speaks after -q",
+    )
+    .await;
+
+    alice_handle.quit(None).await.unwrap();
+    bob_handle.quit(None).await.unwrap();
+    server_handle.abort();
+}
+
+// ── Test: +q requires channel privileges ───────────────────────────
+
+#[tokio::test]
+async fn quiet_non_op_rejected() {
+    let (addr, server_handle) = start_test_server(empty_resolver()).await;
+
+    let alice_cfg = ConnectConfig {
+        server_addr: addr.to_string(),
+        nick: "alice".to_string(),
+        user: "alice".to_string(),
+        realname: "Alice".to_string(),
+        ..Default::default()
+    };
+    let (alice_handle, mut alice_events) = client::connect(alice_cfg, None);
+    expect_event(
+        &mut alice_events,
+        2000,
+        |e| matches!(e, Event::Registered { .. }),
+        "Alice registered",
+    )
+    .await;
+    alice_handle.join("#quiet-noop").await.unwrap();
+    expect_event(
+        &mut alice_events,
+        2000,
+        |e| matches!(e, Event::Joined { .. }),
+        "Alice joined",
+    )
+    .await;
+
+    let bob_cfg = ConnectConfig {
+        server_addr: addr.to_string(),
+        nick: "bob".to_string(),
+        user: "bob".to_string(),
+        realname: "Bob".to_string(),
+        ..Default::default()
+    };
+    let (bob_handle, mut bob_events) = client::connect(bob_cfg, None);
+    expect_event(
+        &mut bob_events,
+        2000,
+        |e| matches!(e, Event::Registered { .. }),
+        "Bob registered",
+    )
+    .await;
+    bob_handle.join("#quiet-noop").await.unwrap();
+    expect_event(
+        &mut bob_events,
+        2000,
+        |e| matches!(e, Event::Joined { .. }),
+        "Bob joined",
+    )
+    .await;
+
+    bob_handle
+        .raw("MODE #quiet-noop +q alice!*@*")
+        .await
+        .unwrap();
+    expect_event(
+        &mut bob_events,
+        2000,
+        |e| matches!(e, Event::RawLine(line) if line.contains(" 482 ")),
+        "Bob gets 482 ERR_CHANOPRIVSNEEDED",
+    )
+    .await;
+
+    alice_handle.raw("MODE #quiet-noop +q").await.unwrap();
+    expect_event(
+        &mut alice_events,
+        2000,
+        |e| matches!(e, Event::RawLine(line) if line.contains(" 729 ")),
+        "list end (no 728 entries)",
+    )
+    .await;
+
+    alice_handle.quit(None).await.unwrap();
+    bob_handle.quit(None).await.unwrap();
+    server_handle.abort();
+}
+
+// ── Test: +u auditorium hides ordinary members from each other ──────
+
+#[tokio::test]
+async fn auditorium_hides_ordinary_joins() {
+    let (addr, server_handle) = start_test_server(empty_resolver()).await;
+
+    let mut clients = Vec::new();
+    for nick in ["alice", "bob", "carol"] {
+        let cfg = ConnectConfig {
+            server_addr: addr.to_string(),
+            nick: nick.to_string(),
+            user: nick.to_string(),
+            realname: nick.to_string(),
+            ..Default::default()
+        };
+        let (handle, mut events) = client::connect(cfg, None);
+        expect_event(
+            &mut events,
+            2000,
+            |e| matches!(e, Event::Registered { .. }),
+            "registered",
+        )
+        .await;
+        clients.push((handle, events));
+    }
+    let (carol_handle, mut carol_events) = clients.pop().unwrap();
+    let (bob_handle, mut bob_events) = clients.pop().unwrap();
+    let (alice_handle, mut alice_events) = clients.pop().unwrap();
+
+    // Alice creates the channel (op) and makes it an auditorium.
+    alice_handle.join("#stage").await.unwrap();
+    expect_event(
+        &mut alice_events,
+        2000,
+        |e| matches!(e, Event::Joined { .. }),
+        "Alice joined",
+    )
+    .await;
+    alice_handle.raw("MODE #stage +u").await.unwrap();
+    expect_event(
+        &mut alice_events,
+        2000,
+        |e| matches!(e, Event::ModeChanged { mode, .. } if mode == "+u"),
+        "+u set",
+    )
+    .await;
+
+    // Bob's NAMES lists the op and himself, with the full count.
+    bob_handle.join("#stage").await.unwrap();
+    let names = expect_event(
+        &mut bob_events,
+        2000,
+        |e| matches!(e, Event::Names { channel, .. } if channel == "#stage"),
+        "Bob gets NAMES",
+    )
+    .await;
+    let Event::Names { nicks, .. } = names else {
+        unreachable!()
+    };
+    assert!(nicks.contains(&"@alice".to_string()), "{nicks:?}");
+    assert!(nicks.contains(&"bob".to_string()), "{nicks:?}");
+    expect_event(
+        &mut bob_events,
+        2000,
+        |e| matches!(e, Event::RawLine(line) if line.contains(" 366 ") && line.contains("(2 members)")),
+        "End of NAMES carries the member count",
+    )
+    .await;
+
+    // Carol's JOIN reaches Alice (op) but not Bob.
+    carol_handle.join("#stage").await.unwrap();
+    expect_event(
+        &mut carol_events,
+        2000,
+        |e| matches!(e, Event::Joined { nick, .. } if nick == "carol"),
+        "Carol joined",
+    )
+    .await;
+    expect_event(
+        &mut alice_events,
+        2000,
+        |e| matches!(e, Event::Joined { nick, .. } if nick == "carol"),
+        "Alice sees Carol join",
+    )
+    .await;
+    alice_handle.privmsg("#stage", "welcome").await.unwrap();
+    let event = expect_event(
+        &mut bob_events,
+        2000,
+        |e| matches!(e, Event::Joined { .. } | Event::Message { .. }),
+        "Bob gets the message",
+    )
+    .await;
+    assert!(
+        matches!(event, Event::Message { .. }),
+        "Bob saw a join in a +u channel: {event:?}"
+    );
+
+    // Voicing Carol makes her appear to Bob.
+    alice_handle.raw("MODE #stage +v carol").await.unwrap();
+    expect_event(
+        &mut bob_events,
+        2000,
+        |e| matches!(e, Event::Joined { nick, .. } if nick == "carol"),
+        "Bob sees voiced Carol join",
+    )
+    .await;
+
+    alice_handle.quit(None).await.unwrap();
+    bob_handle.quit(None).await.unwrap();
+    carol_handle.quit(None).await.unwrap();
+    server_handle.abort();
+}
+
 // ── Test: Founder bypasses +i on rejoin ─────────────────────────────
 //
 // Standard IRC behavior: the channel founder (and DID-ops) can rejoin a
@@ -2054,6 +2377,71 @@ async fn founder_bypasses_invite_only_on_rejoin() {
     server_handle.abort();
 }
 
+// ── Test: Founder prefix, ISUPPORT PREFIX and WHOX ──────────────────
+
+#[tokio::test]
+async fn founder_prefix_in_names_and_whox() {
+    let private_key = PrivateKey::generate_ed25519();
+    let did_str = "did:plc:founderprefix";
+    let doc = did::make_test_did_document(did_str, &private_key.public_key_multibase());
+
+    let mut docs = HashMap::new();
+    docs.insert(did_str.to_string(), doc);
+    let resolver = DidResolver::static_map(docs);
+
+    let (addr, server_handle) = start_test_server(resolver).await;
+
+    let signer: Arc<dyn ChallengeSigner> =
+        Arc::new(KeySigner::new(did_str.to_string(), private_key));
+
+    let config = ConnectConfig {
+        server_addr: addr.to_string(),
+        nick: "alice".to_string(),
+        user: "alice".to_string(),
+        realname: "Alice".to_string(),
+        ..Default::default()
+    };
+
+    let (handle, mut events) = client::connect(config, Some(signer));
+
+    expect_event(
+        &mut events,
+        2000,
+        |e| matches!(e, Event::Registered { .. }),
+        "Alice registered",
+    )
+    .await;
+    expect_event(
+        &mut events,
+        2000,
+        |e| matches!(e, Event::RawLine(line) if line.contains(" 005 ") && line.contains("PREFIX=(Faohv)~&@%+") && line.contains("WHOX")),
+        "ISUPPORT advertises PREFIX and WHOX",
+    )
+    .await;
+
+    // Creating the channel while authenticated makes Alice its founder.
+    handle.join("#prefix").await.unwrap();
+    expect_event(
+        &mut events,
+        2000,
+        |e| matches!(e, Event::Names { channel, nicks } if channel == "#prefix" && nicks.iter().any(|n| n.starts_with('~') && n.ends_with("alice"))),
+        "Founder listed with ~ in NAMES",
+    )
+    .await;
+
+    handle.raw("WHO #prefix %tnfa,42").await.unwrap();
+    expect_event(
+        &mut events,
+        2000,
+        |e| matches!(e, Event::RawLine(line) if line.contains(" 354 alice 42 alice H~") && line.ends_with(did_str)),
+        "WHOX reply with token, nick, flags and account",
+    )
+    .await;
+
+    handle.quit(None).await.unwrap();
+    server_handle.abort();
+}
+
 // ── Test: Founder bypasses +m on speak ──────────────────────────────
 //
 // Standard IRC behavior: the channel founder (and DID-ops) can speak in
@@ -4559,80 +4947,3 @@ async fn join_history_includes_account_tag() {
     handle_bob.quit(None).await.unwrap();
     server_handle.abort();
 }
-
-// ── Test: Guest cannot access DM history ─────────────────────────────
-
-#[tokio::test]
-async fn dm_history_rejected_for_guest() {
-    let key_alice = PrivateKey::generate_secp256k1();
-    let did_alice = "did:plc:dmguest";
-    let doc_alice = did::make_test_did_document(did_alice, &key_alice.public_key_multibase());
-
-    let mut docs = HashMap::new();
-    docs.insert(did_alice.to_string(), doc_alice);
-    let resolver = DidResolver::static_map(docs);
-
-    let (addr, server_handle) = start_test_server(resolver).await;
-
-    // Alice authenticates
-    let signer: Arc<dyn ChallengeSigner> =
-        Arc::new(KeySigner::new(did_alice.to_string(), key_alice));
-    let config_alice = ConnectConfig {
-        server_addr: addr.to_string(),
-        nick: "dmauth".to_string(),
-        user: "dmauth".to_string(),
-        realname: "Auth".to_string(),
-        ..Default::default()
-    };
-    let (handle_alice, mut events_alice) = client::connect(config_alice, Some(signer));
-    expect_event(
-        &mut events_alice,
-        3000,
-        |e| matches!(e, Event::Authenticated { .. }),
-        "Alice authed",
-    )
-    .await;
-    expect_event(
-        &mut events_alice,
-        2000,
-        |e| matches!(e, Event::Registered { .. }),
-        "Alice registered",
-    )
-    .await;
-
-    // Guest connects
-    let config_guest = ConnectConfig {
-        server_addr: addr.to_string(),
-        nick: "dmguest".to_string(),
-        user: "dmguest".to_string(),
-        realname: "Guest".to_string(),
-        ..Default::default()
-    };
-    let (handle_guest, mut events_guest) = client::connect(config_guest, None);
-    expect_event(
-        &mut events_guest,
-        2000,
-        |e| matches!(e, Event::Registered { .. }),
-        "Guest registered",
-    )
-    .await;
-
-    // Guest requests DM history — should fail with ACCOUNT_REQUIRED
-    handle_guest
-        .raw("CHATHISTORY LATEST dmauth * 50")
-        .await
-        .unwrap();
-
-    // Should get a FAIL notice about authentication
-    expect_event(
-        &mut events_guest,
-        2000,
-        |e| matches!(e, Event::ServerNotice { text } if text.contains("authenticated")),
-        "Guest gets auth error",
-    )
-    .await;
-
-    handle_alice.quit(None).await.unwrap();
-    handle_guest.quit(None).await.unwrap();
-    server_handle.abort();
-}
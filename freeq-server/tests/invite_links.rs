@@ -0,0 +1,199 @@
+//! INVITELINK: signed channel invite links, redeemed with
+//! `JOIN <channel> <token>` or through the `/invite/{token}` landing page.
+
+use std::collections::HashMap;
+
+use freeq_sdk::crypto::PrivateKey;
+use freeq_sdk::did::{self, DidResolver};
+use freeq_server::testing::{self, LineClient, TestServer};
+
+const DID_OP: &str = "did:plc:invite_op";
+const DID_NEW: &str = "did:plc:invite_newcomer";
+const DID_LATE: &str = "did:plc:invite_latecomer";
+
+/// Mint an invite, returning its id and token.
+fn mint(c: &mut LineClient, channel: &str, options: &str) -> (String, String) {
+    c.tx(&format!("INVITELINK {channel} CREATE {options}"));
+    let created = c.rx(|l| l.contains("Created INVITE "), "created");
+    let id = created
+        .split("Created INVITE ")
+        .nth(1)
+        .and_then(|s| s.split(' ').next())
+        .unwrap()
+        .to_string();
+    let share = c.rx(|l| l.contains("Share https://"), "share");
+    let token = share.rsplit(' ').next().unwrap().to_string();
+    (id, token)
+}
+
+#[tokio::test]
+async fn invite_links_get_past_key_and_invite_only_and_run_out() {
+    let server = TestServer::start("test-invite-links").await.unwrap();
+    let addr = server.irc_addr;
+
+    let (revoked, spent) = tokio::task::spawn_blocking(move || {
+        let mut op = LineClient::guest(addr, "op");
+        op.tx("JOIN #club");
+        op.rx(|l| l.contains(" 366 "), "op joined");
+        op.tx("MODE #club +ik sesame");
+        op.rx(|l| l.contains("MODE #club +ik"), "modes set");
+        op.tx("JOIN #lobby");
+        op.rx(|l| l.contains(" 366 ") && l.contains("#lobby"), "lobby");
+
+        let (_, token) = mint(&mut op, "#club", "uses=1 expires=2h");
+
+        let mut alice = LineClient::guest(addr, "alice");
+        alice.tx("JOIN #club");
+        alice.rx(|l| l.contains(" 475 "), "needs the key");
+        alice.tx(&format!("JOIN #lobby {token}"));
+        alice.rx(
+            |l| l.contains("invite is for another channel"),
+            "wrong channel",
+        );
+        alice.tx(&format!("JOIN #club {token}"));
+        alice.rx(
+            |l| l.contains(" 366 ") && l.contains("#club"),
+            "alice joined",
+        );
+
+        let mut bob = LineClient::guest(addr, "bob");
+        bob.tx(&format!("JOIN #club {token}"));
+        bob.rx(|l| l.contains("has been used up"), "used up");
+        bob.rx(|l| l.contains(" 475 "), "still needs the key");
+
+        op.tx("INVITELINK #club LIST");
+        let listed = op.rx(|l| l.contains("INVITE "), "list");
+        assert!(listed.contains("uses=1/1"), "{listed}");
+        assert!(listed.contains("by op"), "{listed}");
+
+        bob.tx("INVITELINK #club CREATE");
+        bob.rx(|l| l.contains(" 482 "), "not op");
+
+        let (id, revoked) = mint(&mut op, "#club", "");
+        op.tx(&format!("INVITELINK #club REVOKE {id}"));
+        op.rx(|l| l.contains(&format!("Revoked invite {id}")), "revoked");
+        bob.tx(&format!("JOIN #club {revoked}"));
+        bob.rx(
+            |l| l.contains(" 475 "),
+            "revoked invites are just wrong keys",
+        );
+
+        op.tx("INVITELINK #club CREATE uses=0");
+        op.rx(|l| l.contains("Bad option 'uses=0'"), "bad option");
+        (revoked, token)
+    })
+    .await
+    .unwrap();
+
+    let (_, live) = tokio::task::spawn_blocking(move || {
+        let mut op = LineClient::guest(addr, "op2");
+        op.tx("JOIN #open");
+        op.rx(|l| l.contains(" 366 "), "joined");
+        mint(&mut op, "#open", "uses=5 expires=3d")
+    })
+    .await
+    .unwrap();
+
+    let page = |token: String| {
+        let url = format!("http://{}/invite/{token}", server.web_addr);
+        async move { reqwest::get(url).await.unwrap() }
+    };
+    assert_eq!(page(revoked).await.status(), 404);
+    assert_eq!(page(spent).await.status(), 410);
+    assert_eq!(page("nope.AAAA".into()).await.status(), 404);
+    let resp = page(live.clone()).await;
+    assert_eq!(resp.status(), 200);
+    let html = resp.text().await.unwrap();
+    assert!(html.contains("5 uses left"), "{html}");
+    assert!(html.contains("expires in 3 days"), "{html}");
+    assert!(
+        html.contains(&format!("#auto-join=#open&amp;invite={live}")),
+        "{html}"
+    );
+}
+
+#[tokio::test]
+async fn invites_waive_named_policy_requirements_for_signed_in_users() {
+    let op_key = PrivateKey::generate_ed25519();
+    let new_key = PrivateKey::generate_ed25519();
+    let late_key = PrivateKey::generate_ed25519();
+    let mut docs = HashMap::new();
+    for (did, key) in [
+        (DID_OP, &op_key),
+        (DID_NEW, &new_key),
+        (DID_LATE, &late_key),
+    ] {
+        docs.insert(
+            did.to_string(),
+            did::make_test_did_document(did, &key.public_key_multibase()),
+        );
+    }
+    let server = TestServer::start_with(
+        testing::config("test-invite-waive"),
+        DidResolver::static_map(docs),
+    )
+    .await
+    .unwrap();
+    let addr = server.irc_addr;
+    let engine = server.state.policy_engine.clone().unwrap();
+
+    tokio::task::spawn_blocking(move || {
+        let mut op = LineClient::with_sasl(addr, "op", DID_OP, op_key);
+        op.tx("JOIN #gated");
+        op.rx(|l| l.contains(" 366 "), "founded");
+        op.tx("POLICY #gated SET Be excellent to each other");
+        op.rx(|l| l.contains("Policy set for #gated"), "policy set");
+        op.tx("POLICY #gated REQUIRE email issuer=did:web:mail.example url=/verify");
+        op.rx(|l| l.contains("Credential endpoint 'email'"), "required");
+
+        op.tx("INVITELINK #gated CREATE waive=phone");
+        op.rx(
+            |l| l.contains("no waivable requirement phone; waivable: email"),
+            "unknown waiver",
+        );
+        let (_, plain) = mint(&mut op, "#gated", "");
+        let (_, waiver) = mint(&mut op, "#gated", "waive=email");
+        let (_, single) = mint(&mut op, "#gated", "waive=email uses=1");
+
+        let mut newcomer = LineClient::with_sasl(addr, "newcomer", DID_NEW, new_key);
+        newcomer.tx("JOIN #gated");
+        newcomer.rx(|l| l.contains(" 477 "), "needs the credential");
+        newcomer.tx(&format!("JOIN #gated {plain}"));
+        let refused = newcomer.rx(|l| l.contains(" 477 "), "invite alone isn't enough");
+        assert!(
+            refused.contains("Cannot join with this invite"),
+            "{refused}"
+        );
+        newcomer.tx(&format!("JOIN #gated {waiver}"));
+        newcomer.rx(|l| l.contains("with an invite accepts its rules"), "note");
+        newcomer.rx(|l| l.contains(" 366 "), "joined");
+
+        let mut guest = LineClient::guest(addr, "guest");
+        guest.tx(&format!("JOIN #gated {waiver}"));
+        guest.rx(
+            |l| l.contains(" 477 ") && l.contains("sign in"),
+            "guests still need a DID",
+        );
+
+        // A refused use doesn't leave the rules accepted
+        guest.tx(&format!("JOIN #gated {single}"));
+        guest.rx(|l| l.contains(" 477 "), "the guest spends the use");
+        let mut late = LineClient::with_sasl(addr, "late", DID_LATE, late_key);
+        late.tx(&format!("JOIN #gated {single}"));
+        late.rx(|l| l.contains("That invite has been used up"), "used up");
+    })
+    .await
+    .unwrap();
+
+    let attestation = engine
+        .check_membership("#gated", DID_NEW)
+        .unwrap()
+        .expect("joining through the invite attests");
+    assert_eq!(attestation.role, "member");
+    assert!(
+        engine
+            .check_membership("#gated", DID_LATE)
+            .unwrap()
+            .is_none()
+    );
+}
@@ -0,0 +1,314 @@
+//! Iroh endpoint binding, DID-allowlist gating, `RESUME` and SASL
+//! EXTERNAL, driven through the generic stream handler with an endpoint ID
+//! attached — the entry point the iroh listener hands each connection to
+//! once the QUIC handshake has authenticated the endpoint.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use freeq_sdk::auth::{self, ChallengeSigner, KeySigner};
+use freeq_sdk::crypto::PrivateKey;
+use freeq_sdk::did::{self, DidResolver};
+use freeq_server::server::SharedState;
+use tokio::io::{
+    AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream, Lines, ReadHalf, WriteHalf,
+};
+
+const DID: &str = "did:plc:iroh_roamer";
+const ENDPOINT: &str = "roamer-phone-endpoint";
+
+async fn start(key: &PrivateKey, iroh_allowed_dids: Vec<String>) -> Arc<SharedState> {
+    let mut docs = HashMap::new();
+    docs.insert(
+        DID.to_string(),
+        did::make_test_did_document(DID, &key.public_key_multibase()),
+    );
+    let config = freeq_server::config::ServerConfig {
+        listen_addr: "127.0.0.1:0".to_string(),
+        server_name: "test-iroh".to_string(),
+        challenge_timeout_secs: 60,
+        iroh_allowed_dids,
+        ..Default::default()
+    };
+    let (_addr, _web, _handle, state) =
+        freeq_server::server::Server::with_resolver(config, DidResolver::static_map(docs))
+            .start_with_web_state()
+            .await
+            .unwrap();
+    state
+}
+
+struct C {
+    lines: Lines<BufReader<ReadHalf<DuplexStream>>>,
+    writer: WriteHalf<DuplexStream>,
+}
+
+impl C {
+    fn connect(state: &Arc<SharedState>, endpoint: Option<&str>) -> Self {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(freeq_server::connection::handle_generic_with_meta(
+            server,
+            Arc::clone(state),
+            endpoint.map(str::to_string),
+        ));
+        let (reader, writer) = tokio::io::split(client);
+        Self {
+            lines: BufReader::new(reader).lines(),
+            writer,
+        }
+    }
+
+    async fn tx(&mut self, l: &str) {
+        self.writer
+            .write_all(format!("{l}\r\n").as_bytes())
+            .await
+            .unwrap();
+    }
+
+    async fn rx(&mut self, p: impl Fn(&str) -> bool, d: &str) -> String {
+        loop {
+            match tokio::time::timeout(Duration::from_secs(5), self.lines.next_line()).await {
+                Ok(Ok(Some(l))) if p(&l) => return l,
+                Ok(Ok(Some(_))) => {}
+                Ok(Ok(None)) => panic!("EOF: {d}"),
+                Ok(Err(e)) => panic!("{d}: {e}"),
+                Err(_) => panic!("timed out: {d}"),
+            }
+        }
+    }
+
+    async fn numeric(&mut self, n: &str) -> String {
+        self.rx(|l| l.split_whitespace().nth(1) == Some(n), n).await
+    }
+
+    /// Start registration with `sasl` acked, leaving SASL to the caller.
+    async fn begin_sasl(&mut self, nick: &str) {
+        self.tx("CAP LS 302").await;
+        self.register(nick).await;
+        self.tx("CAP REQ :sasl").await;
+        self.rx(|l| l.contains("ACK"), "ACK").await;
+    }
+
+    async fn register(&mut self, nick: &str) {
+        self.tx(&format!("NICK {nick}")).await;
+        self.tx(&format!("USER {nick} 0 * :test")).await;
+    }
+
+    async fn sasl(&mut self, nick: &str, key: PrivateKey) {
+        self.begin_sasl(nick).await;
+        self.tx("AUTHENTICATE ATPROTO-CHALLENGE").await;
+        let ch = self
+            .rx(|l| l.starts_with("AUTHENTICATE "), "challenge")
+            .await;
+        let bytes =
+            auth::decode_challenge_bytes(ch.strip_prefix("AUTHENTICATE ").unwrap()).unwrap();
+        let resp = KeySigner::new(DID.to_string(), key)
+            .respond(&bytes)
+            .unwrap();
+        self.tx(&format!("AUTHENTICATE {}", auth::encode_response(&resp)))
+            .await;
+        self.numeric("903").await;
+        self.tx("CAP END").await;
+        self.numeric("001").await;
+    }
+}
+
+fn key_copy(key: &PrivateKey) -> PrivateKey {
+    PrivateKey::ed25519_from_bytes(&key.secret_bytes()).unwrap()
+}
+
+#[tokio::test]
+async fn bind_after_sasl_and_resume_reclaims_the_session() {
+    let key = PrivateKey::generate_ed25519();
+    let state = start(&key, vec![]).await;
+
+    let mut c = C::connect(&state, Some(ENDPOINT));
+    c.sasl("roamer", key_copy(&key)).await;
+    // Signing the challenge doesn't bind the endpoint; BIND does.
+    assert!(state.iroh_endpoint_did(ENDPOINT).is_none());
+    c.tx("BIND").await;
+    c.rx(|l| l.contains("is now bound"), "bound").await;
+    assert_eq!(state.iroh_endpoint_did(ENDPOINT).as_deref(), Some(DID));
+    c.tx("JOIN #mobile").await;
+    c.numeric("366").await;
+    c.tx("WHOIS roamer").await;
+    let whois = c.numeric("672").await;
+    assert!(
+        whois.ends_with(&format!("iroh endpoint: {ENDPOINT} (verified)")),
+        "got: {whois}"
+    );
+    drop(c);
+
+    for _ in 0..50 {
+        if state.ghost_sessions.lock().contains_key(DID) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert!(state.ghost_sessions.lock().contains_key(DID));
+
+    // No CAP, SASL, NICK or USER: the bound endpoint is enough.
+    let mut c = C::connect(&state, Some(ENDPOINT));
+    c.tx("RESUME").await;
+    let logged_in = c.numeric("900").await;
+    assert!(logged_in.contains(DID), "got: {logged_in}");
+    let welcome = c.numeric("001").await;
+    assert!(welcome.contains(" roamer "), "got: {welcome}");
+    c.rx(|l| l.contains("JOIN #mobile"), "restored JOIN").await;
+    assert!(state.ghost_sessions.lock().is_empty());
+
+    c.tx("SESSIONS").await;
+    c.rx(
+        |l| l.contains(&format!("ENDPOINT {ENDPOINT} ")),
+        "ENDPOINT line",
+    )
+    .await;
+    c.tx(&format!("SESSIONS REVOKE {ENDPOINT}")).await;
+    c.rx(|l| l.contains(&format!("Revoked {ENDPOINT}")), "revoked")
+        .await;
+    assert!(state.iroh_endpoint_did(ENDPOINT).is_none());
+}
+
+#[tokio::test]
+async fn resume_needs_a_bound_iroh_endpoint() {
+    let key = PrivateKey::generate_ed25519();
+    let state = start(&key, vec![]).await;
+
+    let mut c = C::connect(&state, Some("never-bound"));
+    c.tx("RESUME").await;
+    c.rx(|l| l.contains("FAIL RESUME NOT_BOUND"), "NOT_BOUND")
+        .await;
+    // The connection falls back to normal registration.
+    c.register("fresh").await;
+    c.numeric("001").await;
+    c.tx("WHOIS fresh").await;
+    let whois = c.numeric("672").await;
+    assert!(!whois.contains("verified"), "guest endpoint: {whois}");
+
+    let mut c = C::connect(&state, None);
+    c.tx("RESUME").await;
+    c.rx(|l| l.contains("FAIL RESUME NOT_IROH"), "NOT_IROH")
+        .await;
+}
+
+#[tokio::test]
+async fn allowlist_gates_iroh_but_not_other_transports() {
+    let key = PrivateKey::generate_ed25519();
+    let state = start(&key, vec![DID.to_string()]).await;
+
+    let mut c = C::connect(&state, Some("stranger-endpoint"));
+    c.register("stranger").await;
+    c.rx(|l| l.starts_with("ERROR"), "guest refused over iroh")
+        .await;
+
+    let mut c = C::connect(&state, None);
+    c.register("plain").await;
+    c.numeric("001").await;
+
+    let mut c = C::connect(&state, Some(ENDPOINT));
+    c.sasl("roamer", key).await;
+    c.tx("BIND").await;
+    c.rx(|l| l.contains("is now bound"), "bound").await;
+    assert!(state.iroh_endpoint_admitted(ENDPOINT));
+    assert!(state.iroh_endpoint_admitted("unbound-endpoint"));
+
+    state.bind_iroh_endpoint("other-endpoint", "did:plc:someone_else");
+    assert!(!state.iroh_endpoint_admitted("other-endpoint"));
+}
+
+#[tokio::test]
+async fn bind_lets_a_web_token_login_come_back_with_sasl_external() {
+    use base64::Engine;
+    let b64 = |s: &str| base64::engine::general_purpose::STANDARD.encode(s);
+
+    let key = PrivateKey::generate_ed25519();
+    let state = start(&key, vec![]).await;
+
+    // A web-token login doesn't bind the endpoint by itself.
+    let token = state.mint_web_auth_token(DID, "roamer.test", None);
+    let mut c = C::connect(&state, Some(ENDPOINT));
+    c.begin_sasl("roamer").await;
+    c.tx("AUTHENTICATE ATPROTO-CHALLENGE").await;
+    c.rx(|l| l.starts_with("AUTHENTICATE "), "challenge").await;
+    let response = serde_json::json!({"did": "", "method": "web-token", "signature": token});
+    c.tx(&format!(
+        "AUTHENTICATE {}",
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(response.to_string())
+    ))
+    .await;
+    c.numeric("903").await;
+    c.tx("CAP END").await;
+    c.numeric("001").await;
+    assert!(state.iroh_endpoint_did(ENDPOINT).is_none());
+
+    c.tx("BIND").await;
+    c.rx(|l| l.contains("is now bound to"), "bound").await;
+    assert_eq!(state.iroh_endpoint_did(ENDPOINT).as_deref(), Some(DID));
+    c.tx("BIND").await;
+    c.rx(|l| l.contains("is already bound to"), "idempotent")
+        .await;
+    drop(c);
+
+    // The bound endpoint logs straight in, with or without an authzid.
+    for answer in ["+".to_string(), b64(DID)] {
+        let mut c = C::connect(&state, Some(ENDPOINT));
+        c.begin_sasl("roamer").await;
+        c.tx("AUTHENTICATE EXTERNAL").await;
+        c.rx(|l| l == "AUTHENTICATE +", "EXTERNAL accepted").await;
+        c.tx(&format!("AUTHENTICATE {answer}")).await;
+        let logged_in = c.numeric("900").await;
+        assert!(logged_in.contains(DID), "got: {logged_in}");
+        c.numeric("903").await;
+        c.tx("CAP END").await;
+        c.numeric("001").await;
+    }
+
+    // Asking for a different identity fails.
+    let mut c = C::connect(&state, Some(ENDPOINT));
+    c.begin_sasl("roamer").await;
+    c.tx("AUTHENTICATE EXTERNAL").await;
+    c.rx(|l| l == "AUTHENTICATE +", "EXTERNAL accepted").await;
+    c.tx(&format!("AUTHENTICATE {}", b64("did:plc:someone_else")))
+        .await;
+    c.numeric("904").await;
+
+    // An unbound endpoint can't use EXTERNAL, and a guest can't BIND.
+    let mut c = C::connect(&state, Some("never-bound"));
+    c.begin_sasl("stranger").await;
+    c.tx("AUTHENTICATE EXTERNAL").await;
+    let fail = c.numeric("904").await;
+    assert!(fail.contains("bound with BIND"), "got: {fail}");
+    c.tx("CAP END").await;
+    c.numeric("001").await;
+    c.tx("BIND").await;
+    c.rx(|l| l.contains("FAIL BIND NOT_AUTHENTICATED"), "guest")
+        .await;
+
+    let mut c = C::connect(&state, None);
+    c.register("plain").await;
+    c.numeric("001").await;
+    c.tx("BIND").await;
+    c.rx(|l| l.contains("FAIL BIND NOT_IROH"), "NOT_IROH").await;
+}
+
+#[tokio::test]
+async fn sasl_never_moves_an_endpoint_bound_to_another_did() {
+    let key = PrivateKey::generate_ed25519();
+    let state = start(&key, vec![]).await;
+    state.bind_iroh_endpoint(ENDPOINT, "did:plc:someone_else");
+
+    let mut c = C::connect(&state, Some(ENDPOINT));
+    c.sasl("roamer", key).await;
+    assert_eq!(
+        state.iroh_endpoint_did(ENDPOINT).as_deref(),
+        Some("did:plc:someone_else")
+    );
+    c.tx("BIND").await;
+    c.rx(|l| l.contains("FAIL BIND ALREADY_BOUND"), "ALREADY_BOUND")
+        .await;
+    assert_eq!(
+        state.iroh_endpoint_did(ENDPOINT).as_deref(),
+        Some("did:plc:someone_else")
+    );
+}
@@ -0,0 +1,87 @@
+//! LANGUAGE and translated numeric/notice text (`--locale-dir`).
+
+use freeq_server::testing::{self, LineClient, TestServer};
+
+fn register(c: &mut LineClient, nick: &str) -> String {
+    c.tx(&format!("NICK {nick}"));
+    c.tx(&format!("USER {nick} 0 * :{nick}"));
+    c.rx(|l| l.contains(" 001 "), "welcome")
+}
+
+fn language(c: &mut LineClient, args: &str) -> String {
+    c.tx(format!("LANGUAGE {args}").trim_end());
+    c.rx(|l| l.contains("LANGUAGE"), "LANGUAGE")
+}
+
+#[tokio::test]
+async fn numerics_and_notices_follow_the_chosen_language() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("de.toml"),
+        "language = \"Deutsch\"\n[messages]\n\"No such nick\" = \"Nick unbekannt\"\n",
+    )
+    .unwrap();
+    let mut config = testing::config("test-language");
+    config.locale_dir = Some(dir.path().to_string_lossy().into_owned());
+    let server = TestServer::start_with(
+        config,
+        freeq_sdk::did::DidResolver::static_map(Default::default()),
+    )
+    .await
+    .unwrap();
+    let addr = server.irc_addr;
+
+    tokio::task::spawn_blocking(move || {
+        // The cap value lists the built-in and configured languages.
+        let mut alice = LineClient::connect(addr);
+        alice.tx("CAP LS 302");
+        let ls = alice.rx(|l| l.contains("CAP * LS"), "CAP LS");
+        assert!(ls.contains("freeq.at/language=en,de,fr"), "{ls}");
+        alice.tx("CAP END");
+
+        // Chosen before registration, so the welcome is translated too.
+        let chosen = language(&mut alice, "es,fr-CA");
+        assert_eq!(chosen, ":test-language LANGUAGE fr Français");
+        let welcome = register(&mut alice, "alice");
+        assert!(welcome.contains(" 001 alice :Bienvenue sur"), "{welcome}");
+
+        // Codes and middle parameters stay; only the text changes.
+        alice.tx("WHOIS nobody");
+        let missing = alice.rx(|l| l.contains(" 401 "), "401");
+        assert!(
+            missing.ends_with(" 401 alice nobody :Pseudo inconnu"),
+            "{missing}"
+        );
+
+        let unknown = language(&mut alice, "xx");
+        assert!(
+            unknown.contains("FAIL LANGUAGE UNKNOWN_LANGUAGE xx :Langue inconnue"),
+            "{unknown}"
+        );
+
+        // Other people's words are never touched.
+        let mut bob = LineClient::connect(addr);
+        register(&mut bob, "bob");
+        bob.tx("PRIVMSG alice :No such nick");
+        let relayed = alice.rx(|l| l.contains("PRIVMSG alice"), "PRIVMSG");
+        assert!(relayed.ends_with(":No such nick"), "{relayed}");
+
+        assert_eq!(
+            language(&mut alice, "de"),
+            ":test-language LANGUAGE de Deutsch"
+        );
+        alice.tx("WHOIS nobody");
+        let missing = alice.rx(|l| l.contains(" 401 "), "401");
+        assert!(missing.ends_with(":Nick unbekannt"), "{missing}");
+
+        assert_eq!(
+            language(&mut alice, "en"),
+            ":test-language LANGUAGE en English"
+        );
+        alice.tx("WHOIS nobody");
+        let missing = alice.rx(|l| l.contains(" 401 "), "401");
+        assert!(missing.ends_with(":No such nick"), "{missing}");
+    })
+    .await
+    .unwrap();
+}
@@ -0,0 +1,77 @@
+//! `+freeq.at/priority`: vetted on the way in, relayed and kept in
+//! history under its canonical name.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use freeq_sdk::crypto::PrivateKey;
+use freeq_sdk::did::{self, DidResolver};
+use freeq_server::testing::{self, LineClient, TestServer};
+
+const DID_BOT: &str = "did:plc:priority_bot";
+
+fn connect(addr: SocketAddr, nick: &str, sasl: Option<(&str, PrivateKey)>) -> LineClient {
+    match sasl {
+        Some((did, key)) => LineClient::with_sasl_caps(addr, nick, did, key, "message-tags"),
+        None => LineClient::guest_with_caps(addr, nick, "message-tags"),
+    }
+}
+
+#[tokio::test]
+async fn only_signed_in_agents_send_low_priority() {
+    let key = PrivateKey::generate_ed25519();
+    let mut docs = HashMap::new();
+    docs.insert(
+        DID_BOT.to_string(),
+        did::make_test_did_document(DID_BOT, &key.public_key_multibase()),
+    );
+    let server = TestServer::start_with(
+        testing::config("test-priority"),
+        DidResolver::static_map(docs),
+    )
+    .await
+    .unwrap();
+    let addr = server.irc_addr;
+
+    let state = server.state.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut alice = connect(addr, "alice", None);
+        alice.join("#ops");
+        let mut bot = connect(addr, "ci-bot", Some((DID_BOT, key)));
+        bot.join("#ops");
+
+        // A human can't have their message folded away, nor use a bogus value.
+        alice.tx("@+freeq.at/priority=low PRIVMSG #ops :look at me");
+        let fail = alice.rx(|l| l.contains(" FAIL "), "low refused");
+        assert!(fail.contains("INVALID_PRIORITY #ops"), "{fail}");
+        alice.tx("@+freeq.at/priority=urgent PRIVMSG #ops :now");
+        let fail = alice.rx(|l| l.contains(" FAIL "), "value refused");
+        assert!(fail.contains("low, normal or high"), "{fail}");
+
+        // The short form is relayed under the canonical name.
+        alice.tx("@+freeq/priority=HIGH PRIVMSG #ops :deploy is broken");
+        let line = bot.rx(|l| l.contains("PRIVMSG #ops :deploy"), "high");
+        assert!(line.contains("+freeq.at/priority=high"), "{line}");
+        assert!(!line.contains("+freeq/priority"), "{line}");
+
+        // Signed in, but not yet an agent.
+        bot.tx("@+freeq.at/priority=low PRIVMSG #ops :build 1 passed");
+        bot.rx(|l| l.contains("INVALID_PRIORITY"), "not an agent");
+        bot.tx("AGENT REGISTER :class=agent");
+        bot.rx(|l| l.contains("Agent registered"), "registered");
+        bot.tx("@+freeq.at/priority=low PRIVMSG #ops :build 2 passed");
+        let line = alice.rx(|l| l.contains("PRIVMSG #ops :build"), "low");
+        assert!(line.contains(":build 2 passed"), "{line}");
+        assert!(line.contains("+freeq.at/priority=low"), "{line}");
+    })
+    .await
+    .unwrap();
+
+    let channel = state.channels.get("#ops").unwrap();
+    let low = channel
+        .history
+        .iter()
+        .find(|m| m.text == "build 2 passed")
+        .unwrap();
+    assert_eq!(low.tags["+freeq.at/priority"], "low");
+}
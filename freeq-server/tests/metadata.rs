@@ -0,0 +1,161 @@
+//! draft/metadata-2: METADATA GET/SET/LIST/SUB, visibility, limits,
+//! subscription notifications and per-DID persistence.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use freeq_sdk::crypto::PrivateKey;
+use freeq_sdk::did::{self, DidResolver};
+use freeq_server::testing::LineClient;
+
+const DID_A: &str = "did:plc:meta_alice";
+
+fn guest(addr: SocketAddr, nick: &str) -> LineClient {
+    LineClient::guest_with_caps(addr, nick, "draft/metadata-2")
+}
+
+fn login(addr: SocketAddr, nick: &str, did: &str, key: PrivateKey) -> LineClient {
+    LineClient::with_sasl_caps(addr, nick, did, key, "draft/metadata-2")
+}
+
+fn fail(c: &mut LineClient, code: &str) -> String {
+    c.rx(|l| l.contains(&format!("FAIL METADATA {code}")), code)
+}
+
+async fn start(
+    db_path: &str,
+    docs: HashMap<String, did::DidDocument>,
+) -> (SocketAddr, tokio::task::JoinHandle<anyhow::Result<()>>) {
+    let config = freeq_server::config::ServerConfig {
+        listen_addr: "127.0.0.1:0".to_string(),
+        server_name: "test-metadata".to_string(),
+        db_path: Some(db_path.to_string()),
+        ..Default::default()
+    };
+    freeq_server::server::Server::with_resolver(config, DidResolver::static_map(docs))
+        .start()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn metadata_get_set_sub_and_persist() {
+    let dir = tempfile::tempdir().unwrap();
+    let db_path = dir.path().join("meta.db").to_string_lossy().to_string();
+    let key_a = PrivateKey::generate_ed25519();
+    let mut docs = HashMap::new();
+    docs.insert(
+        DID_A.to_string(),
+        did::make_test_did_document(DID_A, &key_a.public_key_multibase()),
+    );
+    let (addr, handle) = start(&db_path, docs.clone()).await;
+
+    let key_again = PrivateKey::ed25519_from_bytes(&key_a.secret_bytes()).unwrap();
+    tokio::task::spawn_blocking(move || {
+        let mut alice = login(addr, "alice", DID_A, key_a);
+        let mut bob = guest(addr, "bob");
+        bob.tx("METADATA * SUB pronouns url");
+        bob.num("770");
+        alice.join("#meta");
+        bob.join("#meta");
+
+        // SET → 761 to the setter, METADATA notification to subscribers
+        alice.tx("METADATA * SET pronouns :they/them");
+        let l = alice.num("761");
+        assert!(l.ends_with("alice pronouns * they/them"), "{l}");
+        let l = bob.rx(|l| l.contains(" METADATA "), "notification");
+        assert!(l.ends_with("METADATA alice pronouns * they/them"), "{l}");
+
+        // GET: set and unset keys
+        bob.tx("METADATA alice GET pronouns url");
+        assert!(bob.num("761").ends_with(" they/them"));
+        assert!(bob.num("766").contains("alice url"));
+
+        // Only the owner can write, only the owner can read private keys
+        bob.tx("METADATA alice SET pronouns :he/him");
+        fail(&mut bob, "KEY_NO_PERMISSION");
+        alice.tx("METADATA * SET private/notes :remember the milk");
+        alice.num("761");
+        bob.tx("METADATA alice GET private/notes");
+        fail(&mut bob, "KEY_NO_PERMISSION");
+        alice.tx("METADATA * GET private/notes");
+        assert!(alice.num("761").contains("private/notes private :remember"));
+
+        // Validation and limits
+        alice.tx("METADATA * SET Bad:Key x");
+        fail(&mut alice, "KEY_INVALID");
+        alice.tx(&format!("METADATA * SET bio :{}", "x".repeat(301)));
+        fail(&mut alice, "VALUE_INVALID");
+        alice.tx("METADATA nobody-here GET url");
+        fail(&mut alice, "INVALID_TARGET");
+        alice.tx("METADATA * FROB");
+        fail(&mut alice, "SUBCOMMAND_INVALID");
+        // (paced to stay under the flood limiter)
+        for i in 0..23 {
+            std::thread::sleep(Duration::from_millis(120));
+            alice.tx(&format!("METADATA * SET k{i} v"));
+            alice.num("761");
+        }
+        alice.tx("METADATA * SET one-too-many v");
+        fail(&mut alice, "LIMIT_REACHED");
+
+        // Channel metadata: ops only
+        alice.tx("METADATA #meta SET url :https://example.com/rules");
+        alice.num("761");
+        bob.rx(
+            |l| l.ends_with("METADATA #meta url * https://example.com/rules"),
+            "channel notification",
+        );
+        bob.tx("METADATA #meta SET url :https://evil.example");
+        fail(&mut bob, "KEY_NO_PERMISSION");
+
+        // A subscriber joining gets the channel's and members' metadata
+        let mut carol = guest(addr, "carol");
+        carol.tx("METADATA * SUB url pronouns");
+        carol.num("770");
+        carol.join("#meta");
+        carol.rx(
+            |l| l.ends_with("METADATA #meta url * https://example.com/rules"),
+            "join sync channel",
+        );
+        carol.rx(
+            |l| l.ends_with("METADATA alice pronouns * they/them"),
+            "join sync member",
+        );
+
+        // Guest metadata is dropped with the session
+        bob.tx("METADATA * SET url :https://bob.example");
+        bob.num("761");
+        carol.rx(
+            |l| l.ends_with("METADATA bob url * https://bob.example"),
+            "bob url",
+        );
+
+        // A +s or +i channel's keys are for its members
+        alice.tx("MODE #meta +s");
+        alice.rx(|l| l.contains("MODE #meta +s"), "+s");
+        let mut dave = guest(addr, "dave");
+        dave.tx("METADATA #meta GET url");
+        fail(&mut dave, "KEY_NO_PERMISSION");
+        carol.tx("METADATA #meta GET url");
+        assert!(carol.num("761").ends_with(" https://example.com/rules"));
+    })
+    .await
+    .unwrap();
+    handle.abort();
+    let _ = handle.await;
+
+    // DID metadata survives a restart
+    let (addr, _handle) = start(&db_path, docs).await;
+    tokio::task::spawn_blocking(move || {
+        let mut alice = login(addr, "alice", DID_A, key_again);
+        alice.tx("METADATA * GET pronouns");
+        assert!(alice.num("761").ends_with(" they/them"));
+        let mut dave = guest(addr, "dave");
+        dave.tx("METADATA #meta GET url");
+        assert!(dave.num("761").ends_with(" https://example.com/rules"));
+    })
+    .await
+    .unwrap();
+}
@@ -0,0 +1,145 @@
+//! CASE: per-channel moderation case files, visible only to the channel's
+//! authority.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use freeq_sdk::crypto::PrivateKey;
+use freeq_sdk::did::{self, DidResolver};
+use freeq_server::testing::{self, LineClient, TestServer};
+
+const DID_A: &str = "did:plc:case_alice";
+const DID_C: &str = "did:plc:case_carol";
+
+fn resolver(entries: Vec<(&str, &PrivateKey)>) -> DidResolver {
+    let mut docs = HashMap::new();
+    for (did, key) in entries {
+        docs.insert(
+            did.to_string(),
+            did::make_test_did_document(did, &key.public_key_multibase()),
+        );
+    }
+    DidResolver::static_map(docs)
+}
+
+fn with_sasl(addr: SocketAddr, nick: &str, did: &str, key: PrivateKey) -> LineClient {
+    LineClient::with_sasl_caps(addr, nick, did, key, "message-tags")
+}
+
+/// Send a CASE command and return the text of the NOTICE it gets back.
+fn case(c: &mut LineClient, args: &str) -> String {
+    c.tx(&format!("CASE {args}"));
+    let line = c.rx(|l| l.contains(" NOTICE "), args);
+    line.split_once(" :").unwrap().1.to_string()
+}
+
+/// NOTICE texts up to and including the one starting with `End of`.
+fn until_end(c: &mut LineClient) -> Vec<String> {
+    let mut lines = Vec::new();
+    loop {
+        let line = c.rx(|l| l.contains(" NOTICE "), "case listing");
+        let text = line.split_once(" :").unwrap().1.to_string();
+        let end = text.starts_with("End of");
+        lines.push(text);
+        if end {
+            return lines;
+        }
+    }
+}
+
+fn copy(key: &PrivateKey) -> PrivateKey {
+    PrivateKey::ed25519_from_bytes(&key.secret_bytes()).unwrap()
+}
+
+#[tokio::test]
+async fn ops_keep_case_files_with_evidence_from_history() {
+    let ka = PrivateKey::generate_ed25519();
+    let kc = PrivateKey::generate_ed25519();
+    let config = testing::config("test-cases");
+    let server = TestServer::start_with(config, resolver(vec![(DID_A, &ka), (DID_C, &kc)]))
+        .await
+        .unwrap();
+    let addr = server.irc_addr;
+
+    tokio::task::spawn_blocking(move || {
+        let mut alice = with_sasl(addr, "alice", DID_A, copy(&ka));
+        alice.tx("JOIN #mod");
+        alice.rx(|l| l.contains(" 366 "), "alice joined");
+        let mut carol = with_sasl(addr, "carol", DID_C, copy(&kc));
+        carol.tx("JOIN #mod");
+        carol.rx(|l| l.contains(" 366 "), "carol joined");
+        let mut bob = LineClient::guest(addr, "bob");
+        bob.tx("JOIN #mod");
+        bob.rx(|l| l.contains(" 366 "), "bob joined");
+
+        bob.tx("PRIVMSG #mod :buy cheap followers");
+        let line = alice.rx(|l| l.contains("PRIVMSG #mod :buy"), "spam");
+        let msgid = line
+            .split([';', ' '])
+            .find_map(|t| t.trim_start_matches('@').strip_prefix("msgid="))
+            .unwrap()
+            .to_string();
+
+        // Only signed-in channel authority may touch the cases.
+        assert!(case(&mut bob, "LIST #mod").contains("authenticated identity"));
+        assert!(case(&mut carol, "LIST #mod").contains("operators"));
+
+        // A nick resolves to its DID; a guest's can't.
+        assert!(
+            alice
+                .case("OPEN #mod bob :spam")
+                .contains("isn't signed in")
+        );
+        let opened = case(&mut alice, "OPEN #mod carol :argued in bad faith");
+        assert!(opened.contains(DID_C), "{opened}");
+        let opened = case(&mut alice, "OPEN #mod did:plc:bob :follower spam");
+        let id: i64 = opened
+            .strip_prefix("Opened case ")
+            .and_then(|r| r.split(' ').next())
+            .and_then(|n| n.parse().ok())
+            .unwrap_or_else(|| panic!("{opened}"));
+
+        let missing = case(&mut alice, &format!("EVIDENCE #mod {id} nosuchmsg {msgid}"));
+        assert!(missing.contains("No message nosuchmsg"), "{missing}");
+        let attached = alice.rx(|l| l.contains(" NOTICE "), "attached");
+        assert!(attached.contains("attached 1 message(s)"), "{attached}");
+        assert!(
+            alice
+                .case(&format!("ACTION #mod {id} :kicked and warned"))
+                .contains("action recorded")
+        );
+        assert!(
+            alice
+                .case(&format!("CLOSE #mod {id} :resolved"))
+                .contains("Closed")
+        );
+        assert!(
+            alice
+                .case(&format!("CLOSE #mod {id}"))
+                .contains("already closed")
+        );
+        assert!(
+            alice
+                .case(&format!("SHOW #other {id}"))
+                .contains("operators")
+        );
+
+        alice.tx("CASE LIST #mod");
+        let open = until_end(&mut alice);
+        assert_eq!(open.len(), 2, "{open:?}");
+        assert!(open[0].contains(DID_C) && open[0].contains(" open "));
+        alice.tx("CASE LIST #mod ALL");
+        let all = until_end(&mut alice);
+        assert!(all[0].starts_with(&format!("CASE {id} closed did:plc:bob")));
+
+        alice.tx(&format!("CASE SHOW #mod {id}"));
+        let shown = until_end(&mut alice);
+        assert_eq!(shown.len(), 5, "{shown:?}");
+        assert!(shown[1].contains(&format!("evidence {msgid} from bob!")));
+        assert!(shown[1].ends_with("buy cheap followers"));
+        assert!(shown[2].contains(&format!("action by {DID_A}")));
+        assert!(shown[3].contains("closed by") && shown[3].ends_with("resolved"));
+    })
+    .await
+    .unwrap();
+}
@@ -0,0 +1,89 @@
+//! NAMES paging (`NAMES <channel> LIMIT <n> AFTER <nick>`) and chunked
+//! 353 replies.
+
+use freeq_server::testing::{LineClient, TestServer};
+
+fn register(c: &mut LineClient, nick: &str) -> String {
+    c.tx(&format!("NICK {nick}"));
+    c.tx(&format!("USER {nick} 0 * :{nick}"));
+    c.rx(|l| l.contains(" 001 "), "welcome")
+}
+
+/// The nicks in 353 lines up to the 366, and the lines before it that
+/// weren't 353s.
+fn names(c: &mut LineClient) -> (Vec<String>, Vec<String>, String) {
+    let mut nicks = Vec::new();
+    let mut other = Vec::new();
+    loop {
+        let l = c.rx(
+            |l| l.contains(" 353 ") || l.contains(" 366 ") || l.contains("NAMES"),
+            "NAMES",
+        );
+        if l.contains(" 366 ") {
+            return (nicks, other, l);
+        }
+        if l.contains(" 353 ") {
+            let list = l.splitn(6, ' ').nth(5).unwrap().trim_start_matches(':');
+            nicks.extend(
+                list.split(' ')
+                    .map(|n| n.trim_start_matches(['@', '+']).to_string()),
+            );
+        } else {
+            other.push(l);
+        }
+    }
+}
+
+#[tokio::test]
+async fn names_pages_through_a_channel() {
+    let server = TestServer::start("test-names").await.unwrap();
+    let addr = server.irc_addr;
+
+    tokio::task::spawn_blocking(move || {
+        let mut clients: Vec<LineClient> = (0..12)
+            .map(|i| {
+                let mut c = LineClient::connect(addr);
+                register(&mut c, &format!("member{i:02}"));
+                c.tx("JOIN #big");
+                c.rx(|l| l.contains(" 366 "), "join NAMES");
+                c
+            })
+            .collect();
+        let viewer = &mut clients[0];
+
+        // Without LIMIT, the whole list.
+        viewer.tx("NAMES #big");
+        let (all, _, end) = names(viewer);
+        assert_eq!(all.len(), 12, "{all:?}");
+        assert!(end.ends_with(":End of /NAMES list"), "{end}");
+
+        // Pages come in casefolded nick order, each pointing at the next.
+        viewer.tx("NAMES #big LIMIT 5");
+        let (page, notes, _) = names(viewer);
+        assert_eq!(
+            page,
+            ["member00", "member01", "member02", "member03", "member04"]
+        );
+        assert_eq!(notes, [":test-names NOTE NAMES MORE #big member04 :7 more"]);
+
+        viewer.tx("NAMES #big limit 5 after MEMBER04");
+        let (page, notes, _) = names(viewer);
+        assert_eq!(page.first().map(String::as_str), Some("member05"));
+        assert_eq!(notes, [":test-names NOTE NAMES MORE #big member09 :2 more"]);
+
+        viewer.tx("NAMES #big LIMIT 5 AFTER member09");
+        let (page, notes, _) = names(viewer);
+        assert_eq!(page, ["member10", "member11"]);
+        assert!(notes.is_empty(), "{notes:?}");
+
+        // RFC 1459's server parameter is still ignored.
+        viewer.tx("NAMES #big test-names");
+        assert_eq!(names(viewer).0.len(), 12);
+
+        viewer.tx("NAMES #big LIMIT 0");
+        let fail = viewer.rx(|l| l.contains("FAIL NAMES"), "FAIL");
+        assert!(fail.contains("FAIL NAMES INVALID_PARAMS #big :"), "{fail}");
+    })
+    .await
+    .unwrap();
+}
@@ -94,6 +94,7 @@ async fn start_server_with_malicious_pds(
     state.web_sessions.lock().insert(
         (VICTIM_DID.to_string(), OauthPurpose::Login),
         WebSession {
+            id: "sess1".into(),
             did: VICTIM_DID.to_string(),
             handle: "victim.example".into(),
             pds_url: pds_url.to_string(),
@@ -101,6 +102,7 @@ async fn start_server_with_malicious_pds(
             dpop_key_b64: DpopKey::generate().to_base64url(),
             dpop_nonce: None,
             created_at: Instant::now(),
+            last_used: Instant::now(),
             granted_scope: "atproto".into(),
         },
     );
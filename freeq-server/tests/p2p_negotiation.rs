@@ -0,0 +1,120 @@
+//! Direct DM transport negotiation (`freeq.at/p2p-dm`): the server relays
+//! p2p offers and answers between authenticated DM peers, stamped with
+//! the sender's DID, and refuses the ones it can't vouch for.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use freeq_sdk::crypto::PrivateKey;
+use freeq_sdk::did::{self, DidResolver};
+use freeq_server::testing::LineClient;
+
+const DID_A: &str = "did:plc:p2p_alice";
+const DID_B: &str = "did:plc:p2p_bob";
+
+fn tagmsg_fail(c: &mut LineClient, code: &str) -> String {
+    c.rx(|l| l.contains(&format!("FAIL TAGMSG {code}")), code)
+}
+
+async fn start(keys: [(&str, &PrivateKey); 2]) -> SocketAddr {
+    let mut docs = HashMap::new();
+    for (d, k) in keys {
+        docs.insert(
+            d.to_string(),
+            did::make_test_did_document(d, &k.public_key_multibase()),
+        );
+    }
+    let config = freeq_server::config::ServerConfig {
+        listen_addr: "127.0.0.1:0".to_string(),
+        server_name: "test-p2p".to_string(),
+        challenge_timeout_secs: 60,
+        ..Default::default()
+    };
+    let server = freeq_server::server::Server::with_resolver(config, DidResolver::static_map(docs));
+    let (addr, _web, _h, _state) = server.start_with_web_state().await.unwrap();
+    addr
+}
+
+const CAPS: &str = "message-tags freeq.at/p2p-dm";
+
+#[tokio::test]
+async fn offer_and_answer_are_relayed_with_the_sender_did() {
+    let key_a = PrivateKey::generate_ed25519();
+    let key_b = PrivateKey::generate_ed25519();
+    let addr = start([(DID_A, &key_a), (DID_B, &key_b)]).await;
+    let ep_a = "a1".repeat(32);
+    let ep_b = "b2".repeat(32);
+
+    tokio::task::spawn_blocking(move || {
+        let mut alice = LineClient::with_sasl_caps(addr, "alice", DID_A, key_a, CAPS);
+        let mut bob = LineClient::with_sasl_caps(addr, "bob", DID_B, key_b, CAPS);
+
+        // A forged DID tag is replaced by the server's.
+        alice.tx(&format!(
+            "@+freeq.at/p2p-offer={ep_a};freeq.at/p2p-did=did:plc:forged TAGMSG bob"
+        ));
+        let offer = bob.rx(|l| l.contains("p2p-offer"), "offer");
+        assert!(
+            offer.contains(&format!("+freeq.at/p2p-offer={ep_a}")),
+            "{offer}"
+        );
+        assert!(
+            offer.contains(&format!("freeq.at/p2p-did={DID_A}")),
+            "{offer}"
+        );
+        assert!(!offer.contains("forged"), "{offer}");
+
+        bob.tx(&format!("@+freeq.at/p2p-answer={ep_b} TAGMSG alice"));
+        let answer = alice.rx(|l| l.contains("p2p-answer"), "answer");
+        assert!(
+            answer.contains(&format!("+freeq.at/p2p-answer={ep_b}")),
+            "{answer}"
+        );
+        assert!(
+            answer.contains(&format!("freeq.at/p2p-did={DID_B}")),
+            "{answer}"
+        );
+
+        alice.tx("@+freeq.at/p2p-close=done TAGMSG bob");
+        let close = bob.rx(|l| l.contains("p2p-close"), "close");
+        assert!(!close.contains("p2p-did"), "{close}");
+    })
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn negotiation_the_server_cannot_vouch_for_is_refused() {
+    let key_a = PrivateKey::generate_ed25519();
+    let key_b = PrivateKey::generate_ed25519();
+    let addr = start([(DID_A, &key_a), (DID_B, &key_b)]).await;
+    let ep = "c3".repeat(32);
+
+    tokio::task::spawn_blocking(move || {
+        let mut alice = LineClient::with_sasl_caps(addr, "alice", DID_A, key_a, CAPS);
+        let mut plain = LineClient::with_sasl_caps(addr, "bob", DID_B, key_b, "message-tags");
+        let mut guest = LineClient::guest_with_caps(addr, "guest", CAPS);
+
+        alice.tx(&format!("@+freeq.at/p2p-offer={ep} TAGMSG #room"));
+        tagmsg_fail(&mut alice, "P2P_DM_ONLY");
+        alice.tx("@+freeq.at/p2p-offer=not-an-endpoint TAGMSG bob");
+        tagmsg_fail(&mut alice, "P2P_INVALID_ENDPOINT");
+        // Bob is authenticated but didn't negotiate the cap; the guest did
+        // but isn't authenticated.
+        alice.tx(&format!("@+freeq.at/p2p-offer={ep} TAGMSG bob"));
+        let fail = tagmsg_fail(&mut alice, "P2P_UNAVAILABLE");
+        assert!(fail.contains(" bob "), "names the target: {fail}");
+        alice.tx(&format!("@+freeq.at/p2p-offer={ep} TAGMSG guest"));
+        tagmsg_fail(&mut alice, "P2P_UNAVAILABLE");
+
+        guest.tx(&format!("@+freeq.at/p2p-offer={ep} TAGMSG alice"));
+        tagmsg_fail(&mut guest, "P2P_AUTH_REQUIRED");
+
+        // Nothing got through to Bob.
+        plain.tx("PING :sync");
+        let next = plain.rx(|l| l.contains("p2p") || l.contains("PONG"), "PONG");
+        assert!(next.contains("PONG"), "{next}");
+    })
+    .await
+    .unwrap();
+}
@@ -0,0 +1,157 @@
+//! `POLICY <channel> TEST <did>` and its HTTP twin: a dry-run join
+//! evaluation for channel admins that records nothing.
+
+use freeq_server::testing::{LineClient, TestServer};
+
+const SUBJECT: &str = "did:plc:subject";
+
+/// Run a policy test and collect its NOTICE lines up to the result.
+fn test(c: &mut LineClient, channel: &str, did: &str) -> Vec<String> {
+    c.tx(&format!("POLICY {channel} TEST {did}"));
+    c.rx(|l| l.contains("Policy test for"), "test header");
+    let mut lines = Vec::new();
+    loop {
+        let l = c.rx(|l| l.contains(" NOTICE "), "test line");
+        let done = l.contains("Result: ");
+        lines.push(l);
+        if done {
+            return lines;
+        }
+    }
+}
+
+#[tokio::test]
+async fn policy_test_traces_a_join_without_recording_it() {
+    let server = TestServer::start("test-policy-dry-run").await.unwrap();
+    let addr = server.irc_addr;
+    let engine = server.state.policy_engine.clone().unwrap();
+
+    let mut op = tokio::task::spawn_blocking(move || {
+        let mut op = LineClient::guest(addr, "op");
+        op.tx("JOIN #gated");
+        op.rx(|l| l.contains(" 366 "), "joined");
+        op.tx("POLICY #gated SET Be excellent to each other");
+        op.rx(|l| l.contains("Policy set for #gated"), "policy set");
+        op.tx("POLICY #gated REQUIRE email issuer=did:web:mail.example url=/verify");
+        op.rx(|l| l.contains("Credential endpoint 'email'"), "required");
+
+        let lines = test(&mut op, "#gated", SUBJECT);
+        let text = lines.join("\n");
+        assert!(text.contains("Credentials: none"), "{text}");
+        assert!(text.contains("[pass] ACCEPT("), "{text}");
+        assert!(
+            text.contains("[FAIL] PRESENT(email, issuer=did:web:mail.example)"),
+            "{text}"
+        );
+        assert!(text.contains("Result: would be refused"), "{text}");
+
+        op.tx("POLICY #gated TEST not-a-did");
+        op.rx(
+            |l| l.contains("Usage: POLICY <channel> TEST <did>"),
+            "usage",
+        );
+        op
+    })
+    .await
+    .unwrap();
+
+    engine
+        .store_credential(
+            SUBJECT,
+            "email",
+            "did:web:mail.example",
+            &serde_json::json!({}),
+        )
+        .unwrap();
+
+    tokio::task::spawn_blocking(move || {
+        let text = test(&mut op, "#gated", SUBJECT).join("\n");
+        assert!(
+            text.contains("Credential: email from did:web:mail.example"),
+            "{text}"
+        );
+        assert!(text.contains("[pass] PRESENT(email"), "{text}");
+        assert!(text.contains("Result: would join as member"), "{text}");
+
+        // Only ops may run it.
+        let mut other = LineClient::guest(addr, "other");
+        other.tx(&format!("POLICY #gated TEST {SUBJECT}"));
+        other.rx(|l| l.contains(" 482 "), "not op");
+    })
+    .await
+    .unwrap();
+
+    assert!(
+        engine
+            .check_membership("#gated", SUBJECT)
+            .unwrap()
+            .is_none(),
+        "a dry run must not attest"
+    );
+}
+
+#[tokio::test]
+async fn policy_test_over_http_needs_the_founder_or_a_did_op() {
+    const FOUNDER: &str = "did:plc:founder";
+
+    let server = TestServer::start("test-policy-dry-run-http").await.unwrap();
+    let state = server.state.clone();
+    let engine = state.policy_engine.clone().unwrap();
+    engine
+        .create_channel_policy(
+            "#gated",
+            Some(FOUNDER),
+            freeq_server::policy::Requirement::Prove {
+                proof_type: "kyc".into(),
+            },
+            Default::default(),
+        )
+        .unwrap();
+    state.channels.insert(
+        "#gated".to_string(),
+        freeq_server::server::ChannelState {
+            founder_did: Some(FOUNDER.to_string()),
+            ..Default::default()
+        },
+    );
+    for (sid, did) in [("founder-sid", FOUNDER), ("other-sid", "did:plc:other")] {
+        state
+            .session_dids
+            .lock()
+            .insert(sid.to_string(), did.to_string());
+    }
+
+    let url = format!(
+        "http://{}/api/v1/policy/gated/test/{SUBJECT}",
+        server.web_addr
+    );
+    let client = reqwest::Client::new();
+    let resp = client.get(&url).send().await.unwrap();
+    assert_eq!(resp.status(), 401);
+    let resp = client
+        .get(&url)
+        .bearer_auth("other-sid")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 403);
+
+    let resp = client
+        .get(&url)
+        .bearer_auth("founder-sid")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let trace: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(trace["would_join"], false);
+    assert_eq!(trace["refusal"], "Missing proof: kyc");
+    assert_eq!(trace["requirements"]["requirement"], "PROVE(kyc)");
+    assert_eq!(trace["requirements"]["satisfied"], false);
+    assert!(
+        engine
+            .check_membership("#gated", SUBJECT)
+            .unwrap()
+            .is_none()
+    );
+}
@@ -0,0 +1,142 @@
+//! PREF: per-DID preferences, typed and quota'd, readable and writable
+//! over IRC and HTTP, with changes pushed to the DID's other sessions.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use freeq_sdk::crypto::PrivateKey;
+use freeq_sdk::did::{self, DidResolver};
+use freeq_server::testing::{self, LineClient, TestServer};
+use serde_json::json;
+
+const DID_ALICE: &str = "did:plc:prefs_alice";
+
+fn alice_key() -> PrivateKey {
+    PrivateKey::ed25519_from_bytes(&[9; 32]).unwrap()
+}
+
+/// Sign in as `did`; also returns the session's API bearer.
+fn with_sasl(addr: SocketAddr, nick: &str, did: &str, key: PrivateKey) -> (LineClient, String) {
+    let mut c = LineClient::connect(addr);
+    c.tx("CAP LS 302");
+    c.tx(&format!("NICK {nick}"));
+    c.tx(&format!("USER {nick} 0 * :test"));
+    c.tx("CAP REQ :sasl");
+    c.rx(|l| l.contains("ACK"), "ACK");
+    c.authenticate(did, key);
+    let notice = c.rx(|l| l.contains("API-BEARER"), "bearer");
+    let bearer = notice.split_whitespace().last().unwrap().to_string();
+    c.tx("CAP END");
+    c.num("001");
+    (c, bearer)
+}
+
+#[tokio::test]
+async fn preferences_follow_the_did_across_sessions_and_http() {
+    let mut docs = HashMap::new();
+    docs.insert(
+        DID_ALICE.to_string(),
+        did::make_test_did_document(DID_ALICE, &alice_key().public_key_multibase()),
+    );
+    let server =
+        TestServer::start_with(testing::config("test-prefs"), DidResolver::static_map(docs))
+            .await
+            .unwrap();
+    let addr = server.irc_addr;
+
+    let (mut phone, mut laptop, bearer) = tokio::task::spawn_blocking(move || {
+        let mut guest = LineClient::guest(addr, "guest");
+        guest.tx("PREF LIST");
+        guest.rx(|l| l.contains("FAIL PREF ACCOUNT_REQUIRED"), "guests can't");
+
+        let (mut phone, bearer) = with_sasl(addr, "alice", DID_ALICE, alice_key());
+        let (mut laptop, _) = with_sasl(addr, "alice", DID_ALICE, alice_key());
+
+        // Typed, normalized, and pushed to the other device
+        phone.tx("PREF SET muted #Dev, #ops #dev");
+        phone.rx(|l| l.ends_with(" PREF muted :#dev #ops"), "set");
+        laptop.rx(|l| l.ends_with(" PREF muted :#dev #ops"), "pushed");
+        phone.tx("PREF SET show-joins maybe");
+        phone.rx(
+            |l| l.contains("FAIL PREF INVALID_VALUE show-joins"),
+            "bool only",
+        );
+        phone.tx("PREF SET colour red");
+        phone.rx(
+            |l| l.contains("FAIL PREF UNKNOWN_KEY colour"),
+            "unknown key",
+        );
+        phone.tx("PREF GET");
+        phone.rx(|l| l.contains(" 461 "), "needs a key");
+
+        laptop.tx("PREF GET show-joins");
+        laptop.rx(|l| l.ends_with(" PREF show-joins on"), "default");
+        laptop.tx("PREF");
+        laptop.rx(|l| l.ends_with(" PREF muted :#dev #ops"), "listed");
+        laptop.rx(|l| l.ends_with(" PREF * END"), "end of list");
+        (phone, laptop, bearer)
+    })
+    .await
+    .unwrap();
+
+    let http = reqwest::Client::new();
+    let url = format!("{}/api/v1/me/prefs", server.web_url());
+    let unauthenticated = http.get(&url).send().await.unwrap();
+    assert_eq!(unauthenticated.status(), 401);
+
+    let body: serde_json::Value = http
+        .get(&url)
+        .bearer_auth(&bearer)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["prefs"]["muted"], "#dev #ops");
+    assert_eq!(body["prefs"]["show-joins"], "on");
+    assert_eq!(body["used_bytes"], 9);
+    assert!(
+        body["keys"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|k| k["name"] == "ignore" && k["type"] == "masks")
+    );
+
+    let resp = http
+        .put(format!("{url}/show-joins"))
+        .bearer_auth(&bearer)
+        .json(&json!({ "value": "no" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let too_big = (0..90).map(|i| format!("{i:0>99} ")).collect::<String>();
+    let resp = http
+        .put(format!("{url}/highlights"))
+        .bearer_auth(&bearer)
+        .json(&json!({ "value": too_big }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 413);
+    let resp = http
+        .delete(format!("{url}/muted"))
+        .bearer_auth(&bearer)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+
+    tokio::task::spawn_blocking(move || {
+        for device in [&mut phone, &mut laptop] {
+            device.rx(|l| l.ends_with(" PREF show-joins off"), "HTTP change");
+            device.rx(|l| l.ends_with(" PREF muted :"), "HTTP reset");
+        }
+        phone.tx("PREF GET muted");
+        phone.rx(|l| l.ends_with(" PREF muted :"), "reset");
+    })
+    .await
+    .unwrap();
+}
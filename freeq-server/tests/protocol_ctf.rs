@@ -287,10 +287,9 @@ async fn ctf_21_plus_e_channel_rejects_plaintext_with_encrypted_tag() {
 
     // Plant an encrypted-only channel (+E) with one member.
     {
-        let mut channels = state.channels.lock();
         let mut members = HashSet::new();
         members.insert("ctf-fixture-session".to_string());
-        channels.insert(
+        state.channels.insert(
             "#secret".to_string(),
             ChannelState {
                 members,
@@ -298,6 +297,8 @@ async fn ctf_21_plus_e_channel_rejects_plaintext_with_encrypted_tag() {
                 ops: HashSet::new(),
                 halfops: HashSet::new(),
                 voiced: HashSet::new(),
+                founders: HashSet::new(),
+                admins: HashSet::new(),
                 founder_did: None,
                 did_ops: HashSet::new(),
                 created_at: 0,
@@ -305,12 +306,25 @@ async fn ctf_21_plus_e_channel_rejects_plaintext_with_encrypted_tag() {
                 invite_only: false,
                 invites: HashSet::new(),
                 invite_exceptions: vec![],
+                quiets: vec![],
+                bots_restricted: false,
+                bot_allowlist: vec![],
                 history: std::collections::VecDeque::new(),
                 topic: None,
+                topic_history: std::collections::VecDeque::new(),
                 topic_locked: false,
                 no_ext_msg: false,
                 moderated: false,
                 encrypted_only: true,
+                archived: false,
+                auditorium: false,
+                no_stats: false,
+                secret: false,
+                temporary: None,
+                empty_since: None,
+                expiry_warned: false,
+                history_visibility: Default::default(),
+                member_since: HashMap::new(),
                 key: None,
                 pins: vec![],
             },
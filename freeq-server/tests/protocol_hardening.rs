@@ -980,3 +980,53 @@ async fn nick_change_multi_channel_broadcast() {
     })
     .await;
 }
+
+// ══════════════════════════════════════════════════════════════════════
+// 26. Malformed lines are quarantined, not misparsed
+// ══════════════════════════════════════════════════════════════════════
+
+#[tokio::test]
+async fn malformed_lines_quarantined() {
+    run(|addr| {
+        let mut a = C::new(addr, "quar_a");
+        a.reg();
+        a.drain();
+        let mut b = C::new(addr, "quar_b");
+        b.reg();
+        b.drain();
+        b.tx("JOIN #quar");
+        b.num("366");
+        b.drain();
+
+        // Each of these would once have been acted on as a JOIN or a
+        // PRIVMSG; now none of them reaches a handler.
+        a.tx("@bad_key=1 JOIN #quar");
+        a.tx("@ JOIN #quar");
+        a.tx(": JOIN #quar");
+        a.tx("JO1N #quar");
+        a.tx("PRIVMSG quar_b :nul\0byte");
+        a.writer
+            .write_all(b"PRIVMSG quar_b :bad \xff utf8\r\n")
+            .unwrap();
+        a.writer.flush().ok();
+        let leaked = b.maybe(
+            |l| (l.contains("JOIN") && l.contains("quar_a")) || l.contains("PRIVMSG"),
+            500,
+        );
+        assert!(leaked.is_none(), "malformed line was acted on: {leaked:?}");
+
+        // A multi-byte character split across two reads still decodes.
+        a.writer.write_all(b"PRIVMSG quar_b :caf\xc3").unwrap();
+        a.writer.flush().ok();
+        std::thread::sleep(Duration::from_millis(100));
+        a.writer.write_all(b"\xa9\r\n").unwrap();
+        a.writer.flush().ok();
+        let msg = b.rx(|l| l.contains("PRIVMSG quar_b"), "split utf-8");
+        assert!(msg.ends_with(" café"), "{msg}");
+
+        // The session is still healthy.
+        a.tx("JOIN #quar");
+        a.num("366");
+    })
+    .await;
+}
@@ -0,0 +1,65 @@
+//! Orchestrator probes: `/healthz` (liveness) and `/readyz` (readiness
+//! with per-dependency detail).
+
+use std::sync::atomic::Ordering;
+
+use freeq_server::testing::{self, TestServer};
+
+async fn get(server: &TestServer, path: &str) -> (u16, serde_json::Value) {
+    let resp = reqwest::get(format!("{}{path}", server.web_url()))
+        .await
+        .unwrap();
+    let status = resp.status().as_u16();
+    (status, resp.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn readyz_reports_each_dependency() {
+    let server = TestServer::start("test-ready").await.unwrap();
+
+    let (status, body) = get(&server, "/healthz").await;
+    assert_eq!(status, 200);
+    assert_eq!(body["status"], "ok");
+
+    let (status, body) = get(&server, "/readyz").await;
+    assert_eq!(status, 200, "{body}");
+    assert_eq!(body["status"], "ready");
+    let checks = &body["checks"];
+    assert_eq!(checks["listeners"]["ok"], true);
+    assert_eq!(checks["data_dir"]["ok"], true);
+    assert_eq!(checks["history_db"]["ok"], true);
+    assert_eq!(checks["history_db"]["enabled"], true);
+    assert_eq!(checks["s2s"]["enabled"], false);
+
+    // A server that has stopped accepting isn't ready, but is still alive.
+    server.state.listening.store(false, Ordering::Relaxed);
+    let (status, body) = get(&server, "/readyz").await;
+    assert_eq!(status, 503);
+    assert_eq!(body["status"], "not_ready");
+    assert_eq!(body["checks"]["listeners"]["ok"], false);
+    assert_eq!(get(&server, "/healthz").await.0, 200);
+    server.state.listening.store(true, Ordering::Relaxed);
+
+    // Nor is one that can't write its data directory.
+    std::fs::remove_dir_all(server.data_dir()).unwrap();
+    let (status, body) = get(&server, "/readyz").await;
+    assert_eq!(status, 503);
+    assert_eq!(body["checks"]["data_dir"]["ok"], false);
+    assert!(body["checks"]["data_dir"]["error"].is_string());
+}
+
+#[tokio::test]
+async fn readyz_reports_s2s_peers_without_failing() {
+    let (a, _b, link) = testing::pair().await.unwrap();
+
+    let (status, body) = get(&a, "/readyz").await;
+    assert_eq!(status, 200, "{body}");
+    assert_eq!(body["checks"]["s2s"]["enabled"], true);
+    assert_eq!(body["checks"]["s2s"]["connected"], 1);
+
+    // Losing every peer is reported; the server still serves its clients.
+    link.cut().await;
+    let (status, body) = get(&a, "/readyz").await;
+    assert_eq!(status, 200);
+    assert_eq!(body["checks"]["s2s"]["connected"], 0);
+}
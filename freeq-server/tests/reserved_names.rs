@@ -0,0 +1,83 @@
+//! Reserved nick and channel namespaces: guests can't take `admin` or open
+//! `#freeq-*`; exempt DIDs can, and others may join what they opened.
+
+use std::collections::HashMap;
+
+use freeq_sdk::crypto::PrivateKey;
+use freeq_sdk::did::{self, DidResolver};
+use freeq_server::testing::{self, LineClient, TestServer};
+
+const DID_STAFF: &str = "did:plc:reserved_staff";
+const DID_OTHER: &str = "did:plc:reserved_other";
+
+fn key(seed: u8) -> PrivateKey {
+    PrivateKey::ed25519_from_bytes(&[seed; 32]).unwrap()
+}
+
+#[tokio::test]
+async fn reserved_names_need_an_exempt_identity() {
+    let mut docs = HashMap::new();
+    for (did, seed) in [(DID_STAFF, 1), (DID_OTHER, 2)] {
+        docs.insert(
+            did.to_string(),
+            did::make_test_did_document(did, &key(seed).public_key_multibase()),
+        );
+    }
+    let mut config = testing::config("test-reserved");
+    config.reserved_nicks = vec!["admin".into(), "*serv".into()];
+    config.reserved_channels = vec!["#freeq-*".into()];
+    config.reserved_exempt_dids = vec![DID_STAFF.into()];
+    let server = TestServer::start_with(config, DidResolver::static_map(docs))
+        .await
+        .unwrap();
+    let addr = server.irc_addr;
+
+    tokio::task::spawn_blocking(move || {
+        // A plain guest is refused outright, and can pick another nick.
+        let mut guest = LineClient::connect(addr);
+        guest.tx("NICK Admin");
+        guest.rx(
+            |l| l.contains(" 432 ") && l.contains("Nickname is reserved"),
+            "432",
+        );
+        guest.tx("NICK guest");
+        guest.tx("USER guest 0 * :test");
+        guest.rx(|l| l.contains(" 001 "), "welcome");
+        guest.tx("NICK ChanServ");
+        guest.rx(|l| l.contains(" 432 ") && l.contains("ChanServ"), "glob");
+        guest.tx("JOIN #freeq-staff");
+        guest.rx(
+            |l| l.contains(" 479 ") && l.contains("Channel name is reserved"),
+            "479",
+        );
+
+        // Signed in, but not exempt: the nick stashed during CAP is
+        // swapped for a Guest nick at registration.
+        let _other = LineClient::with_sasl(addr, "admin", DID_OTHER, key(2));
+        guest.tx("WHOIS admin");
+        guest.rx(|l| l.contains(" 401 "), "nobody is admin");
+        // Nor is a guest who claimed one before CAP END.
+        let mut renamed = LineClient::connect(addr);
+        renamed.tx("CAP LS 302");
+        renamed.tx("NICK nickserv");
+        renamed.tx("USER x 0 * :test");
+        renamed.tx("CAP END");
+        renamed.rx(|l| l.contains("Nick nickserv is reserved"), "renamed");
+        let welcome = renamed.rx(|l| l.contains(" 001 "), "welcome");
+        assert!(welcome.contains(" 001 Guest"), "{welcome}");
+
+        // Exempt: keeps the nick and opens the channel.
+        let mut staff = LineClient::with_sasl(addr, "admin", DID_STAFF, key(1));
+        staff.tx("JOIN #freeq-staff");
+        staff.rx(|l| l.contains(" 366 admin #freeq-staff "), "staff joined");
+
+        // Once open, the channel is joinable like any other.
+        guest.tx("JOIN #freeq-staff");
+        guest.rx(
+            |l| l.contains(" 366 ") && l.contains("#freeq-staff"),
+            "joined",
+        );
+    })
+    .await
+    .unwrap();
+}
@@ -0,0 +1,170 @@
+//! Netsplit and partition tests driven by S2S fault injection. Needs the
+//! test-only feature:
+//!
+//! ```sh
+//! cargo test -p freeq-server --features s2s-faults --test s2s_faults
+//! ```
+#![cfg(feature = "s2s-faults")]
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use freeq_sdk::client::{self, ClientHandle, ConnectConfig};
+use freeq_sdk::did::DidResolver;
+use freeq_sdk::event::Event;
+use freeq_server::s2s_faults;
+use freeq_server::testing::{self, TestServer};
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Long enough for an unfaulted link to deliver a JOIN.
+const SETTLE: Duration = Duration::from_millis(300);
+
+async fn connect(server: &TestServer, nick: &str) -> (ClientHandle, mpsc::Receiver<Event>) {
+    let config = ConnectConfig {
+        server_addr: server.irc_addr_string(),
+        nick: nick.to_string(),
+        user: nick.to_string(),
+        realname: nick.to_string(),
+        ..Default::default()
+    };
+    let (handle, mut events) = client::connect(config, None);
+    wait_for(
+        &mut events,
+        |e| matches!(e, Event::Registered { .. }),
+        "registered",
+    )
+    .await;
+    (handle, events)
+}
+
+async fn wait_for(
+    events: &mut mpsc::Receiver<Event>,
+    predicate: impl Fn(&Event) -> bool,
+    desc: &str,
+) -> Event {
+    timeout(TIMEOUT, async {
+        loop {
+            match events.recv().await {
+                Some(e) if predicate(&e) => return e,
+                Some(_) => continue,
+                None => panic!("event stream closed waiting for: {desc}"),
+            }
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("timed out waiting for: {desc}"))
+}
+
+async fn eventually(desc: &str, check: impl Fn() -> bool) {
+    timeout(TIMEOUT, async {
+        while !check() {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("timed out waiting for: {desc}"));
+}
+
+fn has_remote_member(server: &TestServer, channel: &str, nick: &str) -> bool {
+    server
+        .state
+        .channels
+        .get(channel)
+        .is_some_and(|ch| ch.remote_members.contains_key(nick))
+}
+
+async fn join(handle: &ClientHandle, events: &mut mpsc::Receiver<Event>, channel: &str) {
+    handle.join(channel).await.unwrap();
+    wait_for(
+        events,
+        |e| matches!(e, Event::Joined { channel: c, .. } if c == channel),
+        "joined",
+    )
+    .await;
+}
+
+async fn peer_count(server: &TestServer) -> usize {
+    server.s2s().unwrap().peers.lock().await.len()
+}
+
+#[tokio::test]
+async fn paused_link_delivers_on_resume() {
+    let (a, b, _link) = testing::pair().await.unwrap();
+    let (a_id, b_id) = (a.server_id().unwrap(), b.server_id().unwrap());
+    let (alice, mut alice_events) = connect(&a, "alice").await;
+
+    s2s_faults::update(&a_id, &b_id, |f| f.paused = true);
+    join(&alice, &mut alice_events, "#held").await;
+    tokio::time::sleep(SETTLE).await;
+    assert!(!has_remote_member(&b, "#held", "alice"));
+
+    s2s_faults::update(&a_id, &b_id, |f| f.paused = false);
+    eventually("b sees alice once the link resumes", || {
+        has_remote_member(&b, "#held", "alice")
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn partition_drops_traffic_until_healed() {
+    let (a, b, link) = testing::pair().await.unwrap();
+    let (a_id, b_id) = (a.server_id().unwrap(), b.server_id().unwrap());
+    let (alice, mut alice_events) = connect(&a, "alice").await;
+
+    s2s_faults::partition(&a_id, &b_id);
+    join(&alice, &mut alice_events, "#lost").await;
+    tokio::time::sleep(SETTLE).await;
+    assert!(!has_remote_member(&b, "#lost", "alice"));
+    assert!(s2s_faults::stats(&a_id, &b_id).dropped > 0);
+    // A silent partition: neither side has noticed.
+    assert!(link.is_established().await);
+
+    s2s_faults::heal(&a_id, &b_id);
+    join(&alice, &mut alice_events, "#found").await;
+    eventually("traffic flows again after heal", || {
+        has_remote_member(&b, "#found", "alice")
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn squit_drops_the_link_on_both_sides() {
+    let mut config = testing::config("a.test");
+    config.oper_password = Some("hunter2".to_string());
+    let mut a = TestServer::start_with(config, DidResolver::static_map(HashMap::new()))
+        .await
+        .unwrap();
+    a.enable_s2s().await;
+    let b = TestServer::start_federated("b.test").await.unwrap();
+    let _link = testing::link(&a, &b).await.unwrap();
+
+    let (admin, mut admin_events) = connect(&a, "admin").await;
+    admin.raw("SQUIT b.test :not yet").await.unwrap();
+    wait_for(
+        &mut admin_events,
+        |e| matches!(e, Event::ServerNotice { text } if text.contains("Permission Denied")),
+        "permission denied",
+    )
+    .await;
+    assert_eq!(peer_count(&a).await, 1);
+
+    admin.raw("OPER admin hunter2").await.unwrap();
+    admin.raw("SQUIT b.test :maintenance").await.unwrap();
+    wait_for(
+        &mut admin_events,
+        |e| matches!(e, Event::ServerNotice { text } if text.contains("dropped: maintenance")),
+        "SQUIT confirmation",
+    )
+    .await;
+
+    timeout(TIMEOUT, async {
+        while peer_count(&a).await + peer_count(&b).await > 0 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("both sides forget the link");
+}
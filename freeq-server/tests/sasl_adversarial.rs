@@ -15,6 +15,7 @@ use freeq_sdk::client::{self, ConnectConfig};
 use freeq_sdk::crypto::PrivateKey;
 use freeq_sdk::did::{self, DidResolver};
 use freeq_sdk::event::Event;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
 
 const DID_A: &str = "did:plc:sasl_test_alice";
 const DID_B: &str = "did:plc:sasl_test_bob";
@@ -159,6 +160,96 @@ impl C {
     }
 }
 
+/// Start a server with a TLS listener on a throwaway self-signed
+/// certificate. Returns the TLS address, the directory holding the
+/// certificate, which must outlive the test, and a client config
+/// trusting it.
+async fn start_tls(resolver: DidResolver) -> (SocketAddr, tempfile::TempDir, TlsConfig) {
+    let _ = tokio_rustls::rustls::crypto::ring::default_provider().install_default();
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let cert_path = dir.path().join("cert.pem");
+    let key_path = dir.path().join("key.pem");
+    std::fs::write(&cert_path, cert.cert.pem()).unwrap();
+    std::fs::write(&key_path, cert.key_pair.serialize_pem()).unwrap();
+    let config = freeq_server::config::ServerConfig {
+        listen_addr: "127.0.0.1:0".to_string(),
+        tls_listen_addr: "127.0.0.1:0".to_string(),
+        tls_cert: Some(cert_path.to_str().unwrap().to_string()),
+        tls_key: Some(key_path.to_str().unwrap().to_string()),
+        server_name: "test-sasl-tls".to_string(),
+        challenge_timeout_secs: 60,
+        ..Default::default()
+    };
+    let (_addr, tls_addr, _h) = freeq_server::server::Server::with_resolver(config, resolver)
+        .start_tls()
+        .await
+        .unwrap();
+    let mut roots = tokio_rustls::rustls::RootCertStore::empty();
+    roots.add(cert.cert.der().clone()).unwrap();
+    let client = tokio_rustls::rustls::ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    (tls_addr, dir, Arc::new(client))
+}
+
+type TlsConfig = Arc<tokio_rustls::rustls::ClientConfig>;
+type ClientTls = tokio_rustls::client::TlsStream<tokio::net::TcpStream>;
+
+/// A raw line connection over TLS, and its channel binding as the client
+/// sees it.
+struct TlsLines {
+    lines: tokio::io::Lines<tokio::io::BufReader<tokio::io::ReadHalf<ClientTls>>>,
+    writer: tokio::io::WriteHalf<ClientTls>,
+    binding: String,
+}
+
+impl TlsLines {
+    async fn connect(addr: SocketAddr, config: &TlsConfig) -> Self {
+        let tcp = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let name = tokio_rustls::rustls::pki_types::ServerName::try_from("localhost").unwrap();
+        let tls = tokio_rustls::TlsConnector::from(Arc::clone(config))
+            .connect(name, tcp)
+            .await
+            .unwrap();
+        let binding = freeq_sdk::tls::channel_binding(&tls).unwrap();
+        let (reader, writer) = tokio::io::split(tls);
+        Self {
+            lines: tokio::io::BufReader::new(reader).lines(),
+            writer,
+            binding,
+        }
+    }
+    async fn tx(&mut self, l: &str) {
+        self.writer
+            .write_all(format!("{l}\r\n").as_bytes())
+            .await
+            .unwrap();
+    }
+    async fn rx(&mut self, p: impl Fn(&str) -> bool, d: &str) -> String {
+        let lines = &mut self.lines;
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let l = lines.next_line().await.unwrap();
+                let l = l.unwrap_or_else(|| panic!("EOF: {d}"));
+                if p(&l) {
+                    return l;
+                }
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("Timeout: {d}"))
+    }
+    /// Ask for a challenge and return its raw bytes.
+    async fn challenge(&mut self) -> Vec<u8> {
+        self.tx("AUTHENTICATE ATPROTO-CHALLENGE").await;
+        let l = self
+            .rx(|l| l.starts_with("AUTHENTICATE "), "challenge")
+            .await;
+        auth::decode_challenge_bytes(l.strip_prefix("AUTHENTICATE ").unwrap()).unwrap()
+    }
+}
+
 async fn run(addr: SocketAddr, f: impl FnOnce(SocketAddr) + Send + 'static) {
     tokio::task::spawn_blocking(move || f(addr)).await.unwrap();
 }
@@ -327,6 +418,218 @@ async fn sasl_three_failures_disconnect() {
     .await;
 }
 
+#[tokio::test]
+async fn sasl_failures_counted_per_source_across_connections() {
+    let config = freeq_server::config::ServerConfig {
+        listen_addr: "127.0.0.1:0".to_string(),
+        server_name: "test-sasl-counters".to_string(),
+        challenge_timeout_secs: 60,
+        ..Default::default()
+    };
+    let server = freeq_server::server::Server::with_resolver(config, make_resolver(vec![]));
+    let (addr, _web, _h, state) = server.start_with_web_state().await.unwrap();
+    run(addr, move |addr| {
+        // Two connections, two failures each: the per-connection limit
+        // never trips, but the per-IP counter sees all four.
+        for n in 0..2 {
+            let mut c = C::raw(addr);
+            c.tx("CAP LS 302");
+            c.tx(&format!("NICK counted{n}"));
+            c.tx(&format!("USER counted{n} 0 * :test"));
+            c.tx("CAP REQ :sasl");
+            c.rx(|l| l.contains("ACK"), "CAP ACK");
+            let bad_response = {
+                use base64::Engine;
+                base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(
+                    serde_json::json!({"did": "did:plc:fake", "signature": "AAAA"})
+                        .to_string()
+                        .as_bytes(),
+                )
+            };
+            for _ in 0..2 {
+                c.tx("AUTHENTICATE ATPROTO-CHALLENGE");
+                c.rx(|l| l.starts_with("AUTHENTICATE "), "challenge");
+                c.tx(&format!("AUTHENTICATE {bad_response}"));
+                c.num("904");
+            }
+        }
+    })
+    .await;
+    assert_eq!(state.auth_failure_count("127.0.0.1"), 4);
+}
+
+#[tokio::test]
+async fn sasl_source_refused_after_failure_limit() {
+    let config = freeq_server::config::ServerConfig {
+        listen_addr: "127.0.0.1:0".to_string(),
+        server_name: "test-sasl-limit".to_string(),
+        challenge_timeout_secs: 60,
+        ..Default::default()
+    };
+    let server = freeq_server::server::Server::with_resolver(config, make_resolver(vec![]));
+    let (addr, _web, _h, state) = server.start_with_web_state().await.unwrap();
+    let limit = freeq_server::server::AUTH_FAILURE_LIMIT;
+    run(addr, move |addr| {
+        let bad_response = {
+            use base64::Engine;
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(
+                serde_json::json!({"did": "did:plc:fake", "signature": "AAAA"})
+                    .to_string()
+                    .as_bytes(),
+            )
+        };
+        // Three failures per connection before it's dropped: 3+3+3+1.
+        let mut failed = 0;
+        let mut n = 0;
+        let mut c = loop {
+            let mut c = C::raw(addr);
+            c.tx("CAP LS 302");
+            c.tx(&format!("NICK limited{n}"));
+            c.tx(&format!("USER limited{n} 0 * :test"));
+            c.tx("CAP REQ :sasl");
+            c.rx(|l| l.contains("ACK"), "CAP ACK");
+            for _ in 0..3 {
+                c.tx("AUTHENTICATE ATPROTO-CHALLENGE");
+                c.rx(|l| l.starts_with("AUTHENTICATE "), "challenge");
+                c.tx(&format!("AUTHENTICATE {bad_response}"));
+                c.num("904");
+                failed += 1;
+                if failed == limit {
+                    break;
+                }
+            }
+            if failed == limit {
+                break c;
+            }
+            n += 1;
+        };
+
+        // The 11th attempt gets no challenge.
+        c.tx("AUTHENTICATE ATPROTO-CHALLENGE");
+        let refused = c.num("904");
+        assert!(refused.contains("Too many failed"), "{refused}");
+
+        // Nor does a new connection from the same address.
+        let mut c = C::raw(addr);
+        let err = c.rx(|l| l.starts_with("ERROR"), "refused connection");
+        assert!(err.contains("Too many failed"), "{err}");
+    })
+    .await;
+    assert_eq!(state.auth_failure_count("127.0.0.1"), limit);
+}
+
+#[tokio::test]
+async fn sasl_challenge_binding_mismatch_rejected() {
+    let config = freeq_server::config::ServerConfig {
+        listen_addr: "127.0.0.1:0".to_string(),
+        server_name: "test-sasl-binding".to_string(),
+        challenge_timeout_secs: 60,
+        ..Default::default()
+    };
+    let server = freeq_server::server::Server::with_resolver(config, make_resolver(vec![]));
+    let (_addr, _web, _h, state) = server.start_with_web_state().await.unwrap();
+
+    let encoded = state.issue_sasl_challenge("sess-bind", Some("iroh:aaaa"));
+    let bytes = auth::decode_challenge_bytes(&encoded).unwrap();
+    let challenge: auth::Challenge = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(challenge.binding.as_deref(), Some("iroh:aaaa"));
+
+    assert_eq!(
+        state
+            .take_sasl_challenge("sess-bind", Some("iroh:bbbb"), Some("iroh:aaaa"))
+            .unwrap_err(),
+        freeq_server::server::ChallengeRejection::BindingMismatch
+    );
+    // The failed take consumed it
+    assert_eq!(
+        state
+            .take_sasl_challenge("sess-bind", Some("iroh:aaaa"), Some("iroh:aaaa"))
+            .unwrap_err(),
+        freeq_server::server::ChallengeRejection::Missing
+    );
+}
+
+#[tokio::test]
+async fn sasl_response_signed_on_another_connection_rejected() {
+    let key = PrivateKey::generate_ed25519();
+    let (tls_addr, _certs, tls) = start_tls(make_resolver(vec![(DID_A, &key)])).await;
+    let signer = KeySigner::new(DID_A.to_string(), key);
+
+    // A relay holds connection A to the server and connection B to the
+    // victim. Here B ends at the server too; all that matters is that its
+    // binding differs from A's.
+    let mut a = TlsLines::connect(tls_addr, &tls).await;
+    let victim = TlsLines::connect(tls_addr, &tls).await;
+    assert_ne!(a.binding, victim.binding);
+    a.tx("CAP LS 302").await;
+    a.tx("NICK relayed").await;
+    a.tx("USER relayed 0 * :test").await;
+    a.tx("CAP REQ :sasl").await;
+    a.rx(|l| l.contains("ACK"), "CAP ACK").await;
+
+    // The victim signs A's challenge under B's binding
+    let challenge = a.challenge().await;
+    let relayed = signer
+        .respond_bound(&challenge, Some(victim.binding.as_str()))
+        .unwrap();
+    a.tx(&format!("AUTHENTICATE {}", auth::encode_response(&relayed)))
+        .await;
+    let fail = a.rx(|l| l.contains(" 904 "), "relayed response").await;
+    assert!(fail.contains("challenge rejected"), "{fail}");
+
+    // Dropping the report doesn't help: the signature covers it
+    let challenge = a.challenge().await;
+    let mut stripped = signer
+        .respond_bound(&challenge, Some(victim.binding.as_str()))
+        .unwrap();
+    stripped.binding = None;
+    a.tx(&format!(
+        "AUTHENTICATE {}",
+        auth::encode_response(&stripped)
+    ))
+    .await;
+    a.rx(|l| l.contains(" 904 "), "stripped response").await;
+
+    // Signed under A's own binding it goes through
+    let challenge = a.challenge().await;
+    let own = signer
+        .respond_bound(&challenge, Some(a.binding.as_str()))
+        .unwrap();
+    a.tx(&format!("AUTHENTICATE {}", auth::encode_response(&own)))
+        .await;
+    a.rx(|l| l.contains(" 903 "), "own response").await;
+}
+
+#[tokio::test]
+async fn sdk_authenticates_over_tls_with_its_channel_binding() {
+    let key = PrivateKey::generate_ed25519();
+    let (tls_addr, _certs, _) = start_tls(make_resolver(vec![(DID_A, &key)])).await;
+
+    let signer: Arc<dyn ChallengeSigner> = Arc::new(KeySigner::new(DID_A.to_string(), key));
+    let config = ConnectConfig {
+        server_addr: tls_addr.to_string(),
+        nick: "sasl_tls".to_string(),
+        user: "sasl_tls".to_string(),
+        realname: "test".to_string(),
+        tls: true,
+        tls_insecure: true,
+        ..Default::default()
+    };
+    let (_handle, mut events) = client::connect(config, Some(signer));
+    let auth = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some(e) = events.recv().await {
+                if matches!(e, Event::Authenticated { .. }) {
+                    return e;
+                }
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert!(matches!(auth, Event::Authenticated { did } if did == DID_A));
+}
+
 #[tokio::test]
 async fn sasl_valid_after_one_failure() {
     let key = PrivateKey::generate_ed25519();
@@ -1,6 +1,6 @@
 //! SEARCH command acceptance tests.
 //!
-//! Covers membership gating, DM auth requirements, DM privacy (search runs
+//! Covers membership gating, DM targets, DM privacy (search runs
 //! against the requester's own canonical DM key), result batching, and
 //! parameter validation.
 
@@ -269,14 +269,14 @@ async fn search_requires_params() {
 // ═══════════════════════════════════════════════════════════════
 
 #[tokio::test]
-async fn dm_search_requires_authentication() {
+async fn dm_search_needs_a_known_target() {
     let (addr, _h) = start(DidResolver::static_map(HashMap::new())).await;
     run(addr, |addr| {
         let mut guest = C::with_caps(addr, "guest");
         guest.reg();
         guest.tx("SEARCH someone :anything");
         let fail = guest.rx(|l| l.contains("FAIL SEARCH"), "FAIL");
-        assert!(fail.contains("ACCOUNT_REQUIRED"), "got: {fail}");
+        assert!(fail.contains("INVALID_TARGET"), "got: {fail}");
     })
     .await;
 }
@@ -0,0 +1,124 @@
+//! Temporary channels (+T): purged with their history once empty past
+//! the TTL, with a warning to the founder first.
+
+use std::collections::HashMap;
+
+use freeq_sdk::did::DidResolver;
+use freeq_server::server::ChannelState;
+use freeq_server::temporary::{self, Temporary};
+use freeq_server::testing::{self, LineClient, TestServer};
+
+#[tokio::test]
+async fn empty_temporary_channel_is_purged_after_its_ttl() {
+    let server = TestServer::start_with(
+        testing::config("test-temporary"),
+        DidResolver::static_map(HashMap::new()),
+    )
+    .await
+    .unwrap();
+    let addr = server.irc_addr;
+
+    let alice = tokio::task::spawn_blocking(move || {
+        let mut alice = LineClient::guest(addr, "alice");
+        alice.tx("JOIN #breakout");
+        alice.rx(|l| l.contains(" 366 "), "joined");
+        alice.tx("MODE #breakout +T 4m");
+        alice.rx(|l| l.contains(" 696 "), "TTL too short");
+        alice.tx("MODE #breakout +T 600");
+        alice.rx(|l| l.contains("MODE #breakout +T 10m"), "set");
+        alice.tx("MODE #breakout");
+        let modes = alice.rx(|l| l.contains(" 324 "), "modes");
+        assert!(modes.contains('T') && modes.ends_with(" 10m"), "{modes}");
+        alice.tx("PRIVMSG #breakout :see you all later");
+        alice.tx("PART #breakout");
+        alice.rx(|l| l.contains("PART #breakout"), "parted");
+        alice
+    })
+    .await
+    .unwrap();
+
+    let state = server.state.clone();
+    let now = 1_700_000_000;
+    temporary::sweep(&state, now);
+    temporary::sweep(&state, now + 599);
+    assert!(state.channels.contains_key("#breakout"));
+    let stored = state.with_db(|db| db.get_messages("#breakout", 10, None));
+    assert_eq!(stored.map(|m| m.len()), Some(1));
+
+    temporary::sweep(&state, now + 600);
+    assert!(!state.channels.contains_key("#breakout"));
+    let stored = state.with_db(|db| db.get_messages("#breakout", 10, None));
+    assert_eq!(stored.map(|m| m.len()), Some(0));
+    let persisted = state.with_db(|db| db.load_channels()).unwrap();
+    assert!(!persisted.contains_key("#breakout"));
+
+    // Rejoining starts a fresh channel with no +T and no history.
+    tokio::task::spawn_blocking(move || {
+        let mut alice = alice;
+        alice.tx("JOIN #breakout");
+        alice.rx(|l| l.contains(" 366 "), "rejoined");
+        alice.tx("MODE #breakout");
+        let modes = alice.rx(|l| l.contains(" 324 "), "modes");
+        assert!(!modes.contains('T'), "{modes}");
+    })
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn founded_channels_need_the_flag_and_the_founder_is_warned() {
+    const FOUNDER: &str = "did:plc:founder";
+
+    let server = TestServer::start_with(
+        testing::config("test-temporary-founder"),
+        DidResolver::static_map(HashMap::new()),
+    )
+    .await
+    .unwrap();
+    let addr = server.irc_addr;
+    let state = server.state.clone();
+
+    let mut bob = tokio::task::spawn_blocking(move || LineClient::guest(addr, "bob"))
+        .await
+        .unwrap();
+    // Stand bob in for the founder's online session.
+    let bob_session = state
+        .nick_to_session
+        .lock()
+        .get_session("bob")
+        .unwrap()
+        .to_string();
+    state
+        .did_sessions
+        .lock()
+        .entry(FOUNDER.to_string())
+        .or_default()
+        .insert(bob_session);
+
+    let ttl = 3_600;
+    state.channels.insert(
+        "#event".to_string(),
+        ChannelState {
+            founder_did: Some(FOUNDER.to_string()),
+            temporary: Temporary::parse("1h"),
+            ..Default::default()
+        },
+    );
+    let now = 1_700_000_000;
+    temporary::sweep(&state, now);
+    temporary::sweep(&state, now + ttl);
+    assert!(state.channels.contains_key("#event"), "founded: exempt");
+
+    state.channels.get("#event").unwrap().temporary = Temporary::parse("1h!");
+    temporary::sweep(&state, now);
+    temporary::sweep(&state, now + ttl - 600);
+    tokio::task::spawn_blocking(move || {
+        let warning = bob.rx(|l| l.contains("#event is temporary"), "warned");
+        assert!(warning.contains(" NOTICE bob :"), "{warning}");
+        assert!(warning.contains("in about 10m"), "{warning}");
+    })
+    .await
+    .unwrap();
+    temporary::sweep(&state, now + ttl);
+    assert!(!state.channels.contains_key("#event"));
+}
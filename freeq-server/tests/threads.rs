@@ -0,0 +1,114 @@
+//! Reply threads: `+freeq.at/thread` root stamping and CHATHISTORY THREAD.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use freeq_sdk::did::DidResolver;
+use freeq_server::testing::LineClient;
+
+/// Register with message-tags, echo-message and batch.
+fn register(addr: SocketAddr, nick: &str) -> LineClient {
+    LineClient::guest_with_caps(addr, nick, "message-tags echo-message batch")
+}
+
+/// Wait for a PRIVMSG whose body is `text`.
+fn privmsg(c: &mut LineClient, text: &str) -> String {
+    c.rx(
+        |l| l.contains(" PRIVMSG ") && l.ends_with(&format!(":{text}")),
+        text,
+    )
+}
+
+fn tag(line: &str, key: &str) -> Option<String> {
+    let tags = line.strip_prefix('@')?.split(' ').next()?;
+    tags.split(';')
+        .find_map(|t| t.strip_prefix(&format!("{key}=")))
+        .map(str::to_string)
+}
+
+async fn start(db_path: &str) -> (SocketAddr, tokio::task::JoinHandle<anyhow::Result<()>>) {
+    let config = freeq_server::config::ServerConfig {
+        listen_addr: "127.0.0.1:0".to_string(),
+        server_name: "test-threads".to_string(),
+        db_path: Some(db_path.to_string()),
+        ..Default::default()
+    };
+    let resolver = DidResolver::static_map(HashMap::new());
+    freeq_server::server::Server::with_resolver(config, resolver)
+        .start()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn replies_carry_thread_root_and_thread_replays() {
+    let dir = tempfile::tempdir().unwrap();
+    let db_path = dir.path().join("threads.db").to_string_lossy().to_string();
+    let (addr, _handle) = start(&db_path).await;
+
+    tokio::task::spawn_blocking(move || {
+        let mut alice = register(addr, "alice");
+        let mut bob = register(addr, "bob");
+        alice.join("#t");
+        bob.join("#t");
+
+        alice.tx("PRIVMSG #t :root message");
+        let root = tag(&privmsg(&mut alice, "root message"), "msgid").unwrap();
+        assert_eq!(
+            tag(&privmsg(&mut bob, "root message"), "+freeq.at/thread"),
+            None
+        );
+
+        bob.tx(&format!("@+reply={root} PRIVMSG #t :first reply"));
+        let l = privmsg(&mut alice, "first reply");
+        assert_eq!(tag(&l, "+freeq.at/thread").as_deref(), Some(root.as_str()));
+        let first = tag(&l, "msgid").unwrap();
+
+        // A reply to a reply still points at the root
+        alice.tx(&format!("@+reply={first} PRIVMSG #t :nested reply"));
+        let l = privmsg(&mut bob, "nested reply");
+        assert_eq!(tag(&l, "+freeq.at/thread").as_deref(), Some(root.as_str()));
+        let nested = tag(&l, "msgid").unwrap();
+
+        // Clients can't forge the thread tag
+        bob.tx("@+freeq.at/thread=bogus PRIVMSG #t :not a reply");
+        assert_eq!(
+            privmsg(&mut alice, "not a reply").find("freeq.at/thread"),
+            None
+        );
+
+        // Any msgid in the thread fetches the whole thread, root first
+        let mut carol = register(addr, "carol");
+        carol.join("#t");
+        carol.tx(&format!("CHATHISTORY THREAD #t msgid={nested}"));
+        carol.rx(|l| l.contains("BATCH +"), "batch start");
+        let mut ids = Vec::new();
+        loop {
+            let l = carol.rx(
+                |l| l.contains(" PRIVMSG ") || l.contains("BATCH -"),
+                "thread",
+            );
+            if l.contains("BATCH -") {
+                break;
+            }
+            if ids.is_empty() {
+                assert_eq!(tag(&l, "+freeq.at/thread"), None, "{l}");
+            } else {
+                assert_eq!(tag(&l, "+freeq.at/thread").as_deref(), Some(root.as_str()));
+            }
+            ids.push(tag(&l, "msgid").unwrap());
+        }
+        assert_eq!(ids, [root, first, nested]);
+
+        // Unknown msgids give an empty batch
+        carol.tx("CHATHISTORY THREAD #t msgid=nope");
+        carol.rx(|l| l.contains("BATCH +"), "batch start");
+        let l = carol.rx(
+            |l| l.contains(" PRIVMSG ") || l.contains("BATCH -"),
+            "empty",
+        );
+        assert!(l.contains("BATCH -"), "{l}");
+    })
+    .await
+    .unwrap();
+}
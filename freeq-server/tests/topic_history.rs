@@ -0,0 +1,119 @@
+//! TOPICHIST and `TOPIC <chan> --revert <n>`.
+//!
+//! Previous topics are listed to channel members, ops can restore one by
+//! index, non-ops can't, and the history survives a restart.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use freeq_sdk::did::DidResolver;
+use freeq_server::testing::LineClient;
+
+fn topic(c: &mut LineClient, chan: &str, text: &str) {
+    c.tx(&format!("TOPIC {chan} :{text}"));
+    c.rx(|l| l.contains(" TOPIC ") && l.ends_with(text), "topic echo");
+}
+
+/// Send TOPICHIST and collect the NOTICE lines up to the end marker.
+fn topichist(c: &mut LineClient, chan: &str) -> Vec<String> {
+    c.tx(&format!("TOPICHIST {chan}"));
+    let mut lines = Vec::new();
+    loop {
+        let l = c.rx(|l| l.contains(" NOTICE "), "TOPICHIST");
+        if l.contains("End of TOPICHIST") {
+            return lines;
+        }
+        lines.push(l);
+    }
+}
+
+async fn start(db_path: &str) -> (SocketAddr, tokio::task::JoinHandle<anyhow::Result<()>>) {
+    let config = freeq_server::config::ServerConfig {
+        listen_addr: "127.0.0.1:0".to_string(),
+        server_name: "test-topichist".to_string(),
+        db_path: Some(db_path.to_string()),
+        ..Default::default()
+    };
+    let resolver = DidResolver::static_map(HashMap::new());
+    freeq_server::server::Server::with_resolver(config, resolver)
+        .start()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn topichist_lists_and_ops_revert() {
+    let dir = tempfile::tempdir().unwrap();
+    let db_path = dir.path().join("topics.db").to_string_lossy().to_string();
+    let (addr, handle) = start(&db_path).await;
+
+    tokio::task::spawn_blocking(move || {
+        let mut op = LineClient::guest(addr, "opnick");
+        op.join("#hist");
+        // -t so the vandal can change the topic at all
+        op.tx("MODE #hist -t");
+        op.rx(|l| l.contains("MODE #hist -t"), "mode -t");
+        topic(&mut op, "#hist", "welcome to hist");
+        topic(&mut op, "#hist", "release notes at example.com");
+
+        let mut vandal = LineClient::guest(addr, "vandal");
+        vandal.join("#hist");
+        op.rx(
+            |l| l.contains("JOIN") && l.contains("vandal"),
+            "vandal join",
+        );
+        topic(&mut vandal, "#hist", "lol pwned");
+
+        let lines = topichist(&mut op, "#hist");
+        assert_eq!(lines.len(), 3, "{lines:#?}");
+        assert!(lines[0].contains("[0]") && lines[0].ends_with("lol pwned"));
+        assert!(lines[1].contains("[1]") && lines[1].ends_with("release notes at example.com"));
+        assert!(lines[2].contains("[2]") && lines[2].ends_with("welcome to hist"));
+
+        // Non-ops can't revert, even with -t
+        vandal.tx("TOPIC #hist --revert 1");
+        vandal.rx(|l| l.split_whitespace().nth(1) == Some("482"), "482");
+
+        // Out-of-range index
+        op.tx("TOPIC #hist --revert 9");
+        op.rx(|l| l.contains("FAIL TOPIC INVALID_INDEX"), "invalid index");
+
+        // Revert as separate params and as a trailing param both work
+        op.tx("TOPIC #hist --revert 1");
+        vandal.rx(
+            |l| l.contains(" TOPIC #hist ") && l.ends_with("release notes at example.com"),
+            "revert broadcast",
+        );
+        // The revert pushed "lol pwned" onto the history, so the
+        // original topic is now three back.
+        op.tx("TOPIC #hist :--revert 3");
+        op.rx(
+            |l| l.contains(" TOPIC #hist ") && l.ends_with("welcome to hist"),
+            "revert trailing",
+        );
+
+        // Non-members can't read the history
+        let mut outsider = LineClient::guest(addr, "outsider");
+        outsider.tx("TOPICHIST #hist");
+        outsider.rx(|l| l.split_whitespace().nth(1) == Some("442"), "442");
+    })
+    .await
+    .unwrap();
+    handle.abort();
+    let _ = handle.await;
+
+    // History survives a restart
+    let (addr, _handle) = start(&db_path).await;
+    tokio::task::spawn_blocking(move || {
+        let mut c = LineClient::guest(addr, "later");
+        c.join("#hist");
+        let lines = topichist(&mut c, "#hist");
+        assert!(lines[0].contains("[0]") && lines[0].ends_with("welcome to hist"));
+        assert!(
+            lines.iter().any(|l| l.ends_with("lol pwned")),
+            "vandalised topic kept in history: {lines:#?}"
+        );
+    })
+    .await
+    .unwrap();
+}
@@ -0,0 +1,49 @@
+//! WEBIRC from trusted gateways (`--webirc-gateways`).
+
+use freeq_server::testing::{self, LineClient, TestServer};
+
+fn register(c: &mut LineClient, nick: &str) {
+    c.tx(&format!("NICK {nick}"));
+    c.tx(&format!("USER {nick} 0 * :{nick}"));
+    c.rx(|l| l.contains(" 001 "), "welcome");
+}
+
+#[tokio::test]
+async fn trusted_gateways_pass_on_the_client_address() {
+    let mut config = testing::config("test-webirc");
+    config.webirc_gateways = vec!["s3cret@127.0.0.0/8".to_string()];
+    let server = TestServer::start_with(
+        config,
+        freeq_sdk::did::DidResolver::static_map(Default::default()),
+    )
+    .await
+    .unwrap();
+    let addr = server.irc_addr;
+
+    tokio::task::spawn_blocking(move || {
+        // WHOIS on yourself shows the address the server believes.
+        let mut alice = LineClient::connect(addr);
+        alice.tx("WEBIRC s3cret kiwi client.example 203.0.113.9");
+        register(&mut alice, "alice");
+        alice.tx("WHOIS alice");
+        let actually = alice.rx(|l| l.contains(" 338 "), "338");
+        assert!(actually.contains("203.0.113.9"), "{actually}");
+
+        // Too late once registered.
+        alice.tx("WEBIRC s3cret kiwi other.example 198.51.100.1");
+        let error = alice.rx(|l| l.starts_with("ERROR"), "late WEBIRC");
+        assert!(error.contains("before NICK"), "{error}");
+
+        let mut bob = LineClient::connect(addr);
+        bob.tx("WEBIRC wrong kiwi client.example 203.0.113.10");
+        let error = bob.rx(|l| l.starts_with("ERROR"), "bad password");
+        assert!(error.contains("not a trusted gateway"), "{error}");
+
+        let mut carol = LineClient::connect(addr);
+        carol.tx("WEBIRC s3cret kiwi client.example not-an-ip");
+        let error = carol.rx(|l| l.starts_with("ERROR"), "bad ip");
+        assert!(error.contains("invalid client IP"), "{error}");
+    })
+    .await
+    .unwrap();
+}
@@ -0,0 +1,115 @@
+//! WHOIS extension numerics (`freeq.at/whois-extended`).
+//!
+//! Credential types, shared-channel roles and the E2EE identity key
+//! fingerprint are only sent to clients that negotiated the cap, and
+//! never reveal credential claims or roles in channels the requester
+//! isn't in.
+
+use std::collections::HashMap;
+
+use freeq_sdk::crypto::PrivateKey;
+use freeq_sdk::did::{self, DidResolver};
+use freeq_server::testing::LineClient;
+
+const DID_A: &str = "did:plc:whois_alice";
+const DID_B: &str = "did:plc:whois_bob";
+
+/// Send WHOIS and collect every numeric up to 318.
+fn whois(c: &mut LineClient, nick: &str) -> Vec<String> {
+    c.tx(&format!("WHOIS {nick}"));
+    let mut lines = Vec::new();
+    loop {
+        let l = c.rx(|_| true, "WHOIS reply");
+        let done = l.split_whitespace().nth(1) == Some("318");
+        lines.push(l);
+        if done {
+            return lines;
+        }
+    }
+}
+
+fn numeric<'a>(lines: &'a [String], n: &str) -> Option<&'a String> {
+    lines
+        .iter()
+        .find(|l| l.split_whitespace().nth(1) == Some(n))
+}
+
+#[tokio::test]
+async fn whois_extended_numerics() {
+    let key_a = PrivateKey::generate_ed25519();
+    let key_b = PrivateKey::generate_ed25519();
+    let mut docs = HashMap::new();
+    for (d, k) in [(DID_A, &key_a), (DID_B, &key_b)] {
+        docs.insert(
+            d.to_string(),
+            did::make_test_did_document(d, &k.public_key_multibase()),
+        );
+    }
+    let config = freeq_server::config::ServerConfig {
+        listen_addr: "127.0.0.1:0".to_string(),
+        server_name: "test-whois".to_string(),
+        challenge_timeout_secs: 60,
+        ..Default::default()
+    };
+    let server = freeq_server::server::Server::with_resolver(config, DidResolver::static_map(docs));
+    let (addr, _web, _h, state) = server.start_with_web_state().await.unwrap();
+
+    let engine = state.policy_engine.clone().unwrap();
+    engine
+        .store_credential(
+            DID_B,
+            "github_membership",
+            "did:web:verify.example",
+            &serde_json::json!({"org": "secret-org"}),
+        )
+        .unwrap();
+    for d in [DID_A, DID_B] {
+        state.prekey_bundles.lock().insert(
+            d.to_string(),
+            serde_json::json!({"identity_key": "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA"}),
+        );
+    }
+
+    tokio::task::spawn_blocking(move || {
+        // Bob founds #shared and #bobonly; Alice only shares #shared.
+        let mut bob = LineClient::with_sasl(addr, "bob", DID_B, key_b);
+        bob.tx("JOIN #shared");
+        bob.rx(|l| l.contains("JOIN") && l.contains("#shared"), "bob join");
+        bob.tx("JOIN #bobonly");
+        bob.rx(
+            |l| l.contains("JOIN") && l.contains("#bobonly"),
+            "bob join 2",
+        );
+
+        let mut alice =
+            LineClient::with_sasl_caps(addr, "alice", DID_A, key_a, "freeq.at/whois-extended");
+        alice.tx("JOIN #shared");
+        alice.rx(
+            |l| l.contains("JOIN") && l.contains("#shared"),
+            "alice join",
+        );
+
+        let lines = whois(&mut alice, "bob");
+        let creds = numeric(&lines, "674").expect("credential types");
+        assert!(creds.contains("github_membership"), "{creds}");
+        assert!(!creds.contains("secret-org"), "claims leaked: {creds}");
+
+        let roles = numeric(&lines, "675").expect("roles");
+        assert!(roles.contains("#shared=founder"), "{roles}");
+        assert!(
+            !roles.contains("#bobonly"),
+            "unshared channel leaked: {roles}"
+        );
+
+        let e2ee = numeric(&lines, "676").expect("e2ee fingerprint");
+        assert!(e2ee.contains("e2ee identity key: "), "{e2ee}");
+
+        // Without the cap: none of the extension numerics
+        let lines = whois(&mut bob, "alice");
+        for n in ["674", "675", "676"] {
+            assert!(numeric(&lines, n).is_none(), "{n} sent without cap");
+        }
+    })
+    .await
+    .unwrap();
+}
@@ -0,0 +1,124 @@
+//! PRIVACY settings and how WHOIS / WHO honour them.
+//!
+//! Hidden channels, idle time and client platform disappear for other
+//! users but stay visible to the user themselves and to server opers;
+//! 338 (actual IP) is only ever shown to those two.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use freeq_sdk::did::DidResolver;
+use freeq_server::testing::LineClient;
+
+fn connect(addr: SocketAddr, nick: &str) -> LineClient {
+    let mut c = LineClient::connect(addr);
+    c.tx(&format!("NICK {nick}"));
+    c.tx(&format!("USER {nick} 0 * :irssi"));
+    c.num("001");
+    c
+}
+
+fn privacy(c: &mut LineClient, args: &str) -> String {
+    c.tx(format!("PRIVACY {args}").trim_end());
+    c.rx(|l| l.contains("NOTICE") && l.contains("PRIVACY"), "PRIVACY")
+}
+
+/// Send WHOIS and collect every line up to 318.
+fn whois(c: &mut LineClient, nick: &str) -> Vec<String> {
+    c.tx(&format!("WHOIS {nick}"));
+    until(c, "318")
+}
+
+fn who_lines(c: &mut LineClient, target: &str) -> Vec<String> {
+    c.tx(&format!("WHO {target}"));
+    until(c, "315")
+}
+
+fn until(c: &mut LineClient, end: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    loop {
+        let l = c.rx(|_| true, end);
+        let done = l.split_whitespace().nth(1) == Some(end);
+        lines.push(l);
+        if done {
+            return lines;
+        }
+    }
+}
+
+fn numeric<'a>(lines: &'a [String], n: &str) -> Option<&'a String> {
+    lines
+        .iter()
+        .find(|l| l.split_whitespace().nth(1) == Some(n))
+}
+
+#[tokio::test]
+async fn privacy_controls_whois_and_who() {
+    let config = freeq_server::config::ServerConfig {
+        listen_addr: "127.0.0.1:0".to_string(),
+        server_name: "test-privacy".to_string(),
+        challenge_timeout_secs: 60,
+        oper_password: Some("sekrit".to_string()),
+        ..Default::default()
+    };
+    let server = freeq_server::server::Server::with_resolver(
+        config,
+        DidResolver::static_map(HashMap::new()),
+    );
+    let (addr, _h) = server.start().await.unwrap();
+
+    tokio::task::spawn_blocking(move || {
+        let mut alice = connect(addr, "alice");
+        let mut bob = connect(addr, "bob");
+        alice.join("#shared");
+        alice.join("#hideout");
+        bob.join("#shared");
+
+        // Defaults: everything visible, but never the IP to others.
+        let w = whois(&mut bob, "alice");
+        let channels = numeric(&w, "319").expect("319");
+        assert!(channels.contains("#shared") && channels.contains("#hideout"));
+        assert!(numeric(&w, "317").is_some(), "idle shown: {w:?}");
+        assert!(w.iter().any(|l| l.contains("client: irssi")));
+        assert!(numeric(&w, "338").is_none(), "IP leaked: {w:?}");
+
+        assert!(privacy(&mut alice, "").contains("shows everything"));
+        assert!(privacy(&mut alice, "HIDE channels,idle").contains("channels, idle"));
+        assert!(privacy(&mut alice, "HIDE bogus").contains("unknown field"));
+        let reply = privacy(&mut alice, "HIDE platform");
+        assert!(reply.contains("channels, idle, platform"), "{reply}");
+
+        let w = whois(&mut bob, "alice");
+        let channels = numeric(&w, "319").expect("shared channel still listed");
+        assert!(channels.contains("#shared"));
+        assert!(!channels.contains("#hideout"), "{channels}");
+        assert!(numeric(&w, "317").is_none(), "idle hidden: {w:?}");
+        assert!(!w.iter().any(|l| l.contains("client: ")), "{w:?}");
+
+        // WHO on a channel bob isn't in leaves alice out.
+        let who = who_lines(&mut bob, "#hideout");
+        assert!(numeric(&who, "352").is_none(), "{who:?}");
+        let who = who_lines(&mut bob, "#shared");
+        assert!(who.iter().any(|l| l.contains(" alice ")), "{who:?}");
+
+        // Alice sees her own WHOIS in full, including 338.
+        let w = whois(&mut alice, "alice");
+        assert!(numeric(&w, "319").unwrap().contains("#hideout"));
+        assert!(numeric(&w, "317").is_some());
+        assert!(numeric(&w, "338").unwrap().contains("127.0.0.1"));
+
+        // Opers bypass privacy.
+        bob.tx("OPER bob sekrit");
+        bob.num("381");
+        let w = whois(&mut bob, "alice");
+        assert!(numeric(&w, "319").unwrap().contains("#hideout"));
+        assert!(numeric(&w, "338").is_some());
+        let who = who_lines(&mut bob, "#hideout");
+        assert!(numeric(&who, "352").is_some());
+
+        assert!(privacy(&mut alice, "SHOW all").contains("shows everything"));
+        assert!(privacy(&mut alice, "").contains("shows everything"));
+    })
+    .await
+    .unwrap();
+}
@@ -26,6 +26,9 @@ use ratatui::backend::CrosstermBackend;
 
 use crate::app::App;
 
+/// Channel-status symbols a server may put before a nick in NAMES.
+const NICK_PREFIXES: [char; 5] = ['~', '&', '@', '%', '+'];
+
 /// Minimal TUI client for IRC with AT Protocol authentication.
 ///
 /// Just run `freeq-tui` to connect to irc.freeq.at with TLS.
@@ -278,8 +281,11 @@ async fn main() -> Result<()> {
             realname: "freeq tui".to_string(),
             tls: resolved.tls,
             tls_insecure: resolved.tls_insecure,
+            tls_options: Default::default(),
             web_token: None,
             websocket_url: None,
+            nick_fallback: Default::default(),
+            capabilities: Default::default(),
         })
         .await?
     };
@@ -291,8 +297,11 @@ async fn main() -> Result<()> {
         realname: "freeq tui".to_string(),
         tls: resolved.tls,
         tls_insecure: resolved.tls_insecure,
+        tls_options: Default::default(),
         web_token: None,
         websocket_url: None,
+        nick_fallback: Default::default(),
+        capabilities: Default::default(),
     };
 
     let (mut handle, mut events) =
@@ -888,6 +897,20 @@ fn process_irc_event(app: &mut App, event: Event, _handle: &client::ClientHandle
         Event::AuthFailed { reason } => {
             app.status_msg(&format!("Authentication failed: {reason}"));
         }
+        Event::CapabilitiesAcked {
+            acked,
+            rejected,
+            unavailable,
+        } => {
+            let status = app.buffer_mut("status");
+            status.push_system(&format!("Capabilities: {}", acked.join(" ")));
+            if !rejected.is_empty() {
+                status.push_system(&format!("Refused by the server: {}", rejected.join(" ")));
+            }
+            if !unavailable.is_empty() {
+                status.push_system(&format!("Not offered: {}", unavailable.join(" ")));
+            }
+        }
         Event::Joined { channel, nick, .. } => {
             let cloak = app
                 .nick_hosts
@@ -896,7 +919,7 @@ fn process_irc_event(app: &mut App, event: Event, _handle: &client::ClientHandle
                 .cloned();
             let buf = app.buffer_mut(&channel);
             if !buf.nicks.iter().any(|n| {
-                let bare = n.trim_start_matches(['@', '+']);
+                let bare = n.trim_start_matches(NICK_PREFIXES);
                 bare == nick
             }) {
                 buf.nicks.push(nick.clone());
@@ -912,7 +935,7 @@ fn process_irc_event(app: &mut App, event: Event, _handle: &client::ClientHandle
         Event::Parted { channel, nick } => {
             let buf = app.buffer_mut(&channel);
             buf.nicks.retain(|n| {
-                let bare = n.trim_start_matches(['@', '+']);
+                let bare = n.trim_start_matches(NICK_PREFIXES);
                 bare != nick
             });
             buf.push_system(&format!("{nick} has left"));
@@ -922,6 +945,7 @@ fn process_irc_event(app: &mut App, event: Event, _handle: &client::ClientHandle
             target,
             text,
             tags,
+            ..
         } => {
             // Try E2EE decryption if we have a key for this channel
             let (text, was_encrypted) = {
@@ -1237,17 +1261,17 @@ fn process_irc_event(app: &mut App, event: Event, _handle: &client::ClientHandle
             // Update nick prefixes for +o/-o/+v/-v
             if let Some(ref target_nick) = arg {
                 let buf = app.buffer_mut(&channel);
-                let bare = target_nick.trim_start_matches(['@', '+']);
+                let bare = target_nick.trim_start_matches(NICK_PREFIXES);
                 match mode.as_str() {
                     "+o" => {
                         // Remove any existing entry, add with @
                         buf.nicks
-                            .retain(|n| n.trim_start_matches(['@', '+']) != bare);
+                            .retain(|n| n.trim_start_matches(NICK_PREFIXES) != bare);
                         buf.nicks.push(format!("@{bare}"));
                     }
                     "-o" => {
                         buf.nicks
-                            .retain(|n| n.trim_start_matches(['@', '+']) != bare);
+                            .retain(|n| n.trim_start_matches(NICK_PREFIXES) != bare);
                         buf.nicks.push(bare.to_string());
                     }
                     "+v" => {
@@ -1255,7 +1279,7 @@ fn process_irc_event(app: &mut App, event: Event, _handle: &client::ClientHandle
                         let was_op = buf.nicks.iter().any(|n| n == &format!("@{bare}"));
                         if !was_op {
                             buf.nicks
-                                .retain(|n| n.trim_start_matches(['@', '+']) != bare);
+                                .retain(|n| n.trim_start_matches(NICK_PREFIXES) != bare);
                             buf.nicks.push(format!("+{bare}"));
                         }
                     }
@@ -1263,7 +1287,7 @@ fn process_irc_event(app: &mut App, event: Event, _handle: &client::ClientHandle
                         let was_op = buf.nicks.iter().any(|n| n == &format!("@{bare}"));
                         if !was_op {
                             buf.nicks
-                                .retain(|n| n.trim_start_matches(['@', '+']) != bare);
+                                .retain(|n| n.trim_start_matches(NICK_PREFIXES) != bare);
                             buf.nicks.push(bare.to_string());
                         }
                     }
@@ -1290,7 +1314,7 @@ fn process_irc_event(app: &mut App, event: Event, _handle: &client::ClientHandle
                 let msg = format!("{nick} was kicked by {by} ({reason})");
                 let buf = app.buffer_mut(&channel);
                 buf.nicks.retain(|n| {
-                    let bare = n.trim_start_matches(['@', '+']);
+                    let bare = n.trim_start_matches(NICK_PREFIXES);
                     bare.to_lowercase() != nick.to_lowercase()
                 });
                 buf.push_system(&msg);
@@ -1339,12 +1363,12 @@ fn process_irc_event(app: &mut App, event: Event, _handle: &client::ClientHandle
             for buf_name in buffers {
                 let buf = app.buffer_mut(&buf_name);
                 let was_in = buf.nicks.iter().any(|n| {
-                    let bare = n.trim_start_matches(['@', '+']);
+                    let bare = n.trim_start_matches(NICK_PREFIXES);
                     bare == nick
                 });
                 if was_in {
                     buf.nicks.retain(|n| {
-                        let bare = n.trim_start_matches(['@', '+']);
+                        let bare = n.trim_start_matches(NICK_PREFIXES);
                         bare != nick
                     });
                     buf.push_system(&format!("{nick} has quit ({reason})"));
@@ -1426,6 +1450,9 @@ fn process_irc_event(app: &mut App, event: Event, _handle: &client::ClientHandle
                 app.buffer_mut(&name).push_system(&msg);
             }
         }
+        Event::NickAssigned { nick } => {
+            app.nick = nick.clone();
+        }
         Event::NickChanged { old_nick, new_nick } => {
             let msg = format!("{old_nick} is now known as {new_nick}");
             for (name, buf) in app.buffers.iter_mut() {
@@ -1456,6 +1483,14 @@ fn process_irc_event(app: &mut App, event: Event, _handle: &client::ClientHandle
             app.buffer_mut("status")
                 .push_system(&format!("  DM: {nick}  (last: {ts_display})"));
         }
+        // The raw JOIN/PART/QUIT/AWAY events above already drive the
+        // nick list and status lines.
+        Event::PresenceChanged { .. }
+        | Event::MemberAdded { .. }
+        | Event::MemberRemoved { .. }
+        | Event::MemberChanged { .. } => {}
+        // Replies render inline from their `Message` event.
+        Event::ThreadReply { .. } => {}
         Event::RawLine(ref line) => {
             // Stash host part of the prefix on JOIN lines so we can surface
             // hostname cloaks (freeq/plc/xxx, freeq/guest) without changing
@@ -2758,7 +2793,7 @@ fn try_nick_complete(app: &mut App) {
 
     // Find first matching nick (strip @ and + prefixes for comparison)
     let matching = nicks.iter().find_map(|n| {
-        let bare = n.trim_start_matches(['@', '+']);
+        let bare = n.trim_start_matches(NICK_PREFIXES);
         if bare.to_lowercase().starts_with(&fragment_lower) {
             Some(bare.to_string())
         } else {
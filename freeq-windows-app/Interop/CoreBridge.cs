@@ -54,6 +54,16 @@ public sealed class CoreBridge : IDisposable
         return NativeMethods.Disconnect(_handle);
     }
 
+    /// <summary>
+    /// Rejoin and backfill what a crashed previous run had open.
+    /// Returns 6 (NotFound) when there is nothing to restore.
+    /// </summary>
+    public int RestoreState()
+    {
+        if (_handle == 0) return 1;
+        return NativeMethods.RestoreState(_handle);
+    }
+
     public int Join(string channel)
     {
         if (_handle == 0) return 1;
@@ -75,11 +85,22 @@ public sealed class CoreBridge : IDisposable
     public string? GetSnapshotJson()
     {
         if (_handle == 0) return null;
-        var ptr = NativeMethods.GetSnapshotJson(_handle);
+        return TakeString(NativeMethods.GetSnapshotJsonUtf16(_handle));
+    }
+
+    public string? GetNick()
+    {
+        if (_handle == 0) return null;
+        return TakeString(NativeMethods.GetNickUtf16(_handle));
+    }
+
+    /// <summary>Copy and free a caller-owned UTF-16 string from the core.</summary>
+    private static string? TakeString(IntPtr ptr)
+    {
         if (ptr == IntPtr.Zero) return null;
         try
         {
-            return Marshal.PtrToStringUTF8(ptr);
+            return Marshal.PtrToStringUni(ptr);
         }
         finally
         {
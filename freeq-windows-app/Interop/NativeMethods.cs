@@ -33,6 +33,9 @@ internal static partial class NativeMethods
     [LibraryImport(DllName, EntryPoint = "freeq_win_disconnect")]
     public static partial int Disconnect(ulong handle);
 
+    [LibraryImport(DllName, EntryPoint = "freeq_win_restore_state")]
+    public static partial int RestoreState(ulong handle);
+
     [LibraryImport(DllName, EntryPoint = "freeq_win_join", StringMarshalling = StringMarshalling.Utf8)]
     public static partial int Join(ulong handle, string channel);
 
@@ -42,10 +45,22 @@ internal static partial class NativeMethods
     [LibraryImport(DllName, EntryPoint = "freeq_win_send_raw", StringMarshalling = StringMarshalling.Utf8)]
     public static partial int SendRaw(ulong handle, string line);
 
-    [LibraryImport(DllName, EntryPoint = "freeq_win_get_snapshot_json")]
-    public static partial IntPtr GetSnapshotJson(ulong handle);
+    // Returned strings are owned by the caller and released with FreeString.
+
+    [LibraryImport(DllName, EntryPoint = "freeq_win_get_snapshot_json_utf16")]
+    public static partial IntPtr GetSnapshotJsonUtf16(ulong handle);
+
+    [LibraryImport(DllName, EntryPoint = "freeq_win_get_channel_snapshot_json_utf16", StringMarshalling = StringMarshalling.Utf8)]
+    public static partial IntPtr GetChannelSnapshotJsonUtf16(ulong handle, string channel);
 
-    [LibraryImport(DllName, EntryPoint = "freeq_win_free_string")]
+    // Blocks until the page arrives; call from a background thread.
+    [LibraryImport(DllName, EntryPoint = "freeq_win_get_history_json_utf16", StringMarshalling = StringMarshalling.Utf8)]
+    public static partial IntPtr GetHistoryJsonUtf16(ulong handle, string target, string? beforeMsgid, uint limit);
+
+    [LibraryImport(DllName, EntryPoint = "freeq_win_get_nick_utf16")]
+    public static partial IntPtr GetNickUtf16(ulong handle);
+
+    [LibraryImport(DllName, EntryPoint = "freeq_string_free")]
     public static partial void FreeString(IntPtr ptr);
 
     // ── Rich messaging ──
@@ -74,6 +89,9 @@ internal static partial class NativeMethods
     [LibraryImport(DllName, EntryPoint = "freeq_win_history_before", StringMarshalling = StringMarshalling.Utf8)]
     public static partial int HistoryBefore(ulong handle, string target, string msgid, uint count);
 
+    [LibraryImport(DllName, EntryPoint = "freeq_win_fetch_thread", StringMarshalling = StringMarshalling.Utf8)]
+    public static partial int FetchThread(ulong handle, string target, string msgid);
+
     [LibraryImport(DllName, EntryPoint = "freeq_win_pin", StringMarshalling = StringMarshalling.Utf8)]
     public static partial int Pin(ulong handle, string channel, string msgid);
 
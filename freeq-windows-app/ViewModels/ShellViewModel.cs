@@ -206,6 +206,8 @@ public partial class ShellViewModel : ObservableObject, IDisposable
         ShowConnectPanel = false;
         _userDisconnected = false;
 
+        // Pick up where a crashed run left off (no-op otherwise)
+        _bridge.RestoreState();
         var result = _bridge.Connect();
         if (result != 0)
         {
@@ -300,6 +302,8 @@ public partial class ShellViewModel : ObservableObject, IDisposable
         ShowConnectPanel = false;
         _userDisconnected = false;
 
+        // Pick up where a crashed run left off (no-op otherwise)
+        _bridge.RestoreState();
         var result = _bridge.Connect();
         if (result != 0)
         {
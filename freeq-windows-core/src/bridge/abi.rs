@@ -2,8 +2,9 @@
 //!
 //! All functions are `extern "C"` and `#[no_mangle]`.
 //! Handles are opaque `u64` IDs into a global `DashMap`.
+//! String ownership follows the conventions in [`crate::bridge::strings`].
 
-use std::ffi::{c_char, c_void, CStr, CString};
+use std::ffi::{c_char, c_void};
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 
@@ -13,9 +14,11 @@ use parking_lot::Mutex;
 
 use crate::bridge::callback::{CallbackSink, EventCallback};
 use crate::bridge::envelope::EventEnvelope;
+use crate::bridge::strings::{self, read_c_str};
 use crate::core::AppCore;
 use crate::error::FfiResult;
-use crate::event::convert_event;
+use crate::event::{convert_event, DomainEvent};
+use crate::restore::{self, RestoreState, StateFile};
 use crate::RUNTIME;
 
 /// Global handle table. Maps handle IDs → Arc<AppCore>.
@@ -24,17 +27,6 @@ static HANDLES: Lazy<DashMap<u64, Arc<AppCore>>> = Lazy::new(DashMap::new);
 /// Monotonic handle counter.
 static NEXT_HANDLE: AtomicU64 = AtomicU64::new(1);
 
-/// Helper: read a C string pointer into a Rust String, returning None on null or invalid UTF-8.
-unsafe fn read_c_str(ptr: *const c_char) -> Option<String> {
-    if ptr.is_null() {
-        return None;
-    }
-    unsafe { CStr::from_ptr(ptr) }
-        .to_str()
-        .ok()
-        .map(String::from)
-}
-
 // ─── Create / Destroy ────────────────────────────────────────────────
 
 /// Create a new client instance from a JSON configuration string.
@@ -42,17 +34,30 @@ unsafe fn read_c_str(ptr: *const c_char) -> Option<String> {
 /// # Safety
 ///
 /// `config_json` must be a valid, NUL-terminated UTF-8 C string, or null.
+/// It is only borrowed for the duration of the call.
 ///
 /// Config JSON schema:
 /// ```json
 /// {
 ///   "server": "irc.example.com:6697",
 ///   "nick": "myuser",
-///   "tls": true
+///   "tls": true,
+///   "tls_options": {
+///     "spki_pins": ["sha256/<base64>"],
+///     "root_certs_pem": ["-----BEGIN CERTIFICATE-----\n..."],
+///     "alpn": ["irc"]
+///   },
+///   "state_dir": "C:\\Users\\me\\AppData\\Local\\freeq"
 /// }
 /// ```
 ///
-/// Returns a non-zero handle on success, or 0 on failure.
+/// `tls_options` and each of its fields are optional; see
+/// `freeq_sdk::tls::TlsOptions`. `state_dir` is where the crash-recovery
+/// state file is kept (default `%LOCALAPPDATA%\freeq`); see
+/// [`crate::restore`] and `freeq_win_restore_state`.
+///
+/// Returns a non-zero handle on success, or 0 on failure (including an
+/// unparseable pin or root certificate).
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn freeq_win_create_client(config_json: *const c_char) -> u64 {
     let Some(json_str) = (unsafe { read_c_str(config_json) }) else {
@@ -74,6 +79,29 @@ pub unsafe extern "C" fn freeq_win_create_client(config_json: *const c_char) ->
         .to_string();
     let nick = parsed["nick"].as_str().unwrap_or("freeq_user").to_string();
     let tls = parsed["tls"].as_bool().unwrap_or(false);
+    let strings = |key: &str| -> Vec<String> {
+        parsed["tls_options"][key]
+            .as_array()
+            .map(|a| a.iter().filter_map(|v| v.as_str().map(String::from)).collect())
+            .unwrap_or_default()
+    };
+    let tls_options = freeq_sdk::tls::TlsOptions {
+        spki_pins: strings("spki_pins"),
+        root_certs_pem: strings("root_certs_pem"),
+        alpn: strings("alpn"),
+    };
+    if let Err(e) = tls_options.validate() {
+        tracing::error!("freeq_win_create_client: {e}");
+        return 0;
+    }
+
+    let state_file = parsed["state_dir"]
+        .as_str()
+        .map(std::path::PathBuf::from)
+        .or_else(restore::default_dir)
+        .map(|dir| StateFile::new(&dir, &server, &nick));
+    // Read before this run starts overwriting it
+    let saved_state = state_file.as_ref().and_then(StateFile::load);
 
     let id = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
     let core = Arc::new(AppCore {
@@ -85,8 +113,13 @@ pub unsafe extern "C" fn freeq_win_create_client(config_json: *const c_char) ->
         server_addr: server,
         initial_nick: nick,
         tls,
+        tls_options,
         web_token: Mutex::new(None),
         channels: Mutex::new(Vec::new()),
+        registered: AtomicBool::new(false),
+        state_file: state_file.map(Mutex::new),
+        saved_state: Mutex::new(saved_state),
+        pending_restore: Mutex::new(None),
     });
 
     HANDLES.insert(id, core);
@@ -96,7 +129,8 @@ pub unsafe extern "C" fn freeq_win_create_client(config_json: *const c_char) ->
 
 /// Destroy a client instance and free all associated resources.
 ///
-/// Safe to call multiple times — second call is a no-op.
+/// Deletes the crash-recovery state file. Safe to call multiple times —
+/// second call is a no-op.
 ///
 /// # Safety
 ///
@@ -114,6 +148,9 @@ pub unsafe extern "C" fn freeq_win_destroy_client(handle: u64) {
             });
         }
         core.connected.store(false, Ordering::Release);
+        if let Some(file) = &core.state_file {
+            file.lock().remove();
+        }
     }
 }
 
@@ -191,14 +228,20 @@ pub unsafe extern "C" fn freeq_win_connect(handle: u64) -> i32 {
                 realname: "freeq windows".to_string(),
                 tls: core.tls,
                 tls_insecure: false,
+                tls_options: core.tls_options.clone(),
                 web_token,
                 websocket_url: None,
+                nick_fallback: Default::default(),
+                capabilities: Default::default(),
             };
 
             let (client_handle, mut event_rx) = freeq_sdk::client::connect(config, None);
 
-            *core.sdk_handle.lock() = Some(client_handle);
+            *core.sdk_handle.lock() = Some(client_handle.clone());
             core.connected.store(true, Ordering::Release);
+            if let Some(file) = &core.state_file {
+                file.lock().reset();
+            }
 
             let mut seq: u64 = 0;
 
@@ -211,11 +254,45 @@ pub unsafe extern "C" fn freeq_win_connect(handle: u64) -> i32 {
                     crate::event::DomainEvent::Disconnected { .. }
                 ) {
                     core.connected.store(false, Ordering::Release);
+                    core.registered.store(false, Ordering::Release);
+                    if let Some(file) = &core.state_file {
+                        file.lock().flush();
+                    }
                 }
 
                 // Track nick changes
                 if let crate::event::DomainEvent::Registered { ref nick } = &domain_event {
                     *core.nick.lock() = nick.clone();
+                    core.update_state(true, |s| {
+                        s.nick = nick.clone();
+                        true
+                    });
+                    // Lock order matches freeq_win_restore_state
+                    let mut pending = core.pending_restore.lock();
+                    core.registered.store(true, Ordering::Release);
+                    if let Some(state) = pending.take() {
+                        RUNTIME.spawn(restore(client_handle.clone(), state));
+                    }
+                }
+                if let DomainEvent::NickAssigned { nick } = &domain_event {
+                    core.update_state(true, |s| {
+                        s.nick = nick.clone();
+                        true
+                    });
+                }
+
+                // Track the newest message per conversation (for restore)
+                if let DomainEvent::Message(msg) = &domain_event {
+                    if let Some(msgid) = &msg.msgid {
+                        let conversation = if msg.target.starts_with(['#', '&'])
+                            || msg.from_nick.eq_ignore_ascii_case(&core.nick.lock())
+                        {
+                            &msg.target
+                        } else {
+                            &msg.from_nick
+                        };
+                        core.update_state(false, |s| s.record_msgid(conversation, msgid));
+                    }
                 }
 
                 // Track joined channels (for reconnect)
@@ -228,23 +305,24 @@ pub unsafe extern "C" fn freeq_win_connect(handle: u64) -> i32 {
                         if !chans.iter().any(|c| c.eq_ignore_ascii_case(channel)) {
                             chans.push(channel.clone());
                         }
+                        save_channels(&core, &chans);
                     }
                     crate::event::DomainEvent::Parted {
                         channel,
                         nick: part_nick,
                     } if part_nick.eq_ignore_ascii_case(&core.nick.lock()) => {
-                        core.channels
-                            .lock()
-                            .retain(|c| !c.eq_ignore_ascii_case(channel));
+                        let mut chans = core.channels.lock();
+                        chans.retain(|c| !c.eq_ignore_ascii_case(channel));
+                        save_channels(&core, &chans);
                     }
                     crate::event::DomainEvent::Kicked {
                         channel,
                         nick: kick_nick,
                         ..
                     } if kick_nick.eq_ignore_ascii_case(&core.nick.lock()) => {
-                        core.channels
-                            .lock()
-                            .retain(|c| !c.eq_ignore_ascii_case(channel));
+                        let mut chans = core.channels.lock();
+                        chans.retain(|c| !c.eq_ignore_ascii_case(channel));
+                        save_channels(&core, &chans);
                     }
                     _ => {}
                 }
@@ -261,14 +339,90 @@ pub unsafe extern "C" fn freeq_win_connect(handle: u64) -> i32 {
 
             // Event loop ended — connection is gone
             core.connected.store(false, Ordering::Release);
+            core.registered.store(false, Ordering::Release);
+            if let Some(file) = &core.state_file {
+                file.lock().flush();
+            }
         });
     });
 
     FfiResult::Ok as i32
 }
 
+fn save_channels(core: &AppCore, channels: &[String]) {
+    core.update_state(true, |s| {
+        s.channels = channels.to_vec();
+        true
+    });
+}
+
+/// Rejoin `state`'s channels and backfill each conversation after its
+/// last read msgid (or the latest messages, if there is none).
+async fn restore(h: freeq_sdk::client::ClientHandle, state: RestoreState) {
+    let channels: Vec<&str> = state.channels.iter().map(String::as_str).collect();
+    if let Err(e) = h.join_many(&channels).await {
+        tracing::warn!("restore: rejoining channels: {e}");
+    }
+    for channel in &state.channels {
+        if !state.last_read.contains_key(channel) {
+            if let Err(e) = h.history_latest(channel, restore::BACKFILL_LIMIT).await {
+                tracing::warn!("restore: backfilling {channel}: {e}");
+            }
+        }
+    }
+    for (target, msgid) in &state.last_read {
+        if let Err(e) = h
+            .history_after(target, msgid, restore::BACKFILL_LIMIT)
+            .await
+        {
+            tracing::warn!("restore: backfilling {target}: {e}");
+        }
+    }
+}
+
+/// Restore the state a previous run left behind, if it crashed or lost
+/// its connection: reuse its nick, and once registered rejoin its
+/// channels and backfill each conversation via CHATHISTORY.
+///
+/// Call it before `freeq_win_connect` so the saved nick is used for
+/// registration; if already registered the rejoin happens at once.
+/// Returns `NotFound` when there is no saved state (or it was restored
+/// already).
+///
+/// # Safety
+///
+/// `handle` must be a valid handle from `freeq_win_create_client`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn freeq_win_restore_state(handle: u64) -> i32 {
+    let Some(core) = HANDLES.get(&handle) else {
+        return FfiResult::InvalidHandle as i32;
+    };
+    let Some(state) = core.saved_state.lock().take() else {
+        return FfiResult::NotFound as i32;
+    };
+    tracing::debug!(
+        "freeq_win_restore_state: {} channel(s), {} conversation(s)",
+        state.channels.len(),
+        state.last_read.len()
+    );
+    if !core.connected.load(Ordering::Acquire) && !state.nick.is_empty() {
+        *core.nick.lock() = state.nick.clone();
+    }
+    let mut pending = core.pending_restore.lock();
+    let sdk = core.sdk_handle.lock().clone();
+    match sdk {
+        Some(h) if core.registered.load(Ordering::Acquire) => {
+            RUNTIME.spawn(restore(h, state));
+        }
+        _ => *pending = Some(state),
+    }
+    FfiResult::Ok as i32
+}
+
 /// Disconnect from the IRC server.
 ///
+/// A deliberate disconnect: the crash-recovery state file is deleted.
+///
 /// # Safety
 ///
 /// `handle` must be a valid handle from `freeq_win_create_client`.
@@ -284,6 +438,9 @@ pub unsafe extern "C" fn freeq_win_disconnect(handle: u64) -> i32 {
                 let _ = h.quit(Some("Goodbye")).await;
             });
             core.connected.store(false, Ordering::Release);
+            if let Some(file) = &core.state_file {
+                file.lock().remove();
+            }
             FfiResult::Ok as i32
         }
         None => FfiResult::NotConnected as i32,
@@ -683,6 +840,43 @@ pub unsafe extern "C" fn freeq_win_history_before(
     }
 }
 
+/// Request the whole thread a message belongs to (CHATHISTORY THREAD).
+///
+/// # Safety
+///
+/// `target` and `msgid` must be valid, NUL-terminated UTF-8 C strings, or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn freeq_win_fetch_thread(
+    handle: u64,
+    target: *const c_char,
+    msgid: *const c_char,
+) -> i32 {
+    let Some(core) = HANDLES.get(&handle) else {
+        return FfiResult::InvalidHandle as i32;
+    };
+    let Some(tgt) = (unsafe { read_c_str(target) }) else {
+        return FfiResult::InvalidArgument as i32;
+    };
+    let Some(mid) = (unsafe { read_c_str(msgid) }) else {
+        return FfiResult::InvalidArgument as i32;
+    };
+    let sdk = core.sdk_handle.lock().clone();
+    let Some(h) = sdk else {
+        return FfiResult::NotConnected as i32;
+    };
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    RUNTIME.spawn(async move {
+        let result = h.fetch_thread(&tgt, &mid).await;
+        let _ = tx.send(result);
+    });
+
+    match rx.recv() {
+        Ok(Ok(())) => FfiResult::Ok as i32,
+        _ => FfiResult::Internal as i32,
+    }
+}
+
 /// Pin a message in a channel.
 ///
 /// # Safety
@@ -885,22 +1079,41 @@ pub unsafe extern "C" fn freeq_win_mode(
 
 // ─── State Query ─────────────────────────────────────────────────────
 
+fn snapshot_json(core: &AppCore) -> String {
+    let presence: serde_json::Map<String, serde_json::Value> = core
+        .sdk_handle
+        .lock()
+        .as_ref()
+        .map(|h| h.presence_snapshot())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(id, state)| (id, state.as_str().into()))
+        .collect();
+    serde_json::json!({
+        "connected": core.connected.load(Ordering::Acquire),
+        "nick": *core.nick.lock(),
+        "server": core.server_addr,
+        "presence": presence,
+    })
+    .to_string()
+}
+
 /// Get a JSON snapshot of the client's current state.
 ///
-/// Returns a heap-allocated C string that must be freed with `freeq_win_free_string`.
-/// Returns null if the handle is invalid.
+/// Returns a caller-owned UTF-8 string, or null if the handle is invalid.
 ///
 /// # Safety
 ///
 /// `handle` must be a valid handle from `freeq_win_create_client`.
-/// The returned pointer must be freed with `freeq_win_free_string`.
+/// The returned pointer must be freed with `freeq_string_free`.
 ///
 /// Snapshot schema:
 /// ```json
 /// {
 ///   "connected": true,
 ///   "nick": "myuser",
-///   "server": "irc.example.com:6697"
+///   "server": "irc.example.com:6697",
+///   "presence": { "did:plc:abc": "online", "bob": "away" }
 /// }
 /// ```
 #[unsafe(no_mangle)]
@@ -908,38 +1121,332 @@ pub unsafe extern "C" fn freeq_win_get_snapshot_json(handle: u64) -> *mut c_char
     let Some(core) = HANDLES.get(&handle) else {
         return std::ptr::null_mut();
     };
+    strings::owned_utf8(&snapshot_json(&core))
+}
 
-    let snapshot = serde_json::json!({
-        "connected": core.connected.load(Ordering::Acquire),
-        "nick": *core.nick.lock(),
-        "server": core.server_addr,
+/// UTF-16 variant of `freeq_win_get_snapshot_json`, ready to wrap as an
+/// `HSTRING` or `System.String` without transcoding.
+///
+/// # Safety
+///
+/// `handle` must be a valid handle from `freeq_win_create_client`.
+/// The returned pointer must be freed with `freeq_string_free`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn freeq_win_get_snapshot_json_utf16(handle: u64) -> *mut u16 {
+    let Some(core) = HANDLES.get(&handle) else {
+        return std::ptr::null_mut();
+    };
+    strings::owned_utf16(&snapshot_json(&core))
+}
+
+fn channel_snapshot_json(core: &AppCore, channel: &str) -> Option<String> {
+    let state = core.sdk_handle.lock().as_ref()?.channel(channel)?;
+    let members: Vec<serde_json::Value> = state
+        .members
+        .iter()
+        .map(|m| {
+            serde_json::json!({
+                "nick": m.nick,
+                "account": m.account,
+                "is_op": m.op,
+                "is_halfop": m.halfop,
+                "is_voiced": m.voiced,
+                "away": m.away,
+            })
+        })
+        .collect();
+    Some(
+        serde_json::json!({
+            "channel": state.name,
+            "topic": state.topic,
+            "topic_set_by": state.topic_set_by,
+            "members": members,
+        })
+        .to_string(),
+    )
+}
+
+/// Get a JSON document with a joined channel's topic and full member list,
+/// for filling a roster in one go.
+///
+/// Returns a caller-owned UTF-8 string, or null if the handle is invalid,
+/// the client is not connected or it is not in `channel`.
+///
+/// # Safety
+///
+/// `channel` must be a valid, NUL-terminated UTF-8 C string, or null.
+/// The returned pointer must be freed with `freeq_string_free`.
+///
+/// Schema (members ordered ops, half-ops, voiced, then the rest, each by nick):
+/// ```json
+/// {
+///   "channel": "#freeq",
+///   "topic": "welcome",
+///   "topic_set_by": "alice",
+///   "members": [
+///     { "nick": "alice", "account": "did:plc:abc", "is_op": true,
+///       "is_halfop": false, "is_voiced": false, "away": false }
+///   ]
+/// }
+/// ```
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn freeq_win_get_channel_snapshot_json(
+    handle: u64,
+    channel: *const c_char,
+) -> *mut c_char {
+    let Some(core) = HANDLES.get(&handle) else {
+        return std::ptr::null_mut();
+    };
+    let Some(chan) = (unsafe { read_c_str(channel) }) else {
+        return std::ptr::null_mut();
+    };
+    match channel_snapshot_json(&core, &chan) {
+        Some(json) => strings::owned_utf8(&json),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// UTF-16 variant of `freeq_win_get_channel_snapshot_json`.
+///
+/// # Safety
+///
+/// Same contract as `freeq_win_get_channel_snapshot_json`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn freeq_win_get_channel_snapshot_json_utf16(
+    handle: u64,
+    channel: *const c_char,
+) -> *mut u16 {
+    let Some(core) = HANDLES.get(&handle) else {
+        return std::ptr::null_mut();
+    };
+    let Some(chan) = (unsafe { read_c_str(channel) }) else {
+        return std::ptr::null_mut();
+    };
+    match channel_snapshot_json(&core, &chan) {
+        Some(json) => strings::owned_utf16(&json),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Fetch a page of history and render it as a JSON document. Blocks until
+/// the server's batch has arrived or the SDK's call timeout passes.
+fn history_json(
+    core: &AppCore,
+    target: String,
+    before: Option<String>,
+    limit: u32,
+) -> Option<String> {
+    let h = core.sdk_handle.lock().clone()?;
+    let (tx, rx) = std::sync::mpsc::channel();
+    RUNTIME.spawn(async move {
+        let result = h
+            .fetch_history(
+                &target,
+                before.as_deref(),
+                limit as usize,
+                Default::default(),
+            )
+            .await;
+        let _ = tx.send((target, result));
     });
+    let (target, result) = rx.recv().ok()?;
+    let events = result
+        .inspect_err(|e| tracing::warn!(%target, error = %e, "history fetch failed"))
+        .ok()?;
+    let messages: Vec<serde_json::Value> = events
+        .iter()
+        .filter_map(|event| match convert_event(event) {
+            crate::event::DomainEvent::Message(data) => serde_json::to_value(data).ok(),
+            _ => None,
+        })
+        .collect();
+    Some(
+        serde_json::json!({
+            "target": target,
+            "messages": messages,
+        })
+        .to_string(),
+    )
+}
 
-    match CString::new(snapshot.to_string()) {
-        Ok(cstr) => cstr.into_raw(),
-        Err(_) => std::ptr::null_mut(),
+/// Fetch up to `limit` messages of `target` before `before_msgid` (the
+/// latest when null) as one JSON document, oldest first, for paging a
+/// virtualized message list. The messages are also delivered to the event
+/// callback as usual.
+///
+/// Blocks until the server has sent the page (up to 15 seconds), so call
+/// it off the UI thread. Returns a caller-owned UTF-8 string, or null if
+/// the handle is invalid, the client is not connected or the request
+/// failed or timed out.
+///
+/// # Safety
+///
+/// `target` must be a valid, NUL-terminated UTF-8 C string, or null.
+/// `before_msgid` must be null or a valid, NUL-terminated UTF-8 C string.
+/// The returned pointer must be freed with `freeq_string_free`.
+///
+/// Schema (each message has the fields of a `message` event's `data`):
+/// ```json
+/// {
+///   "target": "#freeq",
+///   "messages": [
+///     { "from_nick": "alice", "target": "#freeq", "text": "hi",
+///       "msgid": "01J...", "timestamp_ms": 1700000000000, ... }
+///   ]
+/// }
+/// ```
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn freeq_win_get_history_json(
+    handle: u64,
+    target: *const c_char,
+    before_msgid: *const c_char,
+    limit: u32,
+) -> *mut c_char {
+    let Some(core) = HANDLES.get(&handle).map(|c| Arc::clone(&c)) else {
+        return std::ptr::null_mut();
+    };
+    let Some(tgt) = (unsafe { read_c_str(target) }) else {
+        return std::ptr::null_mut();
+    };
+    let before = unsafe { read_c_str(before_msgid) };
+    match history_json(&core, tgt, before, limit) {
+        Some(json) => strings::owned_utf8(&json),
+        None => std::ptr::null_mut(),
     }
 }
 
-/// Free a string previously returned by `freeq_win_get_snapshot_json`.
+/// UTF-16 variant of `freeq_win_get_history_json`.
 ///
 /// # Safety
 ///
-/// `ptr` must be null or a pointer previously returned by `freeq_win_get_snapshot_json`.
-/// Must not be called more than once for the same pointer.
+/// Same contract as `freeq_win_get_history_json`.
 #[unsafe(no_mangle)]
-pub unsafe extern "C" fn freeq_win_free_string(ptr: *mut c_char) {
-    if !ptr.is_null() {
-        drop(unsafe { CString::from_raw(ptr) });
+pub unsafe extern "C" fn freeq_win_get_history_json_utf16(
+    handle: u64,
+    target: *const c_char,
+    before_msgid: *const c_char,
+    limit: u32,
+) -> *mut u16 {
+    let Some(core) = HANDLES.get(&handle).map(|c| Arc::clone(&c)) else {
+        return std::ptr::null_mut();
+    };
+    let Some(tgt) = (unsafe { read_c_str(target) }) else {
+        return std::ptr::null_mut();
+    };
+    let before = unsafe { read_c_str(before_msgid) };
+    match history_json(&core, tgt, before, limit) {
+        Some(json) => strings::owned_utf16(&json),
+        None => std::ptr::null_mut(),
     }
 }
 
+/// Get the client's current nick as a caller-owned UTF-8 string.
+/// Returns null if the handle is invalid.
+///
+/// # Safety
+///
+/// `handle` must be a valid handle from `freeq_win_create_client`.
+/// The returned pointer must be freed with `freeq_string_free`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn freeq_win_get_nick(handle: u64) -> *mut c_char {
+    let Some(core) = HANDLES.get(&handle) else {
+        return std::ptr::null_mut();
+    };
+    let nick = core.nick.lock();
+    strings::owned_utf8(&nick)
+}
+
+/// UTF-16 variant of `freeq_win_get_nick`.
+///
+/// # Safety
+///
+/// `handle` must be a valid handle from `freeq_win_create_client`.
+/// The returned pointer must be freed with `freeq_string_free`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn freeq_win_get_nick_utf16(handle: u64) -> *mut u16 {
+    let Some(core) = HANDLES.get(&handle) else {
+        return std::ptr::null_mut();
+    };
+    let nick = core.nick.lock();
+    strings::owned_utf16(&nick)
+}
+
+/// Copy the client's current nick into a caller-allocated buffer of
+/// `buf_len` bytes, NUL-terminated. `*out_len` receives the nick's length
+/// in bytes, excluding the terminator, even when the buffer is too small.
+///
+/// Returns `Ok`, `InvalidHandle`, or `BufferTooSmall` (nothing written).
+/// Pass a null `buf` with `buf_len = 0` to query the length.
+///
+/// # Safety
+///
+/// `buf` must be null or valid for `buf_len` bytes of writes. `out_len`
+/// must be null or point to a writable `size_t`. Nothing is retained.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn freeq_win_copy_nick(
+    handle: u64,
+    buf: *mut c_char,
+    buf_len: usize,
+    out_len: *mut usize,
+) -> i32 {
+    let Some(core) = HANDLES.get(&handle) else {
+        return FfiResult::InvalidHandle as i32;
+    };
+    let nick = core.nick.lock();
+    (unsafe { strings::copy_utf8(&nick, buf, buf_len, out_len) }) as i32
+}
+
+/// UTF-16 variant of `freeq_win_copy_nick`: `buf_len` and `*out_len` are
+/// counted in UTF-16 code units.
+///
+/// # Safety
+///
+/// `buf` must be null or valid for `buf_len` `uint16_t` writes. `out_len`
+/// must be null or point to a writable `size_t`. Nothing is retained.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn freeq_win_copy_nick_utf16(
+    handle: u64,
+    buf: *mut u16,
+    buf_len: usize,
+    out_len: *mut usize,
+) -> i32 {
+    let Some(core) = HANDLES.get(&handle) else {
+        return FfiResult::InvalidHandle as i32;
+    };
+    let nick = core.nick.lock();
+    (unsafe { strings::copy_utf16(&nick, buf, buf_len, out_len) }) as i32
+}
+
+// ─── Strings ─────────────────────────────────────────────────────────
+
+/// Free any string returned by a `freeq_*` function, UTF-8 or UTF-16.
+///
+/// # Safety
+///
+/// `ptr` must be null or a pointer previously returned by a `freeq_*`
+/// function documented as caller-owned. Must not be called more than once
+/// for the same pointer, nor on callback payloads or `_copy_` buffers.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn freeq_string_free(ptr: *mut c_void) {
+    unsafe { strings::free(ptr) };
+}
+
+/// Deprecated alias for `freeq_string_free`, kept for existing callers.
+///
+/// # Safety
+///
+/// Same contract as `freeq_string_free`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn freeq_win_free_string(ptr: *mut c_char) {
+    unsafe { strings::free(ptr.cast()) };
+}
+
 // ─── Tests ───────────────────────────────────────────────────────────
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::ffi::CString;
+    use std::ffi::{CStr, CString};
 
     fn make_config(json: &str) -> CString {
         CString::new(json).unwrap()
@@ -962,6 +1469,15 @@ mod tests {
         assert_eq!(handle, 0);
     }
 
+    #[test]
+    fn test_create_rejects_bad_tls_pin() {
+        let config = make_config(
+            r#"{"server":"127.0.0.1:6697","tls":true,"tls_options":{"spki_pins":["md5/abc"]}}"#,
+        );
+        let handle = unsafe { freeq_win_create_client(config.as_ptr()) };
+        assert_eq!(handle, 0);
+    }
+
     #[test]
     fn test_create_invalid_json() {
         let config = make_config("not json");
@@ -1021,7 +1537,7 @@ mod tests {
         assert_eq!(parsed["nick"], "snapuser");
         assert_eq!(parsed["server"], "localhost:6667");
 
-        unsafe { freeq_win_free_string(ptr) };
+        unsafe { freeq_string_free(ptr.cast()) };
         unsafe { freeq_win_destroy_client(handle) };
     }
 
@@ -1031,6 +1547,33 @@ mod tests {
         assert!(ptr.is_null());
     }
 
+    #[test]
+    fn test_channel_snapshot_and_history_not_connected() {
+        let config = make_config(r#"{"server":"127.0.0.1:6667","nick":"test"}"#);
+        let handle = unsafe { freeq_win_create_client(config.as_ptr()) };
+        let chan = CString::new("#test").unwrap();
+
+        assert!(unsafe { freeq_win_get_channel_snapshot_json(handle, chan.as_ptr()) }.is_null());
+        assert!(
+            unsafe { freeq_win_get_channel_snapshot_json_utf16(handle, chan.as_ptr()) }.is_null()
+        );
+        assert!(unsafe { freeq_win_get_channel_snapshot_json(handle, std::ptr::null()) }.is_null());
+        assert!(unsafe { freeq_win_get_channel_snapshot_json(999999, chan.as_ptr()) }.is_null());
+
+        let history = |before: *const c_char| unsafe {
+            freeq_win_get_history_json(handle, chan.as_ptr(), before, 50)
+        };
+        assert!(history(std::ptr::null()).is_null());
+        let msgid = CString::new("01J0000000").unwrap();
+        assert!(history(msgid.as_ptr()).is_null());
+        assert!(
+            unsafe { freeq_win_get_history_json(999999, chan.as_ptr(), std::ptr::null(), 50) }
+                .is_null()
+        );
+
+        unsafe { freeq_win_destroy_client(handle) };
+    }
+
     #[test]
     fn test_set_web_token() {
         let config = make_config(r#"{"server":"127.0.0.1:6667","nick":"test"}"#);
@@ -1095,9 +1638,46 @@ mod tests {
     #[test]
     fn test_free_null_string() {
         // Should not crash
+        unsafe { freeq_string_free(std::ptr::null_mut()) };
         unsafe { freeq_win_free_string(std::ptr::null_mut()) };
     }
 
+    #[test]
+    fn test_nick_getters() {
+        let config = make_config(r#"{"server":"127.0.0.1:6667","nick":"zoë"}"#);
+        let handle = unsafe { freeq_win_create_client(config.as_ptr()) };
+
+        let ptr = unsafe { freeq_win_get_nick(handle) };
+        assert_eq!(unsafe { CStr::from_ptr(ptr) }.to_str().unwrap(), "zoë");
+        unsafe { freeq_string_free(ptr.cast()) };
+
+        let ptr = unsafe { freeq_win_get_nick_utf16(handle) };
+        let units = unsafe { std::slice::from_raw_parts(ptr, 4) };
+        assert_eq!(units, [b'z' as u16, b'o' as u16, 0xeb, 0]);
+        unsafe { freeq_string_free(ptr.cast()) };
+
+        // Size query, then copy.
+        let mut len = 0;
+        let result =
+            unsafe { freeq_win_copy_nick_utf16(handle, std::ptr::null_mut(), 0, &mut len) };
+        assert_eq!((result, len), (FfiResult::BufferTooSmall as i32, 3));
+        let mut buf = vec![0u16; len + 1];
+        let result =
+            unsafe { freeq_win_copy_nick_utf16(handle, buf.as_mut_ptr(), buf.len(), &mut len) };
+        assert_eq!(result, FfiResult::Ok as i32);
+        assert_eq!(String::from_utf16(&buf[..len]).unwrap(), "zoë");
+
+        let mut buf = [0 as c_char; 16];
+        let result = unsafe { freeq_win_copy_nick(handle, buf.as_mut_ptr(), buf.len(), &mut len) };
+        assert_eq!((result, len), (FfiResult::Ok as i32, 4));
+
+        let result = unsafe { freeq_win_copy_nick(999999, buf.as_mut_ptr(), buf.len(), &mut len) };
+        assert_eq!(result, FfiResult::InvalidHandle as i32);
+        assert!(unsafe { freeq_win_get_nick_utf16(999999) }.is_null());
+
+        unsafe { freeq_win_destroy_client(handle) };
+    }
+
     #[test]
     fn test_config_defaults() {
         // Minimal config — server and nick should get defaults
@@ -1111,7 +1691,62 @@ mod tests {
         assert_eq!(parsed["server"], "127.0.0.1:6667");
         assert_eq!(parsed["nick"], "freeq_user");
 
-        unsafe { freeq_win_free_string(ptr) };
+        unsafe { freeq_string_free(ptr.cast()) };
+        unsafe { freeq_win_destroy_client(handle) };
+    }
+
+    #[test]
+    fn test_restore_state() {
+        let dir = std::env::temp_dir().join(format!("freeq-abi-restore-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let config = make_config(
+            &serde_json::json!({"server": "127.0.0.1:6667", "nick": "test", "state_dir": dir})
+                .to_string(),
+        );
+
+        // Nothing saved yet
+        let handle = unsafe { freeq_win_create_client(config.as_ptr()) };
+        assert_eq!(
+            unsafe { freeq_win_restore_state(handle) },
+            FfiResult::NotFound as i32
+        );
+        unsafe { freeq_win_destroy_client(handle) };
+
+        // What a crashed run left behind
+        let mut file = StateFile::new(&dir, "127.0.0.1:6667", "test");
+        file.update(true, |s| {
+            s.nick = "test_".to_string();
+            s.channels = vec!["#freeq".to_string()];
+            s.record_msgid("#freeq", "01J00000000000000000000001")
+        });
+        let path = file.path().to_path_buf();
+        assert!(path.exists());
+
+        let handle = unsafe { freeq_win_create_client(config.as_ptr()) };
+        assert_eq!(
+            unsafe { freeq_win_restore_state(handle) },
+            FfiResult::Ok as i32
+        );
+        assert_eq!(*HANDLES.get(&handle).unwrap().nick.lock(), "test_");
+        assert!(HANDLES
+            .get(&handle)
+            .unwrap()
+            .pending_restore
+            .lock()
+            .is_some());
+        // Only once
+        assert_eq!(
+            unsafe { freeq_win_restore_state(handle) },
+            FfiResult::NotFound as i32
+        );
+
+        // Destroying the client is deliberate: nothing left to restore
         unsafe { freeq_win_destroy_client(handle) };
+        assert!(!path.exists());
+        assert_eq!(
+            unsafe { freeq_win_restore_state(handle) },
+            FfiResult::InvalidHandle as i32
+        );
+        let _ = std::fs::remove_dir_all(&dir);
     }
 }
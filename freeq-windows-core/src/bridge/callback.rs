@@ -3,6 +3,9 @@
 use std::ffi::{c_char, c_void, CString};
 
 /// C callback signature: receives a UTF-8 JSON string (pointer + length) and opaque user data.
+///
+/// The payload is borrowed: it is valid only until the callback returns,
+/// must be copied if kept, and must never be passed to `freeq_string_free`.
 pub type EventCallback =
     unsafe extern "C" fn(json_ptr: *const c_char, json_len: usize, user_data: *mut c_void);
 
@@ -59,6 +59,7 @@ mod tests {
             batch_id: None,
             is_action: false,
             timestamp_ms: 1700000000000,
+            formatted_text: None,
         });
         let envelope = EventEnvelope::new(1, event);
         let json = serde_json::to_string(&envelope).unwrap();
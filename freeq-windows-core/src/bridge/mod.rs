@@ -3,3 +3,4 @@
 pub mod abi;
 pub mod callback;
 pub mod envelope;
+pub mod strings;
@@ -0,0 +1,215 @@
+//! String marshaling for the C ABI.
+//!
+//! Every `freeq_*` export follows the same rules:
+//!
+//! - **String arguments** are NUL-terminated UTF-8 (`const char*`),
+//!   borrowed for the duration of the call only. Null or invalid UTF-8
+//!   is rejected (`InvalidArgument`, or the function's failure value).
+//! - **Returned strings** are NUL-terminated, owned by the caller, and
+//!   released exactly once with `freeq_string_free`, whether UTF-8
+//!   (`char*`) or UTF-16 (`uint16_t*`, the `_utf16` variants). A null
+//!   return is a failure and must not be freed.
+//! - **`_copy_` variants** write into a caller-allocated buffer of
+//!   `buf_len` units (bytes, or UTF-16 code units) and store the string's
+//!   length in units, excluding the terminator, in `*out_len`. If it
+//!   doesn't fit with its terminator they write nothing and return
+//!   `BufferTooSmall`, so a call with `buf_len = 0` queries the size.
+//! - **Callback payloads** are borrowed: valid only until the callback
+//!   returns.
+//!
+//! Returned strings carry a hidden size header in front of the pointer
+//! the caller sees, which is how a single `freeq_string_free` releases
+//! both encodings.
+
+use std::alloc::{alloc, dealloc, Layout};
+use std::ffi::{c_char, c_void, CStr};
+use std::mem::{align_of, size_of};
+use std::ptr;
+
+use crate::error::FfiResult;
+
+/// Bytes in front of every returned string, holding its allocation size.
+const HEADER: usize = size_of::<usize>();
+
+/// Read a borrowed C string argument. `None` on null or invalid UTF-8.
+///
+/// # Safety
+///
+/// `ptr` must be null or point to a NUL-terminated string that stays
+/// valid for the duration of the call.
+pub(crate) unsafe fn read_c_str(ptr: *const c_char) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    unsafe { CStr::from_ptr(ptr) }
+        .to_str()
+        .ok()
+        .map(String::from)
+}
+
+/// Allocate `bytes` plus `terminator` zero bytes behind a size header.
+/// Returns the address just past the header, or null if allocation fails.
+fn alloc_owned(bytes: &[u8], terminator: usize) -> *mut u8 {
+    let size = HEADER + bytes.len() + terminator;
+    let Ok(layout) = Layout::from_size_align(size, align_of::<usize>()) else {
+        return ptr::null_mut();
+    };
+    unsafe {
+        let base = alloc(layout);
+        if base.is_null() {
+            return ptr::null_mut();
+        }
+        base.cast::<usize>().write(size);
+        let data = base.add(HEADER);
+        ptr::copy_nonoverlapping(bytes.as_ptr(), data, bytes.len());
+        ptr::write_bytes(data.add(bytes.len()), 0, terminator);
+        data
+    }
+}
+
+/// Hand `s` to the caller as an owned UTF-8 string. Null if `s` contains
+/// a NUL, which the caller couldn't tell from the terminator.
+pub(crate) fn owned_utf8(s: &str) -> *mut c_char {
+    if s.as_bytes().contains(&0) {
+        return ptr::null_mut();
+    }
+    alloc_owned(s.as_bytes(), 1).cast()
+}
+
+/// Hand `s` to the caller as an owned UTF-16 string. Null if `s`
+/// contains a NUL.
+pub(crate) fn owned_utf16(s: &str) -> *mut u16 {
+    let units: Vec<u16> = s.encode_utf16().collect();
+    if units.contains(&0) {
+        return ptr::null_mut();
+    }
+    let bytes = unsafe { std::slice::from_raw_parts(units.as_ptr().cast::<u8>(), units.len() * 2) };
+    alloc_owned(bytes, size_of::<u16>()).cast()
+}
+
+/// Release a string returned by [`owned_utf8`] or [`owned_utf16`].
+///
+/// # Safety
+///
+/// `ptr` must be null or a pointer returned by one of them, not yet freed.
+pub(crate) unsafe fn free(ptr: *mut c_void) {
+    if ptr.is_null() {
+        return;
+    }
+    unsafe {
+        let base = ptr.cast::<u8>().sub(HEADER);
+        let size = base.cast::<usize>().read();
+        dealloc(
+            base,
+            Layout::from_size_align_unchecked(size, align_of::<usize>()),
+        );
+    }
+}
+
+/// Copy `s` into a caller buffer as UTF-8; see the module docs.
+///
+/// # Safety
+///
+/// `buf` must be null or valid for `buf_len` bytes of writes; `out_len`
+/// must be null or valid for one write.
+pub(crate) unsafe fn copy_utf8(
+    s: &str,
+    buf: *mut c_char,
+    buf_len: usize,
+    out_len: *mut usize,
+) -> FfiResult {
+    unsafe { copy_units(s.as_bytes(), buf.cast::<u8>(), buf_len, out_len) }
+}
+
+/// Copy `s` into a caller buffer as UTF-16; see the module docs.
+///
+/// # Safety
+///
+/// `buf` must be null or valid for `buf_len` `u16` writes; `out_len`
+/// must be null or valid for one write.
+pub(crate) unsafe fn copy_utf16(
+    s: &str,
+    buf: *mut u16,
+    buf_len: usize,
+    out_len: *mut usize,
+) -> FfiResult {
+    let units: Vec<u16> = s.encode_utf16().collect();
+    unsafe { copy_units(&units, buf, buf_len, out_len) }
+}
+
+unsafe fn copy_units<T: Copy + Default>(
+    units: &[T],
+    buf: *mut T,
+    buf_len: usize,
+    out_len: *mut usize,
+) -> FfiResult {
+    if !out_len.is_null() {
+        unsafe { out_len.write(units.len()) };
+    }
+    if buf.is_null() && buf_len > 0 {
+        return FfiResult::InvalidArgument;
+    }
+    if units.len() >= buf_len {
+        return FfiResult::BufferTooSmall;
+    }
+    unsafe {
+        ptr::copy_nonoverlapping(units.as_ptr(), buf, units.len());
+        buf.add(units.len()).write(T::default());
+    }
+    FfiResult::Ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn owned_strings_round_trip_and_free() {
+        let p = owned_utf8("héllo");
+        assert_eq!(unsafe { CStr::from_ptr(p) }.to_str().unwrap(), "héllo");
+        unsafe { free(p.cast()) };
+
+        let p = owned_utf16("héllo 🌍");
+        let expected: Vec<u16> = "héllo 🌍".encode_utf16().collect();
+        let got = unsafe { std::slice::from_raw_parts(p, expected.len() + 1) };
+        assert_eq!(&got[..expected.len()], &expected[..]);
+        assert_eq!(got[expected.len()], 0);
+        unsafe { free(p.cast()) };
+
+        let p = owned_utf8("");
+        assert_eq!(unsafe { *p }, 0);
+        unsafe { free(p.cast()) };
+        unsafe { free(ptr::null_mut()) };
+    }
+
+    #[test]
+    fn interior_nul_is_refused() {
+        assert!(owned_utf8("a\0b").is_null());
+        assert!(owned_utf16("a\0b").is_null());
+    }
+
+    #[test]
+    fn copy_reports_length_and_refuses_short_buffers() {
+        let mut len = 0;
+        let result = unsafe { copy_utf16("nick", ptr::null_mut(), 0, &mut len) };
+        assert_eq!((result, len), (FfiResult::BufferTooSmall, 4));
+
+        let mut buf = [0xffffu16; 4];
+        let result = unsafe { copy_utf16("nick", buf.as_mut_ptr(), buf.len(), &mut len) };
+        assert_eq!(result, FfiResult::BufferTooSmall);
+        assert_eq!(buf, [0xffff; 4], "nothing written on failure");
+
+        let mut buf = [0xffffu16; 5];
+        let result = unsafe { copy_utf16("nick", buf.as_mut_ptr(), buf.len(), &mut len) };
+        assert_eq!(result, FfiResult::Ok);
+        assert_eq!(String::from_utf16(&buf[..len]).unwrap(), "nick");
+        assert_eq!(buf[len], 0);
+
+        let mut buf = [0 as c_char; 8];
+        let result = unsafe { copy_utf8("é", buf.as_mut_ptr(), buf.len(), &mut len) };
+        assert_eq!((result, len), (FfiResult::Ok, 2));
+
+        let result = unsafe { copy_utf8("x", ptr::null_mut(), 8, ptr::null_mut()) };
+        assert_eq!(result, FfiResult::InvalidArgument);
+    }
+}
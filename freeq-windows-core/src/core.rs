@@ -5,6 +5,7 @@ use std::sync::atomic::AtomicBool;
 use parking_lot::Mutex;
 
 use crate::bridge::callback::CallbackSink;
+use crate::restore::{RestoreState, StateFile};
 
 /// Per-client state. One instance per `freeq_win_create_client` call.
 ///
@@ -26,8 +27,28 @@ pub struct AppCore {
     pub initial_nick: String,
     /// Whether to use TLS.
     pub tls: bool,
+    /// Custom roots, SPKI pins and ALPN (validated at create time).
+    pub tls_options: freeq_sdk::tls::TlsOptions,
     /// Web token for SASL authentication (consumed on connect).
     pub web_token: Mutex<Option<String>>,
     /// Channels the client has joined (for reconnect re-join).
     pub channels: Mutex<Vec<String>>,
+    /// Whether the server has accepted registration on this connection.
+    pub registered: AtomicBool,
+    /// Crash-recovery state file (None when there's no app data dir).
+    pub state_file: Option<Mutex<StateFile>>,
+    /// What the previous run left in the state file, until restored.
+    pub saved_state: Mutex<Option<RestoreState>>,
+    /// A restore waiting for registration.
+    pub pending_restore: Mutex<Option<RestoreState>>,
+}
+
+impl AppCore {
+    /// Update the crash-recovery state, if it is kept. `urgent` changes
+    /// are written at once.
+    pub fn update_state(&self, urgent: bool, f: impl FnOnce(&mut RestoreState) -> bool) {
+        if let Some(file) = &self.state_file {
+            file.lock().update(urgent, f);
+        }
+    }
 }
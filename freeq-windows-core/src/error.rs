@@ -17,4 +17,8 @@ pub enum FfiResult {
     NotConnected = 3,
     /// An internal error occurred (logged via tracing).
     Internal = 4,
+    /// A caller-allocated buffer was too small; nothing was written.
+    BufferTooSmall = 5,
+    /// There was nothing to restore.
+    NotFound = 6,
 }
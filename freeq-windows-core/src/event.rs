@@ -1,5 +1,6 @@
 //! SDK Event → DomainEvent conversion with JSON serialization.
 
+use freeq_proto::tags as tag;
 use serde::Serialize;
 use std::collections::HashMap;
 
@@ -12,6 +13,27 @@ pub struct MemberInfo {
     pub is_voiced: bool,
 }
 
+/// A channel member in a roster change.
+#[derive(Debug, Clone, Serialize)]
+pub struct RosterMemberData {
+    pub nick: String,
+    pub account: Option<String>,
+    /// `@`, `%`, `+` or empty.
+    pub prefix: String,
+    pub is_away: bool,
+}
+
+impl From<&freeq_sdk::channels::Member> for RosterMemberData {
+    fn from(member: &freeq_sdk::channels::Member) -> Self {
+        Self {
+            nick: member.nick.clone(),
+            account: member.account.clone(),
+            prefix: member.prefix().to_string(),
+            is_away: member.away,
+        }
+    }
+}
+
 /// An IRC message with parsed tag metadata.
 #[derive(Debug, Clone, Serialize)]
 pub struct MessageData {
@@ -24,6 +46,9 @@ pub struct MessageData {
     pub batch_id: Option<String>,
     pub is_action: bool,
     pub timestamp_ms: i64,
+    /// `text` as markdown when it carries IRC formatting; `None` for
+    /// plain text.
+    pub formatted_text: Option<String>,
 }
 
 /// A tag-only message (TAGMSG).
@@ -69,6 +94,15 @@ pub enum DomainEvent {
     },
     Message(MessageData),
     TagMsg(TagMsgData),
+    /// Follows the reply's `message` event.
+    ThreadReply {
+        from: String,
+        target: String,
+        text: String,
+        msgid: String,
+        parent_msgid: String,
+        root_msgid: String,
+    },
     Names {
         channel: String,
         members: Vec<MemberInfo>,
@@ -90,6 +124,10 @@ pub enum DomainEvent {
         old_nick: String,
         new_nick: String,
     },
+    /// Our effective nick, on registration and whenever it changes.
+    NickAssigned {
+        nick: String,
+    },
     AwayChanged {
         nick: String,
         away_msg: Option<String>,
@@ -106,6 +144,24 @@ pub enum DomainEvent {
     BatchEnd {
         id: String,
     },
+    /// `state` is `"online"`, `"away"` or `"offline"`.
+    PresenceChanged {
+        did_or_nick: String,
+        state: String,
+    },
+    /// Roster deltas; see `freeq_sdk::channels`.
+    MemberAdded {
+        channel: String,
+        member: RosterMemberData,
+    },
+    MemberRemoved {
+        channel: String,
+        nick: String,
+    },
+    MemberChanged {
+        channel: String,
+        member: RosterMemberData,
+    },
     Notice {
         text: String,
     },
@@ -124,6 +180,13 @@ pub fn convert_event(event: &freeq_sdk::event::Event) -> DomainEvent {
         Event::AuthFailed { reason } => DomainEvent::AuthFailed {
             reason: reason.clone(),
         },
+        Event::CapabilitiesAcked {
+            acked,
+            rejected,
+            unavailable,
+        } => DomainEvent::Notice {
+            text: capabilities_text(acked, rejected, unavailable),
+        },
         Event::Joined { channel, nick, .. } => DomainEvent::Joined {
             channel: channel.clone(),
             nick: nick.clone(),
@@ -137,11 +200,13 @@ pub fn convert_event(event: &freeq_sdk::event::Event) -> DomainEvent {
             target,
             text,
             tags,
+            formatted_text,
+            ..
         } => {
-            let msgid = tags.get("msgid").cloned();
-            let reply_to = tags.get("+reply").cloned();
-            let edit_of = tags.get("+draft/edit").cloned();
-            let batch_id = tags.get("batch").cloned();
+            let msgid = tags.get(tag::MSGID).cloned();
+            let reply_to = tags.get(tag::REPLY).cloned();
+            let edit_of = tags.get(tag::EDIT).cloned();
+            let batch_id = tags.get(tag::BATCH).cloned();
             let is_action = text.starts_with("\x01ACTION ") && text.ends_with('\x01');
             let clean_text = if is_action {
                 text.trim_start_matches("\x01ACTION ")
@@ -150,8 +215,13 @@ pub fn convert_event(event: &freeq_sdk::event::Event) -> DomainEvent {
             } else {
                 text.clone()
             };
+            let formatted_text = if is_action {
+                freeq_sdk::format::formatted_text(&clean_text)
+            } else {
+                formatted_text.clone()
+            };
             let ts = tags
-                .get("time")
+                .get(tag::TIME)
                 .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
                 .map(|dt: chrono::DateTime<chrono::FixedOffset>| dt.timestamp_millis())
                 .unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
@@ -165,6 +235,7 @@ pub fn convert_event(event: &freeq_sdk::event::Event) -> DomainEvent {
                 batch_id,
                 is_action,
                 timestamp_ms: ts,
+                formatted_text,
             })
         }
         Event::TagMsg { from, target, tags } => DomainEvent::TagMsg(TagMsgData {
@@ -237,6 +308,7 @@ pub fn convert_event(event: &freeq_sdk::event::Event) -> DomainEvent {
             old_nick: old_nick.clone(),
             new_nick: new_nick.clone(),
         },
+        Event::NickAssigned { nick } => DomainEvent::NickAssigned { nick: nick.clone() },
         Event::AwayChanged { nick, away_msg } => DomainEvent::AwayChanged {
             nick: nick.clone(),
             away_msg: away_msg.clone(),
@@ -268,10 +340,53 @@ pub fn convert_event(event: &freeq_sdk::event::Event) -> DomainEvent {
         Event::ChatHistoryTarget { nick, timestamp } => DomainEvent::Notice {
             text: format!("DM: {nick} (last: {})", timestamp.as_deref().unwrap_or("?")),
         },
+        Event::ThreadReply {
+            from,
+            target,
+            text,
+            msgid,
+            parent_msgid,
+            root_msgid,
+        } => DomainEvent::ThreadReply {
+            from: from.clone(),
+            target: target.clone(),
+            text: text.clone(),
+            msgid: msgid.clone(),
+            parent_msgid: parent_msgid.clone(),
+            root_msgid: root_msgid.clone(),
+        },
+        Event::PresenceChanged { did_or_nick, state } => DomainEvent::PresenceChanged {
+            did_or_nick: did_or_nick.clone(),
+            state: state.as_str().to_string(),
+        },
+        Event::MemberAdded { channel, member } => DomainEvent::MemberAdded {
+            channel: channel.clone(),
+            member: member.into(),
+        },
+        Event::MemberRemoved { channel, nick } => DomainEvent::MemberRemoved {
+            channel: channel.clone(),
+            nick: nick.clone(),
+        },
+        Event::MemberChanged { channel, member } => DomainEvent::MemberChanged {
+            channel: channel.clone(),
+            member: member.into(),
+        },
         Event::RawLine(line) => DomainEvent::Notice { text: line.clone() },
     }
 }
 
+/// One status line for `Event::CapabilitiesAcked`.
+fn capabilities_text(acked: &[String], rejected: &[String], unavailable: &[String]) -> String {
+    let mut text = format!("Capabilities: {}", acked.join(" "));
+    if !rejected.is_empty() {
+        text.push_str(&format!(" (refused: {})", rejected.join(" ")));
+    }
+    if !unavailable.is_empty() {
+        text.push_str(&format!(" (not offered: {})", unavailable.join(" ")));
+    }
+    text
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -288,6 +403,8 @@ mod tests {
             target: "#test".to_string(),
             text: "hello world".to_string(),
             tags,
+            formatted_text: None,
+            encrypted: false,
         };
 
         let domain = convert_event(&event);
@@ -308,6 +425,8 @@ mod tests {
             target: "#test".to_string(),
             text: "\x01ACTION waves\x01".to_string(),
             tags: HashMap::new(),
+            formatted_text: None,
+            encrypted: false,
         };
 
         let domain = convert_event(&event);
@@ -362,6 +481,38 @@ mod tests {
         assert_eq!(json["data"]["reason"], "timeout");
     }
 
+    #[test]
+    fn test_convert_presence_changed() {
+        let event = freeq_sdk::event::Event::PresenceChanged {
+            did_or_nick: "did:plc:alice".to_string(),
+            state: freeq_sdk::presence::PresenceState::Away,
+        };
+        let domain = convert_event(&event);
+        let json = serde_json::to_value(&domain).unwrap();
+        assert_eq!(json["type"], "presence_changed");
+        assert_eq!(json["data"]["did_or_nick"], "did:plc:alice");
+        assert_eq!(json["data"]["state"], "away");
+    }
+
+    #[test]
+    fn test_convert_member_changed() {
+        let event = freeq_sdk::event::Event::MemberChanged {
+            channel: "#test".to_string(),
+            member: freeq_sdk::channels::Member {
+                nick: "alice".to_string(),
+                halfop: true,
+                away: true,
+                ..Default::default()
+            },
+        };
+        let domain = convert_event(&event);
+        let json = serde_json::to_value(&domain).unwrap();
+        assert_eq!(json["type"], "member_changed");
+        assert_eq!(json["data"]["channel"], "#test");
+        assert_eq!(json["data"]["member"]["prefix"], "%");
+        assert!(json["data"]["member"]["is_away"].as_bool().unwrap());
+    }
+
     #[test]
     fn test_convert_message_with_edit_tag() {
         let mut tags = HashMap::new();
@@ -373,6 +524,8 @@ mod tests {
             target: "#test".to_string(),
             text: "edited text".to_string(),
             tags,
+            formatted_text: None,
+            encrypted: false,
         };
 
         let domain = convert_event(&event);
@@ -7,6 +7,7 @@ pub mod bridge;
 pub mod core;
 pub mod error;
 pub mod event;
+pub mod restore;
 
 use once_cell::sync::Lazy;
 
@@ -0,0 +1,247 @@
+//! Crash-recovery state file.
+//!
+//! While a client is connected the bridge keeps a small JSON file in the
+//! app data directory with the server, the nick, the joined channels and
+//! the newest msgid handed to the host for each conversation. If the host
+//! crashes, `freeq_win_restore_state` on the next launch reads it back:
+//! the nick is reused, and once registered the client rejoins the channels
+//! and backfills each conversation with CHATHISTORY from where it left off.
+//!
+//! Membership and nick changes are written at once; msgids at most every
+//! [`SAVE_INTERVAL`], which only means a slightly larger backfill. Files
+//! are replaced atomically (write to a temporary file, then rename). A
+//! deliberate disconnect deletes the file, so there is only something to
+//! restore after a crash or a lost connection.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// Minimum time between writes triggered only by new msgids.
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Most messages backfilled per conversation on restore.
+pub const BACKFILL_LIMIT: usize = 100;
+
+/// What is written to the state file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RestoreState {
+    pub server: String,
+    pub nick: String,
+    /// Joined channels, in join order.
+    pub channels: Vec<String>,
+    /// Conversation (channel, or the other nick for DMs) → newest msgid
+    /// delivered to the host.
+    #[serde(default)]
+    pub last_read: BTreeMap<String, String>,
+    /// Unix milliseconds of the last write.
+    #[serde(default)]
+    pub saved_at_ms: i64,
+}
+
+impl RestoreState {
+    /// Record `msgid` as the newest in `conversation`, unless a newer one
+    /// is already recorded (msgids are ULIDs, so they sort by time).
+    /// Returns whether anything changed.
+    pub fn record_msgid(&mut self, conversation: &str, msgid: &str) -> bool {
+        match self.last_read.get(conversation) {
+            Some(newest) if newest.as_str() >= msgid => false,
+            _ => {
+                self.last_read
+                    .insert(conversation.to_string(), msgid.to_string());
+                true
+            }
+        }
+    }
+}
+
+/// The state file for one client: in memory, and where it is saved.
+pub struct StateFile {
+    path: PathBuf,
+    state: RestoreState,
+    dirty: bool,
+    last_save: Option<Instant>,
+    /// Set by [`StateFile::remove`]; nothing is written until
+    /// [`StateFile::reset`].
+    closed: bool,
+}
+
+impl StateFile {
+    /// The state file for `server` and `nick` under `dir`.
+    pub fn new(dir: &Path, server: &str, nick: &str) -> Self {
+        Self {
+            path: dir.join(file_name(server, nick)),
+            state: RestoreState {
+                server: server.to_string(),
+                nick: nick.to_string(),
+                ..Default::default()
+            },
+            dirty: false,
+            last_save: None,
+            closed: false,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Read the saved state, if there is one.
+    pub fn load(&self) -> Option<RestoreState> {
+        let bytes = std::fs::read(&self.path).ok()?;
+        serde_json::from_slice(&bytes)
+            .inspect_err(|e| tracing::warn!(path = %self.path.display(), "bad state file: {e}"))
+            .ok()
+    }
+
+    /// Start over for a new connection: empty, and written again.
+    pub fn reset(&mut self) {
+        self.state = RestoreState {
+            server: std::mem::take(&mut self.state.server),
+            nick: std::mem::take(&mut self.state.nick),
+            ..Default::default()
+        };
+        self.dirty = false;
+        self.closed = false;
+    }
+
+    /// Delete the file and stop writing it (a deliberate disconnect).
+    pub fn remove(&mut self) {
+        self.closed = true;
+        self.dirty = false;
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!(path = %self.path.display(), "removing state: {e}");
+            }
+        }
+    }
+
+    /// Change the state in memory and write it out: at once if `urgent`,
+    /// otherwise once [`SAVE_INTERVAL`] has passed since the last write.
+    pub fn update(&mut self, urgent: bool, f: impl FnOnce(&mut RestoreState) -> bool) {
+        self.dirty |= f(&mut self.state);
+        let due = self
+            .last_save
+            .is_none_or(|at| at.elapsed() >= SAVE_INTERVAL);
+        if self.dirty && (urgent || due) {
+            self.flush();
+        }
+    }
+
+    /// Write the state if it has unsaved changes.
+    pub fn flush(&mut self) {
+        if !self.dirty || self.closed {
+            return;
+        }
+        self.state.saved_at_ms = chrono::Utc::now().timestamp_millis();
+        match write_atomic(&self.path, &self.state) {
+            Ok(()) => {
+                self.dirty = false;
+                self.last_save = Some(Instant::now());
+            }
+            Err(e) => tracing::warn!(path = %self.path.display(), "saving state: {e}"),
+        }
+    }
+}
+
+/// `%LOCALAPPDATA%\freeq` (or `%APPDATA%\freeq`), if either is set.
+pub fn default_dir() -> Option<PathBuf> {
+    ["LOCALAPPDATA", "APPDATA"]
+        .iter()
+        .find_map(std::env::var_os)
+        .map(|dir| PathBuf::from(dir).join("freeq"))
+}
+
+/// `restore-<server>-<nick>.json`, with anything but ASCII letters, digits,
+/// `.` and `-` replaced by `_`.
+fn file_name(server: &str, nick: &str) -> String {
+    let clean = |s: &str| -> String {
+        s.chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect()
+    };
+    format!("restore-{}-{}.json", clean(server), clean(nick))
+}
+
+fn write_atomic(path: &Path, state: &RestoreState) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec_pretty(state)?)?;
+    std::fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("freeq-restore-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn newest_msgid_wins() {
+        let mut state = RestoreState::default();
+        assert!(state.record_msgid("#a", "01J00000000000000000000002"));
+        assert!(!state.record_msgid("#a", "01J00000000000000000000001"));
+        assert!(state.record_msgid("#a", "01J00000000000000000000003"));
+        assert_eq!(state.last_read["#a"], "01J00000000000000000000003");
+    }
+
+    #[test]
+    fn file_names_are_safe() {
+        assert_eq!(
+            file_name("irc.example.com:6697", "zoë|away"),
+            "restore-irc.example.com_6697-zo__away.json"
+        );
+    }
+
+    #[test]
+    fn saves_urgent_changes_and_throttles_the_rest() {
+        let dir = temp_dir("throttle");
+        let mut file = StateFile::new(&dir, "irc.example.com:6697", "alice");
+        assert_eq!(file.load(), None);
+
+        file.update(true, |s| {
+            s.channels.push("#freeq".to_string());
+            true
+        });
+        assert_eq!(file.load().unwrap().channels, ["#freeq"]);
+
+        // Within SAVE_INTERVAL of the last write: kept in memory
+        file.update(false, |s| s.record_msgid("#freeq", "01J1"));
+        assert!(file.load().unwrap().last_read.is_empty());
+
+        file.flush();
+        let saved = file.load().unwrap();
+        assert_eq!(saved.server, "irc.example.com:6697");
+        assert_eq!(saved.nick, "alice");
+        assert_eq!(saved.last_read["#freeq"], "01J1");
+        assert!(!dir
+            .join("restore-irc.example.com_6697-alice.json.tmp")
+            .exists());
+
+        // A deliberate disconnect leaves nothing behind until reconnected
+        file.remove();
+        file.update(true, |s| s.record_msgid("#freeq", "01J2"));
+        assert_eq!(file.load(), None);
+        file.reset();
+        file.update(true, |s| s.record_msgid("#freeq", "01J3"));
+        let saved = file.load().unwrap();
+        assert!(saved.channels.is_empty());
+        assert_eq!(saved.last_read["#freeq"], "01J3");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}